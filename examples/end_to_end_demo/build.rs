@@ -0,0 +1,16 @@
+fn main() {
+    // Each simulated device needs its own copy of the object dictionary statics, for both its
+    // application and its bootloader
+    let builds = [
+        ("APP_A", "device_config.toml"),
+        ("APP_B", "device_config.toml"),
+        ("BOOT_A", "bootloader_config.toml"),
+        ("BOOT_B", "bootloader_config.toml"),
+    ];
+    for (name, config) in builds {
+        if let Err(e) = zencan_build::build_node_from_device_config(name, config) {
+            eprintln!("Error building {name} from {config}: {e}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Drives the demo devices run by `demo_nodes`, using a BusManager
+//!
+//! Exits with a non-zero status if any step of the demo fails.
+use clap::Parser;
+use end_to_end_demo::{run_demo, DemoOptions};
+use zencan_client::{open_transport, BusManager};
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// The bus the devices are on, e.g. "vcan0", or "udp:<bind address>,<peer address>"
+    bus: String,
+    /// The software version of the image to flash into the first device
+    #[clap(long, default_value = "v1.1.0")]
+    version: String,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let args = Args::parse();
+
+    let (tx, rx) = match open_transport(&args.bus) {
+        Ok(transport) => transport,
+        Err(e) => {
+            eprintln!("Error opening {}: {e}", args.bus);
+            std::process::exit(1);
+        }
+    };
+    let mut manager = BusManager::new(tx, rx);
+    let opts = DemoOptions {
+        flash_version: args.version,
+        ..Default::default()
+    };
+
+    let report = match run_demo(&mut manager, &opts).await {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Demo failed: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    println!("Nodes:");
+    for node in &report.nodes {
+        println!("  {node}");
+    }
+    println!("PDO links:");
+    for link in &report.links {
+        println!(
+            "  Node {} TPDO{} -> node {} RPDO{} on COB-ID 0x{:X}",
+            link.producer.node_id,
+            link.producer.pdo,
+            link.consumer.node_id,
+            link.consumer.pdo,
+            link.cob_id
+        );
+    }
+    println!("Inputs:");
+    for (node_id, value) in &report.inputs {
+        println!("  Node {node_id}: {value}");
+    }
+    println!("EMCYs:");
+    for emcy in &report.emcys {
+        println!("  Node {}: 0x{:04X}", emcy.node, emcy.error_code);
+    }
+    println!(
+        "Flashed node {} to {}",
+        report.flash.node_id, report.flash.version
+    );
+}
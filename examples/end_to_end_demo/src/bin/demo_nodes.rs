@@ -0,0 +1,38 @@
+//! Runs the two simulated demo devices on a CAN bus
+//!
+//! Run `demo_controller` on the same bus to drive them, e.g. on a virtual CAN interface:
+//!
+//! ```text
+//! sudo ip link add dev vcan0 type vcan && sudo ip link set up vcan0
+//! cargo run --bin demo_nodes -- vcan0
+//! cargo run --bin demo_controller -- vcan0
+//! ```
+use clap::Parser;
+use end_to_end_demo::{demo_devices, DemoNetwork};
+use zencan_client::open_transport;
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// The bus to run the devices on, e.g. "vcan0", or "udp:<bind address>,<peer address>"
+    bus: String,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let args = Args::parse();
+
+    let (mut tx, mut rx) = match open_transport(&args.bus) {
+        Ok(transport) => transport,
+        Err(e) => {
+            eprintln!("Error opening {}: {e}", args.bus);
+            std::process::exit(1);
+        }
+    };
+
+    let mut network = DemoNetwork::new(demo_devices());
+    for device in network.devices() {
+        log::info!("Starting device with node ID {}", device.node_id());
+    }
+    network.run(&mut tx, &mut rx).await;
+}
@@ -0,0 +1,280 @@
+//! The controller side of the demo, which drives the devices with a [`BusManager`]
+//!
+//! [`run_demo`] goes through the life of a small system:
+//!
+//! 1. Scanning the bus, to find both devices
+//! 2. Linking the counter of each device to the input of the other with a PDO, sent on each SYNC,
+//!    and starting the SYNC and the devices
+//! 3. Checking that each device receives the counter of the other
+//! 4. Raising a fault on one device, and observing the EMCY it sends, then clearing it again
+//! 5. Flashing a new firmware image into one device through its bootloader, and checking the
+//!    version it reports afterwards
+use std::time::Duration;
+
+use snafu::{OptionExt, Snafu};
+use zencan_client::{
+    common::{decode::Emergency, traits::AsyncCanSender},
+    BusManager, EmcyMonitor, FlashError, FlashOptions, FlashReport, LinkedPdo, NodeInfo,
+    PdoLinkError, PdoLinkRequest, SdoClientError, SyncProducerError,
+};
+
+use crate::{firmware, COUNTER_INDEX, FAULT_CODE_INDEX, INPUT_INDEX, NODE_A, NODE_B};
+
+/// The error code of the fault raised by the demo (generic device hardware error)
+pub const DEMO_FAULT_CODE: u16 = 0x5000;
+
+/// The size of the fake firmware image
+const IMAGE_SIZE: usize = 2048;
+
+/// Options for [`run_demo`]
+#[derive(Debug, Clone)]
+pub struct DemoOptions {
+    /// The period of the SYNC which triggers the linked PDOs
+    ///
+    /// Default: 20ms
+    pub sync_period: Duration,
+    /// The time to wait for each expected event, such as data arriving or an EMCY
+    ///
+    /// Default: 2s
+    pub timeout: Duration,
+    /// The software version of the image flashed into the first device
+    ///
+    /// Default: "v1.1.0"
+    pub flash_version: String,
+}
+
+impl Default for DemoOptions {
+    fn default() -> Self {
+        Self {
+            sync_period: Duration::from_millis(20),
+            timeout: Duration::from_secs(2),
+            flash_version: "v1.1.0".into(),
+        }
+    }
+}
+
+/// The results of a run of the demo
+#[derive(Debug, Clone)]
+pub struct DemoReport {
+    /// The devices found by the scan
+    pub nodes: Vec<NodeInfo>,
+    /// The PDO links configured between the devices
+    pub links: Vec<LinkedPdo>,
+    /// The input value read from each device, as (node ID, value), once data was flowing
+    pub inputs: Vec<(u8, u32)>,
+    /// The EMCYs observed when the fault was raised and cleared
+    pub emcys: Vec<Emergency>,
+    /// The result of flashing the first device
+    pub flash: FlashReport,
+}
+
+/// Error returned by [`run_demo`]
+#[derive(Debug, Snafu)]
+pub enum DemoError {
+    /// A device was not found by the scan
+    #[snafu(display("Node {node_id} was not found on the bus"))]
+    NodeNotFound {
+        /// The missing node
+        node_id: u8,
+    },
+    /// A device did not receive the counter of the other device
+    #[snafu(display("Node {node_id} did not receive data from node {producer}"))]
+    NoData {
+        /// The consumer node
+        node_id: u8,
+        /// The producer node
+        producer: u8,
+    },
+    /// An expected EMCY was not received
+    #[snafu(display("No EMCY with code 0x{error_code:04X} received from node {node_id}"))]
+    NoEmcy {
+        /// The node which should have sent it
+        node_id: u8,
+        /// The expected error code
+        error_code: u16,
+    },
+    /// A device reported the wrong version after it was flashed
+    #[snafu(display(
+        "Node {node_id} reports version {actual} after flashing, expected {expected}"
+    ))]
+    WrongVersion {
+        /// The flashed node
+        node_id: u8,
+        /// The version of the image
+        expected: String,
+        /// The version reported by the node
+        actual: String,
+    },
+    /// An SDO transfer failed
+    #[snafu(context(false), display("{source}"))]
+    Sdo {
+        /// The underlying error
+        source: SdoClientError,
+    },
+    /// A PDO link could not be configured
+    #[snafu(context(false), display("{source}"))]
+    PdoLink {
+        /// The underlying error
+        source: PdoLinkError,
+    },
+    /// The SYNC could not be started
+    #[snafu(context(false), display("{source}"))]
+    Sync {
+        /// The underlying error
+        source: SyncProducerError,
+    },
+    /// Flashing the firmware failed
+    #[snafu(context(false), display("{source}"))]
+    Flash {
+        /// The underlying error
+        source: FlashError,
+    },
+}
+
+/// Run the demo, with the two devices on the bus of `manager`
+///
+/// On success, the devices are left running their applications, with the PDO links configured
+/// and the first device running the new image, and the SYNC is stopped.
+pub async fn run_demo<S>(
+    manager: &mut BusManager<S>,
+    opts: &DemoOptions,
+) -> Result<DemoReport, DemoError>
+where
+    S: AsyncCanSender + Sync + Send + 'static,
+{
+    // Discover the devices
+    let nodes = manager.scan_nodes().await;
+    for node_id in [NODE_A, NODE_B] {
+        if !nodes.iter().any(|n| n.node_id == node_id) {
+            return NodeNotFoundSnafu { node_id }.fail();
+        }
+    }
+    for node in &nodes {
+        log::info!("Found {node}");
+    }
+
+    // Send the counter of each device to the other on every SYNC
+    let mut links = Vec::new();
+    for (producer, consumer) in [(NODE_A, NODE_B), (NODE_B, NODE_A)] {
+        let request = PdoLinkRequest::new(producer, consumer)
+            .with_signal((COUNTER_INDEX, 0), (INPUT_INDEX, 0))
+            .with_transmission_type(1);
+        let link = manager.link_pdo(&request).await?;
+        log::info!(
+            "Linked node {producer} TPDO{} to node {consumer} RPDO{} on COB-ID 0x{:X}",
+            link.producer.pdo,
+            link.consumer.pdo,
+            link.cob_id
+        );
+        links.push(link);
+    }
+    manager.start_sync(opts.sync_period, 0)?;
+    manager.nmt_start(0).await;
+
+    // Check that data is flowing both ways
+    let mut inputs = Vec::new();
+    for (producer, consumer) in [(NODE_A, NODE_B), (NODE_B, NODE_A)] {
+        let input = wait_for_input(manager, consumer, opts.timeout)
+            .await?
+            .context(NoDataSnafu {
+                node_id: consumer,
+                producer,
+            })?;
+        log::info!("Node {consumer} received counter value {input} from node {producer}");
+        inputs.push((consumer, input));
+    }
+
+    // Raise a fault, and clear it again
+    let mut monitor = manager.emcy_monitor();
+    let mut emcys = Vec::new();
+    for error_code in [DEMO_FAULT_CODE, 0] {
+        manager
+            .sdo_client(NODE_B)
+            .write_u16(FAULT_CODE_INDEX, 0, error_code)
+            .await?;
+        let emcy = expect_emcy(&mut monitor, NODE_B, error_code, opts.timeout).await?;
+        log::info!("Node {} sent EMCY 0x{:04X}", emcy.node, emcy.error_code);
+        emcys.push(emcy);
+    }
+    manager.stop_sync();
+
+    // Update the firmware of the first device
+    let image = firmware::build_image(&opts.flash_version, IMAGE_SIZE);
+    let flash_opts = FlashOptions {
+        poll_interval: Duration::from_millis(50),
+        ..Default::default()
+    };
+    let flash = manager.flash_node(NODE_A, &image, &flash_opts).await?;
+    if flash.version != opts.flash_version {
+        return WrongVersionSnafu {
+            node_id: NODE_A,
+            expected: opts.flash_version.clone(),
+            actual: flash.version,
+        }
+        .fail();
+    }
+    log::info!(
+        "Flashed node {NODE_A} from {} to {}",
+        flash.previous_version.as_deref().unwrap_or("unknown"),
+        flash.version
+    );
+
+    Ok(DemoReport {
+        nodes,
+        links,
+        inputs,
+        emcys,
+        flash,
+    })
+}
+
+/// Poll the input object of a node until it holds a counter value
+///
+/// Returns None if the input is still 0 after `timeout`.
+async fn wait_for_input<S>(
+    manager: &BusManager<S>,
+    node_id: u8,
+    timeout: Duration,
+) -> Result<Option<u32>, SdoClientError>
+where
+    S: AsyncCanSender + Sync + Send + 'static,
+{
+    let poll = async {
+        loop {
+            let input = manager.sdo_client(node_id).read_u32(INPUT_INDEX, 0).await?;
+            if input != 0 {
+                return Ok::<_, SdoClientError>(input);
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    };
+    match tokio::time::timeout(timeout, poll).await {
+        Ok(result) => result.map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Wait for an EMCY with the given error code from a node
+async fn expect_emcy<S: AsyncCanSender + Sync + Send>(
+    monitor: &mut EmcyMonitor<S>,
+    node_id: u8,
+    error_code: u16,
+    timeout: Duration,
+) -> Result<Emergency, DemoError> {
+    let wait = async {
+        while let Ok(decoded) = monitor.recv().await {
+            if decoded.emcy.node == node_id && decoded.emcy.error_code == error_code {
+                return Some(decoded.emcy);
+            }
+        }
+        None
+    };
+    match tokio::time::timeout(timeout, wait).await {
+        Ok(Some(emcy)) => Ok(emcy),
+        _ => NoEmcySnafu {
+            node_id,
+            error_code,
+        }
+        .fail(),
+    }
+}
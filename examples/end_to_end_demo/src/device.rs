@@ -0,0 +1,348 @@
+//! The simulated demo devices
+//!
+//! Each [`DemoDevice`] emulates a microcontroller with a zencan bootloader and a zencan
+//! application, which share a node ID. The device starts in its application. When the application
+//! is commanded to reset into the bootloader, the bootloader node takes over, and when the
+//! bootloader receives an NMT reset application command, the application starts again, reporting
+//! the version of the image which was programmed, if any.
+//!
+//! A [`DemoNetwork`] runs several devices on one CAN transport, using a
+//! [`MultiNodeRunner`](zencan_node::MultiNodeRunner) so that the devices also receive each
+//! other's messages.
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use zencan_node::{
+    common::{
+        messages::{NmtCommand, NmtCommandSpecifier},
+        sdo::AbortCode,
+        traits::{AsyncCanReceiver, AsyncCanSender},
+        CanMessage, NodeId,
+    },
+    object_dict::{find_object, ODEntry},
+    BootloaderInfo, BootloaderSection, BootloaderSectionCallbacks, MultiNodeRunner, Node, NodeMbox,
+    NodeStateAccess, ProcessResult,
+};
+
+use crate::{firmware, COUNTER_PERIOD_US, FAULT_CODE_INDEX};
+
+/// Upper limit on the time between process calls
+const MAX_PROCESS_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The statics generated for the application of a demo device
+///
+/// Create with the `app_statics!` macro.
+pub struct AppStatics {
+    pub mbox: &'static NodeMbox,
+    pub state: &'static dyn NodeStateAccess,
+    pub od: &'static [ODEntry<'static>],
+    pub bootloader: &'static BootloaderInfo<true, 1>,
+    pub set_serial: fn(u32),
+    pub set_counter: fn(u32),
+    pub set_software_version: fn(&str) -> Result<(), AbortCode>,
+}
+
+/// The statics generated for the bootloader of a demo device
+///
+/// Create with the `boot_statics!` macro.
+pub struct BootStatics {
+    pub mbox: &'static NodeMbox,
+    pub state: &'static dyn NodeStateAccess,
+    pub od: &'static [ODEntry<'static>],
+    pub section: &'static BootloaderSection,
+    pub set_serial: fn(u32),
+}
+
+/// Create the [`AppStatics`] for a module generated from `device_config.toml`
+macro_rules! app_statics {
+    ($module:ident) => {
+        $crate::device::AppStatics {
+            mbox: &$module::NODE_MBOX,
+            state: &$module::NODE_STATE,
+            od: &$module::OD_TABLE,
+            bootloader: &$module::BOOTLOADER_INFO,
+            set_serial: |serial| $module::OBJECT1018.set_serial(serial),
+            set_counter: |value| $module::OBJECT2000.set_value(value),
+            set_software_version: $module::set_software_version,
+        }
+    };
+}
+pub(crate) use app_statics;
+
+/// Create the [`BootStatics`] for a module generated from `bootloader_config.toml`
+macro_rules! boot_statics {
+    ($module:ident) => {
+        $crate::device::BootStatics {
+            mbox: &$module::NODE_MBOX,
+            state: &$module::NODE_STATE,
+            od: &$module::OD_TABLE,
+            section: &$module::BOOTLOADER_SECTION0,
+            set_serial: |serial| $module::OBJECT1018.set_serial(serial),
+        }
+    };
+}
+pub(crate) use boot_statics;
+
+/// Which firmware a device is running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Application,
+    Bootloader,
+}
+
+/// Stands in for the flash memory of the application section
+#[derive(Debug, Default)]
+struct ImageStore {
+    data: Mutex<Vec<u8>>,
+    complete: AtomicBool,
+}
+
+impl ImageStore {
+    /// Take the image, if one has been completely programmed since the last call
+    fn take(&self) -> Option<Vec<u8>> {
+        self.complete
+            .swap(false, Ordering::Relaxed)
+            .then(|| core::mem::take(&mut *self.data.lock().unwrap()))
+    }
+}
+
+impl BootloaderSectionCallbacks for ImageStore {
+    fn erase(&self) -> bool {
+        self.data.lock().unwrap().clear();
+        self.complete.store(false, Ordering::Relaxed);
+        true
+    }
+
+    fn write(&self, data: &[u8]) {
+        self.data.lock().unwrap().extend_from_slice(data);
+    }
+
+    fn finalize(&self) -> bool {
+        self.complete.store(true, Ordering::Relaxed);
+        true
+    }
+}
+
+/// A simulated device, with an application and a bootloader
+pub struct DemoDevice {
+    node_id: NodeId,
+    app: AppStatics,
+    boot: BootStatics,
+    image: &'static ImageStore,
+    mode: Mode,
+    /// Set when the bootloader is commanded to start the application
+    start_app: bool,
+    /// The fault code most recently reported with an EMCY
+    fault: u16,
+}
+
+impl DemoDevice {
+    /// Create a device
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node_id` is not a valid node ID
+    pub fn new(node_id: u8, serial: u32, app: AppStatics, boot: BootStatics) -> Self {
+        let node_id = NodeId::new(node_id).expect("Invalid node ID");
+        (app.set_serial)(serial);
+        (boot.set_serial)(serial);
+        app.bootloader.clear_reset_flag();
+        // The image store stands in for the flash of the device, so it lives as long as the
+        // statics do
+        let image: &'static ImageStore = Box::leak(Box::default());
+        boot.section.register_callbacks(image);
+        Self {
+            node_id,
+            app,
+            boot,
+            image,
+            mode: Mode::Application,
+            start_app: false,
+            fault: 0,
+        }
+    }
+
+    /// Get the node ID of the device
+    pub fn node_id(&self) -> u8 {
+        self.node_id.raw()
+    }
+
+    /// Get which firmware the device is running
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Create the node for the current mode
+    fn create_node(&self) -> Node {
+        match self.mode {
+            Mode::Application => {
+                Node::new(self.node_id, self.app.mbox, self.app.state, self.app.od)
+            }
+            Mode::Bootloader => {
+                Node::new(self.node_id, self.boot.mbox, self.boot.state, self.boot.od)
+            }
+        }
+    }
+
+    /// Check a received frame for a command which resets the bootloader into the application
+    fn check_command(&mut self, msg: CanMessage) {
+        if self.mode != Mode::Bootloader {
+            return;
+        }
+        if let Ok(cmd) = NmtCommand::try_from(msg) {
+            if cmd.cs == NmtCommandSpecifier::ResetApp
+                && (cmd.node == 0 || cmd.node == self.node_id.raw())
+            {
+                self.start_app = true;
+            }
+        }
+    }
+
+    /// Run the application logic, before the node is processed
+    fn update(&mut self, node: &mut Node, now_us: u64) {
+        if self.mode != Mode::Application {
+            return;
+        }
+        (self.app.set_counter)((now_us / COUNTER_PERIOD_US) as u32);
+
+        let fault = find_object(self.app.od, FAULT_CODE_INDEX)
+            .and_then(|obj| obj.read_u16(0).ok())
+            .unwrap_or(0);
+        if fault != self.fault {
+            // An error code of 0 is an error reset
+            log::info!(
+                "Node {} fault code changed to 0x{fault:04X}",
+                self.node_id()
+            );
+            node.send_emcy(fault, [0; 5]);
+            self.fault = fault;
+        }
+    }
+
+    /// Switch between the application and bootloader, after the node is processed
+    ///
+    /// Returns the new node, if the device was reset
+    fn check_reset(&mut self) -> Option<Node> {
+        match self.mode {
+            Mode::Application if self.app.bootloader.reset_flag() => {
+                log::info!("Node {} resetting to bootloader", self.node_id());
+                self.app.bootloader.clear_reset_flag();
+                self.mode = Mode::Bootloader;
+            }
+            Mode::Bootloader if self.start_app => {
+                self.start_app = false;
+                if let Some(image) = self.image.take() {
+                    match firmware::image_version(&image) {
+                        Some(version) => {
+                            log::info!("Node {} starting new image {version}", self.node_id());
+                            (self.app.set_software_version)(version).ok();
+                        }
+                        None => {
+                            log::warn!("Node {} programmed with an invalid image", self.node_id())
+                        }
+                    }
+                }
+                self.mode = Mode::Application;
+                self.fault = 0;
+            }
+            _ => return None,
+        }
+        Some(self.create_node())
+    }
+}
+
+/// Runs a set of [`DemoDevice`]s on a CAN bus
+pub struct DemoNetwork {
+    devices: Vec<DemoDevice>,
+    runner: MultiNodeRunner,
+    epoch: Instant,
+}
+
+impl DemoNetwork {
+    /// Create a network of devices, each starting in its application
+    pub fn new(devices: Vec<DemoDevice>) -> Self {
+        let mut runner = MultiNodeRunner::new();
+        for device in &devices {
+            runner.add_node(device.create_node());
+        }
+        Self {
+            devices,
+            runner,
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Get the devices, in the order they were added
+    pub fn devices(&self) -> &[DemoDevice] {
+        &self.devices
+    }
+
+    /// Deliver a received frame to the devices
+    pub fn dispatch(&mut self, msg: CanMessage) {
+        for device in &mut self.devices {
+            device.check_command(msg);
+        }
+        self.runner.dispatch(msg);
+    }
+
+    /// Run the application logic and process the node of every device
+    ///
+    /// Messages transmitted by the devices are passed to `send_cb`, and are also delivered to the
+    /// other devices.
+    pub fn process(&mut self, now_us: u64, send_cb: &mut dyn FnMut(CanMessage)) -> ProcessResult {
+        for (device, node) in self.devices.iter_mut().zip(self.runner.nodes_mut()) {
+            device.update(node, now_us);
+        }
+        let result = self.runner.process(now_us, send_cb);
+        for (device, node) in self.devices.iter_mut().zip(self.runner.nodes_mut()) {
+            if let Some(new_node) = device.check_reset() {
+                *node = new_node;
+            }
+        }
+        result
+    }
+
+    /// Run the devices on a CAN transport
+    ///
+    /// This function does not return.
+    pub async fn run(
+        &mut self,
+        sender: &mut impl AsyncCanSender,
+        receiver: &mut impl AsyncCanReceiver,
+    ) {
+        loop {
+            let now_us = self.epoch.elapsed().as_micros() as u64;
+            let mut tx_messages = Vec::new();
+            let result = self.process(now_us, &mut |msg| tx_messages.push(msg));
+            for msg in tx_messages {
+                if sender.send(msg).await.is_err() {
+                    log::warn!("Failed to send CAN message");
+                }
+            }
+
+            let wait = result
+                .next_action_us
+                .map(Duration::from_micros)
+                .map_or(MAX_PROCESS_INTERVAL, |t| t.min(MAX_PROCESS_INTERVAL));
+            match tokio::time::timeout(wait, receiver.recv()).await {
+                Ok(Ok(msg)) => {
+                    self.dispatch(msg);
+                    // Drain any other frames which are already waiting
+                    while let Some(msg) = receiver.try_recv() {
+                        self.dispatch(msg);
+                    }
+                }
+                Ok(Err(e)) => {
+                    log::error!("Error receiving CAN message: {e:?}");
+                    tokio::time::sleep(MAX_PROCESS_INTERVAL).await;
+                }
+                Err(_) => (),
+            }
+        }
+    }
+}
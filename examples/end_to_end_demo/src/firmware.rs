@@ -0,0 +1,45 @@
+//! Fake firmware images for the demo devices
+//!
+//! An image starts with [`IMAGE_MAGIC`], followed by the length of the version string as a u8, the
+//! version string, and filler bytes up to the size of the image. When a simulated device starts an
+//! image, it reports the version string in its software version object (0x100A).
+
+/// The bytes at the start of every image
+pub const IMAGE_MAGIC: [u8; 4] = *b"ZDFW";
+
+/// Build an image of `size` bytes for the given software version
+///
+/// The image is longer than `size` if the header does not fit.
+pub fn build_image(version: &str, size: usize) -> Vec<u8> {
+    let version = &version.as_bytes()[..version.len().min(u8::MAX as usize)];
+    let mut image = Vec::with_capacity(size);
+    image.extend_from_slice(&IMAGE_MAGIC);
+    image.push(version.len() as u8);
+    image.extend_from_slice(version);
+    let filler = (image.len()..size).map(|i| i as u8);
+    image.extend(filler);
+    image
+}
+
+/// Get the software version of an image, or None if it is not a valid image
+pub fn image_version(image: &[u8]) -> Option<&str> {
+    let rest = image.strip_prefix(&IMAGE_MAGIC)?;
+    let (&len, rest) = rest.split_first()?;
+    let version = rest.get(..len as usize)?;
+    core::str::from_utf8(version).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_version() {
+        let image = build_image("v1.1.0", 256);
+        assert_eq!(256, image.len());
+        assert_eq!(Some("v1.1.0"), image_version(&image));
+
+        assert_eq!(None, image_version(&image[..8]));
+        assert_eq!(None, image_version(&[0; 16]));
+    }
+}
@@ -0,0 +1,72 @@
+//! An end-to-end demo of zencan, pairing two simulated devices with a controller
+//!
+//! The demo is split into the two halves of a real system:
+//!
+//! - The `demo_nodes` binary runs two simulated [devices](device) on a CAN bus. Each one has an
+//!   application and a bootloader, generated from `device_config.toml` and
+//!   `bootloader_config.toml`.
+//! - The `demo_controller` binary uses a [`BusManager`](zencan_client::BusManager) to
+//!   [drive the devices](controller): it discovers them, links their PDOs, checks the data they
+//!   exchange, raises and observes an EMCY, and flashes a fake [firmware] image.
+//!
+//! The integration test in `tests/end_to_end.rs` runs both halves on a [`VirtualBus`], so the
+//! whole demo is checked by `cargo test`. See `docs/end_to_end_demo.md` for a walkthrough.
+pub mod controller;
+pub mod device;
+pub mod firmware;
+pub mod virtual_bus;
+
+pub use controller::{run_demo, DemoError, DemoOptions, DemoReport};
+pub use device::{DemoDevice, DemoNetwork};
+pub use virtual_bus::VirtualBus;
+
+pub mod app_a {
+    zencan_node::include_modules!(APP_A);
+}
+pub mod app_b {
+    zencan_node::include_modules!(APP_B);
+}
+pub mod boot_a {
+    zencan_node::include_modules!(BOOT_A);
+}
+pub mod boot_b {
+    zencan_node::include_modules!(BOOT_B);
+}
+
+/// The node ID of the first demo device
+pub const NODE_A: u8 = 1;
+/// The node ID of the second demo device
+pub const NODE_B: u8 = 2;
+
+/// The counter object of a device, which is incremented every [`COUNTER_PERIOD_US`]
+pub const COUNTER_INDEX: u16 = 0x2000;
+/// The input object of a device, which the controller links to the counter of the other device
+pub const INPUT_INDEX: u16 = 0x2001;
+/// The fault code object of a device
+///
+/// Writing a non-zero error code raises an EMCY with that code, and writing 0 clears it with an
+/// error reset EMCY.
+pub const FAULT_CODE_INDEX: u16 = 0x2002;
+/// The time between increments of the counter object
+pub const COUNTER_PERIOD_US: u64 = 10_000;
+
+/// Create the two demo devices, with node IDs [`NODE_A`] and [`NODE_B`]
+///
+/// Each device uses its own set of generated statics, so this should only be called once in a
+/// process.
+pub fn demo_devices() -> Vec<DemoDevice> {
+    vec![
+        DemoDevice::new(
+            NODE_A,
+            0xA,
+            device::app_statics!(app_a),
+            device::boot_statics!(boot_a),
+        ),
+        DemoDevice::new(
+            NODE_B,
+            0xB,
+            device::app_statics!(app_b),
+            device::boot_statics!(boot_b),
+        ),
+    ]
+}
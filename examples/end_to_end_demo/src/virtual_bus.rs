@@ -0,0 +1,83 @@
+//! An in-memory CAN bus, for running the demo without a CAN interface
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use zencan_node::common::{
+    traits::{AsyncCanReceiver, AsyncCanSender},
+    CanMessage,
+};
+
+#[derive(Debug, Default)]
+struct BusShared {
+    endpoints: Vec<UnboundedSender<CanMessage>>,
+}
+
+/// An in-memory CAN bus
+///
+/// Each [`endpoint`](Self::endpoint) is a sender and receiver pair, like a socket on a CAN
+/// interface. A frame sent from one endpoint is received by every other endpoint, but not by the
+/// endpoint which sent it.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualBus {
+    shared: Arc<Mutex<BusShared>>,
+}
+
+impl VirtualBus {
+    /// Create a bus with no endpoints
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connect a new endpoint to the bus
+    pub fn endpoint(&self) -> (VirtualBusSender, VirtualBusReceiver) {
+        let (tx, rx) = unbounded_channel();
+        let mut shared = self.shared.lock().unwrap();
+        let index = shared.endpoints.len();
+        shared.endpoints.push(tx);
+        (
+            VirtualBusSender {
+                shared: self.shared.clone(),
+                index,
+            },
+            VirtualBusReceiver { rx },
+        )
+    }
+}
+
+/// Sends frames onto a [`VirtualBus`]
+#[derive(Debug, Clone)]
+pub struct VirtualBusSender {
+    shared: Arc<Mutex<BusShared>>,
+    index: usize,
+}
+
+impl AsyncCanSender for VirtualBusSender {
+    async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
+        let shared = self.shared.lock().unwrap();
+        for (i, endpoint) in shared.endpoints.iter().enumerate() {
+            // An endpoint which has been dropped is no longer on the bus
+            if i != self.index {
+                endpoint.send(msg).ok();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Receives frames from a [`VirtualBus`]
+#[derive(Debug)]
+pub struct VirtualBusReceiver {
+    rx: UnboundedReceiver<CanMessage>,
+}
+
+impl AsyncCanReceiver for VirtualBusReceiver {
+    type Error = ();
+
+    fn try_recv(&mut self) -> Option<CanMessage> {
+        self.rx.try_recv().ok()
+    }
+
+    async fn recv(&mut self) -> Result<CanMessage, Self::Error> {
+        self.rx.recv().await.ok_or(())
+    }
+}
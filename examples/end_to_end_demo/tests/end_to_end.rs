@@ -0,0 +1,52 @@
+//! Runs the whole demo, with the devices and the controller on a virtual bus
+//!
+//! This checks the system as a whole: discovery, PDO configuration, data exchange between nodes,
+//! EMCY, and firmware updates through the bootloader.
+
+use end_to_end_demo::{
+    controller::DEMO_FAULT_CODE, demo_devices, device::Mode, run_demo, DemoNetwork, DemoOptions,
+    VirtualBus, NODE_A, NODE_B,
+};
+use zencan_client::BusManager;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_end_to_end() {
+    let bus = VirtualBus::new();
+    let (mut node_tx, mut node_rx) = bus.endpoint();
+    let (manager_tx, manager_rx) = bus.endpoint();
+
+    let mut network = DemoNetwork::new(demo_devices());
+    let mut manager = BusManager::new(manager_tx, manager_rx);
+    let opts = DemoOptions::default();
+
+    let report = tokio::select! {
+        _ = network.run(&mut node_tx, &mut node_rx) => unreachable!(),
+        result = run_demo(&mut manager, &opts) => result.unwrap(),
+    };
+
+    let node_ids: Vec<u8> = report.nodes.iter().map(|n| n.node_id).collect();
+    assert_eq!(vec![NODE_A, NODE_B], node_ids);
+    assert_eq!(Some("Demo IO"), report.nodes[0].device_name.as_deref());
+
+    // Each device sends its counter to the other
+    assert_eq!(2, report.links.len());
+    assert_eq!(NODE_A, report.links[0].producer.node_id);
+    assert_eq!(NODE_B, report.links[0].consumer.node_id);
+    assert_ne!(report.links[0].cob_id, report.links[1].cob_id);
+    assert!(report.inputs.iter().all(|(_, value)| *value > 0));
+
+    // The fault is raised, then reset
+    let codes: Vec<(u8, u16)> = report
+        .emcys
+        .iter()
+        .map(|e| (e.node, e.error_code))
+        .collect();
+    assert_eq!(vec![(NODE_B, DEMO_FAULT_CODE), (NODE_B, 0)], codes);
+
+    assert_eq!(Some("v1.0.0"), report.flash.previous_version.as_deref());
+    assert_eq!("v1.1.0", report.flash.version);
+    assert!(network
+        .devices()
+        .iter()
+        .all(|d| d.mode() == Mode::Application));
+}
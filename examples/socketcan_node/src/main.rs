@@ -1,9 +1,4 @@
-use std::{
-    convert::Infallible,
-    io::Write as _,
-    sync::OnceLock,
-    time::{Duration, Instant},
-};
+use std::time::{Duration, Instant};
 
 use clap::Parser;
 use tokio::time::timeout;
@@ -11,7 +6,7 @@ use zencan_node::common::{
     traits::{AsyncCanReceiver, AsyncCanSender},
     CanMessage, NodeId,
 };
-use zencan_node::Node;
+use zencan_node::{storage::FileObjectStore, Node};
 
 use zencan_node::open_socketcan;
 
@@ -30,30 +25,8 @@ struct Args {
     serial: Option<u32>,
 }
 
-static OBJECT_STORE_PATH: OnceLock<String> = OnceLock::new();
-fn store_objects_callback(reader: &mut dyn embedded_io::Read<Error = Infallible>, _len: usize) {
-    let path = OBJECT_STORE_PATH.get().unwrap();
-    log::info!("Storing objects to {path}");
-
-    match std::fs::OpenOptions::new()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .open(path)
-    {
-        Ok(mut f) => {
-            let mut buf = [0; 32];
-            loop {
-                let n = reader.read(&mut buf).unwrap();
-                f.write_all(&buf[..n]).unwrap();
-                if n != buf.len() {
-                    break;
-                }
-            }
-        }
-        Err(e) => log::error!("Error storing objects to {}: {:?}", path, e),
-    }
-}
+/// Upper limit on the time between process calls
+const MAX_PROCESS_INTERVAL: Duration = Duration::from_millis(100);
 
 #[tokio::main]
 async fn main() {
@@ -67,13 +40,11 @@ async fn main() {
     // Set the serial number using the provided serial, or a random number if none is provided
     zencan::OBJECT1018.set_serial(args.serial.unwrap_or(rand::random()));
     // Load saved object values if they are found
-    if args.storage {
-        OBJECT_STORE_PATH
-            .set(format!("zencan_node.{}.flash", node_id.raw()))
-            .unwrap();
-        if let Ok(data) = std::fs::read(OBJECT_STORE_PATH.get().unwrap()) {
-            zencan_node::restore_stored_objects(&zencan::OD_TABLE, &data);
-        }
+    let store = args
+        .storage
+        .then(|| FileObjectStore::new(format!("zencan_node.{}.flash", node_id.raw())));
+    if let Some(store) = &store {
+        store.restore(&zencan::OD_TABLE);
     }
 
     let mut node = Node::new(
@@ -83,7 +54,9 @@ async fn main() {
         &zencan::OD_TABLE,
     );
 
-    node.register_store_objects(&store_objects_callback);
+    if let Some(store) = store {
+        node.register_store_objects(store.into_callback());
+    }
     let (mut tx, mut rx) = open_socketcan(&args.socket).unwrap();
 
     // Node requires callbacks be static, so use Box::leak to make static ref from closure on heap
@@ -116,7 +89,7 @@ async fn main() {
 
         let now_us = Instant::now().duration_since(epoch).as_micros() as u64;
         // Run node processing, collecting messages to send
-        node.process(now_us, &mut |msg: CanMessage| {
+        let result = node.process(now_us, &mut |msg: CanMessage| {
             tx_messages.push(msg);
         });
 
@@ -127,9 +100,12 @@ async fn main() {
             }
         }
 
-        // Wait for notification to run, or a timeout
-        timeout(Duration::from_millis(1), process_notify.notified())
-            .await
-            .ok();
+        // Wait for notification to run, or until the node's next scheduled action
+        let sleep_time = result
+            .next_action_us
+            .map(Duration::from_micros)
+            .unwrap_or(MAX_PROCESS_INTERVAL)
+            .min(MAX_PROCESS_INTERVAL);
+        timeout(sleep_time, process_notify.notified()).await.ok();
     }
 }
@@ -22,4 +22,11 @@ fn main() {
         eprintln!("Error building node from example3_bootloader.toml: {}", e);
         std::process::exit(1);
     }
+    if let Err(e) = zencan_build::build_client_bindings_from_device_config(
+        "EXAMPLE1",
+        "device_configs/example1.toml",
+    ) {
+        eprintln!("Error generating client bindings from example1.toml: {}", e);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,164 @@
+#!/usr/bin/env python3
+"""Interop checks of a zencan node, with python-canopen as the master
+
+Run by `tests/interop_test.rs`, against a node built from `device_configs/example1.toml`. Each
+check prints its name, and the script exits with a non-zero status at the first failure.
+
+Requires python-canopen (`pip install canopen`).
+"""
+import argparse
+import struct
+import sys
+import time
+
+import canopen
+from canopen.lss import CS_INQUIRE_PRODUCT_CODE, CS_INQUIRE_REVISION_NUMBER, CS_INQUIRE_VENDOR_ID
+from canopen.nmt import NmtError
+from canopen.sdo import SdoAbortedError, SdoError
+
+# Values from device_configs/example1.toml
+VENDOR_ID = 1234
+PRODUCT_CODE = 12000
+REVISION_NUMBER = 1
+DEVICE_NAME = "Example 1"
+
+
+class CheckFailed(Exception):
+    pass
+
+
+def expect(name, expected, actual):
+    if expected != actual:
+        raise CheckFailed(f"{name}: expected {expected!r}, got {actual!r}")
+
+
+def u32(value):
+    return struct.pack("<I", value)
+
+
+def check_nmt(node):
+    node.nmt.state = "RESET COMMUNICATION"
+    node.nmt.wait_for_bootup(5)
+    # Heartbeats are off by default, so turn them on to observe the NMT state
+    node.sdo.download(0x1017, 0, struct.pack("<H", 50))
+    expect("state after boot", "PRE-OPERATIONAL", node.nmt.wait_for_heartbeat(1))
+    for state in ["OPERATIONAL", "STOPPED", "PRE-OPERATIONAL"]:
+        node.nmt.state = state
+        time.sleep(0.2)
+        expect(f"state after {state} command", state, node.nmt.wait_for_heartbeat(1))
+
+
+def check_sdo(node):
+    # Expedited upload
+    identity = node.sdo.upload(0x1018, 1), node.sdo.upload(0x1018, 2)
+    expect("identity", (u32(VENDOR_ID), u32(PRODUCT_CODE)), identity)
+    # Segmented upload
+    expect("device name", DEVICE_NAME.encode(), node.sdo.upload(0x1008, 0))
+    # Expedited download
+    node.sdo.download(0x3000, 0, u32(0x12345678))
+    expect("u32 var", u32(0x12345678), node.sdo.upload(0x3000, 0))
+    # Segmented download
+    node.sdo.download(0x2003, 0, b"interop test")
+    expect("string var", b"interop test", node.sdo.upload(0x2003, 0))
+    # Block download
+    data = bytes(i % 251 for i in range(1200))
+    with node.sdo.open(0x3006, 0, "wb", size=len(data), block_transfer=True) as f:
+        f.write(data)
+    expect("byte array", data, node.sdo.upload(0x3006, 0))
+    # Aborts
+    try:
+        node.sdo.upload(0x5FFF, 0)
+        raise CheckFailed("upload of a missing object was not aborted")
+    except SdoAbortedError as e:
+        expect("missing object abort code", 0x06020000, e.code)
+    try:
+        node.sdo.download(0x3004, 0, struct.pack("<h", 1))
+        raise CheckFailed("download to a read-only object was not aborted")
+    except SdoAbortedError as e:
+        expect("read-only abort code", 0x06010002, e.code)
+
+
+def check_pdo(network, node, node_id):
+    tpdo_cob_id = 0x180 + node_id
+    rpdo_cob_id = 0x200 + node_id
+
+    # TPDO1: 0x3000 sent on every SYNC
+    node.sdo.download(0x1800, 1, u32(0x80000000 | tpdo_cob_id))
+    node.sdo.download(0x1800, 2, bytes([1]))
+    node.sdo.download(0x1A00, 0, bytes([0]))
+    node.sdo.download(0x1A00, 1, u32(0x30000020))
+    node.sdo.download(0x1A00, 0, bytes([1]))
+    node.sdo.download(0x1800, 1, u32(tpdo_cob_id))
+
+    # RPDO1: 0x3000, applied on reception
+    node.sdo.download(0x1400, 1, u32(0x80000000 | rpdo_cob_id))
+    node.sdo.download(0x1400, 2, bytes([254]))
+    node.sdo.download(0x1600, 0, bytes([0]))
+    node.sdo.download(0x1600, 1, u32(0x30000020))
+    node.sdo.download(0x1600, 0, bytes([1]))
+    node.sdo.download(0x1400, 1, u32(rpdo_cob_id))
+
+    received = []
+    network.subscribe(tpdo_cob_id, lambda cob_id, data, timestamp: received.append(bytes(data)))
+    node.nmt.state = "OPERATIONAL"
+    time.sleep(0.1)
+
+    node.sdo.download(0x3000, 0, u32(0xCAFE0001))
+    network.sync.transmit()
+    time.sleep(0.2)
+    expect("TPDO on SYNC", [u32(0xCAFE0001)], received)
+
+    network.send_message(rpdo_cob_id, u32(0xCAFE0002))
+    time.sleep(0.2)
+    expect("u32 var after RPDO", u32(0xCAFE0002), node.sdo.upload(0x3000, 0))
+
+    network.unsubscribe(tpdo_cob_id)
+    node.nmt.state = "PRE-OPERATIONAL"
+
+
+def check_lss(network, node_id):
+    lss = network.lss
+    lss.send_switch_state_global(lss.CONFIGURATION_STATE)
+    try:
+        expect("LSS vendor ID", VENDOR_ID, lss.inquire_lss_address(CS_INQUIRE_VENDOR_ID))
+        expect("LSS product code", PRODUCT_CODE, lss.inquire_lss_address(CS_INQUIRE_PRODUCT_CODE))
+        expect(
+            "LSS revision", REVISION_NUMBER, lss.inquire_lss_address(CS_INQUIRE_REVISION_NUMBER)
+        )
+        expect("LSS node ID", node_id, lss.inquire_node_id())
+    finally:
+        lss.send_switch_state_global(lss.WAITING_STATE)
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--channel", default="vcan0", help="The socketcan interface")
+    parser.add_argument("--node-id", type=int, required=True, help="The ID of the node to test")
+    args = parser.parse_args()
+
+    network = canopen.Network()
+    network.connect(interface="socketcan", channel=args.channel)
+    node = canopen.RemoteNode(args.node_id, canopen.ObjectDictionary())
+    network.add_node(node)
+
+    checks = [
+        ("nmt", lambda: check_nmt(node)),
+        ("sdo", lambda: check_sdo(node)),
+        ("pdo", lambda: check_pdo(network, node, args.node_id)),
+        ("lss", lambda: check_lss(network, args.node_id)),
+    ]
+    try:
+        for name, check in checks:
+            print(f"{name}...", flush=True)
+            check()
+            print(f"{name} ok", flush=True)
+    except (CheckFailed, SdoError, NmtError) as e:
+        print(f"FAILED: {e}", flush=True)
+        return 1
+    finally:
+        network.disconnect()
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -0,0 +1,31 @@
+#!/bin/sh
+# Build the reference implementations and run the interop tests against them
+#
+# CANopenLinux, which provides the CANopenNode based `canopend` reference slave, is cloned and
+# built under target/interop, and python-canopen is installed into a virtualenv there. Set
+# ZENCAN_INTEROP_IFACE to use an interface other than vcan0.
+set -e
+
+cd "$(dirname "$0")/../.."
+WORK="$PWD/target/interop"
+IFACE="${ZENCAN_INTEROP_IFACE:-vcan0}"
+mkdir -p "$WORK"
+
+integration_tests/interop/setup_vcan.sh "$IFACE"
+
+if [ ! -x "$WORK/CANopenLinux/canopend" ]; then
+    rm -rf "$WORK/CANopenLinux"
+    git clone --depth 1 --recurse-submodules --shallow-submodules \
+        https://github.com/CANopenNode/CANopenLinux.git "$WORK/CANopenLinux"
+    make -C "$WORK/CANopenLinux"
+fi
+
+if [ ! -x "$WORK/venv/bin/python" ]; then
+    python3 -m venv "$WORK/venv"
+    "$WORK/venv/bin/pip" install --quiet canopen
+fi
+
+ZENCAN_INTEROP_IFACE="$IFACE" \
+ZENCAN_CANOPEND="$WORK/CANopenLinux/canopend" \
+ZENCAN_PYTHON="$WORK/venv/bin/python" \
+    cargo test -p integration_tests --test interop_test -- --ignored --test-threads=1
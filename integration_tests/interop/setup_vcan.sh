@@ -0,0 +1,17 @@
+#!/bin/sh
+# Create a virtual CAN interface for the interop tests, if it does not already exist
+#
+# Usage: setup_vcan.sh [interface], e.g. setup_vcan.sh vcan0. Requires root, or sudo.
+set -e
+
+IFACE="${1:-vcan0}"
+SUDO=""
+if [ "$(id -u)" -ne 0 ]; then
+    SUDO="sudo"
+fi
+
+if ! ip link show "$IFACE" > /dev/null 2>&1; then
+    $SUDO modprobe vcan
+    $SUDO ip link add dev "$IFACE" type vcan
+fi
+$SUDO ip link set up "$IFACE"
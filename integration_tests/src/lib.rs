@@ -7,4 +7,8 @@ pub mod object_dict2 {
 pub mod object_dict3 {
     zencan_node::include_modules!(EXAMPLE3);
 }
+pub mod client_od1 {
+    zencan_client::include_bindings!(EXAMPLE1);
+}
+pub mod manager;
 pub mod sim_bus;
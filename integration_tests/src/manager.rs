@@ -0,0 +1,22 @@
+//! Helpers for tests which drive a node through a [`BusManager`]
+
+use std::ops::DerefMut;
+
+use zencan_client::{
+    common::traits::{AsyncCanReceiver, AsyncCanSender},
+    BusManager, SdoClient,
+};
+
+/// Get the manager's SDO client for a node
+///
+/// A test which uses a [`BusManager`] should make all of its SDO transfers to a node through the
+/// manager, rather than through a second client such as the one of a
+/// [`NodeFixture`](zencan_client::testing::NodeFixture). Both clients use the node's default SDO
+/// COB-IDs, so the responses to the transfers of one would also be received by the other, and
+/// taken for the responses to its own next transfer.
+pub fn manager_client<S: AsyncCanSender + Sync + Send>(
+    manager: &BusManager<S>,
+    node_id: u8,
+) -> impl DerefMut<Target = SdoClient<impl AsyncCanSender, impl AsyncCanReceiver>> + '_ {
+    manager.sdo_client(node_id)
+}
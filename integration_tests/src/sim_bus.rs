@@ -70,6 +70,10 @@ impl AsyncCanSender for SimBusSender<'_> {
 
         Ok(())
     }
+
+    fn echoes_sent_frames(&self) -> bool {
+        true
+    }
 }
 
 pub struct SimBusReceiver {
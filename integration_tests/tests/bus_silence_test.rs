@@ -0,0 +1,56 @@
+//! Tests for detection of a silent bus by the BusManager
+//!
+
+use std::time::Duration;
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::{testing::NodeFixture, BusEvent, BusManager, SdoClientError};
+
+#[serial]
+#[tokio::test]
+async fn test_bus_silence() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+    let mut events = manager.bus_events();
+    assert_eq!(None, manager.silence_timeout());
+    manager.set_silence_timeout(Some(Duration::from_millis(50)));
+    assert_eq!(Some(Duration::from_millis(50)), manager.silence_timeout());
+
+    // The node is not processed yet, so nothing is heard from the bus
+    let Some(BusEvent::Silent { idle_time }) = events.recv().await else {
+        panic!("Expected a silent event");
+    };
+    assert!(idle_time >= Duration::from_millis(50));
+    assert!(manager.bus_silence().is_some());
+    let mut device = manager.device(1);
+    assert!(matches!(
+        device.read::<u32>((0x1000, 0)).await,
+        Err(SdoClientError::BusSilent { .. })
+    ));
+
+    fixture
+        .run(async {
+            // Keep the bus busy with heartbeats, so that a missing node is reported as such
+            let original_period: u16 = device.read((0x1017, 0)).await.unwrap();
+            device.write((0x1017, 0), 10u16).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(None, manager.bus_silence());
+            assert_eq!(
+                Err(SdoClientError::NoResponse),
+                manager.device(2).read::<u32>((0x1000, 0)).await
+            );
+
+            device.write((0x1017, 0), original_period).await.unwrap();
+        })
+        .await;
+
+    manager.set_silence_timeout(None);
+    assert_eq!(None, manager.silence_timeout());
+    assert_eq!(None, manager.bus_silence());
+}
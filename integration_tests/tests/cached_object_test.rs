@@ -0,0 +1,94 @@
+//! Tests for objects whose value is read from the application and cached
+//!
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_common::{
+    messages::{CanId, NmtState, SyncObject},
+    objects::DataType,
+    sdo::AbortCode,
+    NodeId,
+};
+use zencan_node::{
+    object_dict::{find_object, CacheRefresh, CachedCallbackObject},
+    Node,
+};
+
+static READS: AtomicU32 = AtomicU32::new(0);
+static LIVE_VALUE: CachedCallbackObject<4> =
+    CachedCallbackObject::new(DataType::UInt32, CacheRefresh::Interval(1000));
+
+/// Returns the number of times it has been called
+fn read_live_value(buf: &mut [u8]) -> Result<(), AbortCode> {
+    let count = READS.fetch_add(1, Ordering::Relaxed) + 1;
+    buf.copy_from_slice(&count.to_le_bytes());
+    Ok(())
+}
+
+#[serial]
+#[test]
+fn test_cached_callback_object() {
+    let od = &object_dict1::OD_TABLE;
+    let mbox = &object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(1).unwrap(), mbox, &object_dict1::NODE_STATE, od);
+    const TPDO_COB_ID: u16 = 0x182;
+
+    LIVE_VALUE.register_handler(&read_live_value, mbox.cache_clock());
+    object_dict1::OBJECT3200.register_handler(&LIVE_VALUE);
+    let object = find_object(od, 0x3200).unwrap();
+
+    let process = |node: &mut Node, now_us: u64| {
+        let mut sent = Vec::new();
+        node.process(now_us, &mut |msg| {
+            if msg.id() == CanId::Std(TPDO_COB_ID) {
+                sent.push(msg);
+            }
+        });
+        sent
+    };
+
+    // The value is read at most once per interval
+    process(&mut node, 0);
+    assert_eq!(Ok(1), object.read_u32(0));
+    process(&mut node, 500);
+    assert_eq!(Ok(1), object.read_u32(0));
+    process(&mut node, 1000);
+    assert_eq!(Ok(2), object.read_u32(0));
+    assert_eq!(Ok(2), object.read_u32(0));
+
+    // Configure TPDO1 to send on every SYNC, mapping 0x3200sub0
+    let tpdo_comm = find_object(od, 0x1801).unwrap();
+    tpdo_comm
+        .write(1, &(TPDO_COB_ID as u32).to_le_bytes())
+        .unwrap();
+    tpdo_comm.write(2, &[1]).unwrap();
+    let tpdo_mapping = find_object(od, 0x1A01).unwrap();
+    tpdo_mapping
+        .write(1, &((0x3200u32 << 16) | 32).to_le_bytes())
+        .unwrap();
+    tpdo_mapping.write(0, &[1]).unwrap();
+    node.request_state(NmtState::Operational);
+
+    // Once per SYNC, the TPDO and SDO reads share a single read of the value
+    LIVE_VALUE.set_refresh(CacheRefresh::Sync);
+    process(&mut node, 5000);
+    assert_eq!(Ok(2), object.read_u32(0));
+    for expected in [3u32, 4] {
+        mbox.store_message(SyncObject::new(0).into()).unwrap();
+        let sent = process(&mut node, 6000);
+        assert_eq!(1, sent.len());
+        assert_eq!(&expected.to_le_bytes(), &sent[0].data()[0..4]);
+        assert_eq!(Ok(expected), object.read_u32(0));
+    }
+    assert_eq!(4, READS.load(Ordering::Relaxed));
+
+    // The value cannot be written
+    assert_eq!(Err(AbortCode::ReadOnly), object.write(0, &[0; 4]));
+
+    // Disable the TPDO again
+    tpdo_comm
+        .write(1, &(TPDO_COB_ID as u32 | 1 << 31).to_le_bytes())
+        .unwrap();
+}
@@ -0,0 +1,75 @@
+//! Tests for the COB-ID registry of the BusManager
+//!
+
+use std::time::Duration;
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::{
+    cob_registry::{CobIdUser, ConflictReason},
+    testing::NodeFixture,
+    topology::PdoEndpoint,
+    BusManager, NodeConfig,
+};
+
+#[serial]
+#[tokio::test]
+async fn test_apply_node_config_conflicts() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut client = fixture.sdo_client();
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let test_task = async move {
+        let original_tpdo0 = client.read_tpdo(0).await.unwrap().unwrap();
+        let original_tpdo1 = client.read_tpdo(1).await.unwrap().unwrap();
+
+        // Frames sent by the node are observed by the manager
+        client.upload_u32(0x1000, 0).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(manager.cob_ids().last_observed(0x581).is_some());
+
+        let config = NodeConfig::load_from_str(
+            r#"
+            [tpdo.0]
+            enabled = true
+            cob = 0x1A0
+            transmission_type = 254
+            mappings = [{ index = 0x3000, sub = 0, size = 32 }]
+
+            [tpdo.1]
+            enabled = true
+            cob = 0x100
+            transmission_type = 254
+            mappings = [{ index = 0x3000, sub = 0, size = 32 }]
+        "#,
+        )
+        .unwrap();
+        let conflicts = manager.apply_node_config(1, &config, false).await.unwrap();
+        assert_eq!(1, conflicts.len());
+        assert_eq!(0x100, conflicts[0].cob_id);
+        assert_eq!(ConflictReason::Reserved, conflicts[0].reason);
+
+        // The applied PDOs are recorded, and are not free for allocation
+        let tpdo0 = CobIdUser::Tpdo(PdoEndpoint { node_id: 1, pdo: 0 });
+        assert_eq!(vec![tpdo0], manager.cob_ids().users(0x1A0));
+        assert!(!manager.cob_ids().is_free(0x1A0, &[]));
+        assert_ne!(Some(0x1A0), manager.cob_ids().allocate(Some(0x1A0), &[]));
+
+        // Another node sending on the same COB-ID conflicts
+        let conflicts = manager.cob_id_conflicts(2, &config);
+        assert_eq!(
+            ConflictReason::Produced(PdoEndpoint { node_id: 1, pdo: 0 }),
+            conflicts[0].reason
+        );
+
+        client.configure_tpdo(0, &original_tpdo0).await.unwrap();
+        client.configure_tpdo(1, &original_tpdo1).await.unwrap();
+    };
+
+    fixture.run(test_task).await;
+}
@@ -0,0 +1,57 @@
+//! Tests for detection of another master on the bus by the BusManager
+//!
+use std::time::Duration;
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::{
+    common::{messages::CanId, sdo::SdoRequest, traits::AsyncCanSender},
+    testing::NodeFixture,
+    BusEvent, BusManager, MasterPolicy, SdoClientError,
+};
+
+#[serial]
+#[tokio::test]
+async fn test_competing_master() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut other_master = fixture.sender();
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+    let mut events = manager.bus_events();
+
+    let test_task = async move {
+        // The manager's own requests are not taken for another master's
+        assert_eq!(MasterPolicy::Warn, manager.master_policy());
+        manager.sdo_client(1).upload(0x1008, 0).await.unwrap();
+        assert_eq!(None, manager.competing_master());
+
+        let request = SdoRequest::initiate_upload(0x1018, 1).to_can_message(CanId::sdo_rx(1));
+        other_master.send(request).await.unwrap();
+        assert_eq!(
+            Some(BusEvent::CompetingMaster {
+                cob_id: CanId::sdo_rx(1)
+            }),
+            events.recv().await
+        );
+        assert_eq!(Some(CanId::sdo_rx(1)), manager.competing_master());
+        // The other master's transfer is left to finish
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // When yielding, no requests are sent
+        manager.set_master_policy(MasterPolicy::Yield);
+        assert!(matches!(
+            manager.sdo_client(1).upload(0x1008, 0).await,
+            Err(SdoClientError::CompetingMaster { .. })
+        ));
+
+        manager.set_master_policy(MasterPolicy::Continue);
+        manager.sdo_client(1).upload(0x1008, 0).await.unwrap();
+        manager.set_master_policy(MasterPolicy::Warn);
+    };
+
+    fixture.run(test_task).await;
+}
@@ -0,0 +1,148 @@
+//! Tests for the Device handle provided by the BusManager
+//!
+
+use std::time::Duration;
+
+use integration_tests::{client_od1, object_dict1};
+use serial_test::serial;
+use zencan_client::{
+    common::{messages::NmtState, objects::ObjectId},
+    testing::NodeFixture,
+    BusManager, SdoClientError,
+};
+
+#[serial]
+#[tokio::test]
+async fn test_device() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let test_task = async move {
+        let mut device = manager.device(1);
+        assert_eq!(1, device.node_id());
+        assert_eq!("Example 1", device.name().await.unwrap());
+        assert_eq!("v2.1.0", device.software_version().await.unwrap());
+        assert_eq!(1234, device.identity().await.unwrap().vendor_id);
+
+        let original: u32 = device.read((0x2000, 1)).await.unwrap();
+        device.write((0x2000, 1), 0x1234_5678u32).await.unwrap();
+        assert_eq!(
+            0x1234_5678,
+            device
+                .read::<u32>(ObjectId {
+                    index: 0x2000,
+                    sub: 1
+                })
+                .await
+                .unwrap()
+        );
+        // The size of the object must match the type
+        assert!(matches!(
+            device.read::<u16>((0x2000, 1)).await,
+            Err(SdoClientError::UnexpectedSize)
+        ));
+        assert_eq!(
+            0x1234_5679,
+            device.update((0x2000, 1), |v: u32| v | 1).await.unwrap()
+        );
+        device.write((0x2000, 1), original).await.unwrap();
+
+        device.set_restart_timeout(Duration::from_millis(500));
+        device.restart().await.unwrap();
+        // The node boots into PreOperational, and is started. Its state is reported by its
+        // heartbeat.
+        assert_eq!(Some(NmtState::PreOperational), device.nmt_state().await);
+        device.write((0x1017, 0), 10u16).await.unwrap();
+        assert!(device.start().await);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(Some(NmtState::Operational), device.nmt_state().await);
+        device.write((0x1017, 0), 0u16).await.unwrap();
+    };
+
+    fixture.run(test_task).await;
+}
+
+#[serial]
+#[tokio::test]
+async fn test_device_client_bindings() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let test_task = async move {
+        let mut device = manager.device(1);
+
+        assert_eq!(2, client_od1::ARRAY_EXAMPLE.len());
+        let elem = client_od1::ARRAY_EXAMPLE.at(1);
+        let original = device.get(elem).await.unwrap();
+        device.set(elem, 42).await.unwrap();
+        assert_eq!(42, device.get(elem).await.unwrap());
+        assert_eq!(42, device.read::<u32>((0x2000, 2)).await.unwrap());
+        device.set(elem, original).await.unwrap();
+
+        let sub1 = client_od1::RECORD_EXAMPLE.sub1;
+        assert_eq!(140, device.get(sub1).await.unwrap());
+        device.set(sub1, 141).await.unwrap();
+        assert_eq!(141, device.get(sub1).await.unwrap());
+        device.set(sub1, 140).await.unwrap();
+
+        assert_eq!(
+            "Some String",
+            device
+                .get(client_od1::NON_PERSISTED_STRING_VAR)
+                .await
+                .unwrap()
+        );
+    };
+
+    fixture.run(test_task).await;
+}
+
+#[serial]
+#[tokio::test]
+async fn test_device_object_cache() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let test_task = async move {
+        let mut device = manager.device(1);
+        assert_eq!(None, manager.object_cache().get(1).device_name);
+        assert_eq!("Example 1", device.name().await.unwrap());
+        assert_eq!(1234, device.identity().await.unwrap().vendor_id);
+        let cached = manager.object_cache().get(1);
+        assert_eq!(Some("Example 1".to_string()), cached.device_name);
+        assert_eq!(Some(1234), cached.identity.map(|id| id.vendor_id));
+
+        // Cached values are shared with other devices, and returned without reading the node
+        manager
+            .object_cache()
+            .update(1, |c| c.device_name = Some("Cached".into()));
+        assert_eq!("Cached", manager.device(1).name().await.unwrap());
+
+        // The cache is invalidated when the node boots up
+        device.set_restart_timeout(Duration::from_millis(500));
+        device.restart().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(None, manager.object_cache().get(1).device_name);
+        assert_eq!("Example 1", device.name().await.unwrap());
+
+        device.invalidate_cache();
+        assert_eq!(None, manager.object_cache().get(1).device_name);
+    };
+
+    fixture.run(test_task).await;
+}
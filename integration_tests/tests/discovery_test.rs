@@ -0,0 +1,104 @@
+//! Tests for background discovery of nodes by the BusManager
+//!
+
+use std::time::Duration;
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::{
+    common::{
+        messages::{Heartbeat, NmtState},
+        traits::AsyncCanSender,
+    },
+    testing::{NodeFixture, TestBusSender},
+    BusManager, DetachReason, DiscoveryOptions, NodeEvent, NodeEvents,
+};
+
+async fn send_heartbeat(sender: &mut TestBusSender, node: u8, state: NmtState) {
+    let msg = Heartbeat {
+        node,
+        toggle: false,
+        state,
+    };
+    sender.send(msg.into()).await.unwrap();
+}
+
+async fn next_event(events: &mut NodeEvents) -> Option<NodeEvent> {
+    tokio::time::timeout(Duration::from_secs(1), events.recv())
+        .await
+        .ok()
+        .flatten()
+}
+
+#[serial]
+#[tokio::test]
+async fn test_discovery_events() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut sender = fixture.sender();
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let test_task = async move {
+        let opts = DiscoveryOptions {
+            heartbeat_timeout: Duration::from_millis(200),
+            probe_timeout: Duration::from_millis(50),
+            probe_only: true,
+        };
+        let mut events = manager.node_events();
+        assert_eq!(None, manager.discovery());
+        manager.start_discovery(opts);
+        assert_eq!(Some(opts), manager.discovery());
+
+        // The node's boot-up message is sent by the fixture, so send one on its behalf
+        send_heartbeat(&mut sender, 1, NmtState::Bootup).await;
+        match next_event(&mut events).await {
+            Some(NodeEvent::Attached(info)) => {
+                assert_eq!(1, info.node_id);
+                assert_eq!(1234, info.identity.unwrap().vendor_id);
+                assert_eq!(12000, info.identity.unwrap().product_code);
+            }
+            event => panic!("Expected node 1 to attach, got {event:?}"),
+        }
+        assert_eq!(1, manager.node_list().await.len());
+
+        // A boot-up from an attached node detaches it and probes it again
+        send_heartbeat(&mut sender, 1, NmtState::Bootup).await;
+        assert!(matches!(
+            next_event(&mut events).await,
+            Some(NodeEvent::Detached {
+                node_id: 1,
+                reason: DetachReason::Rebooted
+            })
+        ));
+        assert!(matches!(
+            next_event(&mut events).await,
+            Some(NodeEvent::Attached(info)) if info.node_id == 1
+        ));
+
+        // A node which does not respond to SDO is never attached, and the heartbeat timeout
+        // detaches node 1
+        send_heartbeat(&mut sender, 5, NmtState::PreOperational).await;
+        assert!(matches!(
+            next_event(&mut events).await,
+            Some(NodeEvent::Detached {
+                node_id: 1,
+                reason: DetachReason::HeartbeatTimeout
+            })
+        ));
+
+        assert!(manager.stop_discovery());
+        assert!(!manager.stop_discovery());
+        send_heartbeat(&mut sender, 1, NmtState::Bootup).await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(300), events.recv())
+                .await
+                .is_err()
+        );
+    };
+
+    fixture.run(test_task).await;
+}
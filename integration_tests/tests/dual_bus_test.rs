@@ -0,0 +1,92 @@
+//! Tests for a single node bridged across two redundant buses
+//!
+use std::time::Duration;
+
+use integration_tests::{object_dict1, sim_bus::SimBus};
+use serial_test::serial;
+use zencan_client::SdoClient;
+use zencan_common::{
+    messages::{NmtCommand, NmtCommandSpecifier},
+    traits::{AsyncCanReceiver, AsyncCanSender},
+    CanId, CanMessage, NodeId,
+};
+use zencan_node::{DualBusRunner, Node};
+
+/// Collect the frames with the given ID which are waiting on a receiver
+fn drain_id(receiver: &mut impl AsyncCanReceiver, id: CanId) -> Vec<CanMessage> {
+    let mut frames = Vec::new();
+    while let Some(msg) = receiver.try_recv() {
+        if msg.id() == id {
+            frames.push(msg);
+        }
+    }
+    frames
+}
+
+#[serial]
+#[tokio::test]
+async fn test_dual_bus_runner() {
+    let mut runner = DualBusRunner::new(Node::new(
+        NodeId::new(1).unwrap(),
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+        &object_dict1::OD_TABLE,
+    ));
+
+    let mut bus_a = SimBus::new(vec![]);
+    let mut bus_b = SimBus::new(vec![]);
+    let mut runner_sender_a = bus_a.new_sender();
+    let mut runner_receiver_a = bus_a.new_receiver();
+    let mut runner_sender_b = bus_b.new_sender();
+    let mut runner_receiver_b = bus_b.new_receiver();
+    let mut client_a = SdoClient::new_std(1, bus_a.new_sender(), bus_a.new_receiver());
+    let mut client_b = SdoClient::new_std(1, bus_b.new_sender(), bus_b.new_receiver());
+    let mut nmt_a = bus_a.new_sender();
+    let mut nmt_b = bus_b.new_sender();
+    let mut monitor_a = bus_a.new_receiver();
+    let mut monitor_b = bus_b.new_receiver();
+
+    let test_task = async {
+        // Give the node time to boot. The boot-up message is sent on both buses.
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(1, drain_id(&mut monitor_a, CanId::heartbeat(1)).len());
+        assert_eq!(1, drain_id(&mut monitor_b, CanId::heartbeat(1)).len());
+
+        // SDO requests are accepted on either bus, and answered on the same bus
+        let name = client_a.upload(0x1008, 0).await.unwrap();
+        assert_eq!(b"Example 1".as_slice(), name.as_slice());
+        assert!(!drain_id(&mut monitor_a, CanId::sdo_tx(1)).is_empty());
+        assert!(drain_id(&mut monitor_b, CanId::sdo_tx(1)).is_empty());
+
+        let name = client_b.upload(0x1008, 0).await.unwrap();
+        assert_eq!(b"Example 1".as_slice(), name.as_slice());
+        assert!(drain_id(&mut monitor_a, CanId::sdo_tx(1)).is_empty());
+        assert!(!drain_id(&mut monitor_b, CanId::sdo_tx(1)).is_empty());
+
+        // A reset sent on both buses is only performed once
+        let reset: CanMessage = NmtCommand {
+            cs: NmtCommandSpecifier::ResetComm,
+            node: 1,
+        }
+        .into();
+        nmt_a.send(reset).await.unwrap();
+        nmt_b.send(reset).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(1, drain_id(&mut monitor_a, CanId::heartbeat(1)).len());
+        assert_eq!(1, drain_id(&mut monitor_b, CanId::heartbeat(1)).len());
+    };
+
+    tokio::select! {
+        _ = runner.run(
+            &mut runner_sender_a,
+            &mut runner_receiver_a,
+            &mut runner_sender_b,
+            &mut runner_receiver_b,
+            Duration::from_millis(1),
+            tokio::time::sleep,
+        ) => panic!("Runner exited"),
+        _ = test_task => (),
+    }
+
+    assert_eq!(1, runner.node().node_id());
+}
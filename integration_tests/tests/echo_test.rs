@@ -0,0 +1,76 @@
+//! Tests for recognizing our own transmissions when the transport echoes them back
+//!
+//! The bus of a `NodeFixture` delivers every frame sent to all receivers, including the receiver
+//! of the client which sent it.
+use std::time::Duration;
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::{
+    common::{messages::CanId, node_id::ConfiguredId, sdo::SdoRequest, traits::AsyncCanSender},
+    testing::NodeFixture,
+    BusManager, SdoClient, SdoClientError,
+};
+
+#[serial]
+#[tokio::test]
+async fn test_sdo_client_echoes() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut client = fixture.sdo_client();
+    let mut other_client = fixture.sender();
+    let mut missing_client = SdoClient::new_std(5, fixture.sender(), fixture.receiver());
+
+    let test_task = async move {
+        // The client's own requests are not taken for another client's
+        client.upload(0x1008, 0).await.unwrap();
+        assert_eq!(0, client.competing_requests());
+
+        // A request from another client on the same COB-ID during a transfer is detected
+        let request = SdoRequest::initiate_upload(0x1018, 1).to_can_message(CanId::sdo_rx(5));
+        let (result, _) = tokio::join!(missing_client.upload_u32(0x1000, 0), async {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            other_client.send(request).await.unwrap();
+        });
+        assert_eq!(Err(SdoClientError::NoResponse), result);
+        assert_eq!(1, missing_client.competing_requests());
+    };
+
+    fixture.run(test_task).await;
+}
+
+#[serial]
+#[tokio::test]
+async fn test_manager_ignores_echoes() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let test_task = async move {
+        // The manager's own heartbeat does not make it appear as a node
+        manager.start_heartbeat(ConfiguredId::new(100).unwrap(), Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        manager.stop_heartbeat();
+        assert!(manager
+            .node_list()
+            .await
+            .iter()
+            .all(|node| node.node_id != 100));
+
+        // SDO transfers through the manager still work
+        assert_eq!(
+            b"Example 1".as_slice(),
+            manager.sdo_client(1).upload(0x1008, 0).await.unwrap()
+        );
+    };
+
+    fixture.run(test_task).await;
+}
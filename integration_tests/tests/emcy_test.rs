@@ -0,0 +1,167 @@
+//! Tests for EMCY message transmission
+//!
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_common::{
+    messages::{CanId, CanMessage},
+    NodeId,
+};
+use zencan_node::{object_dict::find_object, Node};
+
+#[serial]
+#[test]
+fn test_emcy_inhibit_time() {
+    let mut node = Node::new(
+        NodeId::new(1).unwrap(),
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+        &object_dict1::OD_TABLE,
+    );
+    let inhibit_obj = find_object(&object_dict1::OD_TABLE, 0x1015).unwrap();
+    // 10ms inhibit time
+    inhibit_obj.write(0, &100u16.to_le_bytes()).unwrap();
+
+    let mut sent = Vec::new();
+    let mut process = |node: &mut Node, now_us: u64| {
+        let mut emcys = Vec::new();
+        let result = node.process(now_us, &mut |msg: CanMessage| {
+            if msg.id() == CanId::emcy(1) {
+                emcys.push(msg)
+            }
+        });
+        sent.extend_from_slice(&emcys);
+        (emcys, result)
+    };
+
+    // Boot the node
+    process(&mut node, 0);
+
+    node.send_emcy(0x8110, [1, 2, 3, 4, 5]);
+    let (emcys, _) = process(&mut node, 1000);
+    assert_eq!(1, emcys.len());
+    assert_eq!(&[0x10, 0x81, 0, 1, 2, 3, 4, 5], emcys[0].data());
+
+    // Further EMCYs are held until the inhibit time expires, and repeats of an error code are
+    // merged so that only the newest is sent
+    node.send_emcy(0x8110, [0; 5]);
+    node.send_emcy(0x8110, [7; 5]);
+    node.send_emcy(0x3110, [9; 5]);
+    let (emcys, result) = process(&mut node, 5000);
+    assert!(emcys.is_empty());
+    assert_eq!(Some(6000), result.next_action_us);
+    let (emcys, _) = process(&mut node, 11000);
+    assert_eq!(1, emcys.len());
+    assert_eq!(&[0x10, 0x81, 0, 7, 7, 7, 7, 7], emcys[0].data());
+    assert_eq!(1, node.emcy_merged_count());
+
+    // The EMCY with a different error code follows after another inhibit time
+    let (emcys, _) = process(&mut node, 15000);
+    assert!(emcys.is_empty());
+    let (emcys, _) = process(&mut node, 21000);
+    assert_eq!(1, emcys.len());
+    assert_eq!(&[0x10, 0x31, 0, 9, 9, 9, 9, 9], emcys[0].data());
+
+    // With the inhibit time disabled, all EMCYs are sent on the next process call
+    inhibit_obj.write(0, &0u16.to_le_bytes()).unwrap();
+    node.send_emcy(0x1000, [0; 5]);
+    node.send_emcy(0x2000, [0; 5]);
+    let (emcys, _) = process(&mut node, 21100);
+    assert_eq!(2, emcys.len());
+    assert_eq!(5, sent.len());
+}
+
+#[serial]
+#[test]
+fn test_emcy_cob_id() {
+    let mut node = Node::new(
+        NodeId::new(1).unwrap(),
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+        &object_dict1::OD_TABLE,
+    );
+    let cob_id_obj = find_object(&object_dict1::OD_TABLE, 0x1014).unwrap();
+    find_object(&object_dict1::OD_TABLE, 0x1015)
+        .unwrap()
+        .write(0, &0u16.to_le_bytes())
+        .unwrap();
+
+    let process = |node: &mut Node, now_us: u64| {
+        let mut sent = Vec::new();
+        node.process(now_us, &mut |msg: CanMessage| sent.push(msg));
+        sent
+    };
+
+    // Boot the node
+    process(&mut node, 0);
+    assert_eq!(0x81, cob_id_obj.read_u32(0).unwrap());
+
+    cob_id_obj.write(0, &0x123u32.to_le_bytes()).unwrap();
+    node.send_emcy(0x8110, [0; 5]);
+    let sent = process(&mut node, 1000);
+    assert_eq!(1, sent.len());
+    assert_eq!(CanId::std(0x123), sent[0].id());
+
+    // With bit 31 set, EMCYs are not sent
+    cob_id_obj.write(0, &0x8000_0123u32.to_le_bytes()).unwrap();
+    node.send_emcy(0x8110, [0; 5]);
+    assert!(process(&mut node, 2000).is_empty());
+
+    object_dict1::NODE_STATE.cob_ids().set_emcy(None);
+}
+
+#[serial]
+#[test]
+fn test_error_history() {
+    let mut node = Node::new(
+        NodeId::new(1).unwrap(),
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+        &object_dict1::OD_TABLE,
+    );
+    find_object(&object_dict1::OD_TABLE, 0x1015)
+        .unwrap()
+        .write(0, &0u16.to_le_bytes())
+        .unwrap();
+    let history_obj = find_object(&object_dict1::OD_TABLE, 0x1003).unwrap();
+    history_obj.write(0, &[0]).unwrap();
+
+    let process = |node: &mut Node, now_us: u64| {
+        node.process(now_us, &mut |_: CanMessage| {});
+    };
+
+    // Boot the node
+    process(&mut node, 0);
+
+    // Errors are recorded when they are sent, newest first
+    for (i, code) in [0x1000u16, 0x8110, 0x3110, 0x4210, 0x5000]
+        .into_iter()
+        .enumerate()
+    {
+        node.send_emcy(code, [i as u8, 0xA0, 0, 0, 0]);
+        process(&mut node, 1000 * (i as u64 + 1));
+    }
+    // Only the 4 most recent errors are held
+    assert_eq!(4, history_obj.read_u8(0).unwrap());
+    assert_eq!(0xA004_5000, history_obj.read_u32(1).unwrap());
+    assert_eq!(0xA001_8110, history_obj.read_u32(4).unwrap());
+    let history = object_dict1::NODE_STATE.error_history();
+    assert_eq!(Some(0xA003_4210), history.get(1));
+
+    history_obj.write(0, &[0]).unwrap();
+    assert!(history.is_empty());
+
+    // An EMCY which is replaced before it is sent is recorded as well
+    find_object(&object_dict1::OD_TABLE, 0x1015)
+        .unwrap()
+        .write(0, &100u16.to_le_bytes())
+        .unwrap();
+    node.send_emcy(0x1000, [1, 0xB0, 0, 0, 0]);
+    node.send_emcy(0x1000, [2, 0xB0, 0, 0, 0]);
+    process(&mut node, 20_000);
+    assert_eq!(2, history_obj.read_u8(0).unwrap());
+    assert_eq!(0xB002_1000, history_obj.read_u32(1).unwrap());
+    assert_eq!(0xB001_1000, history_obj.read_u32(2).unwrap());
+
+    history_obj.write(0, &[0]).unwrap();
+}
@@ -0,0 +1,108 @@
+//! Tests for failing over between the buses of a redundant network
+//!
+
+use std::time::Duration;
+
+use integration_tests::sim_bus::SimBus;
+use zencan_client::{BusEvent, BusManager, FailoverOptions, FailoverReason, Link};
+use zencan_common::{
+    messages::{CanId, CanMessage},
+    traits::{AsyncCanReceiver, AsyncCanSender},
+};
+
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+fn frame(n: u8) -> CanMessage {
+    CanMessage::new(CanId::std(0x181), &[n])
+}
+
+#[tokio::test]
+async fn test_failover() {
+    let mut primary_bus = SimBus::new(vec![]);
+    let mut backup_bus = SimBus::new(vec![]);
+    let options = FailoverOptions {
+        silence_timeout: Duration::from_millis(50),
+        error_timeout: Duration::from_millis(50),
+    };
+    let mut manager = BusManager::new_redundant(
+        primary_bus.new_sender(),
+        primary_bus.new_receiver(),
+        backup_bus.new_sender(),
+        backup_bus.new_receiver(),
+        options,
+    );
+    let mut events = manager.bus_events();
+    let mut raw = manager.raw_handle();
+    let mut primary_node = primary_bus.new_sender();
+    let mut backup_node = backup_bus.new_sender();
+    assert_eq!(Some(Link::Primary), manager.active_link());
+
+    // A frame seen on both buses is only received once
+    primary_node.send(frame(0)).await.unwrap();
+    backup_node.send(frame(0)).await.unwrap();
+    let msg = tokio::time::timeout(TIMEOUT, raw.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(&[0], msg.data());
+
+    // The primary bus goes silent, while the backup carries on
+    for n in 1..=10 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        backup_node.send(frame(n)).await.unwrap();
+    }
+    let event = tokio::time::timeout(TIMEOUT, events.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let BusEvent::Failover { from, to, reason } = event else {
+        panic!("Expected a failover event, got {event:?}");
+    };
+    assert_eq!((Link::Primary, Link::Backup), (from, to));
+    assert!(matches!(reason, FailoverReason::Silent { .. }));
+    assert_eq!(Some(Link::Backup), manager.active_link());
+
+    // The frames received on the backup while the failure was detected are replayed, in order
+    for n in 1..=10 {
+        let msg = tokio::time::timeout(TIMEOUT, raw.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&[n], msg.data());
+    }
+    assert!(raw.try_recv().is_none());
+
+    // Frames are now sent on the backup bus
+    let mut primary_rx = primary_bus.new_receiver();
+    let mut backup_rx = backup_bus.new_receiver();
+    raw.send(frame(11)).await.unwrap();
+    let msg = tokio::time::timeout(TIMEOUT, backup_rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(&[11], msg.data());
+    assert!(primary_rx.try_recv().is_none());
+
+    // Switch back on request
+    assert!(manager.set_active_link(Link::Primary));
+    assert!(!manager.set_active_link(Link::Primary));
+    let event = tokio::time::timeout(TIMEOUT, events.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(
+        event,
+        BusEvent::Failover {
+            reason: FailoverReason::Requested,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_single_bus_has_no_link() {
+    let mut bus = SimBus::new(vec![]);
+    let manager = BusManager::new(bus.new_sender(), bus.new_receiver());
+    assert_eq!(None, manager.active_link());
+    assert!(!manager.set_active_link(Link::Backup));
+}
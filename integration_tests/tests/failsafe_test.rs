@@ -0,0 +1,252 @@
+//! Tests for the failsafe outputs of a node, and for configuring them from the client
+//!
+
+use std::{sync::Mutex, time::Duration};
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::{
+    common::{node_id::ConfiguredId, CanId},
+    testing::NodeFixture,
+    AnalogFailsafe, BusManager, DigitalFailsafe, FailsafeConfig, FailsafeError,
+};
+use zencan_common::{
+    messages::{CanMessage, Heartbeat, NmtState},
+    NodeId,
+};
+use zencan_node::{
+    failsafe::{Failsafe, FailsafeGroup, FailsafeReason, HeartbeatSlot},
+    object_dict::find_object,
+    Node,
+};
+
+static HEARTBEATS: [HeartbeatSlot; 2] = [const { HeartbeatSlot::new() }; 2];
+static FAILSAFE: Failsafe = Failsafe::new(
+    &[
+        FailsafeGroup::DIGITAL_OUTPUT_8,
+        FailsafeGroup::ANALOG_OUTPUT_16,
+    ],
+    &HEARTBEATS,
+);
+static REASONS: Mutex<Vec<FailsafeReason>> = Mutex::new(Vec::new());
+
+/// The failsafe objects of object_dict1, with their default values
+const DEFAULTS: [(u16, u8, &[u8]); 12] = [
+    (0x6200, 1, &[0]),
+    (0x6200, 2, &[0]),
+    (0x6206, 1, &[0xFF]),
+    (0x6206, 2, &[0xFF]),
+    (0x6207, 1, &[0]),
+    (0x6207, 2, &[0]),
+    (0x6411, 1, &[0, 0]),
+    (0x6411, 2, &[0, 0]),
+    (0x6443, 1, &[1]),
+    (0x6443, 2, &[1]),
+    (0x6444, 1, &[0; 4]),
+    (0x6444, 2, &[0; 4]),
+];
+
+fn write_od(index: u16, sub: u8, data: &[u8]) {
+    find_object(&object_dict1::OD_TABLE, index)
+        .unwrap()
+        .write(sub, data)
+        .unwrap();
+}
+
+fn restore_defaults() {
+    for (index, sub, data) in DEFAULTS {
+        write_od(index, sub, data);
+    }
+    write_od(0x1016, 1, &0u32.to_le_bytes());
+    write_od(0x1016, 2, &0u32.to_le_bytes());
+    write_od(0x1003, 0, &[0]);
+    FAILSAFE.clear();
+    REASONS.lock().unwrap().clear();
+}
+
+fn test_config() -> FailsafeConfig {
+    FailsafeConfig {
+        digital_outputs: vec![DigitalFailsafe {
+            block: 1,
+            error_mode: 0x0F,
+            error_value: 0x05,
+        }],
+        analog_outputs: vec![
+            AnalogFailsafe {
+                channel: 1,
+                error_value: Some(-200),
+            },
+            AnalogFailsafe {
+                channel: 2,
+                error_value: None,
+            },
+        ],
+    }
+}
+
+#[serial]
+#[test]
+fn test_failsafe_outputs() {
+    let od = &object_dict1::OD_TABLE;
+    let mbox = &object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(1).unwrap(), mbox, &object_dict1::NODE_STATE, od);
+    restore_defaults();
+    FAILSAFE.register_callback(&|reason| REASONS.lock().unwrap().push(reason));
+    node.set_failsafe(&FAILSAFE);
+
+    let process = |node: &mut Node, now_us: u64| {
+        let mut sent = Vec::new();
+        node.process(now_us, &mut |msg| sent.push(msg));
+        sent
+    };
+    let heartbeat = |node: u8| -> CanMessage {
+        Heartbeat {
+            node,
+            toggle: false,
+            state: NmtState::Operational,
+        }
+        .into()
+    };
+    let outputs = find_object(od, 0x6200).unwrap();
+    let analog_outputs = find_object(od, 0x6411).unwrap();
+
+    // Monitor node 100 with a 50ms timeout
+    write_od(0x1016, 1, &(100u32 << 16 | 50).to_le_bytes());
+    write_od(0x6206, 1, &[0x0F]);
+    write_od(0x6207, 1, &[0x05]);
+    write_od(0x6443, 2, &[0]);
+    write_od(0x6444, 1, &(-200i32).to_le_bytes());
+    write_od(0x6200, 1, &[0xFA]);
+    write_od(0x6200, 2, &[0xFF]);
+    write_od(0x6411, 1, &1000i16.to_le_bytes());
+    write_od(0x6411, 2, &300i16.to_le_bytes());
+
+    process(&mut node, 0);
+    node.request_state(NmtState::Operational);
+    // Monitoring does not start until the first heartbeat
+    process(&mut node, 1000);
+    process(&mut node, 100_000);
+    assert_eq!(None, FAILSAFE.active());
+
+    mbox.store_message(heartbeat(100)).unwrap();
+    let result = node.process(110_000, &mut |_| {});
+    assert!(result.next_action_us.is_some_and(|t| t <= 50_000));
+    mbox.store_message(heartbeat(100)).unwrap();
+    process(&mut node, 150_000);
+    process(&mut node, 199_000);
+    assert_eq!(None, FAILSAFE.active());
+
+    // The heartbeat times out
+    let sent = process(&mut node, 200_000);
+    let emcy = sent
+        .iter()
+        .find(|msg| msg.id() == CanId::std(0x81))
+        .expect("No EMCY sent");
+    assert_eq!(&[0x30, 0x81], &emcy.data()[0..2]);
+    assert_eq!(100, emcy.data()[3]);
+    assert_eq!(
+        Some(FailsafeReason::HeartbeatLost { node_id: 100 }),
+        FAILSAFE.active()
+    );
+    // Only the outputs selected by the error mode are changed
+    assert_eq!(Ok(0xF5), outputs.read_u8(1));
+    assert_eq!(Ok(0), outputs.read_u8(2));
+    assert_eq!(Ok(-200), analog_outputs.read_i16(1));
+    assert_eq!(Ok(300), analog_outputs.read_i16(2));
+
+    // It is reported once, until the heartbeat is received again
+    process(&mut node, 300_000);
+    assert_eq!(1, REASONS.lock().unwrap().len());
+
+    // Leaving Operational applies the error values again
+    FAILSAFE.clear();
+    write_od(0x6200, 1, &[0xF0]);
+    node.request_state(NmtState::PreOperational);
+    assert_eq!(
+        Some(FailsafeReason::LeftOperational(NmtState::PreOperational)),
+        FAILSAFE.active()
+    );
+    assert_eq!(Ok(0xF5), outputs.read_u8(1));
+    assert_eq!(
+        vec![
+            FailsafeReason::HeartbeatLost { node_id: 100 },
+            FailsafeReason::LeftOperational(NmtState::PreOperational)
+        ],
+        *REASONS.lock().unwrap()
+    );
+
+    restore_defaults();
+    process(&mut node, 400_000);
+}
+
+#[serial]
+#[tokio::test]
+async fn test_configure_failsafe() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    restore_defaults();
+    fixture.node_mut().set_failsafe(&FAILSAFE);
+    let mut client = fixture.sdo_client();
+    let mut rx = fixture.receiver();
+    let mut manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let test_task = async move {
+        let config = test_config();
+        let results = manager
+            .configure_failsafe(&[1], &[(100, Duration::from_millis(50))], &config)
+            .await;
+        assert_eq!(vec![(1, Ok(()))], results);
+        assert_eq!(0x0064_0032, client.upload_u32(0x1016, 1).await.unwrap());
+        assert_eq!(0x0F, client.upload_u8(0x6206, 1).await.unwrap());
+        assert_eq!(0, client.upload_u8(0x6443, 2).await.unwrap());
+        assert_eq!(Ok(()), client.verify_failsafe(&config).await);
+
+        // Outputs which the node does not have are rejected before anything is written
+        let mut bad_config = config.clone();
+        bad_config.analog_outputs[0].channel = 3;
+        assert_eq!(
+            Err(FailsafeError::NoSuchOutput {
+                index: 0x6444,
+                sub: 3,
+                available: 2
+            }),
+            client.configure_failsafe(&bad_config).await
+        );
+        assert_eq!(-200, client.upload_i32(0x6444, 1).await.unwrap());
+
+        // The node applies the configuration when the manager's heartbeat stops
+        client.download_u8(0x6200, 1, 0xFA).await.unwrap();
+        client
+            .download(0x6411, 1, &1000i16.to_le_bytes())
+            .await
+            .unwrap();
+        manager.nmt_start(1).await;
+        manager.start_heartbeat(ConfiguredId::new(100).unwrap(), Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(None, FAILSAFE.active());
+        manager.stop_heartbeat();
+        let emcy = rx
+            .expect(CanId::std(0x81), Duration::from_millis(500))
+            .await
+            .expect("No EMCY from node");
+        assert_eq!(&[0x30, 0x81], &emcy.data()[0..2]);
+        assert_eq!(100, emcy.data()[3]);
+        assert_eq!(0xF5, client.upload_u8(0x6200, 1).await.unwrap());
+        assert_eq!(-200, client.upload_i16(0x6411, 1).await.unwrap());
+
+        manager.nmt_stop(1).await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(
+            Some(FailsafeReason::LeftOperational(NmtState::Stopped)),
+            FAILSAFE.active()
+        );
+    };
+
+    fixture.run(test_task).await;
+    restore_defaults();
+    fixture.node_mut().request_state(NmtState::PreOperational);
+}
@@ -0,0 +1,84 @@
+use core::ffi::c_void;
+
+use integration_tests::object_dict1;
+use zencan_common::{sdo::AbortCode, NodeId};
+use zencan_node::Node;
+use zencan_node_ffi::{
+    zencan_node_handle_frame, zencan_node_id, zencan_node_nmt_state, zencan_node_process,
+    zencan_node_read_object, zencan_node_write_object, ZencanFrame, ZencanNode, ZENCAN_OK,
+};
+
+unsafe extern "C" fn collect_frame(ctx: *mut c_void, frame: *const ZencanFrame) {
+    let frames = &mut *(ctx as *mut Vec<ZencanFrame>);
+    frames.push(*frame);
+}
+
+#[serial_test::serial]
+#[test]
+fn test_ffi_node() {
+    let node = Node::new(
+        NodeId::new(3).unwrap(),
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+        &object_dict1::OD_TABLE,
+    );
+    let mut node = ZencanNode::new(node, &object_dict1::NODE_MBOX, &object_dict1::OD_TABLE);
+    let node: *mut ZencanNode = &mut node;
+    let mut frames: Vec<ZencanFrame> = Vec::new();
+    let ctx = &mut frames as *mut Vec<ZencanFrame> as *mut c_void;
+
+    unsafe {
+        assert_eq!(3, zencan_node_id(node));
+
+        // The boot-up message is sent on the first process
+        zencan_node_process(node, 0, Some(collect_frame), ctx);
+        assert_eq!(0x703, frames[0].id);
+        assert_eq!(&[0], &frames[0].data[..frames[0].dlc as usize]);
+        assert_eq!(127, zencan_node_nmt_state(node));
+
+        // An NMT start command puts the node into operational
+        let start = ZencanFrame {
+            id: 0,
+            flags: 0,
+            dlc: 2,
+            data: [1, 3, 0, 0, 0, 0, 0, 0],
+        };
+        assert!(zencan_node_handle_frame(node, &start));
+        zencan_node_process(node, 1000, Some(collect_frame), ctx);
+        assert_eq!(5, zencan_node_nmt_state(node));
+
+        // Objects can be written and read back
+        let value = 0x12345678u32.to_le_bytes();
+        assert_eq!(
+            ZENCAN_OK,
+            zencan_node_write_object(node, 0x3000, 0, value.as_ptr(), value.len())
+        );
+        let mut buf = [0u8; 8];
+        let mut len = 0;
+        assert_eq!(
+            ZENCAN_OK,
+            zencan_node_read_object(node, 0x3000, 0, buf.as_mut_ptr(), buf.len(), &mut len)
+        );
+        assert_eq!(&value, &buf[..len]);
+
+        // A buffer which is too small reports the required size
+        assert_eq!(
+            AbortCode::DataTypeMismatchLengthHigh as u32,
+            zencan_node_read_object(node, 0x2002, 0, buf.as_mut_ptr(), 4, &mut len)
+        );
+        assert_eq!("Some String".len(), len);
+
+        assert_eq!(
+            AbortCode::NoSuchObject as u32,
+            zencan_node_read_object(node, 0x4FFF, 0, buf.as_mut_ptr(), buf.len(), &mut len)
+        );
+        assert_eq!(
+            AbortCode::DataTypeMismatchLengthLow as u32,
+            zencan_node_write_object(node, 0x3000, 0, value.as_ptr(), 2)
+        );
+
+        // Restore the default value for other tests
+        let zero = [0u8; 4];
+        zencan_node_write_object(node, 0x3000, 0, zero.as_ptr(), zero.len());
+    }
+}
@@ -0,0 +1,69 @@
+//! Tests for the zencan-client test fixtures
+//!
+
+use std::time::Duration;
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::{testing::NodeFixture, PdoConfig, PdoMapping};
+use zencan_common::{
+    messages::{CanId, NmtState},
+    traits::AsyncCanReceiver,
+};
+
+#[serial]
+#[tokio::test]
+async fn test_fixture_sdo_and_tpdo() {
+    const TPDO_COB_ID: u32 = 0x181;
+
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut client = fixture.sdo_client();
+    let mut rx = fixture.receiver();
+
+    fixture.node_mut().request_state(NmtState::Operational);
+
+    let test_task = async move {
+        client.download_u32(0x3101, 1, 3).await.unwrap();
+        assert_eq!(3, client.upload_u32(0x3101, 1).await.unwrap());
+
+        let config = PdoConfig {
+            cob: TPDO_COB_ID,
+            enabled: true,
+            mappings: vec![PdoMapping {
+                index: 0x3101,
+                sub: 1,
+                size: 32,
+            }],
+            transmission_type: 254,
+        };
+        client.configure_tpdo(0, &config).await.unwrap();
+
+        rx.flush();
+        object_dict1::OBJECT3101.set_counter_notify(5);
+        let msg = rx
+            .expect(CanId::std(TPDO_COB_ID as u16), Duration::from_millis(20))
+            .await
+            .expect("TPDO was not sent");
+        assert_eq!(&5u32.to_le_bytes(), &msg.data()[..4]);
+
+        // Restore the default value, and disable the PDO
+        client.download_u32(0x3101, 1, 7).await.unwrap();
+        client
+            .download_u32(0x1800, 1, TPDO_COB_ID | 1 << 31)
+            .await
+            .unwrap();
+        // No further TPDOs once disabled
+        object_dict1::OBJECT3101.set_counter_notify(7);
+        assert!(rx
+            .expect(CanId::std(TPDO_COB_ID as u16), Duration::from_millis(5))
+            .await
+            .is_none());
+    };
+
+    fixture.run(test_task).await;
+}
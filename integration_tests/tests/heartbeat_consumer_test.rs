@@ -0,0 +1,177 @@
+//! Tests for configuring heartbeat producers and consumers from the client, and for the heartbeat
+//! produced by the client itself
+//!
+
+use std::time::Duration;
+
+use integration_tests::{manager::manager_client, object_dict1};
+use serial_test::serial;
+use zencan_client::{
+    common::{node_id::ConfiguredId, traits::AsyncCanReceiver, CanId},
+    testing::NodeFixture,
+    BusManager, HeartbeatConsumerError, ManagerHeartbeat, NodeConfig,
+};
+
+#[serial]
+#[tokio::test]
+async fn test_configure_heartbeat_consumers() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let test_task = async move {
+        let client = || manager_client(&manager, 1);
+        manager
+            .configure_heartbeat_consumers(
+                1,
+                &[(5, Duration::from_millis(500)), (6, Duration::from_secs(2))],
+            )
+            .await
+            .unwrap();
+        assert_eq!(2, client().upload_u8(0x1016, 0).await.unwrap());
+        assert_eq!(0x0005_01F4, client().upload_u32(0x1016, 1).await.unwrap());
+        assert_eq!(0x0006_07D0, client().upload_u32(0x1016, 2).await.unwrap());
+
+        // Reconfiguring with fewer peers clears the unused entries
+        manager
+            .configure_heartbeat_consumers(1, &[(6, Duration::from_millis(100))])
+            .await
+            .unwrap();
+        assert_eq!(0x0006_0064, client().upload_u32(0x1016, 1).await.unwrap());
+        assert_eq!(0, client().upload_u32(0x1016, 2).await.unwrap());
+
+        let result = manager
+            .configure_heartbeat_consumers(
+                1,
+                &[
+                    (2, Duration::from_millis(100)),
+                    (3, Duration::from_millis(100)),
+                    (4, Duration::from_millis(100)),
+                ],
+            )
+            .await;
+        assert_eq!(
+            Err(HeartbeatConsumerError::TooManyPeers {
+                node_id: 1,
+                requested: 3,
+                available: 2
+            }),
+            result
+        );
+
+        // Restore the default
+        manager.configure_heartbeat_consumers(1, &[]).await.unwrap();
+        assert_eq!(0, client().upload_u32(0x1016, 1).await.unwrap());
+    };
+
+    fixture.run(test_task).await;
+}
+
+#[serial]
+#[tokio::test]
+async fn test_apply_heartbeat_producer_time() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+    let mut rx = fixture.receiver();
+
+    let test_task = async move {
+        let client = || manager_client(&manager, 1);
+        let heartbeat_id = CanId::heartbeat(1);
+        let original_period = client().upload_u16(0x1017, 0).await.unwrap();
+        assert_eq!(0, original_period);
+
+        let config = NodeConfig::load_from_str("heartbeat_producer_time = 20").unwrap();
+        manager.apply_node_config(1, &config, true).await.unwrap();
+        assert_eq!(20, client().upload_u16(0x1017, 0).await.unwrap());
+
+        // The running node picks up the new period without a restart
+        let mut last_time = None;
+        for _ in 0..3 {
+            rx.expect(heartbeat_id, Duration::from_millis(200))
+                .await
+                .expect("No heartbeat from node");
+            let now = tokio::time::Instant::now();
+            if let Some(last_time) = last_time {
+                assert!(now - last_time < Duration::from_millis(100));
+            }
+            last_time = Some(now);
+        }
+
+        // A period of 0 stops the heartbeat
+        client()
+            .download_u16(0x1017, 0, original_period)
+            .await
+            .unwrap();
+        while rx.try_recv().is_some() {}
+        assert!(rx
+            .expect(heartbeat_id, Duration::from_millis(100))
+            .await
+            .is_none());
+    };
+
+    fixture.run(test_task).await;
+}
+
+#[serial]
+#[tokio::test]
+async fn test_manager_heartbeat() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+    let mut rx = fixture.receiver();
+
+    let test_task = async move {
+        let node_id = ConfiguredId::new(100).unwrap();
+        let heartbeat_id = CanId::std(0x700 + 100);
+        assert_eq!(None, manager.heartbeat());
+        assert!(!manager.set_heartbeat_period(Duration::from_millis(10)));
+
+        manager.start_heartbeat(node_id, Duration::from_millis(20));
+        assert_eq!(
+            Some(ManagerHeartbeat {
+                node_id,
+                period: Duration::from_millis(20)
+            }),
+            manager.heartbeat()
+        );
+        for _ in 0..3 {
+            let msg = rx
+                .expect(heartbeat_id, Duration::from_millis(200))
+                .await
+                .expect("No heartbeat from manager");
+            // Operational
+            assert_eq!(&[5], msg.data());
+        }
+
+        assert!(manager.set_heartbeat_period(Duration::from_millis(30)));
+        assert_eq!(
+            Duration::from_millis(30),
+            manager.heartbeat().unwrap().period
+        );
+
+        assert!(manager.stop_heartbeat());
+        assert!(!manager.stop_heartbeat());
+        assert_eq!(None, manager.heartbeat());
+        // Discard any heartbeat sent before it was stopped
+        while rx.try_recv().is_some() {}
+        assert!(rx
+            .expect(heartbeat_id, Duration::from_millis(100))
+            .await
+            .is_none());
+    };
+
+    fixture.run(test_task).await;
+}
@@ -0,0 +1,179 @@
+//! Interop tests against reference CANopen implementations
+//!
+//! These check zencan-client against a CANopenNode based slave (`canopend`, from CANopenLinux), and
+//! zencan-node against a python-canopen master, over a virtual CAN interface. They need the
+//! reference implementations and a vcan interface, so they are ignored by default.
+//! `integration_tests/interop/run.sh` builds the references and runs them; see
+//! `integration_tests/interop/README.md`.
+//!
+//! Environment variables:
+//! - `ZENCAN_INTEROP_IFACE`: The interface to use (default `vcan0`)
+//! - `ZENCAN_CANOPEND`: The path of the `canopend` binary (default `canopend`)
+//! - `ZENCAN_PYTHON`: The python interpreter with python-canopen installed (default `python3`)
+#![cfg(target_os = "linux")]
+
+use std::{
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::{
+    common::{
+        messages::NmtState,
+        traits::{AsyncCanReceiver, AsyncCanSender},
+        NodeId,
+    },
+    open_transport, BusManager,
+};
+use zencan_node::Node;
+
+/// The node ID given to the CANopenNode slave
+const CANOPEND_NODE_ID: u8 = 4;
+/// The node ID given to the zencan node tested by python-canopen
+const ZENCAN_NODE_ID: u8 = 16;
+
+fn env_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+fn interop_iface() -> String {
+    env_or("ZENCAN_INTEROP_IFACE", "vcan0")
+}
+
+/// A child process which is killed when dropped, so that a failed test does not leave it running
+struct ChildGuard(Child);
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        self.0.kill().ok();
+        self.0.wait().ok();
+    }
+}
+
+#[serial]
+#[tokio::test]
+#[ignore = "requires vcan and CANopenLinux, see integration_tests/interop/README.md"]
+async fn test_client_with_canopennode_slave() {
+    let iface = interop_iface();
+    let canopend = env_or("ZENCAN_CANOPEND", "canopend");
+    // Keep the slave's storage files out of the source tree
+    let storage = std::env::temp_dir().join("zencan-interop-canopend-");
+    let _slave = ChildGuard(
+        Command::new(&canopend)
+            .arg(&iface)
+            .args(["-i", &CANOPEND_NODE_ID.to_string()])
+            .arg("-s")
+            .arg(&storage)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap_or_else(|e| panic!("Failed to run {canopend}: {e}")),
+    );
+
+    let (tx, rx) = open_transport(&iface).unwrap();
+    let mut manager = BusManager::new(tx, rx);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // Scan
+    let nodes = manager.scan_nodes().await;
+    let info = nodes
+        .iter()
+        .find(|n| n.node_id == CANOPEND_NODE_ID)
+        .expect("CANopenNode slave not found by scan");
+
+    // SDO: expedited and segmented uploads, and expedited download
+    let mut device = manager.device(CANOPEND_NODE_ID);
+    let identity = device.identity().await.unwrap();
+    assert_eq!(Some(identity), info.identity);
+    device.device_type().await.unwrap();
+    assert!(!device.name().await.unwrap().is_empty());
+    device.write((0x1017, 0), 100u16).await.unwrap();
+    assert_eq!(100, device.read::<u16>((0x1017, 0)).await.unwrap());
+
+    // PDO: the default communication parameters use the predefined connection set
+    let tpdo_cob_id: u32 = device.read((0x1800, 1)).await.unwrap();
+    assert_eq!(0x180 + CANOPEND_NODE_ID as u32, tpdo_cob_id & 0x7FF);
+    let rpdo_cob_id: u32 = device.read((0x1400, 1)).await.unwrap();
+    assert_eq!(0x200 + CANOPEND_NODE_ID as u32, rpdo_cob_id & 0x7FF);
+
+    // NMT, observed through the heartbeats enabled above
+    for (stop, expected) in [(true, NmtState::Stopped), (false, NmtState::Operational)] {
+        if stop {
+            assert!(device.stop().await);
+        } else {
+            assert!(device.start().await);
+        }
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while device.nmt_state().await != Some(expected) {
+            assert!(
+                Instant::now() < deadline,
+                "Slave did not report {expected:?}, last state {:?}",
+                device.nmt_state().await
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    // LSS
+    let identities = manager.lss_fastscan(Duration::from_millis(20)).await;
+    assert_eq!(vec![identity], identities);
+}
+
+#[serial]
+#[tokio::test]
+#[ignore = "requires vcan and python-canopen, see integration_tests/interop/README.md"]
+async fn test_node_with_python_canopen_master() {
+    let iface = interop_iface();
+    let python = env_or("ZENCAN_PYTHON", "python3");
+    let script = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("interop/python_master.py");
+
+    let (mut tx, mut rx) = open_transport(&iface).unwrap();
+    let mut node = Node::new(
+        NodeId::new(ZENCAN_NODE_ID).unwrap(),
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+        &object_dict1::OD_TABLE,
+    );
+
+    let master = tokio::task::spawn_blocking(move || {
+        Command::new(&python)
+            .arg(script)
+            .args(["--channel", &iface])
+            .args(["--node-id", &ZENCAN_NODE_ID.to_string()])
+            .output()
+            .unwrap_or_else(|e| panic!("Failed to run {python}: {e}"))
+    });
+    let receive = async {
+        loop {
+            match rx.recv().await {
+                Ok(msg) => {
+                    object_dict1::NODE_MBOX.store_message(msg).ok();
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        }
+    };
+    let process = async {
+        let epoch = Instant::now();
+        loop {
+            let now_us = epoch.elapsed().as_micros() as u64;
+            let mut messages = Vec::new();
+            node.process(now_us, &mut |msg| messages.push(msg));
+            for msg in messages {
+                tx.send(msg).await.ok();
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    };
+
+    let output = tokio::select! {
+        _ = receive => unreachable!(),
+        _ = process => unreachable!(),
+        output = master => output.unwrap(),
+    };
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.status.success(), "python-canopen checks failed");
+}
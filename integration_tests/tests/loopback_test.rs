@@ -0,0 +1,68 @@
+//! Tests for the loopback self-test object (0x5009)
+//!
+use std::time::Duration;
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::testing::NodeFixture;
+use zencan_common::{
+    loopback_test::{LoopbackStatus, TEST_FRAME_ID},
+    messages::CanMessage,
+    traits::{AsyncCanReceiver, AsyncCanSender},
+};
+
+#[serial]
+#[tokio::test]
+async fn test_loopback_test() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut client = fixture.sdo_client();
+    let mut sender = fixture.sender();
+    let mut rx = fixture.receiver();
+    let poll_interval = Duration::from_millis(5);
+
+    let test_task = async move {
+        // The test bus does not deliver the node's own frames back to it, so echo the test frame
+        let (status, echoed) = tokio::join!(client.run_loopback_test(poll_interval), async {
+            let msg = rx
+                .expect(TEST_FRAME_ID, Duration::from_millis(100))
+                .await
+                .expect("No test frame sent");
+            sender.send(msg).await.unwrap();
+            msg
+        });
+        assert_eq!(LoopbackStatus::Passed, status.unwrap());
+        assert_eq!(1, echoed.data()[0]);
+
+        // A corrupted echo fails the test. The echo of the first test is discarded first.
+        rx.flush();
+        let (status, _) = tokio::join!(client.run_loopback_test(poll_interval), async {
+            let msg = rx
+                .expect(TEST_FRAME_ID, Duration::from_millis(100))
+                .await
+                .expect("No test frame sent");
+            let mut data = [0; 8];
+            data.copy_from_slice(msg.data());
+            data[5] = !data[5];
+            sender
+                .send(CanMessage::new(TEST_FRAME_ID, &data))
+                .await
+                .unwrap();
+        });
+        assert_eq!(LoopbackStatus::Corrupted, status.unwrap());
+
+        // Without an echo, the test times out
+        let status = client.run_loopback_test(poll_interval).await.unwrap();
+        assert_eq!(LoopbackStatus::NoEcho, status);
+        assert_eq!(
+            LoopbackStatus::NoEcho as u8,
+            client.upload_u8(0x5009, 2).await.unwrap()
+        );
+    };
+
+    fixture.run(test_task).await;
+}
@@ -1,10 +1,10 @@
-use std::time::Duration;
+use std::{sync::Mutex, time::Duration};
 
 use assertables::assert_contains;
 use integration_tests::{object_dict1, object_dict2, sim_bus::SimBus};
 use zencan_client::LssMaster;
 use zencan_common::{lss::LssIdentity, NodeId};
-use zencan_node::Node;
+use zencan_node::{LssAssignment, Node};
 
 use serial_test::serial;
 
@@ -91,3 +91,109 @@ async fn test_fast_scan() {
     )
     .await;
 }
+
+#[serial]
+#[tokio::test]
+async fn test_auto_assign() {
+    let (mbox1, state1, od1) = (
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+        &object_dict1::OD_TABLE,
+    );
+    let (mbox2, state2, od2) = (
+        &object_dict2::NODE_MBOX,
+        &object_dict2::NODE_STATE,
+        &object_dict2::OD_TABLE,
+    );
+    object_dict1::OBJECT1018.set_serial(9999);
+    object_dict2::OBJECT1018.set_serial(5432);
+
+    let mut node1 = Node::new(NodeId::new(255).unwrap(), mbox1, state1, od1);
+    let mut node2 = Node::new(NodeId::new(255).unwrap(), mbox2, state2, od2);
+
+    let mut bus = SimBus::new(vec![mbox1, mbox2]);
+
+    let _logger = BusLogger::new(bus.new_receiver());
+
+    const TIMEOUT: Duration = Duration::from_millis(5);
+
+    test_with_background_process(
+        &mut [&mut node1, &mut node2],
+        &mut bus.new_sender(),
+        async move {
+            let mut lss_master = LssMaster::new(bus.new_sender(), bus.new_receiver());
+
+            // Only one valid ID available; the second device must be reported as unassigned
+            let report = lss_master.auto_assign(0..=1, TIMEOUT).await;
+            assert_eq!(1, report.assigned.len());
+            assert_eq!(1, report.unassigned.len());
+            assert!(report.failed.is_empty());
+            assert_eq!(NodeId::new(1).unwrap(), report.assigned[0].node_id);
+
+            // Running again finds only the remaining unconfigured device
+            let report = lss_master.auto_assign(20..=30, TIMEOUT).await;
+            assert_eq!(1, report.assigned.len());
+            assert!(report.unassigned.is_empty());
+            assert_eq!(NodeId::new(20).unwrap(), report.assigned[0].node_id);
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let mut assigned = [state1.snapshot().node_id, state2.snapshot().node_id];
+            assigned.sort_by_key(|id| id.raw());
+            assert_eq!(
+                [NodeId::new(1).unwrap(), NodeId::new(20).unwrap()],
+                assigned
+            );
+        },
+    )
+    .await;
+}
+
+#[serial]
+#[tokio::test]
+async fn test_lss_assignment_callback() {
+    static ASSIGNMENTS: Mutex<Vec<LssAssignment>> = Mutex::new(Vec::new());
+    fn record_assignment(assignment: LssAssignment) {
+        ASSIGNMENTS.lock().unwrap().push(assignment);
+    }
+
+    let (mbox, state, od) = (
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+        &object_dict1::OD_TABLE,
+    );
+    object_dict1::OBJECT1018.set_serial(1111);
+
+    let mut node = Node::new(NodeId::Unconfigured, mbox, state, od);
+    node.register_lss_assignment_callback(&record_assignment);
+
+    let mut bus = SimBus::new(vec![mbox]);
+    let _logger = BusLogger::new(bus.new_receiver());
+
+    test_with_background_process(&mut [&mut node], &mut bus.new_sender(), async move {
+        let mut lss_master = LssMaster::new(bus.new_sender(), bus.new_receiver());
+
+        // The unconfigured node must still respond to selective switching
+        lss_master
+            .enter_config_by_identity(1234, 12000, 1, 1111)
+            .await
+            .expect("Node did not respond to switch state selective");
+        // Only the standard bit timing table is supported
+        assert!(lss_master.set_baud_rate(128, 0).await.is_err());
+        lss_master.set_baud_rate(0, 3).await.unwrap();
+        lss_master
+            .set_node_id(NodeId::new(20).unwrap())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    })
+    .await;
+
+    assert_eq!(20, node.node_id());
+    assert_eq!(
+        vec![
+            LssAssignment::BitTiming { table: 0, index: 3 },
+            LssAssignment::NodeId(NodeId::new(20).unwrap()),
+        ],
+        *ASSIGNMENTS.lock().unwrap()
+    );
+}
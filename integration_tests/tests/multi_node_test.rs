@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use integration_tests::{object_dict1, object_dict2, sim_bus::SimBus};
+use serial_test::serial;
+use zencan_client::SdoClient;
+use zencan_common::NodeId;
+use zencan_node::{MultiNodeRunner, Node};
+
+#[serial]
+#[tokio::test]
+async fn test_multi_node_runner() {
+    let mut runner = MultiNodeRunner::new();
+    runner.add_node(Node::new(
+        NodeId::new(1).unwrap(),
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+        &object_dict1::OD_TABLE,
+    ));
+    runner.add_node(Node::new(
+        NodeId::new(2).unwrap(),
+        &object_dict2::NODE_MBOX,
+        &object_dict2::NODE_STATE,
+        &object_dict2::OD_TABLE,
+    ));
+
+    // The runner shares a single transport between the two nodes
+    let mut bus = SimBus::new(vec![]);
+    let mut runner_sender = bus.new_sender();
+    let mut runner_receiver = bus.new_receiver();
+    let mut client1 = SdoClient::new_std(1, bus.new_sender(), bus.new_receiver());
+    let mut client2 = SdoClient::new_std(2, bus.new_sender(), bus.new_receiver());
+
+    let test_task = async {
+        // Each request must be routed to the node with the matching ID
+        let name1 = client1.upload(0x1008, 0).await.unwrap();
+        let name2 = client2.upload(0x1008, 0).await.unwrap();
+        assert_eq!(b"Example 1".as_slice(), name1.as_slice());
+        assert_eq!(b"Bootload Example".as_slice(), name2.as_slice());
+
+        let mut client3 = SdoClient::new_std(3, bus.new_sender(), bus.new_receiver());
+        assert!(client3.upload(0x1008, 0).await.is_err());
+    };
+
+    tokio::select! {
+        _ = runner.run(
+            &mut runner_sender,
+            &mut runner_receiver,
+            Duration::from_millis(1),
+            tokio::time::sleep,
+        ) => panic!("Runner exited"),
+        _ = test_task => (),
+    }
+
+    assert_eq!(2, runner.nodes().len());
+    assert!(runner.node_by_id(2).is_some());
+}
@@ -1,11 +1,18 @@
-use zencan_common::{messages::NmtState, traits::AsyncCanSender, NodeId};
+use std::{cell::RefCell, time::Duration};
+
+use zencan_common::{
+    constants::values::NMT_UNLOCK_CMD,
+    messages::{CanId, CanMessage, NmtCommand, NmtCommandSpecifier, NmtState},
+    traits::AsyncCanSender,
+    NodeId,
+};
 
 use integration_tests::sim_bus::SimBus;
 use zencan_client::nmt_master::NmtMaster;
-use zencan_node::Node;
+use zencan_node::{object_dict::find_object, Node};
 
 mod utils;
-use utils::BusLogger;
+use utils::{test_with_background_process, BusLogger};
 
 use serial_test::serial;
 
@@ -57,3 +64,172 @@ async fn test_nmt_init() {
     assert_eq!(NmtState::Stopped, node.nmt_state());
     assert_eq!(2, node.rx_message_count());
 }
+
+#[serial]
+#[tokio::test]
+async fn test_request_state() {
+    let od = &integration_tests::object_dict1::OD_TABLE;
+    let state = &integration_tests::object_dict1::NODE_STATE;
+    let mbox = &integration_tests::object_dict1::NODE_MBOX;
+
+    // An unconfigured node cannot change state
+    let mut node = Node::new(NodeId::Unconfigured, mbox, state, od);
+    node.process(0, &mut |_| {});
+    assert!(!node.request_state(NmtState::Operational));
+    assert_eq!(NmtState::PreOperational, node.nmt_state());
+
+    let mut node = Node::new(NodeId::new(1).unwrap(), mbox, state, od);
+    let mut bus = SimBus::new(vec![mbox]);
+    let _logger = BusLogger::new(bus.new_receiver());
+    let sender = bus.new_sender();
+    let receiver = bus.new_receiver();
+    let mut master = NmtMaster::new(sender, receiver);
+    let mut sender = bus.new_sender();
+    let mut sender_fn = |tx_msg| {
+        futures::executor::block_on(sender.send(tx_msg)).unwrap();
+    };
+
+    node.process(0, &mut sender_fn);
+    assert_eq!(NmtState::PreOperational, node.nmt_state());
+
+    assert!(node.request_state(NmtState::Operational));
+    assert_eq!(NmtState::Operational, node.nmt_state());
+    node.process(1000, &mut sender_fn);
+    assert_eq!(NmtState::Operational, state.snapshot().nmt_state);
+
+    assert!(node.request_state(NmtState::Stopped));
+    assert_eq!(NmtState::Stopped, node.nmt_state());
+
+    // A reset returns to PreOperational, and sends a boot up message
+    assert!(node.request_state(NmtState::Bootup));
+    node.process(2000, &mut sender_fn);
+    assert_eq!(NmtState::PreOperational, node.nmt_state());
+    let nodes = master.get_nodes();
+    assert_eq!(1, nodes.len());
+    assert_eq!(NmtState::PreOperational, nodes[0].state);
+}
+
+#[serial]
+#[tokio::test]
+async fn test_emergency_stop() {
+    const RPDO_COB_ID: u16 = 0x202;
+    let od = &integration_tests::object_dict1::OD_TABLE;
+    let state = &integration_tests::object_dict1::NODE_STATE;
+    let mbox = &integration_tests::object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(1).unwrap(), mbox, state, od);
+    let mut bus = SimBus::new(vec![mbox]);
+    let _logger = BusLogger::new(bus.new_receiver());
+    let mut master = NmtMaster::new(bus.new_sender(), bus.new_receiver());
+    let mut sender = bus.new_sender();
+
+    // Configure RPDO1 to map 0x2000sub1, which holds the "output" put into a safe state
+    let obj = find_object(od, 0x2000).unwrap();
+    let orig_sub1 = obj.read_u32(1).unwrap();
+    let comm = find_object(od, 0x1401).unwrap();
+    comm.write(1, &(RPDO_COB_ID as u32).to_le_bytes()).unwrap();
+    let mapping = find_object(od, 0x1601).unwrap();
+    mapping
+        .write(1, &((0x2000u32 << 16) | (1 << 8) | 32).to_le_bytes())
+        .unwrap();
+    mapping.write(0, &[1]).unwrap();
+
+    let test_task = async move {
+        master.nmt_start(0).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let safe_state = [CanMessage::new(
+            CanId::std(RPDO_COB_ID),
+            &0xABCDu32.to_le_bytes(),
+        )];
+        master
+            .emergency_stop(&safe_state, Duration::from_millis(10))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    test_with_background_process(&mut [&mut node], &mut sender, test_task).await;
+
+    // The RPDO was processed before the node stopped
+    assert_eq!(NmtState::Stopped, node.nmt_state());
+    assert_eq!(0xABCD, obj.read_u32(1).unwrap());
+
+    // Disable the PDO and restore the value for other tests
+    comm.write(1, &(RPDO_COB_ID as u32 | 1 << 31).to_le_bytes())
+        .unwrap();
+    obj.write(1, &orig_sub1.to_le_bytes()).unwrap();
+}
+
+#[serial]
+#[test]
+fn test_nmt_protection() {
+    let od = &integration_tests::object_dict1::OD_TABLE;
+    let state = &integration_tests::object_dict1::NODE_STATE;
+    let mbox = &integration_tests::object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(1).unwrap(), mbox, state, od);
+    find_object(od, 0x1015)
+        .unwrap()
+        .write(0, &0u16.to_le_bytes())
+        .unwrap();
+
+    let emcys = RefCell::new(Vec::new());
+    let process = |node: &mut Node, now_us: u64| {
+        node.process(now_us, &mut |msg: CanMessage| {
+            if msg.id() == CanId::emcy(1) {
+                emcys.borrow_mut().push(msg)
+            }
+        });
+    };
+    let reset_app: CanMessage = NmtCommand {
+        cs: NmtCommandSpecifier::ResetApp,
+        node: 1,
+    }
+    .into();
+
+    process(&mut node, 0);
+    assert!(node.request_state(NmtState::Operational));
+
+    // The protected command is ignored while operational, and reported with an EMCY
+    mbox.store_message(reset_app).unwrap();
+    process(&mut node, 1000);
+    process(&mut node, 2000);
+    assert_eq!(NmtState::Operational, node.nmt_state());
+    assert_eq!(1, emcys.borrow().len());
+    assert_eq!(&[0x01, 0xFF], &emcys.borrow()[0].data()[..2]);
+    assert_eq!(
+        &[
+            NmtCommandSpecifier::ResetApp as u8,
+            NmtState::Operational as u8
+        ],
+        &emcys.borrow()[0].data()[3..5]
+    );
+
+    // Unprotected commands are accepted
+    mbox.store_message(
+        NmtCommand {
+            cs: NmtCommandSpecifier::Stop,
+            node: 1,
+        }
+        .into(),
+    )
+    .unwrap();
+    process(&mut node, 3000);
+    assert_eq!(NmtState::Stopped, node.nmt_state());
+    assert!(node.request_state(NmtState::Operational));
+
+    // An unlock accepts the command once
+    let unlock = find_object(od, 0x500E).unwrap();
+    unlock.write(0, &NMT_UNLOCK_CMD.to_le_bytes()).unwrap();
+    assert_eq!(1, unlock.read_u32(0).unwrap());
+    process(&mut node, 4000);
+    mbox.store_message(reset_app).unwrap();
+    process(&mut node, 5000);
+    process(&mut node, 6000);
+    assert_eq!(NmtState::PreOperational, node.nmt_state());
+    assert_eq!(0, unlock.read_u32(0).unwrap());
+
+    // The command is accepted in PreOperational without an unlock
+    mbox.store_message(reset_app).unwrap();
+    process(&mut node, 7000);
+    process(&mut node, 8000);
+    assert_eq!(1, emcys.borrow().len());
+}
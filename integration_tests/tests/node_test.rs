@@ -1,12 +1,22 @@
 use std::{
     convert::Infallible,
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
     time::Duration,
 };
 
 use integration_tests::object_dict1;
-use zencan_client::{RawAbortCode, SdoClientError};
-use zencan_common::sdo::AbortCode;
+use zencan_client::{debug_log::DebugLogTail, RawAbortCode, SdoClientError};
+use zencan_common::{
+    access_trace::{AccessKind, AccessTraceEntry},
+    messages::{CanId, CanMessage, NmtState, LSS_REQ_ID, NMT_CMD_ID, SYNC_ID},
+    sdo::{AbortCode, SdoRequest, SdoResponse},
+    traits::AsyncCanSender,
+    NodeId,
+};
+use zencan_node::{object_dict::find_object, reduce_filters, AcceptFilter, Node};
 
 mod utils;
 use utils::{setup_single_node, test_with_background_process, BusLogger};
@@ -39,6 +49,30 @@ async fn test_device_info_readback() {
             &client.read_utf8(DEVICE_SW_VER_ID, 0).await.unwrap(),
             "v2.1.0"
         );
+
+        // The software version can be set by the application, but not over SDO
+        object_dict1::set_software_version("v2.1.0-14-g3f2e1d0").unwrap();
+        assert_eq!(
+            &client.read_utf8(DEVICE_SW_VER_ID, 0).await.unwrap(),
+            "v2.1.0-14-g3f2e1d0"
+        );
+        assert_eq!(
+            Err(AbortCode::DataTypeMismatchLengthHigh),
+            object_dict1::set_software_version(&"v".repeat(33))
+        );
+        assert_eq!(
+            Some(RawAbortCode::Valid(AbortCode::ReadOnly)),
+            client
+                .download(DEVICE_SW_VER_ID, 0, b"v9")
+                .await
+                .unwrap_err()
+                .abort_code()
+        );
+        object_dict1::set_software_version("v2.1.0").unwrap();
+        assert_eq!(
+            &client.read_utf8(DEVICE_SW_VER_ID, 0).await.unwrap(),
+            "v2.1.0"
+        );
     };
 
     test_with_background_process(&mut [&mut node], &mut bus.new_sender(), test_task).await;
@@ -139,6 +173,192 @@ async fn test_string_write() {
     test_with_background_process(&mut [&mut node], &mut sender, test_task).await;
 }
 
+#[tokio::test]
+#[serial_test::serial]
+async fn test_short_string_is_null_padded() {
+    let (mut node, mut client, mut bus) = setup_single_node(
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut sender = bus.new_sender();
+    let object = &object_dict1::OBJECT2003;
+
+    let test_task = async move {
+        client.download(0x2003, 0, b"A long string").await.unwrap();
+        client.download(0x2003, 0, b"Short").await.unwrap();
+        assert_eq!(b"Short", client.upload(0x2003, 0).await.unwrap().as_slice());
+
+        // No bytes of the previous value remain after the short one
+        let mut expected = [0u8; 16];
+        expected[..5].copy_from_slice(b"Short");
+        assert_eq!(expected, object.get_value());
+
+        // The same semantics are available to the application
+        object.set_value_str(b"App").unwrap();
+        let mut buf = [0u8; 16];
+        assert_eq!(b"App", object.get_value_str(&mut buf));
+        assert_eq!(b"App", client.upload(0x2003, 0).await.unwrap().as_slice());
+
+        // Restore the default value for other tests
+        object.set_value_str(b"Some String").unwrap();
+    };
+
+    test_with_background_process(&mut [&mut node], &mut sender, test_task).await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_debug_log() {
+    let (mut node, mut client, mut bus) = setup_single_node(
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut sender = bus.new_sender();
+
+    object_dict1::DEBUG_LOG.clear();
+    assert!(node.log_str("booted"));
+
+    let test_task = async move {
+        let mut tail = DebugLogTail::new();
+        let snapshot = client.read_debug_log().await.unwrap();
+        assert_eq!(7, snapshot.position);
+        assert_eq!("booted\n", tail.update(&snapshot).text);
+
+        // Messages can be logged from other tasks, and those over the rate limit are dropped
+        let accepted: Vec<String> = (0..6)
+            .map(|i| format!("message {i}"))
+            .filter(|msg| object_dict1::DEBUG_LOG.log_str(msg))
+            .collect();
+        assert!(accepted.len() <= 4);
+        let dropped = client.upload_u32(0x5004, 3).await.unwrap();
+        assert_eq!(6 - accepted.len() as u32, dropped);
+
+        let snapshot = client.read_debug_log().await.unwrap();
+        let expected: String = accepted.iter().map(|msg| format!("{msg}\n")).collect();
+        let update = tail.update(&snapshot);
+        assert_eq!(expected, update.text);
+        assert_eq!(0, update.missed);
+
+        client.clear_debug_log().await.unwrap();
+        let snapshot = client.read_debug_log().await.unwrap();
+        assert_eq!(0, snapshot.position);
+        assert!(snapshot.text.is_empty());
+        assert_eq!(0, client.upload_u32(0x5004, 3).await.unwrap());
+        // Only 0 may be written to the position
+        client.download_u32(0x5004, 1, 5).await.unwrap_err();
+    };
+
+    test_with_background_process(&mut [&mut node], &mut sender, test_task).await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_event_log() {
+    let (mut node, mut client, mut bus) = setup_single_node(
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut sender = bus.new_sender();
+
+    object_dict1::EVENT_LOG.clear();
+    assert_eq!(Some(0), node.log_event(0x100, [1, 2, 3, 4]));
+
+    let test_task = async move {
+        let events = client.fetch_events_since(0).await.unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!(0, events[0].seq);
+        assert_eq!(0x100, events[0].code);
+        assert_eq!([1, 2, 3, 4], events[0].data);
+
+        // Only the new events are fetched
+        for i in 0..2 {
+            object_dict1::EVENT_LOG.log_event(0x200 + i, [0; 4]);
+        }
+        let events = client.fetch_events_since(1).await.unwrap();
+        let seqs: Vec<u32> = events.iter().map(|e| e.seq).collect();
+        assert_eq!(vec![1, 2], seqs);
+        assert!(client.fetch_events_since(3).await.unwrap().is_empty());
+
+        // Once the log is full, the oldest events are overwritten
+        for i in 0..3 {
+            object_dict1::EVENT_LOG.log_event(0x300 + i, [0; 4]);
+        }
+        let events = client.fetch_events_since(1).await.unwrap();
+        let seqs: Vec<u32> = events.iter().map(|e| e.seq).collect();
+        assert_eq!(vec![2, 3, 4, 5], seqs);
+
+        // After a clear, a client asking for later events gets all of the new ones
+        client.clear_event_log().await.unwrap();
+        object_dict1::EVENT_LOG.log_event(0x400, [0; 4]);
+        let events = client.fetch_events_since(6).await.unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!((0, 0x400), (events[0].seq, events[0].code));
+
+        // The stored events cannot be replaced while the log holds events
+        client.download(0x5008, 4, &[0; 14]).await.unwrap_err();
+        client.clear_event_log().await.unwrap();
+    };
+
+    test_with_background_process(&mut [&mut node], &mut sender, test_task).await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_access_trace() {
+    let (mut node, mut client, mut bus) = setup_single_node(
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut sender = bus.new_sender();
+
+    let test_task = async move {
+        // Clear the trace. The clearing write is itself the first entry.
+        client.download_u32(0x5003, 1, 0).await.unwrap();
+
+        let original = client.upload_u32(0x3000, 0).await.unwrap();
+        client.download_u32(0x3000, 0, 0x1234).await.unwrap();
+        let err = client.upload(0x4fff, 0).await.unwrap_err();
+        assert!(matches!(
+            err,
+            SdoClientError::ServerAbort {
+                abort_code: RawAbortCode::Valid(AbortCode::NoSuchObject),
+                ..
+            }
+        ));
+
+        assert_eq!(4, client.upload_u32(0x5003, 1).await.unwrap());
+        let data = client.upload(0x5003, 2).await.unwrap();
+        let entries: Vec<_> = AccessTraceEntry::decode_all(&data)
+            .map(|e| (e.index, e.sub, e.kind, e.abort_code))
+            .collect();
+        assert_eq!(
+            vec![
+                (0x5003, 1, AccessKind::SdoWrite, 0),
+                (0x3000, 0, AccessKind::SdoRead, 0),
+                (0x3000, 0, AccessKind::SdoWrite, 0),
+                (
+                    0x4fff,
+                    0,
+                    AccessKind::SdoRead,
+                    AbortCode::NoSuchObject as u32
+                ),
+                (0x5003, 1, AccessKind::SdoRead, 0),
+            ],
+            entries
+        );
+
+        // Restore state for other tests
+        client.download_u32(0x3000, 0, original).await.unwrap();
+        client.download_u32(0x5003, 1, 0).await.unwrap();
+    };
+
+    test_with_background_process(&mut [&mut node], &mut sender, test_task).await;
+}
+
 #[tokio::test]
 #[serial_test::serial]
 async fn test_record_access() {
@@ -226,6 +446,141 @@ async fn test_array_access() {
     test_with_background_process(&mut [&mut node], &mut sender, test_task).await;
 }
 
+#[serial_test::serial]
+#[tokio::test]
+async fn test_scaled_object() {
+    const SCALED_ID: u16 = 0x3100;
+
+    let (mut node, mut client, mut bus) = setup_single_node(
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+
+    let _logger = BusLogger::new(bus.new_receiver());
+
+    let read_f32 = |bytes: Vec<u8>| f32::from_le_bytes(bytes.try_into().unwrap());
+
+    let test_task = async move {
+        assert_eq!(4, client.upload_u8(SCALED_ID, 0).await.unwrap());
+        // Defaults: 100 * 0.5 - 10
+        assert_eq!(100, client.upload_i16(SCALED_ID, 1).await.unwrap());
+        assert_eq!(40.0, read_f32(client.upload(SCALED_ID, 4).await.unwrap()));
+
+        // Calibrate the gain and offset via SDO
+        client
+            .download(SCALED_ID, 2, &2.0f32.to_le_bytes())
+            .await
+            .unwrap();
+        client
+            .download(SCALED_ID, 3, &1.0f32.to_le_bytes())
+            .await
+            .unwrap();
+        object_dict1::OBJECT3100.set_raw(-4);
+        assert_eq!(-7.0, read_f32(client.upload(SCALED_ID, 4).await.unwrap()));
+        assert_eq!(-7.0, object_dict1::OBJECT3100.get_scaled());
+
+        // The scaled value is computed, and cannot be written
+        let err = client
+            .download(SCALED_ID, 4, &0.0f32.to_le_bytes())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            Some(RawAbortCode::Valid(AbortCode::ReadOnly)),
+            err.abort_code()
+        );
+
+        // Restore defaults for other tests
+        object_dict1::OBJECT3100.set_raw(100);
+        object_dict1::OBJECT3100.set_gain(0.5);
+        object_dict1::OBJECT3100.set_offset(-10.0);
+    };
+
+    test_with_background_process(&mut [&mut node], &mut bus.new_sender(), test_task).await;
+}
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_atomic_storage_object() {
+    const ATOMIC_ID: u16 = 0x3101;
+
+    let (mut node, mut client, mut bus) = setup_single_node(
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+
+    let _logger = BusLogger::new(bus.new_receiver());
+
+    let test_task = async move {
+        assert_eq!(7, client.upload_u32(ATOMIC_ID, 1).await.unwrap());
+        assert_eq!(1.5, object_dict1::OBJECT3101.get_setpoint());
+
+        client.download_u32(ATOMIC_ID, 1, 42).await.unwrap();
+        assert_eq!(42, object_dict1::OBJECT3101.get_counter());
+        object_dict1::OBJECT3101.set_setpoint(-3.0);
+        let bytes = client.upload(ATOMIC_ID, 2).await.unwrap();
+        assert_eq!(-3.0, f32::from_le_bytes(bytes.try_into().unwrap()));
+
+        // Restore defaults for other tests
+        object_dict1::OBJECT3101.set_counter(7);
+        object_dict1::OBJECT3101.set_setpoint(1.5);
+    };
+
+    test_with_background_process(&mut [&mut node], &mut bus.new_sender(), test_task).await;
+}
+
+#[serial_test::serial]
+#[test]
+fn test_object_constructors() {
+    let record = object_dict1::Object3101::new();
+    assert_eq!(7, record.get_counter());
+    assert_eq!(1.5, record.get_setpoint());
+
+    // Additional instances are independent of the generated static
+    record.set_counter(100);
+    assert_eq!(7, object_dict1::OBJECT3101.get_counter());
+
+    let sparse = object_dict1::Object3102::default();
+    assert_eq!(1, sparse.get_sub1());
+    assert_eq!(3, sparse.get_sub3());
+}
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_record_with_gaps() {
+    const SPARSE_ID: u16 = 0x3102;
+
+    let (mut node, mut client, mut bus) = setup_single_node(
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+
+    let _logger = BusLogger::new(bus.new_receiver());
+
+    let test_task = async move {
+        // Sub0 includes the reserved sub 4
+        assert_eq!(4, client.upload_u8(SPARSE_ID, 0).await.unwrap());
+        assert_eq!(1, client.upload_u16(SPARSE_ID, 1).await.unwrap());
+        assert_eq!(3, client.upload_u16(SPARSE_ID, 3).await.unwrap());
+
+        // The skipped sub and the reserved sub both do not exist
+        for sub in [2, 4] {
+            assert_eq!(
+                client.upload(SPARSE_ID, sub).await.unwrap_err(),
+                SdoClientError::ServerAbort {
+                    index: SPARSE_ID,
+                    sub,
+                    abort_code: RawAbortCode::Valid(AbortCode::NoSuchSubIndex)
+                }
+            );
+        }
+    };
+
+    test_with_background_process(&mut [&mut node], &mut bus.new_sender(), test_task).await;
+}
+
 #[tokio::test]
 #[serial_test::serial]
 async fn test_store_and_restore_objects() {
@@ -323,3 +678,544 @@ async fn test_empty_string_read() {
     };
     test_with_background_process(&mut [&mut node], &mut sender, test_task).await;
 }
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_node_state_snapshot() {
+    let state = &object_dict1::NODE_STATE;
+    let (mut node, _client, mut bus) =
+        setup_single_node(&object_dict1::OD_TABLE, &object_dict1::NODE_MBOX, state);
+    let mut sender = bus.new_sender();
+
+    node.process(0, &mut |msg| {
+        futures::executor::block_on(sender.send(msg)).unwrap()
+    });
+
+    let snapshot = state.snapshot();
+    assert_eq!(NodeId::new(1).unwrap(), snapshot.node_id);
+    assert_eq!(NmtState::PreOperational, snapshot.nmt_state);
+    assert!(!snapshot.sdo_transfer_active);
+    assert!(!snapshot.node_id_change_pending);
+
+    object_dict1::OBJECT1001.set_value(0x11);
+    node.process(1000, &mut |msg| {
+        futures::executor::block_on(sender.send(msg)).unwrap()
+    });
+    assert_eq!(0x11, state.snapshot().error_register);
+    object_dict1::OBJECT1001.set_value(0);
+}
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_shutdown() {
+    let od = &object_dict1::OD_TABLE;
+    let state = &object_dict1::NODE_STATE;
+    let (mut node, client, mut bus) = setup_single_node(od, &object_dict1::NODE_MBOX, state);
+    let client = Arc::new(tokio::sync::Mutex::new(client));
+
+    let mut sender = bus.new_sender();
+    let _logger = BusLogger::new(bus.new_receiver());
+
+    let save_count: &'static AtomicUsize = Box::leak(Box::new(AtomicUsize::new(0)));
+    let store_objects_callback = Box::leak(Box::new(
+        move |reader: &mut dyn embedded_io::Read<Error = Infallible>, _size: usize| {
+            let mut buf = [0; 32];
+            while reader.read(&mut buf).unwrap() > 0 {}
+            save_count.fetch_add(1, Ordering::Relaxed);
+        },
+    ));
+    node.register_store_objects(store_objects_callback);
+
+    // Clear any modifications left over from previous tests
+    node.shutdown(&mut |_| {});
+    save_count.store(0, Ordering::Relaxed);
+
+    // Writing a non-persistent object does not require a save
+    let c = client.clone();
+    test_with_background_process(&mut [&mut node], &mut sender, async move {
+        let mut client = c.lock().await;
+        client.download(0x2003, 0, b"TRANSIENT").await.unwrap();
+    })
+    .await;
+    assert!(!state.storage_context().is_dirty());
+    node.shutdown(&mut |_| {});
+    assert_eq!(0, save_count.load(Ordering::Relaxed));
+    assert_eq!(NmtState::Stopped, node.nmt_state());
+    assert_eq!(NmtState::Stopped, state.snapshot().nmt_state);
+
+    // Writing a persistent object triggers a save on shutdown
+    let c = client.clone();
+    test_with_background_process(&mut [&mut node], &mut sender, async move {
+        let mut client = c.lock().await;
+        client.download(0x2002, 0, b"PERSIST").await.unwrap();
+    })
+    .await;
+    assert!(state.storage_context().is_dirty());
+    node.shutdown(&mut |_| {});
+    assert_eq!(1, save_count.load(Ordering::Relaxed));
+    assert!(!state.storage_context().is_dirty());
+}
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_process_next_action() {
+    let od = &object_dict1::OD_TABLE;
+    let state = &object_dict1::NODE_STATE;
+
+    let heartbeat_time = find_object(od, 0x1017).unwrap();
+    heartbeat_time.write(0, &100u16.to_le_bytes()).unwrap();
+    let (mut node, _client, _bus) = setup_single_node(od, &object_dict1::NODE_MBOX, state);
+
+    // The boot up message is sent on the first call, and the next heartbeat is due after one period
+    let result = node.process(0, &mut |_| {});
+    assert_eq!(Some(100_000), result.next_action_us);
+    assert!(!result.objects_updated);
+
+    let result = node.process(40_000, &mut |_| {});
+    assert_eq!(Some(60_000), result.next_action_us);
+
+    let mut sent = Vec::new();
+    let result = node.process(100_000, &mut |msg| sent.push(msg));
+    assert_eq!(1, sent.len());
+    assert_eq!(Some(100_000), result.next_action_us);
+
+    // Restore the default for other tests
+    heartbeat_time.write(0, &0u16.to_le_bytes()).unwrap();
+}
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_clock_wrap() {
+    let od = &object_dict1::OD_TABLE;
+    let mbox = &object_dict1::NODE_MBOX;
+    let heartbeat_time = find_object(od, 0x1017).unwrap();
+
+    // A 32-bit clock wraps every ~71 minutes, and a 64-bit clock after ~584,940 years
+    for (bits, mask) in [(32, u32::MAX as u64), (64, u64::MAX)] {
+        heartbeat_time.write(0, &100u16.to_le_bytes()).unwrap();
+        let (mut node, _client, _bus) = setup_single_node(od, mbox, &object_dict1::NODE_STATE);
+        node.set_clock_bits(bits);
+
+        // The clock wraps 120ms after the first call
+        let start_us = mask - 119_999;
+        let mut process = |offset_us: u64| {
+            let mut sent = Vec::new();
+            let now_us = start_us.wrapping_add(offset_us) & mask;
+            let result = node.process(now_us, &mut |msg| sent.push(msg));
+            (sent, result.next_action_us)
+        };
+        let heartbeats = |sent: &[CanMessage]| {
+            sent.iter()
+                .filter(|msg| msg.id() == CanId::std(0x701))
+                .count()
+        };
+
+        let (sent, next_action_us) = process(0);
+        assert_eq!(1, heartbeats(&sent), "No boot-up with {bits}-bit clock");
+        assert_eq!(Some(100_000), next_action_us);
+
+        // Start a segmented upload of the device name, which times out after 25ms
+        let req = SdoRequest::initiate_upload(0x1008, 0);
+        mbox.store_message(req.to_can_message(CanId::sdo_rx(1)))
+            .unwrap();
+        let (sent, _) = process(100_000);
+        assert_eq!(1, heartbeats(&sent));
+        assert_eq!(2, sent.len());
+
+        // The heartbeat and the SDO timeout continue across the wrap
+        let (sent, _) = process(110_000);
+        assert!(sent.is_empty());
+        let (sent, _) = process(126_000);
+        assert_eq!(1, sent.len(), "No SDO timeout with {bits}-bit clock");
+        assert_eq!(
+            SdoResponse::abort(0x1008, 0, AbortCode::SdoTimeout),
+            SdoResponse::try_from(sent[0]).unwrap()
+        );
+        let (sent, next_action_us) = process(199_999);
+        assert!(sent.is_empty());
+        assert_eq!(Some(1), next_action_us);
+        let (sent, _) = process(200_000);
+        assert_eq!(1, heartbeats(&sent), "No heartbeat with {bits}-bit clock");
+
+        // A clock which steps backwards does not delay the heartbeat by a whole wrap
+        process(150_000);
+        let (sent, _) = process(300_000);
+        assert_eq!(1, heartbeats(&sent));
+    }
+    // The 64-bit case runs last, which leaves the watchdog in the mbox with the default width
+    heartbeat_time.write(0, &0u16.to_le_bytes()).unwrap();
+}
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_statistics() {
+    let od = &object_dict1::OD_TABLE;
+    let state = &object_dict1::NODE_STATE;
+    let stats = &object_dict1::OBJECT5001;
+    stats.set_power_cycles(5);
+    stats.set_operating_minutes(0);
+    stats.set_bus_off_count(0);
+
+    // Creating the node counts a power cycle
+    let (mut node, _client, _bus) = setup_single_node(od, &object_dict1::NODE_MBOX, state);
+    assert_eq!(6, stats.get_power_cycles());
+    assert!(state.storage_context().is_dirty());
+
+    node.process(0, &mut |_| {});
+    node.process(59_000_000, &mut |_| {});
+    assert_eq!(0, stats.get_operating_minutes());
+    node.process(121_000_000, &mut |_| {});
+    assert_eq!(2, stats.get_operating_minutes());
+
+    node.report_bus_off();
+    assert_eq!(1, stats.get_bus_off_count());
+
+    // Restore defaults for other tests
+    stats.set_power_cycles(0);
+    stats.set_operating_minutes(0);
+    stats.set_bus_off_count(0);
+}
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_boot_delay_and_min_heartbeat_interval() {
+    let od = &object_dict1::OD_TABLE;
+    let state = &object_dict1::NODE_STATE;
+    let timing = &object_dict1::OBJECT5002;
+    timing.set_boot_delay_max_ms(50);
+    timing.set_min_heartbeat_interval_ms(30);
+
+    let heartbeat_time = find_object(od, 0x1017).unwrap();
+    heartbeat_time.write(0, &10u16.to_le_bytes()).unwrap();
+    let (mut node, _client, _bus) = setup_single_node(od, &object_dict1::NODE_MBOX, state);
+
+    // The boot-up message is held back by a delay of up to 50ms
+    let mut boot_time = None;
+    for now_us in (0..=50_000).step_by(1000) {
+        let mut sent = Vec::new();
+        let result = node.process(now_us, &mut |msg| sent.push(msg));
+        if sent.is_empty() {
+            assert_eq!(NmtState::Bootup, node.nmt_state());
+            assert!(result.next_action_us.unwrap() <= 50_000 - now_us);
+        } else {
+            assert_eq!(1, sent.len());
+            boot_time = Some(now_us);
+            break;
+        }
+    }
+    let boot_time = boot_time.expect("Boot-up message not sent within the maximum delay");
+
+    // Heartbeats are limited to one per 30ms, although the period is 10ms
+    let mut sent = Vec::new();
+    let result = node.process(boot_time + 10_000, &mut |msg| sent.push(msg));
+    assert!(sent.is_empty());
+    assert_eq!(Some(20_000), result.next_action_us);
+
+    // A state change is reported once the minimum interval has passed
+    node.request_state(NmtState::Operational);
+    let result = node.process(boot_time + 20_000, &mut |msg| sent.push(msg));
+    assert!(sent.is_empty());
+    assert_eq!(Some(10_000), result.next_action_us);
+    node.process(boot_time + 30_000, &mut |msg| sent.push(msg));
+    assert_eq!(1, sent.len());
+    assert_eq!(NmtState::Operational as u8, sent[0].data()[0] & 0x7F);
+
+    // Restore defaults for other tests
+    timing.set_boot_delay_max_ms(0);
+    timing.set_min_heartbeat_interval_ms(1);
+    heartbeat_time.write(0, &0u16.to_le_bytes()).unwrap();
+}
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_process_queued() {
+    let od = &object_dict1::OD_TABLE;
+    let mbox = &object_dict1::NODE_MBOX;
+    let (mut node, _client, _bus) = setup_single_node(od, mbox, &object_dict1::NODE_STATE);
+    let overflow_start = node.tx_overflow_count();
+
+    // Messages are queued instead of sent, until the application takes them
+    node.process_queued(0);
+    assert_eq!(1, node.tx_queue_len());
+    for now_us in [1000, 2000, 3000] {
+        let req = SdoRequest::initiate_upload(0x1000, 0);
+        mbox.store_message(req.to_can_message(CanId::sdo_rx(1)))
+            .unwrap();
+        node.process_queued(now_us);
+    }
+
+    // The queue holds 3 messages, so the last SDO response was dropped
+    assert_eq!(3, node.tx_queue_len());
+    assert_eq!(overflow_start + 1, node.tx_overflow_count());
+    let ids: Vec<CanId> = core::iter::from_fn(|| node.pop_tx())
+        .map(|msg| msg.id())
+        .collect();
+    assert_eq!(
+        vec![CanId::std(0x701), CanId::sdo_tx(1), CanId::sdo_tx(1)],
+        ids
+    );
+    assert_eq!(0, node.tx_queue_len());
+}
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_scan_response_jitter() {
+    static JITTER_US: AtomicUsize = AtomicUsize::new(0);
+
+    let od = &object_dict1::OD_TABLE;
+    let mbox = &object_dict1::NODE_MBOX;
+    let timing = &object_dict1::OBJECT5002;
+    timing.set_scan_response_jitter_max_ms(20);
+    let (mut node, _client, _bus) = setup_single_node(od, mbox, &object_dict1::NODE_STATE);
+    node.process(0, &mut |_| {});
+
+    let process = |node: &mut Node, now_us: u64| {
+        let mut sent = Vec::new();
+        let result = node.process(now_us, &mut |msg| sent.push(msg));
+        let responses: Vec<CanMessage> = sent
+            .into_iter()
+            .filter(|msg| msg.id() == CanId::sdo_tx(1))
+            .collect();
+        (responses, result.next_action_us)
+    };
+    let upload = |index: u16| {
+        let req = SdoRequest::initiate_upload(index, 0);
+        mbox.store_message(req.to_can_message(CanId::sdo_rx(1)))
+            .unwrap();
+    };
+
+    // Reads of other objects are not delayed
+    upload(0x2000);
+    let (sent, _) = process(&mut node, 1000);
+    assert_eq!(1, sent.len());
+
+    // The response to a read of the device type is held back by up to 20ms
+    upload(0x1000);
+    let mut response_time = None;
+    for now_us in (2000..=22_000).step_by(1000) {
+        let (sent, next_action_us) = process(&mut node, now_us);
+        if sent.is_empty() {
+            assert!(next_action_us.unwrap() <= 22_000 - now_us);
+        } else {
+            assert_eq!(1, sent.len());
+            response_time = Some(now_us);
+            break;
+        }
+    }
+    assert!(
+        response_time.is_some(),
+        "Scan response not sent within the maximum delay"
+    );
+
+    // The application can choose the delay
+    node.register_scan_response_callback(&|index, jitter_us| {
+        assert_eq!(0x1018, index);
+        JITTER_US.store(jitter_us as usize, Ordering::Relaxed);
+        50_000
+    });
+    upload(0x1018);
+    let (sent, next_action_us) = process(&mut node, 100_000);
+    assert!(sent.is_empty());
+    assert!(next_action_us.is_some_and(|t| t <= 50_000));
+    assert!(JITTER_US.load(Ordering::Relaxed) <= 20_000);
+    let (sent, _) = process(&mut node, 149_000);
+    assert!(sent.is_empty());
+    let (sent, _) = process(&mut node, 150_000);
+    assert_eq!(1, sent.len());
+
+    // Restore defaults for other tests
+    timing.set_scan_response_jitter_max_ms(0);
+}
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_accept_filters() {
+    static FILTER_CHANGES: AtomicUsize = AtomicUsize::new(0);
+
+    let od = &object_dict1::OD_TABLE;
+    let mbox = &object_dict1::NODE_MBOX;
+    mbox.set_filter_change_callback(&|| {
+        FILTER_CHANGES.fetch_add(1, Ordering::Relaxed);
+    });
+    let (mut node, _client, _bus) = setup_single_node(od, mbox, &object_dict1::NODE_STATE);
+
+    let filter_ids = || mbox.accept_filters().map(|f| f.id).collect::<Vec<_>>();
+
+    node.process(0, &mut |_| {});
+    let changes = FILTER_CHANGES.load(Ordering::Relaxed);
+    let ids = filter_ids();
+    for id in [0, 0x80, 0x7E5, 0x601, 0x7E0] {
+        assert!(ids.contains(&CanId::std(id)), "Missing filter for {id:x}");
+    }
+    assert!(!ids.contains(&CanId::std(0x201)));
+
+    // Enabling an RPDO adds a filter for it
+    let rpdo_comm = find_object(od, 0x1400).unwrap();
+    rpdo_comm.write(1, &0x201u32.to_le_bytes()).unwrap();
+    node.process(1000, &mut |_| {});
+    assert_eq!(changes + 1, FILTER_CHANGES.load(Ordering::Relaxed));
+    assert!(filter_ids().contains(&CanId::std(0x201)));
+
+    // No change, no callback
+    node.process(2000, &mut |_| {});
+    assert_eq!(changes + 1, FILTER_CHANGES.load(Ordering::Relaxed));
+
+    // The filters can be reduced to fit the hardware, and still accept every consumed ID
+    let mut filters: Vec<AcceptFilter> = mbox.accept_filters().collect();
+    let n = reduce_filters(&mut filters, 2);
+    assert_eq!(2, n);
+    for id in filter_ids() {
+        assert!(filters[..n].iter().any(|f| f.matches(id)));
+    }
+
+    rpdo_comm.write(1, &0x8000_0000u32.to_le_bytes()).unwrap();
+    node.process(3000, &mut |_| {});
+}
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_malformed_sdo_requests_dropped() {
+    let mbox = &object_dict1::NODE_MBOX;
+    let (mut node, _client, _bus) =
+        setup_single_node(&object_dict1::OD_TABLE, mbox, &object_dict1::NODE_STATE);
+    node.process(0, &mut |_| {});
+    let dropped = mbox.dropped_counts();
+
+    // An upload of the device type object (0x1000)
+    let request = [0x40, 0x00, 0x10, 0x00, 0, 0, 0, 0];
+    let sdo_id = CanId::std(0x601);
+    for len in 0..8 {
+        let msg = CanMessage::new(sdo_id, &request[..len]);
+        assert_eq!(Err(msg), mbox.store_message(msg));
+    }
+    let rtr = CanMessage::new_rtr(sdo_id);
+    assert_eq!(Err(rtr), mbox.store_message(rtr));
+
+    let mut sent = Vec::new();
+    node.process(1000, &mut |msg| sent.push(msg));
+    assert!(sent.is_empty());
+    assert_eq!(dropped, mbox.dropped_counts());
+
+    // A well formed request is still answered
+    mbox.store_message(CanMessage::new(sdo_id, &request))
+        .unwrap();
+    node.process(2000, &mut |msg| sent.push(msg));
+    assert_eq!(1, sent.len());
+    assert_eq!(CanId::std(0x581), sent[0].id());
+}
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_short_frames_ignored() {
+    let od = &object_dict1::OD_TABLE;
+    let mbox = &object_dict1::NODE_MBOX;
+    let (mut node, _client, _bus) = setup_single_node(od, mbox, &object_dict1::NODE_STATE);
+    node.process(0, &mut |_| {});
+    assert_eq!(NmtState::PreOperational, node.nmt_state());
+
+    // A start command for all nodes, without the node ID
+    let nmt_start = [0x01, 0x00];
+    for len in 0..2 {
+        mbox.store_message(CanMessage::new(NMT_CMD_ID, &nmt_start[..len]))
+            .unwrap();
+        node.process(1000, &mut |_| {});
+        assert_eq!(NmtState::PreOperational, node.nmt_state());
+    }
+
+    // An LSS switch state selective request, which is rejected when the vendor ID does not fit
+    let lss_request = [0x40, 1, 2, 3, 4, 0, 0, 0];
+    for len in 0..5 {
+        let msg = CanMessage::new(LSS_REQ_ID, &lss_request[..len]);
+        assert_eq!(Err(msg), mbox.store_message(msg));
+    }
+
+    // Any SYNC length is accepted, as the counter is optional
+    let sync = [1, 2, 3, 4, 5, 6, 7, 8];
+    for len in 0..=8 {
+        mbox.store_message(CanMessage::new(SYNC_ID, &sync[..len]))
+            .unwrap();
+        node.process(2000, &mut |_| {});
+    }
+
+    // Map 0x2000sub1 to RPDO2, which is not written by a shorter frame
+    let rpdo_comm = find_object(od, 0x1402).unwrap();
+    rpdo_comm.write(1, &0x203u32.to_le_bytes()).unwrap();
+    rpdo_comm.write(2, &[254]).unwrap();
+    let rpdo_mapping = find_object(od, 0x1602).unwrap();
+    rpdo_mapping
+        .write(1, &((0x2000u32 << 16) | 1 << 8 | 32).to_le_bytes())
+        .unwrap();
+    rpdo_mapping.write(0, &[1]).unwrap();
+    let value_obj = find_object(od, 0x2000).unwrap();
+    let original = value_obj.read_u32(1).unwrap();
+    value_obj.write(1, &u32::MAX.to_le_bytes()).unwrap();
+    node.request_state(NmtState::Operational);
+    node.process(3000, &mut |_| {});
+
+    let rpdo = 0x12345678u32.to_le_bytes();
+    for len in 0..4 {
+        mbox.store_message(CanMessage::new(CanId::std(0x203), &rpdo[..len]))
+            .unwrap();
+        node.process(4000, &mut |_| {});
+        assert_eq!(u32::MAX, value_obj.read_u32(1).unwrap());
+    }
+    mbox.store_message(CanMessage::new(CanId::std(0x203), &rpdo))
+        .unwrap();
+    node.process(5000, &mut |_| {});
+    assert_eq!(0x12345678, value_obj.read_u32(1).unwrap());
+
+    // Restore the configuration for other tests
+    value_obj.write(1, &original.to_le_bytes()).unwrap();
+    rpdo_mapping.write(0, &[0]).unwrap();
+    rpdo_comm.write(1, &0x8000_0000u32.to_le_bytes()).unwrap();
+    node.request_state(NmtState::PreOperational);
+    node.process(6000, &mut |_| {});
+}
+
+#[serial_test::serial]
+#[tokio::test]
+async fn test_application_watchdog() {
+    static EXPIRED: std::sync::Mutex<Vec<Option<CanMessage>>> = std::sync::Mutex::new(Vec::new());
+    let mbox = &object_dict1::NODE_MBOX;
+    let (mut node, _client, _bus) =
+        setup_single_node(&object_dict1::OD_TABLE, mbox, &object_dict1::NODE_STATE);
+    node.register_watchdog_callback(&|emcy| EXPIRED.lock().unwrap().push(emcy));
+    EXPIRED.lock().unwrap().clear();
+
+    node.set_watchdog(10_000);
+    let result = node.process(0, &mut |_| {});
+    assert_eq!(Some(10_001), result.next_action_us);
+
+    // Feeding keeps it alive, as long as process is called
+    for t in (5_000..50_000).step_by(5_000) {
+        node.feed_watchdog();
+        node.process(t, &mut |_| {});
+        assert!(!mbox.watchdog().check(t + 9_000));
+    }
+    node.feed_watchdog();
+
+    // A hung application stops calling process, which is detected by a check from elsewhere
+    assert!(!mbox.watchdog().check(55_000));
+    assert!(mbox.watchdog().check(55_001));
+    assert!(!mbox.watchdog().check(60_000));
+    let expired = EXPIRED.lock().unwrap().clone();
+    assert_eq!(1, expired.len());
+    let emcy = expired[0].unwrap();
+    assert_eq!(CanId::std(0x81), emcy.id());
+    assert_eq!(&[0x00, 0x61], &emcy.data()[0..2]);
+    assert_eq!(1, emcy.data()[2] & 1);
+
+    // Process calls without feeding are detected by process
+    node.feed_watchdog();
+    node.process(100_000, &mut |_| {});
+    assert!(!mbox.watchdog().is_expired());
+    node.process(110_001, &mut |_| {});
+    assert!(mbox.watchdog().is_expired());
+    assert_eq!(2, EXPIRED.lock().unwrap().len());
+
+    // Disabling it restores the default for other tests
+    node.set_watchdog(0);
+    node.process(200_000, &mut |_| {});
+    assert!(!mbox.watchdog().check(1_000_000));
+    assert_eq!(2, EXPIRED.lock().unwrap().len());
+}
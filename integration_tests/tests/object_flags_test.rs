@@ -1,7 +1,12 @@
-use integration_tests::object_dict1::{NODE_STATE, OBJECT3008, OBJECT3009, OBJECT300A};
+use integration_tests::object_dict1::{
+    NODE_STATE, OBJECT3008, OBJECT3009, OBJECT300A, OBJECT3100, OBJECT3101,
+};
+use serial_test::serial;
+use zencan_common::sdo::AbortCode;
 use zencan_node::object_dict::ObjectAccess;
 
 #[test]
+#[serial]
 fn test_event_flags() {
     fn test_event_flags(obj: &dyn ObjectAccess, n: u8) {
         // No flags set after toggle
@@ -35,3 +40,43 @@ fn test_event_flags() {
     test_event_flags(&OBJECT3009, 8);
     test_event_flags(&OBJECT300A, 9);
 }
+
+#[test]
+#[serial]
+fn test_notify_setters() {
+    // Clear any flags left in both flag sets
+    let objects: [&dyn ObjectAccess; 3] = [&OBJECT3008, &OBJECT3100, &OBJECT3101];
+    for _ in 0..2 {
+        NODE_STATE.pdo_sync().toggle();
+        for obj in objects {
+            obj.clear_events();
+        }
+    }
+    NODE_STATE.pdo_sync().toggle();
+
+    // Array elements are bounds checked, and flag their own sub index
+    OBJECT3008.set_notify(2, 5).unwrap();
+    assert_eq!(Err(AbortCode::NoSuchSubIndex), OBJECT3008.set_notify(7, 5));
+    // Record fields flag their sub index
+    OBJECT3101.set_counter_notify(8);
+    // Scaled objects flag both the raw and scaled values
+    OBJECT3100.set_raw_notify(100);
+
+    NODE_STATE.pdo_sync().toggle();
+    assert_eq!(5, OBJECT3008.get(2).unwrap());
+    for i in 0..8 {
+        assert_eq!(i == 3, OBJECT3008.read_event_flag(i));
+    }
+    assert_eq!(8, OBJECT3101.get_counter());
+    assert!(OBJECT3101.read_event_flag(1));
+    assert!(!OBJECT3101.read_event_flag(2));
+    assert!(OBJECT3100.read_event_flag(1));
+    assert!(OBJECT3100.read_event_flag(4));
+
+    // The plain setters do not set flags
+    OBJECT3101.set_counter(7);
+    OBJECT3008.set(2, 0).unwrap();
+    NODE_STATE.pdo_sync().toggle();
+    assert!(!OBJECT3101.read_event_flag(1));
+    assert!(!OBJECT3008.read_event_flag(3));
+}
@@ -0,0 +1,102 @@
+//! Tests for linking objects between nodes with a PDO
+//!
+
+use integration_tests::{manager::manager_client, object_dict1};
+use serial_test::serial;
+use zencan_client::{
+    testing::NodeFixture, BusManager, PdoLinkError, PdoLinkRequest, PdoValidationError,
+};
+
+#[serial]
+#[tokio::test]
+async fn test_link_pdo() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let test_task = async move {
+        let client = || manager_client(&manager, 1);
+        let mut original_tpdos = Vec::new();
+        let mut original_rpdos = Vec::new();
+        for pdo in 0..4 {
+            original_tpdos.push(client().read_tpdo(pdo).await.unwrap().unwrap());
+            original_rpdos.push(client().read_rpdo(pdo).await.unwrap().unwrap());
+        }
+
+        // Objects of different sizes cannot be linked, and nothing is written
+        let request = PdoLinkRequest::new(1, 1).with_signal((0x3000, 0), (0x6411, 1));
+        assert!(matches!(
+            manager.link_pdo(&request).await,
+            Err(PdoLinkError::Validation {
+                node_id: 1,
+                source: PdoValidationError::SizeMismatch { .. }
+            })
+        ));
+
+        // Nor can an object which the device does not allow to be mapped
+        let request = PdoLinkRequest::new(1, 1).with_signal((0x3000, 0), (0x6444, 1));
+        assert!(matches!(
+            manager.link_pdo(&request).await,
+            Err(PdoLinkError::Validation {
+                node_id: 1,
+                source: PdoValidationError::MappingNotAllowed { kind: "RPDO", .. }
+            })
+        ));
+
+        // The node is linked to itself, which exercises both sides of the link
+        let request = PdoLinkRequest::new(1, 1).with_signal((0x3000, 0), (0x2000, 1));
+        let linked = manager.link_pdo(&request).await.unwrap();
+        assert_eq!(1, linked.producer.node_id);
+        assert_eq!(1, linked.consumer.node_id);
+
+        let tpdo = client()
+            .read_tpdo(linked.producer.pdo)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(tpdo.enabled);
+        assert_eq!(linked.cob_id, tpdo.cob);
+        assert_eq!(254, tpdo.transmission_type);
+        assert_eq!(1, tpdo.mappings.len());
+        assert_eq!(0x3000, tpdo.mappings[0].index);
+        assert_eq!(32, tpdo.mappings[0].size);
+        let rpdo = client()
+            .read_rpdo(linked.consumer.pdo)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(rpdo.enabled);
+        assert_eq!(linked.cob_id, rpdo.cob);
+        assert_eq!(0x2000, rpdo.mappings[0].index);
+        assert_eq!(1, rpdo.mappings[0].sub);
+
+        // A second link uses different PDOs, and a different COB-ID
+        let second = manager.link_pdo(&request).await.unwrap();
+        assert_ne!(linked.producer, second.producer);
+        assert_ne!(linked.consumer, second.consumer);
+        assert_ne!(linked.cob_id, second.cob_id);
+
+        // A COB-ID which is already used is rejected
+        let request = request.with_cob_id(linked.cob_id);
+        assert_eq!(
+            Err(PdoLinkError::CobIdInUse {
+                cob_id: linked.cob_id
+            }),
+            manager.link_pdo(&request).await
+        );
+
+        // Restore the original configuration
+        for (pdo, cfg) in original_tpdos.iter().enumerate() {
+            client().configure_tpdo(pdo, cfg).await.unwrap();
+        }
+        for (pdo, cfg) in original_rpdos.iter().enumerate() {
+            client().configure_rpdo(pdo, cfg).await.unwrap();
+        }
+    };
+
+    fixture.run(test_task).await;
+}
@@ -1,7 +1,13 @@
 //! Test node PDO operations
 //!
 
-use std::time::Duration;
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
 
 use integration_tests::{
     object_dict1,
@@ -9,13 +15,24 @@ use integration_tests::{
 };
 use serial_test::serial;
 use tokio::time::timeout;
-use zencan_client::{nmt_master::NmtMaster, PdoConfig, PdoMapping, SdoClient};
+use zencan_client::{
+    nmt_master::NmtMaster,
+    stamped_pdo::{StampCheck, StampedPdoDecoder},
+    PdoConfig, PdoMapping, PdoValidationError, SdoClient,
+};
 use zencan_common::{
-    messages::{CanId, CanMessage, SyncObject},
+    messages::{CanId, CanMessage, NmtState, SyncObject},
+    objects::{ObjectCode, SubInfo},
+    pdo_stamp::{StampValue, TpdoStamp},
+    sdo::{AbortCode, SdoRequest},
     traits::{AsyncCanReceiver, AsyncCanSender},
     NodeId,
 };
-use zencan_node::object_dict::{find_object, ODEntry};
+use zencan_node::object_dict::{
+    find_object, set_event_flags_bulk, ODEntry, ObjectAccess, ObjectFlagAccess, ObjectFlags,
+    SubObjectAccess,
+};
+use zencan_node::pdo::{RpdoValue, TpdoOrder};
 use zencan_node::{Node, NodeMbox, NodeStateAccess};
 
 mod utils;
@@ -62,7 +79,7 @@ async fn test_rpdo_assignment() {
 
     let test_task = async move {
         // Readback the largest sub index
-        assert_eq!(2, client.upload_u8(0x1400, 0).await.unwrap());
+        assert_eq!(3, client.upload_u8(0x1400, 0).await.unwrap());
 
         // Set COB-ID and readback
         // Invalid bit cleared, and ID == 0x201.
@@ -306,3 +323,791 @@ async fn test_pdo_configuration() {
         panic!("{}", e);
     }
 }
+
+#[serial]
+#[tokio::test]
+async fn test_pdo_validation() {
+    let od = &integration_tests::object_dict1::OD_TABLE;
+    let state = &integration_tests::object_dict1::NODE_STATE;
+    let mbox = &integration_tests::object_dict1::NODE_MBOX;
+    let (mut node, mut client, mut bus) = setup(od, mbox, state);
+
+    let _logger = BusLogger::new(bus.new_receiver());
+
+    let test_task = async move {
+        async fn read_mapping(
+            client: &mut SdoClient<SimBusSender<'_>, SimBusReceiver>,
+        ) -> Vec<u32> {
+            let mut values = Vec::new();
+            for sub in 1..=8 {
+                values.push(client.upload_u32(0x1A00, sub).await.unwrap());
+            }
+            values
+        }
+        let mapping = read_mapping(&mut client).await;
+        // Mappability is only checked on a disabled PDO
+        let cob_id = client.upload_u32(0x1800, 1).await.unwrap();
+        client
+            .download_u32(0x1800, 1, cob_id | (1 << 31))
+            .await
+            .unwrap();
+
+        let mut config = PdoConfig {
+            cob: 0x301,
+            enabled: true,
+            mappings: vec![PdoMapping {
+                index: 0x2001,
+                sub: 1,
+                size: 64,
+            }],
+            transmission_type: 254,
+        };
+
+        assert_eq!(
+            Err(PdoValidationError::SizeMismatch {
+                index: 0x2001,
+                sub: 1,
+                actual: 32,
+                declared: 64
+            }),
+            client.validate_tpdo(0, &config).await
+        );
+
+        config.mappings[0] = PdoMapping {
+            index: 0x5555,
+            sub: 0,
+            size: 8,
+        };
+        assert_eq!(
+            Err(PdoValidationError::NoSuchObject {
+                index: 0x5555,
+                sub: 0
+            }),
+            client.validate_tpdo(0, &config).await
+        );
+
+        // The read-only sub object of the record is not PDO mappable
+        config.mappings[0] = PdoMapping {
+            index: 0x2001,
+            sub: 3,
+            size: 16,
+        };
+        assert_eq!(
+            Err(PdoValidationError::MappingNotAllowed {
+                kind: "TPDO",
+                index: 0x2001,
+                sub: 3
+            }),
+            client.validate_tpdo(0, &config).await
+        );
+
+        config.mappings[0] = PdoMapping {
+            index: 0x2001,
+            sub: 1,
+            size: 32,
+        };
+        assert_eq!(Ok(()), client.validate_tpdo(0, &config).await);
+
+        // The mapping entries used to probe the device are restored
+        assert_eq!(mapping, read_mapping(&mut client).await);
+
+        // Nothing is written to an enabled PDO, so the device cannot reject the mapping
+        client
+            .download_u32(0x1800, 1, cob_id & !(1 << 31))
+            .await
+            .unwrap();
+        config.mappings[0] = PdoMapping {
+            index: 0x2001,
+            sub: 3,
+            size: 16,
+        };
+        assert_eq!(Ok(()), client.validate_tpdo(0, &config).await);
+        client.download_u32(0x1800, 1, cob_id).await.unwrap();
+
+        assert_eq!(
+            Err(PdoValidationError::NoSuchPdo {
+                kind: "TPDO",
+                pdo: 100
+            }),
+            client.validate_tpdo(100, &config).await
+        );
+    };
+
+    test_with_background_process(&mut [&mut node], &mut bus.new_sender(), test_task).await;
+}
+
+#[serial]
+#[test]
+fn test_tpdo_inhibit_time_coalescing() {
+    let od = &integration_tests::object_dict1::OD_TABLE;
+    let mut node = Node::new(
+        NodeId::new(1).unwrap(),
+        &integration_tests::object_dict1::NODE_MBOX,
+        &integration_tests::object_dict1::NODE_STATE,
+        od,
+    );
+    const TPDO_COB_ID: u16 = 0x182;
+
+    // Configure TPDO1 as event driven, with a 1ms inhibit time, mapping 0x2000sub1
+    let comm = find_object(od, 0x1801).unwrap();
+    comm.write(1, &(TPDO_COB_ID as u32).to_le_bytes()).unwrap();
+    comm.write(2, &[254]).unwrap();
+    comm.write(3, &10u16.to_le_bytes()).unwrap();
+    let mapping = find_object(od, 0x1A01).unwrap();
+    let mapping_entry: u32 = (0x2000 << 16) | (1 << 8) | 32;
+    mapping.write(1, &mapping_entry.to_le_bytes()).unwrap();
+    mapping.write(0, &[1]).unwrap();
+
+    // Run process, and return the number of TPDO1 messages sent and the next action time
+    let process = |node: &mut Node, now_us: u64| {
+        let mut count = 0;
+        let result = node.process(now_us, &mut |msg| {
+            if msg.id() == CanId::Std(TPDO_COB_ID) {
+                count += 1;
+            }
+        });
+        (count, result.next_action_us)
+    };
+
+    process(&mut node, 0);
+    node.request_state(NmtState::Operational);
+    process(&mut node, 0);
+
+    let obj = find_object(od, 0x2000).unwrap();
+    obj.set_event_flag(1).unwrap();
+    assert_eq!(1, process(&mut node, 10).0);
+
+    // Events within the inhibit time are held
+    obj.set_event_flag(1).unwrap();
+    let (count, next_action_us) = process(&mut node, 100);
+    assert_eq!(0, count);
+    assert!(next_action_us.unwrap() <= 910);
+    set_event_flags_bulk(&[(obj, 1), (obj, 1)]).unwrap();
+    assert_eq!(0, process(&mut node, 500).0);
+
+    // And sent once when it expires
+    assert_eq!(1, process(&mut node, 1010).0);
+    assert_eq!(0, process(&mut node, 5000).0);
+
+    // Disable the PDO again for other tests
+    comm.write(1, &(TPDO_COB_ID as u32 | 1 << 31).to_le_bytes())
+        .unwrap();
+    comm.write(3, &0u16.to_le_bytes()).unwrap();
+}
+
+/// An application object which copies written values to 0x2000sub1, and flags it for transmission
+struct NotifyOnWrite;
+
+impl SubObjectAccess for NotifyOnWrite {
+    fn read(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize, AbortCode> {
+        Ok(0)
+    }
+
+    fn read_size(&self) -> usize {
+        0
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), AbortCode> {
+        let obj = find_object(&object_dict1::OD_TABLE, 0x2000).unwrap();
+        obj.write(1, data)?;
+        obj.set_event_flag(1)
+    }
+}
+
+#[serial]
+#[test]
+fn test_sdo_response_sent_before_triggered_tpdo() {
+    let od = &object_dict1::OD_TABLE;
+    let mbox = &object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(1).unwrap(), mbox, &object_dict1::NODE_STATE, od);
+    const TPDO_COB_ID: u16 = 0x182;
+    const SDO_RESP_ID: CanId = CanId::Std(0x581);
+
+    object_dict1::OBJECT3007
+        .value
+        .register_handler(&NotifyOnWrite);
+
+    // Configure TPDO1 as event driven, mapping 0x2000sub1
+    let comm = find_object(od, 0x1801).unwrap();
+    comm.write(1, &(TPDO_COB_ID as u32).to_le_bytes()).unwrap();
+    comm.write(2, &[254]).unwrap();
+    let mapping = find_object(od, 0x1A01).unwrap();
+    let mapping_entry: u32 = (0x2000 << 16) | (1 << 8) | 32;
+    mapping.write(1, &mapping_entry.to_le_bytes()).unwrap();
+    mapping.write(0, &[1]).unwrap();
+
+    node.process(0, &mut |_| {});
+    node.request_state(NmtState::Operational);
+    node.process(0, &mut |_| {});
+
+    // Write the application object, which triggers the TPDO from its write handler
+    let req = SdoRequest::expedited_download(0x3007, 0, &[1, 2, 3, 4]);
+    mbox.store_message(req.to_can_message(CanId::Std(0x601)))
+        .unwrap();
+    let mut sent = Vec::new();
+    node.process(10, &mut |msg| {
+        if msg.id() == SDO_RESP_ID || msg.id() == CanId::Std(TPDO_COB_ID) {
+            sent.push(msg)
+        }
+    });
+
+    let ids: Vec<CanId> = sent.iter().map(|msg| msg.id()).collect();
+    assert_eq!(vec![SDO_RESP_ID, CanId::Std(TPDO_COB_ID)], ids);
+    assert_eq!(&[1, 2, 3, 4], &sent[1].data()[0..4]);
+
+    // Disable the PDO again for other tests
+    comm.write(1, &(TPDO_COB_ID as u32 | 1 << 31).to_le_bytes())
+        .unwrap();
+}
+
+/// Values received by the RPDO callback, as (index, sub, data)
+static RECEIVED_VALUES: Mutex<Vec<(u16, u8, Vec<u8>)>> = Mutex::new(Vec::new());
+
+fn record_rpdo(values: &[RpdoValue]) {
+    let mut received = RECEIVED_VALUES.lock().unwrap();
+    for v in values {
+        received.push((v.index, v.sub, v.data.to_vec()));
+    }
+}
+
+#[serial]
+#[test]
+fn test_rpdo_callback() {
+    let od = &object_dict1::OD_TABLE;
+    let mbox = &object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(1).unwrap(), mbox, &object_dict1::NODE_STATE, od);
+    const RPDO_COB_ID: u16 = 0x202;
+
+    assert!(node.register_rpdo_callback(1, &record_rpdo));
+    assert!(!node.register_rpdo_callback(4, &record_rpdo));
+
+    let obj = find_object(od, 0x2000).unwrap();
+    let orig_sub1 = obj.read_u32(1).unwrap();
+    let orig_sub2 = obj.read_u32(2).unwrap();
+
+    // Configure RPDO1 to map 0x2000sub1 and 0x2000sub2
+    let comm = find_object(od, 0x1401).unwrap();
+    comm.write(1, &(RPDO_COB_ID as u32).to_le_bytes()).unwrap();
+    let mapping = find_object(od, 0x1601).unwrap();
+    mapping
+        .write(1, &((0x2000u32 << 16) | (1 << 8) | 32).to_le_bytes())
+        .unwrap();
+    mapping
+        .write(2, &((0x2000u32 << 16) | (2 << 8) | 32).to_le_bytes())
+        .unwrap();
+    mapping.write(0, &[2]).unwrap();
+
+    node.process(0, &mut |_| {});
+    node.request_state(NmtState::Operational);
+    node.process(0, &mut |_| {});
+    RECEIVED_VALUES.lock().unwrap().clear();
+
+    mbox.store_message(CanMessage::new(
+        CanId::Std(RPDO_COB_ID),
+        &[1, 2, 3, 4, 5, 6, 7, 8],
+    ))
+    .unwrap();
+    let result = node.process(10, &mut |_| {});
+    assert!(result.objects_updated);
+
+    assert_eq!(
+        vec![(0x2000, 1, vec![1, 2, 3, 4]), (0x2000, 2, vec![5, 6, 7, 8]),],
+        *RECEIVED_VALUES.lock().unwrap()
+    );
+    // The values are stored before the callback is called
+    assert_eq!(0x04030201, obj.read_u32(1).unwrap());
+    assert_eq!(0x08070605, obj.read_u32(2).unwrap());
+
+    // Disable the PDO and restore the values for other tests
+    comm.write(1, &(RPDO_COB_ID as u32 | 1 << 31).to_le_bytes())
+        .unwrap();
+    obj.write(1, &orig_sub1.to_le_bytes()).unwrap();
+    obj.write(2, &orig_sub2.to_le_bytes()).unwrap();
+}
+
+#[serial]
+#[test]
+fn test_tpdo_manual_trigger() {
+    let od = &object_dict1::OD_TABLE;
+    let mut node = Node::new(
+        NodeId::new(1).unwrap(),
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+        od,
+    );
+    const TPDO_COB_ID: u16 = 0x182;
+
+    // Configure TPDO1 as event driven, with a 1ms inhibit time, mapping 0x2000sub1
+    let comm = find_object(od, 0x1801).unwrap();
+    comm.write(1, &(TPDO_COB_ID as u32).to_le_bytes()).unwrap();
+    comm.write(2, &[254]).unwrap();
+    comm.write(3, &10u16.to_le_bytes()).unwrap();
+    let mapping = find_object(od, 0x1A01).unwrap();
+    let mapping_entry: u32 = (0x2000 << 16) | (1 << 8) | 32;
+    mapping.write(1, &mapping_entry.to_le_bytes()).unwrap();
+    mapping.write(0, &[1]).unwrap();
+
+    // The PDO state is shared with other tests, so start well after any earlier transmission
+    const T0: u64 = 1_000_000_000;
+    let process = |node: &mut Node, now_us: u64| {
+        let mut count = 0;
+        node.process(T0 + now_us, &mut |msg| {
+            if msg.id() == CanId::Std(TPDO_COB_ID) {
+                count += 1;
+            }
+        });
+        count
+    };
+
+    process(&mut node, 0);
+    node.request_state(NmtState::Operational);
+    process(&mut node, 0);
+
+    // Nothing is sent without an event
+    assert_eq!(0, process(&mut node, 10));
+
+    // A trigger sends the PDO without any event flag set
+    assert!(node.trigger_tpdo(1));
+    assert_eq!(1, process(&mut node, 20));
+    assert_eq!(0, process(&mut node, 30));
+
+    // The inhibit time is respected
+    assert!(node.trigger_tpdo(1));
+    assert_eq!(0, process(&mut node, 500));
+    assert_eq!(1, process(&mut node, 1020));
+
+    // Unknown TPDO numbers are rejected
+    assert!(!node.trigger_tpdo(4));
+
+    // Disable the PDO again for other tests
+    comm.write(1, &(TPDO_COB_ID as u32 | 1 << 31).to_le_bytes())
+        .unwrap();
+    comm.write(3, &0u16.to_le_bytes()).unwrap();
+}
+
+#[serial]
+#[test]
+fn test_sync_window_length() {
+    let od = &object_dict1::OD_TABLE;
+    let mbox = &object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(1).unwrap(), mbox, &object_dict1::NODE_STATE, od);
+    const TPDO_COB_ID: u16 = 0x182;
+
+    // Configure TPDO1 to be sent on every SYNC, mapping 0x2000sub1
+    let comm = find_object(od, 0x1801).unwrap();
+    comm.write(1, &(TPDO_COB_ID as u32).to_le_bytes()).unwrap();
+    comm.write(2, &[0]).unwrap();
+    let mapping = find_object(od, 0x1A01).unwrap();
+    let mapping_entry: u32 = (0x2000 << 16) | (1 << 8) | 32;
+    mapping.write(1, &mapping_entry.to_le_bytes()).unwrap();
+    mapping.write(0, &[1]).unwrap();
+    // Set a 1ms synchronous window
+    let window = find_object(od, 0x1007).unwrap();
+    window.write(0, &1000u32.to_le_bytes()).unwrap();
+
+    let process = |node: &mut Node, now_us: u64| {
+        let mut count = 0;
+        node.process(now_us, &mut |msg| {
+            if msg.id() == CanId::Std(TPDO_COB_ID) {
+                count += 1;
+            }
+        });
+        count
+    };
+
+    process(&mut node, 0);
+    node.request_state(NmtState::Operational);
+    process(&mut node, 0);
+
+    // Processed within the window, the TPDO is sent
+    mbox.store_message_at(SyncObject::new(1).into(), 10_000)
+        .unwrap();
+    assert_eq!(1, process(&mut node, 10_500));
+    assert_eq!(0, node.sync_window_skip_count());
+
+    // Processed after the window, it is skipped for this cycle
+    mbox.store_message_at(SyncObject::new(2).into(), 20_000)
+        .unwrap();
+    assert_eq!(0, process(&mut node, 21_500));
+    assert_eq!(1, node.sync_window_skip_count());
+
+    // Without a reception time, the SYNC is assumed to be received at the time of processing
+    mbox.store_message(SyncObject::new(3).into()).unwrap();
+    assert_eq!(1, process(&mut node, 40_000));
+    assert_eq!(1, node.sync_window_skip_count());
+
+    // Restore defaults for other tests
+    window.write(0, &0u32.to_le_bytes()).unwrap();
+    comm.write(1, &(TPDO_COB_ID as u32 | 1 << 31).to_le_bytes())
+        .unwrap();
+}
+
+/// An application implemented u32 VAR object, with an event flag for triggering TPDOs
+struct CallbackValue {
+    value: AtomicU32,
+    flags: ObjectFlags<1>,
+}
+
+impl ObjectAccess for CallbackValue {
+    fn read(&self, sub: u8, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        let bytes = self.value.load(Ordering::Relaxed).to_le_bytes();
+        let len = buf.len().min(bytes.len().saturating_sub(offset));
+        buf[..len].copy_from_slice(&bytes[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn read_size(&self, sub: u8) -> Result<usize, AbortCode> {
+        self.sub_info(sub).map(|info| info.size)
+    }
+
+    fn write(&self, sub: u8, data: &[u8]) -> Result<(), AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        let value = data.try_into().map_err(|_| AbortCode::DataTypeMismatch)?;
+        self.value
+            .store(u32::from_le_bytes(value), Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Var
+    }
+
+    fn sub_info(&self, sub: u8) -> Result<SubInfo, AbortCode> {
+        if sub == 0 {
+            Ok(SubInfo {
+                pdo_mapping: zencan_common::objects::PdoMapping::Both,
+                ..SubInfo::new_u32().rw_access()
+            })
+        } else {
+            Err(AbortCode::NoSuchSubIndex)
+        }
+    }
+
+    fn set_event_flag(&self, sub: u8) -> Result<(), AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        self.flags.set_flag(0);
+        Ok(())
+    }
+
+    fn read_event_flag(&self, sub: u8) -> bool {
+        self.flags.get_flag(sub)
+    }
+
+    fn clear_events(&self) {
+        self.flags.clear();
+    }
+}
+
+static CALLBACK_VALUE: CallbackValue = CallbackValue {
+    value: AtomicU32::new(0),
+    flags: ObjectFlags::new(object_dict1::NODE_STATE.pdo_sync()),
+};
+
+#[serial]
+#[test]
+fn test_pdo_mapping_of_callback_object() {
+    let od = &object_dict1::OD_TABLE;
+    let mbox = &object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(1).unwrap(), mbox, &object_dict1::NODE_STATE, od);
+    const TPDO_COB_ID: u16 = 0x182;
+    const RPDO_COB_ID: u16 = 0x202;
+    let mapping_entry: u32 = (0x3200 << 16) | 32;
+
+    // The object cannot be mapped until its handler is registered
+    let tpdo_mapping = find_object(od, 0x1A01).unwrap();
+    assert_eq!(
+        Err(AbortCode::ResourceNotAvailable),
+        tpdo_mapping.write(1, &mapping_entry.to_le_bytes())
+    );
+    object_dict1::OBJECT3200.register_handler(&CALLBACK_VALUE);
+
+    // Configure TPDO1 as event driven, and RPDO1, both mapping 0x3200sub0
+    let tpdo_comm = find_object(od, 0x1801).unwrap();
+    tpdo_comm
+        .write(1, &(TPDO_COB_ID as u32).to_le_bytes())
+        .unwrap();
+    tpdo_comm.write(2, &[254]).unwrap();
+    tpdo_mapping.write(1, &mapping_entry.to_le_bytes()).unwrap();
+    tpdo_mapping.write(0, &[1]).unwrap();
+    let rpdo_comm = find_object(od, 0x1401).unwrap();
+    rpdo_comm
+        .write(1, &(RPDO_COB_ID as u32).to_le_bytes())
+        .unwrap();
+    let rpdo_mapping = find_object(od, 0x1601).unwrap();
+    rpdo_mapping.write(1, &mapping_entry.to_le_bytes()).unwrap();
+    rpdo_mapping.write(0, &[1]).unwrap();
+
+    // The PDO state is shared with other tests, so start well after any earlier transmission
+    const T0: u64 = 2_000_000_000;
+    let process = |node: &mut Node, now_us: u64| {
+        let mut sent = Vec::new();
+        node.process(T0 + now_us, &mut |msg| {
+            if msg.id() == CanId::Std(TPDO_COB_ID) {
+                sent.push(msg);
+            }
+        });
+        sent
+    };
+
+    process(&mut node, 0);
+    node.request_state(NmtState::Operational);
+    process(&mut node, 0);
+
+    // The event flag of the handler triggers the TPDO, which is read through the handler
+    CALLBACK_VALUE.value.store(0x12345678, Ordering::Relaxed);
+    object_dict1::OBJECT3200.set_event_flag(0).unwrap();
+    let sent = process(&mut node, 10);
+    assert_eq!(1, sent.len());
+    assert_eq!(&0x12345678u32.to_le_bytes(), &sent[0].data()[0..4]);
+    assert!(!CALLBACK_VALUE.read_event_flag(0));
+    assert!(process(&mut node, 20).is_empty());
+
+    // A received RPDO is written through the handler
+    mbox.store_message(CanMessage::new(CanId::Std(RPDO_COB_ID), &[1, 2, 3, 4]))
+        .unwrap();
+    process(&mut node, 30);
+    assert_eq!(0x04030201, CALLBACK_VALUE.value.load(Ordering::Relaxed));
+
+    // Disable the PDOs again for other tests
+    tpdo_comm
+        .write(1, &(TPDO_COB_ID as u32 | 1 << 31).to_le_bytes())
+        .unwrap();
+    rpdo_comm
+        .write(1, &(RPDO_COB_ID as u32 | 1 << 31).to_le_bytes())
+        .unwrap();
+}
+
+#[serial]
+#[test]
+fn test_tpdo_stamps() {
+    let od = &object_dict1::OD_TABLE;
+    let mbox = &object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(1).unwrap(), mbox, &object_dict1::NODE_STATE, od);
+    // TPDO2 is stamped with the SYNC counter, and TPDO3 with a timestamp, in example1.toml
+    const SYNC_COB_ID: u16 = 0x183;
+    const TIMESTAMP_COB_ID: u16 = 0x184;
+    let mapping_entry: u32 = (0x2000 << 16) | (1 << 8) | 32;
+    let value = find_object(od, 0x2000).unwrap().read_u32(1).unwrap();
+
+    // Configure TPDO2 to send on every SYNC, and TPDO3 as event driven
+    let sync_comm = find_object(od, 0x1802).unwrap();
+    sync_comm
+        .write(1, &(SYNC_COB_ID as u32).to_le_bytes())
+        .unwrap();
+    sync_comm.write(2, &[0]).unwrap();
+    let timestamp_comm = find_object(od, 0x1803).unwrap();
+    timestamp_comm
+        .write(1, &(TIMESTAMP_COB_ID as u32).to_le_bytes())
+        .unwrap();
+    timestamp_comm.write(2, &[254]).unwrap();
+    for index in [0x1A02, 0x1A03] {
+        let mapping = find_object(od, index).unwrap();
+        mapping.write(1, &mapping_entry.to_le_bytes()).unwrap();
+        mapping.write(0, &[1]).unwrap();
+    }
+
+    let process = |node: &mut Node, now_us: u64| {
+        let mut sent = Vec::new();
+        node.process(now_us, &mut |msg| {
+            if msg.id() == CanId::Std(SYNC_COB_ID) || msg.id() == CanId::Std(TIMESTAMP_COB_ID) {
+                sent.push(msg);
+            }
+        });
+        sent
+    };
+
+    process(&mut node, 0);
+    node.request_state(NmtState::Operational);
+    process(&mut node, 0);
+
+    let mut decoder = StampedPdoDecoder::new(TpdoStamp::SyncCounter);
+    let mut sync = |node: &mut Node, count: u8, now_us: u64| {
+        mbox.store_message(SyncObject::new(count).into()).unwrap();
+        let sent = process(node, now_us);
+        assert_eq!(1, sent.len());
+        assert_eq!(&value.to_le_bytes(), &sent[0].data()[0..4]);
+        let pdo = decoder.decode(sent[0].data()).unwrap();
+        (pdo.stamp, pdo.check)
+    };
+
+    assert_eq!(
+        (Some(StampValue::SyncCounter(5)), StampCheck::First),
+        sync(&mut node, 5, 1000)
+    );
+    // A SYNC without a counter is counted by the node
+    assert_eq!(
+        (Some(StampValue::SyncCounter(6)), StampCheck::Ok),
+        sync(&mut node, 0, 2000)
+    );
+    assert_eq!(
+        (Some(StampValue::SyncCounter(9)), StampCheck::Dropped(2)),
+        sync(&mut node, 9, 3000)
+    );
+
+    // The event driven TPDO carries the time in milliseconds
+    assert!(node.trigger_tpdo(3));
+    let sent = process(&mut node, 70_123_456);
+    assert_eq!(1, sent.len());
+    assert_eq!(&value.to_le_bytes(), &sent[0].data()[0..4]);
+    assert_eq!(
+        Some(StampValue::Timestamp((70_123u32 % 65536) as u16)),
+        TpdoStamp::Timestamp.read(sent[0].data())
+    );
+
+    // Disable the PDOs again for other tests
+    sync_comm
+        .write(1, &(SYNC_COB_ID as u32 | 1 << 31).to_le_bytes())
+        .unwrap();
+    timestamp_comm
+        .write(1, &(TIMESTAMP_COB_ID as u32 | 1 << 31).to_le_bytes())
+        .unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn test_pdo_statistics() {
+    let od = &object_dict1::OD_TABLE;
+    let state = &object_dict1::NODE_STATE;
+    let mbox = &object_dict1::NODE_MBOX;
+    let (mut node, mut client, mut bus) = setup(od, mbox, state);
+
+    let _logger = BusLogger::new(bus.new_receiver());
+    let mut rx = bus.new_receiver();
+    let mut nmt = NmtMaster::new(bus.new_sender(), bus.new_receiver());
+    let mut pdo_sender = bus.new_sender();
+
+    let test_task = async move {
+        // TPDO 1 is sent on each SYNC, and RPDO 1 writes 0x2000sub1
+        let mapping_entry: u32 = (0x2000 << 16) | (1 << 8) | 32;
+        client.download_u32(0x1801, 1, 0x182).await.unwrap();
+        client.download_u8(0x1801, 2, 1).await.unwrap();
+        client.download_u32(0x1A01, 1, mapping_entry).await.unwrap();
+        client.download_u8(0x1A01, 0, 1).await.unwrap();
+        client.download_u32(0x1401, 1, 0x202).await.unwrap();
+        client.download_u32(0x1601, 1, mapping_entry).await.unwrap();
+        client.download_u8(0x1601, 0, 1).await.unwrap();
+        nmt.nmt_start(0).await.unwrap();
+
+        let tpdo_before = client.read_tpdo_counts(1).await.unwrap();
+        let rpdo_before = client.read_rpdo_counts(1).await.unwrap();
+        // The other PDOs are not counted
+        let other_tpdo = client.read_tpdo_counts(3).await.unwrap();
+
+        rx.flush();
+        for _ in 0..2 {
+            pdo_sender.send(SyncObject::new(1).into()).await.unwrap();
+            timeout(Duration::from_millis(50), async {
+                while rx.recv().await.unwrap().id != CanId::std(0x182) {}
+            })
+            .await
+            .expect("No TPDO sent");
+        }
+        pdo_sender
+            .send(CanMessage::new(CanId::std(0x202), &7u32.to_le_bytes()))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let tpdo = client.read_tpdo_counts(1).await.unwrap();
+        assert_eq!(tpdo_before.count + 2, tpdo.count);
+        let rpdo = client.read_rpdo_counts(1).await.unwrap();
+        assert_eq!(rpdo_before.count + 1, rpdo.count);
+        assert_eq!(other_tpdo, client.read_tpdo_counts(3).await.unwrap());
+
+        // The time of the last transfer advances. The statistics are shared with other tests, which
+        // may have run their nodes on a different clock, so only times from this test are compared.
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        pdo_sender.send(SyncObject::new(1).into()).await.unwrap();
+        pdo_sender
+            .send(CanMessage::new(CanId::std(0x202), &8u32.to_le_bytes()))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(client.read_tpdo_counts(1).await.unwrap().last_ms >= tpdo.last_ms + 10);
+        assert!(client.read_rpdo_counts(1).await.unwrap().last_ms >= rpdo.last_ms + 10);
+
+        // The statistics cannot be written
+        let err = client.download_u32(0x500A, 2, 0).await.unwrap_err();
+        assert_eq!(Some(AbortCode::ReadOnly), err.abort_code().unwrap().known());
+        assert_eq!(4, client.upload_u8(0x500D, 0).await.unwrap());
+
+        // Disable the PDOs again, as the node state is shared with the other tests
+        client.download_u32(0x1801, 1, 0x8000_0182).await.unwrap();
+        client.download_u32(0x1401, 1, 0x8000_0202).await.unwrap();
+    };
+
+    let mut sender = bus.new_sender();
+    test_with_background_process(&mut [&mut node], &mut sender, test_task).await;
+}
+
+#[serial]
+#[test]
+fn test_tpdo_round_robin() {
+    let od = &object_dict1::OD_TABLE;
+    let mut node = Node::new(
+        NodeId::new(1).unwrap(),
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+        od,
+    );
+    const COB_BASE: u16 = 0x190;
+
+    // Configure all four TPDOs as event driven, with no mappings
+    for i in 0..4 {
+        let comm = find_object(od, 0x1800 + i).unwrap();
+        comm.write(1, &((COB_BASE + i) as u32).to_le_bytes())
+            .unwrap();
+        comm.write(2, &[254]).unwrap();
+    }
+
+    // The PDO state is shared with other tests, so start well after any earlier transmission
+    const T0: u64 = 2_000_000_000;
+    node.process_queued(T0);
+    node.request_state(NmtState::Operational);
+    node.process_queued(T0);
+    while node.pop_tx().is_some() {}
+
+    // Trigger every TPDO, then return the TPDOs which fit in the three message transmit queue
+    let mut now_us = T0;
+    let mut process = |node: &mut Node| {
+        for i in 0..4 {
+            node.trigger_tpdo(i);
+        }
+        now_us += 1000;
+        node.process_queued(now_us);
+        core::iter::from_fn(|| node.pop_tx())
+            .map(|msg| msg.id().raw() as u16 - COB_BASE)
+            .collect::<Vec<_>>()
+    };
+
+    // By default, TPDOs are sent in order of PDO number, so the last one is never sent
+    assert_eq!(vec![0, 1, 2], process(&mut node));
+    assert_eq!(vec![0, 1, 2], process(&mut node));
+
+    // Round-robin starts with the first TPDO rejected in the previous call
+    node.set_tpdo_order(TpdoOrder::RoundRobin);
+    assert_eq!(vec![0, 1, 2], process(&mut node));
+    assert_eq!(vec![3, 0, 1], process(&mut node));
+    assert_eq!(vec![2, 3, 0], process(&mut node));
+    assert_eq!(vec![1, 2, 3], process(&mut node));
+
+    // Send the TPDO still pending, and disable the PDOs again for other tests
+    node.process_queued(T0 + 1_000_000);
+    assert_eq!(
+        Some(CanId::std(COB_BASE)),
+        node.pop_tx().map(|msg| msg.id())
+    );
+    node.set_tpdo_order(TpdoOrder::ByNumber);
+    for i in 0..4 {
+        let comm = find_object(od, 0x1800 + i).unwrap();
+        comm.write(1, &((COB_BASE + i) as u32 | 1 << 31).to_le_bytes())
+            .unwrap();
+    }
+}
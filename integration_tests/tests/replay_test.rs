@@ -0,0 +1,221 @@
+//! Tests that node behavior is determined only by its inputs and the time passed to process
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::{
+    testing::{
+        replay::{check_replay_file, replay, Direction, FrameRecording, ReplayServer},
+        NodeFixture,
+    },
+    BusManager,
+};
+use zencan_common::{
+    messages::{NmtCommand, NmtCommandSpecifier},
+    sdo::SdoRequest,
+    CanId, CanMessage, NodeId,
+};
+use zencan_node::{object_dict::find_object, Node};
+
+const NODE_ID: u8 = 1;
+const TICK_US: u64 = 500;
+const END_US: u64 = 100_000;
+
+/// Frames received by the node, with the time at which they are delivered
+fn input_log() -> Vec<(u64, CanMessage)> {
+    let sdo_rx = CanId::sdo_rx(NODE_ID);
+    vec![
+        (
+            2_000,
+            NmtCommand {
+                cs: NmtCommandSpecifier::Start,
+                node: NODE_ID,
+            }
+            .into(),
+        ),
+        // Start a segmented upload of the device name, and abandon it so that it times out
+        (
+            3_000,
+            SdoRequest::initiate_upload(0x1008, 0).to_can_message(sdo_rx),
+        ),
+        (
+            4_000,
+            SdoRequest::upload_segment_request(false).to_can_message(sdo_rx),
+        ),
+        (
+            50_000,
+            SdoRequest::initiate_upload(0x1018, 1).to_can_message(sdo_rx),
+        ),
+    ]
+}
+
+/// Run a fresh node against the input log using a virtual clock, and return the transmitted
+/// frames with the time at which they were sent
+fn replay_inputs(inputs: &[(u64, CanMessage)]) -> Vec<(u64, CanMessage)> {
+    let od = &object_dict1::OD_TABLE;
+    let heartbeat_time = find_object(od, 0x1017).unwrap();
+    heartbeat_time.write(0, &10u16.to_le_bytes()).unwrap();
+    let mut node = Node::new(
+        NodeId::new(NODE_ID).unwrap(),
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+        od,
+    );
+
+    let mut output = Vec::new();
+    let mut inputs = inputs.iter().peekable();
+    let mut now_us = 0;
+    while now_us <= END_US {
+        while let Some((_, msg)) = inputs.next_if(|(t, _)| *t <= now_us) {
+            object_dict1::NODE_MBOX.store_message(*msg).unwrap();
+        }
+        node.process(now_us, &mut |msg| output.push((now_us, msg)));
+        now_us += TICK_US;
+    }
+    // Restore the default so that other tests are not affected
+    heartbeat_time.write(0, &0u16.to_le_bytes()).unwrap();
+    output
+}
+
+#[serial]
+#[test]
+fn test_replay_is_deterministic() {
+    let inputs = input_log();
+    let first = replay_inputs(&inputs);
+    let second = replay_inputs(&inputs);
+    assert_eq!(first, second);
+
+    // Boot up, plus a heartbeat every 10ms
+    let heartbeats: Vec<u64> = first
+        .iter()
+        .filter(|(_, msg)| msg.id() == CanId::heartbeat(NODE_ID))
+        .map(|(t, _)| *t)
+        .collect();
+    assert_eq!((0..=10).map(|i| i * 10_000).collect::<Vec<_>>(), heartbeats);
+
+    // The abandoned upload is aborted after the SDO timeout, measured only with the virtual clock
+    let sdo_tx = CanId::sdo_tx(NODE_ID);
+    let sdo_times: Vec<u64> = first
+        .iter()
+        .filter(|(_, msg)| msg.id() == sdo_tx)
+        .map(|(t, _)| *t)
+        .collect();
+    assert_eq!(vec![3_000, 4_000, 29_500, 50_000], sdo_times);
+}
+
+fn new_node() -> Node {
+    Node::new(
+        NodeId::new(NODE_ID).unwrap(),
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+        &object_dict1::OD_TABLE,
+    )
+}
+
+#[serial]
+#[test]
+fn test_replay_fixture() {
+    let mut node = new_node();
+    check_replay_file(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/replay/sdo_upload.txt"),
+        &mut node,
+        &object_dict1::NODE_MBOX,
+        TICK_US,
+    );
+}
+
+#[serial]
+#[tokio::test]
+async fn test_capture_and_replay() {
+    let mut fixture = NodeFixture::new(
+        NODE_ID,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut client = fixture.sdo_client();
+    fixture.start_capture();
+    fixture
+        .run(async move {
+            client.upload_u32(0x1000, 0).await.unwrap();
+            client.upload(0x4fff, 0).await.unwrap_err();
+        })
+        .await;
+    let capture = fixture.take_capture();
+    drop(fixture);
+
+    let sdo_rx = CanId::sdo_rx(NODE_ID);
+    let sdo_tx = CanId::sdo_tx(NODE_ID);
+    let frames: Vec<_> = capture
+        .frames
+        .iter()
+        .map(|f| (f.direction, f.msg.id()))
+        .collect();
+    assert_eq!(
+        vec![
+            (Direction::Rx, sdo_rx),
+            (Direction::Tx, sdo_tx),
+            (Direction::Rx, sdo_rx),
+            (Direction::Tx, sdo_tx),
+        ],
+        frames
+    );
+    // The capture can be stored as text
+    assert_eq!(capture, capture.to_string().parse().unwrap());
+
+    // A fresh node sends the same responses when replayed against the capture. The tick is short
+    // so that each request is delivered before the next.
+    let mut node = new_node();
+    let replayed = replay(&mut node, &object_dict1::NODE_MBOX, &capture, 20);
+    let sdo_responses = |r: &FrameRecording| -> Vec<CanMessage> {
+        r.tx_frames()
+            .filter(|f| f.msg.id() == sdo_tx)
+            .map(|f| f.msg)
+            .collect()
+    };
+    assert_eq!(sdo_responses(&capture), sdo_responses(&replayed));
+}
+
+#[serial]
+#[tokio::test]
+async fn test_manager_capture_and_replay_server() {
+    let mut fixture = NodeFixture::new(
+        NODE_ID,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+    assert!(!manager.is_capturing());
+    manager.start_capture();
+    assert!(manager.is_capturing());
+    let name = fixture
+        .run(async {
+            let mut client = manager.sdo_client(NODE_ID);
+            client.upload(0x1008, 0).await.unwrap()
+        })
+        .await;
+    let mut capture = manager.stop_capture().unwrap();
+    assert!(!manager.is_capturing());
+
+    // A segmented upload, with the requests sent by the manager recorded as received by the node
+    let sdo_rx = CanId::sdo_rx(NODE_ID);
+    let sdo_tx = CanId::sdo_tx(NODE_ID);
+    capture
+        .frames
+        .retain(|f| f.msg.id() == sdo_rx || f.msg.id() == sdo_tx);
+    assert!(capture.frames.len() >= 4);
+    for frame in &capture.frames {
+        let expected = if frame.msg.id() == sdo_rx {
+            Direction::Rx
+        } else {
+            Direction::Tx
+        };
+        assert_eq!(expected, frame.direction);
+    }
+
+    // The recording stands in for the node, without running it
+    let recording: FrameRecording = capture.to_string().parse().unwrap();
+    let server = ReplayServer::new(&recording);
+    let mut client = server.sdo_client(NODE_ID);
+    assert_eq!(name, client.upload(0x1008, 0).await.unwrap());
+    server.finish().unwrap();
+}
@@ -0,0 +1,66 @@
+//! Tests for scanning the bus with the client
+//!
+
+use std::time::Duration;
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::{testing::NodeFixture, BusManager, ScanOptions};
+
+#[serial]
+#[tokio::test]
+async fn test_scan_capabilities() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut client = fixture.sdo_client();
+    let mut manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let test_task = async move {
+        let heartbeat_period = client.upload_u16(0x1017, 0).await.unwrap();
+
+        let opts = ScanOptions {
+            timeout: Duration::from_millis(50),
+            parallelism: 32,
+            ..Default::default()
+        };
+        let nodes = manager.scan_nodes_with(&opts).await;
+        assert_eq!(1, nodes.len());
+        assert_eq!(None, nodes[0].capabilities);
+
+        let opts = ScanOptions {
+            probe_capabilities: true,
+            ..opts
+        };
+        let nodes = manager.scan_nodes_with(&opts).await;
+        assert_eq!(1, nodes.len());
+        let capabilities = nodes[0].capabilities.expect("Capabilities were not probed");
+        assert_eq!(Some(true), capabilities.block_transfer);
+        assert_eq!(Some(true), capabilities.lss);
+        assert_eq!(Some(4), capabilities.num_rpdo);
+        assert_eq!(Some(4), capabilities.num_tpdo);
+        assert_eq!(Some(heartbeat_period), capabilities.heartbeat_period_ms);
+        assert_eq!(
+            Some(heartbeat_period != 0),
+            capabilities.heartbeat_configured()
+        );
+
+        // Probing the block transfer support leaves the device type unchanged
+        let device_type = client.read_device_type().await.unwrap();
+        assert_eq!(nodes[0].device_type, Some(device_type));
+
+        // Capabilities are kept by later scans which do not probe them
+        let opts = ScanOptions {
+            probe_capabilities: false,
+            ..opts
+        };
+        manager.scan_nodes_with(&opts).await;
+        let cached = manager.node_list().await;
+        assert_eq!(Some(capabilities), cached[0].capabilities);
+    };
+
+    fixture.run(test_task).await;
+}
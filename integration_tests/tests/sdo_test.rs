@@ -2,14 +2,26 @@ use std::{
     cell::RefCell,
     sync::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
-        Mutex,
+        Arc, Mutex,
     },
+    time::{Duration, Instant},
 };
 
-use integration_tests::sim_bus::SimBus;
-use zencan_client::{RawAbortCode, SdoClient, SdoClientError};
-use zencan_common::{sdo::AbortCode, NodeId};
-use zencan_node::object_dict::SubObjectAccess;
+use integration_tests::sim_bus::{SimBus, SimBusReceiver, SimBusSender};
+use zencan_client::{
+    testing::NodeFixture, BusManager, DownloadCheckpoint, FileTransferError, FramePacing,
+    RawAbortCode, ResumableDownloadError, SdoClient, SdoClientError, SdoCobIds, TransferMode,
+    VerifyMethod,
+};
+use zencan_common::{
+    messages::CanId,
+    objects::DataType,
+    sdo::{AbortCode, SdoRequest, SdoResponse, SdoServerStatus, SdoTransferState},
+    traits::{AsyncCanReceiver, AsyncCanSender},
+    value::Value,
+    CanMessage, NodeId,
+};
+use zencan_node::object_dict::{find_object, SubObjectAccess};
 use zencan_node::Node;
 
 mod utils;
@@ -44,6 +56,63 @@ async fn test_sdo_read() {
     .await;
 }
 
+#[tokio::test]
+#[serial_test::serial]
+async fn test_read_many() {
+    const SLAVE_NODE_ID: u8 = 1;
+
+    let od = &integration_tests::object_dict1::OD_TABLE;
+    let state = &integration_tests::object_dict1::NODE_STATE;
+    let mbox = &integration_tests::object_dict1::NODE_MBOX;
+
+    let mut node = Node::new(NodeId::new(SLAVE_NODE_ID).unwrap(), mbox, state, od);
+    let mut bus = SimBus::new(vec![mbox]);
+    let mut sender = bus.new_sender();
+
+    test_with_background_process(&mut [&mut node], &mut sender, async move {
+        let sender = bus.new_sender();
+        let receiver = bus.new_receiver();
+        let mut client = SdoClient::new_std(SLAVE_NODE_ID, sender, receiver);
+
+        client.write_u32(0x3000, 0, 0x12345678).await.unwrap();
+        client.write_i8(0x3003, 0, -3).await.unwrap();
+        client.download(0x2003, 0, b"Hello world!").await.unwrap();
+
+        let results = client
+            .read_many(&[(0x3000, 0), (0x4000, 0), (0x2003, 0), (0x3003, 0)])
+            .await;
+        assert_eq!(4, results.len());
+        assert_eq!(Ok(0x12345678u32.to_le_bytes().to_vec()), results[0]);
+        assert_eq!(
+            Err(SdoClientError::ServerAbort {
+                index: 0x4000,
+                sub: 0,
+                abort_code: RawAbortCode::Valid(AbortCode::NoSuchObject)
+            }),
+            results[1]
+        );
+        assert_eq!(Ok(b"Hello world!".to_vec()), results[2]);
+        assert_eq!(Ok(vec![0xfd]), results[3]);
+
+        let values = client
+            .read_many_values(&[
+                (0x3000, 0, DataType::UInt32),
+                (0x3003, 0, DataType::Int8),
+                (0x2003, 0, DataType::VisibleString),
+            ])
+            .await;
+        assert_eq!(
+            vec![
+                Ok(Value::U32(0x12345678)),
+                Ok(Value::I8(-3)),
+                Ok(Value::Str("Hello world!".into()))
+            ],
+            values
+        );
+    })
+    .await;
+}
+
 #[tokio::test]
 #[serial_test::serial]
 async fn test_block_download() {
@@ -212,3 +281,778 @@ async fn test_domain_access() {
     })
     .await;
 }
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_file_transfer() {
+    const SLAVE_NODE_ID: u8 = 1;
+
+    let od = &integration_tests::object_dict1::OD_TABLE;
+    let state = &integration_tests::object_dict1::NODE_STATE;
+    let mbox = &integration_tests::object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(SLAVE_NODE_ID).unwrap(), mbox, state, od);
+    let mut bus = SimBus::new(vec![mbox]);
+    let mut sender = bus.new_sender();
+    let _bus_logger = BusLogger::new(bus.new_receiver());
+
+    let domain: &MockDomainData = Box::leak(Box::new(MockDomainData::new(vec![0; 1200])));
+    integration_tests::object_dict1::OBJECT3007
+        .value
+        .register_handler(domain);
+
+    let dir = std::env::temp_dir();
+    let put_path = dir.join(format!("zencan-put-{}.bin", std::process::id()));
+    let get_path = dir.join(format!("zencan-get-{}.bin", std::process::id()));
+    let data = Vec::from_iter((0..1200).map(|i| (i * 7) as u8));
+    std::fs::write(&put_path, &data).unwrap();
+
+    test_with_background_process(&mut [&mut node], &mut sender, async move {
+        let sender = bus.new_sender();
+        let receiver = bus.new_receiver();
+        let mut client = SdoClient::new_std(SLAVE_NODE_ID, sender, receiver);
+
+        let mut progress = Vec::new();
+        let written = client
+            .download_from_file(0x3007, 0, &put_path, |p| progress.push(p))
+            .await
+            .unwrap();
+        assert_eq!(1200, written);
+        assert_eq!(data, domain.get_data());
+        // The file is large enough to be sent with a block download
+        assert_eq!(Some(true), client.block_supported());
+        let last = progress.last().unwrap();
+        assert_eq!(
+            (1200, Some(1200), 1),
+            (last.bytes, last.total, last.attempt)
+        );
+
+        let mut progress = Vec::new();
+        let read = client
+            .upload_to_file(0x3007, 0, &get_path, |p| progress.push(p))
+            .await
+            .unwrap();
+        assert_eq!(1200, read);
+        assert_eq!(data, std::fs::read(&get_path).unwrap());
+        assert!(progress.windows(2).all(|w| w[0].bytes <= w[1].bytes));
+        assert_eq!(1200, progress.last().unwrap().bytes);
+
+        // Errors which are not retryable are returned after one attempt
+        let result = client
+            .upload_to_file(0x3007, 9, &get_path, |_| {})
+            .await
+            .unwrap_err();
+        assert!(matches!(result, FileTransferError::Sdo { attempts: 1, .. }));
+
+        std::fs::remove_file(&put_path).ok();
+        std::fs::remove_file(&get_path).ok();
+    })
+    .await;
+}
+
+/// A domain which writes at the offset set in 0x3000 by a resumable download
+#[derive(Debug)]
+struct OffsetDomainData {
+    buffer: Mutex<Vec<u8>>,
+    write_pos: AtomicUsize,
+    starts: Mutex<Vec<usize>>,
+}
+
+impl OffsetDomainData {
+    pub fn new(size: usize) -> Self {
+        Self {
+            buffer: Mutex::new(vec![0; size]),
+            write_pos: AtomicUsize::new(0),
+            starts: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn offset(&self) -> usize {
+        integration_tests::object_dict1::OBJECT3000.get_value() as usize
+    }
+}
+
+impl SubObjectAccess for OffsetDomainData {
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        let buffer = self.buffer.lock().unwrap();
+        if offset < buffer.len() {
+            let read_len = buf.len().min(buffer.len() - offset);
+            buf[..read_len].copy_from_slice(&buffer[offset..offset + read_len]);
+            Ok(read_len)
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn read_size(&self) -> usize {
+        self.buffer.lock().unwrap().len()
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), AbortCode> {
+        self.begin_partial()?;
+        self.write_partial(data)
+    }
+
+    fn begin_partial(&self) -> Result<(), AbortCode> {
+        let offset = self.offset();
+        self.starts.lock().unwrap().push(offset);
+        self.write_pos.store(offset, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn write_partial(&self, buf: &[u8]) -> Result<(), AbortCode> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let write_pos = self.write_pos.load(Ordering::Relaxed);
+        if write_pos + buf.len() > buffer.len() {
+            return Err(AbortCode::DataTypeMismatchLengthHigh);
+        }
+        buffer[write_pos..write_pos + buf.len()].copy_from_slice(buf);
+        self.write_pos
+            .store(write_pos + buf.len(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn end_partial(&self) -> Result<(), AbortCode> {
+        Ok(())
+    }
+}
+
+/// A sender which fails to send the nth frame after being armed
+struct FlakySender<S> {
+    inner: S,
+    fail_in: Arc<AtomicUsize>,
+}
+
+impl<S: AsyncCanSender> AsyncCanSender for FlakySender<S> {
+    async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
+        match self.fail_in.load(Ordering::Relaxed) {
+            0 => (),
+            1 => {
+                self.fail_in.store(0, Ordering::Relaxed);
+                return Err(msg);
+            }
+            n => self.fail_in.store(n - 1, Ordering::Relaxed),
+        }
+        self.inner.send(msg).await
+    }
+
+    fn echoes_sent_frames(&self) -> bool {
+        self.inner.echoes_sent_frames()
+    }
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_resumable_block_download() {
+    const SLAVE_NODE_ID: u8 = 1;
+
+    let od = &integration_tests::object_dict1::OD_TABLE;
+    let state = &integration_tests::object_dict1::NODE_STATE;
+    let mbox = &integration_tests::object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(SLAVE_NODE_ID).unwrap(), mbox, state, od);
+    let mut bus = SimBus::new(vec![mbox]);
+    let mut sender = bus.new_sender();
+    let _bus_logger = BusLogger::new(bus.new_receiver());
+
+    let domain: &OffsetDomainData = Box::leak(Box::new(OffsetDomainData::new(3000)));
+    integration_tests::object_dict1::OBJECT3007
+        .value
+        .register_handler(domain);
+    let original_offset = integration_tests::object_dict1::OBJECT3000.get_value();
+
+    test_with_background_process(&mut [&mut node], &mut sender, async move {
+        let fail_in = Arc::new(AtomicUsize::new(0));
+        let flaky_sender = FlakySender {
+            inner: bus.new_sender(),
+            fail_in: fail_in.clone(),
+        };
+        let mut client = SdoClient::new_std(SLAVE_NODE_ID, flaky_sender, bus.new_receiver());
+
+        // A send failure part way through the second block is resumed after the first block
+        let data = Vec::from_iter((0..3000).map(|i| (i * 3) as u8));
+        let mut checkpoint = DownloadCheckpoint::new(0x3007, 0, 0x3000, 0, data.len());
+        let mut checkpoints = Vec::new();
+        fail_in.store(200, Ordering::Relaxed);
+        client
+            .resumable_block_download(&data, &mut checkpoint, |c| checkpoints.push(c.offset))
+            .await
+            .unwrap();
+        assert!(checkpoint.is_complete());
+        assert_eq!(data, *domain.buffer.lock().unwrap());
+        assert_eq!(vec![0, 889], *domain.starts.lock().unwrap());
+        assert_eq!(vec![889, 1778, 2667, 3000], checkpoints);
+
+        // Without retries, the checkpoint is returned to be resumed later
+        domain.starts.lock().unwrap().clear();
+        client.set_transfer_retries(0);
+        let data = Vec::from_iter((0..3000).map(|i| (i * 5) as u8));
+        let mut checkpoint = DownloadCheckpoint::new(0x3007, 0, 0x3000, 0, data.len());
+        fail_in.store(300, Ordering::Relaxed);
+        let result = client
+            .resumable_block_download(&data, &mut checkpoint, |_| {})
+            .await;
+        assert!(matches!(
+            result,
+            Err(ResumableDownloadError::Sdo {
+                attempts: 1,
+                offset: 1778,
+                source: SdoClientError::SocketSendFailed,
+            })
+        ));
+        assert_eq!(1778, checkpoint.offset);
+
+        // The checkpoint is rejected for other data
+        assert!(matches!(
+            client
+                .resumable_block_download(&data[..2999], &mut checkpoint, |_| {})
+                .await,
+            Err(ResumableDownloadError::CheckpointMismatch)
+        ));
+
+        // A new client resumes from the checkpoint
+        let mut client = SdoClient::new_std(SLAVE_NODE_ID, bus.new_sender(), bus.new_receiver());
+        client
+            .resumable_block_download(&data, &mut checkpoint, |_| {})
+            .await
+            .unwrap();
+        assert_eq!(data, *domain.buffer.lock().unwrap());
+        assert_eq!(vec![0, 1778], *domain.starts.lock().unwrap());
+
+        // Restore values for other tests
+        client
+            .download_u32(0x3000, 0, original_offset)
+            .await
+            .unwrap();
+    })
+    .await;
+}
+
+/// A domain which, on the first few reads, acts as if another client had just written it, by
+/// changing the generation counter in 0x3000
+#[derive(Debug)]
+struct ContendedDomainData {
+    buffer: Mutex<Vec<u8>>,
+    conflicts: AtomicUsize,
+}
+
+impl SubObjectAccess for ContendedDomainData {
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        if offset == 0 && self.conflicts.load(Ordering::Relaxed) > 0 {
+            self.conflicts.fetch_sub(1, Ordering::Relaxed);
+            let generation = &integration_tests::object_dict1::OBJECT3000;
+            generation.set_value(generation.get_value().wrapping_add(1));
+        }
+        let buffer = self.buffer.lock().unwrap();
+        if offset < buffer.len() {
+            let read_len = buf.len().min(buffer.len() - offset);
+            buf[..read_len].copy_from_slice(&buffer[offset..offset + read_len]);
+            Ok(read_len)
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn read_size(&self) -> usize {
+        self.buffer.lock().unwrap().len()
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), AbortCode> {
+        *self.buffer.lock().unwrap() = data.to_vec();
+        Ok(())
+    }
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_update() {
+    const SLAVE_NODE_ID: u8 = 1;
+
+    let od = &integration_tests::object_dict1::OD_TABLE;
+    let state = &integration_tests::object_dict1::NODE_STATE;
+    let mbox = &integration_tests::object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(SLAVE_NODE_ID).unwrap(), mbox, state, od);
+    let mut bus = SimBus::new(vec![mbox]);
+    let mut sender = bus.new_sender();
+    let _bus_logger = BusLogger::new(bus.new_receiver());
+
+    let domain: &ContendedDomainData = Box::leak(Box::new(ContendedDomainData {
+        buffer: Mutex::new(vec![1, 2, 3, 4]),
+        conflicts: AtomicUsize::new(0),
+    }));
+    integration_tests::object_dict1::OBJECT3007
+        .value
+        .register_handler(domain);
+    let original_generation = integration_tests::object_dict1::OBJECT3000.get_value();
+
+    test_with_background_process(&mut [&mut node], &mut sender, async move {
+        let sender = bus.new_sender();
+        let receiver = bus.new_receiver();
+        let mut client = SdoClient::new_std(SLAVE_NODE_ID, sender, receiver);
+
+        // Set a bit in a u32 object
+        let original = client.upload_u32(0x2000, 1).await.unwrap();
+        let updated = client
+            .update(0x2000, 1, |v: u32| v | 0x8000_0000)
+            .await
+            .unwrap();
+        assert_eq!(original | 0x8000_0000, updated);
+        assert_eq!(updated, client.upload_u32(0x2000, 1).await.unwrap());
+
+        let set_flag = |mut v: Vec<u8>| {
+            v[0] |= 0x80;
+            v
+        };
+
+        // Without conflicting changes, the first attempt succeeds
+        let updated = client
+            .update_guarded(0x3007, 0, (0x3000, 0), set_flag)
+            .await
+            .unwrap();
+        assert_eq!(vec![0x81, 2, 3, 4], updated);
+
+        // The update is started again after a conflicting change, and applied to the new value
+        *domain.buffer.lock().unwrap() = vec![5, 6, 7, 8];
+        domain.conflicts.store(1, Ordering::Relaxed);
+        let updated = client
+            .update_guarded(0x3007, 0, (0x3000, 0), set_flag)
+            .await
+            .unwrap();
+        assert_eq!(vec![0x85, 6, 7, 8], updated);
+        assert_eq!(updated, *domain.buffer.lock().unwrap());
+
+        // When the object keeps changing, the update gives up without writing
+        *domain.buffer.lock().unwrap() = vec![1, 2, 3, 4];
+        domain.conflicts.store(100, Ordering::Relaxed);
+        assert_eq!(
+            Err(SdoClientError::ConcurrentModification {
+                index: 0x3007,
+                sub: 0
+            }),
+            client
+                .update_guarded(0x3007, 0, (0x3000, 0), set_flag)
+                .await
+        );
+        assert_eq!(vec![1, 2, 3, 4], *domain.buffer.lock().unwrap());
+
+        // Restore values for other tests
+        client.download_u32(0x2000, 1, original).await.unwrap();
+        client
+            .download_u32(0x3000, 0, original_generation)
+            .await
+            .unwrap();
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_write_verified() {
+    const SLAVE_NODE_ID: u8 = 1;
+
+    let od = &integration_tests::object_dict1::OD_TABLE;
+    let state = &integration_tests::object_dict1::NODE_STATE;
+    let mbox = &integration_tests::object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(SLAVE_NODE_ID).unwrap(), mbox, state, od);
+    let mut bus = SimBus::new(vec![mbox]);
+    let mut sender = bus.new_sender();
+    let _bus_logger = BusLogger::new(bus.new_receiver());
+
+    let domain: &MockDomainData = Box::leak(Box::new(MockDomainData::new(vec![0; 1200])));
+    integration_tests::object_dict1::OBJECT3007
+        .value
+        .register_handler(domain);
+
+    test_with_background_process(&mut [&mut node], &mut sender, async move {
+        let sender = bus.new_sender();
+        let receiver = bus.new_receiver();
+        let mut client = SdoClient::new_std(SLAVE_NODE_ID, sender, receiver);
+
+        let original = client.upload(0x3000, 0).await.unwrap();
+        client
+            .write_verified(0x3000, 0, &[1, 2, 3, 4], VerifyMethod::Readback)
+            .await
+            .unwrap();
+
+        // A string readback stops at the first null, so it does not match
+        let original_str = client.upload(0x2002, 0).await.unwrap();
+        assert_eq!(
+            Err(SdoClientError::VerifyFailed {
+                index: 0x2002,
+                sub: 0
+            }),
+            client
+                .write_verified(0x2002, 0, b"ab\0", VerifyMethod::Readback)
+                .await
+        );
+
+        // Use 0x3102sub1 as the CRC object for the domain, as a device would calculate it
+        let original_crc = client.upload(0x3102, 1).await.unwrap();
+        let data = Vec::from_iter((0..1200).map(|i| i as u8));
+        let crc = crc16::State::<crc16::XMODEM>::calculate(&data);
+        client.download_u16(0x3102, 1, crc).await.unwrap();
+        let method = VerifyMethod::Crc {
+            index: 0x3102,
+            sub: 1,
+        };
+        client
+            .write_verified(0x3007, 0, &data, method)
+            .await
+            .unwrap();
+        assert_eq!(data, domain.get_data());
+        assert_eq!(
+            Err(SdoClientError::VerifyFailed {
+                index: 0x3007,
+                sub: 0
+            }),
+            client.write_verified(0x3007, 0, &[0; 1200], method).await
+        );
+
+        // Restore values for other tests
+        client.download(0x3000, 0, &original).await.unwrap();
+        client.download(0x2002, 0, &original_str).await.unwrap();
+        client.download(0x3102, 1, &original_crc).await.unwrap();
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_write_selects_transfer() {
+    const SLAVE_NODE_ID: u8 = 1;
+
+    let od = &integration_tests::object_dict1::OD_TABLE;
+    let state = &integration_tests::object_dict1::NODE_STATE;
+    let mbox = &integration_tests::object_dict1::NODE_MBOX;
+    let mut node = Node::new(NodeId::new(SLAVE_NODE_ID).unwrap(), mbox, state, od);
+    let mut bus = SimBus::new(vec![mbox]);
+    let mut sender = bus.new_sender();
+    let _bus_logger = BusLogger::new(bus.new_receiver());
+
+    test_with_background_process(&mut [&mut node], &mut sender, async move {
+        let sender = bus.new_sender();
+        let receiver = bus.new_receiver();
+        let mut client = SdoClient::new_std(SLAVE_NODE_ID, sender, receiver);
+
+        // Small writes do not attempt a block download
+        let data = Vec::from_iter(0..16);
+        client.write(0x3006, 0, &data).await.unwrap();
+        assert_eq!(None, client.block_supported());
+        assert_eq!(
+            data,
+            integration_tests::object_dict1::OBJECT3006.get_value()[0..data.len()]
+        );
+
+        // Large writes use a block download
+        let data = Vec::from_iter((0..1200).map(|i| i as u8));
+        client.write(0x3006, 0, &data).await.unwrap();
+        assert_eq!(Some(true), client.block_supported());
+        assert_eq!(
+            data,
+            integration_tests::object_dict1::OBJECT3006.get_value()
+        );
+    })
+    .await;
+}
+
+/// Run a minimal SDO server for node 1, which accepts expedited and segmented downloads, but
+/// rejects block downloads
+async fn run_segmented_only_server(
+    mut sender: SimBusSender<'static>,
+    mut receiver: SimBusReceiver,
+    received: Arc<Mutex<Vec<u8>>>,
+) {
+    loop {
+        let msg = receiver.recv().await.unwrap();
+        if msg.id() != CanId::sdo_rx(1) {
+            continue;
+        }
+        let resp = match SdoRequest::try_from(msg.data()) {
+            Ok(SdoRequest::InitiateDownload { index, sub, .. }) => {
+                received.lock().unwrap().clear();
+                SdoResponse::download_acknowledge(index, sub)
+            }
+            Ok(SdoRequest::DownloadSegment { t, n, data, .. }) => {
+                received
+                    .lock()
+                    .unwrap()
+                    .extend_from_slice(&data[..7 - n as usize]);
+                SdoResponse::download_segment_acknowledge(t)
+            }
+            Ok(SdoRequest::InitiateBlockDownload { index, sub, .. }) => {
+                SdoResponse::abort(index, sub, AbortCode::InvalidCommandSpecifier)
+            }
+            _ => continue,
+        };
+        sender
+            .send(resp.to_can_message(CanId::sdo_tx(1)))
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_write_falls_back_without_block_support() {
+    let mut bus = SimBus::new(vec![]);
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let server = tokio::spawn(run_segmented_only_server(
+        bus.new_sender(),
+        bus.new_receiver(),
+        received.clone(),
+    ));
+    let mut client = SdoClient::new_std(1, bus.new_sender(), bus.new_receiver());
+
+    let data = Vec::from_iter((0..100).map(|i| i as u8));
+    client.write(0x2000, 0, &data).await.unwrap();
+    assert_eq!(Some(false), client.block_supported());
+    assert_eq!(data, *received.lock().unwrap());
+
+    // Later writes go straight to a segmented download
+    let data = Vec::from_iter((0..80).map(|i| i as u8));
+    client.write(0x2000, 0, &data).await.unwrap();
+    assert_eq!(data, *received.lock().unwrap());
+
+    // Unless block transfer is forced
+    client.set_transfer_mode(TransferMode::Block);
+    let result = client.write(0x2000, 0, &data).await;
+    assert_eq!(
+        Some(RawAbortCode::Valid(AbortCode::InvalidCommandSpecifier)),
+        result.unwrap_err().abort_code()
+    );
+
+    server.abort();
+}
+
+#[test]
+#[serial_test::serial]
+fn test_sdo_server_cob_id() {
+    let od = &integration_tests::object_dict1::OD_TABLE;
+    let mbox = &integration_tests::object_dict1::NODE_MBOX;
+    let state = &integration_tests::object_dict1::NODE_STATE;
+    let mut node = Node::new(NodeId::new(1).unwrap(), mbox, state, od);
+
+    let process = |node: &mut Node| {
+        let mut sent = Vec::new();
+        node.process(0, &mut |msg| sent.push(msg));
+        sent
+    };
+    process(&mut node);
+
+    // Move the SDO server to 0x640/0x5C0. The acknowledgement is sent on the old COB-ID.
+    let new_rx = CanId::std(0x640);
+    let new_tx = CanId::std(0x5C0);
+    for (sub, cob_id) in [(2u8, new_tx), (1u8, new_rx)] {
+        let req = SdoRequest::expedited_download(0x1200, sub, &cob_id.raw().to_le_bytes());
+        mbox.store_message(req.to_can_message(CanId::sdo_rx(1)))
+            .unwrap();
+        let sent = process(&mut node);
+        let expected_tx = if sub == 2 { CanId::sdo_tx(1) } else { new_tx };
+        assert_eq!(1, sent.len());
+        assert_eq!(expected_tx, sent[0].id());
+        assert_eq!(
+            SdoResponse::download_acknowledge(0x1200, sub),
+            SdoResponse::try_from(sent[0]).unwrap()
+        );
+    }
+
+    // Requests on the old COB-ID are ignored
+    let req = SdoRequest::initiate_upload(0x1200, 1);
+    assert!(mbox
+        .store_message(req.to_can_message(CanId::sdo_rx(1)))
+        .is_err());
+
+    mbox.store_message(req.to_can_message(new_rx)).unwrap();
+    let sent = process(&mut node);
+    assert_eq!(1, sent.len());
+    assert_eq!(new_tx, sent[0].id());
+    assert_eq!(
+        SdoResponse::expedited_upload(0x1200, 1, &0x640u32.to_le_bytes()),
+        SdoResponse::try_from(sent[0]).unwrap()
+    );
+
+    state.cob_ids().set_sdo_rx(None);
+    state.cob_ids().set_sdo_tx(None);
+    process(&mut node);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_sdo_client_with_cob_ids() {
+    let state = &integration_tests::object_dict1::NODE_STATE;
+    let mut fixture = NodeFixture::new(
+        1,
+        &integration_tests::object_dict1::OD_TABLE,
+        &integration_tests::object_dict1::NODE_MBOX,
+        state,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let moved = SdoCobIds::new(CanId::std(0x640), CanId::extended(0x12_3456));
+    assert_eq!((0x640, 0x2012_3456), moved.raw());
+    assert_eq!(None, SdoCobIds::from_raw(0x8000_0601, 0x581));
+
+    let test_task = async {
+        let mut client = manager.sdo_client(1);
+        assert_eq!(SdoCobIds::std(1), client.cob_ids());
+        assert_eq!(
+            Some(SdoCobIds::std(1)),
+            client.read_sdo_server_cob_ids(0).await.unwrap()
+        );
+
+        // Move the response first, as the acknowledgement is sent on the old COB-ID. Later
+        // responses are sent on the new one.
+        let (request, response) = moved.raw();
+        client.write_u32(0x1200, 2, response).await.unwrap();
+        drop(client);
+        let mut client =
+            manager.sdo_client_with_cob_ids(SdoCobIds::new(CanId::std(0x601), moved.response));
+        client.write_u32(0x1200, 1, request).await.unwrap();
+        drop(client);
+
+        // The default server no longer responds
+        let mut client = manager.sdo_client(1);
+        assert_eq!(
+            Err(SdoClientError::NoResponse),
+            client.read_device_type().await
+        );
+        drop(client);
+
+        let mut client = manager.sdo_client_with_cob_ids(moved);
+        assert_eq!(moved, client.cob_ids());
+        assert_eq!(
+            Some(moved),
+            client.read_sdo_server_cob_ids(0).await.unwrap()
+        );
+        client.read_device_type().await.unwrap();
+    };
+    fixture.run(test_task).await;
+
+    state.cob_ids().set_sdo_rx(None);
+    state.cob_ids().set_sdo_tx(None);
+    fixture.process();
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_frame_pacing() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &integration_tests::object_dict1::OD_TABLE,
+        &integration_tests::object_dict1::NODE_MBOX,
+        &integration_tests::object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let pacing = FramePacing::min_gap(Duration::from_millis(2));
+    manager.set_frame_pacing(1, pacing);
+    assert_eq!(pacing, manager.frame_pacing(1));
+    assert_eq!(FramePacing::default(), manager.frame_pacing(2));
+
+    let test_task = async {
+        let mut client = manager.sdo_client(1);
+        assert_eq!(pacing, client.frame_pacing());
+
+        // An initiate, 19 segments, and an end request are sent at least 2ms apart
+        let data = Vec::from_iter(0..128);
+        let start = Instant::now();
+        client.block_download(0x3006, 0, &data).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert_eq!(
+            data,
+            integration_tests::object_dict1::OBJECT3006.get_value()[0..data.len()]
+        );
+        drop(client);
+
+        manager.set_frame_pacing(1, FramePacing::default());
+        let client = manager.sdo_client(1);
+        assert_eq!(Duration::ZERO, client.frame_pacing().gap());
+    };
+    fixture.run(test_task).await;
+}
+
+#[test]
+#[serial_test::serial]
+fn test_sdo_server_status() {
+    let od = &integration_tests::object_dict1::OD_TABLE;
+    let mbox = &integration_tests::object_dict1::NODE_MBOX;
+    let state = &integration_tests::object_dict1::NODE_STATE;
+    let mut node = Node::new(NodeId::new(1).unwrap(), mbox, state, od);
+    let status_object = find_object(od, 0x5005).unwrap();
+
+    let request = |node: &mut Node, req: SdoRequest| {
+        mbox.store_message(req.to_can_message(CanId::sdo_rx(1)))
+            .unwrap();
+        node.process(0, &mut |_| {});
+        state.snapshot().sdo_server
+    };
+    node.process(0, &mut |_| {});
+    assert_eq!(SdoServerStatus::IDLE, state.snapshot().sdo_server);
+
+    let status = request(
+        &mut node,
+        SdoRequest::initiate_download(0x3006, 0, Some(1200)),
+    );
+    assert_eq!(
+        SdoServerStatus {
+            state: SdoTransferState::SegmentedDownload,
+            index: 0x3006,
+            sub: 0,
+            bytes: 0,
+        },
+        status
+    );
+    assert!(state.snapshot().sdo_transfer_active);
+
+    let status = request(
+        &mut node,
+        SdoRequest::download_segment(false, false, &[0; 7]),
+    );
+    assert_eq!(7, status.bytes);
+    // The status object reports the same status
+    assert_eq!(1, status_object.read_u8(1).unwrap());
+    assert_eq!(0x3006, status_object.read_u16(2).unwrap());
+    assert_eq!(0, status_object.read_u8(3).unwrap());
+    assert_eq!(7, status_object.read_u32(4).unwrap());
+
+    let status = request(
+        &mut node,
+        SdoRequest::abort(0x3006, 0, AbortCode::GeneralError),
+    );
+    assert_eq!(SdoServerStatus::IDLE, status);
+    assert_eq!(0, status_object.read_u8(1).unwrap());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_manager_transfer_monitor() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &integration_tests::object_dict1::OD_TABLE,
+        &integration_tests::object_dict1::NODE_MBOX,
+        &integration_tests::object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let test_task = async {
+        assert!(manager.sdo_transfers().is_empty());
+
+        // There is no node 5, so the read is in progress until it times out
+        let read = async { manager.sdo_client(5).read_device_type().await };
+        let inspect = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            manager.sdo_transfer(5)
+        };
+        let (result, transfer) = tokio::join!(read, inspect);
+        assert_eq!(Err(SdoClientError::NoResponse), result);
+        let transfer = transfer.expect("No transfer in progress");
+        assert_eq!(Some(5), transfer.node_id);
+        assert_eq!(SdoCobIds::std(5), transfer.cob_ids);
+        assert_eq!(SdoTransferState::SegmentedUpload, transfer.state);
+        assert_eq!((0x1000, 0), (transfer.index, transfer.sub));
+        assert_eq!(0, transfer.bytes_transferred);
+
+        // Completed transfers are removed
+        assert!(manager.sdo_transfers().is_empty());
+        manager.sdo_client(1).read_device_type().await.unwrap();
+        assert_eq!(None, manager.sdo_transfer(1));
+    };
+    fixture.run(test_task).await;
+}
@@ -0,0 +1,104 @@
+//! Tests for the secondary heartbeat, sent on a second COB-ID for redundant networks
+//!
+mod utils;
+
+use std::time::Duration;
+
+use integration_tests::{object_dict1, object_dict2};
+use serial_test::serial;
+use utils::setup_single_node;
+use zencan_client::{
+    common::{
+        messages::{CanId, Heartbeat, NmtState},
+        traits::AsyncCanSender,
+    },
+    testing::{NodeFixture, TestBusSender},
+    BusManager, HeartbeatSource, NodeInfo,
+};
+use zencan_node::object_dict::find_object;
+
+async fn send_heartbeat(sender: &mut TestBusSender, id: CanId, node: u8, state: NmtState) {
+    let msg = Heartbeat {
+        node,
+        toggle: false,
+        state,
+    };
+    sender.send(msg.to_can_message(id)).await.unwrap();
+    // Give the manager time to process it
+    tokio::time::sleep(Duration::from_millis(10)).await;
+}
+
+async fn node_info<S>(manager: &BusManager<S>, node_id: u8) -> Option<NodeInfo>
+where
+    S: AsyncCanSender + Sync + Send,
+{
+    manager
+        .node_list()
+        .await
+        .into_iter()
+        .find(|n| n.node_id == node_id)
+}
+
+#[serial]
+#[tokio::test]
+async fn test_node_secondary_heartbeat() {
+    let od = &object_dict2::OD_TABLE;
+    let (mut node, _client, _bus) =
+        setup_single_node(od, &object_dict2::NODE_MBOX, &object_dict2::NODE_STATE);
+    assert_eq!(Some(CanId::std(0x681)), node.secondary_heartbeat_cob_id());
+
+    // The boot-up message is sent on both COB-IDs
+    let mut sent = Vec::new();
+    node.process(0, &mut |msg| sent.push(msg));
+    assert_eq!(2, sent.len());
+    assert_eq!(CanId::std(0x701), sent[0].id());
+    assert_eq!(CanId::std(0x681), sent[1].id());
+    assert_eq!(sent[0].data(), sent[1].data());
+
+    // Setting bit 31 disables it
+    let secondary = find_object(od, 0x5007).unwrap();
+    secondary.write(0, &0x8000_0680u32.to_le_bytes()).unwrap();
+    assert_eq!(None, node.secondary_heartbeat_cob_id());
+    secondary.write(0, &0x680u32.to_le_bytes()).unwrap();
+}
+
+#[serial]
+#[tokio::test]
+async fn test_manager_merges_heartbeats() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut sender = fixture.sender();
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+    let timeout = Duration::from_secs(1);
+
+    let test_task = async move {
+        // Without a base, the secondary heartbeat is not recognized
+        assert_eq!(None, manager.secondary_heartbeat_base());
+        send_heartbeat(&mut sender, CanId::std(0x685), 5, NmtState::Operational).await;
+        assert!(node_info(&manager, 5).await.is_none());
+
+        manager.set_secondary_heartbeat_base(Some(0x680));
+        assert_eq!(Some(0x680), manager.secondary_heartbeat_base());
+        send_heartbeat(&mut sender, CanId::std(0x685), 5, NmtState::Operational).await;
+        let info = node_info(&manager, 5).await.unwrap();
+        assert_eq!(Some(NmtState::Operational), info.nmt_state);
+        assert!(info.is_heartbeating(timeout));
+        assert!(info.is_heartbeating_on(HeartbeatSource::Secondary, timeout));
+        assert!(!info.is_heartbeating_on(HeartbeatSource::Primary, timeout));
+
+        // The most recent heartbeat from either source gives the state
+        send_heartbeat(&mut sender, CanId::heartbeat(5), 5, NmtState::Stopped).await;
+        let info = node_info(&manager, 5).await.unwrap();
+        assert_eq!(Some(NmtState::Stopped), info.nmt_state);
+        assert!(info.is_heartbeating_on(HeartbeatSource::Primary, timeout));
+        assert!(info.is_heartbeating_on(HeartbeatSource::Secondary, timeout));
+
+        manager.set_secondary_heartbeat_base(None);
+    };
+
+    fixture.run(test_task).await;
+}
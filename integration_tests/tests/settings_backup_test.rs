@@ -0,0 +1,46 @@
+//! Tests for backing up and restoring node settings via the settings backup object
+//!
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::testing::NodeFixture;
+
+#[serial]
+#[tokio::test]
+async fn test_settings_backup_and_restore() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut client = fixture.sdo_client();
+
+    let test_task = async move {
+        let original = client.backup_settings().await.unwrap();
+
+        client.download_u32(0x2000, 1, 1111).await.unwrap();
+        client.download(0x2002, 0, b"BACKUP").await.unwrap();
+        let backup = client.backup_settings().await.unwrap();
+        assert!(!backup.is_empty());
+
+        client.download_u32(0x2000, 1, 2222).await.unwrap();
+        client.download(0x2002, 0, b"CHANGED VALUE").await.unwrap();
+
+        client.restore_settings(&backup, false).await.unwrap();
+        assert_eq!(1111, client.upload_u32(0x2000, 1).await.unwrap());
+        assert_eq!(b"BACKUP".to_vec(), client.upload(0x2002, 0).await.unwrap());
+
+        // A backup which ends part way through a value is rejected
+        client
+            .restore_settings(&backup[..backup.len() - 1], false)
+            .await
+            .unwrap_err();
+
+        // Restore the original values
+        client.restore_settings(&original, false).await.unwrap();
+    };
+    fixture.run(test_task).await;
+
+    assert!(object_dict1::NODE_STATE.storage_context().is_dirty());
+}
@@ -0,0 +1,100 @@
+//! Tests for shutting down and dropping the BusManager
+//!
+use std::time::{Duration, Instant};
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::{
+    common::{node_id::ConfiguredId, traits::AsyncCanReceiver, CanId},
+    testing::NodeFixture,
+    BusManager, DiscoveryOptions, SdoClientError,
+};
+
+#[serial]
+#[tokio::test]
+async fn test_shutdown() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+    let mut rx = fixture.receiver();
+
+    let test_task = async move {
+        let heartbeat_id = CanId::std(0x700 + 100);
+        manager.start_heartbeat(ConfiguredId::new(100).unwrap(), Duration::from_millis(10));
+        let mut device = manager.device(1);
+        let mut missing = manager.device(5);
+        device.read::<u32>((0x2000, 1)).await.unwrap();
+        rx.expect(heartbeat_id, Duration::from_millis(100))
+            .await
+            .expect("No heartbeat from manager");
+
+        // A transfer to a missing node is in progress when the manager is shut down
+        let start = Instant::now();
+        let (result, aborted) = tokio::join!(missing.read::<u32>((0x1000, 0)), async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            manager.shutdown().await
+        });
+        assert_eq!(1, aborted.len());
+        assert_eq!(
+            (Some(5), 0x1000, 0),
+            (aborted[0].node_id, aborted[0].index, aborted[0].sub)
+        );
+        // The transfer fails without waiting for the response timeout
+        assert!(result.is_err());
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        let abort = rx
+            .expect_matching(
+                |msg| msg.id() == CanId::std(0x605) && msg.data()[0] == 0x80,
+                Duration::from_millis(50),
+            )
+            .await
+            .expect("No SDO abort sent");
+        // General error
+        assert_eq!(&0x0800_0000u32.to_le_bytes(), &abort.data()[4..8]);
+
+        // The heartbeat is stopped, and later requests fail
+        while rx.try_recv().is_some() {}
+        assert!(rx
+            .expect(heartbeat_id, Duration::from_millis(50))
+            .await
+            .is_none());
+        assert!(matches!(
+            device.read::<u32>((0x2000, 1)).await,
+            Err(SdoClientError::SocketSendFailed)
+        ));
+    };
+
+    fixture.run(test_task).await;
+}
+
+#[serial]
+#[test]
+fn test_runtime_shutdown() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let manager = runtime.block_on(async {
+        let manager = BusManager::new(fixture.sender(), fixture.receiver());
+        manager.start_heartbeat(ConfiguredId::new(100).unwrap(), Duration::from_millis(10));
+        manager.start_discovery(DiscoveryOptions::default());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        manager
+    });
+
+    // The runtime shuts down with the manager's tasks running, and the manager can still be
+    // dropped afterwards
+    drop(runtime);
+    drop(manager);
+}
@@ -0,0 +1,78 @@
+//! Tests for the SYNC produced by the client
+//!
+
+use std::time::Duration;
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::{
+    common::{messages::SYNC_ID, traits::AsyncCanReceiver},
+    testing::NodeFixture,
+    BusManager, ManagerSync, SyncProducerError,
+};
+
+#[serial]
+#[tokio::test]
+async fn test_manager_sync() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+    let mut rx = fixture.receiver();
+
+    let test_task = async move {
+        assert_eq!(None, manager.sync());
+        assert!(!manager.set_sync_period(Duration::from_millis(10)).unwrap());
+        assert!(matches!(
+            manager.start_sync(Duration::from_millis(10), 241),
+            Err(SyncProducerError::InvalidCounterOverflow { value: 241 })
+        ));
+
+        manager.start_sync(Duration::from_millis(20), 3).unwrap();
+        assert_eq!(
+            Some(ManagerSync {
+                period: Duration::from_millis(20),
+                counter_overflow: 3,
+                offload: None,
+            }),
+            manager.sync()
+        );
+        // The counter runs from 1 to the overflow value
+        let mut counts = Vec::new();
+        for _ in 0..4 {
+            let msg = rx
+                .expect(SYNC_ID, Duration::from_millis(200))
+                .await
+                .expect("No SYNC from manager");
+            counts.push(msg.data()[0]);
+        }
+        assert_eq!(vec![1, 2, 3, 1], counts);
+
+        assert!(manager.set_sync_period(Duration::from_millis(30)).unwrap());
+        assert_eq!(Duration::from_millis(30), manager.sync().unwrap().period);
+
+        // Without a counter, SYNCs carry no data
+        manager.start_sync(Duration::from_millis(20), 0).unwrap();
+        while rx.try_recv().is_some() {}
+        let msg = rx
+            .expect(SYNC_ID, Duration::from_millis(200))
+            .await
+            .expect("No SYNC from manager");
+        assert!(msg.data().is_empty());
+
+        assert!(manager.stop_sync());
+        assert!(!manager.stop_sync());
+        assert_eq!(None, manager.sync());
+        // Discard any SYNC sent before it was stopped
+        while rx.try_recv().is_some() {}
+        assert!(rx
+            .expect(SYNC_ID, Duration::from_millis(100))
+            .await
+            .is_none());
+    };
+
+    fixture.run(test_task).await;
+}
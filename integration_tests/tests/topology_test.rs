@@ -0,0 +1,73 @@
+//! Tests for exporting the bus topology from the client
+//!
+
+use std::time::Duration;
+
+use integration_tests::object_dict1;
+use serial_test::serial;
+use zencan_client::{
+    testing::NodeFixture, topology::PdoEndpoint, BusManager, PdoConfig, PdoMapping, ScanOptions,
+};
+
+#[serial]
+#[tokio::test]
+async fn test_export_topology() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut client = fixture.sdo_client();
+    let mut manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let test_task = async move {
+        let original_tpdo = client.read_tpdo(1).await.unwrap().unwrap();
+        let original_rpdo = client.read_rpdo(2).await.unwrap().unwrap();
+
+        let tpdo = PdoConfig {
+            cob: 0x2AB,
+            enabled: true,
+            mappings: vec![PdoMapping {
+                index: 0x2000,
+                sub: 1,
+                size: 32,
+            }],
+            transmission_type: 254,
+        };
+        client.configure_tpdo(1, &tpdo).await.unwrap();
+        client.configure_rpdo(2, &tpdo).await.unwrap();
+
+        // Nodes must be discovered before they are included
+        assert!(manager.export_topology().await.nodes.is_empty());
+        let opts = ScanOptions {
+            timeout: Duration::from_millis(50),
+            parallelism: 32,
+            probe_only: true,
+            ..Default::default()
+        };
+        manager.scan_nodes_with(&opts).await;
+
+        let topology = manager.export_topology().await;
+        assert_eq!(1, topology.nodes.len());
+        let node = &topology.nodes[0];
+        assert_eq!(None, node.error);
+        assert_eq!(4, node.tpdos.len());
+        assert_eq!(4, node.rpdos.len());
+        assert_eq!(0x2AB, node.tpdos[1].config.cob);
+        assert_eq!(0x2000, node.tpdos[1].config.mappings[0].index);
+
+        let link = topology.links.iter().find(|l| l.cob_id == 0x2AB).unwrap();
+        assert_eq!(vec![PdoEndpoint { node_id: 1, pdo: 1 }], link.producers);
+        assert_eq!(vec![PdoEndpoint { node_id: 1, pdo: 2 }], link.consumers);
+        assert!(topology
+            .to_dot()
+            .contains("node1 -> node1 [label=\"0x2AB\"];"));
+
+        // Restore the original configuration
+        client.configure_tpdo(1, &original_tpdo).await.unwrap();
+        client.configure_rpdo(2, &original_rpdo).await.unwrap();
+    };
+
+    fixture.run(test_task).await;
+}
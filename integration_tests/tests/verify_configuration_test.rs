@@ -0,0 +1,87 @@
+//! Tests for the verify configuration object (0x1020), and skipping configuration of nodes which
+//! already hold it
+//!
+
+use integration_tests::{manager::manager_client, object_dict1};
+use serial_test::serial;
+use zencan_client::{testing::NodeFixture, BusManager, ConfigStamp, NodeConfig};
+
+#[serial]
+#[tokio::test]
+async fn test_verify_configuration() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let manager = BusManager::new(fixture.sender(), fixture.receiver());
+
+    let test_task = async move {
+        let client = || manager_client(&manager, 1);
+        let original_value = client().upload_u32(0x3000, 0).await.unwrap();
+        assert_eq!(
+            Some(ConfigStamp::default()),
+            client().read_verify_configuration().await.unwrap()
+        );
+
+        let mut config = NodeConfig::load_from_str(
+            r#"
+            [verify_configuration]
+            date = 15000
+            time = 1000
+
+            [[writes]]
+            type = "u32"
+            value = 0x1234
+            index = 0x3000
+            sub = 0
+        "#,
+        )
+        .unwrap();
+        assert!(!client().is_configured(&config).await.unwrap());
+        manager.apply_node_config(1, &config, true).await.unwrap();
+        assert_eq!(0x1234, client().upload_u32(0x3000, 0).await.unwrap());
+        assert_eq!(
+            config.verify_configuration(),
+            client().read_verify_configuration().await.unwrap()
+        );
+        assert!(client().is_configured(&config).await.unwrap());
+
+        // The node holds the stamp, so the configuration is not written again
+        client().download_u32(0x3000, 0, 0).await.unwrap();
+        manager.apply_node_config(1, &config, false).await.unwrap();
+        assert_eq!(0, client().upload_u32(0x3000, 0).await.unwrap());
+
+        // A new stamp is written
+        let stamp = ConfigStamp {
+            date: 15001,
+            time: 0,
+        };
+        config.set_verify_configuration(Some(stamp));
+        manager.apply_node_config(1, &config, false).await.unwrap();
+        assert_eq!(0x1234, client().upload_u32(0x3000, 0).await.unwrap());
+        assert_eq!(
+            Some(stamp),
+            client().read_verify_configuration().await.unwrap()
+        );
+
+        // Without a stamp, the configuration is always written
+        config.set_verify_configuration(None);
+        client().download_u32(0x3000, 0, 0).await.unwrap();
+        assert!(!client().is_configured(&config).await.unwrap());
+        manager.apply_node_config(1, &config, false).await.unwrap();
+        assert_eq!(0x1234, client().upload_u32(0x3000, 0).await.unwrap());
+
+        client()
+            .download_u32(0x3000, 0, original_value)
+            .await
+            .unwrap();
+        client()
+            .write_verify_configuration(ConfigStamp::default(), false)
+            .await
+            .unwrap();
+    };
+
+    fixture.run(test_task).await;
+}
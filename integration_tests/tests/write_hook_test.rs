@@ -0,0 +1,61 @@
+//! Tests for post-write hooks on generated objects
+//!
+
+use std::sync::Mutex;
+
+use integration_tests::object_dict1::{self, OBJECT3103};
+use serial_test::serial;
+use zencan_client::testing::NodeFixture;
+use zencan_common::sdo::AbortCode;
+use zencan_node::object_dict::{ObjectAccess, ProvidesSubObjects};
+
+static WRITES: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+fn take_writes() -> Vec<u8> {
+    std::mem::take(&mut *WRITES.lock().unwrap())
+}
+
+#[serial]
+#[tokio::test]
+async fn test_write_hook() {
+    let mut fixture = NodeFixture::new(
+        1,
+        &object_dict1::OD_TABLE,
+        &object_dict1::NODE_MBOX,
+        &object_dict1::NODE_STATE,
+    );
+    let mut client = fixture.sdo_client();
+
+    OBJECT3103.register_write_hook(&|sub| WRITES.lock().unwrap().push(sub));
+    take_writes();
+
+    let test_task = async move {
+        client.download_u16(0x3103, 1, 500).await.unwrap();
+        assert_eq!(vec![1], take_writes());
+        assert_eq!(500, OBJECT3103.get_setpoint());
+
+        client.download_u16(0x3103, 2, 7).await.unwrap();
+        client.download_u16(0x3103, 1, 600).await.unwrap();
+        assert_eq!(vec![2, 1], take_writes());
+
+        // Reads and failed writes do not call the hook
+        client.upload_u16(0x3103, 1).await.unwrap();
+        client.download(0x3103, 1, &[1, 2, 3, 4]).await.unwrap_err();
+        client.download_u8(0x3103, 0, 1).await.unwrap_err();
+        assert!(take_writes().is_empty());
+    };
+    fixture.run(test_task).await;
+
+    // Writes via ObjectAccess call the hook, but the application's setters do not
+    OBJECT3103.write(2, &9u16.to_le_bytes()).unwrap();
+    assert_eq!(vec![2], take_writes());
+    OBJECT3103.set_gain(10);
+    assert!(take_writes().is_empty());
+    assert_eq!(Err(AbortCode::NoSuchSubIndex), OBJECT3103.write(3, &[0, 0]));
+    assert!(take_writes().is_empty());
+
+    // Restore the original state
+    OBJECT3103.write_hook().unwrap().clear();
+    OBJECT3103.set_setpoint(0);
+    OBJECT3103.set_gain(0);
+}
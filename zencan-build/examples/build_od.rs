@@ -6,7 +6,7 @@ use std::path::PathBuf;
 
 use clap::Parser;
 
-use zencan_build::device_config_to_string;
+use zencan_build::{device_config_to_string, stub_unsupported_objects, MemoryReport};
 use zencan_common::device_config::DeviceConfig;
 
 #[derive(Clone, Debug, Parser)]
@@ -14,6 +14,12 @@ struct Args {
     config: PathBuf,
     #[clap(short, long)]
     format: bool,
+    /// Print the estimated memory usage instead of the generated code
+    #[clap(short, long)]
+    memory: bool,
+    /// Generate stubs for objects which are not supported, instead of failing
+    #[clap(long)]
+    permissive: bool,
 }
 
 fn main() {
@@ -26,7 +32,7 @@ fn main() {
         )
     });
 
-    let config = match DeviceConfig::load_from_str(&config_content) {
+    let mut config = match DeviceConfig::load_from_str(&config_content) {
         Ok(config) => config,
         Err(e) => {
             eprintln!("Failed to parse TOML file: {}", e);
@@ -34,6 +40,17 @@ fn main() {
         }
     };
 
+    if args.permissive {
+        for (index, reason) in stub_unsupported_objects(&mut config) {
+            eprintln!("Warning: object 0x{:04X} is a stub: {}", index, reason);
+        }
+    }
+
+    if args.memory {
+        println!("{}", MemoryReport::new(&config, 4));
+        return;
+    }
+
     let compiled = device_config_to_string(&config, args.format).expect("Failed to compile");
 
     println!("{}", compiled);
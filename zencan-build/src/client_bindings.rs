@@ -0,0 +1,286 @@
+//! Generation of client side bindings for a device
+//!
+//! The bindings contain an `OdRef` constant for each object in the device config, named after the
+//! object's `parameter_name`, so that host code using `zencan-client` can read and write the
+//! device's objects with the index, sub index and type checked at compile time. Record objects
+//! are given a struct, with a field for each sub object. The `unit` and `scale` of an object are
+//! included in its docs, and set on its `OdRef`.
+use std::collections::HashSet;
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use zencan_common::device_config::{DataType as DCDataType, DeviceConfig, Object};
+
+use crate::codegen::{get_sub_field_name, tokens_to_string};
+use crate::errors::CompileError;
+
+/// Get the rust type used to read and write a data type with `zencan-client`
+fn client_type(data_type: DCDataType) -> TokenStream {
+    match data_type {
+        DCDataType::Boolean => quote!(bool),
+        DCDataType::Int8 => quote!(i8),
+        DCDataType::Int16 => quote!(i16),
+        DCDataType::Int32 => quote!(i32),
+        DCDataType::UInt8 => quote!(u8),
+        DCDataType::UInt16 => quote!(u16),
+        DCDataType::UInt32 => quote!(u32),
+        DCDataType::Real32 => quote!(f32),
+        DCDataType::VisibleString(_) | DCDataType::UnicodeString(_) => quote!(String),
+        DCDataType::OctetString(_)
+        | DCDataType::TimeOfDay
+        | DCDataType::TimeDifference
+        | DCDataType::Domain => quote!(Vec<u8>),
+    }
+}
+
+/// Convert a parameter name to an upper case constant name, e.g. "Raw Analog Input" to
+/// `RAW_ANALOG_INPUT`
+///
+/// Returns None if the name does not produce a valid identifier.
+fn const_name(parameter_name: &str) -> Option<syn::Ident> {
+    let mut name = String::new();
+    for c in parameter_name.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_uppercase());
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    let name = name.trim_end_matches('_');
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    syn::parse_str(name).ok()
+}
+
+fn index_lit(index: u16) -> syn::Lit {
+    syn::parse_str(&format!("0x{:X}", index)).unwrap()
+}
+
+/// Describe the unit and scale of an object, for its doc comment
+fn units_doc(unit: &Option<String>, scale: Option<f64>) -> String {
+    match (unit, scale) {
+        (Some(unit), Some(scale)) => format!(". Unit: {unit}, scale: {scale}"),
+        (Some(unit), None) => format!(". Unit: {unit}"),
+        (None, Some(scale)) => format!(". Scale: {scale}"),
+        (None, None) => String::new(),
+    }
+}
+
+/// Get the call which sets the unit and scale on an `OdRef`, if either is set
+fn with_units(unit: &Option<String>, scale: Option<f64>) -> TokenStream {
+    if unit.is_none() && scale.is_none() {
+        return TokenStream::new();
+    }
+    let unit = match unit {
+        Some(unit) => quote!(Some(#unit)),
+        None => quote!(None),
+    };
+    let scale = match scale {
+        Some(scale) => {
+            let scale = proc_macro2::Literal::f64_suffixed(scale);
+            quote!(Some(#scale))
+        }
+        None => quote!(None),
+    };
+    quote!(.with_units(#unit, #scale))
+}
+
+fn od_ref(ty: TokenStream, index: u16, sub: u8) -> (TokenStream, TokenStream) {
+    let index = index_lit(index);
+    (
+        quote!(zencan_client::OdRef<#ty>),
+        quote!(zencan_client::OdRef::new(#index, #sub)),
+    )
+}
+
+/// Generate the client bindings for a device as a TokenStream
+pub fn device_config_to_client_tokens(dev: &DeviceConfig) -> Result<TokenStream, CompileError> {
+    let mut objects: Vec<_> = dev.objects.iter().collect();
+    objects.sort_by_key(|o| o.index);
+
+    let mut used_names = HashSet::new();
+    let mut tokens = TokenStream::new();
+    for obj in objects {
+        let index = obj.index;
+        // Fall back to the index when the name is missing, or used by an earlier object
+        let name = const_name(&obj.parameter_name)
+            .filter(|name| !used_names.contains(name))
+            .unwrap_or_else(|| format_ident!("OBJECT{:X}", index));
+        used_names.insert(name.clone());
+        let doc = format!(" {} (0x{:04X})", obj.parameter_name, index);
+
+        let fields: Vec<(syn::Ident, String, TokenStream, TokenStream)> = match &obj.object {
+            // Stubs cannot be read or written, so they get no binding
+            Object::Stub(_) => continue,
+            Object::Var(def) => {
+                let (ty, value) = od_ref(client_type(def.data_type), index, 0);
+                let doc = doc + &units_doc(&def.unit, def.scale);
+                let units = with_units(&def.unit, def.scale);
+                tokens.extend(quote! {
+                    #[doc = #doc]
+                    pub const #name: #ty = #value #units;
+                });
+                continue;
+            }
+            Object::Array(def) => {
+                let ty = client_type(def.data_type);
+                let size = def.array_size;
+                let index = index_lit(index);
+                let doc = doc + &units_doc(&def.unit, def.scale);
+                let units = with_units(&def.unit, def.scale);
+                tokens.extend(quote! {
+                    #[doc = #doc]
+                    pub const #name: zencan_client::OdRef<#ty, #size> =
+                        zencan_client::OdRef::new(#index, 1) #units;
+                });
+                continue;
+            }
+            Object::Record(def) => def
+                .subs
+                .iter()
+                .map(|sub| {
+                    let (ty, value) = od_ref(client_type(sub.data_type), index, sub.sub_index);
+                    let units = with_units(&sub.unit, sub.scale);
+                    let value = quote!(#value #units);
+                    let doc = format!(
+                        " {} (sub {}){}",
+                        sub.parameter_name,
+                        sub.sub_index,
+                        units_doc(&sub.unit, sub.scale)
+                    );
+                    Ok((get_sub_field_name(sub)?, doc, ty, value))
+                })
+                .collect::<Result<_, CompileError>>()?,
+            Object::Scaled(def) => [
+                ("raw", " The raw value (sub 1)", client_type(def.data_type)),
+                ("gain", " Gain (sub 2)", quote!(f32)),
+                ("offset", " Offset (sub 3)", quote!(f32)),
+                ("scaled", " Scaled value (sub 4)", quote!(f32)),
+            ]
+            .into_iter()
+            .zip(1u8..)
+            .map(|((field, doc, ty), sub)| {
+                let (ty, value) = od_ref(ty, index, sub);
+                (format_ident!("{}", field), doc.to_string(), ty, value)
+            })
+            .collect(),
+        };
+
+        let struct_name = format_ident!("Object{:X}", index);
+        let field_defs = fields.iter().map(|(field, doc, ty, _)| {
+            quote! {
+                #[doc = #doc]
+                pub #field: #ty,
+            }
+        });
+        let field_values = fields
+            .iter()
+            .map(|(field, _, _, value)| quote!(#field: #value,));
+        let struct_doc = format!(" The sub objects of{}", doc);
+        tokens.extend(quote! {
+            #[doc = #struct_doc]
+            #[derive(Debug, Clone, Copy)]
+            pub struct #struct_name {
+                #(#field_defs)*
+            }
+            #[doc = #doc]
+            pub const #name: #struct_name = #struct_name {
+                #(#field_values)*
+            };
+        });
+    }
+    Ok(tokens)
+}
+
+/// Generate the client bindings for a device as a string
+///
+/// # Arguments
+/// * `dev` - The device config
+/// * `format` - If true, generated code will be formatted with `prettyplease`
+pub fn device_config_to_client_string(
+    dev: &DeviceConfig,
+    format: bool,
+) -> Result<String, CompileError> {
+    Ok(tokens_to_string(
+        device_config_to_client_tokens(dev)?,
+        format,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_const_name() {
+        assert_eq!(
+            Some(format_ident!("RAW_ANALOG_INPUT")),
+            const_name("Raw analog input")
+        );
+        assert_eq!(
+            Some(format_ident!("NON_PERSISTED_STRING_VAR")),
+            const_name("Non-persisted String Var ")
+        );
+        assert_eq!(None, const_name(""));
+        assert_eq!(None, const_name("1st value"));
+    }
+
+    #[test]
+    fn test_client_bindings() {
+        let dev = DeviceConfig::load_from_str(
+            r#"
+            device_name = "test"
+
+            [identity]
+            vendor_id = 1
+            product_code = 2
+            revision_number = 3
+
+            [[objects]]
+            index = 0x2000
+            parameter_name = "Raw Analog Input"
+            object_type = "array"
+            data_type = "UInt16"
+            access_type = "ro"
+            array_size = 4
+
+            [[objects]]
+            index = 0x2001
+            parameter_name = "Raw Analog Input"
+            object_type = "var"
+            data_type = "VisibleString(8)"
+            access_type = "rw"
+
+            [[objects]]
+            index = 0x2002
+            parameter_name = "Limits"
+            object_type = "record"
+            [[objects.subs]]
+            sub_index = 1
+            field_name = "low"
+            data_type = "Int32"
+            access_type = "rw"
+
+            [[objects]]
+            index = 0x2003
+            parameter_name = "Supply Voltage"
+            object_type = "var"
+            data_type = "UInt16"
+            access_type = "ro"
+            unit = "V"
+            scale = 0.001
+        "#,
+        )
+        .unwrap();
+        let code = device_config_to_client_string(&dev, true).unwrap();
+        assert!(code.contains("pub const RAW_ANALOG_INPUT: zencan_client::OdRef<u16, 4usize>"));
+        // A repeated name falls back to the index
+        assert!(code.contains("pub const OBJECT2001: zencan_client::OdRef<String>"));
+        assert!(code.contains("pub struct Object2002"));
+        assert!(code.contains("pub low: zencan_client::OdRef<i32>"));
+        assert!(code.contains("pub const LIMITS: Object2002"));
+        assert!(code.contains("Supply Voltage (0x2003). Unit: V, scale: 0.001"));
+        assert!(code.contains(".with_units(Some(\"V\"), Some(0.001f64))"));
+    }
+}
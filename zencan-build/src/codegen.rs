@@ -2,12 +2,13 @@ use crate::errors::CompileError;
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 use zencan_common::device_config::{
-    DataType as DCDataType, DefaultValue, DeviceConfig, Object, ObjectDefinition, PdoMapping,
-    SubDefinition,
+    DataType as DCDataType, DefaultValue, DeviceConfig, NmtCommandName, NmtStateName, Object,
+    ObjectDefinition, PdoMapping, StubDefinition, SubDefinition,
 };
-use zencan_common::objects::{AccessType, ObjectCode};
+use zencan_common::objects::{AccessType, DataType, ObjectCode};
+use zencan_common::pdo_stamp::TpdoStamp;
 
-fn get_sub_field_name(sub: &SubDefinition) -> Result<syn::Ident, CompileError> {
+pub(crate) fn get_sub_field_name(sub: &SubDefinition) -> Result<syn::Ident, CompileError> {
     match &sub.field_name {
         Some(field_name) => {
             // Validate that the given field name is a valid rust identifier
@@ -25,17 +26,29 @@ fn get_sub_field_name(sub: &SubDefinition) -> Result<syn::Ident, CompileError> {
     }
 }
 
+/// Get the type used to store scalar values
+fn scalar_field_type(atomic: bool) -> TokenStream {
+    if atomic {
+        quote!(AtomicScalarField)
+    } else {
+        quote!(ScalarField)
+    }
+}
+
 /// Get the struct attribute type used to store this type
-fn get_storage_type(data_type: DCDataType) -> (syn::Type, usize) {
+///
+/// If `atomic` is true, scalar values are stored in an `AtomicScalarField`
+fn get_storage_type(data_type: DCDataType, atomic: bool) -> (syn::Type, usize) {
+    let field = scalar_field_type(atomic);
     match data_type {
-        DCDataType::Boolean => (syn::parse_quote!(ScalarField<bool>), 1),
-        DCDataType::Int8 => (syn::parse_quote!(ScalarField<i8>), 1),
-        DCDataType::Int16 => (syn::parse_quote!(ScalarField<i16>), 2),
-        DCDataType::Int32 => (syn::parse_quote!(ScalarField<i32>), 4),
-        DCDataType::UInt8 => (syn::parse_quote!(ScalarField<u8>), 1),
-        DCDataType::UInt16 => (syn::parse_quote!(ScalarField<u16>), 2),
-        DCDataType::UInt32 => (syn::parse_quote!(ScalarField<u32>), 4),
-        DCDataType::Real32 => (syn::parse_quote!(ScalarField<f32>), 4),
+        DCDataType::Boolean => (syn::parse_quote!(#field<bool>), 1),
+        DCDataType::Int8 => (syn::parse_quote!(#field<i8>), 1),
+        DCDataType::Int16 => (syn::parse_quote!(#field<i16>), 2),
+        DCDataType::Int32 => (syn::parse_quote!(#field<i32>), 4),
+        DCDataType::UInt8 => (syn::parse_quote!(#field<u8>), 1),
+        DCDataType::UInt16 => (syn::parse_quote!(#field<u16>), 2),
+        DCDataType::UInt32 => (syn::parse_quote!(#field<u32>), 4),
+        DCDataType::Real32 => (syn::parse_quote!(#field<f32>), 4),
         DCDataType::VisibleString(n) | DCDataType::UnicodeString(n) => (
             syn::parse_str(&format!("NullTermByteField::<{}>", n)).unwrap(),
             n,
@@ -46,6 +59,32 @@ fn get_storage_type(data_type: DCDataType) -> (syn::Type, usize) {
     }
 }
 
+/// Returns true if the type is stored in a `NullTermByteField`
+fn is_null_term_str(data_type: DCDataType) -> bool {
+    matches!(
+        data_type,
+        DCDataType::VisibleString(_) | DCDataType::UnicodeString(_)
+    )
+}
+
+/// Get the string accessors for a null terminated string field of a var or record object
+fn null_term_str_accessors(field_name: &syn::Ident, field_type: &syn::Type) -> TokenStream {
+    let setter_name = format_ident!("set_{}_str", field_name);
+    let getter_name = format_ident!("get_{}_str", field_name);
+    quote! {
+        /// Store a string, padding it with nulls if it is shorter than the object
+        #[allow(dead_code)]
+        pub fn #setter_name(&self, value: &[u8]) -> Result<(), AbortCode> {
+            self.#field_name.set_str(value)
+        }
+        /// Load the string into `buf`, and return the bytes before the null terminator
+        #[allow(dead_code)]
+        pub fn #getter_name<'a>(&self, buf: &'a mut #field_type) -> &'a [u8] {
+            self.#field_name.get_str(buf)
+        }
+    }
+}
+
 fn get_rust_type_and_size(data_type: DCDataType) -> (syn::Type, usize) {
     match data_type {
         DCDataType::Boolean => (syn::parse_quote!(bool), 1),
@@ -112,6 +151,30 @@ fn data_type_to_tokens(dt: DCDataType) -> TokenStream {
     }
 }
 
+/// Convert a CANopen data type, as stored in a stub object, to tokens expressing the variant
+fn canopen_data_type_to_tokens(dt: DataType) -> TokenStream {
+    match dt {
+        DataType::Boolean => quote!(zencan_node::common::objects::DataType::Boolean),
+        DataType::Int8 => quote!(zencan_node::common::objects::DataType::Int8),
+        DataType::Int16 => quote!(zencan_node::common::objects::DataType::Int16),
+        DataType::Int32 => quote!(zencan_node::common::objects::DataType::Int32),
+        DataType::UInt8 => quote!(zencan_node::common::objects::DataType::UInt8),
+        DataType::UInt16 => quote!(zencan_node::common::objects::DataType::UInt16),
+        DataType::UInt32 => quote!(zencan_node::common::objects::DataType::UInt32),
+        DataType::Real32 => quote!(zencan_node::common::objects::DataType::Real32),
+        DataType::VisibleString => quote!(zencan_node::common::objects::DataType::VisibleString),
+        DataType::OctetString => quote!(zencan_node::common::objects::DataType::OctetString),
+        DataType::UnicodeString => quote!(zencan_node::common::objects::DataType::UnicodeString),
+        DataType::TimeOfDay => quote!(zencan_node::common::objects::DataType::TimeOfDay),
+        DataType::TimeDifference => quote!(zencan_node::common::objects::DataType::TimeDifference),
+        DataType::Domain => quote!(zencan_node::common::objects::DataType::Domain),
+        DataType::Real64 => quote!(zencan_node::common::objects::DataType::Real64),
+        DataType::Int64 => quote!(zencan_node::common::objects::DataType::Int64),
+        DataType::UInt64 => quote!(zencan_node::common::objects::DataType::UInt64),
+        DataType::Other(code) => quote!(zencan_node::common::objects::DataType::Other(#code)),
+    }
+}
+
 fn pdo_mapping_to_tokens(p: PdoMapping) -> TokenStream {
     match p {
         PdoMapping::None => quote!(zencan_node::common::objects::PdoMapping::None),
@@ -122,12 +185,29 @@ fn pdo_mapping_to_tokens(p: PdoMapping) -> TokenStream {
 }
 
 /// Return true if any subobjects on the object support being mapped to a TPDO
-fn object_supports_tpdo(obj: &ObjectDefinition) -> bool {
-    match &obj.object {
-        Object::Var(def) => def.pdo_mapping.supports_tpdo(),
-        Object::Array(def) => def.pdo_mapping.supports_tpdo(),
-        Object::Record(def) => def.subs.iter().any(|s| s.pdo_mapping.supports_tpdo()),
-    }
+/// Get the number of bytes of event flags needed by an object, or 0 if it needs none
+///
+/// Event flags are used to trigger TPDOs, with one bit per sub index. Only sub indices up to the
+/// highest one which can be mapped to a TPDO are stored, so objects which cannot be mapped to a TPDO
+/// have no flags.
+fn event_flag_bytes(obj: &ObjectDefinition) -> usize {
+    let flag_count = match &obj.object {
+        Object::Var(def) => def.pdo_mapping.supports_tpdo().then_some(1),
+        Object::Array(def) => def
+            .pdo_mapping
+            .supports_tpdo()
+            .then_some(def.array_size + 1),
+        Object::Record(def) => def
+            .subs
+            .iter()
+            .filter(|s| s.pdo_mapping.supports_tpdo())
+            .map(|s| s.sub_index as usize + 1)
+            .max(),
+        // The scaled value is sub 4
+        Object::Scaled(def) => def.pdo_mapping.supports_tpdo().then_some(5),
+        Object::Stub(_) => None,
+    };
+    flag_count.unwrap_or(0).div_ceil(8)
 }
 
 fn string_to_byte_literal_tokens(s: &str, size: usize) -> Result<TokenStream, CompileError> {
@@ -143,51 +223,59 @@ fn string_to_byte_literal_tokens(s: &str, size: usize) -> Result<TokenStream, Co
     Ok(quote!([#(#padded),*]))
 }
 
-fn generate_object_definition(obj: &ObjectDefinition) -> Result<TokenStream, CompileError> {
-    if obj.application_callback {
-        // Objects implemented in application callbacks do not generate a struct
+fn generate_object_definition(
+    obj: &ObjectDefinition,
+    atomic: bool,
+) -> Result<TokenStream, CompileError> {
+    if obj.application_callback || matches!(obj.object, Object::Stub(_)) {
+        // Objects implemented in application callbacks, and stubs, do not generate a struct
         return Ok(quote! {});
     }
     let struct_name: syn::Ident = syn::parse_str(&format!("Object{:X}", obj.index)).unwrap();
 
     let mut field_tokens = TokenStream::new();
-    let mut tpdo_mapping = false;
-    let mut highest_sub_index = 0;
     match &obj.object {
         Object::Record(def) => {
             for sub in &def.subs {
                 let field_name = get_sub_field_name(sub)?;
-                let (field_type, _) = get_storage_type(sub.data_type);
+                let (field_type, _) = get_storage_type(sub.data_type, atomic);
                 field_tokens.extend(quote! {
                     pub #field_name: #field_type,
                 });
-                tpdo_mapping |= sub.pdo_mapping.supports_tpdo();
-                highest_sub_index = highest_sub_index.max(sub.sub_index);
             }
         }
         Object::Array(def) => {
-            let (field_type, _) = get_storage_type(def.data_type);
+            let (field_type, _) = get_storage_type(def.data_type, atomic);
             let array_size = def.array_size;
             field_tokens.extend(quote! {
                 pub array: [#field_type; #array_size],
             });
-            tpdo_mapping |= def.pdo_mapping.supports_tpdo();
-            highest_sub_index = array_size as u8;
         }
         Object::Var(def) => {
-            let (field_type, _) = get_storage_type(def.data_type);
+            let (field_type, _) = get_storage_type(def.data_type, atomic);
             field_tokens.extend(quote! {
                 pub value: #field_type,
             });
-            tpdo_mapping |= def.pdo_mapping.supports_tpdo();
-            highest_sub_index = 0;
         }
+        Object::Scaled(def) => {
+            let (raw_type, _) = get_rust_type_and_size(def.data_type);
+            field_tokens.extend(quote! {
+                pub value: ScaledField<#raw_type>,
+            });
+        }
+        Object::Stub(_) => unreachable!("Stub objects do not generate a struct"),
     }
 
-    if tpdo_mapping {
-        let n = (highest_sub_index as usize + 1).div_ceil(8);
+    let flag_bytes = event_flag_bytes(obj);
+    if flag_bytes > 0 {
         field_tokens.extend(quote! {
-            flags: ObjectFlags<#n>,
+            flags: ObjectFlags<#flag_bytes>,
+        });
+    }
+
+    if obj.on_write_callback {
+        field_tokens.extend(quote! {
+            write_hook: WriteHook,
         });
     }
 
@@ -222,7 +310,9 @@ fn default_default_value(data_type: DCDataType) -> DefaultValue {
 fn get_default_tokens(
     value: &DefaultValue,
     data_type: DCDataType,
+    atomic: bool,
 ) -> Result<TokenStream, CompileError> {
+    let field = scalar_field_type(atomic);
     if matches!(data_type, DCDataType::Domain) {
         return Ok(quote!(CallbackSubObject::new()));
     }
@@ -245,7 +335,10 @@ fn get_default_tokens(
             }
         }
         DefaultValue::Float(f) => match data_type {
-            DCDataType::Real32 => Ok(quote!(ScalarField<f32>::new(#f))),
+            DCDataType::Real32 => {
+                let f = *f as f32;
+                Ok(quote!(#field::<f32>::new(#f)))
+            }
             _ => Err(CompileError::DefaultValueTypeMismatch {
                 message: format!(
                     "Default value {} is not a valid value for type {:?}",
@@ -258,18 +351,18 @@ fn get_default_tokens(
             match data_type {
                 DCDataType::Boolean => {
                     if *i != 0 {
-                        Ok(quote!(ScalarField<bool>::new(true)))
+                        Ok(quote!(#field::<bool>::new(true)))
                     } else {
-                        Ok(quote!(ScalarField<bool>::new(false)))
+                        Ok(quote!(#field::<bool>::new(false)))
                     }
                 }
-                DCDataType::Int8 => Ok(quote!(ScalarField::<i8>::new(#i as i8))),
-                DCDataType::Int16 => Ok(quote!(ScalarField::<i16>::new(#i as i16))),
-                DCDataType::Int32 => Ok(quote!(ScalarField::<i32>::new(#i as i32))),
-                DCDataType::UInt8 => Ok(quote!(ScalarField::<u8>::new(#i as u8))),
-                DCDataType::UInt16 => Ok(quote!(ScalarField::<u16>::new(#i as u16))),
-                DCDataType::UInt32 => Ok(quote!(ScalarField::<u32>::new(#i as u32))),
-                DCDataType::Real32 => Ok(quote!(ScalarField::<f32>::new(#i as f32))),
+                DCDataType::Int8 => Ok(quote!(#field::<i8>::new(#i as i8))),
+                DCDataType::Int16 => Ok(quote!(#field::<i16>::new(#i as i16))),
+                DCDataType::Int32 => Ok(quote!(#field::<i32>::new(#i as i32))),
+                DCDataType::UInt8 => Ok(quote!(#field::<u8>::new(#i as u8))),
+                DCDataType::UInt16 => Ok(quote!(#field::<u16>::new(#i as u16))),
+                DCDataType::UInt32 => Ok(quote!(#field::<u32>::new(#i as u32))),
+                DCDataType::Real32 => Ok(quote!(#field::<f32>::new(#i as f32))),
                 _ => Err(CompileError::DefaultValueTypeMismatch {
                     message: format!(
                         "Default value {} is not a valid value for type {:?}",
@@ -284,11 +377,11 @@ fn get_default_tokens(
 fn get_object_impls(
     obj: &ObjectDefinition,
     struct_name: &syn::Ident,
+    atomic: bool,
 ) -> Result<TokenStream, CompileError> {
     let mut accessor_methods = TokenStream::new();
     let mut default_init_tokens = TokenStream::new();
     let mut get_sub_tokens = TokenStream::new();
-    let mut flag_number = 0usize;
     let object_code;
 
     match &obj.object {
@@ -306,15 +399,11 @@ fn get_object_impls(
                 .default_value
                 .clone()
                 .unwrap_or(default_default_value(def.data_type));
-            let default_value = get_default_tokens(&default_value, def.data_type)?;
+            let default_value = get_default_tokens(&default_value, def.data_type, atomic)?;
             default_init_tokens.extend(quote! {
                 #field_name: #default_value,
             });
 
-            if def.pdo_mapping.supports_tpdo() {
-                flag_number = 1;
-            }
-
             // Accessors are generated for all data types, except Domain
             if !matches!(def.data_type, DCDataType::Domain) {
                 accessor_methods.extend(quote! {
@@ -328,6 +417,20 @@ fn get_object_impls(
                         self.#field_name.load()
                     }
                 });
+                if is_null_term_str(def.data_type) {
+                    accessor_methods.extend(null_term_str_accessors(&field_name, &field_type));
+                }
+                if def.pdo_mapping.supports_tpdo() {
+                    let notify_name = format_ident!("set_{}_notify", field_name);
+                    accessor_methods.extend(quote! {
+                        /// Store a new value, and set its event flag to trigger mapped TPDOs
+                        #[allow(dead_code)]
+                        pub fn #notify_name(&self, value: #field_type) {
+                            self.#field_name.store(value);
+                            self.flags.set_flag(0);
+                        }
+                    });
+                }
             }
 
             get_sub_tokens.extend(quote! {
@@ -364,7 +467,7 @@ fn get_object_impls(
 
             let default_tokens: Vec<_> = default_value
                 .iter()
-                .map(|v| get_default_tokens(v, def.data_type))
+                .map(|v| get_default_tokens(v, def.data_type, atomic))
                 .collect::<Result<Vec<_>, CompileError>>()?;
 
             if !matches!(def.data_type, DCDataType::Domain) {
@@ -385,6 +488,43 @@ fn get_object_impls(
                         Ok(self.array[idx].load())
                     }
                 });
+                if is_null_term_str(def.data_type) {
+                    accessor_methods.extend(quote! {
+                        /// Store a string to an element, padding it with nulls if it is shorter
+                        /// than the element
+                        #[allow(dead_code)]
+                        pub fn set_str(&self, idx: usize, value: &[u8]) -> Result<(), AbortCode> {
+                            if idx >= #array_size {
+                                return Err(AbortCode::NoSuchSubIndex)
+                            }
+                            self.array[idx].set_str(value)
+                        }
+                        /// Load the string in an element into `buf`, and return the bytes before
+                        /// the null terminator
+                        #[allow(dead_code)]
+                        pub fn get_str<'a>(&self, idx: usize, buf: &'a mut #field_type) -> Result<&'a [u8], AbortCode> {
+                            if idx >= #array_size {
+                                return Err(AbortCode::NoSuchSubIndex)
+                            }
+                            Ok(self.array[idx].get_str(buf))
+                        }
+                    });
+                }
+                if def.pdo_mapping.supports_tpdo() {
+                    accessor_methods.extend(quote! {
+                        /// Store a new value for an element, and set its event flag to trigger
+                        /// mapped TPDOs
+                        #[allow(dead_code)]
+                        pub fn set_notify(&self, idx: usize, value: #field_type) -> Result<(), AbortCode> {
+                            if idx >= #array_size {
+                                return Err(AbortCode::NoSuchSubIndex)
+                            }
+                            self.array[idx].store(value);
+                            self.flags.set_flag(idx as u8 + 1);
+                            Ok(())
+                        }
+                    });
+                }
             }
 
             default_init_tokens.extend(quote! {
@@ -410,22 +550,21 @@ fn get_object_impls(
                 }
             });
 
-            if def.pdo_mapping.supports_tpdo() {
-                flag_number = array_size + 1;
-            }
-
             object_code = quote!(zencan_node::common::objects::ObjectCode::Array);
         }
 
         Object::Record(def) => {
             let mut match_statements = TokenStream::new();
 
-            // For records, sub0 gives the highest sub object support by the record
-            let max_sub = def.subs.iter().map(|s| s.sub_index).max().unwrap_or(0);
-
-            if object_supports_tpdo(obj) {
-                flag_number = max_sub as usize + 1;
-            }
+            // For records, sub0 gives the highest sub object support by the record, including
+            // reserved subs
+            let max_sub = def
+                .subs
+                .iter()
+                .map(|s| s.sub_index)
+                .chain(def.reserved_subs.iter().copied())
+                .max()
+                .unwrap_or(0);
 
             accessor_methods.extend(quote! {
                 #[allow(dead_code)]
@@ -459,7 +598,7 @@ fn get_object_impls(
                     .default_value
                     .clone()
                     .unwrap_or(default_default_value(sub.data_type));
-                let default_tokens = get_default_tokens(&default_value, sub.data_type)?;
+                let default_tokens = get_default_tokens(&default_value, sub.data_type, atomic)?;
 
                 let access_type = access_type_to_tokens(sub.access_type.0);
 
@@ -474,6 +613,20 @@ fn get_object_impls(
                             self.#field_name.load()
                         }
                     });
+                    if is_null_term_str(sub.data_type) {
+                        accessor_methods.extend(null_term_str_accessors(&field_name, &field_type));
+                    }
+                    if sub.pdo_mapping.supports_tpdo() {
+                        let notify_name = format_ident!("set_{}_notify", field_name);
+                        accessor_methods.extend(quote! {
+                            /// Store a new value, and set its event flag to trigger mapped TPDOs
+                            #[allow(dead_code)]
+                            pub fn #notify_name(&self, value: #field_type) {
+                                self.#field_name.store(value);
+                                self.flags.set_flag(#sub_index);
+                            }
+                        });
+                    }
                 }
                 match_statements.extend(quote! {
                     #sub_index => Some(
@@ -494,6 +647,13 @@ fn get_object_impls(
                 });
             }
 
+            // Reserved subs have no sub object
+            for reserved in &def.reserved_subs {
+                match_statements.extend(quote! {
+                    #reserved => None,
+                });
+            }
+
             get_sub_tokens.extend(quote! {
                 match sub {
                     #match_statements
@@ -503,27 +663,179 @@ fn get_object_impls(
 
             object_code = quote!(zencan_node::common::objects::ObjectCode::Record);
         }
+
+        Object::Scaled(def) => {
+            let (raw_type, raw_size) = get_rust_type_and_size(def.data_type);
+            let data_type = data_type_to_tokens(def.data_type);
+            let access_type = access_type_to_tokens(def.access_type.0);
+            let pdo_mapping = pdo_mapping_to_tokens(def.pdo_mapping);
+            // The scaled value is read-only, so it can only be mapped to TPDOs
+            let scaled_pdo_mapping = if def.pdo_mapping.supports_tpdo() {
+                pdo_mapping_to_tokens(PdoMapping::Tpdo)
+            } else {
+                pdo_mapping_to_tokens(PdoMapping::None)
+            };
+            let persist = def.persist;
+            let gain = def.gain;
+            let offset = def.offset;
+
+            let default_value = def
+                .default_value
+                .clone()
+                .unwrap_or(default_default_value(def.data_type));
+            // The raw value is always a ScalarField, as required by ScaledField
+            let raw_default = get_default_tokens(&default_value, def.data_type, false)?;
+            default_init_tokens.extend(quote! {
+                value: ScaledField::new(#raw_default, #gain, #offset),
+            });
+
+            accessor_methods.extend(quote! {
+                #[allow(dead_code)]
+                pub fn get_sub0(&self) -> u8 {
+                    4
+                }
+                #[allow(dead_code)]
+                pub fn set_raw(&self, value: #raw_type) {
+                    self.value.raw.store(value)
+                }
+                #[allow(dead_code)]
+                pub fn get_raw(&self) -> #raw_type {
+                    self.value.raw.load()
+                }
+                #[allow(dead_code)]
+                pub fn set_gain(&self, value: f32) {
+                    self.value.gain.store(value)
+                }
+                #[allow(dead_code)]
+                pub fn get_gain(&self) -> f32 {
+                    self.value.gain.load()
+                }
+                #[allow(dead_code)]
+                pub fn set_offset(&self, value: f32) {
+                    self.value.offset.store(value)
+                }
+                #[allow(dead_code)]
+                pub fn get_offset(&self) -> f32 {
+                    self.value.offset.load()
+                }
+                #[allow(dead_code)]
+                pub fn get_scaled(&self) -> f32 {
+                    self.value.scaled()
+                }
+            });
+            if def.pdo_mapping.supports_tpdo() {
+                accessor_methods.extend(quote! {
+                    /// Store a new raw value, and set the event flags of the raw and scaled values
+                    /// to trigger mapped TPDOs
+                    #[allow(dead_code)]
+                    pub fn set_raw_notify(&self, value: #raw_type) {
+                        self.value.raw.store(value);
+                        self.flags.set_flag(1);
+                        self.flags.set_flag(4);
+                    }
+                });
+            }
+
+            get_sub_tokens.extend(quote! {
+                match sub {
+                    0 => Some((
+                        SubInfo::MAX_SUB_NUMBER,
+                        const { &ConstField::new(4u8.to_le_bytes()) },
+                    )),
+                    1 => Some((
+                        SubInfo {
+                            access_type: #access_type,
+                            data_type: #data_type,
+                            size: #raw_size,
+                            pdo_mapping: #pdo_mapping,
+                            persist: false,
+                        },
+                        &self.value.raw,
+                    )),
+                    2 => Some((
+                        SubInfo {
+                            access_type: zencan_node::common::objects::AccessType::Rw,
+                            data_type: zencan_node::common::objects::DataType::Real32,
+                            size: 4,
+                            pdo_mapping: zencan_node::common::objects::PdoMapping::None,
+                            persist: #persist,
+                        },
+                        &self.value.gain,
+                    )),
+                    3 => Some((
+                        SubInfo {
+                            access_type: zencan_node::common::objects::AccessType::Rw,
+                            data_type: zencan_node::common::objects::DataType::Real32,
+                            size: 4,
+                            pdo_mapping: zencan_node::common::objects::PdoMapping::None,
+                            persist: #persist,
+                        },
+                        &self.value.offset,
+                    )),
+                    4 => Some((
+                        SubInfo {
+                            access_type: zencan_node::common::objects::AccessType::Ro,
+                            data_type: zencan_node::common::objects::DataType::Real32,
+                            size: 4,
+                            pdo_mapping: #scaled_pdo_mapping,
+                            persist: false,
+                        },
+                        &self.value,
+                    )),
+                    _ => None,
+                }
+            });
+
+            object_code = quote!(zencan_node::common::objects::ObjectCode::Record);
+        }
+        Object::Stub(_) => unreachable!("Stub objects are generated as a StubObject"),
     }
 
     let mut flag_method_tokens = TokenStream::new();
     let mut flag_default_tokens = TokenStream::new();
-    if flag_number > 0 {
-        let flag_size = (flag_number).div_ceil(8);
+    let flag_bytes = event_flag_bytes(obj);
+    if flag_bytes > 0 {
         flag_method_tokens.extend(quote! {
             fn flags(&self) -> Option<&dyn ObjectFlagAccess> {
                 Some(&self.flags)
             }
         });
         flag_default_tokens.extend(quote! {
-            flags: ObjectFlags::<#flag_size>::new(NODE_STATE.pdo_sync()),
+            flags: ObjectFlags::<#flag_bytes>::new(NODE_STATE.pdo_sync()),
+        });
+    }
+
+    let mut hook_method_tokens = TokenStream::new();
+    let mut hook_accessor_tokens = TokenStream::new();
+    if obj.on_write_callback {
+        hook_method_tokens.extend(quote! {
+            fn write_hook(&self) -> Option<&WriteHook> {
+                Some(&self.write_hook)
+            }
+        });
+        flag_default_tokens.extend(quote! {
+            write_hook: WriteHook::new(),
+        });
+        hook_accessor_tokens.extend(quote! {
+            /// Register a function to be called with the sub index after each write to the object
+            /// via the object dictionary
+            ///
+            /// It replaces any previously registered function.
+            #[allow(dead_code)]
+            pub fn register_write_hook(&self, hook: &'static WriteHookFn) {
+                self.write_hook.register(hook);
+            }
         });
     }
 
     Ok(quote! {
         impl #struct_name {
             #accessor_methods
+            #hook_accessor_tokens
 
-            const fn default() -> Self {
+            /// Create a new instance of the object, initialized with its configured default values
+            #[allow(dead_code)]
+            pub const fn new() -> Self {
                 #struct_name {
                     #default_init_tokens
                     #flag_default_tokens
@@ -531,12 +843,19 @@ fn get_object_impls(
             }
         }
 
+        impl Default for #struct_name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
         impl ProvidesSubObjects for #struct_name {
             fn get_sub_object(&self, sub: u8) -> Option<(SubInfo, &dyn SubObjectAccess)> {
                 #get_sub_tokens
             }
 
             #flag_method_tokens
+            #hook_method_tokens
 
             fn object_code(&self) -> zencan_node::common::objects::ObjectCode {
                 #object_code
@@ -548,9 +867,10 @@ fn get_object_impls(
 pub fn generate_object_code(
     obj: &ObjectDefinition,
     struct_name: &syn::Ident,
+    atomic: bool,
 ) -> Result<TokenStream, CompileError> {
-    let struct_def = generate_object_definition(obj)?;
-    let impls = get_object_impls(obj, struct_name)?;
+    let struct_def = generate_object_definition(obj, atomic)?;
+    let impls = get_object_impls(obj, struct_name, atomic)?;
 
     Ok(quote! {
         #struct_def
@@ -561,6 +881,10 @@ pub fn generate_object_code(
 pub fn generate_state_inst(dev: &DeviceConfig) -> TokenStream {
     let n_rpdo = dev.pdos.num_rpdo as usize;
     let n_tpdo = dev.pdos.num_tpdo as usize;
+    let n_error_history = dev.error_history as usize;
+    let rpdo_queue_depth = dev.rpdo_queue_depth();
+    let sdo_queue_depth = dev.mbox.sdo_queue_depth;
+    let nmt_queue_depth = dev.mbox.nmt_queue_depth;
 
     let mut tokens = TokenStream::new();
 
@@ -611,6 +935,13 @@ pub fn generate_state_inst(dev: &DeviceConfig) -> TokenStream {
         ];
     });
 
+    tokens.extend(quote! {
+        pub static EMCY_COB_ID_OBJECT: zencan_node::cob_id::EmcyCobIdObject =
+            zencan_node::cob_id::EmcyCobIdObject::new(NODE_STATE.cob_ids());
+        pub static SDO_SERVER_OBJECT: zencan_node::cob_id::SdoServerObject =
+            zencan_node::cob_id::SdoServerObject::new(NODE_STATE.cob_ids());
+    });
+
     if dev.support_storage {
         tokens.extend(quote! {
             pub static STORAGE_COMMAND_OBJECT: StorageCommandObject =
@@ -618,17 +949,359 @@ pub fn generate_state_inst(dev: &DeviceConfig) -> TokenStream {
         });
     }
 
+    if dev.error_history > 0 {
+        tokens.extend(quote! {
+            pub static ERROR_HISTORY_OBJECT: zencan_node::ErrorHistoryObject =
+                zencan_node::ErrorHistoryObject::new(NODE_STATE.error_history());
+        });
+    }
+
+    if dev.sdo_status {
+        tokens.extend(quote! {
+            pub static SDO_STATUS_OBJECT: zencan_node::sdo_status::SdoStatusObject =
+                zencan_node::sdo_status::SdoStatusObject::new(NODE_STATE.status());
+        });
+    }
+
+    if dev.settings_backup {
+        let value_size = settings_backup_value_size(dev);
+        tokens.extend(quote! {
+            static SETTINGS_BACKUP_BUFFER: zencan_node::BufferCell<[u8; #value_size]> =
+                zencan_node::BufferCell::new([0; #value_size]);
+            pub static SETTINGS_BACKUP_OBJECT: zencan_node::storage::SettingsBackupObject =
+                zencan_node::storage::SettingsBackupObject::new(
+                    &OD_TABLE,
+                    NODE_STATE.storage_context(),
+                    &SETTINGS_BACKUP_BUFFER,
+                );
+        });
+    }
+
+    let access_trace = if dev.access_trace.depth > 0 {
+        let depth = dev.access_trace.depth;
+        let trace_pdos = dev.access_trace.pdos;
+        tokens.extend(quote! {
+            static ACCESS_TRACE_BUFFER: zencan_node::BufferCell<[zencan_node::common::access_trace::AccessTraceEntry; #depth]> =
+                zencan_node::BufferCell::new([zencan_node::common::access_trace::AccessTraceEntry::EMPTY; #depth]);
+            pub static ACCESS_TRACE: zencan_node::AccessTrace =
+                zencan_node::AccessTrace::new(&ACCESS_TRACE_BUFFER, #trace_pdos);
+        });
+        quote!(.with_access_trace(&ACCESS_TRACE))
+    } else {
+        quote!()
+    };
+
+    let debug_log = if dev.debug_log.size > 0 {
+        let size = dev.debug_log.size;
+        let rate_limit = dev.debug_log.rate_limit;
+        tokens.extend(quote! {
+            static DEBUG_LOG_BUFFER: zencan_node::BufferCell<[u8; #size]> =
+                zencan_node::BufferCell::new([0; #size]);
+            pub static DEBUG_LOG: zencan_node::DebugLog =
+                zencan_node::DebugLog::new(&DEBUG_LOG_BUFFER, #rate_limit);
+        });
+        quote!(.with_debug_log(&DEBUG_LOG))
+    } else {
+        quote!()
+    };
+
+    let event_log = if dev.event_log.depth > 0 {
+        let depth = dev.event_log.depth;
+        let persist = dev.event_log.persist;
+        tokens.extend(quote! {
+            static EVENT_LOG_BUFFER: zencan_node::BufferCell<[zencan_node::common::event_log::EventLogEntry; #depth]> =
+                zencan_node::BufferCell::new([zencan_node::common::event_log::EventLogEntry::EMPTY; #depth]);
+            pub static EVENT_LOG: zencan_node::EventLog =
+                zencan_node::EventLog::new(&EVENT_LOG_BUFFER, #persist);
+        });
+        quote!(.with_event_log(&EVENT_LOG))
+    } else {
+        quote!()
+    };
+
+    let loopback_test = if dev.loopback_test {
+        tokens.extend(quote! {
+            pub static LOOPBACK_TEST: zencan_node::LoopbackTest = zencan_node::LoopbackTest::new();
+        });
+        quote!(.with_loopback_test(&LOOPBACK_TEST))
+    } else {
+        quote!()
+    };
+
+    let pdo_statistics = if dev.pdos.statistics {
+        tokens.extend(quote! {
+            static TPDO_COUNTERS: [zencan_node::PdoCounter; #n_tpdo] =
+                [const { zencan_node::PdoCounter::new() }; #n_tpdo];
+            static RPDO_COUNTERS: [zencan_node::PdoCounter; #n_rpdo] =
+                [const { zencan_node::PdoCounter::new() }; #n_rpdo];
+            pub static PDO_STATISTICS: zencan_node::PdoStatistics =
+                zencan_node::PdoStatistics::new(&TPDO_COUNTERS, &RPDO_COUNTERS);
+            pub static TPDO_COUNT_OBJECT: zencan_node::PdoStatisticsObject =
+                zencan_node::PdoStatisticsObject::counts(&TPDO_COUNTERS);
+            pub static TPDO_TIME_OBJECT: zencan_node::PdoStatisticsObject =
+                zencan_node::PdoStatisticsObject::timestamps(&TPDO_COUNTERS);
+            pub static RPDO_COUNT_OBJECT: zencan_node::PdoStatisticsObject =
+                zencan_node::PdoStatisticsObject::counts(&RPDO_COUNTERS);
+            pub static RPDO_TIME_OBJECT: zencan_node::PdoStatisticsObject =
+                zencan_node::PdoStatisticsObject::timestamps(&RPDO_COUNTERS);
+        });
+        quote!(.with_pdo_statistics(&PDO_STATISTICS))
+    } else {
+        quote!()
+    };
+
+    let nmt_protection = if dev.nmt_protection.commands.is_empty() {
+        quote!()
+    } else {
+        let commands = dev.nmt_protection.commands.iter().map(|cmd| {
+            let variant = match cmd {
+                NmtCommandName::Start => format_ident!("Start"),
+                NmtCommandName::Stop => format_ident!("Stop"),
+                NmtCommandName::EnterPreOp => format_ident!("EnterPreOp"),
+                NmtCommandName::ResetApp => format_ident!("ResetApp"),
+                NmtCommandName::ResetComm => format_ident!("ResetComm"),
+            };
+            quote!(.protect(zencan_node::common::messages::NmtCommandSpecifier::#variant))
+        });
+        let states = dev.nmt_protection.allowed_states.iter().map(|state| {
+            let variant = match state {
+                NmtStateName::PreOperational => format_ident!("PreOperational"),
+                NmtStateName::Operational => format_ident!("Operational"),
+                NmtStateName::Stopped => format_ident!("Stopped"),
+            };
+            quote!(.allow_in(zencan_node::common::messages::NmtState::#variant))
+        });
+        tokens.extend(quote! {
+            pub static NMT_PROTECTION: zencan_node::NmtProtection =
+                zencan_node::NmtProtection::new()#(#commands)*#(#states)*;
+        });
+        quote!(.with_nmt_protection(&NMT_PROTECTION))
+    };
+
+    let tpdo_stamps = if dev.pdos.tpdo_stamps.is_empty() {
+        quote!()
+    } else {
+        let stamps = (0..n_tpdo).map(|i| match dev.pdos.tpdo_stamp(i) {
+            TpdoStamp::None => quote!(zencan_node::common::pdo_stamp::TpdoStamp::None),
+            TpdoStamp::SyncCounter => {
+                quote!(zencan_node::common::pdo_stamp::TpdoStamp::SyncCounter)
+            }
+            TpdoStamp::Timestamp => quote!(zencan_node::common::pdo_stamp::TpdoStamp::Timestamp),
+        });
+        quote!(.with_tpdo_stamps([#(#stamps),*]))
+    };
+
+    let tx_queue = if dev.mbox.tx_queue_depth > 0 {
+        let tx_queue_depth = dev.mbox.tx_queue_depth;
+        tokens.extend(quote! {
+            static TX_QUEUE: zencan_node::BufferCell<[zencan_node::TxSlot; #tx_queue_depth]> =
+                zencan_node::BufferCell::new([zencan_node::TxSlot::EMPTY; #tx_queue_depth]);
+        });
+        quote!(.with_tx_queue(&TX_QUEUE))
+    } else {
+        quote!()
+    };
+
+    let sdo_write_buffer = if dev.mbox.sdo_double_buffer {
+        tokens.extend(quote! {
+            static SDO_WRITE_BUFFER: zencan_node::BufferCell<[u8; SDO_BUFFER_SIZE]> =
+                zencan_node::BufferCell::new([0; SDO_BUFFER_SIZE]);
+        });
+        quote!(.with_sdo_write_buffer(&SDO_WRITE_BUFFER))
+    } else {
+        quote!()
+    };
+
     tokens.extend(quote! {
-        #[allow(static_mut_refs)]
-        static mut SDO_BUFFER: [u8; SDO_BUFFER_SIZE] = [0; SDO_BUFFER_SIZE];
-        pub static NODE_STATE: NodeState<#n_rpdo, #n_tpdo> = NodeState::new();
-        #[allow(static_mut_refs)]
-        pub static NODE_MBOX: NodeMbox = NodeMbox::new(NODE_STATE.rpdos(), unsafe { &mut SDO_BUFFER });
+        static SDO_BUFFER: zencan_node::BufferCell<[u8; SDO_BUFFER_SIZE]> =
+            zencan_node::BufferCell::new([0; SDO_BUFFER_SIZE]);
+        static RPDO_QUEUE: zencan_node::BufferCell<[Option<CanMessage>; #rpdo_queue_depth]> =
+            zencan_node::BufferCell::new([None; #rpdo_queue_depth]);
+        static SDO_QUEUE: zencan_node::BufferCell<[Option<CanMessage>; #sdo_queue_depth]> =
+            zencan_node::BufferCell::new([None; #sdo_queue_depth]);
+        static NMT_QUEUE: zencan_node::BufferCell<[Option<CanMessage>; #nmt_queue_depth]> =
+            zencan_node::BufferCell::new([None; #nmt_queue_depth]);
+        pub static NODE_STATE: NodeState<#n_rpdo, #n_tpdo, #n_error_history> = NodeState::new()#access_trace #debug_log #event_log #loopback_test #nmt_protection #pdo_statistics #tpdo_stamps;
+        pub static NODE_MBOX: NodeMbox = NodeMbox::new(
+            NODE_STATE.rpdos(),
+            &SDO_BUFFER,
+            &RPDO_QUEUE,
+            &SDO_QUEUE,
+            &NMT_QUEUE,
+        )#sdo_write_buffer #tx_queue;
     });
 
     tokens
 }
 
+/// Get the size of the buffer used to stage each value restored via the settings backup object
+///
+/// This is the size of the largest persisted sub object, and at least 4 bytes, for the persisted
+/// communication objects, e.g. the PDO COB-IDs.
+pub(crate) fn settings_backup_value_size(dev: &DeviceConfig) -> usize {
+    dev.objects
+        .iter()
+        .filter_map(|obj| match &obj.object {
+            Object::Var(def) if def.persist => Some(def.data_type.size()),
+            Object::Array(def) if def.persist => Some(def.data_type.size()),
+            Object::Record(def) => def
+                .subs
+                .iter()
+                .filter(|sub| sub.persist)
+                .map(|sub| sub.data_type.size())
+                .max(),
+            Object::Scaled(def) if def.persist => Some(def.data_type.size()),
+            _ => None,
+        })
+        .fold(4, usize::max)
+}
+
+/// Get the static which implements an object of one of the node's optional subsystems
+///
+/// Returns None if no subsystem enabled in the config implements `index`, so that an object
+/// defined by the application at a reserved index is not wired to a static which was never
+/// generated.
+pub(crate) fn subsystem_object_ident(dev: &DeviceConfig, index: u16) -> Option<syn::Ident> {
+    let name = match index {
+        0x1003 if dev.error_history > 0 => "ERROR_HISTORY_OBJECT",
+        0x5003 if dev.access_trace.depth > 0 => "ACCESS_TRACE",
+        0x5004 if dev.debug_log.size > 0 => "DEBUG_LOG",
+        0x5005 if dev.sdo_status => "SDO_STATUS_OBJECT",
+        0x5006 if dev.memory.var_pool > 0 => "VAR_POOL_STATUS_OBJECT",
+        0x5008 if dev.event_log.depth > 0 => "EVENT_LOG",
+        0x5009 if dev.loopback_test => "LOOPBACK_TEST",
+        0x500A if dev.pdos.statistics => "TPDO_COUNT_OBJECT",
+        0x500B if dev.pdos.statistics => "TPDO_TIME_OBJECT",
+        0x500C if dev.pdos.statistics => "RPDO_COUNT_OBJECT",
+        0x500D if dev.pdos.statistics => "RPDO_TIME_OBJECT",
+        0x500E if dev.nmt_protection.unlock && !dev.nmt_protection.commands.is_empty() => {
+            "NMT_PROTECTION"
+        }
+        0x5F10 if dev.settings_backup => "SETTINGS_BACKUP_OBJECT",
+        _ => return None,
+    };
+    Some(format_ident!("{}", name))
+}
+
+/// Get the SubInfo of a pooled object
+///
+/// Pooled objects are validated by the device config to be string vars with no PDO mapping.
+fn pooled_sub_info_tokens(obj: &ObjectDefinition) -> TokenStream {
+    let Object::Var(def) = &obj.object else {
+        unreachable!("Pooled objects must be vars");
+    };
+    let size = def.data_type.size();
+    let data_type = data_type_to_tokens(def.data_type);
+    let access_type = access_type_to_tokens(def.access_type.0);
+    let persist = def.persist;
+    quote! {
+        SubInfo {
+            size: #size,
+            data_type: #data_type,
+            access_type: #access_type,
+            pdo_mapping: zencan_node::common::objects::PdoMapping::None,
+            persist: #persist,
+        }
+    }
+}
+
+/// Generate the var pool holding the values of pooled objects, and its status object
+fn generate_var_pool(dev: &DeviceConfig) -> Result<TokenStream, CompileError> {
+    let size = dev.memory.var_pool;
+    if size == 0 {
+        return Ok(quote! {});
+    }
+    let mut init = Vec::new();
+    let mut lens = Vec::new();
+    for obj in dev.pooled_objects() {
+        let Object::Var(def) = &obj.object else {
+            unreachable!("Pooled objects must be vars");
+        };
+        let value = match &def.default_value {
+            None => &[][..],
+            Some(DefaultValue::String(s)) => s.as_bytes(),
+            Some(other) => {
+                return Err(CompileError::DefaultValueTypeMismatch {
+                    message: format!(
+                        "Default value {:?} is not a string for type {:?}",
+                        other, def.data_type
+                    ),
+                })
+            }
+        };
+        if value.len() > def.data_type.size() {
+            return Err(CompileError::DefaultValueTooLong {
+                message: format!(
+                    "Default value of object 0x{:04X} is too long for type {:?}",
+                    obj.index, def.data_type
+                ),
+            });
+        }
+        lens.push(value.len());
+        init.extend_from_slice(value);
+    }
+    let count = lens.len();
+    Ok(quote! {
+        pub static VAR_POOL: zencan_node::var_pool::VarPool<#size, #count> =
+            zencan_node::var_pool::VarPool::new(&[#(#init),*], [#(#lens),*]);
+        pub static VAR_POOL_STATUS_OBJECT: zencan_node::var_pool::VarPoolStatusObject =
+            zencan_node::var_pool::VarPoolStatusObject::new(&VAR_POOL);
+    })
+}
+
+/// Get the reason an object cannot be generated, if it uses a feature which is not supported yet
+fn unsupported_reason(obj: &ObjectDefinition) -> Option<String> {
+    if obj.application_callback {
+        // Callback objects are implemented by the application, so only their object code is used
+        return None;
+    }
+    let data_types: Vec<DCDataType> = match &obj.object {
+        Object::Var(def) => vec![def.data_type],
+        Object::Array(def) => vec![def.data_type],
+        Object::Record(def) => def.subs.iter().map(|sub| sub.data_type).collect(),
+        Object::Scaled(def) => vec![def.data_type],
+        Object::Stub(_) => Vec::new(),
+    };
+    data_types
+        .into_iter()
+        .find(|dt| matches!(dt, DCDataType::TimeOfDay | DCDataType::TimeDifference))
+        .map(|dt| format!("data type {:?} is not supported", dt))
+}
+
+/// Replace the objects in a device config which cannot be generated with stub objects
+///
+/// Objects which use features that are not supported yet, e.g. the TIME_OF_DAY data type, would
+/// otherwise fail code generation. Each one is replaced by an [`Object::Stub`], which is generated
+/// as a read-only var returning a `ResourceNotAvailable` abort when read, so that the rest of the
+/// node can be built while support for it is added.
+///
+/// Returns the stubbed objects.
+pub fn stub_unsupported_objects(dev: &mut DeviceConfig) -> Vec<(u16, String)> {
+    let mut stubbed = Vec::new();
+    for obj in &mut dev.objects {
+        let Some(reason) = unsupported_reason(obj) else {
+            continue;
+        };
+        let data_type = match &obj.object {
+            Object::Var(def) => stub_data_type(def.data_type),
+            _ => DataType::Domain,
+        };
+        stubbed.push((obj.index, reason.clone()));
+        obj.on_write_callback = false;
+        obj.object = Object::Stub(StubDefinition { data_type, reason });
+    }
+    stubbed
+}
+
+/// Get the CANopen data type reported by a stub for an unsupported var
+fn stub_data_type(dt: DCDataType) -> DataType {
+    match dt {
+        DCDataType::TimeOfDay => DataType::TimeOfDay,
+        DCDataType::TimeDifference => DataType::TimeDifference,
+        _ => DataType::Domain,
+    }
+}
+
 /// Generate code for a node from a [`DeviceConfig`] as a TokenStream
 pub fn device_config_to_tokens(dev: &DeviceConfig) -> Result<TokenStream, CompileError> {
     let mut object_defs = TokenStream::new();
@@ -638,10 +1311,23 @@ pub fn device_config_to_tokens(dev: &DeviceConfig) -> Result<TokenStream, Compil
     let mut sorted_objects: Vec<&ObjectDefinition> = dev.objects.iter().collect();
     sorted_objects.sort_by_key(|o| o.index);
 
+    if let Some((index, message)) = sorted_objects
+        .iter()
+        .find_map(|obj| unsupported_reason(obj).map(|message| (obj.index, message)))
+    {
+        return Err(CompileError::UnsupportedObject { index, message });
+    }
+
+    let pooled = dev.pooled_objects();
+
     for obj in &sorted_objects {
         let struct_name = format_ident!("Object{:X}", obj.index);
         let inst_name = format_ident!("OBJECT{:X}", obj.index);
         let index: syn::Lit = syn::parse_str(&format!("0x{:X}", obj.index)).unwrap();
+        let link_section = match obj.link_section.as_ref().or(dev.link_section.as_ref()) {
+            Some(section) => quote!(#[link_section = #section]),
+            None => quote!(),
+        };
         if obj.index == 0x1010 {
             table_entries.extend(quote! {
                 ODEntry {
@@ -649,6 +1335,27 @@ pub fn device_config_to_tokens(dev: &DeviceConfig) -> Result<TokenStream, Compil
                     data: &STORAGE_COMMAND_OBJECT,
                 },
             });
+        } else if obj.index == 0x1014 {
+            table_entries.extend(quote! {
+                ODEntry {
+                    index: #index,
+                    data: &EMCY_COB_ID_OBJECT,
+                },
+            });
+        } else if obj.index == 0x1200 {
+            table_entries.extend(quote! {
+                ODEntry {
+                    index: #index,
+                    data: &SDO_SERVER_OBJECT,
+                },
+            });
+        } else if let Some(object_ident) = subsystem_object_ident(dev, obj.index) {
+            table_entries.extend(quote! {
+                ODEntry {
+                    index: #index,
+                    data: &#object_ident,
+                },
+            });
         } else if obj.index == 0x5500 {
             // bootloader info object as usize
             table_entries.extend(quote! {
@@ -698,10 +1405,37 @@ pub fn device_config_to_tokens(dev: &DeviceConfig) -> Result<TokenStream, Compil
                     data: &TPDO_MAPPING_OBJECTS[#n]
                 },
             })
+        } else if let Some(slot) = pooled.iter().position(|o| o.index == obj.index) {
+            let info = pooled_sub_info_tokens(obj);
+            object_instantiations.extend(quote! {
+                #link_section
+                pub static #inst_name: zencan_node::var_pool::PoolObject =
+                    zencan_node::var_pool::PoolObject::new(&VAR_POOL, #slot, #info);
+            });
+            table_entries.extend(quote! {
+                ODEntry {
+                    index: #index,
+                    data: &#inst_name,
+                },
+            });
+        } else if let Object::Stub(def) = &obj.object {
+            let data_type = canopen_data_type_to_tokens(def.data_type);
+            object_instantiations.extend(quote! {
+                #link_section
+                pub static #inst_name: StubObject = StubObject::new(#data_type);
+            });
+            table_entries.extend(quote! {
+                ODEntry {
+                    index: #index,
+                    data: &#inst_name,
+                },
+            });
         } else if !obj.application_callback {
-            object_defs.extend(generate_object_code(obj, &struct_name)?);
+            let atomic = obj.atomic_storage.unwrap_or(dev.atomic_storage);
+            object_defs.extend(generate_object_code(obj, &struct_name, atomic)?);
             object_instantiations.extend(quote! {
-                pub static #inst_name: #struct_name = #struct_name::default();
+                #link_section
+                pub static #inst_name: #struct_name = #struct_name::new();
             });
             table_entries.extend(quote! {
                 ODEntry {
@@ -712,7 +1446,8 @@ pub fn device_config_to_tokens(dev: &DeviceConfig) -> Result<TokenStream, Compil
         } else {
             let object_code = object_code_to_tokens(obj.object_code());
             object_instantiations.extend(quote! {
-                pub static #inst_name: CallbackObject = CallbackObject::new(&OD_TABLE, #object_code);
+                #link_section
+                pub static #inst_name: CallbackObject = CallbackObject::new(#object_code);
             });
             table_entries.extend(quote! {
                 ODEntry {
@@ -724,6 +1459,19 @@ pub fn device_config_to_tokens(dev: &DeviceConfig) -> Result<TokenStream, Compil
     }
 
     object_instantiations.extend(generate_state_inst(dev));
+    object_instantiations.extend(generate_var_pool(dev)?);
+    if dev.software_version_size.is_some() {
+        object_instantiations.extend(quote! {
+            /// Set the software version reported in object 0x100A
+            ///
+            /// Returns an error if the version is longer than the `software_version_size` set in
+            /// the device config.
+            #[allow(dead_code)]
+            pub fn set_software_version(version: &str) -> Result<(), AbortCode> {
+                OBJECT100A.set_value_str(version.as_bytes())
+            }
+        });
+    }
 
     let table_len = dev.objects.len();
     Ok(quote! {
@@ -750,13 +1498,20 @@ pub fn device_config_to_tokens(dev: &DeviceConfig) -> Result<TokenStream, Compil
             SubObjectAccess,
             ObjectFlagAccess,
             ScalarField,
+            AtomicScalarField,
+            ScaledField,
             ByteField,
             ConstField,
             NullTermByteField,
+            StubObject,
+            WriteHook,
+            WriteHookFn,
         };
         #[allow(unused_imports)]
         use zencan_node::SDO_BUFFER_SIZE;
         #[allow(unused_imports)]
+        use zencan_node::common::messages::CanMessage;
+        #[allow(unused_imports)]
         use zencan_node::pdo::{PdoCommObject, PdoMappingObject};
         #[allow(unused_imports)]
         use zencan_node::storage::StorageCommandObject;
@@ -778,15 +1533,18 @@ pub fn device_config_to_tokens(dev: &DeviceConfig) -> Result<TokenStream, Compil
 /// * `dev` - The device config
 /// * `format` - If true, generated code will be formatted with `prettyplease`
 pub fn device_config_to_string(dev: &DeviceConfig, format: bool) -> Result<String, CompileError> {
-    let tokens = device_config_to_tokens(dev)?;
+    Ok(tokens_to_string(device_config_to_tokens(dev)?, format))
+}
 
+/// Convert generated tokens to a string, optionally formatting them with `prettyplease`
+pub(crate) fn tokens_to_string(tokens: TokenStream, format: bool) -> String {
     if format {
         let parsed_file = match syn::parse_file(&tokens.to_string()) {
             Ok(f) => f,
             Err(e) => panic!("Error parsing generated code: {}", e),
         };
-        Ok(prettyplease::unparse(&parsed_file))
+        prettyplease::unparse(&parsed_file)
     } else {
-        Ok(tokens.to_string())
+        tokens.to_string()
     }
 }
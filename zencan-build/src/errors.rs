@@ -16,6 +16,11 @@ pub enum CompileError {
     /// Default value does not match the object type
     #[snafu(display("DefaultValueTypeMismatch: {message}"))]
     DefaultValueTypeMismatch { message: String },
+    /// An object uses a feature which code generation does not support
+    #[snafu(display(
+        "UnsupportedObject: object 0x{index:04X}: {message}. Permissive generation can be used to generate a stub instead"
+    ))]
+    UnsupportedObject { index: u16, message: String },
     /// Missing cargo env vars
     #[snafu(display("NotRunViaCargo: Missing expected cargo env variables"))]
     NotRunViaCargo,
@@ -27,4 +32,7 @@ pub enum CompileError {
     DeviceConfig {
         source: zencan_common::device_config::LoadError,
     },
+    /// An error occurred while loading an EDS file
+    #[snafu(display("Error loading EDS: {source}"))]
+    Eds { source: zencan_eds::LoadError },
 }
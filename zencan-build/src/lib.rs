@@ -38,16 +38,43 @@
 //! }
 //! ```
 //!
+//! ### EDS files
+//!
+//! A node can also be generated from an EDS file, using [`build_node_from_eds()`] in place of
+//! [`build_node_from_device_config()`]. The EDS is converted to a [`DeviceConfig`], so the
+//! generated code is the same as for a device config file. Objects in the communication profile
+//! area (0x1000-0x1FFF) are implemented by the node itself, so they are not taken from the EDS.
+//!
+//! ### Permissive generation
+//!
+//! Objects which use features that zencan does not support yet, e.g. the TIME_OF_DAY data type or
+//! data types which cannot be represented in a device config, normally fail the build. When
+//! importing an existing dictionary, [`build_node_from_device_config_permissive()`] and
+//! [`build_node_from_eds_permissive()`] can be used instead. They replace each unsupported object
+//! with a stub, and report it as a build warning. A stub is a read-only var in the object
+//! dictionary, which returns a `ResourceNotAvailable` abort when read, so that the rest of the node
+//! can be used while support for the stubbed objects is filled in.
+//!
 //! ## The generated code
 //!
 //! The generated code looks something like this:
 //!
 //! ```ignore
-//! pub static OBJECT1000: Object1000 = Object1000::default();
-//! pub static OBJECT1001: Object1001 = Object1001::default();
-//! pub static OBJECT1008: Object1008 = Object1008::default();
-//! pub static NODE_STATE: NodeState<4usize, 4usize> = NodeState::new();
-//! pub static NODE_MBOX: NodeMbox = NodeMbox::new(NODE_STATE.rpdos());
+//! pub static OBJECT1000: Object1000 = Object1000::new();
+//! pub static OBJECT1001: Object1001 = Object1001::new();
+//! pub static OBJECT1008: Object1008 = Object1008::new();
+//! static SDO_BUFFER: BufferCell<[u8; SDO_BUFFER_SIZE]> = BufferCell::new([0; SDO_BUFFER_SIZE]);
+//! static RPDO_QUEUE: BufferCell<[Option<CanMessage>; 4usize]> = BufferCell::new([None; 4usize]);
+//! static SDO_QUEUE: BufferCell<[Option<CanMessage>; 1usize]> = BufferCell::new([None; 1usize]);
+//! static NMT_QUEUE: BufferCell<[Option<CanMessage>; 2usize]> = BufferCell::new([None; 2usize]);
+//! pub static NODE_STATE: NodeState<4usize, 4usize, 0usize> = NodeState::new();
+//! pub static NODE_MBOX: NodeMbox = NodeMbox::new(
+//!     NODE_STATE.rpdos(),
+//!     &SDO_BUFFER,
+//!     &RPDO_QUEUE,
+//!     &SDO_QUEUE,
+//!     &NMT_QUEUE,
+//! );
 //! pub static OD_TABLE: [ODEntry; 31usize] = [
 //!     ODEntry {
 //!         index: 0x1000,
@@ -65,10 +92,37 @@
 //! ```
 //!
 //! For each object defined in the object dictionary, a type is created -- e.g. `Object1000` for
-//! object 0x1000 -- as well as an instance. All objects are put into a 'static table, called
+//! object 0x1000 -- as well as an instance. The types implement `const fn new()` and `Default`,
+//! which create an object with the configured default values, so additional instances can be
+//! created, e.g. for tests. Event flags of additional instances are synchronized with the node's
+//! PDOs, the same as the generated instance. All objects are put into a 'static table, called
 //! OD_TABLE. Additionally, a NODE_STATE and a NODE_MBOX are created, and these must be provided
 //! when instantiating node.
 //!
+//! ## Client bindings
+//!
+//! With the `client-bindings` feature, [`build_client_bindings_from_device_config()`] generates a
+//! companion module for host applications which talk to the node using `zencan-client`. It has a
+//! constant for each object, named after its `parameter_name`, which gives the index, sub index and
+//! type of the object, e.g. `RAW_ANALOG_INPUT: OdRef<u16, 4>` for an array of four `UInt16`s. It is
+//! included with the `include_bindings!` macro from `zencan-client`:
+//!
+//! ```ignore
+//! mod od {
+//!     zencan_client::include_bindings!(EXAMPLE);
+//! }
+//!
+//! let raw = device.get(od::RAW_ANALOG_INPUT.at(0)).await?;
+//! ```
+//!
+//! ## Memory usage
+//!
+//! [`build_node_from_device_config()`] also writes an estimate of the static RAM and flash used by
+//! each object and by each subsystem of the node to `OUT_DIR/zencan_node_{name}_memory.txt`, and
+//! prints it to the build script output, which is shown by `cargo build -vv`. Set the
+//! `ZENCAN_MEMORY_REPORT` environment variable to also show the totals as build warnings. See
+//! [`memory_report`] for details.
+//!
 //!
 #![warn(
     missing_docs,
@@ -80,12 +134,20 @@ use std::path::Path;
 
 use snafu::ResultExt;
 
+#[cfg(feature = "client-bindings")]
+mod client_bindings;
 mod codegen;
 pub mod errors;
+pub mod memory_report;
 
+#[cfg(feature = "client-bindings")]
+pub use client_bindings::{device_config_to_client_string, device_config_to_client_tokens};
 pub use codegen::device_config_to_string;
 pub use codegen::device_config_to_tokens;
-use zencan_common::device_config::DeviceConfig;
+pub use codegen::stub_unsupported_objects;
+pub use memory_report::MemoryReport;
+use zencan_common::device_config::{DeviceConfig, Object};
+use zencan_eds::ElectronicDataSheet;
 
 use errors::*;
 
@@ -100,9 +162,37 @@ pub fn compile_device_config(
     out_path: impl AsRef<Path>,
 ) -> Result<(), CompileError> {
     let config = DeviceConfig::load(config_path.as_ref()).context(DeviceConfigSnafu)?;
+    write_generated_code(&config, out_path)
+}
+
+/// Compile an EDS file into rust code
+///
+/// The EDS is converted to a [`DeviceConfig`], and the same code is generated as for a device
+/// config file. See [`ElectronicDataSheet::to_device_config`] for how the EDS is interpreted.
+///
+/// # Arguments
+///
+/// * `eds_path` - Path to the EDS file
+/// * `out_path` - Path to write the generated code to
+pub fn compile_eds(
+    eds_path: impl AsRef<Path>,
+    out_path: impl AsRef<Path>,
+) -> Result<(), CompileError> {
+    let config = load_eds(eds_path)?;
+    write_generated_code(&config, out_path)
+}
 
-    let code = device_config_to_string(&config, true)?.to_string();
+fn load_eds(eds_path: impl AsRef<Path>) -> Result<DeviceConfig, CompileError> {
+    ElectronicDataSheet::load(eds_path)
+        .and_then(|eds| eds.to_device_config())
+        .context(EdsSnafu)
+}
 
+fn write_generated_code(
+    config: &DeviceConfig,
+    out_path: impl AsRef<Path>,
+) -> Result<(), CompileError> {
+    let code = device_config_to_string(config, true)?;
     std::fs::write(out_path.as_ref(), code.as_bytes()).context(IoSnafu)?;
     Ok(())
 }
@@ -129,15 +219,138 @@ pub fn build_node_from_device_config(
     name: &str,
     config_path: impl AsRef<Path>,
 ) -> Result<(), CompileError> {
-    let output_file_path =
-        Path::new(&std::env::var_os("OUT_DIR").ok_or(NotRunViaCargoSnafu.build())?)
-            .join(format!("zencan_node_{}.rs", name));
+    let config = DeviceConfig::load(config_path.as_ref()).context(DeviceConfigSnafu)?;
+    build_node(name, &config)
+}
+
+/// Generate a node from an EDS file for inclusion via `include_modules!` macro
+///
+/// This is the same as [`build_node_from_device_config()`], except that the node is defined by an
+/// EDS file. See [`compile_eds()`].
+pub fn build_node_from_eds(name: &str, eds_path: impl AsRef<Path>) -> Result<(), CompileError> {
+    let config = load_eds(eds_path)?;
+    build_node(name, &config)
+}
+
+/// Generate a node for inclusion via `include_modules!` macro, replacing unsupported objects with
+/// stubs
+///
+/// This is the same as [`build_node_from_device_config()`], except that objects which cannot be
+/// generated are replaced by stubs, and reported as build warnings, instead of failing the build.
+/// See [`stub_unsupported_objects()`].
+pub fn build_node_from_device_config_permissive(
+    name: &str,
+    config_path: impl AsRef<Path>,
+) -> Result<(), CompileError> {
+    let config = DeviceConfig::load(config_path.as_ref()).context(DeviceConfigSnafu)?;
+    build_node_permissive(name, config)
+}
+
+/// Generate a node from an EDS file for inclusion via `include_modules!` macro, replacing
+/// unsupported objects with stubs
+///
+/// This is the same as [`build_node_from_eds()`], except that objects which cannot be converted or
+/// generated are replaced by stubs, and reported as build warnings, instead of failing the build.
+/// See [`ElectronicDataSheet::to_device_config_permissive`].
+pub fn build_node_from_eds_permissive(
+    name: &str,
+    eds_path: impl AsRef<Path>,
+) -> Result<(), CompileError> {
+    let config = ElectronicDataSheet::load(eds_path)
+        .and_then(|eds| eds.to_device_config_permissive())
+        .context(EdsSnafu)?;
+    build_node_permissive(name, config)
+}
 
-    compile_device_config(&config_path, &output_file_path)?;
+fn build_node_permissive(name: &str, mut config: DeviceConfig) -> Result<(), CompileError> {
+    stub_unsupported_objects(&mut config);
+    for obj in &config.objects {
+        if let Object::Stub(def) = &obj.object {
+            println!(
+                "cargo:warning=zencan node {}: object 0x{:04X} is a stub: {}",
+                name, obj.index, def.reason
+            );
+        }
+    }
+    build_node(name, &config)
+}
+
+fn build_node(name: &str, config: &DeviceConfig) -> Result<(), CompileError> {
+    let out_dir =
+        Path::new(&std::env::var_os("OUT_DIR").ok_or(NotRunViaCargoSnafu.build())?).to_path_buf();
+    let output_file_path = out_dir.join(format!("zencan_node_{}.rs", name));
+
+    write_generated_code(config, &output_file_path)?;
 
     let env_var = format!("ZENCAN_INCLUDE_GENERATED_{}", name);
     println!("cargo:rustc-env={}={}", env_var, output_file_path.display());
 
+    write_memory_report(name, config, &out_dir)?;
+
+    Ok(())
+}
+
+/// Generate client bindings for a device for inclusion via the `include_bindings!` macro from
+/// `zencan-client`
+///
+/// This is intended to be run in the build.rs of a host application. As with
+/// [`build_node_from_device_config()`], the name is used to reference the generated file from the
+/// macro.
+///
+/// # Example
+///
+/// ```ignore
+/// if let Err(e) =
+///     zencan_build::build_client_bindings_from_device_config("EXAMPLE", "example_device_config.toml")
+/// {
+///     eprintln!("Error generating bindings from example_device_config.toml: {}", e);
+///     std::process::exit(1);
+/// }
+/// ```
+#[cfg(feature = "client-bindings")]
+pub fn build_client_bindings_from_device_config(
+    name: &str,
+    config_path: impl AsRef<Path>,
+) -> Result<(), CompileError> {
+    let config = DeviceConfig::load(config_path.as_ref()).context(DeviceConfigSnafu)?;
+    let out_dir =
+        Path::new(&std::env::var_os("OUT_DIR").ok_or(NotRunViaCargoSnafu.build())?).to_path_buf();
+    let output_file_path = out_dir.join(format!("zencan_client_{}.rs", name));
+
+    let code = device_config_to_client_string(&config, true)?;
+    std::fs::write(&output_file_path, code.as_bytes()).context(IoSnafu)?;
+
+    let env_var = format!("ZENCAN_INCLUDE_CLIENT_{}", name);
+    println!("cargo:rustc-env={}={}", env_var, output_file_path.display());
+    Ok(())
+}
+
+/// Write the memory report for a node to OUT_DIR, and print it to the build script output
+fn write_memory_report(
+    name: &str,
+    config: &DeviceConfig,
+    out_dir: &Path,
+) -> Result<(), CompileError> {
+    let pointer_size = std::env::var("CARGO_CFG_TARGET_POINTER_WIDTH")
+        .ok()
+        .and_then(|bits| bits.parse::<usize>().ok())
+        .map(|bits| bits / 8)
+        .unwrap_or(4);
+    let report = MemoryReport::new(config, pointer_size);
+
+    let report_path = out_dir.join(format!("zencan_node_{}_memory.txt", name));
+    std::fs::write(&report_path, report.to_string()).context(IoSnafu)?;
+
+    println!("{report}");
+    if std::env::var_os("ZENCAN_MEMORY_REPORT").is_some() {
+        println!(
+            "cargo:warning=zencan node {}: estimated {} bytes RAM, {} bytes flash (see {})",
+            name,
+            report.total_ram(),
+            report.total_flash(),
+            report_path.display()
+        );
+    }
     Ok(())
 }
 
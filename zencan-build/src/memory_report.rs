@@ -0,0 +1,583 @@
+//! Estimates of the memory used by a generated node
+//!
+//! MCU targets often have little RAM, and every object in the object dictionary is stored in a
+//! static. A [`MemoryReport`] lists the estimated bytes of static RAM and of const data (flash)
+//! used by each object, and by each of the node's subsystems, such as the SDO buffer and message
+//! queues, so that the largest users can be found before the firmware is flashed.
+//!
+//! The sizes are estimates computed from the device config. They include the stored object values,
+//! TPDO event flags, and the pointers held by generated statics, but not padding, or the code and
+//! sub object metadata which are compiled into functions. Object values with a non-zero default
+//! are counted in both RAM and flash, because their initial values are copied from flash at
+//! startup.
+//!
+//! [`build_node_from_device_config`](crate::build_node_from_device_config) writes the report for
+//! each generated node to `OUT_DIR`, and it can also be created directly:
+//!
+//! ```
+//! use zencan_build::MemoryReport;
+//! use zencan_common::device_config::DeviceConfig;
+//!
+//! let config = DeviceConfig::load_from_str(r#"
+//!     device_name = "example"
+//!     [identity]
+//!     vendor_id = 1
+//!     product_code = 2
+//!     revision_number = 3
+//! "#).unwrap();
+//! let report = MemoryReport::new(&config, 4);
+//! println!("{report}");
+//! ```
+use std::fmt;
+
+use crate::codegen::subsystem_object_ident;
+
+use zencan_common::access_trace::ENTRY_SIZE as ACCESS_TRACE_ENTRY_SIZE;
+use zencan_common::device_config::{DataType, DefaultValue, DeviceConfig, Object};
+use zencan_common::event_log::ENTRY_SIZE as EVENT_LOG_ENTRY_SIZE;
+use zencan_common::messages::CanMessage;
+
+/// Size of each SDO buffer in generated code
+///
+/// This must match `zencan_node::SDO_BUFFER_SIZE`
+const SDO_BUFFER_SIZE: usize = 889;
+/// Number of mapping parameters stored for each PDO
+const N_MAPPING_PARAMS: usize = 8;
+
+/// The estimated memory used by a single object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectUsage {
+    /// The object index
+    pub index: u16,
+    /// The object's parameter name
+    pub name: String,
+    /// Estimated bytes of static RAM
+    pub ram: usize,
+    /// Estimated bytes of const data
+    pub flash: usize,
+}
+
+/// The estimated memory used by one of the node's subsystems
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubsystemUsage {
+    /// The name of the subsystem
+    pub name: &'static str,
+    /// Estimated bytes of static RAM
+    pub ram: usize,
+    /// Estimated bytes of const data
+    pub flash: usize,
+}
+
+/// A report of the estimated memory used by a generated node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// The pointer size of the target, in bytes
+    pub pointer_size: usize,
+    /// Usage of each object with generated storage, sorted by RAM usage, largest first
+    pub objects: Vec<ObjectUsage>,
+    /// Usage of the subsystems, including the objects which they implement
+    pub subsystems: Vec<SubsystemUsage>,
+}
+
+/// Returns true for objects which are implemented by a subsystem, rather than by generated storage
+fn is_subsystem_object(dev: &DeviceConfig, index: u16) -> bool {
+    subsystem_object_ident(dev, index).is_some()
+        || index == 0x1010
+        || index == 0x1014
+        || index == 0x1200
+        || (0x1400..0x1C00).contains(&index)
+        || (0x5500..=0x551f).contains(&index)
+}
+
+/// Returns true if the value must be stored in flash to initialize the object
+fn is_nonzero(value: Option<&DefaultValue>) -> bool {
+    match value {
+        None => false,
+        Some(DefaultValue::Integer(v)) => *v != 0,
+        Some(DefaultValue::Float(v)) => *v != 0.0,
+        Some(DefaultValue::String(s)) => !s.is_empty(),
+    }
+}
+
+/// Estimated RAM used by the field storing a value of the given type
+fn field_ram(data_type: DataType, ptr: usize) -> usize {
+    match data_type {
+        // CallbackSubObject holds a reference to the handler
+        DataType::Domain => 2 * ptr,
+        // Byte fields also track the offset of a partial write
+        t if t.is_str() => t.size() + 2 * ptr,
+        t => t.size(),
+    }
+}
+
+/// Estimated flash used to initialize a value of the given type
+fn default_flash(data_type: DataType, value: Option<&DefaultValue>) -> usize {
+    if is_nonzero(value) {
+        data_type.size()
+    } else {
+        0
+    }
+}
+
+/// Estimated RAM used by the TPDO event flags of an object
+fn flags_ram(highest_sub_index: usize, ptr: usize) -> usize {
+    // Two flag sets, plus a reference to the shared sync object
+    2 * (highest_sub_index + 1).div_ceil(8) + ptr
+}
+
+fn object_usage(object: &Object, ptr: usize) -> (usize, usize) {
+    match object {
+        Object::Var(def) => {
+            let flags = if def.pdo_mapping.supports_tpdo() {
+                flags_ram(0, ptr)
+            } else {
+                0
+            };
+            (
+                field_ram(def.data_type, ptr) + flags,
+                default_flash(def.data_type, def.default_value.as_ref()),
+            )
+        }
+        Object::Array(def) => {
+            let flags = if def.pdo_mapping.supports_tpdo() {
+                flags_ram(def.array_size, ptr)
+            } else {
+                0
+            };
+            let flash = match &def.default_value {
+                Some(values) => values
+                    .iter()
+                    .map(|v| default_flash(def.data_type, Some(v)))
+                    .sum(),
+                None => 0,
+            };
+            (
+                def.array_size * field_ram(def.data_type, ptr) + flags,
+                flash,
+            )
+        }
+        Object::Record(def) => {
+            let mut ram = 0;
+            let mut flash = 0;
+            // Flags are only stored up to the highest TPDO mappable sub
+            let mut highest_tpdo_sub = None;
+            for sub in &def.subs {
+                ram += field_ram(sub.data_type, ptr);
+                flash += default_flash(sub.data_type, sub.default_value.as_ref());
+                if sub.pdo_mapping.supports_tpdo() {
+                    highest_tpdo_sub = highest_tpdo_sub.max(Some(sub.sub_index as usize));
+                }
+            }
+            if let Some(highest_sub_index) = highest_tpdo_sub {
+                ram += flags_ram(highest_sub_index, ptr);
+            }
+            (ram, flash)
+        }
+        Object::Scaled(def) => {
+            let flags = if def.pdo_mapping.supports_tpdo() {
+                flags_ram(4, ptr)
+            } else {
+                0
+            };
+            // The raw value, and the f32 gain and offset
+            let mut flash = default_flash(def.data_type, def.default_value.as_ref());
+            flash += if def.gain != 0.0 { 4 } else { 0 };
+            flash += if def.offset != 0.0 { 4 } else { 0 };
+            (def.data_type.size() + 8 + flags, flash)
+        }
+        // A StubObject holds only its data type, and is never modified
+        Object::Stub(_) => (0, 4),
+    }
+}
+
+impl MemoryReport {
+    /// Create a memory report for a device config
+    ///
+    /// # Arguments
+    ///
+    /// * `dev` - The device config
+    /// * `pointer_size` - The size of a pointer on the target, in bytes, e.g. 4 for a 32-bit MCU
+    pub fn new(dev: &DeviceConfig, pointer_size: usize) -> Self {
+        let ptr = pointer_size;
+
+        let mut objects: Vec<ObjectUsage> = dev
+            .objects
+            .iter()
+            .filter(|obj| !is_subsystem_object(dev, obj.index))
+            .map(|obj| {
+                let (mut ram, flash) = if obj.application_callback {
+                    // CallbackObject holds an optional reference to the handler, and the object code
+                    (4 * ptr, 0)
+                } else if obj.pooled {
+                    // The value is counted with the var pool
+                    (0, 0)
+                } else {
+                    object_usage(&obj.object, ptr)
+                };
+                if obj.on_write_callback {
+                    // A WriteHook holds an optional reference to the registered function
+                    ram += 2 * ptr;
+                }
+                ObjectUsage {
+                    index: obj.index,
+                    name: obj.parameter_name.clone(),
+                    ram,
+                    flash,
+                }
+            })
+            .collect();
+        objects.sort_by(|a, b| b.ram.cmp(&a.ram).then(a.index.cmp(&b.index)));
+
+        let mut subsystems = Vec::new();
+
+        let n_sdo_buffers = if dev.mbox.sdo_double_buffer { 2 } else { 1 };
+        subsystems.push(SubsystemUsage {
+            name: "SDO buffers",
+            ram: n_sdo_buffers * SDO_BUFFER_SIZE,
+            flash: 0,
+        });
+
+        let queue_depth =
+            dev.rpdo_queue_depth() + dev.mbox.sdo_queue_depth + dev.mbox.nmt_queue_depth;
+        subsystems.push(SubsystemUsage {
+            name: "Message queues",
+            ram: queue_depth * std::mem::size_of::<Option<CanMessage>>(),
+            flash: 0,
+        });
+
+        if dev.mbox.tx_queue_depth > 0 {
+            // Each slot holds the message, along with its priority
+            subsystems.push(SubsystemUsage {
+                name: "Transmit queue",
+                ram: dev.mbox.tx_queue_depth * std::mem::size_of::<Option<(u8, CanMessage)>>(),
+                flash: 0,
+            });
+        }
+
+        // Each PDO stores its mapping parameters, which reference an OD entry, along with its
+        // communication parameters and transmission state. The comm and mapping objects are
+        // const statics holding references to the PDO and the OD table.
+        let n_pdo = dev.pdos.num_rpdo as usize + dev.pdos.num_tpdo as usize;
+        subsystems.push(SubsystemUsage {
+            name: "PDOs",
+            ram: n_pdo * (N_MAPPING_PARAMS * (ptr + 4) + 32),
+            flash: n_pdo * 4 * ptr,
+        });
+
+        if dev.error_history > 0 {
+            // The entries and count held in the node state, and the object's references to them
+            subsystems.push(SubsystemUsage {
+                name: "Error history",
+                ram: dev.error_history as usize * 4 + 1 + 2 * ptr,
+                flash: 0,
+            });
+        }
+
+        if dev.support_storage {
+            subsystems.push(SubsystemUsage {
+                name: "Storage",
+                ram: 0,
+                flash: 3 * ptr,
+            });
+        }
+
+        if !dev.bootloader.sections.is_empty() {
+            // Each section holds its name, size, and a reference to the application callbacks
+            subsystems.push(SubsystemUsage {
+                name: "Bootloader",
+                ram: 8 + dev.bootloader.sections.len() * (5 * ptr + 4),
+                flash: dev.bootloader.sections.iter().map(|s| s.name.len()).sum(),
+            });
+        }
+
+        if dev.access_trace.depth > 0 {
+            // The ring buffer entries, and the buffer reference, position and counters
+            subsystems.push(SubsystemUsage {
+                name: "Access trace",
+                ram: dev.access_trace.depth * ACCESS_TRACE_ENTRY_SIZE + 2 * ptr + 16,
+                flash: 0,
+            });
+        }
+
+        if dev.debug_log.size > 0 {
+            // The text buffer, and the buffer reference, positions, counters and rate limit window
+            subsystems.push(SubsystemUsage {
+                name: "Debug log",
+                ram: dev.debug_log.size + 3 * ptr + 32,
+                flash: 0,
+            });
+        }
+
+        if dev.event_log.depth > 0 {
+            // The ring buffer entries, and the buffer reference, positions and counters
+            subsystems.push(SubsystemUsage {
+                name: "Event log",
+                ram: dev.event_log.depth * EVENT_LOG_ENTRY_SIZE + 3 * ptr + 16,
+                flash: 0,
+            });
+        }
+
+        if dev.loopback_test {
+            // The test state and sequence number, and the frame held by the mailbox
+            subsystems.push(SubsystemUsage {
+                name: "Loopback test",
+                ram: 48 + ptr,
+                flash: 0,
+            });
+        }
+
+        if dev.pdos.statistics {
+            // A count and timestamp for each PDO, plus the references held by the four objects
+            let n_pdo = dev.pdos.num_tpdo as usize + dev.pdos.num_rpdo as usize;
+            subsystems.push(SubsystemUsage {
+                name: "PDO statistics",
+                ram: n_pdo * 8 + 10 * ptr,
+                flash: 0,
+            });
+        }
+
+        if !dev.nmt_protection.commands.is_empty() {
+            // The command and state masks, and the unlock state and its expiry time
+            subsystems.push(SubsystemUsage {
+                name: "NMT protection",
+                ram: 24,
+                flash: 0,
+            });
+        }
+
+        if dev.sdo_status {
+            // Four sub objects, each holding a reference to the node status and a sub index
+            subsystems.push(SubsystemUsage {
+                name: "SDO server status",
+                ram: 4 * (ptr + 1),
+                flash: 0,
+            });
+        }
+
+        if dev.memory.var_pool > 0 {
+            // The pool, the length of each pooled value, and the high-water mark, plus the
+            // initial values in flash
+            let pooled = dev.pooled_objects();
+            let flash = pooled
+                .iter()
+                .filter_map(|obj| match &obj.object {
+                    Object::Var(def) => def.default_value.as_ref(),
+                    _ => None,
+                })
+                .map(|value| match value {
+                    DefaultValue::String(s) => s.len(),
+                    _ => 0,
+                })
+                .sum();
+            subsystems.push(SubsystemUsage {
+                name: "Var pool",
+                ram: dev.memory.var_pool + (pooled.len() + 1) * ptr,
+                flash,
+            });
+        }
+
+        if dev.settings_backup {
+            // The value staging buffer, and the object's references and restore state
+            subsystems.push(SubsystemUsage {
+                name: "Settings backup",
+                ram: crate::codegen::settings_backup_value_size(dev) + 4 * ptr + 24,
+                flash: 0,
+            });
+        }
+
+        // Each entry holds the index, and a reference to the object
+        subsystems.push(SubsystemUsage {
+            name: "Object table",
+            ram: 0,
+            flash: dev.objects.len() * 3 * ptr,
+        });
+
+        Self {
+            pointer_size,
+            objects,
+            subsystems,
+        }
+    }
+
+    /// Get the total estimated bytes of static RAM
+    pub fn total_ram(&self) -> usize {
+        self.objects.iter().map(|o| o.ram).sum::<usize>()
+            + self.subsystems.iter().map(|s| s.ram).sum::<usize>()
+    }
+
+    /// Get the total estimated bytes of const data
+    pub fn total_flash(&self) -> usize {
+        self.objects.iter().map(|o| o.flash).sum::<usize>()
+            + self.subsystems.iter().map(|s| s.flash).sum::<usize>()
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Estimated memory usage ({}-bit target)",
+            self.pointer_size * 8
+        )?;
+        writeln!(f)?;
+        writeln!(f, "{:<8}{:>8}{:>8}  Name", "Index", "RAM", "Flash")?;
+        for obj in &self.objects {
+            writeln!(
+                f,
+                "0x{:04X}  {:>8}{:>8}  {}",
+                obj.index, obj.ram, obj.flash, obj.name
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:<16}{:>8}{:>8}", "Subsystem", "RAM", "Flash")?;
+        for sub in &self.subsystems {
+            writeln!(f, "{:<16}{:>8}{:>8}", sub.name, sub.ram, sub.flash)?;
+        }
+        writeln!(f)?;
+        write!(
+            f,
+            "Total: {} bytes RAM, {} bytes flash",
+            self.total_ram(),
+            self.total_flash()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        device_name = "test"
+        [identity]
+        vendor_id = 1
+        product_code = 2
+        revision_number = 3
+
+        [pdos]
+        num_rpdo = 1
+        num_tpdo = 1
+
+        [[objects]]
+        index = 0x2000
+        parameter_name = "Big String"
+        object_type = "var"
+        data_type = "visiblestring(100)"
+        access_type = "rw"
+
+        [[objects]]
+        index = 0x2001
+        parameter_name = "Array"
+        object_type = "array"
+        data_type = "uint32"
+        access_type = "rw"
+        array_size = 3
+        default_value = [1, 0, 2]
+        pdo_mapping = "tpdo"
+    "#;
+
+    #[test]
+    fn test_var_pool_report() {
+        let config = DeviceConfig::load_from_str(&format!(
+            r#"{CONFIG}
+            [memory]
+            var_pool = 64
+
+            [[objects]]
+            index = 0x2002
+            parameter_name = "Pooled String"
+            pooled = true
+            object_type = "var"
+            data_type = "visiblestring(100)"
+            access_type = "rw"
+            default_value = "abc"
+            "#
+        ))
+        .unwrap();
+        let report = MemoryReport::new(&config, 4);
+
+        let pooled = report.objects.iter().find(|o| o.index == 0x2002).unwrap();
+        assert_eq!(0, pooled.ram);
+        let pool = report
+            .subsystems
+            .iter()
+            .find(|s| s.name == "Var pool")
+            .unwrap();
+        assert_eq!(64 + 2 * 4, pool.ram);
+        assert_eq!(3, pool.flash);
+    }
+
+    #[test]
+    fn test_reserved_index_without_subsystem() {
+        // When the subsystem is not enabled, its index is free for an application object
+        for index in [
+            0x1003, 0x5003, 0x5004, 0x5005, 0x5006, 0x5008, 0x5009, 0x500A, 0x500D, 0x500E, 0x5F10,
+        ] {
+            let config = DeviceConfig::load_from_str(&format!(
+                r#"{CONFIG}
+                [[objects]]
+                index = {index}
+                parameter_name = "Application Object"
+                object_type = "var"
+                data_type = "uint32"
+                access_type = "rw"
+                "#
+            ))
+            .unwrap();
+            let report = MemoryReport::new(&config, 4);
+            assert!(report.objects.iter().any(|o| o.index == index));
+            let code = crate::codegen::device_config_to_string(&config, false).unwrap();
+            assert!(code.contains(&format!("OBJECT{index:X}")));
+        }
+    }
+
+    #[test]
+    fn test_error_history_report() {
+        let config = DeviceConfig::load_from_str(CONFIG).unwrap();
+        let report = MemoryReport::new(&config, 4);
+        assert!(!report.subsystems.iter().any(|s| s.name == "Error history"));
+
+        let config = DeviceConfig::load_from_str(&format!("error_history = 8\n{CONFIG}")).unwrap();
+        let report = MemoryReport::new(&config, 4);
+        // The object is counted with the subsystem
+        assert!(!report.objects.iter().any(|o| o.index == 0x1003));
+        let history = report
+            .subsystems
+            .iter()
+            .find(|s| s.name == "Error history")
+            .unwrap();
+        assert_eq!(8 * 4 + 1 + 2 * 4, history.ram);
+    }
+
+    #[test]
+    fn test_memory_report() {
+        let config = DeviceConfig::load_from_str(CONFIG).unwrap();
+        let report = MemoryReport::new(&config, 4);
+
+        // The largest object is listed first
+        let first = &report.objects[0];
+        assert_eq!(0x2000, first.index);
+        assert_eq!(100 + 8, first.ram);
+        assert_eq!(0, first.flash);
+
+        let array = report.objects.iter().find(|o| o.index == 0x2001).unwrap();
+        // Three u32 values, plus two flag bytes and a pointer
+        assert_eq!(12 + 2 + 4, array.ram);
+        // Only the non-zero defaults are stored in flash
+        assert_eq!(8, array.flash);
+
+        // PDO objects are counted with the PDO subsystem
+        assert!(!report.objects.iter().any(|o| o.index == 0x1400));
+        let sdo = &report.subsystems[0];
+        assert_eq!(SDO_BUFFER_SIZE, sdo.ram);
+
+        let text = report.to_string();
+        assert!(text.starts_with("Estimated memory usage (32-bit target)"));
+        assert!(text.contains("0x2000       108       0  Big String"));
+        assert!(text.ends_with(&format!(
+            "Total: {} bytes RAM, {} bytes flash",
+            report.total_ram(),
+            report.total_flash()
+        )));
+    }
+}
@@ -9,3 +9,127 @@ fn compile_test() {
     let _compiled =
         zencan_build::device_config_to_string(&config, false).expect("Failed to compile");
 }
+
+#[test]
+fn link_section_test() {
+    const CONFIG: &str = r#"
+        device_name = "test"
+        link_section = ".ccmram"
+        [identity]
+        vendor_id = 1
+        product_code = 2
+        revision_number = 3
+
+        [[objects]]
+        index = 0x2000
+        parameter_name = "Retained"
+        link_section = ".backup_sram"
+        object_type = "var"
+        data_type = "uint32"
+        access_type = "rw"
+    "#;
+
+    let config = DeviceConfig::load_from_str(CONFIG).expect("Failed to parse config");
+    let compiled = zencan_build::device_config_to_string(&config, true).expect("Failed to compile");
+
+    assert!(
+        compiled.contains("#[link_section = \".backup_sram\"]\npub static OBJECT2000: Object2000")
+    );
+    assert!(compiled.contains("#[link_section = \".ccmram\"]\npub static OBJECT1000: Object1000"));
+}
+
+#[test]
+fn permissive_test() {
+    const CONFIG: &str = r#"
+        device_name = "test"
+        [identity]
+        vendor_id = 1
+        product_code = 2
+        revision_number = 3
+
+        [[objects]]
+        index = 0x2000
+        parameter_name = "Timestamp"
+        object_type = "var"
+        data_type = "timeofday"
+        access_type = "rw"
+    "#;
+
+    let mut config = DeviceConfig::load_from_str(CONFIG).expect("Failed to parse config");
+    assert!(matches!(
+        zencan_build::device_config_to_string(&config, true),
+        Err(zencan_build::errors::CompileError::UnsupportedObject { index: 0x2000, .. })
+    ));
+
+    let stubbed = zencan_build::stub_unsupported_objects(&mut config);
+    assert_eq!(1, stubbed.len());
+    assert_eq!(0x2000, stubbed[0].0);
+    let compiled = zencan_build::device_config_to_string(&config, true).expect("Failed to compile");
+    assert!(compiled.contains("pub static OBJECT2000: StubObject = StubObject::new("));
+    assert!(compiled.contains("DataType::TimeOfDay"));
+}
+
+#[test]
+fn var_pool_test() {
+    const CONFIG: &str = r#"
+        device_name = "test"
+        [identity]
+        vendor_id = 1
+        product_code = 2
+        revision_number = 3
+
+        [memory]
+        var_pool = 64
+
+        [[objects]]
+        index = 0x2000
+        parameter_name = "Label"
+        object_type = "var"
+        data_type = "visiblestring(32)"
+        access_type = "rw"
+        default_value = "abc"
+        pooled = true
+    "#;
+
+    let config = DeviceConfig::load_from_str(CONFIG).expect("Failed to parse config");
+    let compiled = zencan_build::device_config_to_string(&config, true).expect("Failed to compile");
+    assert!(compiled.contains("pub static VAR_POOL: zencan_node::var_pool::VarPool<64"));
+    assert!(compiled.contains("pub static OBJECT2000: zencan_node::var_pool::PoolObject"));
+    assert!(compiled.contains("pub static VAR_POOL_STATUS_OBJECT"));
+}
+
+#[test]
+fn eds_compile_test() {
+    let eds_path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/example.eds");
+    let out_file = tempfile::NamedTempFile::new().expect("Failed to create tempfile");
+
+    zencan_build::compile_eds(eds_path, out_file.path()).expect("Failed to compile EDS");
+
+    let compiled = std::fs::read_to_string(out_file.path()).unwrap();
+    assert!(compiled.contains("pub static OBJECT1018: Object1018"));
+}
+
+#[test]
+fn no_unsafe_test() {
+    const CONFIG: &str = r#"
+        device_name = "test"
+        [identity]
+        vendor_id = 1
+        product_code = 2
+        revision_number = 3
+
+        [mbox]
+        sdo_double_buffer = true
+
+        [access_trace]
+        depth = 4
+    "#;
+
+    let config = DeviceConfig::load_from_str(CONFIG).expect("Failed to parse config");
+    let compiled = zencan_build::device_config_to_string(&config, true).expect("Failed to compile");
+
+    assert!(compiled.contains("static SDO_WRITE_BUFFER"));
+    assert!(compiled.contains("static ACCESS_TRACE_BUFFER"));
+    assert!(!compiled.contains("unsafe"));
+    assert!(!compiled.contains("static mut"));
+}
@@ -0,0 +1,205 @@
+//! Human readable aliases for node IDs
+//!
+//! `alias 12 left-motor` names node 12, after which `left-motor` can be used in any command which
+//! takes a node ID, e.g. `read left-motor 0x1008 0`, and the name is shown alongside the ID in the
+//! output of commands such as `scan`. Each node has at most one alias, so aliasing a node again
+//! renames it. Aliases are saved in the CLI config file, `~/.zencan-cli.toml`, so they are kept
+//! between sessions:
+//!
+//! ```toml
+//! [aliases]
+//! left-motor = 12
+//! right-motor = 13
+//! ```
+use std::{collections::BTreeMap, path::Path, sync::RwLock};
+
+use crate::config::CliConfig;
+
+/// The aliases in use by the session, consulted when parsing node IDs
+static ALIASES: RwLock<NodeAliases> = RwLock::new(NodeAliases::new());
+
+/// A set of node aliases
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeAliases {
+    names: BTreeMap<u8, String>,
+}
+
+impl NodeAliases {
+    /// Create an empty set of aliases
+    pub const fn new() -> Self {
+        Self {
+            names: BTreeMap::new(),
+        }
+    }
+
+    /// Create a set of aliases from a map of node IDs, by alias, as stored in the config file
+    pub fn from_map(map: &BTreeMap<String, u8>) -> Result<Self, String> {
+        let mut aliases = Self::new();
+        for (name, node_id) in map {
+            aliases.set(*node_id, name)?;
+        }
+        Ok(aliases)
+    }
+
+    /// Get the aliases as a map of node IDs, by alias, as stored in the config file
+    pub fn to_map(&self) -> BTreeMap<String, u8> {
+        self.names
+            .iter()
+            .map(|(id, name)| (name.clone(), *id))
+            .collect()
+    }
+
+    /// Load aliases from a config file
+    ///
+    /// A missing file is treated as an empty config.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Self::from_map(&CliConfig::load(path)?.aliases)
+    }
+
+    /// Save the aliases to a config file, keeping the rest of its contents
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        CliConfig::update(path, |config| {
+            config.aliases = self.to_map();
+            Ok(())
+        })
+    }
+
+    /// Get the alias of a node, if it has one
+    pub fn name(&self, node_id: u8) -> Option<&str> {
+        self.names.get(&node_id).map(String::as_str)
+    }
+
+    /// Get the node ID with an alias
+    pub fn resolve(&self, name: &str) -> Option<u8> {
+        self.names
+            .iter()
+            .find(|(_, n)| n.as_str() == name)
+            .map(|(id, _)| *id)
+    }
+
+    /// Set the alias of a node, replacing any alias it already has
+    ///
+    /// If another node already has the alias, it is moved to this node.
+    pub fn set(&mut self, node_id: u8, name: &str) -> Result<(), String> {
+        if !(1..=127).contains(&node_id) {
+            return Err(format!("Invalid node ID {node_id}"));
+        }
+        let valid = !name.is_empty()
+            && name != "all"
+            && name.parse::<u8>().is_err()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(format!(
+                "Invalid alias '{name}'. An alias is made of letters, digits, '-' and '_', and \
+                 cannot be a number or 'all'"
+            ));
+        }
+        self.names.retain(|_, n| n != name);
+        self.names.insert(node_id, name.to_string());
+        Ok(())
+    }
+
+    /// Remove the alias of a node, returning it
+    pub fn remove(&mut self, node_id: u8) -> Option<String> {
+        self.names.remove(&node_id)
+    }
+
+    /// Iterate over the aliases, as (node ID, alias), in order of node ID
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &str)> {
+        self.names.iter().map(|(id, name)| (*id, name.as_str()))
+    }
+
+    /// Format a node ID, followed by its alias if it has one, e.g. "12 (left-motor)"
+    pub fn label(&self, node_id: u8) -> String {
+        match self.name(node_id) {
+            Some(name) => format!("{node_id} ({name})"),
+            None => node_id.to_string(),
+        }
+    }
+}
+
+/// Get the aliases in use by the session
+pub fn aliases() -> NodeAliases {
+    ALIASES.read().unwrap().clone()
+}
+
+/// Replace the aliases in use by the session
+pub fn set_aliases(aliases: NodeAliases) {
+    *ALIASES.write().unwrap() = aliases;
+}
+
+/// Parse a node ID, given as a number or an alias
+pub fn parse_node_id(s: &str) -> Result<u8, String> {
+    if let Ok(id) = s.parse::<u8>() {
+        return Ok(id);
+    }
+    ALIASES
+        .read()
+        .unwrap()
+        .resolve(s)
+        .ok_or_else(|| format!("'{s}' is not a node ID or a known alias"))
+}
+
+/// Parse a node ID in the range 1..=127, given as a number or an alias
+pub fn parse_configured_node_id(s: &str) -> Result<u8, String> {
+    let id = parse_node_id(s)?;
+    if (1..=127).contains(&id) {
+        Ok(id)
+    } else {
+        Err(format!("{id} is not in 1..=127"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aliases() {
+        let mut aliases = NodeAliases::new();
+        aliases.set(12, "left-motor").unwrap();
+        aliases.set(13, "right_motor").unwrap();
+        assert_eq!(Some(12), aliases.resolve("left-motor"));
+        assert_eq!("13 (right_motor)", aliases.label(13));
+        assert_eq!("14", aliases.label(14));
+
+        // Renaming a node replaces its alias, and reusing an alias moves it
+        aliases.set(12, "left").unwrap();
+        assert_eq!(None, aliases.resolve("left-motor"));
+        aliases.set(14, "left").unwrap();
+        assert_eq!(None, aliases.name(12));
+        assert_eq!(Some(14), aliases.resolve("left"));
+
+        assert!(aliases.set(15, "12").is_err());
+        assert!(aliases.set(15, "all").is_err());
+        assert!(aliases.set(15, "two words").is_err());
+        assert!(aliases.set(128, "high").is_err());
+        assert_eq!(Some("left".to_string()), aliases.remove(14));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path =
+            std::env::temp_dir().join(format!("zencan-cli-alias-{}.toml", std::process::id()));
+        let mut aliases = NodeAliases::new();
+        aliases.set(5, "pump").unwrap();
+        aliases.save(&path).unwrap();
+        assert_eq!(aliases, NodeAliases::load(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(NodeAliases::new(), NodeAliases::load(&path).unwrap());
+    }
+
+    #[test]
+    fn test_parse_node_id() {
+        let mut aliases = NodeAliases::new();
+        aliases.set(7, "valve").unwrap();
+        set_aliases(aliases);
+        assert_eq!(Ok(7), parse_node_id("valve"));
+        assert_eq!(Ok(3), parse_node_id("3"));
+        assert_eq!(Ok(0), parse_node_id("0"));
+        assert!(parse_configured_node_id("0").is_err());
+        assert!(parse_node_id("pump").is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! SDO latency and throughput measurement
+//!
+//! Used by the `bench` command of zencan-cli. Expedited round-trip latency is measured by
+//! repeatedly reading the device type object (0x1000), which every node implements. Throughput is
+//! measured by writing, and then reading back, an object chosen by the user which can hold the
+//! payload, such as a domain or a large string, using each SDO protocol in turn. The contents of
+//! that object are overwritten.
+//!
+//! Slow or widely spread transfer times can point to an overloaded bus, or to a node whose SDO
+//! buffer is too small for the payloads being transferred.
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use zencan_client::{
+    common::traits::{AsyncCanReceiver, AsyncCanSender},
+    SdoClient, SdoClientError,
+};
+
+/// The object read to measure expedited latency
+const DEVICE_TYPE_INDEX: u16 = 0x1000;
+
+/// Summary of the durations of a set of repeated transfers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of samples
+    pub count: usize,
+    /// The median duration
+    pub p50: Duration,
+    /// The 95th percentile duration
+    pub p95: Duration,
+    /// The longest duration
+    pub max: Duration,
+}
+
+impl Stats {
+    /// Summarize a set of samples, or return None if there are none
+    pub fn from_samples(samples: &[Duration]) -> Option<Self> {
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let max = *sorted.last()?;
+        // Nearest-rank percentile
+        let percentile = |p: usize| sorted[(sorted.len() * p).div_ceil(100).max(1) - 1];
+        Some(Self {
+            count: sorted.len(),
+            p50: percentile(50),
+            p95: percentile(95),
+            max,
+        })
+    }
+}
+
+/// The kind of transfer being measured
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BenchMode {
+    /// Expedited read of the device type object
+    Expedited,
+    /// Segmented download. Payloads of 4 bytes or less are sent expedited.
+    SegmentedDownload,
+    /// Block download
+    BlockDownload,
+    /// Segmented upload
+    SegmentedUpload,
+}
+
+impl Display for BenchMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            BenchMode::Expedited => "expedited read",
+            BenchMode::SegmentedDownload => "segmented write",
+            BenchMode::BlockDownload => "block write",
+            BenchMode::SegmentedUpload => "segmented read",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The result of timing a set of repeated transfers
+#[derive(Clone, Debug)]
+pub struct Measurement {
+    /// The kind of transfer
+    pub mode: BenchMode,
+    /// The number of bytes in each transfer
+    pub size: usize,
+    /// Timing of the successful transfers, or None if they all failed
+    pub stats: Option<Stats>,
+    /// The number of failed transfers
+    pub failures: usize,
+    /// The error returned by the last failed transfer
+    pub last_error: Option<SdoClientError>,
+}
+
+impl Measurement {
+    /// Get the throughput in bytes per second, based on the median transfer time
+    pub fn throughput(&self) -> Option<f64> {
+        let secs = self.stats?.p50.as_secs_f64();
+        (secs > 0.0).then(|| self.size as f64 / secs)
+    }
+}
+
+/// Time `count` transfers of `size` bytes to or from `index`/`sub`
+///
+/// `index` and `sub` are ignored for [`BenchMode::Expedited`]. For an upload, the size is the
+/// number of bytes actually read, which depends on the data last written to the object.
+pub async fn measure<S: AsyncCanSender, R: AsyncCanReceiver>(
+    client: &mut SdoClient<S, R>,
+    mode: BenchMode,
+    index: u16,
+    sub: u8,
+    size: usize,
+    count: usize,
+) -> Measurement {
+    let data: Vec<u8> = (0..size).map(|i| i as u8).collect();
+    let mut samples = Vec::with_capacity(count);
+    let mut measurement = Measurement {
+        mode,
+        size,
+        stats: None,
+        failures: 0,
+        last_error: None,
+    };
+    for _ in 0..count {
+        let start = Instant::now();
+        let result = match mode {
+            BenchMode::Expedited => client.upload(DEVICE_TYPE_INDEX, 0).await.map(|d| d.len()),
+            BenchMode::SegmentedDownload => client.download(index, sub, &data).await.map(|()| size),
+            BenchMode::BlockDownload => client
+                .block_download(index, sub, &data)
+                .await
+                .map(|()| size),
+            BenchMode::SegmentedUpload => client.upload(index, sub).await.map(|d| d.len()),
+        };
+        match result {
+            Ok(len) => {
+                samples.push(start.elapsed());
+                measurement.size = len;
+            }
+            Err(e) => {
+                measurement.failures += 1;
+                measurement.last_error = Some(e);
+            }
+        }
+    }
+    measurement.stats = Stats::from_samples(&samples);
+    measurement
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(None, Stats::from_samples(&[]));
+
+        let samples: Vec<_> = (1..=100).rev().map(Duration::from_millis).collect();
+        let stats = Stats::from_samples(&samples).unwrap();
+        assert_eq!(100, stats.count);
+        assert_eq!(Duration::from_millis(50), stats.p50);
+        assert_eq!(Duration::from_millis(95), stats.p95);
+        assert_eq!(Duration::from_millis(100), stats.max);
+
+        let stats = Stats::from_samples(&[Duration::from_millis(3)]).unwrap();
+        assert_eq!(Duration::from_millis(3), stats.p50);
+        assert_eq!(Duration::from_millis(3), stats.p95);
+    }
+
+    #[test]
+    fn test_throughput() {
+        let measurement = Measurement {
+            mode: BenchMode::BlockDownload,
+            size: 1000,
+            stats: Stats::from_samples(&[Duration::from_millis(500)]),
+            failures: 0,
+            last_error: None,
+        };
+        assert_eq!(Some(2000.0), measurement.throughput());
+    }
+}
@@ -1,10 +1,10 @@
-//! A REPL-style interactive shell for talking to CAN devices via socketcan
+//! A REPL-style interactive shell for talking to CAN devices via socketcan or CAN over UDP
 use std::{
-    array::TryFromSliceError,
     borrow::Cow,
+    collections::HashMap,
     ffi::OsString,
     marker::PhantomData,
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
     sync::{Arc, Mutex},
     time::Duration,
@@ -17,33 +17,67 @@ use reedline::{
     Span,
 };
 use shlex::Shlex;
-use zencan_cli::command::{Cli, Commands, LssCommands, NmtAction, SdoDataType};
+use zencan_cli::{
+    alias,
+    bench::{measure, BenchMode, Measurement},
+    clock::{init_logger, Clock},
+    command::{
+        Cli, Commands, ConfigCommands, ErrorsAction, GenAction, HeartbeatCommands, LinkCommands,
+        LintArgs, LogCommands, LssCommands, NmtAction, RecordCommands, SdoDataType,
+        SessionCommands,
+    },
+    config::config_path,
+    plugin::{self, PluginContext},
+    session::Session,
+};
 use zencan_client::{
-    common::{lss::LssState, NodeId},
-    open_socketcan, BusManager, NodeConfig,
+    common::{
+        decode::{emcy_error_class, error_register_names},
+        device_config::{DataType as DCDataType, DeviceConfig},
+        lss::LssState,
+        node_id::ConfiguredId,
+        traits::AsyncCanSender,
+        value::Value,
+        CanId, CanMessage, NodeId,
+    },
+    debug_log::DebugLogTail,
+    open_transport, BusManager, CobIdConflict, DecodedEmcy, FlashError, FlashOptions, FlashReport,
+    Fleet, FramePacing, NodeConfig, NodeConfigTemplate, NodeInfo, ParseMode, ScanOptions,
+    TransferProgress, VerifyMethod,
 };
 
 #[derive(Parser)]
 struct Args {
-    /// The CAN socket to connect to (e.g. 'can0' or 'van0')
-    socket: String,
+    /// The CAN bus to connect to (e.g. 'can0' on Linux, or 'udp:<bind addr>,<peer addr>')
+    #[clap(required_unless_present = "session")]
+    socket: Option<String>,
+    /// Restore a session saved with 'session save NAME'. If a bus is also given, it is opened as
+    /// well, and made the active interface.
+    #[clap(long, value_name = "NAME")]
+    session: Option<String>,
+    /// Prefix log messages with monotonic time, in seconds since startup, as well as wall clock
+    /// time
+    #[clap(long)]
+    log_clock: bool,
+    /// Log a marker recording the monotonic and wall clock time together every SECONDS
+    #[clap(long, value_name = "SECONDS", requires = "log_clock")]
+    marker_period: Option<u64>,
 }
 
 struct ZencanPrompt {
-    socket: String,
+    socket: Arc<Mutex<String>>,
     node_state: Arc<Mutex<usize>>,
 }
 
 impl ZencanPrompt {
-    pub fn new<S: Into<String>>(socket: S, node_state: Arc<Mutex<usize>>) -> Self {
-        let socket = socket.into();
+    pub fn new(socket: Arc<Mutex<String>>, node_state: Arc<Mutex<usize>>) -> Self {
         Self { socket, node_state }
     }
 }
 
 impl Prompt for ZencanPrompt {
     fn render_prompt_left(&self) -> std::borrow::Cow<str> {
-        Cow::from(&self.socket)
+        Cow::Owned(self.socket.lock().unwrap().clone())
     }
 
     fn render_prompt_right(&self) -> std::borrow::Cow<str> {
@@ -77,13 +111,93 @@ impl Prompt for ZencanPrompt {
     }
 }
 
+/// Information from the session used to provide context-aware completions
+#[derive(Default)]
+struct CompletionContext {
+    /// Node IDs found in the latest scan
+    node_ids: Vec<u8>,
+    /// Objects from the attached device config, as (index, name)
+    objects: Vec<(u16, String)>,
+    /// Sub objects from the attached device config which have a unit or scale, by (index, sub)
+    units: HashMap<(u16, u8), ObjectUnits>,
+}
+
+/// How to display the value of a sub object with a unit or scale
+#[derive(Clone)]
+struct ObjectUnits {
+    data_type: SdoDataType,
+    unit: Option<String>,
+    scale: Option<f64>,
+}
+
 struct Completer<C: Parser + Send + Sync + 'static> {
     c_phantom: PhantomData<C>,
+    context: Arc<Mutex<CompletionContext>>,
 }
 impl<C: Parser + Send + Sync + 'static> Completer<C> {
-    pub fn new() -> Self {
+    pub fn new(context: Arc<Mutex<CompletionContext>>) -> Self {
         Self {
             c_phantom: PhantomData::<C>,
+            context,
+        }
+    }
+
+    /// Provide completions for positional arguments which clap does not know the values of
+    ///
+    /// Returns None if the argument being completed is not a node ID or object index
+    fn complete_from_context(
+        &self,
+        cmd: &clap::Command,
+        args: &[OsString],
+        span: Span,
+    ) -> Option<Vec<reedline::Suggestion>> {
+        // args[0] is the empty binary name, args[1] the subcommand
+        if args.len() < 3 {
+            return None;
+        }
+        let subcommand = cmd.find_subcommand(args[1].to_str()?)?;
+        let current = args.last()?.to_str()?;
+        let positional = subcommand.get_positionals().nth(args.len() - 3)?;
+        let context = self.context.lock().unwrap();
+        let suggestion = |value: String, description: Option<String>| reedline::Suggestion {
+            value,
+            description,
+            style: None,
+            extra: None,
+            span,
+            append_whitespace: true,
+        };
+        match positional.get_id().as_str() {
+            "node_id" => {
+                // Known nodes are suggested by ID, with their alias, and aliases by name
+                let aliases = alias::aliases();
+                let ids = context
+                    .node_ids
+                    .iter()
+                    .map(|id| (id.to_string(), aliases.name(*id).map(String::from)));
+                let names = aliases
+                    .iter()
+                    .map(|(id, name)| (name.to_string(), Some(id.to_string())));
+                Some(
+                    ids.chain(names)
+                        .filter(|(value, _)| value.starts_with(current))
+                        .map(|(value, description)| suggestion(value, description))
+                        .collect(),
+                )
+            }
+            "index" if !context.objects.is_empty() => Some(
+                context
+                    .objects
+                    .iter()
+                    .map(|(index, name)| (format!("0x{index:04X}"), name))
+                    .filter(|(index, name)| {
+                        index.to_lowercase().starts_with(&current.to_lowercase())
+                            || name.to_lowercase().contains(&current.to_lowercase())
+                    })
+                    .map(|(index, name)| suggestion(index, Some(name.clone())))
+                    .collect(),
+            ),
+            _ => None,
         }
     }
 }
@@ -105,6 +219,10 @@ impl<C: Parser + Send + Sync + 'static> reedline::Completer for Completer<C> {
         let arg_index = args.len() - 1;
         let span = Span::new(pos - args[arg_index].len(), pos);
 
+        if let Some(suggestions) = self.complete_from_context(&cmd, &args, span) {
+            return suggestions;
+        }
+
         if line.is_empty() {
             return cmd
                 .get_subcommands()
@@ -140,86 +258,417 @@ impl<C: Parser + Send + Sync + 'static> reedline::Completer for Completer<C> {
     }
 }
 
-pub struct MismatchedSizeError {}
-impl From<TryFromSliceError> for MismatchedSizeError {
-    fn from(_value: TryFromSliceError) -> Self {
-        Self {}
+fn convert_write_value_to_bytes(data_type: SdoDataType, value: &str) -> Result<Vec<u8>, String> {
+    Value::parse(data_type.into(), value)
+        .map(|v| v.to_le_bytes())
+        .map_err(|e| e.to_string())
+}
+
+/// Decode a value read from an object, showing it in its unit along with the raw value if it has
+/// one
+fn convert_read_bytes_to_string(
+    data_type: SdoDataType,
+    bytes: &[u8],
+    units: Option<&ObjectUnits>,
+) -> Result<String, String> {
+    let value = Value::from_le_bytes(data_type.into(), bytes).map_err(|e| e.to_string())?;
+    Ok(match units {
+        Some(units) => format!(
+            "{} (raw {value})",
+            value.format_scaled(units.scale, units.unit.as_deref())
+        ),
+        None => value.to_string(),
+    })
+}
+
+/// Get the location of the persistent history file
+/// List the names of the bits set in an error register value
+fn describe_error_register(value: u8) -> String {
+    if value == 0 {
+        return "no errors".into();
+    }
+    error_register_names(value).collect::<Vec<_>>().join(", ")
+}
+
+/// Format a received EMCY message for display
+///
+/// The diagnostic from the vendor's EMCY decoder is appended when one is available
+fn format_emcy(decoded: &DecodedEmcy) -> String {
+    let emcy = &decoded.emcy;
+    let time = chrono::Local::now().format("%H:%M:%S%.3f");
+    if emcy.is_reset() {
+        return format!("{time} Node {}: EMCY error reset", emcy.node);
     }
+    let mut text = format!(
+        "{time} Node {}: EMCY 0x{:04X} ({}), register 0x{:02X} ({}), data {:02X?}",
+        emcy.node,
+        emcy.error_code,
+        emcy_error_class(emcy.error_code),
+        emcy.error_register,
+        describe_error_register(emcy.error_register),
+        emcy.vendor_data
+    );
+    if let Some(diagnostic) = &decoded.diagnostic {
+        text.push_str(&format!(": {diagnostic}"));
+    }
+    text
 }
 
-fn convert_write_value_to_bytes(data_type: SdoDataType, value: &str) -> Result<Vec<u8>, String> {
-    match data_type {
-        SdoDataType::U32 => {
-            let num = clap_num::maybe_hex::<u32>(value)?;
-            Ok(num.to_le_bytes().to_vec())
-        }
-        SdoDataType::U16 => {
-            let num = clap_num::maybe_hex::<u16>(value)?;
-            Ok(num.to_le_bytes().to_vec())
-        }
-        SdoDataType::U8 => {
-            let num = clap_num::maybe_hex::<u8>(value)?;
-            Ok(vec![num])
-        }
-        SdoDataType::I32 => {
-            let num = value.parse::<i32>().map_err(|e| e.to_string())?;
-            Ok(num.to_le_bytes().to_vec())
+/// Print a table with the outcome of a firmware update on each node
+fn print_flash_results(
+    prefix: &str,
+    results: &[(u8, Result<FlashReport, FlashError>)],
+    expect_version: Option<&str>,
+) {
+    println!(
+        "{prefix}{:<6} {:<20} {:<20} Result",
+        "Node", "Old Version", "New Version"
+    );
+    for (node_id, result) in results {
+        let (old, new, outcome) = match result {
+            Ok(report) => {
+                let outcome = match expect_version {
+                    Some(expected) if expected != report.version => {
+                        format!("FAILED: expected version '{expected}'")
+                    }
+                    _ => "OK".to_string(),
+                };
+                (
+                    report.previous_version.clone().unwrap_or("-".into()),
+                    report.version.clone(),
+                    outcome,
+                )
+            }
+            Err(e) => ("-".into(), "-".into(), format!("FAILED: {e}")),
+        };
+        println!("{prefix}{node_id:<6} {old:<20} {new:<20} {outcome}");
+    }
+}
+
+/// Create a progress callback which prints the progress of a file transfer on a single line
+fn progress_printer() -> impl FnMut(TransferProgress) + Send {
+    use std::io::Write;
+
+    const PRINT_INTERVAL: usize = 1024;
+    let mut last_printed = None;
+    move |progress: TransferProgress| {
+        let due = match last_printed {
+            Some((attempt, bytes)) => {
+                attempt != progress.attempt
+                    || progress.bytes >= bytes + PRINT_INTERVAL
+                    || Some(progress.bytes) == progress.total
+            }
+            None => true,
+        };
+        if !due {
+            return;
         }
-        SdoDataType::I16 => {
-            let num = value.parse::<i16>().map_err(|e| e.to_string())?;
-            Ok(num.to_le_bytes().to_vec())
+        last_printed = Some((progress.attempt, progress.bytes));
+        let retry = if progress.attempt > 1 {
+            format!(" (attempt {})", progress.attempt)
+        } else {
+            String::new()
+        };
+        match progress.total {
+            Some(total) if total > 0 => print!(
+                "\r{} / {} bytes ({}%){retry}",
+                progress.bytes,
+                total,
+                progress.bytes * 100 / total
+            ),
+            _ => print!("\r{} bytes{retry}", progress.bytes),
         }
-        SdoDataType::I8 => {
-            let num = value.parse::<i8>().map_err(|e| e.to_string())?;
-            Ok(vec![num as u8])
+        std::io::stdout().flush().ok();
+    }
+}
+
+fn format_ms(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn print_measurement(prefix: &str, m: &Measurement) {
+    let size = format!("{} B", m.size);
+    match m.stats {
+        Some(stats) => {
+            let throughput = match m.throughput() {
+                Some(bps) => format!("{:.1} kB/s", bps / 1000.0),
+                None => "-".to_string(),
+            };
+            println!(
+                "{prefix}{:<16} {size:>8} {:>10} {:>10} {:>10} {throughput:>12}",
+                m.mode.to_string(),
+                format_ms(stats.p50),
+                format_ms(stats.p95),
+                format_ms(stats.max),
+            );
         }
-        SdoDataType::F32 => {
-            let num = value.parse::<f32>().map_err(|e| e.to_string())?;
-            Ok(num.to_le_bytes().to_vec())
+        None => println!(
+            "{prefix}{:<16} {size:>8} all transfers failed",
+            m.mode.to_string()
+        ),
+    }
+    if let Some(e) = &m.last_error {
+        println!("{prefix}    {} failed, last error: {e}", m.failures);
+    }
+}
+
+/// Check a configuration against a node, then write it
+///
+/// Returns the COB-ID conflicts found, which are warnings, and do not stop the configuration being
+/// written.
+async fn apply_config<S: AsyncCanSender + Sync + Send>(
+    manager: &BusManager<S>,
+    node_id: u8,
+    config: &NodeConfig,
+    verify: bool,
+) -> Result<Vec<CobIdConflict>, String> {
+    manager
+        .sdo_client(node_id)
+        .validate_node_config(config)
+        .await
+        .map_err(|e| format!("Config is not compatible: {e}"))?;
+    manager
+        .apply_node_config(node_id, config, verify)
+        .await
+        .map_err(|e| format!("Error applying config: {e}"))
+}
+
+/// Check a node config file, which may be a template, and print the problems found
+fn lint_config(args: &LintArgs) {
+    let mode = if args.lenient {
+        ParseMode::Lenient
+    } else {
+        ParseMode::Strict
+    };
+    let vars: HashMap<_, _> = args.vars.iter().cloned().collect();
+    let diagnostics = match NodeConfigTemplate::load_from_file(&args.path)
+        .and_then(|template| template.validate(args.node_id, &vars, mode))
+    {
+        Ok(diagnostics) => diagnostics,
+        Err(e) => {
+            println!("Error reading config file: {e}");
+            return;
         }
-        SdoDataType::Utf8 => Ok(value.as_bytes().to_vec()),
+    };
+    for diagnostic in &diagnostics {
+        println!("{}:{diagnostic}", args.path.display());
     }
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+    println!("{errors} errors, {warnings} warnings");
 }
 
-/// Attempt to print a byte slice based on data type and return true if successful
-fn convert_read_bytes_to_string(
-    data_type: SdoDataType,
-    bytes: &[u8],
-) -> Result<String, MismatchedSizeError> {
+/// Build a frame for the send and gen commands, using an extended ID if the ID needs more than 11
+/// bits
+fn raw_frame(id: u32, data: &[u8]) -> Result<CanMessage, String> {
+    if data.len() > 8 {
+        return Err(format!(
+            "A CAN frame holds at most 8 bytes, got {}",
+            data.len()
+        ));
+    }
+    let id = match id {
+        0..=0x7FF => CanId::std(id as u16),
+        0x800..=0x1FFF_FFFF => CanId::extended(id),
+        _ => return Err(format!("0x{id:X} does not fit in a 29-bit CAN ID")),
+    };
+    Ok(CanMessage::new(id, data))
+}
+
+/// Print information about a node, with its alias if it has one
+fn print_node_info(prefix: &str, info: &NodeInfo) {
+    let text = info.to_string();
+    let text = match alias::aliases().name(info.node_id) {
+        Some(name) => text.replacen(
+            &format!("Node {}:", info.node_id),
+            &format!("Node {} ({name}):", info.node_id),
+            1,
+        ),
+        None => text,
+    };
+    println!("{prefix}{text}");
+}
+
+/// Get the format used to read a numeric device config data type
+fn numeric_format(data_type: DCDataType) -> Option<SdoDataType> {
     match data_type {
-        SdoDataType::U32 => Ok(u32::from_le_bytes(bytes.try_into()?).to_string()),
-        SdoDataType::U16 => Ok(u16::from_le_bytes(bytes.try_into()?).to_string()),
-        SdoDataType::U8 => {
-            if !bytes.is_empty() {
-                Ok(bytes[0].to_string())
-            } else {
-                Err(MismatchedSizeError {})
+        DCDataType::Int8 => Some(SdoDataType::I8),
+        DCDataType::Int16 => Some(SdoDataType::I16),
+        DCDataType::Int32 => Some(SdoDataType::I32),
+        DCDataType::UInt8 => Some(SdoDataType::U8),
+        DCDataType::UInt16 => Some(SdoDataType::U16),
+        DCDataType::UInt32 => Some(SdoDataType::U32),
+        DCDataType::Real32 => Some(SdoDataType::F32),
+        _ => None,
+    }
+}
+
+/// Attach a device config, so that its objects are offered as completions, and values read from
+/// them are shown in their units
+///
+/// Returns the number of objects attached
+fn attach_od(path: &Path, context: &Mutex<CompletionContext>) -> Result<usize, String> {
+    let config =
+        DeviceConfig::load(path).map_err(|e| format!("Error loading device config: {e}"))?;
+    let mut objects: Vec<_> = config
+        .objects
+        .iter()
+        .map(|o| (o.index, o.parameter_name.clone()))
+        .collect();
+    objects.sort();
+    let units = config
+        .objects
+        .iter()
+        .flat_map(|o| (0..=u8::MAX).map(move |sub| (o, sub)))
+        .filter_map(|(o, sub)| {
+            let units = o.units(sub)?;
+            let units = ObjectUnits {
+                data_type: numeric_format(units.data_type)?,
+                unit: units.unit.map(String::from),
+                scale: units.scale,
+            };
+            Some(((o.index, sub), units))
+        })
+        .collect();
+    let count = objects.len();
+    let mut context = context.lock().unwrap();
+    context.objects = objects;
+    context.units = units;
+    Ok(count)
+}
+
+/// Restore a saved session
+///
+/// The interfaces of the session are opened, in addition to any already open, its active
+/// interface is made active, its device config is attached, and its aliases are added to those in
+/// use. Anything which cannot be restored is reported, and the rest of the session is still
+/// restored.
+fn restore_session(
+    session: &Session,
+    managers: &mut HashMap<String, BusManager>,
+    active: &mut String,
+    attached_od: &mut Option<PathBuf>,
+    context: &Mutex<CompletionContext>,
+) {
+    for interface in &session.interfaces {
+        if managers.contains_key(interface) {
+            continue;
+        }
+        match open_transport(interface) {
+            Ok((tx, rx)) => {
+                managers.insert(interface.clone(), BusManager::new(tx, rx));
             }
+            Err(e) => println!("Failed to open {interface}: {e}"),
+        }
+    }
+    if let Some(interface) = &session.active {
+        if managers.contains_key(interface) {
+            *active = interface.clone();
         }
-        SdoDataType::I32 => Ok(i32::from_le_bytes(bytes.try_into()?).to_string()),
-        SdoDataType::I16 => Ok(i16::from_le_bytes(bytes.try_into()?).to_string()),
-        SdoDataType::I8 => {
-            if !bytes.is_empty() {
-                Ok((bytes[0] as i8).to_string())
-            } else {
-                Err(MismatchedSizeError {})
+    }
+    if let Some(path) = &session.attached_od {
+        match attach_od(path, context) {
+            Ok(count) => {
+                println!("Attached {count} objects from {}", path.display());
+                *attached_od = Some(path.clone());
             }
+            Err(e) => println!("{e}"),
         }
-        SdoDataType::F32 => Ok(f32::from_le_bytes(bytes.try_into()?).to_string()),
-        SdoDataType::Utf8 => Ok(String::from_utf8_lossy(bytes).to_string()),
+    }
+    let mut aliases = alias::aliases();
+    for (name, node_id) in &session.aliases {
+        if let Err(e) = aliases.set(*node_id, name) {
+            println!("{e}");
+        }
+    }
+    alias::set_aliases(aliases);
+}
+
+fn history_path() -> PathBuf {
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".zencan-cli-history"),
+        None => PathBuf::from("/tmp/zencan-cli-history"),
     }
 }
 
 #[tokio::main]
 async fn main() {
-    env_logger::init();
     let args = Args::parse();
+    if args.log_clock {
+        let mut clock = Clock::new();
+        init_logger(&clock);
+        if let Some(period) = args.marker_period {
+            clock = clock.with_marker_period(Duration::from_secs(period));
+            tokio::spawn(async move {
+                loop {
+                    if let Some(marker) = clock.poll_marker() {
+                        log::info!("{marker}");
+                    }
+                    tokio::time::sleep(clock.until_marker().unwrap_or_default()).await;
+                }
+            });
+        }
+    } else {
+        env_logger::init();
+    }
 
-    let node_state = Arc::new(Mutex::new(0));
-    let prompt = ZencanPrompt::new(&args.socket, node_state.clone());
+    match alias::NodeAliases::load(&config_path()) {
+        Ok(aliases) => alias::set_aliases(aliases),
+        Err(e) => println!("{e}"),
+    }
 
-    let (tx, rx) = open_socketcan(&args.socket).expect("Failed to open bus socket");
-    let mut manager = BusManager::new(tx, rx);
+    // One bus manager is kept for each open interface, and commands go to the active one
+    let mut managers = HashMap::new();
+    let mut active = String::new();
+    if let Some(socket) = &args.socket {
+        let (tx, rx) = open_transport(socket).expect("Failed to open bus");
+        managers.insert(socket.clone(), BusManager::new(tx, rx));
+        active = socket.clone();
+    }
+    // The path of the attached device config, recorded when the session is saved
+    let mut attached_od: Option<PathBuf> = None;
+    // The interface being recorded by the record command, and the file to write
+    let mut recording: Option<(String, PathBuf)> = None;
+    let completion_context = Arc::new(Mutex::new(CompletionContext::default()));
+
+    if let Some(name) = &args.session {
+        let session = match Session::load(&config_path(), name) {
+            Ok(session) => session,
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        };
+        restore_session(
+            &session,
+            &mut managers,
+            &mut active,
+            &mut attached_od,
+            &completion_context,
+        );
+        // A bus given on the command line takes precedence over the active interface of the
+        // session
+        if let Some(socket) = &args.socket {
+            active = socket.clone();
+        }
+        if !managers.contains_key(&active) {
+            let mut names: Vec<_> = managers.keys().cloned().collect();
+            names.sort();
+            match names.into_iter().next() {
+                Some(name) => active = name,
+                None => {
+                    eprintln!("No interfaces could be opened");
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
+    let node_state = Arc::new(Mutex::new(0));
+    let active_socket = Arc::new(Mutex::new(active.clone()));
+    let prompt = ZencanPrompt::new(active_socket.clone(), node_state.clone());
+    // Tasks sending periodic frames, started by the gen command
+    let mut generators: Vec<tokio::task::JoinHandle<()>> = Vec::new();
 
     let completion_menu = Box::new(
         reedline::IdeMenu::default()
@@ -238,16 +687,17 @@ async fn main() {
     let edit_mode = Box::new(Emacs::new(keybindings));
 
     let mut rl = Reedline::create()
-        .with_completer(Box::new(Completer::<Cli>::new()))
+        .with_completer(Box::new(Completer::<Cli>::new(completion_context.clone())))
         .with_menu(ReedlineMenu::EngineCompleter(completion_menu))
         .with_history(Box::new(
-            FileBackedHistory::with_file(10000, "/tmp/zencan-cli-history".into()).unwrap(),
+            FileBackedHistory::with_file(10000, history_path()).unwrap(),
         ))
         .with_edit_mode(edit_mode);
 
     loop {
-        let nodes = manager.node_list().await;
+        let nodes = managers.get(&active).unwrap().node_list().await;
         *node_state.lock().unwrap() = nodes.len();
+        completion_context.lock().unwrap().node_ids = nodes.iter().map(|n| n.node_id).collect();
         let line = match rl.read_line(&prompt) {
             Ok(Signal::Success(line)) => line,
             Ok(Signal::CtrlC) => continue,
@@ -265,7 +715,28 @@ async fn main() {
                 ) {
                     Ok(c) => c,
                     Err(e) => {
-                        println!("{e}");
+                        // A command which is not built in may be a plugin
+                        let plugin = (e.kind() == clap::error::ErrorKind::InvalidSubcommand)
+                            .then(|| plugin::find(&split[0]))
+                            .flatten();
+                        let Some(plugin) = plugin else {
+                            println!("{e}");
+                            continue;
+                        };
+                        let context = PluginContext {
+                            bus: active.clone(),
+                            nodes: nodes.iter().map(|n| n.node_id).collect(),
+                            device_config: attached_od.clone(),
+                        };
+                        let result =
+                            tokio::task::block_in_place(|| plugin.run(&split[1..], &context));
+                        match result {
+                            Ok(status) if !status.success() => {
+                                println!("{} exited with {status}", plugin.name)
+                            }
+                            Ok(_) => (),
+                            Err(e) => println!("{e}"),
+                        }
                         continue;
                     }
                 }
@@ -275,18 +746,302 @@ async fn main() {
             }
         };
 
+        match &cmd.command {
+            Commands::Open(args) => {
+                if !managers.contains_key(&args.interface) {
+                    match open_transport(&args.interface) {
+                        Ok((tx, rx)) => {
+                            managers.insert(args.interface.clone(), BusManager::new(tx, rx));
+                        }
+                        Err(e) => {
+                            println!("Failed to open {}: {e}", args.interface);
+                            continue;
+                        }
+                    }
+                }
+                active = args.interface.clone();
+                *active_socket.lock().unwrap() = active.clone();
+                continue;
+            }
+            Commands::Use(args) => {
+                match &args.interface {
+                    Some(interface) if managers.contains_key(interface) => {
+                        active = interface.clone();
+                        *active_socket.lock().unwrap() = active.clone();
+                    }
+                    Some(interface) => {
+                        println!("{interface} is not open. Use 'open {interface}' to open it.")
+                    }
+                    None => {
+                        let mut names: Vec<_> = managers.keys().collect();
+                        names.sort();
+                        for name in names {
+                            let marker = if *name == active { "*" } else { " " };
+                            println!("{marker} {name}");
+                        }
+                    }
+                }
+                continue;
+            }
+            Commands::Link(cmd) => {
+                run_link_command(cmd, &active);
+                continue;
+            }
+            Commands::Record(cmd) => {
+                match cmd {
+                    RecordCommands::Start { path } => {
+                        if let Some((interface, path)) = &recording {
+                            println!("Already recording {interface} to {}", path.display());
+                            continue;
+                        }
+                        managers.get(&active).unwrap().start_capture();
+                        println!("Recording {active} to {}", path.display());
+                        recording = Some((active.clone(), path.clone()));
+                    }
+                    RecordCommands::Stop => {
+                        let Some((interface, path)) = recording.take() else {
+                            println!("Not recording");
+                            continue;
+                        };
+                        let capture = managers
+                            .get(&interface)
+                            .and_then(|manager| manager.stop_capture())
+                            .unwrap_or_default();
+                        let text = format!(
+                            "# Recorded on {interface} by zencan-cli at {}\n{capture}",
+                            chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+                        );
+                        match std::fs::write(&path, text) {
+                            Ok(()) => println!(
+                                "Wrote {} frames to {}",
+                                capture.frames.len(),
+                                path.display()
+                            ),
+                            Err(e) => println!("Error writing {}: {e}", path.display()),
+                        }
+                    }
+                }
+                continue;
+            }
+            Commands::Config(ConfigCommands::Lint(args)) => {
+                lint_config(args);
+                continue;
+            }
+            Commands::Plugins => {
+                let plugins = plugin::discover();
+                if plugins.is_empty() {
+                    println!("No plugins found on the PATH");
+                }
+                for plugin in plugins {
+                    println!("{:<20} {}", plugin.name, plugin.path.display());
+                }
+                continue;
+            }
+            Commands::Session(cmd) => {
+                match cmd {
+                    SessionCommands::Save { name } => {
+                        let mut interfaces: Vec<_> = managers.keys().cloned().collect();
+                        interfaces.sort();
+                        let session = Session {
+                            interfaces,
+                            active: Some(active.clone()),
+                            attached_od: attached_od.clone(),
+                            aliases: alias::aliases().to_map(),
+                        };
+                        match session.save(&config_path(), name) {
+                            Ok(()) => println!("Saved session '{name}'"),
+                            Err(e) => println!("{e}"),
+                        }
+                    }
+                    SessionCommands::Load { name } => match Session::load(&config_path(), name) {
+                        Ok(session) => {
+                            restore_session(
+                                &session,
+                                &mut managers,
+                                &mut active,
+                                &mut attached_od,
+                                &completion_context,
+                            );
+                            *active_socket.lock().unwrap() = active.clone();
+                        }
+                        Err(e) => println!("{e}"),
+                    },
+                    SessionCommands::List => match Session::list(&config_path()) {
+                        Ok(names) => {
+                            for name in names {
+                                println!("{name}");
+                            }
+                        }
+                        Err(e) => println!("{e}"),
+                    },
+                    SessionCommands::Delete { name } => {
+                        if let Err(e) = Session::delete(&config_path(), name) {
+                            println!("{e}");
+                        }
+                    }
+                }
+                continue;
+            }
+            _ => (),
+        }
+
+        // Prefix output with the interface name when more than one is open
+        let prefix = if managers.len() > 1 {
+            format!("[{active}] ")
+        } else {
+            String::new()
+        };
+        let manager = managers.get_mut(&active).unwrap();
+
         match cmd.command {
-            Commands::Scan => {
-                let nodes = manager.scan_nodes().await;
+            Commands::Open(_)
+            | Commands::Use(_)
+            | Commands::Link(_)
+            | Commands::Session(_)
+            | Commands::Record(_)
+            | Commands::Config(_)
+            | Commands::Plugins => {
+                unreachable!()
+            }
+            Commands::Scan(args) => {
+                let opts = ScanOptions {
+                    timeout: Duration::from_millis(args.timeout),
+                    parallelism: args.parallel,
+                    incremental: args.incremental,
+                    probe_only: args.probe,
+                    stagger: Duration::from_millis(args.stagger),
+                    probe_capabilities: args.capabilities,
+                    ..Default::default()
+                };
+                let nodes = manager.scan_nodes_with(&opts).await;
                 for n in &nodes {
-                    println!("{n}");
+                    print_node_info(&prefix, n);
                 }
             }
             Commands::Info => {
                 let nodes = manager.node_list().await;
                 for n in &nodes {
-                    println!("{n}");
+                    print_node_info(&prefix, n);
+                }
+            }
+            Commands::Topology(args) => {
+                let topology = manager.export_topology().await;
+                if topology.nodes.is_empty() {
+                    println!("No known nodes. Run 'scan' first");
+                    continue;
                 }
+                for node in topology.nodes.iter().filter(|n| n.error.is_some()) {
+                    println!(
+                        "{prefix}Node {}: PDO configuration incomplete: {}",
+                        node.node_id,
+                        node.error.as_deref().unwrap_or_default()
+                    );
+                }
+                let output = if args.dot {
+                    topology.to_dot()
+                } else {
+                    topology.to_json()
+                };
+                match &args.output {
+                    Some(path) => match std::fs::write(path, output) {
+                        Ok(()) => println!("Wrote topology to {}", path.display()),
+                        Err(e) => println!("Error writing {}: {e}", path.display()),
+                    },
+                    None => println!("{output}"),
+                }
+            }
+            Commands::Bench(args) => {
+                let mut client = manager.sdo_client(args.node_id);
+                println!(
+                    "{prefix}{:<16} {:>8} {:>10} {:>10} {:>10} {:>12}",
+                    "transfer", "size", "p50", "p95", "max", "throughput"
+                );
+                let latency =
+                    measure(&mut *client, BenchMode::Expedited, 0, 0, 0, args.count).await;
+                print_measurement(&prefix, &latency);
+                let Some(index) = args.index else {
+                    continue;
+                };
+                for &size in &args.sizes {
+                    for mode in [
+                        BenchMode::SegmentedDownload,
+                        BenchMode::BlockDownload,
+                        BenchMode::SegmentedUpload,
+                    ] {
+                        let m =
+                            measure(&mut *client, mode, index, args.sub, size, args.rounds).await;
+                        print_measurement(&prefix, &m);
+                    }
+                }
+            }
+            Commands::Pace(args) => {
+                if args.clear {
+                    manager.set_frame_pacing(args.node_id, FramePacing::default());
+                } else if args.max_fps.is_some() || args.min_gap_us.is_some() {
+                    let pacing = FramePacing {
+                        max_frames_per_sec: args.max_fps,
+                        min_gap_us: args.min_gap_us,
+                    };
+                    manager.set_frame_pacing(args.node_id, pacing);
+                }
+                let gap = manager.frame_pacing(args.node_id).gap();
+                if gap.is_zero() {
+                    println!("{prefix}Node {}: not paced", args.node_id);
+                } else {
+                    println!(
+                        "{prefix}Node {}: at least {} us between frames",
+                        args.node_id,
+                        gap.as_micros()
+                    );
+                }
+            }
+            Commands::Send(args) => match raw_frame(args.id, &args.data) {
+                Ok(msg) => {
+                    if manager.raw_handle().send(msg).await.is_err() {
+                        println!("{prefix}Failed to send frame");
+                    }
+                }
+                Err(e) => println!("{e}"),
+            },
+            Commands::Gen(args) => {
+                if let Some(GenAction::Stop) = args.action {
+                    let count = generators.len();
+                    for generator in generators.drain(..) {
+                        generator.abort();
+                    }
+                    println!("Stopped {count} generator(s)");
+                    continue;
+                }
+                let (Some(id), Some(period_ms)) = (args.id, args.period_ms) else {
+                    unreachable!("id and period are required without a subcommand");
+                };
+                let msg = match raw_frame(id, &args.data) {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        println!("{e}");
+                        continue;
+                    }
+                };
+                let mut handle = manager.raw_handle();
+                let mut interval = tokio::time::interval(Duration::from_millis(period_ms));
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                generators.push(tokio::spawn(async move {
+                    loop {
+                        tokio::select! {
+                            _ = interval.tick() => {
+                                if handle.send(msg).await.is_err() {
+                                    log::warn!("Generator failed to send {msg:?}");
+                                }
+                            }
+                            // Received frames are not used, but must be consumed so that the
+                            // handle's queue does not overflow
+                            result = handle.recv() => if result.is_err() {
+                                break;
+                            },
+                        }
+                    }
+                }));
+                println!("{prefix}Sending every {period_ms} ms, use 'gen stop' to stop");
             }
             Commands::Nmt(cmd) => match cmd.action {
                 NmtAction::ResetApp => manager.nmt_reset_app(cmd.node.raw()).await,
@@ -294,43 +1049,105 @@ async fn main() {
                 NmtAction::Start => manager.nmt_start(cmd.node.raw()).await,
                 NmtAction::Stop => manager.nmt_stop(cmd.node.raw()).await,
             },
+            Commands::Estop(args) => {
+                let frames: Vec<CanMessage> = args
+                    .frames
+                    .iter()
+                    .filter_map(|(id, data)| match raw_frame(*id, data) {
+                        Ok(msg) => Some(msg),
+                        Err(e) => {
+                            println!("{e}");
+                            None
+                        }
+                    })
+                    .collect();
+                let settle_time = Duration::from_millis(args.settle_ms);
+                if manager.emergency_stop(&frames, settle_time).await {
+                    println!(
+                        "{prefix}Sent {} safe state frame(s) and broadcast NMT stop",
+                        frames.len()
+                    );
+                } else {
+                    println!("{prefix}Failed to send emergency stop");
+                }
+            }
             Commands::LoadConfig(args) => {
-                let config = match NodeConfig::load_from_file(&args.path) {
+                let vars: HashMap<_, _> = args.vars.iter().cloned().collect();
+                let config = match NodeConfigTemplate::load_from_file(&args.path)
+                    .and_then(|template| template.render(args.node_id, &vars))
+                {
                     Ok(c) => c,
                     Err(e) => {
                         println!("Error reading config file: ");
                         println!("{e}");
-                        return;
+                        continue;
                     }
                 };
-                let mut client = manager.sdo_client(args.node_id);
-                for (pdo_num, cfg) in config.tpdos() {
-                    if let Err(e) = client.configure_tpdo(*pdo_num, cfg).await {
-                        println!("Error configuring TPDO {pdo_num}:");
-                        println!("{e}");
-                        continue;
+                match apply_config(manager, args.node_id, &config, args.verify).await {
+                    Ok(conflicts) => {
+                        for conflict in conflicts {
+                            println!("{prefix}Warning: {conflict}");
+                        }
                     }
+                    Err(e) => println!("Node {}: {e}", args.node_id),
                 }
-                for (pdo_num, cfg) in config.rpdos() {
-                    if let Err(e) = client.configure_rpdo(*pdo_num, cfg).await {
-                        println!("Error configuring RPDO {pdo_num}:");
-                        println!("{e}");
+            }
+            Commands::LoadFleet(args) => {
+                let configs = match Fleet::load_from_file(&args.path).and_then(|f| f.render()) {
+                    Ok(configs) => configs,
+                    Err(e) => {
+                        println!("Error loading fleet: {e}");
                         continue;
                     }
+                };
+                for (node_id, config) in &configs {
+                    match apply_config(manager, *node_id, config, args.verify).await {
+                        Ok(conflicts) => {
+                            for conflict in conflicts {
+                                println!("{prefix}Warning: {conflict}");
+                            }
+                            println!("{prefix}Node {node_id}: configured");
+                        }
+                        Err(e) => println!("{prefix}Node {node_id}: {e}"),
+                    }
                 }
-                for store in config.stores() {
-                    if let Err(e) = client
-                        .download(store.index, store.sub, &store.raw_value())
-                        .await
-                    {
-                        println!(
-                            "Error storing object at index {:04X} sub {}: {e}",
-                            store.index, store.sub
-                        );
-                        continue;
+            }
+            Commands::Alias(args) => {
+                let mut aliases = alias::aliases();
+                let (Some(node_id), Some(name)) = (args.node_id, &args.name) else {
+                    for (node_id, name) in aliases.iter() {
+                        println!("{node_id:>3} {name}");
                     }
+                    continue;
+                };
+                if let Err(e) = aliases.set(node_id, name) {
+                    println!("{e}");
+                    continue;
                 }
+                if let Err(e) = aliases.save(&config_path()) {
+                    println!("{e}");
+                }
+                alias::set_aliases(aliases);
             }
+            Commands::Unalias(args) => {
+                let mut aliases = alias::aliases();
+                if aliases.remove(args.node_id).is_none() {
+                    println!("Node {} has no alias", args.node_id);
+                    continue;
+                }
+                if let Err(e) = aliases.save(&config_path()) {
+                    println!("{e}");
+                }
+                alias::set_aliases(aliases);
+            }
+            Commands::AttachOd(args) => match attach_od(&args.path, &completion_context) {
+                Ok(count) => {
+                    println!("Attached {count} objects");
+                    // Saved sessions may be restored from another working directory
+                    attached_od = Some(std::fs::canonicalize(&args.path).unwrap_or(args.path));
+                }
+                Err(e) => println!("{e}"),
+            },
             Commands::Lss(lss_cmd) => match lss_cmd {
                 LssCommands::Activate { identity } => {
                     match manager.lss_activate(identity.into()).await {
@@ -349,6 +1166,38 @@ async fn main() {
                         );
                     }
                 }
+                LssCommands::AutoAssign {
+                    first,
+                    last,
+                    timeout,
+                } => {
+                    let timeout = Duration::from_millis(timeout);
+                    let report = manager.lss_auto_assign(first..=last, timeout).await;
+                    for a in &report.assigned {
+                        let id = a.identity;
+                        println!(
+                            "0x{:x} 0x{:x} 0x{:x} 0x{:x} -> node {}{}",
+                            id.vendor_id,
+                            id.product_code,
+                            id.revision,
+                            id.serial,
+                            a.node_id.raw(),
+                            if a.stored { "" } else { " (not stored)" }
+                        );
+                    }
+                    for id in &report.unassigned {
+                        println!(
+                            "0x{:x} 0x{:x} 0x{:x} 0x{:x} -> no ID available",
+                            id.vendor_id, id.product_code, id.revision, id.serial
+                        );
+                    }
+                    for (id, e) in &report.failed {
+                        println!(
+                            "0x{:x} 0x{:x} 0x{:x} 0x{:x} -> failed: {e}",
+                            id.vendor_id, id.product_code, id.revision, id.serial
+                        );
+                    }
+                }
                 LssCommands::SetNodeId { node_id, identity } => {
                     let node_id = match NodeId::try_from(node_id) {
                         Ok(id) => id,
@@ -414,21 +1263,25 @@ async fn main() {
                     }
                 };
                 let mut client = manager.sdo_client(node_id.raw());
+                let units = completion_context
+                    .lock()
+                    .unwrap()
+                    .units
+                    .get(&(args.index, args.sub))
+                    .cloned();
                 match client.upload(args.index, args.sub).await {
-                    Ok(bytes) => match args.data_type {
-                        Some(data_type) => match convert_read_bytes_to_string(data_type, &bytes) {
-                            Ok(str) => {
-                                println!("Value: {str}");
+                    Ok(bytes) => match args.data_type.or(units.as_ref().map(|u| u.data_type)) {
+                        Some(data_type) => {
+                            match convert_read_bytes_to_string(data_type, &bytes, units.as_ref()) {
+                                Ok(str) => {
+                                    println!("Value: {str}");
+                                }
+                                Err(e) => {
+                                    println!("Cannot interpret response as {data_type:?}: {e}");
+                                    println!("Bytes: {:?}", &bytes);
+                                }
                             }
-                            Err(_) => {
-                                println!(
-                                    "Read invalid data size {} for type {:?}",
-                                    bytes.len(),
-                                    data_type
-                                );
-                                println!("Bytes: {:?}", &bytes);
-                            }
-                        },
+                        }
                         None => {
                             println!("Read bytes: {:?}", &bytes);
                         }
@@ -449,20 +1302,77 @@ async fn main() {
                     }
                 };
                 let mut client = manager.sdo_client(node_id.raw());
-                match convert_write_value_to_bytes(args.data_type, &args.value) {
-                    Ok(bytes) => match client.download(args.index, args.sub, &bytes).await {
-                        Ok(_) => {
-                            println!("Wrote {} bytes", bytes.len());
-                        }
-                        Err(e) => {
-                            println!("Download error: {e}");
+                let data_type = match args.data_type {
+                    Some(data_type) => data_type,
+                    None => {
+                        // Use the current size of the object to pick the encoding
+                        match client.upload(args.index, args.sub).await {
+                            Ok(current) => SdoDataType::infer(&args.value, current.len()),
+                            Err(e) => {
+                                println!("Cannot infer format, specify one with 'as': {e}");
+                                continue;
+                            }
                         }
-                    },
+                    }
+                };
+                let bytes = match convert_write_value_to_bytes(data_type, &args.value) {
+                    Ok(bytes) => bytes,
                     Err(e) => {
-                        println!("Cannot convert value to {:?}: {}", args.data_type, e);
+                        println!("Cannot convert value to {:?}: {}", data_type, e);
+                        continue;
+                    }
+                };
+                let result = if args.verify {
+                    client
+                        .write_verified(args.index, args.sub, &bytes, VerifyMethod::Readback)
+                        .await
+                } else {
+                    client.write(args.index, args.sub, &bytes).await
+                };
+                match result {
+                    Ok(_) if args.verify => {
+                        println!(
+                            "Wrote and verified {} bytes as {:?}",
+                            bytes.len(),
+                            data_type
+                        );
+                    }
+                    Ok(_) => {
+                        println!("Wrote {} bytes as {:?}", bytes.len(), data_type);
+                    }
+                    Err(e) => {
+                        println!("Download error: {e}");
                     }
                 }
             }
+            Commands::GetFile(args) | Commands::PutFile(args) => {
+                let node_id = match NodeId::new(args.node_id) {
+                    Ok(id) => id,
+                    Err(_) => {
+                        println!("{} is not a valid node ID", args.node_id);
+                        continue;
+                    }
+                };
+                let mut client = manager.sdo_client(node_id.raw());
+                client.set_transfer_retries(args.retries);
+                let result = if matches!(cmd.command, Commands::GetFile(_)) {
+                    client
+                        .upload_to_file(args.index, args.sub, &args.path, progress_printer())
+                        .await
+                } else {
+                    client
+                        .download_from_file(args.index, args.sub, &args.path, progress_printer())
+                        .await
+                };
+                println!();
+                match result {
+                    Ok(bytes) if matches!(cmd.command, Commands::GetFile(_)) => {
+                        println!("Read {bytes} bytes to {}", args.path.display());
+                    }
+                    Ok(bytes) => println!("Wrote {bytes} bytes from {}", args.path.display()),
+                    Err(e) => println!("File transfer error: {e}"),
+                }
+            }
             Commands::SaveObjects(args) => {
                 // Make sure node ID is valid
                 let node_id = match NodeId::new(args.node_id) {
@@ -478,6 +1388,280 @@ async fn main() {
                     Err(e) => println!("Error: {e}"),
                 }
             }
+            Commands::FlashAll(args) => {
+                let image = match std::fs::read(&args.file) {
+                    Ok(image) => image,
+                    Err(e) => {
+                        println!("Error reading {}: {e}", args.file.display());
+                        continue;
+                    }
+                };
+                let scan_opts = ScanOptions {
+                    probe_only: true,
+                    ..Default::default()
+                };
+                let targets: Vec<u8> = manager
+                    .scan_nodes_with(&scan_opts)
+                    .await
+                    .iter()
+                    .filter(|n| n.identity.is_some_and(|id| args.pattern.matches(&id)))
+                    .map(|n| n.node_id)
+                    .collect();
+                if targets.is_empty() {
+                    println!("{prefix}No matching nodes found");
+                    continue;
+                }
+                println!(
+                    "{prefix}Flashing {} bytes to nodes {:?}",
+                    image.len(),
+                    targets
+                );
+                let flash_opts = FlashOptions {
+                    section: args.section,
+                    reboot_timeout: Duration::from_millis(args.reboot_timeout),
+                    ..Default::default()
+                };
+                let results = manager
+                    .flash_nodes(&targets, &image, &flash_opts, args.parallel)
+                    .await;
+                print_flash_results(&prefix, &results, args.expect_version.as_deref());
+            }
+            Commands::Errors(args) => {
+                if let Some(ErrorsAction::Clear { node_id }) = args.action {
+                    if NodeId::new(node_id).is_err() {
+                        println!("{node_id} is not a valid node ID");
+                        continue;
+                    }
+                    let mut client = manager.sdo_client(node_id);
+                    match client.clear_error_history().await {
+                        Ok(_) => println!("{prefix}Cleared error history of node {node_id}"),
+                        Err(e) => println!("Error clearing error history: {e}"),
+                    }
+                    continue;
+                }
+
+                let nodes: Vec<u8> = match args.node_id {
+                    Some(node_id) => {
+                        if NodeId::new(node_id).is_err() {
+                            println!("{node_id} is not a valid node ID");
+                            continue;
+                        }
+                        vec![node_id]
+                    }
+                    None => manager
+                        .node_list()
+                        .await
+                        .iter()
+                        .map(|n| n.node_id)
+                        .collect(),
+                };
+                if nodes.is_empty() {
+                    println!("No known nodes. Run 'scan' first, or specify a node ID");
+                }
+                for node_id in nodes {
+                    let label = alias::aliases().label(node_id);
+                    let mut client = manager.sdo_client(node_id);
+                    match client.read_error_register().await {
+                        Ok(value) => println!(
+                            "{prefix}Node {label}: error register 0x{value:02X} ({})",
+                            describe_error_register(value)
+                        ),
+                        Err(e) => {
+                            println!("{prefix}Node {label}: error reading error register: {e}");
+                            continue;
+                        }
+                    }
+                    match client.read_error_history().await {
+                        Ok(history) if history.is_empty() => {
+                            println!("{prefix}    No errors in history")
+                        }
+                        Ok(history) => {
+                            for (i, entry) in history.iter().enumerate() {
+                                let code = *entry as u16;
+                                println!(
+                                    "{prefix}    {}: 0x{code:04X} ({}), info 0x{:04X}",
+                                    i + 1,
+                                    emcy_error_class(code),
+                                    entry >> 16
+                                );
+                            }
+                        }
+                        Err(e) => println!("{prefix}    Error history unavailable: {e}"),
+                    }
+                }
+
+                if args.no_watch {
+                    continue;
+                }
+                let node_filter = args.node_id;
+                let mut monitor = manager.emcy_monitor();
+                println!("{prefix}Watching for EMCY messages, press Ctrl-C to stop");
+                loop {
+                    tokio::select! {
+                        decoded = monitor.recv() => match decoded {
+                            Ok(decoded) => {
+                                if node_filter.is_none() || node_filter == Some(decoded.emcy.node) {
+                                    println!("{prefix}{}", format_emcy(&decoded));
+                                }
+                            }
+                            Err(_) => break,
+                        },
+                        _ = tokio::signal::ctrl_c() => break,
+                    }
+                }
+            }
+            Commands::Heartbeat(cmd) => match cmd {
+                HeartbeatCommands::Start { node_id, period_ms } => {
+                    // The ID is range checked by the parser
+                    let id = ConfiguredId::new(node_id).unwrap();
+                    manager.start_heartbeat(id, Duration::from_millis(period_ms));
+                    if period_ms == 0 {
+                        println!("{prefix}Heartbeat stopped");
+                    } else {
+                        println!(
+                            "{prefix}Sending heartbeat from node {node_id} every {period_ms} ms"
+                        );
+                    }
+                }
+                HeartbeatCommands::Period { period_ms } => {
+                    if manager.set_heartbeat_period(Duration::from_millis(period_ms)) {
+                        println!("{prefix}Heartbeat period set to {period_ms} ms");
+                    } else {
+                        println!("Heartbeat is not running");
+                    }
+                }
+                HeartbeatCommands::Stop => {
+                    if manager.stop_heartbeat() {
+                        println!("{prefix}Heartbeat stopped");
+                    } else {
+                        println!("Heartbeat is not running");
+                    }
+                }
+                HeartbeatCommands::Status => match manager.heartbeat() {
+                    Some(hb) => println!(
+                        "{prefix}Sending heartbeat from node {} every {} ms",
+                        hb.node_id.raw(),
+                        hb.period.as_millis()
+                    ),
+                    None => println!("{prefix}Heartbeat is not running"),
+                },
+            },
+            Commands::Selftest(args) => {
+                if NodeId::new(args.node_id).is_err() {
+                    println!("{} is not a valid node ID", args.node_id);
+                    continue;
+                }
+                let mut client = manager.sdo_client(args.node_id);
+                match client.run_loopback_test(Duration::from_millis(20)).await {
+                    Ok(status) => println!("{prefix}Node {} self-test {status}", args.node_id),
+                    Err(e) => println!("Error running self-test: {e}"),
+                }
+            }
+            Commands::Log(cmd) => {
+                let node_id = match cmd {
+                    LogCommands::Show { node_id }
+                    | LogCommands::Tail { node_id, .. }
+                    | LogCommands::Clear { node_id } => node_id,
+                };
+                if NodeId::new(node_id).is_err() {
+                    println!("{node_id} is not a valid node ID");
+                    continue;
+                }
+                match cmd {
+                    LogCommands::Show { .. } => {
+                        let mut client = manager.sdo_client(node_id);
+                        match client.read_debug_log().await {
+                            Ok(snapshot) => {
+                                let mut tail = DebugLogTail::new();
+                                print_log_text(&tail.update(&snapshot).text);
+                            }
+                            Err(e) => println!("Error reading debug log: {e}"),
+                        }
+                    }
+                    LogCommands::Clear { .. } => {
+                        let mut client = manager.sdo_client(node_id);
+                        match client.clear_debug_log().await {
+                            Ok(_) => println!("{prefix}Cleared debug log of node {node_id}"),
+                            Err(e) => println!("Error clearing debug log: {e}"),
+                        }
+                    }
+                    LogCommands::Tail { period_ms, .. } => {
+                        let period = Duration::from_millis(period_ms.max(1));
+                        let mut tail = DebugLogTail::new();
+                        println!(
+                            "{prefix}Tailing debug log of node {node_id}, press Ctrl-C to stop"
+                        );
+                        loop {
+                            let result = manager.sdo_client(node_id).read_debug_log().await;
+                            match result {
+                                Ok(snapshot) => {
+                                    let update = tail.update(&snapshot);
+                                    if update.reset {
+                                        println!("{prefix}-- log cleared --");
+                                    }
+                                    if update.missed > 0 {
+                                        println!("{prefix}-- {} bytes missed --", update.missed);
+                                    }
+                                    print_log_text(&update.text);
+                                }
+                                Err(e) => {
+                                    println!("Error reading debug log: {e}");
+                                    break;
+                                }
+                            }
+                            tokio::select! {
+                                _ = tokio::time::sleep(period) => (),
+                                _ = tokio::signal::ctrl_c() => break,
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Configure a socketcan interface, defaulting to the active interface
+#[cfg(all(target_os = "linux", feature = "netlink"))]
+fn run_link_command(cmd: &LinkCommands, active: &str) {
+    use zencan_cli::link::{link_down, link_status, link_up, LinkSettings};
+
+    let interface = match cmd {
+        LinkCommands::Up(args) => args.interface.as_str(),
+        LinkCommands::Down { interface } | LinkCommands::Status { interface } => {
+            interface.as_deref().unwrap_or(active)
+        }
+    };
+    if interface.starts_with("udp:") {
+        println!("{interface} is not a socketcan interface");
+        return;
+    }
+    let result = match cmd {
+        LinkCommands::Up(args) => {
+            let settings = LinkSettings {
+                bitrate: args.bitrate,
+                sample_point: args.sample_point,
+                data_bitrate: args.data_bitrate,
+                data_sample_point: args.data_sample_point,
+            };
+            link_up(interface, &settings).map(|_| println!("{interface} is up"))
         }
+        LinkCommands::Down { .. } => link_down(interface).map(|_| println!("{interface} is down")),
+        LinkCommands::Status { .. } => link_status(interface).map(|status| println!("{status}")),
+    };
+    if let Err(e) = result {
+        println!("{e}");
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "netlink")))]
+fn run_link_command(_cmd: &LinkCommands, _active: &str) {
+    println!("The link command requires Linux, and zencan-cli built with the netlink feature");
+}
+
+/// Print text read from a debug log, one line at a time
+fn print_log_text(text: &str) {
+    for line in text.lines() {
+        println!("{line}");
     }
 }
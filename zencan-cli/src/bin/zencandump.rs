@@ -1,15 +1,62 @@
-use clap::Parser;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use clap::{Parser, ValueEnum};
+use zencan_cli::{
+    clock::{Clock, CorrelationMarker, Timestamp},
+    timing::{IntervalSample, IntervalTracker},
+};
 use zencan_client::common::{
-    messages::{MessageError, ZencanMessage},
+    decode::{classify, CanOpenFrame},
+    messages::{MessageError, NmtState, ZencanMessage},
     traits::AsyncCanReceiver,
     CanMessage,
 };
 
 #[derive(Parser)]
 struct Args {
+    /// The CAN bus to monitor (e.g. 'can0' on Linux, or 'udp:<bind addr>,<peer addr>')
     socket: String,
     #[clap(short, long)]
     verbose: bool,
+    /// Output format
+    #[clap(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    /// Display a periodically refreshed table of nodes instead of individual frames
+    #[clap(long)]
+    nodes: bool,
+    /// Timestamp each frame with monotonic time, in seconds since the dump started, as well as
+    /// wall clock time
+    #[clap(long)]
+    monotonic: bool,
+    /// Print a marker recording the monotonic and wall clock time together every SECONDS, for
+    /// aligning the dump with logs from other systems
+    #[clap(long, value_name = "SECONDS")]
+    marker_period: Option<u64>,
+    /// Measure the period and jitter of SYNC, TIME, heartbeat and PDO frames, and annotate each
+    /// frame with its timing
+    #[clap(long)]
+    timing: bool,
+    /// With --timing, flag intervals which differ from the measured period by more than PERCENT
+    #[clap(
+        long,
+        value_name = "PERCENT",
+        default_value_t = 10.0,
+        requires = "timing"
+    )]
+    tolerance: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum OutputFormat {
+    /// Human readable text
+    Text,
+    /// One JSON object per line
+    Json,
+    /// Comma separated values, with a header row
+    Csv,
 }
 
 pub enum Message {
@@ -31,21 +78,316 @@ impl From<CanMessage> for Message {
     }
 }
 
+/// A structured description of a single received frame
+struct Record {
+    time: Timestamp,
+    /// Include the monotonic time in the output
+    monotonic: bool,
+    msg: CanMessage,
+    frame: CanOpenFrame,
+    /// The measured timing of the frame, if --timing is enabled
+    timing: Option<IntervalSample>,
+}
+
+impl Record {
+    fn new(time: Timestamp, monotonic: bool, msg: CanMessage) -> Self {
+        Self {
+            time,
+            monotonic,
+            msg,
+            frame: classify(msg),
+            timing: None,
+        }
+    }
+
+    /// Measure the timing of the frame with `tracker`
+    fn measure(mut self, tracker: &mut IntervalTracker) -> Self {
+        self.timing = tracker.observe(self.msg.id(), &self.frame, self.msg.receive_instant());
+        self
+    }
+
+    fn decoded(&self) -> String {
+        match &self.frame {
+            CanOpenFrame::Nmt(cmd) => cmd.to_string(),
+            CanOpenFrame::Heartbeat(hb) => hb.to_string(),
+            CanOpenFrame::Emcy(emcy) => format!("{emcy:?}"),
+            CanOpenFrame::SdoRequest { req, .. } => req.to_string(),
+            CanOpenFrame::SdoResponse { resp, .. } => resp.to_string(),
+            CanOpenFrame::LssRequest(req) => req.to_string(),
+            CanOpenFrame::LssResponse(resp) => resp.to_string(),
+            CanOpenFrame::Sync { counter } => match counter {
+                Some(count) => format!("Sync count={count}"),
+                None => "Sync".into(),
+            },
+            CanOpenFrame::Pdo(pdo) => format!("{:?}{}", pdo.direction, pdo.number),
+            CanOpenFrame::Malformed { reason, .. } => format!("{reason:?}"),
+            CanOpenFrame::Time(_) | CanOpenFrame::Unknown(_) => String::new(),
+        }
+    }
+
+    fn data_hex(&self) -> String {
+        self.msg
+            .data()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<Vec<_>>()
+            .join("")
+    }
+
+    fn to_json(&self) -> String {
+        let mut json = serde_json::json!({
+            "timestamp": self.time.wall_str(),
+            "id": self.msg.id().raw(),
+            "extended": self.msg.id().is_extended(),
+            "rtr": self.msg.is_rtr(),
+            "dlc": self.msg.dlc,
+            "data": self.data_hex(),
+            "type": self.frame.kind(),
+            "node": self.frame.node(),
+            "decoded": self.decoded(),
+        });
+        if self.monotonic {
+            json["monotonic"] = self.time.monotonic.as_secs_f64().into();
+        }
+        if let Some(timing) = &self.timing {
+            json["interval_us"] = (timing.interval.as_micros() as u64).into();
+            json["period_us"] = (timing.period.as_micros() as u64).into();
+            json["jitter_us"] = (timing.jitter.as_micros() as u64).into();
+            json["outlier"] = timing.outlier.into();
+        }
+        json.to_string()
+    }
+
+    const CSV_HEADER: &str = "timestamp,id,extended,rtr,dlc,data,type,node,decoded";
+    const CSV_HEADER_MONOTONIC: &str =
+        "timestamp,monotonic,id,extended,rtr,dlc,data,type,node,decoded";
+    /// Columns appended to the header with --timing
+    const CSV_TIMING_COLUMNS: &str = ",interval_us,period_us,jitter_us,outlier";
+
+    /// Format the timing columns of a CSV row, which are empty for frames without timing
+    fn csv_timing(&self) -> String {
+        match &self.timing {
+            Some(t) => format!(
+                ",{},{},{},{}",
+                t.interval.as_micros(),
+                t.period.as_micros(),
+                t.jitter.as_micros(),
+                t.outlier
+            ),
+            None => ",,,,".into(),
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        format!(
+            "{}{},{},{},{},{},{},{},{},{}",
+            csv_time(&self.time, self.monotonic),
+            self.msg.id().raw(),
+            self.msg.id().is_extended(),
+            self.msg.is_rtr(),
+            self.msg.dlc,
+            self.data_hex(),
+            self.frame.kind(),
+            self.frame.node().map(|n| n.to_string()).unwrap_or_default(),
+            csv_quote(&self.decoded()),
+        )
+    }
+}
+
+/// Summary of the traffic seen from a single node
+#[derive(Default)]
+struct NodeSummary {
+    nmt_state: Option<NmtState>,
+    last_heartbeat: Option<Instant>,
+    /// Exponentially smoothed interval between heartbeats
+    heartbeat_period: Option<Duration>,
+    last_emcy: Option<(Instant, String)>,
+    sdo_frames: u64,
+    last_sdo: Option<Instant>,
+}
+
+impl NodeSummary {
+    fn update(&mut self, record: &Record, now: Instant) {
+        match record.frame {
+            CanOpenFrame::Heartbeat(hb) => {
+                if let Some(last) = self.last_heartbeat {
+                    let interval = now - last;
+                    self.heartbeat_period = Some(match self.heartbeat_period {
+                        Some(p) => (p * 7 + interval) / 8,
+                        None => interval,
+                    });
+                }
+                self.last_heartbeat = Some(now);
+                self.nmt_state = Some(hb.state);
+            }
+            CanOpenFrame::Emcy(emcy) => {
+                self.last_emcy = Some((now, format!("{:04X}", emcy.error_code)))
+            }
+            CanOpenFrame::SdoRequest { .. } | CanOpenFrame::SdoResponse { .. } => {
+                self.sdo_frames += 1;
+                self.last_sdo = Some(now);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn age(t: Option<Instant>, now: Instant) -> String {
+    t.map(|t| format!("{:.1}s ago", (now - t).as_secs_f32()))
+        .unwrap_or("-".into())
+}
+
+/// Clear the terminal and print the node table
+fn draw_node_table(nodes: &BTreeMap<u8, NodeSummary>) {
+    let now = Instant::now();
+    print!("\x1b[2J\x1b[H");
+    println!(
+        "{:>4}  {:<15} {:>10} {:>12}  {:<28} {:>10} {:>12}",
+        "Node", "NMT State", "HB Period", "Last HB", "Last EMCY", "SDO Frames", "Last SDO"
+    );
+    for (id, node) in nodes {
+        let state = node.nmt_state.map(|s| s.to_string()).unwrap_or("-".into());
+        let period = node
+            .heartbeat_period
+            .map(|p| format!("{}ms", p.as_millis()))
+            .unwrap_or("-".into());
+        let emcy = node
+            .last_emcy
+            .as_ref()
+            .map(|(t, data)| format!("{data} ({})", age(Some(*t), now)))
+            .unwrap_or("-".into());
+        println!(
+            "{:>4}  {:<15} {:>10} {:>12}  {:<28} {:>10} {:>12}",
+            id,
+            state,
+            period,
+            age(node.last_heartbeat, now),
+            emcy,
+            node.sdo_frames,
+            age(node.last_sdo, now)
+        );
+    }
+}
+
+/// Quote a CSV field, escaping any embedded quotes
+fn csv_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+/// Format the leading time columns of a CSV row
+fn csv_time(time: &Timestamp, monotonic: bool) -> String {
+    if monotonic {
+        format!("{},{}", time.wall_str(), time.monotonic_str())
+    } else {
+        time.wall_str()
+    }
+}
+
+fn print_marker(marker: &CorrelationMarker, format: OutputFormat, monotonic: bool) {
+    match format {
+        OutputFormat::Text => println!("--- {marker} ---"),
+        OutputFormat::Json => println!("{}", marker.to_json()),
+        // Markers are rows with a type of "clock_marker", and the marker in the decoded column
+        OutputFormat::Csv => println!(
+            "{},,,,,,clock_marker,,{}",
+            csv_time(&marker.time, monotonic),
+            csv_quote(&marker.to_string())
+        ),
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
-    let (_tx, mut rx) = zencan_client::open_socketcan(&args.socket).unwrap();
+    let (_tx, mut rx) = zencan_client::open_transport(&args.socket).unwrap();
+    let mut clock = Clock::new();
+    if let Some(period) = args.marker_period {
+        clock = clock.with_marker_period(Duration::from_secs(period));
+    }
+
+    if args.nodes {
+        let mut nodes = BTreeMap::<u8, NodeSummary>::new();
+        let mut redraw = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            tokio::select! {
+                msg = rx.recv() => {
+                    if let Ok(msg) = msg {
+                        let record = Record::new(clock.now(), false, msg);
+                        let node = record.frame.node().filter(|n| (1..=127).contains(n));
+                        if let Some(node) = node {
+                            nodes.entry(node).or_default().update(&record, msg.receive_instant());
+                        }
+                    }
+                }
+                _ = redraw.tick() => draw_node_table(&nodes),
+            }
+        }
+    }
+
+    let mut tracker = args
+        .timing
+        .then(|| IntervalTracker::new(args.tolerance / 100.0));
+
+    if args.format == OutputFormat::Csv {
+        let header = if args.monotonic {
+            Record::CSV_HEADER_MONOTONIC
+        } else {
+            Record::CSV_HEADER
+        };
+        let timing = if args.timing {
+            Record::CSV_TIMING_COLUMNS
+        } else {
+            ""
+        };
+        println!("{header}{timing}");
+    }
 
     loop {
-        if let Ok(msg) = rx.recv().await {
-            let time = chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, false);
-
-            match msg.into() {
-                Message::Recognized(msg) => println!("{time}: {msg:?}"),
-                Message::Unrecognized { msg, reason } => {
-                    println!("{time}: {msg:?}");
-                    if args.verbose {
-                        println!("Unrecognized reason: {reason:?}");
+        if let Some(marker) = clock.poll_marker() {
+            print_marker(&marker, args.format, args.monotonic);
+        }
+        let msg = match clock.until_marker() {
+            Some(wait) => match tokio::time::timeout(wait, rx.recv()).await {
+                Ok(msg) => msg,
+                // A marker is due
+                Err(_) => continue,
+            },
+            None => rx.recv().await,
+        };
+
+        if let Ok(msg) = msg {
+            // Prefer the time the frame was received by the kernel over the time it is printed
+            let time = clock.stamp(msg.receive_instant(), msg.receive_time());
+            let time_str = if args.monotonic {
+                time.to_string()
+            } else {
+                time.wall_str()
+            };
+
+            let mut record = Record::new(time, args.monotonic, msg);
+            if let Some(tracker) = &mut tracker {
+                record = record.measure(tracker);
+            }
+
+            match args.format {
+                OutputFormat::Json => println!("{}", record.to_json()),
+                OutputFormat::Csv if args.timing => {
+                    println!("{}{}", record.to_csv(), record.csv_timing())
+                }
+                OutputFormat::Csv => println!("{}", record.to_csv()),
+                OutputFormat::Text => {
+                    let timing = record
+                        .timing
+                        .map(|t| format!("  [{t}]"))
+                        .unwrap_or_default();
+                    match msg.into() {
+                        Message::Recognized(msg) => println!("{time_str}: {msg}{timing}"),
+                        Message::Unrecognized { msg, reason } => {
+                            println!("{time_str}: {msg}{timing}");
+                            if args.verbose {
+                                println!("Unrecognized reason: {reason:?}");
+                            }
+                        }
                     }
                 }
             }
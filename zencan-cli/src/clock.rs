@@ -0,0 +1,233 @@
+//! Timestamps for correlating bus captures with logs from other systems
+//!
+//! Wall clock time alone is not enough to align a capture with another system's logs after an
+//! incident: the RTC may be stepped by NTP during the capture, and the other system's clock may
+//! not agree with this one. A [`Clock`] stamps each event with both a monotonic time, counted
+//! from when the clock was created, and the wall clock time, and can produce periodic
+//! [`CorrelationMarker`]s recording the pair at a single instant. A step of the wall clock shows up
+//! as a change in the offset between the two times from one marker to the next.
+
+use std::{
+    fmt::Display,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Local, SecondsFormat};
+
+/// A point in time, as both monotonic and wall clock time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timestamp {
+    /// Time since the [`Clock`] was created
+    pub monotonic: Duration,
+    /// Wall clock time
+    pub wall: DateTime<Local>,
+}
+
+impl Timestamp {
+    /// Format the monotonic time in seconds, with microsecond resolution
+    pub fn monotonic_str(&self) -> String {
+        format!(
+            "{}.{:06}",
+            self.monotonic.as_secs(),
+            self.monotonic.subsec_micros()
+        )
+    }
+
+    /// Format the wall clock time as RFC 3339, with microsecond resolution
+    pub fn wall_str(&self) -> String {
+        self.wall.to_rfc3339_opts(SecondsFormat::Micros, false)
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} [{}]", self.wall_str(), self.monotonic_str())
+    }
+}
+
+/// A record of the monotonic and wall clock time at the same instant
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CorrelationMarker {
+    /// The time of the marker
+    pub time: Timestamp,
+    /// Counts up from 0 for each marker produced by a [`Clock`]
+    pub sequence: u64,
+}
+
+impl CorrelationMarker {
+    /// The wall clock time as microseconds since the UNIX epoch
+    pub fn unix_us(&self) -> i64 {
+        self.time.wall.timestamp_micros()
+    }
+
+    /// Format the marker as a single JSON object
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"marker\":{},\"monotonic\":{},\"wall\":\"{}\",\"unix_us\":{}}}",
+            self.sequence,
+            self.time.monotonic_str(),
+            self.time.wall_str(),
+            self.unix_us()
+        )
+    }
+}
+
+impl Display for CorrelationMarker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "clock marker {}: monotonic={} wall={} unix_us={}",
+            self.sequence,
+            self.time.monotonic_str(),
+            self.time.wall_str(),
+            self.unix_us()
+        )
+    }
+}
+
+/// Produces [`Timestamp`]s relative to a common start time
+#[derive(Debug)]
+pub struct Clock {
+    start: Instant,
+    marker_period: Option<Duration>,
+    next_marker: Instant,
+    marker_sequence: u64,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock {
+    /// Create a clock, with monotonic time starting from now
+    pub fn new() -> Self {
+        let start = Instant::now();
+        Self {
+            start,
+            marker_period: None,
+            next_marker: start,
+            marker_sequence: 0,
+        }
+    }
+
+    /// Produce a correlation marker every `period` from [`poll_marker`](Self::poll_marker)
+    ///
+    /// The first marker is produced immediately.
+    pub fn with_marker_period(mut self, period: Duration) -> Self {
+        self.marker_period = Some(period);
+        self
+    }
+
+    /// The period at which correlation markers are produced, if enabled
+    pub fn marker_period(&self) -> Option<Duration> {
+        self.marker_period
+    }
+
+    /// Get the current time
+    pub fn now(&self) -> Timestamp {
+        self.stamp(Instant::now(), None)
+    }
+
+    /// Get the timestamp of an event which happened at `instant`
+    ///
+    /// If the wall clock time of the event is known, e.g. from a kernel receive timestamp, it can
+    /// be passed as `wall`. Otherwise it is calculated from the current wall clock time.
+    pub fn stamp(&self, instant: Instant, wall: Option<SystemTime>) -> Timestamp {
+        stamp(self.start, instant, wall)
+    }
+
+    /// Get a correlation marker, if one is due
+    pub fn poll_marker(&mut self) -> Option<CorrelationMarker> {
+        let period = self.marker_period?;
+        let now = Instant::now();
+        if now < self.next_marker {
+            return None;
+        }
+        // Skip any markers which were missed, rather than producing them in a burst
+        while self.next_marker <= now {
+            self.next_marker += period.max(Duration::from_millis(1));
+        }
+        let marker = CorrelationMarker {
+            time: self.stamp(now, None),
+            sequence: self.marker_sequence,
+        };
+        self.marker_sequence += 1;
+        Some(marker)
+    }
+
+    /// Get the time until the next correlation marker is due, if enabled
+    pub fn until_marker(&self) -> Option<Duration> {
+        self.marker_period?;
+        Some(self.next_marker.saturating_duration_since(Instant::now()))
+    }
+}
+
+fn stamp(start: Instant, instant: Instant, wall: Option<SystemTime>) -> Timestamp {
+    let wall = wall.unwrap_or_else(|| {
+        let age = Instant::now().saturating_duration_since(instant);
+        SystemTime::now().checked_sub(age).unwrap_or(UNIX_EPOCH)
+    });
+    Timestamp {
+        monotonic: instant.saturating_duration_since(start),
+        wall: wall.into(),
+    }
+}
+
+/// Initialize env_logger to prefix each log message with both monotonic and wall clock time
+///
+/// Use the same [`Clock`] start time for dumps and logs, so that the monotonic times agree.
+pub fn init_logger(clock: &Clock) {
+    use std::io::Write;
+
+    let start = clock.start;
+    env_logger::Builder::from_default_env()
+        .format(move |buf, record| {
+            let time = stamp(start, Instant::now(), None);
+            writeln!(
+                buf,
+                "{} [{}] {:<5} {}: {}",
+                time.wall_str(),
+                time.monotonic_str(),
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        })
+        .init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stamp() {
+        let clock = Clock::new();
+        let instant = clock.start + Duration::from_micros(1_500_250);
+        let wall = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let time = clock.stamp(instant, Some(wall));
+        assert_eq!("1.500250", time.monotonic_str());
+        assert_eq!(1_700_000_000_000_000, time.wall.timestamp_micros());
+
+        // Times before the clock was created saturate to 0
+        let time = clock.stamp(clock.start - Duration::from_secs(1), Some(wall));
+        assert_eq!(Duration::ZERO, time.monotonic);
+    }
+
+    #[test]
+    fn test_markers() {
+        let mut clock = Clock::new();
+        assert_eq!(None, clock.poll_marker());
+        assert_eq!(None, clock.until_marker());
+
+        let mut clock = clock.with_marker_period(Duration::from_secs(60));
+        let marker = clock.poll_marker().unwrap();
+        assert_eq!(0, marker.sequence);
+        assert!(marker.to_json().starts_with("{\"marker\":0,"));
+        // The next marker is not due for another period
+        assert_eq!(None, clock.poll_marker());
+        assert!(clock.until_marker().unwrap() > Duration::from_secs(59));
+    }
+}
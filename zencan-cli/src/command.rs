@@ -1,7 +1,12 @@
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use clap_num::maybe_hex;
 use std::{path::PathBuf, str::FromStr};
-use zencan_client::common::lss::LssIdentity;
+use zencan_client::{
+    common::{lss::LssIdentity, objects::DataType, value::parse_hex_bytes},
+    IdentityMatch,
+};
+
+use crate::alias::{parse_configured_node_id, parse_node_id};
 
 #[derive(Debug, Parser)]
 pub struct Cli {
@@ -15,24 +20,81 @@ pub enum Commands {
     Read(ReadArgs),
     /// Write an object via SDO
     Write(WriteArgs),
+    /// Upload the contents of an object to a file, e.g. to retrieve a log
+    GetFile(FileArgs),
+    /// Download the contents of a file to an object, e.g. to upload an asset
+    PutFile(FileArgs),
     /// Scan all node IDs to find configured devices
-    Scan,
+    Scan(ScanArgs),
     /// Print info about nodes
     Info,
+    /// Print the PDO links between known nodes, as JSON or graphviz DOT
+    Topology(TopologyArgs),
     /// Load a configuration from a file to a node
     LoadConfig(LoadConfigArgs),
+    /// Load configurations rendered from templates to every node listed in a fleet file
+    LoadFleet(LoadFleetArgs),
+    /// Check node configuration files, without loading them to a node
+    #[command(subcommand)]
+    Config(ConfigCommands),
     /// Send command to save persistable objects
     SaveObjects(SaveObjectsArgs),
+    /// Program a firmware image into every node matching an identity pattern
+    FlashAll(FlashAllArgs),
+    /// Show node error registers and error history, and monitor EMCY messages
+    Errors(ErrorsArgs),
+    /// Read the text log of a node
+    #[command(subcommand)]
+    Log(LogCommands),
+    /// Run a node's loopback self-test, to check its transceiver and bus wiring
+    Selftest(SelftestArgs),
+    /// Measure SDO latency and throughput to a node
+    Bench(BenchArgs),
+    /// Show or set the pacing of SDO frames sent to a slow node
+    Pace(PaceArgs),
+    /// Send a single arbitrary CAN frame
+    Send(SendArgs),
+    /// Send an arbitrary CAN frame periodically, or stop all periodic frames
+    Gen(GenArgs),
     /// NMT commands
     Nmt(NmtArgs),
+    /// Send optional safe state frames, then broadcast an NMT stop to all nodes
+    Estop(EstopArgs),
     /// LSS commands
     #[command(subcommand)]
     Lss(LssCommands),
+    /// Send a heartbeat from this manager, for nodes which monitor the master
+    #[command(subcommand)]
+    Heartbeat(HeartbeatCommands),
+    /// Attach a device config file, to enable completion of object names, and display of values
+    /// in their units
+    AttachOd(AttachOdArgs),
+    /// Name a node, so that the name can be used in place of its node ID, or list the aliases
+    Alias(AliasArgs),
+    /// Remove the alias of a node
+    Unalias(UnaliasArgs),
+    /// Save the interfaces, attached device config and aliases of this session, or restore them
+    #[command(subcommand)]
+    Session(SessionCommands),
+    /// Set the bit rate of a socketcan interface, bring it up or down, or show its status
+    #[command(subcommand)]
+    Link(LinkCommands),
+    /// Open an additional CAN interface, and make it the active interface
+    Open(OpenArgs),
+    /// Select the active CAN interface, or list the open interfaces
+    Use(UseArgs),
+    /// Record the frames sent and received on the active interface to a file, for use as a test
+    /// fixture
+    #[command(subcommand)]
+    Record(RecordCommands),
+    /// List the plugin commands found on the PATH, i.e. executables named 'zencan-cli-<command>'
+    Plugins,
 }
 
 #[derive(Debug, Args)]
 pub struct ReadArgs {
     /// The ID of the node to read from
+    #[clap(value_parser = parse_node_id)]
     pub node_id: u8,
     /// The object index to read
     #[clap(value_parser=maybe_hex::<u16>)]
@@ -40,53 +102,566 @@ pub struct ReadArgs {
     /// The sub object to read
     #[clap(value_parser=maybe_hex::<u8>)]
     pub sub: u8,
-    /// How to interpret the response (optional)
+    /// Literal 'as', followed by the format
+    #[clap(value_name = "as", value_parser = ["as"], requires = "data_type")]
+    pub as_keyword: Option<String>,
+    /// How to interpret the response (optional). Objects with a unit in the attached device
+    /// config are interpreted by their data type by default, and shown in the unit.
+    #[clap(requires = "as_keyword")]
     pub data_type: Option<SdoDataType>,
 }
 
-#[derive(Clone, Copy, Debug, ValueEnum)]
+/// Format specifiers for encoding and decoding SDO values
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
 pub enum SdoDataType {
+    U64,
     U32,
     U16,
     U8,
+    I64,
     I32,
     I16,
     I8,
+    F64,
     F32,
-    Utf8,
+    #[value(alias = "utf8")]
+    Str,
+    /// Raw bytes, as a hex string
+    Hex,
+}
+
+impl From<SdoDataType> for DataType {
+    fn from(value: SdoDataType) -> Self {
+        match value {
+            SdoDataType::U64 => DataType::UInt64,
+            SdoDataType::U32 => DataType::UInt32,
+            SdoDataType::U16 => DataType::UInt16,
+            SdoDataType::U8 => DataType::UInt8,
+            SdoDataType::I64 => DataType::Int64,
+            SdoDataType::I32 => DataType::Int32,
+            SdoDataType::I16 => DataType::Int16,
+            SdoDataType::I8 => DataType::Int8,
+            SdoDataType::F64 => DataType::Real64,
+            SdoDataType::F32 => DataType::Real32,
+            SdoDataType::Str => DataType::VisibleString,
+            SdoDataType::Hex => DataType::OctetString,
+        }
+    }
+}
+
+impl SdoDataType {
+    /// Guess a format for writing `value` to an object whose current value is `current_size` bytes
+    ///
+    /// Integers are sized to match the object, and are signed if the value is negative. Values with
+    /// a decimal point are written as floats, and anything else as a string.
+    pub fn infer(value: &str, current_size: usize) -> SdoDataType {
+        let is_int = value.parse::<i64>().is_ok()
+            || value
+                .strip_prefix("0x")
+                .is_some_and(|hex| u64::from_str_radix(hex, 16).is_ok());
+        if is_int {
+            let signed = value.starts_with('-');
+            match (current_size, signed) {
+                (1, false) => SdoDataType::U8,
+                (1, true) => SdoDataType::I8,
+                (2, false) => SdoDataType::U16,
+                (2, true) => SdoDataType::I16,
+                (8, false) => SdoDataType::U64,
+                (8, true) => SdoDataType::I64,
+                (_, false) => SdoDataType::U32,
+                (_, true) => SdoDataType::I32,
+            }
+        } else if value.parse::<f64>().is_ok() {
+            if current_size == 8 {
+                SdoDataType::F64
+            } else {
+                SdoDataType::F32
+            }
+        } else {
+            SdoDataType::Str
+        }
+    }
 }
 
 #[derive(Debug, Args)]
 pub struct WriteArgs {
-    /// The ID of the node to read from
+    /// The ID of the node to write to
+    #[clap(value_parser = parse_node_id)]
     pub node_id: u8,
-    /// The object index to read
+    /// The object index to write
     #[clap(value_parser=maybe_hex::<u16>)]
     pub index: u16,
-    /// The sub object to read
+    /// The sub object to write
     #[clap(value_parser=maybe_hex::<u8>)]
     pub sub: u8,
-    /// How to interpret the value
-    pub data_type: SdoDataType,
     /// The value to write
+    #[clap(allow_hyphen_values = true)]
     pub value: String,
+    /// Literal 'as', followed by the format
+    #[clap(value_name = "as", value_parser = ["as"], requires = "data_type")]
+    pub as_keyword: Option<String>,
+    /// How to encode the value. If omitted, it is inferred from the value and the current size of
+    /// the object
+    #[clap(requires = "as_keyword")]
+    pub data_type: Option<SdoDataType>,
+    /// Read the value back after writing, and report an error if it does not match
+    #[clap(long)]
+    pub verify: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct FileArgs {
+    /// The ID of the node
+    #[clap(value_parser = parse_node_id)]
+    pub node_id: u8,
+    /// The object index
+    #[clap(value_parser=maybe_hex::<u16>)]
+    pub index: u16,
+    /// The sub object
+    #[clap(value_parser=maybe_hex::<u8>)]
+    pub sub: u8,
+    /// Path to the local file
+    #[arg(value_hint=clap::ValueHint::FilePath)]
+    pub path: PathBuf,
+    /// Number of times to restart the transfer after a timeout
+    #[clap(long, default_value_t = 2)]
+    pub retries: u32,
+}
+
+#[derive(Debug, Args)]
+pub struct BenchArgs {
+    /// The ID of the node to measure
+    #[clap(value_parser = parse_node_id)]
+    pub node_id: u8,
+    /// Number of expedited reads used to measure latency
+    #[clap(long, default_value_t = 100)]
+    pub count: usize,
+    /// Object to write and read back to measure throughput, e.g. a domain. Its contents are
+    /// overwritten. If omitted, only latency is measured.
+    #[clap(long, value_parser=maybe_hex::<u16>)]
+    pub index: Option<u16>,
+    /// The sub object to use for measuring throughput
+    #[clap(long, value_parser=maybe_hex::<u8>, default_value_t = 0)]
+    pub sub: u8,
+    /// Payload sizes to measure throughput for, in bytes
+    #[clap(long, value_delimiter = ',', default_value = "64,256,1024")]
+    pub sizes: Vec<usize>,
+    /// Number of transfers timed for each payload size and protocol
+    #[clap(long, default_value_t = 10)]
+    pub rounds: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct SendArgs {
+    /// The CAN ID. IDs above 0x7FF are sent as extended IDs.
+    #[clap(value_parser=maybe_hex::<u32>)]
+    pub id: u32,
+    /// Up to 8 data bytes
+    #[clap(value_parser=maybe_hex::<u8>, num_args = 0..=8)]
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct GenArgs {
+    #[command(subcommand)]
+    pub action: Option<GenAction>,
+    /// The CAN ID. IDs above 0x7FF are sent as extended IDs.
+    #[clap(value_parser=maybe_hex::<u32>, required = true)]
+    pub id: Option<u32>,
+    /// Up to 8 data bytes, followed by literal 'every'
+    // The length is checked when the command runs: if clap stopped at 8 values, 'every' would be
+    // parsed as the period
+    #[clap(value_parser=maybe_hex::<u8>, num_args = 0.., value_terminator = "every")]
+    pub data: Vec<u8>,
+    /// The period between frames, in milliseconds
+    #[clap(value_parser = clap::value_parser!(u64).range(1..), required = true)]
+    pub period_ms: Option<u64>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum GenAction {
+    /// Stop all periodic frames started with 'gen'
+    Stop,
+}
+
+#[derive(Debug, Args)]
+pub struct EstopArgs {
+    /// A frame to send before stopping, e.g. an RPDO commanding a node's outputs off, as
+    /// 'ID:DATA' with the data in hex, e.g. '0x205:0000'. May be given more than once.
+    #[clap(long = "frame", value_parser = parse_frame)]
+    pub frames: Vec<(u32, Vec<u8>)>,
+    /// Time to wait after sending the frames, for the nodes to process them, in milliseconds
+    #[clap(long, default_value_t = 10)]
+    pub settle_ms: u64,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LinkCommands {
+    /// Set the bit timing of an interface, and bring it up
+    Up(LinkUpArgs),
+    /// Bring an interface down
+    Down {
+        /// The socketcan interface. Defaults to the active interface.
+        interface: Option<String>,
+    },
+    /// Show the state, bit timing and error counters of an interface
+    Status {
+        /// The socketcan interface. Defaults to the active interface.
+        interface: Option<String>,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct LinkUpArgs {
+    /// The socketcan interface, e.g. 'can0'
+    pub interface: String,
+    /// The nominal bit rate, in bits/s
+    pub bitrate: u32,
+    /// The nominal sample point, as a fraction of the bit time, e.g. 0.875. If omitted, the driver
+    /// chooses it.
+    #[clap(long, value_parser = parse_sample_point)]
+    pub sample_point: Option<u32>,
+    /// Enable CAN FD, with this data phase bit rate in bits/s
+    #[clap(long)]
+    pub data_bitrate: Option<u32>,
+    /// The data phase sample point, as a fraction of the bit time
+    #[clap(long, value_parser = parse_sample_point, requires = "data_bitrate")]
+    pub data_sample_point: Option<u32>,
+}
+
+/// Parse a sample point given as a fraction, e.g. '0.875', into tenths of a percent
+pub fn parse_sample_point(s: &str) -> Result<u32, String> {
+    let value: f64 = s.parse().map_err(|_| format!("'{s}' is not a number"))?;
+    if !(value > 0.0 && value < 1.0) {
+        return Err(format!(
+            "Sample point must be a fraction between 0 and 1, e.g. 0.875, got '{s}'"
+        ));
+    }
+    Ok((value * 1000.0).round() as u32)
+}
+
+/// Parse a frame of the form 'ID:DATA', where the data is a string of hex bytes
+pub fn parse_frame(s: &str) -> Result<(u32, Vec<u8>), String> {
+    let (id, data) = s
+        .split_once(':')
+        .ok_or_else(|| format!("Expected 'ID:DATA', got '{s}'"))?;
+    let id = maybe_hex::<u32>(id)?;
+    if id > 0x1FFF_FFFF {
+        return Err(format!("0x{id:X} does not fit in a 29-bit CAN ID"));
+    }
+    let data =
+        parse_hex_bytes(data).ok_or_else(|| format!("'{data}' is not a string of hex bytes"))?;
+    if data.len() > 8 {
+        return Err(format!(
+            "A CAN frame holds at most 8 bytes, got {}",
+            data.len()
+        ));
+    }
+    Ok((id, data))
 }
 
 #[derive(Debug, Args)]
 pub struct LoadConfigArgs {
     /// The ID of the node to load the configuration into
+    #[clap(value_parser = parse_node_id)]
     pub node_id: u8,
-    /// Path to a node config TOML file
+    /// Path to a node config TOML file, which may be a template
+    #[arg(value_hint=clap::ValueHint::FilePath)]
+    pub path: PathBuf,
+    /// Read back every setting after writing it, and stop at the first which does not match
+    #[clap(long)]
+    pub verify: bool,
+    /// Set a template variable, e.g. 'axis=2'. May be given more than once.
+    #[clap(long = "var", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+}
+
+/// Parse a template variable assignment of the form 'name=value'
+pub fn parse_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("Expected 'name=value', got '{s}'")),
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct LoadFleetArgs {
+    /// Path to a fleet TOML file, listing nodes and the template and variables for each
+    #[arg(value_hint=clap::ValueHint::FilePath)]
+    pub path: PathBuf,
+    /// Read back every setting after writing it, and stop at the first which does not match
+    #[clap(long)]
+    pub verify: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct OpenArgs {
+    /// The CAN bus to open (e.g. 'can1' on Linux, or 'udp:<bind addr>,<peer addr>')
+    pub interface: String,
+}
+
+#[derive(Debug, Args)]
+pub struct UseArgs {
+    /// The interface to make active. If omitted, the open interfaces are listed.
+    pub interface: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct AttachOdArgs {
+    /// Path to a device config TOML file
     #[arg(value_hint=clap::ValueHint::FilePath)]
     pub path: PathBuf,
 }
 
+#[derive(Debug, Args)]
+pub struct AliasArgs {
+    /// The ID of the node to name. If omitted, all aliases are listed.
+    #[clap(value_parser = parse_node_id, requires = "name")]
+    pub node_id: Option<u8>,
+    /// The alias, made of letters, digits, '-' and '_'. A node which already has an alias is
+    /// renamed.
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct UnaliasArgs {
+    /// The alias, or ID, of the node
+    #[clap(value_parser = parse_node_id)]
+    pub node_id: u8,
+}
+
+#[derive(Debug, Args)]
+pub struct TopologyArgs {
+    /// Print a graphviz DOT digraph instead of JSON
+    #[clap(long)]
+    pub dot: bool,
+    /// Write the output to a file instead of printing it
+    #[arg(long, value_hint=clap::ValueHint::FilePath)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ScanArgs {
+    /// Only probe node IDs which are not currently sending heartbeats
+    #[clap(short, long)]
+    pub incremental: bool,
+    /// Only read the device type and identity objects from each node
+    #[clap(short, long)]
+    pub probe: bool,
+    /// Time to wait for each SDO response, in milliseconds
+    #[clap(short, long, default_value_t = 100)]
+    pub timeout: u64,
+    /// Number of node IDs to probe concurrently
+    #[clap(long, default_value_t = 10)]
+    pub parallel: usize,
+    /// Minimum time between starting the probes of consecutive node IDs, in milliseconds
+    #[clap(long, default_value_t = 0)]
+    pub stagger: u64,
+    /// Also probe the capabilities of each node found, e.g. block transfer and LSS support
+    #[clap(short, long)]
+    pub capabilities: bool,
+}
+
 #[derive(Debug, Args)]
 pub struct SaveObjectsArgs {
     /// The ID of the node to command
+    #[clap(value_parser = parse_node_id)]
     pub node_id: u8,
 }
 
+#[derive(Debug, Args)]
+pub struct SelftestArgs {
+    /// The ID of the node to test
+    #[clap(value_parser = parse_node_id)]
+    pub node_id: u8,
+}
+
+#[derive(Debug, Args)]
+pub struct FlashAllArgs {
+    /// Identity fields nodes must match to be updated, e.g. 'vendor=0xCAFE,product=1032'
+    ///
+    /// Fields are vendor, product, revision and serial.
+    #[clap(long = "match", value_parser = parse_identity_match)]
+    pub pattern: IdentityMatch,
+    /// Path to the firmware image
+    #[arg(long, value_hint=clap::ValueHint::FilePath)]
+    pub file: PathBuf,
+    /// The bootloader section to program
+    #[clap(long, default_value_t = 0)]
+    pub section: u8,
+    /// Number of nodes to update concurrently
+    #[clap(long, default_value_t = 1)]
+    pub parallel: usize,
+    /// Time to wait for a node to restart after each reset, in milliseconds
+    #[clap(long, default_value_t = 10000)]
+    pub reboot_timeout: u64,
+    /// Report a failure for any node which does not run this software version after the update
+    #[clap(long)]
+    pub expect_version: Option<String>,
+}
+
+/// Parse a comma separated list of `field=value` pairs into an identity pattern
+pub fn parse_identity_match(s: &str) -> Result<IdentityMatch, String> {
+    let mut pattern = IdentityMatch::any();
+    for field in s.split(',') {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or_else(|| format!("Expected field=value, got '{field}'"))?;
+        let value = maybe_hex::<u32>(value.trim())?;
+        pattern = match key.trim() {
+            "vendor" | "vendor_id" => pattern.vendor_id(value),
+            "product" | "product_code" => pattern.product_code(value),
+            "revision" => pattern.revision(value),
+            "serial" => pattern.serial(value),
+            other => return Err(format!("Unknown identity field '{other}'")),
+        };
+    }
+    Ok(pattern)
+}
+
+#[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct ErrorsArgs {
+    #[command(subcommand)]
+    pub action: Option<ErrorsAction>,
+    /// The node to show errors for. If omitted, all known nodes are shown.
+    #[clap(value_parser = parse_node_id)]
+    pub node_id: Option<u8>,
+    /// Show the current errors, without waiting for EMCY messages
+    #[clap(long)]
+    pub no_watch: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ErrorsAction {
+    /// Clear the error history (0x1003) of a node
+    Clear {
+        /// The ID of the node to clear
+        #[clap(value_parser = parse_node_id)]
+        node_id: u8,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LogCommands {
+    /// Print the buffered text of a node's debug log (0x5004)
+    Show {
+        /// The ID of the node to read
+        #[clap(value_parser = parse_node_id)]
+        node_id: u8,
+    },
+    /// Print text as it is added to a node's debug log, until Ctrl-C is pressed
+    Tail {
+        /// The ID of the node to read
+        #[clap(value_parser = parse_node_id)]
+        node_id: u8,
+        /// How often to read the log, in milliseconds
+        #[clap(long, default_value = "250")]
+        period_ms: u64,
+    },
+    /// Clear a node's debug log
+    Clear {
+        /// The ID of the node to clear
+        #[clap(value_parser = parse_node_id)]
+        node_id: u8,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommands {
+    /// Check a node config file, and report every problem in it
+    Lint(LintArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct LintArgs {
+    /// Path to a node config TOML file, which may be a template
+    #[arg(value_hint=clap::ValueHint::FilePath)]
+    pub path: PathBuf,
+    /// Report unknown keys as warnings, rather than errors
+    #[clap(long)]
+    pub lenient: bool,
+    /// The node ID used to render a template
+    #[clap(long, default_value_t = 1, value_parser = parse_configured_node_id)]
+    pub node_id: u8,
+    /// Set a template variable, e.g. 'axis=2'. May be given more than once.
+    #[clap(long = "var", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RecordCommands {
+    /// Start recording. The frames are written to the file when the recording is stopped.
+    Start {
+        /// The file to write the recording to
+        path: PathBuf,
+    },
+    /// Stop recording, and write the recorded frames to the file
+    Stop,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SessionCommands {
+    /// Save the open interfaces, attached device config and aliases under a name
+    Save {
+        /// The name of the session
+        name: String,
+    },
+    /// Open the interfaces, attach the device config and apply the aliases of a saved session
+    Load {
+        /// The name of the session
+        name: String,
+    },
+    /// List the saved sessions
+    List,
+    /// Remove a saved session
+    Delete {
+        /// The name of the session
+        name: String,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct PaceArgs {
+    /// The ID of the node to pace
+    #[clap(value_parser = parse_configured_node_id)]
+    pub node_id: u8,
+    /// The maximum number of frames sent to the node per second
+    #[clap(long)]
+    pub max_fps: Option<u32>,
+    /// The minimum time between frames sent to the node, in microseconds
+    #[clap(long)]
+    pub min_gap_us: Option<u32>,
+    /// Remove the pacing, and send frames to the node without delay
+    #[clap(long, conflicts_with_all = ["max_fps", "min_gap_us"])]
+    pub clear: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum HeartbeatCommands {
+    /// Start sending a heartbeat, replacing any running heartbeat
+    Start {
+        /// The node ID to send the heartbeat from. It must not be used by any node on the bus.
+        #[clap(value_parser = clap::value_parser!(u8).range(1..=127))]
+        node_id: u8,
+        /// The time between heartbeats, in milliseconds
+        #[clap(long, default_value = "1000")]
+        period_ms: u64,
+    },
+    /// Change the period of the running heartbeat
+    Period {
+        /// The time between heartbeats, in milliseconds
+        period_ms: u64,
+    },
+    /// Stop sending the heartbeat
+    Stop,
+    /// Show whether the heartbeat is running
+    Status,
+}
+
 /// Specifies a node to apply an NMT command
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum NmtNodeArg {
@@ -119,8 +694,10 @@ impl FromStr for NmtNodeArg {
             Err(_) => {
                 if s == "all" {
                     Ok(Self::All)
+                } else if let Ok(id) = parse_node_id(s) {
+                    Ok(Self::Specific(id))
                 } else {
-                    Err("Must specify a node ID, or 'all' to broadcast")
+                    Err("Must specify a node ID or alias, or 'all' to broadcast")
                 }
             }
         }
@@ -130,7 +707,7 @@ impl FromStr for NmtNodeArg {
 #[derive(Debug, Args)]
 pub struct NmtArgs {
     pub action: NmtAction,
-    /// Specify the node ID to command. Use '0' or 'all' to broadcast to all nodes.
+    /// Specify the node ID or alias to command. Use '0' or 'all' to broadcast to all nodes.
     pub node: NmtNodeArg,
 }
 
@@ -186,8 +763,19 @@ pub enum LssCommands {
         #[arg(default_value = "5")]
         timeout: u64,
     },
+    /// Find all unconfigured nodes, and assign them IDs from a range
+    AutoAssign {
+        /// The first node ID which may be assigned
+        first: u8,
+        /// The last node ID which may be assigned
+        last: u8,
+        /// Timeout for waiting for fastscan response in milliseconds
+        #[arg(default_value = "5")]
+        timeout: u64,
+    },
     SetNodeId {
         /// The node ID to assign
+        #[clap(value_parser = parse_node_id)]
         node_id: u8,
         #[clap(flatten)]
         identity: Option<IdentityArgs>,
@@ -203,3 +791,404 @@ pub enum LssCommands {
         enable: u8,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Commands {
+        Cli::try_parse_from(std::iter::once("").chain(line.split_whitespace()))
+            .unwrap()
+            .command
+    }
+
+    #[test]
+    fn test_read_format() {
+        let Commands::Read(args) = parse("read 1 0x2000 1 as i16") else {
+            panic!("Wrong command");
+        };
+        assert_eq!(Some(SdoDataType::I16), args.data_type);
+
+        let Commands::Read(args) = parse("read 1 0x2000 1") else {
+            panic!("Wrong command");
+        };
+        assert_eq!(None, args.data_type);
+    }
+
+    #[test]
+    fn test_write_format() {
+        let Commands::Write(args) = parse("write 1 0x2000 1 -12 as i32") else {
+            panic!("Wrong command");
+        };
+        assert_eq!("-12", args.value);
+        assert_eq!(Some(SdoDataType::I32), args.data_type);
+    }
+
+    #[test]
+    fn test_file_args() {
+        let Commands::GetFile(args) = parse("get-file 3 0x3000 1 log.bin") else {
+            panic!("Wrong command");
+        };
+        assert_eq!(3, args.node_id);
+        assert_eq!(0x3000, args.index);
+        assert_eq!(PathBuf::from("log.bin"), args.path);
+        assert_eq!(2, args.retries);
+
+        let Commands::PutFile(args) = parse("put-file 3 0x3001 0 asset.bin --retries 5") else {
+            panic!("Wrong command");
+        };
+        assert_eq!(0, args.sub);
+        assert_eq!(5, args.retries);
+    }
+
+    #[test]
+    fn test_config_lint_args() {
+        let Commands::Config(ConfigCommands::Lint(args)) =
+            parse("config lint drive.toml --lenient --node-id 3 --var axis=2")
+        else {
+            panic!("Wrong command");
+        };
+        assert_eq!(PathBuf::from("drive.toml"), args.path);
+        assert!(args.lenient);
+        assert_eq!(3, args.node_id);
+        assert_eq!(vec![("axis".to_string(), "2".to_string())], args.vars);
+    }
+
+    #[test]
+    fn test_load_config_vars() {
+        let Commands::LoadConfig(args) =
+            parse("load-config 3 drive.toml --var axis=2 --var name=x=y")
+        else {
+            panic!("Wrong command");
+        };
+        assert_eq!(
+            vec![
+                ("axis".to_string(), "2".to_string()),
+                ("name".to_string(), "x=y".to_string())
+            ],
+            args.vars
+        );
+        assert!(parse_var("axis").is_err());
+        assert!(parse_var("=2").is_err());
+    }
+
+    #[test]
+    fn test_topology_args() {
+        let Commands::Topology(args) = parse("topology") else {
+            panic!("Wrong command");
+        };
+        assert!(!args.dot);
+        assert_eq!(None, args.output);
+
+        let Commands::Topology(args) = parse("topology --dot --output bus.dot") else {
+            panic!("Wrong command");
+        };
+        assert!(args.dot);
+        assert_eq!(Some(PathBuf::from("bus.dot")), args.output);
+    }
+
+    #[test]
+    fn test_bench_args() {
+        let Commands::Bench(args) = parse("bench 4") else {
+            panic!("Wrong command");
+        };
+        assert_eq!(4, args.node_id);
+        assert_eq!(None, args.index);
+        assert_eq!(vec![64, 256, 1024], args.sizes);
+
+        let Commands::Bench(args) = parse("bench 4 --index 0x3000 --sub 1 --sizes 8,4096") else {
+            panic!("Wrong command");
+        };
+        assert_eq!(Some(0x3000), args.index);
+        assert_eq!(1, args.sub);
+        assert_eq!(vec![8, 4096], args.sizes);
+    }
+
+    #[test]
+    fn test_send_args() {
+        let Commands::Send(args) = parse("send 0x7FF 1 0x02 255") else {
+            panic!("Wrong command");
+        };
+        assert_eq!(0x7FF, args.id);
+        assert_eq!(vec![1, 2, 255], args.data);
+
+        let Commands::Send(args) = parse("send 0x100") else {
+            panic!("Wrong command");
+        };
+        assert!(args.data.is_empty());
+    }
+
+    #[test]
+    fn test_gen_args() {
+        let Commands::Gen(args) = parse("gen 0x181 0xAA 0xBB every 100") else {
+            panic!("Wrong command");
+        };
+        assert!(args.action.is_none());
+        assert_eq!(Some(0x181), args.id);
+        assert_eq!(vec![0xAA, 0xBB], args.data);
+        assert_eq!(Some(100), args.period_ms);
+
+        let Commands::Gen(args) = parse("gen 0x181 every 10") else {
+            panic!("Wrong command");
+        };
+        assert!(args.data.is_empty());
+        assert_eq!(Some(10), args.period_ms);
+
+        let Commands::Gen(args) = parse("gen stop") else {
+            panic!("Wrong command");
+        };
+        assert!(matches!(args.action, Some(GenAction::Stop)));
+    }
+
+    #[test]
+    fn test_estop_args() {
+        let Commands::Estop(args) = parse("estop") else {
+            panic!("Wrong command");
+        };
+        assert!(args.frames.is_empty());
+        assert_eq!(10, args.settle_ms);
+
+        let Commands::Estop(args) = parse("estop --frame 0x205:00ff --frame 0x306: --settle-ms 50")
+        else {
+            panic!("Wrong command");
+        };
+        assert_eq!(vec![(0x205, vec![0, 0xff]), (0x306, vec![])], args.frames);
+        assert_eq!(50, args.settle_ms);
+
+        assert!(parse_frame("0x205").is_err());
+        assert!(parse_frame("0x205:0").is_err());
+        assert!(parse_frame("0x205:zz").is_err());
+        assert!(parse_frame("0x205:000000000000000000").is_err());
+        assert!(parse_frame("0x20000000:00").is_err());
+    }
+
+    #[test]
+    fn test_flash_all_args() {
+        let Commands::FlashAll(args) =
+            parse("flash-all --match vendor=0xCAFE,product=1032 --file fw.bin --parallel 4")
+        else {
+            panic!("Wrong command");
+        };
+        assert_eq!(
+            IdentityMatch::any().vendor_id(0xCAFE).product_code(1032),
+            args.pattern
+        );
+        assert_eq!(PathBuf::from("fw.bin"), args.file);
+        assert_eq!(4, args.parallel);
+        assert_eq!(0, args.section);
+
+        assert!(parse_identity_match("vendor=1,color=2").is_err());
+        assert!(parse_identity_match("vendor").is_err());
+        assert!(parse_identity_match("serial=zz").is_err());
+    }
+
+    #[test]
+    fn test_errors_args() {
+        let Commands::Errors(args) = parse("errors") else {
+            panic!("Wrong command");
+        };
+        assert!(args.action.is_none());
+        assert_eq!(None, args.node_id);
+
+        let Commands::Errors(args) = parse("errors 5 --no-watch") else {
+            panic!("Wrong command");
+        };
+        assert_eq!(Some(5), args.node_id);
+        assert!(args.no_watch);
+
+        let Commands::Errors(args) = parse("errors clear 5") else {
+            panic!("Wrong command");
+        };
+        assert!(matches!(
+            args.action,
+            Some(ErrorsAction::Clear { node_id: 5 })
+        ));
+    }
+
+    #[test]
+    fn test_link_args() {
+        let Commands::Link(LinkCommands::Up(args)) = parse("link up can0 500000") else {
+            panic!("Wrong command");
+        };
+        assert_eq!("can0", args.interface);
+        assert_eq!(500000, args.bitrate);
+        assert_eq!(None, args.sample_point);
+        assert_eq!(None, args.data_bitrate);
+
+        let Commands::Link(LinkCommands::Up(args)) = parse(
+            "link up can1 1000000 --sample-point 0.8 --data-bitrate 5000000 --data-sample-point 0.75",
+        ) else {
+            panic!("Wrong command");
+        };
+        assert_eq!(Some(800), args.sample_point);
+        assert_eq!(Some(5000000), args.data_bitrate);
+        assert_eq!(Some(750), args.data_sample_point);
+
+        // A data sample point requires a data bit rate
+        assert!(Cli::try_parse_from([
+            "",
+            "link",
+            "up",
+            "can0",
+            "500000",
+            "--data-sample-point",
+            "0.75"
+        ])
+        .is_err());
+
+        assert!(matches!(
+            parse("link down"),
+            Commands::Link(LinkCommands::Down { interface: None })
+        ));
+        let Commands::Link(LinkCommands::Status { interface }) = parse("link status can0") else {
+            panic!("Wrong command");
+        };
+        assert_eq!(Some("can0".to_string()), interface);
+
+        assert_eq!(Ok(875), parse_sample_point("0.875"));
+        assert!(parse_sample_point("87.5").is_err());
+        assert!(parse_sample_point("0").is_err());
+        assert!(parse_sample_point("abc").is_err());
+    }
+
+    #[test]
+    fn test_log_args() {
+        let Commands::Log(LogCommands::Tail { node_id, period_ms }) = parse("log tail 3") else {
+            panic!("Wrong command");
+        };
+        assert_eq!(3, node_id);
+        assert_eq!(250, period_ms);
+
+        let Commands::Log(LogCommands::Tail { period_ms, .. }) =
+            parse("log tail 3 --period-ms 1000")
+        else {
+            panic!("Wrong command");
+        };
+        assert_eq!(1000, period_ms);
+
+        assert!(matches!(
+            parse("log show 4"),
+            Commands::Log(LogCommands::Show { node_id: 4 })
+        ));
+        assert!(matches!(
+            parse("log clear 4"),
+            Commands::Log(LogCommands::Clear { node_id: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_selftest_args() {
+        assert!(matches!(
+            parse("selftest 5"),
+            Commands::Selftest(SelftestArgs { node_id: 5 })
+        ));
+        assert!(Cli::try_parse_from(["", "selftest"]).is_err());
+    }
+
+    #[test]
+    fn test_heartbeat_args() {
+        assert!(matches!(
+            parse("heartbeat start 100"),
+            Commands::Heartbeat(HeartbeatCommands::Start {
+                node_id: 100,
+                period_ms: 1000
+            })
+        ));
+        assert!(matches!(
+            parse("heartbeat start 100 --period-ms 200"),
+            Commands::Heartbeat(HeartbeatCommands::Start { period_ms: 200, .. })
+        ));
+        assert!(matches!(
+            parse("heartbeat period 500"),
+            Commands::Heartbeat(HeartbeatCommands::Period { period_ms: 500 })
+        ));
+        assert!(matches!(
+            parse("heartbeat stop"),
+            Commands::Heartbeat(HeartbeatCommands::Stop)
+        ));
+        let too_high = ["", "heartbeat", "start", "128"];
+        assert!(Cli::try_parse_from(too_high).is_err());
+    }
+
+    #[test]
+    fn test_pace_args() {
+        let Commands::Pace(args) = parse("pace 5 --max-fps 1000 --min-gap-us 500") else {
+            panic!("Expected pace command");
+        };
+        assert_eq!(5, args.node_id);
+        assert_eq!(Some(1000), args.max_fps);
+        assert_eq!(Some(500), args.min_gap_us);
+        assert!(!args.clear);
+
+        let Commands::Pace(args) = parse("pace 5") else {
+            panic!("Expected pace command");
+        };
+        assert_eq!(None, args.max_fps);
+        assert_eq!(None, args.min_gap_us);
+
+        let conflict = ["", "pace", "5", "--clear", "--max-fps", "1000"];
+        assert!(Cli::try_parse_from(conflict).is_err());
+    }
+
+    #[test]
+    fn test_alias_args() {
+        let Commands::Alias(args) = parse("alias 12 left-motor") else {
+            panic!("Wrong command");
+        };
+        assert_eq!(Some(12), args.node_id);
+        assert_eq!(Some("left-motor".to_string()), args.name);
+        let Commands::Alias(args) = parse("alias") else {
+            panic!("Wrong command");
+        };
+        assert_eq!(None, args.node_id);
+        // A name is required when a node is given
+        assert!(Cli::try_parse_from(["", "alias", "12"]).is_err());
+        assert!(matches!(
+            parse("unalias 12"),
+            Commands::Unalias(UnaliasArgs { node_id: 12 })
+        ));
+    }
+
+    #[test]
+    fn test_infer_format() {
+        assert_eq!(SdoDataType::U16, SdoDataType::infer("12", 2));
+        assert_eq!(SdoDataType::I8, SdoDataType::infer("-1", 1));
+        assert_eq!(SdoDataType::F32, SdoDataType::infer("1.5", 4));
+        assert_eq!(SdoDataType::Str, SdoDataType::infer("hello", 5));
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        // Unknown commands are run as plugins by the shell
+        let e = Cli::try_parse_from(["", "calibrate", "5"]).unwrap_err();
+        assert_eq!(clap::error::ErrorKind::InvalidSubcommand, e.kind());
+        assert!(matches!(parse("plugins"), Commands::Plugins));
+    }
+
+    #[test]
+    fn test_record_args() {
+        let Commands::Record(RecordCommands::Start { path }) = parse("record start upload.txt")
+        else {
+            panic!("Wrong command");
+        };
+        assert_eq!(PathBuf::from("upload.txt"), path);
+        assert!(matches!(
+            parse("record stop"),
+            Commands::Record(RecordCommands::Stop)
+        ));
+        assert!(Cli::try_parse_from(["", "record", "start"]).is_err());
+    }
+
+    #[test]
+    fn test_session_args() {
+        let Commands::Session(SessionCommands::Save { name }) = parse("session save bringup")
+        else {
+            panic!("Wrong command");
+        };
+        assert_eq!("bringup", name);
+        assert!(matches!(
+            parse("session list"),
+            Commands::Session(SessionCommands::List)
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+//! The zencan-cli config file
+//!
+//! Settings which are kept between sessions of zencan-cli, such as [node aliases](crate::alias)
+//! and [saved sessions](crate::session), are stored in `~/.zencan-cli.toml`.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::session::Session;
+
+/// The contents of the CLI config file
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CliConfig {
+    /// Node IDs, by alias
+    #[serde(default)]
+    pub aliases: BTreeMap<String, u8>,
+    /// Saved sessions, by name
+    #[serde(default)]
+    pub sessions: BTreeMap<String, Session>,
+}
+
+impl CliConfig {
+    /// Load the config from a file
+    ///
+    /// A missing file is treated as an empty config.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("Error reading {}: {e}", path.display())),
+        };
+        toml::from_str(&text).map_err(|e| format!("Error parsing {}: {e}", path.display()))
+    }
+
+    /// Save the config to a file
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, text).map_err(|e| format!("Error writing {}: {e}", path.display()))
+    }
+
+    /// Load the config from a file, modify it, and save it again
+    ///
+    /// The file is not written if `f` returns an error.
+    pub fn update<T>(
+        path: &Path,
+        f: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut config = Self::load(path)?;
+        let result = f(&mut config)?;
+        config.save(path)?;
+        Ok(result)
+    }
+}
+
+/// Get the location of the CLI config file
+pub fn config_path() -> PathBuf {
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".zencan-cli.toml"),
+        None => PathBuf::from("/tmp/zencan-cli.toml"),
+    }
+}
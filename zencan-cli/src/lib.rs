@@ -1,6 +1,8 @@
 //! Command-line utilities for zencan
 //!
-//! Collection of tools for interacting with devices via a socketcan interface on linux.
+//! Collection of tools for interacting with devices via a socketcan interface on linux, or CAN over
+//! UDP on any platform. A bus is named either by its socketcan interface, e.g. `can0`, or as
+//! `udp:<bind address>,<peer address>`.
 //!
 //! # zencandump
 //!
@@ -13,5 +15,40 @@
 //!
 //! A REPL-style interactive shell for controlling CAN devices.
 //!
+//! On Linux, with the default `netlink` feature, the `link` command configures the bit rate of
+//! socketcan interfaces and brings them up or down.
+//!
+//! Nodes can be given names with the `alias` command, e.g. `alias 12 left-motor`, which can then be
+//! used in place of their node ID. See [`alias`].
+//!
+//! The setup of a session, i.e. its open interfaces, attached device config and aliases, can be
+//! saved with `session save <name>`, and restored by starting the CLI with `--session <name>`. See
+//! [`session`].
+//!
+//! Commands which are not built in are run as plugins, i.e. executables on the `PATH` named
+//! `zencan-cli-<command>`, so that device specific commands can be added without changing the CLI.
+//! See [`plugin`].
+//!
+//! # Correlating timestamps
+//!
+//! Both tools can timestamp their output with monotonic time as well as wall clock time, and emit
+//! periodic clock markers, so that bus captures can be aligned with logs from other systems. Use
+//! `zencandump --monotonic --marker-period 10 can0`, or `zencan-cli --log-clock --marker-period 10
+//! can0`. See [`clock`].
+//!
+//! # Checking cyclic timing
+//!
+//! `zencandump --timing can0` measures the period and jitter of SYNC, TIME, heartbeat and PDO
+//! traffic on each COB-ID, and flags frames which arrive outside of a tolerance of the measured
+//! period (10% by default, set with `--tolerance`). See [`timing`].
 
+pub mod alias;
+pub mod bench;
+pub mod clock;
 pub mod command;
+pub mod config;
+#[cfg(all(target_os = "linux", feature = "netlink"))]
+pub mod link;
+pub mod plugin;
+pub mod session;
+pub mod timing;
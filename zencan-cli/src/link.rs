@@ -0,0 +1,197 @@
+//! Configuration of socketcan interfaces via netlink
+//!
+//! This provides the equivalent of `ip link set can0 up type can bitrate 500000`, so that an
+//! interface can be brought up, or its bit rate changed, without leaving zencan-cli. Changing the
+//! settings of an interface requires the `CAP_NET_ADMIN` capability, e.g. running as root.
+//!
+//! Sample points are given in tenths of a percent, as used by the kernel, e.g. 875 for 87.5%.
+
+use socketcan::nl::{CanCtrlMode, CanCtrlModes, CanInterface};
+
+/// Error returned when configuring an interface fails
+#[derive(Debug)]
+pub struct LinkError {
+    interface: String,
+    action: &'static str,
+    message: String,
+}
+
+impl std::fmt::Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to {} {}: {}",
+            self.action, self.interface, self.message
+        )
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+/// The bit timing to apply when bringing an interface up
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinkSettings {
+    /// The nominal bit rate, in bits/s
+    pub bitrate: u32,
+    /// The nominal sample point, in tenths of a percent, or None to let the driver choose
+    pub sample_point: Option<u32>,
+    /// The data phase bit rate, in bits/s. When set, CAN FD is enabled.
+    pub data_bitrate: Option<u32>,
+    /// The data phase sample point, in tenths of a percent, or None to let the driver choose
+    pub data_sample_point: Option<u32>,
+}
+
+/// The state of an interface, as reported by [`link_status`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinkStatus {
+    /// The interface name
+    pub interface: String,
+    /// True if the interface is up
+    pub up: bool,
+    /// The nominal bit rate and sample point, if configured
+    pub bit_timing: Option<(u32, u32)>,
+    /// The data phase bit rate and sample point, if CAN FD is configured
+    pub data_bit_timing: Option<(u32, u32)>,
+    /// The controller state, e.g. `ErrorActive` or `BusOff`
+    pub state: Option<String>,
+    /// The transmit and receive error counters, if the driver reports them
+    pub error_counters: Option<(u16, u16)>,
+}
+
+/// Format a sample point in tenths of a percent as a fraction, e.g. 875 as "0.875"
+pub fn format_sample_point(sample_point: u32) -> String {
+    format!("{}.{:03}", sample_point / 1000, sample_point % 1000)
+}
+
+impl std::fmt::Display for LinkStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}",
+            self.interface,
+            if self.up { "UP" } else { "DOWN" }
+        )?;
+        if let Some(state) = &self.state {
+            write!(f, ", state {state}")?;
+        }
+        match self.bit_timing {
+            Some((bitrate, sample_point)) => write!(
+                f,
+                ", bitrate {bitrate}, sample point {}",
+                format_sample_point(sample_point)
+            )?,
+            None => write!(f, ", bitrate not configured")?,
+        }
+        if let Some((bitrate, sample_point)) = self.data_bit_timing {
+            write!(
+                f,
+                ", FD data bitrate {bitrate}, sample point {}",
+                format_sample_point(sample_point)
+            )?;
+        }
+        if let Some((tx, rx)) = self.error_counters {
+            write!(f, ", tx errors {tx}, rx errors {rx}")?;
+        }
+        Ok(())
+    }
+}
+
+fn open(interface: &str, action: &'static str) -> Result<CanInterface, LinkError> {
+    CanInterface::open(interface).map_err(|e| LinkError {
+        interface: interface.to_string(),
+        action,
+        message: e.to_string(),
+    })
+}
+
+/// Apply bit timing to an interface, and bring it up
+///
+/// The interface is brought down first, as the bit timing cannot be changed while it is up.
+pub fn link_up(interface: &str, settings: &LinkSettings) -> Result<(), LinkError> {
+    let action = "bring up";
+    let err = |e: &dyn std::fmt::Display| LinkError {
+        interface: interface.to_string(),
+        action,
+        message: e.to_string(),
+    };
+    let iface = open(interface, action)?;
+    iface.bring_down().map_err(|e| err(&e))?;
+    iface
+        .set_bitrate(settings.bitrate, settings.sample_point)
+        .map_err(|e| err(&e))?;
+    let fd = settings.data_bitrate.is_some();
+    iface
+        .set_ctrlmode(CanCtrlModes::from_mode(CanCtrlMode::Fd, fd))
+        .map_err(|e| err(&e))?;
+    if let Some(data_bitrate) = settings.data_bitrate {
+        iface
+            .set_data_bitrate(data_bitrate, settings.data_sample_point)
+            .map_err(|e| err(&e))?;
+    }
+    iface.bring_up().map_err(|e| err(&e))
+}
+
+/// Bring an interface down
+pub fn link_down(interface: &str) -> Result<(), LinkError> {
+    let action = "bring down";
+    open(interface, action)?
+        .bring_down()
+        .map_err(|e| LinkError {
+            interface: interface.to_string(),
+            action,
+            message: e.to_string(),
+        })
+}
+
+/// Read the state of an interface
+pub fn link_status(interface: &str) -> Result<LinkStatus, LinkError> {
+    let action = "read status of";
+    let details = open(interface, action)?.details().map_err(|e| LinkError {
+        interface: interface.to_string(),
+        action,
+        message: e.to_string(),
+    })?;
+    let can = details.can;
+    let timing = |t: Option<socketcan::nl::CanBitTiming>| {
+        t.filter(|t| t.bitrate != 0)
+            .map(|t| (t.bitrate, t.sample_point))
+    };
+    Ok(LinkStatus {
+        interface: interface.to_string(),
+        up: details.is_up,
+        bit_timing: timing(can.bit_timing),
+        data_bit_timing: timing(can.data_bit_timing),
+        state: can.state.map(|s| format!("{s:?}")),
+        error_counters: can.berr_counter.map(|c| (c.txerr, c.rxerr)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_display() {
+        let mut status = LinkStatus {
+            interface: "can0".into(),
+            up: true,
+            bit_timing: Some((500000, 875)),
+            data_bit_timing: None,
+            state: Some("ErrorActive".into()),
+            error_counters: Some((0, 3)),
+        };
+        assert_eq!(
+            "can0: UP, state ErrorActive, bitrate 500000, sample point 0.875, tx errors 0, rx errors 3",
+            status.to_string()
+        );
+
+        status.up = false;
+        status.data_bit_timing = Some((2000000, 750));
+        status.state = None;
+        status.error_counters = None;
+        assert_eq!(
+            "can0: DOWN, bitrate 500000, sample point 0.875, FD data bitrate 2000000, sample point 0.750",
+            status.to_string()
+        );
+    }
+}
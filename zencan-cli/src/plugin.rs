@@ -0,0 +1,287 @@
+//! Plugin commands, for adding device specific commands without changing zencan-cli
+//!
+//! A command which zencan-cli does not implement itself is looked up as a plugin: an executable
+//! named `zencan-cli-<command>` in one of the directories on the `PATH`, in the same way that cargo
+//! finds its external subcommands. `calibrate 5 --axis 2` runs `zencan-cli-calibrate 5 --axis 2`,
+//! and the shell waits for it to exit. `plugins` lists the plugins found on the `PATH`.
+//!
+//! A plugin is told about the session through environment variables, which it can read with
+//! [`PluginContext::from_env`]:
+//!
+//! | Variable | Description |
+//! | -------- | ----------- |
+//! | `ZENCAN_CLI_BUS` | The active bus, e.g. `can0`, as accepted by [`open_transport`] |
+//! | `ZENCAN_CLI_NODES` | The IDs of the nodes found on the active bus, separated by commas |
+//! | `ZENCAN_CLI_DEVICE_CONFIG` | The path of the attached device config, if there is one |
+//!
+//! The plugin opens its own connection to the bus with [`PluginContext::open_bus`]. A socketcan
+//! interface can be opened by any number of processes, but a `udp:` bus cannot, because the bind
+//! address is held by zencan-cli. Node aliases can be read from the [config file](crate::config)
+//! with [`NodeAliases::load`](crate::alias::NodeAliases::load).
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+use zencan_client::{
+    open_transport, transport::TransportError, TransportReceiver, TransportSender,
+};
+
+/// The prefix of the name of plugin executables
+pub const PLUGIN_PREFIX: &str = "zencan-cli-";
+/// The environment variable holding the active bus
+pub const BUS_ENV: &str = "ZENCAN_CLI_BUS";
+/// The environment variable holding the IDs of the known nodes
+pub const NODES_ENV: &str = "ZENCAN_CLI_NODES";
+/// The environment variable holding the path of the attached device config
+pub const DEVICE_CONFIG_ENV: &str = "ZENCAN_CLI_DEVICE_CONFIG";
+
+/// The session state passed to a plugin
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PluginContext {
+    /// The active bus
+    pub bus: String,
+    /// The IDs of the nodes found on the active bus
+    pub nodes: Vec<u8>,
+    /// The path of the attached device config
+    pub device_config: Option<PathBuf>,
+}
+
+impl PluginContext {
+    /// Read the context passed to a plugin from its environment
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_vars(|name| std::env::var_os(name))
+    }
+
+    /// Read the context from a set of environment variables
+    pub fn from_vars(var: impl Fn(&str) -> Option<OsString>) -> Result<Self, String> {
+        let bus = var(BUS_ENV)
+            .ok_or_else(|| format!("{BUS_ENV} is not set. Plugins must be run from zencan-cli"))?
+            .into_string()
+            .map_err(|_| format!("{BUS_ENV} is not valid UTF-8"))?;
+        let nodes = match var(NODES_ENV) {
+            Some(nodes) => nodes
+                .to_string_lossy()
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                    s.parse()
+                        .map_err(|_| format!("Invalid node ID '{s}' in {NODES_ENV}"))
+                })
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        let device_config = var(DEVICE_CONFIG_ENV).map(PathBuf::from);
+        Ok(Self {
+            bus,
+            nodes,
+            device_config,
+        })
+    }
+
+    /// Get the environment variables which pass the context to a plugin
+    pub fn to_vars(&self) -> Vec<(&'static str, OsString)> {
+        let nodes: Vec<String> = self.nodes.iter().map(|id| id.to_string()).collect();
+        let mut vars = vec![
+            (BUS_ENV, OsString::from(&self.bus)),
+            (NODES_ENV, OsString::from(nodes.join(","))),
+        ];
+        if let Some(path) = &self.device_config {
+            vars.push((DEVICE_CONFIG_ENV, path.clone().into_os_string()));
+        }
+        vars
+    }
+
+    /// Open a connection to the active bus
+    pub fn open_bus(&self) -> Result<(TransportSender, TransportReceiver), TransportError> {
+        open_transport(&self.bus)
+    }
+}
+
+/// A plugin executable
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plugin {
+    /// The command which runs the plugin
+    pub name: String,
+    /// The path of the executable
+    pub path: PathBuf,
+}
+
+impl Plugin {
+    /// Run the plugin with `args`, and wait for it to exit
+    ///
+    /// The plugin inherits the standard input and output of the shell.
+    pub fn run(&self, args: &[String], context: &PluginContext) -> Result<ExitStatus, String> {
+        Command::new(&self.path)
+            .args(args)
+            .envs(context.to_vars())
+            .status()
+            .map_err(|e| format!("Failed to run {}: {e}", self.path.display()))
+    }
+}
+
+/// Get the command name of a plugin from its file name, if it is one
+fn plugin_name(file_name: &str) -> Option<&str> {
+    let name = file_name.strip_prefix(PLUGIN_PREFIX)?;
+    let name = if cfg!(windows) {
+        name.strip_suffix(".exe")?
+    } else {
+        name
+    };
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Find the plugins in a list of directories
+///
+/// If more than one directory holds a plugin with the same name, the first one is used, as for a
+/// command on the `PATH`. The plugins are returned in order of name.
+pub fn discover_in(dirs: impl IntoIterator<Item = PathBuf>) -> Vec<Plugin> {
+    let mut plugins = BTreeMap::new();
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let file_name = entry.file_name();
+            let Some(name) = file_name.to_str().and_then(plugin_name) else {
+                continue;
+            };
+            if !plugins.contains_key(name) && is_executable(&path) {
+                plugins.insert(name.to_string(), path);
+            }
+        }
+    }
+    plugins
+        .into_iter()
+        .map(|(name, path)| Plugin { name, path })
+        .collect()
+}
+
+/// Find a plugin by command name in a list of directories
+pub fn find_in(dirs: impl IntoIterator<Item = PathBuf>, name: &str) -> Option<Plugin> {
+    let file_name = if cfg!(windows) {
+        format!("{PLUGIN_PREFIX}{name}.exe")
+    } else {
+        format!("{PLUGIN_PREFIX}{name}")
+    };
+    if name.is_empty() || name.contains(std::path::is_separator) {
+        return None;
+    }
+    dirs.into_iter()
+        .map(|dir| dir.join(&file_name))
+        .find(|path| is_executable(path))
+        .map(|path| Plugin {
+            name: name.to_string(),
+            path,
+        })
+}
+
+fn path_dirs() -> Vec<PathBuf> {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect())
+        .unwrap_or_default()
+}
+
+/// Find the plugins on the `PATH`
+pub fn discover() -> Vec<Plugin> {
+    discover_in(path_dirs())
+}
+
+/// Find a plugin by command name on the `PATH`
+pub fn find(name: &str) -> Option<Plugin> {
+    find_in(path_dirs(), name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_vars() {
+        let context = PluginContext {
+            bus: "can0".into(),
+            nodes: vec![3, 12],
+            device_config: Some(PathBuf::from("/tmp/device.toml")),
+        };
+        let vars: BTreeMap<_, _> = context.to_vars().into_iter().collect();
+        assert_eq!(Some(&OsString::from("3,12")), vars.get(NODES_ENV));
+        let parsed = PluginContext::from_vars(|name| vars.get(name).cloned()).unwrap();
+        assert_eq!(context, parsed);
+
+        // No nodes, and no device config
+        let context = PluginContext {
+            bus: "udp:127.0.0.1:5000,127.0.0.1:5001".into(),
+            ..Default::default()
+        };
+        let vars: BTreeMap<_, _> = context.to_vars().into_iter().collect();
+        assert_eq!(
+            context,
+            PluginContext::from_vars(|name| vars.get(name).cloned()).unwrap()
+        );
+
+        assert!(PluginContext::from_vars(|_| None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_discover() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join(format!("zencan-cli-plugins-{}", std::process::id()));
+        let dirs = [root.join("a"), root.join("b")];
+        for dir in &dirs {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        let create = |path: PathBuf, mode: u32| {
+            std::fs::write(&path, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+            path
+        };
+        let calibrate = create(dirs[0].join("zencan-cli-calibrate"), 0o755);
+        create(dirs[1].join("zencan-cli-calibrate"), 0o755);
+        let flash = create(dirs[1].join("zencan-cli-flash-motor"), 0o755);
+        // Not executable, or not a plugin
+        create(dirs[1].join("zencan-cli-notes"), 0o644);
+        create(dirs[1].join("zencan-cli-"), 0o755);
+        create(dirs[1].join("other"), 0o755);
+
+        let plugins = discover_in(dirs.clone());
+        assert_eq!(
+            vec![
+                Plugin {
+                    name: "calibrate".into(),
+                    path: calibrate.clone()
+                },
+                Plugin {
+                    name: "flash-motor".into(),
+                    path: flash
+                },
+            ],
+            plugins
+        );
+        assert_eq!(
+            Some(calibrate),
+            find_in(dirs.clone(), "calibrate").map(|p| p.path)
+        );
+        assert_eq!(None, find_in(dirs.clone(), "notes"));
+        assert_eq!(None, find_in(dirs.clone(), "../b/zencan-cli-calibrate"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
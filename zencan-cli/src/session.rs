@@ -0,0 +1,105 @@
+//! Saving and restoring the setup of a zencan-cli session
+//!
+//! `session save bringup` records the open interfaces, the active interface, the attached device
+//! config and the node aliases under the name `bringup` in the [config file](crate::config).
+//! Starting the CLI with `zencan-cli --session bringup`, or running `session load bringup`, opens
+//! the same interfaces and attaches the same device config again, so that a routine setup does not
+//! need to be typed in every time. The aliases of a restored session are added to the aliases
+//! already in use.
+//!
+//! ```toml
+//! [sessions.bringup]
+//! interfaces = ["can0", "can1"]
+//! active = "can1"
+//! attached_od = "/home/user/project/device.toml"
+//!
+//! [sessions.bringup.aliases]
+//! left-motor = 12
+//! ```
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::CliConfig;
+
+/// The saved setup of a session
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Session {
+    /// The open interfaces
+    #[serde(default)]
+    pub interfaces: Vec<String>,
+    /// The active interface
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active: Option<String>,
+    /// The path of the attached device config
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attached_od: Option<PathBuf>,
+    /// Node IDs, by alias
+    #[serde(default)]
+    pub aliases: BTreeMap<String, u8>,
+}
+
+impl Session {
+    /// Load the session called `name` from a config file
+    pub fn load(path: &Path, name: &str) -> Result<Self, String> {
+        CliConfig::load(path)?
+            .sessions
+            .remove(name)
+            .ok_or_else(|| format!("No session named '{name}'"))
+    }
+
+    /// Save the session as `name` in a config file, replacing any session with the same name
+    pub fn save(&self, path: &Path, name: &str) -> Result<(), String> {
+        CliConfig::update(path, |config| {
+            config.sessions.insert(name.to_string(), self.clone());
+            Ok(())
+        })
+    }
+
+    /// Remove the session called `name` from a config file
+    pub fn delete(path: &Path, name: &str) -> Result<(), String> {
+        CliConfig::update(path, |config| match config.sessions.remove(name) {
+            Some(_) => Ok(()),
+            None => Err(format!("No session named '{name}'")),
+        })
+    }
+
+    /// List the names of the sessions saved in a config file
+    pub fn list(path: &Path) -> Result<Vec<String>, String> {
+        Ok(CliConfig::load(path)?.sessions.into_keys().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let path =
+            std::env::temp_dir().join(format!("zencan-cli-session-{}.toml", std::process::id()));
+        let session = Session {
+            interfaces: vec!["can0".into(), "can1".into()],
+            active: Some("can1".into()),
+            attached_od: Some("/tmp/device.toml".into()),
+            aliases: [("pump".to_string(), 5)].into(),
+        };
+        session.save(&path, "bringup").unwrap();
+        Session::default().save(&path, "empty").unwrap();
+        // Saving aliases does not disturb the saved sessions
+        crate::alias::NodeAliases::new().save(&path).unwrap();
+
+        assert_eq!(session, Session::load(&path, "bringup").unwrap());
+        assert_eq!(Session::default(), Session::load(&path, "empty").unwrap());
+        assert_eq!(vec!["bringup", "empty"], Session::list(&path).unwrap());
+
+        Session::delete(&path, "empty").unwrap();
+        assert!(Session::load(&path, "empty").is_err());
+        assert!(Session::delete(&path, "empty").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+//! Measuring the period of cyclic traffic
+//!
+//! SYNC, TIME, heartbeat and PDO messages are usually sent periodically, and late or early frames
+//! are often the first sign of an overloaded bus or node. An [`IntervalTracker`] measures the
+//! interval between consecutive frames with the same COB-ID, keeps smoothed estimates of the period
+//! and jitter, and flags intervals which differ from the period by more than a tolerance.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use zencan_client::common::{decode::CanOpenFrame, messages::CanId};
+
+/// The number of intervals which must be measured before outliers are flagged
+const MIN_INTERVALS: u32 = 2;
+
+/// The timing of a single frame, relative to the previous frame with the same COB-ID
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IntervalSample {
+    /// The time since the previous frame
+    pub interval: Duration,
+    /// The smoothed period, before this interval was included
+    pub period: Duration,
+    /// The smoothed mean deviation of the interval from the period
+    pub jitter: Duration,
+    /// The interval differs from the period by more than the tolerance
+    pub outlier: bool,
+}
+
+impl Display for IntervalSample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "interval={} period={} jitter={}",
+            ms_str(self.interval),
+            ms_str(self.period),
+            ms_str(self.jitter)
+        )?;
+        if self.outlier {
+            write!(f, " OUTLIER")?;
+        }
+        Ok(())
+    }
+}
+
+fn ms_str(d: Duration) -> String {
+    format!("{:.3}ms", d.as_secs_f64() * 1000.0)
+}
+
+/// Statistics of the intervals seen on a single COB-ID
+#[derive(Clone, Copy, Debug)]
+pub struct PeriodStats {
+    last: Instant,
+    /// The number of intervals measured
+    pub intervals: u32,
+    /// The exponentially smoothed period
+    pub period: Duration,
+    /// The exponentially smoothed mean deviation of the interval from the period
+    pub jitter: Duration,
+    /// The shortest interval measured
+    pub min: Duration,
+    /// The longest interval measured
+    pub max: Duration,
+    /// The number of intervals flagged as outliers
+    pub outliers: u32,
+}
+
+impl PeriodStats {
+    fn new(now: Instant) -> Self {
+        Self {
+            last: now,
+            intervals: 0,
+            period: Duration::ZERO,
+            jitter: Duration::ZERO,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+            outliers: 0,
+        }
+    }
+
+    fn update(&mut self, now: Instant, tolerance: f64) -> IntervalSample {
+        let interval = now.saturating_duration_since(self.last);
+        self.last = now;
+        let deviation = interval.abs_diff(self.period);
+        let outlier = self.intervals >= MIN_INTERVALS
+            && deviation.as_secs_f64() > self.period.as_secs_f64() * tolerance;
+        let sample = IntervalSample {
+            interval,
+            period: self.period,
+            jitter: self.jitter,
+            outlier,
+        };
+
+        if self.intervals == 0 {
+            self.period = interval;
+        } else {
+            // Smoothed the same way as the heartbeat period in the node table, so that the estimate
+            // follows a node which changes its period
+            self.period = (self.period * 7 + interval) / 8;
+            self.jitter = (self.jitter * 7 + deviation) / 8;
+        }
+        self.intervals += 1;
+        self.min = self.min.min(interval);
+        self.max = self.max.max(interval);
+        if outlier {
+            self.outliers += 1;
+        }
+        sample
+    }
+}
+
+/// Measures the interval between frames on each COB-ID carrying periodic traffic
+#[derive(Debug)]
+pub struct IntervalTracker {
+    tolerance: f64,
+    stats: HashMap<(bool, u32), PeriodStats>,
+}
+
+impl IntervalTracker {
+    /// Create a tracker which flags intervals differing from the period by more than `tolerance`
+    ///
+    /// The tolerance is a fraction of the period, e.g. 0.1 for 10%.
+    pub fn new(tolerance: f64) -> Self {
+        Self {
+            tolerance: tolerance.max(0.0),
+            stats: HashMap::new(),
+        }
+    }
+
+    /// Returns true if the frame is a type which is normally sent periodically
+    pub fn is_periodic(frame: &CanOpenFrame) -> bool {
+        matches!(
+            frame,
+            CanOpenFrame::Sync { .. }
+                | CanOpenFrame::Time(_)
+                | CanOpenFrame::Heartbeat(_)
+                | CanOpenFrame::Pdo(_)
+        )
+    }
+
+    /// Record a frame received at `now`
+    ///
+    /// Returns the timing of the frame, or None if the frame is not periodic traffic or is the
+    /// first frame seen on its COB-ID.
+    pub fn observe(
+        &mut self,
+        id: CanId,
+        frame: &CanOpenFrame,
+        now: Instant,
+    ) -> Option<IntervalSample> {
+        if !Self::is_periodic(frame) {
+            return None;
+        }
+        match self.stats.get_mut(&(id.is_extended(), id.raw())) {
+            Some(stats) => Some(stats.update(now, self.tolerance)),
+            None => {
+                self.stats
+                    .insert((id.is_extended(), id.raw()), PeriodStats::new(now));
+                None
+            }
+        }
+    }
+
+    /// Get the statistics for a COB-ID, if any frames have been seen on it
+    pub fn stats(&self, id: CanId) -> Option<&PeriodStats> {
+        self.stats.get(&(id.is_extended(), id.raw()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zencan_client::common::{
+        decode::classify,
+        messages::{CanMessage, SYNC_ID},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_interval_tracker() {
+        let mut tracker = IntervalTracker::new(0.1);
+        let sync = CanMessage::new(SYNC_ID, &[]);
+        let frame = classify(sync);
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        assert_eq!(None, tracker.observe(SYNC_ID, &frame, at(0)));
+        for i in 1..=10 {
+            let sample = tracker.observe(SYNC_ID, &frame, at(i * 10)).unwrap();
+            assert_eq!(Duration::from_millis(10), sample.interval);
+            assert!(!sample.outlier);
+        }
+        let stats = tracker.stats(SYNC_ID).unwrap();
+        assert_eq!(10, stats.intervals);
+        assert_eq!(Duration::from_millis(10), stats.period);
+        assert_eq!(Duration::ZERO, stats.jitter);
+
+        // Within tolerance
+        let sample = tracker.observe(SYNC_ID, &frame, at(109)).unwrap();
+        assert!(!sample.outlier);
+        // A missed SYNC
+        let sample = tracker.observe(SYNC_ID, &frame, at(129)).unwrap();
+        assert!(sample.outlier);
+        assert!(sample.to_string().ends_with("OUTLIER"));
+        let stats = tracker.stats(SYNC_ID).unwrap();
+        assert_eq!(1, stats.outliers);
+        assert_eq!(Duration::from_millis(9), stats.min);
+        assert_eq!(Duration::from_millis(20), stats.max);
+    }
+
+    #[test]
+    fn test_non_periodic_ignored() {
+        let mut tracker = IntervalTracker::new(0.1);
+        let id = CanId::std(0x601);
+        let frame = classify(CanMessage::new(id, &[0x40, 0, 0x10, 0, 0, 0, 0, 0]));
+        let now = Instant::now();
+        assert_eq!(None, tracker.observe(id, &frame, now));
+        assert_eq!(None, tracker.observe(id, &frame, now));
+        assert!(tracker.stats(id).is_none());
+    }
+}
@@ -0,0 +1,335 @@
+//! Pacing of SDO traffic to a bus utilization budget
+//!
+//! Applying a large [`NodeConfig`](crate::NodeConfig) or flashing firmware sends SDO frames as fast
+//! as the node responds, and block transfers send whole blocks back to back. On a running machine
+//! this can delay the PDO and heartbeat traffic which the machine relies on.
+//!
+//! A [`BusLoadLimiter`] measures the frames on the bus, and delays SDO requests so that they use at
+//! most a fraction of the bus capacity, and never more than the capacity left over by the other
+//! traffic. A budget is set on a [`BusManager`](crate::BusManager) with
+//! [`set_bus_load_budget`](crate::BusManager::set_bus_load_budget), and then applies to all SDO
+//! clients it creates.
+//!
+//! Some nodes, such as those running a bootloader, only poll their CAN controller occasionally, and
+//! a block transfer sent back to back can overrun their receive FIFO. A [`FramePacing`] sets a
+//! minimum gap between the frames sent to a single node, and is set per node with
+//! [`BusManager::set_frame_pacing`](crate::BusManager::set_frame_pacing), or in a
+//! [`NodeConfig`](crate::NodeConfig).
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+/// The number of bits in the largest standard CAN frame, including worst case bit stuffing
+const FRAME_BITS: u64 = 135;
+
+/// The period over which frames are counted
+const WINDOW: Duration = Duration::from_millis(100);
+
+/// A limit on the bus utilization of SDO traffic
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BusLoadBudget {
+    /// The bitrate of the bus, in bits per second
+    pub bitrate: u32,
+    /// The fraction of the bus capacity which SDO traffic may use, from 0.0 to 1.0
+    pub utilization: f32,
+}
+
+impl BusLoadBudget {
+    /// Create a budget for a bus bitrate, and a percentage of its capacity
+    pub fn new(bitrate: u32, percent: u8) -> Self {
+        Self {
+            bitrate,
+            utilization: percent.min(100) as f32 / 100.0,
+        }
+    }
+
+    /// The number of frames the bus can carry in one window
+    fn capacity(&self) -> usize {
+        (self.bitrate as u64 * WINDOW.as_millis() as u64 / 1000 / FRAME_BITS) as usize
+    }
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    budget: Option<BusLoadBudget>,
+    /// Times at which frames were seen on the bus, within the last window
+    observed: VecDeque<Instant>,
+    /// Times at which paced frames were sent, within the last window
+    sent: VecDeque<Instant>,
+}
+
+impl LimiterState {
+    fn prune(&mut self, now: Instant) {
+        for times in [&mut self.observed, &mut self.sent] {
+            while times
+                .front()
+                .is_some_and(|t| now.duration_since(*t) >= WINDOW)
+            {
+                times.pop_front();
+            }
+        }
+    }
+
+    /// Get the time to wait before a frame may be sent, or None if it may be sent now
+    fn delay(&mut self, now: Instant) -> Option<Duration> {
+        let budget = self.budget?;
+        self.prune(now);
+        let capacity = budget.capacity();
+        let budgeted = (capacity as f32 * budget.utilization) as usize;
+        // Frames sent by the limiter may also be observed, so only count the excess as other
+        // traffic
+        let other = self.observed.len().saturating_sub(self.sent.len());
+        // At least one frame per window is always allowed, so that transfers make progress
+        let allowed = budgeted.min(capacity.saturating_sub(other)).max(1);
+        if self.sent.len() < allowed {
+            return None;
+        }
+        // Wait until enough sent frames leave the window
+        let oldest = self.sent[self.sent.len() - allowed];
+        Some((oldest + WINDOW).saturating_duration_since(now))
+    }
+}
+
+/// Measures bus traffic, and paces frames to a [`BusLoadBudget`]
+///
+/// The limiter is a cheap handle which can be cloned, and all clones share the same measurements
+/// and budget. Every frame on the bus must be passed to [`record_frame`](Self::record_frame); a
+/// [`BusManager`](crate::BusManager) does this for its own limiter.
+#[derive(Debug, Clone, Default)]
+pub struct BusLoadLimiter {
+    state: Arc<Mutex<LimiterState>>,
+}
+
+impl BusLoadLimiter {
+    /// Create a limiter, with no budget
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the budget, or remove it to send without pacing
+    pub fn set_budget(&self, budget: Option<BusLoadBudget>) {
+        let mut state = self.state.lock().unwrap();
+        state.budget = budget;
+        state.sent.clear();
+    }
+
+    /// Get the current budget
+    pub fn budget(&self) -> Option<BusLoadBudget> {
+        self.state.lock().unwrap().budget
+    }
+
+    /// Record a frame received from the bus
+    pub fn record_frame(&self) {
+        let mut state = self.state.lock().unwrap();
+        // Nothing is measured without a budget, so that an unused limiter costs nothing
+        if state.budget.is_some() {
+            let now = Instant::now();
+            state.observed.push_back(now);
+            state.prune(now);
+        }
+    }
+
+    /// Get the measured bus utilization over the last window, from 0.0 to 1.0
+    ///
+    /// Returns None if no budget is set, as the bitrate is unknown and frames are not measured
+    pub fn utilization(&self) -> Option<f32> {
+        let mut state = self.state.lock().unwrap();
+        let budget = state.budget?;
+        state.prune(Instant::now());
+        let frames = state.observed.len().max(state.sent.len());
+        Some(frames as f32 / budget.capacity().max(1) as f32)
+    }
+
+    /// Wait until a frame may be sent within the budget, and count it as sent
+    pub async fn acquire(&self) {
+        loop {
+            let delay = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                match state.delay(now) {
+                    None => {
+                        if state.budget.is_some() {
+                            state.sent.push_back(now);
+                        }
+                        return;
+                    }
+                    Some(delay) => delay,
+                }
+            };
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Pacing of the frames sent to a single node
+///
+/// When both limits are set, the longer of the two gaps is used.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FramePacing {
+    /// The maximum number of frames sent per second
+    pub max_frames_per_sec: Option<u32>,
+    /// The minimum time between frames, in microseconds
+    pub min_gap_us: Option<u32>,
+}
+
+impl FramePacing {
+    /// Create a pacing with a maximum frame rate
+    pub fn max_frames_per_sec(rate: u32) -> Self {
+        Self {
+            max_frames_per_sec: Some(rate),
+            min_gap_us: None,
+        }
+    }
+
+    /// Create a pacing with a minimum gap between frames
+    pub fn min_gap(gap: Duration) -> Self {
+        Self {
+            max_frames_per_sec: None,
+            min_gap_us: Some(gap.as_micros().min(u32::MAX as u128) as u32),
+        }
+    }
+
+    /// Get the minimum time between the start of two frames
+    pub fn gap(&self) -> Duration {
+        let rate_gap = match self.max_frames_per_sec {
+            Some(rate) if rate > 0 => Duration::from_secs(1) / rate,
+            _ => Duration::ZERO,
+        };
+        let min_gap = Duration::from_micros(self.min_gap_us.unwrap_or(0) as u64);
+        rate_gap.max(min_gap)
+    }
+
+    /// Get the time to wait before a frame may be sent, or None if it may be sent now
+    fn delay(&self, last: Option<Instant>, now: Instant) -> Option<Duration> {
+        let ready = last? + self.gap();
+        ready.checked_duration_since(now).filter(|d| !d.is_zero())
+    }
+}
+
+/// Paces the frames sent to a single node to a [`FramePacing`]
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct FramePacer {
+    pacing: FramePacing,
+    last: Option<Instant>,
+}
+
+impl FramePacer {
+    pub fn pacing(&self) -> FramePacing {
+        self.pacing
+    }
+
+    pub fn set_pacing(&mut self, pacing: FramePacing) {
+        self.pacing = pacing;
+    }
+
+    /// Wait until the next frame may be sent, and count it as sent
+    pub async fn acquire(&mut self) {
+        if let Some(delay) = self.pacing.delay(self.last, Instant::now()) {
+            tokio::time::sleep(delay).await;
+        }
+        self.last = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(percent: u8) -> LimiterState {
+        LimiterState {
+            // 1000 frames per window
+            budget: Some(BusLoadBudget::new(1_350_000, percent)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        assert_eq!(1000, BusLoadBudget::new(1_350_000, 30).capacity());
+        assert_eq!(370, BusLoadBudget::new(500_000, 30).capacity());
+    }
+
+    #[test]
+    fn test_no_budget() {
+        let mut state = LimiterState::default();
+        assert_eq!(None, state.delay(Instant::now()));
+    }
+
+    #[test]
+    fn test_budget_limits_sent_frames() {
+        let mut state = state(30);
+        let start = Instant::now();
+        for _ in 0..300 {
+            assert_eq!(None, state.delay(start));
+            state.sent.push_back(start);
+        }
+        // The next frame must wait until the first frames leave the window
+        assert_eq!(Some(WINDOW), state.delay(start));
+        assert_eq!(None, state.delay(start + WINDOW));
+    }
+
+    #[test]
+    fn test_other_traffic_reduces_allowance() {
+        let mut state = state(30);
+        let start = Instant::now();
+        // Other traffic uses 900 of the 1000 frames
+        state.observed.extend(std::iter::repeat_n(start, 900));
+        for _ in 0..100 {
+            assert_eq!(None, state.delay(start));
+            state.sent.push_back(start);
+            // The frames sent are also observed on the bus
+            state.observed.push_back(start);
+        }
+        assert!(state.delay(start).is_some());
+    }
+
+    #[test]
+    fn test_saturated_bus_makes_progress() {
+        let mut state = state(30);
+        let start = Instant::now();
+        state.observed.extend(std::iter::repeat_n(start, 2000));
+        assert_eq!(None, state.delay(start));
+        state.sent.push_back(start);
+        assert_eq!(Some(WINDOW), state.delay(start));
+    }
+
+    #[test]
+    fn test_frame_pacing_gap() {
+        assert_eq!(Duration::ZERO, FramePacing::default().gap());
+        assert_eq!(
+            Duration::from_millis(1),
+            FramePacing::max_frames_per_sec(1000).gap()
+        );
+        assert_eq!(
+            Duration::from_micros(1500),
+            FramePacing::min_gap(Duration::from_micros(1500)).gap()
+        );
+        // The longer gap applies
+        let pacing = FramePacing {
+            max_frames_per_sec: Some(1000),
+            min_gap_us: Some(200),
+        };
+        assert_eq!(Duration::from_millis(1), pacing.gap());
+        assert_eq!(Duration::ZERO, FramePacing::max_frames_per_sec(0).gap());
+    }
+
+    #[test]
+    fn test_frame_pacing_delay() {
+        let pacing = FramePacing::max_frames_per_sec(1000);
+        let start = Instant::now();
+        assert_eq!(None, pacing.delay(None, start));
+        assert_eq!(
+            Some(Duration::from_micros(600)),
+            pacing.delay(Some(start), start + Duration::from_micros(400))
+        );
+        assert_eq!(
+            None,
+            pacing.delay(Some(start), start + Duration::from_millis(1))
+        );
+    }
+}
@@ -1,32 +1,186 @@
-use std::ops::{Deref, DerefMut};
+use std::ops::{Deref, DerefMut, RangeInclusive};
 use std::sync::Mutex;
 use std::time::Duration;
 use std::{collections::HashMap, sync::Arc, time::Instant};
 
-use futures::future::join_all;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
 use tokio::task::JoinHandle;
+use zencan_common::constants::object_ids;
+use zencan_common::decode::Emergency;
 use zencan_common::lss::{LssIdentity, LssState};
-use zencan_common::messages::{NmtCommand, NmtCommandSpecifier, NmtState, ZencanMessage};
+use zencan_common::messages::{CanId, CanMessage, NmtCommand, NmtCommandSpecifier, NmtState};
+use zencan_common::sdo::{AbortCode, SdoRequest};
 use zencan_common::{
+    node_id::ConfiguredId,
     traits::{AsyncCanReceiver, AsyncCanSender},
     NodeId,
 };
 
+use super::device::Device;
+use super::discovery::{Discovery, DiscoveryOptions, NodeEvent, NodeEvents, ProbeClients};
+use super::heartbeat_producer::{HeartbeatProducer, ManagerHeartbeat};
+use super::raw_handle::RawHandle;
+use super::secondary_heartbeat::{HeartbeatSource, SecondaryHeartbeat};
 use super::shared_sender::SharedSender;
-use crate::sdo_client::{SdoClient, SdoClientError};
-use crate::{LssError, LssMaster};
+use super::sync_producer::{ManagerSync, SyncProducer, SyncProducerError};
+use crate::bus_load::{BusLoadBudget, BusLoadLimiter, FramePacing};
+use crate::bus_silence::{BusActivity, BusEvent, BusEvents, SilenceWatchdog};
+use crate::cob_registry::{CobIdConflict, CobIdRegistry, CobIdUser};
+use crate::competing_master::{MasterActivity, MasterPolicy};
+use crate::echo_filter::EchoFilter;
+use crate::emcy::{DecodedEmcy, EmcyDecoder, EmcyDecoders, EmcyMonitor};
+use crate::failover::{FailoverOptions, FailoverState, Link, RedundantReceiver, RedundantSender};
+use crate::failsafe::{self, FailsafeConfig, FailsafeError};
+use crate::firmware::{self, FlashError, FlashOptions, FlashReport, FlashStage, SdoSnafu};
+use crate::frame_capture::{FrameCapture, FrameRecording};
+use crate::heartbeat_consumer::{self, HeartbeatConsumerError};
+use crate::identity::{IdentityError, IdentityMatch, MismatchSnafu, ReadFailedSnafu};
+use crate::object_cache::ObjectCache;
+use crate::pdo_link::{self, LinkedPdo, PdoLinkError, PdoLinkRequest};
+use crate::sdo_client::{SdoClient, SdoClientError, SdoCobIds};
+use crate::topology::{self, PdoEndpoint, Topology};
+use crate::transaction_log::{Operation, Outcome, Started, Transaction, TransactionRecorder};
+use crate::transfer_monitor::{InFlightTransfer, TransferMonitor};
+use crate::{AutoAssignReport, LssError, LssMaster, NodeConfig};
 
 use super::shared_receiver::{SharedReceiver, SharedReceiverChannel};
 
-#[derive(Debug, Clone)]
+/// Information about a node discovered on the bus
+///
+/// When serialized, the `last_seen` and heartbeat times are stored as their age in milliseconds,
+/// since an [`Instant`] has no meaning outside of the current process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeInfo {
+    /// The node ID
     pub node_id: u8,
+    /// The identity read from object 0x1018
     pub identity: Option<LssIdentity>,
+    /// The device name read from object 0x1008
     pub device_name: Option<String>,
+    /// The software version read from object 0x100A
     pub software_version: Option<String>,
+    /// The hardware version read from object 0x1009
     pub hardware_version: Option<String>,
+    /// The last time any information was received from the node
+    #[serde(with = "age_ms", rename = "last_seen_age_ms")]
     pub last_seen: Instant,
+    /// The last time a heartbeat was received from the node, from either source
+    #[serde(with = "opt_age_ms", rename = "last_heartbeat_age_ms")]
+    pub last_heartbeat: Option<Instant>,
+    /// The last time a heartbeat was received on the standard COB-ID
+    #[serde(default, with = "opt_age_ms", rename = "last_primary_heartbeat_age_ms")]
+    pub last_primary_heartbeat: Option<Instant>,
+    /// The last time a heartbeat was received on the secondary COB-ID
+    ///
+    /// See [`BusManager::set_secondary_heartbeat_base`].
+    #[serde(
+        default,
+        with = "opt_age_ms",
+        rename = "last_secondary_heartbeat_age_ms"
+    )]
+    pub last_secondary_heartbeat: Option<Instant>,
+    /// The NMT state reported in the most recent heartbeat
     pub nmt_state: Option<NmtState>,
+    /// The device type read from object 0x1000
+    pub device_type: Option<u32>,
+    /// The capabilities of the node, if they were probed
+    ///
+    /// See [`ScanOptions::probe_capabilities`].
+    #[serde(default)]
+    pub capabilities: Option<NodeCapabilities>,
+}
+
+/// The capabilities of a node, found by probing it during a scan
+///
+/// Each field is None if it could not be determined, e.g. because the node stopped responding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeCapabilities {
+    /// Whether the SDO server supports block downloads
+    ///
+    /// See [`SdoClient::probe_block_support`].
+    pub block_transfer: Option<bool>,
+    /// Whether the node responded when selected by its identity using LSS
+    pub lss: Option<bool>,
+    /// The number of RPDOs, counted from the communication parameter objects at 0x1400
+    pub num_rpdo: Option<u16>,
+    /// The number of TPDOs, counted from the communication parameter objects at 0x1800
+    pub num_tpdo: Option<u16>,
+    /// The heartbeat producer time in milliseconds, read from object 0x1017
+    pub heartbeat_period_ms: Option<u16>,
+}
+
+impl NodeCapabilities {
+    /// Returns true if the node is configured to produce a heartbeat
+    pub fn heartbeat_configured(&self) -> Option<bool> {
+        self.heartbeat_period_ms.map(|period| period != 0)
+    }
+
+    /// Update / merge newly probed capabilities
+    fn update(&mut self, other: &NodeCapabilities) {
+        self.block_transfer = other.block_transfer.or(self.block_transfer);
+        self.lss = other.lss.or(self.lss);
+        self.num_rpdo = other.num_rpdo.or(self.num_rpdo);
+        self.num_tpdo = other.num_tpdo.or(self.num_tpdo);
+        self.heartbeat_period_ms = other.heartbeat_period_ms.or(self.heartbeat_period_ms);
+    }
+}
+
+impl core::fmt::Display for NodeCapabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn show<T: core::fmt::Display>(value: Option<T>) -> String {
+            value.map(|v| v.to_string()).unwrap_or("?".into())
+        }
+        write!(
+            f,
+            "block transfer: {}, LSS: {}, RPDOs: {}, TPDOs: {}, heartbeat: {}",
+            show(self.block_transfer),
+            show(self.lss),
+            show(self.num_rpdo),
+            show(self.num_tpdo),
+            match self.heartbeat_period_ms {
+                Some(0) => "disabled".into(),
+                Some(period) => format!("{period}ms"),
+                None => "?".into(),
+            }
+        )
+    }
+}
+
+/// Serialize an [`Instant`] as the number of milliseconds elapsed since it
+mod age_ms {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::{Duration, Instant};
+
+    pub fn serialize<S: Serializer>(t: &Instant, s: S) -> Result<S::Ok, S::Error> {
+        (t.elapsed().as_millis() as u64).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Instant, D::Error> {
+        let age = Duration::from_millis(u64::deserialize(d)?);
+        let now = Instant::now();
+        Ok(now.checked_sub(age).unwrap_or(now))
+    }
+}
+
+/// Serialize an optional [`Instant`] as the number of milliseconds elapsed since it
+mod opt_age_ms {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Instant;
+
+    pub fn serialize<S: Serializer>(t: &Option<Instant>, s: S) -> Result<S::Ok, S::Error> {
+        match t {
+            Some(t) => super::age_ms::serialize(t, s),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Instant>, D::Error> {
+        #[derive(Deserialize)]
+        struct Age(#[serde(with = "super::age_ms")] Instant);
+        Ok(Option::<Age>::deserialize(d)?.map(|age| age.0))
+    }
 }
 
 impl core::fmt::Display for NodeInfo {
@@ -58,6 +212,9 @@ impl core::fmt::Display for NodeInfo {
             self.software_version.as_deref().unwrap_or("Unknown"),
             self.hardware_version.as_deref().unwrap_or("Unknown")
         )?;
+        if let Some(capabilities) = &self.capabilities {
+            writeln!(f, "    Capabilities: {capabilities}")?;
+        }
         let age = Instant::now().duration_since(self.last_seen);
         writeln!(f, "    Last Seen: {}s ago", age.as_secs())?;
 
@@ -66,6 +223,7 @@ impl core::fmt::Display for NodeInfo {
 }
 
 impl NodeInfo {
+    /// Create a new NodeInfo, with no information yet known about the node
     pub fn new(node_id: u8) -> Self {
         Self {
             node_id,
@@ -75,6 +233,43 @@ impl NodeInfo {
             software_version: None,
             hardware_version: None,
             nmt_state: None,
+            last_heartbeat: None,
+            last_primary_heartbeat: None,
+            last_secondary_heartbeat: None,
+            device_type: None,
+            capabilities: None,
+        }
+    }
+
+    /// Returns true if a heartbeat has been received from the node within `timeout`
+    pub fn is_heartbeating(&self, timeout: Duration) -> bool {
+        self.last_heartbeat.is_some_and(|t| t.elapsed() < timeout)
+    }
+
+    /// Returns true if a heartbeat has been received from the node on `source` within `timeout`
+    ///
+    /// On a redundant network, a node which is heartbeating on one source but not the other has
+    /// lost its connection to one of the networks.
+    pub fn is_heartbeating_on(&self, source: HeartbeatSource, timeout: Duration) -> bool {
+        let last = match source {
+            HeartbeatSource::Primary => self.last_primary_heartbeat,
+            HeartbeatSource::Secondary => self.last_secondary_heartbeat,
+        };
+        last.is_some_and(|t| t.elapsed() < timeout)
+    }
+
+    /// Record a heartbeat received from the node at `now`
+    fn record_heartbeat(&mut self, state: NmtState, source: HeartbeatSource, now: Instant) {
+        // A node is pre-operational once it has sent its boot-up message
+        self.nmt_state = Some(match state {
+            NmtState::Bootup => NmtState::PreOperational,
+            state => state,
+        });
+        self.last_seen = now;
+        self.last_heartbeat = Some(now);
+        match source {
+            HeartbeatSource::Primary => self.last_primary_heartbeat = Some(now),
+            HeartbeatSource::Secondary => self.last_secondary_heartbeat = Some(now),
         }
     }
 
@@ -95,60 +290,213 @@ impl NodeInfo {
         if info.nmt_state.is_some() {
             self.nmt_state = info.nmt_state;
         }
+        if info.device_type.is_some() {
+            self.device_type = info.device_type;
+        }
+        if let Some(capabilities) = &info.capabilities {
+            self.capabilities
+                .get_or_insert_with(Default::default)
+                .update(capabilities);
+        }
         self.last_seen = Instant::now();
     }
 }
 
+/// Options controlling a node scan
+///
+/// See [`BusManager::scan_nodes_with`].
+#[derive(Debug, Clone, Copy)]
+pub struct ScanOptions {
+    /// Time to wait for each SDO response before considering a node absent
+    ///
+    /// Default: 100ms
+    pub timeout: Duration,
+    /// The number of node IDs to probe concurrently
+    ///
+    /// Default: 10
+    pub parallelism: usize,
+    /// When true, node IDs which are currently sending heartbeats are not probed, and their cached
+    /// information is returned instead
+    ///
+    /// Default: false
+    pub incremental: bool,
+    /// The maximum time since the last heartbeat for a node to be considered alive during an
+    /// incremental scan
+    ///
+    /// Default: 3s
+    pub heartbeat_timeout: Duration,
+    /// When true, only the device type (0x1000) and identity (0x1018) objects are read from each
+    /// node, skipping the device name and version strings
+    ///
+    /// Default: false
+    pub probe_only: bool,
+    /// The minimum time between starting the probes of consecutive node IDs
+    ///
+    /// When many nodes are probed at once, their responses arrive in bursts, which can overflow the
+    /// receive FIFOs of small devices on the bus. Staggering the probes spreads out the responses.
+    /// Nodes can also delay their responses to scans, see `scan_response_jitter_max_ms` in the
+    /// device config.
+    ///
+    /// Default: 0
+    pub stagger: Duration,
+    /// When true, the capabilities of each node found are probed, and stored in
+    /// [`NodeInfo::capabilities`]
+    ///
+    /// The SDO server of each node is probed concurrently with the other nodes, with the same
+    /// parallelism as the scan. LSS support is then probed one node at a time, since only one node
+    /// can be selected at once, so it is only probed for nodes whose identity was read. This puts
+    /// all LSS slaves into Waiting mode.
+    ///
+    /// Default: false
+    pub probe_capabilities: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(100),
+            parallelism: 10,
+            incremental: false,
+            heartbeat_timeout: Duration::from_secs(3),
+            probe_only: false,
+            stagger: Duration::ZERO,
+            probe_capabilities: false,
+        }
+    }
+}
+
 async fn scan_node<S: AsyncCanSender + Sync + Send>(
     node_id: u8,
     clients: &SdoClientMutex<S>,
+    opts: &ScanOptions,
 ) -> Option<NodeInfo> {
     let mut sdo_client = clients.lock(node_id);
     log::info!("Scanning Node {node_id}");
-    let identity = match sdo_client.read_identity().await {
-        Ok(id) => Some(id),
-        Err(SdoClientError::NoResponse) => {
+    let mut info = probe_node(node_id, &mut sdo_client, opts.timeout, opts.probe_only).await?;
+    if opts.probe_capabilities {
+        info.capabilities = Some(probe_capabilities(node_id, &mut sdo_client).await);
+    }
+    Some(info)
+}
+
+/// The highest number of PDOs of each type a node can have
+const MAX_PDOS: u16 = 512;
+
+/// Count the PDOs of a node by reading their communication parameters, starting at `base`
+///
+/// Returns None if the node stops responding.
+async fn count_pdos<S: AsyncCanSender + Sync + Send>(
+    sdo_client: &mut SdoClient<SharedSender<S>, SharedReceiverChannel>,
+    base: u16,
+) -> Option<u16> {
+    for n in 0..MAX_PDOS {
+        match sdo_client.upload_u8(base + n, 0).await {
+            Ok(_) => (),
+            // The first missing object follows the last PDO
+            Err(e) if e.abort_code().is_some() => return Some(n),
+            Err(_) => return None,
+        }
+    }
+    Some(MAX_PDOS)
+}
+
+/// Probe the capabilities of a node via its SDO server
+///
+/// LSS support is not probed here, as it cannot be probed concurrently for several nodes.
+async fn probe_capabilities<S: AsyncCanSender + Sync + Send>(
+    node_id: u8,
+    sdo_client: &mut SdoClient<SharedSender<S>, SharedReceiverChannel>,
+) -> NodeCapabilities {
+    let block_transfer = match sdo_client.probe_block_support().await {
+        Ok(supported) => Some(supported),
+        Err(e) => {
+            log::error!("Failed to probe block transfer support of node {node_id}: {e:?}");
+            None
+        }
+    };
+    let heartbeat_period_ms = match sdo_client
+        .upload_u16(object_ids::HEARTBEAT_PRODUCER_TIME, 0)
+        .await
+    {
+        Ok(period) => Some(period),
+        Err(e) => {
+            log::error!("SDO Abort Response scanning node {node_id} heartbeat time: {e:?}");
+            None
+        }
+    };
+    NodeCapabilities {
+        block_transfer,
+        lss: None,
+        num_rpdo: count_pdos(sdo_client, 0x1400).await,
+        num_tpdo: count_pdos(sdo_client, 0x1800).await,
+        heartbeat_period_ms,
+    }
+}
+
+/// Read the identifying objects of a node
+///
+/// Returns None if the node does not respond.
+pub(super) async fn probe_node<S: AsyncCanSender + Sync + Send>(
+    node_id: u8,
+    sdo_client: &mut SdoClient<SharedSender<S>, SharedReceiverChannel>,
+    timeout: Duration,
+    probe_only: bool,
+) -> Option<NodeInfo> {
+    sdo_client.set_timeout(timeout);
+    let device_type = match sdo_client.read_device_type().await {
+        Ok(t) => Some(t),
+        Err(SdoClientError::NoResponse | SdoClientError::BusSilent { .. }) => {
             log::info!("No response from node {node_id}");
             return None;
         }
+        // The node was not probed at all
+        Err(SdoClientError::CompetingMaster { .. }) => return None,
+        Err(e) => {
+            // A server responded, but we failed to read the device type. An unexpected situation,
+            // as all nodes should implement the device type object
+            log::error!("SDO Abort Response scanning node {node_id} device type: {e:?}");
+            None
+        }
+    };
+    let identity = match sdo_client.read_identity().await {
+        Ok(id) => Some(id),
+        Err(SdoClientError::NoResponse | SdoClientError::BusSilent { .. }) => return None,
         Err(e) => {
-            // A server responded, but we failed to read identity. An unexpected situation, as all
-            // nodes should implement the identity object
             log::error!("SDO Abort Response scanning node {node_id} identity: {e:?}");
             None
         }
     };
-    let device_name = match sdo_client.read_device_name().await {
+    let mut info = NodeInfo {
+        identity,
+        device_type,
+        ..NodeInfo::new(node_id)
+    };
+    if probe_only {
+        return Some(info);
+    }
+    info.device_name = match sdo_client.read_device_name().await {
         Ok(s) => Some(s),
-        Err(SdoClientError::NoResponse) => return None,
+        Err(SdoClientError::NoResponse | SdoClientError::BusSilent { .. }) => return None,
         Err(e) => {
             log::error!("SDO Abort Response scanning node {node_id} device name: {e:?}");
             None
         }
     };
-    let software_version = match sdo_client.read_software_version().await {
+    info.software_version = match sdo_client.read_software_version().await {
         Ok(s) => Some(s),
         Err(e) => {
             log::error!("SDO Abort Response scanning node {node_id} SW version: {e:?}");
             None
         }
     };
-    let hardware_version = match sdo_client.read_hardware_version().await {
+    info.hardware_version = match sdo_client.read_hardware_version().await {
         Ok(s) => Some(s),
         Err(e) => {
             log::error!("SDO Abort Response scanning node {node_id} HW version: {e:?}");
             None
         }
     };
-    Some(NodeInfo {
-        node_id,
-        identity,
-        device_name,
-        software_version,
-        hardware_version,
-        nmt_state: None,
-        last_seen: Instant::now(),
-    })
+    Some(info)
 }
 
 #[derive(Debug)]
@@ -157,10 +505,21 @@ where
     S: AsyncCanSender,
     R: AsyncCanReceiver,
 {
-    _guard: std::sync::MutexGuard<'a, ()>,
+    /// Remembers whether the node supports block downloads between clients
+    block_supported: std::sync::MutexGuard<'a, Option<bool>>,
     client: SdoClient<S, R>,
 }
 
+impl<S, R> Drop for SdoClientGuard<'_, S, R>
+where
+    S: AsyncCanSender,
+    R: AsyncCanReceiver,
+{
+    fn drop(&mut self) {
+        *self.block_supported = self.client.block_supported();
+    }
+}
+
 impl<S, R> Deref for SdoClientGuard<'_, S, R>
 where
     S: AsyncCanSender,
@@ -190,7 +549,13 @@ where
 {
     sender: SharedSender<S>,
     receiver: SharedReceiverChannel,
-    clients: HashMap<u8, Mutex<()>>,
+    clients: HashMap<u8, Mutex<Option<bool>>>,
+    recorder: Option<TransactionRecorder>,
+    bus_load: BusLoadLimiter,
+    pacing: Arc<Mutex<HashMap<u8, FramePacing>>>,
+    transfers: TransferMonitor,
+    bus_activity: BusActivity,
+    master_activity: MasterActivity,
 }
 
 impl<S> SdoClientMutex<S>
@@ -200,13 +565,37 @@ where
     pub fn new(sender: SharedSender<S>, receiver: SharedReceiverChannel) -> Self {
         let mut clients = HashMap::new();
         for i in 0u8..128 {
-            clients.insert(i, Mutex::new(()));
+            clients.insert(i, Mutex::new(None));
         }
 
         Self {
             sender,
             receiver,
             clients,
+            recorder: None,
+            bus_load: BusLoadLimiter::new(),
+            pacing: Default::default(),
+            transfers: TransferMonitor::new(),
+            bus_activity: BusActivity::new(),
+            master_activity: MasterActivity::new(),
+        }
+    }
+
+    pub fn pacing(&self, id: u8) -> FramePacing {
+        self.pacing
+            .lock()
+            .unwrap()
+            .get(&id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn set_pacing(&self, id: u8, pacing: FramePacing) {
+        let mut all = self.pacing.lock().unwrap();
+        if pacing == FramePacing::default() {
+            all.remove(&id);
+        } else {
+            all.insert(id, pacing);
         }
     }
 
@@ -214,23 +603,116 @@ where
         if !(1..=127).contains(&id) {
             panic!("ID {} out of range", id);
         }
-        let guard = self.clients.get(&id).unwrap().lock().unwrap();
-        let client = SdoClient::new_std(id, self.sender.clone(), self.receiver.clone());
+        let block_supported = self.clients.get(&id).unwrap().lock().unwrap();
+        let mut client = self.unlocked(id);
+        client.set_block_supported(*block_supported);
         SdoClientGuard {
-            _guard: guard,
+            block_supported,
             client,
         }
     }
+
+    /// Create a client for a node's default SDO server, without taking the node's lock
+    pub fn unlocked(&self, id: u8) -> SdoClient<SharedSender<S>, SharedReceiverChannel> {
+        let mut client = SdoClient::new_std(id, self.sender.clone(), self.receiver.clone());
+        client.set_recorder(self.recorder.clone());
+        client.set_bus_load_limiter(Some(self.bus_load.clone()));
+        client.set_frame_pacing(self.pacing(id));
+        client.set_transfer_monitor(Some(self.transfers.clone()));
+        client.set_bus_activity(Some(self.bus_activity.clone()));
+        client.set_master_activity(Some(self.master_activity.clone()));
+        client
+    }
+
+    pub fn client_with_cob_ids(
+        &self,
+        cob_ids: SdoCobIds,
+    ) -> SdoClient<SharedSender<S>, SharedReceiverChannel> {
+        let mut client =
+            SdoClient::from_cob_ids(cob_ids, self.sender.clone(), self.receiver.clone());
+        client.set_recorder(self.recorder.clone());
+        client.set_bus_load_limiter(Some(self.bus_load.clone()));
+        client.set_transfer_monitor(Some(self.transfers.clone()));
+        client.set_bus_activity(Some(self.bus_activity.clone()));
+        client.set_master_activity(Some(self.master_activity.clone()));
+        client
+    }
+
+    /// Get a factory for clients which can be used from a background task
+    pub fn probe_clients(&self) -> ProbeClients<S> {
+        ProbeClients {
+            sender: self.sender.clone(),
+            receiver: self.receiver.clone(),
+            recorder: self.recorder.clone(),
+            bus_load: self.bus_load.clone(),
+            pacing: self.pacing.clone(),
+            transfers: self.transfers.clone(),
+            bus_activity: self.bus_activity.clone(),
+            master_activity: self.master_activity.clone(),
+        }
+    }
 }
 
 /// Manage a zencan bus
+///
+/// The manager runs background tasks which receive from the bus, and optionally send a heartbeat
+/// or SYNC, or discover nodes. Use [`shutdown`](Self::shutdown) to stop them and release the bus cleanly.
+///
+/// Dropping the manager also stops its background tasks and drops the receiver, so that
+/// [`Device`]s and clients created from it stop receiving, and their transfers fail, but it sends
+/// no SDO aborts, and the sender is only dropped once they have all been dropped too. The tasks
+/// are also stopped if the tokio runtime shuts down first, and the manager can still be dropped
+/// afterwards.
 #[derive(Debug)]
 pub struct BusManager<S: AsyncCanSender + Sync + Send> {
     sender: SharedSender<S>,
     receiver: SharedReceiver,
     nodes: Arc<tokio::sync::Mutex<HashMap<u8, NodeInfo>>>,
     sdo_clients: SdoClientMutex<S>,
-    _monitor_task: JoinHandle<()>,
+    emcy_decoders: Arc<std::sync::RwLock<EmcyDecoders>>,
+    heartbeat: Mutex<Option<HeartbeatProducer>>,
+    sync: Mutex<Option<SyncProducer>>,
+    discovery: Mutex<Option<Discovery>>,
+    node_events: tokio::sync::broadcast::Sender<NodeEvent>,
+    silence_watchdog: Mutex<Option<SilenceWatchdog>>,
+    bus_events: tokio::sync::broadcast::Sender<BusEvent>,
+    secondary_heartbeat: SecondaryHeartbeat,
+    cob_ids: CobIdRegistry,
+    object_cache: ObjectCache,
+    capture: FrameCapture,
+    /// The links of a redundant bus, if the manager was created with two transports
+    failover: Option<FailoverState>,
+    monitor_task: JoinHandle<()>,
+}
+
+impl<P, B> BusManager<RedundantSender<P, B>>
+where
+    P: AsyncCanSender + Sync + Send,
+    B: AsyncCanSender + Sync + Send,
+{
+    /// Create a bus manager for a redundant network, with a transport for each of its buses
+    ///
+    /// Frames are sent and received on the primary bus, until it fails, e.g. by going bus-off or
+    /// silent, after which the manager fails over to the backup bus. The failure is detected as set
+    /// by `options`, and reported with a [`BusEvent::Failover`]. See [`crate::failover`].
+    ///
+    /// The receivers must be cancel safe: a receive from one bus is cancelled whenever a frame is
+    /// received from the other.
+    pub fn new_redundant(
+        primary_sender: P,
+        primary_receiver: impl AsyncCanReceiver + Sync + 'static,
+        backup_sender: B,
+        backup_receiver: impl AsyncCanReceiver + Sync + 'static,
+        options: FailoverOptions,
+    ) -> Self {
+        let failover = FailoverState::new(options);
+        let sender = RedundantSender::new(primary_sender, backup_sender, failover.clone());
+        let receiver = RedundantReceiver::new(primary_receiver, backup_receiver, failover.clone());
+        let mut manager = Self::new(sender, receiver);
+        failover.set_events(manager.bus_events.clone());
+        manager.failover = Some(failover);
+        manager
+    }
 }
 
 impl<S: AsyncCanSender + Sync + Send> BusManager<S> {
@@ -242,38 +724,60 @@ impl<S: AsyncCanSender + Sync + Send> BusManager<S> {
     /// - `receiver`: An object which implements [`AsyncCanReceiver`] to be used for receiving
     ///   messages from the bus
     ///
-    /// When using socketcan, these can be created with [`crate::open_socketcan`]
+    /// These can be created with [`crate::open_transport`]. If the transport delivers the frames sent
+    /// by the manager back to its receiver, as reported by
+    /// [`AsyncCanSender::echoes_sent_frames`], they are recognized and dropped.
     pub fn new(sender: S, receiver: impl AsyncCanReceiver + Sync + 'static) -> Self {
-        let mut receiver = SharedReceiver::new(receiver);
-        let sender = SharedSender::new(Arc::new(tokio::sync::Mutex::new(sender)));
+        // Frames sent by the manager are dropped if the transport echoes them back, so that they are
+        // not mistaken for traffic from another master
+        let echoes = EchoFilter::new(sender.echoes_sent_frames());
+        let capture = FrameCapture::default();
+        let mut receiver = SharedReceiver::new(receiver, echoes.clone(), capture.clone());
+        let sender = SharedSender::new(sender, echoes, capture.clone());
         let sdo_clients = SdoClientMutex::new(sender.clone(), receiver.create_rx());
 
         let mut state_rx = receiver.create_rx();
         let nodes = Arc::new(tokio::sync::Mutex::new(HashMap::new()));
+        let secondary_heartbeat = SecondaryHeartbeat::default();
+        let cob_ids = CobIdRegistry::new();
+        let object_cache = ObjectCache::new();
+        let bus_events = tokio::sync::broadcast::channel(16).0;
 
         let monitor_task = {
             let nodes = nodes.clone();
+            let bus_load = sdo_clients.bus_load.clone();
+            let bus_activity = sdo_clients.bus_activity.clone();
+            let master_activity = sdo_clients.master_activity.clone();
+            let bus_events = bus_events.clone();
+            let cob_ids = cob_ids.clone();
+            let object_cache = object_cache.clone();
+            let secondary_heartbeat = secondary_heartbeat.clone();
             tokio::spawn(async move {
-                loop {
-                    if let Ok(msg) = state_rx.recv().await {
-                        if let Ok(ZencanMessage::Heartbeat(heartbeat)) =
-                            ZencanMessage::try_from(msg)
-                        {
-                            let id_num = heartbeat.node;
-                            if let Ok(node_id) = NodeId::try_from(id_num) {
-                                let mut nodes = nodes.lock().await;
-                                if let std::collections::hash_map::Entry::Vacant(e) =
-                                    nodes.entry(id_num)
-                                {
-                                    e.insert(NodeInfo::new(node_id.raw()));
-                                } else {
-                                    let node = nodes.get_mut(&id_num).unwrap();
-                                    node.nmt_state = Some(heartbeat.state);
-                                    node.last_seen = Instant::now();
-                                }
-                            } else {
-                                log::warn!("Invalid heartbeat node ID {id_num} received");
+                // The channel is closed when the manager is shut down
+                while let Ok(msg) = state_rx.recv().await {
+                    bus_load.record_frame();
+                    bus_activity.record_frame(msg.receive_instant());
+                    if let Some(event) =
+                        master_activity.record_frame(msg.id(), msg.receive_instant())
+                    {
+                        bus_events.send(event).ok();
+                    }
+                    cob_ids.record_frame(msg.id(), msg.receive_instant());
+                    if let Some((heartbeat, source)) = secondary_heartbeat.decode(msg) {
+                        let id_num = heartbeat.node;
+                        if let Ok(node_id) = NodeId::try_from(id_num) {
+                            // A node which has booted may have been reflashed or
+                            // reconfigured
+                            if heartbeat.state == NmtState::Bootup {
+                                object_cache.invalidate(id_num);
                             }
+                            let mut nodes = nodes.lock().await;
+                            let node = nodes
+                                .entry(id_num)
+                                .or_insert_with(|| NodeInfo::new(node_id.raw()));
+                            node.record_heartbeat(heartbeat.state, source, msg.receive_instant());
+                        } else {
+                            log::warn!("Invalid heartbeat node ID {id_num} received");
                         }
                     }
                 }
@@ -285,8 +789,58 @@ impl<S: AsyncCanSender + Sync + Send> BusManager<S> {
             receiver,
             sdo_clients,
             nodes,
-            _monitor_task: monitor_task,
+            emcy_decoders: Default::default(),
+            heartbeat: Mutex::new(None),
+            sync: Mutex::new(None),
+            discovery: Mutex::new(None),
+            node_events: tokio::sync::broadcast::channel(64).0,
+            silence_watchdog: Mutex::new(None),
+            bus_events,
+            secondary_heartbeat,
+            cob_ids,
+            object_cache,
+            capture,
+            failover: None,
+            monitor_task,
+        }
+    }
+
+    /// Shut down the manager, and release the bus
+    ///
+    /// This:
+    /// - Stops the manager heartbeat and SYNC, background discovery and the silence watchdog
+    /// - Sends an SDO abort for each transfer in progress, e.g. on a [`Device`] used by another
+    ///   task, so that the servers do not wait for the rest of the transfer
+    /// - Closes the transport. The sender and receiver are dropped, so that the transfers in
+    ///   progress fail without waiting for a timeout, and later requests from devices and clients
+    ///   created from the manager fail with [`SdoClientError::SocketSendFailed`]
+    /// - Flushes the [transaction recorder](Self::set_recorder)
+    ///
+    /// Only transfers by clients created from the manager are aborted. Returns the transfers which
+    /// were aborted.
+    pub async fn shutdown(self) -> Vec<InFlightTransfer> {
+        self.stop_heartbeat();
+        self.stop_sync();
+        self.stop_discovery();
+        self.set_silence_timeout(None);
+
+        let transfers = self.sdo_clients.transfers.transfers();
+        let aborts: Vec<CanMessage> = transfers
+            .iter()
+            .map(|t| {
+                SdoRequest::abort(t.index, t.sub, AbortCode::GeneralError)
+                    .to_can_message(t.cob_ids.request)
+            })
+            .collect();
+        self.sender.close(&aborts).await;
+        self.receiver.close();
+
+        if let Some(recorder) = &self.sdo_clients.recorder {
+            if let Err(e) = recorder.flush() {
+                log::error!("Failed to flush transaction log: {e}");
+            }
         }
+        transfers
     }
 
     /// Get an SDO client for a particular node
@@ -300,6 +854,795 @@ impl<S: AsyncCanSender + Sync + Send> BusManager<S> {
         self.sdo_clients.lock(node_id)
     }
 
+    /// Get a [`Device`] handle for working with a single node
+    ///
+    /// The device has its own SDO client, which does not take the lock used by
+    /// [`sdo_client`](Self::sdo_client), so it should not be used at the same time as another
+    /// client for the same node. It shares the manager's bus, settings, and EMCY decoders.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node_id` is not in the range 1..=127
+    pub fn device(&self, node_id: u8) -> Device<S> {
+        if !(1..=127).contains(&node_id) {
+            panic!("ID {} out of range", node_id);
+        }
+        Device::new(
+            node_id,
+            self.sdo_clients.unlocked(node_id),
+            self.sender.clone(),
+            self.sdo_clients.receiver.clone(),
+            self.emcy_decoders.clone(),
+            self.nodes.clone(),
+            self.object_cache.clone(),
+        )
+    }
+
+    /// Get an SDO client for a server on a non-default COB-ID pair
+    ///
+    /// This is used for devices whose SDO server has been moved from the default COB-IDs, or which
+    /// provide additional SDO servers. Unlike [`sdo_client`](Self::sdo_client), access is not
+    /// serialized, so the caller must ensure only one client talks to each server at a time.
+    pub fn sdo_client_with_cob_ids(
+        &self,
+        cob_ids: SdoCobIds,
+    ) -> SdoClient<SharedSender<S>, SharedReceiverChannel> {
+        self.sdo_clients.client_with_cob_ids(cob_ids)
+    }
+
+    /// Record all SDO, NMT and LSS operations performed by the manager to a transaction log
+    ///
+    /// SDO clients obtained from [`sdo_client`](Self::sdo_client) after this is called also record
+    /// their operations. Pass `None` to stop recording. See [`crate::transaction_log`].
+    pub fn set_recorder(&mut self, recorder: Option<TransactionRecorder>) {
+        self.sdo_clients.recorder = recorder;
+    }
+
+    /// Start recording every frame sent and received by the manager
+    ///
+    /// Frames sent by the manager, and by the devices and clients created from it, are recorded as
+    /// received by the nodes, and frames received from the bus as transmitted by the nodes. Any
+    /// capture in progress is discarded. See [`crate::frame_capture`] for how a capture can be
+    /// stored, and used as a test fixture.
+    pub fn start_capture(&self) {
+        self.capture.start();
+    }
+
+    /// Stop recording, and return the frames recorded since [`start_capture`](Self::start_capture)
+    ///
+    /// Frames are timed from the start of the capture. Returns None if no capture was started.
+    pub fn stop_capture(&self) -> Option<FrameRecording> {
+        self.capture.stop()
+    }
+
+    /// Returns true if a capture started with [`start_capture`](Self::start_capture) is running
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_active()
+    }
+
+    fn record(&self, start: Started, operation: Operation, outcome: Outcome) {
+        if let Some(recorder) = &self.sdo_clients.recorder {
+            recorder.record_operation(start, operation, outcome);
+        }
+    }
+
+    /// Perform the operations in a transaction log again
+    ///
+    /// Only successful operations which change the state of a node are performed, in the order they
+    /// were recorded: SDO downloads, NMT commands, and LSS configuration commands. Reads, and failed
+    /// operations, are skipped. This can be used to repeat a commissioning session on replacement
+    /// hardware.
+    ///
+    /// Returns the result of each performed operation. Re-application stops at the first failure.
+    pub async fn reapply(
+        &mut self,
+        transactions: &[Transaction],
+    ) -> Vec<(Operation, Result<(), String>)> {
+        let mut results = Vec::new();
+        for t in transactions {
+            if !t.operation.is_write() || !t.outcome.is_ok() {
+                continue;
+            }
+            let result = self.reapply_operation(&t.operation).await;
+            let failed = result.is_err();
+            results.push((t.operation.clone(), result));
+            if failed {
+                break;
+            }
+        }
+        results
+    }
+
+    async fn reapply_operation(&mut self, operation: &Operation) -> Result<(), String> {
+        match operation {
+            Operation::SdoDownload {
+                node,
+                index,
+                sub,
+                data,
+                block,
+            } => {
+                let node = node.ok_or("SDO download has no node ID")?;
+                let mut client = self.sdo_client(node);
+                let result = if *block {
+                    client.block_download(*index, *sub, data).await
+                } else {
+                    client.download(*index, *sub, data).await
+                };
+                result.map_err(|e| e.to_string())
+            }
+            Operation::Nmt { command, node } => {
+                let cmd = match command.as_str() {
+                    "Start" => NmtCommandSpecifier::Start,
+                    "Stop" => NmtCommandSpecifier::Stop,
+                    "EnterPreOp" => NmtCommandSpecifier::EnterPreOp,
+                    "ResetApp" => NmtCommandSpecifier::ResetApp,
+                    "ResetComm" => NmtCommandSpecifier::ResetComm,
+                    _ => return Err(format!("Unknown NMT command {command}")),
+                };
+                self.send_nmt_cmd(cmd, *node).await;
+                Ok(())
+            }
+            Operation::LssGlobalMode { mode } => {
+                let mode = match mode.as_str() {
+                    "Waiting" => LssState::Waiting,
+                    "Configuring" => LssState::Configuring,
+                    _ => return Err(format!("Unknown LSS mode {mode}")),
+                };
+                self.lss_set_global_mode(mode).await;
+                Ok(())
+            }
+            Operation::LssActivate {
+                vendor_id,
+                product_code,
+                revision,
+                serial,
+            } => self
+                .lss_activate(LssIdentity::new(
+                    *vendor_id,
+                    *product_code,
+                    *revision,
+                    *serial,
+                ))
+                .await
+                .map_err(|e| e.to_string()),
+            Operation::LssSetNodeId { node_id } => {
+                let node_id = NodeId::new(*node_id).map_err(|_| "Invalid node ID".to_string())?;
+                self.lss_set_node_id(node_id)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+            Operation::LssStoreConfig => self.lss_store_config().await.map_err(|e| e.to_string()),
+            Operation::SdoUpload { .. } | Operation::LssFastscan | Operation::LssAutoAssign => {
+                Ok(())
+            }
+        }
+    }
+
+    /// Limit the bus utilization of SDO traffic from the manager, or remove the limit
+    ///
+    /// SDO requests from all clients created by the manager, including those used to apply
+    /// configurations and flash firmware, are delayed so that they use at most the budgeted
+    /// fraction of the bus, and leave room for the other traffic measured on the bus. See
+    /// [`crate::bus_load`].
+    pub fn set_bus_load_budget(&self, budget: Option<BusLoadBudget>) {
+        self.sdo_clients.bus_load.set_budget(budget);
+    }
+
+    /// Set the pacing of the SDO frames sent to a node
+    ///
+    /// The pacing applies to all SDO clients for the node created by the manager after it is set,
+    /// including those used to apply configurations and flash firmware. Setting the default
+    /// [`FramePacing`] removes the pacing. See [`crate::bus_load`].
+    ///
+    /// # Panics
+    /// Panics if the node ID is not in the range 1 to 127
+    pub fn set_frame_pacing(&self, node_id: u8, pacing: FramePacing) {
+        if !(1..=127).contains(&node_id) {
+            panic!("ID {} out of range", node_id);
+        }
+        self.sdo_clients.set_pacing(node_id, pacing);
+    }
+
+    /// Get the pacing of the SDO frames sent to a node
+    pub fn frame_pacing(&self, node_id: u8) -> FramePacing {
+        self.sdo_clients.pacing(node_id)
+    }
+
+    /// Get the SDO transfers currently being run by clients created by the manager, oldest first
+    ///
+    /// This can be called from another task while a transfer appears to be stuck, to see which
+    /// object it accesses, how far it got, and when the node last responded. See
+    /// [`crate::transfer_monitor`].
+    pub fn sdo_transfers(&self) -> Vec<InFlightTransfer> {
+        self.sdo_clients.transfers.transfers()
+    }
+
+    /// Get the SDO transfer currently being run with a node's default SDO server, if any
+    pub fn sdo_transfer(&self, node_id: u8) -> Option<InFlightTransfer> {
+        self.sdo_clients.transfers.transfer(node_id)
+    }
+
+    /// Get the bus utilization measured over the last 100ms, from 0.0 to 1.0
+    ///
+    /// Returns None if no bus load budget is set, as the bus is only measured when one is.
+    pub fn bus_utilization(&self) -> Option<f32> {
+        self.sdo_clients.bus_load.utilization()
+    }
+
+    /// Get a handle for raw access to the bus
+    ///
+    /// The returned [`RawHandle`] can send arbitrary messages using the same socket as the
+    /// manager, and receives a copy of all bus traffic without interfering with the manager's own
+    /// protocol processing. A filter can be applied with [`RawHandle::with_filter`].
+    pub fn raw_handle(&mut self) -> RawHandle<S> {
+        RawHandle::new(self.sender.clone(), self.receiver.create_rx())
+    }
+
+    /// Start sending a heartbeat from the manager
+    ///
+    /// Some CiA 302 setups require the nodes to monitor the heartbeat of the manager, e.g. so that
+    /// they enter a safe state when it is lost. Once started, an Operational heartbeat is sent from
+    /// `node_id` every `period` in a background task, until [`stop_heartbeat`](Self::stop_heartbeat)
+    /// is called or the manager is dropped. Starting the heartbeat again replaces the previous
+    /// configuration, and a period of zero stops it.
+    ///
+    /// `node_id` must not be used by any node on the bus.
+    pub fn start_heartbeat(&self, node_id: ConfiguredId, period: Duration)
+    where
+        S: 'static,
+    {
+        let mut heartbeat = self.heartbeat.lock().unwrap();
+        // Stop the previous task first, so that two heartbeats are never sent at once
+        *heartbeat = None;
+        if !period.is_zero() {
+            let config = ManagerHeartbeat { node_id, period };
+            *heartbeat = Some(HeartbeatProducer::start(self.sender.clone(), config));
+        }
+    }
+
+    /// Change the period of the manager heartbeat
+    ///
+    /// Returns false if the heartbeat is not running.
+    pub fn set_heartbeat_period(&self, period: Duration) -> bool
+    where
+        S: 'static,
+    {
+        match self.heartbeat() {
+            Some(config) => {
+                self.start_heartbeat(config.node_id, period);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop sending the manager heartbeat
+    ///
+    /// Returns false if the heartbeat was not running.
+    pub fn stop_heartbeat(&self) -> bool {
+        self.heartbeat.lock().unwrap().take().is_some()
+    }
+
+    /// Get the configuration of the manager heartbeat, or None if it is not running
+    pub fn heartbeat(&self) -> Option<ManagerHeartbeat> {
+        self.heartbeat.lock().unwrap().as_ref().map(|h| h.config())
+    }
+
+    /// Start sending SYNC from the manager
+    ///
+    /// Once started, a SYNC is sent every `period`, starting one period from now, until
+    /// [`stop_sync`](Self::stop_sync) is called or the manager is dropped. Nodes send their
+    /// synchronous TPDOs in response to each SYNC, so their timing follows the SYNC timing. With a
+    /// `counter_overflow` from 2 to 240, each SYNC carries a counter from 1 to `counter_overflow`.
+    /// With 0, SYNCs carry no counter.
+    ///
+    /// The SYNC is sent from a background task, so its period is subject to the scheduling jitter
+    /// of the application. On Linux, use [`start_sync_offloaded`](Self::start_sync_offloaded) to
+    /// send it from a kernel timer instead. Starting the SYNC again replaces the previous
+    /// configuration, and a period of zero stops it.
+    pub fn start_sync(
+        &self,
+        period: Duration,
+        counter_overflow: u8,
+    ) -> Result<(), SyncProducerError>
+    where
+        S: 'static,
+    {
+        self.start_sync_with(ManagerSync {
+            period,
+            counter_overflow,
+            offload: None,
+        })
+    }
+
+    /// Start sending SYNC from the kernel Broadcast Manager of a socketcan interface
+    ///
+    /// This is the same as [`start_sync`](Self::start_sync), except that the SYNCs are sent by a
+    /// kernel timer, at an exact period without user space jitter, even when the application is
+    /// busy. Changing the period with [`set_sync_period`](Self::set_sync_period) re-programs the
+    /// kernel job. `device` must be the interface the manager is using, e.g. "can0", and
+    /// offloading is only supported on Linux.
+    ///
+    /// The SYNCs are sent on their own socket, so they are seen by the manager, and e.g.
+    /// [captured](Self::start_capture), as frames sent by another device.
+    pub fn start_sync_offloaded(
+        &self,
+        device: &str,
+        period: Duration,
+        counter_overflow: u8,
+    ) -> Result<(), SyncProducerError>
+    where
+        S: 'static,
+    {
+        self.start_sync_with(ManagerSync {
+            period,
+            counter_overflow,
+            offload: Some(device.to_string()),
+        })
+    }
+
+    fn start_sync_with(&self, config: ManagerSync) -> Result<(), SyncProducerError>
+    where
+        S: 'static,
+    {
+        let mut sync = self.sync.lock().unwrap();
+        // Stop the previous producer first, so that two SYNCs are never sent at once
+        *sync = None;
+        if !config.period.is_zero() {
+            *sync = Some(SyncProducer::start(self.sender.clone(), config)?);
+        }
+        Ok(())
+    }
+
+    /// Change the period of the manager SYNC
+    ///
+    /// Returns false if the SYNC is not running. A period of zero stops it.
+    pub fn set_sync_period(&self, period: Duration) -> Result<bool, SyncProducerError>
+    where
+        S: 'static,
+    {
+        let mut sync = self.sync.lock().unwrap();
+        match sync.as_mut() {
+            Some(_) if period.is_zero() => {
+                *sync = None;
+                Ok(true)
+            }
+            Some(producer) => {
+                producer.set_period(self.sender.clone(), period)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Stop sending the manager SYNC
+    ///
+    /// Returns false if the SYNC was not running.
+    pub fn stop_sync(&self) -> bool {
+        self.sync.lock().unwrap().take().is_some()
+    }
+
+    /// Get the configuration of the manager SYNC, or None if it is not running
+    pub fn sync(&self) -> Option<ManagerSync> {
+        self.sync
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|s| s.config().clone())
+    }
+
+    /// Start discovering nodes in the background
+    ///
+    /// Once started, a background task listens for heartbeats and boot-up messages. When one is
+    /// received from a node which is not known to be present, the node's identity is read as it
+    /// would be by [`scan_nodes_with`](Self::scan_nodes_with), the information is added to the
+    /// [`node_list`](Self::node_list), and a [`NodeEvent::Attached`] event is sent to all
+    /// [`NodeEvents`] receivers. When an attached node stops sending heartbeats for
+    /// `opts.heartbeat_timeout`, or sends a boot-up message, a [`NodeEvent::Detached`] event is
+    /// sent. Nodes which do not produce a heartbeat are not discovered.
+    ///
+    /// Discovery runs until [`stop_discovery`](Self::stop_discovery) is called or the manager is
+    /// dropped. Starting it again replaces the options, and forgets which nodes are attached.
+    ///
+    /// The probe of a new node does not wait for an [`sdo_client`](Self::sdo_client) locked by
+    /// another task, so an application which accesses a node immediately after it boots may see
+    /// the probe's SDO responses interfere with its own.
+    pub fn start_discovery(&self, opts: DiscoveryOptions)
+    where
+        S: 'static,
+    {
+        let mut discovery = self.discovery.lock().unwrap();
+        *discovery = None;
+        *discovery = Some(Discovery::start(
+            self.sdo_clients.receiver.clone(),
+            self.sdo_clients.probe_clients(),
+            self.nodes.clone(),
+            self.node_events.clone(),
+            self.secondary_heartbeat.clone(),
+            opts,
+        ));
+    }
+
+    /// Stop discovering nodes in the background
+    ///
+    /// Returns false if discovery was not running.
+    pub fn stop_discovery(&self) -> bool {
+        self.discovery.lock().unwrap().take().is_some()
+    }
+
+    /// Get the options of background discovery, or None if it is not running
+    pub fn discovery(&self) -> Option<DiscoveryOptions> {
+        self.discovery.lock().unwrap().as_ref().map(|d| d.options())
+    }
+
+    /// Get a receiver for the events produced by background discovery
+    ///
+    /// The receiver gets the events sent after it is created. See
+    /// [`start_discovery`](Self::start_discovery).
+    pub fn node_events(&self) -> NodeEvents {
+        NodeEvents::new(self.node_events.subscribe())
+    }
+
+    /// Set the base COB-ID of the secondary heartbeats sent by nodes on a redundant network
+    ///
+    /// Nodes built with a secondary heartbeat (object 0x5007) send each heartbeat on this base +
+    /// node ID, as well as on 0x700 + node ID. Once it is set, heartbeats received on either
+    /// COB-ID update the state of the node, and keep it attached during [background
+    /// discovery](Self::start_discovery), so that a node is only lost when both are missing. The
+    /// time of the last heartbeat from each source is kept in the [`NodeInfo`], and can be checked
+    /// with [`NodeInfo::is_heartbeating_on`] to detect the loss of one network.
+    ///
+    /// Both sources must reach the manager's receiver, e.g. from a transport which receives from
+    /// both networks, or a gateway which forwards the secondary heartbeats. Pass None to only
+    /// accept heartbeats on the standard COB-ID.
+    pub fn set_secondary_heartbeat_base(&self, base: Option<u16>) {
+        self.secondary_heartbeat.set_base(base);
+    }
+
+    /// Get the base COB-ID of secondary heartbeats, if set
+    pub fn secondary_heartbeat_base(&self) -> Option<u16> {
+        self.secondary_heartbeat.base()
+    }
+
+    /// Watch for the bus going silent
+    ///
+    /// When no frame has been received from the bus for `timeout`, a [`BusEvent::Silent`] is sent
+    /// to all [`BusEvents`] receivers, and SDO requests made through the manager which get no
+    /// response fail with [`SdoClientError::BusSilent`] instead of [`SdoClientError::NoResponse`].
+    /// A [`BusEvent::Active`] is sent when a frame is received again. Pass None to stop watching.
+    /// See [`crate::bus_silence`].
+    pub fn set_silence_timeout(&self, timeout: Option<Duration>) {
+        let mut watchdog = self.silence_watchdog.lock().unwrap();
+        *watchdog = None;
+        let activity = &self.sdo_clients.bus_activity;
+        activity.set_timeout(timeout);
+        if let Some(timeout) = timeout {
+            *watchdog = Some(SilenceWatchdog::start(
+                activity.clone(),
+                timeout,
+                self.bus_events.clone(),
+            ));
+        }
+    }
+
+    /// Get the silence timeout, or None if the manager is not watching for a silent bus
+    pub fn silence_timeout(&self) -> Option<Duration> {
+        self.sdo_clients.bus_activity.timeout()
+    }
+
+    /// Get the time since a frame was last received, if it exceeds the silence timeout
+    pub fn bus_silence(&self) -> Option<Duration> {
+        self.sdo_clients.bus_activity.silence()
+    }
+
+    /// Get a receiver for the events produced by the silence watchdog, the competing master
+    /// detection, and the failover of a redundant bus
+    ///
+    /// The receiver gets the events sent after it is created. See
+    /// [`set_silence_timeout`](Self::set_silence_timeout),
+    /// [`set_master_policy`](Self::set_master_policy) and [`crate::failover`].
+    pub fn bus_events(&self) -> BusEvents {
+        BusEvents::new(self.bus_events.subscribe())
+    }
+
+    /// Get the link in use, if the manager was created with
+    /// [`new_redundant`](BusManager::new_redundant)
+    pub fn active_link(&self) -> Option<Link> {
+        self.failover.as_ref().map(|f| f.active())
+    }
+
+    /// Switch a redundant bus to `link`
+    ///
+    /// A [`BusEvent::Failover`] is sent, with
+    /// [`FailoverReason::Requested`](crate::failover::FailoverReason::Requested). The manager still
+    /// fails over automatically if the link fails. Returns false if the link was already active,
+    /// or the manager was not created with [`new_redundant`](BusManager::new_redundant).
+    pub fn set_active_link(&self, link: Link) -> bool {
+        self.failover.as_ref().is_some_and(|f| f.set_active(link))
+    }
+
+    /// Set what the manager does when another master is active on the bus
+    ///
+    /// A [`BusEvent::CompetingMaster`] is sent whatever the policy. With [`MasterPolicy::Yield`],
+    /// NMT commands are not sent and SDO requests fail with [`SdoClientError::CompetingMaster`]
+    /// while the other master is active. See [`crate::competing_master`].
+    pub fn set_master_policy(&self, policy: MasterPolicy) {
+        self.sdo_clients.master_activity.set_policy(policy);
+    }
+
+    /// Get the policy for when another master is active on the bus
+    pub fn master_policy(&self) -> MasterPolicy {
+        self.sdo_clients.master_activity.policy()
+    }
+
+    /// Get the COB-ID of the last frame from another master, if one is active on the bus
+    pub fn competing_master(&self) -> Option<CanId> {
+        self.sdo_clients.master_activity.active()
+    }
+
+    /// Register a decoder for the manufacturer specific bytes of EMCYs from nodes with a vendor ID
+    ///
+    /// Replaces any decoder previously registered for the vendor. The decoder is used by
+    /// [`BusManager::decode_emcy`] and by all [`EmcyMonitor`]s created by the manager, including
+    /// those created before it was registered.
+    pub fn register_emcy_decoder(&self, vendor_id: u32, decoder: impl EmcyDecoder + 'static) {
+        self.emcy_decoders
+            .write()
+            .unwrap()
+            .register(vendor_id, decoder);
+    }
+
+    /// Decode an EMCY, using the decoder registered for the vendor ID of the node which sent it
+    ///
+    /// The vendor ID is taken from the node's identity, read when the node was scanned. EMCYs from
+    /// nodes which have not been scanned are returned without a diagnostic.
+    pub async fn decode_emcy(&self, emcy: Emergency) -> DecodedEmcy {
+        let vendor_id = self
+            .nodes
+            .lock()
+            .await
+            .get(&emcy.node)
+            .and_then(|n| n.identity)
+            .map(|id| id.vendor_id);
+        self.emcy_decoders.read().unwrap().decode(emcy, vendor_id)
+    }
+
+    /// Get a monitor which receives EMCYs from all nodes, decoded with the registered decoders
+    pub fn emcy_monitor(&mut self) -> EmcyMonitor<S> {
+        EmcyMonitor::new(
+            self.raw_handle(),
+            self.emcy_decoders.clone(),
+            self.nodes.clone(),
+        )
+    }
+
+    /// Read the identity object (0x1018) from a node, and check it against an expected pattern
+    ///
+    /// This should be used before an operation which would be harmful if applied to the wrong
+    /// device, such as loading firmware or applying a configuration. On success, the identity read
+    /// from the node is returned.
+    pub async fn verify_identity(
+        &self,
+        node_id: u8,
+        expected: IdentityMatch,
+    ) -> Result<LssIdentity, IdentityError> {
+        let mut client = self.sdo_client(node_id);
+        let actual = client
+            .read_identity()
+            .await
+            .context(ReadFailedSnafu { node_id })?;
+        let mismatches = expected.mismatches(&actual);
+        if mismatches.is_empty() {
+            Ok(actual)
+        } else {
+            MismatchSnafu {
+                node_id,
+                actual,
+                mismatches,
+            }
+            .fail()
+        }
+    }
+
+    /// Configure the heartbeat consumer entries (object 0x1016) of a node
+    ///
+    /// Each entry in `consumers` is a peer node ID, and the time after which the node should
+    /// consider the peer lost if no heartbeat is received from it. The entries are written in order
+    /// starting from sub index 1, and any remaining entries are cleared. All entries are then read
+    /// back to verify that the node accepted them.
+    ///
+    /// To set up mutual supervision, call this for each node with the others as its peers.
+    pub async fn configure_heartbeat_consumers(
+        &self,
+        node_id: u8,
+        consumers: &[(u8, Duration)],
+    ) -> Result<(), HeartbeatConsumerError> {
+        let entries = heartbeat_consumer::encode_entries(consumers)?;
+        let mut client = self.sdo_client(node_id);
+        let available = client
+            .upload_u8(object_ids::HEARTBEAT_CONSUMER_TIME, 0)
+            .await
+            .context(heartbeat_consumer::SdoSnafu { node_id })? as usize;
+        if entries.len() > available {
+            return heartbeat_consumer::TooManyPeersSnafu {
+                node_id,
+                requested: entries.len(),
+                available,
+            }
+            .fail();
+        }
+
+        // Clear all entries first, so that moving a peer to a different entry does not create a
+        // duplicate while the new entries are written
+        for sub in 1..=available as u8 {
+            client
+                .download_u32(object_ids::HEARTBEAT_CONSUMER_TIME, sub, 0)
+                .await
+                .context(heartbeat_consumer::SdoSnafu { node_id })?;
+        }
+        for (i, entry) in entries.iter().enumerate() {
+            client
+                .download_u32(object_ids::HEARTBEAT_CONSUMER_TIME, i as u8 + 1, *entry)
+                .await
+                .context(heartbeat_consumer::SdoSnafu { node_id })?;
+        }
+
+        for sub in 1..=available as u8 {
+            let expected = entries.get(sub as usize - 1).copied().unwrap_or(0);
+            let actual = client
+                .upload_u32(object_ids::HEARTBEAT_CONSUMER_TIME, sub)
+                .await
+                .context(heartbeat_consumer::SdoSnafu { node_id })?;
+            if actual != expected {
+                return heartbeat_consumer::VerifyFailedSnafu {
+                    node_id,
+                    sub,
+                    expected,
+                    actual,
+                }
+                .fail();
+            }
+        }
+        Ok(())
+    }
+
+    /// Configure the failsafe outputs of several nodes, and verify them
+    ///
+    /// Each node is given the heartbeat consumer entries in `heartbeat_consumers`, as in
+    /// [`configure_heartbeat_consumers`](Self::configure_heartbeat_consumers), normally listing the
+    /// master producing its [heartbeat](Self::start_heartbeat), so that it detects when the
+    /// master is lost. The entries are left unchanged if `heartbeat_consumers` is empty. The
+    /// outputs are then configured with [`SdoClient::configure_failsafe`]. See
+    /// [`crate::failsafe`].
+    ///
+    /// The nodes are configured concurrently, and a failure on one node does not stop the others.
+    /// The results are returned in the order of `nodes`.
+    pub async fn configure_failsafe(
+        &self,
+        nodes: &[u8],
+        heartbeat_consumers: &[(u8, Duration)],
+        config: &FailsafeConfig,
+    ) -> Vec<(u8, Result<(), FailsafeError>)> {
+        futures::future::join_all(nodes.iter().map(|&node_id| async move {
+            let result = async {
+                if !heartbeat_consumers.is_empty() {
+                    self.configure_heartbeat_consumers(node_id, heartbeat_consumers)
+                        .await
+                        .context(failsafe::HeartbeatConsumerSnafu)?;
+                }
+                self.sdo_client(node_id).configure_failsafe(config).await
+            }
+            .await;
+            (node_id, result)
+        }))
+        .await
+    }
+
+    /// Program new firmware into a node using its bootloader
+    ///
+    /// If the node is running its application, it is first commanded to reset into the
+    /// bootloader. The section selected by `opts` is then erased and programmed with `image`, and
+    /// the node is reset to start the new application. See [`crate::firmware`].
+    ///
+    /// On success, the software version read from the new application is returned so that the
+    /// caller can verify that the expected firmware is running, and the cached [`NodeInfo`] for the
+    /// node is updated.
+    ///
+    /// The identity of the node should usually be checked with
+    /// [`verify_identity`](Self::verify_identity) first.
+    pub async fn flash_node(
+        &self,
+        node_id: u8,
+        image: &[u8],
+        opts: &FlashOptions,
+    ) -> Result<FlashReport, FlashError> {
+        let mut client = self.sdo_client(node_id);
+        let app_running = firmware::is_app_running(&mut client)
+            .await
+            .context(SdoSnafu {
+                node_id,
+                stage: FlashStage::ReadBootloaderInfo,
+            })?;
+        let mut previous_version = None;
+        if app_running {
+            previous_version = client.read_software_version().await.ok();
+            firmware::command_bootloader_reset(&mut client)
+                .await
+                .context(SdoSnafu {
+                    node_id,
+                    stage: FlashStage::EnterBootloader,
+                })?;
+            firmware::wait_for_mode(
+                &mut client,
+                node_id,
+                false,
+                FlashStage::EnterBootloader,
+                opts,
+            )
+            .await?;
+        }
+
+        client.set_timeout(opts.transfer_timeout);
+        firmware::program_section(&mut client, node_id, opts.section, image).await?;
+
+        if !self
+            .send_nmt_cmd(NmtCommandSpecifier::ResetApp, node_id)
+            .await
+        {
+            return Err(FlashError::NmtSendFailed { node_id });
+        }
+        firmware::wait_for_mode(
+            &mut client,
+            node_id,
+            true,
+            FlashStage::StartApplication,
+            opts,
+        )
+        .await?;
+        let version = client.read_software_version().await.context(SdoSnafu {
+            node_id,
+            stage: FlashStage::ReadVersion,
+        })?;
+
+        let mut node_map = self.nodes.lock().await;
+        let info = NodeInfo {
+            software_version: Some(version.clone()),
+            ..NodeInfo::new(node_id)
+        };
+        node_map
+            .entry(node_id)
+            .and_modify(|existing| existing.update(&info))
+            .or_insert(info);
+
+        Ok(FlashReport {
+            node_id,
+            previous_version,
+            version,
+        })
+    }
+
+    /// Program the same firmware image into several nodes
+    ///
+    /// Up to `parallelism` nodes are updated at once; use 1 to update them one at a time. Each node
+    /// is updated as in [`flash_node`](Self::flash_node), and a failure on one node does not stop
+    /// the others. The results are returned sorted by node ID.
+    pub async fn flash_nodes(
+        &self,
+        nodes: &[u8],
+        image: &[u8],
+        opts: &FlashOptions,
+        parallelism: usize,
+    ) -> Vec<(u8, Result<FlashReport, FlashError>)> {
+        let mut results: Vec<_> = futures::stream::iter(nodes.iter().copied())
+            .map(|id| async move { (id, self.flash_node(id, image, opts).await) })
+            .buffer_unordered(parallelism.max(1))
+            .collect()
+            .await;
+        results.sort_by_key(|(id, _)| *id);
+        results
+    }
+
     /// Get a list of known nodes
     pub async fn node_list(&self) -> Vec<NodeInfo> {
         let node_map = self.nodes.lock().await;
@@ -312,59 +1655,288 @@ impl<S: AsyncCanSender + Sync + Send> BusManager<S> {
         nodes
     }
 
+    /// Read the PDO configuration of all known nodes, and describe the links between them
+    ///
+    /// Only nodes which have been discovered, e.g. by [`scan_nodes`](Self::scan_nodes), are
+    /// included. See the [topology module](crate::topology) for details. The PDOs read are also
+    /// recorded in the [COB-ID registry](Self::cob_ids).
+    pub async fn export_topology(&self) -> Topology {
+        let mut nodes = Vec::new();
+        for info in self.node_list().await {
+            let mut client = self.sdo_client(info.node_id);
+            let node = topology::read_node(&mut *client, &info).await;
+            self.cob_ids.record_node(&node);
+            nodes.push(node);
+        }
+        Topology::new(nodes)
+    }
+
+    /// Configure a TPDO on one node and an RPDO on another, so that objects on the producer feed
+    /// objects on the consumer
+    ///
+    /// The PDOs and the COB-ID are chosen automatically, unless the request gives a COB-ID. See
+    /// the [pdo_link module](crate::pdo_link) for details. The source and target objects are
+    /// checked before anything is written, so a failed check leaves both nodes unchanged.
+    pub async fn link_pdo(&self, request: &PdoLinkRequest) -> Result<LinkedPdo, PdoLinkError> {
+        request.validate()?;
+        let (producer, consumer) = (request.producer, request.consumer);
+
+        // The PDOs of the linked nodes must be read completely, but errors reading other nodes
+        // only leave their COB-IDs out
+        let mut nodes = Vec::new();
+        for info in self.node_list().await {
+            if info.node_id != producer && info.node_id != consumer {
+                let mut client = self.sdo_client(info.node_id);
+                let node = topology::read_node(&mut *client, &info).await;
+                self.cob_ids.record_node(&node);
+                nodes.push(node);
+            }
+        }
+        for node_id in [producer, consumer] {
+            if nodes.iter().any(|n| n.node_id == node_id) {
+                continue;
+            }
+            let mut client = self.sdo_client(node_id);
+            let node = topology::read_pdos(&mut *client, &NodeInfo::new(node_id))
+                .await
+                .context(pdo_link::SdoSnafu { node_id })?;
+            self.cob_ids.record_node(&node);
+            nodes.push(node);
+        }
+        let find = |node_id: u8| nodes.iter().find(|n| n.node_id == node_id).unwrap();
+
+        let tpdo = PdoEndpoint {
+            node_id: producer,
+            pdo: pdo_link::free_pdo(&find(producer).tpdos)
+                .ok_or(PdoLinkError::NoFreeTpdo { node_id: producer })?,
+        };
+        let rpdo = PdoEndpoint {
+            node_id: consumer,
+            pdo: pdo_link::free_pdo(&find(consumer).rpdos)
+                .ok_or(PdoLinkError::NoFreeRpdo { node_id: consumer })?,
+        };
+        // The chosen PDOs are reconfigured, so the COB-IDs they hold now are free
+        let ignore = [CobIdUser::Tpdo(tpdo), CobIdUser::Rpdo(rpdo)];
+        let cob_id = match request.cob_id {
+            Some(cob_id) if !self.cob_ids.is_free(cob_id, &ignore) => {
+                return pdo_link::CobIdInUseSnafu { cob_id }.fail()
+            }
+            Some(cob_id) => cob_id,
+            None => self
+                .cob_ids
+                .allocate(pdo_link::default_cob_id(tpdo), &ignore)
+                .ok_or(PdoLinkError::NoFreeCobId)?,
+        };
+
+        let mut sizes = Vec::with_capacity(request.signals.len());
+        {
+            let mut client = self.sdo_client(producer);
+            for (source, _) in &request.signals {
+                let size = pdo_link::object_size(&mut *client, *source)
+                    .await
+                    .context(pdo_link::ValidationSnafu { node_id: producer })?;
+                sizes.push(size);
+            }
+        }
+        let tpdo_cfg = pdo_link::link_config(
+            request.signals.iter().map(|(source, _)| *source),
+            &sizes,
+            cob_id,
+            request.transmission_type,
+        );
+        let rpdo_cfg = pdo_link::link_config(
+            request.signals.iter().map(|(_, target)| *target),
+            &sizes,
+            cob_id,
+            request.transmission_type,
+        );
+        self.sdo_client(producer)
+            .validate_tpdo(tpdo.pdo, &tpdo_cfg)
+            .await
+            .context(pdo_link::ValidationSnafu { node_id: producer })?;
+        self.sdo_client(consumer)
+            .validate_rpdo(rpdo.pdo, &rpdo_cfg)
+            .await
+            .context(pdo_link::ValidationSnafu { node_id: consumer })?;
+
+        // Enable the consumer first, so that it is ready for the first message from the producer
+        self.sdo_client(consumer)
+            .configure_rpdo(rpdo.pdo, &rpdo_cfg)
+            .await
+            .context(pdo_link::SdoSnafu { node_id: consumer })?;
+        self.sdo_client(producer)
+            .configure_tpdo(tpdo.pdo, &tpdo_cfg)
+            .await
+            .context(pdo_link::SdoSnafu { node_id: producer })?;
+        self.cob_ids.record_pdo(CobIdUser::Rpdo(rpdo), &rpdo_cfg);
+        self.cob_ids.record_pdo(CobIdUser::Tpdo(tpdo), &tpdo_cfg);
+
+        Ok(LinkedPdo {
+            cob_id,
+            producer: tpdo,
+            consumer: rpdo,
+        })
+    }
+
+    /// Get the registry of the COB-IDs observed and configured on the bus
+    ///
+    /// Every frame received by the manager is recorded, along with the PDOs of each node whenever
+    /// they are read or written by the manager. See [`crate::cob_registry`].
+    pub fn cob_ids(&self) -> &CobIdRegistry {
+        &self.cob_ids
+    }
+
+    /// Get the cache of the constant objects read from each node
+    ///
+    /// It is shared by every [`Device`] created by the manager, and filled in by node scans. See
+    /// [`crate::object_cache`].
+    pub fn object_cache(&self) -> &ObjectCache {
+        &self.object_cache
+    }
+
+    /// Check the TPDOs in a configuration for a node for COB-ID conflicts with the rest of the bus
+    ///
+    /// See [`CobIdRegistry::conflicts`].
+    pub fn cob_id_conflicts(&self, node_id: u8, config: &NodeConfig) -> Vec<CobIdConflict> {
+        self.cob_ids.conflicts(node_id, config)
+    }
+
+    /// Write a [`NodeConfig`] to a node, warning about COB-ID conflicts
+    ///
+    /// The configuration is checked with [`cob_id_conflicts`](Self::cob_id_conflicts) first. Each
+    /// conflict is logged as a warning and returned, but does not stop the configuration being
+    /// applied. When `verify` is true, every setting is read back, as in
+    /// [`SdoClient::apply_node_config_verified`]. Once written, the PDOs are recorded in the
+    /// [COB-ID registry](Self::cob_ids).
+    ///
+    /// If the config has a [verify configuration](NodeConfig::verify_configuration) stamp, and the
+    /// node's verify configuration object (0x1020) already matches it, the node was provisioned
+    /// with this configuration before, and nothing is written. See
+    /// [`SdoClient::is_configured`].
+    pub async fn apply_node_config(
+        &self,
+        node_id: u8,
+        config: &NodeConfig,
+        verify: bool,
+    ) -> Result<Vec<CobIdConflict>, SdoClientError> {
+        let conflicts = self.cob_id_conflicts(node_id, config);
+        for conflict in &conflicts {
+            log::warn!("{conflict}");
+        }
+
+        let mut client = self.sdo_client(node_id);
+        if client.is_configured(config).await? {
+            log::info!("Node {node_id} already holds the configuration, skipping");
+        } else if verify {
+            client.apply_node_config_verified(config).await?;
+        } else {
+            client.apply_node_config(config).await?;
+        }
+
+        for (&pdo, cfg) in config.tpdos() {
+            let user = CobIdUser::Tpdo(PdoEndpoint { node_id, pdo });
+            self.cob_ids.record_pdo(user, cfg);
+        }
+        for (&pdo, cfg) in config.rpdos() {
+            let user = CobIdUser::Rpdo(PdoEndpoint { node_id, pdo });
+            self.cob_ids.record_pdo(user, cfg);
+        }
+        Ok(conflicts)
+    }
+
     /// Perform a scan of all possible node IDs
     ///
     /// Will find all configured devices, and read metadata from required objects, including:
+    /// - Device Type
     /// - Identity
     /// - Device Name
     /// - Software Version
     /// - Hardware Version
+    ///
+    /// This is equivalent to [`scan_nodes_with`](Self::scan_nodes_with) using the default
+    /// [`ScanOptions`].
     pub async fn scan_nodes(&mut self) -> Vec<NodeInfo> {
-        const N_PARALLEL: usize = 10;
-
-        let ids = Vec::from_iter(1..128u8);
-        let mut nodes = Vec::new();
-
-        let mut chunks = Vec::new();
-        for chunk in ids.chunks(128 / N_PARALLEL) {
-            chunks.push(Vec::from_iter(chunk.iter().cloned()));
-        }
+        self.scan_nodes_with(&ScanOptions::default()).await
+    }
 
-        let mut futures = Vec::new();
+    /// Perform a scan of node IDs, using the provided options
+    ///
+    /// Discovered nodes are cached, and can be retrieved later with
+    /// [`node_list`](Self::node_list). When `opts.incremental` is set, nodes which are currently
+    /// sending heartbeats are not probed again, and the cached information for them is returned
+    /// along with any newly found nodes.
+    pub async fn scan_nodes_with(&mut self, opts: &ScanOptions) -> Vec<NodeInfo> {
+        let alive: Vec<u8> = if opts.incremental {
+            let node_map = self.nodes.lock().await;
+            node_map
+                .values()
+                .filter(|n| n.is_heartbeating(opts.heartbeat_timeout))
+                .map(|n| n.node_id)
+                .collect()
+        } else {
+            Vec::new()
+        };
 
-        for block in chunks {
-            futures.push(async {
-                let mut block_nodes = Vec::new();
-                for id in block {
-                    block_nodes.push(scan_node(id, &self.sdo_clients).await);
+        let ids = (1..128u8).filter(|id| !alive.contains(id));
+        let sdo_clients = &self.sdo_clients;
+        let stagger = opts.stagger;
+        let mut nodes: Vec<NodeInfo> = futures::stream::iter(ids)
+            .then(|id| async move {
+                if !stagger.is_zero() {
+                    tokio::time::sleep(stagger).await;
                 }
-                block_nodes
-            });
+                id
+            })
+            .map(|id| scan_node(id, sdo_clients, opts))
+            .buffer_unordered(opts.parallelism.max(1))
+            .filter_map(|n| async { n })
+            .collect()
+            .await;
+        if opts.probe_capabilities {
+            self.probe_lss_support(&mut nodes).await;
         }
 
-        let results = join_all(futures).await;
-        for r in results {
-            nodes.extend(r.into_iter().flatten());
+        for n in &nodes {
+            self.object_cache.record_node_info(n);
         }
 
         let mut node_map = self.nodes.lock().await;
         // Update our nodes
         for n in &nodes {
-            if let std::collections::hash_map::Entry::Vacant(e) = node_map.entry(n.node_id) {
-                e.insert(n.clone());
-            } else {
-                node_map.get_mut(&n.node_id).unwrap().update(n);
-            }
+            node_map
+                .entry(n.node_id)
+                .and_modify(|existing| existing.update(n))
+                .or_insert_with(|| n.clone());
         }
 
         // Pull the just scanned nodes from the collection so that
-        // 1) We only included nodes which responded just now to the scan, but
+        // 1) We only included nodes which responded just now to the scan, or are known to be alive
+        //    from their heartbeat, but
         // 2) we also display the latest NMT state for that node, which comes from the heartbeat
         //    rather than the scan
-        nodes
+        let mut result: Vec<NodeInfo> = nodes
             .iter()
-            .map(|n| node_map.get(&n.node_id).unwrap().clone())
-            .collect()
+            .map(|n| n.node_id)
+            .chain(alive)
+            .filter_map(|id| node_map.get(&id).cloned())
+            .collect();
+        result.sort_by_key(|n| n.node_id);
+        result
+    }
+
+    /// Select each scanned node by its identity using LSS, to find out which nodes support it
+    async fn probe_lss_support(&mut self, nodes: &mut [NodeInfo]) {
+        let mut lss = LssMaster::new(self.sender.clone(), self.receiver.create_rx());
+        for node in nodes.iter_mut() {
+            let (Some(id), Some(capabilities)) = (node.identity, node.capabilities.as_mut()) else {
+                continue;
+            };
+            let result = lss
+                .enter_config_by_identity(id.vendor_id, id.product_code, id.revision, id.serial)
+                .await;
+            capabilities.lss = Some(result.is_ok());
+        }
+        lss.set_global_mode(LssState::Waiting).await;
     }
 
     /// Find all unconfigured devices on the bus
@@ -376,6 +1948,7 @@ impl<S: AsyncCanSender + Sync + Send> BusManager<S> {
     ///
     /// After devices are found, they are all put back into waiting state
     pub async fn lss_fastscan(&mut self, timeout: Duration) -> Vec<LssIdentity> {
+        let start = Started::now();
         let mut devices = Vec::new();
         let mut lss = LssMaster::new(self.sender.clone(), self.receiver.create_rx());
 
@@ -390,9 +1963,38 @@ impl<S: AsyncCanSender + Sync + Send> BusManager<S> {
 
         lss.set_global_mode(LssState::Waiting).await;
 
+        self.record(
+            start,
+            Operation::LssFastscan,
+            Outcome::Ok {
+                response: Some(devices.len().to_le_bytes().to_vec()),
+            },
+        );
         devices
     }
 
+    /// Find all unconfigured devices, and assign them node IDs from a pool
+    ///
+    /// IDs belonging to nodes already known to the manager, e.g. from a previous scan or their
+    /// heartbeat, are removed from the pool. See [`LssMaster::auto_assign`].
+    pub async fn lss_auto_assign(
+        &mut self,
+        pool: RangeInclusive<u8>,
+        timeout: Duration,
+    ) -> AutoAssignReport {
+        let in_use: Vec<u8> = self.nodes.lock().await.keys().copied().collect();
+        let pool = pool.filter(|id| !in_use.contains(id));
+        let start = Started::now();
+        let mut lss = LssMaster::new(self.sender.clone(), self.receiver.create_rx());
+        let report = lss.auto_assign_from(pool, timeout).await;
+        self.record(
+            start,
+            Operation::LssAutoAssign,
+            Outcome::Ok { response: None },
+        );
+        report
+    }
+
     /// Activate a single LSS slave by its identity
     ///
     /// All nodes are put into Waiting mode via the global command, then the specified node is
@@ -402,15 +2004,28 @@ impl<S: AsyncCanSender + Sync + Send> BusManager<S> {
     /// identify a device on the bus. If they are not known, they can be found using
     /// [`lss_fastscan()`](Self::lss_fastscan).
     pub async fn lss_activate(&mut self, ident: LssIdentity) -> Result<(), LssError> {
+        let start = Started::now();
         let mut lss = LssMaster::new(self.sender.clone(), self.receiver.create_rx());
         lss.set_global_mode(LssState::Waiting).await;
-        lss.enter_config_by_identity(
-            ident.vendor_id,
-            ident.product_code,
-            ident.revision,
-            ident.serial,
-        )
-        .await
+        let result = lss
+            .enter_config_by_identity(
+                ident.vendor_id,
+                ident.product_code,
+                ident.revision,
+                ident.serial,
+            )
+            .await;
+        self.record(
+            start,
+            Operation::LssActivate {
+                vendor_id: ident.vendor_id,
+                product_code: ident.product_code,
+                revision: ident.revision,
+                serial: ident.serial,
+            },
+            Outcome::from_result(&result, |_| None),
+        );
+        result
     }
 
     /// Set the node ID of LSS slave in Configuration mode
@@ -418,8 +2033,17 @@ impl<S: AsyncCanSender + Sync + Send> BusManager<S> {
     /// It is required that one node has been put into Configuration mode already when this is
     /// called, e.g. using [`lss_activate`](Self::lss_activate)
     pub async fn lss_set_node_id(&mut self, node_id: NodeId) -> Result<(), LssError> {
+        let start = Started::now();
         let mut lss = LssMaster::new(self.sender.clone(), self.receiver.create_rx());
-        lss.set_node_id(node_id).await?;
+        let result = lss.set_node_id(node_id).await;
+        self.record(
+            start,
+            Operation::LssSetNodeId {
+                node_id: node_id.raw(),
+            },
+            Outcome::from_result(&result, |_| None),
+        );
+        result?;
         Ok(())
     }
 
@@ -428,21 +2052,36 @@ impl<S: AsyncCanSender + Sync + Send> BusManager<S> {
     /// It is required that one node has been put into Configuration mode already when this is
     /// called, e.g. using [`lss_activate`](Self::lss_activate)
     pub async fn lss_store_config(&mut self) -> Result<(), LssError> {
+        let start = Started::now();
         let mut lss = LssMaster::new(self.sender.clone(), self.receiver.create_rx());
-        lss.store_config().await
+        let result = lss.store_config().await;
+        self.record(
+            start,
+            Operation::LssStoreConfig,
+            Outcome::from_result(&result, |_| None),
+        );
+        result
     }
 
     /// Send a command to put all devices into the specified LSS state
     pub async fn lss_set_global_mode(&mut self, mode: LssState) {
+        let start = Started::now();
         let mut lss = LssMaster::new(self.sender.clone(), self.receiver.create_rx());
         lss.set_global_mode(mode).await;
+        self.record(
+            start,
+            Operation::LssGlobalMode {
+                mode: format!("{mode:?}"),
+            },
+            Outcome::Ok { response: None },
+        );
     }
 
     /// Send application reset command
     ///
     /// node - The node ID to command, or 0 to broadcast to all nodes
     pub async fn nmt_reset_app(&mut self, node: u8) {
-        self.send_nmt_cmd(NmtCommandSpecifier::ResetApp, node).await
+        self.send_nmt_cmd(NmtCommandSpecifier::ResetApp, node).await;
     }
 
     /// Send communications reset command
@@ -450,25 +2089,142 @@ impl<S: AsyncCanSender + Sync + Send> BusManager<S> {
     /// node - The node ID to command, or 0 to broadcast to all nodes
     pub async fn nmt_reset_comms(&mut self, node: u8) {
         self.send_nmt_cmd(NmtCommandSpecifier::ResetComm, node)
-            .await
+            .await;
     }
 
     /// Send start operation command
     ///
     /// node - The node ID to command, or 0 to broadcast to all nodes
     pub async fn nmt_start(&mut self, node: u8) {
-        self.send_nmt_cmd(NmtCommandSpecifier::Start, node).await
+        self.send_nmt_cmd(NmtCommandSpecifier::Start, node).await;
     }
 
     /// Send start operation command
     ///
     /// node - The node ID to command, or 0 to broadcast to all nodes
     pub async fn nmt_stop(&mut self, node: u8) {
-        self.send_nmt_cmd(NmtCommandSpecifier::Stop, node).await
+        self.send_nmt_cmd(NmtCommandSpecifier::Stop, node).await;
+    }
+
+    /// Put nodes into a safe state, and then broadcast a stop command to all nodes
+    ///
+    /// See [`NmtMaster::emergency_stop`](crate::nmt_master::NmtMaster::emergency_stop). Returns
+    /// true if every frame and the stop command were sent successfully. The stop command is sent
+    /// even when yielding to another master.
+    pub async fn emergency_stop(
+        &mut self,
+        safe_state: &[CanMessage],
+        settle_time: Duration,
+    ) -> bool {
+        let mut sent = true;
+        for msg in safe_state {
+            sent &= self.sender.clone().send(*msg).await.is_ok();
+        }
+        if !safe_state.is_empty() {
+            tokio::time::sleep(settle_time).await;
+        }
+        self.transmit_nmt_cmd(NmtCommandSpecifier::Stop, 0, false)
+            .await
+            && sent
     }
 
-    async fn send_nmt_cmd(&mut self, cmd: NmtCommandSpecifier, node: u8) {
+    /// Send an NMT command, unless yielding to another master, and return true if it was sent
+    /// successfully
+    async fn send_nmt_cmd(&self, cmd: NmtCommandSpecifier, node: u8) -> bool {
+        self.transmit_nmt_cmd(cmd, node, true).await
+    }
+
+    async fn transmit_nmt_cmd(&self, cmd: NmtCommandSpecifier, node: u8, may_yield: bool) -> bool {
+        let start = Started::now();
         let message = NmtCommand { cs: cmd, node };
-        self.sender.send(message.into()).await.ok();
+        let result = match self.sdo_clients.master_activity.yield_to() {
+            Some(cob_id) if may_yield => {
+                log::warn!("Not sending NMT {cmd:?}: another master is active ({cob_id:?})");
+                Err(())
+            }
+            _ => self
+                .sender
+                .clone()
+                .send(message.into())
+                .await
+                .map_err(|_| ()),
+        };
+        self.record(
+            start,
+            Operation::Nmt {
+                command: format!("{cmd:?}"),
+                node,
+            },
+            match result {
+                Ok(()) => Outcome::Ok { response: None },
+                Err(_) => Outcome::Error {
+                    message: "Failed to send NMT command".into(),
+                },
+            },
+        );
+        result.is_ok()
+    }
+}
+
+impl<S: AsyncCanSender + Sync + Send> Drop for BusManager<S> {
+    fn drop(&mut self) {
+        // The receiver, heartbeat, SYNC and discovery stop their own tasks when dropped
+        self.monitor_task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_info_serde() {
+        let mut info = NodeInfo::new(3);
+        info.identity = Some(LssIdentity::new(1, 2, 3, 4));
+        info.nmt_state = Some(NmtState::Operational);
+        info.last_seen = Instant::now() - Duration::from_secs(2);
+
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(3, json["node_id"]);
+        assert_eq!("Operational", json["nmt_state"]);
+        assert!(json["last_seen_age_ms"].as_u64().unwrap() >= 2000);
+        assert!(json["last_heartbeat_age_ms"].is_null());
+
+        let loaded: NodeInfo = serde_json::from_value(json).unwrap();
+        assert_eq!(info.identity, loaded.identity);
+        assert_eq!(info.nmt_state, loaded.nmt_state);
+        assert!(loaded.last_seen.elapsed() >= Duration::from_secs(2));
+        assert_eq!(None, loaded.last_heartbeat);
+
+        // Files written before the heartbeat sources were recorded can still be loaded
+        let mut json = serde_json::to_value(&info).unwrap();
+        json.as_object_mut()
+            .unwrap()
+            .remove("last_secondary_heartbeat_age_ms");
+        let loaded: NodeInfo = serde_json::from_value(json).unwrap();
+        assert_eq!(None, loaded.last_secondary_heartbeat);
+    }
+
+    #[test]
+    fn test_heartbeat_sources() {
+        let timeout = Duration::from_secs(1);
+        let mut info = NodeInfo::new(3);
+        info.record_heartbeat(
+            NmtState::PreOperational,
+            HeartbeatSource::Secondary,
+            Instant::now(),
+        );
+        assert_eq!(Some(NmtState::PreOperational), info.nmt_state);
+        assert!(info.is_heartbeating(timeout));
+        assert!(info.is_heartbeating_on(HeartbeatSource::Secondary, timeout));
+        assert!(!info.is_heartbeating_on(HeartbeatSource::Primary, timeout));
+
+        info.record_heartbeat(
+            NmtState::Operational,
+            HeartbeatSource::Primary,
+            Instant::now(),
+        );
+        assert_eq!(Some(NmtState::Operational), info.nmt_state);
+        assert!(info.is_heartbeating_on(HeartbeatSource::Primary, timeout));
     }
 }
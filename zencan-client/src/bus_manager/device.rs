@@ -0,0 +1,381 @@
+//! A handle combining the services used to work with a single node
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use snafu::Snafu;
+use zencan_common::{
+    lss::LssIdentity,
+    messages::{NmtCommand, NmtCommandSpecifier, NmtState, ZencanMessage},
+    objects::{DataType, ObjectId},
+    traits::AsyncCanSender,
+    value::Value,
+};
+
+use super::{
+    bus_manager::NodeInfo, raw_handle::RawHandle, shared_receiver::SharedReceiverChannel,
+    shared_sender::SharedSender,
+};
+use crate::{
+    emcy::{DecodedEmcy, EmcyDecoders, EmcyMonitor},
+    object_cache::ObjectCache,
+    od_ref::OdRef,
+    sdo_client::{SdoClient, SdoClientError},
+};
+
+type Result<T> = std::result::Result<T, SdoClientError>;
+
+/// A Rust type which can be read from and written to a sub object by [`Device::read`] and
+/// [`Device::write`], or updated by [`SdoClient::update`]
+pub trait SdoValue: Into<Value> + Sized {
+    /// The data type used to decode the sub object
+    const DATA_TYPE: DataType;
+
+    /// Get the value from a [`Value`] decoded as [`DATA_TYPE`](Self::DATA_TYPE)
+    fn from_value(value: Value) -> Option<Self>;
+}
+
+macro_rules! impl_sdo_value {
+    ($t:ty, $data_type:ident, $variant:ident) => {
+        impl SdoValue for $t {
+            const DATA_TYPE: DataType = DataType::$data_type;
+
+            fn from_value(value: Value) -> Option<Self> {
+                match value {
+                    Value::$variant(v) => Some(v),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_sdo_value!(bool, Boolean, Bool);
+impl_sdo_value!(u8, UInt8, U8);
+impl_sdo_value!(u16, UInt16, U16);
+impl_sdo_value!(u32, UInt32, U32);
+impl_sdo_value!(u64, UInt64, U64);
+impl_sdo_value!(i8, Int8, I8);
+impl_sdo_value!(i16, Int16, I16);
+impl_sdo_value!(i32, Int32, I32);
+impl_sdo_value!(i64, Int64, I64);
+impl_sdo_value!(f32, Real32, F32);
+impl_sdo_value!(f64, Real64, F64);
+impl_sdo_value!(String, VisibleString, Str);
+impl_sdo_value!(Vec<u8>, OctetString, Bytes);
+
+/// Error returned by [`Device::restart`]
+#[derive(Debug, Clone, Copy, Snafu)]
+pub enum RestartError {
+    /// The NMT reset command could not be sent
+    #[snafu(display("Failed to send NMT reset to node {node_id}"))]
+    ResetSendFailed {
+        /// The node being restarted
+        node_id: u8,
+    },
+    /// The node did not send a boot-up message after the reset
+    #[snafu(display("Node {node_id} did not boot up within {timeout:?}"))]
+    NoBootup {
+        /// The node being restarted
+        node_id: u8,
+        /// The time waited for the boot-up message
+        timeout: Duration,
+    },
+}
+
+/// A handle for working with a single node
+///
+/// Combines an SDO client, NMT commands, and an EMCY monitor for one node, with methods for the
+/// operations most applications need, so that they do not have to manage each service separately.
+/// Anything else can be done with the underlying [`sdo_client`](Self::sdo_client).
+///
+/// Created by [`BusManager::device`](super::BusManager::device).
+///
+/// ```no_run
+/// # async fn example(manager: &zencan_client::BusManager<zencan_client::TransportSender>) {
+/// let mut device = manager.device(4);
+/// println!("{} ({})", device.name().await.unwrap(), device.software_version().await.unwrap());
+/// let setpoint: u32 = device.read((0x2000, 1)).await.unwrap();
+/// device.write((0x2000, 1), setpoint + 1).await.unwrap();
+/// device.restart().await.unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Device<S: AsyncCanSender> {
+    node_id: u8,
+    client: SdoClient<SharedSender<S>, SharedReceiverChannel>,
+    sender: SharedSender<S>,
+    receiver: SharedReceiverChannel,
+    decoders: Arc<RwLock<EmcyDecoders>>,
+    nodes: Arc<tokio::sync::Mutex<HashMap<u8, NodeInfo>>>,
+    emcy: Option<EmcyMonitor<S>>,
+    restart_timeout: Duration,
+    cache: ObjectCache,
+}
+
+impl<S: AsyncCanSender + Sync> Device<S> {
+    pub(super) fn new(
+        node_id: u8,
+        client: SdoClient<SharedSender<S>, SharedReceiverChannel>,
+        sender: SharedSender<S>,
+        receiver: SharedReceiverChannel,
+        decoders: Arc<RwLock<EmcyDecoders>>,
+        nodes: Arc<tokio::sync::Mutex<HashMap<u8, NodeInfo>>>,
+        cache: ObjectCache,
+    ) -> Self {
+        Self {
+            node_id,
+            client,
+            sender,
+            receiver,
+            decoders,
+            nodes,
+            emcy: None,
+            restart_timeout: Duration::from_secs(5),
+            cache,
+        }
+    }
+
+    /// Get the node ID of the device
+    pub fn node_id(&self) -> u8 {
+        self.node_id
+    }
+
+    /// Get the SDO client used by the device
+    pub fn sdo_client(&mut self) -> &mut SdoClient<SharedSender<S>, SharedReceiverChannel> {
+        &mut self.client
+    }
+
+    /// Set the time [`restart`](Self::restart) waits for the node to boot up
+    ///
+    /// Default: 5s
+    pub fn set_restart_timeout(&mut self, timeout: Duration) {
+        self.restart_timeout = timeout;
+    }
+
+    /// Read the device name (0x1008)
+    ///
+    /// The value is cached, see [`invalidate_cache`](Self::invalidate_cache).
+    pub async fn name(&mut self) -> Result<String> {
+        if let Some(name) = self.cache.get(self.node_id).device_name {
+            return Ok(name);
+        }
+        let name = self.client.read_device_name().await?;
+        self.cache
+            .update(self.node_id, |c| c.device_name = Some(name.clone()));
+        Ok(name)
+    }
+
+    /// Read the identity (0x1018)
+    ///
+    /// The value is cached, see [`invalidate_cache`](Self::invalidate_cache).
+    pub async fn identity(&mut self) -> Result<LssIdentity> {
+        if let Some(identity) = self.cache.get(self.node_id).identity {
+            return Ok(identity);
+        }
+        let identity = self.client.read_identity().await?;
+        self.cache
+            .update(self.node_id, |c| c.identity = Some(identity));
+        Ok(identity)
+    }
+
+    /// Read the device type (0x1000)
+    ///
+    /// The value is cached, see [`invalidate_cache`](Self::invalidate_cache).
+    pub async fn device_type(&mut self) -> Result<u32> {
+        if let Some(device_type) = self.cache.get(self.node_id).device_type {
+            return Ok(device_type);
+        }
+        let device_type = self.client.read_device_type().await?;
+        self.cache
+            .update(self.node_id, |c| c.device_type = Some(device_type));
+        Ok(device_type)
+    }
+
+    /// Read the software version (0x100A)
+    ///
+    /// The value is cached, see [`invalidate_cache`](Self::invalidate_cache).
+    pub async fn software_version(&mut self) -> Result<String> {
+        if let Some(version) = self.cache.get(self.node_id).software_version {
+            return Ok(version);
+        }
+        let version = self.client.read_software_version().await?;
+        self.cache
+            .update(self.node_id, |c| c.software_version = Some(version.clone()));
+        Ok(version)
+    }
+
+    /// Read the hardware version (0x1009)
+    ///
+    /// The value is cached, see [`invalidate_cache`](Self::invalidate_cache).
+    pub async fn hardware_version(&mut self) -> Result<String> {
+        if let Some(version) = self.cache.get(self.node_id).hardware_version {
+            return Ok(version);
+        }
+        let version = self.client.read_hardware_version().await?;
+        self.cache
+            .update(self.node_id, |c| c.hardware_version = Some(version.clone()));
+        Ok(version)
+    }
+
+    /// Drop the cached values of the node's constant objects, so that they are read again
+    ///
+    /// The cache is shared with the [`BusManager`](super::BusManager), and is invalidated
+    /// automatically when the node sends a boot-up message. See [`crate::object_cache`].
+    pub fn invalidate_cache(&self) {
+        self.cache.invalidate(self.node_id);
+    }
+
+    /// Read a sub object, and decode it as `T`
+    ///
+    /// The object is given as an [`ObjectId`], or an `(index, sub)` tuple. Returns
+    /// [`SdoClientError::UnexpectedSize`] if the size of the sub object does not match `T`.
+    pub async fn read<T: SdoValue>(&mut self, object: impl Into<ObjectId>) -> Result<T> {
+        let ObjectId { index, sub } = object.into();
+        let value = self.client.read_value(index, sub, T::DATA_TYPE).await?;
+        T::from_value(value).ok_or(SdoClientError::MalformedResponse)
+    }
+
+    /// Write a value to a sub object
+    ///
+    /// The object is given as an [`ObjectId`], or an `(index, sub)` tuple. The protocol is selected
+    /// as in [`SdoClient::write`].
+    pub async fn write<T: SdoValue>(
+        &mut self,
+        object: impl Into<ObjectId>,
+        value: T,
+    ) -> Result<()> {
+        let ObjectId { index, sub } = object.into();
+        self.client.write_value(index, sub, &value.into()).await
+    }
+
+    /// Read a sub object, apply `f` to its value, and write back the result
+    ///
+    /// Returns the value written. See [`SdoClient::update`], and [`SdoClient::update_guarded`] for
+    /// objects which are shared with other clients.
+    pub async fn update<T: SdoValue + Clone>(
+        &mut self,
+        object: impl Into<ObjectId>,
+        f: impl Fn(T) -> T + Send,
+    ) -> Result<T> {
+        let ObjectId { index, sub } = object.into();
+        self.client.update(index, sub, f).await
+    }
+
+    /// Read the sub object referred to by a typed [`OdRef`]
+    pub async fn get<T: SdoValue>(&mut self, object: OdRef<T>) -> Result<T> {
+        self.read(object).await
+    }
+
+    /// Write the sub object referred to by a typed [`OdRef`]
+    pub async fn set<T: SdoValue>(&mut self, object: OdRef<T>, value: T) -> Result<()> {
+        self.write(object, value).await
+    }
+
+    /// Get the NMT state reported in the node's most recent heartbeat, if one has been received
+    pub async fn nmt_state(&self) -> Option<NmtState> {
+        self.nodes
+            .lock()
+            .await
+            .get(&self.node_id)
+            .and_then(|n| n.nmt_state)
+    }
+
+    /// Command the node to enter the Operational state
+    ///
+    /// Returns false if the command could not be sent.
+    pub async fn start(&mut self) -> bool {
+        self.send_nmt(NmtCommandSpecifier::Start).await
+    }
+
+    /// Command the node to enter the Stopped state
+    ///
+    /// Returns false if the command could not be sent.
+    pub async fn stop(&mut self) -> bool {
+        self.send_nmt(NmtCommandSpecifier::Stop).await
+    }
+
+    /// Reset the node's application, and wait for it to boot up again
+    pub async fn restart(&mut self) -> std::result::Result<(), RestartError> {
+        let node_id = self.node_id;
+        // Listen before sending the reset, so that a fast boot-up is not missed
+        let mut bootup = RawHandle::new(self.sender.clone(), self.receiver.clone());
+        if !self.send_nmt(NmtCommandSpecifier::ResetApp).await {
+            return ResetSendFailedSnafu { node_id }.fail();
+        }
+        let wait = async {
+            while let Ok(msg) = bootup.recv().await {
+                if let Ok(ZencanMessage::Heartbeat(hb)) = ZencanMessage::try_from(msg) {
+                    if hb.node == node_id && hb.state == NmtState::Bootup {
+                        return true;
+                    }
+                }
+            }
+            false
+        };
+        match tokio::time::timeout(self.restart_timeout, wait).await {
+            Ok(true) => Ok(()),
+            _ => NoBootupSnafu {
+                node_id,
+                timeout: self.restart_timeout,
+            }
+            .fail(),
+        }
+    }
+
+    /// Wait for the next EMCY from the node, decoded with the decoders registered on the manager
+    ///
+    /// EMCYs are queued from the first call, so that none are missed between calls. Returns None
+    /// if the bus is closed.
+    pub async fn recv_emcy(&mut self) -> Option<DecodedEmcy> {
+        let emcy = self.emcy.get_or_insert_with(|| {
+            EmcyMonitor::new(
+                RawHandle::new(self.sender.clone(), self.receiver.clone()),
+                self.decoders.clone(),
+                self.nodes.clone(),
+            )
+        });
+        loop {
+            let decoded = emcy.recv().await.ok()?;
+            if decoded.emcy.node == self.node_id {
+                return Some(decoded);
+            }
+        }
+    }
+
+    async fn send_nmt(&mut self, cmd: NmtCommandSpecifier) -> bool {
+        let message = NmtCommand {
+            cs: cmd,
+            node: self.node_id,
+        };
+        self.sender.send(message.into()).await.is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sdo_value() {
+        assert_eq!(DataType::UInt16, <u16 as SdoValue>::DATA_TYPE);
+        assert_eq!(Some(7u16), u16::from_value(Value::U16(7)));
+        assert_eq!(None, u16::from_value(Value::U32(7)));
+        assert_eq!(
+            Some("abc".to_string()),
+            String::from_value(Value::Str("abc".into()))
+        );
+        assert_eq!(Value::F32(1.5), 1.5f32.into());
+    }
+
+    #[test]
+    fn test_restart_error_display() {
+        let err = RestartError::NoBootup {
+            node_id: 3,
+            timeout: Duration::from_secs(1),
+        };
+        assert_eq!("Node 3 did not boot up within 1s", err.to_string());
+    }
+}
@@ -0,0 +1,282 @@
+//! Background discovery of nodes joining and leaving the bus
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    sync::{broadcast, mpsc},
+    task::JoinHandle,
+};
+use zencan_common::{messages::NmtState, traits::AsyncCanSender};
+
+use super::{
+    bus_manager::{probe_node, NodeInfo},
+    secondary_heartbeat::SecondaryHeartbeat,
+    shared_receiver::SharedReceiverChannel,
+    shared_sender::SharedSender,
+};
+use crate::{
+    bus_load::{BusLoadLimiter, FramePacing},
+    bus_silence::BusActivity,
+    competing_master::MasterActivity,
+    sdo_client::SdoClient,
+    transaction_log::TransactionRecorder,
+    transfer_monitor::TransferMonitor,
+};
+
+/// Options for background node discovery
+///
+/// See [`BusManager::start_discovery`](super::BusManager::start_discovery).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscoveryOptions {
+    /// The time without a heartbeat after which a node is considered detached
+    ///
+    /// Default: 3s
+    pub heartbeat_timeout: Duration,
+    /// Time to wait for each SDO response while probing a new node
+    ///
+    /// Default: 100ms
+    pub probe_timeout: Duration,
+    /// When true, only the device type (0x1000) and identity (0x1018) objects are read from new
+    /// nodes, skipping the device name and version strings
+    ///
+    /// Default: false
+    pub probe_only: bool,
+}
+
+impl Default for DiscoveryOptions {
+    fn default() -> Self {
+        Self {
+            heartbeat_timeout: Duration::from_secs(3),
+            probe_timeout: Duration::from_millis(100),
+            probe_only: false,
+        }
+    }
+}
+
+/// The reason a node was detached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetachReason {
+    /// No heartbeat was received from the node within the heartbeat timeout
+    HeartbeatTimeout,
+    /// The node sent a boot-up message, so it was reset or replaced
+    ///
+    /// The node is probed again, and is attached again if it responds.
+    Rebooted,
+}
+
+/// A change in the nodes present on the bus, found by background discovery
+#[derive(Debug, Clone)]
+pub enum NodeEvent {
+    /// A node started sending heartbeats, and responded to the probe of its identity
+    Attached(NodeInfo),
+    /// A node which was attached has gone away
+    Detached {
+        /// The ID of the node
+        node_id: u8,
+        /// Why the node was detached
+        reason: DetachReason,
+    },
+}
+
+/// Receives the [`NodeEvent`]s produced by background discovery
+///
+/// Created by [`BusManager::node_events`](super::BusManager::node_events).
+#[derive(Debug)]
+pub struct NodeEvents {
+    rx: broadcast::Receiver<NodeEvent>,
+}
+
+impl NodeEvents {
+    pub(super) fn new(rx: broadcast::Receiver<NodeEvent>) -> Self {
+        Self { rx }
+    }
+
+    /// Wait for the next event
+    ///
+    /// Returns None if the manager has been dropped. If the receiver falls too far behind, the
+    /// oldest events are skipped.
+    pub async fn recv(&mut self) -> Option<NodeEvent> {
+        loop {
+            match self.rx.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    log::warn!("Dropped {n} node events");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Creates SDO clients configured like those of the manager, which are `Send`, so that they can be
+/// used from a background task
+#[derive(Debug)]
+pub(super) struct ProbeClients<S: AsyncCanSender> {
+    pub sender: SharedSender<S>,
+    pub receiver: SharedReceiverChannel,
+    pub recorder: Option<TransactionRecorder>,
+    pub bus_load: BusLoadLimiter,
+    pub pacing: Arc<Mutex<HashMap<u8, FramePacing>>>,
+    pub transfers: TransferMonitor,
+    pub bus_activity: BusActivity,
+    pub master_activity: MasterActivity,
+}
+
+impl<S: AsyncCanSender> ProbeClients<S> {
+    fn client(&self, node_id: u8) -> SdoClient<SharedSender<S>, SharedReceiverChannel> {
+        let mut client = SdoClient::new_std(node_id, self.sender.clone(), self.receiver.clone());
+        client.set_recorder(self.recorder.clone());
+        client.set_bus_load_limiter(Some(self.bus_load.clone()));
+        let pacing = self.pacing.lock().unwrap().get(&node_id).copied();
+        client.set_frame_pacing(pacing.unwrap_or_default());
+        client.set_transfer_monitor(Some(self.transfers.clone()));
+        client.set_bus_activity(Some(self.bus_activity.clone()));
+        client.set_master_activity(Some(self.master_activity.clone()));
+        client
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Presence {
+    Probing,
+    Attached,
+    /// The probe got no response, and is not retried until the given time
+    Unresponsive(Instant),
+}
+
+#[derive(Debug)]
+struct TrackedNode {
+    presence: Presence,
+    last_heartbeat: Instant,
+}
+
+/// A running discovery task, which is stopped when this is dropped
+#[derive(Debug)]
+pub(super) struct Discovery {
+    opts: DiscoveryOptions,
+    task: JoinHandle<()>,
+}
+
+impl Discovery {
+    /// Spawn a task which watches heartbeats, probes new nodes, and sends events on `events`
+    ///
+    /// The probed information is merged into `nodes`. Heartbeats received on the secondary
+    /// COB-ID count the same as those on the standard COB-ID.
+    pub fn start<S>(
+        mut rx: SharedReceiverChannel,
+        clients: ProbeClients<S>,
+        nodes: Arc<tokio::sync::Mutex<HashMap<u8, NodeInfo>>>,
+        events: broadcast::Sender<NodeEvent>,
+        secondary_heartbeat: SecondaryHeartbeat,
+        opts: DiscoveryOptions,
+    ) -> Self
+    where
+        S: AsyncCanSender + Sync + Send + 'static,
+    {
+        let clients = Arc::new(clients);
+        let task = tokio::spawn(async move {
+            let (probe_tx, mut probe_rx) = mpsc::unbounded_channel();
+            let mut tracked = HashMap::<u8, TrackedNode>::new();
+            let mut sweep =
+                tokio::time::interval((opts.heartbeat_timeout / 4).max(Duration::from_millis(10)));
+
+            let start_probe = |node_id: u8| {
+                let clients = clients.clone();
+                let probe_tx = probe_tx.clone();
+                tokio::spawn(async move {
+                    let mut client = clients.client(node_id);
+                    let info =
+                        probe_node(node_id, &mut client, opts.probe_timeout, opts.probe_only).await;
+                    probe_tx.send((node_id, info)).ok();
+                });
+            };
+
+            loop {
+                tokio::select! {
+                    msg = rx.recv() => {
+                        let Ok(msg) = msg else { continue };
+                        let Some((heartbeat, _)) = secondary_heartbeat.decode(msg) else { continue };
+                        let node_id = heartbeat.node;
+                        if !(1..=127).contains(&node_id) {
+                            continue;
+                        }
+                        let now = msg.receive_instant();
+                        let node = tracked.entry(node_id).or_insert_with(|| {
+                            log::info!("Discovered node {node_id}");
+                            start_probe(node_id);
+                            TrackedNode { presence: Presence::Probing, last_heartbeat: now }
+                        });
+                        node.last_heartbeat = now;
+                        match node.presence {
+                            Presence::Attached if heartbeat.state == NmtState::Bootup => {
+                                events.send(NodeEvent::Detached {
+                                    node_id,
+                                    reason: DetachReason::Rebooted,
+                                }).ok();
+                                node.presence = Presence::Probing;
+                                start_probe(node_id);
+                            }
+                            Presence::Unresponsive(retry) if now >= retry => {
+                                node.presence = Presence::Probing;
+                                start_probe(node_id);
+                            }
+                            _ => (),
+                        }
+                    }
+                    Some((node_id, info)) = probe_rx.recv() => {
+                        // The node may have timed out while it was being probed
+                        let Some(node) = tracked.get_mut(&node_id) else { continue };
+                        if node.presence != Presence::Probing {
+                            continue;
+                        }
+                        match info {
+                            Some(info) => {
+                                let mut nodes = nodes.lock().await;
+                                let info = nodes
+                                    .entry(node_id)
+                                    .and_modify(|existing| existing.update(&info))
+                                    .or_insert(info)
+                                    .clone();
+                                node.presence = Presence::Attached;
+                                events.send(NodeEvent::Attached(info)).ok();
+                            }
+                            None => {
+                                log::warn!("Discovered node {node_id} did not respond to probe");
+                                node.presence =
+                                    Presence::Unresponsive(Instant::now() + opts.heartbeat_timeout);
+                            }
+                        }
+                    }
+                    _ = sweep.tick() => {
+                        tracked.retain(|&node_id, node| {
+                            if node.last_heartbeat.elapsed() < opts.heartbeat_timeout {
+                                return true;
+                            }
+                            if node.presence == Presence::Attached {
+                                events.send(NodeEvent::Detached {
+                                    node_id,
+                                    reason: DetachReason::HeartbeatTimeout,
+                                }).ok();
+                            }
+                            false
+                        });
+                    }
+                }
+            }
+        });
+        Self { opts, task }
+    }
+
+    pub fn options(&self) -> DiscoveryOptions {
+        self.opts
+    }
+}
+
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
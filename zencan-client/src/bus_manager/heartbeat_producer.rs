@@ -0,0 +1,64 @@
+//! Heartbeat producer for the manager itself
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use zencan_common::{
+    messages::{Heartbeat, NmtState},
+    node_id::ConfiguredId,
+    traits::AsyncCanSender,
+};
+
+use super::shared_sender::SharedSender;
+
+/// The configuration of the heartbeat produced by a [`BusManager`](super::BusManager)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ManagerHeartbeat {
+    /// The node ID the heartbeat is sent from
+    pub node_id: ConfiguredId,
+    /// The time between heartbeats
+    pub period: Duration,
+}
+
+/// A running heartbeat task, which is stopped when this is dropped
+#[derive(Debug)]
+pub(super) struct HeartbeatProducer {
+    config: ManagerHeartbeat,
+    task: JoinHandle<()>,
+}
+
+impl HeartbeatProducer {
+    /// Spawn a task which sends an Operational heartbeat from `config.node_id` every period
+    ///
+    /// The first heartbeat is sent immediately.
+    pub fn start<S>(mut sender: SharedSender<S>, config: ManagerHeartbeat) -> Self
+    where
+        S: AsyncCanSender + Send + 'static,
+    {
+        let mut interval = tokio::time::interval(config.period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let task = tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                let heartbeat = Heartbeat {
+                    node: config.node_id.raw(),
+                    toggle: false,
+                    state: NmtState::Operational,
+                };
+                if sender.send(heartbeat.into()).await.is_err() {
+                    log::warn!("Failed to send manager heartbeat");
+                }
+            }
+        });
+        Self { config, task }
+    }
+
+    pub fn config(&self) -> ManagerHeartbeat {
+        self.config
+    }
+}
+
+impl Drop for HeartbeatProducer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -1,4 +1,17 @@
 mod bus_manager;
+mod device;
+mod discovery;
+mod heartbeat_producer;
+mod raw_handle;
+mod secondary_heartbeat;
 mod shared_receiver;
 mod shared_sender;
-pub use bus_manager::BusManager;
+mod sync_producer;
+pub use bus_manager::{BusManager, NodeCapabilities, NodeInfo, ScanOptions};
+pub use device::{Device, RestartError, SdoValue};
+pub use discovery::{DetachReason, DiscoveryOptions, NodeEvent, NodeEvents};
+pub use heartbeat_producer::ManagerHeartbeat;
+pub use raw_handle::RawHandle;
+pub use secondary_heartbeat::HeartbeatSource;
+pub use shared_receiver::NoMsgError;
+pub use sync_producer::{ManagerSync, SyncProducerError};
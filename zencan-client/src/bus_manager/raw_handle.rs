@@ -0,0 +1,115 @@
+//! Raw access to the bus shared by a [`BusManager`](super::BusManager)
+//!
+
+use zencan_common::{
+    traits::{AsyncCanReceiver, AsyncCanSender},
+    CanMessage,
+};
+
+use super::shared_receiver::{NoMsgError, SharedReceiverChannel};
+use super::shared_sender::SharedSender;
+
+type FilterFn = dyn Fn(&CanMessage) -> bool + Send + Sync;
+
+/// A handle for sending and receiving arbitrary CAN messages on a bus managed by a
+/// [`BusManager`](super::BusManager)
+///
+/// The handle shares the socket used by the bus manager, and receives its own copy of all traffic,
+/// so it can be used to implement protocols which zencan does not know about without disturbing
+/// the protocol processing done by the manager.
+///
+/// Created by [`BusManager::raw_handle`](super::BusManager::raw_handle).
+pub struct RawHandle<S: AsyncCanSender> {
+    sender: SharedSender<S>,
+    receiver: SharedReceiverChannel,
+    filter: Option<Box<FilterFn>>,
+}
+
+impl<S: AsyncCanSender> core::fmt::Debug for RawHandle<S> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RawHandle")
+            .field("filtered", &self.filter.is_some())
+            .finish()
+    }
+}
+
+impl<S: AsyncCanSender> RawHandle<S> {
+    pub(crate) fn new(sender: SharedSender<S>, receiver: SharedReceiverChannel) -> Self {
+        Self {
+            sender,
+            receiver,
+            filter: None,
+        }
+    }
+
+    /// Set a filter for received messages
+    ///
+    /// Only messages for which `filter` returns true will be returned from
+    /// [`recv`](Self::recv) and [`try_recv`](Self::try_recv).
+    pub fn with_filter(
+        mut self,
+        filter: impl Fn(&CanMessage) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    fn accept(&self, msg: &CanMessage) -> bool {
+        self.filter.as_ref().map(|f| f(msg)).unwrap_or(true)
+    }
+
+    /// Send a message to the bus
+    pub async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
+        self.sender.send(msg).await
+    }
+
+    /// Wait for the next message passing the filter
+    pub async fn recv(&mut self) -> Result<CanMessage, NoMsgError> {
+        loop {
+            let msg = self.receiver.recv().await?;
+            if self.accept(&msg) {
+                return Ok(msg);
+            }
+        }
+    }
+
+    /// Return the next queued message passing the filter, if there is one
+    pub fn try_recv(&mut self) -> Option<CanMessage> {
+        while let Some(msg) = self.receiver.try_recv() {
+            if self.accept(&msg) {
+                return Some(msg);
+            }
+        }
+        None
+    }
+
+    /// Discard all queued received messages
+    pub fn flush(&mut self) {
+        self.receiver.flush();
+    }
+}
+
+impl<S: AsyncCanSender> AsyncCanSender for RawHandle<S> {
+    fn send(
+        &mut self,
+        msg: CanMessage,
+    ) -> impl core::future::Future<Output = Result<(), CanMessage>> {
+        self.send(msg)
+    }
+}
+
+impl<S: AsyncCanSender> AsyncCanReceiver for RawHandle<S> {
+    type Error = NoMsgError;
+
+    fn try_recv(&mut self) -> Option<CanMessage> {
+        self.try_recv()
+    }
+
+    fn recv(&mut self) -> impl core::future::Future<Output = Result<CanMessage, Self::Error>> {
+        self.recv()
+    }
+
+    fn flush(&mut self) {
+        self.flush()
+    }
+}
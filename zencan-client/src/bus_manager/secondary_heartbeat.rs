@@ -0,0 +1,81 @@
+//! Merging of heartbeats received on a secondary COB-ID
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use zencan_common::messages::{CanId, CanMessage, Heartbeat, ZencanMessage};
+
+/// The COB-ID a heartbeat was received on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HeartbeatSource {
+    /// The standard heartbeat COB-ID, 0x700 + node ID
+    Primary,
+    /// The secondary heartbeat COB-ID, set with
+    /// [`BusManager::set_secondary_heartbeat_base`](super::BusManager::set_secondary_heartbeat_base)
+    Secondary,
+}
+
+/// The base COB-ID of secondary heartbeats, shared between the manager and its background tasks
+#[derive(Debug, Clone, Default)]
+pub(super) struct SecondaryHeartbeat {
+    base: Arc<Mutex<Option<u16>>>,
+}
+
+impl SecondaryHeartbeat {
+    pub fn set_base(&self, base: Option<u16>) {
+        *self.base.lock().unwrap() = base;
+    }
+
+    pub fn base(&self) -> Option<u16> {
+        *self.base.lock().unwrap()
+    }
+
+    /// Decode a heartbeat received on either the standard or the secondary COB-ID
+    ///
+    /// Returns None if the message is not a heartbeat.
+    pub fn decode(&self, msg: CanMessage) -> Option<(Heartbeat, HeartbeatSource)> {
+        if let (Some(base), CanId::Std(id)) = (self.base(), msg.id()) {
+            if let Some(node) = id.checked_sub(base).filter(|n| (1..=127).contains(n)) {
+                let heartbeat = Heartbeat::decode(node as u8, &msg).ok()?;
+                return Some((heartbeat, HeartbeatSource::Secondary));
+            }
+        }
+        match ZencanMessage::try_from(msg) {
+            Ok(ZencanMessage::Heartbeat(heartbeat)) => Some((heartbeat, HeartbeatSource::Primary)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zencan_common::messages::NmtState;
+
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let heartbeat = Heartbeat {
+            node: 5,
+            toggle: false,
+            state: NmtState::Operational,
+        };
+        let primary: CanMessage = heartbeat.into();
+        let secondary = heartbeat.to_can_message(CanId::std(0x685));
+
+        let merger = SecondaryHeartbeat::default();
+        let decoded = merger.decode(primary).unwrap();
+        assert_eq!((5, HeartbeatSource::Primary), (decoded.0.node, decoded.1));
+        assert!(merger.decode(secondary).is_none());
+
+        merger.clone().set_base(Some(0x680));
+        assert_eq!(Some(0x680), merger.base());
+        let decoded = merger.decode(secondary).unwrap();
+        assert_eq!((5, HeartbeatSource::Secondary), (decoded.0.node, decoded.1));
+        assert_eq!(NmtState::Operational, decoded.0.state);
+        assert!(merger.decode(primary).is_some());
+        // The base itself is not a node ID
+        assert!(merger
+            .decode(heartbeat.to_can_message(CanId::std(0x680)))
+            .is_none());
+    }
+}
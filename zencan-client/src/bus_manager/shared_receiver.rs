@@ -4,6 +4,7 @@
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use snafu::Snafu;
 use tokio::sync::mpsc::error::TrySendError;
 use tokio::{
     sync::mpsc::{channel, Receiver, Sender},
@@ -11,37 +12,64 @@ use tokio::{
 };
 use zencan_common::{traits::AsyncCanReceiver, CanMessage};
 
-#[derive(Clone, Copy, Debug)]
+use crate::{
+    echo_filter::EchoFilter,
+    frame_capture::{Direction, FrameCapture},
+};
+
+/// Error returned when receiving from the bus manager after its receiver has closed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Snafu)]
+#[snafu(display("The bus receiver is closed"))]
 pub struct NoMsgError;
 
 #[derive(Debug)]
 struct SharedRecieiverInner {
     senders: Vec<Sender<CanMessage>>,
+    /// Set once the receiver is closed, after which new channels are created closed
+    closed: bool,
 }
 
 impl SharedRecieiverInner {
     pub fn create_rx(&mut self) -> Receiver<CanMessage> {
         let (tx, rx) = channel(100);
-        self.senders.push(tx);
+        if !self.closed {
+            self.senders.push(tx);
+        }
         rx
     }
 }
 
+/// Distributes received frames to any number of channels
+///
+/// The receiving task is stopped, and the channels are closed, when this is dropped.
 #[derive(Debug)]
 pub struct SharedReceiver {
-    _task_handle: JoinHandle<()>,
+    task_handle: JoinHandle<()>,
     inner: Arc<Mutex<SharedRecieiverInner>>,
 }
 
 impl SharedReceiver {
-    pub fn new<R: AsyncCanReceiver + Send + 'static>(mut receiver: R) -> Self {
+    /// Start a task distributing the frames from `receiver` to the channels
+    ///
+    /// Echoes of the frames recorded in `echoes` are dropped. All other frames are recorded in
+    /// `capture`, as transmitted by the nodes.
+    pub fn new<R: AsyncCanReceiver + Send + 'static>(
+        mut receiver: R,
+        echoes: EchoFilter,
+        capture: FrameCapture,
+    ) -> Self {
         let inner = Arc::new(Mutex::new(SharedRecieiverInner {
             senders: Vec::new(),
+            closed: false,
         }));
         let inner_clone = inner.clone();
         let task_handle = tokio::spawn(async move {
             loop {
                 if let Ok(msg) = receiver.recv().await {
+                    if echoes.take_echo(&msg) {
+                        continue;
+                    }
+                    capture.record(Direction::Tx, msg, msg.receive_instant());
                     let mut inner = inner_clone.lock().unwrap();
                     inner.senders.retain(|sender| {
                         if let Err(e) = sender.try_send(msg) {
@@ -59,10 +87,18 @@ impl SharedReceiver {
                 };
             }
         });
-        Self {
-            _task_handle: task_handle,
-            inner,
-        }
+        Self { task_handle, inner }
+    }
+
+    /// Stop receiving, and close all of the channels
+    ///
+    /// The underlying receiver is dropped. Channels return the frames they already hold, and then
+    /// return an error from `recv`.
+    pub fn close(&self) {
+        self.task_handle.abort();
+        let mut inner = self.inner.lock().unwrap();
+        inner.closed = true;
+        inner.senders.clear();
     }
 
     pub fn create_rx(&mut self) -> SharedReceiverChannel {
@@ -82,6 +118,12 @@ impl SharedReceiver {
     }
 }
 
+impl Drop for SharedReceiver {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
 #[derive(Debug)]
 pub struct SharedReceiverChannel {
     /// Data shared with the multi consumer Rx
@@ -167,7 +209,9 @@ mod tests {
     async fn test_shared_receiver() {
         let (chan_tx, chan_rx) = channel(8);
         let can_receiver = MockReceiver::new(chan_rx);
-        let mut shared_receiver = SharedReceiver::new(can_receiver);
+        let echoes = EchoFilter::new(true);
+        let mut shared_receiver =
+            SharedReceiver::new(can_receiver, echoes.clone(), FrameCapture::default());
 
         let mut channel_a = shared_receiver.create_rx();
         let mut channel_b = shared_receiver.create_rx();
@@ -189,5 +233,36 @@ mod tests {
         assert_eq!(msg100, channel_b.recv().await.unwrap());
 
         assert_eq!(1, shared_receiver.num_channels());
+
+        // Echoes of sent frames are not delivered
+        echoes.record_sent(msg100);
+        chan_tx.send(msg100).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(None, channel_b.try_recv());
+    }
+
+    #[tokio::test]
+    async fn test_close() {
+        let (chan_tx, chan_rx) = channel(8);
+        let can_receiver = MockReceiver::new(chan_rx);
+        let mut shared_receiver =
+            SharedReceiver::new(can_receiver, EchoFilter::default(), FrameCapture::default());
+        let mut channel = shared_receiver.create_rx();
+
+        let msg100 = CanMessage::new(CanId::std(100), &[0, 1, 2, 3]);
+        chan_tx.send(msg100).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        shared_receiver.close();
+        // Frames already received are still delivered, and then the channel is closed
+        assert_eq!(msg100, channel.recv().await.unwrap());
+        assert!(channel.recv().await.is_err());
+        // Channels created after the close are closed too
+        assert!(channel.clone().recv().await.is_err());
+        assert_eq!(0, shared_receiver.num_channels());
+
+        // The underlying receiver is dropped with the task
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(chan_tx.is_closed());
     }
 }
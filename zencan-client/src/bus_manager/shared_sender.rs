@@ -1,30 +1,72 @@
 //! Utility for sharing a single socket among tasks
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 use tokio::sync::Mutex;
 
 use zencan_common::{traits::AsyncCanSender, CanMessage};
 
+use crate::{
+    echo_filter::EchoFilter,
+    frame_capture::{Direction, FrameCapture},
+};
+
 #[derive(Debug)]
 pub struct SharedSender<S: AsyncCanSender> {
-    inner: Arc<Mutex<S>>,
+    /// The underlying sender, which is None once closed
+    inner: Arc<Mutex<Option<S>>>,
+    echoes: EchoFilter,
+    capture: FrameCapture,
 }
 
 impl<S: AsyncCanSender> Clone for SharedSender<S> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            echoes: self.echoes.clone(),
+            capture: self.capture.clone(),
         }
     }
 }
 
 impl<S: AsyncCanSender> SharedSender<S> {
-    pub fn new(sender: Arc<Mutex<S>>) -> Self {
-        Self { inner: sender }
+    /// Create a shared sender, which records each frame sent in `echoes`, and in `capture` as
+    /// received by the nodes
+    ///
+    /// Echoes of the frames sent are dropped by the manager's receiver, so the shared sender does
+    /// not report that its transport echoes.
+    pub(crate) fn new(sender: S, echoes: EchoFilter, capture: FrameCapture) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Some(sender))),
+            echoes,
+            capture,
+        }
     }
 
     async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
         let mut inner = self.inner.lock().await;
-        inner.send(msg).await
+        let Some(sender) = inner.as_mut() else {
+            return Err(msg);
+        };
+        self.capture.record(Direction::Rx, msg, Instant::now());
+        sender.send(msg).await?;
+        self.echoes.record_sent(msg);
+        Ok(())
+    }
+
+    /// Send `last`, and then drop the underlying sender, so that all later sends by any clone fail
+    ///
+    /// No other frame can be sent between the frames in `last` and the close.
+    pub async fn close(&self, last: &[CanMessage]) {
+        let mut inner = self.inner.lock().await;
+        let Some(mut sender) = inner.take() else {
+            return;
+        };
+        for &msg in last {
+            self.capture.record(Direction::Rx, msg, Instant::now());
+            match sender.send(msg).await {
+                Ok(()) => self.echoes.record_sent(msg),
+                Err(_) => log::warn!("Failed to send {msg:?} while closing the bus"),
+            }
+        }
     }
 }
 
@@ -0,0 +1,202 @@
+//! SYNC producer for the manager itself
+use std::time::Duration;
+
+use snafu::{ResultExt, Snafu};
+use tokio::task::JoinHandle;
+#[cfg(target_os = "linux")]
+use zencan_common::CyclicTransmitter;
+use zencan_common::{
+    messages::{CanMessage, SyncObject},
+    traits::AsyncCanSender,
+};
+
+use super::shared_sender::SharedSender;
+
+/// The largest valid SYNC counter overflow value
+const MAX_COUNTER_OVERFLOW: u8 = 240;
+
+/// The configuration of the SYNC produced by a [`BusManager`](super::BusManager)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagerSync {
+    /// The time between SYNCs
+    pub period: Duration,
+    /// The highest value of the SYNC counter, after which it restarts from 1
+    ///
+    /// 0 sends SYNCs with no counter.
+    pub counter_overflow: u8,
+    /// The socketcan interface whose kernel Broadcast Manager sends the SYNC, if it is offloaded
+    ///
+    /// Offloading is only supported on Linux.
+    pub offload: Option<String>,
+}
+
+/// An error starting the manager SYNC
+#[derive(Debug, Snafu)]
+pub enum SyncProducerError {
+    /// The counter overflow value is reserved
+    #[snafu(display("Invalid SYNC counter overflow {value}. It must be 0, or 2 to 240"))]
+    InvalidCounterOverflow {
+        /// The requested value
+        value: u8,
+    },
+    /// The Broadcast Manager job could not be programmed
+    #[snafu(display("Failed to offload SYNC to {device}: {source}"))]
+    Offload {
+        /// The socketcan interface
+        device: String,
+        /// The underlying error
+        source: std::io::Error,
+    },
+}
+
+/// Get the SYNC sent for each step of the counter
+fn sync_frames(counter_overflow: u8) -> Vec<CanMessage> {
+    if counter_overflow == 0 {
+        vec![SyncObject::new(0).into()]
+    } else {
+        (1..=counter_overflow)
+            .map(|count| SyncObject::new(count).into())
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+enum Timer {
+    Task(JoinHandle<()>),
+    #[cfg(target_os = "linux")]
+    Kernel(CyclicTransmitter),
+}
+
+#[cfg(target_os = "linux")]
+fn offload(
+    device: &str,
+    frames: &[CanMessage],
+    period: Duration,
+) -> Result<Timer, SyncProducerError> {
+    let transmitter = CyclicTransmitter::open(device).context(OffloadSnafu { device })?;
+    transmitter
+        .start(frames, period)
+        .context(OffloadSnafu { device })?;
+    Ok(Timer::Kernel(transmitter))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn offload(
+    device: &str,
+    _frames: &[CanMessage],
+    _period: Duration,
+) -> Result<Timer, SyncProducerError> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "The Broadcast Manager is only available on Linux",
+    ))
+    .context(OffloadSnafu { device })
+}
+
+/// A running SYNC producer, which is stopped when this is dropped
+#[derive(Debug)]
+pub(super) struct SyncProducer {
+    config: ManagerSync,
+    timer: Timer,
+}
+
+impl SyncProducer {
+    /// Start sending SYNC every period, from a background task or the kernel
+    pub fn start<S>(sender: SharedSender<S>, config: ManagerSync) -> Result<Self, SyncProducerError>
+    where
+        S: AsyncCanSender + Send + 'static,
+    {
+        if config.counter_overflow == 1 || config.counter_overflow > MAX_COUNTER_OVERFLOW {
+            return InvalidCounterOverflowSnafu {
+                value: config.counter_overflow,
+            }
+            .fail();
+        }
+        let frames = sync_frames(config.counter_overflow);
+        let timer = match &config.offload {
+            Some(device) => offload(device, &frames, config.period)?,
+            None => Timer::Task(Self::spawn(sender, frames, config.period)),
+        };
+        Ok(Self { config, timer })
+    }
+
+    fn spawn<S>(
+        mut sender: SharedSender<S>,
+        frames: Vec<CanMessage>,
+        period: Duration,
+    ) -> JoinHandle<()>
+    where
+        S: AsyncCanSender + Send + 'static,
+    {
+        // Match the kernel timer, which sends the first SYNC after one period
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        tokio::spawn(async move {
+            for frame in frames.iter().cycle() {
+                interval.tick().await;
+                if sender.send(*frame).await.is_err() {
+                    log::warn!("Failed to send manager SYNC");
+                }
+            }
+        })
+    }
+
+    /// Change the period, keeping the counter overflow and offload
+    ///
+    /// An offloaded SYNC is re-programmed in place. Otherwise the task is restarted.
+    pub fn set_period<S>(
+        &mut self,
+        sender: SharedSender<S>,
+        period: Duration,
+    ) -> Result<(), SyncProducerError>
+    where
+        S: AsyncCanSender + Send + 'static,
+    {
+        let frames = sync_frames(self.config.counter_overflow);
+        match &mut self.timer {
+            #[cfg(target_os = "linux")]
+            Timer::Kernel(transmitter) => {
+                transmitter.start(&frames, period).context(OffloadSnafu {
+                    device: transmitter.device(),
+                })?;
+            }
+            Timer::Task(task) => {
+                task.abort();
+                *task = Self::spawn(sender, frames, period);
+            }
+        }
+        self.config.period = period;
+        Ok(())
+    }
+
+    pub fn config(&self) -> &ManagerSync {
+        &self.config
+    }
+}
+
+impl Drop for SyncProducer {
+    fn drop(&mut self) {
+        match &self.timer {
+            Timer::Task(task) => task.abort(),
+            // Dropping the transmitter closes its socket, which stops the job
+            #[cfg(target_os = "linux")]
+            Timer::Kernel(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_frames() {
+        let frames = sync_frames(0);
+        assert_eq!(1, frames.len());
+        assert!(frames[0].data().is_empty());
+
+        let frames = sync_frames(3);
+        let counts: Vec<u8> = frames.iter().map(|f| f.data()[0]).collect();
+        assert_eq!(vec![1, 2, 3], counts);
+    }
+}
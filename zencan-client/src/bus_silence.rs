@@ -0,0 +1,249 @@
+//! Detection of a silent bus
+//!
+//! A node which does not respond to a request may simply be missing, but when no frames at all are
+//! received from the bus for a while, it is more likely that the interface is down or the cabling
+//! is faulty. A [`BusManager`](crate::BusManager) watches for this once a timeout is set with
+//! [`set_silence_timeout`](crate::BusManager::set_silence_timeout):
+//!
+//! - A [`BusEvent::Silent`] is sent to all [`BusEvents`] receivers when no frame has been received
+//!   for the timeout, and a [`BusEvent::Active`] when a frame is received again.
+//! - SDO requests made through the manager which get no response while the bus is silent fail
+//!   with [`SdoClientError::BusSilent`](crate::SdoClientError::BusSilent), instead of
+//!   [`SdoClientError::NoResponse`](crate::SdoClientError::NoResponse).
+//!
+//! Frames sent by the manager itself are not normally received back, so they do not count as
+//! activity. On a bus where the nodes are normally quiet, enable heartbeats on the nodes, or choose
+//! a timeout longer than the longest expected gap between frames.
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::{sync::broadcast, task::JoinHandle};
+use zencan_common::messages::CanId;
+
+use crate::failover::{FailoverReason, Link};
+
+/// The shortest time between checks of the bus activity
+const MIN_POLL_PERIOD: Duration = Duration::from_millis(1);
+
+#[derive(Debug)]
+struct ActivityState {
+    last_frame: Instant,
+    timeout: Option<Duration>,
+}
+
+/// Tracks the time of the last frame received from the bus
+///
+/// Clones share the same state.
+#[derive(Debug, Clone)]
+pub struct BusActivity {
+    state: Arc<Mutex<ActivityState>>,
+}
+
+impl Default for BusActivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BusActivity {
+    /// Create a tracker, with no silence timeout
+    ///
+    /// The bus is treated as having been active when the tracker is created.
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ActivityState {
+                last_frame: Instant::now(),
+                timeout: None,
+            })),
+        }
+    }
+
+    /// Record a frame received at `at`
+    pub fn record_frame(&self, at: Instant) {
+        let mut state = self.state.lock().unwrap();
+        state.last_frame = state.last_frame.max(at);
+    }
+
+    /// Set the time without a frame after which the bus is considered silent, or None to never
+    /// consider it silent
+    pub fn set_timeout(&self, timeout: Option<Duration>) {
+        self.state.lock().unwrap().timeout = timeout;
+    }
+
+    /// Get the silence timeout
+    pub fn timeout(&self) -> Option<Duration> {
+        self.state.lock().unwrap().timeout
+    }
+
+    /// Get the time the last frame was received
+    pub fn last_frame(&self) -> Instant {
+        self.state.lock().unwrap().last_frame
+    }
+
+    /// Get the time since the last frame was received
+    pub fn idle_time(&self) -> Duration {
+        self.last_frame().elapsed()
+    }
+
+    /// Get the time since the last frame was received, if it exceeds the silence timeout
+    pub fn silence(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        let idle = state.last_frame.elapsed();
+        state
+            .timeout
+            .is_some_and(|timeout| idle >= timeout)
+            .then_some(idle)
+    }
+}
+
+/// A change in the activity of the bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusEvent {
+    /// No frame has been received for the silence timeout
+    Silent {
+        /// The time since the last frame was received
+        idle_time: Duration,
+    },
+    /// A frame has been received after the bus was silent
+    Active {
+        /// How long the bus was silent for
+        silent_time: Duration,
+    },
+    /// A frame was received from another master. See [`crate::competing_master`].
+    CompetingMaster {
+        /// The COB-ID of the frame
+        cob_id: CanId,
+    },
+    /// A redundant bus switched to its other link. See [`crate::failover`].
+    Failover {
+        /// The link which was active
+        from: Link,
+        /// The link which is now active
+        to: Link,
+        /// Why the link was switched
+        reason: FailoverReason,
+    },
+}
+
+/// Receives the [`BusEvent`]s produced by the silence watchdog, the competing master detection,
+/// and the failover of a redundant bus
+///
+/// Created by [`BusManager::bus_events`](crate::BusManager::bus_events).
+#[derive(Debug)]
+pub struct BusEvents {
+    rx: broadcast::Receiver<BusEvent>,
+}
+
+impl BusEvents {
+    pub(crate) fn new(rx: broadcast::Receiver<BusEvent>) -> Self {
+        Self { rx }
+    }
+
+    /// Wait for the next event
+    ///
+    /// Returns None if the manager has been dropped. If the receiver falls too far behind, the
+    /// oldest events are skipped.
+    pub async fn recv(&mut self) -> Option<BusEvent> {
+        loop {
+            match self.rx.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    log::warn!("Dropped {n} bus events");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// A running silence watchdog task, which is stopped when this is dropped
+#[derive(Debug)]
+pub(crate) struct SilenceWatchdog {
+    task: JoinHandle<()>,
+}
+
+impl SilenceWatchdog {
+    /// Spawn a task which sends an event when the bus goes silent for `timeout`, and when it
+    /// becomes active again
+    pub fn start(
+        activity: BusActivity,
+        timeout: Duration,
+        events: broadcast::Sender<BusEvent>,
+    ) -> Self {
+        let task = tokio::spawn(async move {
+            // The time of the last frame before the bus went silent, while it is silent
+            let mut silent_after = None;
+            loop {
+                let last_frame = activity.last_frame();
+                let idle = last_frame.elapsed();
+                match silent_after {
+                    None if idle >= timeout => {
+                        log::warn!("No frames received for {} ms", idle.as_millis());
+                        silent_after = Some(last_frame);
+                        events.send(BusEvent::Silent { idle_time: idle }).ok();
+                    }
+                    Some(previous) if last_frame > previous => {
+                        let silent_time = last_frame - previous;
+                        log::info!("Bus active after {} ms", silent_time.as_millis());
+                        silent_after = None;
+                        events.send(BusEvent::Active { silent_time }).ok();
+                    }
+                    _ => (),
+                }
+                // While active, the bus cannot go silent before the timeout expires. While silent,
+                // poll for the first frame.
+                let wait = match silent_after {
+                    None => timeout.saturating_sub(idle),
+                    Some(_) => timeout / 10,
+                };
+                tokio::time::sleep(wait.max(MIN_POLL_PERIOD)).await;
+            }
+        });
+        Self { task }
+    }
+}
+
+impl Drop for SilenceWatchdog {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bus_activity() {
+        let activity = BusActivity::new();
+        assert_eq!(None, activity.silence());
+        activity.set_timeout(Some(Duration::ZERO));
+        assert!(activity.silence().is_some());
+        activity.set_timeout(Some(Duration::from_secs(60)));
+        assert_eq!(None, activity.silence());
+
+        // An older frame does not move the last frame time back
+        let now = Instant::now();
+        activity.clone().record_frame(now);
+        activity.record_frame(now - Duration::from_secs(1));
+        assert_eq!(now, activity.last_frame());
+    }
+
+    #[tokio::test]
+    async fn test_watchdog_events() {
+        let activity = BusActivity::new();
+        let (tx, rx) = broadcast::channel(4);
+        let mut events = BusEvents::new(rx);
+        let _watchdog = SilenceWatchdog::start(activity.clone(), Duration::from_millis(20), tx);
+
+        let Some(BusEvent::Silent { idle_time }) = events.recv().await else {
+            panic!("Expected a silent event");
+        };
+        assert!(idle_time >= Duration::from_millis(20));
+
+        activity.record_frame(Instant::now());
+        assert!(matches!(events.recv().await, Some(BusEvent::Active { .. })));
+    }
+}
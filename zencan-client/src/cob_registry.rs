@@ -0,0 +1,439 @@
+//! Tracking of the COB-IDs in use on a bus
+//!
+//! A [`CobIdRegistry`] records two kinds of use of a COB-ID:
+//!
+//! - COB-IDs observed on the bus, from any frame received
+//! - COB-IDs configured on the PDOs of nodes, whenever the PDO configuration of a node is read or
+//!   written by the [`BusManager`](crate::BusManager)
+//!
+//! It is used to [allocate](CobIdRegistry::allocate) free COB-IDs from the PDO range, e.g. when
+//! [linking](crate::pdo_link) nodes, and to [check](CobIdRegistry::conflicts) a [`NodeConfig`] for
+//! COB-IDs which would clash with another node before it is applied.
+//!
+//! The registry only knows about the PDOs it has been told about, so the PDO configuration of the
+//! bus should be read first, e.g. with
+//! [`BusManager::export_topology`](crate::BusManager::export_topology).
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use zencan_common::messages::CanId;
+
+use crate::{
+    topology::{PdoEndpoint, TopologyNode},
+    NodeConfig, PdoConfig,
+};
+
+/// The range of COB-IDs assigned to PDOs by the predefined connection set
+const PDO_COB_IDS: std::ops::RangeInclusive<u32> = 0x181..=0x57F;
+
+/// Returns true if a COB-ID is used by a service of the predefined connection set other than PDOs
+///
+/// These are NMT, SYNC, EMCY, TIME, SDO, heartbeat and LSS.
+pub fn is_reserved(cob_id: u32) -> bool {
+    matches!(
+        cob_id,
+        0 | 0x80..=0x100 | 0x581..=0x5FF | 0x601..=0x67F | 0x701..=0x77F | 0x7E4 | 0x7E5
+    )
+}
+
+/// A PDO which is configured to use a COB-ID
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CobIdUser {
+    /// A transmit PDO
+    Tpdo(PdoEndpoint),
+    /// A receive PDO
+    Rpdo(PdoEndpoint),
+}
+
+impl CobIdUser {
+    /// The node which the PDO belongs to
+    pub fn node_id(&self) -> u8 {
+        match self {
+            CobIdUser::Tpdo(e) | CobIdUser::Rpdo(e) => e.node_id,
+        }
+    }
+}
+
+impl Display for CobIdUser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CobIdUser::Tpdo(e) => write!(f, "TPDO {} of node {}", e.pdo, e.node_id),
+            CobIdUser::Rpdo(e) => write!(f, "RPDO {} of node {}", e.pdo, e.node_id),
+        }
+    }
+}
+
+/// Why the COB-ID of a PDO conflicts with the rest of the bus
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictReason {
+    /// The COB-ID is used by another service, such as SDO or heartbeat
+    Reserved,
+    /// The COB-ID is also sent by another TPDO
+    Produced(PdoEndpoint),
+    /// Frames with the COB-ID have been observed on the bus, but are not sent by any known TPDO
+    ///
+    /// They may be sent by a device which is not a known node, or by a node whose PDOs have not
+    /// been read.
+    Observed,
+}
+
+/// A COB-ID conflict found by [`CobIdRegistry::conflicts`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CobIdConflict {
+    /// The COB-ID
+    pub cob_id: u32,
+    /// The PDO in the configuration which uses the COB-ID
+    pub user: CobIdUser,
+    /// Why it conflicts
+    pub reason: ConflictReason,
+}
+
+impl Display for CobIdConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} uses COB-ID 0x{:X}, ", self.user, self.cob_id)?;
+        match self.reason {
+            ConflictReason::Reserved => write!(f, "which is reserved for another service"),
+            ConflictReason::Produced(e) => {
+                write!(
+                    f,
+                    "which is also sent by TPDO {} of node {}",
+                    e.pdo, e.node_id
+                )
+            }
+            ConflictReason::Observed => write!(f, "which is already sent by an unknown device"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    observed: HashMap<u32, Instant>,
+    /// The COB-ID and enabled flag of each known PDO
+    configured: HashMap<CobIdUser, (u32, bool)>,
+}
+
+/// Tracks the COB-IDs observed and configured on a bus
+///
+/// The registry is a cheap handle which can be cloned, and all clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CobIdRegistry {
+    inner: Arc<Mutex<Registry>>,
+}
+
+impl CobIdRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a frame received from the bus
+    pub fn record_frame(&self, id: CanId, received: Instant) {
+        // The raw ID matches the COB-ID convention of PdoConfig, where values above 0x7FF are
+        // extended IDs
+        self.inner
+            .lock()
+            .unwrap()
+            .observed
+            .insert(id.raw(), received);
+    }
+
+    /// Record the configuration of a PDO
+    pub fn record_pdo(&self, user: CobIdUser, config: &PdoConfig) {
+        self.inner
+            .lock()
+            .unwrap()
+            .configured
+            .insert(user, (config.cob, config.enabled));
+    }
+
+    /// Replace the recorded PDOs of a node with its current configuration
+    pub fn record_node(&self, node: &TopologyNode) {
+        let node_id = node.node_id;
+        self.forget_node(node_id);
+        for p in &node.tpdos {
+            let endpoint = PdoEndpoint {
+                node_id,
+                pdo: p.pdo,
+            };
+            self.record_pdo(CobIdUser::Tpdo(endpoint), &p.config);
+        }
+        for p in &node.rpdos {
+            let endpoint = PdoEndpoint {
+                node_id,
+                pdo: p.pdo,
+            };
+            self.record_pdo(CobIdUser::Rpdo(endpoint), &p.config);
+        }
+    }
+
+    /// Forget the PDOs of a node, e.g. when it leaves the bus
+    pub fn forget_node(&self, node_id: u8) {
+        self.inner
+            .lock()
+            .unwrap()
+            .configured
+            .retain(|user, _| user.node_id() != node_id);
+    }
+
+    /// Get the last time a frame with a COB-ID was received, if it has been
+    pub fn last_observed(&self, cob_id: u32) -> Option<Instant> {
+        self.inner.lock().unwrap().observed.get(&cob_id).copied()
+    }
+
+    /// Get the PDOs configured to use a COB-ID, whether they are enabled or not
+    pub fn users(&self, cob_id: u32) -> Vec<CobIdUser> {
+        let mut users: Vec<CobIdUser> = self
+            .inner
+            .lock()
+            .unwrap()
+            .configured
+            .iter()
+            .filter(|(_, (cob, _))| *cob == cob_id)
+            .map(|(user, _)| *user)
+            .collect();
+        users.sort_by_key(|u| match u {
+            CobIdUser::Tpdo(e) => (0, e.node_id, e.pdo),
+            CobIdUser::Rpdo(e) => (1, e.node_id, e.pdo),
+        });
+        users
+    }
+
+    /// Returns true if a COB-ID has not been observed, is not configured on any PDO except those in
+    /// `ignore`, and is not reserved
+    pub fn is_free(&self, cob_id: u32, ignore: &[CobIdUser]) -> bool {
+        let inner = self.inner.lock().unwrap();
+        Self::is_free_locked(&inner, cob_id, ignore)
+    }
+
+    fn is_free_locked(inner: &Registry, cob_id: u32, ignore: &[CobIdUser]) -> bool {
+        !is_reserved(cob_id)
+            && !inner.observed.contains_key(&cob_id)
+            && !inner
+                .configured
+                .iter()
+                .any(|(user, (cob, _))| *cob == cob_id && !ignore.contains(user))
+    }
+
+    /// Find a free COB-ID in the PDO range
+    ///
+    /// `preferred` is returned if it is free, otherwise the lowest free COB-ID is. PDOs in `ignore`
+    /// are about to be reconfigured, so the COB-IDs they use are considered free. Disabled PDOs are
+    /// not ignored, so that enabling them later does not create a conflict.
+    ///
+    /// The COB-ID is not reserved; it becomes used once the PDO configured with it is recorded.
+    pub fn allocate(&self, preferred: Option<u32>, ignore: &[CobIdUser]) -> Option<u32> {
+        let inner = self.inner.lock().unwrap();
+        preferred
+            .into_iter()
+            .chain(PDO_COB_IDS)
+            .find(|cob| Self::is_free_locked(&inner, *cob, ignore))
+    }
+
+    /// Check the enabled TPDOs in a configuration for a node against the rest of the bus
+    ///
+    /// A TPDO conflicts if its COB-ID is reserved, is used by another enabled TPDO, either on
+    /// another node or in the same configuration, or has been observed on the bus without a known
+    /// producer. RPDOs never conflict, as any number of nodes can receive a PDO.
+    pub fn conflicts(&self, node_id: u8, config: &NodeConfig) -> Vec<CobIdConflict> {
+        let inner = self.inner.lock().unwrap();
+        let mut tpdos: Vec<(usize, &PdoConfig)> = config
+            .tpdos()
+            .iter()
+            .filter(|(_, c)| c.enabled)
+            .map(|(pdo, c)| (*pdo, c))
+            .collect();
+        tpdos.sort_by_key(|(pdo, _)| *pdo);
+
+        // The recorded TPDOs of the node which the configuration replaces are not compared
+        let producers: Vec<(PdoEndpoint, u32)> = inner
+            .configured
+            .iter()
+            .filter_map(|(user, (cob, enabled))| match user {
+                CobIdUser::Tpdo(e) if *enabled => Some((*e, *cob)),
+                _ => None,
+            })
+            .filter(|(e, _)| e.node_id != node_id || !config.tpdos().contains_key(&e.pdo))
+            .collect();
+
+        let mut conflicts = Vec::new();
+        for (i, &(pdo, cfg)) in tpdos.iter().enumerate() {
+            let user = CobIdUser::Tpdo(PdoEndpoint { node_id, pdo });
+            let cob_id = cfg.cob;
+            let reason = if is_reserved(cob_id) {
+                Some(ConflictReason::Reserved)
+            } else if let Some(&(other, _)) = tpdos[..i].iter().find(|(_, c)| c.cob == cob_id) {
+                Some(ConflictReason::Produced(PdoEndpoint {
+                    node_id,
+                    pdo: other,
+                }))
+            } else if let Some((other, _)) = producers
+                .iter()
+                .filter(|(_, cob)| *cob == cob_id)
+                .min_by_key(|(e, _)| (e.node_id, e.pdo))
+            {
+                Some(ConflictReason::Produced(*other))
+            } else if inner.observed.contains_key(&cob_id)
+                && !inner.configured.iter().any(|(u, (cob, _))| {
+                    *cob == cob_id && matches!(u, CobIdUser::Tpdo(e) if e.node_id == node_id)
+                })
+            {
+                Some(ConflictReason::Observed)
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                conflicts.push(CobIdConflict {
+                    cob_id,
+                    user,
+                    reason,
+                });
+            }
+        }
+        conflicts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::TopologyPdo;
+
+    fn pdo(cob: u32, enabled: bool) -> PdoConfig {
+        PdoConfig {
+            cob,
+            enabled,
+            mappings: Vec::new(),
+            transmission_type: 254,
+        }
+    }
+
+    fn tpdo(node_id: u8, pdo: usize) -> CobIdUser {
+        CobIdUser::Tpdo(PdoEndpoint { node_id, pdo })
+    }
+
+    fn node(node_id: u8, tpdos: Vec<PdoConfig>, rpdos: Vec<PdoConfig>) -> TopologyNode {
+        let pdos = |configs: Vec<PdoConfig>| {
+            configs
+                .into_iter()
+                .enumerate()
+                .map(|(pdo, config)| TopologyPdo { pdo, config })
+                .collect()
+        };
+        TopologyNode {
+            node_id,
+            identity: None,
+            device_name: None,
+            tpdos: pdos(tpdos),
+            rpdos: pdos(rpdos),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_allocate() {
+        let registry = CobIdRegistry::new();
+        registry.record_node(&node(
+            1,
+            vec![pdo(0x181, true), pdo(0x281, false)],
+            vec![pdo(0x201, false)],
+        ));
+        registry.record_frame(CanId::std(0x182), Instant::now());
+
+        assert!(!registry.is_free(0x181, &[]));
+        assert!(registry.is_free(0x181, &[tpdo(1, 0)]));
+        // Disabled PDOs still hold their COB-ID
+        assert!(!registry.is_free(0x281, &[]));
+        assert!(!registry.is_free(0x182, &[]));
+        assert!(!registry.is_free(0x701, &[]));
+        assert_eq!(vec![tpdo(1, 1)], registry.users(0x281));
+
+        assert_eq!(Some(0x183), registry.allocate(None, &[]));
+        assert_eq!(Some(0x281), registry.allocate(Some(0x281), &[tpdo(1, 1)]));
+        assert_eq!(Some(0x183), registry.allocate(Some(0x281), &[]));
+
+        registry.forget_node(1);
+        assert!(registry.users(0x281).is_empty());
+        assert_eq!(Some(0x181), registry.allocate(None, &[]));
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let registry = CobIdRegistry::new();
+        registry.record_node(&node(1, vec![pdo(0x181, true), pdo(0x281, true)], vec![]));
+        registry.record_node(&node(2, vec![pdo(0x182, true)], vec![]));
+        registry.record_frame(CanId::std(0x190), Instant::now());
+        registry.record_frame(CanId::std(0x182), Instant::now());
+
+        let config = NodeConfig::load_from_str(
+            r#"
+            [tpdo.0]
+            enabled = true
+            cob = 0x181
+            transmission_type = 254
+            mappings = []
+
+            [tpdo.1]
+            enabled = true
+            cob = 0x182
+            transmission_type = 254
+            mappings = []
+
+            [tpdo.2]
+            enabled = true
+            cob = 0x190
+            transmission_type = 254
+            mappings = []
+
+            [tpdo.3]
+            enabled = true
+            cob = 0x190
+            transmission_type = 254
+            mappings = []
+
+            [tpdo.4]
+            enabled = true
+            cob = 0x701
+            transmission_type = 254
+            mappings = []
+
+            [tpdo.5]
+            enabled = false
+            cob = 0x182
+            transmission_type = 254
+            mappings = []
+        "#,
+        )
+        .unwrap();
+        // The frames on 0x182 are sent by node 2's TPDO 0, so moving the COB-ID to TPDO 1 is not a
+        // conflict
+        let conflicts = registry.conflicts(2, &config);
+        let conflict = |pdo, reason| CobIdConflict {
+            cob_id: config.tpdos()[&pdo].cob,
+            user: tpdo(2, pdo),
+            reason,
+        };
+        assert_eq!(
+            vec![
+                conflict(
+                    0,
+                    ConflictReason::Produced(PdoEndpoint { node_id: 1, pdo: 0 })
+                ),
+                conflict(2, ConflictReason::Observed),
+                conflict(
+                    3,
+                    ConflictReason::Produced(PdoEndpoint { node_id: 2, pdo: 2 })
+                ),
+                conflict(4, ConflictReason::Reserved),
+            ],
+            conflicts
+        );
+        assert_eq!(
+            "TPDO 0 of node 2 uses COB-ID 0x181, which is also sent by TPDO 0 of node 1",
+            conflicts[0].to_string()
+        );
+    }
+}
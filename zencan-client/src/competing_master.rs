@@ -0,0 +1,162 @@
+//! Detection of another master on the bus
+//!
+//! A CANopen network is expected to have a single NMT master, and an SDO server can only talk to
+//! one client at a time. When two configuration tools run at once, their SDO transfers to the same
+//! node interleave, and are silently corrupted.
+//!
+//! A [`BusManager`](crate::BusManager) watches for frames on the COB-IDs which only a master
+//! sends: NMT commands, LSS requests, and requests to the default SDO servers. Since the manager's
+//! own transmissions are recognized and dropped, such a frame means another master is active. What
+//! happens then is set by the [`MasterPolicy`], with
+//! [`set_master_policy`](crate::BusManager::set_master_policy):
+//!
+//! - In all cases, a [`BusEvent::CompetingMaster`](crate::BusEvent::CompetingMaster) is sent to
+//!   all [`BusEvents`](crate::BusEvents) receivers when another master is first seen, or is seen
+//!   again after being quiet for [`MASTER_TIMEOUT`].
+//! - With [`MasterPolicy::Warn`], the default, a warning is also logged.
+//! - With [`MasterPolicy::Yield`], the manager also stops sending NMT commands and SDO requests
+//!   while the other master is active. SDO requests fail with
+//!   [`SdoClientError::CompetingMaster`](crate::SdoClientError::CompetingMaster).
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use zencan_common::messages::{CanId, LSS_REQ_ID, NMT_CMD_ID};
+
+use crate::bus_silence::BusEvent;
+
+/// How long after its last frame another master is considered to still be active
+pub const MASTER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What a [`BusManager`](crate::BusManager) does when another master is active on the bus
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MasterPolicy {
+    /// Carry on, sending only the event
+    Continue,
+    /// Carry on, and log a warning
+    #[default]
+    Warn,
+    /// Log a warning, and send no NMT commands or SDO requests until the other master has been
+    /// quiet for [`MASTER_TIMEOUT`]
+    Yield,
+}
+
+/// Returns true if frames with the given ID are only sent by a master
+fn is_master_cob_id(id: CanId) -> bool {
+    if id == NMT_CMD_ID || id == LSS_REQ_ID {
+        return true;
+    }
+    // Requests to the default SDO servers
+    matches!(id, CanId::Std(0x601..=0x67F))
+}
+
+#[derive(Debug, Default)]
+struct MasterState {
+    policy: MasterPolicy,
+    last_frame: Option<(Instant, CanId)>,
+}
+
+/// Tracks frames received from other masters
+///
+/// Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct MasterActivity {
+    state: Arc<Mutex<MasterState>>,
+}
+
+impl MasterActivity {
+    /// Create a tracker, with the default policy and no other master seen
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the policy for when another master is active
+    pub fn set_policy(&self, policy: MasterPolicy) {
+        self.state.lock().unwrap().policy = policy;
+    }
+
+    /// Get the policy for when another master is active
+    pub fn policy(&self) -> MasterPolicy {
+        self.state.lock().unwrap().policy
+    }
+
+    /// Record a frame received from the bus at `at`, which was not sent by us
+    ///
+    /// Returns the event to send if the frame shows that another master has become active.
+    pub fn record_frame(&self, id: CanId, at: Instant) -> Option<BusEvent> {
+        if !is_master_cob_id(id) {
+            return None;
+        }
+        let mut state = self.state.lock().unwrap();
+        let was_active = state
+            .last_frame
+            .is_some_and(|(last, _)| at.saturating_duration_since(last) < MASTER_TIMEOUT);
+        if state.last_frame.is_none_or(|(last, _)| at >= last) {
+            state.last_frame = Some((at, id));
+        }
+        if was_active {
+            return None;
+        }
+        if state.policy != MasterPolicy::Continue {
+            log::warn!("Another master is active on the bus (frame on {id:?})");
+        }
+        Some(BusEvent::CompetingMaster { cob_id: id })
+    }
+
+    /// Get the COB-ID of the last frame from another master, if one was received within
+    /// [`MASTER_TIMEOUT`]
+    pub fn active(&self) -> Option<CanId> {
+        let state = self.state.lock().unwrap();
+        state
+            .last_frame
+            .filter(|(last, _)| last.elapsed() < MASTER_TIMEOUT)
+            .map(|(_, id)| id)
+    }
+
+    /// Get the COB-ID of the last frame from another master, if the policy is to yield to it and
+    /// it is active
+    pub fn yield_to(&self) -> Option<CanId> {
+        if self.policy() == MasterPolicy::Yield {
+            self.active()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_master_activity() {
+        let activity = MasterActivity::new();
+        let now = Instant::now();
+        assert_eq!(MasterPolicy::Warn, activity.policy());
+
+        // Frames sent by nodes are ignored
+        assert_eq!(None, activity.record_frame(CanId::std(0x581), now));
+        assert_eq!(None, activity.record_frame(CanId::std(0x701), now));
+        assert_eq!(None, activity.active());
+
+        let sdo_req = CanId::std(0x605);
+        assert_eq!(
+            Some(BusEvent::CompetingMaster { cob_id: sdo_req }),
+            activity.record_frame(sdo_req, now)
+        );
+        assert_eq!(Some(sdo_req), activity.active());
+        // Only the first frame of a burst produces an event
+        assert_eq!(None, activity.record_frame(NMT_CMD_ID, now));
+        assert_eq!(None, activity.yield_to());
+        activity.set_policy(MasterPolicy::Yield);
+        assert_eq!(Some(NMT_CMD_ID), activity.yield_to());
+
+        // After a quiet period, another event is produced
+        let later = now + MASTER_TIMEOUT * 2;
+        assert_eq!(
+            Some(BusEvent::CompetingMaster { cob_id: LSS_REQ_ID }),
+            activity.record_frame(LSS_REQ_ID, later)
+        );
+    }
+}
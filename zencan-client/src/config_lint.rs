@@ -0,0 +1,1005 @@
+//! Checking node configuration files, and reporting every problem found
+//!
+//! [`NodeConfig::load_from_str`](crate::NodeConfig::load_from_str) stops at the first error in a
+//! file. [`NodeConfig::validate`](crate::NodeConfig::validate) instead checks the whole file, and
+//! returns a [`Diagnostic`] for each problem, with the line and column at which it was found, so
+//! that all of the mistakes in a file can be fixed at once. This is what `zencan-cli config lint`
+//! runs.
+//!
+//! The checks include:
+//!
+//! - TOML syntax
+//! - Unknown keys, which are errors in [strict](ParseMode::Strict) mode, and warnings in
+//!   [lenient](ParseMode::Lenient) mode
+//! - Missing fields, values of the wrong type, and integers out of range
+//! - PDO mappings with a size of zero, or which map more than 64 bits
+//! - Reserved PDO transmission types, and enabled PDOs which share a COB-ID
+//! - Store values which cannot be converted to their type
+//! - Stores which write an object that is also set by a dedicated key, such as
+//!   `heartbeat_producer_time` or a PDO
+//!
+//! A configuration which loads with only warnings can be loaded with
+//! [`NodeConfig::load_from_str_with`](crate::NodeConfig::load_from_str_with), which returns the
+//! warnings alongside it.
+use std::collections::HashMap;
+
+use crate::node_configuration::{toml_to_value, StoreType};
+
+/// How unknown keys are treated when parsing a configuration
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Unknown keys are errors
+    #[default]
+    Strict,
+    /// Unknown keys are reported as warnings, and otherwise ignored
+    ///
+    /// This allows loading a file which contains keys for a newer version, or for other tools.
+    Lenient,
+}
+
+/// The severity of a [`Diagnostic`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The configuration can be loaded, but may not do what was intended
+    Warning,
+    /// The configuration cannot be loaded
+    Error,
+}
+
+impl core::fmt::Display for Severity {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found in a configuration file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Whether the problem prevents the configuration from being loaded
+    pub severity: Severity,
+    /// The key at which the problem was found, e.g. `tpdo.0.mappings[1].size`
+    ///
+    /// Empty for problems with the file as a whole, such as a syntax error.
+    pub path: String,
+    /// A description of the problem
+    pub message: String,
+    /// The line of the file at which the problem was found, starting from 1
+    pub line: Option<usize>,
+    /// The column of the file at which the problem was found, starting from 1
+    pub column: Option<usize>,
+}
+
+impl Diagnostic {
+    /// Returns true if this is an error, rather than a warning
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl core::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "{line}:{column}: ")?,
+            (Some(line), None) => write!(f, "{line}: ")?,
+            _ => (),
+        }
+        write!(f, "{}: ", self.severity)?;
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// The keys allowed at the top level of a configuration
+const CONFIG_KEYS: &[&str] = &[
+    "tpdo",
+    "rpdo",
+    "store",
+    "writes",
+    "heartbeat_producer_time",
+    "emcy_inhibit_time",
+    "sync",
+    "pacing",
+    "verify_configuration",
+];
+
+/// Objects which are also set by a dedicated key
+const SETTING_OBJECTS: &[(u16, &str)] = &[
+    (0x1005, "sync.cob_id"),
+    (0x1006, "sync.cycle_period"),
+    (0x1007, "sync.window_length"),
+    (0x1015, "emcy_inhibit_time"),
+    (0x1017, "heartbeat_producer_time"),
+    (0x1019, "sync.counter_overflow"),
+    (0x1020, "verify_configuration"),
+];
+
+/// The largest valid SYNC counter overflow value
+const MAX_COUNTER_OVERFLOW: i64 = 240;
+
+/// Milliseconds in a day, the limit of the time of a configuration stamp
+const MS_PER_DAY: i64 = 86_400_000;
+
+/// Check a configuration, returning the parsed TOML with any unknown keys removed, and the
+/// problems found sorted by their position in the file
+///
+/// The table is only returned if the file is valid TOML.
+pub(crate) fn lint(s: &str, mode: ParseMode) -> (Option<toml::Table>, Vec<Diagnostic>) {
+    let mut config: toml::Table = match toml::from_str(s) {
+        Ok(config) => config,
+        Err(e) => {
+            let (line, column) = match e.span() {
+                Some(span) => {
+                    let (line, column) = line_column(s, span.start);
+                    (Some(line), Some(column))
+                }
+                None => (None, None),
+            };
+            let diagnostic = Diagnostic {
+                severity: Severity::Error,
+                path: String::new(),
+                message: e.message().trim().to_string(),
+                line,
+                column,
+            };
+            return (None, vec![diagnostic]);
+        }
+    };
+
+    let mut linter = Linter {
+        mode,
+        locations: Locations::scan(s),
+        diagnostics: Vec::new(),
+    };
+    linter.lint_config(&mut config);
+    let mut diagnostics = linter.diagnostics;
+    // Problems without a location go last
+    diagnostics.sort_by_key(|d| (d.line.unwrap_or(usize::MAX), d.column.unwrap_or(usize::MAX)));
+    (Some(config), diagnostics)
+}
+
+/// Get the line and column of a byte offset in `s`, both starting from 1
+fn line_column(s: &str, offset: usize) -> (usize, usize) {
+    let before = &s[..offset.min(s.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|pos| pos + 1).unwrap_or(0);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// Append a key to a path
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+/// Collects the diagnostics for a configuration
+struct Linter {
+    mode: ParseMode,
+    locations: Locations,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter {
+    fn report(&mut self, severity: Severity, path: &str, message: impl Into<String>) {
+        let location = self.locations.find(path);
+        self.diagnostics.push(Diagnostic {
+            severity,
+            path: path.to_string(),
+            message: message.into(),
+            line: location.map(|(line, _)| line),
+            column: location.map(|(_, column)| column),
+        });
+    }
+
+    fn error(&mut self, path: &str, message: impl Into<String>) {
+        self.report(Severity::Error, path, message);
+    }
+
+    fn warning(&mut self, path: &str, message: impl Into<String>) {
+        self.report(Severity::Warning, path, message);
+    }
+
+    /// Report and remove the keys of `table` which are not in `known`
+    fn check_keys(&mut self, path: &str, table: &mut toml::Table, known: &[&str]) {
+        let unknown: Vec<String> = table
+            .keys()
+            .filter(|key| !known.contains(&key.as_str()))
+            .cloned()
+            .collect();
+        let severity = match self.mode {
+            ParseMode::Strict => Severity::Error,
+            ParseMode::Lenient => Severity::Warning,
+        };
+        for key in unknown {
+            self.report(
+                severity,
+                &join(path, &key),
+                format!("unknown key '{key}', expected one of {}", known.join(", ")),
+            );
+            table.remove(&key);
+        }
+    }
+
+    fn table<'v>(&mut self, path: &str, value: &'v mut toml::Value) -> Option<&'v mut toml::Table> {
+        if !value.is_table() {
+            self.error(
+                path,
+                format!("expected a table, found {}", value.type_str()),
+            );
+        }
+        value.as_table_mut()
+    }
+
+    /// Get a field which must be present
+    fn required<'v>(
+        &mut self,
+        path: &str,
+        table: &'v toml::Table,
+        key: &str,
+    ) -> Option<&'v toml::Value> {
+        let value = table.get(key);
+        if value.is_none() {
+            self.error(path, format!("missing field '{key}'"));
+        }
+        value
+    }
+
+    fn integer(&mut self, path: &str, value: &toml::Value, min: i64, max: i64) -> Option<i64> {
+        match value {
+            toml::Value::Integer(i) if (min..=max).contains(i) => Some(*i),
+            toml::Value::Integer(i) => {
+                self.error(
+                    path,
+                    format!("{i} is out of range, expected an integer from {min} to {max}"),
+                );
+                None
+            }
+            other => {
+                self.error(
+                    path,
+                    format!("expected an integer, found {}", other.type_str()),
+                );
+                None
+            }
+        }
+    }
+
+    fn bool(&mut self, path: &str, value: &toml::Value) -> Option<bool> {
+        if !value.is_bool() {
+            self.error(
+                path,
+                format!("expected a boolean, found {}", value.type_str()),
+            );
+        }
+        value.as_bool()
+    }
+
+    /// Check an integer field, if it is present
+    fn optional_integer(
+        &mut self,
+        path: &str,
+        table: &toml::Table,
+        key: &str,
+        max: i64,
+    ) -> Option<i64> {
+        let value = table.get(key)?;
+        self.integer(&join(path, key), value, 0, max)
+    }
+
+    /// Check an integer field which must be present
+    fn required_integer(
+        &mut self,
+        path: &str,
+        table: &toml::Table,
+        key: &str,
+        max: i64,
+    ) -> Option<i64> {
+        let value = self.required(path, table, key)?;
+        self.integer(&join(path, key), value, 0, max)
+    }
+
+    fn lint_config(&mut self, config: &mut toml::Table) {
+        self.check_keys("", config, CONFIG_KEYS);
+
+        self.optional_integer("", config, "heartbeat_producer_time", u16::MAX as i64);
+        self.optional_integer("", config, "emcy_inhibit_time", u16::MAX as i64);
+        if let Some(value) = config.get_mut("sync") {
+            self.lint_sync(value);
+        }
+        if let Some(value) = config.get_mut("pacing") {
+            self.lint_pacing(value);
+        }
+        if let Some(value) = config.get_mut("verify_configuration") {
+            self.lint_stamp(value);
+        }
+
+        let mut cob_ids = Vec::new();
+        for kind in ["tpdo", "rpdo"] {
+            if let Some(value) = config.get_mut(kind) {
+                cob_ids.extend(self.lint_pdos(kind, value));
+            }
+        }
+        // A node cannot tell apart two PDOs on the same COB-ID, whether they are sent or received
+        for (i, (path, cob)) in cob_ids.iter().enumerate() {
+            if let Some((other, _)) = cob_ids[..i].iter().find(|(_, c)| c == cob) {
+                self.error(path, format!("COB-ID 0x{cob:X} is also used by {other}"));
+            }
+        }
+
+        let mut objects = Vec::new();
+        for key in ["store", "writes"] {
+            if let Some(value) = config.get_mut(key) {
+                objects.extend(self.lint_stores(key, value));
+            }
+        }
+        for (i, (path, index, sub)) in objects.iter().enumerate() {
+            if let Some(setting) = setting_for_object(config, *index) {
+                self.error(
+                    path,
+                    format!("0x{index:X} is also set by {setting}, which is written first"),
+                );
+            }
+            if let Some((other, _, _)) = objects[..i]
+                .iter()
+                .find(|(_, other_index, other_sub)| (other_index, other_sub) == (index, sub))
+            {
+                self.warning(
+                    path,
+                    format!("0x{index:X}.{sub} is also written by {other}, and is overwritten"),
+                );
+            }
+        }
+    }
+
+    fn lint_sync(&mut self, value: &mut toml::Value) {
+        let path = "sync";
+        let Some(table) = self.table(path, value) else {
+            return;
+        };
+        self.check_keys(
+            path,
+            table,
+            &[
+                "cob_id",
+                "cycle_period",
+                "window_length",
+                "counter_overflow",
+            ],
+        );
+        self.optional_integer(path, table, "cob_id", u32::MAX as i64);
+        self.optional_integer(path, table, "cycle_period", u32::MAX as i64);
+        self.optional_integer(path, table, "window_length", u32::MAX as i64);
+        if let Some(overflow) =
+            self.optional_integer(path, table, "counter_overflow", u8::MAX as i64)
+        {
+            if overflow == 1 || overflow > MAX_COUNTER_OVERFLOW {
+                self.error(
+                    &join(path, "counter_overflow"),
+                    format!("counter overflow {overflow} is reserved, it must be 0, or 2 to 240"),
+                );
+            }
+        }
+    }
+
+    fn lint_pacing(&mut self, value: &mut toml::Value) {
+        let path = "pacing";
+        let Some(table) = self.table(path, value) else {
+            return;
+        };
+        self.check_keys(path, table, &["max_frames_per_sec", "min_gap_us"]);
+        self.optional_integer(path, table, "max_frames_per_sec", u32::MAX as i64);
+        self.optional_integer(path, table, "min_gap_us", u32::MAX as i64);
+    }
+
+    fn lint_stamp(&mut self, value: &mut toml::Value) {
+        let path = "verify_configuration";
+        let Some(table) = self.table(path, value) else {
+            return;
+        };
+        self.check_keys(path, table, &["date", "time"]);
+        self.required_integer(path, table, "date", u32::MAX as i64);
+        self.required_integer(path, table, "time", MS_PER_DAY - 1);
+    }
+
+    /// Check a map of PDOs, returning the path and COB-ID of each enabled PDO
+    fn lint_pdos(&mut self, kind: &str, value: &mut toml::Value) -> Vec<(String, u32)> {
+        let Some(table) = self.table(kind, value) else {
+            return Vec::new();
+        };
+        let mut numbers: HashMap<usize, String> = HashMap::new();
+        let mut cob_ids = Vec::new();
+        for (key, pdo) in table.iter_mut() {
+            let path = join(kind, key);
+            match key.parse::<usize>() {
+                Ok(number) => {
+                    // Multiple keys could parse to the same number, e.g. "0" and "00"
+                    if let Some(other) = numbers.insert(number, key.clone()) {
+                        self.error(
+                            &path,
+                            format!("duplicate {kind} number {number}, also given as '{other}'"),
+                        );
+                    }
+                }
+                Err(_) => self.error(
+                    &path,
+                    format!("'{key}' is not a valid PDO number, expected a non-negative integer"),
+                ),
+            }
+            if let Some(cob) = self.lint_pdo(&path, pdo) {
+                cob_ids.push((path, cob));
+            }
+        }
+        cob_ids
+    }
+
+    /// Check a PDO, returning its COB-ID if it is enabled
+    fn lint_pdo(&mut self, path: &str, value: &mut toml::Value) -> Option<u32> {
+        let table = self.table(path, value)?;
+        self.check_keys(
+            path,
+            table,
+            &["cob", "enabled", "mappings", "transmission_type"],
+        );
+        let cob = self.required_integer(path, table, "cob", u32::MAX as i64);
+        let enabled = self
+            .required(path, table, "enabled")
+            .and_then(|value| self.bool(&join(path, "enabled"), value));
+        if let Some(ty) = self.required_integer(path, table, "transmission_type", u8::MAX as i64) {
+            if (241..=251).contains(&ty) {
+                self.error(
+                    &join(path, "transmission_type"),
+                    format!("transmission type {ty} is reserved"),
+                );
+            }
+        }
+
+        if let Some(value) = table.get_mut("mappings") {
+            let mappings_path = join(path, "mappings");
+            match value.as_array_mut() {
+                Some(mappings) => {
+                    if mappings.is_empty() && enabled == Some(true) {
+                        self.warning(&mappings_path, "the PDO is enabled, but maps no objects");
+                    }
+                    let mut total = 0;
+                    for (i, mapping) in mappings.iter_mut().enumerate() {
+                        let mapping_path = format!("{mappings_path}[{i}]");
+                        total += self.lint_mapping(&mapping_path, mapping).unwrap_or(0);
+                    }
+                    if total > 64 {
+                        self.error(
+                            &mappings_path,
+                            format!("maps {total} bits, but a PDO can hold at most 64"),
+                        );
+                    }
+                }
+                None => self.error(
+                    &mappings_path,
+                    format!("expected an array, found {}", value.type_str()),
+                ),
+            }
+        } else {
+            self.error(path, "missing field 'mappings'");
+        }
+
+        match (enabled, cob) {
+            (Some(true), Some(cob)) => Some(cob as u32),
+            _ => None,
+        }
+    }
+
+    /// Check a PDO mapping, returning its size in bits
+    fn lint_mapping(&mut self, path: &str, value: &mut toml::Value) -> Option<i64> {
+        let table = self.table(path, value)?;
+        self.check_keys(path, table, &["index", "sub", "size"]);
+        self.required_integer(path, table, "index", u16::MAX as i64);
+        self.required_integer(path, table, "sub", u8::MAX as i64);
+        let size = self.required_integer(path, table, "size", u8::MAX as i64)?;
+        if size == 0 {
+            self.error(&join(path, "size"), "a mapping must have a non-zero size");
+        }
+        Some(size)
+    }
+
+    /// Check a list of stores, returning the path, index and sub index of each
+    fn lint_stores(&mut self, key: &str, value: &mut toml::Value) -> Vec<(String, u16, u8)> {
+        let Some(stores) = value.as_array_mut() else {
+            self.error(
+                key,
+                format!("expected an array of tables, found {}", value.type_str()),
+            );
+            return Vec::new();
+        };
+        let mut objects = Vec::new();
+        for (i, store) in stores.iter_mut().enumerate() {
+            let path = format!("{key}[{i}]");
+            if let Some(object) = self.lint_store(&path, store) {
+                objects.push((path, object.0, object.1));
+            }
+        }
+        objects
+    }
+
+    /// Check a store, returning its index and sub index
+    fn lint_store(&mut self, path: &str, value: &mut toml::Value) -> Option<(u16, u8)> {
+        let table = self.table(path, value)?;
+        self.check_keys(path, table, &["index", "sub", "value", "type", "verify"]);
+        let index = self.required_integer(path, table, "index", u16::MAX as i64);
+        let sub = self.required_integer(path, table, "sub", u8::MAX as i64);
+        if let Some(verify) = table.get("verify") {
+            self.bool(&join(path, "verify"), verify);
+        }
+        let ty = self.required(path, table, "type").and_then(|ty| {
+            match ty.clone().try_into::<StoreType>() {
+                Ok(ty) => Some(ty),
+                Err(e) => {
+                    self.error(&join(path, "type"), e.message().trim());
+                    None
+                }
+            }
+        });
+        let value = self.required(path, table, "value");
+        if let (Some(ty), Some(value)) = (ty, value) {
+            if let Err(e) = toml_to_value(ty.into(), value) {
+                self.error(&join(path, "value"), e.to_string());
+            }
+        }
+        Some((index? as u16, sub? as u8))
+    }
+}
+
+/// Get the key which also sets an object, if it is present in the configuration
+fn setting_for_object(config: &toml::Table, index: u16) -> Option<String> {
+    let pdo = |kind: &str, number: u16| {
+        let configured = config
+            .get(kind)
+            .and_then(|pdos| pdos.as_table())
+            .is_some_and(|pdos| {
+                pdos.keys()
+                    .any(|key| key.parse::<u16>().ok() == Some(number))
+            });
+        configured.then(|| format!("{kind}.{number}"))
+    };
+    match index {
+        0x1400..=0x15FF => pdo("rpdo", index - 0x1400),
+        0x1600..=0x17FF => pdo("rpdo", index - 0x1600),
+        0x1800..=0x19FF => pdo("tpdo", index - 0x1800),
+        0x1A00..=0x1BFF => pdo("tpdo", index - 0x1A00),
+        _ => {
+            let (_, setting) = SETTING_OBJECTS.iter().find(|(i, _)| *i == index)?;
+            let mut keys = setting.split('.');
+            let mut value = config.get(keys.next()?)?;
+            for key in keys {
+                value = value.get(key)?;
+            }
+            Some(setting.to_string())
+        }
+    }
+}
+
+/// The line and column of the keys in a TOML document, by path
+#[derive(Debug, Default)]
+struct Locations(HashMap<String, (usize, usize)>);
+
+impl Locations {
+    /// Find the keys in a document
+    ///
+    /// The document must already have been parsed successfully.
+    fn scan(s: &str) -> Self {
+        let mut scanner = Scanner::new(s);
+        let mut locations = Locations::default();
+        // The path of the current table, set by the most recent header
+        let mut table = String::new();
+        // The number of elements of each array of tables seen so far
+        let mut array_lengths: HashMap<String, usize> = HashMap::new();
+
+        loop {
+            scanner.skip_whitespace();
+            let Some(c) = scanner.peek() else {
+                break;
+            };
+            let start = scanner.location();
+            if c == '[' {
+                scanner.next();
+                let is_array = scanner.eat('[');
+                let keys = scanner.key();
+                scanner.skip_line();
+
+                let mut path = String::new();
+                for key in &keys {
+                    path = join(&path, key);
+                    locations.insert(&path, start);
+                }
+                if is_array {
+                    let length = array_lengths.entry(path.clone()).or_default();
+                    path = format!("{path}[{length}]");
+                    *length += 1;
+                    locations.insert(&path, start);
+                }
+                table = path;
+            } else {
+                let keys = scanner.key();
+                if keys.is_empty() || !scanner.eat('=') {
+                    scanner.skip_line();
+                    continue;
+                }
+                let path = join(&table, &keys.join("."));
+                locations.insert(&path, start);
+                scanner.value(&path, &mut locations);
+            }
+        }
+        locations
+    }
+
+    fn insert(&mut self, path: &str, location: (usize, usize)) {
+        self.0.entry(path.to_string()).or_insert(location);
+    }
+
+    /// Find the location of a path, or of its closest parent which has one
+    fn find(&self, mut path: &str) -> Option<(usize, usize)> {
+        loop {
+            if let Some(location) = self.0.get(path) {
+                return Some(*location);
+            }
+            let parent = path.rfind(['.', '['])?;
+            path = &path[..parent];
+        }
+    }
+}
+
+/// Steps through a TOML document, keeping track of the line and column
+struct Scanner {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Scanner {
+    fn new(s: &str) -> Self {
+        Self {
+            chars: s.chars().collect(),
+            pos: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn location(&self) -> (usize, usize) {
+        (self.line, self.column)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_spaces();
+        let found = self.peek() == Some(c);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    /// Skip spaces and tabs on the current line
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.next();
+        }
+    }
+
+    /// Skip whitespace, newlines and comments
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r' | '\n') => {
+                    self.next();
+                }
+                Some('#') => self.skip_line(),
+                _ => break,
+            }
+        }
+    }
+
+    /// Skip to the start of the next line
+    fn skip_line(&mut self) {
+        while let Some(c) = self.next() {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    /// Read a dotted key, returning its parts
+    fn key(&mut self) -> Vec<String> {
+        let mut keys = Vec::new();
+        loop {
+            self.skip_spaces();
+            let key = match self.peek() {
+                Some('"' | '\'') => self.string(),
+                _ => {
+                    let mut key = String::new();
+                    while let Some(c) = self
+                        .peek()
+                        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+                    {
+                        key.push(c);
+                        self.next();
+                    }
+                    key
+                }
+            };
+            keys.push(key);
+            if !self.eat('.') {
+                break;
+            }
+        }
+        if keys.len() == 1 && keys[0].is_empty() {
+            keys.clear();
+        }
+        keys
+    }
+
+    /// Read a quoted string, returning its contents without processing escapes
+    fn string(&mut self) -> String {
+        let Some(quote) = self.next() else {
+            return String::new();
+        };
+        let triple: String = [quote; 3].iter().collect();
+        let multiline = self.starts_with(&triple[1..]);
+        if multiline {
+            self.next();
+            self.next();
+        }
+        let mut contents = String::new();
+        loop {
+            if multiline && self.starts_with(&triple) {
+                self.next();
+                self.next();
+                self.next();
+                break;
+            }
+            match self.next() {
+                None => break,
+                Some(c) if c == quote && !multiline => break,
+                Some('\\') if quote == '"' => {
+                    contents.push('\\');
+                    if let Some(c) = self.next() {
+                        contents.push(c);
+                    }
+                }
+                Some(c) => contents.push(c),
+            }
+        }
+        contents
+    }
+
+    /// Read a value, recording the location of the elements of any arrays and inline tables in it
+    fn value(&mut self, path: &str, locations: &mut Locations) {
+        self.skip_spaces();
+        match self.peek() {
+            Some('[') => {
+                self.next();
+                let mut index = 0;
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        None => break,
+                        Some(']') => {
+                            self.next();
+                            break;
+                        }
+                        Some(',') => {
+                            self.next();
+                        }
+                        Some(_) => {
+                            let element = format!("{path}[{index}]");
+                            locations.insert(&element, self.location());
+                            let pos = self.pos;
+                            self.value(&element, locations);
+                            if self.pos == pos {
+                                // Never get stuck on something unexpected
+                                self.next();
+                            }
+                            index += 1;
+                        }
+                    }
+                }
+            }
+            Some('{') => {
+                self.next();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        None => break,
+                        Some('}') => {
+                            self.next();
+                            break;
+                        }
+                        Some(',') => {
+                            self.next();
+                        }
+                        Some(_) => {
+                            let start = self.location();
+                            let keys = self.key();
+                            if keys.is_empty() || !self.eat('=') {
+                                self.next();
+                                continue;
+                            }
+                            let key = join(path, &keys.join("."));
+                            locations.insert(&key, start);
+                            self.value(&key, locations);
+                        }
+                    }
+                }
+            }
+            Some('"' | '\'') => {
+                self.string();
+            }
+            _ => {
+                while let Some(c) = self.peek() {
+                    if matches!(c, ',' | ']' | '}' | '\n' | '#') {
+                        break;
+                    }
+                    self.next();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(diagnostics: &'a [Diagnostic], path: &str) -> &'a Diagnostic {
+        diagnostics
+            .iter()
+            .find(|d| d.path == path)
+            .unwrap_or_else(|| panic!("No diagnostic for {path} in {diagnostics:#?}"))
+    }
+
+    #[test]
+    fn test_collects_all_errors() {
+        let s = r#"
+heartbeat_producer_time = 70000
+colour = "red"
+
+[tpdo.0]
+enabled = true
+cob = 0x181
+transmission_type = 245
+mappings = [
+    { index=0x2000, sub=1, size=32 },
+    { index=0x2000, sub=2, size=0, scale=2 },
+    { index=0x2000, sub=3, size=40 },
+]
+
+[tpdo.1]
+enabled = true
+cob = 0x181
+mappings = []
+
+[[store]]
+index = 0x1017
+sub = 0
+type = "u8"
+value = 256
+"#;
+        let (_, diagnostics) = lint(s, ParseMode::Strict);
+
+        let d = find(&diagnostics, "heartbeat_producer_time");
+        assert!(d.is_error());
+        assert_eq!((Some(2), Some(1)), (d.line, d.column));
+
+        let d = find(&diagnostics, "colour");
+        assert!(d.is_error());
+        assert_eq!(Some(3), d.line);
+
+        let d = find(&diagnostics, "tpdo.0.transmission_type");
+        assert_eq!(Some(8), d.line);
+
+        let d = find(&diagnostics, "tpdo.0.mappings[1].size");
+        assert_eq!((Some(11), Some(28)), (d.line, d.column));
+        let d = find(&diagnostics, "tpdo.0.mappings[1].scale");
+        assert_eq!((Some(11), Some(36)), (d.line, d.column));
+        let d = find(&diagnostics, "tpdo.0.mappings");
+        assert!(d.message.contains("72 bits"));
+
+        // tpdo.1 is missing its transmission type, shares a COB-ID, and maps nothing
+        let tpdo1: Vec<_> = diagnostics.iter().filter(|d| d.path == "tpdo.1").collect();
+        assert_eq!(2, tpdo1.len());
+        assert!(tpdo1.iter().all(|d| d.line == Some(15)));
+        let d = find(&diagnostics, "tpdo.1.mappings");
+        assert_eq!(Severity::Warning, d.severity);
+
+        let d = find(&diagnostics, "store[0].value");
+        assert_eq!(Some(24), d.line);
+        let d = find(&diagnostics, "store[0]");
+        assert!(d.message.contains("heartbeat_producer_time"));
+        assert_eq!(Some(20), d.line);
+
+        // Sorted by position
+        let lines: Vec<_> = diagnostics.iter().map(|d| d.line).collect();
+        let mut sorted = lines.clone();
+        sorted.sort();
+        assert_eq!(sorted, lines);
+    }
+
+    #[test]
+    fn test_lenient_unknown_keys() {
+        let s = r#"
+future_option = 1
+
+[sync]
+cycle_period = 1000
+window = 10
+"#;
+        let (_, diagnostics) = lint(s, ParseMode::Strict);
+        assert_eq!(2, diagnostics.len());
+        assert!(diagnostics.iter().all(Diagnostic::is_error));
+
+        let (table, diagnostics) = lint(s, ParseMode::Lenient);
+        assert_eq!(2, diagnostics.len());
+        assert!(diagnostics.iter().all(|d| !d.is_error()));
+        assert_eq!(Some(6), find(&diagnostics, "sync.window").line);
+        let table = table.unwrap();
+        assert!(!table.contains_key("future_option"));
+        assert!(!table["sync"].as_table().unwrap().contains_key("window"));
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let s = "heartbeat_producer_time = 100\nemcy_inhibit_time = = 2\n";
+        let (table, diagnostics) = lint(s, ParseMode::Strict);
+        assert!(table.is_none());
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(Some(2), diagnostics[0].line);
+    }
+
+    #[test]
+    fn test_locations() {
+        let s = r#"
+top = 1 # comment with 'quote
+
+[[store]]
+value = "a ] string, with { }"
+[[store]]
+  value = [1, 2, [3, 4]]
+"#;
+        let locations = Locations::scan(s);
+        assert_eq!(Some((2, 1)), locations.find("top"));
+        assert_eq!(Some((4, 1)), locations.find("store[0]"));
+        assert_eq!(Some((5, 1)), locations.find("store[0].value"));
+        assert_eq!(Some((7, 3)), locations.find("store[1].value"));
+        assert_eq!(Some((7, 22)), locations.find("store[1].value[2][1]"));
+        // Falls back to the closest parent
+        assert_eq!(Some((7, 3)), locations.find("store[1].value.missing"));
+        assert_eq!(None, locations.find("missing"));
+    }
+}
@@ -0,0 +1,455 @@
+//! Node configuration templates, and fleet files which apply them to many nodes
+//!
+//! A [`NodeConfigTemplate`] is a [`NodeConfig`] TOML file containing `${...}` placeholders, which
+//! are replaced with values for a particular node when the template is rendered. This allows a
+//! group of identical devices, such as several drives on one bus, to share a single configuration
+//! file:
+//!
+//! ```toml
+//! [tpdo.0]
+//! enabled = true
+//! cob = ${0x180 + node_id}
+//! transmission_type = 254
+//! mappings = [{ index=0x2000, sub=1, size=32 }]
+//!
+//! [[store]]
+//! index = 0x2001
+//! sub = 1
+//! type = "u8"
+//! value = ${axis}
+//! ```
+//!
+//! A placeholder contains a variable name, or a sum of variables and integer literals, such as
+//! `${0x180 + node_id}`. A variable is replaced by its value as written, and a sum by its result
+//! in decimal. The `node_id` variable is always defined as the ID of the node being configured.
+//! Write `$$` for a literal `$`. A file without placeholders renders to itself, so any
+//! configuration file can be used as a template.
+//!
+//! A [`Fleet`] file lists the nodes to configure, with the template and variable values for each:
+//!
+//! ```toml
+//! # The default template for nodes which do not specify one
+//! template = "drive.toml"
+//!
+//! # Variables shared by all nodes
+//! [vars]
+//! gear_ratio = 12
+//!
+//! [[node]]
+//! node_id = 3
+//! vars = { axis = 0 }
+//!
+//! [[node]]
+//! node_id = 4
+//! vars = { axis = 1 }
+//! ```
+//!
+//! Template paths are relative to the directory containing the fleet file.
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use snafu::ResultExt;
+
+use crate::config_lint::{Diagnostic, ParseMode};
+use crate::node_configuration::{
+    ConfigError, InvalidExpressionSnafu, InvalidVariableSnafu, IoSnafu, MissingTemplateSnafu,
+    RenderSnafu, TomlDeserializationSnafu, UndefinedVariableSnafu,
+};
+use crate::NodeConfig;
+
+/// A node configuration containing `${...}` placeholders
+///
+/// See the [module docs](crate::config_template) for the placeholder syntax.
+#[derive(Debug, Clone)]
+pub struct NodeConfigTemplate {
+    text: String,
+}
+
+impl NodeConfigTemplate {
+    /// Read a template from a file
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).context(IoSnafu {
+            path: path.to_string_lossy(),
+        })?;
+        Self::load_from_str(&content)
+    }
+
+    /// Read a template from a string
+    ///
+    /// The placeholder syntax is checked, but variables are not resolved until the template is
+    /// rendered.
+    pub fn load_from_str(s: &str) -> Result<Self, ConfigError> {
+        // Substituting a dummy value checks that every placeholder is well formed
+        substitute(s, |_| Some("0".into()))?;
+        Ok(Self {
+            text: s.to_string(),
+        })
+    }
+
+    /// Get the names of the variables used by the template, sorted and without duplicates
+    pub fn variables(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        substitute(&self.text, |name| {
+            names.push(name.to_string());
+            Some("0".into())
+        })
+        .expect("Template was validated when loaded");
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Render the template for a node, and parse the result as a [`NodeConfig`]
+    ///
+    /// The `node_id` variable is set to `node_id`, and overrides any value in `vars`.
+    pub fn render(
+        &self,
+        node_id: u8,
+        vars: &HashMap<String, String>,
+    ) -> Result<NodeConfig, ConfigError> {
+        let text = self.render_text(node_id, vars)?;
+        NodeConfig::load_from_str(&text)
+    }
+
+    /// Render the template for a node, and check the result with [`NodeConfig::validate`]
+    ///
+    /// The line numbers of the diagnostics refer to the template. Columns may be shifted on lines
+    /// containing placeholders.
+    pub fn validate(
+        &self,
+        node_id: u8,
+        vars: &HashMap<String, String>,
+        mode: ParseMode,
+    ) -> Result<Vec<Diagnostic>, ConfigError> {
+        let text = self.render_text(node_id, vars)?;
+        Ok(NodeConfig::validate(&text, mode))
+    }
+
+    fn render_text(
+        &self,
+        node_id: u8,
+        vars: &HashMap<String, String>,
+    ) -> Result<String, ConfigError> {
+        let node_id = node_id.to_string();
+        substitute(&self.text, |name| match name {
+            "node_id" => Some(node_id.clone()),
+            _ => vars.get(name).cloned(),
+        })
+    }
+}
+
+/// Replace the placeholders in `text`, using `lookup` to get the value of each variable
+fn substitute(
+    text: &str,
+    mut lookup: impl FnMut(&str) -> Option<String>,
+) -> Result<String, ConfigError> {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('$') {
+        output.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        if let Some(after) = rest.strip_prefix("$$") {
+            output.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let Some(end) = after.find('}') else {
+                return InvalidExpressionSnafu {
+                    expr: after,
+                    reason: "missing closing '}'",
+                }
+                .fail();
+            };
+            output.push_str(&evaluate(&after[..end], &mut lookup)?);
+            rest = &after[end + 1..];
+        } else {
+            output.push('$');
+            rest = &rest[1..];
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Evaluate the contents of a placeholder
+fn evaluate(
+    expr: &str,
+    lookup: &mut impl FnMut(&str) -> Option<String>,
+) -> Result<String, ConfigError> {
+    let terms: Vec<&str> = expr.split('+').map(str::trim).collect();
+    if let [term] = terms[..] {
+        return resolve_term(expr, term, lookup);
+    }
+    let mut sum: i64 = 0;
+    for term in terms {
+        let text = resolve_term(expr, term, lookup)?;
+        let value = parse_int(&text).ok_or_else(|| {
+            InvalidExpressionSnafu {
+                expr,
+                reason: format!("'{text}' is not an integer"),
+            }
+            .build()
+        })?;
+        sum = sum.checked_add(value).ok_or_else(|| {
+            InvalidExpressionSnafu {
+                expr,
+                reason: "overflow",
+            }
+            .build()
+        })?;
+    }
+    Ok(sum.to_string())
+}
+
+/// Get the text of a single term of a placeholder, which is an integer literal or a variable
+fn resolve_term(
+    expr: &str,
+    term: &str,
+    lookup: &mut impl FnMut(&str) -> Option<String>,
+) -> Result<String, ConfigError> {
+    if term.starts_with(|c: char| c.is_ascii_digit()) {
+        if parse_int(term).is_none() {
+            return InvalidExpressionSnafu {
+                expr,
+                reason: format!("'{term}' is not an integer"),
+            }
+            .fail();
+        }
+        return Ok(term.to_string());
+    }
+    let is_name = !term.is_empty() && term.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_name {
+        return InvalidExpressionSnafu {
+            expr,
+            reason: "expected a variable name or an integer",
+        }
+        .fail();
+    }
+    lookup(term).ok_or_else(|| UndefinedVariableSnafu { name: term }.build())
+}
+
+fn parse_int(s: &str) -> Option<i64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// A node listed in a [`Fleet`]
+#[derive(Debug, Clone)]
+pub struct FleetNode {
+    /// The ID of the node
+    pub node_id: u8,
+    /// The path of the template used to configure the node
+    pub template: PathBuf,
+    /// The variable values for the node, including those shared by the whole fleet
+    pub vars: HashMap<String, String>,
+}
+
+/// A list of nodes to configure from templates
+///
+/// See the [module docs](crate::config_template) for the file format.
+#[derive(Debug, Clone)]
+pub struct Fleet {
+    nodes: Vec<FleetNode>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FleetSerializer {
+    template: Option<PathBuf>,
+    #[serde(default)]
+    vars: HashMap<String, toml::Value>,
+    #[serde(default, rename = "node")]
+    nodes: Vec<FleetNodeSerializer>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FleetNodeSerializer {
+    node_id: u8,
+    template: Option<PathBuf>,
+    #[serde(default)]
+    vars: HashMap<String, toml::Value>,
+}
+
+/// Convert a variable value from a fleet file to the text substituted into templates
+fn var_to_string(name: &str, value: &toml::Value) -> Result<String, ConfigError> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        _ => InvalidVariableSnafu {
+            name,
+            reason: "must be a string, number or boolean",
+        }
+        .fail(),
+    }
+}
+
+impl Fleet {
+    /// Read a fleet from a file
+    ///
+    /// Template paths are resolved relative to the directory containing the file.
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).context(IoSnafu {
+            path: path.to_string_lossy(),
+        })?;
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        Self::load_from_str_in(&content, base_dir)
+    }
+
+    /// Read a fleet from a string
+    ///
+    /// Template paths are resolved relative to the current directory.
+    pub fn load_from_str(s: &str) -> Result<Self, ConfigError> {
+        Self::load_from_str_in(s, Path::new(""))
+    }
+
+    fn load_from_str_in(s: &str, base_dir: &Path) -> Result<Self, ConfigError> {
+        let raw: FleetSerializer = toml::from_str(s).context(TomlDeserializationSnafu)?;
+        let mut shared = HashMap::new();
+        for (name, value) in &raw.vars {
+            shared.insert(name.clone(), var_to_string(name, value)?);
+        }
+        let mut nodes = Vec::with_capacity(raw.nodes.len());
+        for node in raw.nodes {
+            let Some(template) = node.template.or_else(|| raw.template.clone()) else {
+                return MissingTemplateSnafu {
+                    node_id: node.node_id,
+                }
+                .fail();
+            };
+            let mut vars = shared.clone();
+            for (name, value) in &node.vars {
+                vars.insert(name.clone(), var_to_string(name, value)?);
+            }
+            nodes.push(FleetNode {
+                node_id: node.node_id,
+                template: base_dir.join(template),
+                vars,
+            });
+        }
+        Ok(Self { nodes })
+    }
+
+    /// Get the nodes in the fleet, in the order they are listed in the file
+    pub fn nodes(&self) -> &[FleetNode] {
+        &self.nodes
+    }
+
+    /// Load the templates and render the configuration for each node
+    ///
+    /// Every node is rendered before any is returned, so that an error in the fleet is found
+    /// before any node is configured.
+    pub fn render(&self) -> Result<Vec<(u8, NodeConfig)>, ConfigError> {
+        let mut templates: HashMap<&Path, NodeConfigTemplate> = HashMap::new();
+        let mut configs = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let path = node.template.as_path();
+            let template = match templates.entry(path) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(NodeConfigTemplate::load_from_file(path)?),
+            };
+            let config = template
+                .render(node.node_id, &node.vars)
+                .context(RenderSnafu {
+                    node_id: node.node_id,
+                })?;
+            configs.push((node.node_id, config));
+        }
+        Ok(configs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATE: &str = r#"
+        [tpdo.0]
+        enabled = true
+        cob = ${0x180 + node_id}
+        transmission_type = 254
+        mappings = [{ index=0x2000, sub=1, size=32 }]
+
+        [[store]]
+        type = "u8"
+        value = ${axis}
+        index = 0x2001
+        sub = 1
+        "#;
+
+    #[test]
+    fn test_render_template() {
+        let template = NodeConfigTemplate::load_from_str(TEMPLATE).unwrap();
+        assert_eq!(vec!["axis", "node_id"], template.variables());
+
+        let vars = HashMap::from([("axis".to_string(), "2".to_string())]);
+        let config = template.render(5, &vars).unwrap();
+        assert_eq!(0x185, config.tpdos()[&0].cob);
+        assert_eq!(zencan_common::value::Value::U8(2), config.stores()[0].value);
+
+        assert!(matches!(
+            template.render(5, &HashMap::new()),
+            Err(ConfigError::UndefinedVariable { name }) if name == "axis"
+        ));
+    }
+
+    #[test]
+    fn test_substitute() {
+        let lookup = |name: &str| (name == "x").then(|| "0x10".to_string());
+        assert_eq!("a $ b $x", substitute("a $ b $$x", lookup).unwrap());
+        assert_eq!("0x10", substitute("${x}", lookup).unwrap());
+        assert_eq!("17", substitute("${ x + 1 }", lookup).unwrap());
+
+        assert!(matches!(
+            substitute("${x", lookup),
+            Err(ConfigError::InvalidExpression { .. })
+        ));
+        assert!(matches!(
+            substitute("${x - 1}", lookup),
+            Err(ConfigError::InvalidExpression { .. })
+        ));
+        assert!(NodeConfigTemplate::load_from_str("${1 +}").is_err());
+    }
+
+    #[test]
+    fn test_fleet() {
+        let fleet = Fleet::load_from_str(
+            r#"
+            template = "drive.toml"
+            [vars]
+            axis = 0
+            gain = "high"
+
+            [[node]]
+            node_id = 3
+
+            [[node]]
+            node_id = 4
+            template = "other.toml"
+            vars = { axis = 1 }
+            "#,
+        )
+        .unwrap();
+        let nodes = fleet.nodes();
+        assert_eq!(2, nodes.len());
+        assert_eq!(PathBuf::from("drive.toml"), nodes[0].template);
+        assert_eq!("0", nodes[0].vars["axis"]);
+        assert_eq!(PathBuf::from("other.toml"), nodes[1].template);
+        assert_eq!("1", nodes[1].vars["axis"]);
+        assert_eq!("high", nodes[1].vars["gain"]);
+
+        let result = Fleet::load_from_str("[[node]]\nnode_id = 3");
+        assert!(matches!(
+            result,
+            Err(ConfigError::MissingTemplate { node_id: 3 })
+        ));
+    }
+}
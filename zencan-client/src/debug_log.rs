@@ -0,0 +1,168 @@
+//! Reading the text log of a node (object 0x5004)
+//!
+//! Nodes which enable `[debug_log]` in their device config keep a ring buffer of text messages,
+//! which can be read with [`SdoClient::read_debug_log`](crate::SdoClient::read_debug_log). Each
+//! read returns the whole buffer, along with the number of bytes logged since the log was cleared,
+//! and a [`DebugLogTail`] uses this to return only the text which is new since the previous read.
+//!
+//! ```
+//! use zencan_client::debug_log::{DebugLogSnapshot, DebugLogTail};
+//!
+//! let mut tail = DebugLogTail::new();
+//! let snapshot = DebugLogSnapshot::decode(b"\x06\0\0\0hello\n").unwrap();
+//! assert_eq!("hello\n", tail.update(&snapshot).text);
+//! let snapshot = DebugLogSnapshot::decode(b"\x0c\0\0\0hello\nworld\n").unwrap();
+//! assert_eq!("world\n", tail.update(&snapshot).text);
+//! ```
+
+/// The contents of a node's debug log, as read from 0x5004sub2
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DebugLogSnapshot {
+    /// The number of bytes logged since the log was cleared, i.e. the position of the end of `text`
+    pub position: u32,
+    /// The buffered text, oldest first
+    ///
+    /// If the buffer has wrapped, this may start part way through a line.
+    pub text: Vec<u8>,
+}
+
+impl DebugLogSnapshot {
+    /// Decode the data read from 0x5004sub2
+    ///
+    /// Returns None if the data is too short to hold the position.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let position = u32::from_le_bytes(data.get(..4)?.try_into().unwrap());
+        Some(Self {
+            position,
+            text: data[4..].to_vec(),
+        })
+    }
+
+    /// Returns true if text has been overwritten since the log was cleared
+    pub fn wrapped(&self) -> bool {
+        self.position as usize > self.text.len()
+    }
+}
+
+/// New text returned by [`DebugLogTail::update`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugLogUpdate {
+    /// The text logged since the previous update
+    ///
+    /// Invalid UTF-8 is replaced with the replacement character.
+    pub text: String,
+    /// The number of bytes logged since the previous update which were overwritten before they
+    /// could be read
+    pub missed: u32,
+    /// True if the log was cleared, or the node restarted, since the previous update
+    pub reset: bool,
+}
+
+/// Tracks the position of a reader in a node's debug log
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DebugLogTail {
+    position: Option<u32>,
+}
+
+impl DebugLogTail {
+    /// Create a new tail, which returns all of the buffered text on the first update
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the text from a snapshot which has not been returned by a previous update
+    ///
+    /// When some of the new text has already been overwritten, the partial line at the start of the
+    /// buffer is skipped, and the number of bytes lost is returned in
+    /// [`missed`](DebugLogUpdate::missed).
+    pub fn update(&mut self, snapshot: &DebugLogSnapshot) -> DebugLogUpdate {
+        let len = snapshot.text.len() as u32;
+        // Where the new text starts, or None if there is no previous read to continue from
+        let start = match self.position {
+            Some(last) if snapshot.position >= last => Some(last),
+            _ => None,
+        };
+        let reset = self.position.is_some() && start.is_none();
+        self.position = Some(snapshot.position);
+
+        let new_len = snapshot.position - start.unwrap_or(0);
+        let mut text: &[u8] = if new_len <= len {
+            &snapshot.text[(len - new_len) as usize..]
+        } else {
+            &snapshot.text
+        };
+        let mut missed = 0;
+        if new_len > len {
+            // Skip the partial first line, so that the output starts on a line boundary
+            let skip = text
+                .iter()
+                .position(|&b| b == b'\n')
+                .map(|i| i + 1)
+                .unwrap_or(text.len());
+            text = &text[skip..];
+            // Text which was overwritten before the first read is not counted as missed
+            if start.is_some() {
+                missed = new_len - len + skip as u32;
+            }
+        }
+        DebugLogUpdate {
+            text: String::from_utf8_lossy(text).into_owned(),
+            missed,
+            reset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(position: u32, text: &str) -> DebugLogSnapshot {
+        DebugLogSnapshot {
+            position,
+            text: text.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(None, DebugLogSnapshot::decode(&[1, 0, 0]));
+        let snapshot = DebugLogSnapshot::decode(&[2, 0, 0, 0, b'a', b'\n']).unwrap();
+        assert_eq!(2, snapshot.position);
+        assert_eq!(b"a\n".to_vec(), snapshot.text);
+        assert!(!snapshot.wrapped());
+    }
+
+    #[test]
+    fn test_tail() {
+        let mut tail = DebugLogTail::new();
+        // Nothing has been logged yet
+        assert_eq!(DebugLogUpdate::default(), tail.update(&snapshot(0, "")));
+
+        assert_eq!("one\n", tail.update(&snapshot(4, "one\n")).text);
+        assert_eq!("", tail.update(&snapshot(4, "one\n")).text);
+        // The buffer has wrapped, but all of the new text is still buffered
+        let update = tail.update(&snapshot(14, "e\ntwo\nthree\n"));
+        assert_eq!("two\nthree\n", update.text);
+        assert_eq!(0, update.missed);
+
+        // More text was logged than fits in the buffer
+        let update = tail.update(&snapshot(30, "ur\nfive\nsix\n"));
+        assert_eq!("five\nsix\n", update.text);
+        assert_eq!(7, update.missed);
+
+        // The log was cleared
+        let update = tail.update(&snapshot(5, "seven"));
+        assert!(update.reset);
+        assert_eq!("seven", update.text);
+    }
+
+    #[test]
+    fn test_first_read_of_wrapped_log() {
+        let mut tail = DebugLogTail::new();
+        let update = tail.update(&snapshot(100, "artial\nwhole\n"));
+        assert_eq!("whole\n", update.text);
+        assert_eq!(0, update.missed);
+        assert!(!update.reset);
+    }
+}
@@ -0,0 +1,120 @@
+//! Recognition of our own transmissions among received frames
+//!
+//! Some transports deliver the frames sent on them back to their own receiver, e.g. a socketcan
+//! socket with `CAN_RAW_RECV_OWN_MSGS` enabled, an adapter with local echo, or the in-memory bus of
+//! [`NodeFixture`](crate::testing::NodeFixture). Without care, these echoes look like traffic from
+//! another master on the bus. An [`EchoFilter`] remembers the frames which were sent, so that the
+//! first matching frame received afterwards can be recognized as an echo and dropped.
+//!
+//! The filter is only enabled for transports which report that they echo, with
+//! [`AsyncCanSender::echoes_sent_frames`](zencan_common::traits::AsyncCanSender::echoes_sent_frames),
+//! so that a frame sent by another master with the same ID and data is never mistaken for an echo
+//! on a transport which does not echo.
+//!
+//! Frames are matched on their ID and data. A sent frame is forgotten when its echo is received,
+//! or after [`ECHO_WINDOW`].
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use zencan_common::CanMessage;
+
+/// How long to wait for the echo of a sent frame
+const ECHO_WINDOW: Duration = Duration::from_secs(1);
+
+/// The most sent frames remembered at once
+///
+/// This allows for a few full SDO blocks to be sent before their echoes are read.
+const MAX_PENDING: usize = 512;
+
+/// Tracks sent frames whose echoes may still be received
+///
+/// Clones share the same state. A disabled filter recognizes no echoes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EchoFilter {
+    enabled: bool,
+    pending: Arc<Mutex<VecDeque<(CanMessage, Instant)>>>,
+}
+
+impl EchoFilter {
+    /// Create a filter, which is enabled if the transport echoes the frames sent on it
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    /// Record a frame which has been sent
+    pub fn record_sent(&self, msg: CanMessage) {
+        if !self.enabled {
+            return;
+        }
+        let mut pending = self.pending.lock().unwrap();
+        let now = Instant::now();
+        Self::expire(&mut pending, now);
+        if pending.len() == MAX_PENDING {
+            pending.pop_front();
+        }
+        pending.push_back((msg, now));
+    }
+
+    /// Returns true if `msg` is the echo of a sent frame, which is then forgotten
+    pub fn take_echo(&self, msg: &CanMessage) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let mut pending = self.pending.lock().unwrap();
+        Self::expire(&mut pending, Instant::now());
+        match pending.iter().position(|(sent, _)| sent == msg) {
+            Some(pos) => {
+                pending.remove(pos);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expire(pending: &mut VecDeque<(CanMessage, Instant)>, now: Instant) {
+        while pending
+            .front()
+            .is_some_and(|(_, sent)| now.duration_since(*sent) > ECHO_WINDOW)
+        {
+            pending.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zencan_common::CanId;
+
+    use super::*;
+
+    #[test]
+    fn test_echo_filter() {
+        let filter = EchoFilter::new(true);
+        let a = CanMessage::new(CanId::std(0x601), &[0x40, 0x00, 0x10, 0, 0, 0, 0, 0]);
+        let b = CanMessage::new(CanId::std(0x601), &[0x60, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(!filter.take_echo(&a));
+
+        filter.record_sent(a);
+        filter.clone().record_sent(a);
+        // The timestamp of a received frame does not matter
+        assert!(filter.take_echo(&a.with_timestamp(1000)));
+        assert!(!filter.take_echo(&b));
+        assert!(filter.take_echo(&a));
+        // Each echo is only recognized once
+        assert!(!filter.take_echo(&a));
+    }
+
+    #[test]
+    fn test_disabled_echo_filter() {
+        let filter = EchoFilter::new(false);
+        let a = CanMessage::new(CanId::std(0x601), &[0x40, 0x00, 0x10, 0, 0, 0, 0, 0]);
+        filter.record_sent(a);
+        assert!(!filter.take_echo(&a));
+    }
+}
@@ -0,0 +1,231 @@
+//! Decoding of EMCY messages received from nodes
+//!
+//! The first three bytes of an EMCY are standardized, but the remaining five bytes are
+//! manufacturer specific. An [`EmcyDecoder`] can be registered for a vendor ID with
+//! [`BusManager::register_emcy_decoder`](crate::BusManager::register_emcy_decoder), and is then used
+//! to decode EMCYs from nodes with that vendor ID into an [`EmcyDiagnostic`].
+//!
+//! The vendor ID of a node is taken from the identity read when the node was scanned, so EMCYs
+//! from nodes which have not been scanned are not decoded.
+//!
+//! ```
+//! use zencan_client::common::decode::Emergency;
+//! use zencan_client::emcy::{EmcyDecoders, EmcyDiagnostic};
+//!
+//! let mut decoders = EmcyDecoders::new();
+//! decoders.register(0xCAFE, |emcy: &Emergency| {
+//!     (emcy.error_code == 0x5000).then(|| {
+//!         EmcyDiagnostic::new("Sensor fault").with_field("channel", emcy.vendor_data[0])
+//!     })
+//! });
+//! ```
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+use zencan_common::{
+    decode::{classify, CanOpenFrame, Emergency},
+    traits::AsyncCanSender,
+};
+
+use crate::bus_manager::NoMsgError;
+use crate::{NodeInfo, RawHandle};
+
+/// Decodes the manufacturer specific part of EMCY messages for a vendor
+///
+/// Decoders are implemented for closures taking an [`Emergency`], so a closure can be registered
+/// directly.
+pub trait EmcyDecoder: Send + Sync {
+    /// Decode an EMCY message
+    ///
+    /// Returns None if the EMCY is not recognized by the decoder
+    fn decode(&self, emcy: &Emergency) -> Option<EmcyDiagnostic>;
+}
+
+impl<F> EmcyDecoder for F
+where
+    F: Fn(&Emergency) -> Option<EmcyDiagnostic> + Send + Sync,
+{
+    fn decode(&self, emcy: &Emergency) -> Option<EmcyDiagnostic> {
+        self(emcy)
+    }
+}
+
+/// Structured diagnostic information decoded from an EMCY
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EmcyDiagnostic {
+    /// A description of the error
+    pub description: String,
+    /// Named values decoded from the manufacturer specific bytes
+    pub fields: Vec<(String, String)>,
+}
+
+impl EmcyDiagnostic {
+    /// Create a diagnostic with a description, and no fields
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Add a named value to the diagnostic
+    pub fn with_field(mut self, name: impl Into<String>, value: impl ToString) -> Self {
+        self.fields.push((name.into(), value.to_string()));
+        self
+    }
+}
+
+impl core::fmt::Display for EmcyDiagnostic {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.description)?;
+        if !self.fields.is_empty() {
+            let fields: Vec<String> = self
+                .fields
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect();
+            write!(f, " ({})", fields.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// An EMCY message, along with the result of decoding its manufacturer specific bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedEmcy {
+    /// The received EMCY
+    pub emcy: Emergency,
+    /// The vendor ID of the node which sent it, if known
+    pub vendor_id: Option<u32>,
+    /// The diagnostic produced by the decoder registered for the vendor, if any
+    pub diagnostic: Option<EmcyDiagnostic>,
+}
+
+/// A set of EMCY decoders, keyed by vendor ID
+#[derive(Clone, Default)]
+pub struct EmcyDecoders {
+    decoders: HashMap<u32, Arc<dyn EmcyDecoder>>,
+}
+
+impl core::fmt::Debug for EmcyDecoders {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EmcyDecoders")
+            .field("vendor_ids", &self.decoders.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl EmcyDecoders {
+    /// Create an empty set of decoders
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the decoder for a vendor ID, replacing any previous decoder for it
+    pub fn register(&mut self, vendor_id: u32, decoder: impl EmcyDecoder + 'static) {
+        self.decoders.insert(vendor_id, Arc::new(decoder));
+    }
+
+    /// Remove the decoder for a vendor ID
+    pub fn unregister(&mut self, vendor_id: u32) {
+        self.decoders.remove(&vendor_id);
+    }
+
+    /// Decode an EMCY sent by a node with the given vendor ID
+    pub fn decode(&self, emcy: Emergency, vendor_id: Option<u32>) -> DecodedEmcy {
+        let diagnostic = vendor_id
+            .and_then(|id| self.decoders.get(&id))
+            .and_then(|decoder| decoder.decode(&emcy));
+        DecodedEmcy {
+            emcy,
+            vendor_id,
+            diagnostic,
+        }
+    }
+}
+
+/// Receives and decodes EMCY messages on a bus
+///
+/// Created by [`BusManager::emcy_monitor`](crate::BusManager::emcy_monitor).
+#[derive(Debug)]
+pub struct EmcyMonitor<S: AsyncCanSender> {
+    handle: RawHandle<S>,
+    decoders: Arc<RwLock<EmcyDecoders>>,
+    nodes: Arc<Mutex<HashMap<u8, NodeInfo>>>,
+}
+
+impl<S: AsyncCanSender> EmcyMonitor<S> {
+    pub(crate) fn new(
+        handle: RawHandle<S>,
+        decoders: Arc<RwLock<EmcyDecoders>>,
+        nodes: Arc<Mutex<HashMap<u8, NodeInfo>>>,
+    ) -> Self {
+        Self {
+            handle: handle.with_filter(|msg| matches!(classify(*msg), CanOpenFrame::Emcy(_))),
+            decoders,
+            nodes,
+        }
+    }
+
+    /// Wait for the next EMCY message, and decode it
+    pub async fn recv(&mut self) -> Result<DecodedEmcy, NoMsgError> {
+        loop {
+            let msg = self.handle.recv().await?;
+            if let CanOpenFrame::Emcy(emcy) = classify(msg) {
+                let vendor_id = self
+                    .nodes
+                    .lock()
+                    .await
+                    .get(&emcy.node)
+                    .and_then(|n| n.identity)
+                    .map(|id| id.vendor_id);
+                return Ok(self.decoders.read().unwrap().decode(emcy, vendor_id));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emcy(error_code: u16) -> Emergency {
+        Emergency {
+            node: 3,
+            error_code,
+            error_register: 0x81,
+            vendor_data: [7, 0, 0, 0, 0],
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut decoders = EmcyDecoders::new();
+        decoders.register(0xCAFE, |emcy: &Emergency| {
+            (emcy.error_code == 0x5000).then(|| {
+                EmcyDiagnostic::new("Sensor fault")
+                    .with_field("channel", emcy.vendor_data[0])
+                    .with_field("state", "open")
+            })
+        });
+
+        let decoded = decoders.decode(emcy(0x5000), Some(0xCAFE));
+        let diagnostic = decoded.diagnostic.unwrap();
+        assert_eq!(
+            "Sensor fault (channel=7, state=open)",
+            diagnostic.to_string()
+        );
+
+        // Unrecognized codes, other vendors, and unknown vendors are not decoded
+        assert_eq!(None, decoders.decode(emcy(0x6000), Some(0xCAFE)).diagnostic);
+        assert_eq!(None, decoders.decode(emcy(0x5000), Some(0xBEEF)).diagnostic);
+        assert_eq!(None, decoders.decode(emcy(0x5000), None).diagnostic);
+
+        decoders.unregister(0xCAFE);
+        assert_eq!(None, decoders.decode(emcy(0x5000), Some(0xCAFE)).diagnostic);
+    }
+}
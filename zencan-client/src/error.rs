@@ -0,0 +1,273 @@
+//! A single error type for applications which use several client services
+//!
+//! Each service returns its own error type, e.g. [`SdoClientError`] or [`LssError`], which
+//! describes exactly what went wrong. Applications which combine several services can convert them
+//! all into a [`ZencanClientError`] with `?`, and use [`ZencanClientError::kind`] to handle broad
+//! categories of failure, e.g. to retry after a timeout, without matching on each service's
+//! variants.
+//!
+//! ```
+//! use zencan_client::{ErrorKind, SdoClientError, ZencanClientError};
+//!
+//! fn check(result: Result<(), SdoClientError>) -> Result<(), ZencanClientError> {
+//!     Ok(result?)
+//! }
+//!
+//! let err = check(Err(SdoClientError::NoResponse)).unwrap_err();
+//! assert_eq!(ErrorKind::Timeout, err.kind());
+//! ```
+use snafu::Snafu;
+
+use crate::bus_manager::RestartError;
+use crate::firmware::FlashError;
+use crate::heartbeat_consumer::HeartbeatConsumerError;
+use crate::identity::IdentityError;
+use crate::lss_master::LssError;
+use crate::node_configuration::PdoValidationError;
+use crate::pdo_link::PdoLinkError;
+use crate::sdo_client::{RawAbortCode, SdoClientError};
+
+/// The broad category of a [`ZencanClientError`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// A message could not be sent on the bus
+    Transport,
+    /// The node did not respond in time
+    Timeout,
+    /// The node did not respond in time, and no frames at all were being received from the bus, so
+    /// the interface or cabling is more likely at fault than the node. See [`crate::bus_silence`].
+    BusSilent,
+    /// The node sent a response which did not follow the protocol
+    Protocol,
+    /// The node refused the request, with an SDO abort or an LSS error response
+    RemoteAbort,
+    /// A value read from the node did not match the value written or expected
+    Verification,
+    /// The request was not valid for the node, and was not sent
+    InvalidRequest,
+    /// Another master was active on the bus, so the request was not sent. See
+    /// [`crate::competing_master`].
+    CompetingMaster,
+}
+
+/// An error returned by any of the client services
+#[derive(Debug, Clone, Snafu)]
+pub enum ZencanClientError {
+    /// An SDO transfer failed
+    #[snafu(context(false), display("{source}"))]
+    Sdo {
+        /// The underlying error
+        source: SdoClientError,
+    },
+    /// An LSS operation failed
+    #[snafu(context(false), display("{source}"))]
+    Lss {
+        /// The underlying error
+        source: LssError,
+    },
+    /// A firmware update failed
+    #[snafu(context(false), display("{source}"))]
+    Flash {
+        /// The underlying error
+        source: FlashError,
+    },
+    /// Verifying a node's identity failed
+    #[snafu(context(false), display("{source}"))]
+    Identity {
+        /// The underlying error
+        source: IdentityError,
+    },
+    /// Configuring heartbeat consumers failed
+    #[snafu(context(false), display("{source}"))]
+    HeartbeatConsumer {
+        /// The underlying error
+        source: HeartbeatConsumerError,
+    },
+    /// A PDO configuration is not compatible with the node
+    #[snafu(context(false), display("{source}"))]
+    PdoValidation {
+        /// The underlying error
+        source: PdoValidationError,
+    },
+    /// Restarting a device failed
+    #[snafu(context(false), display("{source}"))]
+    Restart {
+        /// The underlying error
+        source: RestartError,
+    },
+    /// Linking nodes with a PDO failed
+    #[snafu(context(false), display("{source}"))]
+    PdoLink {
+        /// The underlying error
+        source: PdoLinkError,
+    },
+}
+
+fn pdo_validation_error_kind(e: &PdoValidationError) -> ErrorKind {
+    match e {
+        PdoValidationError::Sdo { source, .. } => sdo_error_kind(source),
+        _ => ErrorKind::InvalidRequest,
+    }
+}
+
+fn sdo_error_kind(e: &SdoClientError) -> ErrorKind {
+    match e {
+        SdoClientError::NoResponse => ErrorKind::Timeout,
+        SdoClientError::BusSilent { .. } => ErrorKind::BusSilent,
+        SdoClientError::CompetingMaster { .. } => ErrorKind::CompetingMaster,
+        SdoClientError::SocketSendFailed => ErrorKind::Transport,
+        SdoClientError::ServerAbort { .. } => ErrorKind::RemoteAbort,
+        SdoClientError::VerifyFailed { .. } | SdoClientError::ConcurrentModification { .. } => {
+            ErrorKind::Verification
+        }
+        SdoClientError::MalformedResponse
+        | SdoClientError::UnexpectedResponse { .. }
+        | SdoClientError::ToggleNotAlternated
+        | SdoClientError::MismatchedObjectIndex { .. }
+        | SdoClientError::UnexpectedSize
+        | SdoClientError::BlockSizeChangedTooSmall => ErrorKind::Protocol,
+    }
+}
+
+impl ZencanClientError {
+    /// Get the SDO error which caused this error, if any
+    pub fn sdo_error(&self) -> Option<&SdoClientError> {
+        match self {
+            ZencanClientError::Sdo { source } => Some(source),
+            ZencanClientError::Flash {
+                source: FlashError::Sdo { source, .. },
+            } => Some(source),
+            ZencanClientError::Identity {
+                source: IdentityError::ReadFailed { source, .. },
+            } => Some(source),
+            ZencanClientError::HeartbeatConsumer {
+                source: HeartbeatConsumerError::Sdo { source, .. },
+            } => Some(source),
+            ZencanClientError::PdoValidation {
+                source: PdoValidationError::Sdo { source, .. },
+            } => Some(source),
+            ZencanClientError::PdoLink {
+                source:
+                    PdoLinkError::Sdo { source, .. }
+                    | PdoLinkError::Validation {
+                        source: PdoValidationError::Sdo { source, .. },
+                        ..
+                    },
+            } => Some(source),
+            _ => None,
+        }
+    }
+
+    /// Get the category of the error
+    ///
+    /// Errors caused by a failed SDO transfer are categorized by the SDO error, whichever service
+    /// performed the transfer.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ZencanClientError::Sdo { source } => sdo_error_kind(source),
+            ZencanClientError::Lss { source } => match source {
+                LssError::Timeout => ErrorKind::Timeout,
+                LssError::BitTimingConfigError { .. }
+                | LssError::NodeIdConfigError { .. }
+                | LssError::NodeStoreConfigError { .. } => ErrorKind::RemoteAbort,
+            },
+            ZencanClientError::Flash { source } => match source {
+                FlashError::Sdo { source, .. } => sdo_error_kind(source),
+                FlashError::Timeout { .. } => ErrorKind::Timeout,
+                FlashError::NmtSendFailed { .. } => ErrorKind::Transport,
+            },
+            ZencanClientError::Identity { source } => match source {
+                IdentityError::ReadFailed { source, .. } => sdo_error_kind(source),
+                IdentityError::Mismatch { .. } => ErrorKind::Verification,
+            },
+            ZencanClientError::HeartbeatConsumer { source } => match source {
+                HeartbeatConsumerError::Sdo { source, .. } => sdo_error_kind(source),
+                HeartbeatConsumerError::VerifyFailed { .. } => ErrorKind::Verification,
+                _ => ErrorKind::InvalidRequest,
+            },
+            ZencanClientError::PdoValidation { source } => pdo_validation_error_kind(source),
+            ZencanClientError::Restart { source } => match source {
+                RestartError::ResetSendFailed { .. } => ErrorKind::Transport,
+                RestartError::NoBootup { .. } => ErrorKind::Timeout,
+            },
+            ZencanClientError::PdoLink { source } => match source {
+                PdoLinkError::Sdo { source, .. } => sdo_error_kind(source),
+                PdoLinkError::Validation { source, .. } => pdo_validation_error_kind(source),
+                _ => ErrorKind::InvalidRequest,
+            },
+        }
+    }
+
+    /// Get the abort code, if the error was caused by an SDO abort from the server
+    pub fn abort_code(&self) -> Option<RawAbortCode> {
+        self.sdo_error().and_then(|e| e.abort_code())
+    }
+
+    /// Returns true if the error may be transient, so that repeating the operation may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self.sdo_error() {
+            Some(sdo) => sdo.is_retryable(),
+            None => self.kind() == ErrorKind::Timeout,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zencan_common::sdo::AbortCode;
+
+    use super::*;
+    use crate::firmware::FlashStage;
+
+    #[test]
+    fn test_kind_and_abort_code() {
+        let abort = SdoClientError::ServerAbort {
+            index: 0x1000,
+            sub: 0,
+            abort_code: RawAbortCode::Valid(AbortCode::NoSuchObject),
+        };
+        let err: ZencanClientError = abort.clone().into();
+        assert_eq!(ErrorKind::RemoteAbort, err.kind());
+        assert_eq!(
+            Some(RawAbortCode::Valid(AbortCode::NoSuchObject)),
+            err.abort_code()
+        );
+
+        // The abort code is preserved when the SDO error is wrapped by another service
+        let err: ZencanClientError = FlashError::Sdo {
+            node_id: 1,
+            stage: FlashStage::Download,
+            source: abort,
+        }
+        .into();
+        assert_eq!(ErrorKind::RemoteAbort, err.kind());
+        assert_eq!(
+            Some(RawAbortCode::Valid(AbortCode::NoSuchObject)),
+            err.abort_code()
+        );
+
+        let err: ZencanClientError = LssError::Timeout.into();
+        assert_eq!(ErrorKind::Timeout, err.kind());
+        assert!(err.is_retryable());
+        assert_eq!(None, err.abort_code());
+
+        let err: ZencanClientError = SdoClientError::BusSilent {
+            idle_time: std::time::Duration::from_millis(500),
+        }
+        .into();
+        assert_eq!(ErrorKind::BusSilent, err.kind());
+        assert!(!err.is_retryable());
+
+        let err: ZencanClientError = SdoClientError::ConcurrentModification {
+            index: 0x2000,
+            sub: 1,
+        }
+        .into();
+        assert_eq!(ErrorKind::Verification, err.kind());
+        assert!(err.is_retryable());
+
+        let err: ZencanClientError = HeartbeatConsumerError::InvalidPeer { peer: 0 }.into();
+        assert_eq!(ErrorKind::InvalidRequest, err.kind());
+        assert!(!err.is_retryable());
+    }
+}
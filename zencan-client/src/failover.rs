@@ -0,0 +1,578 @@
+//! Failover between the two buses of a redundant network
+//!
+//! On a redundant network, every node is connected to two independent buses, so that the network
+//! survives the loss of one of them. A [`BusManager`](crate::BusManager) created with
+//! [`new_redundant`](crate::BusManager::new_redundant) has a transport for each bus. It sends and
+//! receives on one [`Link`] at a time, starting with the primary, and fails over to the other link
+//! when the active one fails:
+//!
+//! - The active link is silent: no frame has been received on it for
+//!   [`FailoverOptions::silence_timeout`], while frames are still being received on the other link.
+//!   A network which is quiet on both links does not fail over.
+//! - The active link has errors: every send and receive on it has failed for
+//!   [`FailoverOptions::error_timeout`], e.g. because its controller is bus-off or its interface is
+//!   down, while the other link has no errors.
+//!
+//! Both links are received all the time, so the manager fails back to the primary in the same way
+//! if the backup fails later, and [`set_active_link`](crate::BusManager::set_active_link) switches
+//! links on request. Each switch is reported to all [`BusEvents`](crate::BusEvents) receivers with a
+//! [`BusEvent::Failover`].
+//!
+//! The subscriptions of the manager, such as its SDO clients, raw handles, EMCY monitors and node
+//! events, receive from whichever link is active, so they carry on after a failover without being
+//! created again. Frames received on the standby link which were not also received on the failing
+//! link are held, and replayed to the subscriptions when the manager fails over, so that heartbeats,
+//! EMCYs and responses which arrived while the failure was being detected are not lost. The manager
+//! heartbeat and SYNC producers send through the active link, except for a SYNC
+//! [offloaded](crate::BusManager::start_sync_offloaded) to the kernel of an interface, which stays
+//! on that interface.
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use zencan_common::{
+    traits::{AsyncCanReceiver, AsyncCanSender},
+    CanMessage,
+};
+
+use crate::bus_silence::BusEvent;
+
+/// The most frames received on the standby link which are held for replay
+pub const MAX_REPLAY_FRAMES: usize = 256;
+
+/// The time to wait before receiving again from a link which returned an error
+const ERROR_BACKOFF: Duration = Duration::from_millis(10);
+
+/// One of the two buses of a redundant network
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Link {
+    /// The bus used to begin with
+    Primary,
+    /// The bus used when the primary fails
+    Backup,
+}
+
+impl Link {
+    /// Get the other link
+    pub fn other(self) -> Self {
+        match self {
+            Link::Primary => Link::Backup,
+            Link::Backup => Link::Primary,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl core::fmt::Display for Link {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Link::Primary => write!(f, "primary"),
+            Link::Backup => write!(f, "backup"),
+        }
+    }
+}
+
+/// Why a [`BusManager`](crate::BusManager) switched links
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailoverReason {
+    /// No frame was received on the link, while frames were received on the other link
+    Silent {
+        /// The time since the last frame was received on the link
+        idle_time: Duration,
+    },
+    /// Every send and receive on the link failed
+    Errors {
+        /// The time since the link started failing
+        duration: Duration,
+    },
+    /// The switch was requested with [`set_active_link`](crate::BusManager::set_active_link)
+    Requested,
+}
+
+/// Options controlling when a [`BusManager`](crate::BusManager) fails over to the other link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailoverOptions {
+    /// The time without a frame after which the active link is considered failed, if frames are
+    /// received on the other link
+    ///
+    /// This must be longer than the longest expected gap between frames on a healthy bus. Enable
+    /// heartbeats on the nodes so that the bus is never quiet for long.
+    ///
+    /// Default: 1s
+    pub silence_timeout: Duration,
+    /// The time for which sends and receives on the active link must keep failing before it is
+    /// considered failed
+    ///
+    /// Default: 500ms
+    pub error_timeout: Duration,
+}
+
+impl Default for FailoverOptions {
+    fn default() -> Self {
+        Self {
+            silence_timeout: Duration::from_secs(1),
+            error_timeout: Duration::from_millis(500),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LinkHealth {
+    /// The time the last frame was received
+    last_frame: Instant,
+    /// The time of the first of the errors since the last successful send or receive
+    first_error: Option<Instant>,
+}
+
+impl LinkHealth {
+    fn new(now: Instant) -> Self {
+        Self {
+            last_frame: now,
+            first_error: None,
+        }
+    }
+
+    fn failing_for(&self, now: Instant) -> Option<Duration> {
+        self.first_error.map(|t| now.saturating_duration_since(t))
+    }
+}
+
+#[derive(Debug)]
+struct FailoverInner {
+    options: FailoverOptions,
+    active: Link,
+    health: [LinkHealth; 2],
+    events: Option<broadcast::Sender<BusEvent>>,
+}
+
+impl FailoverInner {
+    /// Get the reason the active link should be abandoned, if it has failed and the other link
+    /// has not
+    fn failure(&self, now: Instant) -> Option<FailoverReason> {
+        let active = &self.health[self.active.index()];
+        let standby = &self.health[self.active.other().index()];
+        if let Some(duration) = active.failing_for(now) {
+            if duration >= self.options.error_timeout && standby.first_error.is_none() {
+                return Some(FailoverReason::Errors { duration });
+            }
+        }
+        let idle_time = now.saturating_duration_since(active.last_frame);
+        if idle_time >= self.options.silence_timeout
+            && standby.last_frame > active.last_frame
+            && now.saturating_duration_since(standby.last_frame) < self.options.silence_timeout
+        {
+            return Some(FailoverReason::Silent { idle_time });
+        }
+        None
+    }
+
+    fn switch(&mut self, reason: FailoverReason) {
+        let from = self.active;
+        self.active = from.other();
+        log::warn!(
+            "Failing over from the {from} link to the {} link: {reason:?}",
+            self.active
+        );
+        if let Some(events) = &self.events {
+            events
+                .send(BusEvent::Failover {
+                    from,
+                    to: self.active,
+                    reason,
+                })
+                .ok();
+        }
+    }
+}
+
+/// The state of the links of a redundant bus, shared between its sender and receiver
+///
+/// Clones share the same state.
+#[derive(Debug, Clone)]
+pub(crate) struct FailoverState {
+    inner: Arc<Mutex<FailoverInner>>,
+}
+
+impl FailoverState {
+    pub fn new(options: FailoverOptions) -> Self {
+        let now = Instant::now();
+        Self {
+            inner: Arc::new(Mutex::new(FailoverInner {
+                options,
+                active: Link::Primary,
+                health: [LinkHealth::new(now); 2],
+                events: None,
+            })),
+        }
+    }
+
+    /// Set the channel which failover events are sent to
+    pub fn set_events(&self, events: broadcast::Sender<BusEvent>) {
+        self.inner.lock().unwrap().events = Some(events);
+    }
+
+    pub fn active(&self) -> Link {
+        self.inner.lock().unwrap().active
+    }
+
+    /// Get the age of the oldest frame which is replayed after a failover
+    pub fn replay_window(&self) -> Duration {
+        let options = self.inner.lock().unwrap().options;
+        2 * options.silence_timeout.max(options.error_timeout)
+    }
+
+    /// Switch to `link` on request, returning false if it was already active
+    pub fn set_active(&self, link: Link) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.active == link {
+            return false;
+        }
+        inner.switch(FailoverReason::Requested);
+        true
+    }
+
+    /// Record a frame received on `link` at `at`
+    pub fn record_frame(&self, link: Link, at: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        let health = &mut inner.health[link.index()];
+        health.last_frame = health.last_frame.max(at);
+        health.first_error = None;
+    }
+
+    /// Record a frame sent successfully on `link`
+    pub fn record_sent(&self, link: Link) {
+        self.inner.lock().unwrap().health[link.index()].first_error = None;
+    }
+
+    /// Record a failed send or receive on `link` at `now`
+    pub fn record_error(&self, link: Link, now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.health[link.index()].first_error.get_or_insert(now);
+    }
+
+    /// Fail over if the active link has failed, returning true if the active link changed
+    pub fn check(&self, now: Instant) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.failure(now) {
+            Some(reason) => {
+                inner.switch(reason);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Sends frames on the active link of a redundant bus
+///
+/// Created by [`BusManager::new_redundant`](crate::BusManager::new_redundant). A frame which
+/// cannot be sent on a link which has failed is sent again on the other link after failing over.
+pub struct RedundantSender<P, B = P> {
+    primary: P,
+    backup: B,
+    state: FailoverState,
+}
+
+impl<P, B> core::fmt::Debug for RedundantSender<P, B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RedundantSender")
+            .field("active", &self.state.active())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P: AsyncCanSender, B: AsyncCanSender> RedundantSender<P, B> {
+    pub(crate) fn new(primary: P, backup: B, state: FailoverState) -> Self {
+        Self {
+            primary,
+            backup,
+            state,
+        }
+    }
+
+    async fn send_on(&mut self, link: Link, msg: CanMessage) -> Result<(), CanMessage> {
+        let result = match link {
+            Link::Primary => self.primary.send(msg).await,
+            Link::Backup => self.backup.send(msg).await,
+        };
+        match result {
+            Ok(()) => self.state.record_sent(link),
+            Err(_) => self.state.record_error(link, Instant::now()),
+        }
+        result
+    }
+
+    async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
+        let link = self.state.active();
+        match self.send_on(link, msg).await {
+            Err(msg) if self.state.check(Instant::now()) => {
+                let link = self.state.active();
+                self.send_on(link, msg).await
+            }
+            result => result,
+        }
+    }
+}
+
+impl<P: AsyncCanSender, B: AsyncCanSender> AsyncCanSender for RedundantSender<P, B> {
+    fn send(
+        &mut self,
+        msg: CanMessage,
+    ) -> impl core::future::Future<Output = Result<(), CanMessage>> + Send {
+        self.send(msg)
+    }
+
+    fn echoes_sent_frames(&self) -> bool {
+        self.primary.echoes_sent_frames() || self.backup.echoes_sent_frames()
+    }
+}
+
+/// Receive from `receiver`, after waiting until `retry_at` if it is set
+async fn recv_after<R: AsyncCanReceiver>(
+    receiver: &mut R,
+    retry_at: Option<Instant>,
+) -> Result<CanMessage, R::Error> {
+    if let Some(t) = retry_at {
+        tokio::time::sleep_until(t.into()).await;
+    }
+    receiver.recv().await
+}
+
+/// Returns true if two frames received on different links are copies of the same frame
+fn same_frame(a: &CanMessage, b: &CanMessage) -> bool {
+    a.id() == b.id() && a.is_rtr() == b.is_rtr() && a.data() == b.data()
+}
+
+/// Push a frame to a bounded queue, dropping the oldest frame if it is full
+fn push_bounded(queue: &mut VecDeque<CanMessage>, msg: CanMessage) {
+    if queue.len() == MAX_REPLAY_FRAMES {
+        queue.pop_front();
+    }
+    queue.push_back(msg);
+}
+
+/// Receives frames from the active link of a redundant bus
+///
+/// Both links are received, so that the health of the standby link is known. Each frame received
+/// on the standby link is paired with its copy from the active link, and the frames which have no
+/// copy yet are held, so that they can be replayed after a failover. Receive errors are recorded
+/// as link failures rather than returned, so `recv` never fails.
+///
+/// The `recv` futures of both receivers must be cancel safe, since a receive from one link is
+/// cancelled whenever a frame is received on the other.
+pub(crate) struct RedundantReceiver<P, B> {
+    primary: P,
+    backup: B,
+    state: FailoverState,
+    /// The link which frames were last delivered from
+    active: Link,
+    /// When each link may be received from again after an error
+    retry_at: [Option<Instant>; 2],
+    /// Frames received on the standby link, which have not been received on the active link
+    held: VecDeque<CanMessage>,
+    /// Frames received on the active link, which have not been received on the standby link
+    delivered: VecDeque<CanMessage>,
+    /// Frames to return before receiving again
+    replay: VecDeque<CanMessage>,
+}
+
+impl<P, B> core::fmt::Debug for RedundantReceiver<P, B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RedundantReceiver")
+            .field("active", &self.active)
+            .field("held", &self.held.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P: AsyncCanReceiver, B: AsyncCanReceiver> RedundantReceiver<P, B> {
+    pub fn new(primary: P, backup: B, state: FailoverState) -> Self {
+        Self {
+            primary,
+            backup,
+            active: state.active(),
+            state,
+            retry_at: [None; 2],
+            held: VecDeque::new(),
+            delivered: VecDeque::new(),
+            replay: VecDeque::new(),
+        }
+    }
+
+    /// Check for a failure of the active link, and replay the frames held from the other link if
+    /// the active link has changed
+    ///
+    /// The link may also have been changed by the sender, or on request.
+    fn check(&mut self, now: Instant) {
+        self.state.check(now);
+        let active = self.state.active();
+        if active == self.active {
+            return;
+        }
+        self.active = active;
+        // Frames older than the silence which caused the failover were most likely lost on both
+        // links, or are stale
+        let window = self.state.replay_window();
+        self.held
+            .retain(|m| now.saturating_duration_since(m.receive_instant()) < window);
+        if !self.held.is_empty() {
+            log::info!("Replaying {} frames after failover", self.held.len());
+        }
+        self.replay.append(&mut self.held);
+        self.delivered.clear();
+    }
+
+    /// Handle a frame received on `link`, returning it if it is to be delivered
+    fn handle_frame(&mut self, link: Link, msg: CanMessage) -> Option<CanMessage> {
+        self.state.record_frame(link, msg.receive_instant());
+        self.check(Instant::now());
+        if link == self.active {
+            match self.held.iter().position(|m| same_frame(m, &msg)) {
+                Some(i) => {
+                    self.held.remove(i);
+                }
+                None => push_bounded(&mut self.delivered, msg),
+            }
+            if self.replay.is_empty() {
+                return Some(msg);
+            }
+            self.replay.push_back(msg);
+        } else {
+            match self.delivered.iter().position(|m| same_frame(m, &msg)) {
+                Some(i) => {
+                    self.delivered.remove(i);
+                }
+                None => push_bounded(&mut self.held, msg),
+            }
+        }
+        None
+    }
+
+    fn handle_error(&mut self, link: Link, error: String) {
+        log::debug!("Error receiving on the {link} link: {error}");
+        let now = Instant::now();
+        self.state.record_error(link, now);
+        self.retry_at[link.index()] = Some(now + ERROR_BACKOFF);
+        self.check(now);
+    }
+}
+
+impl<P, B> AsyncCanReceiver for RedundantReceiver<P, B>
+where
+    P: AsyncCanReceiver,
+    B: AsyncCanReceiver,
+{
+    type Error = core::convert::Infallible;
+
+    fn try_recv(&mut self) -> Option<CanMessage> {
+        if let Some(msg) = self.replay.pop_front() {
+            return Some(msg);
+        }
+        let link = self.active;
+        let msg = match link {
+            Link::Primary => self.primary.try_recv(),
+            Link::Backup => self.backup.try_recv(),
+        }?;
+        self.handle_frame(link, msg)
+            .or_else(|| self.replay.pop_front())
+    }
+
+    async fn recv(&mut self) -> Result<CanMessage, Self::Error> {
+        loop {
+            if let Some(msg) = self.replay.pop_front() {
+                return Ok(msg);
+            }
+            let [primary_retry, backup_retry] = self.retry_at;
+            // The errors of the two receivers may have different types, so they are only logged
+            let (link, result) = tokio::select! {
+                result = recv_after(&mut self.primary, primary_retry) => {
+                    (Link::Primary, result.map_err(|e| format!("{e:?}")))
+                }
+                result = recv_after(&mut self.backup, backup_retry) => {
+                    (Link::Backup, result.map_err(|e| format!("{e:?}")))
+                }
+            };
+            match result {
+                Ok(msg) => {
+                    self.retry_at[link.index()] = None;
+                    if let Some(msg) = self.handle_frame(link, msg) {
+                        return Ok(msg);
+                    }
+                }
+                Err(e) => self.handle_error(link, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> FailoverOptions {
+        FailoverOptions {
+            silence_timeout: Duration::from_millis(100),
+            error_timeout: Duration::from_millis(50),
+        }
+    }
+
+    #[test]
+    fn test_fail_over_on_silence() {
+        let state = FailoverState::new(options());
+        let (tx, mut rx) = broadcast::channel(4);
+        state.set_events(tx);
+        let start = Instant::now();
+
+        // Both links are quiet
+        assert!(!state.check(start + Duration::from_millis(200)));
+
+        // Only the backup is receiving frames
+        state.record_frame(Link::Backup, start + Duration::from_millis(150));
+        assert!(!state.check(start + Duration::from_millis(90)));
+        assert!(state.check(start + Duration::from_millis(200)));
+        assert_eq!(Link::Backup, state.active());
+        let Ok(BusEvent::Failover { from, to, reason }) = rx.try_recv() else {
+            panic!("Expected a failover event");
+        };
+        assert_eq!((Link::Primary, Link::Backup), (from, to));
+        assert!(matches!(reason, FailoverReason::Silent { .. }));
+
+        // The primary recovers, and is used again when the backup goes silent
+        state.record_frame(Link::Primary, start + Duration::from_millis(300));
+        assert!(state.check(start + Duration::from_millis(360)));
+        assert_eq!(Link::Primary, state.active());
+    }
+
+    #[test]
+    fn test_fail_over_on_errors() {
+        let state = FailoverState::new(options());
+        let start = Instant::now();
+        state.record_frame(Link::Primary, start);
+        state.record_frame(Link::Backup, start);
+
+        state.record_error(Link::Primary, start);
+        assert!(!state.check(start + Duration::from_millis(40)));
+        // A successful send clears the errors
+        state.record_sent(Link::Primary);
+        assert!(!state.check(start + Duration::from_millis(60)));
+
+        state.record_error(Link::Primary, start + Duration::from_millis(60));
+        // Failing over to a link which is also failing does not help
+        state.record_error(Link::Backup, start + Duration::from_millis(60));
+        assert!(!state.check(start + Duration::from_millis(120)));
+        state.record_sent(Link::Backup);
+        assert!(state.check(start + Duration::from_millis(120)));
+        assert_eq!(Link::Backup, state.active());
+
+        assert!(!state.set_active(Link::Backup));
+        assert!(state.set_active(Link::Primary));
+        assert_eq!(Link::Primary, state.active());
+    }
+}
@@ -0,0 +1,330 @@
+//! Configuration of the failsafe outputs of CiA 401 output devices
+//!
+//! An output device has, alongside each array of outputs, an error mode object which selects the
+//! outputs to change when the device detects an error, such as the loss of the master's heartbeat,
+//! and an error value object holding the values they change to:
+//!
+//! | Outputs                  | Error mode                  | Error value                     |
+//! | ------------------------ | --------------------------- | ------------------------------- |
+//! | 0x6200 digital, 8 bit    | 0x6206, u8 mask per block   | 0x6207, u8 per block            |
+//! | 0x6411 analogue, 16 bit  | 0x6443, u8 per channel      | 0x6444, i32 per channel         |
+//!
+//! [`SdoClient::configure_failsafe`] writes a [`FailsafeConfig`] to a node, and reads it back to
+//! check that the node accepted it.
+//! [`BusManager::configure_failsafe`](crate::BusManager::configure_failsafe) does this for a set of
+//! nodes, along with the heartbeat consumer entries which allow each node to detect that the
+//! master has been lost. A zencan node applies the configuration with a
+//! `zencan_node::failsafe::Failsafe`.
+use std::collections::HashMap;
+
+use snafu::{ResultExt, Snafu};
+use zencan_common::{
+    constants::object_ids,
+    traits::{AsyncCanReceiver, AsyncCanSender},
+};
+
+use crate::{HeartbeatConsumerError, SdoClient, SdoClientError};
+
+/// The failsafe behavior of a block of 8 digital outputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DigitalFailsafe {
+    /// The sub index of the block in the output objects, starting from 1
+    pub block: u8,
+    /// A bit mask of the outputs in the block which are set to their error value on an error
+    ///
+    /// Outputs which are not selected keep their value.
+    pub error_mode: u8,
+    /// The values of the selected outputs on an error
+    pub error_value: u8,
+}
+
+/// The failsafe behavior of an analogue output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnalogFailsafe {
+    /// The sub index of the channel in the output objects, starting from 1
+    pub channel: u8,
+    /// The value of the output on an error, or None if it keeps its value
+    pub error_value: Option<i32>,
+}
+
+/// The failsafe behavior of the outputs of a node
+///
+/// Outputs which are not listed are not changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FailsafeConfig {
+    /// The behavior of blocks of 8-bit digital outputs (0x6200)
+    pub digital_outputs: Vec<DigitalFailsafe>,
+    /// The behavior of 16-bit analogue outputs (0x6411)
+    pub analog_outputs: Vec<AnalogFailsafe>,
+}
+
+/// Error returned when configuring failsafe outputs
+#[derive(Debug, Clone, PartialEq, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum FailsafeError {
+    /// An output sub index is 0, or is given more than once
+    #[snafu(display("Output sub index {sub} of object 0x{index:04X} is invalid or repeated"))]
+    InvalidOutput {
+        /// The index of the error mode object
+        index: u16,
+        /// The invalid sub index
+        sub: u8,
+    },
+    /// The node has fewer outputs than the configuration refers to
+    #[snafu(display(
+        "Object 0x{index:04X} has {available} sub objects, but sub index {sub} was configured"
+    ))]
+    NoSuchOutput {
+        /// The index of the error mode or error value object
+        index: u16,
+        /// The configured sub index
+        sub: u8,
+        /// The highest sub index of the object on the node
+        available: u8,
+    },
+    /// The heartbeat consumer entries could not be configured
+    #[snafu(display("{source}"))]
+    HeartbeatConsumer {
+        /// The underlying error
+        source: HeartbeatConsumerError,
+    },
+    /// An SDO transfer to the node failed
+    #[snafu(display("SDO error accessing 0x{index:04X}sub{sub}: {source}"))]
+    Sdo {
+        /// The object index
+        index: u16,
+        /// The sub index
+        sub: u8,
+        /// The underlying SDO error
+        source: SdoClientError,
+    },
+    /// A value read back from the node does not match the value written
+    #[snafu(display("0x{index:04X}sub{sub} is {actual}, expected {expected}"))]
+    VerifyFailed {
+        /// The object index
+        index: u16,
+        /// The sub index
+        sub: u8,
+        /// The value written
+        expected: i32,
+        /// The value read back
+        actual: i32,
+    },
+}
+
+/// A value written to an error mode or error value object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SettingValue {
+    U8(u8),
+    I32(i32),
+}
+
+impl SettingValue {
+    fn as_i32(self) -> i32 {
+        match self {
+            SettingValue::U8(value) => value as i32,
+            SettingValue::I32(value) => value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Setting {
+    index: u16,
+    sub: u8,
+    value: SettingValue,
+}
+
+impl FailsafeConfig {
+    /// Get the sub objects to write, in order
+    ///
+    /// The error values are written before the error modes, so that an output is never selected
+    /// while it has an old error value.
+    fn settings(&self) -> Result<Vec<Setting>, FailsafeError> {
+        let digital_subs: Vec<u8> = self.digital_outputs.iter().map(|d| d.block).collect();
+        check_subs(object_ids::DIGITAL_OUTPUT_ERROR_MODE_8, &digital_subs)?;
+        let analog_subs: Vec<u8> = self.analog_outputs.iter().map(|a| a.channel).collect();
+        check_subs(object_ids::ANALOG_OUTPUT_ERROR_MODE, &analog_subs)?;
+
+        let mut settings = Vec::new();
+        for d in &self.digital_outputs {
+            settings.push(Setting {
+                index: object_ids::DIGITAL_OUTPUT_ERROR_VALUE_8,
+                sub: d.block,
+                value: SettingValue::U8(d.error_value),
+            });
+        }
+        for a in &self.analog_outputs {
+            if let Some(value) = a.error_value {
+                settings.push(Setting {
+                    index: object_ids::ANALOG_OUTPUT_ERROR_VALUE,
+                    sub: a.channel,
+                    value: SettingValue::I32(value),
+                });
+            }
+        }
+        for d in &self.digital_outputs {
+            settings.push(Setting {
+                index: object_ids::DIGITAL_OUTPUT_ERROR_MODE_8,
+                sub: d.block,
+                value: SettingValue::U8(d.error_mode),
+            });
+        }
+        for a in &self.analog_outputs {
+            settings.push(Setting {
+                index: object_ids::ANALOG_OUTPUT_ERROR_MODE,
+                sub: a.channel,
+                value: SettingValue::U8(a.error_value.is_some() as u8),
+            });
+        }
+        Ok(settings)
+    }
+}
+
+/// Check that the sub indices of one output type are valid and unique
+fn check_subs(index: u16, subs: &[u8]) -> Result<(), FailsafeError> {
+    for (i, &sub) in subs.iter().enumerate() {
+        if sub == 0 || subs[..i].contains(&sub) {
+            return InvalidOutputSnafu { index, sub }.fail();
+        }
+    }
+    Ok(())
+}
+
+impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
+    /// Configure the failsafe outputs of the node, and read them back to verify them
+    ///
+    /// Before anything is written, the node is checked to have every configured output. See the
+    /// [module docs](crate::failsafe).
+    pub async fn configure_failsafe(
+        &mut self,
+        config: &FailsafeConfig,
+    ) -> Result<(), FailsafeError> {
+        let settings = config.settings()?;
+
+        let mut available = HashMap::new();
+        for setting in &settings {
+            let index = setting.index;
+            let max_sub = match available.get(&index) {
+                Some(&max_sub) => max_sub,
+                None => {
+                    let max_sub = self
+                        .upload_u8(index, 0)
+                        .await
+                        .context(SdoSnafu { index, sub: 0 })?;
+                    available.insert(index, max_sub);
+                    max_sub
+                }
+            };
+            if setting.sub > max_sub {
+                return NoSuchOutputSnafu {
+                    index,
+                    sub: setting.sub,
+                    available: max_sub,
+                }
+                .fail();
+            }
+        }
+
+        for setting in &settings {
+            let Setting { index, sub, value } = *setting;
+            let result = match value {
+                SettingValue::U8(value) => self.download_u8(index, sub, value).await,
+                SettingValue::I32(value) => self.download_i32(index, sub, value).await,
+            };
+            result.context(SdoSnafu { index, sub })?;
+        }
+        self.check_failsafe_settings(&settings).await
+    }
+
+    /// Check that the failsafe outputs of the node match a configuration
+    pub async fn verify_failsafe(&mut self, config: &FailsafeConfig) -> Result<(), FailsafeError> {
+        let settings = config.settings()?;
+        self.check_failsafe_settings(&settings).await
+    }
+
+    async fn check_failsafe_settings(&mut self, settings: &[Setting]) -> Result<(), FailsafeError> {
+        for setting in settings {
+            let Setting { index, sub, value } = *setting;
+            let actual = match value {
+                SettingValue::U8(_) => self.upload_u8(index, sub).await.map(|v| v as i32),
+                SettingValue::I32(_) => self.upload_i32(index, sub).await,
+            }
+            .context(SdoSnafu { index, sub })?;
+            let expected = value.as_i32();
+            if actual != expected {
+                return VerifyFailedSnafu {
+                    index,
+                    sub,
+                    expected,
+                    actual,
+                }
+                .fail();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_order() {
+        let config = FailsafeConfig {
+            digital_outputs: vec![DigitalFailsafe {
+                block: 1,
+                error_mode: 0x0F,
+                error_value: 0x05,
+            }],
+            analog_outputs: vec![
+                AnalogFailsafe {
+                    channel: 2,
+                    error_value: Some(-100),
+                },
+                AnalogFailsafe {
+                    channel: 1,
+                    error_value: None,
+                },
+            ],
+        };
+        let settings: Vec<_> = config
+            .settings()
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.index, s.sub, s.value.as_i32()))
+            .collect();
+        assert_eq!(
+            vec![
+                (0x6207, 1, 0x05),
+                (0x6444, 2, -100),
+                (0x6206, 1, 0x0F),
+                (0x6443, 2, 1),
+                (0x6443, 1, 0),
+            ],
+            settings
+        );
+
+        let config = FailsafeConfig {
+            digital_outputs: vec![],
+            analog_outputs: vec![
+                AnalogFailsafe {
+                    channel: 1,
+                    error_value: None,
+                },
+                AnalogFailsafe {
+                    channel: 1,
+                    error_value: Some(1),
+                },
+            ],
+        };
+        assert_eq!(
+            Err(FailsafeError::InvalidOutput {
+                index: 0x6443,
+                sub: 1
+            }),
+            config.settings()
+        );
+    }
+}
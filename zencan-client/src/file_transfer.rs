@@ -0,0 +1,168 @@
+//! Transfers between files and domain objects
+//!
+//! Domain objects hold data which does not fit the usual object types, such as logs recorded by a
+//! node, or assets used by its application. [`SdoClient::upload_to_file`] and
+//! [`SdoClient::download_from_file`] move the contents of a domain to or from a file, reporting
+//! progress as the transfer runs.
+//!
+//! Downloads use the same protocol selection as [`SdoClient::write`], so large files are sent with
+//! a block download when the server supports it. Uploads are written to the file as each segment
+//! is received, rather than being collected in memory first.
+//!
+//! A transfer which fails with a retryable error, such as a timeout, is restarted. SDO has no way
+//! to continue a transfer part way through, so each retry starts again from the beginning of the
+//! data, and an upload truncates the partially written file. Devices which accept an offset for
+//! writes to an object can instead be sent a [resumable download](crate::resumable_download).
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use snafu::{ResultExt, Snafu};
+use zencan_common::{
+    sdo::AbortCode,
+    traits::{AsyncCanReceiver, AsyncCanSender},
+};
+
+use crate::{SdoClient, SdoClientError};
+
+/// The progress of a file transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferProgress {
+    /// The number of bytes transferred so far in this attempt
+    pub bytes: usize,
+    /// The total size of the transfer, if known
+    ///
+    /// The size of an upload is known only if the server indicates it.
+    pub total: Option<usize>,
+    /// The attempt number, starting from 1
+    pub attempt: u32,
+}
+
+/// Error returned by a file transfer
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum FileTransferError {
+    /// The file could not be read or written
+    #[snafu(display("Error accessing {}: {source}", path.display()))]
+    Io {
+        /// The file being transferred
+        path: PathBuf,
+        /// The underlying IO error
+        source: std::io::Error,
+    },
+    /// The SDO transfer failed
+    #[snafu(display("Transfer failed after {attempts} attempt(s): {source}"))]
+    Sdo {
+        /// The number of times the transfer was attempted
+        attempts: u32,
+        /// The error from the last attempt
+        source: SdoClientError,
+    },
+}
+
+impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
+    /// Upload the contents of a sub object to a file, returning the number of bytes written
+    ///
+    /// The file is created, or truncated if it exists. `on_progress` is called after each
+    /// response from the server. See the [module docs](crate::file_transfer) for how failed
+    /// transfers are retried.
+    pub async fn upload_to_file(
+        &mut self,
+        index: u16,
+        sub: u8,
+        path: impl AsRef<Path>,
+        mut on_progress: impl FnMut(TransferProgress) + Send,
+    ) -> Result<usize, FileTransferError> {
+        let path = path.as_ref();
+        let mut attempt = 1;
+        loop {
+            let file = File::create(path).context(IoSnafu { path })?;
+            let mut writer = BufWriter::new(file);
+            let mut written = 0;
+            let mut write_error = None;
+            let result = self
+                .upload_with_progress(
+                    index,
+                    sub,
+                    &mut |data| {
+                        if write_error.is_none() {
+                            match writer.write_all(data) {
+                                Ok(()) => written += data.len(),
+                                Err(e) => write_error = Some(e),
+                            }
+                        }
+                    },
+                    &mut |bytes, total| {
+                        on_progress(TransferProgress {
+                            bytes,
+                            total,
+                            attempt,
+                        })
+                    },
+                )
+                .await;
+            if let Some(e) = write_error {
+                return Err(e).context(IoSnafu { path });
+            }
+            match result {
+                Ok(()) => {
+                    writer.flush().context(IoSnafu { path })?;
+                    return Ok(written);
+                }
+                Err(e) => self.retry_or_fail(index, sub, &mut attempt, e).await?,
+            }
+        }
+    }
+
+    /// Download the contents of a file to a sub object, returning the number of bytes written
+    ///
+    /// The file is read into memory before the transfer starts, as the server must be told the
+    /// size of the data up front. `on_progress` is called after each response from the server. See
+    /// the [module docs](crate::file_transfer) for how failed transfers are retried.
+    pub async fn download_from_file(
+        &mut self,
+        index: u16,
+        sub: u8,
+        path: impl AsRef<Path>,
+        mut on_progress: impl FnMut(TransferProgress) + Send,
+    ) -> Result<usize, FileTransferError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).context(IoSnafu { path })?;
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .write_with_progress(index, sub, &data, &mut |bytes, total| {
+                    on_progress(TransferProgress {
+                        bytes,
+                        total,
+                        attempt,
+                    })
+                })
+                .await;
+            match result {
+                Ok(()) => return Ok(data.len()),
+                Err(e) => self.retry_or_fail(index, sub, &mut attempt, e).await?,
+            }
+        }
+    }
+
+    /// Prepare to retry a failed transfer, or return the error if it cannot be retried
+    async fn retry_or_fail(
+        &mut self,
+        index: u16,
+        sub: u8,
+        attempt: &mut u32,
+        error: SdoClientError,
+    ) -> Result<(), FileTransferError> {
+        if !error.is_retryable() || *attempt > self.transfer_retries() {
+            return Err(error).context(SdoSnafu { attempts: *attempt });
+        }
+        log::warn!("Transfer of 0x{index:X}sub{sub} failed ({error}), restarting");
+        // The server may still be waiting for the rest of the failed transfer
+        self.send_abort(index, sub, AbortCode::SdoTimeout).await;
+        *attempt += 1;
+        Ok(())
+    }
+}
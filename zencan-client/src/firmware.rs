@@ -0,0 +1,236 @@
+//! Firmware updates using the zencan bootloader objects
+//!
+//! Nodes which include a bootloader implement the bootloader info object (0x5500), and one section
+//! object (0x5510 + N) for each programmable section. A node is programmed by:
+//!
+//! 1. Commanding the application to reset into the bootloader, if the bootloader is not already
+//!    running
+//! 2. Erasing the section
+//! 3. Downloading the new image to the section's data sub object
+//! 4. Resetting the node with an NMT command, so that the new application starts
+//!
+//! After the reset, the software version (0x100A) is read back from the application so that the
+//! update can be verified. See [`BusManager::flash_node`](crate::BusManager::flash_node) and
+//! [`BusManager::flash_nodes`](crate::BusManager::flash_nodes).
+use std::time::{Duration, Instant};
+
+use snafu::{ResultExt, Snafu};
+use zencan_common::{
+    constants::values::{BOOTLOADER_ERASE_CMD, BOOTLOADER_RESET_CMD},
+    traits::{AsyncCanReceiver, AsyncCanSender},
+};
+
+use crate::{SdoClient, SdoClientError};
+
+/// The bootloader info object index
+pub const BOOTLOADER_INFO_INDEX: u16 = 0x5500;
+/// The index of the first bootloader section object
+pub const BOOTLOADER_SECTION_INDEX: u16 = 0x5510;
+/// The bit in the bootloader config (0x5500sub1) which is set while the application is running
+const CONFIG_APP_BIT: u32 = 1 << 1;
+
+/// Options controlling a firmware update
+#[derive(Debug, Clone, Copy)]
+pub struct FlashOptions {
+    /// The bootloader section to program
+    ///
+    /// Default: 0
+    pub section: u8,
+    /// Time to wait for each SDO response while erasing and downloading the image
+    ///
+    /// Erasing flash can be slow, so this is longer than the usual SDO timeout.
+    ///
+    /// Default: 2s
+    pub transfer_timeout: Duration,
+    /// The maximum time to wait for a node to come back after each reset
+    ///
+    /// Default: 10s
+    pub reboot_timeout: Duration,
+    /// The time between attempts to contact a node while waiting for it to reset
+    ///
+    /// Default: 200ms
+    pub poll_interval: Duration,
+}
+
+impl Default for FlashOptions {
+    fn default() -> Self {
+        Self {
+            section: 0,
+            transfer_timeout: Duration::from_secs(2),
+            reboot_timeout: Duration::from_secs(10),
+            poll_interval: Duration::from_millis(200),
+        }
+    }
+}
+
+/// The steps of a firmware update, used to report where an update failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashStage {
+    /// Reading the bootloader info object
+    ReadBootloaderInfo,
+    /// Resetting the application into the bootloader
+    EnterBootloader,
+    /// Erasing the section
+    Erase,
+    /// Downloading the image
+    Download,
+    /// Resetting the bootloader into the new application
+    StartApplication,
+    /// Reading the software version from the new application
+    ReadVersion,
+}
+
+impl core::fmt::Display for FlashStage {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let s = match self {
+            FlashStage::ReadBootloaderInfo => "read bootloader info",
+            FlashStage::EnterBootloader => "enter bootloader",
+            FlashStage::Erase => "erase section",
+            FlashStage::Download => "download image",
+            FlashStage::StartApplication => "start application",
+            FlashStage::ReadVersion => "read software version",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Error returned when a firmware update fails
+#[derive(Debug, Clone, PartialEq, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum FlashError {
+    /// An SDO access failed
+    #[snafu(display("Failed to {stage} on node {node_id}: {source}"))]
+    Sdo {
+        /// The node being updated
+        node_id: u8,
+        /// The step which failed
+        stage: FlashStage,
+        /// The underlying SDO error
+        source: SdoClientError,
+    },
+    /// The node did not respond in the expected mode after a reset
+    #[snafu(display("Timed out waiting for node {node_id} to {stage}"))]
+    Timeout {
+        /// The node being updated
+        node_id: u8,
+        /// The step which timed out
+        stage: FlashStage,
+    },
+    /// The NMT reset command could not be sent
+    #[snafu(display("Failed to send NMT reset to node {node_id}"))]
+    NmtSendFailed {
+        /// The node being updated
+        node_id: u8,
+    },
+}
+
+/// The result of a successful firmware update
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlashReport {
+    /// The node which was updated
+    pub node_id: u8,
+    /// The software version read before the update, if the application was running and reported
+    /// one
+    pub previous_version: Option<String>,
+    /// The software version read from the new application
+    pub version: String,
+}
+
+/// Read the bootloader config, and return true if the application is running
+pub(crate) async fn is_app_running<S: AsyncCanSender, R: AsyncCanReceiver>(
+    client: &mut SdoClient<S, R>,
+) -> Result<bool, SdoClientError> {
+    let config = client.read_u32(BOOTLOADER_INFO_INDEX, 1).await?;
+    Ok(config & CONFIG_APP_BIT != 0)
+}
+
+/// Command a running application to reset into its bootloader
+pub(crate) async fn command_bootloader_reset<S: AsyncCanSender, R: AsyncCanReceiver>(
+    client: &mut SdoClient<S, R>,
+) -> Result<(), SdoClientError> {
+    match client
+        .write_u32(BOOTLOADER_INFO_INDEX, 3, BOOTLOADER_RESET_CMD)
+        .await
+    {
+        // The node may reset before it responds
+        Ok(()) | Err(SdoClientError::NoResponse | SdoClientError::BusSilent { .. }) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Erase a section, and download a new image to it
+pub(crate) async fn program_section<S: AsyncCanSender, R: AsyncCanReceiver>(
+    client: &mut SdoClient<S, R>,
+    node_id: u8,
+    section: u8,
+    image: &[u8],
+) -> Result<(), FlashError> {
+    let index = BOOTLOADER_SECTION_INDEX + section as u16;
+    client
+        .write_u32(index, 3, BOOTLOADER_ERASE_CMD)
+        .await
+        .context(SdoSnafu {
+            node_id,
+            stage: FlashStage::Erase,
+        })?;
+    client.write(index, 4, image).await.context(SdoSnafu {
+        node_id,
+        stage: FlashStage::Download,
+    })
+}
+
+/// Poll a node until it reports the expected mode in its bootloader config
+///
+/// Nodes which do not respond, or respond with an error, are assumed to still be resetting.
+pub(crate) async fn wait_for_mode<S: AsyncCanSender, R: AsyncCanReceiver>(
+    client: &mut SdoClient<S, R>,
+    node_id: u8,
+    app: bool,
+    stage: FlashStage,
+    opts: &FlashOptions,
+) -> Result<(), FlashError> {
+    let deadline = Instant::now() + opts.reboot_timeout;
+    client.set_timeout(opts.poll_interval);
+    loop {
+        let poll_start = Instant::now();
+        if let Ok(running) = is_app_running(client).await {
+            if running == app {
+                return Ok(());
+            }
+        }
+        if Instant::now() >= deadline {
+            return TimeoutSnafu { node_id, stage }.fail();
+        }
+        // Don't spin when a node responds quickly with the wrong mode
+        let elapsed = poll_start.elapsed();
+        if elapsed < opts.poll_interval {
+            tokio::time::sleep(opts.poll_interval - elapsed).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flash_error_display() {
+        let err = FlashError::Sdo {
+            node_id: 4,
+            stage: FlashStage::Erase,
+            source: SdoClientError::NoResponse,
+        };
+        assert!(err
+            .to_string()
+            .starts_with("Failed to erase section on node 4"));
+
+        let err = FlashError::Timeout {
+            node_id: 5,
+            stage: FlashStage::StartApplication,
+        };
+        assert_eq!(
+            "Timed out waiting for node 5 to start application",
+            err.to_string()
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Frame level recordings of bus traffic
+//!
+//! A [`FrameRecording`] is a sequence of timestamped frames received and transmitted by the nodes
+//! on a bus. Recordings are made by a [`BusManager`](crate::BusManager), with
+//! [`start_capture`](crate::BusManager::start_capture), or by a `NodeFixture` in tests, and can be
+//! replayed against a node, or used as a mock of the nodes when testing a client, with the
+//! `testing::replay` module (requires the `testing` feature).
+//!
+//! The direction of each frame is given from the point of view of the nodes: frames sent by the
+//! client are received (`rx`), and the responses of the nodes are transmitted (`tx`).
+//!
+//! Recordings are stored as text, with one frame per line, in the form `<time_us> <rx|tx>
+//! <id>#<data>`. The ID is in hex, with 8 digits for an extended ID, and the data is a string of
+//! hex bytes, or `R` for a remote request, as in the candump log format. Blank lines and lines
+//! starting with `#` are ignored.
+//!
+//! ```text
+//! # Read the device type
+//! 0 tx 701#00
+//! 1000 rx 601#4000100000000000
+//! 1000 tx 581#4300100000000000
+//! ```
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use snafu::Snafu;
+use zencan_common::{
+    messages::{CanId, CanMessage},
+    value::parse_hex_bytes,
+};
+
+/// Whether a recorded frame was received or transmitted by the node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// A frame received by the node
+    Rx,
+    /// A frame transmitted by the node
+    Tx,
+}
+
+/// A frame received or transmitted by a node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordedFrame {
+    /// The time of the frame, in microseconds since the start of the recording
+    pub time_us: u64,
+    /// Whether the node received or transmitted the frame
+    pub direction: Direction,
+    /// The frame
+    pub msg: CanMessage,
+}
+
+impl core::fmt::Display for RecordedFrame {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let direction = match self.direction {
+            Direction::Rx => "rx",
+            Direction::Tx => "tx",
+        };
+        write!(f, "{} {direction} ", self.time_us)?;
+        match self.msg.id() {
+            CanId::Std(id) => write!(f, "{id:03X}#")?,
+            CanId::Extended(id) => write!(f, "{id:08X}#")?,
+        }
+        if self.msg.is_rtr() {
+            write!(f, "R")
+        } else {
+            self.msg
+                .data()
+                .iter()
+                .try_for_each(|b| write!(f, "{b:02X}"))
+        }
+    }
+}
+
+/// Error returned when parsing a [`FrameRecording`] fails
+#[derive(Debug, Snafu)]
+#[snafu(display("Line {line}: {message}"))]
+pub struct ParseRecordingError {
+    /// The line number at which the error occurred, starting from 1
+    pub line: usize,
+    /// A description of the error
+    pub message: String,
+}
+
+fn parse_frame(line: &str) -> Result<RecordedFrame, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [time, direction, frame] = fields[..] else {
+        return Err(format!(
+            "Expected '<time_us> <rx|tx> <id>#<data>', got '{line}'"
+        ));
+    };
+    let time_us = time.parse().map_err(|_| format!("Invalid time '{time}'"))?;
+    let direction = match direction {
+        "rx" => Direction::Rx,
+        "tx" => Direction::Tx,
+        other => return Err(format!("Invalid direction '{other}'")),
+    };
+    let (id, data) = frame
+        .split_once('#')
+        .ok_or_else(|| format!("Expected '<id>#<data>', got '{frame}'"))?;
+    let raw_id = u32::from_str_radix(id, 16).map_err(|_| format!("Invalid ID '{id}'"))?;
+    let id = match id.len() {
+        1..=3 if raw_id <= 0x7FF => CanId::std(raw_id as u16),
+        8 if raw_id <= 0x1FFF_FFFF => CanId::extended(raw_id),
+        _ => return Err(format!("Invalid ID '{id}'")),
+    };
+    let msg = if data == "R" {
+        CanMessage::new_rtr(id)
+    } else {
+        let bytes = parse_hex_bytes(data)
+            .ok_or_else(|| format!("'{data}' is not a string of hex bytes"))?;
+        CanMessage::try_new(id, &bytes).map_err(|e| e.to_string())?
+    };
+    Ok(RecordedFrame {
+        time_us,
+        direction,
+        msg,
+    })
+}
+
+/// A sequence of frames received and transmitted by a node
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameRecording {
+    /// The frames, in the order they were received or transmitted
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl FrameRecording {
+    /// Create an empty recording
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a frame to the end of the recording
+    pub fn push(&mut self, time_us: u64, direction: Direction, msg: CanMessage) {
+        self.frames.push(RecordedFrame {
+            time_us,
+            direction,
+            msg,
+        });
+    }
+
+    /// Get the frames received by the node
+    pub fn rx_frames(&self) -> impl Iterator<Item = &RecordedFrame> {
+        self.frames.iter().filter(|f| f.direction == Direction::Rx)
+    }
+
+    /// Get the frames transmitted by the node
+    pub fn tx_frames(&self) -> impl Iterator<Item = &RecordedFrame> {
+        self.frames.iter().filter(|f| f.direction == Direction::Tx)
+    }
+
+    /// Get the time of the last frame, or 0 if the recording is empty
+    pub fn end_us(&self) -> u64 {
+        self.frames.iter().map(|f| f.time_us).max().unwrap_or(0)
+    }
+}
+
+impl FromStr for FrameRecording {
+    type Err = ParseRecordingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let frames = s
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| {
+                parse_frame(line).map_err(|message| ParseRecordingError {
+                    line: i + 1,
+                    message,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { frames })
+    }
+}
+
+impl core::fmt::Display for FrameRecording {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for frame in &self.frames {
+            writeln!(f, "{frame}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Records the frames sent and received by a bus manager, while a capture is running
+///
+/// Clones share the same capture.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FrameCapture {
+    inner: Arc<Mutex<Option<(Instant, FrameRecording)>>>,
+}
+
+impl FrameCapture {
+    /// Start a new capture, discarding any capture in progress
+    pub fn start(&self) {
+        *self.inner.lock().unwrap() = Some((Instant::now(), FrameRecording::new()));
+    }
+
+    /// Stop the capture, and return the frames recorded, in order of time
+    pub fn stop(&self) -> Option<FrameRecording> {
+        let (_, mut recording) = self.inner.lock().unwrap().take()?;
+        // Sent and received frames are recorded by different tasks
+        recording.frames.sort_by_key(|f| f.time_us);
+        Some(recording)
+    }
+
+    /// Returns true if a capture is running
+    pub fn is_active(&self) -> bool {
+        self.inner.lock().unwrap().is_some()
+    }
+
+    /// Record a frame, if a capture is running
+    pub fn record(&self, direction: Direction, msg: CanMessage, at: Instant) {
+        if let Some((start, recording)) = self.inner.lock().unwrap().as_mut() {
+            let time_us = at.saturating_duration_since(*start).as_micros() as u64;
+            recording.push(time_us, direction, msg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recording_round_trip() {
+        let text = "\
+# A comment
+
+0 tx 701#00
+1500 rx 601#4000100000000000
+1500 tx 581#4300100000000000
+2000 rx 1FFFFFFF#
+2500 rx 123#R
+";
+        let recording: FrameRecording = text.parse().unwrap();
+        assert_eq!(5, recording.frames.len());
+        assert_eq!(
+            RecordedFrame {
+                time_us: 1500,
+                direction: Direction::Rx,
+                msg: CanMessage::new(CanId::std(0x601), &[0x40, 0, 0x10, 0, 0, 0, 0, 0]),
+            },
+            recording.frames[1]
+        );
+        assert_eq!(CanId::extended(0x1FFFFFFF), recording.frames[3].msg.id());
+        assert!(recording.frames[4].msg.is_rtr());
+        assert_eq!(2, recording.tx_frames().count());
+        assert_eq!(2500, recording.end_us());
+
+        let formatted = recording.to_string();
+        assert_eq!(
+            text.lines().skip(2).collect::<Vec<_>>().join("\n") + "\n",
+            formatted
+        );
+        assert_eq!(recording, formatted.parse().unwrap());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = "0 tx 701#00\n1 rx 601"
+            .parse::<FrameRecording>()
+            .unwrap_err();
+        assert_eq!(2, err.line);
+        assert!("x tx 701#00".parse::<FrameRecording>().is_err());
+        assert!("0 up 701#00".parse::<FrameRecording>().is_err());
+        assert!("0 tx 801#00".parse::<FrameRecording>().is_err());
+        assert!("0 tx 701#0".parse::<FrameRecording>().is_err());
+        assert!("0 tx 701#000000000000000000"
+            .parse::<FrameRecording>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_capture() {
+        let capture = FrameCapture::default();
+        let msg = CanMessage::new(CanId::std(0x601), &[0x40, 0, 0x10, 0, 0, 0, 0, 0]);
+        // Frames are not recorded until a capture starts
+        capture.record(Direction::Rx, msg, Instant::now());
+        assert!(!capture.is_active());
+        assert_eq!(None, capture.stop());
+
+        capture.start();
+        let start = Instant::now();
+        let later = start + std::time::Duration::from_millis(2);
+        capture.record(
+            Direction::Tx,
+            CanMessage::new(CanId::std(0x581), &[0x43]),
+            later,
+        );
+        capture.record(Direction::Rx, msg, start);
+        assert!(capture.is_active());
+
+        let recording = capture.stop().unwrap();
+        assert!(!capture.is_active());
+        let directions: Vec<_> = recording.frames.iter().map(|f| f.direction).collect();
+        assert_eq!(vec![Direction::Rx, Direction::Tx], directions);
+        assert!(recording.frames[1].time_us >= 2000);
+    }
+}
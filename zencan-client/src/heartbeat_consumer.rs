@@ -0,0 +1,133 @@
+//! Configuration of heartbeat consumers (object 0x1016)
+//!
+//! A node with heartbeat consumer entries monitors the heartbeats of other nodes, and detects when
+//! one of them stops producing heartbeats. Each entry of object 0x1016 is a u32 holding the node ID
+//! of the monitored node in bits 16-23, and the timeout in milliseconds in bits 0-15.
+use std::time::Duration;
+
+use snafu::Snafu;
+
+use crate::SdoClientError;
+
+/// Error returned when configuring heartbeat consumers
+#[derive(Debug, Clone, PartialEq, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum HeartbeatConsumerError {
+    /// A peer node ID is not a valid node ID
+    #[snafu(display("Invalid heartbeat consumer peer node ID {peer}"))]
+    InvalidPeer {
+        /// The invalid node ID
+        peer: u8,
+    },
+    /// The same peer was given more than once
+    #[snafu(display("Heartbeat consumer peer {peer} is listed more than once"))]
+    DuplicatePeer {
+        /// The repeated node ID
+        peer: u8,
+    },
+    /// A timeout is zero, or too large to be represented in milliseconds as a u16
+    #[snafu(display("Invalid heartbeat timeout {timeout:?} for peer {peer}"))]
+    InvalidTimeout {
+        /// The peer node ID
+        peer: u8,
+        /// The invalid timeout
+        timeout: Duration,
+    },
+    /// The node has fewer consumer entries than the number of peers
+    #[snafu(display(
+        "Node {node_id} supports {available} heartbeat consumers, but {requested} were requested"
+    ))]
+    TooManyPeers {
+        /// The node being configured
+        node_id: u8,
+        /// The number of peers requested
+        requested: usize,
+        /// The number of entries in the node's 0x1016 object
+        available: usize,
+    },
+    /// An SDO transfer to the node failed
+    #[snafu(display("SDO error configuring heartbeat consumers of node {node_id}: {source}"))]
+    Sdo {
+        /// The node being configured
+        node_id: u8,
+        /// The underlying SDO error
+        source: SdoClientError,
+    },
+    /// An entry read back from the node does not match the value written
+    #[snafu(display(
+        "Heartbeat consumer entry {sub} of node {node_id} is 0x{actual:08X}, expected 0x{expected:08X}"
+    ))]
+    VerifyFailed {
+        /// The node being configured
+        node_id: u8,
+        /// The sub index of the entry
+        sub: u8,
+        /// The value written
+        expected: u32,
+        /// The value read back
+        actual: u32,
+    },
+}
+
+/// Encode the heartbeat consumer entries for a list of peers
+///
+/// Returns the entries in the order given, after validating the peer IDs and timeouts.
+pub(crate) fn encode_entries(
+    consumers: &[(u8, Duration)],
+) -> Result<Vec<u32>, HeartbeatConsumerError> {
+    let mut entries = Vec::with_capacity(consumers.len());
+    for (i, &(peer, timeout)) in consumers.iter().enumerate() {
+        if !(1..=127).contains(&peer) {
+            return InvalidPeerSnafu { peer }.fail();
+        }
+        if consumers[..i].iter().any(|(p, _)| *p == peer) {
+            return DuplicatePeerSnafu { peer }.fail();
+        }
+        let timeout_ms = timeout.as_millis();
+        if timeout_ms == 0 || timeout_ms > u16::MAX as u128 {
+            return InvalidTimeoutSnafu { peer, timeout }.fail();
+        }
+        entries.push(encode_entry(peer, timeout_ms as u16));
+    }
+    Ok(entries)
+}
+
+/// Encode a single heartbeat consumer entry
+pub(crate) fn encode_entry(peer: u8, timeout_ms: u16) -> u32 {
+    (peer as u32) << 16 | timeout_ms as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_entries() {
+        let entries = encode_entries(&[
+            (5, Duration::from_millis(500)),
+            (127, Duration::from_millis(1)),
+        ])
+        .unwrap();
+        assert_eq!(vec![0x0005_01F4, 0x007F_0001], entries);
+
+        assert_eq!(
+            Err(HeartbeatConsumerError::InvalidPeer { peer: 0 }),
+            encode_entries(&[(0, Duration::from_millis(100))])
+        );
+        assert_eq!(
+            Err(HeartbeatConsumerError::DuplicatePeer { peer: 3 }),
+            encode_entries(&[
+                (3, Duration::from_millis(100)),
+                (3, Duration::from_millis(200))
+            ])
+        );
+        assert!(matches!(
+            encode_entries(&[(3, Duration::from_secs(70))]),
+            Err(HeartbeatConsumerError::InvalidTimeout { peer: 3, .. })
+        ));
+        assert!(matches!(
+            encode_entries(&[(3, Duration::ZERO)]),
+            Err(HeartbeatConsumerError::InvalidTimeout { peer: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,186 @@
+//! Verification of node identity against expected values
+use snafu::Snafu;
+use zencan_common::lss::LssIdentity;
+
+use crate::SdoClientError;
+
+/// A pattern to match against a node's identity object (0x1018)
+///
+/// Each field is either an exact value, or `None` to match any value.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct IdentityMatch {
+    /// Expected vendor ID
+    pub vendor_id: Option<u32>,
+    /// Expected product code
+    pub product_code: Option<u32>,
+    /// Expected revision number
+    pub revision: Option<u32>,
+    /// Expected serial number
+    pub serial: Option<u32>,
+}
+
+impl From<LssIdentity> for IdentityMatch {
+    fn from(id: LssIdentity) -> Self {
+        Self {
+            vendor_id: Some(id.vendor_id),
+            product_code: Some(id.product_code),
+            revision: Some(id.revision),
+            serial: Some(id.serial),
+        }
+    }
+}
+
+impl IdentityMatch {
+    /// Create a pattern which matches any identity
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    /// Require a specific vendor ID
+    pub fn vendor_id(mut self, vendor_id: u32) -> Self {
+        self.vendor_id = Some(vendor_id);
+        self
+    }
+
+    /// Require a specific product code
+    pub fn product_code(mut self, product_code: u32) -> Self {
+        self.product_code = Some(product_code);
+        self
+    }
+
+    /// Require a specific revision number
+    pub fn revision(mut self, revision: u32) -> Self {
+        self.revision = Some(revision);
+        self
+    }
+
+    /// Require a specific serial number
+    pub fn serial(mut self, serial: u32) -> Self {
+        self.serial = Some(serial);
+        self
+    }
+
+    /// Compare an identity against the pattern, and return a list of fields which do not match
+    pub fn mismatches(&self, identity: &LssIdentity) -> Vec<IdentityMismatch> {
+        [
+            ("vendor_id", self.vendor_id, identity.vendor_id),
+            ("product_code", self.product_code, identity.product_code),
+            ("revision", self.revision, identity.revision),
+            ("serial", self.serial, identity.serial),
+        ]
+        .into_iter()
+        .filter_map(|(field, expected, actual)| match expected {
+            Some(expected) if expected != actual => Some(IdentityMismatch {
+                field,
+                expected,
+                actual,
+            }),
+            _ => None,
+        })
+        .collect()
+    }
+
+    /// Returns true if the identity matches the pattern
+    pub fn matches(&self, identity: &LssIdentity) -> bool {
+        self.mismatches(identity).is_empty()
+    }
+}
+
+/// A single identity field which did not match the expected value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IdentityMismatch {
+    /// The name of the mismatched field
+    pub field: &'static str,
+    /// The expected value
+    pub expected: u32,
+    /// The value read from the node
+    pub actual: u32,
+}
+
+impl core::fmt::Display for IdentityMismatch {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} is 0x{:X}, expected 0x{:X}",
+            self.field, self.actual, self.expected
+        )
+    }
+}
+
+/// Error returned when verifying a node's identity
+#[derive(Debug, Clone, PartialEq, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum IdentityError {
+    /// The identity object could not be read from the node
+    #[snafu(display("Failed to read identity of node {node_id}: {source}"))]
+    ReadFailed {
+        /// The node which was being verified
+        node_id: u8,
+        /// The underlying SDO error
+        source: SdoClientError,
+    },
+    /// The node's identity did not match the expected pattern
+    #[snafu(display(
+        "Identity of node {node_id} does not match: {}",
+        display_mismatches(mismatches)
+    ))]
+    Mismatch {
+        /// The node which was being verified
+        node_id: u8,
+        /// The identity read from the node
+        actual: LssIdentity,
+        /// The fields which did not match
+        mismatches: Vec<IdentityMismatch>,
+    },
+}
+
+fn display_mismatches(mismatches: &[IdentityMismatch]) -> String {
+    mismatches
+        .iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_match() {
+        let id = LssIdentity::new(1, 2, 3, 4);
+        assert!(IdentityMatch::any().matches(&id));
+        assert!(IdentityMatch::from(id).matches(&id));
+        assert!(IdentityMatch::any()
+            .vendor_id(1)
+            .product_code(2)
+            .matches(&id));
+
+        let pattern = IdentityMatch::any().vendor_id(1).revision(5).serial(6);
+        assert_eq!(
+            vec![
+                IdentityMismatch {
+                    field: "revision",
+                    expected: 5,
+                    actual: 3
+                },
+                IdentityMismatch {
+                    field: "serial",
+                    expected: 6,
+                    actual: 4
+                }
+            ],
+            pattern.mismatches(&id)
+        );
+
+        let err = IdentityError::Mismatch {
+            node_id: 3,
+            actual: id,
+            mismatches: pattern.mismatches(&id),
+        };
+        assert_eq!(
+            "Identity of node 3 does not match: revision is 0x3, expected 0x5, serial is 0x4, expected 0x6",
+            err.to_string()
+        );
+    }
+}
@@ -6,8 +6,15 @@
 //! - An [LSS master](LssMaster) for discovering and configuring un-configured nodes with IDs
 //! - A [BusManager] which is intended to be the engine behind an application, such as `zencan-cli`,
 //!   keeping track of nodes, and providing an API for managing them.
+//! - A [Device] handle combining the SDO client, NMT commands and EMCYs of a single node
 //! - Defining a [NodeConfig] TOML file format, which allows for storing and loading node configuration (primarily
 //!   PDOs, but any objects can be written)
+//! - Test fixtures for connecting a node to an in-memory bus, in the `testing` module enabled by the
+//!   `testing` feature
+//!
+//! Other services, such as [firmware updates](firmware), [file transfers](file_transfer),
+//! [PDO linking](pdo_link) and [transports](transport) for connecting to a bus, are described in
+//! the documentation of their modules. All of their errors convert into [ZencanClientError].
 //!
 //! This library is currently based on tokio/async. The plan is to also include blocking APIs in the
 //! future.
@@ -21,15 +28,77 @@
 )]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+pub mod bus_load;
 mod bus_manager;
+pub mod bus_silence;
+pub mod cob_registry;
+pub mod competing_master;
+pub mod config_lint;
+pub mod config_template;
+pub mod debug_log;
+mod echo_filter;
+pub mod emcy;
+pub mod error;
+pub mod failover;
+pub mod failsafe;
+pub mod file_transfer;
+pub mod firmware;
+pub mod frame_capture;
+mod heartbeat_consumer;
+mod identity;
 mod lss_master;
 pub mod nmt_master;
 mod node_configuration;
+pub mod object_cache;
+pub mod od_ref;
+pub mod pdo_link;
+pub mod resumable_download;
 mod sdo_client;
+pub mod stamped_pdo;
+#[cfg(feature = "testing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+pub mod testing;
+pub mod topology;
+pub mod transaction_log;
+pub mod transfer_monitor;
+pub mod transport;
 pub use zencan_common as common;
 
-pub use bus_manager::BusManager;
-pub use common::open_socketcan;
-pub use lss_master::{LssError, LssMaster};
-pub use node_configuration::{NodeConfig, PdoConfig, PdoMapping};
-pub use sdo_client::{RawAbortCode, SdoClient, SdoClientError};
+pub use bus_load::{BusLoadBudget, BusLoadLimiter, FramePacing};
+pub use bus_manager::{
+    BusManager, DetachReason, Device, DiscoveryOptions, HeartbeatSource, ManagerHeartbeat,
+    ManagerSync, NoMsgError, NodeCapabilities, NodeEvent, NodeEvents, NodeInfo, RawHandle,
+    RestartError, ScanOptions, SdoValue, SyncProducerError,
+};
+pub use bus_silence::{BusActivity, BusEvent, BusEvents};
+pub use cob_registry::{CobIdConflict, CobIdRegistry};
+#[cfg(target_os = "linux")]
+#[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
+pub use common::{open_socketcan, SocketCanTransport};
+pub use competing_master::{MasterActivity, MasterPolicy};
+pub use config_lint::{Diagnostic, ParseMode, Severity};
+pub use config_template::{Fleet, NodeConfigTemplate};
+pub use emcy::{DecodedEmcy, EmcyDecoder, EmcyDiagnostic, EmcyMonitor};
+pub use error::{ErrorKind, ZencanClientError};
+pub use failover::{FailoverOptions, FailoverReason, Link, RedundantSender};
+pub use failsafe::{AnalogFailsafe, DigitalFailsafe, FailsafeConfig, FailsafeError};
+pub use file_transfer::{FileTransferError, TransferProgress};
+pub use firmware::{FlashError, FlashOptions, FlashReport};
+pub use heartbeat_consumer::HeartbeatConsumerError;
+pub use identity::{IdentityError, IdentityMatch, IdentityMismatch};
+pub use lss_master::{AutoAssignReport, LssAssignment, LssError, LssMaster};
+#[allow(deprecated)]
+pub use node_configuration::StoreValue;
+pub use node_configuration::{
+    ConfigStamp, NodeConfig, PdoConfig, PdoMapping, PdoValidationError, Store, SyncConfig,
+};
+pub use object_cache::ObjectCache;
+pub use od_ref::OdRef;
+pub use pdo_link::{LinkedPdo, PdoLinkError, PdoLinkRequest};
+pub use resumable_download::{DownloadCheckpoint, ResumableDownloadError};
+pub use sdo_client::{
+    PdoCounts, RawAbortCode, SdoClient, SdoClientError, SdoCobIds, TransferMode, VerifyMethod,
+};
+pub use topology::Topology;
+pub use transaction_log::TransactionRecorder;
+pub use transport::{open_transport, TransportReceiver, TransportSender};
@@ -1,5 +1,5 @@
 //! A
-use core::time::Duration;
+use core::{ops::RangeInclusive, time::Duration};
 
 use tokio::time::timeout_at;
 use zencan_common::{
@@ -8,6 +8,7 @@ use zencan_common::{
     NodeId,
 };
 
+use serde::{Deserialize, Serialize};
 use snafu::Snafu;
 
 #[derive(Debug)]
@@ -18,7 +19,7 @@ pub struct LssMaster<S, R> {
 }
 
 /// Error returned by [`LssMaster`]
-#[derive(Debug, Snafu, Clone, Copy)]
+#[derive(Debug, Snafu, Clone, Copy, Serialize, Deserialize)]
 pub enum LssError {
     /// Timed out while waiting for an expected LSS response
     #[snafu(display("Timed out waiting for LSS response"))]
@@ -76,6 +77,30 @@ pub enum LssError {
     },
 }
 
+/// A node ID assigned to a device by [`LssMaster::auto_assign`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LssAssignment {
+    /// The identity of the device
+    pub identity: LssIdentity,
+    /// The node ID assigned to the device
+    pub node_id: NodeId,
+    /// True if the device acknowledged a command to store its configuration
+    ///
+    /// If false, the device will lose the assigned node ID when it is reset
+    pub stored: bool,
+}
+
+/// The result of [`LssMaster::auto_assign`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AutoAssignReport {
+    /// Devices which were successfully assigned a node ID
+    pub assigned: Vec<LssAssignment>,
+    /// Devices which were found, but not assigned an ID because the pool was exhausted
+    pub unassigned: Vec<LssIdentity>,
+    /// Devices which were found, but failed to be configured
+    pub failed: Vec<(LssIdentity, LssError)>,
+}
+
 impl<S: AsyncCanSender, R: AsyncCanReceiver> LssMaster<S, R> {
     /// Create a new LssMaster
     ///
@@ -85,7 +110,7 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> LssMaster<S, R> {
     /// - `receiver`: An object which implements [`AsyncCanReceiver`] to be used for receiving
     ///   messages from the bus
     ///
-    /// When using socketcan, these can be created with [`crate::open_socketcan`].
+    /// These can be created with [`crate::open_transport`].
     pub fn new(sender: S, receiver: R) -> Self {
         Self { sender, receiver }
     }
@@ -305,6 +330,88 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> LssMaster<S, R> {
         })
     }
 
+    /// Find all unconfigured devices, and assign each of them a node ID from a pool
+    ///
+    /// Devices are found using [`fast_scan`](Self::fast_scan), and then each one is selected by
+    /// its identity, assigned the next available ID from `pool`, and commanded to store its
+    /// configuration. IDs in the pool which are not valid node IDs are skipped. IDs are only
+    /// consumed by successful assignments.
+    ///
+    /// Note that the pool must not include IDs already in use by configured nodes on the bus.
+    ///
+    /// # Arguments
+    /// * `pool` - The range of node IDs to assign from
+    /// * `timeout` - The fast scan response timeout. See [`fast_scan`](Self::fast_scan).
+    pub async fn auto_assign(
+        &mut self,
+        pool: RangeInclusive<u8>,
+        timeout: Duration,
+    ) -> AutoAssignReport {
+        self.auto_assign_from(pool, timeout).await
+    }
+
+    /// Implements auto_assign, for any collection of IDs
+    pub(crate) async fn auto_assign_from(
+        &mut self,
+        pool: impl IntoIterator<Item = u8>,
+        timeout: Duration,
+    ) -> AutoAssignReport {
+        let mut report = AutoAssignReport::default();
+
+        // Find all the unconfigured devices. Each device remains in configuration mode once it is
+        // identified, so that it will not respond to the following scans.
+        self.set_global_mode(LssState::Waiting).await;
+        let mut identities = Vec::new();
+        while let Some(id) = self.fast_scan(timeout).await {
+            identities.push(id);
+        }
+        self.set_global_mode(LssState::Waiting).await;
+
+        let mut pool = pool
+            .into_iter()
+            .filter_map(|id| NodeId::new(id).ok().filter(|id| id.is_configured()))
+            .peekable();
+        for identity in identities {
+            let Some(&node_id) = pool.peek() else {
+                report.unassigned.push(identity);
+                continue;
+            };
+            match self.assign(identity, node_id).await {
+                Ok(stored) => {
+                    pool.next();
+                    report.assigned.push(LssAssignment {
+                        identity,
+                        node_id,
+                        stored,
+                    });
+                }
+                Err(e) => report.failed.push((identity, e)),
+            }
+        }
+
+        self.set_global_mode(LssState::Waiting).await;
+        report
+    }
+
+    /// Select a single device, and assign it a node ID
+    ///
+    /// Returns true if the device also stored its configuration
+    async fn assign(&mut self, identity: LssIdentity, node_id: NodeId) -> Result<bool, LssError> {
+        self.enter_config_by_identity(
+            identity.vendor_id,
+            identity.product_code,
+            identity.revision,
+            identity.serial,
+        )
+        .await?;
+        self.set_node_id(node_id).await?;
+        match self.store_config().await {
+            Ok(()) => Ok(true),
+            Err(LssError::NodeStoreConfigError { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Send command to the bus to set the LSS mode for all nodes
     pub async fn set_global_mode(&mut self, mode: LssState) {
         // Send global mode to put all nodes into waiting state. No response expected.
@@ -1,8 +1,9 @@
 //! Simple interface for sending NMT commands to a bus
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use zencan_common::{
-    messages::{CanMessage, NmtCommand, NmtCommandSpecifier, NmtState, ZencanMessage},
+    client::{HeartbeatMonitor, NodeStatus},
+    messages::{CanMessage, NmtCommand, NmtCommandSpecifier, NmtState},
     traits::{AsyncCanReceiver, AsyncCanSender},
 };
 
@@ -38,7 +39,9 @@ const MAX_NODES: usize = 127;
 pub struct NmtMaster<S, R> {
     sender: S,
     receiver: R,
-    nodes: [Node; MAX_NODES],
+    monitor: HeartbeatMonitor<MAX_NODES>,
+    /// The time base for heartbeat times stored in the monitor
+    epoch: Instant,
 }
 
 impl<S: AsyncCanSender, R: AsyncCanReceiver> NmtMaster<S, R> {
@@ -50,13 +53,13 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> NmtMaster<S, R> {
     /// - `receiver`: An object which implements [`AsyncCanReceiver`] to be used for receiving
     ///   messages from the bus
     ///
-    /// When using socketcan, these can be created with [`crate::open_socketcan`].
+    /// These can be created with [`crate::open_transport`].
     pub fn new(sender: S, receiver: R) -> Self {
-        let nodes = [Node::default(); MAX_NODES];
         Self {
             sender,
             receiver,
-            nodes,
+            monitor: HeartbeatMonitor::new(),
+            epoch: Instant::now(),
         }
     }
 
@@ -68,55 +71,27 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> NmtMaster<S, R> {
     }
 
     fn handle_message(&mut self, msg: CanMessage) {
-        // Attempt to convert the raw message into a zencanMessage. This may fail, e.g. if
-        // non zencan messages are received, and that's OK; those are ignored.
-        let open_msg: ZencanMessage = match msg.try_into() {
-            Ok(m) => m,
-            Err(_) => return,
-        };
-
-        if let ZencanMessage::Heartbeat(heartbeat) = open_msg {
-            self.handle_heartbeat(heartbeat.node, heartbeat.state, heartbeat.toggle)
-        }
+        // Non heartbeat messages are ignored by the monitor
+        let now_us = self.epoch.elapsed().as_micros() as u64;
+        self.monitor.handle_message(msg, now_us);
     }
 
     /// Get a list of all nodes detected on the bus via heartbeat/reset messages
-    pub fn get_nodes(&mut self) -> &[Node] {
+    pub fn get_nodes(&mut self) -> Vec<Node> {
         self.process_rx();
-
-        // Find the first empty slot; this indicates the end of the list
-        let n = self
-            .nodes
+        self.monitor
+            .nodes()
             .iter()
-            .position(|n| n.id == 0)
-            .unwrap_or(MAX_NODES);
-        &self.nodes[0..n]
+            .map(|status| self.to_node(status))
+            .collect()
     }
 
-    fn handle_heartbeat(&mut self, node: u8, state: NmtState, toggle: bool) {
-        // Find the node in the ordered list, inserting if needed.
-        for i in 0..self.nodes.len() {
-            let list_node = &mut self.nodes[i];
-            if list_node.id == node {
-                // Node already in list. Update it
-                list_node.last_status = Instant::now();
-                list_node.last_toggle = toggle;
-                list_node.state = state;
-                break;
-            } else if list_node.id == 0 || list_node.id > node {
-                // Found end of list or higher node - insert here
-                // Shift all higher nodes
-                for j in self.nodes.len() - 1..i {
-                    self.nodes[j] = self.nodes[j - 1];
-                }
-                self.nodes[i] = Node {
-                    id: node,
-                    state,
-                    last_status: Instant::now(),
-                    last_toggle: toggle,
-                };
-                break;
-            }
+    fn to_node(&self, status: &NodeStatus) -> Node {
+        Node {
+            id: status.id,
+            state: status.state,
+            last_status: self.epoch + Duration::from_micros(status.last_seen_us),
+            last_toggle: status.toggle,
         }
     }
 
@@ -157,6 +132,36 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> NmtMaster<S, R> {
         self.send_nmt_cmd(NmtCommandSpecifier::Stop, node).await
     }
 
+    /// Broadcast a stop command to all nodes
+    pub async fn stop_all(&mut self) -> Result<()> {
+        self.nmt_stop(0).await
+    }
+
+    /// Put nodes into a safe state, and then broadcast a stop command to all nodes
+    ///
+    /// Each frame in `safe_state` is sent first -- typically RPDOs which command the outputs of
+    /// selected nodes to a safe value. A stopped node ignores PDOs, so the stop command is only
+    /// sent after waiting `settle_time` for the nodes to process them. If there are no frames, the
+    /// stop command is sent immediately.
+    ///
+    /// The stop command is sent even if sending a frame fails, but an error is still returned.
+    pub async fn emergency_stop(
+        &mut self,
+        safe_state: &[CanMessage],
+        settle_time: Duration,
+    ) -> Result<()> {
+        let mut result = Ok(());
+        for msg in safe_state {
+            if self.sender.send(*msg).await.is_err() {
+                result = Err(());
+            }
+        }
+        if !safe_state.is_empty() {
+            tokio::time::sleep(settle_time).await;
+        }
+        self.stop_all().await.and(result)
+    }
+
     async fn send_nmt_cmd(&mut self, cmd: NmtCommandSpecifier, node: u8) -> Result<()> {
         let message = NmtCommand { cs: cmd, node };
         self.sender.send(message.into()).await.map_err(|_| ())?;
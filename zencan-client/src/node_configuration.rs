@@ -1,10 +1,24 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, SystemTime},
+};
 
-use serde::{de, Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer, Serialize};
 use snafu::{ResultExt, Snafu};
 
+use zencan_common::{
+    objects::DataType,
+    value::{Value, ValueError},
+};
+
+use crate::bus_load::FramePacing;
+use crate::config_lint::{self, Diagnostic, ParseMode};
+use crate::sdo_client::SdoClientError;
+
 // Error returned when loading node configuration files
 #[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
 pub enum ConfigError {
     #[snafu(display("IO error loading {path}: {source:?}"))]
     Io {
@@ -13,6 +27,105 @@ pub enum ConfigError {
     },
     #[snafu(display("Error parsing TOML: {source}"))]
     TomlDeserialization { source: toml::de::Error },
+    #[snafu(display("Undefined variable '{name}' in template"))]
+    UndefinedVariable { name: String },
+    #[snafu(display("Invalid template expression '{expr}': {reason}"))]
+    InvalidExpression { expr: String, reason: String },
+    #[snafu(display("Invalid value for variable '{name}': {reason}"))]
+    InvalidVariable { name: String, reason: String },
+    #[snafu(display("Fleet node {node_id} has no template, and there is no default template"))]
+    MissingTemplate { node_id: u8 },
+    #[snafu(display("Invalid configuration:\n{}", format_diagnostics(diagnostics)))]
+    Invalid { diagnostics: Vec<Diagnostic> },
+    #[snafu(display("Error rendering config for node {node_id}: {source}"))]
+    Render {
+        node_id: u8,
+        #[snafu(source(from(ConfigError, Box::new)))]
+        source: Box<ConfigError>,
+    },
+}
+
+/// List the errors among a set of diagnostics, one per line
+fn format_diagnostics(diagnostics: &[Diagnostic]) -> String {
+    diagnostics
+        .iter()
+        .filter(|d| d.is_error())
+        .map(|d| d.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Error returned when a PDO configuration is not compatible with the target device
+#[derive(Debug, Clone, PartialEq, Snafu)]
+pub enum PdoValidationError {
+    /// The device does not implement the PDO communication object
+    #[snafu(display("{kind} {pdo} does not exist on the device"))]
+    NoSuchPdo {
+        /// "TPDO" or "RPDO"
+        kind: &'static str,
+        /// The PDO number
+        pdo: usize,
+    },
+    /// A mapped sub object does not exist on the device
+    #[snafu(display("0x{index:X}.{sub} does not exist on the device"))]
+    NoSuchObject {
+        /// Object index
+        index: u16,
+        /// Object sub index
+        sub: u8,
+    },
+    /// A mapped sub object is too large to be mapped to a PDO
+    #[snafu(display("0x{index:X}.{sub} is {size} bits, and cannot be mapped to a PDO"))]
+    NotMappable {
+        /// Object index
+        index: u16,
+        /// Object sub index
+        sub: u8,
+        /// The size of the object on the device, in bits
+        size: usize,
+    },
+    /// The device does not allow a mapped sub object to be mapped to the PDO
+    #[snafu(display("0x{index:X}.{sub} cannot be mapped to a {kind} on the device"))]
+    MappingNotAllowed {
+        /// "TPDO" or "RPDO"
+        kind: &'static str,
+        /// Object index
+        index: u16,
+        /// Object sub index
+        sub: u8,
+    },
+    /// The size of a mapped sub object does not match the size declared in the mapping
+    #[snafu(display("0x{index:X}.{sub} is {actual} bits, mapping declares {declared}"))]
+    SizeMismatch {
+        /// Object index
+        index: u16,
+        /// Object sub index
+        sub: u8,
+        /// The size of the object on the device, in bits
+        actual: usize,
+        /// The size declared in the mapping, in bits
+        declared: u8,
+    },
+    /// The sum of the mapped object sizes does not fit in a single CAN frame
+    #[snafu(display("{kind} {pdo} maps {total} bits, but a PDO can hold at most 64"))]
+    PdoTooLarge {
+        /// "TPDO" or "RPDO"
+        kind: &'static str,
+        /// The PDO number
+        pdo: usize,
+        /// The total size of all mappings, in bits
+        total: usize,
+    },
+    /// An unexpected SDO error occurred while validating
+    #[snafu(display("SDO error accessing 0x{index:X}.{sub}: {source}"))]
+    Sdo {
+        /// Object index
+        index: u16,
+        /// Object sub index
+        sub: u8,
+        /// The underlying error
+        source: SdoClientError,
+    },
 }
 
 /// Represents a store command to write a value to an object
@@ -23,40 +136,63 @@ pub struct Store {
     /// Sub index to be written
     pub sub: u8,
     /// The value to be written to the sub object
-    pub value: StoreValue,
+    pub value: Value,
+    /// If true, the value is read back after writing and compared
+    pub verify: bool,
 }
 
 impl Store {
     /// Get the value as bytes
     pub fn raw_value(&self) -> Vec<u8> {
-        self.value.raw()
+        self.value.to_le_bytes()
     }
 }
 
 /// Value to be stored by a [Store] command
+///
+/// [`Store::value`] is now a [`Value`], which covers all of the CANopen data types. Code which
+/// built a [`Store`] from a `StoreValue` must convert it with `.into()`.
+#[deprecated(note = "Use zencan_common::value::Value, which StoreValue converts into")]
 #[derive(Clone, Debug, Deserialize, PartialEq)]
 pub enum StoreValue {
+    /// An unsigned 32-bit integer
     U32(u32),
+    /// An unsigned 16-bit integer
     U16(u16),
+    /// An unsigned 8-bit integer
     U8(u8),
+    /// A signed 32-bit integer
     I32(i32),
+    /// A signed 16-bit integer
     I16(i16),
+    /// A signed 8-bit integer
     I8(i8),
+    /// A 32-bit float
     F32(f32),
+    /// A string
     String(String),
 }
 
+#[allow(deprecated)]
 impl StoreValue {
+    /// Get the value as bytes
     pub fn raw(&self) -> Vec<u8> {
-        match self {
-            StoreValue::U32(v) => v.to_le_bytes().to_vec(),
-            StoreValue::U16(v) => v.to_le_bytes().to_vec(),
-            StoreValue::U8(v) => vec![*v],
-            StoreValue::I32(v) => v.to_le_bytes().to_vec(),
-            StoreValue::I16(v) => v.to_le_bytes().to_vec(),
-            StoreValue::I8(v) => vec![*v as u8],
-            StoreValue::F32(v) => v.to_le_bytes().to_vec(),
-            StoreValue::String(ref s) => s.as_bytes().to_vec(),
+        Value::from(self.clone()).to_le_bytes()
+    }
+}
+
+#[allow(deprecated)]
+impl From<StoreValue> for Value {
+    fn from(value: StoreValue) -> Self {
+        match value {
+            StoreValue::U32(v) => Value::U32(v),
+            StoreValue::U16(v) => Value::U16(v),
+            StoreValue::U8(v) => Value::U8(v),
+            StoreValue::I32(v) => Value::I32(v),
+            StoreValue::I16(v) => Value::I16(v),
+            StoreValue::I8(v) => Value::I8(v),
+            StoreValue::F32(v) => Value::F32(v),
+            StoreValue::String(s) => Value::Str(s),
         }
     }
 }
@@ -87,6 +223,34 @@ impl NodeConfig {
         Ok(NodeConfig(raw_config))
     }
 
+    /// Read a configuration from a string, with unknown keys treated according to `mode`
+    ///
+    /// Rather than stopping at the first problem, the whole configuration is checked, and
+    /// [`ConfigError::Invalid`] lists every error found. On success, any warnings are returned
+    /// with the configuration.
+    pub fn load_from_str_with(
+        s: &str,
+        mode: ParseMode,
+    ) -> Result<(NodeConfig, Vec<Diagnostic>), ConfigError> {
+        let (config, diagnostics) = config_lint::lint(s, mode);
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            return InvalidSnafu { diagnostics }.fail();
+        }
+        let config = config.expect("A config without errors is valid TOML");
+        let raw_config: NodeConfigSerializer = toml::Value::Table(config)
+            .try_into()
+            .context(TomlDeserializationSnafu)?;
+        Ok((NodeConfig(raw_config), diagnostics))
+    }
+
+    /// Check a configuration, returning every problem found in it
+    ///
+    /// The configuration can be loaded if none of the diagnostics are errors. See
+    /// [`config_lint`](crate::config_lint) for the checks which are made.
+    pub fn validate(s: &str, mode: ParseMode) -> Vec<Diagnostic> {
+        config_lint::lint(s, mode).1
+    }
+
     /// Get the transmit PDO configurations
     pub fn tpdos(&self) -> &HashMap<usize, PdoConfig> {
         &self.0.tpdo
@@ -103,6 +267,103 @@ impl NodeConfig {
     pub fn stores(&self) -> &[Store] {
         &self.0.store
     }
+
+    /// Get the generic object writes
+    ///
+    /// These are written after the PDO and communication settings, in the order they appear in the
+    /// file
+    pub fn writes(&self) -> &[Store] {
+        &self.0.writes
+    }
+
+    /// Get the heartbeat producer time in milliseconds, if specified
+    pub fn heartbeat_producer_time(&self) -> Option<u16> {
+        self.0.heartbeat_producer_time
+    }
+
+    /// Get the EMCY inhibit time in units of 100us, if specified
+    pub fn emcy_inhibit_time(&self) -> Option<u16> {
+        self.0.emcy_inhibit_time
+    }
+
+    /// Get the SYNC configuration, if specified
+    pub fn sync(&self) -> Option<&SyncConfig> {
+        self.0.sync.as_ref()
+    }
+
+    /// Get the pacing of frames sent to the node, if specified
+    ///
+    /// This is not written to the node, but sets the pacing of the client which applies the
+    /// config.
+    pub fn frame_pacing(&self) -> Option<FramePacing> {
+        self.0.pacing
+    }
+
+    /// Get the stamp written to the verify configuration object (0x1020), if specified
+    ///
+    /// The stamp identifies this version of the configuration. It is written once the rest of the
+    /// configuration has been applied, and a node which already holds it is not configured again
+    /// by [`BusManager::apply_node_config`](crate::BusManager::apply_node_config). It must be
+    /// changed whenever the configuration is changed.
+    pub fn verify_configuration(&self) -> Option<ConfigStamp> {
+        self.0.verify_configuration
+    }
+
+    /// Set the stamp written to the verify configuration object (0x1020)
+    pub fn set_verify_configuration(&mut self, stamp: Option<ConfigStamp>) {
+        self.0.verify_configuration = stamp;
+    }
+}
+
+/// The date and time a configuration was written to a node, as stored in the verify configuration
+/// object (0x1020)
+///
+/// A stamp of all zeros means that the node has not been configured.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigStamp {
+    /// Days since January 1, 1984
+    pub date: u32,
+    /// Milliseconds after midnight
+    pub time: u32,
+}
+
+impl ConfigStamp {
+    /// Seconds from the UNIX epoch to the CANopen epoch, January 1, 1984
+    const CANOPEN_EPOCH_SECS: u64 = 441_763_200;
+    const MS_PER_DAY: u64 = 86_400_000;
+
+    /// Create a stamp for a time
+    ///
+    /// Times before 1984 are clamped to the CANopen epoch.
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let ms = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .saturating_sub(Duration::from_secs(Self::CANOPEN_EPOCH_SECS))
+            .as_millis() as u64;
+        Self {
+            date: (ms / Self::MS_PER_DAY) as u32,
+            time: (ms % Self::MS_PER_DAY) as u32,
+        }
+    }
+
+    /// Create a stamp for the current time
+    pub fn now() -> Self {
+        Self::from_system_time(SystemTime::now())
+    }
+
+    /// Get the time of the stamp
+    pub fn to_system_time(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH
+            + Duration::from_secs(Self::CANOPEN_EPOCH_SECS)
+            + Duration::from_millis(self.date as u64 * Self::MS_PER_DAY + self.time as u64)
+    }
+
+    /// Returns true if the stamp marks a configured node, i.e. it is not all zeros
+    pub fn is_set(&self) -> bool {
+        *self != Self::default()
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -114,10 +375,33 @@ struct NodeConfigSerializer {
     pub rpdo: HashMap<usize, PdoConfig>,
     #[serde(default, deserialize_with = "deserialize_store")]
     pub store: Vec<Store>,
+    #[serde(default, deserialize_with = "deserialize_store")]
+    pub writes: Vec<Store>,
+    pub heartbeat_producer_time: Option<u16>,
+    pub emcy_inhibit_time: Option<u16>,
+    pub sync: Option<SyncConfig>,
+    pub pacing: Option<FramePacing>,
+    pub verify_configuration: Option<ConfigStamp>,
+}
+
+/// Represents the SYNC related settings of a node
+///
+/// All fields are optional, and only specified fields will be written
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SyncConfig {
+    /// The COB-ID used for the SYNC message (object 0x1005)
+    pub cob_id: Option<u32>,
+    /// The communication cycle period in microseconds (object 0x1006)
+    pub cycle_period: Option<u32>,
+    /// The synchronous window length in microseconds (object 0x1007)
+    pub window_length: Option<u32>,
+    /// The synchronous counter overflow value (object 0x1019)
+    pub counter_overflow: Option<u8>,
 }
 
 /// Represents the configuration parameters for a single PDO
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct PdoConfig {
     /// The COB ID this PDO will use to send/receive
@@ -135,10 +419,37 @@ pub struct PdoConfig {
     pub transmission_type: u8,
 }
 
+impl PdoConfig {
+    /// Decode the values of the mapped sub objects from the data of a received PDO
+    ///
+    /// `data_types` gives the data type of each mapped sub object, in the order of the mappings.
+    /// Mappings without a data type are not decoded. Returns an error if the PDO is too short to
+    /// hold a mapping, or if a mapping's size does not match its data type.
+    pub fn decode(&self, data: &[u8], data_types: &[DataType]) -> Result<Vec<Value>, ValueError> {
+        let mut offset = 0;
+        self.mappings
+            .iter()
+            .zip(data_types)
+            .map(|(mapping, &data_type)| {
+                let len = mapping.size as usize / 8;
+                let bytes = data
+                    .get(offset..offset + len)
+                    .ok_or(ValueError::WrongSize {
+                        data_type,
+                        expected: len,
+                        actual: data.len().saturating_sub(offset),
+                    })?;
+                offset += len;
+                Value::from_le_bytes(data_type, bytes)
+            })
+            .collect()
+    }
+}
+
 /// Represents a PDO mapping
 ///
 /// Each mapping specifies one sub-object to be included in the PDO.
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct PdoMapping {
     /// The object index
@@ -151,7 +462,7 @@ pub struct PdoMapping {
 
 #[derive(Debug, Clone, Copy, Deserialize)]
 #[serde(rename_all = "lowercase")]
-enum StoreType {
+pub(crate) enum StoreType {
     U32,
     U16,
     U8,
@@ -160,6 +471,23 @@ enum StoreType {
     I8,
     F32,
     String,
+    Bytes,
+}
+
+impl From<StoreType> for DataType {
+    fn from(value: StoreType) -> Self {
+        match value {
+            StoreType::U32 => DataType::UInt32,
+            StoreType::U16 => DataType::UInt16,
+            StoreType::U8 => DataType::UInt8,
+            StoreType::I32 => DataType::Int32,
+            StoreType::I16 => DataType::Int16,
+            StoreType::I8 => DataType::Int8,
+            StoreType::F32 => DataType::Real32,
+            StoreType::String => DataType::VisibleString,
+            StoreType::Bytes => DataType::OctetString,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -170,6 +498,22 @@ struct StoreSerializer {
     pub value: toml::Value,
     #[serde(rename = "type")]
     pub ty: StoreType,
+    #[serde(default)]
+    pub verify: bool,
+}
+
+/// Convert a TOML value to a [`Value`] of the given type
+pub(crate) fn toml_to_value(data_type: DataType, value: &toml::Value) -> Result<Value, ValueError> {
+    match (data_type, value) {
+        (DataType::VisibleString, toml::Value::String(s)) => Ok(Value::Str(s.clone())),
+        (_, toml::Value::String(s)) => Value::parse(data_type, s),
+        (_, toml::Value::Integer(i)) => Value::from_integer(data_type, *i as i128),
+        (_, toml::Value::Float(f)) => Value::from_float(data_type, *f),
+        _ => Err(ValueError::Parse {
+            text: value.to_string(),
+            data_type,
+        }),
+    }
 }
 
 fn deserialize_store<'de, D>(deserializer: D) -> Result<Vec<Store>, D::Error>
@@ -178,106 +522,18 @@ where
 {
     let raw_store = Vec::<StoreSerializer>::deserialize(deserializer)?;
 
-    let store = raw_store
+    raw_store
         .into_iter()
         .map(|raw| {
-            let value = match raw.ty {
-                StoreType::U32 => {
-                    let value = raw.value.as_integer().ok_or(de::Error::invalid_type(
-                        de::Unexpected::Str(&raw.value.to_string()),
-                        &"an integer",
-                    ))?;
-                    Ok(StoreValue::U32(value.try_into().map_err(|_| {
-                        de::Error::invalid_value(
-                            de::Unexpected::Signed(value),
-                            &"an integer in range [0..2^32]",
-                        )
-                    })?))
-                }
-                StoreType::U16 => {
-                    let value = raw.value.as_integer().ok_or(de::Error::invalid_type(
-                        de::Unexpected::Str(&raw.value.to_string()),
-                        &"an integer",
-                    ))?;
-                    Ok(StoreValue::U16(value.try_into().map_err(|_| {
-                        de::Error::invalid_value(
-                            de::Unexpected::Signed(value),
-                            &"an integer in range [0..65536]",
-                        )
-                    })?))
-                }
-                StoreType::U8 => {
-                    let value = raw.value.as_integer().ok_or(de::Error::invalid_type(
-                        de::Unexpected::Str(&raw.value.to_string()),
-                        &"an integer",
-                    ))?;
-                    Ok(StoreValue::U8(value.try_into().map_err(|_| {
-                        de::Error::invalid_value(
-                            de::Unexpected::Signed(value),
-                            &"an integer in range [0..256]",
-                        )
-                    })?))
-                }
-                StoreType::I32 => {
-                    let value = raw.value.as_integer().ok_or(de::Error::invalid_type(
-                        de::Unexpected::Str(&raw.value.to_string()),
-                        &"an integer",
-                    ))?;
-                    Ok(StoreValue::I32(value.try_into().map_err(|_| {
-                        de::Error::invalid_value(
-                            de::Unexpected::Signed(value),
-                            &"an integer in range [-2^31..2^31]",
-                        )
-                    })?))
-                }
-                StoreType::I16 => {
-                    let value = raw.value.as_integer().ok_or(de::Error::invalid_type(
-                        de::Unexpected::Str(&raw.value.to_string()),
-                        &"an integer",
-                    ))?;
-                    Ok(StoreValue::I16(value.try_into().map_err(|_| {
-                        de::Error::invalid_value(
-                            de::Unexpected::Signed(value),
-                            &"an integer in range [-32767..32768]",
-                        )
-                    })?))
-                }
-                StoreType::I8 => {
-                    let value = raw.value.as_integer().ok_or(de::Error::invalid_type(
-                        de::Unexpected::Str(&raw.value.to_string()),
-                        &"an integer",
-                    ))?;
-                    Ok(StoreValue::I8(value.try_into().map_err(|_| {
-                        de::Error::invalid_value(
-                            de::Unexpected::Signed(value),
-                            &"an integer in range [-127..128]",
-                        )
-                    })?))
-                }
-                StoreType::F32 => {
-                    let value = raw.value.as_float().ok_or(de::Error::invalid_type(
-                        de::Unexpected::Str(&raw.value.to_string()),
-                        &"a float",
-                    ))?;
-                    Ok(StoreValue::F32(value as f32))
-                }
-                StoreType::String => {
-                    let value = raw.value.as_str().ok_or(de::Error::invalid_type(
-                        de::Unexpected::Str(&raw.value.to_string()),
-                        &"a string",
-                    ))?;
-                    Ok(StoreValue::String(value.to_string()))
-                }
-            }?;
+            let value = toml_to_value(raw.ty.into(), &raw.value).map_err(de::Error::custom)?;
             Ok(Store {
                 index: raw.index,
                 sub: raw.sub,
                 value,
+                verify: raw.verify,
             })
         })
-        .collect::<Result<Vec<_>, _>>()?;
-
-    Ok(store)
+        .collect()
 }
 
 fn deserialize_pdo_map<'de, D>(deserializer: D) -> Result<HashMap<usize, PdoConfig>, D::Error>
@@ -311,6 +567,59 @@ where
 mod test {
     use super::*;
 
+    #[test]
+    fn test_pdo_decode() {
+        let config = PdoConfig {
+            cob: 0x181,
+            enabled: true,
+            mappings: vec![
+                PdoMapping {
+                    index: 0x2000,
+                    sub: 1,
+                    size: 16,
+                },
+                PdoMapping {
+                    index: 0x2000,
+                    sub: 2,
+                    size: 32,
+                },
+            ],
+            transmission_type: 254,
+        };
+        let data = [0x34, 0x12, 0, 0, 0x80, 0xbf, 0xff];
+        assert_eq!(
+            Ok(vec![Value::U16(0x1234), Value::F32(-1.0)]),
+            config.decode(&data, &[DataType::UInt16, DataType::Real32])
+        );
+        assert_eq!(
+            Ok(vec![Value::I16(0x1234)]),
+            config.decode(&data, &[DataType::Int16])
+        );
+        assert_eq!(
+            Err(ValueError::WrongSize {
+                data_type: DataType::UInt32,
+                expected: 4,
+                actual: 3
+            }),
+            config.decode(&data[..5], &[DataType::UInt16, DataType::UInt32])
+        );
+        // The mapped size does not match the data type
+        assert!(config
+            .decode(&data, &[DataType::UInt8, DataType::UInt32])
+            .is_err());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_store_value_into_value() {
+        assert_eq!(Value::I16(-2), StoreValue::I16(-2).into());
+        assert_eq!(
+            Value::Str("abc".to_string()),
+            StoreValue::String("abc".to_string()).into()
+        );
+        assert_eq!(vec![0xfe, 0xff], StoreValue::I16(-2).raw());
+    }
+
     #[test]
     fn test_node_config_parse() {
         let str = r#"
@@ -343,6 +652,91 @@ mod test {
         assert_eq!(1, config.stores().len());
     }
 
+    #[test]
+    fn test_commissioning_settings_parse() {
+        let str = r#"
+        heartbeat_producer_time = 500
+        emcy_inhibit_time = 10
+
+        [sync]
+        cycle_period = 10000
+        window_length = 2000
+        counter_overflow = 4
+
+        [pacing]
+        max_frames_per_sec = 1000
+        min_gap_us = 500
+
+        [verify_configuration]
+        date = 15000
+        time = 3600000
+
+        [[writes]]
+        type = "bytes"
+        value = "01 02 ff"
+        index = 0x2000
+        sub = 1
+        verify = true
+
+        [[writes]]
+        type = "f32"
+        value = 1.5
+        index = 0x2001
+        sub = 2
+        "#;
+
+        let config = NodeConfig::load_from_str(str).unwrap();
+        assert_eq!(Some(500), config.heartbeat_producer_time());
+        assert_eq!(Some(10), config.emcy_inhibit_time());
+        assert_eq!(
+            Some(&SyncConfig {
+                cob_id: None,
+                cycle_period: Some(10000),
+                window_length: Some(2000),
+                counter_overflow: Some(4),
+            }),
+            config.sync()
+        );
+        assert_eq!(
+            Some(FramePacing {
+                max_frames_per_sec: Some(1000),
+                min_gap_us: Some(500),
+            }),
+            config.frame_pacing()
+        );
+        assert_eq!(
+            Some(ConfigStamp {
+                date: 15000,
+                time: 3600000
+            }),
+            config.verify_configuration()
+        );
+        assert_eq!(2, config.writes().len());
+        assert_eq!(Value::Bytes(vec![1, 2, 0xff]), config.writes()[0].value);
+        assert!(config.writes()[0].verify);
+        assert!(!config.writes()[1].verify);
+    }
+
+    #[test]
+    fn test_config_stamp() {
+        let epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(ConfigStamp::CANOPEN_EPOCH_SECS);
+        assert_eq!(ConfigStamp::default(), ConfigStamp::from_system_time(epoch));
+        assert!(!ConfigStamp::from_system_time(SystemTime::UNIX_EPOCH).is_set());
+
+        // 2024-01-01 12:00:00 UTC
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_110_400);
+        let stamp = ConfigStamp::from_system_time(time);
+        assert_eq!(
+            ConfigStamp {
+                date: 14610,
+                time: 43_200_000
+            },
+            stamp
+        );
+        assert!(stamp.is_set());
+        assert_eq!(time, stamp.to_system_time());
+    }
+
     #[test]
     fn test_out_of_range_integer() {
         let str = r#"
@@ -360,4 +754,32 @@ mod test {
             .to_string()
             .contains("expected an integer in range [0..256]"));
     }
+
+    #[test]
+    fn test_load_with_mode() {
+        let str = r#"
+        heartbeat_producer_time = 500
+        comment = "For a newer version"
+
+        [[writes]]
+        type = "u8"
+        value = 1
+        index = 0x2000
+        sub = 1
+        "#;
+
+        let err = NodeConfig::load_from_str_with(str, ParseMode::Strict).unwrap_err();
+        let ConfigError::Invalid { diagnostics } = err else {
+            panic!("Expected invalid config, got {err:?}");
+        };
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("comment", diagnostics[0].path);
+        assert_eq!(Some(3), diagnostics[0].line);
+
+        let (config, warnings) = NodeConfig::load_from_str_with(str, ParseMode::Lenient).unwrap();
+        assert_eq!(1, warnings.len());
+        assert!(!warnings[0].is_error());
+        assert_eq!(Some(500), config.heartbeat_producer_time());
+        assert_eq!(1, config.writes().len());
+    }
 }
@@ -0,0 +1,124 @@
+//! Caching of the constant objects of each node
+//!
+//! The device type (0x1000), device name (0x1008), hardware version (0x1009), software version
+//! (0x100A) and identity (0x1018) of a node do not change while it is running, so there is no need
+//! to read them from the node every time they are displayed. An [`ObjectCache`] holds the values
+//! read by each [`Device`](crate::Device), and by [node scans](crate::BusManager::scan_nodes), so
+//! that later reads of them do not generate any bus traffic.
+//!
+//! The cached values for a node are dropped when it sends a boot-up message, since it may have been
+//! reflashed or reconfigured while it was down. They can also be dropped explicitly with
+//! [`ObjectCache::invalidate`].
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use zencan_common::lss::LssIdentity;
+
+use crate::NodeInfo;
+
+/// The cached constant objects of one node
+///
+/// Each field is None until the object has been read from the node.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CachedObjects {
+    /// The device type (0x1000)
+    pub device_type: Option<u32>,
+    /// The device name (0x1008)
+    pub device_name: Option<String>,
+    /// The hardware version (0x1009)
+    pub hardware_version: Option<String>,
+    /// The software version (0x100A)
+    pub software_version: Option<String>,
+    /// The identity (0x1018)
+    pub identity: Option<LssIdentity>,
+}
+
+/// A per-node cache of constant objects
+///
+/// Clones share the same cache. See the [module documentation](self).
+#[derive(Clone, Debug, Default)]
+pub struct ObjectCache {
+    nodes: Arc<Mutex<HashMap<u8, CachedObjects>>>,
+}
+
+impl ObjectCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the cached objects of a node
+    pub fn get(&self, node_id: u8) -> CachedObjects {
+        self.nodes
+            .lock()
+            .unwrap()
+            .get(&node_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Drop the cached objects of a node, so that they are read again on next use
+    pub fn invalidate(&self, node_id: u8) {
+        self.nodes.lock().unwrap().remove(&node_id);
+    }
+
+    /// Drop the cached objects of all nodes
+    pub fn clear(&self) {
+        self.nodes.lock().unwrap().clear();
+    }
+
+    /// Modify the cached objects of a node
+    ///
+    /// This can be used to fill in values which are already known, e.g. from a previous session.
+    pub fn update(&self, node_id: u8, f: impl FnOnce(&mut CachedObjects)) {
+        f(self.nodes.lock().unwrap().entry(node_id).or_default());
+    }
+
+    /// Store the objects read from a node during a scan
+    pub(crate) fn record_node_info(&self, info: &NodeInfo) {
+        self.update(info.node_id, |cached| {
+            if info.device_type.is_some() {
+                cached.device_type = info.device_type;
+            }
+            if info.device_name.is_some() {
+                cached.device_name = info.device_name.clone();
+            }
+            if info.hardware_version.is_some() {
+                cached.hardware_version = info.hardware_version.clone();
+            }
+            if info.software_version.is_some() {
+                cached.software_version = info.software_version.clone();
+            }
+            if info.identity.is_some() {
+                cached.identity = info.identity;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_cache() {
+        let cache = ObjectCache::new();
+        assert_eq!(CachedObjects::default(), cache.get(1));
+
+        cache.update(1, |c| c.device_name = Some("Node 1".into()));
+        let info = NodeInfo {
+            device_type: Some(0x191),
+            ..NodeInfo::new(1)
+        };
+        cache.record_node_info(&info);
+        let cached = cache.clone().get(1);
+        assert_eq!(Some("Node 1".into()), cached.device_name);
+        assert_eq!(Some(0x191), cached.device_type);
+        assert_eq!(None, cache.get(2).device_name);
+
+        cache.invalidate(1);
+        assert_eq!(CachedObjects::default(), cache.get(1));
+    }
+}
@@ -0,0 +1,187 @@
+//! Typed references to the objects of a device
+//!
+//! An [`OdRef`] records the index, sub index and Rust type of a sub object, so that host code which
+//! reads and writes a device's objects with [`Device::get`](crate::Device::get) and
+//! [`Device::set`](crate::Device::set) is checked by the compiler. They are usually generated
+//! from the device config by `zencan-build`, with the `client-bindings` feature, and included with
+//! [`include_bindings!`](crate::include_bindings):
+//!
+//! ```ignore
+//! mod od {
+//!     zencan_client::include_bindings!(EXAMPLE);
+//! }
+//!
+//! let raw: u16 = device.get(od::RAW_ANALOG_INPUT.at(2)).await?;
+//! ```
+//!
+//! When the device config gives an object a `unit` or `scale`, its reference carries them, so that
+//! values can be shown in the unit, e.g. with `od::SUPPLY_VOLTAGE.format(raw)`.
+use core::marker::PhantomData;
+
+use zencan_common::{objects::ObjectId, value::Value};
+
+/// A reference to `N` consecutive sub objects of type `T`
+///
+/// A var object, or a single sub object, is an `OdRef<T>`. The elements of an array object are an
+/// `OdRef<T, N>`, starting at sub index 1, and a single element is selected with
+/// [`at`](Self::at).
+#[derive(Debug)]
+pub struct OdRef<T, const N: usize = 1> {
+    index: u16,
+    sub: u8,
+    unit: Option<&'static str>,
+    scale: Option<f64>,
+    _type: PhantomData<fn() -> T>,
+}
+
+// Implemented by hand, as derive would require T: Clone
+impl<T, const N: usize> Clone for OdRef<T, N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, const N: usize> Copy for OdRef<T, N> {}
+
+impl<T, const N: usize> PartialEq for OdRef<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.sub == other.sub
+    }
+}
+
+impl<T, const N: usize> Eq for OdRef<T, N> {}
+
+impl<T, const N: usize> OdRef<T, N> {
+    /// Create a reference to `N` sub objects, starting at `index`/`sub`
+    pub const fn new(index: u16, sub: u8) -> Self {
+        Self {
+            index,
+            sub,
+            unit: None,
+            scale: None,
+            _type: PhantomData,
+        }
+    }
+
+    /// Set the unit of the values, and the factor which converts a raw value to the unit
+    pub const fn with_units(self, unit: Option<&'static str>, scale: Option<f64>) -> Self {
+        Self {
+            unit,
+            scale,
+            ..self
+        }
+    }
+
+    /// The unit of the values, after scaling
+    pub const fn unit(&self) -> Option<&'static str> {
+        self.unit
+    }
+
+    /// The factor which converts a raw value to the unit
+    pub const fn scale(&self) -> Option<f64> {
+        self.scale
+    }
+
+    /// The object index
+    pub const fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// The sub index of the first sub object
+    pub const fn sub(&self) -> u8 {
+        self.sub
+    }
+
+    /// The number of sub objects referenced
+    pub const fn len(&self) -> usize {
+        N
+    }
+
+    /// Returns true if no sub objects are referenced
+    pub const fn is_empty(&self) -> bool {
+        N == 0
+    }
+
+    /// Get a reference to the `i`th sub object, counting from 0
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not less than `N`
+    pub const fn at(&self, i: usize) -> OdRef<T> {
+        assert!(i < N, "OdRef element out of range");
+        OdRef::new(self.index, self.sub + i as u8).with_units(self.unit, self.scale)
+    }
+
+    /// Format a raw value in the unit, e.g. "3.321 V"
+    ///
+    /// See [`Value::format_scaled`].
+    pub fn format(&self, raw: T) -> String
+    where
+        T: Into<Value>,
+    {
+        raw.into().format_scaled(self.scale, self.unit)
+    }
+}
+
+impl<T> From<OdRef<T>> for ObjectId {
+    fn from(r: OdRef<T>) -> Self {
+        ObjectId {
+            index: r.index,
+            sub: r.sub,
+        }
+    }
+}
+
+/// Include the client bindings generated by `zencan-build` in build.rs
+///
+/// The name is the one passed to `zencan_build::build_client_bindings_from_device_config`.
+#[macro_export]
+macro_rules! include_bindings {
+    ($name: tt) => {
+        include!(env!(
+            concat!("ZENCAN_INCLUDE_CLIENT_", stringify!($name),),
+            concat!(
+                "Missing env var ",
+                "ZENCAN_INCLUDE_CLIENT_",
+                stringify!($name),
+                ". Did you generate client bindings named ",
+                stringify!($name),
+                " in build.rs?"
+            )
+        ));
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_od_ref() {
+        const ARRAY: OdRef<u16, 4> = OdRef::new(0x2000, 1);
+        assert_eq!(4, ARRAY.len());
+        let elem = ARRAY.at(3);
+        assert_eq!(OdRef::<u16>::new(0x2000, 4), elem);
+        assert_eq!(
+            ObjectId {
+                index: 0x2000,
+                sub: 4
+            },
+            elem.into()
+        );
+    }
+
+    #[test]
+    fn test_od_ref_units() {
+        const VOLTAGES: OdRef<u16, 2> = OdRef::new(0x2000, 1).with_units(Some("V"), Some(0.001));
+        assert_eq!("3.321 V", VOLTAGES.at(1).format(3321));
+        assert_eq!(Some("V"), VOLTAGES.at(0).unit());
+        assert_eq!("12", OdRef::<u8>::new(0x2001, 0).format(12));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_od_ref_out_of_range() {
+        OdRef::<u16, 4>::new(0x2000, 1).at(4);
+    }
+}
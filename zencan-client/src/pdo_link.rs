@@ -0,0 +1,258 @@
+//! Linking objects on two nodes with a PDO
+//!
+//! A [`PdoLinkRequest`] declares that objects on a producer node should feed objects on a consumer
+//! node, e.g. "node 2 object 0x6000.1 feeds node 5 object 0x2100.1".
+//! [`BusManager::link_pdo`](crate::BusManager::link_pdo) turns this into PDO configuration:
+//!
+//! - The size of each source object is read from the producer, and checked against the target
+//!   object on the consumer
+//! - The first disabled TPDO on the producer, and the first disabled RPDO on the consumer, are
+//!   chosen
+//! - A free COB-ID is [allocated](crate::cob_registry::CobIdRegistry::allocate) from the
+//!   manager's COB-ID registry, which holds the PDOs of known nodes and the COB-IDs observed on the
+//!   bus. The default COB-ID of the chosen TPDO is preferred, when it is free.
+//! - The RPDO is configured and enabled first, then the TPDO, so that no messages are sent before
+//!   the consumer is ready for them
+//!
+//! Only nodes which have been discovered, plus the two linked nodes, are considered when choosing
+//! the COB-ID, so the bus should be scanned first.
+//!
+//! ```no_run
+//! # async fn example(manager: &zencan_client::BusManager<zencan_client::TransportSender>) {
+//! use zencan_client::pdo_link::PdoLinkRequest;
+//!
+//! let request = PdoLinkRequest::new(2, 5).with_signal((0x6000, 1), (0x2100, 1));
+//! let linked = manager.link_pdo(&request).await.unwrap();
+//! println!("Linked on COB-ID 0x{:X}", linked.cob_id);
+//! # }
+//! ```
+use snafu::Snafu;
+use zencan_common::{
+    objects::ObjectId,
+    sdo::AbortCode,
+    traits::{AsyncCanReceiver, AsyncCanSender},
+};
+
+use crate::{
+    sdo_client::RawAbortCode,
+    topology::{PdoEndpoint, TopologyPdo},
+    PdoConfig, PdoMapping, PdoValidationError, SdoClient, SdoClientError,
+};
+
+/// Error returned when linking nodes with a PDO
+#[derive(Debug, Clone, PartialEq, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum PdoLinkError {
+    /// The request has no signals
+    #[snafu(display("A PDO link must have at least one signal"))]
+    NoSignals,
+    /// A node ID in the request is not a valid node ID
+    #[snafu(display("Invalid node ID {node_id}"))]
+    InvalidNodeId {
+        /// The invalid node ID
+        node_id: u8,
+    },
+    /// All of the producer's TPDOs are already enabled
+    #[snafu(display("Node {node_id} has no free TPDO"))]
+    NoFreeTpdo {
+        /// The producer node
+        node_id: u8,
+    },
+    /// All of the consumer's RPDOs are already enabled
+    #[snafu(display("Node {node_id} has no free RPDO"))]
+    NoFreeRpdo {
+        /// The consumer node
+        node_id: u8,
+    },
+    /// Every PDO COB-ID is already in use
+    #[snafu(display("No free PDO COB-ID"))]
+    NoFreeCobId,
+    /// The COB-ID given in the request is already in use
+    #[snafu(display("COB-ID 0x{cob_id:X} is already in use"))]
+    CobIdInUse {
+        /// The requested COB-ID
+        cob_id: u32,
+    },
+    /// A source or target object cannot be mapped
+    #[snafu(display("Invalid PDO for node {node_id}: {source}"))]
+    Validation {
+        /// The node the object belongs to
+        node_id: u8,
+        /// The underlying error
+        source: PdoValidationError,
+    },
+    /// An SDO transfer failed
+    #[snafu(display("SDO error linking PDO on node {node_id}: {source}"))]
+    Sdo {
+        /// The node being accessed
+        node_id: u8,
+        /// The underlying error
+        source: SdoClientError,
+    },
+}
+
+/// A request to feed objects on one node from objects on another, using a PDO
+///
+/// See the [module documentation](self).
+#[derive(Clone, Debug, PartialEq)]
+pub struct PdoLinkRequest {
+    /// The node which sends the PDO
+    pub producer: u8,
+    /// The node which receives the PDO
+    pub consumer: u8,
+    /// Pairs of (source object on the producer, target object on the consumer), in the order they
+    /// are mapped
+    pub signals: Vec<(ObjectId, ObjectId)>,
+    /// The transmission type of the TPDO and RPDO
+    ///
+    /// Default: 254 (event driven)
+    pub transmission_type: u8,
+    /// The COB-ID to use. When None, a free COB-ID is chosen.
+    pub cob_id: Option<u32>,
+}
+
+impl PdoLinkRequest {
+    /// Create a request to link `producer` to `consumer`, with no signals
+    pub fn new(producer: u8, consumer: u8) -> Self {
+        Self {
+            producer,
+            consumer,
+            signals: Vec::new(),
+            transmission_type: 254,
+            cob_id: None,
+        }
+    }
+
+    /// Add a signal, from `source` on the producer to `target` on the consumer
+    pub fn with_signal(mut self, source: impl Into<ObjectId>, target: impl Into<ObjectId>) -> Self {
+        self.signals.push((source.into(), target.into()));
+        self
+    }
+
+    /// Set the transmission type
+    pub fn with_transmission_type(mut self, transmission_type: u8) -> Self {
+        self.transmission_type = transmission_type;
+        self
+    }
+
+    /// Use a specific COB-ID, instead of choosing a free one
+    pub fn with_cob_id(mut self, cob_id: u32) -> Self {
+        self.cob_id = Some(cob_id);
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), PdoLinkError> {
+        for node_id in [self.producer, self.consumer] {
+            if !(1..=127).contains(&node_id) {
+                return InvalidNodeIdSnafu { node_id }.fail();
+            }
+        }
+        if self.signals.is_empty() {
+            return NoSignalsSnafu.fail();
+        }
+        Ok(())
+    }
+}
+
+/// The PDOs configured by [`BusManager::link_pdo`](crate::BusManager::link_pdo)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkedPdo {
+    /// The COB-ID of the link
+    pub cob_id: u32,
+    /// The TPDO configured on the producer
+    pub producer: PdoEndpoint,
+    /// The RPDO configured on the consumer
+    pub consumer: PdoEndpoint,
+}
+
+/// Get the number of the first disabled PDO
+pub(crate) fn free_pdo(pdos: &[TopologyPdo]) -> Option<usize> {
+    pdos.iter().find(|p| !p.config.enabled).map(|p| p.pdo)
+}
+
+/// Get the COB-ID of a TPDO in the predefined connection set, if it has one
+pub(crate) fn default_cob_id(tpdo: PdoEndpoint) -> Option<u32> {
+    (tpdo.pdo < 4).then(|| 0x180 + 0x100 * tpdo.pdo as u32 + tpdo.node_id as u32)
+}
+
+/// Read an object to find its size in bits, for mapping it to a PDO
+pub(crate) async fn object_size<S: AsyncCanSender, R: AsyncCanReceiver>(
+    client: &mut SdoClient<S, R>,
+    object: ObjectId,
+) -> Result<u8, PdoValidationError> {
+    let ObjectId { index, sub } = object;
+    match client.upload(index, sub).await {
+        Ok(data) if data.len() <= 8 => Ok(data.len() as u8 * 8),
+        Ok(data) => Err(PdoValidationError::NotMappable {
+            index,
+            sub,
+            size: data.len() * 8,
+        }),
+        Err(SdoClientError::ServerAbort {
+            abort_code: RawAbortCode::Valid(AbortCode::NoSuchObject | AbortCode::NoSuchSubIndex),
+            ..
+        }) => Err(PdoValidationError::NoSuchObject { index, sub }),
+        Err(source) => Err(PdoValidationError::Sdo { index, sub, source }),
+    }
+}
+
+/// Build the mappings for one side of a link, given the size of each source object
+pub(crate) fn link_config(
+    objects: impl Iterator<Item = ObjectId>,
+    sizes: &[u8],
+    cob: u32,
+    transmission_type: u8,
+) -> PdoConfig {
+    PdoConfig {
+        cob,
+        enabled: true,
+        mappings: objects
+            .zip(sizes)
+            .map(|(object, &size)| PdoMapping {
+                index: object.index,
+                sub: object.sub,
+                size,
+            })
+            .collect(),
+        transmission_type,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pdo(pdo: usize, enabled: bool) -> TopologyPdo {
+        TopologyPdo {
+            pdo,
+            config: PdoConfig {
+                cob: 0x181,
+                enabled,
+                mappings: Vec::new(),
+                transmission_type: 254,
+            },
+        }
+    }
+
+    #[test]
+    fn test_free_pdo() {
+        assert_eq!(Some(1), free_pdo(&[pdo(0, true), pdo(1, false)]));
+        assert_eq!(None, free_pdo(&[pdo(0, true)]));
+        let tpdo = PdoEndpoint { node_id: 5, pdo: 1 };
+        assert_eq!(Some(0x285), default_cob_id(tpdo));
+        assert_eq!(None, default_cob_id(PdoEndpoint { node_id: 5, pdo: 4 }));
+    }
+
+    #[test]
+    fn test_validate_request() {
+        let request = PdoLinkRequest::new(1, 2);
+        assert_eq!(Err(PdoLinkError::NoSignals), request.validate());
+        let request = request.with_signal((0x2000, 1), (0x2001, 0));
+        assert_eq!(Ok(()), request.validate());
+        let request = PdoLinkRequest::new(1, 0).with_signal((0x2000, 1), (0x2001, 0));
+        assert_eq!(
+            Err(PdoLinkError::InvalidNodeId { node_id: 0 }),
+            request.validate()
+        );
+    }
+}
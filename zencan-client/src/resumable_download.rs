@@ -0,0 +1,221 @@
+//! Block downloads which resume from where an interrupted attempt stopped
+//!
+//! A block download is confirmed by the server one block at a time, and the zencan SDO server
+//! writes each block to the object as it is confirmed. When a large download, such as a firmware
+//! image, is interrupted by a transport error, the data up to the last confirmed block has already
+//! been written, and only the rest needs to be sent again.
+//!
+//! SDO has no way to start a transfer part way through an object, so resuming requires help from
+//! the device: an application defined offset object, which is written with the offset into the
+//! target object before the remaining data is downloaded. The target object must then apply that
+//! offset to the data which follows, in both its `write` and `begin_partial` methods, as the
+//! remaining data may fit in a single block.
+//!
+//! [`SdoClient::resumable_block_download`] keeps a [`DownloadCheckpoint`] up to date as blocks are
+//! confirmed. Retryable errors, and failures to send, are resumed from the checkpoint
+//! automatically, up to the [transfer retries](SdoClient::set_transfer_retries). The checkpoint
+//! can also be persisted, e.g. as JSON, so that a download can be resumed later by a new client,
+//! once a failed transport is back. The checkpoint records a CRC of the data already sent, so that
+//! it is not used to resume the download of different data.
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, Snafu};
+use zencan_common::{
+    sdo::AbortCode,
+    traits::{AsyncCanReceiver, AsyncCanSender},
+};
+
+use crate::{SdoClient, SdoClientError};
+
+type Crc = crc16::State<crc16::XMODEM>;
+
+/// The progress of a resumable block download
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadCheckpoint {
+    /// The index of the object being written
+    pub index: u16,
+    /// The sub index of the object being written
+    pub sub: u8,
+    /// The index of the u32 object which sets the offset of the next write
+    pub offset_index: u16,
+    /// The sub index of the u32 object which sets the offset of the next write
+    pub offset_sub: u8,
+    /// The total size of the data
+    pub size: usize,
+    /// The number of bytes confirmed by the server
+    pub offset: usize,
+    /// The CRC16 (XMODEM) of the confirmed bytes
+    pub crc: u16,
+}
+
+impl DownloadCheckpoint {
+    /// Create a checkpoint for a download of `size` bytes which has not started
+    pub fn new(index: u16, sub: u8, offset_index: u16, offset_sub: u8, size: usize) -> Self {
+        Self {
+            index,
+            sub,
+            offset_index,
+            offset_sub,
+            size,
+            offset: 0,
+            crc: Crc::calculate(&[]),
+        }
+    }
+
+    /// Returns true if all of the data has been confirmed
+    pub fn is_complete(&self) -> bool {
+        self.offset == self.size
+    }
+
+    /// Check that the checkpoint was recorded for a download of `data`
+    pub fn check(&self, data: &[u8]) -> Result<(), ResumableDownloadError> {
+        ensure!(
+            data.len() == self.size && self.offset <= self.size,
+            CheckpointMismatchSnafu
+        );
+        ensure!(
+            Crc::calculate(&data[..self.offset]) == self.crc,
+            CheckpointMismatchSnafu
+        );
+        Ok(())
+    }
+}
+
+/// Error returned by a resumable block download
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum ResumableDownloadError {
+    /// The checkpoint was not recorded for a download of the data given
+    #[snafu(display("The checkpoint does not match the data to download"))]
+    CheckpointMismatch,
+    /// The SDO transfer failed
+    #[snafu(display("Transfer failed after {attempts} attempt(s), at offset {offset}: {source}"))]
+    Sdo {
+        /// The number of times the transfer was attempted
+        attempts: u32,
+        /// The number of bytes confirmed by the server, from which the download can be resumed
+        offset: usize,
+        /// The error from the last attempt
+        source: SdoClientError,
+    },
+}
+
+impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
+    /// Download `data` with a block download, resuming from `checkpoint`
+    ///
+    /// `checkpoint` must have been created with [`DownloadCheckpoint::new`] for the same data, or
+    /// recorded by an earlier call. It is updated as each block is confirmed by the server, after
+    /// which `on_checkpoint` is called, e.g. to persist it. See the
+    /// [module docs](crate::resumable_download) for what is required of the device.
+    pub async fn resumable_block_download(
+        &mut self,
+        data: &[u8],
+        checkpoint: &mut DownloadCheckpoint,
+        mut on_checkpoint: impl FnMut(&DownloadCheckpoint) + Send,
+    ) -> Result<(), ResumableDownloadError> {
+        checkpoint.check(data)?;
+        let (index, sub) = (checkpoint.index, checkpoint.sub);
+        let mut crc = Crc::new();
+        crc.update(&data[..checkpoint.offset]);
+        let mut attempt = 1;
+        while !checkpoint.is_complete() {
+            let result = self
+                .resume_attempt(data, checkpoint, &mut crc, &mut on_checkpoint)
+                .await;
+            let Err(error) = result else {
+                break;
+            };
+            // The server may still be waiting for the rest of the failed transfer
+            self.send_abort(index, sub, AbortCode::SdoTimeout).await;
+            let resumable =
+                error.is_retryable() || matches!(error, SdoClientError::SocketSendFailed);
+            if !resumable || attempt > self.transfer_retries() {
+                return Err(ResumableDownloadError::Sdo {
+                    attempts: attempt,
+                    offset: checkpoint.offset,
+                    source: error,
+                });
+            }
+            log::warn!(
+                "Download to 0x{index:X}sub{sub} failed ({error}), resuming from offset {}",
+                checkpoint.offset
+            );
+            attempt += 1;
+        }
+        Ok(())
+    }
+
+    /// Write the offset and download the rest of the data once
+    async fn resume_attempt(
+        &mut self,
+        data: &[u8],
+        checkpoint: &mut DownloadCheckpoint,
+        crc: &mut Crc,
+        on_checkpoint: &mut (impl FnMut(&DownloadCheckpoint) + Send),
+    ) -> Result<(), SdoClientError> {
+        let start = checkpoint.offset;
+        let (index, sub) = (checkpoint.index, checkpoint.sub);
+        let (offset_index, offset_sub) = (checkpoint.offset_index, checkpoint.offset_sub);
+        match self
+            .download_u32(offset_index, offset_sub, start as u32)
+            .await
+        {
+            // A server which has not yet handled the abort of the interrupted transfer takes the
+            // write of the offset as a request during that transfer, and aborts the transfer
+            // instead. The server is idle after that, so the write is repeated.
+            Err(SdoClientError::ServerAbort {
+                index: abort_index,
+                sub: abort_sub,
+                ..
+            }) if (abort_index, abort_sub) == (index, sub) => {
+                self.download_u32(offset_index, offset_sub, start as u32)
+                    .await?
+            }
+            result => result?,
+        }
+        let mut advance = |checkpoint: &mut DownloadCheckpoint, confirmed: usize| {
+            if confirmed > checkpoint.offset {
+                crc.update(&data[checkpoint.offset..confirmed]);
+                checkpoint.offset = confirmed;
+                checkpoint.crc = crc.get();
+                on_checkpoint(checkpoint);
+            }
+        };
+        self.block_download_with_progress(index, sub, &data[start..], &mut |bytes, _| {
+            advance(checkpoint, start + bytes)
+        })
+        .await?;
+        // The last block is confirmed by the end of the transfer
+        advance(checkpoint, data.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint() {
+        let data = Vec::from_iter((0..2000).map(|i| i as u8));
+        let mut checkpoint = DownloadCheckpoint::new(0x5000, 1, 0x5001, 0, data.len());
+        assert!(checkpoint.check(&data).is_ok());
+        assert!(!checkpoint.is_complete());
+
+        checkpoint.offset = 889;
+        checkpoint.crc = Crc::calculate(&data[..889]);
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        let restored: DownloadCheckpoint = serde_json::from_str(&json).unwrap();
+        assert_eq!(checkpoint, restored);
+        assert!(restored.check(&data).is_ok());
+
+        // Changes to the data already sent, or to its size, are detected
+        let mut changed = data.clone();
+        changed[100] ^= 1;
+        assert!(restored.check(&changed).is_err());
+        assert!(restored.check(&data[..1999]).is_err());
+        // Changes to the data not yet sent are not
+        changed[100] ^= 1;
+        changed[1000] ^= 1;
+        assert!(restored.check(&changed).is_ok());
+    }
+}
@@ -2,16 +2,36 @@ use std::time::Duration;
 
 use snafu::Snafu;
 use zencan_common::{
-    constants::{object_ids, values::SAVE_CMD},
+    client::{ClientTransfer, SdoBlockDownload, SdoDownload, SdoTransferError, SdoUpload},
+    constants::{
+        object_ids,
+        values::{NMT_UNLOCK_CMD, SAVE_CMD},
+    },
+    event_log::{EventLogEntry, ENTRY_SIZE as EVENT_LOG_ENTRY_SIZE},
+    loopback_test::LoopbackStatus,
     lss::LssIdentity,
     messages::CanId,
-    sdo::{AbortCode, BlockSegment, SdoRequest, SdoResponse},
+    objects::{DataType, ObjectId},
+    sdo::{AbortCode, SdoRequest, SdoResponse, SdoServerStatus, SdoTransferState},
     traits::{AsyncCanReceiver, AsyncCanSender},
+    value::{Value, ValueError},
 };
 
-use crate::node_configuration::PdoConfig;
+use crate::bus_load::{BusLoadLimiter, FramePacer, FramePacing};
+use crate::bus_manager::SdoValue;
+use crate::bus_silence::BusActivity;
+use crate::competing_master::MasterActivity;
+use crate::debug_log::DebugLogSnapshot;
+use crate::echo_filter::EchoFilter;
+use crate::node_configuration::{
+    ConfigStamp, NodeConfig, PdoConfig, PdoMapping, PdoValidationError, Store,
+};
+use crate::transaction_log::{Operation, Outcome, Started, TransactionRecorder};
+use crate::transfer_monitor::{InFlightTransfer, TransferMonitor, TransferTracker};
 
 const RESPONSE_TIMEOUT: Duration = Duration::from_millis(100);
+const DEFAULT_BLOCK_THRESHOLD: usize = 64;
+const DEFAULT_TRANSFER_RETRIES: u32 = 2;
 
 /// A wrapper around the AbortCode enum to allow for unknown values
 ///
@@ -28,19 +48,57 @@ pub enum RawAbortCode {
 impl std::fmt::Display for RawAbortCode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RawAbortCode::Valid(abort_code) => write!(f, "{abort_code:?}"),
-            RawAbortCode::Unknown(code) => write!(f, "{code:X}"),
+            RawAbortCode::Valid(abort_code) => write!(f, "{abort_code}"),
+            RawAbortCode::Unknown(code) => write!(f, "Unknown abort code (0x{code:08X})"),
         }
     }
 }
 
-impl From<u32> for RawAbortCode {
-    fn from(value: u32) -> Self {
+impl RawAbortCode {
+    /// Interpret a raw abort code value
+    ///
+    /// This is lossless: the original value can always be recovered with [`Self::to_u32`].
+    pub fn from_u32(value: u32) -> Self {
         match AbortCode::try_from(value) {
             Ok(code) => Self::Valid(code),
             Err(_) => Self::Unknown(value),
         }
     }
+
+    /// Get the raw abort code value
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            RawAbortCode::Valid(code) => *code as u32,
+            RawAbortCode::Unknown(code) => *code,
+        }
+    }
+
+    /// Get the abort code, if it is a recognized value
+    pub fn known(&self) -> Option<AbortCode> {
+        match self {
+            RawAbortCode::Valid(code) => Some(*code),
+            RawAbortCode::Unknown(_) => None,
+        }
+    }
+
+    /// Returns true if repeating the request later may succeed. See [`AbortCode::is_retryable`].
+    ///
+    /// Unrecognized codes are not considered retryable.
+    pub fn is_retryable(&self) -> bool {
+        self.known().is_some_and(|c| c.is_retryable())
+    }
+
+    /// Returns true if the object cannot be accessed in the requested way. See
+    /// [`AbortCode::is_access_error`].
+    pub fn is_access_error(&self) -> bool {
+        self.known().is_some_and(|c| c.is_access_error())
+    }
+}
+
+impl From<u32> for RawAbortCode {
+    fn from(value: u32) -> Self {
+        Self::from_u32(value)
+    }
 }
 
 /// Error returned by [`SdoClient`] methods
@@ -48,10 +106,32 @@ impl From<u32> for RawAbortCode {
 pub enum SdoClientError {
     /// Timeout while awaiting an expected response
     NoResponse,
+    /// Timeout while awaiting an expected response, while no frames at all were being received
+    /// from the bus
+    ///
+    /// This suggests a problem with the interface or cabling, rather than with the node. Only
+    /// returned by clients with a silence timeout, such as those created by a
+    /// [`BusManager`](crate::BusManager) after
+    /// [`set_silence_timeout`](crate::BusManager::set_silence_timeout). See [`crate::bus_silence`].
+    #[snafu(display("No response, and no frames received from the bus for {} ms", idle_time.as_millis()))]
+    BusSilent {
+        /// The time since the last frame was received from the bus
+        idle_time: Duration,
+    },
+    /// The request was not sent, because another master is active on the bus
+    ///
+    /// Only returned by clients which yield to other masters, such as those created by a
+    /// [`BusManager`](crate::BusManager) with [`MasterPolicy::Yield`](crate::MasterPolicy::Yield).
+    /// See [`crate::competing_master`].
+    #[snafu(display("Another master is active on the bus (frame on {cob_id:?})"))]
+    CompetingMaster {
+        /// The COB-ID of the last frame received from the other master
+        cob_id: CanId,
+    },
     /// Received a response that could not be interpreted
     MalformedResponse,
     /// Received a valid SdoResponse, but with an unexpected command specifier
-    #[snafu(display("Unexpected SDO response. Expected {expecting}, got {response:?}"))]
+    #[snafu(display("Unexpected SDO response. Expected {expecting}, got {response}"))]
     UnexpectedResponse {
         /// The type of response which was expected
         expecting: String,
@@ -90,37 +170,197 @@ pub enum SdoClientError {
     /// allowed to change the block size between each block, and can request resend of part of a
     /// block by not acknowledging all segments.
     BlockSizeChangedTooSmall,
+    /// A value read back after writing did not match the value written
+    #[snafu(display("Readback of object 0x{index:X}sub{sub} did not match the written value"))]
+    VerifyFailed {
+        /// Index of the object which failed verification
+        index: u16,
+        /// Sub index of the object which failed verification
+        sub: u8,
+    },
+    /// An object was modified by another client during every attempt to update it
+    ///
+    /// Returned by [`SdoClient::update_guarded`] when the generation counter kept changing.
+    #[snafu(display("Object 0x{index:X}sub{sub} was modified by another client during update"))]
+    ConcurrentModification {
+        /// Index of the object being updated
+        index: u16,
+        /// Sub index of the object being updated
+        sub: u8,
+    },
+}
+
+impl SdoClientError {
+    /// Returns true if the error may be transient, so that repeating the operation may succeed
+    ///
+    /// This is the case when the server did not respond, aborted with a retryable abort code, or an
+    /// update conflicted with another client. A timeout while the bus is silent is not retryable,
+    /// since the bus needs attention first.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SdoClientError::NoResponse | SdoClientError::ConcurrentModification { .. } => true,
+            SdoClientError::ServerAbort { abort_code, .. } => abort_code.is_retryable(),
+            _ => false,
+        }
+    }
+
+    /// Get the abort code, if the error was caused by an abort from the server
+    pub fn abort_code(&self) -> Option<RawAbortCode> {
+        match self {
+            SdoClientError::ServerAbort { abort_code, .. } => Some(*abort_code),
+            _ => None,
+        }
+    }
 }
 
 type Result<T> = std::result::Result<T, SdoClientError>;
 
-/// Convenience macro for expecting a particular variant of a response and erroring on abort of
-/// unexpected variant
-macro_rules! match_response  {
-    ($resp: ident, $expecting: literal, $($match:pat => $code : expr),*) => {
-                match $resp {
-                    $($match => $code),*
-                    SdoResponse::Abort {
-                        index,
-                        sub,
-                        abort_code,
-                    } => {
-                        return ServerAbortSnafu {
-                            index,
-                            sub,
-                            abort_code,
-                        }
-                        .fail()
-                    }
-                    _ => {
-                        return UnexpectedResponseSnafu {
-                            expecting: $expecting,
-                            response: $resp,
-                        }
-                        .fail()
-                    }
-                }
-    };
+/// Callback receiving the bytes transferred so far, and the total size if known
+pub(crate) type ProgressFn<'a> = dyn FnMut(usize, Option<usize>) + Send + 'a;
+
+fn value_error_to_sdo(e: ValueError) -> SdoClientError {
+    match e {
+        ValueError::WrongSize { .. } => SdoClientError::UnexpectedSize,
+        _ => SdoClientError::MalformedResponse,
+    }
+}
+
+impl From<SdoTransferError> for SdoClientError {
+    fn from(value: SdoTransferError) -> Self {
+        match value {
+            SdoTransferError::ServerAbort {
+                index,
+                sub,
+                abort_code,
+            } => SdoClientError::ServerAbort {
+                index,
+                sub,
+                abort_code: RawAbortCode::from_u32(abort_code),
+            },
+            SdoTransferError::UnexpectedResponse {
+                expecting,
+                response,
+            } => SdoClientError::UnexpectedResponse {
+                expecting: expecting.into(),
+                response,
+            },
+            SdoTransferError::ToggleNotAlternated => SdoClientError::ToggleNotAlternated,
+            SdoTransferError::MismatchedObjectIndex { expected, received } => {
+                SdoClientError::MismatchedObjectIndex { expected, received }
+            }
+            SdoTransferError::BlockSizeChangedTooSmall => SdoClientError::BlockSizeChangedTooSmall,
+        }
+    }
+}
+
+/// Selects the protocol used by [`SdoClient::write`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TransferMode {
+    /// Use a block download for data larger than the block threshold, unless the server is known
+    /// not to support it, and an expedited or segmented download otherwise
+    #[default]
+    Auto,
+    /// Always use an expedited download for 4 bytes or less, or a segmented download for more
+    Segmented,
+    /// Always use a block download
+    Block,
+}
+
+/// Selects how [`SdoClient::write_verified`] checks that data was stored
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VerifyMethod {
+    /// Read the sub object back, and compare it with the data written
+    #[default]
+    Readback,
+    /// Read a CRC of the stored data from another sub object, and compare it with the CRC of the
+    /// data written
+    ///
+    /// This is for domains, which may be too large to read back, or may not be readable at all.
+    /// The CRC is the CRC-16/XMODEM used by SDO block transfers, read as a u16.
+    Crc {
+        /// Index of the object holding the CRC
+        index: u16,
+        /// Sub index of the object holding the CRC
+        sub: u8,
+    },
+}
+
+/// The statistics of a PDO, read with [`SdoClient::read_tpdo_counts`] or
+/// [`SdoClient::read_rpdo_counts`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PdoCounts {
+    /// The number of times the PDO has been sent or received
+    pub count: u32,
+    /// The time the PDO was last sent or received, in milliseconds, on the node's clock
+    ///
+    /// This is 0 if the count is 0.
+    pub last_ms: u32,
+}
+
+/// Returns true if the error indicates that the server does not support block downloads
+fn is_block_unsupported(e: &SdoClientError) -> bool {
+    e.abort_code()
+        .is_some_and(|code| code.known() == Some(AbortCode::InvalidCommandSpecifier))
+}
+
+/// The pair of COB-IDs used to communicate with an SDO server
+///
+/// The default SDO server of a node uses `0x600 + ID` for requests and `0x580 + ID` for responses,
+/// but its COB-IDs can be changed via the SDO server parameter object (0x1200), and devices may
+/// provide additional SDO servers on other COB-IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SdoCobIds {
+    /// The COB-ID of requests sent from the client to the server
+    pub request: CanId,
+    /// The COB-ID of responses sent from the server to the client
+    pub response: CanId,
+}
+
+impl SdoCobIds {
+    /// Bit set in a COB-ID object value when the COB-ID is not valid
+    const INVALID_BIT: u32 = 1 << 31;
+    /// Bit set in a COB-ID object value for an extended ID
+    const EXTENDED_BIT: u32 = 1 << 29;
+
+    /// Create a COB-ID pair
+    pub const fn new(request: CanId, response: CanId) -> Self {
+        Self { request, response }
+    }
+
+    /// The COB-IDs of the default SDO server of a node
+    pub const fn std(node_id: u8) -> Self {
+        Self::new(CanId::sdo_rx(node_id), CanId::sdo_tx(node_id))
+    }
+
+    /// Decode the COB-IDs from the values of sub-objects 1 and 2 of an SDO server parameter object
+    ///
+    /// Returns None if either COB-ID is marked as not valid, i.e. the server is disabled.
+    pub fn from_raw(request: u32, response: u32) -> Option<Self> {
+        Some(Self::new(Self::decode(request)?, Self::decode(response)?))
+    }
+
+    /// Encode the COB-IDs as the values of sub-objects 1 and 2 of an SDO server parameter object
+    pub fn raw(&self) -> (u32, u32) {
+        (Self::encode(self.request), Self::encode(self.response))
+    }
+
+    fn decode(value: u32) -> Option<CanId> {
+        if value & Self::INVALID_BIT != 0 {
+            None
+        } else if value & Self::EXTENDED_BIT != 0 {
+            Some(CanId::extended(value & 0x1FFF_FFFF))
+        } else {
+            Some(CanId::std((value & 0x7FF) as u16))
+        }
+    }
+
+    fn encode(id: CanId) -> u32 {
+        if id.is_extended() {
+            id.raw() | Self::EXTENDED_BIT
+        } else {
+            id.raw()
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -132,6 +372,20 @@ pub struct SdoClient<S, R> {
     resp_cob_id: CanId,
     sender: S,
     receiver: R,
+    timeout: Duration,
+    server_node_id: Option<u8>,
+    recorder: Option<TransactionRecorder>,
+    bus_load: Option<BusLoadLimiter>,
+    pacer: FramePacer,
+    monitor: Option<TransferMonitor>,
+    bus_activity: Option<BusActivity>,
+    transfer_mode: TransferMode,
+    block_threshold: usize,
+    block_supported: Option<bool>,
+    transfer_retries: u32,
+    echoes: EchoFilter,
+    competing_requests: u32,
+    master_activity: Option<MasterActivity>,
 }
 
 impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
@@ -141,285 +395,643 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
     /// shortcut to create a client that that default SDO server.
     ///
     /// It is possible for nodes to have other SDO servers on other COB IDs, and clients for these
-    /// can be created using [`Self::new()`]
+    /// can be created using [`Self::new()`] or [`Self::from_cob_ids()`]
     pub fn new_std(server_node_id: u8, sender: S, receiver: R) -> Self {
-        let req_cob_id = CanId::Std(0x600 + server_node_id as u16);
-        let resp_cob_id = CanId::Std(0x580 + server_node_id as u16);
-        Self::new(req_cob_id, resp_cob_id, sender, receiver)
+        let req_cob_id = CanId::sdo_rx(server_node_id);
+        let resp_cob_id = CanId::sdo_tx(server_node_id);
+        let mut client = Self::new(req_cob_id, resp_cob_id, sender, receiver);
+        client.server_node_id = Some(server_node_id);
+        client
+    }
+
+    /// Create a new SdoClient for a server on the given COB-ID pair
+    ///
+    /// Use this to talk to a device whose SDO server has been moved from the default COB-IDs, or to
+    /// one of its additional SDO servers. The COB-IDs in use can be read from the device's default
+    /// server with [`read_sdo_server_cob_ids`](Self::read_sdo_server_cob_ids).
+    pub fn from_cob_ids(cob_ids: SdoCobIds, sender: S, receiver: R) -> Self {
+        Self::new(cob_ids.request, cob_ids.response, sender, receiver)
     }
 
     /// Create a new SdoClient from request and response COB IDs
     pub fn new(req_cob_id: CanId, resp_cob_id: CanId, sender: S, receiver: R) -> Self {
+        let echoes = EchoFilter::new(sender.echoes_sent_frames());
         Self {
             req_cob_id,
             resp_cob_id,
             sender,
             receiver,
+            timeout: RESPONSE_TIMEOUT,
+            server_node_id: None,
+            recorder: None,
+            bus_load: None,
+            pacer: FramePacer::default(),
+            monitor: None,
+            bus_activity: None,
+            transfer_mode: TransferMode::Auto,
+            block_threshold: DEFAULT_BLOCK_THRESHOLD,
+            block_supported: None,
+            transfer_retries: DEFAULT_TRANSFER_RETRIES,
+            echoes,
+            competing_requests: 0,
+            master_activity: None,
         }
     }
 
-    /// Write data to a sub-object on the SDO server
-    pub async fn download(&mut self, index: u16, sub: u8, data: &[u8]) -> Result<()> {
-        if data.len() <= 4 {
-            // Do an expedited transfer
-            let msg =
-                SdoRequest::expedited_download(index, sub, data).to_can_message(self.req_cob_id);
-            self.sender.send(msg).await.unwrap(); // TODO: Expect errors
-
-            let resp = self.wait_for_response(RESPONSE_TIMEOUT).await?;
-            match_response!(
-                resp,
-                "ConfirmDownload",
-                SdoResponse::ConfirmDownload { index: _, sub: _ } => {
-                    Ok(()) // Success!
-                }
-            )
-        } else {
-            let msg = SdoRequest::initiate_download(index, sub, Some(data.len() as u32))
-                .to_can_message(self.req_cob_id);
-            self.sender.send(msg).await.unwrap();
-
-            let resp = self.wait_for_response(RESPONSE_TIMEOUT).await?;
-            match_response!(
-                resp,
-                "ConfirmDownload",
-                SdoResponse::ConfirmDownload { index: _, sub: _ } => { }
-            );
+    /// Get the COB-IDs used by this client
+    pub fn cob_ids(&self) -> SdoCobIds {
+        SdoCobIds::new(self.req_cob_id, self.resp_cob_id)
+    }
 
-            let mut toggle = false;
-            // Send segments
-            let total_segments = data.len().div_ceil(7);
-            for n in 0..total_segments {
-                let last_segment = n == total_segments - 1;
-                let segment_size = (data.len() - n * 7).min(7);
-                let seg_msg = SdoRequest::download_segment(
-                    toggle,
-                    last_segment,
-                    &data[n * 7..n * 7 + segment_size],
-                )
-                .to_can_message(self.req_cob_id);
-                self.sender
-                    .send(seg_msg)
-                    .await
-                    .expect("failed sending DL segment");
-                let resp = self.wait_for_response(RESPONSE_TIMEOUT).await?;
-                match_response!(
-                    resp,
-                    "ConfirmDownloadSegment",
-                    SdoResponse::ConfirmDownloadSegment { t } => {
-                        // Fail if toggle value doesn't match
-                        if t != toggle {
-                            let abort_msg =
-                                SdoRequest::abort(index, sub, AbortCode::ToggleNotAlternated)
-                                    .to_can_message(self.req_cob_id);
-                            self.sender
-                                .send(abort_msg)
-                                .await
-                                .expect("Error sending abort");
-                            return ToggleNotAlternatedSnafu.fail();
-                        }
-                        // Otherwise, carry on
-                    }
+    /// Record all uploads and downloads performed by this client to a transaction log
+    ///
+    /// See [`crate::transaction_log`].
+    pub fn set_recorder(&mut self, recorder: Option<TransactionRecorder>) {
+        self.recorder = recorder;
+    }
+
+    /// Pace the requests sent by this client with a bus load limiter
+    ///
+    /// See [`crate::bus_load`].
+    pub fn set_bus_load_limiter(&mut self, limiter: Option<BusLoadLimiter>) {
+        self.bus_load = limiter;
+    }
+
+    /// Set the minimum gap between the frames sent by this client
+    ///
+    /// Use this for servers which cannot receive frames back to back, such as a node whose
+    /// bootloader only polls its CAN controller once per millisecond. The pacing applies to every
+    /// request, including each segment of a block download. See [`crate::bus_load`].
+    pub fn set_frame_pacing(&mut self, pacing: FramePacing) {
+        self.pacer.set_pacing(pacing);
+    }
+
+    /// Get the frame pacing of this client
+    pub fn frame_pacing(&self) -> FramePacing {
+        self.pacer.pacing()
+    }
+
+    /// Report the transfers run by this client to a transfer monitor
+    ///
+    /// See [`crate::transfer_monitor`].
+    pub fn set_transfer_monitor(&mut self, monitor: Option<TransferMonitor>) {
+        self.monitor = monitor;
+    }
+
+    /// Report a timeout as [`SdoClientError::BusSilent`] when `activity` shows that the bus has been
+    /// silent for its silence timeout
+    ///
+    /// See [`crate::bus_silence`].
+    pub fn set_bus_activity(&mut self, activity: Option<BusActivity>) {
+        self.bus_activity = activity;
+    }
+
+    /// Send no requests while `activity` shows that another master is active, if its policy is
+    /// [`MasterPolicy::Yield`](crate::MasterPolicy::Yield)
+    ///
+    /// See [`crate::competing_master`].
+    pub fn set_master_activity(&mut self, activity: Option<MasterActivity>) {
+        self.master_activity = activity;
+    }
+
+    /// The error for a response which did not arrive in time
+    fn no_response(&self) -> SdoClientError {
+        match self.bus_activity.as_ref().and_then(|a| a.silence()) {
+            Some(idle_time) => SdoClientError::BusSilent { idle_time },
+            None => SdoClientError::NoResponse,
+        }
+    }
+
+    /// Add a transfer to the monitor, if there is one
+    fn track(&self, state: SdoTransferState, index: u16, sub: u8) -> TransferTracker {
+        let Some(monitor) = &self.monitor else {
+            return TransferTracker::none();
+        };
+        let now = std::time::Instant::now();
+        monitor.begin(InFlightTransfer {
+            node_id: self.server_node_id,
+            cob_ids: self.cob_ids(),
+            state,
+            index,
+            sub,
+            bytes_transferred: 0,
+            total_bytes: None,
+            started: now,
+            last_progress: now,
+        })
+    }
+
+    fn record<T>(
+        &self,
+        start: Started,
+        operation: Operation,
+        result: &Result<T>,
+        response: impl FnOnce(&T) -> Option<Vec<u8>>,
+    ) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record_operation(start, operation, Outcome::from_result(result, response));
+        }
+    }
+
+    fn upload_operation(&self, index: u16, sub: u8) -> Operation {
+        Operation::SdoUpload {
+            node: self.server_node_id,
+            index,
+            sub,
+        }
+    }
+
+    fn download_operation(&self, index: u16, sub: u8, data: &[u8], block: bool) -> Operation {
+        Operation::SdoDownload {
+            node: self.server_node_id,
+            index,
+            sub,
+            data: data.to_vec(),
+            block,
+        }
+    }
+
+    /// Set the time to wait for each response from the server before failing with
+    /// [`SdoClientError::NoResponse`]
+    ///
+    /// The default is 100ms.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Set the protocol used by [`write`](Self::write)
+    ///
+    /// The default is [`TransferMode::Auto`].
+    pub fn set_transfer_mode(&mut self, mode: TransferMode) {
+        self.transfer_mode = mode;
+    }
+
+    /// Set the size in bytes above which [`write`](Self::write) uses a block download in
+    /// [`TransferMode::Auto`]
+    ///
+    /// The default is 64 bytes.
+    pub fn set_block_threshold(&mut self, bytes: usize) {
+        self.block_threshold = bytes;
+    }
+
+    /// Get whether the server is known to support block downloads
+    ///
+    /// This is learned from the result of each block download, and is None until one has been
+    /// attempted.
+    pub fn block_supported(&self) -> Option<bool> {
+        self.block_supported
+    }
+
+    /// Set whether the server is known to support block downloads
+    ///
+    /// This allows knowledge of a server's capabilities to be kept when a new client is created
+    /// for the same node.
+    pub fn set_block_supported(&mut self, supported: Option<bool>) {
+        self.block_supported = supported;
+    }
+
+    /// Set the number of times a file transfer is restarted after a retryable error
+    ///
+    /// See [`upload_to_file`](Self::upload_to_file) and
+    /// [`download_from_file`](Self::download_from_file). This is also the number of times
+    /// [`update_guarded`](Self::update_guarded) starts again after a conflicting change. The
+    /// default is 2.
+    pub fn set_transfer_retries(&mut self, retries: u32) {
+        self.transfer_retries = retries;
+    }
+
+    /// Get the number of SDO requests from another client seen while waiting for responses
+    ///
+    /// A server can only talk to one client at a time, so a request on this client's request
+    /// COB-ID which was not sent by this client means another client is using the same server, and
+    /// the responses received may belong to its transfers. Echoes of this client's own requests,
+    /// as delivered by some transports, are not counted.
+    pub fn competing_requests(&self) -> u32 {
+        self.competing_requests
+    }
+
+    pub(crate) fn transfer_retries(&self) -> u32 {
+        self.transfer_retries
+    }
+
+    /// Abort any transfer of an object which the server may still be running
+    pub(crate) async fn send_abort(&mut self, index: u16, sub: u8, abort_code: AbortCode) {
+        let msg = SdoRequest::abort(index, sub, abort_code).to_can_message(self.req_cob_id);
+        self.pacer.acquire().await;
+        match self.sender.send(msg).await {
+            Ok(()) => self.echoes.record_sent(msg),
+            Err(_) => log::warn!("Failed to send SDO abort for 0x{index:X}sub{sub}"),
+        }
+    }
+
+    /// Write data to a sub-object on the SDO server, using the fastest protocol supported
+    ///
+    /// The protocol is selected by the [`TransferMode`]. In [`TransferMode::Auto`], data larger
+    /// than the block threshold is written using a block download. If the server rejects the block
+    /// download as unsupported, the data is written again using a segmented download, and later
+    /// writes use segmented downloads without trying a block download first.
+    pub async fn write(&mut self, index: u16, sub: u8, data: &[u8]) -> Result<()> {
+        self.write_with_progress(index, sub, data, &mut |_, _| {})
+            .await
+    }
+
+    /// Write data as in [`write`](Self::write), reporting progress after each response
+    pub(crate) async fn write_with_progress(
+        &mut self,
+        index: u16,
+        sub: u8,
+        data: &[u8],
+        on_progress: &mut ProgressFn<'_>,
+    ) -> Result<()> {
+        let use_block = match self.transfer_mode {
+            TransferMode::Auto => {
+                data.len() > self.block_threshold && self.block_supported != Some(false)
+            }
+            TransferMode::Segmented => false,
+            TransferMode::Block => true,
+        };
+        if !use_block {
+            return self
+                .download_with_progress(index, sub, data, on_progress)
+                .await;
+        }
+
+        match self
+            .block_download_with_progress(index, sub, data, on_progress)
+            .await
+        {
+            Err(e) if self.transfer_mode == TransferMode::Auto && is_block_unsupported(&e) => {
+                log::info!(
+                    "SDO server does not support block download, using segmented download instead"
                 );
-                toggle = !toggle;
+                self.download_with_progress(index, sub, data, on_progress)
+                    .await
             }
-            Ok(())
+            result => result,
         }
     }
 
-    /// Read a sub-object on the SDO server
-    pub async fn upload(&mut self, index: u16, sub: u8) -> Result<Vec<u8>> {
-        let mut read_buf = Vec::new();
+    /// Write data to a sub-object on the SDO server, and check that it was stored
+    ///
+    /// The data is written as in [`write`](Self::write), and then checked using `method`. If the
+    /// stored value does not match, [`SdoClientError::VerifyFailed`] is returned.
+    ///
+    /// Note that the readback of string objects returns only the bytes before the first null, so
+    /// strings written with trailing nulls will not match.
+    pub async fn write_verified(
+        &mut self,
+        index: u16,
+        sub: u8,
+        data: &[u8],
+        method: VerifyMethod,
+    ) -> Result<()> {
+        self.write(index, sub, data).await?;
+        self.verify(index, sub, data, method).await
+    }
 
-        let msg = SdoRequest::initiate_upload(index, sub).to_can_message(self.req_cob_id);
-        self.sender.send(msg).await.unwrap();
-
-        let resp = self.wait_for_response(RESPONSE_TIMEOUT).await?;
-
-        let expedited = match_response!(
-            resp,
-            "ConfirmUpload",
-            SdoResponse::ConfirmUpload {
-                n,
-                e,
-                s,
-                index: _,
-                sub: _,
-                data,
+    /// Check that a sub object holds `data`
+    async fn verify(
+        &mut self,
+        index: u16,
+        sub: u8,
+        data: &[u8],
+        method: VerifyMethod,
+    ) -> Result<()> {
+        let matches = match method {
+            VerifyMethod::Readback => self.upload(index, sub).await? == data,
+            VerifyMethod::Crc {
+                index: crc_index,
+                sub: crc_sub,
             } => {
-                if e {
-                    let mut len = 0;
-                    if s {
-                        len = 4 - n as usize;
-                    }
-                    read_buf.extend_from_slice(&data[0..len]);
-                }
-                e
-            }
-        );
-
-        if !expedited {
-            // Read segments
-            let mut toggle = false;
-            loop {
-                let msg =
-                    SdoRequest::upload_segment_request(toggle).to_can_message(self.req_cob_id);
-
-                self.sender.send(msg).await.unwrap();
-
-                let resp = self.wait_for_response(RESPONSE_TIMEOUT).await?;
-                match_response!(
-                    resp,
-                    "UploadSegment",
-                    SdoResponse::UploadSegment { t, n, c, data } => {
-                        if t != toggle {
-                            self.sender
-                                .send(
-                                    SdoRequest::abort(index, sub, AbortCode::ToggleNotAlternated)
-                                        .to_can_message(self.req_cob_id),
-                                )
-                                .await
-                                .expect("Error sending abort");
-                            return ToggleNotAlternatedSnafu.fail();
-                        }
-                        read_buf.extend_from_slice(&data[0..7 - n as usize]);
-                        if c {
-                            // Transfer complete
-                            break;
-                        }
-                    }
-                );
-                toggle = !toggle;
+                let crc = self.upload_u16(crc_index, crc_sub).await?;
+                crc == crc16::State::<crc16::XMODEM>::calculate(data)
             }
+        };
+        if matches {
+            Ok(())
+        } else {
+            VerifyFailedSnafu { index, sub }.fail()
         }
-        Ok(read_buf)
     }
 
-    /// Perform a block download to transfer data to an object
+    /// Write data with a download, and read it back if `verify` is true
+    async fn download_checked(
+        &mut self,
+        index: u16,
+        sub: u8,
+        data: &[u8],
+        verify: bool,
+    ) -> Result<()> {
+        self.download(index, sub, data).await?;
+        if verify {
+            self.verify(index, sub, data, VerifyMethod::Readback)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Write data to a sub-object on the SDO server
     ///
-    /// Block downloads are more efficient for large amounts of data, but may not be supported by
-    /// all devices.
-    pub async fn block_download(&mut self, index: u16, sub: u8, data: &[u8]) -> Result<()> {
-        self.sender
-            .send(
-                SdoRequest::InitiateBlockDownload {
-                    cc: true, // CRC supported
-                    s: true,  // size specified
-                    index,
-                    sub,
-                    size: data.len() as u32,
-                }
-                .to_can_message(self.req_cob_id),
-            )
+    /// An expedited download is used for 4 bytes or less, and a segmented download otherwise. See
+    /// also [`write`](Self::write), which can select a block download when it is faster.
+    pub async fn download(&mut self, index: u16, sub: u8, data: &[u8]) -> Result<()> {
+        self.download_with_progress(index, sub, data, &mut |_, _| {})
             .await
-            .map_err(|_| SocketSendFailedSnafu {}.build())?;
-
-        let resp = self.wait_for_response(RESPONSE_TIMEOUT).await?;
-
-        let (crc_enabled, mut blksize) = match_response!(
-            resp,
-            "ConfirmBlockDownload",
-            SdoResponse::ConfirmBlockDownload {
-                sc,
-                index: resp_index,
-                sub: resp_sub,
-                blksize,
-            } => {
-                if index != resp_index || sub != resp_sub {
-                    return MismatchedObjectIndexSnafu {
-                        expected: (index, sub),
-                        received: (resp_index, resp_sub),
-                    }
-                    .fail();
-                }
-                (sc, blksize)
+    }
+
+    async fn download_with_progress(
+        &mut self,
+        index: u16,
+        sub: u8,
+        data: &[u8],
+        on_progress: &mut ProgressFn<'_>,
+    ) -> Result<()> {
+        let start = Started::now();
+        let tracker = self.track(SdoTransferState::SegmentedDownload, index, sub);
+        let mut transfer = SdoDownload::new(index, sub, data);
+        let result = self
+            .run_transfer(
+                &mut transfer,
+                |t, resp| t.handle_response(resp),
+                &mut |bytes, total| {
+                    tracker.progress(bytes, total);
+                    on_progress(bytes, total);
+                },
+            )
+            .await;
+        let operation = self.download_operation(index, sub, data, false);
+        self.record(start, operation, &result, |_| None);
+        result
+    }
+
+    /// Send all pending requests from a transfer
+    async fn send_requests(&mut self, transfer: &mut impl ClientTransfer) -> Result<()> {
+        if let Some(cob_id) = self.master_activity.as_ref().and_then(|m| m.yield_to()) {
+            return CompetingMasterSnafu { cob_id }.fail();
+        }
+        while let Some(req) = transfer.next_request() {
+            if let Some(limiter) = &self.bus_load {
+                limiter.acquire().await;
             }
-        );
-
-        let mut seqnum = 1;
-        let mut last_block_start = 0;
-        let mut segment_num = 0;
-        let total_segments = data.len().div_ceil(7);
-
-        while segment_num < total_segments {
-            let segment_start = segment_num * 7;
-            let segment_len = (data.len() - segment_start).min(7);
-            // Is this the last segment?
-            let c = segment_start + segment_len == data.len();
-            let mut segment_data = [0; 7];
-            segment_data[0..segment_len]
-                .copy_from_slice(&data[segment_start..segment_start + segment_len]);
-
-            // Send the segment
-            let segment = BlockSegment {
-                c,
-                seqnum,
-                data: segment_data,
-            };
+            self.pacer.acquire().await;
+            let msg = req.to_can_message(self.req_cob_id);
             self.sender
-                .send(segment.to_can_message(self.req_cob_id))
+                .send(msg)
                 .await
                 .map_err(|_| SocketSendFailedSnafu.build())?;
+            self.echoes.record_sent(msg);
+        }
+        Ok(())
+    }
 
-            // Expect a confirmation message after blksize segments are sent, or after sending the
-            // complete flag
-            if c || seqnum == blksize {
-                let resp = self.wait_for_response(RESPONSE_TIMEOUT).await?;
-                match_response!(
-                    resp,
-                    "ConfirmBlock",
-                    SdoResponse::ConfirmBlock {
-                        ackseq,
-                        blksize: new_blksize,
-                    } => {
-                        if ackseq == blksize {
-                            // All segments are acknowledged. Block accepted
-                            seqnum = 1;
-                            segment_num += 1;
-                            last_block_start = segment_num;
-                        } else {
-                            // Missing segments. Resend all segments after ackseq
-                            seqnum = ackseq;
-                            segment_num = last_block_start + ackseq as usize;
-                            // The spec says the block size given by the server can change between
-                            // blocks. What should a client do if it is going to resend a block, and
-                            // the server sets the block size smaller than the already delivered
-                            // segments? This shouldn't happen I think, but, it's possible.
-                            // zencan-node based nodes won't do it, but there are other devices out
-                            // there.
-                            if new_blksize < seqnum {
-                                return BlockSizeChangedTooSmallSnafu.fail();
-                            }
-                        }
-                        blksize = new_blksize;
-                    }
+    /// Drive a transfer to completion, passing each response received to `handle_response`
+    ///
+    /// `on_progress` is called after each response is handled.
+    async fn run_transfer<T: ClientTransfer>(
+        &mut self,
+        transfer: &mut T,
+        mut handle_response: impl FnMut(
+            &mut T,
+            SdoResponse,
+        ) -> std::result::Result<(), SdoTransferError>,
+        on_progress: &mut ProgressFn<'_>,
+    ) -> Result<()> {
+        // Discard responses left over from an earlier transfer, e.g. one which timed out, or one
+        // made by another client of the same server
+        self.receiver.flush();
+        loop {
+            self.send_requests(transfer).await?;
+            if transfer.is_complete() {
+                return Ok(());
+            }
+            let resp = self.wait_for_response(self.timeout).await?;
+            if let Err(e) = handle_response(transfer, resp) {
+                // Send the abort, if the transfer has one to send
+                self.send_requests(transfer).await?;
+                return Err(e.into());
+            }
+            on_progress(transfer.bytes_transferred(), transfer.total_bytes());
+        }
+    }
+
+    /// Read a sub-object on the SDO server
+    pub async fn upload(&mut self, index: u16, sub: u8) -> Result<Vec<u8>> {
+        let start = Started::now();
+        let mut read_buf = Vec::new();
+        let result = self
+            .upload_transfer(
+                index,
+                sub,
+                &mut |d| read_buf.extend_from_slice(d),
+                &mut |_, _| {},
+            )
+            .await;
+        let result = result.map(|()| read_buf);
+        self.record(start, self.upload_operation(index, sub), &result, |d| {
+            Some(d.clone())
+        });
+        result
+    }
+
+    /// Read a sub-object, passing the data to `on_data` as it is received rather than storing it
+    ///
+    /// The transaction log records the upload without its data.
+    pub(crate) async fn upload_with_progress(
+        &mut self,
+        index: u16,
+        sub: u8,
+        on_data: &mut (dyn FnMut(&[u8]) + Send),
+        on_progress: &mut ProgressFn<'_>,
+    ) -> Result<()> {
+        let start = Started::now();
+        let result = self.upload_transfer(index, sub, on_data, on_progress).await;
+        self.record(start, self.upload_operation(index, sub), &result, |_| None);
+        result
+    }
+
+    async fn upload_transfer(
+        &mut self,
+        index: u16,
+        sub: u8,
+        on_data: &mut (dyn FnMut(&[u8]) + Send),
+        on_progress: &mut ProgressFn<'_>,
+    ) -> Result<()> {
+        let tracker = self.track(SdoTransferState::SegmentedUpload, index, sub);
+        let mut transfer = SdoUpload::new(index, sub);
+        self.run_transfer(
+            &mut transfer,
+            |t, resp| t.handle_response(resp, &mut *on_data),
+            &mut |bytes, total| {
+                tracker.progress(bytes, total);
+                on_progress(bytes, total);
+            },
+        )
+        .await
+    }
+
+    /// Read multiple sub-objects from the SDO server
+    ///
+    /// The request for each object is sent as soon as the response for the previous one is
+    /// received, and responses are checked against the requested object, so that a late response
+    /// to an earlier request cannot be mistaken for the current one.
+    ///
+    /// A result is returned for each requested object, in the same order, so that an abort on one
+    /// object does not prevent reading the rest. If the server stops responding, the remaining
+    /// objects are not requested, and return the same error as the object which got no response.
+    pub async fn read_many(&mut self, objects: &[(u16, u8)]) -> Vec<Result<Vec<u8>>> {
+        let mut results = Vec::with_capacity(objects.len());
+        for &(index, sub) in objects {
+            if let Some(Err(e @ (SdoClientError::NoResponse | SdoClientError::BusSilent { .. }))) =
+                results.last()
+            {
+                results.push(Err(e.clone()));
+                continue;
+            }
+            let start = Started::now();
+            let result = self.upload_checked(index, sub).await;
+            self.record(start, self.upload_operation(index, sub), &result, |d| {
+                Some(d.clone())
+            });
+            results.push(result);
+        }
+        results
+    }
+
+    /// Read multiple sub-objects from the SDO server, converting each to a [`Value`]
+    ///
+    /// See [`Self::read_many`].
+    pub async fn read_many_values(
+        &mut self,
+        objects: &[(u16, u8, DataType)],
+    ) -> Vec<Result<Value>> {
+        let ids: Vec<(u16, u8)> = objects.iter().map(|(i, s, _)| (*i, *s)).collect();
+        self.read_many(&ids)
+            .await
+            .into_iter()
+            .zip(objects)
+            .map(|(result, (_, _, data_type))| {
+                Value::from_le_bytes(*data_type, &result?).map_err(value_error_to_sdo)
+            })
+            .collect()
+    }
+
+    /// Perform an upload, skipping any responses which do not refer to the requested object
+    async fn upload_checked(&mut self, index: u16, sub: u8) -> Result<Vec<u8>> {
+        let tracker = self.track(SdoTransferState::SegmentedUpload, index, sub);
+        let mut transfer = SdoUpload::new(index, sub);
+        self.send_requests(&mut transfer).await?;
+
+        let wait_until = tokio::time::Instant::now() + self.timeout;
+        loop {
+            let timeout = wait_until.saturating_duration_since(tokio::time::Instant::now());
+            let resp = self.wait_for_response(timeout).await?;
+            // Aborts are not checked, as some servers do not echo the object in all aborts
+            let resp_object = match resp {
+                SdoResponse::ConfirmUpload { index, sub, .. } => Some((index, sub)),
+                _ => None,
+            };
+            if resp_object.is_some_and(|obj| obj != (index, sub)) {
+                log::warn!(
+                    "Ignoring stale SDO response {resp:?} while reading 0x{index:X}sub{sub}"
                 );
-            } else {
-                seqnum += 1;
-                segment_num += 1;
+                continue;
+            }
+
+            let mut read_buf = Vec::new();
+            if let Err(e) = transfer.handle_response(resp, |d| read_buf.extend_from_slice(d)) {
+                self.send_requests(&mut transfer).await?;
+                return Err(e.into());
             }
+            tracker.progress(transfer.bytes_transferred(), transfer.total_bytes());
+            self.run_transfer(
+                &mut transfer,
+                |t, resp| t.handle_response(resp, |d| read_buf.extend_from_slice(d)),
+                &mut |bytes, total| tracker.progress(bytes, total),
+            )
+            .await?;
+            return Ok(read_buf);
         }
+    }
 
-        // End the download
-        let crc = if crc_enabled {
-            crc16::State::<crc16::XMODEM>::calculate(data)
-        } else {
-            0
-        };
+    /// Perform a block download to transfer data to an object
+    ///
+    /// Block downloads are more efficient for large amounts of data, but may not be supported by
+    /// all devices.
+    pub async fn block_download(&mut self, index: u16, sub: u8, data: &[u8]) -> Result<()> {
+        self.block_download_with_progress(index, sub, data, &mut |_, _| {})
+            .await
+    }
 
-        let n = ((7 - data.len() % 7) % 7) as u8;
+    pub(crate) async fn block_download_with_progress(
+        &mut self,
+        index: u16,
+        sub: u8,
+        data: &[u8],
+        on_progress: &mut ProgressFn<'_>,
+    ) -> Result<()> {
+        let start = Started::now();
+        let tracker = self.track(SdoTransferState::BlockDownload, index, sub);
+        let mut transfer = SdoBlockDownload::new(index, sub, data);
+        let result = self
+            .run_transfer(
+                &mut transfer,
+                |t, resp| t.handle_response(resp),
+                &mut |bytes, total| {
+                    tracker.progress(bytes, total);
+                    on_progress(bytes, total);
+                },
+            )
+            .await;
+        match &result {
+            Ok(()) => self.block_supported = Some(true),
+            Err(e) if is_block_unsupported(e) => self.block_supported = Some(false),
+            Err(_) => (),
+        }
+        let operation = self.download_operation(index, sub, data, true);
+        self.record(start, operation, &result, |_| None);
+        result
+    }
 
+    /// Find out whether the server supports block downloads, without writing to any object
+    ///
+    /// This initiates a block download of the device type (0x1000), which is read-only. A server
+    /// which supports block downloads rejects it as read-only, or acknowledges it, in which case
+    /// the transfer is aborted before any data is sent. A server without block download support
+    /// aborts with an invalid command specifier. The result is stored, and returned by
+    /// [`block_supported`](Self::block_supported).
+    pub async fn probe_block_support(&mut self) -> Result<bool> {
+        let index = object_ids::DEVICE_TYPE;
+        if let Some(cob_id) = self.master_activity.as_ref().and_then(|m| m.yield_to()) {
+            return CompetingMasterSnafu { cob_id }.fail();
+        }
+        self.pacer.acquire().await;
+        let msg =
+            SdoRequest::initiate_block_download(index, 0, false, 4).to_can_message(self.req_cob_id);
         self.sender
-            .send(SdoRequest::EndBlockDownload { n, crc }.to_can_message(self.req_cob_id))
+            .send(msg)
             .await
             .map_err(|_| SocketSendFailedSnafu.build())?;
+        self.echoes.record_sent(msg);
 
-        let resp = self.wait_for_response(RESPONSE_TIMEOUT).await?;
-        match_response!(
-            resp,
-            "ConfirmBlockDownloadEnd",
-            SdoResponse::ConfirmBlockDownloadEnd => { Ok(()) }
-        )
+        let supported = match self.wait_for_response(self.timeout).await? {
+            SdoResponse::ConfirmBlockDownload { .. } => {
+                self.send_abort(index, 0, AbortCode::GeneralError).await;
+                true
+            }
+            SdoResponse::Abort { abort_code, .. } => {
+                RawAbortCode::from_u32(abort_code).known()
+                    != Some(AbortCode::InvalidCommandSpecifier)
+            }
+            response => {
+                return UnexpectedResponseSnafu {
+                    expecting: "block download confirmation",
+                    response,
+                }
+                .fail()
+            }
+        };
+        self.block_supported = Some(supported);
+        Ok(supported)
     }
 
     /// Write to a u32 object on the SDO server
@@ -500,6 +1112,77 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
         self.download_i8(index, sub, data).await
     }
 
+    /// Read a sub object from the SDO server, and decode it as the given data type
+    pub async fn read_value(&mut self, index: u16, sub: u8, data_type: DataType) -> Result<Value> {
+        let data = self.upload(index, sub).await?;
+        Value::from_le_bytes(data_type, &data).map_err(value_error_to_sdo)
+    }
+
+    /// Write a [`Value`] to a sub object on the SDO server
+    ///
+    /// The protocol is selected as in [`write`](Self::write).
+    pub async fn write_value(&mut self, index: u16, sub: u8, value: &Value) -> Result<()> {
+        self.write(index, sub, &value.to_le_bytes()).await
+    }
+
+    /// Read a sub object, apply `f` to its value, and write back the result
+    ///
+    /// Returns the value written. This is convenient for changing some bits of a bitmask object,
+    /// but another client may write the object between the read and the write, and its change
+    /// would be lost. See [`update_guarded`](Self::update_guarded) for objects which are shared
+    /// with other clients.
+    pub async fn update<T: SdoValue + Clone>(
+        &mut self,
+        index: u16,
+        sub: u8,
+        f: impl Fn(T) -> T + Send,
+    ) -> Result<T> {
+        let value = self.read_typed(index, sub).await?;
+        let value = f(value);
+        self.write_value(index, sub, &value.clone().into()).await?;
+        Ok(value)
+    }
+
+    /// Update a sub object as in [`update`](Self::update), checking a generation counter object
+    /// for changes made by other clients
+    ///
+    /// The generation counter is an object which the device changes whenever the updated object is
+    /// written, e.g. an incrementing counter. It is read before the object, and again before the
+    /// new value is written. If it has changed, another client has written the object in between,
+    /// and the update is started again with the new value. After the [transfer
+    /// retries](Self::set_transfer_retries), [`SdoClientError::ConcurrentModification`] is
+    /// returned.
+    ///
+    /// This narrows the window in which another client's change can be lost to the time taken to
+    /// write the new value, but SDO offers no way to close it.
+    pub async fn update_guarded<T: SdoValue + Clone>(
+        &mut self,
+        index: u16,
+        sub: u8,
+        generation: impl Into<ObjectId>,
+        f: impl Fn(T) -> T + Send,
+    ) -> Result<T> {
+        let generation = generation.into();
+        for _ in 0..=self.transfer_retries {
+            let before = self.upload(generation.index, generation.sub).await?;
+            let value = f(self.read_typed(index, sub).await?);
+            let after = self.upload(generation.index, generation.sub).await?;
+            if before != after {
+                log::debug!("Object 0x{index:X}sub{sub} changed during update, retrying");
+                continue;
+            }
+            self.write_value(index, sub, &value.clone().into()).await?;
+            return Ok(value);
+        }
+        ConcurrentModificationSnafu { index, sub }.fail()
+    }
+
+    /// Read a sub object, and decode it as `T`
+    async fn read_typed<T: SdoValue>(&mut self, index: u16, sub: u8) -> Result<T> {
+        let value = self.read_value(index, sub, T::DATA_TYPE).await?;
+        T::from_value(value).ok_or(SdoClientError::MalformedResponse)
+    }
+
     /// Read a string from the SDO server
     pub async fn upload_utf8(&mut self, index: u16, sub: u8) -> Result<String> {
         let data = self.upload(index, sub).await?;
@@ -512,11 +1195,7 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
 
     /// Read a sub-object from the SDO server, assuming it is an u8
     pub async fn upload_u8(&mut self, index: u16, sub: u8) -> Result<u8> {
-        let data = self.upload(index, sub).await?;
-        if data.len() != 1 {
-            return UnexpectedSizeSnafu.fail();
-        }
-        Ok(data[0])
+        self.read_typed(index, sub).await
     }
     /// Alias for `upload_u8`
     ///
@@ -527,11 +1206,7 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
 
     /// Read a sub-object from the SDO server, assuming it is an u16
     pub async fn upload_u16(&mut self, index: u16, sub: u8) -> Result<u16> {
-        let data = self.upload(index, sub).await?;
-        if data.len() != 2 {
-            return UnexpectedSizeSnafu.fail();
-        }
-        Ok(u16::from_le_bytes(data.try_into().unwrap()))
+        self.read_typed(index, sub).await
     }
 
     /// Alias for `upload_u16`
@@ -543,11 +1218,7 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
 
     /// Read a sub-object from the SDO server, assuming it is an u32
     pub async fn upload_u32(&mut self, index: u16, sub: u8) -> Result<u32> {
-        let data = self.upload(index, sub).await?;
-        if data.len() != 4 {
-            return UnexpectedSizeSnafu.fail();
-        }
-        Ok(u32::from_le_bytes(data.try_into().unwrap()))
+        self.read_typed(index, sub).await
     }
 
     /// Alias for `upload_u32`
@@ -559,11 +1230,7 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
 
     /// Read a sub-object from the SDO server, assuming it is an i8
     pub async fn upload_i8(&mut self, index: u16, sub: u8) -> Result<i8> {
-        let data = self.upload(index, sub).await?;
-        if data.len() != 1 {
-            return UnexpectedSizeSnafu.fail();
-        }
-        Ok(i8::from_le_bytes(data.try_into().unwrap()))
+        self.read_typed(index, sub).await
     }
 
     /// Alias for `upload_i8`
@@ -575,11 +1242,7 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
 
     /// Read a sub-object from the SDO server, assuming it is an i16
     pub async fn upload_i16(&mut self, index: u16, sub: u8) -> Result<i16> {
-        let data = self.upload(index, sub).await?;
-        if data.len() != 2 {
-            return UnexpectedSizeSnafu.fail();
-        }
-        Ok(i16::from_le_bytes(data.try_into().unwrap()))
+        self.read_typed(index, sub).await
     }
 
     /// Alias for `upload_i16`
@@ -591,11 +1254,7 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
 
     /// Read a sub-object from the SDO server, assuming it is an i32
     pub async fn upload_i32(&mut self, index: u16, sub: u8) -> Result<i32> {
-        let data = self.upload(index, sub).await?;
-        if data.len() != 4 {
-            return UnexpectedSizeSnafu.fail();
-        }
-        Ok(i32::from_le_bytes(data.try_into().unwrap()))
+        self.read_typed(index, sub).await
     }
 
     /// Alias for `upload_i32`
@@ -613,6 +1272,41 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
         Ok(String::from_utf8_lossy(&bytes).into())
     }
 
+    /// Read the device type object (0x1000)
+    ///
+    /// All nodes should implement this object
+    pub async fn read_device_type(&mut self) -> Result<u32> {
+        self.upload_u32(object_ids::DEVICE_TYPE, 0).await
+    }
+
+    /// Read the COB-IDs of one of the node's SDO servers
+    ///
+    /// `server` selects the SDO server parameter object, starting from 0 for the default server at
+    /// 0x1200. Returns None if the server is disabled.
+    pub async fn read_sdo_server_cob_ids(&mut self, server: u8) -> Result<Option<SdoCobIds>> {
+        let index = object_ids::SDO_SERVER_PARAMETER + server as u16;
+        let request = self.upload_u32(index, 1).await?;
+        let response = self.upload_u32(index, 2).await?;
+        Ok(SdoCobIds::from_raw(request, response))
+    }
+
+    /// Read the SDO server status object (0x5005)
+    ///
+    /// The object is only present on nodes whose device config sets `sdo_status`. As the object is
+    /// read through the SDO server, this reports transfers on the node's other SDO servers, or
+    /// returns [`SdoServerStatus::IDLE`] for the server used to read it.
+    pub async fn read_sdo_server_status(&mut self) -> Result<SdoServerStatus> {
+        let index = object_ids::SDO_SERVER_STATUS;
+        let state = SdoTransferState::try_from(self.upload_u8(index, 1).await?)
+            .map_err(|_| SdoClientError::MalformedResponse)?;
+        Ok(SdoServerStatus {
+            state,
+            index: self.upload_u16(index, 2).await?,
+            sub: self.upload_u8(index, 3).await?,
+            bytes: self.upload_u32(index, 4).await?,
+        })
+    }
+
     /// Read the identity object
     ///
     /// All nodes should implement this object
@@ -635,6 +1329,105 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
             .await
     }
 
+    /// Write object 0x500E to accept the next protected NMT command sent to the node
+    ///
+    /// The unlock is consumed by the command, and expires if no protected command is received
+    /// within 10 seconds.
+    pub async fn unlock_nmt(&mut self) -> Result<()> {
+        self.download_u32(object_ids::NMT_UNLOCK, 0, NMT_UNLOCK_CMD)
+            .await
+    }
+
+    /// Read a backup of all persisted objects from the settings backup object (0x5F10)
+    ///
+    /// The backup is in the compact format used by the node for object storage, and can be restored
+    /// to the same node, or another node with the same firmware, with
+    /// [`restore_settings`](Self::restore_settings). Only nodes which enable `settings_backup` in
+    /// their device config implement this object.
+    pub async fn backup_settings(&mut self) -> Result<Vec<u8>> {
+        self.upload(object_ids::SETTINGS_BACKUP, 0).await
+    }
+
+    /// Restore a backup previously read with [`backup_settings`](Self::backup_settings)
+    ///
+    /// The restored values are applied immediately, but are not saved by the node. If `save` is
+    /// true, a save is requested with [`save_objects`](Self::save_objects) after the restore.
+    pub async fn restore_settings(&mut self, backup: &[u8], save: bool) -> Result<()> {
+        self.download(object_ids::SETTINGS_BACKUP, 0, backup)
+            .await?;
+        if save {
+            self.save_objects().await?;
+        }
+        Ok(())
+    }
+
+    /// Read the debug log (0x5004)
+    ///
+    /// Only nodes which enable `[debug_log]` in their device config implement this object. Use a
+    /// [`DebugLogTail`](crate::debug_log::DebugLogTail) to get the text which is new since a
+    /// previous read.
+    pub async fn read_debug_log(&mut self) -> Result<DebugLogSnapshot> {
+        let data = self.upload(object_ids::DEBUG_LOG, 2).await?;
+        DebugLogSnapshot::decode(&data).ok_or(SdoClientError::UnexpectedSize)
+    }
+
+    /// Clear the debug log (0x5004)
+    pub async fn clear_debug_log(&mut self) -> Result<()> {
+        self.download_u32(object_ids::DEBUG_LOG, 1, 0).await
+    }
+
+    /// Fetch the events in the event log (0x5008) with a sequence number of at least `seq`
+    ///
+    /// The events are returned oldest first. Pass the sequence number following the last event
+    /// received, or 0 to fetch all of the held events. If the first event returned has a higher
+    /// sequence number than requested, the events in between were overwritten before they were
+    /// fetched. If the log has been cleared since `seq` was seen, all of the held events are
+    /// returned.
+    ///
+    /// Only nodes which enable `[event_log]` in their device config implement this object.
+    pub async fn fetch_events_since(&mut self, seq: u32) -> Result<Vec<EventLogEntry>> {
+        let next_seq = self.upload_u32(object_ids::EVENT_LOG, 1).await?;
+        let seq = if seq > next_seq { 0 } else { seq };
+        self.download_u32(object_ids::EVENT_LOG, 2, seq).await?;
+        let data = self.upload(object_ids::EVENT_LOG, 3).await?;
+        if data.len() % EVENT_LOG_ENTRY_SIZE != 0 {
+            return UnexpectedSizeSnafu.fail();
+        }
+        Ok(EventLogEntry::decode_all(&data).collect())
+    }
+
+    /// Clear the event log (0x5008)
+    ///
+    /// The sequence numbers of new events start again from 0.
+    pub async fn clear_event_log(&mut self) -> Result<()> {
+        self.download_u32(object_ids::EVENT_LOG, 1, 0).await
+    }
+
+    /// Run the loopback self-test (0x5009), and wait for its result
+    ///
+    /// The node sends a test frame, and checks that it is received back, which tests its
+    /// transceiver and bus wiring. The status is polled every `poll_interval` until the test
+    /// finishes. A node gives up waiting for the frame after 100 ms, so a test which has not
+    /// finished after 1 second returns [`SdoClientError::NoResponse`].
+    ///
+    /// Only nodes which enable `loopback_test` in their device config implement this object.
+    pub async fn run_loopback_test(&mut self, poll_interval: Duration) -> Result<LoopbackStatus> {
+        self.download_u8(object_ids::LOOPBACK_TEST, 1, 1).await?;
+        let wait_until = tokio::time::Instant::now() + Duration::from_secs(1);
+        loop {
+            let status =
+                LoopbackStatus::try_from(self.upload_u8(object_ids::LOOPBACK_TEST, 2).await?)
+                    .map_err(|_| SdoClientError::MalformedResponse)?;
+            if status.is_finished() {
+                return Ok(status);
+            }
+            if tokio::time::Instant::now() >= wait_until {
+                return NoResponseSnafu.fail();
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
     /// Read the device name object
     ///
     /// All nodes should implement this object
@@ -658,6 +1451,38 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
             .await
     }
 
+    /// Read the error register object (0x1001)
+    ///
+    /// All nodes should implement this object. Use
+    /// [`error_register_names`](zencan_common::decode::error_register_names) to decode the bits.
+    pub async fn read_error_register(&mut self) -> Result<u8> {
+        self.upload_u8(object_ids::ERROR_REGISTER, 0).await
+    }
+
+    /// Read the error history from the pre-defined error field object (0x1003)
+    ///
+    /// Returns the recorded errors, most recent first. The lower 16 bits of each entry are the EMCY
+    /// error code, and the upper 16 bits are manufacturer specific information.
+    pub async fn read_error_history(&mut self) -> Result<Vec<u32>> {
+        let count = self
+            .upload_u8(object_ids::PREDEFINED_ERROR_FIELD, 0)
+            .await?;
+        let mut errors = Vec::with_capacity(count as usize);
+        for sub in 1..=count {
+            errors.push(
+                self.upload_u32(object_ids::PREDEFINED_ERROR_FIELD, sub)
+                    .await?,
+            );
+        }
+        Ok(errors)
+    }
+
+    /// Clear the error history by writing 0 to the number of errors in object 0x1003
+    pub async fn clear_error_history(&mut self) -> Result<()> {
+        self.download_u8(object_ids::PREDEFINED_ERROR_FIELD, 0, 0)
+            .await
+    }
+
     /// Configure a transmit PDO on the device
     ///
     /// This is a convenience function to write the PDO comm and mapping objects based on a
@@ -665,7 +1490,7 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
     pub async fn configure_tpdo(&mut self, pdo_num: usize, cfg: &PdoConfig) -> Result<()> {
         let comm_index = 0x1800 + pdo_num as u16;
         let mapping_index = 0x1a00 + pdo_num as u16;
-        self.store_pdo(comm_index, mapping_index, cfg).await
+        self.store_pdo(comm_index, mapping_index, cfg, false).await
     }
 
     /// Configure a receive PDO on the device
@@ -675,7 +1500,399 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
     pub async fn configure_rpdo(&mut self, pdo_num: usize, cfg: &PdoConfig) -> Result<()> {
         let comm_index = 0x1400 + pdo_num as u16;
         let mapping_index = 0x1600 + pdo_num as u16;
-        self.store_pdo(comm_index, mapping_index, cfg).await
+        self.store_pdo(comm_index, mapping_index, cfg, false).await
+    }
+
+    /// Read the configuration of a transmit PDO from the device
+    ///
+    /// Returns None if the device does not implement the PDO.
+    pub async fn read_tpdo(&mut self, pdo_num: usize) -> Result<Option<PdoConfig>> {
+        let comm_index = 0x1800 + pdo_num as u16;
+        let mapping_index = 0x1a00 + pdo_num as u16;
+        self.load_pdo(comm_index, mapping_index).await
+    }
+
+    /// Read the configuration of a receive PDO from the device
+    ///
+    /// Returns None if the device does not implement the PDO.
+    pub async fn read_rpdo(&mut self, pdo_num: usize) -> Result<Option<PdoConfig>> {
+        let comm_index = 0x1400 + pdo_num as u16;
+        let mapping_index = 0x1600 + pdo_num as u16;
+        self.load_pdo(comm_index, mapping_index).await
+    }
+
+    /// Read the statistics of a transmit PDO (0x500A and 0x500B)
+    ///
+    /// Only nodes which set `statistics` in the `[pdos]` section of their device config implement
+    /// these objects. The node's current time is not available, so a client checking whether a
+    /// PDO is still being sent should compare two reads.
+    pub async fn read_tpdo_counts(&mut self, pdo_num: usize) -> Result<PdoCounts> {
+        let sub = pdo_num as u8 + 1;
+        Ok(PdoCounts {
+            count: self.upload_u32(object_ids::TPDO_TX_COUNT, sub).await?,
+            last_ms: self.upload_u32(object_ids::TPDO_TX_TIME, sub).await?,
+        })
+    }
+
+    /// Read the statistics of a receive PDO (0x500C and 0x500D)
+    ///
+    /// Only nodes which set `statistics` in the `[pdos]` section of their device config implement
+    /// these objects.
+    pub async fn read_rpdo_counts(&mut self, pdo_num: usize) -> Result<PdoCounts> {
+        let sub = pdo_num as u8 + 1;
+        Ok(PdoCounts {
+            count: self.upload_u32(object_ids::RPDO_RX_COUNT, sub).await?,
+            last_ms: self.upload_u32(object_ids::RPDO_RX_TIME, sub).await?,
+        })
+    }
+
+    /// Write all of the settings in a [`NodeConfig`] to the device
+    ///
+    /// Settings are written in this order: PDOs, heartbeat producer time, EMCY inhibit time, SYNC
+    /// settings, stores, and finally the generic writes. Writing stops at the first error.
+    ///
+    /// If the config has a [verify configuration](NodeConfig::verify_configuration) stamp, the
+    /// verify configuration object (0x1020) is cleared before anything else is written, and set to
+    /// the stamp once everything has been written. A node which is left partially configured by an
+    /// error is therefore never marked as configured.
+    ///
+    /// Only stores and writes with `verify` set are read back. See
+    /// [`apply_node_config_verified`](Self::apply_node_config_verified) to verify every setting.
+    ///
+    /// If the config sets a frame pacing, it is applied to this client before anything is written.
+    pub async fn apply_node_config(&mut self, config: &NodeConfig) -> Result<()> {
+        self.apply_node_config_inner(config, false).await
+    }
+
+    /// Write all of the settings in a [`NodeConfig`] to the device, reading back each one
+    ///
+    /// This is the same as [`apply_node_config`](Self::apply_node_config), except that every
+    /// setting is read back after it is written, as if `verify` were set on all of the stores and
+    /// writes. Writing stops at the first error, including the first setting which does not match,
+    /// which is returned as [`SdoClientError::VerifyFailed`].
+    pub async fn apply_node_config_verified(&mut self, config: &NodeConfig) -> Result<()> {
+        self.apply_node_config_inner(config, true).await
+    }
+
+    async fn apply_node_config_inner(&mut self, config: &NodeConfig, verify: bool) -> Result<()> {
+        if let Some(pacing) = config.frame_pacing() {
+            self.set_frame_pacing(pacing);
+        }
+        if config.verify_configuration().is_some() {
+            self.write_verify_configuration(ConfigStamp::default(), verify)
+                .await?;
+        }
+        for (pdo_num, cfg) in config.tpdos() {
+            let pdo_num = *pdo_num as u16;
+            self.store_pdo(0x1800 + pdo_num, 0x1a00 + pdo_num, cfg, verify)
+                .await?;
+        }
+        for (pdo_num, cfg) in config.rpdos() {
+            let pdo_num = *pdo_num as u16;
+            self.store_pdo(0x1400 + pdo_num, 0x1600 + pdo_num, cfg, verify)
+                .await?;
+        }
+        if let Some(period) = config.heartbeat_producer_time() {
+            let data = period.to_le_bytes();
+            self.download_checked(object_ids::HEARTBEAT_PRODUCER_TIME, 0, &data, verify)
+                .await?;
+        }
+        if let Some(inhibit) = config.emcy_inhibit_time() {
+            let data = inhibit.to_le_bytes();
+            self.download_checked(object_ids::EMCY_INHIBIT_TIME, 0, &data, verify)
+                .await?;
+        }
+        if let Some(sync) = config.sync() {
+            if let Some(cob_id) = sync.cob_id {
+                let data = cob_id.to_le_bytes();
+                self.download_checked(object_ids::SYNC_COB_ID, 0, &data, verify)
+                    .await?;
+            }
+            if let Some(period) = sync.cycle_period {
+                let data = period.to_le_bytes();
+                self.download_checked(object_ids::COMM_CYCLE_PERIOD, 0, &data, verify)
+                    .await?;
+            }
+            if let Some(length) = sync.window_length {
+                let data = length.to_le_bytes();
+                self.download_checked(object_ids::SYNC_WINDOW_LENGTH, 0, &data, verify)
+                    .await?;
+            }
+            if let Some(overflow) = sync.counter_overflow {
+                self.download_checked(object_ids::SYNC_COUNTER_OVERFLOW, 0, &[overflow], verify)
+                    .await?;
+            }
+        }
+        for store in config.stores().iter().chain(config.writes()) {
+            let data = store.raw_value();
+            self.download_checked(store.index, store.sub, &data, verify || store.verify)
+                .await?;
+        }
+        if let Some(stamp) = config.verify_configuration() {
+            self.write_verify_configuration(stamp, verify).await?;
+        }
+        Ok(())
+    }
+
+    /// Read the verify configuration object (0x1020)
+    ///
+    /// Returns None if the device does not implement the object.
+    pub async fn read_verify_configuration(&mut self) -> Result<Option<ConfigStamp>> {
+        let date = match self.upload_u32(object_ids::VERIFY_CONFIGURATION, 1).await {
+            Ok(date) => date,
+            Err(SdoClientError::ServerAbort {
+                abort_code: RawAbortCode::Valid(AbortCode::NoSuchObject),
+                ..
+            }) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let time = self.upload_u32(object_ids::VERIFY_CONFIGURATION, 2).await?;
+        Ok(Some(ConfigStamp { date, time }))
+    }
+
+    /// Write the verify configuration object (0x1020), and read it back if `verify` is true
+    pub async fn write_verify_configuration(
+        &mut self,
+        stamp: ConfigStamp,
+        verify: bool,
+    ) -> Result<()> {
+        // Write the time first, so that the date is only set once the whole stamp is valid
+        let index = object_ids::VERIFY_CONFIGURATION;
+        self.download_checked(index, 2, &stamp.time.to_le_bytes(), verify)
+            .await?;
+        self.download_checked(index, 1, &stamp.date.to_le_bytes(), verify)
+            .await
+    }
+
+    /// Check whether the device already holds the configuration
+    ///
+    /// Returns true if the config has a [verify configuration](NodeConfig::verify_configuration)
+    /// stamp, and the device's verify configuration object (0x1020) matches it. A device which
+    /// does not implement the object is never considered configured.
+    pub async fn is_configured(&mut self, config: &NodeConfig) -> Result<bool> {
+        let Some(expected) = config.verify_configuration() else {
+            return Ok(false);
+        };
+        let actual = self.read_verify_configuration().await?;
+        Ok(expected.is_set() && actual == Some(expected))
+    }
+
+    /// Write a single [`Store`] to the device, and read it back if verification is requested
+    pub async fn apply_store(&mut self, store: &Store) -> Result<()> {
+        let data = store.raw_value();
+        self.download_checked(store.index, store.sub, &data, store.verify)
+            .await
+    }
+
+    /// Check that a transmit PDO configuration is compatible with the device
+    ///
+    /// Checks that the PDO comm object exists, that each mapped object exists, that the size of
+    /// each mapped object matches the size declared in the mapping, and that the total mapped size
+    /// fits in a CAN frame. The sizes are checked by reading the mapped objects.
+    ///
+    /// Whether the device allows an object to be mapped can only be learned by writing the mapping,
+    /// so this is checked only when the PDO is disabled on the device: each mapping is written to
+    /// an unused entry of the PDO mapping object, which is restored afterwards. Nothing is written
+    /// to an enabled PDO.
+    pub async fn validate_tpdo(
+        &mut self,
+        pdo_num: usize,
+        cfg: &PdoConfig,
+    ) -> std::result::Result<(), PdoValidationError> {
+        self.validate_pdo("TPDO", 0x1800 + pdo_num as u16, pdo_num, cfg)
+            .await
+    }
+
+    /// Check that a receive PDO configuration is compatible with the device
+    ///
+    /// Checks that the PDO comm object exists, that each mapped object exists, that the size of
+    /// each mapped object matches the size declared in the mapping, and that the total mapped size
+    /// fits in a CAN frame. The sizes are checked by reading the mapped objects, except for
+    /// write-only objects, whose size cannot be read.
+    ///
+    /// Whether the device allows an object to be mapped can only be learned by writing the mapping,
+    /// so this is checked only when the PDO is disabled on the device: each mapping is written to
+    /// an unused entry of the PDO mapping object, which is restored afterwards. Nothing is written
+    /// to an enabled PDO.
+    pub async fn validate_rpdo(
+        &mut self,
+        pdo_num: usize,
+        cfg: &PdoConfig,
+    ) -> std::result::Result<(), PdoValidationError> {
+        self.validate_pdo("RPDO", 0x1400 + pdo_num as u16, pdo_num, cfg)
+            .await
+    }
+
+    /// Check all of the PDO configurations in a [`NodeConfig`] against the device
+    ///
+    /// Returns the first incompatibility found. No enabled PDO is written, and the mapping entries
+    /// of disabled PDOs which are used to check mappability are restored, so this can be used
+    /// before applying a configuration to avoid leaving a node partially configured.
+    pub async fn validate_node_config(
+        &mut self,
+        config: &NodeConfig,
+    ) -> std::result::Result<(), PdoValidationError> {
+        for (pdo_num, cfg) in config.tpdos() {
+            self.validate_tpdo(*pdo_num, cfg).await?;
+        }
+        for (pdo_num, cfg) in config.rpdos() {
+            self.validate_rpdo(*pdo_num, cfg).await?;
+        }
+        Ok(())
+    }
+
+    /// Validate a PDO config against the device, as described for
+    /// [`validate_tpdo`](Self::validate_tpdo)
+    async fn validate_pdo(
+        &mut self,
+        kind: &'static str,
+        comm_index: u16,
+        pdo: usize,
+        cfg: &PdoConfig,
+    ) -> std::result::Result<(), PdoValidationError> {
+        let cob_value = match self.upload_u32(comm_index, 1).await {
+            Ok(value) => value,
+            Err(SdoClientError::ServerAbort {
+                abort_code: RawAbortCode::Valid(AbortCode::NoSuchObject),
+                ..
+            }) => return Err(PdoValidationError::NoSuchPdo { kind, pdo }),
+            Err(source) => {
+                return Err(PdoValidationError::Sdo {
+                    index: comm_index,
+                    sub: 1,
+                    source,
+                })
+            }
+        };
+
+        let mut total = 0usize;
+        for m in &cfg.mappings {
+            total += m.size as usize;
+            self.check_mapped_size(m).await?;
+        }
+        if total > 64 {
+            return Err(PdoValidationError::PdoTooLarge { kind, pdo, total });
+        }
+
+        let enabled = cob_value & (1 << 31) == 0;
+        if !enabled {
+            self.probe_pdo_mappings(kind, comm_index + 0x200, cfg)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Read a mapped object from the device, and check that its size matches the mapping
+    async fn check_mapped_size(
+        &mut self,
+        m: &PdoMapping,
+    ) -> std::result::Result<(), PdoValidationError> {
+        let data = match self.upload(m.index, m.sub).await {
+            Ok(data) => data,
+            Err(SdoClientError::ServerAbort {
+                abort_code: RawAbortCode::Valid(AbortCode::NoSuchObject | AbortCode::NoSuchSubIndex),
+                ..
+            }) => {
+                return Err(PdoValidationError::NoSuchObject {
+                    index: m.index,
+                    sub: m.sub,
+                })
+            }
+            // Write-only objects can still be mapped to an RPDO, but their size cannot be
+            // checked
+            Err(SdoClientError::ServerAbort {
+                abort_code: RawAbortCode::Valid(AbortCode::WriteOnly),
+                ..
+            }) => return Ok(()),
+            Err(source) => {
+                return Err(PdoValidationError::Sdo {
+                    index: m.index,
+                    sub: m.sub,
+                    source,
+                })
+            }
+        };
+        let actual = data.len() * 8;
+        if actual > 64 {
+            return Err(PdoValidationError::NotMappable {
+                index: m.index,
+                sub: m.sub,
+                size: actual,
+            });
+        }
+        if actual != m.size as usize {
+            return Err(PdoValidationError::SizeMismatch {
+                index: m.index,
+                sub: m.sub,
+                actual,
+                declared: m.size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Write each mapping of a PDO config to an unused entry of a disabled PDO's mapping object,
+    /// to have the device check that the object may be mapped
+    ///
+    /// The entry following the active mappings is used, or the first entry if all are in use. It
+    /// is restored afterwards, even when a mapping is rejected.
+    async fn probe_pdo_mappings(
+        &mut self,
+        kind: &'static str,
+        mapping_index: u16,
+        cfg: &PdoConfig,
+    ) -> std::result::Result<(), PdoValidationError> {
+        let sdo_error = |sub| {
+            move |source| PdoValidationError::Sdo {
+                index: mapping_index,
+                sub,
+                source,
+            }
+        };
+        let active = self
+            .upload_u8(mapping_index, 0)
+            .await
+            .map_err(sdo_error(0))?;
+        let mut probe_sub = active + 1;
+        let original = match self.upload_u32(mapping_index, probe_sub).await {
+            Ok(value) => value,
+            Err(SdoClientError::ServerAbort {
+                abort_code: RawAbortCode::Valid(AbortCode::NoSuchSubIndex),
+                ..
+            }) => {
+                probe_sub = 1;
+                self.upload_u32(mapping_index, probe_sub)
+                    .await
+                    .map_err(sdo_error(probe_sub))?
+            }
+            Err(source) => return Err(sdo_error(probe_sub)(source)),
+        };
+
+        let mut result = Ok(());
+        for m in &cfg.mappings {
+            let (index, sub) = (m.index, m.sub);
+            let mapping_value = ((index as u32) << 16) | ((sub as u32) << 8) | (m.size as u32);
+            result = match self
+                .download_u32(mapping_index, probe_sub, mapping_value)
+                .await
+            {
+                Ok(()) => Ok(()),
+                Err(SdoClientError::ServerAbort {
+                    abort_code: RawAbortCode::Valid(AbortCode::UnnallowedPdo),
+                    ..
+                }) => Err(PdoValidationError::MappingNotAllowed { kind, index, sub }),
+                Err(source) => Err(sdo_error(probe_sub)(source)),
+            };
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.download_u32(mapping_index, probe_sub, original)
+            .await
+            .map_err(sdo_error(probe_sub))?;
+        result
     }
 
     async fn store_pdo(
@@ -683,16 +1900,19 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
         comm_index: u16,
         mapping_index: u16,
         cfg: &PdoConfig,
+        verify: bool,
     ) -> Result<()> {
         assert!(cfg.mappings.len() < 0x40);
         for (i, m) in cfg.mappings.iter().enumerate() {
             let mapping_value = ((m.index as u32) << 16) | ((m.sub as u32) << 8) | (m.size as u32);
-            self.write_u32(mapping_index, (i + 1) as u8, mapping_value)
+            let data = mapping_value.to_le_bytes();
+            self.download_checked(mapping_index, (i + 1) as u8, &data, verify)
                 .await?;
         }
 
         let num_mappings = cfg.mappings.len() as u8;
-        self.write_u8(mapping_index, 0, num_mappings).await?;
+        self.download_checked(mapping_index, 0, &[num_mappings], verify)
+            .await?;
 
         let extended = cfg.cob > 0x7ff;
         let mut cob_value = cfg.cob & 0xFFFFFFF;
@@ -702,22 +1922,74 @@ impl<S: AsyncCanSender, R: AsyncCanReceiver> SdoClient<S, R> {
         if extended {
             cob_value |= 1 << 29;
         }
-        self.write_u8(comm_index, 2, cfg.transmission_type).await?;
+        self.download_checked(comm_index, 2, &[cfg.transmission_type], verify)
+            .await?;
         self.write_u32(comm_index, 1, cob_value).await?;
+        if verify {
+            // The node may report that RTR is not allowed on the PDO, which is not part of the
+            // configuration, so bit 30 is not compared
+            const RTR_NOT_ALLOWED: u32 = 1 << 30;
+            let readback = self.upload_u32(comm_index, 1).await?;
+            if readback & !RTR_NOT_ALLOWED != cob_value & !RTR_NOT_ALLOWED {
+                return VerifyFailedSnafu {
+                    index: comm_index,
+                    sub: 1,
+                }
+                .fail();
+            }
+        }
 
         Ok(())
     }
 
+    async fn load_pdo(&mut self, comm_index: u16, mapping_index: u16) -> Result<Option<PdoConfig>> {
+        let cob_value = match self.upload_u32(comm_index, 1).await {
+            Ok(value) => value,
+            Err(SdoClientError::ServerAbort {
+                abort_code: RawAbortCode::Valid(AbortCode::NoSuchObject),
+                ..
+            }) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let transmission_type = self.upload_u8(comm_index, 2).await?;
+        let num_mappings = self.upload_u8(mapping_index, 0).await?;
+        let mut mappings = Vec::with_capacity(num_mappings as usize);
+        for sub in 1..=num_mappings {
+            let value = self.upload_u32(mapping_index, sub).await?;
+            mappings.push(PdoMapping {
+                index: (value >> 16) as u16,
+                sub: (value >> 8) as u8,
+                size: value as u8,
+            });
+        }
+        Ok(Some(PdoConfig {
+            cob: cob_value & 0x1FFF_FFFF,
+            enabled: cob_value & (1 << 31) == 0,
+            mappings,
+            transmission_type,
+        }))
+    }
+
     async fn wait_for_response(&mut self, timeout: Duration) -> Result<SdoResponse> {
         let wait_until = tokio::time::Instant::now() + timeout;
         loop {
             match tokio::time::timeout_at(wait_until, self.receiver.recv()).await {
                 // Err indicates the timeout elapsed, so return
-                Err(_) => return NoResponseSnafu.fail(),
+                Err(_) => return Err(self.no_response()),
                 // Message was recieved. If it is the resp, return. Otherwise, keep waiting
                 Ok(Ok(msg)) => {
                     if msg.id == self.resp_cob_id {
-                        return msg.try_into().map_err(|_| MalformedResponseSnafu.build());
+                        let resp: SdoResponse =
+                            msg.try_into().map_err(|_| MalformedResponseSnafu.build())?;
+                        log::trace!("SDO response on {:?}: {resp}", self.resp_cob_id);
+                        return Ok(resp);
+                    }
+                    if msg.id == self.req_cob_id && !self.echoes.take_echo(&msg) {
+                        self.competing_requests = self.competing_requests.wrapping_add(1);
+                        log::warn!(
+                            "SDO request on {:?} from another client while waiting for a response",
+                            self.req_cob_id
+                        );
                     }
                 }
                 // Recv returned an error
@@ -0,0 +1,262 @@
+//! Checking the stamps embedded in received TPDOs
+//!
+//! Nodes can be configured to embed the SYNC counter, or a millisecond timestamp, in the last
+//! byte(s) of a TPDO (see [`pdo_stamp`](crate::common::pdo_stamp)). A [`StampedPdoDecoder`]
+//! separates the stamp from the mapped data, and compares it with the stamp of the previous
+//! message, so that a control loop can detect when cyclic data was dropped, or has not been
+//! updated.
+//!
+//! ```
+//! use zencan_client::stamped_pdo::{StampCheck, StampedPdoDecoder};
+//! use zencan_client::common::pdo_stamp::TpdoStamp;
+//!
+//! let mut decoder = StampedPdoDecoder::new(TpdoStamp::SyncCounter);
+//! let pdo = decoder.decode(&[1, 2, 3, 4, 5, 6, 7, 10]).unwrap();
+//! assert_eq!(&[1, 2, 3, 4, 5, 6, 7], pdo.data);
+//! assert_eq!(StampCheck::First, pdo.check);
+//! // The PDO sent on SYNC 11 was lost
+//! let pdo = decoder.decode(&[1, 2, 3, 4, 5, 6, 7, 12]).unwrap();
+//! assert_eq!(StampCheck::Dropped(1), pdo.check);
+//! ```
+
+use snafu::Snafu;
+use zencan_common::pdo_stamp::{StampValue, TpdoStamp};
+
+/// The result of comparing the stamp of a received PDO with the previous one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StampCheck {
+    /// The PDO has no stamp
+    Unstamped,
+    /// This is the first PDO received since the decoder was created or reset
+    First,
+    /// The stamp follows on from the previous PDO
+    Ok,
+    /// The stamp is the same as, or older than, the previous PDO, so the data is stale
+    Stale,
+    /// The given number of PDOs were missed since the previous PDO
+    Dropped(u32),
+}
+
+/// A received PDO, split into its mapped data and its stamp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedPdo<'a> {
+    /// The mapped data, without the stamp
+    pub data: &'a [u8],
+    /// The stamp, if the PDO is stamped
+    pub stamp: Option<StampValue>,
+    /// The result of checking the stamp against the previous PDO
+    pub check: StampCheck,
+}
+
+/// Error returned by [`StampedPdoDecoder::decode`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Snafu)]
+pub enum StampError {
+    /// The PDO was too short to hold a stamp
+    #[snafu(display("A stamped PDO must be 8 bytes, but {len} bytes were received"))]
+    TooShort {
+        /// The length of the received PDO
+        len: usize,
+    },
+}
+
+/// Decodes the stamps of the PDOs received from a single TPDO
+#[derive(Debug, Clone, Copy)]
+pub struct StampedPdoDecoder {
+    stamp: TpdoStamp,
+    sync_overflow: u8,
+    sync_interval: u8,
+    period_ms: Option<u16>,
+    last: Option<StampValue>,
+}
+
+impl StampedPdoDecoder {
+    /// Create a decoder for a TPDO with the given stamp
+    ///
+    /// By default, a SYNC counter is expected to count from 1 to 255, and to increase by one on
+    /// each PDO.
+    pub fn new(stamp: TpdoStamp) -> Self {
+        Self {
+            stamp,
+            sync_overflow: 255,
+            sync_interval: 1,
+            period_ms: None,
+            last: None,
+        }
+    }
+
+    /// Set the overflow value of the SYNC counter, i.e. the value of object 0x1019 on the SYNC
+    /// producer
+    pub fn with_sync_overflow(mut self, overflow: u8) -> Self {
+        self.sync_overflow = overflow.max(1);
+        self
+    }
+
+    /// Set the number of SYNCs between PDOs, i.e. the transmission type of a synchronous TPDO
+    pub fn with_sync_interval(mut self, interval: u8) -> Self {
+        self.sync_interval = interval.max(1);
+        self
+    }
+
+    /// Set the period at which a timestamped PDO is sent
+    ///
+    /// Without a period, a timestamp can only be checked for being stale, and never reports
+    /// dropped PDOs.
+    pub fn with_period_ms(mut self, period_ms: u16) -> Self {
+        self.period_ms = (period_ms != 0).then_some(period_ms);
+        self
+    }
+
+    /// Forget the previous stamp, e.g. after the node was restarted
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    /// Decode a received PDO
+    pub fn decode<'a>(&mut self, data: &'a [u8]) -> Result<DecodedPdo<'a>, StampError> {
+        if self.stamp == TpdoStamp::None {
+            return Ok(DecodedPdo {
+                data,
+                stamp: None,
+                check: StampCheck::Unstamped,
+            });
+        }
+        let stamp = self
+            .stamp
+            .read(data)
+            .ok_or(StampError::TooShort { len: data.len() })?;
+        let check = match self.last {
+            None => StampCheck::First,
+            Some(last) => self.check(last, stamp),
+        };
+        // A stale stamp is not newer than the last one, so later PDOs are compared to the last
+        if check != StampCheck::Stale {
+            self.last = Some(stamp);
+        }
+        Ok(DecodedPdo {
+            data: &data[..self.stamp.payload_size()],
+            stamp: Some(stamp),
+            check,
+        })
+    }
+
+    fn check(&self, last: StampValue, stamp: StampValue) -> StampCheck {
+        match (last, stamp) {
+            (StampValue::SyncCounter(last), StampValue::SyncCounter(count)) => {
+                // Counters run from 1 to the overflow value
+                let overflow = self.sync_overflow as i32;
+                let step = (count as i32 - last as i32).rem_euclid(overflow) as u32;
+                let interval = self.sync_interval as u32;
+                if step == 0 {
+                    StampCheck::Stale
+                } else if step <= interval {
+                    StampCheck::Ok
+                } else {
+                    StampCheck::Dropped(step / interval - 1)
+                }
+            }
+            (StampValue::Timestamp(last), StampValue::Timestamp(time)) => {
+                let elapsed = time.wrapping_sub(last);
+                // A timestamp more than half the range behind the last one is older
+                if elapsed == 0 || elapsed >= 0x8000 {
+                    return StampCheck::Stale;
+                }
+                match self.period_ms {
+                    Some(period) => {
+                        let periods = (elapsed as u32 + period as u32 / 2) / period as u32;
+                        match periods {
+                            0 | 1 => StampCheck::Ok,
+                            n => StampCheck::Dropped(n - 1),
+                        }
+                    }
+                    None => StampCheck::Ok,
+                }
+            }
+            // The stamp type is fixed, so the values always match
+            _ => StampCheck::Ok,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sync_pdo(count: u8) -> [u8; 8] {
+        [0, 0, 0, 0, 0, 0, 0, count]
+    }
+
+    fn timestamp_pdo(ms: u16) -> [u8; 8] {
+        let b = ms.to_le_bytes();
+        [1, 2, 3, 4, 5, 6, b[0], b[1]]
+    }
+
+    #[test]
+    fn test_unstamped() {
+        let mut decoder = StampedPdoDecoder::new(TpdoStamp::None);
+        let pdo = decoder.decode(&[1, 2]).unwrap();
+        assert_eq!(&[1, 2], pdo.data);
+        assert_eq!(None, pdo.stamp);
+        assert_eq!(StampCheck::Unstamped, pdo.check);
+    }
+
+    #[test]
+    fn test_sync_counter() {
+        let mut decoder = StampedPdoDecoder::new(TpdoStamp::SyncCounter).with_sync_overflow(10);
+        let check = |decoder: &mut StampedPdoDecoder, count| {
+            decoder.decode(&sync_pdo(count)).unwrap().check
+        };
+        assert_eq!(StampCheck::First, check(&mut decoder, 9));
+        assert_eq!(StampCheck::Ok, check(&mut decoder, 10));
+        // The counter wraps from the overflow value to 1
+        assert_eq!(StampCheck::Ok, check(&mut decoder, 1));
+        assert_eq!(StampCheck::Stale, check(&mut decoder, 1));
+        assert_eq!(StampCheck::Dropped(2), check(&mut decoder, 4));
+        assert_eq!(StampCheck::Dropped(1), check(&mut decoder, 6));
+
+        decoder.reset();
+        assert_eq!(StampCheck::First, check(&mut decoder, 3));
+
+        assert_eq!(
+            Err(StampError::TooShort { len: 7 }),
+            decoder.decode(&[0; 7])
+        );
+    }
+
+    #[test]
+    fn test_sync_interval() {
+        let mut decoder = StampedPdoDecoder::new(TpdoStamp::SyncCounter).with_sync_interval(2);
+        decoder.decode(&sync_pdo(1)).unwrap();
+        assert_eq!(StampCheck::Ok, decoder.decode(&sync_pdo(3)).unwrap().check);
+        assert_eq!(
+            StampCheck::Dropped(1),
+            decoder.decode(&sync_pdo(7)).unwrap().check
+        );
+        // 255 wraps to 1
+        decoder.decode(&sync_pdo(254)).unwrap();
+        assert_eq!(StampCheck::Ok, decoder.decode(&sync_pdo(1)).unwrap().check);
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let mut decoder = StampedPdoDecoder::new(TpdoStamp::Timestamp).with_period_ms(10);
+        let msg = timestamp_pdo(65530);
+        let pdo = decoder.decode(&msg).unwrap();
+        assert_eq!(&[1, 2, 3, 4, 5, 6], pdo.data);
+        assert_eq!(Some(StampValue::Timestamp(65530)), pdo.stamp);
+        assert_eq!(StampCheck::First, pdo.check);
+
+        let check =
+            |decoder: &mut StampedPdoDecoder, ms| decoder.decode(&timestamp_pdo(ms)).unwrap().check;
+        // The timestamp wraps, and small jitter is allowed
+        assert_eq!(StampCheck::Ok, check(&mut decoder, 4));
+        assert_eq!(StampCheck::Ok, check(&mut decoder, 13));
+        assert_eq!(StampCheck::Stale, check(&mut decoder, 13));
+        assert_eq!(StampCheck::Stale, check(&mut decoder, 5));
+        assert_eq!(StampCheck::Dropped(2), check(&mut decoder, 43));
+
+        // Without a period, only stale data is detected
+        let mut decoder = StampedPdoDecoder::new(TpdoStamp::Timestamp);
+        check(&mut decoder, 100);
+        assert_eq!(StampCheck::Ok, check(&mut decoder, 1000));
+    }
+}
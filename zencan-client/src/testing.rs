@@ -0,0 +1,301 @@
+//! Test fixtures for black-box testing of zencan nodes
+//!
+//! [`NodeFixture`] connects a node, using an object dictionary generated by `zencan-build`, to an
+//! in-memory CAN bus. [`SdoClient`]s, senders, and receivers can be connected to the same bus, so
+//! that tests can exercise the node the same way a real client on the bus would, and check the
+//! messages it sends in response.
+//!
+//! Requires the `testing` feature.
+//!
+//! The [`replay`] module provides frame level record and replay of node behavior, for checking
+//! that the exact frames sent by a node do not change, and a [`ReplayServer`](replay::ReplayServer)
+//! which answers a client with the responses in a recording of real devices.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use std::time::Duration;
+//! use zencan_client::common::messages::{CanId, NmtState};
+//! use zencan_client::testing::NodeFixture;
+//!
+//! mod zencan {
+//!     zencan_node::include_modules!(MY_DEVICE);
+//! }
+//!
+//! #[tokio::test]
+//! async fn test_tpdo_on_write() {
+//!     let mut fixture =
+//!         NodeFixture::new(1, &zencan::OD_TABLE, &zencan::NODE_MBOX, &zencan::NODE_STATE);
+//!     fixture.node_mut().request_state(NmtState::Operational);
+//!     let mut client = fixture.sdo_client();
+//!     let mut rx = fixture.receiver();
+//!
+//!     fixture
+//!         .run(async move {
+//!             client.download_u8(0x2005, 1, 3).await.unwrap();
+//!             rx.expect(CanId::std(0x181), Duration::from_millis(20))
+//!                 .await
+//!                 .expect("TPDO1 not sent");
+//!         })
+//!         .await;
+//! }
+//! ```
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use zencan_common::{
+    messages::{CanId, CanMessage},
+    traits::{AsyncCanReceiver, AsyncCanSender},
+    NodeId,
+};
+use zencan_node::{object_dict::ODEntry, Node, NodeMbox, NodeStateAccess};
+
+use crate::SdoClient;
+
+pub mod replay;
+use replay::{Direction, FrameRecording};
+
+/// The interval at which the node is processed while a test is running
+const PROCESS_INTERVAL: Duration = Duration::from_micros(100);
+
+struct BusShared {
+    mboxes: Vec<&'static NodeMbox>,
+    receivers: Vec<UnboundedSender<CanMessage>>,
+    epoch: Instant,
+    capture: Option<FrameRecording>,
+}
+
+impl BusShared {
+    /// Deliver a message to every receiver, and optionally to the node mailboxes
+    ///
+    /// Messages delivered to the nodes come from senders on the bus, and all others come from the
+    /// node.
+    fn deliver(&mut self, msg: CanMessage, to_nodes: bool) {
+        if let Some(capture) = &mut self.capture {
+            let direction = if to_nodes {
+                Direction::Rx
+            } else {
+                Direction::Tx
+            };
+            capture.push(self.epoch.elapsed().as_micros() as u64, direction, msg);
+        }
+        if to_nodes {
+            for mbox in &self.mboxes {
+                // Messages the node does not accept are dropped, as they would be on a real bus
+                mbox.store_message(msg).ok();
+            }
+        }
+        // Drop receivers which have been closed
+        self.receivers.retain(|rx| rx.send(msg).is_ok());
+    }
+}
+
+/// A node connected to an in-memory CAN bus
+///
+/// The node is processed in the background while a test runs with [`NodeFixture::run`], using the
+/// time elapsed since the fixture was created.
+pub struct NodeFixture {
+    node: Node,
+    node_id: u8,
+    bus: Arc<Mutex<BusShared>>,
+    epoch: Instant,
+}
+
+impl core::fmt::Debug for NodeFixture {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("NodeFixture")
+            .field("node_id", &self.node_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl NodeFixture {
+    /// Create a node, and connect it to a new bus
+    ///
+    /// The arguments are the objects generated by `zencan-build` for the node. The node is
+    /// processed once, so that it has completed its boot-up when this returns.
+    ///
+    /// Node state is stored in statics, so tests which share an object dictionary should not run
+    /// in parallel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node_id` is not a valid node ID
+    pub fn new(
+        node_id: u8,
+        od: &'static [ODEntry<'static>],
+        mbox: &'static NodeMbox,
+        state: &'static dyn NodeStateAccess,
+    ) -> Self {
+        let node = Node::new(
+            NodeId::new(node_id).expect("Invalid node ID"),
+            mbox,
+            state,
+            od,
+        );
+        let epoch = Instant::now();
+        let bus = Arc::new(Mutex::new(BusShared {
+            mboxes: vec![mbox],
+            receivers: Vec::new(),
+            epoch,
+            capture: None,
+        }));
+        let mut fixture = Self {
+            node,
+            node_id,
+            bus,
+            epoch,
+        };
+        fixture.process();
+        fixture
+    }
+
+    /// Get the node
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    /// Get the node mutably, e.g. to change its NMT state or register callbacks
+    pub fn node_mut(&mut self) -> &mut Node {
+        &mut self.node
+    }
+
+    /// Create an SDO client for the node's default SDO server
+    pub fn sdo_client(&mut self) -> SdoClient<TestBusSender, TestBusReceiver> {
+        SdoClient::new_std(self.node_id, self.sender(), self.receiver())
+    }
+
+    /// Create a sender for transmitting messages to the node
+    ///
+    /// Messages sent are also received by all receivers on the bus.
+    pub fn sender(&mut self) -> TestBusSender {
+        TestBusSender {
+            bus: self.bus.clone(),
+        }
+    }
+
+    /// Create a receiver for the messages on the bus
+    ///
+    /// The receiver gets all messages sent after it is created, from the node and from all senders.
+    pub fn receiver(&mut self) -> TestBusReceiver {
+        let (tx, rx) = unbounded_channel();
+        self.bus.lock().unwrap().receivers.push(tx);
+        TestBusReceiver { rx }
+    }
+
+    /// Start recording the frames sent and received by the node
+    ///
+    /// Frames are timed from the creation of the fixture, which is the time base of the node. Any
+    /// previous capture is discarded. See [`replay`] for how to use a capture as a test fixture.
+    pub fn start_capture(&mut self) {
+        self.bus.lock().unwrap().capture = Some(FrameRecording::new());
+    }
+
+    /// Stop recording, and return the frames recorded since [`start_capture`](Self::start_capture)
+    ///
+    /// Returns an empty recording if no capture was started.
+    pub fn take_capture(&mut self) -> FrameRecording {
+        self.bus.lock().unwrap().capture.take().unwrap_or_default()
+    }
+
+    /// Process the node once, and deliver the messages it sends
+    pub fn process(&mut self) {
+        let now_us = self.epoch.elapsed().as_micros() as u64;
+        let bus = &self.bus;
+        self.node
+            .process(now_us, &mut |msg| bus.lock().unwrap().deliver(msg, false));
+    }
+
+    /// Run a test, while processing the node in the background
+    ///
+    /// Returns the output of `test` when it completes.
+    pub async fn run<T>(&mut self, test: impl Future<Output = T>) -> T {
+        let process_task = async {
+            loop {
+                tokio::time::sleep(PROCESS_INTERVAL).await;
+                self.process();
+            }
+        };
+
+        tokio::select! {
+            _ = process_task => unreachable!(),
+            result = test => result,
+        }
+    }
+}
+
+/// Sends messages onto the bus of a [`NodeFixture`]
+#[derive(Clone)]
+pub struct TestBusSender {
+    bus: Arc<Mutex<BusShared>>,
+}
+
+impl core::fmt::Debug for TestBusSender {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TestBusSender").finish_non_exhaustive()
+    }
+}
+
+impl AsyncCanSender for TestBusSender {
+    async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
+        self.bus.lock().unwrap().deliver(msg, true);
+        Ok(())
+    }
+
+    /// The bus delivers every frame to all receivers, including those of the sending client
+    fn echoes_sent_frames(&self) -> bool {
+        true
+    }
+}
+
+/// Receives messages from the bus of a [`NodeFixture`]
+#[derive(Debug)]
+pub struct TestBusReceiver {
+    rx: UnboundedReceiver<CanMessage>,
+}
+
+impl TestBusReceiver {
+    /// Wait for a message with the given ID
+    ///
+    /// Messages with other IDs received while waiting are discarded. Returns None if no matching
+    /// message is received within `timeout`.
+    pub async fn expect(&mut self, id: CanId, timeout: Duration) -> Option<CanMessage> {
+        self.expect_matching(|msg| msg.id() == id, timeout).await
+    }
+
+    /// Wait for a message for which `predicate` returns true
+    ///
+    /// Messages which do not match are discarded. Returns None if no matching message is received
+    /// within `timeout`.
+    pub async fn expect_matching(
+        &mut self,
+        mut predicate: impl FnMut(&CanMessage) -> bool,
+        timeout: Duration,
+    ) -> Option<CanMessage> {
+        let wait = async {
+            while let Some(msg) = self.rx.recv().await {
+                if predicate(&msg) {
+                    return Some(msg);
+                }
+            }
+            None
+        };
+        tokio::time::timeout(timeout, wait).await.ok().flatten()
+    }
+}
+
+impl AsyncCanReceiver for TestBusReceiver {
+    type Error = ();
+
+    fn try_recv(&mut self) -> Option<CanMessage> {
+        self.rx.try_recv().ok()
+    }
+
+    async fn recv(&mut self) -> Result<CanMessage, Self::Error> {
+        self.rx.recv().await.ok_or(())
+    }
+}
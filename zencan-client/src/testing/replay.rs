@@ -0,0 +1,353 @@
+//! Frame level record and replay of node behavior
+//!
+//! A [`FrameRecording`] is a sequence of timestamped frames received and transmitted by a node. A
+//! node is replayed against a recording by delivering the received frames to it, while processing
+//! it with a virtual clock, so that the frames it transmits are exactly reproducible.
+//! [`assert_replay`] checks that the transmitted frames match those in the recording, so
+//! recordings checked in as test fixtures catch changes in the node's protocol behavior. See
+//! [`crate::frame_capture`] for the text format of recordings.
+//!
+//! A recording can also be replayed the other way around, with a [`ReplayServer`] standing in for
+//! the nodes, to test a client against the responses of real devices.
+//!
+//! # Creating fixtures
+//!
+//! Start a capture on a [`NodeFixture`](super::NodeFixture) with
+//! [`start_capture`](super::NodeFixture::start_capture), run a test against it, and save the
+//! result of [`take_capture`](super::NodeFixture::take_capture). The captured frames are timed
+//! with the real clock, so the transmitted frames may not be exactly reproducible. Use
+//! [`check_replay_file`] to test against the file, and run the test once with the
+//! `ZENCAN_UPDATE_REPLAY` environment variable set, to replace the transmitted frames with those
+//! produced by the replay.
+
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use zencan_common::{messages::CanMessage, traits::AsyncCanSender};
+use zencan_node::{Node, NodeMbox};
+
+pub use crate::frame_capture::{Direction, FrameRecording, ParseRecordingError, RecordedFrame};
+use crate::SdoClient;
+
+use super::TestBusReceiver;
+
+/// The environment variable which makes [`check_replay_file`] update the file instead of checking
+pub const UPDATE_ENV_VAR: &str = "ZENCAN_UPDATE_REPLAY";
+
+/// Run a node against the received frames of a recording, and record the result
+///
+/// The node is processed every `tick_us` microseconds of virtual time, starting from 0, until the
+/// time of the last frame in `recording`. Each received frame is delivered to `mbox` before the
+/// first call to process at or after its time. The returned recording holds the received frames,
+/// with their original times, and the frames transmitted by the node, with the time of the call to
+/// process which sent them.
+///
+/// The node should be freshly created, and not yet processed, so that its boot-up is included.
+///
+/// # Panics
+///
+/// Panics if `tick_us` is 0
+pub fn replay(
+    node: &mut Node,
+    mbox: &NodeMbox,
+    recording: &FrameRecording,
+    tick_us: u64,
+) -> FrameRecording {
+    assert!(tick_us > 0, "tick_us must be non-zero");
+    let mut output = FrameRecording::new();
+    let mut inputs = recording.rx_frames().peekable();
+    let end_us = recording.end_us();
+    let mut now_us = 0;
+    loop {
+        while let Some(frame) = inputs.next_if(|f| f.time_us <= now_us) {
+            // Frames the node does not accept are dropped, as they would be on a real bus
+            mbox.store_message(frame.msg).ok();
+            output.push(frame.time_us, Direction::Rx, frame.msg);
+        }
+        node.process(now_us, &mut |msg| output.push(now_us, Direction::Tx, msg));
+        if now_us >= end_us {
+            break;
+        }
+        now_us = (now_us + tick_us).min(end_us);
+    }
+    output
+}
+
+/// Describe the first difference between the transmitted frames of two recordings
+fn first_tx_difference(expected: &FrameRecording, actual: &FrameRecording) -> Option<String> {
+    let mut expected_tx = expected.tx_frames();
+    let mut actual_tx = actual.tx_frames();
+    for i in 0.. {
+        match (expected_tx.next(), actual_tx.next()) {
+            (None, None) => return None,
+            (e, a) if e == a => continue,
+            (e, a) => {
+                let show =
+                    |f: Option<&RecordedFrame>| f.map(|f| f.to_string()).unwrap_or("<none>".into());
+                return Some(format!(
+                    "transmitted frame {i} differs\n  expected: {}\n    actual: {}",
+                    show(e),
+                    show(a)
+                ));
+            }
+        }
+    }
+    unreachable!()
+}
+
+/// Replay a recording, and panic if the node's transmitted frames do not exactly match it
+///
+/// See [`replay`] for the arguments. The frames and their times must match.
+pub fn assert_replay(node: &mut Node, mbox: &NodeMbox, expected: &FrameRecording, tick_us: u64) {
+    let actual = replay(node, mbox, expected, tick_us);
+    if let Some(difference) = first_tx_difference(expected, &actual) {
+        let mut msg = format!("Replay mismatch: {difference}\n\nActual recording:\n");
+        write!(msg, "{actual}").unwrap();
+        panic!("{msg}");
+    }
+}
+
+/// Replay a recording stored in a file, and check the node's transmitted frames against it
+///
+/// When the `ZENCAN_UPDATE_REPLAY` environment variable is set, the file is instead replaced with
+/// the result of the replay, which is how fixtures created from a capture are finalized.
+///
+/// # Panics
+///
+/// Panics if the file cannot be read, parsed or written, or if the transmitted frames do not match
+pub fn check_replay_file(path: impl AsRef<Path>, node: &mut Node, mbox: &NodeMbox, tick_us: u64) {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {e}", path.display()));
+    let expected: FrameRecording = text
+        .parse()
+        .unwrap_or_else(|e| panic!("Failed to parse {}: {e}", path.display()));
+    if std::env::var_os(UPDATE_ENV_VAR).is_some() {
+        // Keep the comments at the top of the file
+        let header: String = text
+            .lines()
+            .take_while(|line| line.starts_with('#'))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        let actual = replay(node, mbox, &expected, tick_us);
+        std::fs::write(path, format!("{header}{actual}"))
+            .unwrap_or_else(|e| panic!("Failed to write {}: {e}", path.display()));
+    } else {
+        assert_replay(node, mbox, &expected, tick_us);
+    }
+}
+
+struct ServerState {
+    frames: VecDeque<RecordedFrame>,
+    receivers: Vec<UnboundedSender<CanMessage>>,
+    requests: usize,
+    mismatch: Option<String>,
+}
+
+/// Remove the frame at the front of `frames` if it was transmitted by a node
+fn pop_tx(frames: &mut VecDeque<RecordedFrame>) -> Option<RecordedFrame> {
+    match frames.front() {
+        Some(frame) if frame.direction == Direction::Tx => frames.pop_front(),
+        _ => None,
+    }
+}
+
+impl ServerState {
+    fn handle_request(&mut self, msg: CanMessage) {
+        if self.mismatch.is_some() {
+            return;
+        }
+        let expected = self.frames.iter().find(|f| f.direction == Direction::Rx);
+        if expected.map(|f| f.msg) != Some(msg) {
+            let actual = RecordedFrame {
+                time_us: expected.map(|f| f.time_us).unwrap_or(0),
+                direction: Direction::Rx,
+                msg,
+            };
+            let expected = expected.map(|f| f.to_string()).unwrap_or("<none>".into());
+            self.mismatch = Some(format!(
+                "request {} differs\n  expected: {expected}\n    actual: {actual}",
+                self.requests
+            ));
+            return;
+        }
+        self.requests += 1;
+        // Skip to the request, and deliver the responses which follow it
+        while pop_tx(&mut self.frames).is_some() {}
+        self.frames.pop_front();
+        while let Some(frame) = pop_tx(&mut self.frames) {
+            self.receivers.retain(|rx| rx.send(frame.msg).is_ok());
+        }
+    }
+}
+
+/// A mock of the nodes on a bus, which answers a client with the responses in a recording
+///
+/// The frames received by the nodes in the recording are the requests expected from the client,
+/// in order. When the client sends the next expected request, the frames transmitted after it in
+/// the recording, up to the next request, are delivered to the receivers created with
+/// [`receiver`](Self::receiver). Frames transmitted before the first request are skipped, and the
+/// times in the recording are ignored.
+///
+/// This turns a capture of a live session, e.g. made with `record start` in zencan-cli, into a
+/// regression test of the client: perform the same operations against the server, and check the
+/// result of [`finish`](Self::finish). Frames not related to the operations, such as the heartbeats
+/// of a manager, should be removed from the recording first.
+///
+/// ```ignore
+/// let recording: FrameRecording = include_str!("read_device_type.txt").parse().unwrap();
+/// let server = ReplayServer::new(&recording);
+/// let mut client = server.sdo_client(5);
+/// assert_eq!(0x20192, client.upload_u32(0x1000, 0).await.unwrap());
+/// server.finish().unwrap();
+/// ```
+pub struct ReplayServer {
+    state: Arc<Mutex<ServerState>>,
+}
+
+impl core::fmt::Debug for ReplayServer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ReplayServer").finish_non_exhaustive()
+    }
+}
+
+impl ReplayServer {
+    /// Create a server which answers with the responses in `recording`
+    pub fn new(recording: &FrameRecording) -> Self {
+        let mut frames: VecDeque<RecordedFrame> = recording.frames.iter().copied().collect();
+        while pop_tx(&mut frames).is_some() {}
+        Self {
+            state: Arc::new(Mutex::new(ServerState {
+                frames,
+                receivers: Vec::new(),
+                requests: 0,
+                mismatch: None,
+            })),
+        }
+    }
+
+    /// Create a sender for sending requests to the server
+    pub fn sender(&self) -> ReplaySender {
+        ReplaySender {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Create a receiver for the responses of the server
+    ///
+    /// The receiver gets all responses delivered after it is created.
+    pub fn receiver(&self) -> TestBusReceiver {
+        let (tx, rx) = unbounded_channel();
+        self.state.lock().unwrap().receivers.push(tx);
+        TestBusReceiver { rx }
+    }
+
+    /// Create an SDO client for the default SDO server of a node
+    pub fn sdo_client(&self, node_id: u8) -> SdoClient<ReplaySender, TestBusReceiver> {
+        SdoClient::new_std(node_id, self.sender(), self.receiver())
+    }
+
+    /// Check that the client sent all of the requests in the recording, in order
+    ///
+    /// Returns a description of the first request which differed from the recording, or of the
+    /// first request which was not sent.
+    pub fn finish(self) -> Result<(), String> {
+        let state = self.state.lock().unwrap();
+        if let Some(mismatch) = &state.mismatch {
+            return Err(mismatch.clone());
+        }
+        match state.frames.iter().find(|f| f.direction == Direction::Rx) {
+            Some(frame) => Err(format!(
+                "request {} was not sent\n  expected: {frame}",
+                state.requests
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Sends requests to a [`ReplayServer`]
+#[derive(Clone)]
+pub struct ReplaySender {
+    state: Arc<Mutex<ServerState>>,
+}
+
+impl core::fmt::Debug for ReplaySender {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ReplaySender").finish_non_exhaustive()
+    }
+}
+
+impl AsyncCanSender for ReplaySender {
+    async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
+        self.state.lock().unwrap().handle_request(msg);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replay_server() {
+        let recording: FrameRecording = "\
+0 tx 701#00
+1000 rx 601#4000100000000000
+1000 tx 705#05
+1100 tx 581#4300100092010200
+2000 rx 601#40FF4F0000000000
+2100 tx 581#80FF4F0000000206
+"
+        .parse()
+        .unwrap();
+
+        let server = ReplayServer::new(&recording);
+        let mut client = server.sdo_client(1);
+        assert_eq!(0x20192, client.upload_u32(0x1000, 0).await.unwrap());
+        assert!(client.upload(0x4fff, 0).await.is_err());
+        server.finish().unwrap();
+
+        // A request which was not sent
+        let server = ReplayServer::new(&recording);
+        let mut client = server.sdo_client(1);
+        client.upload_u32(0x1000, 0).await.unwrap();
+        assert!(server
+            .finish()
+            .unwrap_err()
+            .starts_with("request 1 was not sent"));
+
+        // A request which differs
+        let server = ReplayServer::new(&recording);
+        let mut client = server.sdo_client(1);
+        client.set_timeout(std::time::Duration::from_millis(10));
+        assert!(client.upload_u32(0x1001, 0).await.is_err());
+        assert!(server
+            .finish()
+            .unwrap_err()
+            .starts_with("request 0 differs"));
+    }
+
+    #[test]
+    fn test_first_tx_difference() {
+        let expected: FrameRecording = "0 tx 701#00\n10 rx 601#40\n10 tx 581#43".parse().unwrap();
+        let mut actual = expected.clone();
+        // Received frames are not compared
+        actual.frames.remove(1);
+        assert_eq!(None, first_tx_difference(&expected, &actual));
+
+        actual.frames[1].time_us = 20;
+        assert!(first_tx_difference(&expected, &actual)
+            .unwrap()
+            .starts_with("transmitted frame 1 differs"));
+        actual.frames.pop();
+        assert!(first_tx_difference(&expected, &actual)
+            .unwrap()
+            .contains("<none>"));
+    }
+}
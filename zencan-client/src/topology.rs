@@ -0,0 +1,314 @@
+//! Description of the nodes on a bus, and the PDO links between them
+//!
+//! [`BusManager::export_topology`](crate::BusManager::export_topology) reads the PDO configuration
+//! of each known node, and builds a [`Topology`] listing the nodes, the PDOs they produce and
+//! consume, and the links formed by enabled PDOs sharing a COB-ID. It can be serialized as JSON
+//! with [`Topology::to_json`], or rendered with graphviz using [`Topology::to_dot`], to visualize
+//! which nodes talk to each other.
+use std::{collections::BTreeMap, fmt::Write};
+
+use serde::Serialize;
+use zencan_common::{
+    lss::LssIdentity,
+    traits::{AsyncCanReceiver, AsyncCanSender},
+};
+
+use crate::{NodeInfo, PdoConfig, SdoClient};
+
+/// The maximum number of PDOs of each type a node can implement
+const MAX_PDOS: usize = 512;
+
+/// The nodes on a bus and the PDO links between them
+#[derive(Clone, Debug, Serialize)]
+pub struct Topology {
+    /// The known nodes, sorted by node ID
+    pub nodes: Vec<TopologyNode>,
+    /// The links formed by enabled PDOs, sorted by COB-ID
+    pub links: Vec<PdoLink>,
+}
+
+/// A node in a [`Topology`]
+#[derive(Clone, Debug, Serialize)]
+pub struct TopologyNode {
+    /// The node ID
+    pub node_id: u8,
+    /// The identity of the node, if known
+    pub identity: Option<LssIdentity>,
+    /// The device name of the node, if known
+    pub device_name: Option<String>,
+    /// The transmit PDOs implemented by the node
+    pub tpdos: Vec<TopologyPdo>,
+    /// The receive PDOs implemented by the node
+    pub rpdos: Vec<TopologyPdo>,
+    /// The error which stopped the PDO configuration being read, if any
+    ///
+    /// The PDOs read before the error are still listed.
+    pub error: Option<String>,
+}
+
+/// The configuration of a PDO in a [`Topology`]
+#[derive(Clone, Debug, Serialize)]
+pub struct TopologyPdo {
+    /// The PDO number, starting from 0
+    pub pdo: usize,
+    /// The configuration read from the node
+    #[serde(flatten)]
+    pub config: PdoConfig,
+}
+
+/// One end of a [`PdoLink`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct PdoEndpoint {
+    /// The node ID
+    pub node_id: u8,
+    /// The PDO number on the node
+    pub pdo: usize,
+}
+
+/// The enabled PDOs which use a single COB-ID
+///
+/// A link normally has one producer, and any number of consumers. A link without a producer may be
+/// fed by a device which is not a known node, and a link with more than one producer indicates a
+/// COB-ID conflict.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PdoLink {
+    /// The COB-ID. Values above 0x7FF are extended IDs.
+    pub cob_id: u32,
+    /// The TPDOs which send on the COB-ID
+    pub producers: Vec<PdoEndpoint>,
+    /// The RPDOs which receive on the COB-ID
+    pub consumers: Vec<PdoEndpoint>,
+}
+
+impl Topology {
+    /// Create a topology from a list of nodes, finding the links between their PDOs
+    pub fn new(mut nodes: Vec<TopologyNode>) -> Self {
+        nodes.sort_by_key(|n| n.node_id);
+        let mut links: BTreeMap<u32, PdoLink> = BTreeMap::new();
+        for node in &nodes {
+            let endpoints = |pdos: &[TopologyPdo]| -> Vec<(u32, PdoEndpoint)> {
+                pdos.iter()
+                    .filter(|p| p.config.enabled)
+                    .map(|p| {
+                        let endpoint = PdoEndpoint {
+                            node_id: node.node_id,
+                            pdo: p.pdo,
+                        };
+                        (p.config.cob, endpoint)
+                    })
+                    .collect()
+            };
+            for (cob_id, endpoint) in endpoints(&node.tpdos) {
+                link_entry(&mut links, cob_id).producers.push(endpoint);
+            }
+            for (cob_id, endpoint) in endpoints(&node.rpdos) {
+                link_entry(&mut links, cob_id).consumers.push(endpoint);
+            }
+        }
+        Self {
+            nodes,
+            links: links.into_values().collect(),
+        }
+    }
+
+    /// Serialize the topology as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Topology is always serializable")
+    }
+
+    /// Render the topology as a graphviz DOT digraph
+    ///
+    /// Each node is a vertex, and each producer/consumer pair of a link is an edge labeled with the
+    /// COB-ID. A link without a producer or without consumers is drawn to or from a vertex
+    /// representing the COB-ID, so that it is still visible.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph zencan {\n    rankdir=LR;\n");
+        for node in &self.nodes {
+            let mut label = format!("Node {}", node.node_id);
+            if let Some(name) = &node.device_name {
+                label.push_str("\\n");
+                label.push_str(&escape(name));
+            }
+            writeln!(
+                dot,
+                "    node{} [shape=box, label=\"{label}\"];",
+                node.node_id
+            )
+            .unwrap();
+        }
+        for link in &self.links {
+            let cob = format!("0x{:X}", link.cob_id);
+            if link.producers.is_empty() || link.consumers.is_empty() {
+                writeln!(dot, "    cob{cob} [shape=plaintext, label=\"{cob}\"];").unwrap();
+            }
+            let sources: Vec<String> = if link.producers.is_empty() {
+                vec![format!("cob{cob}")]
+            } else {
+                link.producers
+                    .iter()
+                    .map(|p| format!("node{}", p.node_id))
+                    .collect()
+            };
+            let targets: Vec<String> = if link.consumers.is_empty() {
+                vec![format!("cob{cob}")]
+            } else {
+                link.consumers
+                    .iter()
+                    .map(|c| format!("node{}", c.node_id))
+                    .collect()
+            };
+            for source in &sources {
+                for target in &targets {
+                    writeln!(dot, "    {source} -> {target} [label=\"{cob}\"];").unwrap();
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn link_entry(links: &mut BTreeMap<u32, PdoLink>, cob_id: u32) -> &mut PdoLink {
+    links.entry(cob_id).or_insert_with(|| PdoLink {
+        cob_id,
+        producers: Vec::new(),
+        consumers: Vec::new(),
+    })
+}
+
+/// Escape a string for use in a quoted DOT label
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Read the PDO configuration of a node
+pub(crate) async fn read_node<S: AsyncCanSender, R: AsyncCanReceiver>(
+    client: &mut SdoClient<S, R>,
+    info: &NodeInfo,
+) -> TopologyNode {
+    let mut node = TopologyNode {
+        node_id: info.node_id,
+        identity: info.identity,
+        device_name: info.device_name.clone(),
+        tpdos: Vec::new(),
+        rpdos: Vec::new(),
+        error: None,
+    };
+    if let Err(e) = read_pdos_into(client, &mut node).await {
+        node.error = Some(e.to_string());
+    }
+    node
+}
+
+/// Read the PDO configuration of a node, failing if any PDO cannot be read
+pub(crate) async fn read_pdos<S: AsyncCanSender, R: AsyncCanReceiver>(
+    client: &mut SdoClient<S, R>,
+    info: &NodeInfo,
+) -> Result<TopologyNode, crate::SdoClientError> {
+    let mut node = TopologyNode {
+        node_id: info.node_id,
+        identity: info.identity,
+        device_name: info.device_name.clone(),
+        tpdos: Vec::new(),
+        rpdos: Vec::new(),
+        error: None,
+    };
+    read_pdos_into(client, &mut node).await?;
+    Ok(node)
+}
+
+async fn read_pdos_into<S: AsyncCanSender, R: AsyncCanReceiver>(
+    client: &mut SdoClient<S, R>,
+    node: &mut TopologyNode,
+) -> Result<(), crate::SdoClientError> {
+    // PDOs are numbered contiguously, so the first missing one marks the end
+    for pdo in 0..MAX_PDOS {
+        match client.read_tpdo(pdo).await? {
+            Some(config) => node.tpdos.push(TopologyPdo { pdo, config }),
+            None => break,
+        }
+    }
+    for pdo in 0..MAX_PDOS {
+        match client.read_rpdo(pdo).await? {
+            Some(config) => node.rpdos.push(TopologyPdo { pdo, config }),
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pdo(pdo: usize, cob: u32, enabled: bool) -> TopologyPdo {
+        TopologyPdo {
+            pdo,
+            config: PdoConfig {
+                cob,
+                enabled,
+                mappings: Vec::new(),
+                transmission_type: 254,
+            },
+        }
+    }
+
+    fn node(node_id: u8, tpdos: Vec<TopologyPdo>, rpdos: Vec<TopologyPdo>) -> TopologyNode {
+        TopologyNode {
+            node_id,
+            identity: None,
+            device_name: Some(format!("dev{node_id}")),
+            tpdos,
+            rpdos,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_links() {
+        let topology = Topology::new(vec![
+            node(2, vec![], vec![pdo(0, 0x181, true), pdo(1, 0x201, true)]),
+            node(
+                1,
+                vec![pdo(0, 0x181, true), pdo(1, 0x281, false)],
+                vec![pdo(0, 0x281, true)],
+            ),
+            node(3, vec![], vec![pdo(0, 0x181, true)]),
+        ]);
+
+        assert_eq!(1, topology.nodes[0].node_id);
+        assert_eq!(
+            vec![
+                PdoLink {
+                    cob_id: 0x181,
+                    producers: vec![PdoEndpoint { node_id: 1, pdo: 0 }],
+                    consumers: vec![
+                        PdoEndpoint { node_id: 2, pdo: 0 },
+                        PdoEndpoint { node_id: 3, pdo: 0 }
+                    ],
+                },
+                PdoLink {
+                    cob_id: 0x201,
+                    producers: vec![],
+                    consumers: vec![PdoEndpoint { node_id: 2, pdo: 1 }],
+                },
+                // The disabled TPDO does not produce the consumed COB-ID
+                PdoLink {
+                    cob_id: 0x281,
+                    producers: vec![],
+                    consumers: vec![PdoEndpoint { node_id: 1, pdo: 0 }],
+                },
+            ],
+            topology.links
+        );
+
+        let dot = topology.to_dot();
+        assert!(dot.contains("node1 [shape=box, label=\"Node 1\\ndev1\"];"));
+        assert!(dot.contains("node1 -> node3 [label=\"0x181\"];"));
+        assert!(dot.contains("cob0x201 -> node2 [label=\"0x201\"];"));
+
+        let json: serde_json::Value = serde_json::from_str(&topology.to_json()).unwrap();
+        assert_eq!(0x181, json["nodes"][0]["tpdos"][0]["cob"]);
+        assert_eq!(2, json["links"][0]["consumers"][0]["node_id"]);
+    }
+}
@@ -0,0 +1,386 @@
+//! Recording of bus operations to a transaction log
+//!
+//! A [`TransactionRecorder`] can be attached to a [`BusManager`](crate::BusManager) or an
+//! [`SdoClient`](crate::SdoClient) to log every SDO, NMT and LSS operation it performs, along with
+//! its result and how long it took. The log is written as JSON lines, one [`Transaction`] per line,
+//! so that it can be followed while it is written and loaded again with [`load_transactions`].
+//!
+//! This is intended to provide an audit trail during commissioning: a log of exactly what was
+//! written to which node, which can later be analyzed with [`TransactionSummary`] or re-applied to
+//! the bus with [`BusManager::reapply`](crate::BusManager::reapply).
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, LineWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+
+/// An operation performed on the bus
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    /// Read of a sub-object via SDO
+    SdoUpload {
+        /// The node ID of the SDO server, if the client was created for a node ID
+        node: Option<u8>,
+        /// Object index
+        index: u16,
+        /// Object sub index
+        sub: u8,
+    },
+    /// Write of a sub-object via SDO
+    SdoDownload {
+        /// The node ID of the SDO server, if the client was created for a node ID
+        node: Option<u8>,
+        /// Object index
+        index: u16,
+        /// Object sub index
+        sub: u8,
+        /// The data written
+        data: Vec<u8>,
+        /// True if the block transfer protocol was used
+        #[serde(default)]
+        block: bool,
+    },
+    /// An NMT command
+    Nmt {
+        /// The command sent, e.g. "Start"
+        command: String,
+        /// The node commanded, or 0 for all nodes
+        node: u8,
+    },
+    /// Switch all LSS slaves to the given mode
+    LssGlobalMode {
+        /// The mode, e.g. "Waiting" or "Configuring"
+        mode: String,
+    },
+    /// Activate a single LSS slave by its identity
+    LssActivate {
+        /// Vendor ID
+        vendor_id: u32,
+        /// Product code
+        product_code: u32,
+        /// Revision
+        revision: u32,
+        /// Serial number
+        serial: u32,
+    },
+    /// Set the node ID of the LSS slave in configuration mode
+    LssSetNodeId {
+        /// The node ID assigned
+        node_id: u8,
+    },
+    /// Command the LSS slave in configuration mode to store its configuration
+    LssStoreConfig,
+    /// Search for unconfigured nodes with LSS fastscan
+    LssFastscan,
+    /// Assign node IDs to all unconfigured nodes
+    LssAutoAssign,
+}
+
+impl Operation {
+    /// Returns true if the operation changes the state of a node
+    ///
+    /// These are the operations which are performed again by
+    /// [`BusManager::reapply`](crate::BusManager::reapply)
+    pub fn is_write(&self) -> bool {
+        !matches!(
+            self,
+            Operation::SdoUpload { .. } | Operation::LssFastscan | Operation::LssAutoAssign
+        )
+    }
+}
+
+/// The result of an operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Outcome {
+    /// The operation succeeded
+    Ok {
+        /// Data returned by the operation, e.g. the value read by an SDO upload
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        response: Option<Vec<u8>>,
+    },
+    /// The operation failed
+    Error {
+        /// A description of the error
+        message: String,
+    },
+}
+
+impl Outcome {
+    /// Create an outcome from the result of an operation
+    pub fn from_result<T, E: std::fmt::Display>(
+        result: &Result<T, E>,
+        response: impl FnOnce(&T) -> Option<Vec<u8>>,
+    ) -> Self {
+        match result {
+            Ok(value) => Outcome::Ok {
+                response: response(value),
+            },
+            Err(e) => Outcome::Error {
+                message: e.to_string(),
+            },
+        }
+    }
+
+    /// Returns true if the operation succeeded
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Outcome::Ok { .. })
+    }
+}
+
+/// A single entry in the transaction log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transaction {
+    /// The time the operation started, in microseconds since the UNIX epoch
+    pub timestamp_us: u64,
+    /// How long the operation took, in microseconds
+    pub duration_us: u64,
+    /// The operation performed
+    #[serde(flatten)]
+    pub operation: Operation,
+    /// The result of the operation
+    pub outcome: Outcome,
+}
+
+/// Error returned when loading a transaction log
+#[derive(Debug, Snafu)]
+pub enum TransactionLogError {
+    /// Failed to read the log
+    #[snafu(display("Error reading transaction log: {source}"))]
+    Io {
+        /// The underlying error
+        source: std::io::Error,
+    },
+    /// A line of the log could not be parsed
+    #[snafu(display("Invalid transaction on line {line}: {source}"))]
+    Parse {
+        /// The line number, starting at 1
+        line: usize,
+        /// The underlying error
+        source: serde_json::Error,
+    },
+}
+
+/// Writes transactions to a log
+///
+/// The recorder is a cheap handle which can be cloned, and all clones write to the same log. Each
+/// transaction is written as a single line and flushed immediately, so the log is complete up to the
+/// last operation even if the application exits unexpectedly.
+#[derive(Clone)]
+pub struct TransactionRecorder {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl std::fmt::Debug for TransactionRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransactionRecorder")
+            .finish_non_exhaustive()
+    }
+}
+
+impl TransactionRecorder {
+    /// Create a recorder which writes to any writer
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+        }
+    }
+
+    /// Create a recorder which appends to a file, creating it if necessary
+    pub fn to_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(Self::new(LineWriter::new(file)))
+    }
+
+    /// Write a transaction to the log
+    ///
+    /// Failure to write is logged, but does not interrupt the operation being recorded
+    pub fn record(&self, transaction: &Transaction) {
+        // Unwrap: Serialization of a Transaction cannot fail
+        let line = serde_json::to_string(transaction).unwrap();
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(e) = writeln!(writer, "{line}").and_then(|_| writer.flush()) {
+            log::error!("Failed to write transaction log: {e}");
+        }
+    }
+
+    /// Flush the log
+    ///
+    /// Each transaction is flushed as it is written, so this only reports whether the writer can
+    /// still be flushed.
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.writer.lock().unwrap().flush()
+    }
+
+    /// Record an operation which started at `start`
+    pub(crate) fn record_operation(&self, start: Started, operation: Operation, outcome: Outcome) {
+        self.record(&Transaction {
+            timestamp_us: start.timestamp_us,
+            duration_us: start.instant.elapsed().as_micros() as u64,
+            operation,
+            outcome,
+        });
+    }
+}
+
+/// The start time of an operation being recorded
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Started {
+    timestamp_us: u64,
+    instant: Instant,
+}
+
+impl Started {
+    pub fn now() -> Self {
+        Self {
+            timestamp_us: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_micros() as u64)
+                .unwrap_or(0),
+            instant: Instant::now(),
+        }
+    }
+}
+
+/// Read a transaction log from a reader
+pub fn read_transactions(reader: impl BufRead) -> Result<Vec<Transaction>, TransactionLogError> {
+    let mut transactions = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.context(IoSnafu)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        transactions.push(serde_json::from_str(&line).context(ParseSnafu { line: i + 1 })?);
+    }
+    Ok(transactions)
+}
+
+/// Load a transaction log from a file
+pub fn load_transactions(path: impl AsRef<Path>) -> Result<Vec<Transaction>, TransactionLogError> {
+    let file = File::open(path).context(IoSnafu)?;
+    read_transactions(BufReader::new(file))
+}
+
+/// Summary statistics of a transaction log
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TransactionSummary {
+    /// The total number of transactions
+    pub total: usize,
+    /// The number of transactions which failed
+    pub failed: usize,
+    /// The number of transactions which changed the state of a node
+    pub writes: usize,
+    /// The sum of the durations of all transactions, in microseconds
+    pub total_duration_us: u64,
+    /// The duration of the slowest transaction, in microseconds
+    pub max_duration_us: u64,
+}
+
+impl TransactionSummary {
+    /// Compute the summary of a list of transactions
+    pub fn new(transactions: &[Transaction]) -> Self {
+        let mut summary = Self::default();
+        for t in transactions {
+            summary.total += 1;
+            if !t.outcome.is_ok() {
+                summary.failed += 1;
+            }
+            if t.operation.is_write() {
+                summary.writes += 1;
+            }
+            summary.total_duration_us += t.duration_us;
+            summary.max_duration_us = summary.max_duration_us.max(t.duration_us);
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_record_and_load() {
+        let buf = SharedBuf::default();
+        let recorder = TransactionRecorder::new(buf.clone());
+        let transactions = vec![
+            Transaction {
+                timestamp_us: 1000,
+                duration_us: 200,
+                operation: Operation::SdoUpload {
+                    node: Some(3),
+                    index: 0x1008,
+                    sub: 0,
+                },
+                outcome: Outcome::Ok {
+                    response: Some(b"Node".to_vec()),
+                },
+            },
+            Transaction {
+                timestamp_us: 2000,
+                duration_us: 100_000,
+                operation: Operation::SdoDownload {
+                    node: Some(3),
+                    index: 0x2000,
+                    sub: 1,
+                    data: vec![1, 2],
+                    block: false,
+                },
+                outcome: Outcome::Error {
+                    message: "No response".into(),
+                },
+            },
+            Transaction {
+                timestamp_us: 3000,
+                duration_us: 10,
+                operation: Operation::Nmt {
+                    command: "Start".into(),
+                    node: 0,
+                },
+                outcome: Outcome::Ok { response: None },
+            },
+        ];
+        for t in &transactions {
+            recorder.record(t);
+        }
+
+        let data = buf.0.lock().unwrap().clone();
+        assert_eq!(3, data.iter().filter(|b| **b == b'\n').count());
+        let loaded = read_transactions(data.as_slice()).unwrap();
+        assert_eq!(transactions, loaded);
+
+        let summary = TransactionSummary::new(&loaded);
+        assert_eq!(
+            TransactionSummary {
+                total: 3,
+                failed: 1,
+                writes: 2,
+                total_duration_us: 100_210,
+                max_duration_us: 100_000,
+            },
+            summary
+        );
+
+        let err = read_transactions("{}\n".as_bytes()).unwrap_err();
+        assert!(matches!(err, TransactionLogError::Parse { line: 1, .. }));
+    }
+}
@@ -0,0 +1,178 @@
+//! Inspection of the SDO transfers in progress
+//!
+//! A [`TransferMonitor`] can be attached to one or more [`SdoClient`](crate::SdoClient)s, and
+//! holds an [`InFlightTransfer`] for each transfer they are running: the object being accessed,
+//! the bytes transferred so far, and when the server last responded. A
+//! [`BusManager`](crate::BusManager) attaches its own monitor to every client it creates, so a
+//! transfer which appears stuck can be inspected from another task with
+//! [`BusManager::sdo_transfers`](crate::BusManager::sdo_transfers).
+//!
+//! This mirrors the SDO server status object (0x5005), which a node can provide to report the same
+//! information from the server side.
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use zencan_common::sdo::SdoTransferState;
+
+use crate::SdoCobIds;
+
+/// An SDO transfer being run by a client
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InFlightTransfer {
+    /// The node ID of the SDO server, if the client was created for a node ID
+    pub node_id: Option<u8>,
+    /// The COB-IDs of the SDO server
+    pub cob_ids: SdoCobIds,
+    /// The type of transfer
+    ///
+    /// Expedited downloads are reported as segmented downloads, as the client does not know in
+    /// advance which the server will accept.
+    pub state: SdoTransferState,
+    /// The index of the object being transferred
+    pub index: u16,
+    /// The sub index of the object being transferred
+    pub sub: u8,
+    /// The number of data bytes transferred so far
+    ///
+    /// For downloads, this counts only data which has been acknowledged by the server.
+    pub bytes_transferred: usize,
+    /// The total number of data bytes in the transfer, if known
+    pub total_bytes: Option<usize>,
+    /// The time the transfer was started
+    pub started: Instant,
+    /// The time the server last responded, or the start time if it has not responded
+    pub last_progress: Instant,
+}
+
+/// Tracks the SDO transfers in progress on a set of clients
+///
+/// The monitor is a cheap handle which can be cloned, and all clones share the same transfers.
+#[derive(Debug, Clone, Default)]
+pub struct TransferMonitor {
+    transfers: Arc<Mutex<Vec<InFlightTransfer>>>,
+}
+
+impl TransferMonitor {
+    /// Create a monitor with no transfers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get all of the transfers in progress, oldest first
+    pub fn transfers(&self) -> Vec<InFlightTransfer> {
+        self.transfers.lock().unwrap().clone()
+    }
+
+    /// Get the transfer in progress with the default SDO server of a node, if any
+    pub fn transfer(&self, node_id: u8) -> Option<InFlightTransfer> {
+        let cob_ids = SdoCobIds::std(node_id);
+        self.transfers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|t| t.cob_ids == cob_ids)
+            .copied()
+    }
+
+    /// Add a transfer, which is removed when the returned tracker is dropped
+    pub(crate) fn begin(&self, transfer: InFlightTransfer) -> TransferTracker {
+        let mut transfers = self.transfers.lock().unwrap();
+        // A client only runs one transfer at a time, so an existing entry is left over from a
+        // transfer which was cancelled
+        transfers.retain(|t| t.cob_ids != transfer.cob_ids);
+        transfers.push(transfer);
+        TransferTracker {
+            inner: Some((self.clone(), transfer.cob_ids)),
+        }
+    }
+
+    fn update(&self, cob_ids: SdoCobIds, f: impl FnOnce(&mut InFlightTransfer)) {
+        let mut transfers = self.transfers.lock().unwrap();
+        if let Some(transfer) = transfers.iter_mut().find(|t| t.cob_ids == cob_ids) {
+            f(transfer);
+        }
+    }
+
+    fn end(&self, cob_ids: SdoCobIds) {
+        self.transfers
+            .lock()
+            .unwrap()
+            .retain(|t| t.cob_ids != cob_ids);
+    }
+}
+
+/// Updates the progress of a transfer in a [`TransferMonitor`], and removes it when dropped
+///
+/// The transfer is removed even if the future running it is cancelled.
+#[derive(Debug)]
+pub(crate) struct TransferTracker {
+    inner: Option<(TransferMonitor, SdoCobIds)>,
+}
+
+impl TransferTracker {
+    /// Create a tracker for a client with no monitor, which does nothing
+    pub fn none() -> Self {
+        Self { inner: None }
+    }
+
+    /// Record a response from the server
+    pub fn progress(&self, bytes_transferred: usize, total_bytes: Option<usize>) {
+        if let Some((monitor, cob_ids)) = &self.inner {
+            monitor.update(*cob_ids, |t| {
+                t.bytes_transferred = bytes_transferred;
+                t.total_bytes = total_bytes;
+                t.last_progress = Instant::now();
+            });
+        }
+    }
+}
+
+impl Drop for TransferTracker {
+    fn drop(&mut self) {
+        if let Some((monitor, cob_ids)) = &self.inner {
+            monitor.end(*cob_ids);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(node_id: u8) -> InFlightTransfer {
+        let now = Instant::now();
+        InFlightTransfer {
+            node_id: Some(node_id),
+            cob_ids: SdoCobIds::std(node_id),
+            state: SdoTransferState::BlockDownload,
+            index: 0x3000,
+            sub: 1,
+            bytes_transferred: 0,
+            total_bytes: Some(1000),
+            started: now,
+            last_progress: now,
+        }
+    }
+
+    #[test]
+    fn test_tracker() {
+        let monitor = TransferMonitor::new();
+        let tracker = monitor.begin(transfer(1));
+        let other = monitor.begin(transfer(2));
+        assert_eq!(2, monitor.transfers().len());
+
+        tracker.progress(889, Some(1000));
+        let t = monitor.transfer(1).unwrap();
+        assert_eq!(889, t.bytes_transferred);
+        assert!(t.last_progress >= t.started);
+        assert_eq!(0, monitor.transfer(2).unwrap().bytes_transferred);
+
+        drop(tracker);
+        assert_eq!(None, monitor.transfer(1));
+        assert_eq!(1, monitor.transfers().len());
+        drop(other);
+        assert!(monitor.transfers().is_empty());
+    }
+}
@@ -0,0 +1,421 @@
+//! Opening a connection to a CAN bus from a string description
+//!
+//! The client services are generic over the [`AsyncCanSender`] and [`AsyncCanReceiver`] traits, so
+//! they can be used with any CAN interface. This module provides the interfaces supported by the
+//! zencan tools, behind a single [`TransportSender`] and [`TransportReceiver`] type, so that an
+//! application can select one at run time with [`open_transport`]:
+//!
+//! - `udp:<bind address>,<peer address>`: CAN frames tunneled over UDP, with [`open_udp`]. This is
+//!   available on all platforms.
+//! - `socketcan:<interface>`, or just `<interface>`: A socketcan interface, e.g. `can0`. This is
+//!   only available on Linux.
+//! - `slcan:<serial port>[,<bitrate>]`: A serial line adapter, e.g. `slcan:/dev/ttyACM0,500000`,
+//!   with [`open_slcan`]. This requires the `slcan` feature, and is available on Unix platforms.
+//!
+//! ```no_run
+//! # async fn example() {
+//! use zencan_client::{transport::open_transport, BusManager};
+//!
+//! let (tx, rx) = open_transport("udp:0.0.0.0:11898,192.168.1.10:11898").unwrap();
+//! let manager = BusManager::new(tx, rx);
+//! # }
+//! ```
+use std::{
+    net::{SocketAddr, UdpSocket as StdUdpSocket},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use snafu::{ResultExt, Snafu};
+use tokio::net::UdpSocket;
+use zencan_common::{
+    messages::{CanId, CanMessage},
+    traits::{AsyncCanReceiver, AsyncCanSender},
+};
+
+#[cfg(target_os = "linux")]
+use zencan_common::{ReceiveError, SocketCanReceiver, SocketCanSender};
+
+#[cfg(all(feature = "slcan", unix))]
+mod slcan;
+#[cfg(all(feature = "slcan", unix))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "slcan", unix))))]
+pub use slcan::{open_slcan, SlcanReceiver, SlcanSender};
+
+/// Set in the encoded ID of a UDP frame for an extended ID
+const UDP_EXTENDED_FLAG: u32 = 1 << 31;
+/// Set in the encoded ID of a UDP frame for a remote transmission request
+const UDP_RTR_FLAG: u32 = 1 << 30;
+/// The size of the encoded ID at the start of each UDP datagram
+const UDP_HEADER_SIZE: usize = 4;
+
+/// Encode a CAN message as a UDP datagram
+///
+/// Each datagram holds one frame: the ID as a little endian u32, with the extended and RTR flags in
+/// bits 31 and 30, followed by the data bytes.
+fn encode_udp_frame(msg: &CanMessage, buf: &mut [u8; UDP_HEADER_SIZE + 8]) -> usize {
+    let mut id = msg.id().raw();
+    if msg.id().is_extended() {
+        id |= UDP_EXTENDED_FLAG;
+    }
+    if msg.is_rtr() {
+        id |= UDP_RTR_FLAG;
+    }
+    buf[..UDP_HEADER_SIZE].copy_from_slice(&id.to_le_bytes());
+    let data = msg.data();
+    buf[UDP_HEADER_SIZE..UDP_HEADER_SIZE + data.len()].copy_from_slice(data);
+    UDP_HEADER_SIZE + data.len()
+}
+
+/// Decode a UDP datagram created by [`encode_udp_frame`]
+fn decode_udp_frame(datagram: &[u8]) -> Option<CanMessage> {
+    if datagram.len() < UDP_HEADER_SIZE || datagram.len() > UDP_HEADER_SIZE + 8 {
+        return None;
+    }
+    let raw = u32::from_le_bytes(datagram[..UDP_HEADER_SIZE].try_into().unwrap());
+    let id = if raw & UDP_EXTENDED_FLAG != 0 {
+        CanId::extended(raw & 0x1FFF_FFFF)
+    } else {
+        CanId::std((raw & 0x7FF) as u16)
+    };
+    if raw & UDP_RTR_FLAG != 0 {
+        Some(CanMessage::new_rtr(id))
+    } else {
+        Some(CanMessage::new(id, &datagram[UDP_HEADER_SIZE..]))
+    }
+}
+
+/// Sends CAN frames over UDP
+///
+/// Created by [`open_udp`].
+#[derive(Debug, Clone)]
+pub struct UdpCanSender {
+    socket: Arc<UdpSocket>,
+}
+
+impl AsyncCanSender for UdpCanSender {
+    async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
+        let mut buf = [0; UDP_HEADER_SIZE + 8];
+        let len = encode_udp_frame(&msg, &mut buf);
+        match self.socket.send(&buf[..len]).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(msg),
+        }
+    }
+}
+
+/// Receives CAN frames over UDP
+///
+/// Created by [`open_udp`]. Datagrams which are not valid frames are ignored.
+#[derive(Debug, Clone)]
+pub struct UdpCanReceiver {
+    socket: Arc<UdpSocket>,
+}
+
+fn timestamp_now(msg: CanMessage) -> CanMessage {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    msg.with_timestamp(now.as_micros() as u64)
+}
+
+impl AsyncCanReceiver for UdpCanReceiver {
+    type Error = std::io::Error;
+
+    fn try_recv(&mut self) -> Option<CanMessage> {
+        let mut buf = [0; 64];
+        loop {
+            let len = self.socket.try_recv(&mut buf).ok()?;
+            if let Some(msg) = decode_udp_frame(&buf[..len]) {
+                return Some(timestamp_now(msg));
+            }
+        }
+    }
+
+    async fn recv(&mut self) -> Result<CanMessage, Self::Error> {
+        let mut buf = [0; 64];
+        loop {
+            let len = self.socket.recv(&mut buf).await?;
+            if let Some(msg) = decode_udp_frame(&buf[..len]) {
+                return Ok(timestamp_now(msg));
+            }
+        }
+    }
+}
+
+/// Open a CAN bus tunneled over UDP
+///
+/// Frames are sent to `peer`, and only frames received from `peer` are returned. The peer is
+/// typically a CAN to ethernet gateway, or a program bridging to a local CAN interface.
+///
+/// Must be called from within a tokio runtime.
+pub fn open_udp(
+    bind: SocketAddr,
+    peer: SocketAddr,
+) -> std::io::Result<(UdpCanSender, UdpCanReceiver)> {
+    let socket = StdUdpSocket::bind(bind)?;
+    socket.connect(peer)?;
+    socket.set_nonblocking(true)?;
+    let socket = Arc::new(UdpSocket::from_std(socket)?);
+    Ok((
+        UdpCanSender {
+            socket: socket.clone(),
+        },
+        UdpCanReceiver { socket },
+    ))
+}
+
+/// Error returned by [`open_transport`]
+#[derive(Debug, Snafu)]
+pub enum TransportError {
+    /// The transport description could not be parsed
+    #[snafu(display("Invalid transport '{spec}': {reason}"))]
+    InvalidSpec {
+        /// The transport description
+        spec: String,
+        /// Why it is invalid
+        reason: String,
+    },
+    /// The transport is not supported on this platform
+    #[snafu(display("Transport '{spec}' is not supported on this platform"))]
+    Unsupported {
+        /// The transport description
+        spec: String,
+    },
+    /// Opening the transport failed
+    #[snafu(display("Failed to open '{spec}': {source}"))]
+    Open {
+        /// The transport description
+        spec: String,
+        /// The underlying error
+        source: std::io::Error,
+    },
+}
+
+/// A sender for any of the supported transports
+#[derive(Debug)]
+pub enum TransportSender {
+    /// A socketcan interface
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
+    SocketCan(SocketCanSender),
+    /// CAN over UDP
+    Udp(UdpCanSender),
+    /// A serial line adapter
+    #[cfg(all(feature = "slcan", unix))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "slcan", unix))))]
+    Slcan(SlcanSender),
+}
+
+impl AsyncCanSender for TransportSender {
+    async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
+        match self {
+            #[cfg(target_os = "linux")]
+            TransportSender::SocketCan(sender) => sender.send(msg).await,
+            TransportSender::Udp(sender) => sender.send(msg).await,
+            #[cfg(all(feature = "slcan", unix))]
+            TransportSender::Slcan(sender) => sender.send(msg).await,
+        }
+    }
+
+    fn echoes_sent_frames(&self) -> bool {
+        match self {
+            #[cfg(target_os = "linux")]
+            TransportSender::SocketCan(sender) => sender.echoes_sent_frames(),
+            TransportSender::Udp(sender) => sender.echoes_sent_frames(),
+            #[cfg(all(feature = "slcan", unix))]
+            TransportSender::Slcan(sender) => sender.echoes_sent_frames(),
+        }
+    }
+}
+
+/// A receiver for any of the supported transports
+#[derive(Debug)]
+pub enum TransportReceiver {
+    /// A socketcan interface
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
+    SocketCan(SocketCanReceiver),
+    /// CAN over UDP
+    Udp(UdpCanReceiver),
+    /// A serial line adapter
+    #[cfg(all(feature = "slcan", unix))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "slcan", unix))))]
+    Slcan(SlcanReceiver),
+}
+
+/// Error returned by [`TransportReceiver`]
+#[derive(Debug, Snafu)]
+pub enum TransportReceiveError {
+    /// An error from a socketcan interface
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
+    #[snafu(context(false), display("{source}"))]
+    SocketCan {
+        /// The underlying error
+        source: ReceiveError,
+    },
+    /// An error from a UDP socket
+    #[snafu(context(false), display("{source}"))]
+    Udp {
+        /// The underlying error
+        source: std::io::Error,
+    },
+    /// An error from a serial line adapter
+    #[cfg(all(feature = "slcan", unix))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "slcan", unix))))]
+    #[snafu(display("{source}"))]
+    Slcan {
+        /// The underlying error
+        source: std::io::Error,
+    },
+}
+
+impl AsyncCanReceiver for TransportReceiver {
+    type Error = TransportReceiveError;
+
+    fn try_recv(&mut self) -> Option<CanMessage> {
+        match self {
+            #[cfg(target_os = "linux")]
+            TransportReceiver::SocketCan(receiver) => receiver.try_recv(),
+            TransportReceiver::Udp(receiver) => receiver.try_recv(),
+            #[cfg(all(feature = "slcan", unix))]
+            TransportReceiver::Slcan(receiver) => receiver.try_recv(),
+        }
+    }
+
+    async fn recv(&mut self) -> Result<CanMessage, Self::Error> {
+        match self {
+            #[cfg(target_os = "linux")]
+            TransportReceiver::SocketCan(receiver) => Ok(receiver.recv().await?),
+            TransportReceiver::Udp(receiver) => Ok(receiver.recv().await?),
+            #[cfg(all(feature = "slcan", unix))]
+            TransportReceiver::Slcan(receiver) => receiver
+                .recv()
+                .await
+                .map_err(|source| TransportReceiveError::Slcan { source }),
+        }
+    }
+}
+
+fn parse_addr(spec: &str, addr: &str) -> Result<SocketAddr, TransportError> {
+    addr.trim().parse().map_err(|_| {
+        InvalidSpecSnafu {
+            spec,
+            reason: format!("'{addr}' is not a socket address"),
+        }
+        .build()
+    })
+}
+
+/// Open a transport from a description, e.g. `can0` or `udp:0.0.0.0:11898,10.0.0.2:11898`
+///
+/// See the [module documentation](self) for the supported transports. Must be called from within
+/// a tokio runtime.
+pub fn open_transport(spec: &str) -> Result<(TransportSender, TransportReceiver), TransportError> {
+    if let Some(addrs) = spec.strip_prefix("udp:") {
+        let Some((bind, peer)) = addrs.split_once(',') else {
+            return InvalidSpecSnafu {
+                spec,
+                reason: "expected 'udp:<bind address>,<peer address>'",
+            }
+            .fail();
+        };
+        let bind = parse_addr(spec, bind)?;
+        let peer = parse_addr(spec, peer)?;
+        let (tx, rx) = open_udp(bind, peer).context(OpenSnafu { spec })?;
+        return Ok((TransportSender::Udp(tx), TransportReceiver::Udp(rx)));
+    }
+
+    if let Some(args) = spec.strip_prefix("slcan:") {
+        let (path, bitrate) = match args.split_once(',') {
+            Some((path, bitrate)) => {
+                let bitrate = bitrate.trim().parse().map_err(|_| {
+                    InvalidSpecSnafu {
+                        spec,
+                        reason: format!("'{bitrate}' is not a bitrate"),
+                    }
+                    .build()
+                })?;
+                (path, Some(bitrate))
+            }
+            None => (args, None),
+        };
+        #[cfg(all(feature = "slcan", unix))]
+        {
+            let (tx, rx) = open_slcan(path, bitrate).context(OpenSnafu { spec })?;
+            return Ok((TransportSender::Slcan(tx), TransportReceiver::Slcan(rx)));
+        }
+        #[cfg(not(all(feature = "slcan", unix)))]
+        {
+            let _: (&str, Option<u32>) = (path, bitrate);
+            return UnsupportedSnafu { spec }.fail();
+        }
+    }
+
+    let interface = spec.strip_prefix("socketcan:").unwrap_or(spec);
+    #[cfg(target_os = "linux")]
+    {
+        let (tx, rx) = zencan_common::open_socketcan(interface).context(OpenSnafu { spec })?;
+        Ok((
+            TransportSender::SocketCan(tx),
+            TransportReceiver::SocketCan(rx),
+        ))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = interface;
+        UnsupportedSnafu { spec }.fail()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_udp_frame_roundtrip() {
+        let mut buf = [0; UDP_HEADER_SIZE + 8];
+        for msg in [
+            CanMessage::new(CanId::std(0x601), &[0x40, 0x00, 0x10, 0x00]),
+            CanMessage::new(CanId::extended(0x1234567), &[1, 2, 3, 4, 5, 6, 7, 8]),
+            CanMessage::new(CanId::std(0x80), &[]),
+            CanMessage::new_rtr(CanId::std(0x701)),
+        ] {
+            let len = encode_udp_frame(&msg, &mut buf);
+            assert_eq!(Some(msg), decode_udp_frame(&buf[..len]));
+        }
+        assert_eq!(None, decode_udp_frame(&[1, 2, 3]));
+        assert_eq!(None, decode_udp_frame(&[0; 13]));
+    }
+
+    #[tokio::test]
+    async fn test_udp_transport() {
+        let a_addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let a = StdUdpSocket::bind(a_addr).unwrap();
+        let b = StdUdpSocket::bind(a_addr).unwrap();
+        let a_addr = a.local_addr().unwrap();
+        let b_addr = b.local_addr().unwrap();
+        drop((a, b));
+
+        let (mut tx, _rx) = open_udp(a_addr, b_addr).unwrap();
+        let (_tx, mut rx) = open_udp(b_addr, a_addr).unwrap();
+        let msg = CanMessage::new(CanId::std(0x181), &[1, 2, 3]);
+        tx.send(msg).await.unwrap();
+        let received = rx.recv().await.unwrap();
+        assert_eq!(msg, received);
+        assert!(received.timestamp_us().is_some());
+    }
+
+    #[test]
+    fn test_invalid_spec() {
+        assert!(matches!(
+            open_transport("udp:127.0.0.1:1000"),
+            Err(TransportError::InvalidSpec { .. })
+        ));
+        assert!(matches!(
+            open_transport("udp:localhost,127.0.0.1:1000"),
+            Err(TransportError::InvalidSpec { .. })
+        ));
+    }
+}
@@ -0,0 +1,328 @@
+//! CAN through a serial line adapter, using the slcan (Lawicel) ASCII protocol
+//!
+//! Many low cost USB CAN adapters, e.g. CANable and USBtin, present a serial port which speaks
+//! slcan. Each frame is a line of ASCII hex, terminated by a carriage return: `t` followed by a 3
+//! digit standard ID, the data length, and the data bytes, or `T` with an 8 digit extended ID.
+//! Remote requests use `r` and `R`.
+//!
+//! The sender and receiver are generic over the byte stream, so that an adapter bridged over TCP
+//! can also be used. [`open_slcan`] opens a serial port.
+use std::{fmt::Write as _, io, path::Path};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use zencan_common::{
+    messages::{CanId, CanMessage},
+    traits::{AsyncCanReceiver, AsyncCanSender},
+};
+
+use super::timestamp_now;
+
+/// The longest line, for an extended frame with 8 data bytes
+const MAX_LINE: usize = 1 + 8 + 1 + 16;
+
+/// The bitrates which can be selected with the `S` command, in order of the command's argument
+const BITRATES: [u32; 9] = [
+    10_000, 20_000, 50_000, 100_000, 125_000, 250_000, 500_000, 800_000, 1_000_000,
+];
+
+/// Encode a frame as an slcan line, including the terminating carriage return
+fn encode_slcan_frame(msg: &CanMessage) -> String {
+    let mut line = String::with_capacity(MAX_LINE + 1);
+    let kind = match (msg.id(), msg.is_rtr()) {
+        (CanId::Std(_), false) => 't',
+        (CanId::Std(_), true) => 'r',
+        (CanId::Extended(_), false) => 'T',
+        (CanId::Extended(_), true) => 'R',
+    };
+    line.push(kind);
+    match msg.id() {
+        CanId::Std(id) => write!(line, "{id:03X}").unwrap(),
+        CanId::Extended(id) => write!(line, "{id:08X}").unwrap(),
+    }
+    write!(line, "{}", msg.data().len()).unwrap();
+    if !msg.is_rtr() {
+        for b in msg.data() {
+            write!(line, "{b:02X}").unwrap();
+        }
+    }
+    line.push('\r');
+    line
+}
+
+/// Decode a received slcan line, without its terminating carriage return
+///
+/// Returns None for lines which are not frames, e.g. the acknowledgement of a sent frame.
+fn decode_slcan_frame(line: &[u8]) -> Option<CanMessage> {
+    let line = core::str::from_utf8(line).ok()?;
+    let (extended, rtr) = match line.chars().next()? {
+        't' => (false, false),
+        'r' => (false, true),
+        'T' => (true, false),
+        'R' => (true, true),
+        _ => return None,
+    };
+    let id_len = if extended { 8 } else { 3 };
+    let rest = &line[1..];
+    if rest.len() < id_len + 1 || !rest.is_ascii() {
+        return None;
+    }
+    let raw_id = u32::from_str_radix(&rest[..id_len], 16).ok()?;
+    let len = rest[id_len..id_len + 1].parse::<usize>().ok()?;
+    if len > 8 {
+        return None;
+    }
+    let id = if extended {
+        if raw_id > 0x1FFF_FFFF {
+            return None;
+        }
+        CanId::extended(raw_id)
+    } else {
+        if raw_id > 0x7FF {
+            return None;
+        }
+        CanId::std(raw_id as u16)
+    };
+    if rtr {
+        return Some(CanMessage::new_rtr(id));
+    }
+    // Some adapters append a timestamp after the data, which is ignored
+    let hex = &rest[id_len + 1..];
+    if hex.len() < len * 2 {
+        return None;
+    }
+    let mut data = [0u8; 8];
+    for (i, byte) in data.iter_mut().enumerate().take(len) {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(CanMessage::new(id, &data[..len]))
+}
+
+/// Sends CAN frames to an slcan adapter
+///
+/// Created by [`open_slcan`], or by [`SlcanSender::new`] for another byte stream.
+#[derive(Debug)]
+pub struct SlcanSender<W = tokio::fs::File> {
+    writer: W,
+}
+
+impl<W: AsyncWrite + Unpin + Send> SlcanSender<W> {
+    /// Create a sender which writes to `writer`
+    ///
+    /// The adapter must already be open. See [`open_slcan`].
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> AsyncCanSender for SlcanSender<W> {
+    async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
+        let line = encode_slcan_frame(&msg);
+        let result = async {
+            self.writer.write_all(line.as_bytes()).await?;
+            self.writer.flush().await
+        }
+        .await;
+        result.map_err(|_| msg)
+    }
+}
+
+/// Receives CAN frames from an slcan adapter
+///
+/// Created by [`open_slcan`], or by [`SlcanReceiver::new`] for another byte stream. Lines which
+/// are not frames, such as the acknowledgements of sent frames, are ignored. Frames are stamped
+/// with the time they are read, as slcan adapters do not report a timestamp in a common format.
+///
+/// [`try_recv`](AsyncCanReceiver::try_recv) only returns frames which have already been read from
+/// the stream.
+#[derive(Debug)]
+pub struct SlcanReceiver<R = tokio::fs::File> {
+    reader: R,
+    buf: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin + Send> SlcanReceiver<R> {
+    /// Create a receiver which reads from `reader`
+    ///
+    /// The adapter must already be open. See [`open_slcan`].
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Take the next frame from the lines which have been read
+    fn next_buffered(&mut self) -> Option<CanMessage> {
+        // A bell is sent in place of a line when the adapter rejects a command
+        while let Some(end) = self.buf.iter().position(|&b| b == b'\r' || b == 0x07) {
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            if let Some(msg) = decode_slcan_frame(&line[..end]) {
+                return Some(timestamp_now(msg));
+            }
+        }
+        // Without a terminator, the stream is out of step with the protocol
+        if self.buf.len() > MAX_LINE {
+            self.buf.clear();
+        }
+        None
+    }
+}
+
+impl<R: AsyncRead + Unpin + Send> AsyncCanReceiver for SlcanReceiver<R> {
+    type Error = io::Error;
+
+    fn try_recv(&mut self) -> Option<CanMessage> {
+        self.next_buffered()
+    }
+
+    async fn recv(&mut self) -> Result<CanMessage, Self::Error> {
+        loop {
+            if let Some(msg) = self.next_buffered() {
+                return Ok(msg);
+            }
+            let mut chunk = [0; 64];
+            let len = self.reader.read(&mut chunk).await?;
+            if len == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.buf.extend_from_slice(&chunk[..len]);
+        }
+    }
+}
+
+/// Get the argument of the `S` command which selects a bitrate
+fn bitrate_code(bitrate: u32) -> io::Result<usize> {
+    BITRATES.iter().position(|&b| b == bitrate).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("slcan does not support a bitrate of {bitrate}"),
+        )
+    })
+}
+
+/// Put a serial port into raw mode, so that bytes are passed through unchanged
+fn set_raw_mode(file: &std::fs::File) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    // Safety: termios is plain data, which is filled in by tcgetattr
+    let mut termios: libc::termios = unsafe { core::mem::zeroed() };
+    // Safety: the pointer refers to termios, which outlives the calls
+    unsafe {
+        if libc::tcgetattr(fd, &mut termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut termios);
+        // The baud rate has no effect on USB adapters, but is set for real serial lines
+        libc::cfsetispeed(&mut termios, libc::B115200);
+        libc::cfsetospeed(&mut termios, libc::B115200);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+        if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Open an slcan adapter on a serial port, e.g. `/dev/ttyACM0`
+///
+/// The CAN channel is closed, set to `bitrate` if one is given, and opened. Without a bitrate, the
+/// adapter uses the bitrate it was last configured with.
+///
+/// Must be called from within a tokio runtime.
+pub fn open_slcan(
+    path: impl AsRef<Path>,
+    bitrate: Option<u32>,
+) -> io::Result<(SlcanSender, SlcanReceiver)> {
+    use std::io::Write as _;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let code = bitrate.map(bitrate_code).transpose()?;
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(path)?;
+    set_raw_mode(&file)?;
+
+    // Close the channel first, in case it was left open, so that the bitrate can be set
+    let mut setup = String::from("C\r");
+    if let Some(code) = code {
+        write!(setup, "S{code}\r").unwrap();
+    }
+    setup.push_str("O\r");
+    file.write_all(setup.as_bytes())?;
+
+    let reader = tokio::fs::File::from_std(file.try_clone()?);
+    let writer = tokio::fs::File::from_std(file);
+    Ok((SlcanSender::new(writer), SlcanReceiver::new(reader)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slcan_frame_roundtrip() {
+        for (msg, line) in [
+            (
+                CanMessage::new(CanId::std(0x601), &[0x40, 0x00, 0x10, 0x00]),
+                "t601440001000\r",
+            ),
+            (
+                CanMessage::new(CanId::extended(0x1234567), &[1, 2, 3, 4, 5, 6, 7, 8]),
+                "T0123456780102030405060708\r",
+            ),
+            (CanMessage::new(CanId::std(0x80), &[]), "t0800\r"),
+            (CanMessage::new_rtr(CanId::std(0x701)), "r7010\r"),
+        ] {
+            assert_eq!(line, encode_slcan_frame(&msg));
+            let line = line.as_bytes();
+            assert_eq!(Some(msg), decode_slcan_frame(&line[..line.len() - 1]));
+        }
+        // A trailing timestamp is ignored
+        assert_eq!(
+            Some(CanMessage::new(CanId::std(0x181), &[0xAB])),
+            decode_slcan_frame(b"t1811AB1234")
+        );
+        for line in [&b"z"[..], b"", b"t18", b"t1819AB", b"t8001AB", b"t1812AB"] {
+            assert_eq!(None, decode_slcan_frame(line));
+        }
+    }
+
+    #[test]
+    fn test_bitrate_code() {
+        assert_eq!(6, bitrate_code(500_000).unwrap());
+        assert_eq!(8, bitrate_code(1_000_000).unwrap());
+        assert!(bitrate_code(333_333).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_slcan_stream() {
+        let (adapter, host) = tokio::io::duplex(256);
+        let (host_rx, host_tx) = tokio::io::split(host);
+        let (mut adapter_rx, mut adapter_tx) = tokio::io::split(adapter);
+        let mut sender = SlcanSender::new(host_tx);
+        let mut receiver = SlcanReceiver::new(host_rx);
+
+        let msg = CanMessage::new(CanId::std(0x181), &[1, 2, 3]);
+        sender.send(msg).await.unwrap();
+        let mut line = [0; 12];
+        adapter_rx.read_exact(&mut line).await.unwrap();
+        assert_eq!(b"t1813010203\r", &line);
+
+        // Acknowledgements and rejected commands are skipped, and frames may be split across reads
+        adapter_tx.write_all(b"z\r\x07t18").await.unwrap();
+        assert_eq!(None, receiver.try_recv());
+        adapter_tx.write_all(b"11AB\rT000001232").await.unwrap();
+        let received = receiver.recv().await.unwrap();
+        assert_eq!(CanMessage::new(CanId::std(0x181), &[0xAB]), received);
+        assert!(received.timestamp_us().is_some());
+        adapter_tx.write_all(b"0102\r").await.unwrap();
+        assert_eq!(
+            CanMessage::new(CanId::extended(0x123), &[1, 2]),
+            receiver.recv().await.unwrap()
+        );
+    }
+}
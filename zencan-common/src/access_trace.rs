@@ -0,0 +1,146 @@
+//! Entries of the object access trace (object 0x5003)
+//!
+//! A node with an access trace records the most recent SDO and PDO accesses to its objects in a
+//! ring buffer, which can be uploaded from the Domain sub object 0x5003sub2. The upload contains
+//! the entries oldest first, each encoded in [`ENTRY_SIZE`] bytes:
+//!
+//! | Offset | Type | Description |
+//! | ------ | ---- | ----------- |
+//! | 0      | u32  | Time of the access in milliseconds, from the clock passed to the node |
+//! | 4      | u16  | Object index |
+//! | 6      | u8   | Sub index |
+//! | 7      | u8   | [`AccessKind`] |
+//! | 8      | u32  | SDO abort code, or 0 if the access succeeded |
+//!
+//! PDO accesses are recorded once per PDO message, with the index of the PDO communication
+//! parameter object, e.g. 0x1400 for the first RPDO, and sub index 0.
+
+/// The number of bytes in an encoded trace entry
+pub const ENTRY_SIZE: usize = 12;
+
+/// The type of an access recorded in the trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum AccessKind {
+    /// An SDO upload, i.e. a read by a client
+    SdoRead = 0,
+    /// An SDO download, i.e. a write by a client
+    SdoWrite = 1,
+    /// A received RPDO was written to its mapped objects
+    RpdoWrite = 2,
+    /// A TPDO was read from its mapped objects and transmitted
+    TpdoRead = 3,
+}
+
+impl AccessKind {
+    /// Returns true if the access wrote to the object
+    pub fn is_write(&self) -> bool {
+        matches!(self, AccessKind::SdoWrite | AccessKind::RpdoWrite)
+    }
+}
+
+impl TryFrom<u8> for AccessKind {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AccessKind::SdoRead),
+            1 => Ok(AccessKind::SdoWrite),
+            2 => Ok(AccessKind::RpdoWrite),
+            3 => Ok(AccessKind::TpdoRead),
+            _ => Err(value),
+        }
+    }
+}
+
+/// A single access recorded in the trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AccessTraceEntry {
+    /// Time of the access in milliseconds
+    ///
+    /// This is the time passed to the node when the access was processed, so it wraps after
+    /// about 49 days.
+    pub timestamp_ms: u32,
+    /// The object index
+    pub index: u16,
+    /// The sub index
+    pub sub: u8,
+    /// The type of access
+    pub kind: AccessKind,
+    /// The SDO abort code, or 0 if the access succeeded
+    pub abort_code: u32,
+}
+
+impl AccessTraceEntry {
+    /// An entry used to initialize trace buffers
+    pub const EMPTY: Self = Self {
+        timestamp_ms: 0,
+        index: 0,
+        sub: 0,
+        kind: AccessKind::SdoRead,
+        abort_code: 0,
+    };
+
+    /// Encode the entry
+    pub fn to_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..4].copy_from_slice(&self.timestamp_ms.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.index.to_le_bytes());
+        bytes[6] = self.sub;
+        bytes[7] = self.kind as u8;
+        bytes[8..12].copy_from_slice(&self.abort_code.to_le_bytes());
+        bytes
+    }
+
+    /// Decode an entry
+    ///
+    /// Returns None if the access kind is not valid
+    pub fn from_bytes(bytes: &[u8; ENTRY_SIZE]) -> Option<Self> {
+        Some(Self {
+            timestamp_ms: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            index: u16::from_le_bytes(bytes[4..6].try_into().unwrap()),
+            sub: bytes[6],
+            kind: bytes[7].try_into().ok()?,
+            abort_code: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+        })
+    }
+
+    /// Decode the entries of an uploaded trace, oldest first
+    ///
+    /// Any trailing partial entry, and any entries which are not valid, are skipped.
+    pub fn decode_all(data: &[u8]) -> impl Iterator<Item = AccessTraceEntry> + '_ {
+        data.chunks_exact(ENTRY_SIZE)
+            .filter_map(|chunk| Self::from_bytes(chunk.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_roundtrip() {
+        let entry = AccessTraceEntry {
+            timestamp_ms: 123456,
+            index: 0x2001,
+            sub: 4,
+            kind: AccessKind::SdoWrite,
+            abort_code: 0x0607_0012,
+        };
+        let bytes = entry.to_bytes();
+        assert_eq!(Some(entry), AccessTraceEntry::from_bytes(&bytes));
+
+        let mut data = [bytes, AccessTraceEntry::EMPTY.to_bytes()].concat();
+        data.push(0);
+        let decoded: Vec<_> = AccessTraceEntry::decode_all(&data).collect();
+        assert_eq!(vec![entry, AccessTraceEntry::EMPTY], decoded);
+
+        let mut bytes = bytes;
+        bytes[7] = 9;
+        assert_eq!(None, AccessTraceEntry::from_bytes(&bytes));
+    }
+}
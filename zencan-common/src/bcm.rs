@@ -0,0 +1,266 @@
+//! Cyclic transmission by the socketcan Broadcast Manager (BCM)
+//!
+//! Frames sent periodically from a user-space timer, such as SYNC, inherit the scheduling jitter of
+//! the process which sends them. The Linux Broadcast Manager sends them from a kernel timer
+//! instead, so their period is independent of the load on the application. A [`CyclicTransmitter`]
+//! programs BCM jobs on an interface: each job sends one or more frames with the same ID, in turn,
+//! at a fixed period, until it is stopped or the transmitter is dropped.
+//!
+//! The bus manager uses it to offload its SYNC producer. TPDOs are not offloaded, as zencan nodes
+//! do not implement the TPDO event timer: their TPDOs are sent on application events or in response
+//! to SYNC, rather than at a period of their own.
+
+use std::{
+    ffi::CString,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    time::Duration,
+};
+
+use crate::messages::{CanId, CanMessage};
+
+// Opcodes and flags from linux/can/bcm.h
+const TX_SETUP: u32 = 1;
+const TX_DELETE: u32 = 2;
+const SETTIMER: u32 = 0x0001;
+const STARTTIMER: u32 = 0x0002;
+
+/// The maximum number of frames sent in turn by a single job
+pub const MAX_CYCLIC_FRAMES: usize = 256;
+
+/// The size of a `struct can_frame`
+const FRAME_SIZE: usize = 16;
+
+fn invalid_input(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn raw_can_id(id: CanId, rtr: bool) -> u32 {
+    let id = match id {
+        CanId::Std(id) => id as u32,
+        CanId::Extended(id) => id | libc::CAN_EFF_FLAG,
+    };
+    if rtr {
+        id | libc::CAN_RTR_FLAG
+    } else {
+        id
+    }
+}
+
+fn pad_to(buf: &mut Vec<u8>, align: usize) {
+    buf.resize(buf.len().next_multiple_of(align), 0);
+}
+
+/// Encode a `struct bcm_msg_head`, followed by its frames
+///
+/// The layout depends on the size of `long`, which is used for the timer intervals, and the frames
+/// are 8 byte aligned.
+fn encode_msg(
+    opcode: u32,
+    flags: u32,
+    period: Duration,
+    id: CanId,
+    frames: &[CanMessage],
+) -> Vec<u8> {
+    let long_size = core::mem::size_of::<libc::c_long>();
+    let put_long = |buf: &mut Vec<u8>, value: u64| {
+        buf.extend_from_slice(&(value as libc::c_long).to_ne_bytes());
+    };
+
+    let mut buf = Vec::with_capacity(56 + frames.len() * FRAME_SIZE);
+    buf.extend_from_slice(&opcode.to_ne_bytes());
+    buf.extend_from_slice(&flags.to_ne_bytes());
+    // The number of frames to send at ival1, before continuing at ival2
+    buf.extend_from_slice(&0u32.to_ne_bytes());
+    pad_to(&mut buf, long_size);
+    // ival1
+    put_long(&mut buf, 0);
+    put_long(&mut buf, 0);
+    // ival2
+    put_long(&mut buf, period.as_secs());
+    put_long(&mut buf, period.subsec_micros() as u64);
+    buf.extend_from_slice(&raw_can_id(id, false).to_ne_bytes());
+    buf.extend_from_slice(&(frames.len() as u32).to_ne_bytes());
+    pad_to(&mut buf, 8);
+
+    for frame in frames {
+        buf.extend_from_slice(&raw_can_id(frame.id(), frame.is_rtr()).to_ne_bytes());
+        buf.push(frame.data().len() as u8);
+        // Padding, and reserved bytes
+        buf.extend_from_slice(&[0; 3]);
+        let mut data = [0; 8];
+        data[..frame.data().len()].copy_from_slice(frame.data());
+        buf.extend_from_slice(&data);
+    }
+    buf
+}
+
+/// Sends frames at fixed periods using the kernel Broadcast Manager
+///
+/// Each job is identified by the ID of its frames. Dropping the transmitter closes its socket,
+/// which stops all of its jobs.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use zencan_common::{messages::SyncObject, CyclicTransmitter};
+///
+/// let transmitter = CyclicTransmitter::open("can0").unwrap();
+/// // Send a SYNC every 10ms, with a counter from 1 to 4
+/// let frames: Vec<_> = (1..=4).map(|n| SyncObject::new(n).into()).collect();
+/// transmitter.start(&frames, Duration::from_millis(10)).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct CyclicTransmitter {
+    socket: OwnedFd,
+    device: String,
+}
+
+impl CyclicTransmitter {
+    /// Open a Broadcast Manager socket on an interface, e.g. "can0"
+    pub fn open(device: &str) -> io::Result<Self> {
+        let name = CString::new(device).map_err(|_| invalid_input("Invalid interface name"))?;
+        // Safety: if_nametoindex only reads the provided NUL terminated string
+        let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Safety: socket has no memory safety requirements
+        let fd = unsafe { libc::socket(libc::PF_CAN, libc::SOCK_DGRAM, libc::CAN_BCM) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Safety: fd is a newly opened socket, which is owned by nothing else
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // Safety: sockaddr_can is plain data, for which all zeros is valid
+        let mut addr: libc::sockaddr_can = unsafe { core::mem::zeroed() };
+        addr.can_family = libc::AF_CAN as libc::sa_family_t;
+        addr.can_ifindex = ifindex as libc::c_int;
+        // Safety: the pointer and length describe addr, which outlives the call
+        let result = unsafe {
+            libc::connect(
+                socket.as_raw_fd(),
+                &addr as *const libc::sockaddr_can as *const libc::sockaddr,
+                core::mem::size_of::<libc::sockaddr_can>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            socket,
+            device: device.to_string(),
+        })
+    }
+
+    /// Get the name of the interface
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    fn write(&self, msg: &[u8]) -> io::Result<()> {
+        // Safety: the pointer and length describe msg, which outlives the call
+        let result = unsafe {
+            libc::write(
+                self.socket.as_raw_fd(),
+                msg.as_ptr() as *const libc::c_void,
+                msg.len(),
+            )
+        };
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else if result as usize != msg.len() {
+            Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "Short write to BCM socket",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn setup(&self, frames: &[CanMessage], flags: u32, period: Duration) -> io::Result<()> {
+        let Some(first) = frames.first() else {
+            return Err(invalid_input("A cyclic job requires at least one frame"));
+        };
+        if frames.len() > MAX_CYCLIC_FRAMES {
+            return Err(invalid_input("Too many frames for a cyclic job"));
+        }
+        if frames.iter().any(|f| f.id() != first.id()) {
+            return Err(invalid_input(
+                "All frames of a cyclic job must have the same ID",
+            ));
+        }
+        self.write(&encode_msg(TX_SETUP, flags, period, first.id(), frames))
+    }
+
+    /// Start sending `frames` in turn, one every `period`
+    ///
+    /// The first frame is sent after one period. If a job is already running for the ID of the
+    /// frames, it is replaced, and its timer is restarted with the new period. All of the frames
+    /// must have the same ID, and there can be up to [`MAX_CYCLIC_FRAMES`] of them.
+    pub fn start(&self, frames: &[CanMessage], period: Duration) -> io::Result<()> {
+        if period.is_zero() {
+            return Err(invalid_input("The period of a cyclic job must not be zero"));
+        }
+        self.setup(frames, SETTIMER | STARTTIMER, period)
+    }
+
+    /// Change the frames sent by a running job, without restarting its timer
+    ///
+    /// The new frames are sent from the next period onwards.
+    pub fn update(&self, frames: &[CanMessage]) -> io::Result<()> {
+        self.setup(frames, 0, Duration::ZERO)
+    }
+
+    /// Stop the job sending frames with `id`
+    ///
+    /// Returns an error if there is no job for the ID.
+    pub fn stop(&self, id: CanId) -> io::Result<()> {
+        self.write(&encode_msg(TX_DELETE, 0, Duration::ZERO, id, &[]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_msg() {
+        let head_size = if core::mem::size_of::<libc::c_long>() == 8 {
+            56
+        } else {
+            40
+        };
+        let frames = [
+            CanMessage::new(CanId::std(0x80), &[1]),
+            CanMessage::new(CanId::std(0x80), &[2]),
+        ];
+        let msg = encode_msg(
+            TX_SETUP,
+            SETTIMER | STARTTIMER,
+            Duration::from_micros(2_000_500),
+            CanId::std(0x80),
+            &frames,
+        );
+        assert_eq!(head_size + 2 * FRAME_SIZE, msg.len());
+        assert_eq!(&TX_SETUP.to_ne_bytes(), &msg[0..4]);
+        // The ID and number of frames end the header
+        assert_eq!(&0x80u32.to_ne_bytes(), &msg[head_size - 8..head_size - 4]);
+        assert_eq!(&2u32.to_ne_bytes(), &msg[head_size - 4..head_size]);
+        let second = &msg[head_size + FRAME_SIZE..];
+        assert_eq!(&0x80u32.to_ne_bytes(), &second[0..4]);
+        assert_eq!(1, second[4]);
+        assert_eq!(&[2, 0, 0, 0, 0, 0, 0, 0], &second[8..16]);
+
+        let msg = encode_msg(TX_DELETE, 0, Duration::ZERO, CanId::extended(0x1234), &[]);
+        assert_eq!(head_size, msg.len());
+        assert_eq!(
+            &(0x1234 | libc::CAN_EFF_FLAG).to_ne_bytes(),
+            &msg[head_size - 8..head_size - 4]
+        );
+    }
+}
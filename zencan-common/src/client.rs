@@ -0,0 +1,18 @@
+//! Allocation free state machines for implementing a bus master
+//!
+//! These implement the client side of the SDO protocol, and the heartbeat monitoring of an NMT
+//! master, without performing any IO or allocation. They are used by `zencan-client` to implement
+//! its async clients, and they can be used directly by no_std devices which need to act as a bus
+//! master, e.g. a gateway which configures the nodes behind it.
+//!
+//! The state machines are driven by the application: requests are taken from the state machine
+//! and sent to the bus, and responses received from the bus are passed back to it. Timeouts are
+//! left to the application, as it knows how it wants to measure time.
+
+mod nmt;
+mod sdo;
+
+pub use nmt::{HeartbeatMonitor, NodeStatus};
+pub use sdo::{
+    ClientRequest, ClientTransfer, SdoBlockDownload, SdoDownload, SdoTransferError, SdoUpload,
+};
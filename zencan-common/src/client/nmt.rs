@@ -0,0 +1,144 @@
+//! Heartbeat monitoring for an NMT master
+
+use crate::messages::{CanMessage, Heartbeat, NmtState, ZencanMessage};
+
+/// The status of a node on the bus, as reported by its heartbeat messages
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NodeStatus {
+    /// The ID of the node
+    pub id: u8,
+    /// The last NMT state reported by the node
+    pub state: NmtState,
+    /// The time at which the last heartbeat was received from the node, in microseconds
+    ///
+    /// This is on the same time base as the `now_us` values passed to the [`HeartbeatMonitor`].
+    pub last_seen_us: u64,
+    /// The toggle bit of the last heartbeat
+    pub toggle: bool,
+}
+
+impl NodeStatus {
+    const EMPTY: Self = Self {
+        id: 0,
+        state: NmtState::Bootup,
+        last_seen_us: 0,
+        toggle: false,
+    };
+}
+
+/// Tracks the nodes present on a bus using their heartbeat messages
+///
+/// Up to `N` nodes are tracked, kept sorted by node ID. Once the monitor is full, heartbeats from
+/// nodes which are not already tracked are ignored. Tracking every possible node requires an `N`
+/// of 127.
+#[derive(Debug)]
+pub struct HeartbeatMonitor<const N: usize> {
+    nodes: [NodeStatus; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for HeartbeatMonitor<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> HeartbeatMonitor<N> {
+    /// Create an empty monitor
+    pub const fn new() -> Self {
+        Self {
+            nodes: [NodeStatus::EMPTY; N],
+            len: 0,
+        }
+    }
+
+    /// Handle a message received from the bus
+    ///
+    /// Messages other than heartbeats are ignored. Returns the updated status of the node if the
+    /// message was a heartbeat from a tracked node.
+    pub fn handle_message(&mut self, msg: CanMessage, now_us: u64) -> Option<NodeStatus> {
+        match ZencanMessage::try_from(msg) {
+            Ok(ZencanMessage::Heartbeat(heartbeat)) => self.handle_heartbeat(heartbeat, now_us),
+            _ => None,
+        }
+    }
+
+    /// Handle a heartbeat received from the bus
+    ///
+    /// Returns the updated status of the node, or None if the monitor is full and the node was
+    /// not already tracked.
+    pub fn handle_heartbeat(&mut self, heartbeat: Heartbeat, now_us: u64) -> Option<NodeStatus> {
+        // A node is pre-operational once it has sent its boot-up message
+        let state = match heartbeat.state {
+            NmtState::Bootup => NmtState::PreOperational,
+            state => state,
+        };
+        let status = NodeStatus {
+            id: heartbeat.node,
+            state,
+            last_seen_us: now_us,
+            toggle: heartbeat.toggle,
+        };
+        match self.nodes[..self.len].binary_search_by_key(&heartbeat.node, |n| n.id) {
+            Ok(i) => self.nodes[i] = status,
+            Err(i) => {
+                if self.len == N {
+                    return None;
+                }
+                self.nodes.copy_within(i..self.len, i + 1);
+                self.nodes[i] = status;
+                self.len += 1;
+            }
+        }
+        Some(status)
+    }
+
+    /// Get the status of all nodes seen on the bus, sorted by node ID
+    pub fn nodes(&self) -> &[NodeStatus] {
+        &self.nodes[..self.len]
+    }
+
+    /// Get the status of a single node, if it has been seen on the bus
+    pub fn get(&self, id: u8) -> Option<&NodeStatus> {
+        self.nodes()
+            .binary_search_by_key(&id, |n| n.id)
+            .ok()
+            .map(|i| &self.nodes[i])
+    }
+
+    /// Stop tracking all nodes
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heartbeat(node: u8) -> Heartbeat {
+        Heartbeat {
+            node,
+            toggle: false,
+            state: NmtState::PreOperational,
+        }
+    }
+
+    #[test]
+    fn test_nodes_sorted() {
+        let mut monitor = HeartbeatMonitor::<3>::new();
+        monitor.handle_message(heartbeat(5).into(), 10);
+        monitor.handle_message(heartbeat(2).into(), 20);
+        monitor.handle_message(heartbeat(9).into(), 30);
+        monitor.handle_message(heartbeat(5).into(), 40);
+
+        let ids: Vec<u8> = monitor.nodes().iter().map(|n| n.id).collect();
+        assert_eq!(vec![2, 5, 9], ids);
+        assert_eq!(40, monitor.get(5).unwrap().last_seen_us);
+
+        // Monitor is full, so new nodes are dropped
+        assert_eq!(None, monitor.handle_message(heartbeat(1).into(), 50));
+        assert_eq!(3, monitor.nodes().len());
+    }
+}
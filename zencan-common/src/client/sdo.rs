@@ -0,0 +1,707 @@
+//! SDO client transfers
+//!
+//! Each transfer type is a state machine for a single access to an object on an SDO server. A
+//! transfer is created with the object to access, and then driven by repeatedly sending every
+//! request returned by [`ClientTransfer::next_request`], and passing the next response received
+//! from the server to its `handle_response` method, until it completes or fails.
+//!
+//! ```ignore
+//! let mut transfer = SdoDownload::new(0x2000, 1, &data);
+//! loop {
+//!     while let Some(req) = transfer.next_request() {
+//!         send(req.to_can_message(req_cob_id));
+//!     }
+//!     if transfer.is_complete() {
+//!         break;
+//!     }
+//!     transfer.handle_response(wait_for_response()?)?;
+//! }
+//! ```
+//!
+//! When `handle_response` returns an error, the transfer is over, but there may still be an abort
+//! request to be sent to the server.
+
+use snafu::Snafu;
+
+use crate::{
+    messages::{CanId, CanMessage},
+    sdo::{AbortCode, BlockSegment, SdoRequest, SdoResponse},
+};
+
+/// A message to be sent from an SDO client to the server
+#[derive(Clone, Copy, Debug)]
+pub enum ClientRequest {
+    /// An SDO request
+    Sdo(SdoRequest),
+    /// A data segment of a block download
+    Segment(BlockSegment),
+}
+
+impl ClientRequest {
+    /// Create a CanMessage for transmission of the request on the given COB ID
+    pub fn to_can_message(&self, id: CanId) -> CanMessage {
+        match self {
+            ClientRequest::Sdo(req) => req.to_can_message(id),
+            ClientRequest::Segment(segment) => segment.to_can_message(id),
+        }
+    }
+}
+
+/// Error which ends an SDO client transfer
+#[derive(Clone, Copy, Debug, PartialEq, Snafu)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SdoTransferError {
+    /// The server aborted the transfer
+    #[snafu(display("Received abort accessing object 0x{index:X}sub{sub}: 0x{abort_code:08X}"))]
+    ServerAbort {
+        /// Index of the object being accessed
+        index: u16,
+        /// Sub index of the object being accessed
+        sub: u8,
+        /// The abort code sent by the server
+        abort_code: u32,
+    },
+    /// The server sent a valid SDO response, but not the one expected at this point in the
+    /// transfer
+    #[snafu(display("Unexpected SDO response. Expected {expecting}, got {response:?}"))]
+    UnexpectedResponse {
+        /// The type of response which was expected
+        expecting: &'static str,
+        /// The response which was received
+        response: SdoResponse,
+    },
+    /// The server sent a segment with the wrong toggle bit
+    ToggleNotAlternated,
+    /// The server responded with a different object than the one requested
+    #[snafu(display("Received object 0x{:x}sub{} after requesting 0x{:x}sub{}",
+        received.0, received.1, expected.0, expected.1))]
+    MismatchedObjectIndex {
+        /// The requested object
+        expected: (u16, u8),
+        /// The object in the response
+        received: (u16, u8),
+    },
+    /// The server shrunk the block size below the already delivered segments while requesting
+    /// retransmission
+    BlockSizeChangedTooSmall,
+}
+
+/// Get the error for a response which is not the one expected
+fn unexpected(resp: SdoResponse, expecting: &'static str) -> SdoTransferError {
+    match resp {
+        SdoResponse::Abort {
+            index,
+            sub,
+            abort_code,
+        } => SdoTransferError::ServerAbort {
+            index,
+            sub,
+            abort_code,
+        },
+        _ => SdoTransferError::UnexpectedResponse {
+            expecting,
+            response: resp,
+        },
+    }
+}
+
+/// The interface common to all SDO client transfers
+pub trait ClientTransfer {
+    /// Get the next request which must be sent to the server, if any
+    ///
+    /// This should be called until it returns None after creating the transfer, and after each
+    /// response is handled.
+    fn next_request(&mut self) -> Option<ClientRequest>;
+
+    /// Returns true once the transfer has successfully completed
+    fn is_complete(&self) -> bool;
+
+    /// Get the number of data bytes transferred so far
+    ///
+    /// For downloads, this counts only data which has been acknowledged by the server.
+    fn bytes_transferred(&self) -> usize;
+
+    /// Get the total number of data bytes in the transfer, if known
+    ///
+    /// The size of an upload is only known if the server indicates it.
+    fn total_bytes(&self) -> Option<usize>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Initiate,
+    Segments,
+    Complete,
+    Failed,
+}
+
+/// An expedited or segmented download, writing data to an object on the server
+///
+/// An expedited transfer is used when the data fits in a single message.
+#[derive(Debug)]
+pub struct SdoDownload<'a> {
+    index: u16,
+    sub: u8,
+    data: &'a [u8],
+    pos: usize,
+    toggle: bool,
+    state: State,
+    pending: Option<SdoRequest>,
+}
+
+impl<'a> SdoDownload<'a> {
+    /// Create a download of `data` to a sub object
+    pub fn new(index: u16, sub: u8, data: &'a [u8]) -> Self {
+        let initiate = if data.len() <= 4 {
+            SdoRequest::expedited_download(index, sub, data)
+        } else {
+            SdoRequest::initiate_download(index, sub, Some(data.len() as u32))
+        };
+        Self {
+            index,
+            sub,
+            data,
+            pos: 0,
+            toggle: false,
+            state: State::Initiate,
+            pending: Some(initiate),
+        }
+    }
+
+    /// Handle a response from the server
+    ///
+    /// Responses received after the transfer has ended are ignored.
+    pub fn handle_response(&mut self, resp: SdoResponse) -> Result<(), SdoTransferError> {
+        match (self.state, resp) {
+            (State::Initiate, SdoResponse::ConfirmDownload { .. }) => {
+                if self.data.len() <= 4 {
+                    self.pos = self.data.len();
+                    self.state = State::Complete;
+                } else {
+                    self.state = State::Segments;
+                    self.queue_segment();
+                }
+                Ok(())
+            }
+            (State::Initiate, _) => self.fail(unexpected(resp, "ConfirmDownload")),
+            (State::Segments, SdoResponse::ConfirmDownloadSegment { t }) => {
+                if t != self.toggle {
+                    self.pending = Some(SdoRequest::abort(
+                        self.index,
+                        self.sub,
+                        AbortCode::ToggleNotAlternated,
+                    ));
+                    return self.fail(SdoTransferError::ToggleNotAlternated);
+                }
+                self.pos = (self.pos + 7).min(self.data.len());
+                self.toggle = !self.toggle;
+                if self.pos == self.data.len() {
+                    self.state = State::Complete;
+                } else {
+                    self.queue_segment();
+                }
+                Ok(())
+            }
+            (State::Segments, _) => self.fail(unexpected(resp, "ConfirmDownloadSegment")),
+            (State::Complete | State::Failed, _) => Ok(()),
+        }
+    }
+
+    fn queue_segment(&mut self) {
+        let end = (self.pos + 7).min(self.data.len());
+        self.pending = Some(SdoRequest::download_segment(
+            self.toggle,
+            end == self.data.len(),
+            &self.data[self.pos..end],
+        ));
+    }
+
+    fn fail(&mut self, e: SdoTransferError) -> Result<(), SdoTransferError> {
+        self.state = State::Failed;
+        Err(e)
+    }
+}
+
+impl ClientTransfer for SdoDownload<'_> {
+    fn next_request(&mut self) -> Option<ClientRequest> {
+        self.pending.take().map(ClientRequest::Sdo)
+    }
+
+    fn is_complete(&self) -> bool {
+        self.state == State::Complete
+    }
+
+    fn bytes_transferred(&self) -> usize {
+        self.pos
+    }
+
+    fn total_bytes(&self) -> Option<usize> {
+        Some(self.data.len())
+    }
+}
+
+/// An upload, reading data from an object on the server
+///
+/// The server decides whether an expedited or segmented transfer is used. The data is not stored
+/// by the transfer; it is passed to the caller as it is received, so that the caller may store it
+/// however it likes.
+#[derive(Clone, Copy, Debug)]
+pub struct SdoUpload {
+    index: u16,
+    sub: u8,
+    toggle: bool,
+    size: Option<u32>,
+    received: usize,
+    state: State,
+    pending: Option<SdoRequest>,
+}
+
+impl SdoUpload {
+    /// Create an upload from a sub object
+    pub fn new(index: u16, sub: u8) -> Self {
+        Self {
+            index,
+            sub,
+            toggle: false,
+            size: None,
+            received: 0,
+            state: State::Initiate,
+            pending: Some(SdoRequest::initiate_upload(index, sub)),
+        }
+    }
+
+    /// Get the size of the object, if it was indicated by the server
+    ///
+    /// This is known once the response to the initiate request has been handled.
+    pub fn size(&self) -> Option<u32> {
+        self.size
+    }
+
+    /// Handle a response from the server
+    ///
+    /// Any data contained in the response is passed to `on_data`, in order. Responses received
+    /// after the transfer has ended are ignored.
+    pub fn handle_response(
+        &mut self,
+        resp: SdoResponse,
+        mut on_data: impl FnMut(&[u8]),
+    ) -> Result<(), SdoTransferError> {
+        match (self.state, resp) {
+            (State::Initiate, SdoResponse::ConfirmUpload { n, e, s, data, .. }) => {
+                if e {
+                    // Without a size, the server is sending an empty value. See
+                    // `SdoResponse::expedited_upload`.
+                    let len = if s { 4 - n.min(4) as usize } else { 0 };
+                    self.size = Some(len as u32);
+                    self.received += len;
+                    on_data(&data[..len]);
+                    self.state = State::Complete;
+                } else {
+                    if s {
+                        self.size = Some(u32::from_le_bytes(data));
+                    }
+                    self.state = State::Segments;
+                    self.pending = Some(SdoRequest::upload_segment_request(self.toggle));
+                }
+                Ok(())
+            }
+            (State::Initiate, _) => self.fail(unexpected(resp, "ConfirmUpload")),
+            (State::Segments, SdoResponse::UploadSegment { t, n, c, data }) => {
+                if t != self.toggle {
+                    self.pending = Some(SdoRequest::abort(
+                        self.index,
+                        self.sub,
+                        AbortCode::ToggleNotAlternated,
+                    ));
+                    return self.fail(SdoTransferError::ToggleNotAlternated);
+                }
+                let len = 7 - n.min(7) as usize;
+                self.received += len;
+                on_data(&data[..len]);
+                if c {
+                    self.state = State::Complete;
+                } else {
+                    self.toggle = !self.toggle;
+                    self.pending = Some(SdoRequest::upload_segment_request(self.toggle));
+                }
+                Ok(())
+            }
+            (State::Segments, _) => self.fail(unexpected(resp, "UploadSegment")),
+            (State::Complete | State::Failed, _) => Ok(()),
+        }
+    }
+
+    fn fail(&mut self, e: SdoTransferError) -> Result<(), SdoTransferError> {
+        self.state = State::Failed;
+        Err(e)
+    }
+}
+
+impl ClientTransfer for SdoUpload {
+    fn next_request(&mut self) -> Option<ClientRequest> {
+        self.pending.take().map(ClientRequest::Sdo)
+    }
+
+    fn is_complete(&self) -> bool {
+        self.state == State::Complete
+    }
+
+    fn bytes_transferred(&self) -> usize {
+        self.received
+    }
+
+    fn total_bytes(&self) -> Option<usize> {
+        self.size.map(|s| s as usize)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BlockState {
+    Initiate,
+    /// Sending the segments of a block
+    Sending,
+    /// Waiting for the server to confirm a block
+    Confirm,
+    End,
+    Complete,
+    Failed,
+}
+
+/// A block download, writing data to an object on the server
+///
+/// Block downloads are more efficient for large amounts of data, but may not be supported by all
+/// devices.
+#[derive(Debug)]
+pub struct SdoBlockDownload<'a> {
+    index: u16,
+    sub: u8,
+    data: &'a [u8],
+    crc_enabled: bool,
+    blksize: u8,
+    /// The sequence number of the next segment to send, or of the last segment sent while
+    /// waiting for confirmation
+    seqnum: u8,
+    /// The segment to send next, or the last segment sent while waiting for confirmation
+    segment_num: usize,
+    /// The first segment of the current block
+    block_start: usize,
+    state: BlockState,
+    pending: Option<SdoRequest>,
+}
+
+impl<'a> SdoBlockDownload<'a> {
+    /// Create a block download of `data` to a sub object
+    pub fn new(index: u16, sub: u8, data: &'a [u8]) -> Self {
+        Self {
+            index,
+            sub,
+            data,
+            crc_enabled: false,
+            blksize: 0,
+            seqnum: 1,
+            segment_num: 0,
+            block_start: 0,
+            state: BlockState::Initiate,
+            pending: Some(SdoRequest::initiate_block_download(
+                index,
+                sub,
+                true,
+                data.len() as u32,
+            )),
+        }
+    }
+
+    fn total_segments(&self) -> usize {
+        self.data.len().div_ceil(7)
+    }
+
+    /// Handle a response from the server
+    ///
+    /// Responses received after the transfer has ended are ignored.
+    pub fn handle_response(&mut self, resp: SdoResponse) -> Result<(), SdoTransferError> {
+        match (self.state, resp) {
+            (
+                BlockState::Initiate,
+                SdoResponse::ConfirmBlockDownload {
+                    sc,
+                    index,
+                    sub,
+                    blksize,
+                },
+            ) => {
+                if (index, sub) != (self.index, self.sub) {
+                    return self.fail(SdoTransferError::MismatchedObjectIndex {
+                        expected: (self.index, self.sub),
+                        received: (index, sub),
+                    });
+                }
+                self.crc_enabled = sc;
+                self.blksize = blksize;
+                self.next_block();
+                Ok(())
+            }
+            (BlockState::Initiate, _) => self.fail(unexpected(resp, "ConfirmBlockDownload")),
+            (BlockState::Confirm, SdoResponse::ConfirmBlock { ackseq, blksize }) => {
+                if ackseq >= self.seqnum {
+                    // All segments in the block were received
+                    self.segment_num = self.block_start + self.seqnum as usize;
+                    self.block_start = self.segment_num;
+                    self.seqnum = 1;
+                } else {
+                    // Some segments were missed. Resend all segments after ackseq
+                    self.segment_num = self.block_start + ackseq as usize;
+                    self.seqnum = ackseq + 1;
+                    // The spec says the block size given by the server can change between blocks.
+                    // What should a client do if it is going to resend a block, and the server sets
+                    // the block size smaller than the already delivered segments? This shouldn't
+                    // happen I think, but, it's possible. zencan-node based nodes won't do it, but
+                    // there are other devices out there.
+                    if blksize < self.seqnum {
+                        return self.fail(SdoTransferError::BlockSizeChangedTooSmall);
+                    }
+                }
+                self.blksize = blksize;
+                self.next_block();
+                Ok(())
+            }
+            (BlockState::Sending | BlockState::Confirm, _) => {
+                self.fail(unexpected(resp, "ConfirmBlock"))
+            }
+            (BlockState::End, SdoResponse::ConfirmBlockDownloadEnd) => {
+                self.state = BlockState::Complete;
+                Ok(())
+            }
+            (BlockState::End, _) => self.fail(unexpected(resp, "ConfirmBlockDownloadEnd")),
+            (BlockState::Complete | BlockState::Failed, _) => Ok(()),
+        }
+    }
+
+    /// Start sending the next block, or end the download if all segments have been sent
+    fn next_block(&mut self) {
+        if self.segment_num < self.total_segments() {
+            self.state = BlockState::Sending;
+        } else {
+            let crc = if self.crc_enabled {
+                crc16::State::<crc16::XMODEM>::calculate(self.data)
+            } else {
+                0
+            };
+            let n = ((7 - self.data.len() % 7) % 7) as u8;
+            self.pending = Some(SdoRequest::end_block_download(n, crc));
+            self.state = BlockState::End;
+        }
+    }
+
+    fn fail(&mut self, e: SdoTransferError) -> Result<(), SdoTransferError> {
+        self.state = BlockState::Failed;
+        Err(e)
+    }
+}
+
+impl ClientTransfer for SdoBlockDownload<'_> {
+    fn next_request(&mut self) -> Option<ClientRequest> {
+        if let Some(req) = self.pending.take() {
+            return Some(ClientRequest::Sdo(req));
+        }
+        if self.state != BlockState::Sending {
+            return None;
+        }
+
+        let start = self.segment_num * 7;
+        let len = (self.data.len() - start).min(7);
+        let c = start + len == self.data.len();
+        let mut data = [0; 7];
+        data[..len].copy_from_slice(&self.data[start..start + len]);
+        let segment = BlockSegment {
+            c,
+            seqnum: self.seqnum,
+            data,
+        };
+
+        // The server confirms after blksize segments, or after the last segment
+        if c || self.seqnum == self.blksize {
+            self.state = BlockState::Confirm;
+        } else {
+            self.seqnum += 1;
+            self.segment_num += 1;
+        }
+        Some(ClientRequest::Segment(segment))
+    }
+
+    fn is_complete(&self) -> bool {
+        self.state == BlockState::Complete
+    }
+
+    fn bytes_transferred(&self) -> usize {
+        (self.block_start * 7).min(self.data.len())
+    }
+
+    fn total_bytes(&self) -> Option<usize> {
+        Some(self.data.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requests(transfer: &mut impl ClientTransfer) -> Vec<ClientRequest> {
+        core::iter::from_fn(|| transfer.next_request()).collect()
+    }
+
+    #[test]
+    fn test_segmented_download() {
+        let data: Vec<u8> = (0..10).collect();
+        let mut transfer = SdoDownload::new(0x2000, 1, &data);
+        let reqs = requests(&mut transfer);
+        assert!(matches!(
+            reqs[..],
+            [ClientRequest::Sdo(SdoRequest::InitiateDownload {
+                e: false,
+                ..
+            })]
+        ));
+
+        transfer
+            .handle_response(SdoResponse::ConfirmDownload {
+                index: 0x2000,
+                sub: 1,
+            })
+            .unwrap();
+        let reqs = requests(&mut transfer);
+        assert!(matches!(
+            reqs[..],
+            [ClientRequest::Sdo(SdoRequest::DownloadSegment {
+                t: false,
+                n: 0,
+                c: false,
+                ..
+            })]
+        ));
+
+        transfer
+            .handle_response(SdoResponse::ConfirmDownloadSegment { t: false })
+            .unwrap();
+        assert_eq!(7, transfer.bytes_transferred());
+        let reqs = requests(&mut transfer);
+        assert!(matches!(
+            reqs[..],
+            [ClientRequest::Sdo(SdoRequest::DownloadSegment {
+                t: true,
+                n: 4,
+                c: true,
+                ..
+            })]
+        ));
+        assert!(!transfer.is_complete());
+
+        transfer
+            .handle_response(SdoResponse::ConfirmDownloadSegment { t: true })
+            .unwrap();
+        assert!(transfer.is_complete());
+        assert_eq!(10, transfer.bytes_transferred());
+        assert!(requests(&mut transfer).is_empty());
+    }
+
+    #[test]
+    fn test_upload_toggle_error_sends_abort() {
+        let mut transfer = SdoUpload::new(0x1008, 0);
+        requests(&mut transfer);
+        transfer
+            .handle_response(
+                SdoResponse::ConfirmUpload {
+                    n: 0,
+                    e: false,
+                    s: true,
+                    index: 0x1008,
+                    sub: 0,
+                    data: 20u32.to_le_bytes(),
+                },
+                |_| panic!("No data expected"),
+            )
+            .unwrap();
+        assert_eq!(Some(20), transfer.size());
+        assert_eq!(Some(20), transfer.total_bytes());
+        assert_eq!(0, transfer.bytes_transferred());
+        requests(&mut transfer);
+
+        let result = transfer.handle_response(
+            SdoResponse::UploadSegment {
+                t: true,
+                n: 0,
+                c: false,
+                data: [0; 7],
+            },
+            |_| (),
+        );
+        assert_eq!(Err(SdoTransferError::ToggleNotAlternated), result);
+        let reqs = requests(&mut transfer);
+        assert!(matches!(
+            reqs[..],
+            [ClientRequest::Sdo(SdoRequest::Abort { abort_code, .. })]
+                if abort_code == AbortCode::ToggleNotAlternated as u32
+        ));
+    }
+
+    #[test]
+    fn test_block_download_resend() {
+        let data: Vec<u8> = (0..21).collect();
+        let mut transfer = SdoBlockDownload::new(0x3000, 0, &data);
+        requests(&mut transfer);
+        transfer
+            .handle_response(SdoResponse::ConfirmBlockDownload {
+                sc: true,
+                index: 0x3000,
+                sub: 0,
+                blksize: 127,
+            })
+            .unwrap();
+        let seqnums: Vec<u8> = requests(&mut transfer)
+            .iter()
+            .map(|r| match r {
+                ClientRequest::Segment(s) => s.seqnum,
+                _ => panic!("Expected segment"),
+            })
+            .collect();
+        assert_eq!(vec![1, 2, 3], seqnums);
+
+        // Server only received the first segment, and the rest are sent again
+        transfer
+            .handle_response(SdoResponse::ConfirmBlock {
+                ackseq: 1,
+                blksize: 127,
+            })
+            .unwrap();
+        let segments: Vec<BlockSegment> = requests(&mut transfer)
+            .iter()
+            .map(|r| match r {
+                ClientRequest::Segment(s) => *s,
+                _ => panic!("Expected segment"),
+            })
+            .collect();
+        assert_eq!(2, segments.len());
+        assert_eq!(2, segments[0].seqnum);
+        assert_eq!(data[7..14], segments[0].data);
+        assert!(segments[1].c);
+
+        transfer
+            .handle_response(SdoResponse::ConfirmBlock {
+                ackseq: 3,
+                blksize: 127,
+            })
+            .unwrap();
+        assert_eq!(21, transfer.bytes_transferred());
+        let reqs = requests(&mut transfer);
+        assert!(matches!(
+            reqs[..],
+            [ClientRequest::Sdo(SdoRequest::EndBlockDownload {
+                n: 0,
+                ..
+            })]
+        ));
+        transfer
+            .handle_response(SdoResponse::ConfirmBlockDownloadEnd)
+            .unwrap();
+        assert!(transfer.is_complete());
+    }
+}
@@ -4,6 +4,18 @@
 
 /// Object indices for standard objects
 pub mod object_ids {
+    /// The Device Type object index
+    pub const DEVICE_TYPE: u16 = 0x1000;
+    /// The Error Register object index
+    pub const ERROR_REGISTER: u16 = 0x1001;
+    /// The Pre-defined Error Field (error history) object index
+    pub const PREDEFINED_ERROR_FIELD: u16 = 0x1003;
+    /// The SYNC COB-ID object index
+    pub const SYNC_COB_ID: u16 = 0x1005;
+    /// The communication cycle period object index
+    pub const COMM_CYCLE_PERIOD: u16 = 0x1006;
+    /// The synchronous window length object index
+    pub const SYNC_WINDOW_LENGTH: u16 = 0x1007;
     /// The Device Name object index
     pub const DEVICE_NAME: u16 = 0x1008;
     /// The hardware version object index
@@ -12,12 +24,66 @@ pub mod object_ids {
     pub const SAVE_OBJECTS: u16 = 0x1010;
     /// The software version object index
     pub const SOFTWARE_VERSION: u16 = 0x100A;
+    /// The EMCY COB-ID object index
+    pub const EMCY_COB_ID: u16 = 0x1014;
+    /// The EMCY inhibit time object index
+    pub const EMCY_INHIBIT_TIME: u16 = 0x1015;
+    /// The consumer heartbeat time object index
+    pub const HEARTBEAT_CONSUMER_TIME: u16 = 0x1016;
     /// The heartbeat producer time object index
     pub const HEARTBEAT_PRODUCER_TIME: u16 = 0x1017;
     /// The identity object index
     pub const IDENTITY: u16 = 0x1018;
+    /// The synchronous counter overflow value object index
+    pub const SYNC_COUNTER_OVERFLOW: u16 = 0x1019;
+    /// The verify configuration object index
+    pub const VERIFY_CONFIGURATION: u16 = 0x1020;
+    /// The SDO server parameter object index
+    pub const SDO_SERVER_PARAMETER: u16 = 0x1200;
     /// The auto start object index
     pub const AUTO_START: u16 = 0x5000;
+    /// The node statistics object index
+    pub const NODE_STATISTICS: u16 = 0x5001;
+    /// The NMT startup timing object index
+    pub const NMT_STARTUP_TIMING: u16 = 0x5002;
+    /// The object access trace object index
+    pub const ACCESS_TRACE: u16 = 0x5003;
+    /// The debug log object index
+    pub const DEBUG_LOG: u16 = 0x5004;
+    /// The SDO server status object index
+    pub const SDO_SERVER_STATUS: u16 = 0x5005;
+    /// The var pool status object index
+    pub const VAR_POOL_STATUS: u16 = 0x5006;
+    /// The secondary heartbeat object index
+    pub const SECONDARY_HEARTBEAT: u16 = 0x5007;
+    /// The event log object index
+    pub const EVENT_LOG: u16 = 0x5008;
+    /// The loopback self-test object index
+    pub const LOOPBACK_TEST: u16 = 0x5009;
+    /// The TPDO transmit count object index
+    pub const TPDO_TX_COUNT: u16 = 0x500A;
+    /// The TPDO last transmit time object index
+    pub const TPDO_TX_TIME: u16 = 0x500B;
+    /// The RPDO receive count object index
+    pub const RPDO_RX_COUNT: u16 = 0x500C;
+    /// The RPDO last receive time object index
+    pub const RPDO_RX_TIME: u16 = 0x500D;
+    /// The NMT unlock object index
+    pub const NMT_UNLOCK: u16 = 0x500E;
+    /// The settings backup object index
+    pub const SETTINGS_BACKUP: u16 = 0x5F10;
+    /// The CiA 401 write output 8-bit (digital outputs) object index
+    pub const DIGITAL_OUTPUT_8: u16 = 0x6200;
+    /// The CiA 401 error mode output 8-bit object index
+    pub const DIGITAL_OUTPUT_ERROR_MODE_8: u16 = 0x6206;
+    /// The CiA 401 error value output 8-bit object index
+    pub const DIGITAL_OUTPUT_ERROR_VALUE_8: u16 = 0x6207;
+    /// The CiA 401 write analogue output 16-bit object index
+    pub const ANALOG_OUTPUT_16: u16 = 0x6411;
+    /// The CiA 401 analogue output error mode object index
+    pub const ANALOG_OUTPUT_ERROR_MODE: u16 = 0x6443;
+    /// The CiA 401 analogue output error value object index
+    pub const ANALOG_OUTPUT_ERROR_VALUE: u16 = 0x6444;
 }
 
 /// Special values used to access standard objects
@@ -25,6 +91,9 @@ pub mod values {
     /// Magic value used to trigger object storage by writing to object 0x1010
     pub const SAVE_CMD: u32 = 0x73617665;
 
+    /// Magic value used to accept the next protected NMT command by writing to object 0x500E
+    pub const NMT_UNLOCK_CMD: u32 = 0x756E6C6B;
+
     /// Magic value used to trigger a reset to bootloader by writing to object 0x5500
     pub const BOOTLOADER_RESET_CMD: u32 = 0x544F4F42;
 
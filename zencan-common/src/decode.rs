@@ -0,0 +1,431 @@
+//! Classification and decoding of CANopen frames
+//!
+//! [`classify`] identifies the type of a received frame based on its COB-ID, using the CANopen
+//! pre-defined connection set, and decodes its payload. It is intended for tools which observe
+//! traffic on a bus -- e.g. bus monitors or loggers -- rather than for use by nodes, which only need
+//! to handle the messages addressed to them.
+//!
+//! Note that PDO COB-IDs may be re-assigned by configuration, and PDOs are classified according to
+//! the default assignments only.
+
+use crate::{
+    lss::{LssRequest, LssResponse},
+    messages::{
+        CanId, CanMessage, Heartbeat, MessageError, NmtCommand, NmtState, HEARTBEAT_ID, LSS_REQ_ID,
+        LSS_RESP_ID, NMT_CMD_ID, SDO_REQ_BASE, SDO_RESP_BASE, SYNC_ID,
+    },
+    sdo::{SdoRequest, SdoResponse},
+};
+
+/// The COB ID used for TIME messages
+pub const TIME_ID: CanId = CanId::Std(0x100);
+/// The base COB ID for EMCY messages (producer node ID is added)
+pub const EMCY_BASE: u16 = 0x80;
+
+// Function codes (the upper 4 bits of the COB-ID) of the node specific messages
+const EMCY_FUNCTION: u8 = (EMCY_BASE >> 7) as u8;
+const SDO_RESP_FUNCTION: u8 = (SDO_RESP_BASE >> 7) as u8;
+const SDO_REQ_FUNCTION: u8 = (SDO_REQ_BASE >> 7) as u8;
+const HEARTBEAT_FUNCTION: u8 = (HEARTBEAT_ID >> 7) as u8;
+
+/// Direction of a PDO, from the point of view of the node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PdoDirection {
+    /// Transmitted by the node
+    Tpdo,
+    /// Received by the node
+    Rpdo,
+}
+
+/// A decoded EMCY message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Emergency {
+    /// The node which produced the EMCY
+    pub node: u8,
+    /// The emergency error code. A value of 0 indicates an error reset.
+    pub error_code: u16,
+    /// The value of the node's error register (0x1001)
+    pub error_register: u8,
+    /// Manufacturer specific error data
+    pub vendor_data: [u8; 5],
+}
+
+impl TryFrom<CanMessage> for Emergency {
+    type Error = MessageError;
+
+    fn try_from(msg: CanMessage) -> Result<Self, Self::Error> {
+        let raw = msg.id().raw();
+        if msg.id().is_extended() || !(0x81..=0xFF).contains(&raw) {
+            return Err(MessageError::UnrecognizedId { cob_id: msg.id() });
+        }
+        let data = msg.data();
+        if data.len() < 8 {
+            return Err(MessageError::MessageTooShort);
+        }
+        Ok(Self {
+            node: (raw - EMCY_BASE as u32) as u8,
+            error_code: u16::from_le_bytes([data[0], data[1]]),
+            error_register: data[2],
+            vendor_data: data[3..8].try_into().unwrap(),
+        })
+    }
+}
+
+impl Emergency {
+    /// Returns true if this EMCY indicates that all errors have been reset
+    pub fn is_reset(&self) -> bool {
+        self.error_code == 0
+    }
+}
+
+/// The names of the bits in the error register (0x1001), starting with bit 0
+pub const ERROR_REGISTER_BITS: [&str; 8] = [
+    "generic",
+    "current",
+    "voltage",
+    "temperature",
+    "communication",
+    "device profile",
+    "reserved",
+    "manufacturer",
+];
+
+/// Get the names of the bits which are set in an error register (0x1001) value
+pub fn error_register_names(value: u8) -> impl Iterator<Item = &'static str> {
+    ERROR_REGISTER_BITS
+        .iter()
+        .enumerate()
+        .filter(move |(i, _)| value & (1 << i) != 0)
+        .map(|(_, name)| *name)
+}
+
+/// Get a description of the class of error indicated by an EMCY error code
+///
+/// Only the standard error code classes are identified, from the upper bits of the code.
+pub fn emcy_error_class(code: u16) -> &'static str {
+    match code {
+        0x0000..=0x00FF => "no error",
+        0x1000..=0x1FFF => "generic",
+        0x2000..=0x2FFF => "current",
+        0x3000..=0x3FFF => "voltage",
+        0x4000..=0x4FFF => "temperature",
+        0x5000..=0x5FFF => "device hardware",
+        0x6000..=0x6FFF => "device software",
+        0x7000..=0x7FFF => "additional modules",
+        0x8000..=0x8FFF => "monitoring",
+        0x9000..=0x9FFF => "external",
+        0xF000..=0xFEFF => "additional functions",
+        0xFF00..=0xFFFF => "device specific",
+        _ => "unknown",
+    }
+}
+
+/// A PDO frame, classified by its default COB-ID
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PdoFrame {
+    /// Whether this is a TPDO or an RPDO
+    pub direction: PdoDirection,
+    /// The PDO number, from 1 to 4
+    pub number: u8,
+    /// The node ID the PDO belongs to
+    pub node: u8,
+    /// The raw PDO message
+    pub msg: CanMessage,
+}
+
+/// A CAN frame, classified and decoded as a CANopen message
+///
+/// With the `serde` feature, frames can be serialized, e.g. to include the decoded message in a
+/// log. They cannot be deserialized; store the [`CanMessage`] instead, and [`classify`] it again
+/// when it is loaded.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CanOpenFrame {
+    /// An NMT command
+    Nmt(NmtCommand),
+    /// A SYNC message, with its optional counter value
+    Sync {
+        /// The SYNC counter, if the producer is configured to send one
+        counter: Option<u8>,
+    },
+    /// A TIME message
+    Time(CanMessage),
+    /// An EMCY message
+    Emcy(Emergency),
+    /// A PDO message
+    Pdo(PdoFrame),
+    /// An SDO request sent by a client to a server node
+    SdoRequest {
+        /// The ID of the server node
+        node: u8,
+        /// The request
+        req: SdoRequest,
+    },
+    /// An SDO response sent by a server node
+    SdoResponse {
+        /// The ID of the server node
+        node: u8,
+        /// The response
+        resp: SdoResponse,
+    },
+    /// A heartbeat message, including bootup messages
+    Heartbeat(Heartbeat),
+    /// An LSS request from the LSS master
+    LssRequest(LssRequest),
+    /// An LSS response from a slave
+    LssResponse(LssResponse),
+    /// A frame whose COB-ID was recognized, but whose payload could not be decoded
+    Malformed {
+        /// The type of frame indicated by the COB-ID
+        kind: &'static str,
+        /// The raw message
+        msg: CanMessage,
+        /// The reason the payload could not be decoded
+        reason: MessageError,
+    },
+    /// A frame which does not belong to the pre-defined connection set
+    Unknown(CanMessage),
+}
+
+impl CanOpenFrame {
+    /// Get a short, stable name for the type of frame, e.g. "heartbeat" or "sdo_request"
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CanOpenFrame::Nmt(_) => "nmt",
+            CanOpenFrame::Sync { .. } => "sync",
+            CanOpenFrame::Time(_) => "time",
+            CanOpenFrame::Emcy(_) => "emcy",
+            CanOpenFrame::Pdo(_) => "pdo",
+            CanOpenFrame::SdoRequest { .. } => "sdo_request",
+            CanOpenFrame::SdoResponse { .. } => "sdo_response",
+            CanOpenFrame::Heartbeat(_) => "heartbeat",
+            CanOpenFrame::LssRequest(_) => "lss_request",
+            CanOpenFrame::LssResponse(_) => "lss_response",
+            &CanOpenFrame::Malformed { kind, .. } => kind,
+            CanOpenFrame::Unknown(_) => "unknown",
+        }
+    }
+
+    /// Get the ID of the node the frame was sent by or addressed to, if any
+    ///
+    /// Broadcast NMT commands return `Some(0)`.
+    pub fn node(&self) -> Option<u8> {
+        match self {
+            CanOpenFrame::Nmt(cmd) => Some(cmd.node),
+            CanOpenFrame::Emcy(emcy) => Some(emcy.node),
+            CanOpenFrame::Pdo(pdo) => Some(pdo.node),
+            CanOpenFrame::SdoRequest { node, .. } => Some(*node),
+            CanOpenFrame::SdoResponse { node, .. } => Some(*node),
+            CanOpenFrame::Heartbeat(hb) => Some(hb.node),
+            CanOpenFrame::Malformed { msg, kind, .. } => node_from_cob_id(kind, msg.id()),
+            _ => None,
+        }
+    }
+}
+
+fn node_from_cob_id(kind: &str, id: CanId) -> Option<u8> {
+    match kind {
+        "nmt" | "sync" | "time" | "lss_request" | "lss_response" | "unknown" => None,
+        _ => id.node_id(),
+    }
+}
+
+/// Classify a CAN frame by its COB-ID, and decode its payload
+///
+/// This never fails: frames which are not part of the pre-defined connection set are returned as
+/// [`CanOpenFrame::Unknown`], and frames which cannot be decoded as
+/// [`CanOpenFrame::Malformed`].
+pub fn classify(msg: CanMessage) -> CanOpenFrame {
+    let id = msg.id();
+    if id.is_extended() || msg.is_rtr() {
+        return CanOpenFrame::Unknown(msg);
+    }
+    let node = id.node_id().unwrap();
+
+    let malformed = |kind, reason| CanOpenFrame::Malformed { kind, msg, reason };
+
+    if id == NMT_CMD_ID {
+        match NmtCommand::try_from(msg) {
+            Ok(cmd) => CanOpenFrame::Nmt(cmd),
+            Err(e) => malformed("nmt", e),
+        }
+    } else if id == SYNC_ID {
+        CanOpenFrame::Sync {
+            counter: msg.data().first().copied(),
+        }
+    } else if id == TIME_ID {
+        CanOpenFrame::Time(msg)
+    } else if id == LSS_REQ_ID {
+        match LssRequest::try_from(msg.data()) {
+            Ok(req) => CanOpenFrame::LssRequest(req),
+            Err(_) => malformed("lss_request", MessageError::MalformedMsg { cob_id: id }),
+        }
+    } else if id == LSS_RESP_ID {
+        match LssResponse::try_from(msg.data()) {
+            Ok(resp) => CanOpenFrame::LssResponse(resp),
+            Err(_) => malformed("lss_response", MessageError::MalformedMsg { cob_id: id }),
+        }
+    } else if node == 0 {
+        CanOpenFrame::Unknown(msg)
+    } else {
+        match id.function_code().unwrap() {
+            EMCY_FUNCTION => match Emergency::try_from(msg) {
+                Ok(emcy) => CanOpenFrame::Emcy(emcy),
+                Err(e) => malformed("emcy", e),
+            },
+            function @ 0x3..=0xA => {
+                // TPDO1 is function code 3, RPDO1 is 4, etc
+                let direction = if function % 2 == 1 {
+                    PdoDirection::Tpdo
+                } else {
+                    PdoDirection::Rpdo
+                };
+                CanOpenFrame::Pdo(PdoFrame {
+                    direction,
+                    number: (function - 1) / 2,
+                    node,
+                    msg,
+                })
+            }
+            SDO_RESP_FUNCTION => match SdoResponse::try_from(msg) {
+                Ok(resp) => CanOpenFrame::SdoResponse { node, resp },
+                Err(_) => malformed("sdo_response", MessageError::MalformedMsg { cob_id: id }),
+            },
+            SDO_REQ_FUNCTION => match SdoRequest::try_from(msg.data()) {
+                Ok(req) => CanOpenFrame::SdoRequest { node, req },
+                Err(_) => malformed("sdo_request", MessageError::MalformedMsg { cob_id: id }),
+            },
+            HEARTBEAT_FUNCTION => {
+                let Some(&byte) = msg.data().first() else {
+                    return malformed("heartbeat", MessageError::MessageTooShort);
+                };
+                match NmtState::try_from(byte & 0x7F) {
+                    Ok(state) => CanOpenFrame::Heartbeat(Heartbeat {
+                        node,
+                        toggle: byte & 0x80 != 0,
+                        state,
+                    }),
+                    Err(_) => malformed(
+                        "heartbeat",
+                        MessageError::InvalidNmtState { value: byte & 0x7F },
+                    ),
+                }
+            }
+            _ => CanOpenFrame::Unknown(msg),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_register_names() {
+        assert_eq!(0, error_register_names(0).count());
+        assert!(error_register_names(0x15).eq(["generic", "voltage", "communication"]));
+        assert_eq!(Some("manufacturer"), error_register_names(0x80).next());
+    }
+
+    #[test]
+    fn test_emcy_error_class() {
+        assert_eq!("no error", emcy_error_class(0));
+        assert_eq!("voltage", emcy_error_class(0x3110));
+        assert_eq!("monitoring", emcy_error_class(0x8110));
+        assert_eq!("device specific", emcy_error_class(0xFF01));
+        assert_eq!("unknown", emcy_error_class(0xA000));
+    }
+
+    fn std(id: u16, data: &[u8]) -> CanMessage {
+        CanMessage::new(CanId::Std(id), data)
+    }
+
+    #[test]
+    fn test_classify_nodes_and_kinds() {
+        let cases: &[(CanMessage, &str, Option<u8>)] = &[
+            (std(0, &[1, 0]), "nmt", Some(0)),
+            (std(0, &[1]), "nmt", None),
+            (std(0x80, &[]), "sync", None),
+            (std(0x100, &[0; 6]), "time", None),
+            (std(0x85, &[0; 8]), "emcy", Some(5)),
+            (std(0x85, &[0; 3]), "emcy", Some(5)),
+            (std(0x1A0, &[1, 2]), "pdo", Some(0x20)),
+            (std(0x57F, &[]), "pdo", Some(0x7F)),
+            (std(0x701, &[0x7F]), "heartbeat", Some(1)),
+            (std(0x701, &[0x33]), "heartbeat", Some(1)),
+            (std(0x77F, &[]), "heartbeat", Some(0x7F)),
+            (
+                std(0x7E5, &[0x04, 1, 0, 0, 0, 0, 0, 0]),
+                "lss_request",
+                None,
+            ),
+            (std(0x700, &[0]), "unknown", None),
+            (std(0x180, &[0]), "unknown", None),
+            (std(0x7E0, &[0]), "unknown", None),
+            (
+                CanMessage::new(CanId::Extended(0x701), &[0]),
+                "unknown",
+                None,
+            ),
+        ];
+        for (msg, kind, node) in cases {
+            let frame = classify(*msg);
+            assert_eq!(*kind, frame.kind(), "{msg:?}");
+            assert_eq!(*node, frame.node(), "{msg:?}");
+        }
+    }
+
+    #[test]
+    fn test_classify_pdo() {
+        let expected = [
+            (0x180, PdoDirection::Tpdo, 0),
+            (0x200, PdoDirection::Rpdo, 0),
+            (0x280, PdoDirection::Tpdo, 1),
+            (0x300, PdoDirection::Rpdo, 1),
+            (0x380, PdoDirection::Tpdo, 2),
+            (0x400, PdoDirection::Rpdo, 2),
+            (0x480, PdoDirection::Tpdo, 3),
+            (0x500, PdoDirection::Rpdo, 3),
+        ];
+        for (base, direction, number) in expected {
+            let CanOpenFrame::Pdo(pdo) = classify(std(base + 3, &[])) else {
+                panic!("Expected PDO for 0x{base:x}");
+            };
+            assert_eq!(direction, pdo.direction);
+            assert_eq!(number + 1, pdo.number);
+            assert_eq!(3, pdo.node);
+        }
+    }
+
+    #[test]
+    fn test_classify_emcy_and_heartbeat() {
+        let frame = classify(std(0x8A, &[0x10, 0x81, 0x11, 1, 2, 3, 4, 5]));
+        let CanOpenFrame::Emcy(emcy) = frame else {
+            panic!("Expected EMCY, got {frame:?}");
+        };
+        assert_eq!(10, emcy.node);
+        assert_eq!(0x8110, emcy.error_code);
+        assert_eq!(0x11, emcy.error_register);
+        assert_eq!([1, 2, 3, 4, 5], emcy.vendor_data);
+
+        let frame = classify(std(0x70A, &[0x85]));
+        let CanOpenFrame::Heartbeat(hb) = frame else {
+            panic!("Expected heartbeat, got {frame:?}");
+        };
+        assert_eq!(10, hb.node);
+        assert!(hb.toggle);
+        assert_eq!(NmtState::Operational, hb.state);
+
+        let CanOpenFrame::Sync { counter } = classify(std(0x80, &[7])) else {
+            panic!("Expected SYNC");
+        };
+        assert_eq!(Some(7), counter);
+    }
+}
@@ -7,10 +7,19 @@
 //! # An example TOML file
 //!
 //! ```toml
+//! config_version = 1
 //! device_name = "can-io"
 //! software_version = "v0.0.1"
+//! # Optionally reserve room for a longer software version, set by the application at startup
+//! software_version_size = 32
 //! hardware_version = "rev1"
 //! heartbeat_period = 1000
+//! # Allow monitoring the heartbeats of up to 2 other nodes
+//! heartbeat_consumers = 2
+//! # Record the 8 most recent EMCY errors in object 0x1003
+//! error_history = 8
+//! # Allow field service to check the transceiver and bus wiring
+//! loopback_test = true
 //!
 //! # Define 3 out of 4 device unique identifiers. These define the application/device, the fourth is
 //! # the serial number, which must be provided at run-time by the application.
@@ -23,6 +32,50 @@
 //! [pdos]
 //! num_rpdo = 4
 //! num_tpdo = 4
+//! # Optionally embed the SYNC counter, or a timestamp, in the last byte(s) of TPDOs
+//! tpdo_stamps = ["sync_counter", "none", "timestamp"]
+//! # Optionally count the PDOs sent and received, for debugging mappings over SDO
+//! statistics = true
+//!
+//! # Optionally set how many received messages of each class can be buffered between calls to
+//! # `Node::process`
+//! [mbox]
+//! rpdo_queue_depth = 8
+//! sdo_queue_depth = 1
+//! nmt_queue_depth = 2
+//! # Allocate a second SDO buffer, to speed up large downloads to slow objects
+//! sdo_double_buffer = false
+//! # Optionally queue transmitted messages, for use with `Node::process_queued`
+//! tx_queue_depth = 0
+//!
+//! # Optionally spread out the boot-up of many devices which power on together, and their
+//! # responses to bus scans
+//! [nmt]
+//! boot_delay_max_ms = 50
+//! min_heartbeat_interval_ms = 20
+//! scan_response_jitter_max_ms = 5
+//!
+//! # Optionally ignore NMT stop and reset commands from the bus while the device is operating,
+//! # unless they are unlocked first
+//! [nmt_protection]
+//! commands = ["stop", "reset_app", "reset_comm"]
+//! allowed_states = ["pre_operational"]
+//! unlock = true
+//!
+//! # Optionally record the most recent object accesses, for diagnosing field problems
+//! [access_trace]
+//! depth = 32
+//! pdos = false
+//!
+//! # Optionally provide a text log, for devices without a debug UART
+//! [debug_log]
+//! size = 512
+//! rate_limit = 10
+//!
+//! # Optionally keep an audit log of application events, which survives a reset when persisted
+//! [event_log]
+//! depth = 32
+//! persist = true
 //!
 //! # User's can create custom objects to hold application specific data
 //! [[objects]]
@@ -36,6 +89,160 @@
 //! pdo_mapping = "tpdo"
 //! ```
 //!
+//! # Schema Versions
+//!
+//! The `config_version` at the top of the file gives the version of the config file schema it was
+//! written for. When a change to zencan would otherwise give an existing file a different meaning,
+//! e.g. a new default for an option, the schema version is increased, and files written for older
+//! versions are migrated when they are loaded, so that they keep generating the same node. Files
+//! written for a newer version than [CONFIG_VERSION] are rejected with
+//! [LoadError::UnsupportedConfigVersion], rather than having options which this version does not
+//! understand quietly ignored.
+//!
+//! Files without a `config_version` were written before the schema was versioned, and are
+//! treated as version 0. [migrate_config] can be used by tools to upgrade a file to the current
+//! version.
+//!
+//! | Version | Changes |
+//! | ------- | ------- |
+//! | 0       | Files without a `config_version` |
+//! | 1       | Added `config_version`. No other changes. |
+//!
+//! # Scaled Objects
+//!
+//! Sensor devices often need to report a value converted from raw units using a calibration. A
+//! `scaled` object generates a record holding the raw value, along with a gain and offset which
+//! are persisted by default, and a read-only scaled value computed as `raw * gain + offset`. See
+//! [ScaledDefinition] for the sub object layout.
+//!
+//! ```toml
+//! [[objects]]
+//! index = 0x2100
+//! parameter_name = "Pressure"
+//! object_type = "scaled"
+//! data_type = "int16"
+//! access_type = "ro"
+//! gain = 0.01
+//! offset = -5.0
+//! pdo_mapping = "tpdo"
+//! ```
+//!
+//! # Units
+//!
+//! Numeric var, array and record sub objects can be annotated with the `unit` of their value, and
+//! a `scale` which converts the raw value to that unit. The value in `unit` is `raw * scale`. This
+//! does not change the value stored by the node. It is included in the docs of the generated
+//! client bindings, and used by tools to display values, e.g. a raw value of 3321 is shown as
+//! "3.321 V" by zencan-cli when the device config is attached.
+//!
+//! ```toml
+//! [[objects]]
+//! index = 0x2300
+//! parameter_name = "Supply Voltage"
+//! object_type = "var"
+//! data_type = "uint16"
+//! access_type = "ro"
+//! unit = "V"
+//! scale = 0.001
+//! ```
+//!
+//! # Record Sub Indices
+//!
+//! The subs of a record do not need to be contiguous. Sub indices which are not defined have no
+//! sub object, and accessing them returns a `NoSuchSubIndex` error. Sub indices which are reserved,
+//! e.g. for future use, can be listed in `reserved_subs`. These also have no sub object, but are
+//! included in the highest sub index reported by sub 0.
+//!
+//! ```toml
+//! [[objects]]
+//! index = 0x2200
+//! parameter_name = "Motor Status"
+//! object_type = "record"
+//! reserved_subs = [2, 4]
+//! [[objects.subs]]
+//! sub_index = 1
+//! data_type = "uint16"
+//! access_type = "ro"
+//! [[objects.subs]]
+//! sub_index = 3
+//! data_type = "int32"
+//! access_type = "ro"
+//! ```
+//!
+//! # Linker Sections
+//!
+//! By default, object storage is placed in the usual data sections by the linker. Setting
+//! `link_section` at the top level of the file places every generated object in the named
+//! section, and setting it on an object overrides it for that object. This allows, for example,
+//! hot PDO mapped data to be placed in fast RAM such as `.ccmram`, or objects to be placed in
+//! battery-backed SRAM so that their values survive a reset.
+//!
+//! ```toml
+//! device_name = "can-io"
+//! link_section = ".ccmram"
+//!
+//! [[objects]]
+//! index = 0x2200
+//! parameter_name = "Run Hours"
+//! link_section = ".backup_sram"
+//! object_type = "var"
+//! data_type = "uint32"
+//! access_type = "rw"
+//! ```
+//!
+//! The section must be defined in the application's linker script, and the startup code is
+//! responsible for initializing it. Objects in a section which is not initialized, such as a
+//! `NOLOAD` section used to retain values across reset, do not start with their default values.
+//!
+//! # Atomic Storage
+//!
+//! By default, scalar object values are stored in cells which are accessed inside a critical
+//! section. On multicore or interrupt heavy targets, setting `atomic_storage = true` at the top
+//! level of the file, or on individual objects, stores boolean, integer and real32 values in
+//! `core::sync::atomic` types instead, so that reading and writing them never disables
+//! interrupts. The target must support atomic loads and stores of the value sizes. String and
+//! domain values are not affected.
+//!
+//! # Write Hooks
+//!
+//! Setting `on_write_callback = true` on an object adds a `register_write_hook` method to its
+//! generated struct. The registered function is called with the sub index after each successful
+//! write to the object, e.g. by an SDO download or a received PDO, so the application can react to
+//! changes without implementing the object as an `application_callback` object.
+//!
+//! ```toml
+//! [[objects]]
+//! index = 0x2300
+//! parameter_name = "Motor Setpoint"
+//! on_write_callback = true
+//! object_type = "var"
+//! data_type = "int32"
+//! access_type = "rw"
+//! ```
+//!
+//! # Var Pool
+//!
+//! String objects normally reserve a buffer of their maximum size. When many strings are rarely
+//! filled, they can instead share one pool, sized with `var_pool` in the `[memory]` table. Each var
+//! object with `pooled = true` then only uses the length of its current value in the pool, and the
+//! size of its data type is the maximum length. A write which does not fit in the remaining space
+//! is aborted with `OutOfMemory`. The use of the pool, and its high-water mark, are reported in
+//! object 0x5006.
+//!
+//! ```toml
+//! [memory]
+//! var_pool = 2048
+//!
+//! [[objects]]
+//! index = 0x2400
+//! parameter_name = "Location Label"
+//! pooled = true
+//! object_type = "var"
+//! data_type = "visiblestring(64)"
+//! access_type = "rw"
+//! persist = true
+//! ```
+//!
 //! # Object Namespaces
 //!
 //! Application specific objects should be defined in the range 0x2000-0x4fff. Many objects will be
@@ -43,6 +250,25 @@
 //!
 //! # Standard Objects
 //!
+//! ## 0x1003 - Pre-defined Error Field
+//!
+//! An array object of type U32, implemented by the node, which holds the most recent EMCY errors
+//! sent by the node. It is only created when [DeviceConfig::error_history] is non-zero.
+//!
+//! Array size: [DeviceConfig::error_history] Data type: u32
+//!
+//! Sub 0 holds the number of recorded errors, and sub 1 the most recent error. Each entry holds the
+//! EMCY error code in bits 0-15, and the first two bytes of vendor data in bits 16-31. Writing 0 to
+//! sub 0 clears the history.
+//!
+//! ## 0x1007 - Synchronous Window Length
+//!
+//! A VAR object of type U32.
+//!
+//! The length of the window after a SYNC message in which synchronous TPDOs may be sent, in
+//! microseconds. Synchronous TPDOs which are not sent within the window are skipped for that SYNC
+//! cycle. A value of 0 disables the window. It defaults to 0, and can be written at run-time.
+//!
 //! ## 0x1008 - Device Name
 //!
 //! A VAR object containing a string with a human readable device name. This value is set by
@@ -58,6 +284,11 @@
 //! A VAR object containing a string with a human readable software version. This value is set by
 //! [DeviceConfig::software_version]
 //!
+//! By default, the version is stored as constant data. If [DeviceConfig::software_version_size] is
+//! set, it is instead stored in RAM with room for that many bytes, so that the application can set
+//! the version at startup, e.g. from a version string stored by its build, using the generated
+//! `set_software_version` function. It remains read-only over SDO.
+//!
 //! ## 0x1010 - Object Save Command
 //!
 //! An array object used to command the node to store its current object values.
@@ -69,12 +300,37 @@
 //!
 //! To trigger a save, write a u32 with the [magic value](crate::constants::values::SAVE_CMD).
 //!
+//! ## 0x1014 - COB-ID EMCY
+//!
+//! A VAR object of type U32, implemented by the node.
+//!
+//! The COB-ID on which EMCY messages are sent. Bit 31 is set to disable EMCY messages, and bit 29
+//! is set for an extended ID. Default: 0x80 + node ID, which follows the node ID until written.
+//!
+//! ## 0x1015 - Inhibit Time EMCY
+//!
+//! A VAR object of type U16.
+//!
+//! The minimum time between EMCY messages sent by the node, in multiples of 100us. Default: 0, which
+//! disables the inhibit time.
+//!
+//! ## 0x1016 - Consumer Heartbeat Time
+//!
+//! An array object which configures the heartbeats the node expects to receive from other nodes.
+//! It is only created when [DeviceConfig::heartbeat_consumers] is non-zero.
+//!
+//! Array size: [DeviceConfig::heartbeat_consumers] Data type: u32
+//!
+//! Each entry holds the node ID of the monitored node in bits 16-23, and the heartbeat timeout in
+//! milliseconds in bits 0-15. An entry with a node ID or timeout of 0 is unused.
+//!
 //! ## 0x1017 - Heartbeat Producer Time
 //!
 //! A VAR object of type U16.
 //!
-//! This object stores the period at which the heartbeat is sent by the device, in milliseconds. It
-//! is set by [DeviceConfig::heartbeat_period].
+//! This object stores the period at which the heartbeat is sent by the device, in milliseconds. Its
+//! default is set by [DeviceConfig::heartbeat_period], and it may be changed at run time, e.g. by
+//! a configuration tool. A value of 0 disables the heartbeat.
 //!
 //! ## 0x1018 - Identity
 //!
@@ -88,6 +344,37 @@
 //! | 3          | u32  | Revision |
 //! | 4          | u32  | Serial |
 //!
+//! ## 0x1020 - Verify Configuration
+//!
+//! An array object which records when the node was last configured. It is only created when
+//! [DeviceConfig::verify_configuration] is true.
+//!
+//! | Sub Object | Type | Description |
+//! | ---------- | ---- | ----------- |
+//! | 0          | u8   | Max sub index - always 2 |
+//! | 1          | u32  | Configuration date, in days since January 1, 1984 |
+//! | 2          | u32  | Configuration time, in milliseconds after midnight |
+//!
+//! The node does not interpret the values. A configuration tool writes them after configuring the
+//! node, and compares them on a later boot to decide whether the node needs to be configured
+//! again. Both entries are persisted with the other objects when a save is commanded, and default
+//! to 0, meaning that the node is not configured.
+//!
+//! ## 0x1200 - SDO Server Parameter
+//!
+//! A record object, implemented by the node, which configures the COB-IDs used by the SDO server.
+//! Changes take effect immediately, although the response to the write which changes them is sent
+//! on the old COB-ID.
+//!
+//! | Sub Object | Type | Description |
+//! | ---------- | ---- | ----------- |
+//! | 0          | u8   | Max sub index - always 2 |
+//! | 1          | u32  | COB-ID for requests (client to server). Default: 0x600 + node ID |
+//! | 2          | u32  | COB-ID for responses (server to client). Default: 0x580 + node ID |
+//!
+//! As for 0x1014, bit 31 disables the SDO server and bit 29 selects an extended ID. A COB-ID which
+//! has not been written follows the node ID.
+//!
 //! ## 0x1400 to 0x1400 + N - RPDO Communications Parameter
 //!
 //! One object for each RPDO supported by the node. This configures how the PDO is received.
@@ -114,15 +401,169 @@
 //!
 //! # Zencan Extensions
 //!
+//! ## TPDO Stamps
+//!
+//! The `tpdo_stamps` list in the `[pdos]` section sets a [`TpdoStamp`] for each TPDO, starting
+//! with TPDO 0. A stamped TPDO carries the SYNC counter in its last byte, or a millisecond
+//! timestamp in its last two bytes, so that a receiver can detect dropped or stale messages. TPDOs
+//! beyond the end of the list are not stamped. See [`pdo_stamp`](crate::pdo_stamp) for the format.
+//!
 //! ## 0x5000 - Auto Start
 //!
 //! Setting this to a non-zero value causes the node to immediately move into the Operational state
 //! after power-on, without receiving an NMT command to do so. Note that, if the device is later put
 //! into PreOperational via an NMT command, it will not auto-transition to Operational.
 //!
+//! ## 0x5001 - Node Statistics
+//!
+//! A record object of operating statistics maintained by the node, for fleet maintenance. It is
+//! only created when [DeviceConfig::statistics] is set. All sub objects are persisted, so that they
+//! accumulate across power cycles when objects are saved, and may be written to reset them.
+//!
+//! | Sub Object | Type | Description |
+//! | ---------- | ---- | ----------- |
+//! | 0          | u8   | Max sub index - always 4 |
+//! | 1          | u32  | Number of times the node has been started |
+//! | 2          | u32  | Total operating time, in minutes |
+//! | 3          | u32  | Number of bus-off events reported by the application |
+//! | 4          | u32  | Number of EMCY messages sent |
+//!
+//! ## 0x5002 - NMT Startup Timing
+//!
+//! A record object controlling when the node announces itself on the bus. It is only created when
+//! one of the [NmtConfig] times is non-zero, and its defaults are taken from the config. The sub
+//! objects are persisted, so they may be tuned per device on a bus.
+//!
+//! | Sub Object | Type | Description |
+//! | ---------- | ---- | ----------- |
+//! | 0          | u8   | Max sub index - always 3 |
+//! | 1          | u16  | Maximum random delay before sending the boot-up message, in ms |
+//! | 2          | u16  | Minimum time between heartbeat messages, in ms |
+//! | 3          | u16  | Maximum random delay before responding to an SDO read of a scanned object, in ms |
+//!
+//! ## 0x5003 - Access Trace
+//!
+//! A record object holding a ring buffer of the most recent SDO and PDO accesses to the node's
+//! objects, so that intermittent problems can be diagnosed after the fact. It is only created when
+//! [AccessTraceConfig::depth] is non-zero. See [crate::access_trace] for the format of the entries.
+//!
+//! | Sub Object | Type   | Description |
+//! | ---------- | ------ | ----------- |
+//! | 0          | u8     | Max sub index - always 3 |
+//! | 1          | u32    | Number of accesses recorded since the trace was cleared. Write 0 to clear |
+//! | 2          | Domain | The recorded entries, oldest first |
+//! | 3          | u8     | Set to non-zero to record PDO accesses |
+//!
+//! ## 0x5004 - Debug Log
+//!
+//! A record object holding a ring buffer of text messages logged by the application, which can be
+//! read via SDO, e.g. with `log tail` in zencan-cli. It is only created when [DebugLogConfig::size]
+//! is non-zero.
+//!
+//! | Sub Object | Type   | Description |
+//! | ---------- | ------ | ----------- |
+//! | 0          | u8     | Max sub index - always 3 |
+//! | 1          | u32    | Number of bytes logged since the log was cleared. Write 0 to clear |
+//! | 2          | Domain | The value of sub 1 as a u32, followed by the buffered text, oldest first |
+//! | 3          | u32    | Number of messages discarded by the rate limit |
+//!
+//! ## 0x5005 - SDO Server Status
+//!
+//! A record object reporting the transfer in progress on the node's SDO server, for diagnosing
+//! transfers which stall. It is only created when [DeviceConfig::sdo_status] is set. The sub
+//! objects may be mapped to TPDOs.
+//!
+//! | Sub Object | Type | Description |
+//! | ---------- | ---- | ----------- |
+//! | 0          | u8   | Max sub index - always 4 |
+//! | 1          | u8   | The [`SdoTransferState`](crate::sdo::SdoTransferState): 0 idle, 1 segmented download, 2 segmented upload, 3 block download |
+//! | 2          | u16  | The index of the object being transferred |
+//! | 3          | u8   | The sub index of the object being transferred |
+//! | 4          | u32  | The number of bytes transferred so far |
+//!
+//! ## 0x5006 - Var Pool Status
+//!
+//! A record object reporting the use of the var pool. It is only created when
+//! [MemoryConfig::var_pool] is non-zero.
+//!
+//! | Sub Object | Type | Description |
+//! | ---------- | ---- | ----------- |
+//! | 0          | u8   | Max sub index - always 3 |
+//! | 1          | u32  | The size of the pool, in bytes |
+//! | 2          | u32  | The number of bytes used by the current values |
+//! | 3          | u32  | The most bytes used since boot |
+//!
+//! ## 0x5007 - Secondary Heartbeat
+//!
+//! A VAR object of type U32, implemented by the node, which sends each heartbeat and boot-up
+//! message a second time on another COB-ID, e.g. for a device connected to two redundant CAN
+//! networks. It is only created when [NmtConfig::secondary_heartbeat_base] is set, which gives its
+//! default.
+//!
+//! Bits 0-10 hold the base COB-ID, and the secondary heartbeat is sent on the base + node ID. Bit
+//! 31 is set to disable the secondary heartbeat. The value is persisted.
+//!
+//! ## 0x5008 - Event Log
+//!
+//! A record object holding a ring buffer of application events, each with a sequence number, so
+//! that a client can fetch the events which are new since its last fetch. It is only created when
+//! [EventLogConfig::depth] is non-zero. See [`event_log`](crate::event_log) for the format of the
+//! entries.
+//!
+//! | Sub Object | Type   | Description |
+//! | ---------- | ------ | ----------- |
+//! | 0          | u8     | Max sub index - always 4 |
+//! | 1          | u32    | The sequence number of the next event. Write 0 to clear |
+//! | 2          | u32    | The sequence number of the first event read from sub 3 |
+//! | 3          | Domain | The held events with a sequence number of at least sub 2, oldest first |
+//! | 4          | Domain | All held events, oldest first. Persisted when [EventLogConfig::persist] is set |
+//!
+//! ## 0x5009 - Loopback Self-Test
+//!
+//! A record object, implemented by the node, which sends a test frame when sub 1 is written with 1,
+//! and checks that it is received back. It is only created when [DeviceConfig::loopback_test] is
+//! set. See [`loopback_test`](crate::loopback_test) for the test frame.
+//!
+//! | Sub Object | Type | Description |
+//! | ---------- | ---- | ----------- |
+//! | 0          | u8   | Max sub index - always 2 |
+//! | 1          | u8   | Write 1 to start a test. Reads 1 while a test is running |
+//! | 2          | u8   | The [`LoopbackStatus`](crate::loopback_test::LoopbackStatus) of the last test |
+//!
+//! ## 0x500A-0x500D - PDO Statistics
+//!
+//! Read-only arrays of u32, implemented by the node, with an element for each PDO, starting with
+//! PDO 0 in sub 1. They are only created when [PdoConfig::statistics] is set, and the TPDO or RPDO
+//! arrays are omitted if the device has no PDOs of that direction. Times are in milliseconds, from
+//! the clock passed to `Node::process`.
+//!
+//! | Index  | Description |
+//! | ------ | ----------- |
+//! | 0x500A | The number of times each TPDO has been sent |
+//! | 0x500B | The time each TPDO was last sent |
+//! | 0x500C | The number of times each RPDO has been received |
+//! | 0x500D | The time each RPDO was last received |
+//!
+//! ## 0x500E - NMT Unlock
+//!
+//! A VAR object of type U32, implemented by the node, which is only created when
+//! [NmtProtectionConfig::unlock] is set and at least one command is protected. Writing
+//! [NMT_UNLOCK_CMD](crate::constants::values::NMT_UNLOCK_CMD) accepts the next protected NMT
+//! command received within 10 seconds, and writing 0 cancels an unlock. Reading returns 1 while an
+//! unlock is pending.
+//!
+//! ## 0x5F10 - Settings Backup
+//!
+//! A domain object holding all of the persisted sub objects, in the compact format used for object
+//! storage, so that the settings of a device can be backed up and restored with a single transfer.
+//! It is only created when [DeviceConfig::settings_backup] is set. Reading it returns the current
+//! values, and writing data previously read from it restores them. A restore is not saved until it
+//! is requested via object 0x1010.
+//!
 use std::collections::HashMap;
 
 use crate::objects::{AccessType, ObjectCode};
+use crate::pdo_stamp::TpdoStamp;
 use serde::{de::Error, Deserialize};
 
 use snafu::ResultExt as _;
@@ -149,6 +590,12 @@ pub enum LoadError {
         /// index which was defined multiple times
         id: u16,
     },
+    /// A receive queue was configured with zero depth
+    #[snafu(display("Queue depth for {queue} must be at least 1"))]
+    InvalidQueueDepth {
+        /// Name of the invalid queue setting
+        queue: &'static str,
+    },
     /// Duplicate sub objects defined on a record
     #[snafu(display("Multiple definitions of sub index {sub} on object 0x{index:x}"))]
     DuplicateSubObjects {
@@ -157,6 +604,150 @@ pub enum LoadError {
         /// Duplicated sub index
         sub: u8,
     },
+    /// A scaled object was defined with a raw data type which cannot be scaled
+    #[snafu(display("Scaled object 0x{index:x} must have a numeric data type"))]
+    InvalidScaledType {
+        /// Index of the scaled object
+        index: u16,
+    },
+    /// A unit or scale was given for a value which cannot be scaled
+    #[snafu(display(
+        "Object 0x{index:x} has a unit or scale, but is not numeric, or the scale is zero or not finite"
+    ))]
+    InvalidUnits {
+        /// Index of the object
+        index: u16,
+    },
+    /// Sub index 0 of a record was listed as reserved
+    #[snafu(display("Sub index 0 of object 0x{index:x} cannot be reserved"))]
+    ReservedSubZero {
+        /// Index of the record object
+        index: u16,
+    },
+    /// A linker section name is not valid
+    #[snafu(display("Invalid linker section name {name:?}"))]
+    InvalidLinkSection {
+        /// The invalid section name
+        name: String,
+    },
+    /// An application callback object also requested a write hook
+    #[snafu(display(
+        "Object 0x{index:x} cannot set both application_callback and on_write_callback"
+    ))]
+    WriteHookOnCallbackObject {
+        /// Index of the object
+        index: u16,
+    },
+    /// An object which cannot be stored in the var pool set `pooled`
+    #[snafu(display(
+        "Pooled object 0x{index:x} must be a var with a string data type, with no PDO mapping or callbacks"
+    ))]
+    InvalidPooledObject {
+        /// Index of the object
+        index: u16,
+    },
+    /// An object set `pooled`, but no var pool is configured
+    #[snafu(display("Object 0x{index:x} is pooled, but the var pool size is not set"))]
+    NoVarPool {
+        /// Index of the object
+        index: u16,
+    },
+    /// The var pool cannot hold the default values of the pooled objects
+    #[snafu(display(
+        "The var pool is {var_pool} bytes, but the default values of pooled objects need {required} bytes"
+    ))]
+    VarPoolTooSmall {
+        /// The configured pool size
+        var_pool: usize,
+        /// The total length of the default values of pooled objects
+        required: usize,
+    },
+    /// More TPDO stamps were listed than the number of TPDOs
+    #[snafu(display("{stamps} TPDO stamps were listed, but the device has {num_tpdo} TPDOs"))]
+    TooManyTpdoStamps {
+        /// The number of stamps listed
+        stamps: usize,
+        /// The number of TPDOs
+        num_tpdo: u8,
+    },
+    /// The secondary heartbeat base leaves no room for all node IDs in the 11-bit ID range
+    #[snafu(display(
+        "Secondary heartbeat base 0x{base:x} + node ID 127 is not a valid 11-bit COB-ID"
+    ))]
+    InvalidSecondaryHeartbeatBase {
+        /// The configured base
+        base: u16,
+    },
+    /// The config_version is not a non-negative integer
+    #[snafu(display("config_version must be a non-negative integer"))]
+    InvalidConfigVersion,
+    /// The config file was written for a newer schema version than is supported
+    #[snafu(display(
+        "The config file is for schema version {version}, but this version of zencan supports up to version {CONFIG_VERSION}"
+    ))]
+    UnsupportedConfigVersion {
+        /// The version given in the file
+        version: u32,
+    },
+    /// The room reserved for the software version cannot hold the default version
+    #[snafu(display(
+        "software_version_size is {size}, but must be at least 1 and at least the length of software_version ({len})"
+    ))]
+    InvalidSoftwareVersionSize {
+        /// The configured size
+        size: usize,
+        /// The length of the default software version
+        len: usize,
+    },
+}
+
+/// The version of the config file schema supported by this version of zencan
+///
+/// See [Schema Versions](crate::device_config#schema-versions).
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Migrations of a config file from each schema version to the next
+///
+/// `MIGRATIONS[n]` migrates a file from version `n` to version `n + 1`.
+const MIGRATIONS: [fn(&mut toml::Table); CONFIG_VERSION as usize] = [migrate_v0_to_v1];
+
+/// Version 1 only added `config_version`
+fn migrate_v0_to_v1(_config: &mut toml::Table) {}
+
+/// Get the schema version of a parsed config file, and check that it is supported
+fn checked_config_version(config: &toml::Table) -> Result<u32, LoadError> {
+    let version = match config.get("config_version") {
+        None => 0,
+        Some(toml::Value::Integer(v)) => {
+            u32::try_from(*v).map_err(|_| InvalidConfigVersionSnafu.build())?
+        }
+        Some(_) => return InvalidConfigVersionSnafu.fail(),
+    };
+    if version > CONFIG_VERSION {
+        return UnsupportedConfigVersionSnafu { version }.fail();
+    }
+    Ok(version)
+}
+
+/// Apply the migrations from `version` to the current schema version
+fn apply_migrations(config: &mut toml::Table, version: u32) {
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(config);
+    }
+}
+
+/// Migrate a parsed config file to the current schema version
+///
+/// The migrations for each version since the one the file was written for are applied, and its
+/// `config_version` is set to [CONFIG_VERSION]. Returns the version the file was written for.
+pub fn migrate_config(config: &mut toml::Table) -> Result<u32, LoadError> {
+    let version = checked_config_version(config)?;
+    apply_migrations(config, version);
+    config.insert(
+        "config_version".to_string(),
+        toml::Value::Integer(CONFIG_VERSION as i64),
+    );
+    Ok(version)
 }
 
 fn mandatory_objects(config: &DeviceConfig) -> Vec<ObjectDefinition> {
@@ -165,6 +756,10 @@ fn mandatory_objects(config: &DeviceConfig) -> Vec<ObjectDefinition> {
             index: 0x1000,
             parameter_name: "Device Type".to_string(),
             application_callback: false,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
             object: Object::Var(VarDefinition {
                 data_type: DataType::UInt32,
                 access_type: AccessType::Const.into(),
@@ -177,6 +772,10 @@ fn mandatory_objects(config: &DeviceConfig) -> Vec<ObjectDefinition> {
             index: 0x1001,
             parameter_name: "Error Register".to_string(),
             application_callback: false,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
             object: Object::Var(VarDefinition {
                 data_type: DataType::UInt8,
                 access_type: AccessType::Ro.into(),
@@ -185,10 +784,32 @@ fn mandatory_objects(config: &DeviceConfig) -> Vec<ObjectDefinition> {
                 ..Default::default()
             }),
         },
+        ObjectDefinition {
+            index: 0x1007,
+            parameter_name: "Synchronous Window Length (us)".to_string(),
+            application_callback: false,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
+            object: Object::Var(VarDefinition {
+                data_type: DataType::UInt32,
+                access_type: AccessType::Rw.into(),
+                default_value: Some(DefaultValue::Integer(0)),
+                pdo_mapping: PdoMapping::None,
+                persist: true,
+                unit: None,
+                scale: None,
+            }),
+        },
         ObjectDefinition {
             index: 0x1008,
             parameter_name: "Manufacturer Device Name".to_string(),
             application_callback: false,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
             object: Object::Var(VarDefinition {
                 data_type: DataType::VisibleString(config.device_name.len()),
                 access_type: AccessType::Const.into(),
@@ -201,6 +822,10 @@ fn mandatory_objects(config: &DeviceConfig) -> Vec<ObjectDefinition> {
             index: 0x1009,
             parameter_name: "Manufacturer Hardware Version".to_string(),
             application_callback: false,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
             object: Object::Var(VarDefinition {
                 data_type: DataType::VisibleString(config.hardware_version.len()),
                 access_type: AccessType::Const.into(),
@@ -213,30 +838,70 @@ fn mandatory_objects(config: &DeviceConfig) -> Vec<ObjectDefinition> {
             index: 0x100A,
             parameter_name: "Manufacturer Software Version".to_string(),
             application_callback: false,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
             object: Object::Var(VarDefinition {
-                data_type: DataType::VisibleString(config.software_version.len()),
-                access_type: AccessType::Const.into(),
+                data_type: DataType::VisibleString(
+                    config
+                        .software_version_size
+                        .unwrap_or(config.software_version.len()),
+                ),
+                access_type: if config.software_version_size.is_some() {
+                    AccessType::Ro.into()
+                } else {
+                    AccessType::Const.into()
+                },
                 default_value: Some(DefaultValue::String(config.software_version.clone())),
                 pdo_mapping: PdoMapping::None,
                 ..Default::default()
             }),
         },
+        ObjectDefinition {
+            index: 0x1015,
+            parameter_name: "Inhibit Time EMCY".to_string(),
+            application_callback: false,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
+            object: Object::Var(VarDefinition {
+                data_type: DataType::UInt16,
+                access_type: AccessType::Rw.into(),
+                default_value: Some(DefaultValue::Integer(0)),
+                pdo_mapping: PdoMapping::None,
+                persist: true,
+                unit: None,
+                scale: None,
+            }),
+        },
         ObjectDefinition {
             index: 0x1017,
             parameter_name: "Heartbeat Producer Time (ms)".to_string(),
             application_callback: false,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
             object: Object::Var(VarDefinition {
                 data_type: DataType::UInt16,
-                access_type: AccessType::Const.into(),
+                access_type: AccessType::Rw.into(),
                 default_value: Some(DefaultValue::Integer(config.heartbeat_period as i64)),
                 pdo_mapping: PdoMapping::None,
-                persist: false,
+                persist: true,
+                unit: None,
+                scale: None,
             }),
         },
         ObjectDefinition {
             index: 0x1018,
             parameter_name: "Identity".to_string(),
             application_callback: false,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
             object: Object::Record(RecordDefinition {
                 subs: vec![
                     SubDefinition {
@@ -286,18 +951,25 @@ fn mandatory_objects(config: &DeviceConfig) -> Vec<ObjectDefinition> {
                         ..Default::default()
                     },
                 ],
+                reserved_subs: Vec::new(),
             }),
         },
         ObjectDefinition {
             index: 0x5000,
             parameter_name: "Auto Start".to_string(),
             application_callback: false,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
             object: Object::Var(VarDefinition {
                 data_type: DataType::UInt8,
                 access_type: AccessType::Rw.into(),
                 default_value: None,
                 pdo_mapping: PdoMapping::None,
                 persist: true,
+                unit: None,
+                scale: None,
             }),
         },
     ]
@@ -315,6 +987,10 @@ fn pdo_objects(num_rpdo: usize, num_tpdo: usize) -> Vec<ObjectDefinition> {
             index: comm_index + i as u16,
             parameter_name: format!("{}{} Communication Parameter", pdo_type, i),
             application_callback: true,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
             object: Object::Record(RecordDefinition {
                 subs: vec![
                     SubDefinition {
@@ -326,6 +1002,8 @@ fn pdo_objects(num_rpdo: usize, num_tpdo: usize) -> Vec<ObjectDefinition> {
                         default_value: None,
                         pdo_mapping: PdoMapping::None,
                         persist: true,
+                        unit: None,
+                        scale: None,
                     },
                     SubDefinition {
                         sub_index: 2,
@@ -336,8 +1014,23 @@ fn pdo_objects(num_rpdo: usize, num_tpdo: usize) -> Vec<ObjectDefinition> {
                         default_value: None,
                         pdo_mapping: PdoMapping::None,
                         persist: true,
+                        unit: None,
+                        scale: None,
+                    },
+                    SubDefinition {
+                        sub_index: 3,
+                        parameter_name: format!("Inhibit time for {}{}", pdo_type, i),
+                        field_name: None,
+                        data_type: DataType::UInt16,
+                        access_type: AccessType::Rw.into(),
+                        default_value: None,
+                        pdo_mapping: PdoMapping::None,
+                        persist: true,
+                        unit: None,
+                        scale: None,
                     },
                 ],
+                reserved_subs: Vec::new(),
             }),
         });
 
@@ -350,6 +1043,8 @@ fn pdo_objects(num_rpdo: usize, num_tpdo: usize) -> Vec<ObjectDefinition> {
             default_value: Some(DefaultValue::Integer(0)),
             pdo_mapping: PdoMapping::None,
             persist: true,
+            unit: None,
+            scale: None,
         }];
         for sub in 1..65 {
             mapping_subs.push(SubDefinition {
@@ -361,6 +1056,8 @@ fn pdo_objects(num_rpdo: usize, num_tpdo: usize) -> Vec<ObjectDefinition> {
                 default_value: None,
                 pdo_mapping: PdoMapping::None,
                 persist: true,
+                unit: None,
+                scale: None,
             });
         }
 
@@ -368,7 +1065,14 @@ fn pdo_objects(num_rpdo: usize, num_tpdo: usize) -> Vec<ObjectDefinition> {
             index: mapping_index + i as u16,
             parameter_name: format!("{}{} Mapping Parameters", pdo_type, i),
             application_callback: true,
-            object: Object::Record(RecordDefinition { subs: mapping_subs }),
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
+            object: Object::Record(RecordDefinition {
+                subs: mapping_subs,
+                reserved_subs: Vec::new(),
+            }),
         });
     }
     for i in 0..num_rpdo {
@@ -380,6 +1084,60 @@ fn pdo_objects(num_rpdo: usize, num_tpdo: usize) -> Vec<ObjectDefinition> {
     objects
 }
 
+fn cob_id_objects() -> Vec<ObjectDefinition> {
+    fn cob_id_sub(sub_index: u8, parameter_name: &str) -> SubDefinition {
+        SubDefinition {
+            sub_index,
+            parameter_name: parameter_name.to_string(),
+            field_name: None,
+            data_type: DataType::UInt32,
+            access_type: AccessType::Rw.into(),
+            default_value: None,
+            pdo_mapping: PdoMapping::None,
+            persist: true,
+            unit: None,
+            scale: None,
+        }
+    }
+
+    vec![
+        ObjectDefinition {
+            index: 0x1014,
+            parameter_name: "COB-ID EMCY".to_string(),
+            application_callback: true,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
+            object: Object::Var(VarDefinition {
+                data_type: DataType::UInt32,
+                access_type: AccessType::Rw.into(),
+                default_value: None,
+                pdo_mapping: PdoMapping::None,
+                persist: true,
+                unit: None,
+                scale: None,
+            }),
+        },
+        ObjectDefinition {
+            index: 0x1200,
+            parameter_name: "SDO Server Parameter".to_string(),
+            application_callback: true,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
+            object: Object::Record(RecordDefinition {
+                subs: vec![
+                    cob_id_sub(1, "COB-ID Client to Server"),
+                    cob_id_sub(2, "COB-ID Server to Client"),
+                ],
+                reserved_subs: Vec::new(),
+            }),
+        },
+    ]
+}
+
 fn bootloader_objects(cfg: &BootloaderConfig) -> Vec<ObjectDefinition> {
     let mut objects = Vec::new();
 
@@ -390,6 +1148,10 @@ fn bootloader_objects(cfg: &BootloaderConfig) -> Vec<ObjectDefinition> {
         index: 0x5500,
         parameter_name: "Bootloader Info".into(),
         application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
         object: Object::Record(RecordDefinition {
             subs: vec![
                 SubDefinition {
@@ -401,6 +1163,8 @@ fn bootloader_objects(cfg: &BootloaderConfig) -> Vec<ObjectDefinition> {
                     default_value: Some(0.into()),
                     pdo_mapping: PdoMapping::None,
                     persist: false,
+                    unit: None,
+                    scale: None,
                 },
                 SubDefinition {
                     sub_index: 2,
@@ -411,6 +1175,8 @@ fn bootloader_objects(cfg: &BootloaderConfig) -> Vec<ObjectDefinition> {
                     default_value: Some(cfg.sections.len().into()),
                     pdo_mapping: PdoMapping::None,
                     persist: false,
+                    unit: None,
+                    scale: None,
                 },
                 SubDefinition {
                     sub_index: 3,
@@ -421,8 +1187,11 @@ fn bootloader_objects(cfg: &BootloaderConfig) -> Vec<ObjectDefinition> {
                     default_value: None,
                     pdo_mapping: PdoMapping::None,
                     persist: false,
+                    unit: None,
+                    scale: None,
                 },
             ],
+            reserved_subs: Vec::new(),
         }),
     });
 
@@ -431,6 +1200,10 @@ fn bootloader_objects(cfg: &BootloaderConfig) -> Vec<ObjectDefinition> {
             index: 0x5510 + i as u16,
             parameter_name: format!("Bootloader Section {i}"),
             application_callback: true,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
             object: Object::Record(RecordDefinition {
                 subs: vec![
                     SubDefinition {
@@ -471,6 +1244,7 @@ fn bootloader_objects(cfg: &BootloaderConfig) -> Vec<ObjectDefinition> {
                         ..Default::default()
                     },
                 ],
+                reserved_subs: Vec::new(),
             }),
         });
     }
@@ -484,6 +1258,10 @@ fn object_storage_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
             index: 0x1010,
             parameter_name: "Object Save Command".to_string(),
             application_callback: false,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
             object: Object::Array(ArrayDefinition {
                 data_type: DataType::UInt32,
                 access_type: AccessType::Rw.into(),
@@ -497,18 +1275,513 @@ fn object_storage_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
     }
 }
 
-fn default_num_rpdo() -> u8 {
-    4
-}
-fn default_num_tpdo() -> u8 {
-    4
+fn heartbeat_consumer_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if dev.heartbeat_consumers > 0 {
+        vec![ObjectDefinition {
+            index: 0x1016,
+            parameter_name: "Consumer Heartbeat Time".to_string(),
+            application_callback: false,
+            on_write_callback: false,
+            link_section: None,
+            atomic_storage: None,
+            pooled: false,
+            object: Object::Array(ArrayDefinition {
+                data_type: DataType::UInt32,
+                access_type: AccessType::Rw.into(),
+                array_size: dev.heartbeat_consumers as usize,
+                persist: true,
+                ..Default::default()
+            }),
+        }]
+    } else {
+        vec![]
+    }
 }
-fn default_true() -> bool {
-    true
+
+fn error_history_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if dev.error_history == 0 {
+        return vec![];
+    }
+    vec![ObjectDefinition {
+        index: 0x1003,
+        parameter_name: "Pre-defined Error Field".to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Array(ArrayDefinition {
+            data_type: DataType::UInt32,
+            access_type: AccessType::Ro.into(),
+            array_size: dev.error_history as usize,
+            ..Default::default()
+        }),
+    }]
 }
 
-/// Configuration options for PDOs
-#[derive(Deserialize, Debug, Clone, Copy)]
+fn verify_configuration_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if !dev.verify_configuration {
+        return vec![];
+    }
+    vec![ObjectDefinition {
+        index: 0x1020,
+        parameter_name: "Verify Configuration".to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Array(ArrayDefinition {
+            data_type: DataType::UInt32,
+            access_type: AccessType::Rw.into(),
+            array_size: 2,
+            persist: true,
+            ..Default::default()
+        }),
+    }]
+}
+
+fn statistics_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if !dev.statistics {
+        return vec![];
+    }
+    let counter = |sub_index, parameter_name: &str, field_name: &str| SubDefinition {
+        sub_index,
+        parameter_name: parameter_name.to_string(),
+        field_name: Some(field_name.to_string()),
+        data_type: DataType::UInt32,
+        access_type: AccessType::Rw.into(),
+        default_value: Some(DefaultValue::Integer(0)),
+        pdo_mapping: PdoMapping::None,
+        persist: true,
+        unit: None,
+        scale: None,
+    };
+    vec![ObjectDefinition {
+        index: 0x5001,
+        parameter_name: "Node Statistics".to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Record(RecordDefinition {
+            subs: vec![
+                counter(1, "Power Cycles", "power_cycles"),
+                counter(2, "Operating Minutes", "operating_minutes"),
+                counter(3, "Bus Off Count", "bus_off_count"),
+                counter(4, "EMCY Count", "emcy_count"),
+            ],
+            reserved_subs: Vec::new(),
+        }),
+    }]
+}
+
+fn access_trace_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if dev.access_trace.depth == 0 {
+        return vec![];
+    }
+    vec![ObjectDefinition {
+        index: 0x5003,
+        parameter_name: "Access Trace".to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Record(RecordDefinition {
+            subs: vec![
+                SubDefinition {
+                    sub_index: 1,
+                    parameter_name: "Access Count".to_string(),
+                    data_type: DataType::UInt32,
+                    access_type: AccessType::Rw.into(),
+                    ..Default::default()
+                },
+                SubDefinition {
+                    sub_index: 2,
+                    parameter_name: "Trace Data".to_string(),
+                    data_type: DataType::Domain,
+                    access_type: AccessType::Ro.into(),
+                    ..Default::default()
+                },
+                SubDefinition {
+                    sub_index: 3,
+                    parameter_name: "Trace PDOs".to_string(),
+                    data_type: DataType::UInt8,
+                    access_type: AccessType::Rw.into(),
+                    default_value: Some(DefaultValue::Integer(dev.access_trace.pdos as i64)),
+                    ..Default::default()
+                },
+            ],
+            reserved_subs: Vec::new(),
+        }),
+    }]
+}
+
+fn debug_log_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if dev.debug_log.size == 0 {
+        return vec![];
+    }
+    vec![ObjectDefinition {
+        index: 0x5004,
+        parameter_name: "Debug Log".to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Record(RecordDefinition {
+            subs: vec![
+                SubDefinition {
+                    sub_index: 1,
+                    parameter_name: "Log Position".to_string(),
+                    data_type: DataType::UInt32,
+                    access_type: AccessType::Rw.into(),
+                    ..Default::default()
+                },
+                SubDefinition {
+                    sub_index: 2,
+                    parameter_name: "Log Data".to_string(),
+                    data_type: DataType::Domain,
+                    access_type: AccessType::Ro.into(),
+                    ..Default::default()
+                },
+                SubDefinition {
+                    sub_index: 3,
+                    parameter_name: "Dropped Messages".to_string(),
+                    data_type: DataType::UInt32,
+                    access_type: AccessType::Ro.into(),
+                    ..Default::default()
+                },
+            ],
+            reserved_subs: Vec::new(),
+        }),
+    }]
+}
+
+fn event_log_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if dev.event_log.depth == 0 {
+        return vec![];
+    }
+    vec![ObjectDefinition {
+        index: 0x5008,
+        parameter_name: "Event Log".to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Record(RecordDefinition {
+            subs: vec![
+                SubDefinition {
+                    sub_index: 1,
+                    parameter_name: "Next Sequence".to_string(),
+                    data_type: DataType::UInt32,
+                    access_type: AccessType::Rw.into(),
+                    ..Default::default()
+                },
+                SubDefinition {
+                    sub_index: 2,
+                    parameter_name: "Fetch From".to_string(),
+                    data_type: DataType::UInt32,
+                    access_type: AccessType::Rw.into(),
+                    ..Default::default()
+                },
+                SubDefinition {
+                    sub_index: 3,
+                    parameter_name: "Event Data".to_string(),
+                    data_type: DataType::Domain,
+                    access_type: AccessType::Ro.into(),
+                    ..Default::default()
+                },
+                SubDefinition {
+                    sub_index: 4,
+                    parameter_name: "Stored Events".to_string(),
+                    data_type: DataType::Domain,
+                    access_type: AccessType::Rw.into(),
+                    persist: dev.event_log.persist,
+                    ..Default::default()
+                },
+            ],
+            reserved_subs: Vec::new(),
+        }),
+    }]
+}
+
+fn sdo_status_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if !dev.sdo_status {
+        return vec![];
+    }
+    let field = |sub_index, parameter_name: &str, data_type| SubDefinition {
+        sub_index,
+        parameter_name: parameter_name.to_string(),
+        data_type,
+        access_type: AccessType::Ro.into(),
+        pdo_mapping: PdoMapping::Tpdo,
+        ..Default::default()
+    };
+    vec![ObjectDefinition {
+        index: 0x5005,
+        parameter_name: "SDO Server Status".to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Record(RecordDefinition {
+            subs: vec![
+                field(1, "Transfer State", DataType::UInt8),
+                field(2, "Object Index", DataType::UInt16),
+                field(3, "Object Sub Index", DataType::UInt8),
+                field(4, "Bytes Transferred", DataType::UInt32),
+            ],
+            reserved_subs: Vec::new(),
+        }),
+    }]
+}
+
+fn loopback_test_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if !dev.loopback_test {
+        return vec![];
+    }
+    vec![ObjectDefinition {
+        index: 0x5009,
+        parameter_name: "Loopback Test".to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Record(RecordDefinition {
+            subs: vec![
+                SubDefinition {
+                    sub_index: 1,
+                    parameter_name: "Start".to_string(),
+                    data_type: DataType::UInt8,
+                    access_type: AccessType::Rw.into(),
+                    ..Default::default()
+                },
+                SubDefinition {
+                    sub_index: 2,
+                    parameter_name: "Status".to_string(),
+                    data_type: DataType::UInt8,
+                    access_type: AccessType::Ro.into(),
+                    ..Default::default()
+                },
+            ],
+            reserved_subs: Vec::new(),
+        }),
+    }]
+}
+
+fn pdo_statistics_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if !dev.pdos.statistics {
+        return vec![];
+    }
+    let array = |index, parameter_name: &str, size, unit: Option<&str>| ObjectDefinition {
+        index,
+        parameter_name: parameter_name.to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Array(ArrayDefinition {
+            data_type: DataType::UInt32,
+            access_type: AccessType::Ro.into(),
+            array_size: size,
+            unit: unit.map(String::from),
+            ..Default::default()
+        }),
+    };
+    let mut objects = Vec::new();
+    let num_tpdo = dev.pdos.num_tpdo as usize;
+    if num_tpdo > 0 {
+        objects.push(array(0x500A, "TPDO Transmit Count", num_tpdo, None));
+        objects.push(array(
+            0x500B,
+            "TPDO Last Transmit Time",
+            num_tpdo,
+            Some("ms"),
+        ));
+    }
+    let num_rpdo = dev.pdos.num_rpdo as usize;
+    if num_rpdo > 0 {
+        objects.push(array(0x500C, "RPDO Receive Count", num_rpdo, None));
+        objects.push(array(
+            0x500D,
+            "RPDO Last Receive Time",
+            num_rpdo,
+            Some("ms"),
+        ));
+    }
+    objects
+}
+
+fn nmt_unlock_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if !dev.nmt_protection.unlock || dev.nmt_protection.commands.is_empty() {
+        return vec![];
+    }
+    vec![ObjectDefinition {
+        index: 0x500E,
+        parameter_name: "NMT Unlock".to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Var(VarDefinition {
+            data_type: DataType::UInt32,
+            access_type: AccessType::Rw.into(),
+            ..Default::default()
+        }),
+    }]
+}
+
+fn var_pool_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if dev.memory.var_pool == 0 {
+        return vec![];
+    }
+    let field = |sub_index, parameter_name: &str| SubDefinition {
+        sub_index,
+        parameter_name: parameter_name.to_string(),
+        data_type: DataType::UInt32,
+        access_type: AccessType::Ro.into(),
+        ..Default::default()
+    };
+    vec![ObjectDefinition {
+        index: 0x5006,
+        parameter_name: "Var Pool Status".to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Record(RecordDefinition {
+            subs: vec![
+                field(1, "Capacity"),
+                field(2, "Bytes Used"),
+                field(3, "High Water Mark"),
+            ],
+            reserved_subs: Vec::new(),
+        }),
+    }]
+}
+
+fn secondary_heartbeat_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    let Some(base) = dev.nmt.secondary_heartbeat_base else {
+        return vec![];
+    };
+    vec![ObjectDefinition {
+        index: 0x5007,
+        parameter_name: "Secondary Heartbeat".to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Var(VarDefinition {
+            data_type: DataType::UInt32,
+            access_type: AccessType::Rw.into(),
+            default_value: Some(DefaultValue::Integer(base as i64)),
+            pdo_mapping: PdoMapping::None,
+            persist: true,
+            unit: None,
+            scale: None,
+        }),
+    }]
+}
+
+fn settings_backup_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if !dev.settings_backup {
+        return vec![];
+    }
+    vec![ObjectDefinition {
+        index: 0x5F10,
+        parameter_name: "Settings Backup".to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Var(VarDefinition {
+            data_type: DataType::Domain,
+            access_type: AccessType::Rw.into(),
+            default_value: None,
+            pdo_mapping: PdoMapping::None,
+            persist: false,
+            unit: None,
+            scale: None,
+        }),
+    }]
+}
+
+fn nmt_timing_objects(dev: &DeviceConfig) -> Vec<ObjectDefinition> {
+    if dev.nmt.boot_delay_max_ms == 0
+        && dev.nmt.min_heartbeat_interval_ms == 0
+        && dev.nmt.scan_response_jitter_max_ms == 0
+    {
+        return vec![];
+    }
+    let time = |sub_index, parameter_name: &str, field_name: &str, value: u16| SubDefinition {
+        sub_index,
+        parameter_name: parameter_name.to_string(),
+        field_name: Some(field_name.to_string()),
+        data_type: DataType::UInt16,
+        access_type: AccessType::Rw.into(),
+        default_value: Some(DefaultValue::Integer(value as i64)),
+        pdo_mapping: PdoMapping::None,
+        persist: true,
+        unit: None,
+        scale: None,
+    };
+    vec![ObjectDefinition {
+        index: 0x5002,
+        parameter_name: "NMT Startup Timing".to_string(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: Object::Record(RecordDefinition {
+            subs: vec![
+                time(
+                    1,
+                    "Boot Delay Max",
+                    "boot_delay_max_ms",
+                    dev.nmt.boot_delay_max_ms,
+                ),
+                time(
+                    2,
+                    "Min Heartbeat Interval",
+                    "min_heartbeat_interval_ms",
+                    dev.nmt.min_heartbeat_interval_ms,
+                ),
+                time(
+                    3,
+                    "Scan Response Jitter Max",
+                    "scan_response_jitter_max_ms",
+                    dev.nmt.scan_response_jitter_max_ms,
+                ),
+            ],
+            reserved_subs: Vec::new(),
+        }),
+    }]
+}
+
+fn default_num_rpdo() -> u8 {
+    4
+}
+fn default_num_tpdo() -> u8 {
+    4
+}
+fn default_true() -> bool {
+    true
+}
+
+/// Configuration options for PDOs
+#[derive(Deserialize, Debug, Clone)]
 pub struct PdoConfig {
     #[serde(default = "default_num_rpdo")]
     /// The number of TX PDO slots available in the device. Defaults to 4.
@@ -516,6 +1789,22 @@ pub struct PdoConfig {
     #[serde(default = "default_num_tpdo")]
     /// The number of RX PDO slots available in the device. Defaults to 4.
     pub num_rpdo: u8,
+    /// The stamp embedded in each TPDO, starting with TPDO 0
+    ///
+    /// TPDOs beyond the end of the list are not stamped.
+    #[serde(default)]
+    pub tpdo_stamps: Vec<TpdoStamp>,
+    /// Count the PDOs sent and received, and record when each was last seen, in objects
+    /// 0x500A-0x500D. Defaults to false.
+    #[serde(default)]
+    pub statistics: bool,
+}
+
+impl PdoConfig {
+    /// Get the stamp embedded in a TPDO
+    pub fn tpdo_stamp(&self, tpdo: usize) -> TpdoStamp {
+        self.tpdo_stamps.get(tpdo).copied().unwrap_or_default()
+    }
 }
 
 impl Default for PdoConfig {
@@ -523,6 +1812,215 @@ impl Default for PdoConfig {
         Self {
             num_tpdo: default_num_tpdo(),
             num_rpdo: default_num_rpdo(),
+            tpdo_stamps: Vec::new(),
+            statistics: false,
+        }
+    }
+}
+
+fn default_sdo_queue_depth() -> usize {
+    1
+}
+fn default_nmt_queue_depth() -> usize {
+    2
+}
+
+/// Configuration of the boot-up and heartbeat timing of the node
+///
+/// When many devices power on at the same time, their boot-up messages, and the heartbeats which
+/// follow, are sent together and can congest the bus. A random boot-up delay spreads them out, and a
+/// minimum heartbeat interval limits the rate at which each node can send heartbeats. Similarly, a
+/// client scanning the bus probes many nodes at once, and a random scan response delay spreads out
+/// their responses.
+///
+/// The times are stored in object 0x5002, which is only created when one of them is non-zero.
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct NmtConfig {
+    /// The maximum delay before the boot-up message is sent after a reset, in milliseconds
+    ///
+    /// Each node waits for a pseudo-random time up to this value, derived from its identity and
+    /// node ID, so that nodes on the same bus wait for different times. Defaults to 0.
+    #[serde(default)]
+    pub boot_delay_max_ms: u16,
+    /// The minimum time between heartbeat messages, in milliseconds
+    ///
+    /// This applies to the first heartbeat after the boot-up message, and to the heartbeats sent
+    /// immediately when the application changes the NMT state. Defaults to 0.
+    #[serde(default)]
+    pub min_heartbeat_interval_ms: u16,
+    /// The maximum delay before responding to an SDO read of an object used to scan the bus, in
+    /// milliseconds
+    ///
+    /// The objects are the device type (0x1000), device name (0x1008), hardware and software
+    /// versions (0x1009, 0x100A), and identity (0x1018). Each response is delayed by a
+    /// pseudo-random time up to this value, so that the responses of nodes which are probed at the
+    /// same time do not arrive in a burst. Defaults to 0.
+    #[serde(default)]
+    pub scan_response_jitter_max_ms: u16,
+    /// The base COB-ID of a secondary heartbeat, sent in addition to the heartbeat on 0x700 + node
+    /// ID
+    ///
+    /// When set, object 0x5007 is created, and each heartbeat is also sent on this base + node ID,
+    /// e.g. so that an application with two CAN interfaces can send it on the second one. The base
+    /// must leave room for node ID 127 within the 11-bit ID range. Defaults to None.
+    #[serde(default)]
+    pub secondary_heartbeat_base: Option<u16>,
+}
+
+/// An NMT command, as listed in [NmtProtectionConfig::commands]
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NmtCommandName {
+    /// Start, i.e. enter the Operational state
+    Start,
+    /// Enter the Stopped state
+    Stop,
+    /// Enter the PreOperational state
+    EnterPreOp,
+    /// Reset node
+    ResetApp,
+    /// Reset communication
+    ResetComm,
+}
+
+/// An NMT state, as listed in [NmtProtectionConfig::allowed_states]
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NmtStateName {
+    /// The PreOperational state
+    PreOperational,
+    /// The Operational state
+    Operational,
+    /// The Stopped state
+    Stopped,
+}
+
+/// Configuration of the protection against remote NMT commands
+///
+/// NMT commands received from the bus which are listed in `commands` are ignored, unless the node
+/// is in one of the `allowed_states`, or the command has been unlocked via object 0x500E. Each
+/// ignored command is reported with an EMCY.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NmtProtectionConfig {
+    /// The commands which are protected. Defaults to none.
+    #[serde(default)]
+    pub commands: Vec<NmtCommandName>,
+    /// The states in which the protected commands are accepted. Defaults to none.
+    #[serde(default)]
+    pub allowed_states: Vec<NmtStateName>,
+    /// Create the NMT unlock object (0x500E), which allows a single protected command to be
+    /// accepted. Defaults to false.
+    #[serde(default)]
+    pub unlock: bool,
+}
+
+/// Configuration of the object access trace
+///
+/// The trace is stored in object 0x5003, which is only created when `depth` is non-zero.
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct AccessTraceConfig {
+    /// The number of accesses kept in the trace. Defaults to 0.
+    #[serde(default)]
+    pub depth: usize,
+    /// Record PDO accesses, as well as SDO accesses
+    ///
+    /// PDOs are often sent at high rates, and can quickly replace older SDO entries. This sets the
+    /// default, and it can be changed at run time via object 0x5003sub3. Defaults to false.
+    #[serde(default)]
+    pub pdos: bool,
+}
+
+/// Configuration of the debug log
+///
+/// The log is stored in object 0x5004, which is only created when `size` is non-zero.
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct DebugLogConfig {
+    /// The size of the log buffer, in bytes. Defaults to 0.
+    #[serde(default)]
+    pub size: usize,
+    /// The maximum number of messages logged per second, or 0 for no limit
+    ///
+    /// Messages over the limit are discarded, so that a message logged in a loop does not replace
+    /// all of the older messages. Defaults to 0.
+    #[serde(default)]
+    pub rate_limit: u32,
+}
+
+/// Configuration of the event log
+///
+/// The log is stored in object 0x5008, which is only created when `depth` is non-zero.
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct EventLogConfig {
+    /// The number of events kept in the log. Defaults to 0.
+    #[serde(default)]
+    pub depth: usize,
+    /// Persist the events, along with the other persisted objects
+    ///
+    /// The events are only saved when a save is requested via object 0x1010, so the application
+    /// should request one after logging an event which must survive a reset. Defaults to false.
+    #[serde(default)]
+    pub persist: bool,
+}
+
+/// Configuration of shared memory for object storage
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryConfig {
+    /// The size of the pool shared by the values of pooled objects, in bytes
+    ///
+    /// Objects are stored in the pool when they set [`ObjectDefinition::pooled`]. The pool status
+    /// is reported by object 0x5006, which is only created when this is non-zero. Defaults to 0.
+    #[serde(default)]
+    pub var_pool: usize,
+}
+
+/// Configuration of the queues in the node mailbox
+///
+/// Each receive queue holds received messages until they are handled by the next call to
+/// `Node::process`. When a receive queue is full, the oldest message is dropped.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct MboxConfig {
+    /// The number of RPDO frames which can be buffered, shared by all RPDOs
+    ///
+    /// Defaults to the number of RPDOs.
+    #[serde(default)]
+    pub rpdo_queue_depth: Option<usize>,
+    /// The number of SDO requests which can be buffered. Defaults to 1.
+    #[serde(default = "default_sdo_queue_depth")]
+    pub sdo_queue_depth: usize,
+    /// The number of NMT and LSS frames which can be buffered. Defaults to 2.
+    #[serde(default = "default_nmt_queue_depth")]
+    pub nmt_queue_depth: usize,
+    /// Allocate a second SDO buffer, so that data received by segmented and block downloads is
+    /// written to objects while the next data is being received
+    ///
+    /// This keeps large downloads to objects with slow writes, e.g. to flash, close to the bus
+    /// speed, at the cost of another `SDO_BUFFER_SIZE` bytes of RAM. Defaults to false.
+    #[serde(default)]
+    pub sdo_double_buffer: bool,
+    /// The number of messages which can be held in the transmit queue
+    ///
+    /// The transmit queue is used by `Node::process_queued`, which queues the messages produced by
+    /// the node for the application to send at its own pace. Defaults to 0, i.e. no transmit
+    /// queue.
+    #[serde(default)]
+    pub tx_queue_depth: usize,
+}
+
+impl Default for MboxConfig {
+    fn default() -> Self {
+        Self {
+            rpdo_queue_depth: None,
+            sdo_queue_depth: default_sdo_queue_depth(),
+            nmt_queue_depth: default_nmt_queue_depth(),
+            sdo_double_buffer: false,
+            tx_queue_depth: 0,
         }
     }
 }
@@ -595,6 +2093,13 @@ pub struct BootloaderConfig {
 #[serde(deny_unknown_fields)]
 /// Private struct for seserializing device config files
 pub struct DeviceConfig {
+    /// The version of the config file schema
+    ///
+    /// After loading, this is always [CONFIG_VERSION], as older files are migrated. See
+    /// [Schema Versions](crate::device_config#schema-versions).
+    #[serde(default)]
+    pub config_version: u32,
+
     /// The name describing the type of device (e.g. a model)
     pub device_name: String,
 
@@ -610,22 +2115,121 @@ pub struct DeviceConfig {
     /// A version describing the software
     #[serde(default)]
     pub software_version: String,
+    /// Reserve room for a software version set by the application at run-time
+    ///
+    /// When set, object 0x100A is stored in RAM with room for this many bytes, initialized to
+    /// [`software_version`](Self::software_version), and a `set_software_version` function is
+    /// generated to change it.
+    ///
+    /// Default: None, in which case the version is constant
+    #[serde(default)]
+    pub software_version_size: Option<usize>,
 
     /// The period at which to transmit heartbeat messages in milliseconds
     #[serde(default)]
     pub heartbeat_period: u16,
 
-    /// Configures the identity object on the device
-    pub identity: IdentityConfig,
-
+    /// Enables the node statistics object (0x5001)
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub statistics: bool,
+
+    /// Enables the settings backup object (0x5F10)
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub settings_backup: bool,
+
+    /// Enables the SDO server status object (0x5005)
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub sdo_status: bool,
+
+    /// Enables the loopback self-test object (0x5009)
+    ///
+    /// The node's transport must deliver its own transmitted frames back to it for the test to
+    /// pass.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub loopback_test: bool,
+
+    /// Enables the verify configuration object (0x1020)
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub verify_configuration: bool,
+
+    /// The number of heartbeat consumer entries in object 0x1016
+    ///
+    /// Default: 0, in which case object 0x1016 is not created
+    #[serde(default)]
+    pub heartbeat_consumers: u8,
+
+    /// The number of EMCY errors held in the error history (0x1003)
+    ///
+    /// The history is stored in the node state, which is sized by this value.
+    ///
+    /// Default: 0, in which case object 0x1003 is not created
+    #[serde(default)]
+    pub error_history: u8,
+
+    /// Configures the identity object on the device
+    pub identity: IdentityConfig,
+
     /// Configure PDO settings
     #[serde(default)]
     pub pdos: PdoConfig,
 
+    /// Configure the depth of the receive queues
+    #[serde(default)]
+    pub mbox: MboxConfig,
+
+    /// Configure the timing of the boot-up message and heartbeats
+    #[serde(default)]
+    pub nmt: NmtConfig,
+
+    /// Configure which NMT commands from the bus are ignored
+    #[serde(default)]
+    pub nmt_protection: NmtProtectionConfig,
+
+    /// Configure the object access trace
+    #[serde(default)]
+    pub access_trace: AccessTraceConfig,
+
+    /// Configure the debug log
+    #[serde(default)]
+    pub debug_log: DebugLogConfig,
+
+    /// Configure the event log
+    #[serde(default)]
+    pub event_log: EventLogConfig,
+
     /// Configure bootloader options
     #[serde(default)]
     pub bootloader: BootloaderConfig,
 
+    /// Configure the shared pool for variable length objects
+    #[serde(default)]
+    pub memory: MemoryConfig,
+
+    /// Place the storage for all generated objects in the named linker section
+    ///
+    /// Individual objects may override this with [`ObjectDefinition::link_section`].
+    #[serde(default)]
+    pub link_section: Option<String>,
+
+    /// Store the scalar values of generated objects in atomic types, rather than in critical
+    /// section protected cells
+    ///
+    /// Individual objects may override this with [`ObjectDefinition::atomic_storage`].
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub atomic_storage: bool,
+
     /// A list of application specific objects to define on the device
     #[serde(default)]
     pub objects: Vec<ObjectDefinition>,
@@ -660,6 +2264,12 @@ pub struct SubDefinition {
     /// Indicates if this sub object should be saved when the save command is sent
     #[serde(default)]
     pub persist: bool,
+    /// The unit of the sub object's value, after scaling, e.g. "V"
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// The factor which converts the raw value to `unit`
+    #[serde(default)]
+    pub scale: Option<f64>,
 }
 
 /// An enum to represent object default values
@@ -714,6 +2324,16 @@ pub enum Object {
     Array(ArrayDefinition),
     /// A record is a collection of sub objects all with different types
     Record(RecordDefinition),
+    /// A record containing a raw value, and a scaled value computed from it using a configurable
+    /// gain and offset
+    Scaled(ScaledDefinition),
+    /// A placeholder for an object which cannot be implemented yet
+    ///
+    /// Stubs are not read from device config files. They replace unsupported objects when a node
+    /// is generated in permissive mode, and are generated as a read-only var which returns a
+    /// ResourceNotAvailable abort when read.
+    #[serde(skip)]
+    Stub(StubDefinition),
 }
 
 /// Descriptor for a var object
@@ -732,6 +2352,12 @@ pub struct VarDefinition {
     /// Indicates that this object should be saved
     #[serde(default)]
     pub persist: bool,
+    /// The unit of the value, after scaling, e.g. "V"
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// The factor which converts the raw value to `unit`
+    #[serde(default)]
+    pub scale: Option<f64>,
 }
 
 /// Descriptor for an array object
@@ -752,6 +2378,12 @@ pub struct ArrayDefinition {
     #[serde(default)]
     /// Whether this array should be saved to flash on command
     pub persist: bool,
+    /// The unit of all array fields, after scaling, e.g. "V"
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// The factor which converts the raw value of each field to `unit`
+    #[serde(default)]
+    pub scale: Option<f64>,
 }
 
 /// Descriptor for a record object
@@ -761,6 +2393,66 @@ pub struct RecordDefinition {
     /// The sub object definitions for this record object
     #[serde(default)]
     pub subs: Vec<SubDefinition>,
+    /// Sub indices which are reserved, and have no sub object
+    ///
+    /// Reserved subs are counted in the highest sub index reported by sub 0, but accessing them
+    /// returns a `NoSuchSubIndex` error, the same as any other sub index which is not defined.
+    #[serde(default)]
+    pub reserved_subs: Vec<u8>,
+}
+
+/// Descriptor for a scaled object
+///
+/// A scaled object is generated as a record with the following sub objects:
+///
+/// | Sub | Type        | Access        | Description |
+/// | --- | ----------- | ------------- | ----------- |
+/// | 0   | u8          | const         | Highest sub index -- always 4 |
+/// | 1   | `data_type` | `access_type` | The raw value |
+/// | 2   | f32         | rw            | Gain |
+/// | 3   | f32         | rw            | Offset |
+/// | 4   | f32         | ro            | Scaled value, computed on read as `raw * gain + offset` |
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScaledDefinition {
+    /// The data type of the raw value. Must be an integer type, or real32.
+    pub data_type: DataType,
+    /// Access permissions for the raw value
+    pub access_type: AccessTypeDeser,
+    /// The default raw value
+    pub default_value: Option<DefaultValue>,
+    /// The default gain
+    ///
+    /// Default: 1.0
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+    /// The default offset
+    ///
+    /// Default: 0.0
+    #[serde(default)]
+    pub offset: f32,
+    /// Determines which type of PDO the raw value can be mapped to. The scaled value can be mapped
+    /// to TPDOs if the raw value can.
+    #[serde(default)]
+    pub pdo_mapping: PdoMapping,
+    /// Indicates that the gain and offset should be saved
+    ///
+    /// Default: true
+    #[serde(default = "default_true")]
+    pub persist: bool,
+}
+
+/// Descriptor for a stub object
+#[derive(Debug, Clone)]
+pub struct StubDefinition {
+    /// The data type the object was declared with
+    pub data_type: crate::objects::DataType,
+    /// Why the object could not be implemented
+    pub reason: String,
+}
+
+fn default_gain() -> f32 {
+    1.0
 }
 
 /// Descriptor for a domain object
@@ -781,20 +2473,81 @@ pub struct ObjectDefinition {
     /// If true, this object is implemented by an application callback, and no storage will be
     /// allocated for it in the object dictionary.
     pub application_callback: bool,
+    /// If true, a post-write hook can be registered on the generated object
+    ///
+    /// The hook is called after each successful write to the object via the object dictionary, so
+    /// that the application can react to it without replacing the object's storage. It cannot be
+    /// combined with `application_callback`.
+    #[serde(default)]
+    pub on_write_callback: bool,
+    /// Place the storage for this object in the named linker section
+    ///
+    /// Overrides [`DeviceConfig::link_section`] for this object.
+    #[serde(default)]
+    pub link_section: Option<String>,
+    /// Store scalar values in atomic types rather than in critical section protected cells
+    ///
+    /// Overrides [`DeviceConfig::atomic_storage`] for this object.
+    #[serde(default)]
+    pub atomic_storage: Option<bool>,
+    /// Store the value of this object in the shared pool configured by [`MemoryConfig::var_pool`]
+    ///
+    /// Only var objects with a string data type can be pooled. The size of the data type is the
+    /// maximum length of the value, but only the length of the current value is used in the pool.
+    #[serde(default)]
+    pub pooled: bool,
     /// The descriptor for the object
     #[serde(flatten)]
     pub object: Object,
 }
 
+/// The unit and scale of a numeric sub object
+///
+/// See [Units](crate::device_config#units).
+#[derive(Debug, Clone, Copy)]
+pub struct Units<'a> {
+    /// The data type of the raw value
+    pub data_type: DataType,
+    /// The unit of the scaled value
+    pub unit: Option<&'a str>,
+    /// The factor which converts the raw value to `unit`
+    pub scale: Option<f64>,
+}
+
 impl ObjectDefinition {
     /// Get the object code specifying the type of this object
     pub fn object_code(&self) -> ObjectCode {
         match self.object {
-            Object::Var(_) => ObjectCode::Var,
+            Object::Var(_) | Object::Stub(_) => ObjectCode::Var,
             Object::Array(_) => ObjectCode::Array,
-            Object::Record(_) => ObjectCode::Record,
+            Object::Record(_) | Object::Scaled(_) => ObjectCode::Record,
         }
     }
+
+    /// Get the unit and scale of a sub object
+    ///
+    /// Returns None if the sub object does not exist, or has neither a unit nor a scale.
+    pub fn units(&self, sub: u8) -> Option<Units<'_>> {
+        let (data_type, unit, scale) = match &self.object {
+            Object::Var(def) if sub == 0 => (def.data_type, &def.unit, def.scale),
+            Object::Array(def) if sub >= 1 && sub as usize <= def.array_size => {
+                (def.data_type, &def.unit, def.scale)
+            }
+            Object::Record(def) => {
+                let sub = def.subs.iter().find(|s| s.sub_index == sub)?;
+                (sub.data_type, &sub.unit, sub.scale)
+            }
+            _ => return None,
+        };
+        if unit.is_none() && scale.is_none() {
+            return None;
+        }
+        Some(Units {
+            data_type,
+            unit: unit.as_deref(),
+            scale,
+        })
+    }
 }
 
 impl DeviceConfig {
@@ -805,8 +2558,33 @@ impl DeviceConfig {
     }
 
     /// Try to read a config from a &str
+    ///
+    /// Files written for an older schema version are migrated to the current one. See
+    /// [Schema Versions](crate::device_config#schema-versions).
     pub fn load_from_str(config_str: &str) -> Result<Self, LoadError> {
-        let mut config: DeviceConfig = toml::from_str(config_str).context(TomlParsingSnafu)?;
+        let mut table: toml::Table = toml::from_str(config_str).context(TomlParsingSnafu)?;
+        let version = checked_config_version(&table)?;
+        let original = table.clone();
+        apply_migrations(&mut table, version);
+        // Parse the original text when the migrations made no changes, so that errors include
+        // their location in the file
+        let mut config: DeviceConfig = if table == original {
+            toml::from_str(config_str)
+        } else {
+            toml::Value::Table(table).try_into()
+        }
+        .context(TomlParsingSnafu)?;
+        config.config_version = CONFIG_VERSION;
+        config.complete()
+    }
+
+    /// Add the objects implemented by the node itself, e.g. the mandatory and PDO objects, to the
+    /// application objects, and validate the result
+    ///
+    /// This is done by [`DeviceConfig::load`], and is only needed when a `DeviceConfig` is built
+    /// by other means, e.g. when converting from an EDS file. It must only be called once.
+    pub fn complete(self) -> Result<Self, LoadError> {
+        let mut config = self;
 
         // Add mandatory objects to the config
         config.objects.extend(mandatory_objects(&config));
@@ -817,13 +2595,187 @@ impl DeviceConfig {
             config.pdos.num_rpdo as usize,
             config.pdos.num_tpdo as usize,
         ));
+        config.objects.extend(cob_id_objects());
         config.objects.extend(object_storage_objects(&config));
+        config.objects.extend(heartbeat_consumer_objects(&config));
+        config.objects.extend(error_history_objects(&config));
+        config.objects.extend(verify_configuration_objects(&config));
+        config.objects.extend(statistics_objects(&config));
+        config.objects.extend(nmt_timing_objects(&config));
+        config.objects.extend(secondary_heartbeat_objects(&config));
+        config.objects.extend(access_trace_objects(&config));
+        config.objects.extend(debug_log_objects(&config));
+        config.objects.extend(event_log_objects(&config));
+        config.objects.extend(sdo_status_objects(&config));
+        config.objects.extend(loopback_test_objects(&config));
+        config.objects.extend(pdo_statistics_objects(&config));
+        config.objects.extend(nmt_unlock_objects(&config));
+        config.objects.extend(var_pool_objects(&config));
+        config.objects.extend(settings_backup_objects(&config));
 
         Self::validate_unique_indices(&config.objects)?;
+        Self::validate_scaled_objects(&config.objects)?;
+        Self::validate_units(&config.objects)?;
+        Self::validate_mbox(&config)?;
+        Self::validate_tpdo_stamps(&config)?;
+        Self::validate_secondary_heartbeat(&config)?;
+        Self::validate_software_version(&config)?;
+        Self::validate_link_sections(&config)?;
+        Self::validate_write_hooks(&config.objects)?;
+        Self::validate_pooled_objects(&config)?;
 
         Ok(config)
     }
 
+    /// Get the objects stored in the var pool, in the order of their slots in the pool
+    pub fn pooled_objects(&self) -> Vec<&ObjectDefinition> {
+        let mut objects: Vec<_> = self.objects.iter().filter(|o| o.pooled).collect();
+        objects.sort_by_key(|o| o.index);
+        objects
+    }
+
+    /// The depth of the RPDO receive queue, taking into account the default
+    pub fn rpdo_queue_depth(&self) -> usize {
+        self.mbox
+            .rpdo_queue_depth
+            .unwrap_or(self.pdos.num_rpdo as usize)
+    }
+
+    fn validate_mbox(config: &DeviceConfig) -> Result<(), LoadError> {
+        if config.pdos.num_rpdo > 0 && config.rpdo_queue_depth() == 0 {
+            return InvalidQueueDepthSnafu {
+                queue: "rpdo_queue_depth",
+            }
+            .fail();
+        }
+        if config.mbox.sdo_queue_depth == 0 {
+            return InvalidQueueDepthSnafu {
+                queue: "sdo_queue_depth",
+            }
+            .fail();
+        }
+        if config.mbox.nmt_queue_depth == 0 {
+            return InvalidQueueDepthSnafu {
+                queue: "nmt_queue_depth",
+            }
+            .fail();
+        }
+        Ok(())
+    }
+
+    fn validate_tpdo_stamps(config: &DeviceConfig) -> Result<(), LoadError> {
+        let stamps = config.pdos.tpdo_stamps.len();
+        if stamps > config.pdos.num_tpdo as usize {
+            return TooManyTpdoStampsSnafu {
+                stamps,
+                num_tpdo: config.pdos.num_tpdo,
+            }
+            .fail();
+        }
+        Ok(())
+    }
+
+    fn validate_secondary_heartbeat(config: &DeviceConfig) -> Result<(), LoadError> {
+        match config.nmt.secondary_heartbeat_base {
+            Some(base) if base > 0x7FF - 127 => InvalidSecondaryHeartbeatBaseSnafu { base }.fail(),
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_software_version(config: &DeviceConfig) -> Result<(), LoadError> {
+        let len = config.software_version.len();
+        match config.software_version_size {
+            Some(size) if size == 0 || size < len => {
+                InvalidSoftwareVersionSizeSnafu { size, len }.fail()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_link_sections(config: &DeviceConfig) -> Result<(), LoadError> {
+        let sections = config.link_section.iter().chain(
+            config
+                .objects
+                .iter()
+                .filter_map(|o| o.link_section.as_ref()),
+        );
+        for name in sections {
+            if name.is_empty() || name.chars().any(|c| c.is_whitespace() || c == '"') {
+                return InvalidLinkSectionSnafu { name: name.clone() }.fail();
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_write_hooks(objects: &[ObjectDefinition]) -> Result<(), LoadError> {
+        for obj in objects {
+            if obj.on_write_callback && obj.application_callback {
+                return WriteHookOnCallbackObjectSnafu { index: obj.index }.fail();
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_pooled_objects(config: &DeviceConfig) -> Result<(), LoadError> {
+        let mut required = 0;
+        for obj in config.pooled_objects() {
+            if config.memory.var_pool == 0 {
+                return NoVarPoolSnafu { index: obj.index }.fail();
+            }
+            let Object::Var(def) = &obj.object else {
+                return InvalidPooledObjectSnafu { index: obj.index }.fail();
+            };
+            if !def.data_type.is_str()
+                || !matches!(def.pdo_mapping, PdoMapping::None)
+                || obj.application_callback
+                || obj.on_write_callback
+            {
+                return InvalidPooledObjectSnafu { index: obj.index }.fail();
+            }
+            if let Some(DefaultValue::String(value)) = &def.default_value {
+                required += value.len();
+            }
+        }
+        let var_pool = config.memory.var_pool;
+        if required > var_pool {
+            return VarPoolTooSmallSnafu { var_pool, required }.fail();
+        }
+        Ok(())
+    }
+
+    fn validate_scaled_objects(objects: &[ObjectDefinition]) -> Result<(), LoadError> {
+        for obj in objects {
+            if let Object::Scaled(def) = &obj.object {
+                if !def.data_type.is_numeric() {
+                    return InvalidScaledTypeSnafu { index: obj.index }.fail();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_units(objects: &[ObjectDefinition]) -> Result<(), LoadError> {
+        for obj in objects {
+            let subs: Vec<(DataType, bool, Option<f64>)> = match &obj.object {
+                Object::Var(def) => vec![(def.data_type, def.unit.is_some(), def.scale)],
+                Object::Array(def) => vec![(def.data_type, def.unit.is_some(), def.scale)],
+                Object::Record(def) => def
+                    .subs
+                    .iter()
+                    .map(|sub| (sub.data_type, sub.unit.is_some(), sub.scale))
+                    .collect(),
+                _ => continue,
+            };
+            for (data_type, has_unit, scale) in subs {
+                let valid_scale = scale.is_none_or(|scale| scale.is_finite() && scale != 0.0);
+                if (has_unit || scale.is_some()) && !(data_type.is_numeric() && valid_scale) {
+                    return InvalidUnitsSnafu { index: obj.index }.fail();
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn validate_unique_indices(objects: &[ObjectDefinition]) -> Result<(), LoadError> {
         let mut found_indices = HashMap::new();
         for obj in objects {
@@ -844,6 +2796,19 @@ impl DeviceConfig {
                     }
                     found_subs.insert(&sub.sub_index, ());
                 }
+                for sub in &record.reserved_subs {
+                    if *sub == 0 {
+                        return ReservedSubZeroSnafu { index: obj.index }.fail();
+                    }
+                    if found_subs.contains_key(sub) {
+                        return DuplicateSubObjectsSnafu {
+                            index: obj.index,
+                            sub: *sub,
+                        }
+                        .fail();
+                    }
+                    found_subs.insert(sub, ());
+                }
             }
         }
 
@@ -902,6 +2867,20 @@ pub enum DataType {
 }
 
 impl DataType {
+    /// Returns true if the type is an integer or floating point type
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::Real32
+        )
+    }
+
     /// Returns true if the type is one of the stringy types
     pub fn is_str(&self) -> bool {
         matches!(
@@ -986,7 +2965,12 @@ impl<'de> serde::Deserialize<'de> for DataType {
 
 #[cfg(test)]
 mod tests {
-    use crate::device_config::{DeviceConfig, LoadError};
+    use crate::device_config::{
+        migrate_config, DefaultValue, DeviceConfig, LoadError, NmtCommandName, NmtStateName,
+        Object, CONFIG_VERSION,
+    };
+    use crate::objects::{AccessType, ObjectCode};
+    use crate::pdo_stamp::TpdoStamp;
     use assertables::assert_contains;
     #[test]
     fn test_duplicate_objects_errors() {
@@ -1063,4 +3047,631 @@ mod tests {
             err.to_string().as_str()
         );
     }
+
+    #[test]
+    fn test_reserved_subs() {
+        const BASE: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+
+            [[objects]]
+            index = 0x2000
+            parameter_name = "Gaps"
+            object_type = "record"
+            RESERVED
+            [[objects.subs]]
+            sub_index = 1
+            data_type = "int16"
+            access_type = "rw"
+            [[objects.subs]]
+            sub_index = 3
+            data_type = "int16"
+            access_type = "rw"
+        "#;
+
+        let config =
+            DeviceConfig::load_from_str(&BASE.replace("RESERVED", "reserved_subs = [4]")).unwrap();
+        let obj = config.objects.iter().find(|o| o.index == 0x2000).unwrap();
+        let Object::Record(record) = &obj.object else {
+            panic!("Expected record");
+        };
+        assert_eq!(vec![4], record.reserved_subs);
+
+        let err = DeviceConfig::load_from_str(&BASE.replace("RESERVED", "reserved_subs = [3]"))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            LoadError::DuplicateSubObjects {
+                index: 0x2000,
+                sub: 3
+            }
+        ));
+
+        let err = DeviceConfig::load_from_str(&BASE.replace("RESERVED", "reserved_subs = [0]"))
+            .unwrap_err();
+        assert!(matches!(err, LoadError::ReservedSubZero { index: 0x2000 }));
+    }
+
+    #[test]
+    fn test_scaled_object() {
+        const BASE: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+
+            [[objects]]
+            index = 0x2000
+            parameter_name = "Pressure"
+            object_type = "scaled"
+            access_type = "ro"
+            gain = 0.5
+        "#;
+
+        let config = DeviceConfig::load_from_str(&format!("{BASE}data_type = \"int16\"")).unwrap();
+        let obj = config.objects.iter().find(|o| o.index == 0x2000).unwrap();
+        let Object::Scaled(def) = &obj.object else {
+            panic!("Expected scaled object");
+        };
+        assert_eq!(0.5, def.gain);
+        assert_eq!(0.0, def.offset);
+        assert!(def.persist);
+        assert_eq!(ObjectCode::Record, obj.object_code());
+
+        let err = DeviceConfig::load_from_str(&format!("{BASE}data_type = \"visiblestring(4)\""))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            LoadError::InvalidScaledType { index: 0x2000 }
+        ));
+    }
+
+    #[test]
+    fn test_link_section() {
+        const BASE: &str = r#"
+            device_name = "test"
+            link_section = ".ccmram"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+
+            [[objects]]
+            index = 0x2000
+            parameter_name = "Retained"
+            object_type = "var"
+            data_type = "uint32"
+            access_type = "rw"
+        "#;
+
+        let config = DeviceConfig::load_from_str(BASE).unwrap();
+        assert_eq!(Some(".ccmram"), config.link_section.as_deref());
+
+        let toml = format!("{BASE}link_section = \".backup_sram\"\n");
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        let obj = config.objects.iter().find(|o| o.index == 0x2000).unwrap();
+        assert_eq!(Some(".backup_sram"), obj.link_section.as_deref());
+
+        let toml = format!("{BASE}link_section = \"bad name\"\n");
+        let err = DeviceConfig::load_from_str(&toml).unwrap_err();
+        assert!(matches!(err, LoadError::InvalidLinkSection { .. }));
+    }
+
+    #[test]
+    fn test_write_hook() {
+        const BASE: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+
+            [[objects]]
+            index = 0x2000
+            parameter_name = "Setpoint"
+            object_type = "var"
+            data_type = "uint32"
+            access_type = "rw"
+            on_write_callback = true
+        "#;
+
+        let config = DeviceConfig::load_from_str(BASE).unwrap();
+        let obj = config.objects.iter().find(|o| o.index == 0x2000).unwrap();
+        assert!(obj.on_write_callback);
+        assert!(config
+            .objects
+            .iter()
+            .filter(|o| o.index != 0x2000)
+            .all(|o| !o.on_write_callback));
+
+        let toml = format!("{BASE}application_callback = true\n");
+        let err = DeviceConfig::load_from_str(&toml).unwrap_err();
+        assert!(matches!(
+            err,
+            LoadError::WriteHookOnCallbackObject { index: 0x2000 }
+        ));
+    }
+
+    #[test]
+    fn test_var_pool() {
+        const BASE: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+
+            [[objects]]
+            index = 0x2001
+            parameter_name = "Label"
+            object_type = "var"
+            data_type = "visiblestring(32)"
+            access_type = "rw"
+            default_value = "abcd"
+            pooled = true
+
+            [[objects]]
+            index = 0x2000
+            parameter_name = "Name"
+            object_type = "var"
+            data_type = "visiblestring(32)"
+            access_type = "rw"
+            pooled = true
+        "#;
+
+        let err = DeviceConfig::load_from_str(BASE).unwrap_err();
+        assert!(matches!(err, LoadError::NoVarPool { index: 0x2000 }));
+
+        let toml = format!(
+            "{BASE}
+[memory]
+var_pool = 3
+"
+        );
+        let err = DeviceConfig::load_from_str(&toml).unwrap_err();
+        assert!(matches!(
+            err,
+            LoadError::VarPoolTooSmall {
+                var_pool: 3,
+                required: 4
+            }
+        ));
+
+        let toml = format!(
+            "{BASE}
+[memory]
+var_pool = 64
+"
+        );
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        let pooled: Vec<u16> = config.pooled_objects().iter().map(|o| o.index).collect();
+        assert_eq!(vec![0x2000, 0x2001], pooled);
+        assert!(config.objects.iter().any(|o| o.index == 0x5006));
+
+        let toml = toml
+            .replace("visiblestring(32)", "uint32")
+            .replace("\"abcd\"", "0");
+        let err = DeviceConfig::load_from_str(&toml).unwrap_err();
+        assert!(matches!(err, LoadError::InvalidPooledObject { .. }));
+    }
+
+    #[test]
+    fn test_mbox_config() {
+        const BASE: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+            [pdos]
+            num_rpdo = 3
+        "#;
+
+        let config = DeviceConfig::load_from_str(BASE).unwrap();
+        assert_eq!(3, config.rpdo_queue_depth());
+        assert_eq!(1, config.mbox.sdo_queue_depth);
+        assert_eq!(2, config.mbox.nmt_queue_depth);
+        assert!(!config.mbox.sdo_double_buffer);
+
+        let toml = format!(
+            "{BASE}\n[mbox]\nrpdo_queue_depth = 16\nsdo_queue_depth = 2\nsdo_double_buffer = true\n"
+        );
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        assert_eq!(16, config.rpdo_queue_depth());
+        assert_eq!(2, config.mbox.sdo_queue_depth);
+        assert!(config.mbox.sdo_double_buffer);
+
+        let toml = format!("{BASE}\n[mbox]\nnmt_queue_depth = 0\n");
+        let err = DeviceConfig::load_from_str(&toml).unwrap_err();
+        assert!(matches!(
+            err,
+            LoadError::InvalidQueueDepth {
+                queue: "nmt_queue_depth"
+            }
+        ));
+    }
+
+    #[test]
+    fn test_tpdo_stamps() {
+        const BASE: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+            [pdos]
+            num_tpdo = 2
+        "#;
+
+        let config = DeviceConfig::load_from_str(BASE).unwrap();
+        assert_eq!(TpdoStamp::None, config.pdos.tpdo_stamp(0));
+
+        let toml = format!("{BASE}tpdo_stamps = [\"none\", \"sync_counter\"]\n");
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        assert_eq!(TpdoStamp::None, config.pdos.tpdo_stamp(0));
+        assert_eq!(TpdoStamp::SyncCounter, config.pdos.tpdo_stamp(1));
+        assert_eq!(TpdoStamp::None, config.pdos.tpdo_stamp(2));
+
+        let toml = format!("{BASE}tpdo_stamps = [\"timestamp\", \"none\", \"none\"]\n");
+        let err = DeviceConfig::load_from_str(&toml).unwrap_err();
+        assert!(matches!(
+            err,
+            LoadError::TooManyTpdoStamps {
+                stamps: 3,
+                num_tpdo: 2
+            }
+        ));
+    }
+
+    #[test]
+    fn test_secondary_heartbeat() {
+        const BASE: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+        "#;
+
+        let config = DeviceConfig::load_from_str(BASE).unwrap();
+        assert!(!config.objects.iter().any(|o| o.index == 0x5007));
+
+        let toml = format!("{BASE}[nmt]\nsecondary_heartbeat_base = 0x680\n");
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        let obj = config.objects.iter().find(|o| o.index == 0x5007).unwrap();
+        let Object::Var(var) = &obj.object else {
+            panic!("Expected a var object");
+        };
+        assert!(matches!(
+            var.default_value,
+            Some(DefaultValue::Integer(0x680))
+        ));
+
+        let toml = format!("{BASE}[nmt]\nsecondary_heartbeat_base = 0x790\n");
+        let err = DeviceConfig::load_from_str(&toml).unwrap_err();
+        assert!(matches!(
+            err,
+            LoadError::InvalidSecondaryHeartbeatBase { base: 0x790 }
+        ));
+    }
+
+    #[test]
+    fn test_event_log() {
+        const BASE: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+        "#;
+
+        let config = DeviceConfig::load_from_str(BASE).unwrap();
+        assert!(!config.objects.iter().any(|o| o.index == 0x5008));
+
+        let toml = format!("{BASE}[event_log]\ndepth = 16\npersist = true\n");
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        let obj = config.objects.iter().find(|o| o.index == 0x5008).unwrap();
+        let Object::Record(record) = &obj.object else {
+            panic!("Expected a record object");
+        };
+        let persisted: Vec<u8> = record
+            .subs
+            .iter()
+            .filter(|s| s.persist)
+            .map(|s| s.sub_index)
+            .collect();
+        assert_eq!(vec![4], persisted);
+    }
+
+    #[test]
+    fn test_loopback_test() {
+        const BASE: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+        "#;
+
+        let config = DeviceConfig::load_from_str(BASE).unwrap();
+        assert!(!config.objects.iter().any(|o| o.index == 0x5009));
+
+        let toml = format!("loopback_test = true\n{BASE}");
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        let obj = config.objects.iter().find(|o| o.index == 0x5009).unwrap();
+        let Object::Record(record) = &obj.object else {
+            panic!("Expected a record object");
+        };
+        let subs: Vec<_> = record
+            .subs
+            .iter()
+            .map(|s| (s.sub_index, s.access_type.0))
+            .collect();
+        assert_eq!(vec![(1, AccessType::Rw), (2, AccessType::Ro)], subs);
+    }
+
+    #[test]
+    fn test_error_history() {
+        const BASE: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+        "#;
+
+        let config = DeviceConfig::load_from_str(BASE).unwrap();
+        assert_eq!(0, config.error_history);
+        assert!(!config.objects.iter().any(|o| o.index == 0x1003));
+
+        let toml = format!(
+            "error_history = 5
+{BASE}"
+        );
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        let object = config.objects.iter().find(|o| o.index == 0x1003).unwrap();
+        match &object.object {
+            Object::Array(array) => assert_eq!(5, array.array_size),
+            _ => panic!("Expected an array object"),
+        }
+    }
+
+    #[test]
+    fn test_pdo_statistics() {
+        const BASE: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+        "#;
+
+        let config = DeviceConfig::load_from_str(BASE).unwrap();
+        assert!(!config.objects.iter().any(|o| o.index == 0x500A));
+
+        let toml = format!("{BASE}[pdos]\nnum_tpdo = 3\nnum_rpdo = 0\nstatistics = true\n");
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        let sizes: Vec<_> = config
+            .objects
+            .iter()
+            .filter(|o| (0x500A..=0x500D).contains(&o.index))
+            .map(|o| match &o.object {
+                Object::Array(array) => (o.index, array.array_size),
+                _ => panic!("Expected an array object"),
+            })
+            .collect();
+        assert_eq!(vec![(0x500A, 3), (0x500B, 3)], sizes);
+    }
+
+    #[test]
+    fn test_nmt_protection() {
+        const BASE: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+        "#;
+
+        let config = DeviceConfig::load_from_str(BASE).unwrap();
+        assert!(config.nmt_protection.commands.is_empty());
+        assert!(!config.objects.iter().any(|o| o.index == 0x500E));
+
+        let toml = format!(
+            "{BASE}[nmt_protection]\ncommands = [\"stop\", \"reset_app\"]\nallowed_states = [\"pre_operational\"]\nunlock = true\n"
+        );
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        assert_eq!(
+            vec![NmtCommandName::Stop, NmtCommandName::ResetApp],
+            config.nmt_protection.commands
+        );
+        assert_eq!(
+            vec![NmtStateName::PreOperational],
+            config.nmt_protection.allowed_states
+        );
+        assert!(config.objects.iter().any(|o| o.index == 0x500E));
+
+        let toml = format!("{BASE}[nmt_protection]\ncommands = [\"halt\"]\n");
+        assert!(DeviceConfig::load_from_str(&toml).is_err());
+    }
+
+    #[test]
+    fn test_software_version_size() {
+        use crate::device_config::DataType;
+        use crate::objects::AccessType;
+
+        const IDENTITY: &str = r#"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+        "#;
+        let version_object = |config: &DeviceConfig| {
+            let obj = config.objects.iter().find(|o| o.index == 0x100A).unwrap();
+            let Object::Var(var) = &obj.object else {
+                panic!("Expected a var object");
+            };
+            (var.data_type, var.access_type.0)
+        };
+
+        let toml = format!("device_name = \"test\"\nsoftware_version = \"v1.0\"\n{IDENTITY}");
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        assert!(matches!(
+            version_object(&config),
+            (DataType::VisibleString(4), AccessType::Const)
+        ));
+
+        let toml = format!(
+            "device_name = \"test\"\nsoftware_version = \"v1.0\"\nsoftware_version_size = 32\n{IDENTITY}"
+        );
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        assert!(matches!(
+            version_object(&config),
+            (DataType::VisibleString(32), AccessType::Ro)
+        ));
+
+        let toml = format!(
+            "device_name = \"test\"\nsoftware_version = \"v1.0\"\nsoftware_version_size = 3\n{IDENTITY}"
+        );
+        let err = DeviceConfig::load_from_str(&toml).unwrap_err();
+        assert!(matches!(
+            err,
+            LoadError::InvalidSoftwareVersionSize { size: 3, len: 4 }
+        ));
+    }
+
+    #[test]
+    fn test_config_version() {
+        const BODY: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+        "#;
+
+        // Files without a version are migrated from version 0
+        let config = DeviceConfig::load_from_str(BODY).unwrap();
+        assert_eq!(CONFIG_VERSION, config.config_version);
+
+        let toml = format!("config_version = {CONFIG_VERSION}\n{BODY}");
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        assert_eq!(CONFIG_VERSION, config.config_version);
+
+        // Files written for a newer version are rejected
+        let toml = format!("config_version = {}\n{BODY}", CONFIG_VERSION + 1);
+        let err = DeviceConfig::load_from_str(&toml).unwrap_err();
+        assert!(
+            matches!(err, LoadError::UnsupportedConfigVersion { version } if version == CONFIG_VERSION + 1)
+        );
+
+        let toml = format!("config_version = \"1\"\n{BODY}");
+        let err = DeviceConfig::load_from_str(&toml).unwrap_err();
+        assert!(matches!(err, LoadError::InvalidConfigVersion));
+        let toml = format!("config_version = -1\n{BODY}");
+        let err = DeviceConfig::load_from_str(&toml).unwrap_err();
+        assert!(matches!(err, LoadError::InvalidConfigVersion));
+
+        // A parsed file can be upgraded to the current version
+        let mut table: toml::Table = toml::from_str(BODY).unwrap();
+        assert_eq!(0, migrate_config(&mut table).unwrap());
+        assert_eq!(
+            Some(&toml::Value::Integer(CONFIG_VERSION as i64)),
+            table.get("config_version")
+        );
+        assert_eq!(CONFIG_VERSION, migrate_config(&mut table).unwrap());
+    }
+
+    #[test]
+    fn test_units() {
+        use crate::device_config::DataType;
+
+        const HEADER: &str = r#"
+            device_name = "test"
+            [identity]
+            vendor_id = 0
+            product_code = 1
+            revision_number = 2
+        "#;
+        let toml = format!(
+            r#"{HEADER}
+            [[objects]]
+            index = 0x2000
+            parameter_name = "Supply Voltage"
+            object_type = "var"
+            data_type = "uint16"
+            access_type = "ro"
+            unit = "V"
+            scale = 0.001
+
+            [[objects]]
+            index = 0x2001
+            parameter_name = "Currents"
+            object_type = "array"
+            data_type = "int16"
+            access_type = "ro"
+            array_size = 2
+            unit = "mA"
+
+            [[objects]]
+            index = 0x2002
+            parameter_name = "Motor"
+            object_type = "record"
+            [[objects.subs]]
+            sub_index = 1
+            data_type = "uint8"
+            access_type = "ro"
+            [[objects.subs]]
+            sub_index = 2
+            data_type = "uint32"
+            access_type = "ro"
+            scale = 10.0
+            "#
+        );
+        let config = DeviceConfig::load_from_str(&toml).unwrap();
+        let object = |index| config.objects.iter().find(|o| o.index == index).unwrap();
+
+        let units = object(0x2000).units(0).unwrap();
+        assert!(matches!(units.data_type, DataType::UInt16));
+        assert_eq!((Some("V"), Some(0.001)), (units.unit, units.scale));
+        let units = object(0x2001).units(2).unwrap();
+        assert_eq!((Some("mA"), None), (units.unit, units.scale));
+        assert!(object(0x2001).units(3).is_none());
+        assert!(object(0x2002).units(1).is_none());
+        assert_eq!(Some(10.0), object(0x2002).units(2).unwrap().scale);
+
+        // Only numeric values can be scaled, by a finite, non-zero scale
+        let toml = format!(
+            r#"{HEADER}
+            [[objects]]
+            index = 0x2000
+            object_type = "var"
+            data_type = "visiblestring(8)"
+            access_type = "ro"
+            unit = "V"
+            "#
+        );
+        assert!(matches!(
+            DeviceConfig::load_from_str(&toml).unwrap_err(),
+            LoadError::InvalidUnits { index: 0x2000 }
+        ));
+        let toml = format!(
+            r#"{HEADER}
+            [[objects]]
+            index = 0x2000
+            object_type = "var"
+            data_type = "uint8"
+            access_type = "ro"
+            scale = 0.0
+            "#
+        );
+        assert!(matches!(
+            DeviceConfig::load_from_str(&toml).unwrap_err(),
+            LoadError::InvalidUnits { index: 0x2000 }
+        ));
+    }
 }
@@ -0,0 +1,424 @@
+//! Human readable [`Display`] implementations for protocol messages
+//!
+//! These are intended for logging and bus monitoring tools, and format messages in the terms used
+//! by the CANopen standard, e.g. `InitiateUpload 0x1018sub1`. They are only compiled with the
+//! `display` feature (enabled by `std`), so that they do not add to the size of no_std builds.
+
+use core::fmt::{Display, Formatter, Result};
+
+use crate::{
+    lss::{LssRequest, LssResponse},
+    messages::{
+        CanMessage, Heartbeat, NmtCommand, NmtCommandSpecifier, NmtState, SyncObject, ZencanMessage,
+    },
+    sdo::{AbortCode, SdoRequest, SdoResponse},
+};
+
+/// Write bytes as space separated hex, e.g. `[01 02 ff]`
+fn write_bytes(f: &mut Formatter<'_>, bytes: &[u8]) -> Result {
+    write!(f, "[")?;
+    for (i, b) in bytes.iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{b:02x}")?;
+    }
+    write!(f, "]")
+}
+
+fn write_abort(f: &mut Formatter<'_>, index: u16, sub: u8, abort_code: u32) -> Result {
+    write!(f, "Abort 0x{index:04X}sub{sub}: ")?;
+    match AbortCode::try_from(abort_code) {
+        Ok(code) => write!(f, "{code}"),
+        Err(_) => write!(f, "Unknown abort code (0x{abort_code:08X})"),
+    }
+}
+
+/// Write the payload of an initiate upload/download message, which either contains the expedited
+/// data, the size of the transfer, or nothing
+fn write_initiate_data(f: &mut Formatter<'_>, n: u8, e: bool, s: bool, data: &[u8; 4]) -> Result {
+    if e {
+        let len = if s { 4 - n as usize } else { 4 };
+        write!(f, " expedited ")?;
+        write_bytes(f, &data[..len])
+    } else if s {
+        write!(f, " size={}", u32::from_le_bytes(*data))
+    } else {
+        Ok(())
+    }
+}
+
+fn write_segment(f: &mut Formatter<'_>, t: bool, n: u8, c: bool, data: &[u8; 7]) -> Result {
+    write!(f, " t={} ", t as u8)?;
+    write_bytes(f, &data[..7 - (n as usize).min(7)])?;
+    if c {
+        write!(f, " last")?;
+    }
+    Ok(())
+}
+
+impl Display for SdoRequest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match *self {
+            SdoRequest::InitiateDownload {
+                n,
+                e,
+                s,
+                index,
+                sub,
+                data,
+            } => {
+                write!(f, "InitiateDownload 0x{index:04X}sub{sub}")?;
+                write_initiate_data(f, n, e, s, &data)
+            }
+            SdoRequest::DownloadSegment { t, n, c, data } => {
+                write!(f, "DownloadSegment")?;
+                write_segment(f, t, n, c, &data)
+            }
+            SdoRequest::InitiateUpload { index, sub } => {
+                write!(f, "InitiateUpload 0x{index:04X}sub{sub}")
+            }
+            SdoRequest::ReqUploadSegment { t } => write!(f, "ReqUploadSegment t={}", t as u8),
+            SdoRequest::InitiateBlockDownload {
+                cc,
+                s,
+                index,
+                sub,
+                size,
+            } => {
+                write!(f, "InitiateBlockDownload 0x{index:04X}sub{sub}")?;
+                if s {
+                    write!(f, " size={size}")?;
+                }
+                if cc {
+                    write!(f, " crc")?;
+                }
+                Ok(())
+            }
+            SdoRequest::EndBlockDownload { n, crc } => {
+                write!(f, "EndBlockDownload n={n} crc=0x{crc:04X}")
+            }
+            SdoRequest::InitiateBlockUpload {
+                index,
+                sub,
+                blksize,
+                pst,
+            } => write!(
+                f,
+                "InitiateBlockUpload 0x{index:04X}sub{sub} blksize={blksize} pst={pst}"
+            ),
+            SdoRequest::EndBlockUpload => write!(f, "EndBlockUpload"),
+            SdoRequest::StartBlockUpload => write!(f, "StartBlockUpload"),
+            SdoRequest::ConfirmBlock { ackseq, blksize } => {
+                write!(f, "ConfirmBlock ackseq={ackseq} blksize={blksize}")
+            }
+            SdoRequest::Abort {
+                index,
+                sub,
+                abort_code,
+            } => write_abort(f, index, sub, abort_code),
+        }
+    }
+}
+
+impl Display for SdoResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match *self {
+            SdoResponse::ConfirmUpload {
+                n,
+                e,
+                s,
+                index,
+                sub,
+                data,
+            } => {
+                write!(f, "ConfirmUpload 0x{index:04X}sub{sub}")?;
+                write_initiate_data(f, n, e, s, &data)
+            }
+            SdoResponse::UploadSegment { t, n, c, data } => {
+                write!(f, "UploadSegment")?;
+                write_segment(f, t, n, c, &data)
+            }
+            SdoResponse::ConfirmDownload { index, sub } => {
+                write!(f, "ConfirmDownload 0x{index:04X}sub{sub}")
+            }
+            SdoResponse::ConfirmDownloadSegment { t } => {
+                write!(f, "ConfirmDownloadSegment t={}", t as u8)
+            }
+            SdoResponse::ConfirmBlockDownload {
+                sc,
+                index,
+                sub,
+                blksize,
+            } => {
+                write!(
+                    f,
+                    "ConfirmBlockDownload 0x{index:04X}sub{sub} blksize={blksize}"
+                )?;
+                if sc {
+                    write!(f, " crc")?;
+                }
+                Ok(())
+            }
+            SdoResponse::ConfirmBlock { ackseq, blksize } => {
+                write!(f, "ConfirmBlock ackseq={ackseq} blksize={blksize}")
+            }
+            SdoResponse::ConfirmBlockDownloadEnd => write!(f, "ConfirmBlockDownloadEnd"),
+            SdoResponse::ConfirmBlockUpload {
+                sc,
+                s,
+                index,
+                sub,
+                size,
+            } => {
+                write!(f, "ConfirmBlockUpload 0x{index:04X}sub{sub}")?;
+                if s {
+                    write!(f, " size={size}")?;
+                }
+                if sc {
+                    write!(f, " crc")?;
+                }
+                Ok(())
+            }
+            SdoResponse::BlockUploadEnd { n, crc } => {
+                write!(f, "BlockUploadEnd n={n} crc=0x{crc:04X}")
+            }
+            SdoResponse::Abort {
+                index,
+                sub,
+                abort_code,
+            } => write_abort(f, index, sub, abort_code),
+        }
+    }
+}
+
+impl Display for NmtCommandSpecifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            NmtCommandSpecifier::Start => write!(f, "Start"),
+            NmtCommandSpecifier::Stop => write!(f, "Stop"),
+            NmtCommandSpecifier::EnterPreOp => write!(f, "EnterPreOp"),
+            NmtCommandSpecifier::ResetApp => write!(f, "ResetApp"),
+            NmtCommandSpecifier::ResetComm => write!(f, "ResetComm"),
+        }
+    }
+}
+
+impl Display for NmtCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if self.node == 0 {
+            write!(f, "NMT {} all nodes", self.cs)
+        } else {
+            write!(f, "NMT {} node {}", self.cs, self.node)
+        }
+    }
+}
+
+impl Display for Heartbeat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if self.state == NmtState::Bootup {
+            write!(f, "Bootup node {}", self.node)
+        } else {
+            write!(
+                f,
+                "Heartbeat node {} {} t={}",
+                self.node, self.state, self.toggle as u8
+            )
+        }
+    }
+}
+
+impl Display for SyncObject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "Sync count={}", self.count())
+    }
+}
+
+/// Write the error fields of an LSS configuration acknowledgement
+fn write_lss_ack(f: &mut Formatter<'_>, name: &str, error: u8, spec_error: u8) -> Result {
+    match error {
+        0 => write!(f, "{name} ok"),
+        0xff => write!(f, "{name} manufacturer error {spec_error}"),
+        _ => write!(f, "{name} error {error}"),
+    }
+}
+
+impl Display for LssRequest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match *self {
+            LssRequest::SwitchModeGlobal { mode } => match mode {
+                0 => write!(f, "SwitchModeGlobal Waiting"),
+                1 => write!(f, "SwitchModeGlobal Configuring"),
+                _ => write!(f, "SwitchModeGlobal mode={mode}"),
+            },
+            LssRequest::ConfigureNodeId { node_id } => write!(f, "ConfigureNodeId {node_id}"),
+            LssRequest::ConfigureBitTiming { table, index } => {
+                write!(f, "ConfigureBitTiming table={table} index={index}")
+            }
+            LssRequest::StoreConfiguration => write!(f, "StoreConfiguration"),
+            LssRequest::ActivateBitTiming { delay } => {
+                write!(f, "ActivateBitTiming delay={delay}ms")
+            }
+            LssRequest::SwitchStateVendor { vendor_id } => {
+                write!(f, "SwitchStateVendor 0x{vendor_id:08X}")
+            }
+            LssRequest::SwitchStateProduct { product_code } => {
+                write!(f, "SwitchStateProduct 0x{product_code:08X}")
+            }
+            LssRequest::SwitchStateRevision { revision } => {
+                write!(f, "SwitchStateRevision 0x{revision:08X}")
+            }
+            LssRequest::SwitchStateSerial { serial } => {
+                write!(f, "SwitchStateSerial 0x{serial:08X}")
+            }
+            LssRequest::InquireVendor => write!(f, "InquireVendor"),
+            LssRequest::InquireProduct => write!(f, "InquireProduct"),
+            LssRequest::InquireRev => write!(f, "InquireRev"),
+            LssRequest::InquireSerial => write!(f, "InquireSerial"),
+            LssRequest::InquireNodeId => write!(f, "InquireNodeId"),
+            LssRequest::FastScan {
+                id,
+                bit_check,
+                sub,
+                next,
+            } => write!(
+                f,
+                "FastScan id=0x{id:08X} bit_check={bit_check} sub={sub} next={next}"
+            ),
+        }
+    }
+}
+
+impl Display for LssResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match *self {
+            LssResponse::IdentifySlave => write!(f, "IdentifySlave"),
+            LssResponse::SwitchStateResponse => write!(f, "SwitchStateResponse"),
+            LssResponse::ConfigureNodeIdAck { error, spec_error } => {
+                write_lss_ack(f, "ConfigureNodeIdAck", error, spec_error)
+            }
+            LssResponse::ConfigureBitTimingAck { error, spec_error } => {
+                write_lss_ack(f, "ConfigureBitTimingAck", error, spec_error)
+            }
+            LssResponse::StoreConfigurationAck { error, spec_error } => {
+                write_lss_ack(f, "StoreConfigurationAck", error, spec_error)
+            }
+            LssResponse::InquireVendorAck { vendor_id } => {
+                write!(f, "InquireVendorAck 0x{vendor_id:08X}")
+            }
+            LssResponse::InquireProductAck { product_code } => {
+                write!(f, "InquireProductAck 0x{product_code:08X}")
+            }
+            LssResponse::InquireRevAck { revision } => {
+                write!(f, "InquireRevAck 0x{revision:08X}")
+            }
+            LssResponse::InquireSerialAck { serial_number } => {
+                write!(f, "InquireSerialAck 0x{serial_number:08X}")
+            }
+            LssResponse::InquireNodeIdAck { node_id } => write!(f, "InquireNodeIdAck {node_id}"),
+        }
+    }
+}
+
+impl Display for ZencanMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            ZencanMessage::NmtCommand(cmd) => write!(f, "{cmd}"),
+            ZencanMessage::Sync(sync) => write!(f, "{sync}"),
+            ZencanMessage::Heartbeat(hb) => write!(f, "{hb}"),
+            ZencanMessage::SdoRequest(req) => write!(f, "SDO request {req}"),
+            ZencanMessage::SdoResponse(resp) => write!(f, "SDO response {resp}"),
+            ZencanMessage::LssRequest(req) => write!(f, "LSS request {req}"),
+            ZencanMessage::LssResponse(resp) => write!(f, "LSS response {resp}"),
+        }
+    }
+}
+
+impl Display for CanMessage {
+    /// Formats the message like `0x181 [3] 01 02 03`
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if self.id().is_extended() {
+            write!(f, "0x{:08X}", self.id().raw())?;
+        } else {
+            write!(f, "0x{:03X}", self.id().raw())?;
+        }
+        if self.is_rtr() {
+            return write!(f, " RTR");
+        }
+        write!(f, " [{}]", self.dlc)?;
+        for b in self.data() {
+            write!(f, " {b:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::CanId;
+
+    #[test]
+    fn test_sdo_display() {
+        let cases: &[(SdoRequest, &str)] = &[
+            (
+                SdoRequest::initiate_upload(0x1018, 1),
+                "InitiateUpload 0x1018sub1",
+            ),
+            (
+                SdoRequest::expedited_download(0x2000, 0, &[1, 2]),
+                "InitiateDownload 0x2000sub0 expedited [01 02]",
+            ),
+            (
+                SdoRequest::initiate_download(0x2000, 0, Some(100)),
+                "InitiateDownload 0x2000sub0 size=100",
+            ),
+            (
+                SdoRequest::download_segment(true, true, &[0xaa]),
+                "DownloadSegment t=1 [aa] last",
+            ),
+            (
+                SdoRequest::abort(0x1000, 0, AbortCode::ReadOnly),
+                "Abort 0x1000sub0: Attempt to write a read only object (0x06010002)",
+            ),
+        ];
+        for (req, expected) in cases {
+            assert_eq!(*expected, req.to_string());
+        }
+
+        let resp = SdoResponse::Abort {
+            index: 0x2000,
+            sub: 3,
+            abort_code: 0x1234,
+        };
+        assert_eq!(
+            "Abort 0x2000sub3: Unknown abort code (0x00001234)",
+            resp.to_string()
+        );
+    }
+
+    #[test]
+    fn test_message_display() {
+        let nmt = NmtCommand {
+            cs: NmtCommandSpecifier::ResetComm,
+            node: 0,
+        };
+        assert_eq!("NMT ResetComm all nodes", nmt.to_string());
+
+        let hb = Heartbeat {
+            node: 5,
+            toggle: false,
+            state: NmtState::Operational,
+        };
+        assert_eq!("Heartbeat node 5 Operational t=0", hb.to_string());
+
+        let resp = LssResponse::ConfigureNodeIdAck {
+            error: 1,
+            spec_error: 0,
+        };
+        assert_eq!("ConfigureNodeIdAck error 1", resp.to_string());
+
+        let msg = CanMessage::new(CanId::std(0x181), &[1, 2, 0xff]);
+        assert_eq!("0x181 [3] 01 02 ff", msg.to_string());
+    }
+}
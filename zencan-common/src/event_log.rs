@@ -0,0 +1,95 @@
+//! Entries of the event log (object 0x5008)
+//!
+//! A node with an event log records application events, such as faults or configuration changes,
+//! in a ring buffer. Each event is given a sequence number, which counts up from 0 when the log is
+//! cleared, so that a client can fetch only the events which are new since its last fetch. The
+//! events are uploaded from the Domain sub object 0x5008sub3, oldest first, each encoded in
+//! [`ENTRY_SIZE`] bytes:
+//!
+//! | Offset | Type    | Description |
+//! | ------ | ------- | ----------- |
+//! | 0      | u32     | Sequence number |
+//! | 4      | u32     | Time of the event in milliseconds, from the clock passed to the node |
+//! | 8      | u16     | Application defined event code |
+//! | 10     | [u8; 4] | Application defined event data |
+
+/// The number of bytes in an encoded event log entry
+pub const ENTRY_SIZE: usize = 14;
+
+/// A single event recorded in the log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EventLogEntry {
+    /// The sequence number of the event
+    pub seq: u32,
+    /// Time of the event in milliseconds
+    ///
+    /// This is the time passed to the node by the most recent call to `Node::process` before the
+    /// event was logged, so it wraps after about 49 days.
+    pub timestamp_ms: u32,
+    /// The application defined event code
+    pub code: u16,
+    /// The application defined event data
+    pub data: [u8; 4],
+}
+
+impl EventLogEntry {
+    /// An entry used to initialize event log buffers
+    pub const EMPTY: Self = Self {
+        seq: 0,
+        timestamp_ms: 0,
+        code: 0,
+        data: [0; 4],
+    };
+
+    /// Encode the entry
+    pub fn to_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..4].copy_from_slice(&self.seq.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.timestamp_ms.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.code.to_le_bytes());
+        bytes[10..14].copy_from_slice(&self.data);
+        bytes
+    }
+
+    /// Decode an entry
+    pub fn from_bytes(bytes: &[u8; ENTRY_SIZE]) -> Self {
+        Self {
+            seq: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            timestamp_ms: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            code: u16::from_le_bytes(bytes[8..10].try_into().unwrap()),
+            data: bytes[10..14].try_into().unwrap(),
+        }
+    }
+
+    /// Decode the entries of an uploaded log, oldest first
+    ///
+    /// Any trailing partial entry is skipped.
+    pub fn decode_all(data: &[u8]) -> impl Iterator<Item = EventLogEntry> + '_ {
+        data.chunks_exact(ENTRY_SIZE)
+            .map(|chunk| Self::from_bytes(chunk.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_roundtrip() {
+        let entry = EventLogEntry {
+            seq: 41,
+            timestamp_ms: 123456,
+            code: 0x1001,
+            data: [1, 2, 3, 4],
+        };
+        let bytes = entry.to_bytes();
+        assert_eq!(entry, EventLogEntry::from_bytes(&bytes));
+
+        let mut data = [bytes, EventLogEntry::EMPTY.to_bytes()].concat();
+        data.push(0);
+        let decoded: Vec<_> = EventLogEntry::decode_all(&data).collect();
+        assert_eq!(vec![entry, EventLogEntry::EMPTY], decoded);
+    }
+}
@@ -6,25 +6,45 @@
 #![warn(missing_docs, missing_copy_implementations)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+pub mod access_trace;
 mod atomic_cell;
 pub use atomic_cell::AtomicCell;
+pub mod client;
 pub mod constants;
+pub mod decode;
 #[cfg(feature = "std")]
 #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 pub mod device_config;
+#[cfg(feature = "display")]
+mod display;
+pub mod event_log;
+pub mod loopback_test;
 pub mod lss;
 pub mod messages;
 pub mod node_id;
 pub mod objects;
+pub mod pdo_stamp;
 pub mod sdo;
 pub mod traits;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub mod value;
 
+#[cfg(feature = "socketcan")]
+mod bcm;
 #[cfg(feature = "socketcan")]
 mod socketcan;
 
 #[cfg(feature = "socketcan")]
 #[cfg_attr(docsrs, doc(cfg(feature = "socketcan")))]
-pub use socketcan::open_socketcan;
+pub use socketcan::{
+    open_socketcan, InterfaceState, ReceiveError, SocketCanReceiver, SocketCanSender,
+    SocketCanTransport,
+};
+
+#[cfg(feature = "socketcan")]
+#[cfg_attr(docsrs, doc(cfg(feature = "socketcan")))]
+pub use bcm::{CyclicTransmitter, MAX_CYCLIC_FRAMES};
 
 pub use node_id::NodeId;
 
@@ -0,0 +1,68 @@
+//! The loopback self-test (object 0x5009)
+//!
+//! A node with a loopback test transmits a test frame when sub 1 of object 0x5009 is written with
+//! 1, and checks that the same frame is received back. This requires a transport which delivers
+//! transmitted frames back to the node, e.g. a CAN controller with its own frames received, or
+//! socketcan with `recv_own_msgs` set. Since a frame is only received back once it has been
+//! acknowledged by another device, a pass shows that the transceiver and the bus wiring work.
+//!
+//! The test frame is sent on [`TEST_FRAME_ID`], which is in the range reserved by CiA 301, so it
+//! does not collide with the frames of any device. Its data is the node ID, a sequence number which
+//! distinguishes each test, and the [`TEST_PATTERN`]. The result is read from sub 2 as a
+//! [`LoopbackStatus`].
+use int_enum::IntEnum;
+
+use crate::messages::CanId;
+
+/// The COB-ID of the test frame
+pub const TEST_FRAME_ID: CanId = CanId::std(0x7E0);
+
+/// The last six data bytes of the test frame
+///
+/// The pattern contains long runs of both recessive and dominant bits, as well as alternating bits.
+pub const TEST_PATTERN: [u8; 6] = [0x55, 0xAA, 0x00, 0xFF, 0x33, 0xCC];
+
+/// Get the data of the test frame sent by a node
+pub const fn test_frame_data(node_id: u8, seq: u8) -> [u8; 8] {
+    let p = TEST_PATTERN;
+    [node_id, seq, p[0], p[1], p[2], p[3], p[4], p[5]]
+}
+
+/// The result of a loopback self-test
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, IntEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum LoopbackStatus {
+    /// No test has been run since the node started
+    #[default]
+    NotRun = 0,
+    /// A test is in progress
+    Running = 1,
+    /// The test frame was received back unchanged
+    Passed = 2,
+    /// The test frame was not received back within the timeout
+    NoEcho = 3,
+    /// The test frame was received back with a different length or data
+    Corrupted = 4,
+}
+
+impl LoopbackStatus {
+    /// Returns true if a test has finished, whatever its result
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, LoopbackStatus::NotRun | LoopbackStatus::Running)
+    }
+}
+
+impl core::fmt::Display for LoopbackStatus {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let text = match self {
+            LoopbackStatus::NotRun => "not run",
+            LoopbackStatus::Running => "running",
+            LoopbackStatus::Passed => "passed",
+            LoopbackStatus::NoEcho => "failed: the test frame was not received back",
+            LoopbackStatus::Corrupted => "failed: the test frame was received back corrupted",
+        };
+        f.write_str(text)
+    }
+}
@@ -8,6 +8,7 @@ use crate::messages::{CanId, CanMessage, MessageError, LSS_REQ_ID, LSS_RESP_ID};
 
 /// Defines all possible values for the LSS command specifier field
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum LssCommandSpecifier {
     /// Used to change the LSS mode for all nodes on the bus
     SwitchModeGlobal = 0x04,
@@ -51,6 +52,7 @@ pub enum LssCommandSpecifier {
 /// Represents the possible values of the error field returned in response to a ConfigureNodeId
 /// command
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum LssConfigureError {
     /// Success
@@ -92,6 +94,7 @@ impl LssCommandSpecifier {
 
 /// An LSS request send by the master to the slave
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum LssRequest {
     /// Switch the mode of all LSS slaves
@@ -362,6 +365,7 @@ impl From<LssRequest> for CanMessage {
 
 /// An LSS response message sent from the Slave to Master
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum LssResponse {
     /// Sent when a slave's identity matches a FastScan request
@@ -514,7 +518,7 @@ impl TryFrom<CanMessage> for LssResponse {
                 expected: LSS_RESP_ID,
             });
         }
-        LssResponse::try_from(&value.data[..])
+        LssResponse::try_from(value.data())
     }
 }
 
@@ -572,6 +576,8 @@ impl LssResponse {
 
 /// The possible LSS states
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum LssState {
     /// The default state of a node.
@@ -601,6 +607,8 @@ impl LssState {
 /// register on the MCU, or by loading a previously programmed value from flash. It is important
 /// that each device on the bus have a unique identity.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct LssIdentity {
     /// A number indicating the vendor of the device
     pub vendor_id: u32,
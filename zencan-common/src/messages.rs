@@ -11,6 +11,8 @@ use crate::{
 ///
 /// TODO: Consider if this should use the CanId from embedded_can?
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum CanId {
     /// An extended 28-bit identifier
     Extended(u32),
@@ -29,6 +31,71 @@ impl CanId {
         CanId::Std(id)
     }
 
+    /// Get the default COB ID of the SDO server responses transmitted by a node (0x580 + node)
+    pub const fn sdo_tx(node: u8) -> CanId {
+        CanId::Std(SDO_RESP_BASE + node as u16)
+    }
+
+    /// Get the default COB ID of the SDO server requests received by a node (0x600 + node)
+    pub const fn sdo_rx(node: u8) -> CanId {
+        CanId::Std(SDO_REQ_BASE + node as u16)
+    }
+
+    /// Get the default COB ID of TPDO `n` of a node
+    ///
+    /// PDOs are numbered from 1 to 4, so e.g. `CanId::tpdo(1, 5)` is 0x185.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is not in the range 1 to 4, as only the first four PDOs have a default COB ID
+    pub const fn tpdo(n: u8, node: u8) -> CanId {
+        assert!(n >= 1 && n <= 4, "PDO number must be in the range 1 to 4");
+        CanId::Std(0x180 + (n as u16 - 1) * 0x100 + node as u16)
+    }
+
+    /// Get the default COB ID of RPDO `n` of a node
+    ///
+    /// PDOs are numbered from 1 to 4, so e.g. `CanId::rpdo(1, 5)` is 0x205.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is not in the range 1 to 4, as only the first four PDOs have a default COB ID
+    pub const fn rpdo(n: u8, node: u8) -> CanId {
+        assert!(n >= 1 && n <= 4, "PDO number must be in the range 1 to 4");
+        CanId::Std(0x200 + (n as u16 - 1) * 0x100 + node as u16)
+    }
+
+    /// Get the COB ID of the heartbeat messages transmitted by a node (0x700 + node)
+    pub const fn heartbeat(node: u8) -> CanId {
+        CanId::Std(HEARTBEAT_ID + node as u16)
+    }
+
+    /// Get the COB ID of the EMCY messages transmitted by a node (0x80 + node)
+    pub const fn emcy(node: u8) -> CanId {
+        CanId::Std(crate::decode::EMCY_BASE + node as u16)
+    }
+
+    /// Get the CANopen function code of the ID, i.e. the upper 4 bits of a standard ID
+    ///
+    /// Returns None for extended IDs, which are not part of the CANopen pre-defined connection set.
+    pub fn function_code(&self) -> Option<u8> {
+        match self {
+            CanId::Extended(_) => None,
+            CanId::Std(id) => Some(((id >> 7) & 0xF) as u8),
+        }
+    }
+
+    /// Get the node ID encoded in the ID, i.e. the lower 7 bits of a standard ID
+    ///
+    /// This is only meaningful for the node specific COB IDs of the pre-defined connection set.
+    /// Returns None for extended IDs.
+    pub fn node_id(&self) -> Option<u8> {
+        match self {
+            CanId::Extended(_) => None,
+            CanId::Std(id) => Some((id & 0x7F) as u8),
+        }
+    }
+
     /// Get the raw ID as a u32
     pub fn raw(&self) -> u32 {
         match self {
@@ -49,20 +116,56 @@ impl CanId {
 const MAX_DATA_LENGTH: usize = 8;
 
 /// A struct to contain a CanMessage
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+///
+/// Received messages may carry a receive timestamp, when the transport is able to provide one. The
+/// timestamp is not considered when comparing messages.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CanMessage {
     /// The data payload of the message
     ///
-    /// Note, some bytes may be unused. Check dlc.
+    /// Only the first `dlc` bytes are valid, and the rest may hold stale data. Use
+    /// [`data`](Self::data) to access the valid bytes.
     pub data: [u8; MAX_DATA_LENGTH],
     /// The length of the data payload
+    ///
+    /// Values above 8 are treated as 8, as for a classic CAN frame.
     pub dlc: u8,
     /// Indicates this message is a remote transmission request
     pub rtr: bool,
     /// The id of this message
     pub id: CanId,
+    /// The time at which the message was received, in microseconds
+    ///
+    /// The time base is defined by the transport which received the message. The socketcan
+    /// transport provides the kernel receive time, as microseconds since the UNIX epoch. Messages
+    /// created locally have no timestamp.
+    pub timestamp_us: Option<u64>,
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for CanMessage {
+    fn format(&self, f: defmt::Formatter) {
+        // Only the valid bytes of the payload are printed, and the timestamp is omitted
+        defmt::write!(
+            f,
+            "CanMessage {{ id: {}, rtr: {}, data: {=[u8]:x} }}",
+            self.id,
+            self.rtr,
+            self.data()
+        )
+    }
+}
+
+impl PartialEq for CanMessage {
+    fn eq(&self, other: &Self) -> bool {
+        // Bytes beyond the DLC are not part of the message
+        self.data() == other.data() && self.rtr == other.rtr && self.id == other.id
+    }
+}
+
+impl Eq for CanMessage {}
+
 impl Default for CanMessage {
     fn default() -> Self {
         Self {
@@ -70,30 +173,43 @@ impl Default for CanMessage {
             dlc: 0,
             id: CanId::Std(0),
             rtr: false,
+            timestamp_us: None,
         }
     }
 }
 
 impl CanMessage {
     /// Create a new CAN message
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is longer than 8 bytes. Use [`try_new`](Self::try_new) when the length is
+    /// not known to be valid.
     pub fn new(id: CanId, data: &[u8]) -> Self {
-        let dlc = data.len() as u8;
-        if dlc > MAX_DATA_LENGTH as u8 {
-            panic!(
+        match Self::try_new(id, data) {
+            Ok(msg) => msg,
+            Err(_) => panic!(
                 "Data length exceeds maximum size of {} bytes",
                 MAX_DATA_LENGTH
-            );
+            ),
+        }
+    }
+
+    /// Create a new CAN message, or return an error if `data` is longer than 8 bytes
+    pub fn try_new(id: CanId, data: &[u8]) -> Result<Self, MessageError> {
+        if data.len() > MAX_DATA_LENGTH {
+            return Err(MessageError::MessageTooLong { len: data.len() });
         }
         let mut buf = [0u8; MAX_DATA_LENGTH];
-        buf[0..dlc as usize].copy_from_slice(data);
-        let rtr = false;
+        buf[..data.len()].copy_from_slice(data);
 
-        Self {
+        Ok(Self {
             id,
-            dlc,
+            dlc: data.len() as u8,
             data: buf,
-            rtr,
-        }
+            rtr: false,
+            timestamp_us: None,
+        })
     }
 
     /// Create a new RTR message
@@ -113,14 +229,57 @@ impl CanMessage {
     }
 
     /// Get a slice containing the data payload
+    ///
+    /// Only the valid bytes are included, so the length of the slice is the DLC of the message.
     pub fn data(&self) -> &[u8] {
-        &self.data[0..self.dlc as usize]
+        &self.data[..self.dlc() as usize]
+    }
+
+    /// Get the number of valid bytes in the data payload, from 0 to 8
+    ///
+    /// Remote transmission requests have no payload, so this is always 0 for them.
+    pub fn dlc(&self) -> u8 {
+        self.dlc.min(MAX_DATA_LENGTH as u8)
     }
 
     /// Returns true if this message is a remote transmission request
     pub fn is_rtr(&self) -> bool {
         self.rtr
     }
+
+    /// Return a copy of the message with the receive timestamp set
+    pub fn with_timestamp(mut self, timestamp_us: u64) -> Self {
+        self.timestamp_us = Some(timestamp_us);
+        self
+    }
+
+    /// Get the receive timestamp of the message, if known
+    pub fn timestamp_us(&self) -> Option<u64> {
+        self.timestamp_us
+    }
+
+    /// Get the receive time of the message as a [`std::time::SystemTime`]
+    ///
+    /// This assumes the timestamp is relative to the UNIX epoch, as it is for messages received
+    /// via socketcan.
+    #[cfg(feature = "std")]
+    pub fn receive_time(&self) -> Option<std::time::SystemTime> {
+        self.timestamp_us
+            .map(|t| std::time::UNIX_EPOCH + std::time::Duration::from_micros(t))
+    }
+
+    /// Get the receive time of the message as a [`std::time::Instant`]
+    ///
+    /// Messages without a timestamp are assumed to have been received just now, so this can be used
+    /// in place of `Instant::now()` when recording the arrival time of a message.
+    #[cfg(feature = "std")]
+    pub fn receive_instant(&self) -> std::time::Instant {
+        let now = std::time::Instant::now();
+        self.receive_time()
+            .and_then(|t| std::time::SystemTime::now().duration_since(t).ok())
+            .and_then(|age| now.checked_sub(age))
+            .unwrap_or(now)
+    }
 }
 
 /// The error codes which can be delivered in a CAN frame
@@ -128,6 +287,8 @@ impl CanMessage {
 /// These are set by a receiver when it detects an error in a received frame, and received globally
 /// by all nodes on the bus
 #[derive(Clone, Copy, Debug, Snafu)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum CanError {
     /// The transmitter detected a different value on the bus than the value is was transmitting at
@@ -162,6 +323,7 @@ impl CanError {
 
 /// The NMT state transition command specifier
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum NmtCommandSpecifier {
@@ -208,6 +370,7 @@ pub const SDO_RESP_BASE: u16 = 0x580;
 
 /// An NmtCommand message
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct NmtCommand {
     /// Specifies the type of command
@@ -251,6 +414,7 @@ impl From<NmtCommand> for CanMessage {
 
 /// Possible NMT states for a node
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum NmtState {
@@ -278,6 +442,7 @@ impl core::fmt::Display for NmtState {
 }
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// An error for [`NmtState::try_from()`]
 pub struct InvalidNmtStateError(u8);
 
@@ -301,6 +466,7 @@ impl TryFrom<u8> for NmtState {
 
 /// A Heartbeat message
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Heartbeat {
     /// The ID of the node transmitting the heartbeat
@@ -311,26 +477,57 @@ pub struct Heartbeat {
     pub state: NmtState,
 }
 
-impl From<Heartbeat> for CanMessage {
-    fn from(value: Heartbeat) -> Self {
+impl Heartbeat {
+    /// Create a heartbeat message on the given COB-ID, instead of the standard 0x700 + node ID
+    ///
+    /// This is used for a secondary heartbeat, e.g. on a redundant network.
+    pub fn to_can_message(self, id: CanId) -> CanMessage {
         let mut msg = CanMessage {
-            id: CanId::Std(HEARTBEAT_ID | value.node as u16),
+            id,
             dlc: 1,
             ..Default::default()
         };
-        msg.data[0] = value.state as u8;
-        if value.toggle {
+        msg.data[0] = self.state as u8;
+        if self.toggle {
             msg.data[0] |= 1 << 7;
         }
         msg
     }
+
+    /// Decode a heartbeat sent by `node`, regardless of the COB-ID it was received on
+    pub fn decode(node: u8, msg: &CanMessage) -> Result<Self, MessageError> {
+        // Remote requests on the heartbeat COB ID are node guarding requests
+        if msg.is_rtr() {
+            return Err(MessageError::MalformedMsg { cob_id: msg.id() });
+        }
+        let byte = *msg.data().first().ok_or(MessageError::MessageTooShort)?;
+        let toggle = (byte & (1 << 7)) != 0;
+        let state: NmtState = (byte & 0x7f)
+            .try_into()
+            .map_err(|e: InvalidNmtStateError| MessageError::InvalidNmtState { value: e.0 })?;
+        Ok(Heartbeat {
+            node,
+            toggle,
+            state,
+        })
+    }
+}
+
+impl From<Heartbeat> for CanMessage {
+    fn from(value: Heartbeat) -> Self {
+        value.to_can_message(CanId::heartbeat(value.node))
+    }
 }
 /// Represents a SYNC object/message
 ///
 /// A single CAN node can serve as the SYNC provider, sending a periodic sync object to all other
 /// nodes. The one byte count value starts at 1, and increments. On overflow, it should be reset to
 /// 1.
+///
+/// A SYNC producer which is not configured to send a counter sends a message with no data. This is
+/// represented by a count of 0, which is not a valid counter value.
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct SyncObject {
     count: u8,
@@ -341,6 +538,11 @@ impl SyncObject {
     pub fn new(count: u8) -> Self {
         Self { count }
     }
+
+    /// Get the counter value of the SYNC
+    pub fn count(&self) -> u8 {
+        self.count
+    }
 }
 
 impl Default for SyncObject {
@@ -351,14 +553,18 @@ impl Default for SyncObject {
 
 impl From<SyncObject> for CanMessage {
     fn from(value: SyncObject) -> Self {
-        CanMessage::new(SYNC_ID, &[value.count])
+        if value.count == 0 {
+            CanMessage::new(SYNC_ID, &[])
+        } else {
+            CanMessage::new(SYNC_ID, &[value.count])
+        }
     }
 }
 
 impl From<CanMessage> for SyncObject {
     fn from(msg: CanMessage) -> Self {
         if msg.id() == SYNC_ID {
-            let count = msg.data()[0];
+            let count = msg.data().first().copied().unwrap_or(0);
             Self { count }
         } else {
             panic!("Invalid message ID for SyncObject");
@@ -373,24 +579,16 @@ impl TryFrom<CanMessage> for ZencanMessage {
         let cob_id = msg.id();
         if cob_id == NMT_CMD_ID {
             Ok(ZencanMessage::NmtCommand(msg.try_into()?))
-        } else if cob_id.raw() & !0x7f == HEARTBEAT_ID as u32 {
-            let node = (cob_id.raw() & 0x7f) as u8;
-            let toggle = (msg.data[0] & (1 << 7)) != 0;
-            let state: NmtState = (msg.data[0] & 0x7f)
-                .try_into()
-                .map_err(|e: InvalidNmtStateError| MessageError::InvalidNmtState { value: e.0 })?;
-            Ok(ZencanMessage::Heartbeat(Heartbeat {
-                node,
-                toggle,
-                state,
-            }))
-        } else if cob_id.raw() & 0xff80 == 0x580 {
+        } else if cob_id.function_code() == CanId::heartbeat(0).function_code() {
+            let node = cob_id.node_id().unwrap();
+            Ok(ZencanMessage::Heartbeat(Heartbeat::decode(node, &msg)?))
+        } else if cob_id.function_code() == CanId::sdo_tx(0).function_code() {
             // SDO response
             let resp: SdoResponse = msg
                 .try_into()
                 .map_err(|_| MessageError::MalformedMsg { cob_id })?;
             Ok(ZencanMessage::SdoResponse(resp))
-        } else if cob_id.raw() >= 0x580 && cob_id.raw() <= 0x580 + 256 {
+        } else if cob_id.function_code() == CanId::sdo_rx(0).function_code() {
             // SDO request
             let req: SdoRequest = msg
                 .data()
@@ -419,6 +617,7 @@ impl TryFrom<CanMessage> for ZencanMessage {
 
 /// An enum representing all of the standard messages
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[allow(missing_docs)]
 pub enum ZencanMessage {
@@ -433,9 +632,17 @@ pub enum ZencanMessage {
 
 /// An error for problems converting CanMessages to zencan types
 #[derive(Debug, Clone, Copy, PartialEq, Snafu)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum MessageError {
     /// Not enough bytes were present in the message
     MessageTooShort,
+    /// More than 8 data bytes were provided for a message
+    #[snafu(display("Message data length {len} exceeds 8 bytes"))]
+    MessageTooLong {
+        /// The length of the data provided
+        len: usize,
+    },
     /// The message was malformed in some way
     MalformedMsg {
         /// The COB ID of the malformed message
@@ -470,3 +677,128 @@ pub enum MessageError {
         value: u8,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp_ignored_in_comparison() {
+        let msg = CanMessage::new(CanId::std(0x181), &[1, 2, 3]);
+        let stamped = msg.with_timestamp(1_000_000);
+        assert_eq!(Some(1_000_000), stamped.timestamp_us());
+        assert_eq!(None, msg.timestamp_us());
+        assert_eq!(msg, stamped);
+    }
+
+    #[test]
+    fn test_dlc() {
+        let msg = CanMessage::new(CanId::std(0x181), &[1, 2, 3]);
+        assert_eq!(3, msg.dlc());
+        assert_eq!(&[1, 2, 3], msg.data());
+        assert_eq!(
+            Err(MessageError::MessageTooLong { len: 9 }),
+            CanMessage::try_new(CanId::std(0x181), &[0; 9])
+        );
+        assert_eq!(0, CanMessage::new_rtr(CanId::std(0x701)).dlc());
+
+        // Bytes beyond the DLC are not part of the message
+        let mut stale = msg;
+        stale.data[5] = 0xff;
+        assert_eq!(msg, stale);
+        stale.dlc = 6;
+        assert_ne!(msg, stale);
+        stale.dlc = 12;
+        assert_eq!(8, stale.dlc());
+        assert_eq!(8, stale.data().len());
+    }
+
+    #[test]
+    fn test_short_messages() {
+        let decode = |msg: CanMessage| ZencanMessage::try_from(msg).map(|_| ());
+
+        // Heartbeats are one byte, and NMT commands are two
+        let mut heartbeat: CanMessage = Heartbeat {
+            node: 1,
+            toggle: false,
+            state: NmtState::Operational,
+        }
+        .into();
+        assert_eq!(1, heartbeat.dlc());
+        assert_eq!(Ok(()), decode(heartbeat));
+        heartbeat.dlc = 0;
+        assert_eq!(Err(MessageError::MessageTooShort), decode(heartbeat));
+        assert!(decode(CanMessage::new_rtr(CanId::heartbeat(1))).is_err());
+
+        // A heartbeat on another COB-ID decodes the same
+        let secondary = Heartbeat {
+            node: 1,
+            toggle: true,
+            state: NmtState::Stopped,
+        }
+        .to_can_message(CanId::std(0x681));
+        assert_eq!(CanId::std(0x681), secondary.id());
+        let decoded = Heartbeat::decode(1, &secondary).unwrap();
+        assert!(decoded.toggle);
+        assert_eq!(NmtState::Stopped, decoded.state);
+
+        let mut nmt: CanMessage = NmtCommand {
+            cs: NmtCommandSpecifier::Start,
+            node: 0,
+        }
+        .into();
+        assert_eq!(Ok(()), decode(nmt));
+        nmt.dlc = 1;
+        assert_eq!(Err(MessageError::MessageTooShort), decode(nmt));
+
+        // A SYNC may be sent without a counter
+        let sync = SyncObject::from(CanMessage::new(SYNC_ID, &[]));
+        assert_eq!(0, sync.count());
+        assert_eq!(0, CanMessage::from(sync).dlc());
+        let sync = SyncObject::from(CanMessage::new(SYNC_ID, &[7]));
+        assert_eq!(7, sync.count());
+        assert_eq!(&[7], CanMessage::from(sync).data());
+    }
+
+    #[test]
+    fn test_can_id_constructors() {
+        assert_eq!(CanId::std(0x585), CanId::sdo_tx(5));
+        assert_eq!(CanId::std(0x605), CanId::sdo_rx(5));
+        assert_eq!(CanId::std(0x185), CanId::tpdo(1, 5));
+        assert_eq!(CanId::std(0x485), CanId::tpdo(4, 5));
+        assert_eq!(CanId::std(0x205), CanId::rpdo(1, 5));
+        assert_eq!(CanId::std(0x505), CanId::rpdo(4, 5));
+        assert_eq!(CanId::std(0x77F), CanId::heartbeat(127));
+        assert_eq!(CanId::std(0x81), CanId::emcy(1));
+
+        assert_eq!(Some(0xB), CanId::sdo_tx(5).function_code());
+        assert_eq!(Some(5), CanId::sdo_tx(5).node_id());
+        assert_eq!(Some(0), NMT_CMD_ID.function_code());
+        assert_eq!(None, CanId::extended(0x585).function_code());
+        assert_eq!(None, CanId::extended(0x585).node_id());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_tpdo_zero() {
+        CanId::tpdo(0, 5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_tpdo_out_of_range() {
+        CanId::tpdo(5, 5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rpdo_zero() {
+        CanId::rpdo(0, 5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rpdo_out_of_range() {
+        CanId::rpdo(5, 5);
+    }
+}
@@ -5,6 +5,11 @@
 /// configured devices, with the special value of 255 used to represent an unconfigured device.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "u8", into = "u8")
+)]
 pub enum NodeId {
     /// A special node ID indicating the node is not configured (255)
     Unconfigured,
@@ -78,6 +83,7 @@ impl NodeId {
 
 /// Error for converting u8 to a NodeId
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct InvalidNodeIdError;
 
 impl core::fmt::Display for InvalidNodeIdError {
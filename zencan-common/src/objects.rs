@@ -3,6 +3,8 @@
 
 /// A container for the address of a subobject
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct ObjectId {
     /// Object index
     pub index: u16,
@@ -10,10 +12,18 @@ pub struct ObjectId {
     pub sub: u8,
 }
 
+impl From<(u16, u8)> for ObjectId {
+    fn from((index, sub): (u16, u8)) -> Self {
+        Self { index, sub }
+    }
+}
+
 /// Object Code value
 ///
 /// Defines the type of an object or sub object
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum ObjectCode {
     /// An empty object
@@ -56,6 +66,8 @@ impl TryFrom<u8> for ObjectCode {
 
 /// Access type enum
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum AccessType {
     /// Read-only
     #[default]
@@ -82,6 +94,8 @@ impl AccessType {
 
 /// Possible PDO mapping values for an object
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PdoMapping {
     /// Object cannot be mapped to PDOs
     #[default]
@@ -96,6 +110,8 @@ pub enum PdoMapping {
 
 /// Indicate the type of data stored in an object
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u16)]
 pub enum DataType {
     /// A true false value, encoded as a single byte, with 0 for false and 1 for true
@@ -128,6 +144,12 @@ pub enum DataType {
     /// An arbitrary byte access type for e.g. data streams, or large chunks of
     /// data. Size is typically not known at build time.
     Domain = 0xf,
+    /// A 64-bit floating point value
+    Real64 = 0x11,
+    /// A signed 64-bit integer
+    Int64 = 0x15,
+    /// An unsigned 64-bit integer
+    UInt64 = 0x1b,
     /// A contained for an unrecognized data type value
     Other(u16),
 }
@@ -148,6 +170,9 @@ impl From<u16> for DataType {
             0xa => OctetString,
             0xb => UnicodeString,
             0xf => Domain,
+            0x11 => Real64,
+            0x15 => Int64,
+            0x1b => UInt64,
             _ => Other(value),
         }
     }
@@ -165,6 +190,8 @@ impl DataType {
 
 /// Information about a sub object
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct SubInfo {
     /// The size (or max size) of this sub object, in bytes
     pub size: usize,
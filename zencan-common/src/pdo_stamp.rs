@@ -0,0 +1,125 @@
+//! Stamps embedded in the last bytes of TPDOs
+//!
+//! As a zencan extension, a node can be configured to overwrite the last byte(s) of each message
+//! sent by a TPDO with a stamp, which allows a receiver of cyclic data to detect messages which
+//! were dropped, or which carry stale data. The stamp is configured per TPDO by the `tpdo_stamps`
+//! list in the `[pdos]` section of the device config.
+//!
+//! | Stamp          | Bytes | Description |
+//! | -------------- | ----- | ----------- |
+//! | `sync_counter` | 7     | The counter of the most recently received SYNC |
+//! | `timestamp`    | 6-7   | A u16, in milliseconds from the clock passed to the node |
+//!
+//! When the SYNC producer does not send a counter, the node counts the SYNCs it receives instead,
+//! from 1 to 255. The timestamp wraps every 65.536 seconds.
+//!
+//! Mapped data which would overlap the stamp is not sent, so the mappings of a stamped TPDO should
+//! total at most [`TpdoStamp::payload_size`] bytes.
+
+/// The size of a TPDO message, in bytes
+const PDO_SIZE: usize = 8;
+
+/// The type of stamp embedded in a TPDO
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TpdoStamp {
+    /// The TPDO carries only mapped data
+    #[default]
+    None,
+    /// The last byte carries the SYNC counter
+    SyncCounter,
+    /// The last two bytes carry a rolling timestamp in milliseconds
+    Timestamp,
+}
+
+/// A stamp value read from a received TPDO
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StampValue {
+    /// The counter of the SYNC which preceded the TPDO
+    SyncCounter(u8),
+    /// The time at which the TPDO was sent, in milliseconds
+    Timestamp(u16),
+}
+
+impl TpdoStamp {
+    /// The number of bytes used by the stamp
+    pub const fn size(&self) -> usize {
+        match self {
+            TpdoStamp::None => 0,
+            TpdoStamp::SyncCounter => 1,
+            TpdoStamp::Timestamp => 2,
+        }
+    }
+
+    /// The number of bytes available for mapped data
+    pub const fn payload_size(&self) -> usize {
+        PDO_SIZE - self.size()
+    }
+
+    /// Write the stamp into the last bytes of an 8 byte TPDO
+    ///
+    /// # Arguments
+    /// * `data` - The TPDO data
+    /// * `sync_count` - The counter of the most recently received SYNC
+    /// * `now_us` - The current time, in microseconds
+    pub fn write(&self, data: &mut [u8; PDO_SIZE], sync_count: u8, now_us: u64) {
+        match self {
+            TpdoStamp::None => (),
+            TpdoStamp::SyncCounter => data[7] = sync_count,
+            TpdoStamp::Timestamp => {
+                let ms = (now_us / 1000) as u16;
+                data[6..8].copy_from_slice(&ms.to_le_bytes());
+            }
+        }
+    }
+
+    /// Read the stamp from a received TPDO
+    ///
+    /// Returns None if the TPDO has no stamp, or is too short to contain one.
+    pub fn read(&self, data: &[u8]) -> Option<StampValue> {
+        if data.len() < PDO_SIZE {
+            return None;
+        }
+        match self {
+            TpdoStamp::None => None,
+            TpdoStamp::SyncCounter => Some(StampValue::SyncCounter(data[7])),
+            TpdoStamp::Timestamp => Some(StampValue::Timestamp(u16::from_le_bytes([
+                data[6], data[7],
+            ]))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read() {
+        let mut data = [0xAA; 8];
+        TpdoStamp::None.write(&mut data, 3, 5_000);
+        assert_eq!([0xAA; 8], data);
+        assert_eq!(None, TpdoStamp::None.read(&data));
+
+        TpdoStamp::SyncCounter.write(&mut data, 3, 5_000);
+        assert_eq!([0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 3], data);
+        assert_eq!(
+            Some(StampValue::SyncCounter(3)),
+            TpdoStamp::SyncCounter.read(&data)
+        );
+
+        // The timestamp wraps at 65536 ms
+        TpdoStamp::Timestamp.write(&mut data, 3, 65_536_000 + 258_999);
+        assert_eq!([0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 2, 1], data);
+        assert_eq!(
+            Some(StampValue::Timestamp(258)),
+            TpdoStamp::Timestamp.read(&data)
+        );
+        assert_eq!(None, TpdoStamp::Timestamp.read(&data[..7]));
+        assert_eq!(6, TpdoStamp::Timestamp.payload_size());
+    }
+}
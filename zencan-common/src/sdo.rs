@@ -38,6 +38,8 @@ impl TryFrom<u8> for ServerCommand {
 ///
 /// Defines the various reasons an SDO transfer can be aborted
 #[derive(Clone, Copy, Debug, PartialEq, IntEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u32)]
 pub enum AbortCode {
     /// Toggle bit not alternated
@@ -100,6 +102,118 @@ pub enum AbortCode {
     NoData = 0x0800_0024,
 }
 
+impl AbortCode {
+    /// Get a human readable description of the abort code
+    pub fn description(&self) -> &'static str {
+        use AbortCode::*;
+        match self {
+            ToggleNotAlternated => "Toggle bit not alternated",
+            SdoTimeout => "SDO protocol timed out",
+            InvalidCommandSpecifier => "Client/server command specifier not valid or unknown",
+            InvalidBlockSize => "Invalid block size",
+            InvalidSequenceNumber => "Invalid sequence number",
+            CrcError => "CRC error",
+            OutOfMemory => "Out of memory",
+            UnsupportedAccess => "Unsupported access to an object",
+            WriteOnly => "Attempt to read a write only object",
+            ReadOnly => "Attempt to write a read only object",
+            NoSuchObject => "Object does not exist in the object dictionary",
+            UnnallowedPdo => "Object cannot be mapped to the PDO",
+            PdoTooLong => {
+                "The number and length of the objects to be mapped would exceed PDO length"
+            }
+            IncompatibleParameter => "General parameter incompatibility",
+            HardwareError => "Access failed due to a hardware error",
+            DataTypeMismatch => {
+                "Data type does not match, length of service parameter does not match"
+            }
+            DataTypeMismatchLengthHigh => {
+                "Data type does not match, length of service parameter too high"
+            }
+            DataTypeMismatchLengthLow => {
+                "Data type does not match, length of service parameter too low"
+            }
+            NoSuchSubIndex => "Sub-index does not exist",
+            InvalidValue => "Invalid value for parameter",
+            ValueTooHigh => "Value of parameter written too high",
+            ValueTooLow => "Value of parameter written too low",
+            ResourceNotAvailable => "Resource not available",
+            GeneralError => "General error",
+            CantStore => "Data cannot be transferred or stored to the application",
+            CantStoreLocalControl => {
+                "Data cannot be transferred or stored to the application because of local control"
+            }
+            CantStoreDeviceState => {
+                "Data cannot be transferred or stored to the application because of the present \
+                 device state"
+            }
+            NoObjectDict => "No object dictionary is present",
+            NoData => "No data available",
+        }
+    }
+
+    /// Returns true if the abort indicates a transient condition, so that repeating the same
+    /// request later may succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            AbortCode::SdoTimeout
+                | AbortCode::OutOfMemory
+                | AbortCode::ResourceNotAvailable
+                | AbortCode::CantStoreLocalControl
+                | AbortCode::CantStoreDeviceState
+        )
+    }
+
+    /// Returns true if the abort indicates the object cannot be accessed in the requested way,
+    /// e.g. because it does not exist or is read only
+    pub fn is_access_error(&self) -> bool {
+        matches!(
+            self,
+            AbortCode::UnsupportedAccess
+                | AbortCode::WriteOnly
+                | AbortCode::ReadOnly
+                | AbortCode::NoSuchObject
+                | AbortCode::NoSuchSubIndex
+        )
+    }
+
+    /// Returns true if the abort indicates an error in the SDO protocol itself, rather than in the
+    /// accessed object
+    pub fn is_protocol_error(&self) -> bool {
+        matches!(
+            self,
+            AbortCode::ToggleNotAlternated
+                | AbortCode::SdoTimeout
+                | AbortCode::InvalidCommandSpecifier
+                | AbortCode::InvalidBlockSize
+                | AbortCode::InvalidSequenceNumber
+                | AbortCode::CrcError
+        )
+    }
+
+    /// Returns true if the abort indicates the written value was rejected, e.g. because it was out
+    /// of range or the wrong size
+    pub fn is_value_error(&self) -> bool {
+        matches!(
+            self,
+            AbortCode::DataTypeMismatch
+                | AbortCode::DataTypeMismatchLengthHigh
+                | AbortCode::DataTypeMismatchLengthLow
+                | AbortCode::InvalidValue
+                | AbortCode::ValueTooHigh
+                | AbortCode::ValueTooLow
+                | AbortCode::IncompatibleParameter
+        )
+    }
+}
+
+impl core::fmt::Display for AbortCode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} (0x{:08X})", self.description(), *self as u32)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 enum ClientCommand {
@@ -176,6 +290,7 @@ impl BlockSegment {
             dlc: 8,
             rtr: false,
             id,
+            timestamp_us: None,
         }
     }
 }
@@ -266,6 +381,7 @@ impl TryFrom<u8> for BlockUploadServerSubcommand {
 ///
 /// This represents the possible request messages which can be send from client to server
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SdoRequest {
     /// Begin a download, writing data to an object on the server
@@ -660,6 +776,7 @@ impl TryFrom<&[u8]> for SdoRequest {
 
 /// Represents a response from SDO server to client
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SdoResponse {
     /// Response to an [`SdoRequest::InitiateUpload`]
@@ -755,6 +872,11 @@ pub enum SdoResponse {
 impl TryFrom<CanMessage> for SdoResponse {
     type Error = ();
     fn try_from(msg: CanMessage) -> Result<Self, Self::Error> {
+        // SDO responses are always 8 bytes. Shorter frames are rejected, rather than decoding the
+        // stale bytes beyond their DLC.
+        if msg.is_rtr() || msg.data().len() < 8 {
+            return Err(());
+        }
         let scs = msg.data[0] >> 5;
         let command: ServerCommand = scs.try_into()?;
         match command {
@@ -1022,3 +1144,86 @@ impl SdoResponse {
         CanMessage::new(id, &payload)
     }
 }
+
+/// The type of transfer in progress on an SDO server
+///
+/// The value is reported by sub 1 of the SDO server status object (0x5005).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, IntEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum SdoTransferState {
+    /// No transfer is in progress
+    #[default]
+    Idle = 0,
+    /// A segmented download is in progress
+    SegmentedDownload = 1,
+    /// A segmented upload is in progress
+    SegmentedUpload = 2,
+    /// A block download is in progress
+    BlockDownload = 3,
+}
+
+/// The state of an SDO server, for diagnosing transfers which do not complete
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SdoServerStatus {
+    /// The type of transfer in progress
+    pub state: SdoTransferState,
+    /// The index of the object being transferred, or 0 when idle
+    pub index: u16,
+    /// The sub index of the object being transferred, or 0 when idle
+    pub sub: u8,
+    /// The number of data bytes transferred so far
+    ///
+    /// For block downloads, only the data in blocks which have been acknowledged is counted.
+    pub bytes: u32,
+}
+
+impl SdoServerStatus {
+    /// The status of a server with no transfer in progress
+    pub const IDLE: SdoServerStatus = SdoServerStatus {
+        state: SdoTransferState::Idle,
+        index: 0,
+        sub: 0,
+        bytes: 0,
+    };
+
+    /// Returns true if a transfer is in progress
+    pub fn is_active(&self) -> bool {
+        self.state != SdoTransferState::Idle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abort_code_display() {
+        assert_eq!(
+            "Attempt to write a read only object (0x06010002)",
+            AbortCode::ReadOnly.to_string()
+        );
+        assert!(AbortCode::ReadOnly.is_access_error());
+        assert!(!AbortCode::ReadOnly.is_retryable());
+        assert!(AbortCode::SdoTimeout.is_retryable());
+        assert!(AbortCode::SdoTimeout.is_protocol_error());
+        assert!(AbortCode::ValueTooHigh.is_value_error());
+    }
+
+    #[test]
+    fn test_short_response_rejected() {
+        let id = CanId::sdo_tx(1);
+        let full = SdoResponse::download_acknowledge(0x1000, 0).to_can_message(id);
+        assert!(SdoResponse::try_from(full).is_ok());
+        for len in 0..8 {
+            // The bytes beyond the DLC are left in place, but must not be decoded
+            let mut msg = full;
+            msg.dlc = len;
+            assert!(SdoResponse::try_from(msg).is_err(), "Accepted DLC {len}");
+        }
+        assert!(SdoResponse::try_from(CanMessage::new_rtr(id)).is_err());
+    }
+}
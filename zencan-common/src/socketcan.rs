@@ -1,18 +1,17 @@
-use std::sync::Arc;
+use std::{
+    os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
 
 use crate::{
     messages::{CanError, CanId, CanMessage},
     traits::{AsyncCanReceiver, AsyncCanSender},
 };
+use defmt_or_log::{info, warn};
 use snafu::{ResultExt, Snafu};
-use socketcan::{tokio::CanSocket, CanFrame, EmbeddedFrame, Frame, ShouldRetry};
-
-fn socketcan_id_to_zencan_id(id: socketcan::CanId) -> CanId {
-    match id {
-        socketcan::CanId::Standard(id) => CanId::std(id.as_raw()),
-        socketcan::CanId::Extended(id) => CanId::extended(id.as_raw()),
-    }
-}
+use socketcan::{tokio::CanSocket, EmbeddedFrame, ShouldRetry};
+use tokio::io::unix::AsyncFd;
 
 fn zencan_id_to_socketcan_id(id: CanId) -> socketcan::CanId {
     match id {
@@ -21,13 +20,22 @@ fn zencan_id_to_socketcan_id(id: CanId) -> socketcan::CanId {
     }
 }
 
-fn socketcan_frame_to_zencan_message(frame: socketcan::CanFrame) -> Result<CanMessage, CanError> {
-    let id = socketcan_id_to_zencan_id(frame.can_id());
-
-    match frame {
-        CanFrame::Data(frame) => Ok(CanMessage::new(id, frame.data())),
-        CanFrame::Remote(_) => Ok(CanMessage::new_rtr(id)),
-        CanFrame::Error(frame) => Err(CanError::from_raw(frame.error_bits() as u8)),
+fn raw_frame_to_zencan_message(frame: &libc::can_frame) -> Result<CanMessage, CanError> {
+    if frame.can_id & libc::CAN_ERR_FLAG != 0 {
+        return Err(CanError::from_raw(
+            (frame.can_id & libc::CAN_ERR_MASK) as u8,
+        ));
+    }
+    let id = if frame.can_id & libc::CAN_EFF_FLAG != 0 {
+        CanId::extended(frame.can_id & libc::CAN_EFF_MASK)
+    } else {
+        CanId::std((frame.can_id & libc::CAN_SFF_MASK) as u16)
+    };
+    if frame.can_id & libc::CAN_RTR_FLAG != 0 {
+        Ok(CanMessage::new_rtr(id))
+    } else {
+        let len = (frame.can_dlc as usize).min(frame.data.len());
+        Ok(CanMessage::new(id, &frame.data[..len]))
     }
 }
 
@@ -41,31 +49,460 @@ fn zencan_message_to_socket_frame(frame: CanMessage) -> socketcan::CanFrame {
     }
 }
 
+/// Set a socket option from a slice of values
+fn set_socket_option<T>(
+    socket: &CanSocket,
+    level: libc::c_int,
+    name: libc::c_int,
+    values: &[T],
+) -> Result<(), socketcan::IoError> {
+    // Safety: the pointer and length describe the provided slice, which outlives the call
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            values.as_ptr() as *const libc::c_void,
+            core::mem::size_of_val(values) as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(socketcan::IoError::last_os_error())
+    }
+}
+
+/// The timestamps requested with `SO_TIMESTAMPING`: software and raw hardware receive timestamps
+const TIMESTAMPING_FLAGS: libc::c_uint = libc::SOF_TIMESTAMPING_RX_SOFTWARE
+    | libc::SOF_TIMESTAMPING_SOFTWARE
+    | libc::SOF_TIMESTAMPING_RX_HARDWARE
+    | libc::SOF_TIMESTAMPING_RAW_HARDWARE;
+
+/// Convert a timestamp to microseconds since the UNIX epoch, or None if it was not set
+fn timespec_to_us(ts: &libc::timespec) -> Option<u64> {
+    if ts.tv_sec <= 0 {
+        return None;
+    }
+    Some(ts.tv_sec as u64 * 1_000_000 + ts.tv_nsec as u64 / 1000)
+}
+
+/// Read a frame from a non-blocking socket, along with its receive timestamp in microseconds since
+/// the UNIX epoch
+///
+/// The timestamp is taken from the `SCM_TIMESTAMPING` control message. The raw hardware timestamp
+/// is preferred, when the interface provides one, and otherwise the kernel software timestamp is
+/// used.
+fn recv_frame(fd: BorrowedFd<'_>) -> std::io::Result<(libc::can_frame, Option<u64>)> {
+    // Safety: can_frame is plain data, for which all zeroes is valid
+    let mut frame: libc::can_frame = unsafe { core::mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: &mut frame as *mut libc::can_frame as *mut libc::c_void,
+        iov_len: core::mem::size_of::<libc::can_frame>(),
+    };
+    // Room for the SCM_TIMESTAMPING message, with u64 elements for alignment
+    let mut control = [0u64; 16];
+    // Safety: msghdr is plain data, for which all zeroes is valid
+    let mut msg: libc::msghdr = unsafe { core::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = core::mem::size_of_val(&control) as _;
+
+    // Safety: msg points to the frame and control buffers, which outlive the call
+    let len = unsafe { libc::recvmsg(fd.as_raw_fd(), &mut msg, libc::MSG_DONTWAIT) };
+    if len < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if len as usize != core::mem::size_of::<libc::can_frame>() {
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidData));
+    }
+
+    let mut timestamp = None;
+    // Safety: the control messages were written by the kernel into the buffer described by msg,
+    // and the CMSG macros stay within it
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_TIMESTAMPING
+            {
+                // The software, deprecated, and raw hardware timestamps
+                let stamps = (libc::CMSG_DATA(cmsg) as *const [libc::timespec; 3]).read_unaligned();
+                timestamp = timespec_to_us(&stamps[2]).or_else(|| timespec_to_us(&stamps[0]));
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Ok((frame, timestamp))
+}
+
+/// Convert a frame read by [`recv_frame`], applying its timestamp
+fn timestamped((frame, timestamp): (libc::can_frame, Option<u64>)) -> Result<CanMessage, CanError> {
+    let msg = raw_frame_to_zencan_message(&frame)?;
+    Ok(match timestamp {
+        Some(t) => msg.with_timestamp(t),
+        None => msg,
+    })
+}
+
+/// Returns true if the error indicates the interface has been taken down
+fn is_interface_down(e: &socketcan::IoError) -> bool {
+    e.raw_os_error() == Some(libc::ENETDOWN)
+}
+
+/// Returns true if the error indicates the interface no longer exists, and the socket must be
+/// re-opened
+fn is_interface_gone(e: &socketcan::IoError) -> bool {
+    matches!(e.raw_os_error(), Some(libc::ENODEV) | Some(libc::ENXIO))
+}
+
+/// The state of a socketcan interface, as observed by a [`SocketCanReceiver`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterfaceState {
+    /// The interface is up, and frames are being received
+    Up,
+    /// The interface has been taken down. Frames will be received again when it is brought up.
+    Down,
+    /// The interface has been removed, and the socket must be re-opened
+    Removed,
+}
+
+/// Builder for opening a socketcan interface with non-default socket options
+///
+/// [`open_socketcan`] opens an interface with the default options, which receives all frames.
+///
+/// # Example
+///
+/// ```no_run
+/// use zencan_common::SocketCanTransport;
+///
+/// let (tx, rx) = SocketCanTransport::new("can0")
+///     .node_filters(5)
+///     .error_frames(true)
+///     .rx_buffer_size(256 * 1024)
+///     .auto_reopen(true)
+///     .open()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SocketCanTransport {
+    device: String,
+    filters: Vec<libc::can_filter>,
+    error_frames: bool,
+    recv_own_msgs: bool,
+    rx_buffer_size: Option<usize>,
+    auto_reopen: bool,
+    reopen_interval: Duration,
+}
+
+impl SocketCanTransport {
+    /// Create a builder for the named device, e.g. "vcan0" or "can0"
+    pub fn new(device: impl Into<String>) -> Self {
+        Self {
+            device: device.into(),
+            filters: Vec::new(),
+            error_frames: false,
+            recv_own_msgs: false,
+            rx_buffer_size: None,
+            auto_reopen: false,
+            reopen_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Add a kernel-side filter, accepting frames where `received_id & mask == id & mask`
+    ///
+    /// Standard and extended IDs are always distinguished. If no filters are added, all frames are
+    /// received.
+    pub fn filter(mut self, id: CanId, mask: u32) -> Self {
+        let can_id = match id {
+            CanId::Std(id) => id as u32,
+            CanId::Extended(id) => id | libc::CAN_EFF_FLAG,
+        };
+        self.filters.push(libc::can_filter {
+            can_id,
+            can_mask: mask | libc::CAN_EFF_FLAG,
+        });
+        self
+    }
+
+    /// Add a filter which accepts only the given ID
+    pub fn filter_id(self, id: CanId) -> Self {
+        let mask = if id.is_extended() {
+            libc::CAN_EFF_MASK
+        } else {
+            libc::CAN_SFF_MASK
+        };
+        self.filter(id, mask)
+    }
+
+    /// Add filters for the frames a node with the given ID consumes, using the default COB-IDs
+    ///
+    /// This accepts NMT, SYNC, TIME, LSS requests, SDO requests to the node, and the four default
+    /// RPDO COB-IDs of the node. RPDOs configured with other COB-IDs must be added with
+    /// [`filter_id`](Self::filter_id).
+    ///
+    /// Note that the filters are fixed when the socket is opened, so they will not follow a change
+    /// of node ID via LSS.
+    pub fn node_filters(self, node_id: u8) -> Self {
+        let mut builder = self
+            .filter_id(crate::messages::NMT_CMD_ID)
+            .filter_id(crate::messages::SYNC_ID)
+            .filter_id(CanId::std(0x100))
+            .filter_id(crate::messages::LSS_REQ_ID);
+        if (1..=127).contains(&node_id) {
+            builder = builder.filter_id(CanId::sdo_rx(node_id));
+            for n in 1..=4 {
+                builder = builder.filter_id(CanId::rpdo(n, node_id));
+            }
+        }
+        builder
+    }
+
+    /// Subscribe to error frames
+    ///
+    /// When enabled, error frames are returned from the receiver as [`ReceiveError::Can`].
+    pub fn error_frames(mut self, enable: bool) -> Self {
+        self.error_frames = enable;
+        self
+    }
+
+    /// Receive the frames sent on the socket
+    ///
+    /// When enabled, each frame sent is also returned from the receiver once it has been
+    /// transmitted, which some applications use to confirm transmission. The sender reports this
+    /// with [`AsyncCanSender::echoes_sent_frames`], so that the zencan client can recognize the
+    /// echoes.
+    pub fn recv_own_msgs(mut self, enable: bool) -> Self {
+        self.recv_own_msgs = enable;
+        self
+    }
+
+    /// Set the size of the kernel receive buffer, in bytes
+    ///
+    /// A larger buffer reduces the chance of dropped frames when the application is slow to read
+    /// them. The kernel may limit the size to the `net.core.rmem_max` sysctl.
+    pub fn rx_buffer_size(mut self, bytes: usize) -> Self {
+        self.rx_buffer_size = Some(bytes);
+        self
+    }
+
+    /// Automatically re-open the socket when the interface is removed and re-created
+    ///
+    /// This allows e.g. USB CAN adapters to be unplugged and reconnected. While the interface is
+    /// missing, the receiver retries opening it every `reopen_interval` (default 1s).
+    pub fn auto_reopen(mut self, enable: bool) -> Self {
+        self.auto_reopen = enable;
+        self
+    }
+
+    /// Set the interval between attempts to re-open a removed interface
+    pub fn reopen_interval(mut self, interval: Duration) -> Self {
+        self.reopen_interval = interval;
+        self
+    }
+
+    fn open_socket(&self) -> Result<OpenSocket, socketcan::IoError> {
+        let socket = CanSocket::open(&self.device)?;
+        // Timestamps are best effort; messages without them are still usable
+        let _ = set_socket_option(
+            &socket,
+            libc::SOL_SOCKET,
+            libc::SO_TIMESTAMPING,
+            &[TIMESTAMPING_FLAGS],
+        );
+        if !self.filters.is_empty() {
+            set_socket_option(
+                &socket,
+                libc::SOL_CAN_RAW,
+                libc::CAN_RAW_FILTER,
+                &self.filters,
+            )?;
+        }
+        if self.error_frames {
+            set_socket_option(
+                &socket,
+                libc::SOL_CAN_RAW,
+                libc::CAN_RAW_ERR_FILTER,
+                &[libc::CAN_ERR_MASK],
+            )?;
+        }
+        if self.recv_own_msgs {
+            set_socket_option(
+                &socket,
+                libc::SOL_CAN_RAW,
+                libc::CAN_RAW_RECV_OWN_MSGS,
+                &[1 as libc::c_int],
+            )?;
+        }
+        if let Some(size) = self.rx_buffer_size {
+            let size = size.min(libc::c_int::MAX as usize) as libc::c_int;
+            set_socket_option(&socket, libc::SOL_SOCKET, libc::SO_RCVBUF, &[size])?;
+        }
+        // Frames are received with recvmsg, to read their timestamps, through a duplicate of the
+        // socket's descriptor which is registered with tokio separately
+        // Safety: the descriptor belongs to the socket, which is alive for this call
+        let rx = unsafe { BorrowedFd::borrow_raw(socket.as_raw_fd()) }.try_clone_to_owned()?;
+        Ok(OpenSocket {
+            tx: socket,
+            rx: AsyncFd::new(rx)?,
+        })
+    }
+
+    /// Open the interface and split it into a sender and receiver
+    pub fn open(self) -> Result<(SocketCanSender, SocketCanReceiver), socketcan::IoError> {
+        let socket = self.open_socket()?;
+        let shared = Arc::new(SharedSocket {
+            socket: RwLock::new(Arc::new(socket)),
+            state: Mutex::new(InterfaceState::Up),
+            config: self,
+        });
+        let receiver = SocketCanReceiver {
+            shared: shared.clone(),
+        };
+        let sender = SocketCanSender { shared };
+        Ok((sender, receiver))
+    }
+}
+
+/// An open socket, with a separate descriptor for receiving
+#[derive(Debug)]
+struct OpenSocket {
+    tx: CanSocket,
+    rx: AsyncFd<OwnedFd>,
+}
+
+impl OpenSocket {
+    /// Read a frame and its timestamp, waiting until one is received
+    async fn read_frame(&self) -> std::io::Result<(libc::can_frame, Option<u64>)> {
+        loop {
+            let mut guard = self.rx.readable().await?;
+            if let Ok(result) = guard.try_io(|rx| recv_frame(rx.get_ref().as_fd())) {
+                return result;
+            }
+        }
+    }
+}
+
+/// The socket shared by a sender and receiver, which may be replaced when re-opened
+#[derive(Debug)]
+struct SharedSocket {
+    socket: RwLock<Arc<OpenSocket>>,
+    state: Mutex<InterfaceState>,
+    config: SocketCanTransport,
+}
+
+impl SharedSocket {
+    fn socket(&self) -> Arc<OpenSocket> {
+        self.socket.read().unwrap().clone()
+    }
+
+    fn state(&self) -> InterfaceState {
+        *self.state.lock().unwrap()
+    }
+
+    /// Update the interface state, returning the previous state
+    fn set_state(&self, state: InterfaceState) -> InterfaceState {
+        core::mem::replace(&mut *self.state.lock().unwrap(), state)
+    }
+}
+
+/// Receives messages from a socketcan socket
+///
+/// Received messages are stamped with the receive time (see [`CanMessage::timestamp_us()`]), so
+/// that latency measurements are not affected by scheduling delays in the application. The hardware
+/// timestamp of the interface is used when it provides one, and otherwise the kernel receive time.
+///
+/// When the interface goes down or is removed, [`ReceiveError::InterfaceDown`] is returned once,
+/// and the current state can be read with [`interface_state`](Self::interface_state).
 #[derive(Debug, Clone)]
 pub struct SocketCanReceiver {
-    socket: Arc<CanSocket>,
+    shared: Arc<SharedSocket>,
+}
+
+impl SocketCanReceiver {
+    /// Get the last observed state of the interface
+    pub fn interface_state(&self) -> InterfaceState {
+        self.shared.state()
+    }
 }
 
+/// Error returned by [`SocketCanReceiver`]
 #[derive(Debug, Snafu)]
 pub enum ReceiveError {
-    Io { source: socketcan::IoError },
-    Can { source: CanError },
+    /// An error was returned by the socket
+    Io {
+        /// The underlying error
+        source: socketcan::IoError,
+    },
+    /// An error frame was received
+    Can {
+        /// The error reported in the frame
+        source: CanError,
+    },
+    /// The interface went down or was removed
+    InterfaceDown,
 }
 
 impl AsyncCanReceiver for SocketCanReceiver {
     type Error = ReceiveError;
 
     fn try_recv(&mut self) -> Option<CanMessage> {
-        match self.socket.try_read_frame() {
-            Ok(frame) => Some(socketcan_frame_to_zencan_message(frame).unwrap()),
+        let socket = self.shared.socket();
+        match recv_frame(socket.rx.get_ref().as_fd()) {
+            Ok(frame) => timestamped(frame).ok(),
             _ => None,
         }
     }
 
     async fn recv(&mut self) -> Result<CanMessage, ReceiveError> {
         loop {
-            match self.socket.read_frame().await {
-                Ok(frame) => return socketcan_frame_to_zencan_message(frame).context(CanSnafu),
+            if self.shared.state() == InterfaceState::Removed {
+                if !self.shared.config.auto_reopen {
+                    return Err(ReceiveError::InterfaceDown);
+                }
+                match self.shared.config.open_socket() {
+                    Ok(socket) => {
+                        info!(
+                            "Re-opened CAN interface {}",
+                            self.shared.config.device.as_str()
+                        );
+                        *self.shared.socket.write().unwrap() = Arc::new(socket);
+                        self.shared.set_state(InterfaceState::Up);
+                    }
+                    Err(_) => {
+                        tokio::time::sleep(self.shared.config.reopen_interval).await;
+                        continue;
+                    }
+                }
+            }
+
+            let socket = self.shared.socket();
+            match socket.read_frame().await {
+                Ok(frame) => {
+                    if self.shared.set_state(InterfaceState::Up) != InterfaceState::Up {
+                        info!("CAN interface {} is up", self.shared.config.device.as_str());
+                    }
+                    return timestamped(frame).context(CanSnafu);
+                }
+                Err(e) if is_interface_down(&e) => {
+                    // The socket remains bound, and will receive again when the interface is up
+                    if self.shared.set_state(InterfaceState::Down) == InterfaceState::Up {
+                        warn!(
+                            "CAN interface {} is down",
+                            self.shared.config.device.as_str()
+                        );
+                        return Err(ReceiveError::InterfaceDown);
+                    }
+                }
+                Err(e) if is_interface_gone(&e) => {
+                    if self.shared.set_state(InterfaceState::Removed) != InterfaceState::Removed {
+                        warn!(
+                            "CAN interface {} was removed",
+                            self.shared.config.device.as_str()
+                        );
+                        return Err(ReceiveError::InterfaceDown);
+                    }
+                }
                 Err(e) => {
                     if !e.should_retry() {
                         return Err(ReceiveError::Io { source: e });
@@ -76,22 +513,28 @@ impl AsyncCanReceiver for SocketCanReceiver {
     }
 }
 
+/// Sends messages to a socketcan socket
 #[derive(Debug, Clone)]
 pub struct SocketCanSender {
-    socket: Arc<CanSocket>,
+    shared: Arc<SharedSocket>,
 }
 
 impl AsyncCanSender for SocketCanSender {
     async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
         let socketcan_frame = zencan_message_to_socket_frame(msg);
 
-        let result = self.socket.write_frame(socketcan_frame).await;
+        let socket = self.shared.socket();
+        let result = socket.tx.write_frame(socketcan_frame).await;
         if result.is_err() {
             Err(msg)
         } else {
             Ok(())
         }
     }
+
+    fn echoes_sent_frames(&self) -> bool {
+        self.shared.config.recv_own_msgs
+    }
 }
 
 /// Open a socketcan device and split it into a sender and receiver object for use with zencan
@@ -102,16 +545,107 @@ impl AsyncCanSender for SocketCanSender {
 ///
 /// A key benefit of this is that by creating both sender and receiver objects from a shared socket,
 /// the receiver will not receive messages sent by the sender.
+///
+/// To configure filters or other socket options, use [`SocketCanTransport`].
 #[cfg_attr(docsrs, doc(cfg(feature = "socketcan")))]
 pub fn open_socketcan<S: AsRef<str>>(
     device: S,
 ) -> Result<(SocketCanSender, SocketCanReceiver), socketcan::IoError> {
-    let device: &str = device.as_ref();
-    let socket = CanSocket::open(device)?;
-    let socket = Arc::new(socket);
-    let receiver = SocketCanReceiver {
-        socket: socket.clone(),
-    };
-    let sender = SocketCanSender { socket };
-    Ok((sender, receiver))
+    SocketCanTransport::new(device.as_ref()).open()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_filters() {
+        let transport = SocketCanTransport::new("vcan0").node_filters(5);
+        let ids: Vec<u32> = transport.filters.iter().map(|f| f.can_id).collect();
+        assert_eq!(
+            vec![0x000, 0x080, 0x100, 0x7E5, 0x605, 0x205, 0x305, 0x405, 0x505],
+            ids
+        );
+        assert!(transport
+            .filters
+            .iter()
+            .all(|f| f.can_mask == libc::CAN_SFF_MASK | libc::CAN_EFF_FLAG));
+
+        // An unconfigured node only receives broadcast and LSS frames
+        let transport = SocketCanTransport::new("vcan0").node_filters(255);
+        assert_eq!(4, transport.filters.len());
+
+        let transport = SocketCanTransport::new("vcan0").filter_id(CanId::extended(0x1234));
+        assert_eq!(0x1234 | libc::CAN_EFF_FLAG, transport.filters[0].can_id);
+    }
+
+    #[test]
+    fn test_recv_frame_timestamp() {
+        // A UDP socket on the loopback interface gets software receive timestamps in the same way
+        // as a CAN socket
+        let rx = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let tx = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let result = unsafe {
+            libc::setsockopt(
+                rx.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_TIMESTAMPING,
+                &TIMESTAMPING_FLAGS as *const libc::c_uint as *const libc::c_void,
+                core::mem::size_of::<libc::c_uint>() as libc::socklen_t,
+            )
+        };
+        assert_eq!(0, result);
+
+        let mut raw = [0u8; core::mem::size_of::<libc::can_frame>()];
+        raw[..4].copy_from_slice(&(0x1234 | libc::CAN_EFF_FLAG).to_ne_bytes());
+        raw[4] = 2;
+        raw[8..10].copy_from_slice(&[0xAA, 0xBB]);
+
+        // The kernel enables receive timestamps in the background, so the first datagrams may
+        // arrive without one
+        let mut attempts = 0;
+        let (msg, before) = loop {
+            tx.send_to(&raw, rx.local_addr().unwrap()).unwrap();
+            let before = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_micros() as u64;
+            let frame = loop {
+                match recv_frame(rx.as_fd()) {
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        std::thread::yield_now()
+                    }
+                    result => break result.unwrap(),
+                }
+            };
+            let msg = timestamped(frame).unwrap();
+            attempts += 1;
+            if msg.timestamp_us().is_some() || attempts == 100 {
+                break (msg, before);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        };
+        assert_eq!(CanId::extended(0x1234), msg.id());
+        assert_eq!(&[0xAA, 0xBB], msg.data());
+        // The frame was stamped when it was received, shortly before it was read
+        let timestamp = msg.timestamp_us().unwrap();
+        assert!(timestamp <= before + 1_000_000 && timestamp + 1_000_000 >= before);
+
+        // Nothing is waiting
+        assert_eq!(
+            std::io::ErrorKind::WouldBlock,
+            recv_frame(rx.as_fd()).unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn test_raw_frame_conversion() {
+        let mut frame: libc::can_frame = unsafe { core::mem::zeroed() };
+        frame.can_id = 0x123 | libc::CAN_RTR_FLAG;
+        let msg = raw_frame_to_zencan_message(&frame).unwrap();
+        assert!(msg.is_rtr());
+        assert_eq!(CanId::std(0x123), msg.id());
+        frame.can_id = libc::CAN_ERR_FLAG | 0x04;
+        assert!(raw_frame_to_zencan_message(&frame).is_err());
+    }
 }
@@ -4,6 +4,16 @@ use core::time::Duration;
 
 use crate::messages::CanMessage;
 
+#[cfg(feature = "std")]
+mod combinators;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub use combinators::{
+    tee, AsyncCanReceiverExt, AsyncCanSenderExt, BoxedCanReceiver, BoxedCanSender, BoxedError,
+    DynCanReceiver, DynCanSender, RateLimitedSender, RecordingReceiver, RecordingSender,
+    TeeReceiver,
+};
+
 /// A trait for accessing a value
 ///
 /// E.g. from an AtomicCell
@@ -34,10 +44,20 @@ pub trait CanReceiver {
 /// An async CAN sender trait
 pub trait AsyncCanSender: Send {
     /// Send a message to the bus
+    ///
+    /// The returned future must be `Send`, so that a sender can be used from a spawned task.
     fn send(
         &mut self,
         msg: CanMessage,
-    ) -> impl core::future::Future<Output = Result<(), CanMessage>>;
+    ) -> impl core::future::Future<Output = Result<(), CanMessage>> + Send;
+
+    /// Returns true if the frames sent are delivered back to the receiver of the same transport
+    ///
+    /// Clients use this to recognize their own transmissions among the frames they receive. The
+    /// default is false.
+    fn echoes_sent_frames(&self) -> bool {
+        false
+    }
 }
 
 /// An async CAN receiver trait
@@ -0,0 +1,485 @@
+//! Wrappers and combinators for the async transport traits
+//!
+//! These allow transports to be boxed, shared between several consumers, throttled and recorded,
+//! without writing custom glue for each application.
+use core::{fmt::Debug, future::Future, pin::Pin, time::Duration};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use super::{AsyncCanReceiver, AsyncCanSender};
+use crate::messages::CanMessage;
+
+/// A type-erased error, as returned by [`BoxedCanReceiver`]
+pub type BoxedError = Box<dyn Debug + Send>;
+
+/// An object safe version of [`AsyncCanSender`]
+///
+/// This is implemented for all senders, and is used by [`BoxedCanSender`].
+pub trait DynCanSender: Send {
+    /// Send a message to the bus
+    fn send_boxed(
+        &mut self,
+        msg: CanMessage,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CanMessage>> + Send + '_>>;
+
+    /// Returns true if the frames sent are delivered back to the receiver of the same transport
+    fn echoes_sent_frames_dyn(&self) -> bool;
+}
+
+impl<S: AsyncCanSender> DynCanSender for S {
+    fn send_boxed(
+        &mut self,
+        msg: CanMessage,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CanMessage>> + Send + '_>> {
+        Box::pin(self.send(msg))
+    }
+
+    fn echoes_sent_frames_dyn(&self) -> bool {
+        self.echoes_sent_frames()
+    }
+}
+
+/// An object safe version of [`AsyncCanReceiver`]
+///
+/// This is implemented for all receivers, and is used by [`BoxedCanReceiver`].
+pub trait DynCanReceiver: Send {
+    /// Receive available message immediately
+    fn try_recv_dyn(&mut self) -> Option<CanMessage>;
+
+    /// A blocking receive
+    fn recv_boxed(
+        &mut self,
+    ) -> Pin<Box<dyn Future<Output = Result<CanMessage, BoxedError>> + Send + '_>>;
+}
+
+impl<R: AsyncCanReceiver> DynCanReceiver for R
+where
+    R::Error: 'static,
+{
+    fn try_recv_dyn(&mut self) -> Option<CanMessage> {
+        self.try_recv()
+    }
+
+    fn recv_boxed(
+        &mut self,
+    ) -> Pin<Box<dyn Future<Output = Result<CanMessage, BoxedError>> + Send + '_>> {
+        let fut = self.recv();
+        Box::pin(async move { fut.await.map_err(|e| Box::new(e) as BoxedError) })
+    }
+}
+
+/// A sender with its type erased
+///
+/// This allows senders of different types to be selected at runtime, e.g. based on command line
+/// arguments, and stored in a single field.
+#[allow(missing_debug_implementations)]
+pub struct BoxedCanSender(Box<dyn DynCanSender>);
+
+impl BoxedCanSender {
+    /// Box a sender
+    pub fn new(sender: impl AsyncCanSender + 'static) -> Self {
+        Self(Box::new(sender))
+    }
+}
+
+impl AsyncCanSender for BoxedCanSender {
+    fn send(&mut self, msg: CanMessage) -> impl Future<Output = Result<(), CanMessage>> + Send {
+        self.0.send_boxed(msg)
+    }
+
+    fn echoes_sent_frames(&self) -> bool {
+        self.0.echoes_sent_frames_dyn()
+    }
+}
+
+/// A receiver with its type erased
+///
+/// Errors from the inner receiver are returned as a [`BoxedError`].
+#[allow(missing_debug_implementations)]
+pub struct BoxedCanReceiver(Box<dyn DynCanReceiver>);
+
+impl BoxedCanReceiver {
+    /// Box a receiver
+    pub fn new<R>(receiver: R) -> Self
+    where
+        R: AsyncCanReceiver + 'static,
+        R::Error: 'static,
+    {
+        Self(Box::new(receiver))
+    }
+}
+
+impl AsyncCanReceiver for BoxedCanReceiver {
+    type Error = BoxedError;
+
+    fn try_recv(&mut self) -> Option<CanMessage> {
+        self.0.try_recv_dyn()
+    }
+
+    fn recv(&mut self) -> impl Future<Output = Result<CanMessage, Self::Error>> + Send {
+        self.0.recv_boxed()
+    }
+}
+
+struct TeeShared<R> {
+    receiver: futures::lock::Mutex<R>,
+    queues: Mutex<Vec<VecDeque<CanMessage>>>,
+    capacity: usize,
+}
+
+impl<R> TeeShared<R> {
+    fn pop(&self, index: usize) -> Option<CanMessage> {
+        self.queues.lock().unwrap()[index].pop_front()
+    }
+
+    /// Queue a message for every consumer except `index`, dropping the oldest message from full
+    /// queues
+    fn distribute(&self, index: usize, msg: CanMessage) {
+        let mut queues = self.queues.lock().unwrap();
+        for (i, queue) in queues.iter_mut().enumerate() {
+            if i == index {
+                continue;
+            }
+            if queue.len() == self.capacity {
+                queue.pop_front();
+            }
+            queue.push_back(msg);
+        }
+    }
+}
+
+/// One of the consumers created by [`tee`]
+#[allow(missing_debug_implementations)]
+pub struct TeeReceiver<R> {
+    index: usize,
+    shared: Arc<TeeShared<R>>,
+}
+
+/// Split a receiver into several consumers, which each receive a copy of every frame
+///
+/// Frames are read from the underlying receiver by whichever consumer is waiting, and queued for
+/// the others. Each consumer queues up to `capacity` frames; when a consumer falls further behind,
+/// its oldest frames are dropped. Errors are returned only to the consumer which was reading when
+/// the error occurred.
+pub fn tee<R: AsyncCanReceiver>(
+    receiver: R,
+    consumers: usize,
+    capacity: usize,
+) -> Vec<TeeReceiver<R>> {
+    let shared = Arc::new(TeeShared {
+        receiver: futures::lock::Mutex::new(receiver),
+        queues: Mutex::new((0..consumers).map(|_| VecDeque::new()).collect()),
+        capacity,
+    });
+    (0..consumers)
+        .map(|index| TeeReceiver {
+            index,
+            shared: shared.clone(),
+        })
+        .collect()
+}
+
+impl<R: AsyncCanReceiver> AsyncCanReceiver for TeeReceiver<R> {
+    type Error = R::Error;
+
+    fn try_recv(&mut self) -> Option<CanMessage> {
+        if let Some(msg) = self.shared.pop(self.index) {
+            return Some(msg);
+        }
+        let mut receiver = self.shared.receiver.try_lock()?;
+        let msg = receiver.try_recv()?;
+        self.shared.distribute(self.index, msg);
+        Some(msg)
+    }
+
+    async fn recv(&mut self) -> Result<CanMessage, Self::Error> {
+        loop {
+            if let Some(msg) = self.shared.pop(self.index) {
+                return Ok(msg);
+            }
+            let mut receiver = self.shared.receiver.lock().await;
+            // Another consumer may have queued a frame while we were waiting for the lock
+            if !self.shared.queues.lock().unwrap()[self.index].is_empty() {
+                continue;
+            }
+            let msg = receiver.recv().await?;
+            self.shared.distribute(self.index, msg);
+            return Ok(msg);
+        }
+    }
+}
+
+/// A sender which limits the rate of transmitted frames
+///
+/// Consecutive frames are separated by at least `min_interval`. The `sleep` function must return
+/// a future which completes after the given duration, e.g. `tokio::time::sleep`.
+#[allow(missing_debug_implementations)]
+pub struct RateLimitedSender<S, F> {
+    sender: S,
+    min_interval: Duration,
+    sleep: F,
+    last_send: Option<Instant>,
+}
+
+impl<S, F, Fut> RateLimitedSender<S, F>
+where
+    S: AsyncCanSender,
+    F: FnMut(Duration) -> Fut + Send,
+    Fut: Future<Output = ()> + Send,
+{
+    /// Wrap a sender
+    pub fn new(sender: S, min_interval: Duration, sleep: F) -> Self {
+        Self {
+            sender,
+            min_interval,
+            sleep,
+            last_send: None,
+        }
+    }
+
+    /// Get the wrapped sender
+    pub fn into_inner(self) -> S {
+        self.sender
+    }
+}
+
+impl<S, F, Fut> AsyncCanSender for RateLimitedSender<S, F>
+where
+    S: AsyncCanSender,
+    F: FnMut(Duration) -> Fut + Send,
+    Fut: Future<Output = ()> + Send,
+{
+    async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
+        if let Some(last) = self.last_send {
+            let elapsed = last.elapsed();
+            if elapsed < self.min_interval {
+                (self.sleep)(self.min_interval - elapsed).await;
+            }
+        }
+        self.last_send = Some(Instant::now());
+        self.sender.send(msg).await
+    }
+
+    fn echoes_sent_frames(&self) -> bool {
+        self.sender.echoes_sent_frames()
+    }
+}
+
+/// A sender which passes every successfully sent frame to a callback
+///
+/// This can be used to log or record outgoing traffic.
+#[allow(missing_debug_implementations)]
+pub struct RecordingSender<S, F> {
+    sender: S,
+    on_frame: F,
+}
+
+impl<S: AsyncCanSender, F: FnMut(&CanMessage) + Send> RecordingSender<S, F> {
+    /// Wrap a sender
+    pub fn new(sender: S, on_frame: F) -> Self {
+        Self { sender, on_frame }
+    }
+
+    /// Get the wrapped sender
+    pub fn into_inner(self) -> S {
+        self.sender
+    }
+}
+
+impl<S: AsyncCanSender, F: FnMut(&CanMessage) + Send> AsyncCanSender for RecordingSender<S, F> {
+    async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
+        self.sender.send(msg).await?;
+        (self.on_frame)(&msg);
+        Ok(())
+    }
+
+    fn echoes_sent_frames(&self) -> bool {
+        self.sender.echoes_sent_frames()
+    }
+}
+
+/// A receiver which passes every received frame to a callback
+///
+/// This can be used to log or record incoming traffic.
+#[allow(missing_debug_implementations)]
+pub struct RecordingReceiver<R, F> {
+    receiver: R,
+    on_frame: F,
+}
+
+impl<R: AsyncCanReceiver, F: FnMut(&CanMessage) + Send> RecordingReceiver<R, F> {
+    /// Wrap a receiver
+    pub fn new(receiver: R, on_frame: F) -> Self {
+        Self { receiver, on_frame }
+    }
+
+    /// Get the wrapped receiver
+    pub fn into_inner(self) -> R {
+        self.receiver
+    }
+}
+
+impl<R: AsyncCanReceiver, F: FnMut(&CanMessage) + Send> AsyncCanReceiver
+    for RecordingReceiver<R, F>
+{
+    type Error = R::Error;
+
+    fn try_recv(&mut self) -> Option<CanMessage> {
+        let msg = self.receiver.try_recv()?;
+        (self.on_frame)(&msg);
+        Some(msg)
+    }
+
+    async fn recv(&mut self) -> Result<CanMessage, Self::Error> {
+        let msg = self.receiver.recv().await?;
+        (self.on_frame)(&msg);
+        Ok(msg)
+    }
+}
+
+/// Combinator methods for [`AsyncCanSender`]
+pub trait AsyncCanSenderExt: AsyncCanSender + Sized {
+    /// Erase the type of the sender
+    fn boxed(self) -> BoxedCanSender
+    where
+        Self: 'static,
+    {
+        BoxedCanSender::new(self)
+    }
+
+    /// Limit the rate of transmitted frames. See [`RateLimitedSender`].
+    fn rate_limited<F, Fut>(self, min_interval: Duration, sleep: F) -> RateLimitedSender<Self, F>
+    where
+        F: FnMut(Duration) -> Fut + Send,
+        Fut: Future<Output = ()> + Send,
+    {
+        RateLimitedSender::new(self, min_interval, sleep)
+    }
+
+    /// Pass every sent frame to a callback. See [`RecordingSender`].
+    fn recording<F: FnMut(&CanMessage) + Send>(self, on_frame: F) -> RecordingSender<Self, F> {
+        RecordingSender::new(self, on_frame)
+    }
+}
+
+impl<S: AsyncCanSender> AsyncCanSenderExt for S {}
+
+/// Combinator methods for [`AsyncCanReceiver`]
+pub trait AsyncCanReceiverExt: AsyncCanReceiver + Sized {
+    /// Erase the type of the receiver
+    fn boxed(self) -> BoxedCanReceiver
+    where
+        Self: 'static,
+        Self::Error: 'static,
+    {
+        BoxedCanReceiver::new(self)
+    }
+
+    /// Split into several consumers which each receive every frame. See [`tee`].
+    fn tee(self, consumers: usize, capacity: usize) -> Vec<TeeReceiver<Self>> {
+        tee(self, consumers, capacity)
+    }
+
+    /// Pass every received frame to a callback. See [`RecordingReceiver`].
+    fn recording<F: FnMut(&CanMessage) + Send>(self, on_frame: F) -> RecordingReceiver<Self, F> {
+        RecordingReceiver::new(self, on_frame)
+    }
+}
+
+impl<R: AsyncCanReceiver> AsyncCanReceiverExt for R {}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::messages::CanId;
+
+    struct MockReceiver(VecDeque<CanMessage>);
+
+    impl AsyncCanReceiver for MockReceiver {
+        type Error = ();
+
+        fn try_recv(&mut self) -> Option<CanMessage> {
+            self.0.pop_front()
+        }
+
+        async fn recv(&mut self) -> Result<CanMessage, Self::Error> {
+            self.0.pop_front().ok_or(())
+        }
+    }
+
+    struct MockSender(Arc<Mutex<Vec<CanMessage>>>);
+
+    impl AsyncCanSender for MockSender {
+        async fn send(&mut self, msg: CanMessage) -> Result<(), CanMessage> {
+            self.0.lock().unwrap().push(msg);
+            Ok(())
+        }
+    }
+
+    fn msg(id: u16) -> CanMessage {
+        CanMessage::new(CanId::std(id), &[id as u8])
+    }
+
+    #[test]
+    fn test_tee() {
+        let receiver = MockReceiver([msg(1), msg(2), msg(3)].into());
+        let mut consumers = receiver.tee(2, 2);
+        let mut b = consumers.pop().unwrap();
+        let mut a = consumers.pop().unwrap();
+
+        assert_eq!(Ok(msg(1)), block_on(a.recv()));
+        assert_eq!(Ok(msg(2)), block_on(a.recv()));
+        assert_eq!(Ok(msg(3)), block_on(a.recv()));
+        // b can only hold two frames, so the first one was dropped
+        assert_eq!(Ok(msg(2)), block_on(b.recv()));
+        assert_eq!(Some(msg(3)), b.try_recv());
+        assert_eq!(None, b.try_recv());
+        assert_eq!(Err(()), block_on(b.recv()));
+    }
+
+    #[test]
+    fn test_boxed_and_recording() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let mut sender = {
+            let recorded = recorded.clone();
+            MockSender(sent.clone())
+                .recording(move |m| recorded.lock().unwrap().push(*m))
+                .boxed()
+        };
+        block_on(sender.send(msg(5))).unwrap();
+        assert_eq!(vec![msg(5)], *sent.lock().unwrap());
+        assert_eq!(vec![msg(5)], *recorded.lock().unwrap());
+
+        let mut count = 0;
+        let mut receiver = MockReceiver([msg(7)].into()).recording(|_| count += 1);
+        assert_eq!(Ok(msg(7)), block_on(receiver.recv()));
+        drop(receiver);
+        assert_eq!(1, count);
+
+        let mut receiver = MockReceiver(VecDeque::new()).boxed();
+        assert!(block_on(receiver.recv()).is_err());
+    }
+
+    #[test]
+    fn test_rate_limited() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let mut slept = Vec::new();
+        let mut sender = MockSender(sent.clone()).rate_limited(Duration::from_secs(10), |d| {
+            slept.push(d);
+            async {}
+        });
+        block_on(sender.send(msg(1))).unwrap();
+        block_on(sender.send(msg(2))).unwrap();
+        drop(sender);
+        assert_eq!(2, sent.lock().unwrap().len());
+        // The second frame had to wait for (nearly) the whole interval
+        assert_eq!(1, slept.len());
+        assert!(slept[0] > Duration::from_secs(9));
+    }
+}
@@ -0,0 +1,467 @@
+//! A typed representation of object values
+//!
+//! [`Value`] holds a single sub object value, and knows how to convert itself to and from the
+//! little-endian byte representation used on the bus, based on the [`DataType`] of the object.
+
+use snafu::Snafu;
+
+use crate::objects::DataType;
+
+/// Error returned when converting a [`Value`]
+#[derive(Debug, Clone, PartialEq, Snafu)]
+pub enum ValueError {
+    /// The number of bytes does not match the size of the data type
+    #[snafu(display("Expected {expected} bytes for {data_type:?}, got {actual}"))]
+    WrongSize {
+        /// The data type being decoded
+        data_type: DataType,
+        /// The size of the data type
+        expected: usize,
+        /// The number of bytes provided
+        actual: usize,
+    },
+    /// A string value is not valid UTF-8
+    #[snafu(display("String is not valid UTF-8"))]
+    InvalidUtf8,
+    /// An integer value does not fit in the data type
+    #[snafu(display(
+        "Integer {value} out of range, expected an integer in range [{min}..{max_excl}]"
+    ))]
+    OutOfRange {
+        /// The value which was provided
+        value: i128,
+        /// The minimum allowed value
+        min: i128,
+        /// One more than the maximum allowed value
+        max_excl: i128,
+    },
+    /// A string could not be parsed as the data type
+    #[snafu(display("Cannot parse '{text}' as {data_type:?}"))]
+    Parse {
+        /// The text which failed to parse
+        text: String,
+        /// The data type being parsed
+        data_type: DataType,
+    },
+    /// The value cannot be converted to the data type
+    #[snafu(display("{data_type:?} is not supported for this conversion"))]
+    UnsupportedType {
+        /// The requested data type
+        data_type: DataType,
+    },
+}
+
+/// A single object value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A boolean
+    Bool(bool),
+    /// An unsigned 8-bit integer
+    U8(u8),
+    /// An unsigned 16-bit integer
+    U16(u16),
+    /// An unsigned 32-bit integer
+    U32(u32),
+    /// An unsigned 64-bit integer
+    U64(u64),
+    /// A signed 8-bit integer
+    I8(i8),
+    /// A signed 16-bit integer
+    I16(i16),
+    /// A signed 32-bit integer
+    I32(i32),
+    /// A signed 64-bit integer
+    I64(i64),
+    /// A 32-bit float
+    F32(f32),
+    /// A 64-bit float
+    F64(f64),
+    /// A string
+    Str(String),
+    /// An arbitrary byte string
+    Bytes(Vec<u8>),
+}
+
+fn fixed<const N: usize>(data_type: DataType, bytes: &[u8]) -> Result<[u8; N], ValueError> {
+    bytes.try_into().map_err(|_| ValueError::WrongSize {
+        data_type,
+        expected: N,
+        actual: bytes.len(),
+    })
+}
+
+/// Parse a hex string, e.g. "0102FF", "0x0102ff" or "01 02 ff", into bytes
+///
+/// Returns None if the string contains anything other than hex digits and whitespace, or an odd
+/// number of digits.
+pub fn parse_hex_bytes(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    let digits: Vec<u8> = s.bytes().filter(|c| !c.is_ascii_whitespace()).collect();
+    if digits.len() % 2 != 0 || !digits.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        // Unwrap: the digits were checked above
+        .map(|pair| u8::from_str_radix(core::str::from_utf8(pair).unwrap(), 16).ok())
+        .collect()
+}
+
+/// Parse an integer, accepting a `0x` prefix for hex
+fn parse_int(s: &str) -> Option<i128> {
+    let s = s.trim();
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (radix, digits) = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => (16, hex),
+        None => (10, s),
+    };
+    // The sign has already been taken, so only digits may follow
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let value = i128::from_str_radix(digits, radix).ok()?;
+    Some(if neg { -value } else { value })
+}
+
+impl Value {
+    /// Get the data type which best represents this value
+    pub fn data_type(&self) -> DataType {
+        match self {
+            Value::Bool(_) => DataType::Boolean,
+            Value::U8(_) => DataType::UInt8,
+            Value::U16(_) => DataType::UInt16,
+            Value::U32(_) => DataType::UInt32,
+            Value::U64(_) => DataType::UInt64,
+            Value::I8(_) => DataType::Int8,
+            Value::I16(_) => DataType::Int16,
+            Value::I32(_) => DataType::Int32,
+            Value::I64(_) => DataType::Int64,
+            Value::F32(_) => DataType::Real32,
+            Value::F64(_) => DataType::Real64,
+            Value::Str(_) => DataType::VisibleString,
+            Value::Bytes(_) => DataType::OctetString,
+        }
+    }
+
+    /// Convert the value to little-endian bytes
+    pub fn to_le_bytes(&self) -> Vec<u8> {
+        match self {
+            Value::Bool(v) => vec![*v as u8],
+            Value::U8(v) => vec![*v],
+            Value::U16(v) => v.to_le_bytes().to_vec(),
+            Value::U32(v) => v.to_le_bytes().to_vec(),
+            Value::U64(v) => v.to_le_bytes().to_vec(),
+            Value::I8(v) => v.to_le_bytes().to_vec(),
+            Value::I16(v) => v.to_le_bytes().to_vec(),
+            Value::I32(v) => v.to_le_bytes().to_vec(),
+            Value::I64(v) => v.to_le_bytes().to_vec(),
+            Value::F32(v) => v.to_le_bytes().to_vec(),
+            Value::F64(v) => v.to_le_bytes().to_vec(),
+            Value::Str(s) => s.as_bytes().to_vec(),
+            Value::Bytes(b) => b.clone(),
+        }
+    }
+
+    /// Decode a value of the given data type from little-endian bytes
+    ///
+    /// Numeric types require exactly the right number of bytes. String types must be valid UTF-8.
+    /// Types with no specific representation are returned as [`Value::Bytes`].
+    pub fn from_le_bytes(data_type: DataType, bytes: &[u8]) -> Result<Value, ValueError> {
+        Ok(match data_type {
+            DataType::Boolean => Value::Bool(fixed::<1>(data_type, bytes)?[0] != 0),
+            DataType::UInt8 => Value::U8(fixed::<1>(data_type, bytes)?[0]),
+            DataType::UInt16 => Value::U16(u16::from_le_bytes(fixed(data_type, bytes)?)),
+            DataType::UInt32 => Value::U32(u32::from_le_bytes(fixed(data_type, bytes)?)),
+            DataType::UInt64 => Value::U64(u64::from_le_bytes(fixed(data_type, bytes)?)),
+            DataType::Int8 => Value::I8(i8::from_le_bytes(fixed(data_type, bytes)?)),
+            DataType::Int16 => Value::I16(i16::from_le_bytes(fixed(data_type, bytes)?)),
+            DataType::Int32 => Value::I32(i32::from_le_bytes(fixed(data_type, bytes)?)),
+            DataType::Int64 => Value::I64(i64::from_le_bytes(fixed(data_type, bytes)?)),
+            DataType::Real32 => Value::F32(f32::from_le_bytes(fixed(data_type, bytes)?)),
+            DataType::Real64 => Value::F64(f64::from_le_bytes(fixed(data_type, bytes)?)),
+            DataType::VisibleString | DataType::UnicodeString => {
+                Value::Str(String::from_utf8(bytes.to_vec()).map_err(|_| ValueError::InvalidUtf8)?)
+            }
+            _ => Value::Bytes(bytes.to_vec()),
+        })
+    }
+
+    /// Create an integer value of the given data type, checking that it is in range
+    pub fn from_integer(data_type: DataType, value: i128) -> Result<Value, ValueError> {
+        fn check<T: TryFrom<i128>>(value: i128, min: i128, max: i128) -> Result<T, ValueError> {
+            T::try_from(value).map_err(|_| ValueError::OutOfRange {
+                value,
+                min,
+                max_excl: max + 1,
+            })
+        }
+        Ok(match data_type {
+            DataType::Boolean => match value {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                _ => {
+                    return OutOfRangeSnafu {
+                        value,
+                        min: 0,
+                        max_excl: 2,
+                    }
+                    .fail()
+                }
+            },
+            DataType::UInt8 => Value::U8(check(value, 0, u8::MAX as i128)?),
+            DataType::UInt16 => Value::U16(check(value, 0, u16::MAX as i128)?),
+            DataType::UInt32 => Value::U32(check(value, 0, u32::MAX as i128)?),
+            DataType::UInt64 => Value::U64(check(value, 0, u64::MAX as i128)?),
+            DataType::Int8 => Value::I8(check(value, i8::MIN as i128, i8::MAX as i128)?),
+            DataType::Int16 => Value::I16(check(value, i16::MIN as i128, i16::MAX as i128)?),
+            DataType::Int32 => Value::I32(check(value, i32::MIN as i128, i32::MAX as i128)?),
+            DataType::Int64 => Value::I64(check(value, i64::MIN as i128, i64::MAX as i128)?),
+            DataType::Real32 => Value::F32(value as f32),
+            DataType::Real64 => Value::F64(value as f64),
+            _ => return UnsupportedTypeSnafu { data_type }.fail(),
+        })
+    }
+
+    /// Create a floating point value of the given data type
+    pub fn from_float(data_type: DataType, value: f64) -> Result<Value, ValueError> {
+        match data_type {
+            DataType::Real32 => Ok(Value::F32(value as f32)),
+            DataType::Real64 => Ok(Value::F64(value)),
+            _ => UnsupportedTypeSnafu { data_type }.fail(),
+        }
+    }
+
+    /// Parse a value of the given data type from a string
+    ///
+    /// Integers may be given in decimal, or in hex with a `0x` prefix. Booleans may be given as
+    /// `true`/`false` or `1`/`0`. Octet strings and domains are given as hex strings, e.g.
+    /// "01 02 ff".
+    pub fn parse(data_type: DataType, s: &str) -> Result<Value, ValueError> {
+        let parse_err = || ValueError::Parse {
+            text: s.to_string(),
+            data_type,
+        };
+        match data_type {
+            DataType::Boolean => match s.trim() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                other => Value::from_integer(data_type, parse_int(other).ok_or_else(parse_err)?),
+            },
+            DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64 => {
+                Value::from_integer(data_type, parse_int(s).ok_or_else(parse_err)?)
+            }
+            DataType::Real32 | DataType::Real64 => {
+                Value::from_float(data_type, s.trim().parse().map_err(|_| parse_err())?)
+            }
+            DataType::VisibleString | DataType::UnicodeString => Ok(Value::Str(s.to_string())),
+            DataType::OctetString | DataType::Domain => {
+                Ok(Value::Bytes(parse_hex_bytes(s).ok_or_else(parse_err)?))
+            }
+            _ => UnsupportedTypeSnafu { data_type }.fail(),
+        }
+    }
+
+    /// Format the value multiplied by `scale`, followed by `unit`, e.g. "3.321 V"
+    ///
+    /// Scaled integers are shown with as many decimal places as the scale has, so that 3321 with a
+    /// scale of 0.001 is shown as 3.321, and not 3.3210000000000002. Values which are not numeric
+    /// are shown as they are, without the unit.
+    pub fn format_scaled(&self, scale: Option<f64>, unit: Option<&str>) -> String {
+        let raw = match self {
+            Value::U8(v) => *v as f64,
+            Value::U16(v) => *v as f64,
+            Value::U32(v) => *v as f64,
+            Value::U64(v) => *v as f64,
+            Value::I8(v) => *v as f64,
+            Value::I16(v) => *v as f64,
+            Value::I32(v) => *v as f64,
+            Value::I64(v) => *v as f64,
+            Value::F32(v) => *v as f64,
+            Value::F64(v) => *v,
+            Value::Bool(_) | Value::Str(_) | Value::Bytes(_) => return self.to_string(),
+        };
+        let text = match (self, scale) {
+            (_, None) => self.to_string(),
+            (Value::F32(v), Some(scale)) => (v * scale as f32).to_string(),
+            (Value::F64(_), Some(scale)) => (raw * scale).to_string(),
+            (_, Some(scale)) => {
+                let decimals = scale
+                    .to_string()
+                    .split_once('.')
+                    .map_or(0, |(_, d)| d.len());
+                format!("{:.*}", decimals, raw * scale)
+            }
+        };
+        match unit {
+            Some(unit) => format!("{text} {unit}"),
+            None => text,
+        }
+    }
+}
+
+impl core::fmt::Display for Value {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Value::Bool(v) => write!(f, "{v}"),
+            Value::U8(v) => write!(f, "{v}"),
+            Value::U16(v) => write!(f, "{v}"),
+            Value::U32(v) => write!(f, "{v}"),
+            Value::U64(v) => write!(f, "{v}"),
+            Value::I8(v) => write!(f, "{v}"),
+            Value::I16(v) => write!(f, "{v}"),
+            Value::I32(v) => write!(f, "{v}"),
+            Value::I64(v) => write!(f, "{v}"),
+            Value::F32(v) => write!(f, "{v}"),
+            Value::F64(v) => write!(f, "{v}"),
+            Value::Str(s) => write!(f, "{s}"),
+            Value::Bytes(b) => {
+                for (i, byte) in b.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{byte:02x}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+macro_rules! impl_from {
+    ($t:ty, $variant:ident) => {
+        impl From<$t> for Value {
+            fn from(value: $t) -> Self {
+                Value::$variant(value)
+            }
+        }
+    };
+}
+
+impl_from!(bool, Bool);
+impl_from!(u8, U8);
+impl_from!(u16, U16);
+impl_from!(u32, U32);
+impl_from!(u64, U64);
+impl_from!(i8, I8);
+impl_from!(i16, I16);
+impl_from!(i32, I32);
+impl_from!(i64, I64);
+impl_from!(f32, F32);
+impl_from!(f64, F64);
+impl_from!(String, Str);
+impl_from!(Vec<u8>, Bytes);
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Str(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let values = [
+            Value::Bool(true),
+            Value::U8(200),
+            Value::U16(0x1234),
+            Value::U32(0xdeadbeef),
+            Value::U64(u64::MAX),
+            Value::I8(-5),
+            Value::I16(-300),
+            Value::I32(-70000),
+            Value::I64(i64::MIN),
+            Value::F32(1.5),
+            Value::F64(-2.25),
+            Value::Str("hello".into()),
+            Value::Bytes(vec![1, 2, 3]),
+        ];
+        for v in values {
+            let bytes = v.to_le_bytes();
+            assert_eq!(v, Value::from_le_bytes(v.data_type(), &bytes).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_wrong_size() {
+        assert_eq!(
+            Err(ValueError::WrongSize {
+                data_type: DataType::UInt32,
+                expected: 4,
+                actual: 2
+            }),
+            Value::from_le_bytes(DataType::UInt32, &[1, 2])
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Value::U16(0x1234),
+            Value::parse(DataType::UInt16, "0x1234").unwrap()
+        );
+        assert_eq!(Value::I8(-12), Value::parse(DataType::Int8, "-12").unwrap());
+        assert_eq!(
+            Value::Bytes(vec![0xde, 0xad]),
+            Value::parse(DataType::OctetString, "de ad").unwrap()
+        );
+        assert!(Value::parse(DataType::UInt8, "256")
+            .unwrap_err()
+            .to_string()
+            .contains("expected an integer in range [0..256]"));
+        assert!(Value::parse(DataType::Real32, "abc").is_err());
+        assert!(Value::parse(DataType::Int8, "--5").is_err());
+        assert!(Value::parse(DataType::Int8, "-0x-5").is_err());
+        assert!(Value::parse(DataType::Int8, "+-5").is_err());
+        assert_eq!(Value::U8(5), Value::parse(DataType::UInt8, "+5").unwrap());
+    }
+
+    #[test]
+    fn test_parse_hex_bytes() {
+        assert_eq!(Some(vec![1, 2, 0xff]), parse_hex_bytes("0x0102ff"));
+        assert_eq!(Some(vec![1, 2, 0xff]), parse_hex_bytes(" 01 02 FF "));
+        assert_eq!(Some(vec![]), parse_hex_bytes(""));
+        assert_eq!(None, parse_hex_bytes("012"));
+        assert_eq!(None, parse_hex_bytes("+1+2"));
+        assert_eq!(None, parse_hex_bytes("a\u{e9}b"));
+        assert_eq!(None, parse_hex_bytes("0g"));
+    }
+
+    #[test]
+    fn test_format_scaled() {
+        assert_eq!(
+            "3.321 V",
+            Value::U16(3321).format_scaled(Some(0.001), Some("V"))
+        );
+        assert_eq!("-1.50", Value::I8(-6).format_scaled(Some(0.25), None));
+        assert_eq!(
+            "400 rpm",
+            Value::U8(40).format_scaled(Some(10.0), Some("rpm"))
+        );
+        assert_eq!("12 mA", Value::U32(12).format_scaled(None, Some("mA")));
+        assert_eq!(
+            "1.25 m",
+            Value::F32(2.5).format_scaled(Some(0.5), Some("m"))
+        );
+        assert_eq!(
+            "abc",
+            Value::from("abc").format_scaled(Some(2.0), Some("V"))
+        );
+    }
+}
@@ -0,0 +1,513 @@
+//! Conversion of an EDS into a zencan device config
+//!
+//! This allows a node to be generated from an EDS by `zencan-build`, using the same code
+//! generation as a device config TOML file.
+
+use snafu::ResultExt as _;
+use zencan_common::device_config::{
+    AccessTraceConfig, AccessTypeDeser, ArrayDefinition, BootloaderConfig, DataType as DCDataType,
+    DebugLogConfig, DefaultValue, DeviceConfig, EventLogConfig, IdentityConfig, MboxConfig,
+    MemoryConfig, NmtConfig, NmtProtectionConfig, Object as DCObject, ObjectDefinition, PdoConfig,
+    PdoMapping, RecordDefinition, StubDefinition, SubDefinition, VarDefinition, CONFIG_VERSION,
+};
+use zencan_common::objects::{AccessType, DataType};
+
+use crate::{
+    DeviceConfigSnafu, ElectronicDataSheet, LoadError, Object, ObjectType, SubObject,
+    UnsupportedObjectSnafu,
+};
+
+/// Objects in the communication profile area are implemented by the node itself
+const COMM_PROFILE_AREA: core::ops::RangeInclusive<u16> = 0x1000..=0x1FFF;
+
+impl ElectronicDataSheet {
+    /// Create a device config for generating a node which implements this EDS
+    ///
+    /// The device name, identity, and PDO counts are taken from the DeviceInfo section. Objects in
+    /// the communication profile area (0x1000-0x1FFF) are skipped, because the node implements
+    /// these itself based on the device config settings. The verify configuration object (0x1020)
+    /// is enabled if the EDS lists it. All other objects are converted to var, array, or record
+    /// objects.
+    ///
+    /// String objects are sized to fit their default value, so they must have a non-empty
+    /// default. The custom `Unit` and `Scale` fields of a sub object, if present, are taken as
+    /// its [unit and scale](zencan_common::device_config#units).
+    pub fn to_device_config(&self) -> Result<DeviceConfig, LoadError> {
+        self.convert(false)
+    }
+
+    /// Create a device config, replacing objects which cannot be converted with stubs
+    ///
+    /// This is the same as [`to_device_config`](Self::to_device_config), except that an object
+    /// which cannot be represented in a device config, e.g. because it has an unsupported data
+    /// type, is replaced by a [stub](DCObject::Stub) recording the reason, instead of failing the
+    /// conversion.
+    pub fn to_device_config_permissive(&self) -> Result<DeviceConfig, LoadError> {
+        self.convert(true)
+    }
+
+    fn convert(&self, permissive: bool) -> Result<DeviceConfig, LoadError> {
+        let mut objects = Vec::new();
+        for object in self
+            .mandatory_objects
+            .iter()
+            .chain(&self.optional_objects)
+            .chain(&self.manufacturer_objects)
+        {
+            let index = object.object_number as u16;
+            if COMM_PROFILE_AREA.contains(&index) {
+                continue;
+            }
+            match convert_object(object) {
+                Ok(definition) => objects.push(definition),
+                Err(LoadError::UnsupportedObject { message, .. }) if permissive => {
+                    objects.push(stub_object(object, message))
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let config = DeviceConfig {
+            config_version: CONFIG_VERSION,
+            device_name: self.device_info.product_name.clone(),
+            support_storage: true,
+            statistics: false,
+            settings_backup: false,
+            verify_configuration: self
+                .optional_objects
+                .iter()
+                .any(|o| o.object_number == 0x1020),
+            sdo_status: false,
+            loopback_test: false,
+            hardware_version: String::new(),
+            software_version: String::new(),
+            software_version_size: None,
+            heartbeat_period: 0,
+            heartbeat_consumers: 0,
+            error_history: 0,
+            identity: IdentityConfig {
+                vendor_id: self.device_info.vendor_number.unwrap_or(0),
+                product_code: self.device_info.product_number.unwrap_or(0),
+                revision_number: self.device_info.revision_number,
+            },
+            pdos: PdoConfig {
+                num_rpdo: self.device_info.rpdo_count as u8,
+                num_tpdo: self.device_info.tpdo_count as u8,
+                tpdo_stamps: Vec::new(),
+                statistics: false,
+            },
+            mbox: MboxConfig::default(),
+            nmt: NmtConfig::default(),
+            nmt_protection: NmtProtectionConfig::default(),
+            access_trace: AccessTraceConfig::default(),
+            debug_log: DebugLogConfig::default(),
+            event_log: EventLogConfig::default(),
+            bootloader: BootloaderConfig::default(),
+            memory: MemoryConfig::default(),
+            link_section: None,
+            atomic_storage: false,
+            objects,
+        };
+        config.complete().context(DeviceConfigSnafu)
+    }
+}
+
+fn convert_object(object: &Object) -> Result<ObjectDefinition, LoadError> {
+    let index = object.object_number as u16;
+    let definition = match object.object_type {
+        ObjectType::Var => {
+            let sub = object.subs.get(&0).ok_or_else(|| {
+                UnsupportedObjectSnafu {
+                    index,
+                    message: "var object has no value",
+                }
+                .build()
+            })?;
+            DCObject::Var(VarDefinition {
+                data_type: convert_data_type(index, sub)?,
+                access_type: AccessTypeDeser(sub.access_type),
+                default_value: convert_default_value(index, sub)?,
+                pdo_mapping: convert_pdo_mapping(sub),
+                persist: false,
+                unit: sub.unit.clone(),
+                scale: sub.scale,
+            })
+        }
+        ObjectType::Array => {
+            let array_size = object.subs.keys().copied().max().unwrap_or(0) as usize;
+            let first = object.subs.get(&1).ok_or_else(|| {
+                UnsupportedObjectSnafu {
+                    index,
+                    message: "array object has no elements",
+                }
+                .build()
+            })?;
+            let data_type = convert_data_type(index, first)?;
+            let mut default_value = Vec::with_capacity(array_size);
+            let mut has_default = false;
+            for i in 1..=array_size {
+                let sub = object.subs.get(&(i as u8)).ok_or_else(|| {
+                    UnsupportedObjectSnafu {
+                        index,
+                        message: format!("array element {i} is not defined"),
+                    }
+                    .build()
+                })?;
+                if sub.data_type != first.data_type {
+                    return UnsupportedObjectSnafu {
+                        index,
+                        message: format!("array element {i} has a different data type"),
+                    }
+                    .fail();
+                }
+                let value = convert_default_value(index, sub)?;
+                has_default |= value.is_some();
+                default_value.push(value.unwrap_or_else(|| zero_value(data_type)));
+            }
+            DCObject::Array(ArrayDefinition {
+                data_type,
+                access_type: AccessTypeDeser(first.access_type),
+                array_size,
+                default_value: has_default.then_some(default_value),
+                pdo_mapping: convert_pdo_mapping(first),
+                persist: false,
+                unit: first.unit.clone(),
+                scale: first.scale,
+            })
+        }
+        ObjectType::Record => {
+            let mut sub_indices: Vec<u8> =
+                object.subs.keys().copied().filter(|s| *s != 0).collect();
+            sub_indices.sort();
+            let mut subs = Vec::with_capacity(sub_indices.len());
+            for sub_index in sub_indices {
+                let sub = &object.subs[&sub_index];
+                subs.push(SubDefinition {
+                    sub_index,
+                    parameter_name: sub.parameter_name.clone(),
+                    field_name: None,
+                    data_type: convert_data_type(index, sub)?,
+                    access_type: AccessTypeDeser(sub.access_type),
+                    default_value: convert_default_value(index, sub)?,
+                    pdo_mapping: convert_pdo_mapping(sub),
+                    persist: false,
+                    unit: sub.unit.clone(),
+                    scale: sub.scale,
+                });
+            }
+            DCObject::Record(RecordDefinition {
+                subs,
+                reserved_subs: Vec::new(),
+            })
+        }
+        ObjectType::Null | ObjectType::Unknown(_) => {
+            return UnsupportedObjectSnafu {
+                index,
+                message: format!("object type {:?} is not supported", object.object_type),
+            }
+            .fail()
+        }
+    };
+
+    Ok(ObjectDefinition {
+        index,
+        parameter_name: object.parameter_name.clone(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: definition,
+    })
+}
+
+/// Create a stub in place of an object which cannot be converted
+///
+/// Stubs are generated as a var, so a var reports its own data type, and other objects report
+/// DOMAIN.
+fn stub_object(object: &Object, reason: String) -> ObjectDefinition {
+    let data_type = match (object.object_type, object.subs.get(&0)) {
+        (ObjectType::Var, Some(sub)) => sub.data_type,
+        _ => DataType::Domain,
+    };
+    ObjectDefinition {
+        index: object.object_number as u16,
+        parameter_name: object.parameter_name.clone(),
+        application_callback: false,
+        on_write_callback: false,
+        link_section: None,
+        atomic_storage: None,
+        pooled: false,
+        object: DCObject::Stub(StubDefinition { data_type, reason }),
+    }
+}
+
+fn convert_data_type(index: u16, sub: &SubObject) -> Result<DCDataType, LoadError> {
+    let string_size = || {
+        let size = sub.default_value.len();
+        if size == 0 {
+            UnsupportedObjectSnafu {
+                index,
+                message: "string objects must have a default value to determine their size",
+            }
+            .fail()
+        } else {
+            Ok(size)
+        }
+    };
+    Ok(match sub.data_type {
+        DataType::Boolean => DCDataType::Boolean,
+        DataType::Int8 => DCDataType::Int8,
+        DataType::Int16 => DCDataType::Int16,
+        DataType::Int32 => DCDataType::Int32,
+        DataType::UInt8 => DCDataType::UInt8,
+        DataType::UInt16 => DCDataType::UInt16,
+        DataType::UInt32 => DCDataType::UInt32,
+        DataType::Real32 => DCDataType::Real32,
+        DataType::VisibleString => DCDataType::VisibleString(string_size()?),
+        DataType::OctetString => DCDataType::OctetString(string_size()?),
+        DataType::UnicodeString => DCDataType::UnicodeString(string_size()?),
+        DataType::Domain => DCDataType::Domain,
+        other => {
+            return UnsupportedObjectSnafu {
+                index,
+                message: format!("data type {:?} is not supported", other),
+            }
+            .fail()
+        }
+    })
+}
+
+fn convert_default_value(index: u16, sub: &SubObject) -> Result<Option<DefaultValue>, LoadError> {
+    let s = sub.default_value.trim();
+    if s.is_empty() || sub.data_type == DataType::Domain {
+        return Ok(None);
+    }
+    let invalid = || {
+        UnsupportedObjectSnafu {
+            index,
+            message: format!("invalid default value '{s}'"),
+        }
+        .build()
+    };
+    let value = match sub.data_type {
+        DataType::VisibleString | DataType::OctetString | DataType::UnicodeString => {
+            DefaultValue::String(s.to_string())
+        }
+        DataType::Real32 => DefaultValue::Float(s.parse().map_err(|_| invalid())?),
+        _ => DefaultValue::Integer(parse_int(s).ok_or_else(invalid)?),
+    };
+    Ok(Some(value))
+}
+
+/// Parse an integer value in decimal, or in hex with a 0x prefix
+fn parse_int(s: &str) -> Option<i64> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => digits.parse().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// The PDO mapping allowed for a mappable sub object is determined by its access type
+fn convert_pdo_mapping(sub: &SubObject) -> PdoMapping {
+    if !sub.pdo_mapping {
+        return PdoMapping::None;
+    }
+    match sub.access_type {
+        AccessType::Ro | AccessType::Const => PdoMapping::Tpdo,
+        AccessType::Wo => PdoMapping::Rpdo,
+        AccessType::Rw => PdoMapping::Both,
+    }
+}
+
+fn zero_value(data_type: DCDataType) -> DefaultValue {
+    if data_type.is_str() {
+        DefaultValue::String(String::new())
+    } else if matches!(data_type, DCDataType::Real32) {
+        DefaultValue::Float(0.0)
+    } else {
+        DefaultValue::Integer(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE_EDS: &str = include_str!("example.eds");
+
+    const MANUFACTURER_OBJECTS: &str = r#"
+[ManufacturerObjects]
+SupportedObjects=3
+1=0x2000
+2=0x2001
+3=0x2002
+
+[2000]
+ParameterName=Speed
+ObjectType=0x7
+DataType=0x0003
+AccessType=rw
+DefaultValue=-100
+PDOMapping=1
+Unit=rpm
+Scale=0.1
+
+[2001]
+ParameterName=Gains
+ObjectType=0x8
+SubNumber=0x3
+
+[2001sub0]
+ParameterName=Highest sub-index supported
+ObjectType=0x7
+DataType=0x0005
+AccessType=ro
+DefaultValue=0x02
+PDOMapping=0
+
+[2001sub1]
+ParameterName=Gain 1
+ObjectType=0x7
+DataType=0x0008
+AccessType=rw
+DefaultValue=1.5
+PDOMapping=0
+
+[2001sub2]
+ParameterName=Gain 2
+ObjectType=0x7
+DataType=0x0008
+AccessType=rw
+DefaultValue=
+PDOMapping=0
+
+[2002]
+ParameterName=Status
+ObjectType=0x9
+SubNumber=0x3
+
+[2002sub0]
+ParameterName=Highest sub-index supported
+ObjectType=0x7
+DataType=0x0005
+AccessType=ro
+DefaultValue=0x02
+PDOMapping=0
+
+[2002sub1]
+ParameterName=Flags
+ObjectType=0x7
+DataType=0x0007
+AccessType=ro
+DefaultValue=0x10
+PDOMapping=1
+
+[2002sub2]
+ParameterName=Label
+ObjectType=0x7
+DataType=0x0009
+AccessType=ro
+DefaultValue=idle
+PDOMapping=0
+"#;
+
+    #[test]
+    fn test_to_device_config() {
+        let eds = EXAMPLE_EDS.replace(
+            "[ManufacturerObjects]\nSupportedObjects=0",
+            MANUFACTURER_OBJECTS,
+        );
+        let eds = ElectronicDataSheet::from_str(eds).unwrap();
+        let config = eds.to_device_config().unwrap();
+
+        assert_eq!("New Product", config.device_name);
+        assert_eq!(4, config.pdos.num_rpdo);
+        assert_eq!(4, config.pdos.num_tpdo);
+
+        let find = |index| config.objects.iter().find(|o| o.index == index).unwrap();
+
+        let DCObject::Var(speed) = &find(0x2000).object else {
+            panic!("Expected var");
+        };
+        assert!(matches!(speed.data_type, DCDataType::Int16));
+        assert!(matches!(
+            speed.default_value,
+            Some(DefaultValue::Integer(-100))
+        ));
+        assert!(matches!(speed.pdo_mapping, PdoMapping::Both));
+        assert_eq!(Some("rpm"), speed.unit.as_deref());
+        assert_eq!(Some(0.1), speed.scale);
+
+        let DCObject::Array(gains) = &find(0x2001).object else {
+            panic!("Expected array");
+        };
+        assert_eq!(2, gains.array_size);
+        assert_eq!(None, gains.unit);
+        let defaults = gains.default_value.as_ref().unwrap();
+        assert!(matches!(defaults[0], DefaultValue::Float(f) if f == 1.5));
+        assert!(matches!(defaults[1], DefaultValue::Float(f) if f == 0.0));
+
+        let DCObject::Record(status) = &find(0x2002).object else {
+            panic!("Expected record");
+        };
+        assert_eq!(2, status.subs.len());
+        assert_eq!("Flags", status.subs[0].parameter_name);
+        assert!(matches!(status.subs[0].pdo_mapping, PdoMapping::Tpdo));
+        assert!(matches!(
+            status.subs[1].data_type,
+            DCDataType::VisibleString(4)
+        ));
+
+        // Communication objects come from the node, not the EDS
+        let device_type = find(0x1000);
+        assert_eq!("Device Type", device_type.parameter_name);
+    }
+
+    #[test]
+    fn test_to_device_config_permissive() {
+        const INT64_OBJECT: &str = r#"
+[ManufacturerObjects]
+SupportedObjects=1
+1=0x2000
+
+[2000]
+ParameterName=Position
+ObjectType=0x7
+DataType=0x0015
+AccessType=ro
+DefaultValue=0
+PDOMapping=0
+"#;
+        let eds = EXAMPLE_EDS.replace("[ManufacturerObjects]\nSupportedObjects=0", INT64_OBJECT);
+        let eds = ElectronicDataSheet::from_str(eds).unwrap();
+        assert!(matches!(
+            eds.to_device_config(),
+            Err(LoadError::UnsupportedObject { index: 0x2000, .. })
+        ));
+
+        let config = eds.to_device_config_permissive().unwrap();
+        let obj = config.objects.iter().find(|o| o.index == 0x2000).unwrap();
+        assert_eq!("Position", obj.parameter_name);
+        let DCObject::Stub(stub) = &obj.object else {
+            panic!("Expected stub");
+        };
+        assert_eq!(DataType::Int64, stub.data_type);
+        assert!(stub.reason.contains("Int64"));
+    }
+
+    #[test]
+    fn test_parse_int() {
+        assert_eq!(Some(16), parse_int("0x10"));
+        assert_eq!(Some(-5), parse_int("-5"));
+        assert_eq!(None, parse_int("$NODEID+0x180"));
+    }
+}
@@ -4,6 +4,8 @@ use std::{collections::HashMap, path::Path};
 
 use zencan_common::objects::{AccessType, DataType};
 
+mod convert;
+
 #[derive(Debug, Snafu)]
 pub enum LoadError {
     IniFormatError {
@@ -16,6 +18,17 @@ pub enum LoadError {
         message: String,
         source: std::num::ParseIntError,
     },
+    /// An object cannot be represented in a zencan device config
+    #[snafu(display("Unsupported object 0x{index:x}: {message}"))]
+    UnsupportedObject {
+        index: u16,
+        message: String,
+    },
+    /// The device config created from the EDS is not valid
+    #[snafu(display("Invalid device config: {source}"))]
+    DeviceConfig {
+        source: zencan_common::device_config::LoadError,
+    },
 }
 
 #[derive(Clone, Debug, Default)]
@@ -116,6 +129,7 @@ pub struct Object {
 
 #[derive(Clone, Debug, Default)]
 pub struct SubObject {
+    pub parameter_name: String,
     pub data_type: DataType,
     pub access_type: AccessType,
     pub low_limit: Option<String>,
@@ -123,6 +137,10 @@ pub struct SubObject {
     pub default_value: String,
     /// True if this object can be mapped into a PDO
     pub pdo_mapping: bool,
+    /// The unit of the value, from the custom `Unit` field
+    pub unit: Option<String>,
+    /// The factor which converts the raw value to `unit`, from the custom `Scale` field
+    pub scale: Option<f64>,
 }
 
 struct Section<'a> {
@@ -236,6 +254,26 @@ impl<'a> Section<'a> {
         })?))
     }
 
+    /// Read an optional field as a float
+    ///
+    /// If the field is missing or empty, None is returned.
+    pub fn get_f64_opt(&self, field: &str) -> Result<Option<f64>, LoadError> {
+        let str_value = match self.map.get(&field.to_lowercase()) {
+            Some(Some(value)) if !value.is_empty() => value,
+            _ => return Ok(None),
+        };
+        match str_value.parse() {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => EdsFormatSnafu {
+                message: format!(
+                    "Invalid float '{}' for '{}' in section '{}'",
+                    str_value, field, self.section
+                ),
+            }
+            .fail(),
+        }
+    }
+
     pub fn get_bool(&self, field: &str) -> Result<bool, LoadError> {
         // Boolean is stored as 0 or 1
         // Read as u32, and cast
@@ -245,12 +283,18 @@ impl<'a> Section<'a> {
 
 fn get_sub_object(section: &Section) -> Result<SubObject, LoadError> {
     Ok(SubObject {
+        parameter_name: section.get_string("ParameterName").unwrap_or_default(),
         data_type: DataType::from(section.get_u32_hex("DataType")? as u16),
         access_type: str_to_access_type(&section.get_string("AccessType")?)?,
         low_limit: section.get_string("LowLimit").ok(),
         high_limit: section.get_string("HighLimit").ok(),
         default_value: section.get_string("DefaultValue")?,
         pdo_mapping: section.get_bool("PDOMapping")?,
+        unit: section
+            .get_string("Unit")
+            .ok()
+            .filter(|unit| !unit.is_empty()),
+        scale: section.get_f64_opt("Scale")?,
     })
 }
 
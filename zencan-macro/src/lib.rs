@@ -1,16 +1,204 @@
-// use eds_parser::ElectronicDataSheet;
-
 extern crate proc_macro;
-use std::str::FromStr as _;
-use zencan_build::device_config_to_string;
-use zencan_common::device_config::DeviceConfig;
+use std::path::{Path, PathBuf};
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use zencan_build::device_config_to_tokens;
+use zencan_common::device_config::{DeviceConfig, LoadError};
 
-/// Macro to build an object dict from inline device config TOML
+mod object_derive;
+
+/// Macro to build an object dict from a device config
+///
+/// The device config TOML can be provided inline as a string literal, or read from a file with
+/// `file = "path"`, where the path is relative to the crate's `CARGO_MANIFEST_DIR`.
+///
+/// ```ignore
+/// zencan_node::build_object_dict!(file = "device_config.toml");
+/// ```
+///
+/// Errors in the device config are reported as compile errors, including the line and column of
+/// the offending TOML.
 #[proc_macro]
 pub fn build_object_dict(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let str_literal: syn::LitStr =
-        syn::parse(input).expect("Expected string literal as macro argument");
-    let device =
-        DeviceConfig::load_from_str(&str_literal.value()).expect("Error parsing device config");
-    proc_macro::TokenStream::from_str(&device_config_to_string(&device, true).unwrap()).unwrap()
+    let input = syn::parse_macro_input!(input as DictInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Derive `ProvidesSubObjects` for a custom object struct
+///
+/// Each field which implements a sub object is annotated with a `#[canopen(...)]` attribute giving
+/// its sub index and metadata:
+///
+/// - `sub`: The sub index (required)
+/// - `data_type`: The data type, using the same names as a device config file, e.g. `"uint32"` or
+///   `"visiblestring(16)"` (required)
+/// - `access`: `"ro"`, `"rw"`, `"wo"`, or `"const"`. Default: `"ro"`
+/// - `pdo`: `"none"`, `"tpdo"`, `"rpdo"`, or `"both"`. Default: `"none"`
+/// - `persist`: Set to save the sub object when the save command is received
+/// - `size`: Override the size, for types whose size is not implied by the data type
+///
+/// The field types must implement `SubObjectAccess`. Fields without an attribute are ignored, and a
+/// field holding the object's event flags can be marked with `#[canopen(flags)]`.
+///
+/// The object is a record unless `#[canopen(object_code = "var")]` or `"array"` is set on the
+/// struct. For records and arrays, sub index 0 is generated to return the highest sub index, unless
+/// a field implements it.
+///
+/// ```ignore
+/// #[derive(CanOpenObject)]
+/// struct CustomObject {
+///     #[canopen(sub = 1, data_type = "uint32", access = "rw", persist)]
+///     setpoint: ScalarField<u32>,
+///     #[canopen(sub = 2, data_type = "real32", pdo = "tpdo")]
+///     measurement: ExternalSubObject,
+/// }
+/// ```
+#[proc_macro_derive(CanOpenObject, attributes(canopen))]
+pub fn derive_canopen_object(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as syn::DeriveInput);
+    match object_derive::derive(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.write_errors().into(),
+    }
+}
+
+/// The argument of `build_object_dict!`
+enum DictInput {
+    /// Device config TOML provided inline
+    Inline(syn::LitStr),
+    /// Path to a device config file, relative to CARGO_MANIFEST_DIR
+    File(syn::LitStr),
+}
+
+const EXPECTED_INPUT: &str = "expected device config TOML string, or `file = \"path\"`";
+
+impl Parse for DictInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(syn::LitStr) {
+            return Ok(Self::Inline(input.parse()?));
+        }
+        let key: syn::Ident = input
+            .parse()
+            .map_err(|e: syn::Error| syn::Error::new(e.span(), EXPECTED_INPUT))?;
+        if key != "file" {
+            return Err(syn::Error::new(key.span(), EXPECTED_INPUT));
+        }
+        input.parse::<syn::Token![=]>()?;
+        Ok(Self::File(input.parse()?))
+    }
+}
+
+fn expand(input: &DictInput) -> syn::Result<TokenStream> {
+    let (config_str, span, path) = match input {
+        DictInput::Inline(lit) => (lit.value(), lit.span(), None),
+        DictInput::File(lit) => {
+            let path = resolve_path(&lit.value(), lit.span())?;
+            let config_str = std::fs::read_to_string(&path).map_err(|e| {
+                syn::Error::new(
+                    lit.span(),
+                    format!("Failed to read {}: {}", path.display(), e),
+                )
+            })?;
+            (config_str, lit.span(), Some(path))
+        }
+    };
+
+    let device = DeviceConfig::load_from_str(&config_str)
+        .map_err(|e| syn::Error::new(span, load_error_message(&e, &config_str, path.as_deref())))?;
+    let code = device_config_to_tokens(&device)
+        .map_err(|e| syn::Error::new(span, format!("Error generating object dict: {e}")))?;
+
+    // Include the file, so that the crate is rebuilt when it changes
+    let rebuild = path.map(|path| {
+        let path = path.to_string_lossy();
+        quote!(
+            const _: &[u8] = include_bytes!(#path);
+        )
+    });
+
+    Ok(quote! {
+        #code
+        #rebuild
+    })
+}
+
+fn resolve_path(path: &str, span: Span) -> syn::Result<PathBuf> {
+    let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR")
+        .ok_or_else(|| syn::Error::new(span, "CARGO_MANIFEST_DIR is not set"))?;
+    Ok(Path::new(&manifest_dir).join(path))
+}
+
+/// Create an error message for a device config which failed to load
+///
+/// TOML errors are reported with the line and column where they occurred, and the text at that
+/// location, rather than the multi-line report from the TOML parser.
+fn load_error_message(err: &LoadError, config_str: &str, path: Option<&Path>) -> String {
+    let source = match path {
+        Some(path) => path.display().to_string(),
+        None => "device config".to_string(),
+    };
+    let LoadError::TomlParsing { source: toml_err } = err else {
+        return format!("Error in {source}: {err}");
+    };
+    let Some(range) = toml_err.span() else {
+        return format!("Error in {source}: {}", toml_err.message());
+    };
+
+    let before = &config_str[..range.start];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+    let text = config_str[range].lines().next().unwrap_or("").trim();
+    if text.is_empty() {
+        format!(
+            "Error in {source} at line {line}, column {column}: {}",
+            toml_err.message()
+        )
+    } else {
+        format!(
+            "Error in {source} at line {line}, column {column} (`{text}`): {}",
+            toml_err.message()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_input() {
+        let input: DictInput = syn::parse_str(r#""device_name = 'x'""#).unwrap();
+        assert!(matches!(input, DictInput::Inline(_)));
+
+        let input: DictInput = syn::parse_str(r#"file = "config.toml""#).unwrap();
+        let DictInput::File(lit) = input else {
+            panic!("Expected file input");
+        };
+        assert_eq!("config.toml", lit.value());
+
+        assert!(syn::parse_str::<DictInput>(r#"path = "config.toml""#).is_err());
+        assert!(syn::parse_str::<DictInput>("42").is_err());
+    }
+
+    #[test]
+    fn test_load_error_message() {
+        const CONFIG: &str = "device_name = \"test\"\n[identity]\nvendor_id = \"one\"\n";
+        let err = DeviceConfig::load_from_str(CONFIG).unwrap_err();
+        let message = load_error_message(&err, CONFIG, None);
+        assert!(
+            message.starts_with("Error in device config at line 3"),
+            "{message}"
+        );
+
+        let message = load_error_message(&err, CONFIG, Some(Path::new("dev.toml")));
+        assert!(
+            message.starts_with("Error in dev.toml at line 3"),
+            "{message}"
+        );
+    }
 }
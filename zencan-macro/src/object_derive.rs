@@ -0,0 +1,358 @@
+//! Implementation of `#[derive(CanOpenObject)]`
+
+use darling::{ast, FromDeriveInput, FromField};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+#[derive(FromDeriveInput)]
+#[darling(attributes(canopen), supports(struct_named))]
+struct ObjectOpts {
+    ident: syn::Ident,
+    generics: syn::Generics,
+    data: ast::Data<(), FieldOpts>,
+    /// The object code: "var", "array", or "record"
+    #[darling(default)]
+    object_code: Option<syn::LitStr>,
+}
+
+#[derive(FromField)]
+#[darling(attributes(canopen))]
+struct FieldOpts {
+    ident: Option<syn::Ident>,
+    /// The sub index this field implements
+    #[darling(default)]
+    sub: Option<u8>,
+    /// The data type, using the same names as a device config file
+    #[darling(default)]
+    data_type: Option<syn::LitStr>,
+    /// The access type: "ro", "rw", "wo", or "const"
+    #[darling(default)]
+    access: Option<syn::LitStr>,
+    /// The PDO mapping: "none", "tpdo", "rpdo", or "both"
+    #[darling(default)]
+    pdo: Option<syn::LitStr>,
+    /// Overrides the size of the sub object derived from the data type
+    #[darling(default)]
+    size: Option<usize>,
+    #[darling(default)]
+    persist: bool,
+    /// Marks the field which holds the object's event flags
+    #[darling(default)]
+    flags: bool,
+}
+
+/// A field which implements a sub object
+struct SubField {
+    ident: syn::Ident,
+    sub: u8,
+    data_type: TokenStream,
+    size: usize,
+    access: TokenStream,
+    pdo: TokenStream,
+    persist: bool,
+}
+
+pub fn derive(input: &syn::DeriveInput) -> darling::Result<TokenStream> {
+    let opts = ObjectOpts::from_derive_input(input)?;
+    let mut errors = darling::Error::accumulator();
+
+    let object_code = match &opts.object_code {
+        None => quote!(Record),
+        Some(lit) => match lit.value().to_lowercase().as_str() {
+            "var" => quote!(Var),
+            "array" => quote!(Array),
+            "record" => quote!(Record),
+            other => {
+                errors.push(
+                    darling::Error::custom(format!(
+                        "Invalid object_code '{other}'; expected var, array, or record"
+                    ))
+                    .with_span(lit),
+                );
+                quote!(Record)
+            }
+        },
+    };
+    let is_var = matches!(&opts.object_code, Some(lit) if lit.value().eq_ignore_ascii_case("var"));
+
+    let fields = opts
+        .data
+        .take_struct()
+        .expect("supports(struct_named) guarantees a struct")
+        .fields;
+
+    let mut subs: Vec<SubField> = Vec::new();
+    let mut flags_field = None;
+    for field in fields {
+        let ident = field
+            .ident
+            .clone()
+            .expect("named struct fields have idents");
+        if field.flags {
+            if flags_field.is_some() {
+                errors.push(
+                    darling::Error::custom("Only one field may hold flags").with_span(&ident),
+                );
+            }
+            flags_field = Some(ident);
+            continue;
+        }
+        let Some(sub) = field.sub else {
+            // Fields without a sub index are not part of the object
+            if field.data_type.is_some() {
+                errors.push(
+                    darling::Error::custom("Field with a data_type must have a sub index")
+                        .with_span(&ident),
+                );
+            }
+            continue;
+        };
+        if let Some(other) = subs.iter().find(|s| s.sub == sub) {
+            errors.push(
+                darling::Error::custom(format!(
+                    "Sub index {sub} is already implemented by `{}`",
+                    other.ident
+                ))
+                .with_span(&ident),
+            );
+            continue;
+        }
+        if is_var && sub != 0 {
+            errors.push(
+                darling::Error::custom("Var objects may only implement sub index 0")
+                    .with_span(&ident),
+            );
+        }
+        if let Some(sub_field) = errors.handle(parse_sub_field(ident, sub, &field)) {
+            subs.push(sub_field);
+        }
+    }
+    if subs.is_empty() {
+        errors.push(darling::Error::custom(
+            "CanOpenObject requires at least one field with a `#[canopen(sub = ...)]` attribute",
+        ));
+    }
+    errors.finish()?;
+
+    subs.sort_by_key(|s| s.sub);
+    let max_sub = subs.last().map(|s| s.sub).unwrap_or(0);
+    let has_sub0 = subs.first().map(|s| s.sub == 0).unwrap_or(false);
+
+    let mut match_arms = TokenStream::new();
+    if !is_var && !has_sub0 {
+        match_arms.extend(quote! {
+            0 => Some((
+                ::zencan_node::common::objects::SubInfo::MAX_SUB_NUMBER,
+                const { &::zencan_node::object_dict::ConstField::new(#max_sub.to_le_bytes()) },
+            )),
+        });
+    }
+    for s in &subs {
+        let SubField {
+            ident,
+            sub,
+            data_type,
+            size,
+            access,
+            pdo,
+            persist,
+        } = s;
+        match_arms.extend(quote! {
+            #sub => Some((
+                ::zencan_node::common::objects::SubInfo {
+                    size: #size,
+                    data_type: ::zencan_node::common::objects::DataType::#data_type,
+                    access_type: ::zencan_node::common::objects::AccessType::#access,
+                    pdo_mapping: ::zencan_node::common::objects::PdoMapping::#pdo,
+                    persist: #persist,
+                },
+                &self.#ident,
+            )),
+        });
+    }
+
+    let flags_fn = flags_field.map(|ident| {
+        quote! {
+            fn flags(&self) -> Option<&dyn ::zencan_node::object_dict::ObjectFlagAccess> {
+                Some(&self.#ident)
+            }
+        }
+    });
+
+    let name = &opts.ident;
+    let (impl_generics, ty_generics, where_clause) = opts.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::zencan_node::object_dict::ProvidesSubObjects for #name #ty_generics #where_clause {
+            fn get_sub_object(
+                &self,
+                sub: u8,
+            ) -> Option<(
+                ::zencan_node::common::objects::SubInfo,
+                &dyn ::zencan_node::object_dict::SubObjectAccess,
+            )> {
+                match sub {
+                    #match_arms
+                    _ => None,
+                }
+            }
+
+            #flags_fn
+
+            fn object_code(&self) -> ::zencan_node::common::objects::ObjectCode {
+                ::zencan_node::common::objects::ObjectCode::#object_code
+            }
+        }
+    })
+}
+
+fn parse_sub_field(ident: syn::Ident, sub: u8, field: &FieldOpts) -> darling::Result<SubField> {
+    let Some(data_type_lit) = &field.data_type else {
+        return Err(darling::Error::custom(format!(
+            "Sub index {sub} requires a data_type attribute"
+        ))
+        .with_span(&ident));
+    };
+    let (data_type, type_size) = parse_data_type(&data_type_lit.value()).ok_or_else(|| {
+        darling::Error::custom(format!("Invalid data_type '{}'", data_type_lit.value()))
+            .with_span(data_type_lit)
+    })?;
+    let size = match (field.size, type_size) {
+        (Some(size), _) => size,
+        (None, Some(size)) => size,
+        (None, None) => {
+            return Err(darling::Error::custom(format!(
+                "data_type '{}' requires a size attribute",
+                data_type_lit.value()
+            ))
+            .with_span(data_type_lit))
+        }
+    };
+
+    let access = match &field.access {
+        None => quote!(Ro),
+        Some(lit) => match lit.value().to_lowercase().as_str() {
+            "ro" => quote!(Ro),
+            "rw" => quote!(Rw),
+            "wo" => quote!(Wo),
+            "const" => quote!(Const),
+            other => {
+                return Err(darling::Error::custom(format!(
+                    "Invalid access '{other}'; expected ro, rw, wo, or const"
+                ))
+                .with_span(lit))
+            }
+        },
+    };
+
+    let pdo = match &field.pdo {
+        None => quote!(None),
+        Some(lit) => match lit.value().to_lowercase().as_str() {
+            "none" => quote!(None),
+            "tpdo" => quote!(Tpdo),
+            "rpdo" => quote!(Rpdo),
+            "both" => quote!(Both),
+            other => {
+                return Err(darling::Error::custom(format!(
+                    "Invalid pdo '{other}'; expected none, tpdo, rpdo, or both"
+                ))
+                .with_span(lit))
+            }
+        },
+    };
+
+    Ok(SubField {
+        ident,
+        sub,
+        data_type,
+        size,
+        access,
+        pdo,
+        persist: field.persist,
+    })
+}
+
+/// Parse a data type name into the `DataType` variant, and its size if it is fixed
+///
+/// String types may include their size, e.g. "visiblestring(16)"
+fn parse_data_type(s: &str) -> Option<(TokenStream, Option<usize>)> {
+    let s = s.to_lowercase();
+    let (name, size) = match s.split_once('(') {
+        Some((name, rest)) => (name, Some(rest.strip_suffix(')')?.parse::<usize>().ok()?)),
+        None => (s.as_str(), None),
+    };
+    let fixed = |tokens, type_size| {
+        // Fixed size types do not accept a size in the name
+        size.is_none().then_some((tokens, Some(type_size)))
+    };
+    match name {
+        "boolean" => fixed(quote!(Boolean), 1),
+        "int8" => fixed(quote!(Int8), 1),
+        "int16" => fixed(quote!(Int16), 2),
+        "int32" => fixed(quote!(Int32), 4),
+        "uint8" => fixed(quote!(UInt8), 1),
+        "uint16" => fixed(quote!(UInt16), 2),
+        "uint32" => fixed(quote!(UInt32), 4),
+        "real32" => fixed(quote!(Real32), 4),
+        "visiblestring" => Some((quote!(VisibleString), size)),
+        "octetstring" => Some((quote!(OctetString), size)),
+        "unicodestring" => Some((quote!(UnicodeString), size)),
+        "domain" => Some((quote!(Domain), Some(size.unwrap_or(0)))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_data_type() {
+        let (tokens, size) = parse_data_type("UInt32").unwrap();
+        assert_eq!("UInt32", tokens.to_string());
+        assert_eq!(Some(4), size);
+
+        let (tokens, size) = parse_data_type("visiblestring(12)").unwrap();
+        assert_eq!("VisibleString", tokens.to_string());
+        assert_eq!(Some(12), size);
+
+        assert_eq!(None, parse_data_type("octetstring").unwrap().1);
+        assert!(parse_data_type("uint32(4)").is_none());
+        assert!(parse_data_type("float").is_none());
+    }
+
+    #[test]
+    fn test_derive_errors() {
+        let input: syn::DeriveInput = syn::parse_quote! {
+            struct Obj {
+                #[canopen(sub = 1, data_type = "uint32")]
+                a: ScalarField<u32>,
+                #[canopen(sub = 1, data_type = "uint8")]
+                b: ScalarField<u8>,
+                #[canopen(sub = 2)]
+                c: ScalarField<u8>,
+            }
+        };
+        let err = derive(&input).unwrap_err();
+        assert_eq!(2, err.len());
+    }
+
+    #[test]
+    fn test_derive_record() {
+        let input: syn::DeriveInput = syn::parse_quote! {
+            struct Obj {
+                #[canopen(sub = 2, data_type = "real32", access = "rw", pdo = "tpdo", persist)]
+                b: ScalarField<f32>,
+                #[canopen(sub = 1, data_type = "uint32")]
+                a: ScalarField<u32>,
+                #[canopen(flags)]
+                flags: ObjectFlags<1>,
+                other: u32,
+            }
+        };
+        let output = derive(&input).unwrap().to_string();
+        assert!(output.contains("ObjectCode :: Record"));
+        assert!(output.contains("ConstField :: new (2u8 . to_le_bytes ())"));
+        assert!(output.contains("fn flags"));
+        assert!(!output.contains("self . other"));
+    }
+}
@@ -0,0 +1,83 @@
+/*
+ * C interface to a zencan node
+ *
+ * See the zencan-node-ffi crate documentation for how to create a node, and for which functions
+ * may be called concurrently.
+ */
+#ifndef ZENCAN_NODE_H
+#define ZENCAN_NODE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Set in zencan_frame_t.flags for a frame with an extended (29-bit) ID */
+#define ZENCAN_FRAME_EXTENDED (1u << 0)
+/* Set in zencan_frame_t.flags for a remote transmission request */
+#define ZENCAN_FRAME_RTR (1u << 1)
+
+/* Value of zencan_process_result_t.next_action_us when nothing is scheduled */
+#define ZENCAN_NO_ACTION UINT64_MAX
+
+/* Returned by the object access functions on success. Other values are SDO abort codes. */
+#define ZENCAN_OK 0u
+
+/* A node, created by the Rust glue code */
+typedef struct ZencanNode zencan_node_t;
+
+/* A CAN frame */
+typedef struct {
+    /* The CAN ID, without any flags */
+    uint32_t id;
+    /* A combination of ZENCAN_FRAME_EXTENDED and ZENCAN_FRAME_RTR */
+    uint8_t flags;
+    /* The number of valid bytes in data, from 0 to 8 */
+    uint8_t dlc;
+    /* The data payload */
+    uint8_t data[8];
+} zencan_frame_t;
+
+/* The result of zencan_node_process */
+typedef struct {
+    /* True if objects were updated by an SDO download or a received RPDO */
+    bool objects_updated;
+    /* The time in microseconds until zencan_node_process next needs to be called, or
+     * ZENCAN_NO_ACTION */
+    uint64_t next_action_us;
+} zencan_process_result_t;
+
+/* Called by zencan_node_process for each frame to transmit. The frame is only valid for the
+ * duration of the call. */
+typedef void (*zencan_send_callback_t)(void *ctx, const zencan_frame_t *frame);
+
+/* Pass a received CAN frame to the node. Returns false if the frame was not stored. */
+bool zencan_node_handle_frame(const zencan_node_t *node, const zencan_frame_t *frame);
+
+/* Run the node. now_us is a monotonic time in microseconds. */
+zencan_process_result_t zencan_node_process(zencan_node_t *node, uint64_t now_us,
+                                            zencan_send_callback_t send, void *ctx);
+
+/* Read a sub object into buf. The size of the value is stored to out_len, even if buf is too
+ * small. Returns ZENCAN_OK or an SDO abort code. */
+uint32_t zencan_node_read_object(const zencan_node_t *node, uint16_t index, uint8_t sub,
+                                 uint8_t *buf, size_t len, size_t *out_len);
+
+/* Write a sub object. Returns ZENCAN_OK or an SDO abort code. */
+uint32_t zencan_node_write_object(const zencan_node_t *node, uint16_t index, uint8_t sub,
+                                  const uint8_t *data, size_t len);
+
+/* Get the current node ID, or 255 if the node is unconfigured */
+uint8_t zencan_node_id(const zencan_node_t *node);
+
+/* Get the current NMT state, as encoded in heartbeat messages */
+uint8_t zencan_node_nmt_state(const zencan_node_t *node);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ZENCAN_NODE_H */
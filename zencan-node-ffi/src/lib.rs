@@ -0,0 +1,383 @@
+//! A C ABI for the zencan node
+//!
+//! This crate allows existing C firmware to embed a zencan [`Node`], so that a device can be
+//! migrated to Rust incrementally. The object dictionary is still generated by `zencan-build`, and
+//! the node is created in a small amount of Rust glue code, which hands a [`ZencanNode`] pointer to
+//! the C application. From then on, the C application drives the node through the functions in
+//! this crate, which are declared in `include/zencan_node.h`:
+//!
+//! - [`zencan_node_handle_frame`] passes a received CAN frame to the node
+//! - [`zencan_node_process`] runs the node, transmitting frames through a callback
+//! - [`zencan_node_read_object`] and [`zencan_node_write_object`] access objects by index and sub
+//!   index, e.g. to read values written by PDOs, or update values sent in TPDOs
+//!
+//! The glue code is typically built, together with this crate, into a static library which is
+//! linked into the firmware:
+//!
+//! ```ignore
+//! use static_cell::StaticCell;
+//! use zencan_node::{common::NodeId, Node};
+//! use zencan_node_ffi::ZencanNode;
+//!
+//! mod zencan {
+//!     zencan_node::include_modules!(ZENCAN_CONFIG);
+//! }
+//!
+//! #[no_mangle]
+//! pub extern "C" fn app_zencan_init(node_id: u8) -> *mut ZencanNode {
+//!     static NODE: StaticCell<ZencanNode> = StaticCell::new();
+//!     let node_id = NodeId::try_from(node_id).unwrap_or(NodeId::Unconfigured);
+//!     let node = Node::new(node_id, &zencan::NODE_MBOX, &zencan::NODE_STATE, &zencan::OD_TABLE);
+//!     NODE.init(ZencanNode::new(node, &zencan::NODE_MBOX, &zencan::OD_TABLE))
+//! }
+//! ```
+//!
+//! # Concurrency
+//!
+//! [`zencan_node_handle_frame`], [`zencan_node_read_object`] and [`zencan_node_write_object`] only
+//! access the mailbox and object dictionary, which are safe to share, so they may be called from an
+//! interrupt handler or another thread while [`zencan_node_process`] runs. All other functions must
+//! not be called concurrently with each other, or with `zencan_node_process`.
+#![no_std]
+#![warn(missing_docs, missing_debug_implementations)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+
+use core::ffi::c_void;
+
+use zencan_node::common::{
+    messages::{CanId, CanMessage},
+    sdo::AbortCode,
+};
+use zencan_node::object_dict::{find_object, ODEntry};
+use zencan_node::{Node, NodeMbox};
+
+/// Set in [`ZencanFrame::flags`] for a frame with an extended (29-bit) ID
+pub const ZENCAN_FRAME_EXTENDED: u8 = 1 << 0;
+/// Set in [`ZencanFrame::flags`] for a remote transmission request
+pub const ZENCAN_FRAME_RTR: u8 = 1 << 1;
+
+/// Value of [`ZencanProcessResult::next_action_us`] when nothing is scheduled
+pub const ZENCAN_NO_ACTION: u64 = u64::MAX;
+
+/// Returned by the object access functions on success
+pub const ZENCAN_OK: u32 = 0;
+
+/// A CAN frame passed across the C ABI
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ZencanFrame {
+    /// The CAN ID, without any flags
+    pub id: u32,
+    /// A combination of [`ZENCAN_FRAME_EXTENDED`] and [`ZENCAN_FRAME_RTR`]
+    pub flags: u8,
+    /// The number of valid bytes in `data`, from 0 to 8
+    pub dlc: u8,
+    /// The data payload
+    pub data: [u8; 8],
+}
+
+impl From<CanMessage> for ZencanFrame {
+    fn from(msg: CanMessage) -> Self {
+        let mut flags = 0;
+        if msg.id().is_extended() {
+            flags |= ZENCAN_FRAME_EXTENDED;
+        }
+        if msg.is_rtr() {
+            flags |= ZENCAN_FRAME_RTR;
+        }
+        let mut data = [0; 8];
+        data[..msg.data().len()].copy_from_slice(msg.data());
+        Self {
+            id: msg.id().raw(),
+            flags,
+            dlc: msg.data().len() as u8,
+            data,
+        }
+    }
+}
+
+impl ZencanFrame {
+    /// Convert to a [`CanMessage`], or None if the ID or DLC is out of range
+    fn to_message(self) -> Option<CanMessage> {
+        let id = if self.flags & ZENCAN_FRAME_EXTENDED != 0 {
+            if self.id > 0x1FFF_FFFF {
+                return None;
+            }
+            CanId::extended(self.id)
+        } else {
+            if self.id > 0x7FF {
+                return None;
+            }
+            CanId::std(self.id as u16)
+        };
+        if self.flags & ZENCAN_FRAME_RTR != 0 {
+            Some(CanMessage::new_rtr(id))
+        } else if self.dlc <= 8 {
+            Some(CanMessage::new(id, &self.data[..self.dlc as usize]))
+        } else {
+            None
+        }
+    }
+}
+
+/// The result of [`zencan_node_process`]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZencanProcessResult {
+    /// True if objects were updated by an SDO download or a received RPDO
+    pub objects_updated: bool,
+    /// The time in microseconds until `zencan_node_process` next needs to be called, or
+    /// [`ZENCAN_NO_ACTION`] if nothing is scheduled
+    pub next_action_us: u64,
+}
+
+/// Called by [`zencan_node_process`] for each frame to transmit
+///
+/// `ctx` is the pointer passed to `zencan_node_process`, and `frame` is only valid for the duration
+/// of the call.
+pub type ZencanSendCallback =
+    Option<unsafe extern "C" fn(ctx: *mut c_void, frame: *const ZencanFrame)>;
+
+/// A node, along with its mailbox and object dictionary, for access from C
+///
+/// C code only sees this as an opaque pointer.
+pub struct ZencanNode {
+    node: Node,
+    mbox: &'static NodeMbox,
+    od: &'static [ODEntry<'static>],
+}
+
+impl core::fmt::Debug for ZencanNode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ZencanNode")
+            .field("node_id", &self.node.node_id())
+            .field("objects", &self.od.len())
+            .finish()
+    }
+}
+
+impl ZencanNode {
+    /// Create a node handle
+    ///
+    /// `mbox` and `od` must be the same objects which were used to create the node.
+    pub fn new(node: Node, mbox: &'static NodeMbox, od: &'static [ODEntry<'static>]) -> Self {
+        Self { node, mbox, od }
+    }
+
+    /// Get the node, for calls which are not exposed through the C ABI
+    pub fn node(&mut self) -> &mut Node {
+        &mut self.node
+    }
+}
+
+/// Read the object dictionary from a handle without creating a reference to the whole handle,
+/// which may be concurrently borrowed by [`zencan_node_process`]
+///
+/// # Safety
+///
+/// `node` must be non-null, and point to a valid `ZencanNode`
+unsafe fn od(node: *const ZencanNode) -> &'static [ODEntry<'static>] {
+    core::ptr::addr_of!((*node).od).read()
+}
+
+/// Pass a received CAN frame to the node
+///
+/// Returns true if the frame was stored for the next call to [`zencan_node_process`], or false if
+/// the frame is not handled by the node, or its queue is full.
+///
+/// # Safety
+///
+/// `node` must be a pointer to a `ZencanNode` and `frame` a pointer to a `ZencanFrame`, or null.
+#[no_mangle]
+pub unsafe extern "C" fn zencan_node_handle_frame(
+    node: *const ZencanNode,
+    frame: *const ZencanFrame,
+) -> bool {
+    if node.is_null() || frame.is_null() {
+        return false;
+    }
+    let mbox = core::ptr::addr_of!((*node).mbox).read();
+    match (*frame).to_message() {
+        Some(msg) => mbox.store_message(msg).is_ok(),
+        None => false,
+    }
+}
+
+/// Run the node
+///
+/// `now_us` is a monotonic time in microseconds. Frames to transmit are passed to `send`, along with
+/// `ctx`, in the order they must be transmitted. See [`Node::process`].
+///
+/// # Safety
+///
+/// `node` must be a pointer to a `ZencanNode`, or null.
+#[no_mangle]
+pub unsafe extern "C" fn zencan_node_process(
+    node: *mut ZencanNode,
+    now_us: u64,
+    send: ZencanSendCallback,
+    ctx: *mut c_void,
+) -> ZencanProcessResult {
+    if node.is_null() {
+        return ZencanProcessResult {
+            objects_updated: false,
+            next_action_us: ZENCAN_NO_ACTION,
+        };
+    }
+    let node = &mut *core::ptr::addr_of_mut!((*node).node);
+    let result = node.process(now_us, &mut |msg| {
+        if let Some(send) = send {
+            let frame = ZencanFrame::from(msg);
+            send(ctx, &frame);
+        }
+    });
+    ZencanProcessResult {
+        objects_updated: result.objects_updated,
+        next_action_us: result.next_action_us.unwrap_or(ZENCAN_NO_ACTION),
+    }
+}
+
+/// Read the value of a sub object
+///
+/// The value is copied into `buf`, and its size is stored to `out_len`. If `buf` is too small, the
+/// required size is still stored to `out_len`. Access permissions are not checked, as they are for
+/// SDO transfers, since the application is trusted.
+///
+/// Returns [`ZENCAN_OK`] on success, or an SDO abort code describing the failure, e.g. `0x06020000`
+/// if the object does not exist.
+///
+/// # Safety
+///
+/// `node` must be a pointer to a `ZencanNode`, `buf` must be valid for writes of `len` bytes, and
+/// `out_len` must be a pointer to a `size_t`, or null.
+#[no_mangle]
+pub unsafe extern "C" fn zencan_node_read_object(
+    node: *const ZencanNode,
+    index: u16,
+    sub: u8,
+    buf: *mut u8,
+    len: usize,
+    out_len: *mut usize,
+) -> u32 {
+    if node.is_null() || (buf.is_null() && len > 0) {
+        return AbortCode::GeneralError as u32;
+    }
+    let Some(obj) = find_object(od(node), index) else {
+        return AbortCode::NoSuchObject as u32;
+    };
+    let size = match obj.current_size(sub) {
+        Ok(size) => size,
+        Err(abort) => return abort as u32,
+    };
+    if !out_len.is_null() {
+        *out_len = size;
+    }
+    if size > len {
+        return AbortCode::DataTypeMismatchLengthHigh as u32;
+    }
+    if size == 0 {
+        return ZENCAN_OK;
+    }
+    let buf = core::slice::from_raw_parts_mut(buf, size);
+    match obj.read(sub, 0, buf) {
+        Ok(_) => ZENCAN_OK,
+        Err(abort) => abort as u32,
+    }
+}
+
+/// Write the value of a sub object
+///
+/// `len` must match the size of the sub object, except for strings, which may be shorter. Access
+/// permissions are not checked, as they are for SDO transfers, since the application is trusted.
+///
+/// Returns [`ZENCAN_OK`] on success, or an SDO abort code describing the failure.
+///
+/// # Safety
+///
+/// `node` must be a pointer to a `ZencanNode`, and `data` must be valid for reads of `len` bytes,
+/// or null.
+#[no_mangle]
+pub unsafe extern "C" fn zencan_node_write_object(
+    node: *const ZencanNode,
+    index: u16,
+    sub: u8,
+    data: *const u8,
+    len: usize,
+) -> u32 {
+    if node.is_null() || (data.is_null() && len > 0) {
+        return AbortCode::GeneralError as u32;
+    }
+    let Some(obj) = find_object(od(node), index) else {
+        return AbortCode::NoSuchObject as u32;
+    };
+    let data = if len == 0 {
+        &[]
+    } else {
+        core::slice::from_raw_parts(data, len)
+    };
+    match obj.write(sub, data) {
+        Ok(()) => ZENCAN_OK,
+        Err(abort) => abort as u32,
+    }
+}
+
+/// Get the current node ID, or 255 if the node is unconfigured
+///
+/// # Safety
+///
+/// `node` must be a pointer to a `ZencanNode`, or null.
+#[no_mangle]
+pub unsafe extern "C" fn zencan_node_id(node: *const ZencanNode) -> u8 {
+    if node.is_null() {
+        return 255;
+    }
+    (*node).node.node_id()
+}
+
+/// Get the current NMT state of the node
+///
+/// The value is the state as encoded in heartbeat messages, e.g. 5 for operational, or 127 for
+/// pre-operational.
+///
+/// # Safety
+///
+/// `node` must be a pointer to a `ZencanNode`, or null.
+#[no_mangle]
+pub unsafe extern "C" fn zencan_node_nmt_state(node: *const ZencanNode) -> u8 {
+    if node.is_null() {
+        return 0;
+    }
+    (*node).node.nmt_state() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_conversion() {
+        let msg = CanMessage::new(CanId::std(0x601), &[0x40, 0x00, 0x10, 0x00]);
+        let frame = ZencanFrame::from(msg);
+        assert_eq!(0x601, frame.id);
+        assert_eq!(0, frame.flags);
+        assert_eq!(4, frame.dlc);
+        assert_eq!(Some(msg), frame.to_message());
+
+        let msg = CanMessage::new_rtr(CanId::extended(0x1234567));
+        let frame = ZencanFrame::from(msg);
+        assert_eq!(ZENCAN_FRAME_EXTENDED | ZENCAN_FRAME_RTR, frame.flags);
+        assert_eq!(Some(msg), frame.to_message());
+
+        // Out of range IDs and lengths are rejected
+        let frame = ZencanFrame {
+            id: 0x800,
+            ..Default::default()
+        };
+        assert_eq!(None, frame.to_message());
+        let frame = ZencanFrame {
+            dlc: 9,
+            ..Default::default()
+        };
+        assert_eq!(None, frame.to_message());
+    }
+}
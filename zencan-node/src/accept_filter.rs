@@ -0,0 +1,158 @@
+//! Acceptance filters for programming CAN controller hardware filters
+//!
+//! Most CAN controllers can filter received frames in hardware, so that frames which the node does
+//! not consume never raise an interrupt.
+//! [`NodeMbox::accept_filters`](crate::NodeMbox::accept_filters) lists one exact-match filter for
+//! each COB-ID consumed by the node. Controllers usually have fewer filter banks than that, so [`reduce_filters`] can merge them into a smaller number of
+//! mask filters, which accept every consumed frame at the cost of also accepting some others. The
+//! node ignores any frame it does not consume, so a filter which is too broad is never an error.
+//!
+//! The filters change when COB-IDs are reconfigured, e.g. by writing the RPDO communication
+//! parameters or the SDO server parameters. Register a callback with
+//! [`NodeMbox::set_filter_change_callback`](crate::NodeMbox::set_filter_change_callback) to be
+//! notified when the hardware filters should be reprogrammed.
+
+use zencan_common::CanId;
+
+/// A CAN acceptance filter, which matches IDs of the same type which are equal to `id` in every bit
+/// set in `mask`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AcceptFilter {
+    /// The ID to match
+    ///
+    /// Bits which are not set in `mask` are always 0.
+    pub id: CanId,
+    /// The bits of the ID which must match
+    pub mask: u32,
+}
+
+impl AcceptFilter {
+    /// Create a filter which matches only `id`
+    pub const fn exact(id: CanId) -> Self {
+        Self {
+            id,
+            mask: id_bits(id),
+        }
+    }
+
+    /// Returns true if the filter accepts `id`
+    pub fn matches(&self, id: CanId) -> bool {
+        id.is_extended() == self.id.is_extended() && (id.raw() ^ self.id.raw()) & self.mask == 0
+    }
+
+    /// Get the smallest filter which accepts every ID accepted by both `self` and `other`
+    ///
+    /// Returns None if one filter is for standard IDs and the other for extended IDs.
+    pub fn merge(&self, other: &AcceptFilter) -> Option<AcceptFilter> {
+        if self.id.is_extended() != other.id.is_extended() {
+            return None;
+        }
+        let mask = self.mask & other.mask & !(self.id.raw() ^ other.id.raw());
+        let raw = self.id.raw() & mask;
+        let id = if self.id.is_extended() {
+            CanId::Extended(raw)
+        } else {
+            CanId::Std(raw as u16)
+        };
+        Some(AcceptFilter { id, mask })
+    }
+
+    /// The number of ID bits which are not checked by the filter
+    ///
+    /// The filter accepts 2^n IDs.
+    pub fn dont_care_bits(&self) -> u32 {
+        (id_bits(self.id) & !self.mask).count_ones()
+    }
+}
+
+/// Get a mask of the bits used by the type of `id`
+const fn id_bits(id: CanId) -> u32 {
+    match id {
+        CanId::Extended(_) => 0x1FFF_FFFF,
+        CanId::Std(_) => 0x7FF,
+    }
+}
+
+/// Merge filters until there are at most `max` of them
+///
+/// Filters are merged in pairs, each time choosing the pair whose merged filter accepts the fewest
+/// IDs, so that as few unwanted frames as possible are accepted. Filters which are covered by
+/// another, such as duplicates, are always removed. The reduced filters are moved to the start of `filters`, and the number of them is
+/// returned.
+///
+/// Standard and extended filters cannot be merged with each other, so the returned number may be
+/// greater than `max` if `max` is 1 and both types are present.
+pub fn reduce_filters(filters: &mut [AcceptFilter], max: usize) -> usize {
+    let mut len = filters.len();
+    loop {
+        let mut best: Option<(usize, usize, AcceptFilter)> = None;
+        let mut lossless = None;
+        for i in 0..len {
+            for j in i + 1..len {
+                let Some(merged) = filters[i].merge(&filters[j]) else {
+                    continue;
+                };
+                // A filter which is covered by another can be removed without accepting more IDs
+                if merged == filters[i] || merged == filters[j] {
+                    lossless = Some((i, j, merged));
+                }
+                let is_better = match best {
+                    Some((_, _, b)) => merged.dont_care_bits() < b.dont_care_bits(),
+                    None => true,
+                };
+                if is_better {
+                    best = Some((i, j, merged));
+                }
+            }
+        }
+        let Some((i, j, merged)) = lossless.or(if len > max { best } else { None }) else {
+            return len;
+        };
+        filters[i] = merged;
+        filters.swap(j, len - 1);
+        len -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let a = AcceptFilter::exact(CanId::std(0x201));
+        let b = AcceptFilter::exact(CanId::std(0x301));
+        let merged = a.merge(&b).unwrap();
+        assert_eq!(CanId::std(0x201), merged.id);
+        assert_eq!(0x6FF, merged.mask);
+        assert_eq!(1, merged.dont_care_bits());
+        assert!(merged.matches(CanId::std(0x201)));
+        assert!(merged.matches(CanId::std(0x301)));
+        assert!(!merged.matches(CanId::std(0x202)));
+        assert!(!merged.matches(CanId::extended(0x201)));
+
+        assert_eq!(None, a.merge(&AcceptFilter::exact(CanId::extended(0x201))));
+    }
+
+    #[test]
+    fn test_reduce_filters() {
+        let ids = [0x000, 0x080, 0x7E5, 0x601, 0x201, 0x301, 0x201];
+        let mut filters = ids.map(|id| AcceptFilter::exact(CanId::std(id)));
+
+        // The duplicate is removed even when no reduction is required
+        let n = reduce_filters(&mut filters, 8);
+        assert_eq!(6, n);
+
+        let n = reduce_filters(&mut filters[..n], 3);
+        assert_eq!(3, n);
+        for id in ids {
+            assert!(filters[..n].iter().any(|f| f.matches(CanId::std(id))));
+        }
+
+        let mut filters = [
+            AcceptFilter::exact(CanId::std(0x201)),
+            AcceptFilter::exact(CanId::extended(0x201)),
+        ];
+        assert_eq!(2, reduce_filters(&mut filters, 1));
+    }
+}
@@ -0,0 +1,290 @@
+//! Object access trace (object 0x5003)
+//!
+//! When a device config sets an access trace depth, zencan-build creates an [`AccessTrace`] with a
+//! statically allocated ring buffer, and the node records each SDO access, and optionally each PDO,
+//! into it. The buffer can be uploaded via SDO from 0x5003sub2, and decoded with
+//! [`AccessTraceEntry::decode_all`], so that the accesses leading up to a problem in the field can
+//! be inspected without having logged the bus.
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use critical_section::Mutex;
+use zencan_common::{
+    access_trace::{AccessTraceEntry, ENTRY_SIZE},
+    objects::{AccessType, DataType, ObjectCode, PdoMapping, SubInfo},
+    sdo::AbortCode,
+};
+
+use crate::{
+    object_dict::{read_le_bytes, ObjectAccess},
+    BufferCell,
+};
+
+struct TraceRing {
+    entries: &'static BufferCell<[AccessTraceEntry]>,
+    /// The position at which the next entry is written
+    next: usize,
+    /// The number of valid entries
+    len: usize,
+    /// The number of entries recorded since the trace was cleared
+    count: u32,
+}
+
+impl TraceRing {
+    fn get(&self, i: usize) -> Option<AccessTraceEntry> {
+        if i >= self.len {
+            return None;
+        }
+        let entries = self.entries.borrow_mut();
+        let capacity = entries.len();
+        Some(entries[(self.next + capacity - self.len + i) % capacity])
+    }
+}
+
+/// A ring buffer of the most recent object accesses, implementing object 0x5003
+///
+/// This is instantiated by zencan-build, and registered on the node state with
+/// [`NodeState::with_access_trace`](crate::NodeState::with_access_trace).
+pub struct AccessTrace {
+    ring: Mutex<RefCell<TraceRing>>,
+    trace_pdos: AtomicBool,
+}
+
+impl core::fmt::Debug for AccessTrace {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("AccessTrace")
+            .field("len", &self.len())
+            .field("count", &self.count())
+            .finish()
+    }
+}
+
+impl AccessTrace {
+    /// Create a new trace, storing entries in `buffer`
+    ///
+    /// If `trace_pdos` is true, PDO accesses are recorded by default
+    pub const fn new(buffer: &'static BufferCell<[AccessTraceEntry]>, trace_pdos: bool) -> Self {
+        Self {
+            ring: Mutex::new(RefCell::new(TraceRing {
+                entries: buffer,
+                next: 0,
+                len: 0,
+                count: 0,
+            })),
+            trace_pdos: AtomicBool::new(trace_pdos),
+        }
+    }
+
+    /// Record an access, replacing the oldest entry if the buffer is full
+    pub fn record(&self, entry: AccessTraceEntry) {
+        critical_section::with(|cs| {
+            let mut ring = self.ring.borrow_ref_mut(cs);
+            let mut entries = ring.entries.borrow_mut();
+            let capacity = entries.len();
+            if capacity == 0 {
+                return;
+            }
+            let next = ring.next;
+            entries[next] = entry;
+            ring.next = (next + 1) % capacity;
+            ring.len = (ring.len + 1).min(capacity);
+            ring.count = ring.count.wrapping_add(1);
+        })
+    }
+
+    /// Remove all entries, and reset the access count
+    pub fn clear(&self) {
+        critical_section::with(|cs| {
+            let mut ring = self.ring.borrow_ref_mut(cs);
+            ring.next = 0;
+            ring.len = 0;
+            ring.count = 0;
+        })
+    }
+
+    /// Get the number of entries currently held
+    pub fn len(&self) -> usize {
+        critical_section::with(|cs| self.ring.borrow_ref(cs).len)
+    }
+
+    /// Returns true if no entries are held
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the number of accesses recorded since the trace was cleared
+    ///
+    /// This continues to count after the buffer is full, so it shows how many entries were lost.
+    pub fn count(&self) -> u32 {
+        critical_section::with(|cs| self.ring.borrow_ref(cs).count)
+    }
+
+    /// Get an entry, where 0 is the oldest held entry
+    pub fn get(&self, i: usize) -> Option<AccessTraceEntry> {
+        critical_section::with(|cs| self.ring.borrow_ref(cs).get(i))
+    }
+
+    /// Returns true if PDO accesses are recorded
+    pub fn trace_pdos(&self) -> bool {
+        self.trace_pdos.load(Ordering::Relaxed)
+    }
+
+    fn data_size(&self) -> usize {
+        self.len() * ENTRY_SIZE
+    }
+
+    /// Read the encoded entries, oldest first, starting at a byte offset
+    ///
+    /// Each entry is read in its own critical section, so entries recorded during a segmented
+    /// upload may cause a torn read, in which some entries are skipped or repeated.
+    fn read_data(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let size = self.data_size();
+        if offset >= size {
+            return 0;
+        }
+        let read_len = buf.len().min(size - offset);
+        let mut pos = 0;
+        while pos < read_len {
+            let byte = offset + pos;
+            let Some(entry) = self.get(byte / ENTRY_SIZE) else {
+                break;
+            };
+            let bytes = entry.to_bytes();
+            let start = byte % ENTRY_SIZE;
+            let n = (ENTRY_SIZE - start).min(read_len - pos);
+            buf[pos..pos + n].copy_from_slice(&bytes[start..start + n]);
+            pos += n;
+        }
+        pos
+    }
+}
+
+impl ObjectAccess for AccessTrace {
+    fn read(&self, sub: u8, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        match sub {
+            0 => Ok(read_le_bytes(&[3], offset, buf)),
+            1 => Ok(read_le_bytes(&self.count().to_le_bytes(), offset, buf)),
+            2 => Ok(self.read_data(offset, buf)),
+            3 => Ok(read_le_bytes(&[self.trace_pdos() as u8], offset, buf)),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+
+    fn read_size(&self, sub: u8) -> Result<usize, AbortCode> {
+        match sub {
+            0 => Ok(1),
+            1 => Ok(4),
+            2 => Ok(self.data_size()),
+            3 => Ok(1),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+
+    fn write(&self, sub: u8, data: &[u8]) -> Result<(), AbortCode> {
+        match sub {
+            0 | 2 => Err(AbortCode::ReadOnly),
+            1 => match data.len() {
+                4 if data == [0; 4] => {
+                    self.clear();
+                    Ok(())
+                }
+                4 => Err(AbortCode::InvalidValue),
+                n if n < 4 => Err(AbortCode::DataTypeMismatchLengthLow),
+                _ => Err(AbortCode::DataTypeMismatchLengthHigh),
+            },
+            3 => match data {
+                [value] => {
+                    self.trace_pdos.store(*value != 0, Ordering::Relaxed);
+                    Ok(())
+                }
+                [] => Err(AbortCode::DataTypeMismatchLengthLow),
+                _ => Err(AbortCode::DataTypeMismatchLengthHigh),
+            },
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Record
+    }
+
+    fn sub_info(&self, sub: u8) -> Result<SubInfo, AbortCode> {
+        match sub {
+            0 => Ok(SubInfo::MAX_SUB_NUMBER),
+            1 => Ok(SubInfo::new_u32().rw_access()),
+            2 => Ok(SubInfo {
+                size: self.data_size(),
+                data_type: DataType::Domain,
+                access_type: AccessType::Ro,
+                pdo_mapping: PdoMapping::None,
+                persist: false,
+            }),
+            3 => Ok(SubInfo::new_u8().rw_access()),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zencan_common::access_trace::AccessKind;
+
+    use super::*;
+
+    fn entry(index: u16) -> AccessTraceEntry {
+        AccessTraceEntry {
+            timestamp_ms: index as u32 * 10,
+            index,
+            sub: 1,
+            kind: AccessKind::SdoWrite,
+            abort_code: 0,
+        }
+    }
+
+    fn trace() -> AccessTrace {
+        let buffer = Box::leak(Box::new(BufferCell::new([AccessTraceEntry::EMPTY; 3])));
+        AccessTrace::new(buffer, false)
+    }
+
+    #[test]
+    fn test_ring_wraps() {
+        let trace = trace();
+        assert!(trace.is_empty());
+        for i in 0..5 {
+            trace.record(entry(0x2000 + i));
+        }
+        assert_eq!(3, trace.len());
+        assert_eq!(5, trace.count());
+        assert_eq!(Some(entry(0x2002)), trace.get(0));
+        assert_eq!(Some(entry(0x2004)), trace.get(2));
+        assert_eq!(None, trace.get(3));
+
+        // Writing 0 to the count clears the trace
+        assert_eq!(Err(AbortCode::InvalidValue), trace.write(1, &[1, 0, 0, 0]));
+        trace.write(1, &[0; 4]).unwrap();
+        assert!(trace.is_empty());
+        assert_eq!(0, trace.count());
+    }
+
+    #[test]
+    fn test_read_data() {
+        let trace = trace();
+        for i in 0..4 {
+            trace.record(entry(0x2000 + i));
+        }
+        assert_eq!(Ok(3 * ENTRY_SIZE), trace.read_size(2));
+
+        // Read in segments which do not align with the entries
+        let mut data = [0; 3 * ENTRY_SIZE];
+        let mut offset = 0;
+        while offset < data.len() {
+            let end = (offset + 7).min(data.len());
+            let n = trace.read(2, offset, &mut data[offset..end]).unwrap();
+            assert!(n > 0);
+            offset += n;
+        }
+        let entries: Vec<_> = AccessTraceEntry::decode_all(&data).collect();
+        assert_eq!(vec![entry(0x2001), entry(0x2002), entry(0x2003)], entries);
+    }
+}
@@ -35,6 +35,15 @@ impl<const APP: bool, const NUM_SECTIONS: u8> BootloaderInfo<APP, NUM_SECTIONS>
     pub fn reset_flag(&self) -> bool {
         self.reset_flag.load()
     }
+
+    /// Clear the reset_flag
+    ///
+    /// A real device resets when the flag is set, which clears it. An application which emulates
+    /// the reset without restarting, e.g. a simulated device, clears it so that the next reset
+    /// command can be detected.
+    pub fn clear_reset_flag(&self) {
+        self.reset_flag.clear();
+    }
 }
 
 #[derive(Debug, Default)]
@@ -52,6 +61,10 @@ impl ResetField {
     pub fn load(&self) -> bool {
         self.flag.load(Ordering::Relaxed)
     }
+
+    pub fn clear(&self) {
+        self.flag.store(false, Ordering::Relaxed);
+    }
 }
 
 impl SubObjectAccess for ResetField {
@@ -222,6 +235,36 @@ impl ObjectAccess for BootloaderSection {
         }
     }
 
+    fn begin_partial(&self, sub: u8) -> Result<(), AbortCode> {
+        match sub {
+            // Sections are usually larger than the SDO buffer, so the data is passed on in chunks
+            4 => match self.callbacks.load() {
+                Some(_) => Ok(()),
+                None => Err(AbortCode::ResourceNotAvailable),
+            },
+            0..=2 => Err(AbortCode::ReadOnly),
+            3 => Err(AbortCode::UnsupportedAccess),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+
+    fn write_partial(&self, sub: u8, buf: &[u8]) -> Result<(), AbortCode> {
+        match (sub, self.callbacks.load()) {
+            (4, Some(callbacks)) => {
+                callbacks.write(buf);
+                Ok(())
+            }
+            _ => Err(AbortCode::GeneralError),
+        }
+    }
+
+    fn end_partial(&self, sub: u8) -> Result<(), AbortCode> {
+        match (sub, self.callbacks.load()) {
+            (4, Some(callbacks)) if callbacks.finalize() => Ok(()),
+            _ => Err(AbortCode::GeneralError),
+        }
+    }
+
     fn object_code(&self) -> ObjectCode {
         ObjectCode::Record
     }
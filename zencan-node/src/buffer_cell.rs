@@ -0,0 +1,138 @@
+//! Statically allocated buffers shared with the node
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+};
+
+use zencan_common::AtomicCell;
+
+/// A cell holding a buffer which is lent to the node for its exclusive use
+///
+/// The node requires static storage for its SDO buffer and message queues, sized at build time.
+/// Rather than declaring these as `static mut` and creating references to them with `unsafe`, they
+/// can be declared as a plain `static BufferCell`, and passed to the node by shared reference. A
+/// `&'static BufferCell<[T; N]>` coerces to a `&'static BufferCell<[T]>`, so the size of the buffer
+/// does not become part of the type of the object using it.
+///
+/// Mutable access is provided by [`borrow_mut`](Self::borrow_mut), which is checked at run time
+/// like a `RefCell`, with the borrow flag updated in a critical section.
+///
+/// ```
+/// use zencan_node::BufferCell;
+///
+/// static BUFFER: BufferCell<[u8; 4]> = BufferCell::new([0; 4]);
+/// let buffer: &'static BufferCell<[u8]> = &BUFFER;
+/// buffer.borrow_mut()[0] = 1;
+/// assert_eq!(1, buffer.borrow_mut()[0]);
+/// ```
+pub struct BufferCell<T: ?Sized> {
+    borrowed: AtomicCell<bool>,
+    value: UnsafeCell<T>,
+}
+
+// Safety: Access to the value is only possible through a `BufferRefMut`, and the borrow flag
+// ensures that at most one exists at a time
+unsafe impl<T: ?Sized + Send> Sync for BufferCell<T> {}
+
+impl<T: ?Sized> core::fmt::Debug for BufferCell<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BufferCell")
+            .field("borrowed", &self.borrowed.load())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> BufferCell<T> {
+    /// Create a new cell containing `value`
+    pub const fn new(value: T) -> Self {
+        Self {
+            borrowed: AtomicCell::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+}
+
+impl<T: ?Sized> BufferCell<T> {
+    /// Mutably borrow the contents of the cell
+    ///
+    /// Returns None if the contents are already borrowed
+    pub fn try_borrow_mut(&self) -> Option<BufferRefMut<'_, T>> {
+        self.borrowed
+            .fetch_update(|borrowed| (!borrowed).then_some(true))
+            .ok()?;
+        Some(BufferRefMut { cell: self })
+    }
+
+    /// Mutably borrow the contents of the cell
+    ///
+    /// # Panics
+    ///
+    /// Panics if the contents are already borrowed
+    pub fn borrow_mut(&self) -> BufferRefMut<'_, T> {
+        self.try_borrow_mut().expect("BufferCell already borrowed")
+    }
+}
+
+impl<T> BufferCell<[T]> {
+    /// Get the length of the buffer
+    ///
+    /// This does not require borrowing the contents.
+    pub fn len(&self) -> usize {
+        self.value.get().len()
+    }
+
+    /// Returns true if the buffer has a length of 0
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A mutable borrow of the contents of a [`BufferCell`], which is released on drop
+pub struct BufferRefMut<'a, T: ?Sized> {
+    cell: &'a BufferCell<T>,
+}
+
+impl<T: ?Sized> core::fmt::Debug for BufferRefMut<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BufferRefMut").finish_non_exhaustive()
+    }
+}
+
+impl<T: ?Sized> Deref for BufferRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // Safety: The borrow flag is held for the lifetime of self
+        unsafe { &*self.cell.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for BufferRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: The borrow flag is held for the lifetime of self
+        unsafe { &mut *self.cell.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for BufferRefMut<'_, T> {
+    fn drop(&mut self) {
+        self.cell.borrowed.store(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_borrow_is_exclusive() {
+        let cell: &BufferCell<[u8]> = &BufferCell::new([0u8; 3]);
+        let mut buf = cell.borrow_mut();
+        assert_eq!(3, buf.len());
+        buf[1] = 5;
+        assert!(cell.try_borrow_mut().is_none());
+        assert_eq!(3, cell.len());
+        drop(buf);
+        assert_eq!([0, 5, 0], *cell.borrow_mut());
+    }
+}
@@ -0,0 +1,62 @@
+//! Arithmetic on the application clock passed to [`Node::process`](crate::Node::process)
+//!
+//! The application clock is only used to measure the time elapsed between two readings, so it may
+//! wrap. Microcontrollers commonly provide a 32-bit microsecond timer, which wraps every ~71
+//! minutes, and the width of the clock can be set with
+//! [`Node::set_clock_bits`](crate::Node::set_clock_bits). A 64-bit clock wraps after ~584,940
+//! years, but is handled the same way.
+
+/// Get the mask of the valid bits of a clock with the given width
+///
+/// Widths outside of 1 to 64 bits are clamped.
+pub(crate) const fn clock_mask(bits: u32) -> u64 {
+    let bits = if bits == 0 {
+        1
+    } else if bits > 64 {
+        64
+    } else {
+        bits
+    };
+    u64::MAX >> (64 - bits)
+}
+
+/// Get the time elapsed from `from_us` to `to_us` on a clock which wraps at `mask`
+///
+/// A difference of more than half the range of the clock is taken to mean that the clock stepped
+/// backwards, and is treated as no time passing.
+pub(crate) const fn elapsed_us(from_us: u64, to_us: u64, mask: u64) -> u64 {
+    let elapsed = to_us.wrapping_sub(from_us) & mask;
+    if elapsed > mask / 2 {
+        0
+    } else {
+        elapsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_mask() {
+        assert_eq!(0xFFFF_FFFF, clock_mask(32));
+        assert_eq!(u64::MAX, clock_mask(64));
+        assert_eq!(u64::MAX, clock_mask(100));
+        assert_eq!(1, clock_mask(0));
+    }
+
+    #[test]
+    fn test_elapsed() {
+        let mask32 = clock_mask(32);
+        assert_eq!(100, elapsed_us(1000, 1100, mask32));
+        // Across the wrap of a 32-bit clock
+        assert_eq!(200, elapsed_us(0xFFFF_FF9C, 100, mask32));
+        // Bits above the width of the clock are ignored
+        assert_eq!(200, elapsed_us(0xFFFF_FF9C, 0x1_0000_0064, mask32));
+        // Across the wrap of a 64-bit clock
+        assert_eq!(200, elapsed_us(u64::MAX - 99, 100, u64::MAX));
+        // A clock which steps backwards
+        assert_eq!(0, elapsed_us(1100, 1000, mask32));
+        assert_eq!(0, elapsed_us(1100, 1000, u64::MAX));
+    }
+}
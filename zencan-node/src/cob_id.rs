@@ -0,0 +1,334 @@
+//! Configurable COB-IDs for the EMCY producer (0x1014) and the SDO server (0x1200)
+//!
+//! By default, the node uses the pre-defined connection set: EMCY messages are sent on 0x80 + node
+//! ID, and the SDO server receives on 0x600 + node ID and responds on 0x580 + node ID. These can be
+//! moved to other IDs by writing the objects, e.g. to free up the default IDs on a crowded bus.
+//! Changes to the SDO server COB-IDs take effect immediately; the response to the write which
+//! changes them is still sent on the old COB-ID.
+//!
+//! COB-IDs which are not written follow the node ID, so they stay correct when the node ID is
+//! changed via LSS. Only COB-IDs which have been written are saved to persistent storage.
+
+use zencan_common::{
+    messages::CanId,
+    objects::{ObjectCode, SubInfo},
+    sdo::AbortCode,
+    AtomicCell,
+};
+
+use crate::object_dict::{ConstField, ProvidesSubObjects, SubObjectAccess};
+
+/// A COB-ID value as stored in a communication parameter object
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CobId {
+    /// The CAN ID
+    pub id: CanId,
+    /// False if the message is disabled
+    pub valid: bool,
+}
+
+impl CobId {
+    /// Create a valid COB-ID
+    pub const fn new(id: CanId) -> Self {
+        Self { id, valid: true }
+    }
+
+    /// Encode as a u32, with bit 31 set if not valid and bit 29 set for an extended ID
+    pub fn raw(&self) -> u32 {
+        let mut value = self.id.raw();
+        if self.id.is_extended() {
+            value |= 1 << 29;
+        }
+        if !self.valid {
+            value |= 1 << 31;
+        }
+        value
+    }
+
+    /// Decode from a u32
+    ///
+    /// Bit 30 is ignored. Returns [`AbortCode::InvalidValue`] if a standard ID is out of range.
+    pub fn from_raw(value: u32) -> Result<Self, AbortCode> {
+        let id = if value & (1 << 29) != 0 {
+            CanId::Extended(value & 0x1FFF_FFFF)
+        } else {
+            if value & 0x1FFF_F800 != 0 {
+                return Err(AbortCode::InvalidValue);
+            }
+            CanId::Std((value & 0x7FF) as u16)
+        };
+        Ok(Self {
+            id,
+            valid: value & (1 << 31) == 0,
+        })
+    }
+}
+
+/// The COB-IDs used by the node for EMCY and SDO messages
+///
+/// This is stored in the [`NodeState`](crate::NodeState), and exposed in the object dictionary by
+/// [`EmcyCobIdObject`] and [`SdoServerObject`].
+#[allow(missing_debug_implementations)]
+pub struct CobIds {
+    node_id: AtomicCell<u8>,
+    emcy: AtomicCell<Option<CobId>>,
+    sdo_rx: AtomicCell<Option<CobId>>,
+    sdo_tx: AtomicCell<Option<CobId>>,
+    changed: AtomicCell<bool>,
+}
+
+impl Default for CobIds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CobIds {
+    /// Create a new CobIds, with all COB-IDs following the node ID
+    pub const fn new() -> Self {
+        Self {
+            node_id: AtomicCell::new(0),
+            emcy: AtomicCell::new(None),
+            sdo_rx: AtomicCell::new(None),
+            sdo_tx: AtomicCell::new(None),
+            changed: AtomicCell::new(false),
+        }
+    }
+
+    /// Set the node ID used for the default COB-IDs
+    pub(crate) fn set_node_id(&self, node_id: u8) {
+        self.node_id.store(node_id);
+    }
+
+    /// Get the COB-ID used for EMCY messages
+    pub fn emcy(&self) -> CobId {
+        self.emcy
+            .load()
+            .unwrap_or(CobId::new(CanId::emcy(self.node_id.load())))
+    }
+
+    /// Get the COB-ID on which the SDO server receives requests
+    pub fn sdo_rx(&self) -> CobId {
+        self.sdo_rx
+            .load()
+            .unwrap_or(CobId::new(CanId::sdo_rx(self.node_id.load())))
+    }
+
+    /// Get the COB-ID on which the SDO server sends responses
+    pub fn sdo_tx(&self) -> CobId {
+        self.sdo_tx
+            .load()
+            .unwrap_or(CobId::new(CanId::sdo_tx(self.node_id.load())))
+    }
+
+    /// Set the COB-ID used for EMCY messages, or None to use the default for the node ID
+    pub fn set_emcy(&self, cob_id: Option<CobId>) {
+        self.emcy.store(cob_id);
+    }
+
+    /// Set the COB-ID on which the SDO server receives requests, or None to use the default for the
+    /// node ID
+    pub fn set_sdo_rx(&self, cob_id: Option<CobId>) {
+        self.sdo_rx.store(cob_id);
+        self.changed.store(true);
+    }
+
+    /// Set the COB-ID on which the SDO server sends responses, or None to use the default for the
+    /// node ID
+    pub fn set_sdo_tx(&self, cob_id: Option<CobId>) {
+        self.sdo_tx.store(cob_id);
+    }
+
+    /// Returns true if the SDO receive COB-ID has changed since the last call
+    pub(crate) fn take_changed(&self) -> bool {
+        self.changed.take()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum CobIdKind {
+    Emcy,
+    SdoRx,
+    SdoTx,
+}
+
+struct CobIdSubObject {
+    cob_ids: &'static CobIds,
+    kind: CobIdKind,
+}
+
+impl CobIdSubObject {
+    const fn new(cob_ids: &'static CobIds, kind: CobIdKind) -> Self {
+        Self { cob_ids, kind }
+    }
+
+    /// Returns true if the COB-ID has been set, rather than following the node ID
+    fn is_set(&self) -> bool {
+        match self.kind {
+            CobIdKind::Emcy => self.cob_ids.emcy.load().is_some(),
+            CobIdKind::SdoRx => self.cob_ids.sdo_rx.load().is_some(),
+            CobIdKind::SdoTx => self.cob_ids.sdo_tx.load().is_some(),
+        }
+    }
+
+    fn sub_info(&self) -> SubInfo {
+        SubInfo::new_u32().rw_access().persist(self.is_set())
+    }
+}
+
+impl SubObjectAccess for CobIdSubObject {
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        let cob_id = match self.kind {
+            CobIdKind::Emcy => self.cob_ids.emcy(),
+            CobIdKind::SdoRx => self.cob_ids.sdo_rx(),
+            CobIdKind::SdoTx => self.cob_ids.sdo_tx(),
+        };
+        let bytes = cob_id.raw().to_le_bytes();
+        if offset < bytes.len() {
+            let read_len = buf.len().min(bytes.len() - offset);
+            buf[0..read_len].copy_from_slice(&bytes[offset..offset + read_len]);
+            Ok(read_len)
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn read_size(&self) -> usize {
+        4
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), AbortCode> {
+        let value = u32::from_le_bytes(data.try_into().map_err(|_| {
+            if data.len() < 4 {
+                AbortCode::DataTypeMismatchLengthLow
+            } else {
+                AbortCode::DataTypeMismatchLengthHigh
+            }
+        })?);
+        let cob_id = Some(CobId::from_raw(value)?);
+        match self.kind {
+            CobIdKind::Emcy => self.cob_ids.set_emcy(cob_id),
+            CobIdKind::SdoRx => self.cob_ids.set_sdo_rx(cob_id),
+            CobIdKind::SdoTx => self.cob_ids.set_sdo_tx(cob_id),
+        }
+        Ok(())
+    }
+}
+
+/// Implements the EMCY COB-ID object (0x1014)
+#[allow(missing_debug_implementations)]
+pub struct EmcyCobIdObject {
+    cob: CobIdSubObject,
+}
+
+impl EmcyCobIdObject {
+    /// Create a new EmcyCobIdObject
+    pub const fn new(cob_ids: &'static CobIds) -> Self {
+        Self {
+            cob: CobIdSubObject::new(cob_ids, CobIdKind::Emcy),
+        }
+    }
+}
+
+impl ProvidesSubObjects for EmcyCobIdObject {
+    fn get_sub_object(&self, sub: u8) -> Option<(SubInfo, &dyn SubObjectAccess)> {
+        match sub {
+            0 => Some((self.cob.sub_info(), &self.cob)),
+            _ => None,
+        }
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Var
+    }
+}
+
+/// Implements the SDO server parameter object (0x1200)
+#[allow(missing_debug_implementations)]
+pub struct SdoServerObject {
+    rx: CobIdSubObject,
+    tx: CobIdSubObject,
+}
+
+impl SdoServerObject {
+    /// Create a new SdoServerObject
+    pub const fn new(cob_ids: &'static CobIds) -> Self {
+        Self {
+            rx: CobIdSubObject::new(cob_ids, CobIdKind::SdoRx),
+            tx: CobIdSubObject::new(cob_ids, CobIdKind::SdoTx),
+        }
+    }
+}
+
+impl ProvidesSubObjects for SdoServerObject {
+    fn get_sub_object(&self, sub: u8) -> Option<(SubInfo, &dyn SubObjectAccess)> {
+        match sub {
+            0 => Some((
+                SubInfo::MAX_SUB_NUMBER,
+                const { &ConstField::new(2u8.to_le_bytes()) },
+            )),
+            1 => Some((self.rx.sub_info(), &self.rx)),
+            2 => Some((self.tx.sub_info(), &self.tx)),
+            _ => None,
+        }
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::object_dict::ObjectAccess;
+
+    use super::*;
+
+    #[test]
+    fn test_cob_id_raw() {
+        let cob_id = CobId::from_raw(0x8000_0123).unwrap();
+        assert_eq!(CanId::Std(0x123), cob_id.id);
+        assert!(!cob_id.valid);
+        assert_eq!(0x8000_0123, cob_id.raw());
+
+        let cob_id = CobId::from_raw(0x2012_3456).unwrap();
+        assert_eq!(CanId::Extended(0x12_3456), cob_id.id);
+        assert_eq!(0x2012_3456, cob_id.raw());
+
+        // Standard IDs must fit in 11 bits
+        assert_eq!(Err(AbortCode::InvalidValue), CobId::from_raw(0x800));
+    }
+
+    #[test]
+    fn test_sdo_server_object() {
+        let cob_ids: &'static CobIds = Box::leak(Box::new(CobIds::new()));
+        let object = SdoServerObject::new(cob_ids);
+        cob_ids.set_node_id(5);
+
+        // Defaults follow the node ID, and are not persisted
+        assert_eq!(0x605, object.read_u32(1).unwrap());
+        assert_eq!(0x585, object.read_u32(2).unwrap());
+        assert!(!object.sub_info(1).unwrap().persist);
+        assert!(!cob_ids.take_changed());
+
+        object.write(1, &0x640u32.to_le_bytes()).unwrap();
+        assert_eq!(CobId::new(CanId::Std(0x640)), cob_ids.sdo_rx());
+        assert!(object.sub_info(1).unwrap().persist);
+        assert!(cob_ids.take_changed());
+        assert!(!cob_ids.take_changed());
+
+        // A written COB-ID no longer follows the node ID
+        cob_ids.set_node_id(6);
+        assert_eq!(0x640, object.read_u32(1).unwrap());
+        assert_eq!(0x586, object.read_u32(2).unwrap());
+
+        assert_eq!(
+            Err(AbortCode::InvalidValue),
+            object.write(2, &0x1000u32.to_le_bytes())
+        );
+        assert_eq!(
+            Err(AbortCode::DataTypeMismatchLengthLow),
+            object.write(2, &[0; 2])
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Rate limited text log (object 0x5004)
+//!
+//! When a device config sets a debug log size, zencan-build creates a [`DebugLog`] with a
+//! statically allocated ring buffer of text. The application appends messages with
+//! [`Node::log_str`](crate::Node::log_str), or with [`DebugLog::log_str`] from another task, and
+//! they can be read over the bus via SDO, e.g. with `log tail` in zencan-cli. This allows a device
+//! without a debug UART to emit human readable diagnostics.
+//!
+//! Each message is stored as a line of text, terminated by a newline. When the buffer is full, the
+//! oldest text is overwritten. The number of messages accepted per second is limited, so that a
+//! message logged in a tight loop cannot overwrite all of the older messages; messages over the
+//! limit are discarded and counted.
+//!
+//! | Sub Object | Type   | Description |
+//! | ---------- | ------ | ----------- |
+//! | 0          | u8     | Max sub index - always 3 |
+//! | 1          | u32    | Number of bytes logged since the log was cleared. Write 0 to clear |
+//! | 2          | Domain | The value of sub 1 as a u32, followed by the buffered text, oldest first |
+//! | 3          | u32    | Number of messages discarded by the rate limit |
+//!
+//! Sub 2 starts with the position of the end of the text, so that a reader which polls the log
+//! can tell which part of the text is new since its last read. If the buffer has wrapped, the
+//! first line may be incomplete.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use zencan_common::{
+    objects::{AccessType, DataType, ObjectCode, PdoMapping, SubInfo},
+    sdo::AbortCode,
+};
+
+use crate::{
+    object_dict::{read_le_bytes, ObjectAccess},
+    BufferCell,
+};
+
+/// The length of the rate limit window, in microseconds
+const RATE_WINDOW_US: u64 = 1_000_000;
+
+struct LogRing {
+    buffer: &'static BufferCell<[u8]>,
+    /// The position at which the next byte is written
+    next: usize,
+    /// The number of valid bytes
+    len: usize,
+    /// The number of bytes logged since the log was cleared
+    position: u32,
+    /// The number of messages discarded by the rate limit
+    dropped: u32,
+    /// The start of the current rate limit window
+    window_start_us: u64,
+    /// The number of messages accepted in the current rate limit window
+    window_count: u32,
+}
+
+impl LogRing {
+    fn push(&mut self, bytes: &[u8]) {
+        let mut buffer = self.buffer.borrow_mut();
+        let capacity = buffer.len();
+        for &b in bytes {
+            buffer[self.next] = b;
+            self.next = (self.next + 1) % capacity;
+        }
+        self.len = (self.len + bytes.len()).min(capacity);
+        self.position = self.position.wrapping_add(bytes.len() as u32);
+    }
+
+    fn get(&self, i: usize) -> Option<u8> {
+        if i >= self.len {
+            return None;
+        }
+        let buffer = self.buffer.borrow_mut();
+        let capacity = buffer.len();
+        Some(buffer[(self.next + capacity - self.len + i) % capacity])
+    }
+}
+
+/// A ring buffer of text messages, implementing object 0x5004
+///
+/// Each message is stored as a line of text. When the buffer is full, the oldest text is
+/// overwritten. Sub 2 of the object returns the number of bytes logged since the log was cleared,
+/// as a u32, followed by the buffered text, so that a reader polling the log can tell which text is
+/// new.
+///
+/// This is instantiated by zencan-build, and registered on the node state with
+/// [`NodeState::with_debug_log`](crate::NodeState::with_debug_log).
+pub struct DebugLog {
+    ring: Mutex<RefCell<LogRing>>,
+    rate_limit: u32,
+}
+
+impl core::fmt::Debug for DebugLog {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DebugLog")
+            .field("position", &self.position())
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+impl DebugLog {
+    /// Create a new log, storing text in `buffer`
+    ///
+    /// At most `rate_limit` messages are accepted per second, or any number if it is 0.
+    pub const fn new(buffer: &'static BufferCell<[u8]>, rate_limit: u32) -> Self {
+        Self {
+            ring: Mutex::new(RefCell::new(LogRing {
+                buffer,
+                next: 0,
+                len: 0,
+                position: 0,
+                dropped: 0,
+                window_start_us: 0,
+                window_count: 0,
+            })),
+            rate_limit,
+        }
+    }
+
+    /// Append a message to the log
+    ///
+    /// A newline is added after the message, and it is truncated if it does not fit in the buffer.
+    /// Returns false if the message was discarded by the rate limit.
+    ///
+    /// This may be called from any task or interrupt. The rate limit is timed by
+    /// [`Node::process`](crate::Node::process), so all messages fall in the same window until it is
+    /// first called.
+    pub fn log_str(&self, msg: &str) -> bool {
+        critical_section::with(|cs| {
+            let mut ring = self.ring.borrow_ref_mut(cs);
+            let capacity = ring.buffer.len();
+            if capacity == 0 {
+                return false;
+            }
+            if self.rate_limit != 0 && ring.window_count >= self.rate_limit {
+                ring.dropped = ring.dropped.wrapping_add(1);
+                return false;
+            }
+            ring.window_count += 1;
+            let bytes = msg.as_bytes();
+            ring.push(&bytes[..bytes.len().min(capacity - 1)]);
+            ring.push(b"\n");
+            true
+        })
+    }
+
+    /// Start a new rate limit window if the current one has ended
+    pub(crate) fn tick(&self, now_us: u64) {
+        critical_section::with(|cs| {
+            let mut ring = self.ring.borrow_ref_mut(cs);
+            if now_us.saturating_sub(ring.window_start_us) >= RATE_WINDOW_US
+                || now_us < ring.window_start_us
+            {
+                ring.window_start_us = now_us;
+                ring.window_count = 0;
+            }
+        })
+    }
+
+    /// Remove all text, and reset the position and dropped message count
+    pub fn clear(&self) {
+        critical_section::with(|cs| {
+            let mut ring = self.ring.borrow_ref_mut(cs);
+            ring.next = 0;
+            ring.len = 0;
+            ring.position = 0;
+            ring.dropped = 0;
+        })
+    }
+
+    /// Get the number of bytes logged since the log was cleared, including newlines
+    pub fn position(&self) -> u32 {
+        critical_section::with(|cs| self.ring.borrow_ref(cs).position)
+    }
+
+    /// Get the number of messages discarded by the rate limit since the log was cleared
+    pub fn dropped(&self) -> u32 {
+        critical_section::with(|cs| self.ring.borrow_ref(cs).dropped)
+    }
+
+    fn data_size(&self) -> usize {
+        4 + critical_section::with(|cs| self.ring.borrow_ref(cs).len)
+    }
+
+    /// Read the position followed by the buffered text, starting at a byte offset
+    ///
+    /// Each call reads in a single critical section, so messages logged during a segmented upload
+    /// may cause a torn read.
+    fn read_data(&self, offset: usize, buf: &mut [u8]) -> usize {
+        critical_section::with(|cs| {
+            let ring = self.ring.borrow_ref(cs);
+            let position = ring.position.to_le_bytes();
+            let size = 4 + ring.len;
+            if offset >= size {
+                return 0;
+            }
+            let read_len = buf.len().min(size - offset);
+            for (i, b) in buf[..read_len].iter_mut().enumerate() {
+                let pos = offset + i;
+                *b = if pos < 4 {
+                    position[pos]
+                } else {
+                    ring.get(pos - 4).unwrap_or(0)
+                };
+            }
+            read_len
+        })
+    }
+}
+
+impl ObjectAccess for DebugLog {
+    fn read(&self, sub: u8, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        match sub {
+            0 => Ok(read_le_bytes(&[3], offset, buf)),
+            1 => Ok(read_le_bytes(&self.position().to_le_bytes(), offset, buf)),
+            2 => Ok(self.read_data(offset, buf)),
+            3 => Ok(read_le_bytes(&self.dropped().to_le_bytes(), offset, buf)),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+
+    fn read_size(&self, sub: u8) -> Result<usize, AbortCode> {
+        match sub {
+            0 => Ok(1),
+            1 | 3 => Ok(4),
+            2 => Ok(self.data_size()),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+
+    fn write(&self, sub: u8, data: &[u8]) -> Result<(), AbortCode> {
+        match sub {
+            0 | 2 | 3 => Err(AbortCode::ReadOnly),
+            1 => match data.len() {
+                4 if data == [0; 4] => {
+                    self.clear();
+                    Ok(())
+                }
+                4 => Err(AbortCode::InvalidValue),
+                n if n < 4 => Err(AbortCode::DataTypeMismatchLengthLow),
+                _ => Err(AbortCode::DataTypeMismatchLengthHigh),
+            },
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Record
+    }
+
+    fn sub_info(&self, sub: u8) -> Result<SubInfo, AbortCode> {
+        match sub {
+            0 => Ok(SubInfo::MAX_SUB_NUMBER),
+            1 => Ok(SubInfo::new_u32().rw_access()),
+            2 => Ok(SubInfo {
+                size: self.data_size(),
+                data_type: DataType::Domain,
+                access_type: AccessType::Ro,
+                pdo_mapping: PdoMapping::None,
+                persist: false,
+            }),
+            3 => Ok(SubInfo::new_u32()),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(rate_limit: u32) -> DebugLog {
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; 16])));
+        DebugLog::new(buffer, rate_limit)
+    }
+
+    fn read_all(log: &DebugLog) -> Vec<u8> {
+        let mut data = vec![0; log.read_size(2).unwrap()];
+        // Read in small chunks, as a segmented upload would
+        for offset in (0..data.len()).step_by(3) {
+            let end = (offset + 3).min(data.len());
+            log.read(2, offset, &mut data[offset..end]).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn test_ring_wraps() {
+        let log = log(0);
+        assert!(log.log_str("hello"));
+        assert_eq!(b"\x06\0\0\0hello\n".to_vec(), read_all(&log));
+
+        assert!(log.log_str("world"));
+        assert!(log.log_str("again"));
+        assert_eq!(18, log.position());
+        let data = read_all(&log);
+        assert_eq!(18u32.to_le_bytes(), data[..4]);
+        assert_eq!(b"llo\nworld\nagain\n", &data[4..]);
+
+        // Messages longer than the buffer are truncated
+        assert!(log.log_str("0123456789abcdefghij"));
+        assert_eq!(b"0123456789abcde\n", &read_all(&log)[4..]);
+
+        log.write(1, &[0; 4]).unwrap();
+        assert_eq!(0, log.position());
+        assert_eq!(4, log.read_size(2).unwrap());
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let log = log(2);
+        log.tick(0);
+        assert!(log.log_str("a"));
+        assert!(log.log_str("b"));
+        assert!(!log.log_str("c"));
+        log.tick(999_999);
+        assert!(!log.log_str("d"));
+        assert_eq!(2, log.dropped());
+
+        // The limit resets in the next window
+        log.tick(1_000_000);
+        assert!(log.log_str("e"));
+        assert_eq!(b"a\nb\ne\n", &read_all(&log)[4..]);
+        assert_eq!(2, log.read_u32(3).unwrap());
+    }
+}
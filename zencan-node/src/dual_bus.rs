@@ -0,0 +1,167 @@
+//! Bridging a single node across two redundant CAN interfaces
+use core::{future::Future, pin::pin, time::Duration};
+use std::{time::Instant, vec::Vec};
+
+use defmt_or_log::warn;
+use futures::future::{select, Either};
+use zencan_common::{
+    traits::{AsyncCanReceiver, AsyncCanSender},
+    CanMessage,
+};
+
+use crate::{node_mbox::BusInterface, Node, ProcessResult};
+
+/// Runs one [`Node`] over two CAN transports, for redundant bus topologies
+///
+/// The node appears on both buses with the same node ID and object dictionary:
+///
+/// - Frames received on either interface are stored with
+///   [`NodeMbox::store_message_from`](crate::NodeMbox::store_message_from), so an NMT command
+///   received on both buses is only acted on once.
+/// - SDO responses are sent on the interface which received the request.
+/// - All other frames produced by the node, such as heartbeats, PDOs and EMCY messages, are sent on
+///   both interfaces.
+#[allow(missing_debug_implementations)]
+pub struct DualBusRunner {
+    node: Node,
+    epoch: Instant,
+}
+
+impl DualBusRunner {
+    /// Create a runner for `node`
+    pub fn new(node: Node) -> Self {
+        Self {
+            node,
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Get the hosted node
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    /// Get mutable access to the hosted node
+    pub fn node_mut(&mut self) -> &mut Node {
+        &mut self.node
+    }
+
+    /// Deliver a frame received on `interface` to the node
+    ///
+    /// Returns true if the node accepted the frame
+    pub fn dispatch(&self, msg: CanMessage, interface: BusInterface) -> bool {
+        let now_us = self.now_us();
+        self.node
+            .mbox()
+            .store_message_from(msg, interface, now_us)
+            .is_ok()
+    }
+
+    /// Run the process method of the node
+    ///
+    /// Each message transmitted by the node is passed to `send_cb` once for every interface it
+    /// should be sent on.
+    pub fn process(
+        &mut self,
+        now_us: u64,
+        send_cb: &mut dyn FnMut(BusInterface, CanMessage),
+    ) -> ProcessResult {
+        let mut to_send = Vec::new();
+        let result = self.node.process(now_us, &mut |msg| to_send.push(msg));
+        let sdo_tx_cob_id = self.node.sdo_tx_cob_id();
+        for msg in to_send {
+            if Some(msg.id()) == sdo_tx_cob_id {
+                send_cb(self.node.mbox().sdo_interface(), msg);
+            } else {
+                send_cb(BusInterface::A, msg);
+                send_cb(BusInterface::B, msg);
+            }
+        }
+        result
+    }
+
+    fn now_us(&self) -> u64 {
+        self.epoch.elapsed().as_micros() as u64
+    }
+
+    /// Run the node on two CAN transports
+    ///
+    /// The node is processed after each received frame, when it has a scheduled action due, or at
+    /// the latest when `period` has elapsed without any frames. `sleep` must return a future which
+    /// completes after the given duration, e.g. `tokio::time::sleep`.
+    ///
+    /// A failure to send on one interface does not prevent sending on the other.
+    ///
+    /// This function does not return.
+    pub async fn run<SA, RA, SB, RB, F, Fut>(
+        &mut self,
+        sender_a: &mut SA,
+        receiver_a: &mut RA,
+        sender_b: &mut SB,
+        receiver_b: &mut RB,
+        period: Duration,
+        mut sleep: F,
+    ) where
+        SA: AsyncCanSender,
+        RA: AsyncCanReceiver,
+        SB: AsyncCanSender,
+        RB: AsyncCanReceiver,
+        F: FnMut(Duration) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        loop {
+            let now_us = self.now_us();
+            let mut tx_messages = Vec::new();
+            let result = self.process(now_us, &mut |interface, msg| {
+                tx_messages.push((interface, msg))
+            });
+            for (interface, msg) in tx_messages {
+                let sent = match interface {
+                    BusInterface::A => sender_a.send(msg).await.is_ok(),
+                    BusInterface::B => sender_b.send(msg).await.is_ok(),
+                };
+                if !sent {
+                    warn!("Failed to send CAN message on interface {:?}", interface);
+                }
+            }
+
+            let received = {
+                let recv_a = pin!(receiver_a.recv());
+                let recv_b = pin!(receiver_b.recv());
+                let wait = result
+                    .next_action_us
+                    .map(Duration::from_micros)
+                    .map_or(period, |t| t.min(period));
+                let timeout = pin!(sleep(wait));
+                // The receivers have different error types, so only the message is kept
+                match select(select(recv_a, recv_b), timeout).await {
+                    Either::Left((Either::Left((result, _)), _)) => {
+                        Some((BusInterface::A, result.ok()))
+                    }
+                    Either::Left((Either::Right((result, _)), _)) => {
+                        Some((BusInterface::B, result.ok()))
+                    }
+                    Either::Right(_) => None,
+                }
+            };
+            match received {
+                Some((interface, Some(msg))) => {
+                    self.dispatch(msg, interface);
+                }
+                Some((interface, None)) => {
+                    warn!("Error receiving CAN message on interface {:?}", interface);
+                    sleep(period).await;
+                }
+                None => (),
+            }
+            // Drain any other frames which are already waiting. This also keeps frames flowing from
+            // one interface while the other is failing.
+            while let Some(msg) = receiver_a.try_recv() {
+                self.dispatch(msg, BusInterface::A);
+            }
+            while let Some(msg) = receiver_b.try_recv() {
+                self.dispatch(msg, BusInterface::B);
+            }
+        }
+    }
+}
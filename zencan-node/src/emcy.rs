@@ -0,0 +1,181 @@
+//! Emergency (EMCY) message producer
+//!
+//! EMCY messages are queued by the application with [`Node::send_emcy`](crate::Node::send_emcy),
+//! and transmitted from `Node::process`. Transmissions are rate limited by the inhibit time in
+//! object 0x1015, so that a fault which is reported repeatedly cannot saturate the bus. Up to
+//! [`EMCY_QUEUE_LEN`] EMCYs are held while the inhibit time runs. A repeat of a held error code
+//! replaces the held EMCY, and when the queue is full the oldest EMCY is dropped.
+//!
+//! Holding a single EMCY, with each newer one replacing it, would be enough to protect the bus,
+//! but different faults are often reported together -- e.g. a lost heartbeat and an application
+//! fault raised in response to it -- and only the last of them would reach the bus. Since each error
+//! code is held at most once, a fault which is reported repeatedly still cannot queue more than one
+//! EMCY, and the queue bounds the burst which follows an inhibit time. EMCYs which are replaced or
+//! dropped are still recorded in the error history (0x1003).
+
+use zencan_common::messages::{CanId, CanMessage};
+
+/// An EMCY waiting to be transmitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PendingEmcy {
+    pub error_code: u16,
+    pub vendor_data: [u8; 5],
+}
+
+impl PendingEmcy {
+    /// Create the EMCY message, with the current value of the node's error register
+    pub fn to_can_message(self, cob_id: CanId, error_register: u8) -> CanMessage {
+        let mut data = [0u8; 8];
+        data[0..2].copy_from_slice(&self.error_code.to_le_bytes());
+        data[2] = error_register;
+        data[3..8].copy_from_slice(&self.vendor_data);
+        CanMessage::new(cob_id, &data)
+    }
+}
+
+/// The number of EMCYs which can be held while waiting for the inhibit time
+pub(crate) const EMCY_QUEUE_LEN: usize = 4;
+
+/// Holds the queued EMCYs, and tracks the inhibit time
+#[derive(Debug, Default)]
+pub(crate) struct EmcyProducer {
+    /// Queued EMCYs, oldest first
+    pending: [Option<PendingEmcy>; EMCY_QUEUE_LEN],
+    last_tx_us: Option<u64>,
+    merged_count: u32,
+}
+
+impl EmcyProducer {
+    pub const fn new() -> Self {
+        Self {
+            pending: [None; EMCY_QUEUE_LEN],
+            last_tx_us: None,
+            merged_count: 0,
+        }
+    }
+
+    /// Queue an EMCY for transmission
+    ///
+    /// If an EMCY with the same error code is already waiting for the inhibit time to expire, it
+    /// is replaced, so that the most recent data is reported. Otherwise the EMCY is added to the
+    /// queue, dropping the oldest one if the queue is full.
+    ///
+    /// Returns the EMCY which was replaced or dropped, if any
+    pub fn queue(&mut self, error_code: u16, vendor_data: [u8; 5]) -> Option<PendingEmcy> {
+        let emcy = PendingEmcy {
+            error_code,
+            vendor_data,
+        };
+        let displaced = if let Some(slot) = self
+            .pending
+            .iter_mut()
+            .flatten()
+            .find(|p| p.error_code == error_code)
+        {
+            Some(core::mem::replace(slot, emcy))
+        } else if let Some(slot) = self.pending.iter_mut().find(|p| p.is_none()) {
+            *slot = Some(emcy);
+            None
+        } else {
+            let oldest = self.pending[0].take();
+            self.pending.rotate_left(1);
+            self.pending[EMCY_QUEUE_LEN - 1] = Some(emcy);
+            oldest
+        };
+        if displaced.is_some() {
+            self.merged_count = self.merged_count.wrapping_add(1);
+        }
+        displaced
+    }
+
+    /// Get the time until the oldest pending EMCY can be sent, or None if there is no pending EMCY
+    pub fn time_until_due(&self, now_us: u64, inhibit_us: u64) -> Option<u64> {
+        self.pending[0]?;
+        match self.last_tx_us {
+            Some(last) => Some((last + inhibit_us).saturating_sub(now_us)),
+            None => Some(0),
+        }
+    }
+
+    /// Take the oldest pending EMCY if its inhibit time has expired, and restart the inhibit time
+    pub fn take_due(&mut self, now_us: u64, inhibit_us: u64) -> Option<PendingEmcy> {
+        if self.time_until_due(now_us, inhibit_us) != Some(0) {
+            return None;
+        }
+        self.last_tx_us = Some(now_us);
+        let emcy = self.pending[0].take();
+        self.pending.rotate_left(1);
+        emcy
+    }
+
+    /// The number of EMCYs which were replaced or dropped before they could be sent
+    pub fn merged_count(&self) -> u32 {
+        self.merged_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emcy_message() {
+        let emcy = PendingEmcy {
+            error_code: 0x8110,
+            vendor_data: [1, 2, 3, 4, 5],
+        };
+        let msg = emcy.to_can_message(CanId::emcy(3), 0x11);
+        assert_eq!(CanId::std(0x83), msg.id());
+        assert_eq!(&[0x10, 0x81, 0x11, 1, 2, 3, 4, 5], msg.data());
+    }
+
+    #[test]
+    fn test_inhibit_time() {
+        let mut producer = EmcyProducer::new();
+        assert_eq!(None, producer.time_until_due(0, 1000));
+        assert_eq!(None, producer.take_due(0, 1000));
+
+        producer.queue(0x1000, [0; 5]);
+        assert_eq!(0x1000, producer.take_due(100, 1000).unwrap().error_code);
+
+        // Repeats of an error code within the inhibit time are merged, and the newest is sent
+        assert_eq!(None, producer.queue(0x1000, [1; 5]));
+        let replaced = producer.queue(0x1000, [2; 5]).unwrap();
+        assert_eq!([1; 5], replaced.vendor_data);
+        assert_eq!(Some(500), producer.time_until_due(600, 1000));
+        assert_eq!(None, producer.take_due(600, 1000));
+        assert_eq!([2; 5], producer.take_due(1100, 1000).unwrap().vendor_data);
+        assert_eq!(1, producer.merged_count());
+
+        // With no inhibit time, each EMCY is sent immediately
+        producer.queue(0x3000, [0; 5]);
+        producer.queue(0x4000, [0; 5]);
+        assert_eq!(0x3000, producer.take_due(1100, 0).unwrap().error_code);
+        assert_eq!(0x4000, producer.take_due(1100, 0).unwrap().error_code);
+        assert_eq!(None, producer.take_due(1100, 0));
+    }
+
+    #[test]
+    fn test_different_codes_queued() {
+        let mut producer = EmcyProducer::new();
+        producer.queue(0x1000, [0; 5]);
+        producer.take_due(0, 1000);
+
+        // Different error codes are all sent, oldest first, one per inhibit time
+        assert_eq!(None, producer.queue(0x1000, [0; 5]));
+        assert_eq!(None, producer.queue(0x2000, [0; 5]));
+        assert_eq!(0x1000, producer.take_due(1000, 1000).unwrap().error_code);
+        assert_eq!(None, producer.take_due(1500, 1000));
+        assert_eq!(0x2000, producer.take_due(2000, 1000).unwrap().error_code);
+        assert_eq!(None, producer.time_until_due(2000, 1000));
+        assert_eq!(0, producer.merged_count());
+
+        // When the queue is full, the oldest EMCY is dropped
+        for code in 0..EMCY_QUEUE_LEN as u16 {
+            assert_eq!(None, producer.queue(code, [0; 5]));
+        }
+        assert_eq!(0, producer.queue(0x5000, [0; 5]).unwrap().error_code);
+        assert_eq!(1, producer.merged_count());
+        assert_eq!(1, producer.take_due(3000, 1000).unwrap().error_code);
+    }
+}
@@ -0,0 +1,197 @@
+//! Error history (object 0x1003, pre-defined error field)
+//!
+//! When a device config sets `error_history`, the [`NodeState`](crate::NodeState) holds the most
+//! recent EMCY errors sent by the node. The number of entries is the `N_ERROR_HISTORY` const
+//! generic of the node state, so a device without an error history uses no RAM for it.
+//!
+//! The history is reported in object 0x1003. Sub 0 holds the number of recorded errors, and sub 1
+//! holds the most recent one, sub 2 the one before it, etc. Each entry holds the EMCY error code in
+//! bits 0-15, and the first two bytes of vendor data in bits 16-31. When the history is full, the
+//! oldest error is discarded. Writing 0 to sub 0 clears the history.
+//!
+//! An error is recorded when its EMCY is sent, so an EMCY replaced by a newer one during the
+//! inhibit time is not recorded.
+
+use zencan_common::{
+    objects::{ObjectCode, SubInfo},
+    sdo::AbortCode,
+    AtomicCell,
+};
+
+use crate::object_dict::{read_le_bytes, ObjectAccess};
+
+/// A reference to the error history stored in the node state
+#[derive(Clone, Copy)]
+pub struct ErrorHistory<'a> {
+    entries: &'a [AtomicCell<u32>],
+    count: &'a AtomicCell<u8>,
+}
+
+impl core::fmt::Debug for ErrorHistory<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ErrorHistory")
+            .field("capacity", &self.capacity())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<'a> ErrorHistory<'a> {
+    /// Create an error history from its entries and the number of recorded errors
+    pub const fn new(entries: &'a [AtomicCell<u32>], count: &'a AtomicCell<u8>) -> Self {
+        Self { entries, count }
+    }
+
+    /// Get the maximum number of errors which can be held
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Get the number of errors held
+    pub fn len(&self) -> usize {
+        self.count.load() as usize
+    }
+
+    /// Returns true if no errors are held
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get an error, where 0 is the most recent one
+    pub fn get(&self, n: usize) -> Option<u32> {
+        if n < self.len() {
+            Some(self.entries[n].load())
+        } else {
+            None
+        }
+    }
+
+    /// Discard all of the errors
+    pub fn clear(&self) {
+        critical_section::with(|_| {
+            for entry in self.entries {
+                entry.store(0);
+            }
+            self.count.store(0);
+        });
+    }
+
+    /// Record an error sent in an EMCY
+    pub(crate) fn push(&self, error_code: u16, vendor_data: [u8; 5]) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let info = u16::from_le_bytes([vendor_data[0], vendor_data[1]]);
+        let value = error_code as u32 | ((info as u32) << 16);
+        critical_section::with(|_| {
+            for i in (1..self.entries.len()).rev() {
+                self.entries[i].store(self.entries[i - 1].load());
+            }
+            self.entries[0].store(value);
+            let count = (self.len() + 1).min(self.capacity());
+            self.count.store(count as u8);
+        });
+    }
+}
+
+/// Implements object 0x1003, reporting the error history
+#[derive(Debug)]
+pub struct ErrorHistoryObject {
+    history: ErrorHistory<'static>,
+}
+
+impl ErrorHistoryObject {
+    /// Create the object for the error history of a node state
+    pub const fn new(history: ErrorHistory<'static>) -> Self {
+        Self { history }
+    }
+}
+
+impl ObjectAccess for ErrorHistoryObject {
+    fn read(&self, sub: u8, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        if sub == 0 {
+            return Ok(read_le_bytes(&[self.history.len() as u8], offset, buf));
+        }
+        self.sub_info(sub)?;
+        let value = self
+            .history
+            .get(sub as usize - 1)
+            .ok_or(AbortCode::NoData)?;
+        Ok(read_le_bytes(&value.to_le_bytes(), offset, buf))
+    }
+
+    fn read_size(&self, sub: u8) -> Result<usize, AbortCode> {
+        Ok(self.sub_info(sub)?.size)
+    }
+
+    fn write(&self, sub: u8, data: &[u8]) -> Result<(), AbortCode> {
+        if sub != 0 {
+            self.sub_info(sub)?;
+            return Err(AbortCode::ReadOnly);
+        }
+        match data {
+            [0] => {
+                self.history.clear();
+                Ok(())
+            }
+            [_] => Err(AbortCode::InvalidValue),
+            [] => Err(AbortCode::DataTypeMismatchLengthLow),
+            _ => Err(AbortCode::DataTypeMismatchLengthHigh),
+        }
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Array
+    }
+
+    fn sub_info(&self, sub: u8) -> Result<SubInfo, AbortCode> {
+        match sub {
+            0 => Ok(SubInfo::new_u8().rw_access()),
+            n if (n as usize) <= self.history.capacity() => Ok(SubInfo::new_u32()),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static ENTRIES: [AtomicCell<u32>; 2] = [const { AtomicCell::new(0) }; 2];
+    static COUNT: AtomicCell<u8> = AtomicCell::new(0);
+
+    fn read_u32(object: &ErrorHistoryObject, sub: u8) -> Result<u32, AbortCode> {
+        let mut buf = [0; 4];
+        object.read(sub, 0, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    #[test]
+    fn test_error_history() {
+        let history = ErrorHistory::new(&ENTRIES, &COUNT);
+        let object = ErrorHistoryObject::new(history);
+        assert!(history.is_empty());
+        assert_eq!(Err(AbortCode::NoData), read_u32(&object, 1));
+
+        history.push(0x8110, [0x34, 0x12, 0, 0, 0]);
+        history.push(0x1000, [0; 5]);
+        history.push(0x2310, [1, 0, 0, 0, 0]);
+        // The oldest error is discarded
+        assert_eq!(2, history.len());
+        assert_eq!(Some(0x0001_2310), history.get(0));
+        assert_eq!(Some(0x1000), history.get(1));
+
+        let mut buf = [0];
+        object.read(0, 0, &mut buf).unwrap();
+        assert_eq!(2, buf[0]);
+        assert_eq!(Ok(0x0001_2310), read_u32(&object, 1));
+        assert_eq!(Ok(0x1000), read_u32(&object, 2));
+        assert_eq!(Err(AbortCode::NoSuchSubIndex), read_u32(&object, 3));
+
+        assert_eq!(Err(AbortCode::ReadOnly), object.write(1, &[0; 4]));
+        assert_eq!(Err(AbortCode::InvalidValue), object.write(0, &[1]));
+        assert_eq!(Ok(()), object.write(0, &[0]));
+        assert!(history.is_empty());
+        assert_eq!(None, history.get(0));
+    }
+}
@@ -0,0 +1,463 @@
+//! Append-only event log (object 0x5008)
+//!
+//! When a device config sets an event log depth, zencan-build creates an [`EventLog`] with a
+//! statically allocated ring buffer. The application records events, each an application defined
+//! code and 4 bytes of data, with [`Node::log_event`](crate::Node::log_event), or with
+//! [`EventLog::log_event`] from another task. Each event is stamped with the time passed to the
+//! node, and given a sequence number, so that a client can fetch only the events which are new
+//! since its last fetch, e.g. with `SdoClient::fetch_events_since` in zencan-client. When the buffer
+//! is full, the oldest event is overwritten.
+//!
+//! | Sub Object | Type   | Description |
+//! | ---------- | ------ | ----------- |
+//! | 0          | u8     | Max sub index - always 4 |
+//! | 1          | u32    | The sequence number of the next event. Write 0 to clear |
+//! | 2          | u32    | The sequence number of the first event read from sub 3 |
+//! | 3          | Domain | The held events with a sequence number of at least sub 2, oldest first |
+//! | 4          | Domain | All held events, oldest first. Persisted if the device config sets `persist` |
+//!
+//! See [`zencan_common::event_log`] for the format of the entries. When the log is persisted, sub 4
+//! is saved along with the other persisted objects when a save is requested via object 0x1010, and
+//! restoring it when the node starts brings back the events and the sequence number. Sub 4 can
+//! only be written when the log is empty, so that events cannot be replaced.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use zencan_common::{
+    event_log::{EventLogEntry, ENTRY_SIZE},
+    objects::{AccessType, DataType, ObjectCode, PdoMapping, SubInfo},
+    sdo::AbortCode,
+};
+
+use crate::{
+    object_dict::{read_le_bytes, ObjectAccess},
+    BufferCell,
+};
+
+struct EventRing {
+    entries: &'static BufferCell<[EventLogEntry]>,
+    /// The position at which the next entry is written
+    next: usize,
+    /// The number of valid entries
+    len: usize,
+    /// The sequence number of the next event
+    next_seq: u32,
+    /// The sequence number of the first event read from sub 3
+    cursor: u32,
+    /// The time passed to the node by the most recent process call
+    now_ms: u32,
+}
+
+impl EventRing {
+    fn get(&self, i: usize) -> Option<EventLogEntry> {
+        if i >= self.len {
+            return None;
+        }
+        let entries = self.entries.borrow_mut();
+        let capacity = entries.len();
+        Some(entries[(self.next + capacity - self.len + i) % capacity])
+    }
+
+    fn push(&mut self, entry: EventLogEntry) {
+        let mut entries = self.entries.borrow_mut();
+        let capacity = entries.len();
+        entries[self.next] = entry;
+        self.next = (self.next + 1) % capacity;
+        self.len = (self.len + 1).min(capacity);
+    }
+
+    /// The position of the first entry read from sub 3
+    fn cursor_start(&self) -> usize {
+        let oldest_seq = self.next_seq.wrapping_sub(self.len as u32);
+        (self.cursor.saturating_sub(oldest_seq) as usize).min(self.len)
+    }
+}
+
+/// A ring buffer of application events, implementing object 0x5008
+///
+/// This is instantiated by zencan-build, and registered on the node state with
+/// [`NodeState::with_event_log`](crate::NodeState::with_event_log).
+pub struct EventLog {
+    ring: Mutex<RefCell<EventRing>>,
+    persist: bool,
+}
+
+impl core::fmt::Debug for EventLog {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EventLog")
+            .field("len", &self.len())
+            .field("next_seq", &self.next_seq())
+            .finish()
+    }
+}
+
+impl EventLog {
+    /// Create a new log, storing events in `buffer`
+    ///
+    /// If `persist` is true, the events are saved with the other persisted objects.
+    pub const fn new(buffer: &'static BufferCell<[EventLogEntry]>, persist: bool) -> Self {
+        Self {
+            ring: Mutex::new(RefCell::new(EventRing {
+                entries: buffer,
+                next: 0,
+                len: 0,
+                next_seq: 0,
+                cursor: 0,
+                now_ms: 0,
+            })),
+            persist,
+        }
+    }
+
+    /// Record an event, replacing the oldest event if the buffer is full
+    ///
+    /// Returns the sequence number of the event.
+    ///
+    /// This may be called from any task or interrupt. The event is stamped with the time passed to
+    /// the most recent call to [`Node::process`](crate::Node::process).
+    pub fn log_event(&self, code: u16, data: [u8; 4]) -> u32 {
+        critical_section::with(|cs| {
+            let mut ring = self.ring.borrow_ref_mut(cs);
+            let seq = ring.next_seq;
+            let capacity = ring.entries.len();
+            if capacity != 0 {
+                let entry = EventLogEntry {
+                    seq,
+                    timestamp_ms: ring.now_ms,
+                    code,
+                    data,
+                };
+                ring.push(entry);
+            }
+            ring.next_seq = seq.wrapping_add(1);
+            seq
+        })
+    }
+
+    /// Update the time used to stamp events
+    pub(crate) fn tick(&self, now_us: u64) {
+        critical_section::with(|cs| {
+            self.ring.borrow_ref_mut(cs).now_ms = (now_us / 1000) as u32;
+        })
+    }
+
+    /// Remove all events, and reset the sequence number
+    pub fn clear(&self) {
+        critical_section::with(|cs| {
+            let mut ring = self.ring.borrow_ref_mut(cs);
+            ring.next = 0;
+            ring.len = 0;
+            ring.next_seq = 0;
+            ring.cursor = 0;
+        })
+    }
+
+    /// Get the number of events currently held
+    pub fn len(&self) -> usize {
+        critical_section::with(|cs| self.ring.borrow_ref(cs).len)
+    }
+
+    /// Returns true if no events are held
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the sequence number which will be given to the next event
+    ///
+    /// This is the number of events logged since the log was cleared, so it shows how many events
+    /// were lost when the buffer is full.
+    pub fn next_seq(&self) -> u32 {
+        critical_section::with(|cs| self.ring.borrow_ref(cs).next_seq)
+    }
+
+    /// Get an event, where 0 is the oldest held event
+    pub fn get(&self, i: usize) -> Option<EventLogEntry> {
+        critical_section::with(|cs| self.ring.borrow_ref(cs).get(i))
+    }
+
+    fn cursor(&self) -> u32 {
+        critical_section::with(|cs| self.ring.borrow_ref(cs).cursor)
+    }
+
+    /// Get the position of the first event returned by sub 3, and the number of events held
+    fn sub3_range(&self) -> (usize, usize) {
+        critical_section::with(|cs| {
+            let ring = self.ring.borrow_ref(cs);
+            (ring.cursor_start(), ring.len)
+        })
+    }
+
+    fn data_size(&self, sub: u8) -> usize {
+        let (start, len) = match sub {
+            3 => self.sub3_range(),
+            _ => (0, self.len()),
+        };
+        (len - start) * ENTRY_SIZE
+    }
+
+    /// Read the encoded events, oldest first, from the `first`th held event onwards, starting at a
+    /// byte offset
+    ///
+    /// Each event is read in its own critical section, so events logged during a segmented upload
+    /// may cause a torn read, in which some events are skipped or repeated. The sequence numbers
+    /// show where this happened.
+    fn read_data(&self, first: usize, offset: usize, buf: &mut [u8]) -> usize {
+        let size = self.len().saturating_sub(first) * ENTRY_SIZE;
+        if offset >= size {
+            return 0;
+        }
+        let read_len = buf.len().min(size - offset);
+        let mut pos = 0;
+        while pos < read_len {
+            let byte = offset + pos;
+            let Some(entry) = self.get(first + byte / ENTRY_SIZE) else {
+                break;
+            };
+            let bytes = entry.to_bytes();
+            let start = byte % ENTRY_SIZE;
+            let n = (ENTRY_SIZE - start).min(read_len - pos);
+            buf[pos..pos + n].copy_from_slice(&bytes[start..start + n]);
+            pos += n;
+        }
+        pos
+    }
+
+    /// Restore events previously read from sub 4
+    fn restore(&self, data: &[u8]) -> Result<(), AbortCode> {
+        if data.len() % ENTRY_SIZE != 0 {
+            return Err(AbortCode::DataTypeMismatch);
+        }
+        let mut last = None;
+        for entry in EventLogEntry::decode_all(data) {
+            if last.is_some_and(|last: u32| entry.seq != last.wrapping_add(1)) {
+                return Err(AbortCode::InvalidValue);
+            }
+            last = Some(entry.seq);
+        }
+        critical_section::with(|cs| {
+            let mut ring = self.ring.borrow_ref_mut(cs);
+            if ring.len != 0 || ring.next_seq != 0 {
+                return Err(AbortCode::CantStoreDeviceState);
+            }
+            let capacity = ring.entries.len();
+            if capacity == 0 {
+                return Ok(());
+            }
+            for entry in EventLogEntry::decode_all(data) {
+                ring.push(entry);
+            }
+            if let Some(last) = last {
+                ring.next_seq = last.wrapping_add(1);
+            }
+            Ok(())
+        })
+    }
+}
+
+fn u32_from_bytes(data: &[u8]) -> Result<u32, AbortCode> {
+    match data.len() {
+        4 => Ok(u32::from_le_bytes(data.try_into().unwrap())),
+        n if n < 4 => Err(AbortCode::DataTypeMismatchLengthLow),
+        _ => Err(AbortCode::DataTypeMismatchLengthHigh),
+    }
+}
+
+impl ObjectAccess for EventLog {
+    fn read(&self, sub: u8, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        match sub {
+            0 => Ok(read_le_bytes(&[4], offset, buf)),
+            1 => Ok(read_le_bytes(&self.next_seq().to_le_bytes(), offset, buf)),
+            2 => Ok(read_le_bytes(&self.cursor().to_le_bytes(), offset, buf)),
+            3 => Ok(self.read_data(self.sub3_range().0, offset, buf)),
+            4 => Ok(self.read_data(0, offset, buf)),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+
+    fn read_size(&self, sub: u8) -> Result<usize, AbortCode> {
+        match sub {
+            0 => Ok(1),
+            1 | 2 => Ok(4),
+            3 | 4 => Ok(self.data_size(sub)),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+
+    fn write(&self, sub: u8, data: &[u8]) -> Result<(), AbortCode> {
+        match sub {
+            0 | 3 => Err(AbortCode::ReadOnly),
+            1 => match u32_from_bytes(data)? {
+                0 => {
+                    self.clear();
+                    Ok(())
+                }
+                _ => Err(AbortCode::InvalidValue),
+            },
+            2 => {
+                let cursor = u32_from_bytes(data)?;
+                critical_section::with(|cs| self.ring.borrow_ref_mut(cs).cursor = cursor);
+                Ok(())
+            }
+            4 => self.restore(data),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Record
+    }
+
+    fn sub_info(&self, sub: u8) -> Result<SubInfo, AbortCode> {
+        match sub {
+            0 => Ok(SubInfo::MAX_SUB_NUMBER),
+            1 | 2 => Ok(SubInfo::new_u32().rw_access()),
+            3 => Ok(SubInfo {
+                size: self.data_size(3),
+                data_type: DataType::Domain,
+                access_type: AccessType::Ro,
+                pdo_mapping: PdoMapping::None,
+                persist: false,
+            }),
+            4 => Ok(SubInfo {
+                size: self.data_size(4),
+                data_type: DataType::Domain,
+                access_type: AccessType::Rw,
+                pdo_mapping: PdoMapping::None,
+                persist: self.persist,
+            }),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log() -> EventLog {
+        let buffer = Box::leak(Box::new(BufferCell::new([EventLogEntry::EMPTY; 3])));
+        EventLog::new(buffer, true)
+    }
+
+    fn read_all(log: &EventLog, sub: u8) -> Vec<EventLogEntry> {
+        let mut data = vec![0; log.read_size(sub).unwrap()];
+        // Read in segments which do not align with the entries
+        let mut offset = 0;
+        while offset < data.len() {
+            let end = (offset + 5).min(data.len());
+            offset += log.read(sub, offset, &mut data[offset..end]).unwrap();
+        }
+        EventLogEntry::decode_all(&data).collect()
+    }
+
+    fn seqs(entries: &[EventLogEntry]) -> Vec<u32> {
+        entries.iter().map(|e| e.seq).collect()
+    }
+
+    #[test]
+    fn test_ring_wraps() {
+        let log = log();
+        log.tick(5_000);
+        assert_eq!(0, log.log_event(0x10, [1, 2, 3, 4]));
+        assert_eq!(
+            Some(EventLogEntry {
+                seq: 0,
+                timestamp_ms: 5,
+                code: 0x10,
+                data: [1, 2, 3, 4]
+            }),
+            log.get(0)
+        );
+        for i in 1..5 {
+            assert_eq!(i, log.log_event(0x10 + i as u16, [0; 4]));
+        }
+        assert_eq!(3, log.len());
+        assert_eq!(5, log.next_seq());
+        assert_eq!(vec![2, 3, 4], seqs(&read_all(&log, 4)));
+
+        // Only 0 may be written to the sequence number, which clears the log
+        assert_eq!(Err(AbortCode::InvalidValue), log.write(1, &[1, 0, 0, 0]));
+        log.write(1, &[0; 4]).unwrap();
+        assert!(log.is_empty());
+        assert_eq!(0, log.next_seq());
+    }
+
+    #[test]
+    fn test_fetch_since() {
+        let log = log();
+        for _ in 0..4 {
+            log.log_event(1, [0; 4]);
+        }
+        // Events which have been overwritten are skipped
+        assert_eq!(vec![1, 2, 3], seqs(&read_all(&log, 3)));
+        log.write(2, &3u32.to_le_bytes()).unwrap();
+        assert_eq!(vec![3], seqs(&read_all(&log, 3)));
+        log.write(2, &4u32.to_le_bytes()).unwrap();
+        assert!(read_all(&log, 3).is_empty());
+        log.log_event(1, [0; 4]);
+        assert_eq!(vec![4], seqs(&read_all(&log, 3)));
+        // Sub 4 always returns all events
+        assert_eq!(vec![2, 3, 4], seqs(&read_all(&log, 4)));
+    }
+
+    #[test]
+    fn test_restore() {
+        let log1 = log();
+        for i in 0..5 {
+            log1.log_event(i, [i as u8; 4]);
+        }
+        let mut data = vec![0; log1.read_size(4).unwrap()];
+        log1.read(4, 0, &mut data).unwrap();
+        assert!(log1.sub_info(4).unwrap().persist);
+
+        let log2 = log();
+        log2.write(4, &data).unwrap();
+        assert_eq!(read_all(&log1, 4), read_all(&log2, 4));
+        assert_eq!(5, log2.next_seq());
+        // Events cannot be replaced once the log is in use
+        assert_eq!(Err(AbortCode::CantStoreDeviceState), log2.write(4, &data));
+        // Events must be contiguous
+        let log3 = log();
+        assert_eq!(
+            Err(AbortCode::InvalidValue),
+            log3.write(4, &[&data[..ENTRY_SIZE], &data[2 * ENTRY_SIZE..]].concat())
+        );
+    }
+
+    #[test]
+    fn test_persist() {
+        use crate::object_dict::ODEntry;
+        use crate::persist::{restore_stored_objects, serialize};
+
+        let log1 = Box::leak(Box::new(log()));
+        for i in 0..4 {
+            log1.log_event(i, [i as u8; 4]);
+        }
+        let od1 = Box::leak(Box::new([ODEntry {
+            index: 0x5008,
+            data: log1,
+        }]));
+        let data = RefCell::new(Vec::new());
+        // The stored events are larger than a single chunk of the serializer
+        serialize(od1, |reader, _size| {
+            let mut buf = [0; 8];
+            loop {
+                let n = reader.read(&mut buf).unwrap();
+                data.borrow_mut().extend_from_slice(&buf[..n]);
+                if n < buf.len() {
+                    break;
+                }
+            }
+        });
+
+        let log2 = log();
+        let od2 = [ODEntry {
+            index: 0x5008,
+            data: &log2,
+        }];
+        restore_stored_objects(&od2, &data.take());
+        assert_eq!(vec![1, 2, 3], seqs(&read_all(&log2, 4)));
+        assert_eq!(4, log2.next_seq());
+    }
+}
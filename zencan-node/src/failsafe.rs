@@ -0,0 +1,346 @@
+//! Failsafe outputs, which are set to their error values when the controlling master is lost
+//!
+//! Output devices following CiA 401 have, alongside each output object, an error mode object which
+//! selects the outputs to change when the device detects an error, and an error value object holding
+//! the values they change to. Each is an array with one entry per block of 8 digital outputs, or
+//! per analogue output channel. A [`Failsafe`] registered with
+//! [`Node::set_failsafe`](crate::Node::set_failsafe) applies the error values to the outputs:
+//!
+//! - When a heartbeat monitored by the heartbeat consumer (object 0x1016) times out. Monitoring of
+//!   an entry starts when the first heartbeat from its node is received, and restarts with the
+//!   next heartbeat after a timeout. Each timeout also queues an EMCY with the error code
+//!   [`HEARTBEAT_LOST_EMCY_CODE`], with the node ID of the lost node in the first vendor data byte.
+//! - When the node leaves the Operational state, e.g. on an NMT stop command from the master, as
+//!   RPDOs are no longer received to update the outputs.
+//!
+//! The error values are written to the outputs once, through the object dictionary, so an output
+//! implemented as an application callback object sees an ordinary write. A later RPDO or SDO write
+//! changes them again, e.g. once the master has recovered.
+//!
+//! ```rust
+//! use zencan_node::failsafe::{Failsafe, FailsafeGroup, HeartbeatSlot};
+//!
+//! // Monitor up to two heartbeat consumer entries
+//! static HEARTBEATS: [HeartbeatSlot; 2] = [const { HeartbeatSlot::new() }; 2];
+//! static FAILSAFE: Failsafe = Failsafe::new(
+//!     &[FailsafeGroup::DIGITAL_OUTPUT_8, FailsafeGroup::ANALOG_OUTPUT_16],
+//!     &HEARTBEATS,
+//! );
+//! // node.set_failsafe(&FAILSAFE);
+//! ```
+
+use defmt_or_log::warn;
+use zencan_common::{constants::object_ids, messages::NmtState, sdo::AbortCode, AtomicCell};
+
+use crate::object_dict::{find_object, ODEntry, ObjectAccess};
+
+/// The EMCY error code queued when a consumed heartbeat times out (heartbeat error)
+pub const HEARTBEAT_LOST_EMCY_CODE: u16 = 0x8130;
+
+/// How the error mode and error value of a [`FailsafeGroup`] apply to its outputs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OutputKind {
+    /// Each sub object is a u8 holding 8 digital outputs
+    ///
+    /// The error mode is a u8 bit mask of the outputs which change on an error, and the error
+    /// value is a u8 holding their values.
+    Digital,
+    /// Each sub object is a single analogue output
+    ///
+    /// The output changes on an error if its error mode, a u8, is not 0. The error value is an
+    /// i32, which is limited to the range of the output.
+    Analog,
+}
+
+/// An array of outputs, and the error mode and error value objects which control it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FailsafeGroup {
+    /// How the error values apply to the outputs
+    pub kind: OutputKind,
+    /// The index of the output object
+    pub output: u16,
+    /// The index of the error mode object
+    pub error_mode: u16,
+    /// The index of the error value object
+    pub error_value: u16,
+}
+
+impl FailsafeGroup {
+    /// The CiA 401 8-bit digital outputs (0x6200), error mode (0x6206) and error value (0x6207)
+    pub const DIGITAL_OUTPUT_8: Self = Self {
+        kind: OutputKind::Digital,
+        output: object_ids::DIGITAL_OUTPUT_8,
+        error_mode: object_ids::DIGITAL_OUTPUT_ERROR_MODE_8,
+        error_value: object_ids::DIGITAL_OUTPUT_ERROR_VALUE_8,
+    };
+
+    /// The CiA 401 16-bit analogue outputs (0x6411), error mode (0x6443) and error value (0x6444)
+    pub const ANALOG_OUTPUT_16: Self = Self {
+        kind: OutputKind::Analog,
+        output: object_ids::ANALOG_OUTPUT_16,
+        error_mode: object_ids::ANALOG_OUTPUT_ERROR_MODE,
+        error_value: object_ids::ANALOG_OUTPUT_ERROR_VALUE,
+    };
+
+    /// Write the error values to the outputs of this group which have an error mode set
+    ///
+    /// A group whose objects are missing from the object dictionary is skipped, as is an output
+    /// which has no error mode or error value sub object.
+    fn apply(&self, od: &[ODEntry]) {
+        let (Some(output), Some(error_mode), Some(error_value)) = (
+            find_object(od, self.output),
+            find_object(od, self.error_mode),
+            find_object(od, self.error_value),
+        ) else {
+            return;
+        };
+        let count = output.read_u8(0).unwrap_or(0);
+        for sub in 1..=count {
+            let Ok(mode) = error_mode.read_u8(sub) else {
+                continue;
+            };
+            if mode == 0 {
+                continue;
+            }
+            let result = match self.kind {
+                OutputKind::Digital => error_value.read_u8(sub).and_then(|value| {
+                    let current = output.read_u8(sub)?;
+                    output.write(sub, &[(current & !mode) | (value & mode)])
+                }),
+                OutputKind::Analog => error_value
+                    .read_i32(sub)
+                    .and_then(|value| write_analog(output, sub, value)),
+            };
+            if result.is_err() {
+                warn!(
+                    "Failed to apply error value to output 0x{:x}sub{}",
+                    self.output, sub
+                );
+            }
+        }
+    }
+}
+
+/// Write a signed value to an analogue output, limited to the range of its size
+fn write_analog(output: &dyn ObjectAccess, sub: u8, value: i32) -> Result<(), AbortCode> {
+    match output.sub_info(sub)?.size {
+        1 => output.write(
+            sub,
+            &(value.clamp(i8::MIN as i32, i8::MAX as i32) as i8).to_le_bytes(),
+        ),
+        2 => output.write(
+            sub,
+            &(value.clamp(i16::MIN as i32, i16::MAX as i32) as i16).to_le_bytes(),
+        ),
+        4 => output.write(sub, &value.to_le_bytes()),
+        _ => Err(AbortCode::DataTypeMismatch),
+    }
+}
+
+/// Write the error values of each group to its outputs
+///
+/// This is done by the node when a [`Failsafe`] is triggered, and may also be called by the
+/// application, e.g. when it detects an internal failure.
+pub fn apply_error_values(od: &[ODEntry], groups: &[FailsafeGroup]) {
+    for group in groups {
+        group.apply(od);
+    }
+}
+
+/// Why the error values were applied
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FailsafeReason {
+    /// No heartbeat was received from a monitored node within its consumer timeout
+    HeartbeatLost {
+        /// The ID of the node whose heartbeat was lost
+        node_id: u8,
+    },
+    /// The node left the Operational state, and entered this state
+    LeftOperational(NmtState),
+}
+
+/// The signature of the function called when the error values are applied
+pub type FailsafeCallback = dyn Fn(FailsafeReason) + Sync;
+
+/// The monitoring state of one heartbeat consumer entry
+///
+/// A [`Failsafe`] needs one slot for each entry of object 0x1016 which it monitors.
+#[allow(missing_debug_implementations)]
+pub struct HeartbeatSlot {
+    /// The consumer entry, with the node ID in bits 16-23 and the timeout in ms in bits 0-15
+    entry: AtomicCell<u32>,
+    /// The time of the process call which first saw the last heartbeat, or None if no heartbeat
+    /// has been received since monitoring started
+    last_seen_us: AtomicCell<Option<u64>>,
+}
+
+impl Default for HeartbeatSlot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeartbeatSlot {
+    /// Create an unused slot
+    pub const fn new() -> Self {
+        Self {
+            entry: AtomicCell::new(0),
+            last_seen_us: AtomicCell::new(None),
+        }
+    }
+
+    /// Get the monitored node ID and timeout in microseconds, if the entry is in use
+    fn monitored(&self) -> Option<(u8, u64)> {
+        let entry = self.entry.load();
+        let node_id = (entry >> 16) as u8;
+        let timeout_ms = entry as u16;
+        ((1..=127).contains(&node_id) && timeout_ms != 0)
+            .then_some((node_id, timeout_ms as u64 * 1000))
+    }
+}
+
+/// Applies the error values of a set of outputs when the controlling master is lost
+///
+/// See the [module docs](self) for more info.
+#[allow(missing_debug_implementations)]
+pub struct Failsafe {
+    groups: &'static [FailsafeGroup],
+    heartbeats: &'static [HeartbeatSlot],
+    callback: AtomicCell<Option<&'static FailsafeCallback>>,
+    active: AtomicCell<Option<FailsafeReason>>,
+}
+
+impl Failsafe {
+    /// Create a new Failsafe
+    ///
+    /// # Arguments
+    ///
+    /// - `groups`: The outputs to which error values are applied
+    /// - `heartbeats`: Storage for monitoring the heartbeat consumer entries. Entries of object
+    ///   0x1016 beyond the number of slots are not monitored.
+    pub const fn new(
+        groups: &'static [FailsafeGroup],
+        heartbeats: &'static [HeartbeatSlot],
+    ) -> Self {
+        Self {
+            groups,
+            heartbeats,
+            callback: AtomicCell::new(None),
+            active: AtomicCell::new(None),
+        }
+    }
+
+    /// Register a callback to be called each time the error values are applied
+    ///
+    /// It is called from [`Node::process`](crate::Node::process), after the outputs are written.
+    pub fn register_callback(&self, cb: &'static FailsafeCallback) {
+        self.callback.store(Some(cb));
+    }
+
+    /// Get the reason the error values were most recently applied, unless it has been cleared
+    pub fn active(&self) -> Option<FailsafeReason> {
+        self.active.load()
+    }
+
+    /// Clear the reason returned by [`active`](Self::active)
+    pub fn clear(&self) {
+        self.active.store(None);
+    }
+
+    /// Apply the error values to the outputs, and report the reason
+    pub(crate) fn trigger(&self, od: &[ODEntry], reason: FailsafeReason) {
+        warn!("Applying failsafe output values: {:?}", reason);
+        apply_error_values(od, self.groups);
+        self.active.store(Some(reason));
+        if let Some(cb) = self.callback.load() {
+            cb(reason);
+        }
+    }
+
+    /// Read the heartbeat consumer entries from object 0x1016, and return a bit mask of the node
+    /// IDs which are monitored
+    ///
+    /// An entry which has changed starts monitoring again, from its first heartbeat.
+    pub(crate) fn update_entries(&self, od: &[ODEntry]) -> u128 {
+        let consumer = find_object(od, object_ids::HEARTBEAT_CONSUMER_TIME);
+        let count = consumer.and_then(|obj| obj.read_u8(0).ok()).unwrap_or(0) as usize;
+        let mut mask = 0;
+        for (i, slot) in self.heartbeats.iter().enumerate() {
+            let entry = match consumer {
+                Some(obj) if i < count => obj.read_u32(i as u8 + 1).unwrap_or(0),
+                _ => 0,
+            };
+            if slot.entry.load() != entry {
+                slot.entry.store(entry);
+                slot.last_seen_us.store(None);
+            }
+            if let Some((node_id, _)) = slot.monitored() {
+                mask |= 1 << node_id;
+            }
+        }
+        mask
+    }
+
+    /// Update the monitored entries with the heartbeats received since the last call, given as a
+    /// bit mask of node IDs, and call `on_lost` with the ID of each node whose heartbeat has timed
+    /// out
+    pub(crate) fn check_heartbeats(&self, seen: u128, now_us: u64, mut on_lost: impl FnMut(u8)) {
+        for slot in self.heartbeats {
+            let Some((node_id, timeout_us)) = slot.monitored() else {
+                continue;
+            };
+            if seen & (1 << node_id) != 0 {
+                slot.last_seen_us.store(Some(now_us));
+            } else if let Some(last_seen_us) = slot.last_seen_us.load() {
+                if now_us.saturating_sub(last_seen_us) >= timeout_us {
+                    slot.last_seen_us.store(None);
+                    on_lost(node_id);
+                }
+            }
+        }
+    }
+
+    /// Get the time until the next monitored heartbeat times out, if any are being monitored
+    pub(crate) fn next_timeout_us(&self, now_us: u64) -> Option<u64> {
+        self.heartbeats
+            .iter()
+            .filter_map(|slot| {
+                let (_, timeout_us) = slot.monitored()?;
+                let last_seen_us = slot.last_seen_us.load()?;
+                Some((last_seen_us + timeout_us).saturating_sub(now_us))
+            })
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat_timeout() {
+        static HEARTBEATS: [HeartbeatSlot; 2] = [const { HeartbeatSlot::new() }; 2];
+        static FAILSAFE: Failsafe = Failsafe::new(&[], &HEARTBEATS);
+        HEARTBEATS[0].entry.store(5 << 16 | 100);
+        HEARTBEATS[1].entry.store(6 << 16);
+
+        let mut lost = Vec::new();
+        // Monitoring starts with the first heartbeat
+        FAILSAFE.check_heartbeats(0, 0, |id| lost.push(id));
+        assert_eq!(None, FAILSAFE.next_timeout_us(0));
+        FAILSAFE.check_heartbeats(1 << 5, 1000, |id| lost.push(id));
+        assert_eq!(Some(99_000), FAILSAFE.next_timeout_us(2000));
+        FAILSAFE.check_heartbeats(0, 100_999, |id| lost.push(id));
+        assert!(lost.is_empty());
+        FAILSAFE.check_heartbeats(0, 101_000, |id| lost.push(id));
+        assert_eq!(vec![5], lost);
+
+        // A lost heartbeat is reported once, until monitoring restarts with the next heartbeat
+        FAILSAFE.check_heartbeats(0, 300_000, |id| lost.push(id));
+        assert_eq!(vec![5], lost);
+        assert_eq!(None, FAILSAFE.next_timeout_us(300_000));
+    }
+}
@@ -164,43 +164,165 @@
 //! }
 //! ```
 //!
+//! `process` returns a [`ProcessResult`], whose `next_action_us` field gives the time until the
+//! node next has scheduled work to do, e.g. sending a heartbeat. An application can use it to
+//! sleep for exactly that long, rather than waking up at a fixed interval. `process` must still be
+//! called promptly when the application changes objects mapped to event driven TPDOs.
+//!
 //! ## Register callbacks
 //!
 //! The application can register callbacks for persistently storing data, or
 //! notifying the processing task. See examples for more info.
 //!
+//! Received PDO values are stored in the mapped objects, but an application can also register a
+//! callback for each RPDO with [`Node::register_rpdo_callback`], which is called from `process`
+//! with the unpacked values, so that received commands can be acted on immediately.
+//!
+//! ## Application watchdog
+//!
+//! On embedded targets, a hung application task can leave outputs in an unsafe state. With
+//! [`Node::set_watchdog`], the application must call [`Node::feed_watchdog`] regularly, and a
+//! callback registered with [`Node::register_watchdog_callback`] is called if it stops feeding, or
+//! stops calling `process`. See the [watchdog] module for more info.
+//!
+//! ## Debug log
+//!
+//! When the device config has a `[debug_log]` section, the node provides a text log in object
+//! 0x5004. Messages added with [`Node::log_str`] can be read over the bus, e.g. with
+//! `zencan-cli log tail <node>`, which allows a device without a debug UART to report human
+//! readable diagnostics. The number of messages per second is limited by the config, and messages
+//! over the limit are discarded. See [`DebugLog`] for more info.
+//!
+//! ## Event log
+//!
+//! When the device config has an `[event_log]` section, the node provides a log of application
+//! events in object 0x5008. Events added with [`Node::log_event`], each a code and 4 bytes of data,
+//! are stamped with the time and a sequence number, so that a client can fetch only the events it
+//! has not yet seen. The log can optionally be persisted with the other saved objects, for use as
+//! an audit trail. See [`EventLog`] for more info.
+//!
+//! ## Error history
+//!
+//! When the device config sets `error_history`, the node records the most recent EMCY errors it
+//! sends in object 0x1003, which can be read with e.g. `zencan-cli errors <node>`. The depth of the
+//! history is a const generic of the [NodeState], so it takes no RAM unless enabled. See the
+//! [error_history] module for more info.
+//!
+//! ## NMT protection
+//!
+//! When the device config has an `[nmt_protection]` section, NMT commands from the bus which it
+//! lists, e.g. stop and reset, are ignored unless the node is in a state which allows them, or they
+//! have been unlocked via object 0x500E. Each ignored command is reported with an EMCY. See the
+//! [nmt_protection] module for more info.
+//!
+//! ## PDO statistics
+//!
+//! When the device config sets `statistics` in the `[pdos]` section, the node counts each TPDO sent
+//! and each RPDO received, and records when each was last seen, in objects 0x500A-0x500D. This
+//! shows whether a PDO mapping is firing without logging the bus. See [`PdoStatistics`] for more
+//! info.
+//!
+//! ## Loopback self-test
+//!
+//! When the device config sets `loopback_test`, writing 1 to object 0x5009sub1 makes the node send
+//! a test frame and check that it is received back, e.g. with `zencan-cli selftest <node>`. This
+//! checks the transceiver and bus wiring, but requires a transport which delivers the node's own
+//! frames back to its [`NodeMbox`]. See [`LoopbackTest`] for more info.
+//!
+//! ## Failsafe outputs
+//!
+//! An output device can register a [`Failsafe`], which sets its outputs to the CiA 401 error values
+//! configured in its error mode and error value objects when the controlling master is lost: when
+//! a heartbeat configured in object 0x1016 times out, or when the node leaves the Operational
+//! state. See the [failsafe] module for more info.
+//!
+//! ## Logging with defmt
+//!
+//! With the `defmt` feature, log messages are emitted via defmt instead of the `log` crate, and
+//! the public types of zencan-node and zencan-common -- messages, abort codes, node IDs, object
+//! metadata and node events -- implement `defmt::Format`, so they can be passed directly to defmt
+//! logging macros. The format strings are stored in the host side ELF, not in flash, so the cost
+//! of the feature is mostly the code to encode the types which the application actually logs.
+//! Types which are never logged are removed by the linker. With the feature disabled, nothing
+//! is compiled in.
+//!
 #![cfg_attr(all(not(test), not(feature = "std")), no_std)]
 #![warn(missing_docs, missing_debug_implementations)]
 #![allow(clippy::comparison_chain)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+mod accept_filter;
+mod access_trace;
 mod bootloader;
+mod buffer_cell;
+mod clock;
+pub mod cob_id;
+mod debug_log;
+#[cfg(feature = "std")]
+mod dual_bus;
+mod emcy;
+pub mod error_history;
+mod event_log;
+pub mod failsafe;
+mod loopback_test;
 mod lss_slave;
+mod msg_queue;
+#[cfg(feature = "std")]
+mod multi_node;
+pub mod nmt_protection;
+mod nmt_timing;
 mod node;
 mod node_mbox;
 mod node_state;
 pub mod object_dict;
 pub mod pdo;
+mod pdo_statistics;
 mod persist;
 mod sdo_server;
+pub mod sdo_status;
+mod statistics;
 pub mod storage;
+mod tx_order;
+mod tx_queue;
+pub mod var_pool;
+pub mod watchdog;
 
 // Re-export proc macros
 pub use zencan_macro::build_object_dict;
+pub use zencan_macro::CanOpenObject;
 
 // Re-export types used by generated code
 pub use critical_section;
 pub use zencan_common as common;
 
+pub use accept_filter::{reduce_filters, AcceptFilter};
+pub use access_trace::AccessTrace;
 pub use bootloader::{BootloaderInfo, BootloaderSection, BootloaderSectionCallbacks};
+pub use buffer_cell::{BufferCell, BufferRefMut};
 #[cfg(feature = "socketcan")]
 #[cfg_attr(docsrs, doc(cfg(feature = "socketcan")))]
-pub use common::open_socketcan;
-pub use node::Node;
-pub use node_mbox::NodeMbox;
-pub use node_state::{NodeState, NodeStateAccess};
+pub use common::{open_socketcan, SocketCanTransport};
+pub use debug_log::DebugLog;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub use dual_bus::DualBusRunner;
+pub use error_history::{ErrorHistory, ErrorHistoryObject};
+pub use event_log::EventLog;
+pub use failsafe::Failsafe;
+pub use loopback_test::LoopbackTest;
+pub use lss_slave::LssAssignment;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub use multi_node::MultiNodeRunner;
+pub use nmt_protection::NmtProtection;
+pub use node::{Node, ProcessResult};
+pub use node_mbox::{BusInterface, NodeMbox};
+pub use node_state::{NodeSnapshot, NodeState, NodeStateAccess};
+pub use pdo_statistics::{PdoCounter, PdoStatistics, PdoStatisticsObject};
 pub use persist::restore_stored_objects;
 pub use sdo_server::SDO_BUFFER_SIZE;
+pub use tx_queue::{TxOverflowPolicy, TxSlot};
+pub use watchdog::Watchdog;
 
 /// Include the code generated for the object dict in the build script.
 #[macro_export]
@@ -0,0 +1,296 @@
+//! Loopback self-test (object 0x5009)
+//!
+//! When a device config sets `loopback_test`, zencan-build creates a [`LoopbackTest`], which lets
+//! field service check the transceiver and bus wiring of a node, e.g. with `selftest` in
+//! zencan-cli. Writing 1 to sub 1 starts a test: on the next call to
+//! [`Node::process`](crate::Node::process), the node transmits a test frame, and it then waits up
+//! to [`ECHO_TIMEOUT_US`] for the frame to be received back through its [`NodeMbox`]. See
+//! [`zencan_common::loopback_test`] for the test frame, and for what is required of the transport.
+//!
+//! | Sub Object | Type | Description |
+//! | ---------- | ---- | ----------- |
+//! | 0          | u8   | Max sub index - always 2 |
+//! | 1          | u8   | Write 1 to start a test. Reads 1 while a test is running |
+//! | 2          | u8   | The [`LoopbackStatus`] of the last test |
+//!
+//! A test frame received from another node running its own test is ignored.
+
+use zencan_common::{
+    loopback_test::{test_frame_data, LoopbackStatus, TEST_FRAME_ID},
+    messages::CanMessage,
+    objects::{ObjectCode, SubInfo},
+    sdo::AbortCode,
+    AtomicCell,
+};
+
+use crate::{
+    object_dict::{read_le_bytes, ObjectAccess},
+    NodeMbox,
+};
+
+/// How long the node waits for the test frame to be received back, in microseconds
+pub const ECHO_TIMEOUT_US: u64 = 100_000;
+
+#[derive(Clone, Copy, Debug)]
+enum TestState {
+    /// No test is running, and the last one finished with the status
+    Idle(LoopbackStatus),
+    /// A test has been started, and the frame will be sent on the next process call
+    Requested,
+    /// The frame has been sent, and the node is waiting for it to be received
+    Waiting { deadline_us: u64, data: [u8; 8] },
+}
+
+/// The loopback self-test, implementing object 0x5009
+///
+/// This is instantiated by zencan-build, and registered on the node state with
+/// [`NodeState::with_loopback_test`](crate::NodeState::with_loopback_test).
+pub struct LoopbackTest {
+    state: AtomicCell<TestState>,
+    seq: AtomicCell<u8>,
+}
+
+impl core::fmt::Debug for LoopbackTest {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LoopbackTest")
+            .field("status", &self.status())
+            .finish()
+    }
+}
+
+impl Default for LoopbackTest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoopbackTest {
+    /// Create a new loopback test, which has not run
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicCell::new(TestState::Idle(LoopbackStatus::NotRun)),
+            seq: AtomicCell::new(0),
+        }
+    }
+
+    /// Start a test
+    ///
+    /// Returns false if a test is already running.
+    pub fn start(&self) -> bool {
+        self.state
+            .fetch_update(|state| match state {
+                TestState::Idle(_) => Some(TestState::Requested),
+                _ => None,
+            })
+            .is_ok()
+    }
+
+    /// Get the status of the last test
+    pub fn status(&self) -> LoopbackStatus {
+        match self.state.load() {
+            TestState::Idle(status) => status,
+            _ => LoopbackStatus::Running,
+        }
+    }
+
+    /// Advance the test
+    ///
+    /// Returns the test frame when it should be sent.
+    pub(crate) fn process(&self, node_id: u8, now_us: u64, mbox: &NodeMbox) -> Option<CanMessage> {
+        match self.state.load() {
+            TestState::Idle(_) => None,
+            TestState::Requested => {
+                let seq = self.seq.load().wrapping_add(1);
+                self.seq.store(seq);
+                let data = test_frame_data(node_id, seq);
+                mbox.arm_loopback();
+                self.state.store(TestState::Waiting {
+                    deadline_us: now_us + ECHO_TIMEOUT_US,
+                    data,
+                });
+                Some(CanMessage::new(TEST_FRAME_ID, &data))
+            }
+            TestState::Waiting { deadline_us, data } => {
+                if let Some(msg) = mbox.take_loopback_frame() {
+                    let received = msg.data();
+                    if received.len() >= 2 && received[..2] != data[..2] {
+                        // The frame of another node's test
+                        mbox.arm_loopback();
+                    } else {
+                        let status = if received == data {
+                            LoopbackStatus::Passed
+                        } else {
+                            LoopbackStatus::Corrupted
+                        };
+                        self.state.store(TestState::Idle(status));
+                        return None;
+                    }
+                }
+                if now_us >= deadline_us {
+                    mbox.disarm_loopback();
+                    self.state.store(TestState::Idle(LoopbackStatus::NoEcho));
+                }
+                None
+            }
+        }
+    }
+
+    /// Get the time until the test needs processing, if one is running
+    pub(crate) fn next_action_us(&self, now_us: u64) -> Option<u64> {
+        match self.state.load() {
+            TestState::Idle(_) => None,
+            TestState::Requested => Some(0),
+            TestState::Waiting { deadline_us, .. } => Some(deadline_us.saturating_sub(now_us)),
+        }
+    }
+}
+
+impl ObjectAccess for LoopbackTest {
+    fn read(&self, sub: u8, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        match sub {
+            0 => Ok(read_le_bytes(&[2], offset, buf)),
+            1 => {
+                let running = self.status() == LoopbackStatus::Running;
+                Ok(read_le_bytes(&[running as u8], offset, buf))
+            }
+            2 => Ok(read_le_bytes(&[self.status() as u8], offset, buf)),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+
+    fn read_size(&self, sub: u8) -> Result<usize, AbortCode> {
+        match sub {
+            0..=2 => Ok(1),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+
+    fn write(&self, sub: u8, data: &[u8]) -> Result<(), AbortCode> {
+        match sub {
+            0 | 2 => Err(AbortCode::ReadOnly),
+            1 => match data {
+                [1] if self.start() => Ok(()),
+                [1] => Err(AbortCode::CantStoreDeviceState),
+                [_] => Err(AbortCode::InvalidValue),
+                [] => Err(AbortCode::DataTypeMismatchLengthLow),
+                _ => Err(AbortCode::DataTypeMismatchLengthHigh),
+            },
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Record
+    }
+
+    fn sub_info(&self, sub: u8) -> Result<SubInfo, AbortCode> {
+        match sub {
+            0 => Ok(SubInfo::MAX_SUB_NUMBER),
+            1 => Ok(SubInfo::new_u8().rw_access()),
+            2 => Ok(SubInfo::new_u8()),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BufferCell;
+
+    use super::*;
+
+    fn mbox() -> &'static NodeMbox {
+        let sdo_buffer = Box::leak(Box::new(BufferCell::new([0u8; 64])));
+        let rpdo_queue = Box::leak(Box::new(BufferCell::new([None; 2])));
+        let sdo_queue = Box::leak(Box::new(BufferCell::new([None; 2])));
+        let nmt_queue = Box::leak(Box::new(BufferCell::new([None; 2])));
+        let mbox = Box::leak(Box::new(NodeMbox::new(
+            &[],
+            sdo_buffer,
+            rpdo_queue,
+            sdo_queue,
+            nmt_queue,
+        )));
+        mbox.enable_loopback_test();
+        mbox
+    }
+
+    fn read_u8(test: &LoopbackTest, sub: u8) -> u8 {
+        let mut buf = [0];
+        assert_eq!(Ok(1), test.read(sub, 0, &mut buf));
+        buf[0]
+    }
+
+    #[test]
+    fn test_passed() {
+        let mbox = mbox();
+        let test = LoopbackTest::new();
+        assert_eq!(LoopbackStatus::NotRun, test.status());
+        assert_eq!(None, test.process(3, 0, mbox));
+        // Test frames are only consumed while a test is waiting
+        let stray = CanMessage::new(TEST_FRAME_ID, &test_frame_data(3, 1));
+        assert!(mbox.store_message(stray).is_err());
+
+        assert_eq!(Ok(()), test.write(1, &[1]));
+        assert_eq!(1, read_u8(&test, 1));
+        assert_eq!(LoopbackStatus::Running as u8, read_u8(&test, 2));
+        assert_eq!(Err(AbortCode::CantStoreDeviceState), test.write(1, &[1]));
+        assert_eq!(Some(0), test.next_action_us(0));
+
+        let msg = test.process(3, 1000, mbox).unwrap();
+        assert_eq!(TEST_FRAME_ID, msg.id());
+        assert_eq!(&test_frame_data(3, 1), msg.data());
+        assert_eq!(Some(ECHO_TIMEOUT_US - 1000), test.next_action_us(2000));
+
+        // A test frame from another node is ignored
+        let other = CanMessage::new(TEST_FRAME_ID, &test_frame_data(4, 1));
+        assert!(mbox.store_message(other).is_ok());
+        assert_eq!(None, test.process(3, 2000, mbox));
+        assert_eq!(LoopbackStatus::Running, test.status());
+
+        assert!(mbox.store_message(msg).is_ok());
+        assert_eq!(None, test.process(3, 3000, mbox));
+        assert_eq!(LoopbackStatus::Passed, test.status());
+        assert_eq!(0, read_u8(&test, 1));
+        assert_eq!(None, test.next_action_us(3000));
+    }
+
+    #[test]
+    fn test_failures() {
+        let mbox = mbox();
+        let test = LoopbackTest::new();
+
+        assert!(test.start());
+        test.process(3, 0, mbox).unwrap();
+        assert_eq!(None, test.process(3, ECHO_TIMEOUT_US - 1, mbox));
+        assert_eq!(LoopbackStatus::Running, test.status());
+        assert_eq!(None, test.process(3, ECHO_TIMEOUT_US, mbox));
+        assert_eq!(LoopbackStatus::NoEcho, test.status());
+
+        // A second test uses a new sequence number, and a corrupted frame fails it
+        assert!(test.start());
+        let msg = test.process(3, 0, mbox).unwrap();
+        assert_eq!(&test_frame_data(3, 2), msg.data());
+        let mut data = test_frame_data(3, 2);
+        data[7] ^= 0x10;
+        assert!(mbox
+            .store_message(CanMessage::new(TEST_FRAME_ID, &data))
+            .is_ok());
+        test.process(3, 1000, mbox);
+        assert_eq!(LoopbackStatus::Corrupted, test.status());
+    }
+
+    #[test]
+    fn test_object_access() {
+        let test = LoopbackTest::new();
+        assert_eq!(2, read_u8(&test, 0));
+        assert_eq!(Err(AbortCode::InvalidValue), test.write(1, &[2]));
+        assert_eq!(
+            Err(AbortCode::DataTypeMismatchLengthHigh),
+            test.write(1, &[1, 0])
+        );
+        assert_eq!(Err(AbortCode::ReadOnly), test.write(2, &[0]));
+        assert_eq!(Err(AbortCode::NoSuchSubIndex), test.read(3, 0, &mut [0]));
+        assert_eq!(LoopbackStatus::NotRun, test.status());
+    }
+}
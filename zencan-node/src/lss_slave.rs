@@ -30,8 +30,49 @@ pub enum LssEvent {
         /// The new node ID
         node_id: NodeId,
     },
+    /// A new bit timing has been configured, to be used after it is activated
+    ConfigureBitTiming {
+        /// The table used for baud rate lookup
+        table: u8,
+        /// The index into the table
+        index: u8,
+    },
 }
 
+/// A configuration change made to the node by an LSS master
+///
+/// These are reported to the application through the callback registered with
+/// [`Node::register_lss_assignment_callback`](crate::Node::register_lss_assignment_callback), so
+/// that the application can persist them or apply a new bit rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LssAssignment {
+    /// The node ID was changed
+    NodeId(NodeId),
+    /// A new bit timing was configured. It should not be used until it is activated.
+    BitTiming {
+        /// The bit timing table. Only the standard table (0) is supported.
+        table: u8,
+        /// The index of the bit rate in the table
+        index: u8,
+    },
+    /// The master requested activation of the configured bit timing
+    ///
+    /// The application should stop transmitting, wait `delay` ms, switch to the new bit rate, and
+    /// then wait another `delay` ms before transmitting again.
+    ActivateBitTiming {
+        /// The bit timing table
+        table: u8,
+        /// The index of the bit rate in the table
+        index: u8,
+        /// The switch delay in milliseconds
+        delay: u16,
+    },
+}
+
+/// The highest index in the standard CiA bit timing table (10kbit/s)
+const MAX_STANDARD_BIT_TIMING_INDEX: u8 = 8;
+
 /// A Sync structure providing message handling for message receive thread
 pub(crate) struct LssReceiver {
     selected_identity: AtomicCell<LssIdentity>,
@@ -62,31 +103,38 @@ impl LssReceiver {
     ///
     /// Returns true if the request requires further processing in the node 'process' thread
     pub fn handle_req(&self, req: LssRequest) -> bool {
-        // Certain messages we store here for fast handling. Others, are stored to be handled during
-        // process call
+        if self.handle_switch_state(req) {
+            false
+        } else {
+            self.rx_req.store(Some(req));
+            true
+        }
+    }
+
+    /// Handle the switch state requests which are stored here for fast handling
+    ///
+    /// Returns true if the request was consumed. Others must be handled during the process call.
+    pub fn handle_switch_state(&self, req: LssRequest) -> bool {
         match req {
             LssRequest::SwitchStateProduct { product_code } => {
                 let mut selected_identity = self.selected_identity.load();
                 selected_identity.product_code = product_code;
                 self.selected_identity.store(selected_identity);
-                false
+                true
             }
             LssRequest::SwitchStateVendor { vendor_id } => {
                 let mut selected_identity = self.selected_identity.load();
                 selected_identity.vendor_id = vendor_id;
                 self.selected_identity.store(selected_identity);
-                false
+                true
             }
             LssRequest::SwitchStateRevision { revision } => {
                 let mut selected_identity = self.selected_identity.load();
                 selected_identity.revision = revision;
                 self.selected_identity.store(selected_identity);
-                false
-            }
-            _ => {
-                self.rx_req.store(Some(req));
                 true
             }
+            _ => false,
         }
     }
 }
@@ -98,6 +146,8 @@ pub struct LssConfig {
     pub node_id: NodeId,
     /// Indicates the device supports storing of config
     pub store_supported: bool,
+    /// Indicates the device supports changing the bit timing
+    pub bit_timing_supported: bool,
 }
 
 /// Implements LSS slave functionality
@@ -109,6 +159,9 @@ pub(crate) struct LssSlave {
     /// The identity selected by LSS master for configuration
     pending_node_id: NodeId,
     store_config_flag: bool,
+    /// The bit timing (table, index) configured by the master, waiting to be activated
+    pending_bit_timing: Option<(u8, u8)>,
+    bit_timing_event: Option<LssEvent>,
 }
 
 impl LssSlave {
@@ -124,6 +177,8 @@ impl LssSlave {
             fast_scan_sub: 0,
             pending_node_id,
             store_config_flag: false,
+            pending_bit_timing: None,
+            bit_timing_event: None,
         }
     }
 
@@ -147,7 +202,7 @@ impl LssSlave {
             self.store_config_flag = false;
             Some(LssEvent::StoreConfiguration)
         } else {
-            None
+            self.bit_timing_event.take()
         }
     }
 
@@ -184,11 +239,22 @@ impl LssSlave {
                 }
             }
 
-            LssRequest::ConfigureBitTiming { table: _, index: _ } => {
-                // Configuring bit timing is not supported
+            LssRequest::ConfigureBitTiming { table, index } => {
                 if self.state == LssState::Configuring {
+                    // Only the standard table is supported, and only when the application can
+                    // apply a new bit rate
+                    let error = if self.config.bit_timing_supported
+                        && table == 0
+                        && index <= MAX_STANDARD_BIT_TIMING_INDEX
+                    {
+                        self.pending_bit_timing = Some((table, index));
+                        self.bit_timing_event = Some(LssEvent::ConfigureBitTiming { table, index });
+                        0
+                    } else {
+                        1
+                    };
                     Ok(Some(LssResponse::ConfigureBitTimingAck {
-                        error: 1,
+                        error,
                         spec_error: 0,
                     }))
                 } else {
@@ -196,6 +262,19 @@ impl LssSlave {
                 }
             }
 
+            LssRequest::ActivateBitTiming { delay } => {
+                // Activation is a broadcast, and is acted on by every node which has a configured
+                // bit timing. There is no response.
+                if let Some((table, index)) = self.pending_bit_timing.take() {
+                    self.bit_timing_event = Some(LssEvent::ActivateBitTiming {
+                        table,
+                        index,
+                        delay,
+                    });
+                }
+                Ok(None)
+            }
+
             LssRequest::StoreConfiguration => {
                 if self.state == LssState::Configuring {
                     if self.config.store_supported {
@@ -218,8 +297,13 @@ impl LssSlave {
             // Other switch state commands (vendor, product, revision) are handled directly in the
             // receiver
             LssRequest::SwitchStateSerial { serial } => {
+                // Clear the selection so that a partial sequence can not be combined with values
+                // left over from an earlier one
+                let mut selected_identity = receiver.selected_identity.load();
+                receiver
+                    .selected_identity
+                    .store(LssIdentity::new(0, 0, 0, 0));
                 if self.state == LssState::Waiting {
-                    let mut selected_identity = receiver.selected_identity.load();
                     selected_identity.serial = serial;
                     if self.config.identity == selected_identity {
                         // If the identity matches, we are selected and enter the configuration state
@@ -336,6 +420,7 @@ mod tests {
             node_id: NodeId::Unconfigured,
             identity: IDENTITY,
             store_supported: true,
+            bit_timing_supported: false,
         });
 
         let rx = LssReceiver::new();
@@ -386,6 +471,7 @@ mod tests {
             node_id: NodeId::Unconfigured,
             identity: IDENTITY,
             store_supported: true,
+            bit_timing_supported: false,
         });
 
         let mut id = [0, 0, 0, 0];
@@ -453,6 +539,7 @@ mod tests {
             node_id: NodeId::Unconfigured,
             identity: IDENTITY,
             store_supported: true,
+            bit_timing_supported: false,
         });
 
         let rx = LssReceiver::new();
@@ -486,6 +573,7 @@ mod tests {
             node_id: NodeId::new(10).unwrap(),
             identity: IDENTITY,
             store_supported: true,
+            bit_timing_supported: false,
         });
         // Event should be cleared
         assert_eq!(None, slave.pending_event());
@@ -523,6 +611,7 @@ mod tests {
             node_id: NodeId::Unconfigured,
             identity: IDENTITY,
             store_supported: false,
+            bit_timing_supported: false,
         });
 
         let rx = LssReceiver::new();
@@ -546,6 +635,7 @@ mod tests {
             node_id: NodeId::new(10).unwrap(),
             identity: IDENTITY,
             store_supported: false,
+            bit_timing_supported: false,
         });
 
         rx.rx_req.store(Some(LssRequest::StoreConfiguration));
@@ -560,4 +650,126 @@ mod tests {
         // No events
         assert_eq!(None, slave.pending_event());
     }
+
+    #[test]
+    fn test_bit_timing_configuration() {
+        const IDENTITY: LssIdentity = LssIdentity {
+            vendor_id: 0,
+            product_code: 1,
+            revision: 2,
+            serial: 3,
+        };
+
+        let mut slave = LssSlave::new(LssConfig {
+            node_id: NodeId::Unconfigured,
+            identity: IDENTITY,
+            store_supported: false,
+            bit_timing_supported: true,
+        });
+        let rx = LssReceiver::new();
+
+        rx.rx_req
+            .store(Some(LssRequest::SwitchModeGlobal { mode: 1 }));
+        let _ = slave.process(&rx).unwrap();
+
+        // User defined tables are not supported
+        rx.rx_req.store(Some(LssRequest::ConfigureBitTiming {
+            table: 128,
+            index: 0,
+        }));
+        assert_eq!(
+            Ok(Some(LssResponse::ConfigureBitTimingAck {
+                error: 1,
+                spec_error: 0
+            })),
+            slave.process(&rx)
+        );
+        assert_eq!(None, slave.pending_event());
+
+        rx.rx_req
+            .store(Some(LssRequest::ConfigureBitTiming { table: 0, index: 3 }));
+        assert_eq!(
+            Ok(Some(LssResponse::ConfigureBitTimingAck {
+                error: 0,
+                spec_error: 0
+            })),
+            slave.process(&rx)
+        );
+        assert_eq!(
+            Some(LssEvent::ConfigureBitTiming { table: 0, index: 3 }),
+            slave.pending_event()
+        );
+
+        rx.rx_req
+            .store(Some(LssRequest::ActivateBitTiming { delay: 100 }));
+        assert_eq!(Ok(None), slave.process(&rx));
+        assert_eq!(
+            Some(LssEvent::ActivateBitTiming {
+                table: 0,
+                index: 3,
+                delay: 100
+            }),
+            slave.pending_event()
+        );
+        assert_eq!(None, slave.pending_event());
+    }
+
+    #[test]
+    fn test_switch_selective_requires_full_sequence() {
+        const IDENTITY: LssIdentity = LssIdentity {
+            vendor_id: 1,
+            product_code: 2,
+            revision: 3,
+            serial: 4,
+        };
+
+        // An unconfigured node still responds to selective switching
+        let mut slave = LssSlave::new(LssConfig {
+            node_id: NodeId::Unconfigured,
+            identity: IDENTITY,
+            store_supported: false,
+            bit_timing_supported: false,
+        });
+        let rx = LssReceiver::new();
+
+        let send = |slave: &mut LssSlave, req: LssRequest| {
+            if rx.handle_req(req) {
+                slave.process(&rx).unwrap()
+            } else {
+                None
+            }
+        };
+
+        send(&mut slave, LssRequest::SwitchStateVendor { vendor_id: 1 });
+        send(
+            &mut slave,
+            LssRequest::SwitchStateProduct { product_code: 2 },
+        );
+        send(&mut slave, LssRequest::SwitchStateRevision { revision: 3 });
+        // Wrong serial, so no response
+        assert_eq!(
+            None,
+            send(&mut slave, LssRequest::SwitchStateSerial { serial: 5 })
+        );
+        // Serial alone should not match using the previous partial selection
+        assert_eq!(
+            None,
+            send(&mut slave, LssRequest::SwitchStateSerial { serial: 4 })
+        );
+
+        send(&mut slave, LssRequest::SwitchStateVendor { vendor_id: 1 });
+        send(
+            &mut slave,
+            LssRequest::SwitchStateProduct { product_code: 2 },
+        );
+        send(&mut slave, LssRequest::SwitchStateRevision { revision: 3 });
+        assert_eq!(
+            Some(LssResponse::SwitchStateResponse),
+            send(&mut slave, LssRequest::SwitchStateSerial { serial: 4 })
+        );
+        assert_eq!(
+            Some(LssResponse::InquireNodeIdAck { node_id: 255 }),
+            send(&mut slave, LssRequest::InquireNodeId)
+        );
+    }
 }
@@ -0,0 +1,129 @@
+//! A fixed capacity FIFO of CAN messages, shared between a receiving IRQ and the process thread
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use zencan_common::messages::CanMessage;
+
+use crate::BufferCell;
+
+struct QueueInner {
+    buffer: &'static BufferCell<[Option<CanMessage>]>,
+    head: usize,
+    len: usize,
+    dropped: u32,
+}
+
+/// A queue of received CAN messages waiting for processing
+///
+/// Storage is provided by the caller as a static slice, so that its size can be chosen at build
+/// time. When the queue is full, the oldest message is discarded to make room for the new one, so
+/// a depth of one behaves as a single "latest value" mailbox.
+pub(crate) struct MsgQueue {
+    inner: Mutex<RefCell<QueueInner>>,
+}
+
+impl MsgQueue {
+    /// Create a new queue using `buffer` for storage
+    ///
+    /// The capacity of the queue is the length of the buffer.
+    pub const fn new(buffer: &'static BufferCell<[Option<CanMessage>]>) -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(QueueInner {
+                buffer,
+                head: 0,
+                len: 0,
+                dropped: 0,
+            })),
+        }
+    }
+
+    /// Add a message to the back of the queue
+    ///
+    /// Returns false if an older message had to be dropped to make room
+    pub fn push(&self, msg: CanMessage) -> bool {
+        critical_section::with(|cs| {
+            let mut q = self.inner.borrow_ref_mut(cs);
+            let mut buffer = q.buffer.borrow_mut();
+            let capacity = buffer.len();
+            if capacity == 0 {
+                q.dropped = q.dropped.wrapping_add(1);
+                return false;
+            }
+            let mut no_drop = true;
+            if q.len == capacity {
+                q.head = (q.head + 1) % capacity;
+                q.len -= 1;
+                q.dropped = q.dropped.wrapping_add(1);
+                no_drop = false;
+            }
+            let pos = (q.head + q.len) % capacity;
+            buffer[pos] = Some(msg);
+            q.len += 1;
+            no_drop
+        })
+    }
+
+    /// Remove the message at the front of the queue
+    pub fn pop(&self) -> Option<CanMessage> {
+        critical_section::with(|cs| {
+            let mut q = self.inner.borrow_ref_mut(cs);
+            if q.len == 0 {
+                return None;
+            }
+            let mut buffer = q.buffer.borrow_mut();
+            let head = q.head;
+            let msg = buffer[head].take();
+            q.head = (head + 1) % buffer.len();
+            q.len -= 1;
+            msg
+        })
+    }
+
+    /// Returns true if there are no messages in the queue
+    pub fn is_empty(&self) -> bool {
+        critical_section::with(|cs| self.inner.borrow_ref(cs).len == 0)
+    }
+
+    /// The number of messages which have been discarded because the queue was full
+    pub fn dropped(&self) -> u32 {
+        critical_section::with(|cs| self.inner.borrow_ref(cs).dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zencan_common::messages::CanId;
+
+    fn leak_buffer<const N: usize>() -> &'static BufferCell<[Option<CanMessage>]> {
+        Box::leak(Box::new(BufferCell::new([None; N])))
+    }
+
+    #[test]
+    fn test_fifo_order() {
+        let q = MsgQueue::new(leak_buffer::<3>());
+        assert!(q.is_empty());
+        for i in 0..3u16 {
+            assert!(q.push(CanMessage::new(CanId::std(i), &[])));
+        }
+        assert_eq!(Some(CanId::std(0)), q.pop().map(|m| m.id()));
+        assert!(q.push(CanMessage::new(CanId::std(3), &[])));
+        assert_eq!(Some(CanId::std(1)), q.pop().map(|m| m.id()));
+        assert_eq!(Some(CanId::std(2)), q.pop().map(|m| m.id()));
+        assert_eq!(Some(CanId::std(3)), q.pop().map(|m| m.id()));
+        assert_eq!(None, q.pop());
+        assert_eq!(0, q.dropped());
+    }
+
+    #[test]
+    fn test_overflow_drops_oldest() {
+        let q = MsgQueue::new(leak_buffer::<2>());
+        q.push(CanMessage::new(CanId::std(1), &[]));
+        q.push(CanMessage::new(CanId::std(2), &[]));
+        assert!(!q.push(CanMessage::new(CanId::std(3), &[])));
+        assert_eq!(1, q.dropped());
+        assert_eq!(Some(CanId::std(2)), q.pop().map(|m| m.id()));
+        assert_eq!(Some(CanId::std(3)), q.pop().map(|m| m.id()));
+        assert!(q.is_empty());
+    }
+}
@@ -0,0 +1,170 @@
+//! Hosting several nodes on a single CAN interface
+use core::{future::Future, pin::pin, time::Duration};
+use std::{time::Instant, vec::Vec};
+
+use defmt_or_log::warn;
+use futures::future::{select, Either};
+use zencan_common::{
+    traits::{AsyncCanReceiver, AsyncCanSender},
+    CanMessage,
+};
+
+use crate::{Node, ProcessResult};
+
+/// Runs several [`Node`]s over a single CAN transport
+///
+/// This allows one process to emulate an entire network of devices, e.g. for hardware-in-the-loop
+/// testing. Each node has its own node ID, object dictionary and mailbox, as created by separate
+/// `include_modules!` invocations.
+///
+/// Received frames are offered to the mailbox of every node, and each mailbox accepts the frames
+/// whose COB-ID it handles. Broadcast frames, such as NMT and SYNC, are delivered to all nodes.
+/// Frames transmitted by one of the nodes are also delivered to the others, as they would be on a
+/// physical bus.
+#[allow(missing_debug_implementations)]
+pub struct MultiNodeRunner {
+    nodes: Vec<Node>,
+    epoch: Instant,
+}
+
+impl Default for MultiNodeRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MultiNodeRunner {
+    /// Create a runner with no nodes
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Add a node to the runner
+    ///
+    /// Every node must use its own mailbox and object dictionary.
+    pub fn add_node(&mut self, node: Node) {
+        self.nodes.push(node);
+    }
+
+    /// Get the nodes hosted by the runner, in the order they were added
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Get mutable access to the nodes hosted by the runner
+    pub fn nodes_mut(&mut self) -> &mut [Node] {
+        &mut self.nodes
+    }
+
+    /// Find a hosted node by its current node ID
+    pub fn node_by_id(&mut self, node_id: u8) -> Option<&mut Node> {
+        self.nodes.iter_mut().find(|n| n.node_id() == node_id)
+    }
+
+    /// Deliver a received frame to the nodes
+    ///
+    /// Returns true if at least one node accepted the frame
+    pub fn dispatch(&self, msg: CanMessage) -> bool {
+        self.dispatch_except(msg, None)
+    }
+
+    fn dispatch_except(&self, msg: CanMessage, sender: Option<usize>) -> bool {
+        let mut accepted = false;
+        for (i, node) in self.nodes.iter().enumerate() {
+            if Some(i) != sender && node.mbox().store_message(msg).is_ok() {
+                accepted = true;
+            }
+        }
+        accepted
+    }
+
+    /// Run the process method of every node
+    ///
+    /// Messages transmitted by the nodes are passed to `send_cb`, and are also delivered to the other
+    /// hosted nodes.
+    ///
+    /// The returned result reports whether any node updated objects, and the earliest next action
+    /// of all the nodes.
+    pub fn process(&mut self, now_us: u64, send_cb: &mut dyn FnMut(CanMessage)) -> ProcessResult {
+        let mut combined = ProcessResult {
+            objects_updated: false,
+            next_action_us: None,
+        };
+        let mut to_send = Vec::new();
+        for i in 0..self.nodes.len() {
+            let result = self.nodes[i].process(now_us, &mut |msg| to_send.push(msg));
+            combined.objects_updated |= result.objects_updated;
+            combined.next_action_us = match (combined.next_action_us, result.next_action_us) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            for msg in to_send.drain(..) {
+                self.dispatch_except(msg, Some(i));
+                send_cb(msg);
+            }
+        }
+        combined
+    }
+
+    /// Run the nodes on a CAN transport
+    ///
+    /// Received frames are dispatched to the nodes, and the nodes are processed after each received
+    /// frame, when one of the nodes has a scheduled action due, or at the latest when `period` has
+    /// elapsed without any frames. `sleep` must return a future which
+    /// completes after the given duration, e.g. `tokio::time::sleep`.
+    ///
+    /// This function does not return.
+    pub async fn run<S, R, F, Fut>(
+        &mut self,
+        sender: &mut S,
+        receiver: &mut R,
+        period: Duration,
+        mut sleep: F,
+    ) where
+        S: AsyncCanSender,
+        R: AsyncCanReceiver,
+        F: FnMut(Duration) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        loop {
+            let now_us = self.epoch.elapsed().as_micros() as u64;
+            let mut tx_messages = Vec::new();
+            let result = self.process(now_us, &mut |msg| tx_messages.push(msg));
+            for msg in tx_messages {
+                if sender.send(msg).await.is_err() {
+                    warn!("Failed to send CAN message");
+                }
+            }
+
+            let received = {
+                let recv = pin!(receiver.recv());
+                let wait = result
+                    .next_action_us
+                    .map(Duration::from_micros)
+                    .map_or(period, |t| t.min(period));
+                let timeout = pin!(sleep(wait));
+                match select(recv, timeout).await {
+                    Either::Left((result, _)) => Some(result),
+                    Either::Right(_) => None,
+                }
+            };
+            match received {
+                Some(Ok(msg)) => {
+                    self.dispatch(msg);
+                    // Drain any other frames which are already waiting
+                    while let Some(msg) = receiver.try_recv() {
+                        self.dispatch(msg);
+                    }
+                }
+                Some(Err(_)) => {
+                    warn!("Error receiving CAN message");
+                    sleep(period).await;
+                }
+                None => (),
+            }
+        }
+    }
+}
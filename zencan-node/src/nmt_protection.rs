@@ -0,0 +1,257 @@
+//! Protection against remote NMT commands (object 0x500E)
+//!
+//! Some devices, e.g. safety devices, must not be stopped or reset by a stray NMT command from
+//! another device on the bus. When a device config has an `[nmt_protection]` section, zencan-build
+//! creates an [`NmtProtection`], and NMT commands received from the bus which it protects are
+//! ignored, unless:
+//!
+//! - The node is in one of the states in which the commands are allowed, or
+//! - The command has been unlocked by writing [`NMT_UNLOCK_CMD`] to object 0x500E. An unlock
+//!   accepts a single protected command, received within [`UNLOCK_TIMEOUT_US`] of the next call to
+//!   [`Node::process`](crate::Node::process). Reading the object returns 1 while it is unlocked.
+//!
+//! Each ignored command is reported by queuing an EMCY with the device specific error code
+//! [`NMT_REJECTED_EMCY_CODE`]. The first vendor data byte holds the command specifier, and the
+//! second the NMT state of the node.
+//!
+//! Protection only applies to commands received from the bus. State changes requested by the
+//! application, with [`Node::request_state`](crate::Node::request_state), are always accepted.
+
+use zencan_common::{
+    constants::values::NMT_UNLOCK_CMD,
+    messages::{NmtCommandSpecifier, NmtState},
+    objects::{ObjectCode, SubInfo},
+    sdo::AbortCode,
+    AtomicCell,
+};
+
+use crate::object_dict::{read_le_bytes, ObjectAccess};
+
+/// The EMCY error code queued when a protected NMT command is ignored (device specific)
+pub const NMT_REJECTED_EMCY_CODE: u16 = 0xFF01;
+
+/// How long an unlock remains valid, in microseconds
+pub const UNLOCK_TIMEOUT_US: u64 = 10_000_000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum UnlockState {
+    #[default]
+    Locked,
+    /// The unlock has been written, and its timeout starts on the next process call
+    Requested,
+    Unlocked {
+        expires_us: u64,
+    },
+}
+
+const fn command_bit(cmd: NmtCommandSpecifier) -> u8 {
+    match cmd {
+        NmtCommandSpecifier::Start => 1 << 0,
+        NmtCommandSpecifier::Stop => 1 << 1,
+        NmtCommandSpecifier::EnterPreOp => 1 << 2,
+        NmtCommandSpecifier::ResetApp => 1 << 3,
+        NmtCommandSpecifier::ResetComm => 1 << 4,
+    }
+}
+
+const fn state_bit(state: NmtState) -> u8 {
+    match state {
+        NmtState::Bootup => 1 << 0,
+        NmtState::Stopped => 1 << 1,
+        NmtState::Operational => 1 << 2,
+        NmtState::PreOperational => 1 << 3,
+    }
+}
+
+/// The policy for NMT commands received from the bus, implementing object 0x500E
+///
+/// This is instantiated by zencan-build, and registered on the node state with
+/// [`NodeState::with_nmt_protection`](crate::NodeState::with_nmt_protection). See the
+/// [module docs](self) for more info.
+pub struct NmtProtection {
+    commands: u8,
+    allowed_states: u8,
+    unlock: AtomicCell<UnlockState>,
+}
+
+impl core::fmt::Debug for NmtProtection {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("NmtProtection")
+            .field("commands", &self.commands)
+            .field("allowed_states", &self.allowed_states)
+            .field("unlocked", &self.is_unlocked())
+            .finish()
+    }
+}
+
+impl Default for NmtProtection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NmtProtection {
+    /// Create a policy which protects no commands
+    pub const fn new() -> Self {
+        Self {
+            commands: 0,
+            allowed_states: 0,
+            unlock: AtomicCell::new(UnlockState::Locked),
+        }
+    }
+
+    /// Ignore a command, unless it is allowed by the node state or an unlock
+    pub const fn protect(self, cmd: NmtCommandSpecifier) -> Self {
+        Self {
+            commands: self.commands | command_bit(cmd),
+            ..self
+        }
+    }
+
+    /// Accept the protected commands while the node is in `state`
+    pub const fn allow_in(self, state: NmtState) -> Self {
+        Self {
+            allowed_states: self.allowed_states | state_bit(state),
+            ..self
+        }
+    }
+
+    /// Returns true if `cmd` is protected
+    pub fn is_protected(&self, cmd: NmtCommandSpecifier) -> bool {
+        self.commands & command_bit(cmd) != 0
+    }
+
+    /// Accept the next protected command
+    ///
+    /// This has the same effect as writing [`NMT_UNLOCK_CMD`] to object 0x500E.
+    pub fn unlock(&self) {
+        self.unlock.store(UnlockState::Requested);
+    }
+
+    /// Returns true if the next protected command will be accepted
+    pub fn is_unlocked(&self) -> bool {
+        self.unlock.load() != UnlockState::Locked
+    }
+
+    /// Start the timeout of a new unlock, and expire an old one
+    pub(crate) fn tick(&self, now_us: u64) {
+        self.unlock
+            .fetch_update(|state| match state {
+                UnlockState::Requested => Some(UnlockState::Unlocked {
+                    expires_us: now_us + UNLOCK_TIMEOUT_US,
+                }),
+                UnlockState::Unlocked { expires_us } if now_us >= expires_us => {
+                    Some(UnlockState::Locked)
+                }
+                _ => None,
+            })
+            .ok();
+    }
+
+    /// Check whether a command received from the bus should be accepted
+    ///
+    /// An unlock is consumed by the protected command it accepts.
+    pub(crate) fn accept(&self, cmd: NmtCommandSpecifier, state: NmtState) -> bool {
+        if !self.is_protected(cmd) || self.allowed_states & state_bit(state) != 0 {
+            return true;
+        }
+        self.unlock.take() != UnlockState::Locked
+    }
+}
+
+impl ObjectAccess for NmtProtection {
+    fn read(&self, sub: u8, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        Ok(read_le_bytes(
+            &(self.is_unlocked() as u32).to_le_bytes(),
+            offset,
+            buf,
+        ))
+    }
+
+    fn read_size(&self, sub: u8) -> Result<usize, AbortCode> {
+        Ok(self.sub_info(sub)?.size)
+    }
+
+    fn write(&self, sub: u8, data: &[u8]) -> Result<(), AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        let value = match data.len() {
+            4 => u32::from_le_bytes(data.try_into().unwrap()),
+            n if n < 4 => return Err(AbortCode::DataTypeMismatchLengthLow),
+            _ => return Err(AbortCode::DataTypeMismatchLengthHigh),
+        };
+        match value {
+            NMT_UNLOCK_CMD => self.unlock(),
+            0 => self.unlock.store(UnlockState::Locked),
+            _ => return Err(AbortCode::InvalidValue),
+        }
+        Ok(())
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Var
+    }
+
+    fn sub_info(&self, sub: u8) -> Result<SubInfo, AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        Ok(SubInfo::new_u32().rw_access())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn protection() -> NmtProtection {
+        NmtProtection::new()
+            .protect(NmtCommandSpecifier::Stop)
+            .protect(NmtCommandSpecifier::ResetApp)
+            .allow_in(NmtState::PreOperational)
+    }
+
+    #[test]
+    fn test_protected_commands() {
+        let p = protection();
+        assert!(p.accept(NmtCommandSpecifier::Start, NmtState::Operational));
+        assert!(!p.accept(NmtCommandSpecifier::Stop, NmtState::Operational));
+        assert!(!p.accept(NmtCommandSpecifier::ResetApp, NmtState::Stopped));
+        assert!(p.accept(NmtCommandSpecifier::ResetComm, NmtState::Operational));
+        // Allowed in PreOperational
+        assert!(p.accept(NmtCommandSpecifier::Stop, NmtState::PreOperational));
+    }
+
+    #[test]
+    fn test_unlock() {
+        let p = protection();
+        assert_eq!(Err(AbortCode::InvalidValue), p.write(0, &[1, 0, 0, 0]));
+        assert_eq!(Ok(()), p.write(0, &NMT_UNLOCK_CMD.to_le_bytes()));
+        let mut buf = [0; 4];
+        p.read(0, 0, &mut buf).unwrap();
+        assert_eq!(1, u32::from_le_bytes(buf));
+
+        // An unlock accepts a single command
+        p.tick(1000);
+        assert!(p.accept(NmtCommandSpecifier::Stop, NmtState::Operational));
+        assert!(!p.accept(NmtCommandSpecifier::Stop, NmtState::Operational));
+
+        // An unlock expires
+        p.unlock();
+        p.tick(2000);
+        p.tick(2000 + UNLOCK_TIMEOUT_US - 1);
+        assert!(p.is_unlocked());
+        p.tick(2000 + UNLOCK_TIMEOUT_US);
+        assert!(!p.is_unlocked());
+        assert!(!p.accept(NmtCommandSpecifier::Stop, NmtState::Operational));
+
+        // Writing 0 locks again
+        p.unlock();
+        p.write(0, &[0; 4]).unwrap();
+        assert!(!p.accept(NmtCommandSpecifier::ResetApp, NmtState::Operational));
+    }
+}
@@ -0,0 +1,168 @@
+//! Boot-up delay, heartbeat rate limit and scan response jitter (object 0x5002)
+//!
+//! When a device config sets NMT timing, the node waits for a pseudo-random time after each reset
+//! before sending its boot-up message, limits how often it sends heartbeats, and delays its
+//! responses to the SDO reads used to scan a bus by a pseudo-random time. The times are read from
+//! object 0x5002 when they are used, so changes made via SDO take effect on the next reset,
+//! heartbeat or response.
+
+use zencan_common::{constants::object_ids, lss::LssIdentity};
+
+use crate::object_dict::{find_object, ODEntry, ObjectAccess};
+
+/// Sub index of the maximum boot-up delay in milliseconds
+const SUB_BOOT_DELAY_MAX: u8 = 1;
+/// Sub index of the minimum heartbeat interval in milliseconds
+const SUB_MIN_HEARTBEAT_INTERVAL: u8 = 2;
+/// Sub index of the maximum scan response jitter in milliseconds
+const SUB_SCAN_RESPONSE_JITTER_MAX: u8 = 3;
+
+/// Returns true if an object is read by a bus scan
+///
+/// A scan reads the same objects from every node ID, so these are the reads to which many nodes
+/// respond at once.
+pub(crate) fn is_scan_object(index: u16) -> bool {
+    matches!(
+        index,
+        object_ids::DEVICE_TYPE
+            | object_ids::DEVICE_NAME
+            | object_ids::HARDWARE_VERSION
+            | object_ids::SOFTWARE_VERSION
+            | object_ids::IDENTITY
+    )
+}
+
+/// Mix the bits of a value, so that similar inputs produce unrelated outputs
+const fn mix(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+/// Pick a pseudo-random delay up to `max_us`
+///
+/// There is no entropy source available, so the delay is derived from the node's identity and
+/// node ID. Nodes with different serial numbers or IDs get different delays, and a different
+/// `count` gives a different delay.
+fn random_delay_us(max_us: u64, identity: &LssIdentity, node_id: u8, count: u32) -> u64 {
+    if max_us == 0 {
+        return 0;
+    }
+    let mut hash = mix(node_id as u32 ^ count.rotate_left(8));
+    for word in [
+        identity.vendor_id,
+        identity.product_code,
+        identity.revision,
+        identity.serial,
+    ] {
+        hash = mix(hash ^ word);
+    }
+    hash as u64 % (max_us + 1)
+}
+
+/// Reads the NMT timing object, if the node has one
+pub(crate) struct NmtTiming {
+    object: Option<&'static dyn ObjectAccess>,
+    /// The number of boot-ups, so that each reset picks a new delay
+    boot_count: u32,
+    /// The number of delayed scan responses, so that each response picks a new delay
+    response_count: u32,
+}
+
+impl NmtTiming {
+    pub fn new(od: &'static [ODEntry<'static>]) -> Self {
+        Self {
+            object: find_object(od, object_ids::NMT_STARTUP_TIMING),
+            boot_count: 0,
+            response_count: 0,
+        }
+    }
+
+    fn read_ms(&self, sub: u8) -> u64 {
+        self.object
+            .and_then(|obj| obj.read_u16(sub).ok())
+            .unwrap_or(0) as u64
+    }
+
+    /// Get the delay before the next boot-up message is sent, in microseconds
+    ///
+    /// A node gets a different delay each time it boots.
+    pub fn boot_delay_us(&mut self, identity: &LssIdentity, node_id: u8) -> u64 {
+        let max_us = self.read_ms(SUB_BOOT_DELAY_MAX) * 1000;
+        let delay_us = random_delay_us(max_us, identity, node_id, self.boot_count);
+        self.boot_count = self.boot_count.wrapping_add(1);
+        delay_us
+    }
+
+    /// Get the delay before the response to a read of a [scan object](is_scan_object) is sent, in
+    /// microseconds
+    ///
+    /// The delay is chosen in the same way as the boot-up delay, so that nodes which are probed
+    /// together respond at different times.
+    pub fn scan_response_delay_us(&mut self, identity: &LssIdentity, node_id: u8) -> u64 {
+        let max_us = self.read_ms(SUB_SCAN_RESPONSE_JITTER_MAX) * 1000;
+        // Use a different sequence to the boot-up delays
+        let count = !self.response_count;
+        let delay_us = random_delay_us(max_us, identity, node_id, count);
+        self.response_count = self.response_count.wrapping_add(1);
+        delay_us
+    }
+
+    /// Get the minimum time between heartbeats, in microseconds
+    pub fn min_heartbeat_interval_us(&self) -> u64 {
+        self.read_ms(SUB_MIN_HEARTBEAT_INTERVAL) * 1000
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(serial: u32) -> LssIdentity {
+        LssIdentity {
+            vendor_id: 0xCAFE,
+            product_code: 1,
+            revision: 2,
+            serial,
+        }
+    }
+
+    #[test]
+    fn test_mix_spreads_similar_inputs() {
+        // Sequential serial numbers, as on a batch of devices, should not give sequential delays
+        let delays: Vec<u32> = (0..8).map(|serial| mix(serial) % 1000).collect();
+        let mut sorted = delays.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(delays.len(), sorted.len());
+        assert_ne!(delays, sorted);
+    }
+
+    #[test]
+    fn test_no_object() {
+        let mut timing = NmtTiming {
+            object: None,
+            boot_count: 0,
+            response_count: 0,
+        };
+        assert_eq!(0, timing.boot_delay_us(&identity(1), 1));
+        assert_eq!(0, timing.min_heartbeat_interval_us());
+        assert_eq!(0, timing.scan_response_delay_us(&identity(1), 1));
+    }
+
+    #[test]
+    fn test_random_delay() {
+        for count in 0..8 {
+            assert!(random_delay_us(1000, &identity(count), 1, count) <= 1000);
+        }
+        assert_ne!(
+            random_delay_us(1_000_000, &identity(1), 1, 0),
+            random_delay_us(1_000_000, &identity(1), 2, 0)
+        );
+        assert!(is_scan_object(0x1000));
+        assert!(!is_scan_object(0x2000));
+    }
+}
@@ -2,29 +2,72 @@
 //!
 
 use zencan_common::{
+    access_trace::{AccessKind, AccessTraceEntry},
     constants::object_ids,
     lss::LssIdentity,
     messages::{
-        CanId, CanMessage, Heartbeat, NmtCommandSpecifier, NmtState, ZencanMessage, LSS_RESP_ID,
+        CanId, CanMessage, Heartbeat, NmtCommandSpecifier, NmtState, ZencanMessage, LSS_REQ_ID,
+        LSS_RESP_ID,
     },
     NodeId,
 };
 
+use crate::clock::{clock_mask, elapsed_us};
+use crate::statistics::{Statistics, SUB_BUS_OFF_COUNT, SUB_EMCY_COUNT, SUB_POWER_CYCLES};
 use crate::{
-    lss_slave::{LssConfig, LssSlave},
+    emcy::{EmcyProducer, PendingEmcy},
+    failsafe::{Failsafe, FailsafeReason, HEARTBEAT_LOST_EMCY_CODE},
+    lss_slave::{LssAssignment, LssConfig, LssEvent, LssSlave},
+    nmt_protection::NMT_REJECTED_EMCY_CODE,
+    nmt_timing::{is_scan_object, NmtTiming},
     node_mbox::NodeMbox,
-    object_dict::{find_object, ODEntry},
+    object_dict::{find_object, find_object_entry, ODEntry},
+    pdo::{Pdo, RpdoCallback, TpdoOrder},
     storage::StoreObjectsCallback,
+    tx_order::{OrderedSender, TxStage},
+    watchdog::{WatchdogCallback, WATCHDOG_EMCY_CODE},
 };
 use crate::{node_state::NodeStateAccess, sdo_server::SdoServer};
 
-use defmt_or_log::{debug, info};
+use defmt_or_log::{debug, info, warn};
+
+/// The result of a call to [`Node::process`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProcessResult {
+    /// True if objects were updated, i.e. when an SDO download has been completed, or when one or
+    /// more RPDOs have been received
+    pub objects_updated: bool,
+    /// The time in microseconds until the node's next internally scheduled action, such as a
+    /// heartbeat, the end of the boot-up delay, an SDO timeout, a delayed scan response, or a TPDO
+    /// or EMCY held back by its inhibit time
+    ///
+    /// If no messages are received, and the application does not change any objects, there is
+    /// nothing for `process` to do until this much time has passed, so an application may sleep
+    /// until then, or until woken by the [`NodeMbox`] notify callback. `None` means that nothing is
+    /// scheduled.
+    pub next_action_us: Option<u64>,
+}
+
+/// The index of the first RPDO communication parameter object
+const RPDO_COMM_BASE: u16 = 0x1400;
+/// The index of the first TPDO communication parameter object
+const TPDO_COMM_BASE: u16 = 0x1800;
 
 type StoreNodeConfigCallback = dyn Fn(&NodeId) + Sync;
+type LssAssignmentCallback = dyn Fn(LssAssignment) + Sync;
+type ScanResponseCallback = dyn Fn(u16, u64) -> u64 + Sync;
 
 #[derive(Default)]
 struct Callbacks {
     store_node_config: Option<&'static StoreNodeConfigCallback>,
+    lss_assignment: Option<&'static LssAssignmentCallback>,
+    scan_response: Option<&'static ScanResponseCallback>,
+}
+
+/// Returns true if any sub-object of the object is saved when objects are stored
+fn has_persistent_subs(entry: &ODEntry) -> bool {
+    (0..=entry.data.max_sub_number()).any(|sub| entry.data.sub_info(sub).is_ok_and(|i| i.persist))
 }
 
 fn read_identity(od: &[ODEntry]) -> Option<LssIdentity> {
@@ -46,6 +89,43 @@ fn read_heartbeat_period(od: &[ODEntry]) -> Option<u16> {
     obj.read_u16(0).ok()
 }
 
+/// Read the synchronous window length in microseconds, or 0 if the window is disabled
+fn read_sync_window(od: &[ODEntry]) -> u32 {
+    find_object(od, object_ids::SYNC_WINDOW_LENGTH)
+        .and_then(|obj| obj.read_u32(0).ok())
+        .unwrap_or(0)
+}
+
+/// Record an EMCY which was discarded from the queue in the error history, so that the error is
+/// not lost from object 0x1003
+fn record_discarded_emcy(state: &dyn NodeStateAccess, emcy: Option<PendingEmcy>) {
+    if let (Some(emcy), Some(history)) = (emcy, state.error_history()) {
+        history.push(emcy.error_code, emcy.vendor_data);
+    }
+}
+
+/// Read the EMCY inhibit time in microseconds, or 0 if there is no inhibit time
+fn read_emcy_inhibit_time(od: &[ODEntry]) -> u64 {
+    find_object(od, object_ids::EMCY_INHIBIT_TIME)
+        .and_then(|obj| obj.read_u16(0).ok())
+        .map(|t| t as u64 * 100)
+        .unwrap_or(0)
+}
+
+fn read_error_register(od: &[ODEntry]) -> u8 {
+    find_object(od, object_ids::ERROR_REGISTER)
+        .and_then(|obj| obj.read_u8(0).ok())
+        .unwrap_or(0)
+}
+
+/// Read the base COB-ID of the secondary heartbeat, or None if it is not implemented or disabled
+fn read_secondary_heartbeat_base(od: &[ODEntry]) -> Option<u16> {
+    let value = find_object(od, object_ids::SECONDARY_HEARTBEAT)?
+        .read_u32(0)
+        .ok()?;
+    (value & (1 << 31) == 0).then_some((value & 0x7FF) as u16)
+}
+
 fn read_autostart(od: &[ODEntry]) -> Option<bool> {
     let obj = find_object(od, object_ids::AUTO_START)?;
     Some(obj.read_u8(0).unwrap() != 0)
@@ -76,8 +156,26 @@ pub struct Node {
     next_heartbeat_time_us: u64,
     heartbeat_period_ms: u16,
     heartbeat_toggle: bool,
+    heartbeat_pending: bool,
+    last_heartbeat_us: Option<u64>,
+    boot_time_us: Option<u64>,
+    nmt_timing: NmtTiming,
+    /// An SDO response held back by the scan response delay, and the time to send it
+    deferred_sdo_response: Option<(u64, CanMessage)>,
     auto_start: bool,
-    last_process_time_us: u64,
+    /// The application clock at the last call to process, or None before the first call
+    last_process_time_us: Option<u64>,
+    /// The node's own clock, which advances by the time elapsed on the application clock
+    clock_us: u64,
+    /// The mask of the valid bits of the application clock
+    clock_mask: u64,
+    sync_window_skip_count: u32,
+    tpdo_order: TpdoOrder,
+    /// The TPDO to check first on the next call to process, when using round-robin order
+    next_tpdo: usize,
+    failsafe: Option<&'static Failsafe>,
+    emcy: EmcyProducer,
+    statistics: Statistics,
 }
 
 impl Node {
@@ -101,6 +199,7 @@ impl Node {
             identity: read_identity(od).unwrap(),
             node_id,
             store_supported: false,
+            bit_timing_supported: false,
         });
         let nmt_state = NmtState::Bootup;
         let reassigned_node_id = None;
@@ -109,7 +208,16 @@ impl Node {
         let next_heartbeat_time_us = 0;
         let heartbeat_toggle = false;
         let auto_start = read_autostart(od).expect("auto start object must exist");
-        let last_process_time_us = 0;
+        let last_process_time_us = None;
+        // Objects are restored from storage before the node is created, so this counts up from the
+        // stored value
+        let statistics = Statistics::new(od);
+        if statistics.increment(SUB_POWER_CYCLES) {
+            state.storage_context().dirty.store(true);
+        }
+        if state.loopback_test().is_some() {
+            mbox.enable_loopback_test();
+        }
         Self {
             node_id,
             nmt_state,
@@ -123,9 +231,22 @@ impl Node {
             next_heartbeat_time_us,
             heartbeat_period_ms,
             heartbeat_toggle,
+            heartbeat_pending: false,
+            last_heartbeat_us: None,
+            boot_time_us: None,
+            nmt_timing: NmtTiming::new(od),
+            deferred_sdo_response: None,
             auto_start,
             callbacks: Callbacks::default(),
             last_process_time_us,
+            clock_us: 0,
+            clock_mask: u64::MAX,
+            sync_window_skip_count: 0,
+            tpdo_order: TpdoOrder::ByNumber,
+            next_tpdo: 0,
+            failsafe: None,
+            emcy: EmcyProducer::new(),
+            statistics,
         }
     }
 
@@ -136,11 +257,220 @@ impl Node {
         self.reassigned_node_id = Some(node_id);
     }
 
+    /// Request a transition of the local node to a new NMT state
+    ///
+    /// This allows the application to change the state of its own node, e.g. to drop to
+    /// PreOperational when a hardware fault is detected. The request is handled in the same way as
+    /// an NMT command received from the bus, and a heartbeat is sent on the next call to
+    /// [`process`](Self::process) so that the new state is reported immediately. Requesting
+    /// [`NmtState::Bootup`] performs a communications reset.
+    ///
+    /// Returns false if the request was rejected because the node does not have a configured node
+    /// ID, as NMT commands are also ignored in that case.
+    pub fn request_state(&mut self, state: NmtState) -> bool {
+        if !self.node_id.is_configured() {
+            return false;
+        }
+        let cmd = match state {
+            NmtState::Bootup => NmtCommandSpecifier::ResetComm,
+            NmtState::Stopped => NmtCommandSpecifier::Stop,
+            NmtState::Operational => NmtCommandSpecifier::Start,
+            NmtState::PreOperational => NmtCommandSpecifier::EnterPreOp,
+        };
+        let prev_state = self.nmt_state;
+        self.handle_nmt_command(cmd);
+        // A reset will send a boot up message, so only an explicit heartbeat is required for other
+        // transitions
+        if self.nmt_state != prev_state && self.nmt_state != NmtState::Bootup {
+            self.heartbeat_pending = true;
+        }
+        true
+    }
+
     /// Register a callback to store node configuration data persistently
     pub fn register_store_node_config(&mut self, cb: &'static StoreNodeConfigCallback) {
         self.callbacks.store_node_config = Some(cb);
     }
 
+    /// Register a callback to be notified of configuration changes made by an LSS master
+    ///
+    /// The callback is called when the node ID is changed, and when a new bit timing is configured
+    /// or activated, so that the application can persist the new settings or switch bit rates. LSS
+    /// bit timing changes are only accepted when this callback is registered.
+    pub fn register_lss_assignment_callback(&mut self, cb: &'static LssAssignmentCallback) {
+        self.callbacks.lss_assignment = Some(cb);
+    }
+
+    /// Register a callback to choose the delay before responding to the SDO reads of a bus scan
+    ///
+    /// A scan reads the device type, device name, versions and identity objects of every node ID,
+    /// so on a large bus many nodes respond at once. The callback is called with the index of the
+    /// object read, and the pseudo-random delay chosen from the scan response jitter in object
+    /// 0x5002 (0 if the node has no jitter configured), both in microseconds. It returns the delay
+    /// to use, so that an application can e.g. rate limit its responses, or delay them further when
+    /// it is busy.
+    pub fn register_scan_response_callback(&mut self, cb: &'static ScanResponseCallback) {
+        self.callbacks.scan_response = Some(cb);
+    }
+
+    /// Register a callback to be called when an RPDO is received
+    ///
+    /// `rpdo` is the RPDO number, starting from 0. The callback is called from [`Node::process`]
+    /// with the values unpacked from the message, after they have been stored in the mapped
+    /// objects, so that the application can react to received commands immediately instead of
+    /// polling the objects.
+    ///
+    /// Returns false if the node has no such RPDO.
+    pub fn register_rpdo_callback(&mut self, rpdo: usize, cb: &'static RpdoCallback) -> bool {
+        match self.state.get_rpdos().get(rpdo) {
+            Some(pdo) => {
+                pdo.register_rx_callback(cb);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Force transmission of a TPDO on the next call to [`process`](Self::process)
+    ///
+    /// `tpdo` is the TPDO number, starting from 0. The PDO is sent regardless of the event flags
+    /// of its mapped objects, but still respects its inhibit time. This is intended for TPDOs with
+    /// transmission type 254 or 255, where the application decides when data is sent; triggering
+    /// a SYNC driven TPDO has no effect.
+    ///
+    /// Returns false if the node has no such TPDO.
+    pub fn trigger_tpdo(&mut self, tpdo: usize) -> bool {
+        match self.state.get_tpdos().get(tpdo) {
+            Some(pdo) => {
+                pdo.trigger();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Queue an emergency (EMCY) message for transmission
+    ///
+    /// The message is sent on a following call to [`process`](Self::process), along with the
+    /// current value of the error register (0x1001). EMCY messages are rate limited by the inhibit
+    /// time in object 0x1015, in multiples of 100us. If an EMCY with the same error code is still
+    /// waiting for the inhibit time to expire, it is replaced, so that an error which is reported
+    /// repeatedly cannot saturate the bus. A small number of EMCYs with different error codes are
+    /// held; when more are queued, the oldest is discarded.
+    ///
+    /// EMCY messages are only sent in the PreOperational and Operational states, and are held until
+    /// the node enters one of them. They are sent on the COB-ID in object 0x1014, and are held
+    /// while it is marked invalid. When the node has an error history, each EMCY sent or discarded
+    /// is recorded in object 0x1003.
+    pub fn send_emcy(&mut self, error_code: u16, vendor_data: [u8; 5]) {
+        let displaced = self.emcy.queue(error_code, vendor_data);
+        record_discarded_emcy(self.state, displaced);
+    }
+
+    /// Get the number of queued EMCY messages which were discarded, because a newer one with the
+    /// same error code was queued within the inhibit time, or because the queue was full
+    pub fn emcy_merged_count(&self) -> u32 {
+        self.emcy.merged_count()
+    }
+
+    /// Report that the CAN controller has entered the bus-off state
+    ///
+    /// The node has no access to the CAN controller, so the application should call this when its
+    /// driver reports a bus-off event. It is counted in the node statistics object (0x5001), if the
+    /// device config enables it.
+    pub fn report_bus_off(&mut self) {
+        if self.statistics.increment(SUB_BUS_OFF_COUNT) {
+            self.state.storage_context().dirty.store(true);
+        }
+    }
+
+    /// Set the order in which TPDOs which are due in the same call to [`process`](Self::process) are
+    /// transmitted
+    ///
+    /// The default is [`TpdoOrder::ByNumber`]. Use [`TpdoOrder::RoundRobin`] with
+    /// [`process_queued`](Self::process_queued) when the transmit queue may fill up, so that the
+    /// lower numbered TPDOs cannot starve the higher numbered ones.
+    pub fn set_tpdo_order(&mut self, order: TpdoOrder) {
+        self.tpdo_order = order;
+        self.next_tpdo = 0;
+    }
+
+    /// Register a [`Failsafe`], which applies the error values of the node's outputs when the
+    /// controlling master is lost
+    ///
+    /// Once registered, the node consumes the heartbeats configured in object 0x1016, and applies
+    /// the error values when one of them times out, or when the node leaves the Operational state.
+    /// See the [`failsafe`](crate::failsafe) module for more info.
+    pub fn set_failsafe(&mut self, failsafe: &'static Failsafe) {
+        self.failsafe = Some(failsafe);
+    }
+
+    /// Enable the application watchdog, or disable it by passing 0
+    ///
+    /// Once enabled, the application must call [`feed_watchdog`](Self::feed_watchdog) at least
+    /// once every `period_us` microseconds, and must keep calling [`process`](Self::process). If
+    /// it stops doing either, the callback registered with
+    /// [`register_watchdog_callback`](Self::register_watchdog_callback) is called. The period
+    /// starts on the next call to `process`.
+    ///
+    /// `process` can only detect a missing feed. To detect a hung `process` task, the watchdog
+    /// must also be checked from another context, e.g. a timer interrupt, via
+    /// [`NodeMbox::watchdog`]. See the [`watchdog`](crate::watchdog) module for more info.
+    pub fn set_watchdog(&mut self, period_us: u64) {
+        self.mbox.watchdog().set_period(period_us);
+    }
+
+    /// Feed the application watchdog
+    ///
+    /// The feed takes effect on the next call to [`process`](Self::process), so the watchdog
+    /// expires if `process` is not called, even when the application keeps feeding it.
+    pub fn feed_watchdog(&self) {
+        self.mbox.watchdog().feed();
+    }
+
+    /// Register a callback to be called when the application watchdog expires
+    ///
+    /// The callback is passed an EMCY message with error code [`WATCHDOG_EMCY_CODE`], if the node
+    /// is able to send EMCY messages. It may be called from an interrupt, or from `process`, and
+    /// should force the device into a safe state, transmit the EMCY directly, and reset the device.
+    pub fn register_watchdog_callback(&mut self, cb: &'static WatchdogCallback) {
+        self.mbox.watchdog().set_callback(cb);
+    }
+
+    /// Set the width of the clock passed to [`process`](Self::process), in bits
+    ///
+    /// The clock is assumed to wrap to 0 after reaching its maximum value, e.g. a 32-bit
+    /// microsecond timer wraps every ~71 minutes. Only the time elapsed between calls to `process`
+    /// is used, so heartbeats, timeouts and other scheduled actions continue across the wrap. The
+    /// default is 64 bits. The width also applies to the times passed to
+    /// [`NodeMbox::store_message_at`] and [`Watchdog::check`](crate::watchdog::Watchdog::check).
+    pub fn set_clock_bits(&mut self, bits: u32) {
+        self.clock_mask = clock_mask(bits);
+        self.mbox.watchdog().set_clock_mask(self.clock_mask);
+    }
+
+    /// Append a line of text to the debug log (object 0x5004)
+    ///
+    /// Returns false if the node has no debug log, or if the message was discarded by the rate
+    /// limit. To log from another task, use [`DebugLog::log_str`](crate::DebugLog::log_str) on the
+    /// generated `DEBUG_LOG` static.
+    pub fn log_str(&self, msg: &str) -> bool {
+        self.state
+            .debug_log()
+            .is_some_and(|debug_log| debug_log.log_str(msg))
+    }
+
+    /// Record an event in the event log (object 0x5008)
+    ///
+    /// Returns the sequence number of the event, or None if the node has no event log. To record
+    /// events from another task, use [`EventLog::log_event`](crate::EventLog::log_event) on the
+    /// generated `EVENT_LOG` static.
+    pub fn log_event(&self, code: u16, data: [u8; 4]) -> Option<u32> {
+        self.state
+            .event_log()
+            .map(|event_log| event_log.log_event(code, data))
+    }
+
     /// Register a callback to store object data persistently
     pub fn register_store_objects(&mut self, cb: &'static StoreObjectsCallback) {
         self.state.storage_context().store_callback.store(Some(cb));
@@ -157,16 +487,128 @@ impl Node {
     ///
     /// # Arguments
     /// - `now_us`: A monotonic time in microseconds. This is used for measuring time and triggering
-    ///   time-based actions such as heartbeat transmission or SDO timeout
+    ///   time-based actions such as heartbeat transmission or SDO timeout. The node has no other
+    ///   source of time, so given the same sequence of received messages and `now_us` values, it
+    ///   will always transmit the same messages. This allows recorded traffic to be replayed against
+    ///   a virtual clock, e.g. to debug field issues. The clock may wrap, see
+    ///   [`set_clock_bits`](Self::set_clock_bits).
     /// - `send_cb`: A callback function for transmitting can messages
     ///
+    /// # Message ordering
+    ///
+    /// Within a single call, messages are passed to `send_cb` in the following order:
+    ///
+    /// 1. The boot-up message, and its secondary copy, if the node has been reset
+    /// 2. SDO responses, in the order the requests were received
+    /// 3. LSS responses
+    /// 4. An EMCY message, if one is queued and the EMCY inhibit time has expired
+    /// 5. The heartbeat, if one is due, followed by the [secondary
+    ///    heartbeat](Self::secondary_heartbeat_cob_id) if it is enabled
+    /// 6. TPDOs, in order of PDO number, see [`set_tpdo_order`](Self::set_tpdo_order)
+    /// 7. The [loopback self-test](crate::LoopbackTest) frame, if a test has been started
+    ///
+    /// Received RPDOs and SDO writes are applied before TPDOs are checked for events, so if
+    /// writing an object sets an event flag, e.g. in an application object's write handler, the
+    /// triggered TPDO is always sent after the SDO response, and in the same call. An application
+    /// which writes an object and then sets its event flag will likewise never see the TPDO
+    /// carrying the old value.
+    ///
+    /// These guarantees only hold on the bus if messages are transmitted in the order they are
+    /// passed to `send_cb`. When a CAN controller has multiple transmit mailboxes, they are
+    /// arbitrated by CAN ID, and TPDOs have a higher priority than SDO responses, so a FIFO
+    /// transmit queue should be used.
+    ///
+    /// Applications which cannot accept every message as soon as it is produced, e.g. with a CAN
+    /// driver which has a single transmit mailbox, can use
+    /// [`process_queued`](Self::process_queued) instead.
+    ///
     /// # Returns
     ///
-    /// A boolean indicating if objects were updated. This will be true when an SDO download has
-    /// been completed, or when one or more RPDOs have been received.
-    pub fn process(&mut self, now_us: u64, send_cb: &mut dyn FnMut(CanMessage)) -> bool {
-        let elapsed = (now_us - self.last_process_time_us) as u32;
-        self.last_process_time_us = now_us;
+    /// A [`ProcessResult`], indicating if objects were updated, and when `process` next needs to be
+    /// called.
+    pub fn process(&mut self, now_us: u64, send_cb: &mut dyn FnMut(CanMessage)) -> ProcessResult {
+        let mut sender = OrderedSender::new(send_cb);
+        self.process_with(now_us, &mut sender)
+    }
+
+    /// Process the node, adding the messages to be transmitted to the transmit queue
+    ///
+    /// This is the same as [`process`](Self::process), except that messages are added to the
+    /// transmit queue given to [`NodeMbox::with_tx_queue`], for the application to send at its own
+    /// pace with [`pop_tx`](Self::pop_tx). Messages are released in priority order, which is the
+    /// order documented on [`process`](Self::process), so that e.g. a backlog of TPDOs does not
+    /// hold up SDO responses or heartbeats. When the queue is full, messages are discarded
+    /// according to the [overflow policy](NodeMbox::set_tx_overflow_policy), and counted in
+    /// [`tx_overflow_count`](Self::tx_overflow_count). An event driven TPDO which is discarded
+    /// this way remains pending, and is sent on a later call, see
+    /// [`set_tpdo_order`](Self::set_tpdo_order).
+    pub fn process_queued(&mut self, now_us: u64) -> ProcessResult {
+        let mut sender = OrderedSender::queued(self.mbox.tx_queue());
+        self.process_with(now_us, &mut sender)
+    }
+
+    /// Take the next message to transmit from the transmit queue
+    ///
+    /// The queue is shared with the [`NodeMbox`], so this may be called e.g. from a transmit
+    /// complete interrupt.
+    pub fn pop_tx(&self) -> Option<CanMessage> {
+        self.mbox.tx_queue().pop()
+    }
+
+    /// Get the number of messages waiting in the transmit queue
+    pub fn tx_queue_len(&self) -> usize {
+        self.mbox.tx_queue().len()
+    }
+
+    /// Get the number of messages discarded because the transmit queue was full
+    pub fn tx_overflow_count(&self) -> u32 {
+        self.mbox.tx_queue().overflow_count()
+    }
+
+    /// Get the COB-ID of the secondary heartbeat, if it is enabled
+    ///
+    /// When the device config sets a secondary heartbeat base (object 0x5007), each heartbeat and
+    /// boot-up message is passed to the send callback, or added to the transmit queue, a second
+    /// time with this ID, straight after the first. An application connected to redundant networks
+    /// can compare the ID of each message to this, and send the copy on its second interface.
+    ///
+    /// Returns None if the node has no secondary heartbeat, it is disabled, or the node ID is not
+    /// configured.
+    pub fn secondary_heartbeat_cob_id(&self) -> Option<CanId> {
+        let NodeId::Configured(node_id) = self.node_id else {
+            return None;
+        };
+        let id = read_secondary_heartbeat_base(self.od)? + node_id.raw() as u16;
+        (id <= 0x7FF).then_some(CanId::std(id))
+    }
+
+    fn process_with(&mut self, now_us: u64, sender: &mut OrderedSender) -> ProcessResult {
+        // The application clock may wrap, so everything is scheduled on the node's own clock, which
+        // starts at 0 on the first call and advances by the elapsed time. A clock which steps
+        // backwards is treated as no time passing.
+        let app_now_us = now_us;
+        let elapsed = self
+            .last_process_time_us
+            .map(|last_us| elapsed_us(last_us, app_now_us, self.clock_mask))
+            .unwrap_or(0);
+        self.last_process_time_us = Some(app_now_us);
+        self.clock_us += elapsed;
+        let now_us = self.clock_us;
+        let elapsed = elapsed.min(u32::MAX as u64) as u32;
+        self.mbox.watchdog().kick(app_now_us);
+        self.mbox.cache_clock().tick(now_us);
+        if let Some(debug_log) = self.state.debug_log() {
+            debug_log.tick(now_us);
+        }
+        if let Some(event_log) = self.state.event_log() {
+            event_log.tick(app_now_us);
+        }
+        if let Some(protection) = self.state.nmt_protection() {
+            protection.tick(now_us);
+        }
+        if self.statistics.add_time(elapsed as u64) {
+            self.state.storage_context().dirty.store(true);
+        }
 
         let mut update_flag = false;
         if let Some(new_node_id) = self.reassigned_node_id.take() {
@@ -175,9 +617,19 @@ impl Node {
         }
 
         if self.nmt_state == NmtState::Bootup {
-            // Set state before calling boot_up, so the heartbeat state is correct
+            // Wait for the boot-up delay, so that nodes which are powered on together do not all
+            // send their boot-up messages at once
+            if let Some(wait_us) = self.boot_delay_remaining(now_us) {
+                self.publish_status();
+                return ProcessResult {
+                    objects_updated: false,
+                    next_action_us: Some(wait_us),
+                };
+            }
+            // The node enters pre-operational with the boot-up message, which carries the Bootup
+            // state
             self.nmt_state = NmtState::PreOperational;
-            self.boot_up(send_cb);
+            self.boot_up(now_us, sender);
         }
 
         // If auto start is set on boot, and we already have an ID, we make the first transition to
@@ -187,110 +639,490 @@ impl Node {
             self.nmt_state = NmtState::Operational;
         }
 
-        // Process SDO server
-        let (resp, updated_index) =
-            self.sdo_server
-                .process(self.mbox.sdo_receiver(), elapsed, self.od);
-        if let Some(resp) = resp {
-            send_cb(resp.to_can_message(self.sdo_tx_cob_id()));
+        // Process SDO server. It is run at least once to update its timeout, and then once for each
+        // additional queued request, unless a delayed scan response is still waiting to be sent.
+        let mut sdo_elapsed = elapsed;
+        self.apply_sdo_cob_id_change();
+        if self
+            .deferred_sdo_response
+            .is_some_and(|(due_us, _)| now_us >= due_us)
+        {
+            let (_, msg) = self.deferred_sdo_response.take().unwrap();
+            sender.send(TxStage::Sdo, msg);
         }
-        if updated_index.is_some() {
-            update_flag = true;
+        // While a response is held back, the client waits for it before sending another request
+        while self.deferred_sdo_response.is_none() {
+            self.mbox.next_sdo_request();
+            // The response to a write of the SDO server COB-IDs is sent on the old COB-ID
+            let tx_cob_id = self.sdo_tx_cob_id();
+            let (resp, updated_object) =
+                self.sdo_server
+                    .process(self.mbox.sdo_receiver(), sdo_elapsed, self.od);
+            sdo_elapsed = 0;
+            let access = self.sdo_server.take_access();
+            if let (Some(resp), Some(cob_id)) = (resp, tx_cob_id) {
+                let msg = resp.to_can_message(cob_id);
+                let delay_us = match &access {
+                    Some(access) if !access.write && is_scan_object(access.index) => {
+                        self.scan_response_delay_us(access.index)
+                    }
+                    _ => 0,
+                };
+                if delay_us == 0 {
+                    sender.send(TxStage::Sdo, msg);
+                } else {
+                    self.deferred_sdo_response = Some((now_us + delay_us, msg));
+                }
+            }
+            self.apply_sdo_cob_id_change();
+            if let Some(access) = access {
+                let kind = if access.write {
+                    AccessKind::SdoWrite
+                } else {
+                    AccessKind::SdoRead
+                };
+                self.record_access(access.index, access.sub, kind, access.abort_code);
+            }
+            if let Some(id) = updated_object {
+                update_flag = true;
+                if let Some(entry) = find_object_entry(self.od, id.index) {
+                    if has_persistent_subs(entry) {
+                        self.state.storage_context().dirty.store(true);
+                    }
+                }
+            }
+            if !self.mbox.sdo_pending() {
+                break;
+            }
         }
 
-        // Process NMT
-        if let Some(msg) = self.mbox.read_nmt_mbox() {
-            if let Ok(ZencanMessage::NmtCommand(cmd)) = msg.try_into() {
+        // Process NMT and LSS messages, in the order they were received
+        while let Some(msg) = self.mbox.read_nmt_mbox() {
+            if msg.id() == LSS_REQ_ID {
+                if let Ok(req) = msg.data().try_into() {
+                    if self.mbox.lss_receiver().handle_req(req) {
+                        self.process_lss(sender);
+                    }
+                }
+            } else if let Ok(ZencanMessage::NmtCommand(cmd)) = msg.try_into() {
                 self.message_count += 1;
                 // We cannot respond to NMT commands if we do not have a valid node ID
 
                 if let NodeId::Configured(node_id) = self.node_id {
                     if cmd.node == 0 || cmd.node == node_id.raw() {
                         debug!("Received NMT command: {:?}", cmd.cs);
-                        self.handle_nmt_command(cmd.cs);
+                        if self.nmt_command_accepted(cmd.cs) {
+                            self.handle_nmt_command(cmd.cs);
+                        }
                     }
                 }
             }
         }
 
-        if let Ok(Some(resp)) = self.lss_slave.process(self.mbox.lss_receiver()) {
-            send_cb(resp.to_can_message(LSS_RESP_ID));
-
-            if let Some(event) = self.lss_slave.pending_event() {
-                info!("LSS Slave Event: {:?}", event);
-                match event {
-                    crate::lss_slave::LssEvent::StoreConfiguration => {
-                        if let Some(cb) = self.callbacks.store_node_config {
-                            (cb)(&self.node_id)
-                        }
-                    }
-                    crate::lss_slave::LssEvent::ActivateBitTiming {
-                        table: _,
-                        index: _,
-                        delay: _,
-                    } => (),
-                    crate::lss_slave::LssEvent::ConfigureNodeId { node_id } => {
-                        self.set_node_id(node_id)
-                    }
+        if let Some(failsafe) = self.failsafe {
+            self.mbox
+                .set_consumed_heartbeats(failsafe.update_entries(self.od));
+            let seen = self.mbox.take_heartbeats();
+            let od = self.od;
+            let state = self.state;
+            let emcy = &mut self.emcy;
+            failsafe.check_heartbeats(seen, now_us, |node_id| {
+                let displaced = emcy.queue(HEARTBEAT_LOST_EMCY_CODE, [node_id, 0, 0, 0, 0]);
+                record_discarded_emcy(state, displaced);
+                failsafe.trigger(od, FailsafeReason::HeartbeatLost { node_id });
+            });
+        }
+
+        if self.emcy_allowed() {
+            let inhibit_us = read_emcy_inhibit_time(self.od);
+            // With no inhibit time, all queued EMCYs are sent at once
+            while let Some(emcy) = self.emcy.take_due(now_us, inhibit_us) {
+                let cob_id = self.state.get_cob_ids().emcy().id;
+                let msg = emcy.to_can_message(cob_id, read_error_register(self.od));
+                sender.send(TxStage::Emcy, msg);
+                if let Some(history) = self.state.error_history() {
+                    history.push(emcy.error_code, emcy.vendor_data);
+                }
+                if self.statistics.increment(SUB_EMCY_COUNT) {
+                    self.state.storage_context().dirty.store(true);
                 }
             }
         }
 
+        // The producer time may be changed at any time, e.g. by an SDO write to 0x1017, and a new
+        // period takes effect right away
+        let heartbeat_period_ms = read_heartbeat_period(self.od).unwrap_or(0);
+        if heartbeat_period_ms != self.heartbeat_period_ms {
+            self.heartbeat_period_ms = heartbeat_period_ms;
+            self.next_heartbeat_time_us = self.earliest_heartbeat_us().max(now_us);
+        }
+
+        if self.heartbeat_pending {
+            // Report a state change requested by the application right away, or as soon as the
+            // minimum heartbeat interval allows. The heartbeat schedule continues from then.
+            self.heartbeat_pending = false;
+            self.next_heartbeat_time_us = self.earliest_heartbeat_us().max(now_us);
+        }
+
         if self.heartbeat_period_ms != 0 && now_us >= self.next_heartbeat_time_us {
-            self.send_heartbeat(send_cb);
+            if let Some(msg) = self.heartbeat_message(now_us, self.nmt_state) {
+                sender.send(TxStage::Heartbeat, msg);
+                if let Some(secondary) = self.secondary_heartbeat(&msg) {
+                    sender.send(TxStage::Heartbeat, secondary);
+                }
+            }
             // Perform catchup if we are behind, e.g. if we have not send a heartbeat in a long
             // time because we have not been configured
-            if self.next_heartbeat_time_us < now_us {
-                self.next_heartbeat_time_us = now_us;
+            let earliest = self.earliest_heartbeat_us().max(now_us);
+            if self.next_heartbeat_time_us < earliest {
+                self.next_heartbeat_time_us = earliest;
             }
         }
 
-        if self.nmt_state == NmtState::Operational {
+        if self.nmt_state == NmtState::Operational && self.node_id.is_configured() {
             // check if a sync has been received
             let sync = self.mbox.read_sync_flag();
+            // The SYNC time is read from the application clock
+            let sync_age_us = self
+                .mbox
+                .read_sync_time()
+                .map(|sync_time_us| elapsed_us(sync_time_us, app_now_us, self.clock_mask))
+                .unwrap_or(0);
+            // Synchronous TPDOs are only sent within the window after the SYNC
+            let window_us = read_sync_window(self.od) as u64;
+            let sync_late = sync && window_us != 0 && sync_age_us > window_us;
 
-            // Swap the active TPDO flag set. Returns true if any object flags were set since last
-            // toggle. Tracking the global trigger is a performance boost, at least in the frequent
-            // case when no events have been triggered. The goal is for `process` to be as fast as
-            // possible when it has nothing to do, so it can be called frequently with little cost.
-            let global_trigger = self.state.get_pdo_sync().toggle();
-
-            for pdo in self.state.get_tpdos() {
-                if !(pdo.valid()) {
-                    continue;
-                }
-                let transmission_type = pdo.transmission_type();
-                if transmission_type >= 254 {
-                    if global_trigger && pdo.read_events() {
-                        let mut data = [0u8; 8];
-                        pdo.read_pdo_data(&mut data);
-                        let msg = CanMessage::new(pdo.cob_id(), &data);
-                        send_cb(msg);
+            // Received PDOs are stored before TPDOs are sent, so that any events triggered by
+            // their writes are sent in this call
+            while let Some(msg) = self.mbox.read_rpdo() {
+                for (i, rpdo) in self.state.get_rpdos().iter().enumerate() {
+                    if !rpdo.valid() || rpdo.cob_id() != msg.id() {
+                        continue;
+                    }
+                    // CiA 301 requires an RPDO which is shorter than its mapping to be ignored,
+                    // rather than writing the missing bytes
+                    if msg.data().len() < rpdo.mapped_len() {
+                        warn!("Ignoring RPDO {} shorter than its mapping", i);
+                        continue;
                     }
-                } else if sync && pdo.sync_update() {
                     let mut data = [0u8; 8];
-                    pdo.read_pdo_data(&mut data);
-                    let msg = CanMessage::new(pdo.cob_id(), &data);
-                    send_cb(msg);
+                    data[0..msg.data().len()].copy_from_slice(msg.data());
+                    rpdo.store_pdo_data(&data);
+                    self.record_pdo_access(RPDO_COMM_BASE + i as u16, AccessKind::RpdoWrite);
+                    if let Some(stats) = self.state.pdo_statistics() {
+                        stats.record_rpdo(i, (app_now_us / 1000) as u32);
+                    }
+                    update_flag = true;
                 }
             }
 
-            for pdo in self.state.get_tpdos() {
-                pdo.clear_events();
+            self.transmit_tpdos(sync, sync_late, now_us, false, sender);
+        }
+
+        if let Some(loopback_test) = self.state.loopback_test() {
+            if let Some(msg) = loopback_test.process(self.node_id.raw(), now_us, self.mbox) {
+                sender.send(TxStage::SelfTest, msg);
             }
+        }
 
-            for rpdo in self.state.get_rpdos() {
-                if !rpdo.valid() {
+        self.mbox.check_filter_change();
+        self.publish_status();
+        if self.mbox.watchdog().is_enabled() {
+            self.mbox.watchdog().set_emcy(self.watchdog_emcy_message());
+            self.mbox.watchdog().check(app_now_us);
+        }
+
+        ProcessResult {
+            objects_updated: update_flag,
+            next_action_us: self.next_action_us(now_us),
+        }
+    }
+
+    /// Get the time until the next internally scheduled action
+    fn next_action_us(&self, now_us: u64) -> Option<u64> {
+        let heartbeat = if self.heartbeat_period_ms != 0 && self.node_id.is_configured() {
+            Some(self.next_heartbeat_time_us.saturating_sub(now_us))
+        } else {
+            None
+        };
+        // A deferred SDO write is performed on the next call, as soon as possible
+        let sdo_timeout = if self.sdo_server.write_pending() {
+            Some(0)
+        } else {
+            self.sdo_server
+                .time_until_timeout(self.mbox.sdo_receiver())
+                .map(|t| t as u64)
+        };
+        let sdo_response = self
+            .deferred_sdo_response
+            .map(|(due_us, _)| due_us.saturating_sub(now_us));
+        // TPDO events held back by an inhibit time
+        let tpdo_event = if self.nmt_state == NmtState::Operational {
+            self.state
+                .get_tpdos()
+                .iter()
+                .filter_map(|pdo| pdo.next_event_us(now_us))
+                .min()
+        } else {
+            None
+        };
+        let emcy = if self.emcy_allowed() {
+            self.emcy
+                .time_until_due(now_us, read_emcy_inhibit_time(self.od))
+        } else {
+            None
+        };
+        // The watchdog is fed and checked on the application clock
+        let watchdog = self
+            .last_process_time_us
+            .and_then(|app_now_us| self.mbox.watchdog().time_until_expiry(app_now_us));
+        let loopback_test = self
+            .state
+            .loopback_test()
+            .and_then(|test| test.next_action_us(now_us));
+        let heartbeat_timeout = self
+            .failsafe
+            .and_then(|failsafe| failsafe.next_timeout_us(now_us));
+        [
+            heartbeat,
+            sdo_timeout,
+            sdo_response,
+            tpdo_event,
+            emcy,
+            watchdog,
+            loopback_test,
+            heartbeat_timeout,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Returns true if the node is in a state which allows it to send EMCY messages
+    fn emcy_allowed(&self) -> bool {
+        self.node_id.is_configured()
+            && self.state.get_cob_ids().emcy().valid
+            && matches!(
+                self.nmt_state,
+                NmtState::PreOperational | NmtState::Operational
+            )
+    }
+
+    /// Create the EMCY message to be sent if the application watchdog expires
+    fn watchdog_emcy_message(&self) -> Option<CanMessage> {
+        if !self.emcy_allowed() {
+            return None;
+        }
+        let emcy = PendingEmcy {
+            error_code: WATCHDOG_EMCY_CODE,
+            vendor_data: [0; 5],
+        };
+        let cob_id = self.state.get_cob_ids().emcy().id;
+        // Set the generic error bit, as the application cannot update the register itself
+        Some(emcy.to_can_message(cob_id, read_error_register(self.od) | 1))
+    }
+
+    /// Run the LSS slave on a newly received request, and act on any resulting event
+    fn process_lss(&mut self, sender: &mut OrderedSender) {
+        if let Ok(Some(resp)) = self.lss_slave.process(self.mbox.lss_receiver()) {
+            sender.send(TxStage::Lss, resp.to_can_message(LSS_RESP_ID));
+        }
+
+        // Some requests, e.g. bit timing activation, generate events without a response
+        if let Some(event) = self.lss_slave.pending_event() {
+            info!("LSS Slave Event: {:?}", event);
+            match event {
+                LssEvent::StoreConfiguration => {
+                    if let Some(cb) = self.callbacks.store_node_config {
+                        (cb)(&self.node_id)
+                    }
+                }
+                LssEvent::ConfigureBitTiming { table, index } => {
+                    self.report_lss_assignment(LssAssignment::BitTiming { table, index })
+                }
+                LssEvent::ActivateBitTiming {
+                    table,
+                    index,
+                    delay,
+                } => self.report_lss_assignment(LssAssignment::ActivateBitTiming {
+                    table,
+                    index,
+                    delay,
+                }),
+                LssEvent::ConfigureNodeId { node_id } => {
+                    // The event remains pending until the new ID is applied, so only report it
+                    // the first time
+                    if self.reassigned_node_id != Some(node_id) {
+                        self.set_node_id(node_id);
+                        self.report_lss_assignment(LssAssignment::NodeId(node_id));
+                    }
+                }
+            }
+        }
+    }
+
+    fn report_lss_assignment(&self, assignment: LssAssignment) {
+        if let Some(cb) = self.callbacks.lss_assignment {
+            cb(assignment);
+        }
+    }
+
+    /// Send any TPDOs which are due, either because their mapped objects have been flagged as
+    /// updated, or because of a received SYNC
+    ///
+    /// Events on a TPDO within its inhibit time are held until the inhibit time expires, unless
+    /// `ignore_inhibit` is set. If `sync_late` is set, the synchronous window has elapsed since
+    /// the SYNC, and synchronous TPDOs due on this SYNC are skipped.
+    fn transmit_tpdos(
+        &mut self,
+        sync: bool,
+        sync_late: bool,
+        now_us: u64,
+        ignore_inhibit: bool,
+        sender: &mut OrderedSender,
+    ) {
+        // Swap the active TPDO flag set. Returns true if any object flags were set since last
+        // toggle. Tracking the global trigger is a performance boost, at least in the frequent
+        // case when no events have been triggered. The goal is for `process` to be as fast as
+        // possible when it has nothing to do, so it can be called frequently with little cost.
+        let global_trigger = self.state.get_pdo_sync().toggle();
+
+        let tpdos = self.state.get_tpdos();
+        let start = match self.tpdo_order {
+            TpdoOrder::ByNumber => 0,
+            TpdoOrder::RoundRobin => self.next_tpdo,
+        };
+        let mut first_rejected = None;
+        for i in (start..tpdos.len()).chain(0..start) {
+            let pdo = &tpdos[i];
+            if !(pdo.valid()) {
+                continue;
+            }
+            let comm_index = TPDO_COMM_BASE + i as u16;
+            let transmission_type = pdo.transmission_type();
+            if transmission_type >= 254 {
+                if pdo.event_due(global_trigger, now_us, ignore_inhibit) {
+                    // A rejected TPDO keeps its pending event, so that it is sent on a later call
+                    if sender.send(TxStage::Tpdo, self.tpdo_message(i, pdo, now_us)) {
+                        pdo.mark_transmitted(now_us);
+                        self.record_tpdo(i, comm_index);
+                    } else {
+                        first_rejected.get_or_insert(i);
+                    }
+                }
+            } else if sync && pdo.sync_update() {
+                if sync_late {
+                    self.sync_window_skip_count = self.sync_window_skip_count.wrapping_add(1);
                     continue;
                 }
-                if let Some(new_data) = rpdo.buffered_value.take() {
-                    rpdo.store_pdo_data(&new_data);
-                    update_flag = true;
+                if sender.send(TxStage::Tpdo, self.tpdo_message(i, pdo, now_us)) {
+                    self.record_tpdo(i, comm_index);
+                } else {
+                    first_rejected.get_or_insert(i);
                 }
             }
         }
+        if let Some(i) = first_rejected {
+            self.next_tpdo = i;
+        }
+
+        for pdo in tpdos {
+            pdo.clear_events();
+        }
+    }
 
-        update_flag
+    /// Read the mapped objects of a TPDO, and embed its stamp, if it has one
+    fn tpdo_message(&self, tpdo: usize, pdo: &Pdo, now_us: u64) -> CanMessage {
+        let stamp = self.state.tpdo_stamp(tpdo);
+        let mut data = [0u8; 8];
+        pdo.read_pdo_data(&mut data[..stamp.payload_size()]);
+        stamp.write(&mut data, self.mbox.sync_count(), now_us);
+        CanMessage::new(pdo.cob_id(), &data)
+    }
+
+    /// Prepare the node for the application to exit
+    ///
+    /// This should be called once, after the final call to [`process`](Self::process), e.g. when a
+    /// Linux based node is stopped by a service manager. It:
+    ///
+    /// - Transmits any event-driven TPDOs which have pending events, if the node is Operational
+    /// - Saves persistent objects, if any have been written since they were last saved and a store
+    ///   callback has been registered with [`register_store_objects`](Self::register_store_objects)
+    /// - Transitions to the Stopped state, and sends a final heartbeat so that other devices see
+    ///   the node stop rather than waiting for a heartbeat timeout. No heartbeat is sent if the
+    ///   heartbeat producer is disabled.
+    /// - Applies the error values of a registered [`Failsafe`], if the node was Operational
+    ///
+    /// The node may be used again afterwards, e.g. by requesting a new state using
+    /// [`request_state`](Self::request_state).
+    pub fn shutdown(&mut self, send_cb: &mut dyn FnMut(CanMessage)) {
+        if self.nmt_state == NmtState::Operational {
+            let mut sender = OrderedSender::new(send_cb);
+            self.transmit_tpdos(false, false, self.clock_us, true, &mut sender);
+        }
+
+        let storage = self.state.storage_context();
+        if storage.is_dirty() {
+            if let Some(cb) = storage.store_callback.load() {
+                info!("Saving objects before shutdown");
+                crate::persist::serialize(self.od, cb);
+                storage.dirty.store(false);
+            }
+        }
+
+        let prev_state = self.nmt_state;
+        self.nmt_state = NmtState::Stopped;
+        self.check_left_operational(prev_state);
+        if self.heartbeat_period_ms != 0 {
+            if let Some(msg) = self.heartbeat_message(self.clock_us, self.nmt_state) {
+                send_cb(msg);
+                if let Some(secondary) = self.secondary_heartbeat(&msg) {
+                    send_cb(secondary);
+                }
+            }
+        }
+        self.publish_status();
+    }
+
+    /// Copy the current status into the node state, where it can be read by the application
+    fn publish_status(&self) {
+        let node_id = self.node_id;
+        let nmt_state = self.nmt_state;
+        let error_register = read_error_register(self.od);
+        let sdo_transfer_active = self.sdo_server.is_active();
+        let sdo_server = self.sdo_server.status();
+        let node_id_change_pending = self.reassigned_node_id.is_some();
+        self.state
+            .get_status()
+            .fetch_update(|mut s| {
+                s.node_id = node_id;
+                s.nmt_state = nmt_state;
+                s.error_register = error_register;
+                s.sdo_transfer_active = sdo_transfer_active;
+                s.sdo_server = sdo_server;
+                s.node_id_change_pending = node_id_change_pending;
+                Some(s)
+            })
+            .ok();
+    }
+
+    /// Check a command received from the bus against the NMT protection policy, if the node has one
+    ///
+    /// A rejected command is reported with an EMCY.
+    fn nmt_command_accepted(&mut self, cmd: NmtCommandSpecifier) -> bool {
+        let Some(protection) = self.state.nmt_protection() else {
+            return true;
+        };
+        if protection.accept(cmd, self.nmt_state) {
+            return true;
+        }
+        info!("Ignored protected NMT command: {:?}", cmd);
+        let displaced = self.emcy.queue(
+            NMT_REJECTED_EMCY_CODE,
+            [cmd as u8, self.nmt_state as u8, 0, 0, 0],
+        );
+        record_discarded_emcy(self.state, displaced);
+        false
     }
 
     fn handle_nmt_command(&mut self, cmd: NmtCommandSpecifier) {
@@ -313,6 +1145,16 @@ impl Node {
             "NMT state changed from {:?} to {:?}",
             prev_state, self.nmt_state
         );
+        self.check_left_operational(prev_state);
+    }
+
+    /// Apply the failsafe error values if the node has left the Operational state
+    fn check_left_operational(&self, prev_state: NmtState) {
+        if prev_state == NmtState::Operational && self.nmt_state != NmtState::Operational {
+            if let Some(failsafe) = self.failsafe {
+                failsafe.trigger(self.od, FailsafeReason::LeftOperational(self.nmt_state));
+            }
+        }
     }
 
     /// Get the current Node ID
@@ -320,6 +1162,11 @@ impl Node {
         self.node_id.into()
     }
 
+    /// Get the mailbox this node receives messages from
+    pub(crate) fn mbox(&self) -> &'static NodeMbox {
+        self.mbox
+    }
+
     /// Get the current NMT state of the node
     pub fn nmt_state(&self) -> NmtState {
         self.nmt_state
@@ -330,41 +1177,166 @@ impl Node {
         self.message_count
     }
 
-    fn sdo_tx_cob_id(&self) -> CanId {
-        let node_id: u8 = self.node_id.into();
-        CanId::Std(0x580 + node_id as u16)
+    /// Get the number of synchronous TPDO transmissions skipped because the synchronous window
+    /// length (object 0x1007) had elapsed since the SYNC was received
+    ///
+    /// See [`NodeMbox::store_message_at`] for how the SYNC reception time is determined.
+    pub fn sync_window_skip_count(&self) -> u32 {
+        self.sync_window_skip_count
+    }
+
+    /// Get the COB-ID for SDO responses, or None if the SDO server is disabled
+    pub(crate) fn sdo_tx_cob_id(&self) -> Option<CanId> {
+        let cob_id = self.state.get_cob_ids().sdo_tx();
+        cob_id.valid.then_some(cob_id.id)
+    }
+
+    /// Get the COB-ID for SDO requests, or None if the SDO server is disabled
+    fn sdo_rx_cob_id(&self) -> Option<CanId> {
+        let cob_id = self.state.get_cob_ids().sdo_rx();
+        cob_id.valid.then_some(cob_id.id)
     }
 
-    fn sdo_rx_cob_id(&self) -> CanId {
-        let node_id: u8 = self.node_id.into();
-        CanId::Std(0x600 + node_id as u16)
+    /// Update the SDO request filter if the SDO server receive COB-ID (0x1200sub1) was changed
+    fn apply_sdo_cob_id_change(&mut self) {
+        if self.state.get_cob_ids().take_changed() && self.node_id.is_configured() {
+            self.mbox.set_sdo_cob_id(self.sdo_rx_cob_id());
+        }
+    }
+
+    /// Record an object access in the access trace, if the node has one
+    ///
+    /// Accesses are timestamped with the application clock, so that they can be matched with the
+    /// application's own logs.
+    fn record_access(&self, index: u16, sub: u8, kind: AccessKind, abort_code: u32) {
+        if let Some(trace) = self.state.access_trace() {
+            let app_now_us = self.last_process_time_us.unwrap_or(0);
+            trace.record(AccessTraceEntry {
+                timestamp_ms: (app_now_us / 1000) as u32,
+                index,
+                sub,
+                kind,
+                abort_code,
+            });
+        }
+    }
+
+    /// Record a PDO in the access trace, if PDO tracing is enabled
+    ///
+    /// PDOs are recorded with the index of their communication parameter object
+    fn record_pdo_access(&self, comm_index: u16, kind: AccessKind) {
+        if self.state.access_trace().is_some_and(|t| t.trace_pdos()) {
+            self.record_access(comm_index, 0, kind, 0);
+        }
+    }
+
+    /// Record a transmitted TPDO in the access trace and the PDO statistics
+    fn record_tpdo(&self, tpdo: usize, comm_index: u16) {
+        self.record_pdo_access(comm_index, AccessKind::TpdoRead);
+        if let Some(stats) = self.state.pdo_statistics() {
+            let app_now_us = self.last_process_time_us.unwrap_or(0);
+            stats.record_tpdo(tpdo, (app_now_us / 1000) as u32);
+        }
+    }
+
+    /// Get the time remaining until the boot-up message may be sent, or None if it may be sent now
+    ///
+    /// The delay is chosen on the first call after each reset. An unconfigured node sends no boot-up
+    /// message, so it does not wait.
+    fn boot_delay_remaining(&mut self, now_us: u64) -> Option<u64> {
+        let NodeId::Configured(node_id) = self.node_id else {
+            return None;
+        };
+        let boot_time_us = match self.boot_time_us {
+            Some(t) => t,
+            None => {
+                let identity = read_identity(self.od).unwrap();
+                let delay_us = self.nmt_timing.boot_delay_us(&identity, node_id.raw());
+                *self.boot_time_us.insert(now_us + delay_us)
+            }
+        };
+        if now_us < boot_time_us {
+            Some(boot_time_us - now_us)
+        } else {
+            self.boot_time_us = None;
+            None
+        }
+    }
+
+    /// Get the delay before sending the response to an SDO read of a scan object
+    fn scan_response_delay_us(&mut self, index: u16) -> u64 {
+        let NodeId::Configured(node_id) = self.node_id else {
+            return 0;
+        };
+        let identity = read_identity(self.od).unwrap();
+        let jitter_us = self
+            .nmt_timing
+            .scan_response_delay_us(&identity, node_id.raw());
+        match self.callbacks.scan_response {
+            Some(cb) => cb(index, jitter_us),
+            None => jitter_us,
+        }
+    }
+
+    /// Get the earliest time the next heartbeat may be sent, given the minimum heartbeat interval
+    fn earliest_heartbeat_us(&self) -> u64 {
+        self.last_heartbeat_us
+            .map(|t| t + self.nmt_timing.min_heartbeat_interval_us())
+            .unwrap_or(0)
     }
 
-    fn boot_up(&mut self, sender: &mut dyn FnMut(CanMessage)) {
+    fn boot_up(&mut self, now_us: u64, sender: &mut OrderedSender) {
         // Reset the LSS slave with the new ID
         self.lss_slave.update_config(LssConfig {
             identity: read_identity(self.od).unwrap(),
             node_id: self.node_id,
             store_supported: self.callbacks.store_node_config.is_some(),
+            bit_timing_supported: self.callbacks.lss_assignment.is_some(),
         });
 
         if let NodeId::Configured(node_id) = self.node_id {
             info!("Booting node with ID {}", node_id.raw());
-            self.mbox.set_sdo_cob_id(Some(self.sdo_rx_cob_id()));
-            self.send_heartbeat(sender);
+            self.state.get_cob_ids().set_node_id(node_id.raw());
+            self.mbox.set_sdo_cob_id(self.sdo_rx_cob_id());
+            // The heartbeat schedule starts from the boot-up message
+            self.next_heartbeat_time_us = now_us;
+            if let Some(msg) = self.heartbeat_message(now_us, NmtState::Bootup) {
+                sender.send(TxStage::Bootup, msg);
+                if let Some(secondary) = self.secondary_heartbeat(&msg) {
+                    sender.send(TxStage::Bootup, secondary);
+                }
+            }
+        } else {
+            // An unconfigured node only takes part in LSS
+            info!("Booting unconfigured node");
+            self.mbox.set_sdo_cob_id(None);
         }
     }
 
-    fn send_heartbeat(&mut self, sender: &mut dyn FnMut(CanMessage)) {
-        if let NodeId::Configured(node_id) = self.node_id {
-            let heartbeat = Heartbeat {
-                node: node_id.raw(),
-                toggle: self.heartbeat_toggle,
-                state: self.nmt_state,
-            };
-            self.heartbeat_toggle = !self.heartbeat_toggle;
-            sender(heartbeat.into());
-            self.next_heartbeat_time_us += (self.heartbeat_period_ms as u64) * 1000;
-        }
+    /// Copy a heartbeat or boot-up message to the secondary heartbeat COB-ID, if it is enabled
+    fn secondary_heartbeat(&self, msg: &CanMessage) -> Option<CanMessage> {
+        Some(CanMessage::new(
+            self.secondary_heartbeat_cob_id()?,
+            msg.data(),
+        ))
+    }
+
+    /// Create the next heartbeat message, to be sent at `now_us`, and advance the heartbeat schedule
+    ///
+    /// Returns None if the node does not have a configured ID
+    fn heartbeat_message(&mut self, now_us: u64, state: NmtState) -> Option<CanMessage> {
+        let NodeId::Configured(node_id) = self.node_id else {
+            return None;
+        };
+        let heartbeat = Heartbeat {
+            node: node_id.raw(),
+            toggle: self.heartbeat_toggle,
+            state,
+        };
+        self.heartbeat_toggle = !self.heartbeat_toggle;
+        self.last_heartbeat_us = Some(now_us);
+        let period_us = (self.heartbeat_period_ms as u64) * 1000;
+        self.next_heartbeat_time_us += period_us.max(self.nmt_timing.min_heartbeat_interval_us());
+        Some(heartbeat.into())
     }
 }
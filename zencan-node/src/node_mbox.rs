@@ -1,25 +1,86 @@
 //! Implements mailbox for receiving CAN messages
 use defmt_or_log::warn;
 use zencan_common::{
-    messages::{CanId, CanMessage},
+    loopback_test::TEST_FRAME_ID,
+    messages::{CanId, CanMessage, SyncObject, HEARTBEAT_ID, LSS_REQ_ID, NMT_CMD_ID, SYNC_ID},
     AtomicCell,
 };
 
-use crate::{lss_slave::LssReceiver, pdo::Pdo, sdo_server::SdoReceiver};
+use crate::{
+    accept_filter::AcceptFilter,
+    lss_slave::LssReceiver,
+    msg_queue::MsgQueue,
+    object_dict::CacheClock,
+    pdo::Pdo,
+    sdo_server::{ReceiverState, SdoReceiver},
+    tx_queue::{TxOverflowPolicy, TxQueue, TxSlot},
+    watchdog::Watchdog,
+    BufferCell,
+};
+
+/// How long after an NMT command is received on one interface that an identical command received on
+/// the other interface is treated as a copy of it
+const NMT_DUPLICATE_WINDOW_US: u64 = 100_000;
+
+/// Identifies one of the two CAN interfaces of a node bridged across redundant buses
+///
+/// See [`NodeMbox::store_message_from`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusInterface {
+    /// The first interface
+    A,
+    /// The second interface
+    B,
+}
+
+/// The state of the receiver for loopback self-test frames
+#[derive(Clone, Copy, Debug)]
+enum LoopbackRx {
+    /// The node has no loopback test
+    Disabled,
+    /// No test is waiting for its frame
+    Idle,
+    /// A test is waiting for its frame
+    Armed,
+    /// A test frame was received, and has not yet been checked
+    Received(CanMessage),
+}
 
 /// A data structure to be shared between a receiving thread (e.g. a CAN controller IRQ) and the
 /// [`Node`](crate::Node) object.
 ///
 /// Incoming messages should be passed to [NodeMbox::store_message].
+///
+/// Received RPDO frames, SDO requests, and NMT/LSS frames are each held in a separate FIFO queue
+/// until the next call to [`Node::process`](crate::Node::process). The depth of each queue is set
+/// by the length of the buffer provided for it, which zencan-build sizes from the `[mbox]` section
+/// of the device config. When a queue is full, the oldest message in it is dropped.
 #[allow(missing_debug_implementations)]
 pub struct NodeMbox {
     rx_pdos: &'static [Pdo],
     sdo_cob_id: AtomicCell<Option<CanId>>,
+    sdo_interface: AtomicCell<BusInterface>,
+    last_nmt: AtomicCell<Option<(BusInterface, [u8; 2], u64)>>,
+    watchdog: Watchdog,
     sdo_receiver: SdoReceiver,
-    nmt_mbox: AtomicCell<Option<CanMessage>>,
+    rpdo_queue: MsgQueue,
+    sdo_queue: MsgQueue,
+    nmt_queue: MsgQueue,
+    tx_queue: TxQueue,
     lss_receiver: LssReceiver,
     sync_flag: AtomicCell<bool>,
+    sync_time_us: AtomicCell<Option<u64>>,
+    sync_count: AtomicCell<u8>,
+    cache_clock: CacheClock,
+    /// A bit mask of the node IDs whose heartbeats are consumed
+    consumed_heartbeats: AtomicCell<u128>,
+    /// A bit mask of the consumed node IDs whose heartbeats were received since the last process
+    heartbeats_seen: AtomicCell<u128>,
     notify_cb: AtomicCell<Option<&'static (dyn Fn() + Sync)>>,
+    filter_changed: AtomicCell<bool>,
+    filter_change_cb: AtomicCell<Option<&'static (dyn Fn() + Sync)>>,
+    loopback_rx: AtomicCell<LoopbackRx>,
 }
 
 impl NodeMbox {
@@ -28,24 +89,91 @@ impl NodeMbox {
     /// # Args
     ///
     /// - `rx_pdos`: A slice of Pdo objects for all of the receive PDOs
-    pub const fn new(rx_pdos: &'static [Pdo], sdo_buffer: &'static mut [u8]) -> Self {
+    /// - `sdo_buffer`: Buffer used by the SDO server for segmented and block transfers
+    /// - `rpdo_queue`: Storage for received RPDO frames
+    /// - `sdo_queue`: Storage for received SDO requests
+    /// - `nmt_queue`: Storage for received NMT and LSS frames
+    pub const fn new(
+        rx_pdos: &'static [Pdo],
+        sdo_buffer: &'static BufferCell<[u8]>,
+        rpdo_queue: &'static BufferCell<[Option<CanMessage>]>,
+        sdo_queue: &'static BufferCell<[Option<CanMessage>]>,
+        nmt_queue: &'static BufferCell<[Option<CanMessage>]>,
+    ) -> Self {
         let sdo_cob_id = AtomicCell::new(None);
         let sdo_receiver = SdoReceiver::new(sdo_buffer);
-        let nmt_mbox = AtomicCell::new(None);
+        let rpdo_queue = MsgQueue::new(rpdo_queue);
+        let sdo_queue = MsgQueue::new(sdo_queue);
+        let nmt_queue = MsgQueue::new(nmt_queue);
         let lss_receiver = LssReceiver::new();
         let sync_flag = AtomicCell::new(false);
+        let sync_time_us = AtomicCell::new(None);
+        let sync_count = AtomicCell::new(0);
         let notify_cb = AtomicCell::new(None);
+        let filter_changed = AtomicCell::new(false);
+        let filter_change_cb = AtomicCell::new(None);
         Self {
             rx_pdos,
             sdo_cob_id,
+            sdo_interface: AtomicCell::new(BusInterface::A),
+            last_nmt: AtomicCell::new(None),
+            watchdog: Watchdog::new(),
             sdo_receiver,
-            nmt_mbox,
+            rpdo_queue,
+            sdo_queue,
+            nmt_queue,
+            tx_queue: TxQueue::new(),
             lss_receiver,
             sync_flag,
+            sync_time_us,
+            sync_count,
+            cache_clock: CacheClock::new(),
+            consumed_heartbeats: AtomicCell::new(0),
+            heartbeats_seen: AtomicCell::new(0),
             notify_cb,
+            filter_changed,
+            filter_change_cb,
+            loopback_rx: AtomicCell::new(LoopbackRx::Disabled),
+        }
+    }
+
+    /// Add a second SDO buffer, which must be the same size as the `sdo_buffer`
+    ///
+    /// With two buffers, when a segmented or block download fills the SDO buffer, the buffers are
+    /// swapped and the download is acknowledged right away, so that the client can send the next
+    /// data while the full buffer is written to the object on the following call to
+    /// [`Node::process`](crate::Node::process). This speeds up large downloads to objects with slow
+    /// writes, such as a bootloader section writing to flash.
+    ///
+    /// A failed write is reported to the client by aborting the transfer.
+    pub const fn with_sdo_write_buffer(self, sdo_write_buffer: &'static BufferCell<[u8]>) -> Self {
+        Self {
+            sdo_receiver: self.sdo_receiver.with_write_buffer(sdo_write_buffer),
+            ..self
         }
     }
 
+    /// Add a transmit queue, for use with [`Node::process_queued`](crate::Node::process_queued)
+    ///
+    /// The capacity of the queue is the length of the buffer. When the queue is full, messages are
+    /// discarded according to the [overflow policy](Self::set_tx_overflow_policy), which defaults
+    /// to [`TxOverflowPolicy::DropLowestPriority`].
+    pub const fn with_tx_queue(self, tx_queue: &'static BufferCell<[TxSlot]>) -> Self {
+        Self {
+            tx_queue: TxQueue::with_buffer(tx_queue),
+            ..self
+        }
+    }
+
+    /// Set what happens to a message produced when the transmit queue is full
+    pub fn set_tx_overflow_policy(&self, policy: TxOverflowPolicy) {
+        self.tx_queue.set_policy(policy);
+    }
+
+    pub(crate) fn tx_queue(&self) -> &TxQueue {
+        &self.tx_queue
+    }
+
     /// Set a callback for notification when a message is received and requires processing.
     ///
     /// It must be static. Usually this will be a static fn, but in some circumstances, it may be
@@ -54,22 +182,167 @@ impl NodeMbox {
         self.notify_cb.store(Some(callback));
     }
 
+    /// Accept loopback self-test frames, for a node with a loopback test
+    pub(crate) fn enable_loopback_test(&self) {
+        self.loopback_rx.store(LoopbackRx::Idle);
+    }
+
+    /// Wait for a loopback self-test frame
+    pub(crate) fn arm_loopback(&self) {
+        self.loopback_rx.store(LoopbackRx::Armed);
+    }
+
+    /// Stop waiting for a loopback self-test frame
+    pub(crate) fn disarm_loopback(&self) {
+        self.loopback_rx.store(LoopbackRx::Idle);
+    }
+
+    /// Take the loopback self-test frame received since [`arm_loopback`](Self::arm_loopback)
+    pub(crate) fn take_loopback_frame(&self) -> Option<CanMessage> {
+        match self.loopback_rx.load() {
+            LoopbackRx::Received(msg) => {
+                self.disarm_loopback();
+                Some(msg)
+            }
+            _ => None,
+        }
+    }
+
     fn notify(&self) {
         if let Some(notify_cb) = self.notify_cb.load() {
             notify_cb();
         }
     }
 
+    /// Get the clock used to decide when cached callback objects are read again
+    ///
+    /// See [`CachedCallbackObject`](crate::object_dict::CachedCallbackObject). It is updated on
+    /// each call to [`Node::process`](crate::Node::process), and each time a SYNC is received.
+    pub fn cache_clock(&self) -> &CacheClock {
+        &self.cache_clock
+    }
+
+    /// Get the application watchdog
+    ///
+    /// The watchdog is configured through the [`Node`](crate::Node), but is held here so that it
+    /// can be checked with [`Watchdog::check`] from outside of the task which runs the node, e.g.
+    /// from a timer interrupt.
+    pub fn watchdog(&self) -> &Watchdog {
+        &self.watchdog
+    }
+
+    /// Set a callback for notification when the acceptance filters change
+    ///
+    /// The callback is called from [`Node::process`](crate::Node::process) after a change to any of
+    /// the COB-IDs consumed by the node, including when the node boots, and should arrange for the
+    /// hardware filters to be reprogrammed from [`accept_filters`](Self::accept_filters).
+    pub fn set_filter_change_callback(&self, callback: &'static (dyn Fn() + Sync)) {
+        self.filter_change_cb.store(Some(callback));
+    }
+
+    /// Get an exact-match acceptance filter for each COB-ID consumed by the node
+    ///
+    /// This covers NMT commands, SYNC, LSS requests, SDO requests, each valid RPDO, the heartbeats
+    /// monitored by a [`Failsafe`](crate::failsafe::Failsafe), and the loopback self-test frame if
+    /// the node has a loopback test. Any frame which does not match one
+    /// of the filters is ignored by [`store_message`](Self::store_message), so it can be dropped by
+    /// the CAN controller instead. See
+    /// [`reduce_filters`](crate::reduce_filters) to fit the filters into a limited number of
+    /// hardware filter banks.
+    pub fn accept_filters(&self) -> impl Iterator<Item = AcceptFilter> + '_ {
+        [NMT_CMD_ID, SYNC_ID, LSS_REQ_ID]
+            .into_iter()
+            .chain(self.sdo_cob_id.load())
+            .chain(
+                self.rx_pdos
+                    .iter()
+                    .filter(|rpdo| rpdo.valid())
+                    .map(|rpdo| rpdo.cob_id()),
+            )
+            .chain(heartbeat_ids(self.consumed_heartbeats.load()))
+            .chain(
+                (!matches!(self.loopback_rx.load(), LoopbackRx::Disabled)).then_some(TEST_FRAME_ID),
+            )
+            .map(AcceptFilter::exact)
+    }
+
+    /// Call the filter change callback if any consumed COB-ID has changed since the last call
+    pub(crate) fn check_filter_change(&self) {
+        let mut changed = self.filter_changed.take();
+        for rpdo in self.rx_pdos {
+            changed |= rpdo.take_filter_changed();
+        }
+        if changed {
+            if let Some(cb) = self.filter_change_cb.load() {
+                cb();
+            }
+        }
+    }
+
+    /// Set the node IDs whose heartbeats are consumed, as a bit mask
+    pub(crate) fn set_consumed_heartbeats(&self, mask: u128) {
+        if self.consumed_heartbeats.load() != mask {
+            self.consumed_heartbeats.store(mask);
+            self.filter_changed.store(true);
+        }
+    }
+
+    /// Take the bit mask of the consumed node IDs whose heartbeats were received since the last
+    /// call
+    pub(crate) fn take_heartbeats(&self) -> u128 {
+        self.heartbeats_seen.take()
+    }
+
     pub(crate) fn set_sdo_cob_id(&self, cob_id: Option<CanId>) {
-        self.sdo_cob_id.store(cob_id);
+        if self.sdo_cob_id.load() != cob_id {
+            self.sdo_cob_id.store(cob_id);
+            self.filter_changed.store(true);
+        }
     }
 
     pub(crate) fn sdo_receiver(&self) -> &SdoReceiver {
         &self.sdo_receiver
     }
 
+    /// Pass the next queued SDO request to the SDO receiver
+    ///
+    /// Returns false if there were no queued requests
+    pub(crate) fn next_sdo_request(&self) -> bool {
+        match self.sdo_queue.pop() {
+            Some(msg) => {
+                self.sdo_receiver.handle_queued_req(msg.data());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if SDO requests are waiting to be processed
+    pub(crate) fn sdo_pending(&self) -> bool {
+        !self.sdo_queue.is_empty()
+    }
+
+    /// Read the next queued NMT or LSS message
     pub(crate) fn read_nmt_mbox(&self) -> Option<CanMessage> {
-        self.nmt_mbox.take()
+        self.nmt_queue.pop()
+    }
+
+    /// Read the next queued RPDO frame
+    pub(crate) fn read_rpdo(&self) -> Option<CanMessage> {
+        self.rpdo_queue.pop()
+    }
+
+    /// Get the number of messages dropped because their queue was full
+    ///
+    /// Returns a tuple of (RPDO, SDO, NMT/LSS) drop counts. A non-zero count indicates that the
+    /// corresponding queue depth may need to be increased, or that `process` must be called more
+    /// often.
+    pub fn dropped_counts(&self) -> (u32, u32, u32) {
+        (
+            self.rpdo_queue.dropped(),
+            self.sdo_queue.dropped(),
+            self.nmt_queue.dropped(),
+        )
     }
 
     pub(crate) fn lss_receiver(&self) -> &LssReceiver {
@@ -80,24 +353,106 @@ impl NodeMbox {
         self.sync_flag.take()
     }
 
+    /// Read the reception time of the last SYNC message, if it was stored with one
+    pub(crate) fn read_sync_time(&self) -> Option<u64> {
+        self.sync_time_us.take()
+    }
+
+    /// Read the counter of the most recently received SYNC
+    ///
+    /// If the SYNC producer does not send a counter, the received SYNCs are counted instead, from
+    /// 1 to 255. Returns 0 if no SYNC has been received.
+    pub(crate) fn sync_count(&self) -> u8 {
+        self.sync_count.load()
+    }
+
+    /// Store a received CAN message, along with the time at which it was received
+    ///
+    /// `time_us` uses the same clock as the time passed to [`Node::process`](crate::Node::process).
+    /// The time of a received SYNC message is used to enforce the synchronous window length
+    /// (object 0x1007); when messages are stored with [`store_message`](Self::store_message), the
+    /// SYNC is assumed to have been received at the time of the next call to `process`.
+    pub fn store_message_at(&self, msg: CanMessage, time_us: u64) -> Result<(), CanMessage> {
+        if msg.id() == SYNC_ID {
+            self.sync_time_us.store(Some(time_us));
+        }
+        self.store_message(msg)
+    }
+
+    /// Store a CAN message received on one of two redundant interfaces
+    ///
+    /// This is used when one node is bridged across two CAN buses, such as by
+    /// [`DualBusRunner`](crate::DualBusRunner), and otherwise behaves like
+    /// [`store_message_at`](Self::store_message_at), with the following additions:
+    ///
+    /// - The interface of the most recent SDO request is recorded, so that the response can be sent
+    ///   back on the same interface. A client should only use one of the interfaces at a time.
+    /// - A master on a redundant network sends each NMT command on both buses. When an identical NMT
+    ///   command is received on the other interface within 100 ms, it is taken to be the copy of
+    ///   the first, and is dropped so that the command is only acted on once.
+    pub fn store_message_from(
+        &self,
+        msg: CanMessage,
+        interface: BusInterface,
+        time_us: u64,
+    ) -> Result<(), CanMessage> {
+        let id = msg.id();
+        if id == NMT_CMD_ID {
+            if let Ok(data) = <[u8; 2]>::try_from(msg.data()) {
+                let duplicate = self.last_nmt.load().is_some_and(|(last_if, last_data, t)| {
+                    last_if != interface
+                        && last_data == data
+                        && time_us.saturating_sub(t) < NMT_DUPLICATE_WINDOW_US
+                });
+                if duplicate {
+                    self.last_nmt.store(None);
+                    return Ok(());
+                }
+                self.last_nmt.store(Some((interface, data, time_us)));
+            }
+        } else if Some(id) == self.sdo_cob_id.load() {
+            self.sdo_interface.store(interface);
+        }
+        self.store_message_at(msg, time_us)
+    }
+
+    /// Get the interface on which the most recent SDO request was received
+    pub(crate) fn sdo_interface(&self) -> BusInterface {
+        self.sdo_interface.load()
+    }
+
     /// Store a received CAN message
+    ///
+    /// Returns the message as an error if it is not consumed by the node, or if it is a malformed
+    /// message which is dropped, such as an SDO request which is not 8 bytes long.
     pub fn store_message(&self, msg: CanMessage) -> Result<(), CanMessage> {
         let id = msg.id();
-        if id == zencan_common::messages::NMT_CMD_ID {
-            self.nmt_mbox.store(Some(msg));
+        if id == NMT_CMD_ID {
+            self.nmt_queue.push(msg);
             self.notify();
             return Ok(());
         }
 
-        if id == zencan_common::messages::SYNC_ID {
+        if id == SYNC_ID {
+            let count = SyncObject::from(msg).count();
+            self.sync_count
+                .fetch_update(|last| match count {
+                    0 => Some(last.checked_add(1).unwrap_or(1)),
+                    _ => Some(count),
+                })
+                .ok();
+            self.cache_clock.sync_received();
             self.sync_flag.store(true);
             self.notify();
             return Ok(());
         }
 
-        if id == zencan_common::messages::LSS_REQ_ID {
+        if id == LSS_REQ_ID {
             if let Ok(lss_req) = msg.data().try_into() {
-                if self.lss_receiver.handle_req(lss_req) {
+                // Switch state requests are handled immediately; the rest are queued in order with
+                // NMT commands
+                if !self.lss_receiver.handle_switch_state(lss_req) {
+                    self.nmt_queue.push(msg);
                     self.notify();
                 }
             } else {
@@ -107,24 +462,67 @@ impl NodeMbox {
             return Ok(());
         }
 
+        if let CanId::Std(raw) = id {
+            let node_id = raw.wrapping_sub(HEARTBEAT_ID);
+            if (1..=127).contains(&node_id) && self.consumed_heartbeats.load() & (1 << node_id) != 0
+            {
+                self.heartbeats_seen
+                    .fetch_update(|seen| Some(seen | 1 << node_id))
+                    .ok();
+                return Ok(());
+            }
+        }
+
         for rpdo in self.rx_pdos {
             if !rpdo.valid() {
                 continue;
             }
             if id == rpdo.cob_id() {
-                let mut data = [0u8; 8];
-                data[0..msg.data().len()].copy_from_slice(msg.data());
-                rpdo.buffered_value.store(Some(data));
+                self.rpdo_queue.push(msg);
+                self.notify();
                 return Ok(());
             }
         }
 
         if let Some(cob_id) = self.sdo_cob_id.load() {
             if id == cob_id {
-                self.sdo_receiver.handle_req(msg.data());
+                // CiA 301 requires every SDO request to be a data frame with 8 bytes. A remote
+                // frame or a short frame cannot be attributed to a transfer, so it is dropped
+                // without a response, rather than taking a place in the queue.
+                if msg.is_rtr() || msg.data().len() != 8 {
+                    warn!("Dropping malformed SDO request");
+                    return Err(msg);
+                }
+                // Block segments are written directly to the SDO buffer as they arrive; other
+                // requests wait in the queue for processing
+                if self.sdo_receiver.state() == ReceiverState::Normal {
+                    self.sdo_queue.push(msg);
+                    self.notify();
+                } else if self.sdo_receiver.handle_req(msg.data()) {
+                    self.notify();
+                }
+                return Ok(());
+            }
+        }
+
+        if id == TEST_FRAME_ID {
+            let waiting = self.loopback_rx.fetch_update(|rx| match rx {
+                LoopbackRx::Armed => Some(LoopbackRx::Received(msg)),
+                _ => None,
+            });
+            if waiting.is_ok() {
+                self.notify();
+                return Ok(());
             }
         }
 
         Err(msg)
     }
 }
+
+/// Get the heartbeat COB-ID of each node ID in a bit mask
+fn heartbeat_ids(mask: u128) -> impl Iterator<Item = CanId> {
+    (1..=127u8)
+        .filter(move |&node_id| mask & (1 << node_id) != 0)
+        .map(CanId::heartbeat)
+}
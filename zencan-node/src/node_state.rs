@@ -1,5 +1,17 @@
 //! Implements node state struct
+use zencan_common::{
+    messages::NmtState, pdo_stamp::TpdoStamp, sdo::SdoServerStatus, AtomicCell, NodeId,
+};
+
+use crate::access_trace::AccessTrace;
+use crate::cob_id::CobIds;
+use crate::debug_log::DebugLog;
+use crate::error_history::ErrorHistory;
+use crate::event_log::EventLog;
+use crate::loopback_test::LoopbackTest;
+use crate::nmt_protection::NmtProtection;
 use crate::object_dict::ObjectFlagSync;
+use crate::pdo_statistics::PdoStatistics;
 
 use crate::pdo::Pdo;
 use crate::storage::StorageContext;
@@ -14,6 +26,99 @@ pub trait NodeStateAccess: Sync + Send {
     fn get_pdo_sync(&self) -> &ObjectFlagSync;
     /// Get the storage context object
     fn storage_context(&self) -> &StorageContext;
+    /// Get the cell holding the most recent node status
+    fn get_status(&self) -> &AtomicCell<NodeSnapshot>;
+    /// Get the EMCY and SDO COB-IDs
+    fn get_cob_ids(&self) -> &CobIds;
+
+    /// Read a copy of the most recent node status
+    ///
+    /// The status is read in a single critical section, so it is always self-consistent.
+    fn snapshot(&self) -> NodeSnapshot {
+        self.get_status().load()
+    }
+
+    /// Get the object access trace, if the node has one
+    fn access_trace(&self) -> Option<&AccessTrace> {
+        None
+    }
+
+    /// Get the debug log, if the node has one
+    fn debug_log(&self) -> Option<&DebugLog> {
+        None
+    }
+
+    /// Get the event log, if the node has one
+    fn event_log(&self) -> Option<&EventLog> {
+        None
+    }
+
+    /// Get the loopback self-test, if the node has one
+    fn loopback_test(&self) -> Option<&LoopbackTest> {
+        None
+    }
+
+    /// Get the PDO statistics, if the node keeps them
+    fn pdo_statistics(&self) -> Option<&PdoStatistics> {
+        None
+    }
+
+    /// Get the policy for NMT commands received from the bus, if the node has one
+    fn nmt_protection(&self) -> Option<&NmtProtection> {
+        None
+    }
+
+    /// Get the error history, if the node has one
+    fn error_history(&self) -> Option<ErrorHistory<'_>> {
+        None
+    }
+
+    /// Get the stamp embedded in a TPDO
+    fn tpdo_stamp(&self, _tpdo: usize) -> TpdoStamp {
+        TpdoStamp::None
+    }
+}
+
+/// A point-in-time copy of the node status
+///
+/// The status is published by the [`Node`](crate::Node) at the end of each call to
+/// [`Node::process`](crate::Node::process), so it may be read from any thread -- e.g. to display
+/// node status in an application UI or telemetry loop -- without access to the `Node` object.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NodeSnapshot {
+    /// The current node ID
+    pub node_id: NodeId,
+    /// The current NMT state
+    pub nmt_state: NmtState,
+    /// The value of the error register, object 0x1001
+    pub error_register: u8,
+    /// True when an SDO transfer is in progress
+    pub sdo_transfer_active: bool,
+    /// The transfer in progress on the SDO server, if any
+    pub sdo_server: SdoServerStatus,
+    /// True when a new node ID has been assigned, but not yet applied
+    pub node_id_change_pending: bool,
+}
+
+impl NodeSnapshot {
+    /// Create a snapshot representing a node which has not yet been processed
+    pub const fn new() -> Self {
+        Self {
+            node_id: NodeId::Unconfigured,
+            nmt_state: NmtState::Bootup,
+            error_register: 0,
+            sdo_transfer_active: false,
+            sdo_server: SdoServerStatus::IDLE,
+            node_id_change_pending: false,
+        }
+    }
+}
+
+impl Default for NodeSnapshot {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// The NodeState provides config-dependent storage to the [`Node`](crate::Node) object
@@ -21,32 +126,141 @@ pub trait NodeStateAccess: Sync + Send {
 /// The node state has to get instantiated (statically) by zencan-build, based on the device config
 /// file. It is then provided to the node by the application when it is instantiated, and accessed
 /// via the [`NodeStateAccess`] trait.
+///
+/// The storage which scales with the device config is sized by the const generics, so that a
+/// device only pays for the features it uses:
+///
+/// - `N_RPDO` and `N_TPDO`: the number of receive and transmit PDOs
+/// - `N_ERROR_HISTORY`: the depth of the [error history](crate::error_history) in object 0x1003,
+///   which is 0 when the device has no error history
+///
+/// The heartbeat consumer times (object 0x1016) are stored in a generated array object sized by
+/// the device config, and a node has a single SDO server, so neither requires storage here. The
+/// memory used by each is listed in the memory report written by zencan-build.
 #[allow(missing_debug_implementations)]
-pub struct NodeState<const N_RPDO: usize, const N_TPDO: usize> {
+pub struct NodeState<const N_RPDO: usize, const N_TPDO: usize, const N_ERROR_HISTORY: usize = 0> {
     rpdos: [Pdo; N_RPDO],
     tpdos: [Pdo; N_TPDO],
     pdo_sync: ObjectFlagSync,
     storage_context: StorageContext,
+    status: AtomicCell<NodeSnapshot>,
+    cob_ids: CobIds,
+    access_trace: Option<&'static AccessTrace>,
+    debug_log: Option<&'static DebugLog>,
+    event_log: Option<&'static EventLog>,
+    loopback_test: Option<&'static LoopbackTest>,
+    pdo_statistics: Option<&'static PdoStatistics>,
+    nmt_protection: Option<&'static NmtProtection>,
+    tpdo_stamps: [TpdoStamp; N_TPDO],
+    error_history: [AtomicCell<u32>; N_ERROR_HISTORY],
+    error_count: AtomicCell<u8>,
 }
 
-impl<const N_RPDO: usize, const N_TPDO: usize> Default for NodeState<N_RPDO, N_TPDO> {
+impl<const N_RPDO: usize, const N_TPDO: usize, const N_ERROR_HISTORY: usize> Default
+    for NodeState<N_RPDO, N_TPDO, N_ERROR_HISTORY>
+{
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<const N_RPDO: usize, const N_TPDO: usize> NodeState<N_RPDO, N_TPDO> {
+impl<const N_RPDO: usize, const N_TPDO: usize, const N_ERROR_HISTORY: usize>
+    NodeState<N_RPDO, N_TPDO, N_ERROR_HISTORY>
+{
     /// Create a new NodeState object
     pub const fn new() -> Self {
         let rpdos = [const { Pdo::new() }; N_RPDO];
         let tpdos = [const { Pdo::new() }; N_TPDO];
         let pdo_sync = ObjectFlagSync::new();
         let storage_context = StorageContext::new();
+        let status = AtomicCell::new(NodeSnapshot::new());
+        let cob_ids = CobIds::new();
         Self {
             rpdos,
             tpdos,
             pdo_sync,
             storage_context,
+            status,
+            cob_ids,
+            access_trace: None,
+            debug_log: None,
+            event_log: None,
+            loopback_test: None,
+            pdo_statistics: None,
+            nmt_protection: None,
+            tpdo_stamps: [TpdoStamp::None; N_TPDO],
+            error_history: [const { AtomicCell::new(0) }; N_ERROR_HISTORY],
+            error_count: AtomicCell::new(0),
+        }
+    }
+
+    /// Record object accesses in an access trace
+    ///
+    /// This is used by generated code when the device config enables the access trace.
+    pub const fn with_access_trace(self, access_trace: &'static AccessTrace) -> Self {
+        Self {
+            access_trace: Some(access_trace),
+            ..self
+        }
+    }
+
+    /// Provide a debug log, written with [`Node::log_str`](crate::Node::log_str)
+    ///
+    /// This is used by generated code when the device config enables the debug log.
+    pub const fn with_debug_log(self, debug_log: &'static DebugLog) -> Self {
+        Self {
+            debug_log: Some(debug_log),
+            ..self
+        }
+    }
+
+    /// Provide an event log, written with [`Node::log_event`](crate::Node::log_event)
+    ///
+    /// This is used by generated code when the device config enables the event log.
+    pub const fn with_event_log(self, event_log: &'static EventLog) -> Self {
+        Self {
+            event_log: Some(event_log),
+            ..self
+        }
+    }
+
+    /// Provide a loopback self-test
+    ///
+    /// This is used by generated code when the device config sets `loopback_test`.
+    pub const fn with_loopback_test(self, loopback_test: &'static LoopbackTest) -> Self {
+        Self {
+            loopback_test: Some(loopback_test),
+            ..self
+        }
+    }
+
+    /// Count the PDOs sent and received, and record when each was last seen
+    ///
+    /// This is used by generated code when the device config enables PDO statistics.
+    pub const fn with_pdo_statistics(self, pdo_statistics: &'static PdoStatistics) -> Self {
+        Self {
+            pdo_statistics: Some(pdo_statistics),
+            ..self
+        }
+    }
+
+    /// Ignore some NMT commands received from the bus, unless they are allowed by the policy
+    ///
+    /// This is used by generated code when the device config has an `[nmt_protection]` section.
+    pub const fn with_nmt_protection(self, nmt_protection: &'static NmtProtection) -> Self {
+        Self {
+            nmt_protection: Some(nmt_protection),
+            ..self
+        }
+    }
+
+    /// Embed a stamp in the last byte(s) of each TPDO
+    ///
+    /// This is used by generated code when the device config sets `tpdo_stamps`.
+    pub const fn with_tpdo_stamps(self, tpdo_stamps: [TpdoStamp; N_TPDO]) -> Self {
+        Self {
+            tpdo_stamps,
+            ..self
         }
     }
 
@@ -71,9 +285,38 @@ impl<const N_RPDO: usize, const N_TPDO: usize> NodeState<N_RPDO, N_TPDO> {
     pub const fn storage_context(&'static self) -> &'static StorageContext {
         &self.storage_context
     }
+
+    /// Access the EMCY and SDO COB-IDs as a const function
+    pub const fn cob_ids(&'static self) -> &'static CobIds {
+        &self.cob_ids
+    }
+
+    /// Access the error history as a const function
+    ///
+    /// This is required so that it can be shared with the error history object in generated code
+    pub const fn error_history(&'static self) -> ErrorHistory<'static> {
+        ErrorHistory::new(&self.error_history, &self.error_count)
+    }
+
+    /// Access the cell holding the most recent node status as a const function
+    ///
+    /// This is required so that it can be shared with the SDO server status object in generated
+    /// code
+    pub const fn status(&'static self) -> &'static AtomicCell<NodeSnapshot> {
+        &self.status
+    }
+
+    /// Read a copy of the most recent node status
+    ///
+    /// This is cheap enough to call from a UI or telemetry loop; it takes a single critical section
+    pub fn snapshot(&self) -> NodeSnapshot {
+        self.status.load()
+    }
 }
 
-impl<const N_RPDO: usize, const N_TPDO: usize> NodeStateAccess for NodeState<N_RPDO, N_TPDO> {
+impl<const N_RPDO: usize, const N_TPDO: usize, const N_ERROR_HISTORY: usize> NodeStateAccess
+    for NodeState<N_RPDO, N_TPDO, N_ERROR_HISTORY>
+{
     fn get_rpdos(&self) -> &[Pdo] {
         &self.rpdos
     }
@@ -89,4 +332,48 @@ impl<const N_RPDO: usize, const N_TPDO: usize> NodeStateAccess for NodeState<N_R
     fn storage_context(&self) -> &StorageContext {
         &self.storage_context
     }
+
+    fn get_status(&self) -> &AtomicCell<NodeSnapshot> {
+        &self.status
+    }
+
+    fn get_cob_ids(&self) -> &CobIds {
+        &self.cob_ids
+    }
+
+    fn access_trace(&self) -> Option<&AccessTrace> {
+        self.access_trace
+    }
+
+    fn debug_log(&self) -> Option<&DebugLog> {
+        self.debug_log
+    }
+
+    fn event_log(&self) -> Option<&EventLog> {
+        self.event_log
+    }
+
+    fn loopback_test(&self) -> Option<&LoopbackTest> {
+        self.loopback_test
+    }
+
+    fn pdo_statistics(&self) -> Option<&PdoStatistics> {
+        self.pdo_statistics
+    }
+
+    fn nmt_protection(&self) -> Option<&NmtProtection> {
+        self.nmt_protection
+    }
+
+    fn error_history(&self) -> Option<ErrorHistory<'_>> {
+        if N_ERROR_HISTORY > 0 {
+            Some(ErrorHistory::new(&self.error_history, &self.error_count))
+        } else {
+            None
+        }
+    }
+
+    fn tpdo_stamp(&self, tpdo: usize) -> TpdoStamp {
+        self.tpdo_stamps.get(tpdo).copied().unwrap_or_default()
+    }
 }
@@ -0,0 +1,294 @@
+//! Read-only objects whose value is computed by the application, and cached between reads
+use zencan_common::{
+    objects::{AccessType, DataType, ObjectCode, PdoMapping, SubInfo},
+    sdo::AbortCode,
+    AtomicCell,
+};
+
+use super::{sub_objects::read_le_bytes, ObjectAccess};
+
+/// The signature of the function which reads the value of a [`CachedCallbackObject`]
+///
+/// It fills the buffer with the little-endian encoded value, or returns an error which is passed on
+/// to the SDO client. A failed read is not cached, so the next access calls it again.
+pub type CachedReadFn = dyn Fn(&mut [u8]) -> Result<(), AbortCode> + Sync;
+
+/// When the value of a [`CachedCallbackObject`] is read again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CacheRefresh {
+    /// The value is read at most once per interval, in microseconds
+    Interval(u64),
+    /// The value is read at most once per SYNC received
+    Sync,
+}
+
+/// The time and SYNC count used to decide when cached values are stale
+///
+/// The [`NodeMbox`](crate::NodeMbox) holds the clock for a node, which is updated on each call to
+/// [`Node::process`](crate::Node::process), and each time a SYNC is received. Get it with
+/// [`NodeMbox::cache_clock`](crate::NodeMbox::cache_clock) to register a [`CachedCallbackObject`].
+#[allow(missing_debug_implementations)]
+pub struct CacheClock {
+    now_us: AtomicCell<u64>,
+    sync_epoch: AtomicCell<u32>,
+}
+
+impl Default for CacheClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CacheClock {
+    /// Create a new clock, at time 0 with no SYNC received
+    pub const fn new() -> Self {
+        Self {
+            now_us: AtomicCell::new(0),
+            sync_epoch: AtomicCell::new(0),
+        }
+    }
+
+    /// Set the current time, in microseconds
+    ///
+    /// The node sets this from its own monotonic clock, which does not wrap.
+    pub fn tick(&self, now_us: u64) {
+        self.now_us.store(now_us);
+    }
+
+    /// Record the reception of a SYNC
+    pub fn sync_received(&self) {
+        self.sync_epoch
+            .fetch_update(|epoch| Some(epoch.wrapping_add(1)))
+            .ok();
+    }
+
+    fn now_us(&self) -> u64 {
+        self.now_us.load()
+    }
+
+    fn sync_epoch(&self) -> u32 {
+        self.sync_epoch.load()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct CacheEntry<const N: usize> {
+    value: [u8; N],
+    time_us: u64,
+    sync_epoch: u32,
+}
+
+/// A read-only var whose value is read from the application by a callback, and cached
+///
+/// Some values, such as a sensor reading which requires a bus transaction, are too expensive to
+/// read each time the object is accessed. The callback registered with
+/// [`register_handler`](Self::register_handler) is called at most once per [`CacheRefresh`]
+/// period, and SDO reads and mapped TPDOs in between are served from the cached value. The value
+/// is read when it is accessed, so a value which is never accessed is never read.
+///
+/// The object is registered as the handler of an `application_callback` object:
+///
+/// ```rust
+/// use zencan_node::common::{objects::DataType, sdo::AbortCode};
+/// use zencan_node::object_dict::{CacheClock, CacheRefresh, CachedCallbackObject, ObjectAccess};
+///
+/// fn read_temperature(buf: &mut [u8]) -> Result<(), AbortCode> {
+///     // An expensive read from a sensor
+///     buf.copy_from_slice(&21.5f32.to_le_bytes());
+///     Ok(())
+/// }
+///
+/// // Read the sensor at most every 100ms
+/// static TEMPERATURE: CachedCallbackObject<4> =
+///     CachedCallbackObject::new(DataType::Real32, CacheRefresh::Interval(100_000));
+/// // In an application, this is `NODE_MBOX.cache_clock()`
+/// static CLOCK: CacheClock = CacheClock::new();
+///
+/// TEMPERATURE.register_handler(&read_temperature, &CLOCK);
+/// // The generated callback object is then pointed at it, e.g.
+/// // OBJECT2000.register_handler(&TEMPERATURE);
+/// assert_eq!(21.5, f32::from_bits(TEMPERATURE.read_u32(0).unwrap()));
+/// ```
+///
+/// The time is only updated on each call to [`Node::process`](crate::Node::process), so an
+/// interval shorter than the time between calls does not refresh the value more often than that.
+/// Concurrent accesses when the value is stale may each call the read function.
+#[allow(missing_debug_implementations)]
+pub struct CachedCallbackObject<const N: usize> {
+    data_type: DataType,
+    refresh: AtomicCell<CacheRefresh>,
+    handler: AtomicCell<Option<(&'static CachedReadFn, &'static CacheClock)>>,
+    cache: AtomicCell<Option<CacheEntry<N>>>,
+}
+
+impl<const N: usize> CachedCallbackObject<N> {
+    /// Create a new object holding an `N` byte value of `data_type`
+    pub const fn new(data_type: DataType, refresh: CacheRefresh) -> Self {
+        Self {
+            data_type,
+            refresh: AtomicCell::new(refresh),
+            handler: AtomicCell::new(None),
+            cache: AtomicCell::new(None),
+        }
+    }
+
+    /// Register the function which reads the value, and the clock used to decide when to call it
+    pub fn register_handler(&self, read_fn: &'static CachedReadFn, clock: &'static CacheClock) {
+        self.handler.store(Some((read_fn, clock)));
+        self.cache.store(None);
+    }
+
+    /// Change when the value is refreshed
+    pub fn set_refresh(&self, refresh: CacheRefresh) {
+        self.refresh.store(refresh);
+    }
+
+    /// Get when the value is refreshed
+    pub fn refresh(&self) -> CacheRefresh {
+        self.refresh.load()
+    }
+
+    /// Discard the cached value, so that it is read again on the next access
+    pub fn invalidate(&self) {
+        self.cache.store(None);
+    }
+
+    /// Get the cached value, without reading a new one
+    pub fn cached_value(&self) -> Option<[u8; N]> {
+        self.cache.load().map(|entry| entry.value)
+    }
+
+    /// Get the value, calling the read function if the cached value is stale
+    pub fn value(&self) -> Result<[u8; N], AbortCode> {
+        let Some((read_fn, clock)) = self.handler.load() else {
+            return Err(AbortCode::ResourceNotAvailable);
+        };
+        let now_us = clock.now_us();
+        let sync_epoch = clock.sync_epoch();
+        let fresh = self.cache.load().filter(|entry| match self.refresh.load() {
+            // A clock which went backwards, e.g. because the node was re-created, makes it stale
+            CacheRefresh::Interval(interval_us) => now_us
+                .checked_sub(entry.time_us)
+                .is_some_and(|age| age < interval_us),
+            CacheRefresh::Sync => entry.sync_epoch == sync_epoch,
+        });
+        if let Some(entry) = fresh {
+            return Ok(entry.value);
+        }
+
+        let mut value = [0; N];
+        read_fn(&mut value)?;
+        self.cache.store(Some(CacheEntry {
+            value,
+            time_us: now_us,
+            sync_epoch,
+        }));
+        Ok(value)
+    }
+}
+
+impl<const N: usize> ObjectAccess for CachedCallbackObject<N> {
+    fn read(&self, sub: u8, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        self.sub_info(sub)?;
+        Ok(read_le_bytes(&self.value()?, offset, buf))
+    }
+
+    fn read_size(&self, sub: u8) -> Result<usize, AbortCode> {
+        self.sub_info(sub)?;
+        Ok(N)
+    }
+
+    fn write(&self, sub: u8, _data: &[u8]) -> Result<(), AbortCode> {
+        self.sub_info(sub)?;
+        Err(AbortCode::ReadOnly)
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Var
+    }
+
+    fn sub_info(&self, sub: u8) -> Result<SubInfo, AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        Ok(SubInfo {
+            size: N,
+            data_type: self.data_type,
+            access_type: AccessType::Ro,
+            pdo_mapping: PdoMapping::Tpdo,
+            persist: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_interval_refresh() {
+        static READS: AtomicU32 = AtomicU32::new(0);
+        static CLOCK: CacheClock = CacheClock::new();
+        static OBJECT: CachedCallbackObject<4> =
+            CachedCallbackObject::new(DataType::UInt32, CacheRefresh::Interval(1000));
+
+        assert_eq!(Err(AbortCode::ResourceNotAvailable), OBJECT.read_u32(0));
+        OBJECT.register_handler(
+            &|buf| {
+                let count = READS.fetch_add(1, Ordering::Relaxed) + 1;
+                buf.copy_from_slice(&count.to_le_bytes());
+                Ok(())
+            },
+            &CLOCK,
+        );
+
+        assert_eq!(Ok(1), OBJECT.read_u32(0));
+        CLOCK.tick(999);
+        assert_eq!(Ok(1), OBJECT.read_u32(0));
+        CLOCK.tick(1000);
+        assert_eq!(Ok(2), OBJECT.read_u32(0));
+        assert_eq!(Some(2u32.to_le_bytes()), OBJECT.cached_value());
+
+        OBJECT.invalidate();
+        assert_eq!(Ok(3), OBJECT.read_u32(0));
+
+        // The value cannot be written
+        assert_eq!(Err(AbortCode::ReadOnly), OBJECT.write(0, &[0; 4]));
+        assert_eq!(Err(AbortCode::NoSuchSubIndex), OBJECT.read_u32(1));
+    }
+
+    #[test]
+    fn test_sync_refresh() {
+        static READS: AtomicU32 = AtomicU32::new(0);
+        static CLOCK: CacheClock = CacheClock::new();
+        static OBJECT: CachedCallbackObject<2> =
+            CachedCallbackObject::new(DataType::UInt16, CacheRefresh::Sync);
+
+        OBJECT.register_handler(
+            &|buf| {
+                let count = READS.fetch_add(1, Ordering::Relaxed) + 1;
+                if count == 3 {
+                    return Err(AbortCode::GeneralError);
+                }
+                buf.copy_from_slice(&(count as u16).to_le_bytes());
+                Ok(())
+            },
+            &CLOCK,
+        );
+
+        assert_eq!(Ok(1), OBJECT.read_u16(0));
+        CLOCK.tick(1_000_000);
+        assert_eq!(Ok(1), OBJECT.read_u16(0));
+        CLOCK.sync_received();
+        assert_eq!(Ok(2), OBJECT.read_u16(0));
+        assert_eq!(Ok(2), OBJECT.read_u16(0));
+
+        // A failed read is not cached
+        CLOCK.sync_received();
+        assert_eq!(Err(AbortCode::GeneralError), OBJECT.read_u16(0));
+        assert_eq!(Ok(4), OBJECT.read_u16(0));
+    }
+}
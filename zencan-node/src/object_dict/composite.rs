@@ -0,0 +1,223 @@
+use core::mem::MaybeUninit;
+
+use super::{find_object_entry, ODEntry, ObjectAccess};
+
+/// Error returned when combining object dictionary tables
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CompositeOdError {
+    /// The same object index is defined in more than one table
+    Overlap {
+        /// The duplicated object index
+        index: u16,
+    },
+    /// A table is not sorted by index
+    Unsorted {
+        /// The position of the unsorted table in the list of tables
+        table: usize,
+    },
+    /// The buffer provided for the merged table does not match the number of objects
+    BufferSize {
+        /// The number of objects in all tables
+        required: usize,
+        /// The size of the provided buffer
+        provided: usize,
+    },
+}
+
+/// An object dictionary composed of several tables
+///
+/// This allows firmware to be built from modules which each provide their own generated table of
+/// objects -- for example, a base profile dictionary, plus an application dictionary. Objects are
+/// looked up across all tables, and the tables are checked on creation to ensure that no object is
+/// defined more than once.
+///
+/// The [`Node`](crate::Node) operates on a single sorted table, which can be created from the
+/// composite using [`CompositeOd::merge_into`].
+///
+/// Note that the PDO mapping objects created for a generated table resolve mapped objects in the
+/// table they were created with. To allow mapping objects from other tables, point them at the
+/// merged table using [`PdoMappingObject::set_od`](crate::pdo::PdoMappingObject::set_od).
+#[allow(missing_debug_implementations)]
+pub struct CompositeOd<'a, const N: usize> {
+    tables: [&'a [ODEntry<'a>]; N],
+}
+
+impl<'a, const N: usize> CompositeOd<'a, N> {
+    /// Create a composite from a list of tables
+    ///
+    /// Each table must be sorted by index, as generated tables are, and no index may appear in more
+    /// than one table.
+    pub fn new(tables: [&'a [ODEntry<'a>]; N]) -> Result<Self, CompositeOdError> {
+        for (i, table) in tables.iter().enumerate() {
+            if table.windows(2).any(|w| w[0].index >= w[1].index) {
+                return Err(CompositeOdError::Unsorted { table: i });
+            }
+        }
+        let od = Self { tables };
+        let mut last = None;
+        for entry in od.iter() {
+            if last == Some(entry.index) {
+                return Err(CompositeOdError::Overlap { index: entry.index });
+            }
+            last = Some(entry.index);
+        }
+        Ok(od)
+    }
+
+    /// Lookup an entry by index in any of the tables
+    pub fn find_entry(&self, index: u16) -> Option<&'a ODEntry<'a>> {
+        self.tables
+            .iter()
+            .find_map(|table| find_object_entry(table, index))
+    }
+
+    /// Lookup an object by index in any of the tables
+    pub fn find_object(&self, index: u16) -> Option<&'a dyn ObjectAccess> {
+        self.find_entry(index).map(|entry| entry.data)
+    }
+
+    /// The total number of objects in all tables
+    pub fn len(&self) -> usize {
+        self.tables.iter().map(|t| t.len()).sum()
+    }
+
+    /// Returns true if none of the tables contain any objects
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the entries of all tables in order of increasing index
+    pub fn iter(&self) -> CompositeOdIter<'a, '_, N> {
+        CompositeOdIter {
+            tables: &self.tables,
+            positions: [0; N],
+        }
+    }
+
+    /// Write all entries, sorted by index, into `buf` and return the merged table
+    ///
+    /// `buf` must be exactly [`len`](Self::len) entries long. The returned table can be passed to
+    /// [`Node::new`](crate::Node::new) when `buf` has a `'static` lifetime.
+    pub fn merge_into<'b>(
+        &self,
+        buf: &'b mut [MaybeUninit<ODEntry<'a>>],
+    ) -> Result<&'b [ODEntry<'a>], CompositeOdError> {
+        let required = self.len();
+        if buf.len() != required {
+            return Err(CompositeOdError::BufferSize {
+                required,
+                provided: buf.len(),
+            });
+        }
+        for (slot, entry) in buf.iter_mut().zip(self.iter()) {
+            slot.write(*entry);
+        }
+        // Safety: Every element of buf was initialized above, and MaybeUninit<T> has the same
+        // layout as T
+        Ok(unsafe { &*(buf as *const [MaybeUninit<ODEntry<'a>>] as *const [ODEntry<'a>]) })
+    }
+}
+
+/// Iterator over the entries of a [`CompositeOd`], in order of increasing index
+#[allow(missing_debug_implementations)]
+pub struct CompositeOdIter<'a, 'b, const N: usize> {
+    tables: &'b [&'a [ODEntry<'a>]; N],
+    positions: [usize; N],
+}
+
+impl<'a, const N: usize> Iterator for CompositeOdIter<'a, '_, N> {
+    type Item = &'a ODEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut next: Option<(usize, &'a ODEntry<'a>)> = None;
+        for (i, table) in self.tables.iter().enumerate() {
+            if let Some(entry) = table.get(self.positions[i]) {
+                if next.map_or(true, |(_, n)| entry.index < n.index) {
+                    next = Some((i, entry));
+                }
+            }
+        }
+        let (i, entry) = next?;
+        self.positions[i] += 1;
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zencan_common::{
+        objects::{ObjectCode, SubInfo},
+        sdo::AbortCode,
+    };
+
+    use super::*;
+
+    struct Dummy;
+    impl ObjectAccess for Dummy {
+        fn read(&self, _sub: u8, _offset: usize, _buf: &mut [u8]) -> Result<usize, AbortCode> {
+            Ok(0)
+        }
+        fn read_size(&self, _sub: u8) -> Result<usize, AbortCode> {
+            Ok(0)
+        }
+        fn write(&self, _sub: u8, _data: &[u8]) -> Result<(), AbortCode> {
+            Ok(())
+        }
+        fn object_code(&self) -> ObjectCode {
+            ObjectCode::Var
+        }
+        fn sub_info(&self, _sub: u8) -> Result<SubInfo, AbortCode> {
+            Err(AbortCode::NoSuchSubIndex)
+        }
+    }
+    const fn entry(index: u16) -> ODEntry<'static> {
+        ODEntry {
+            index,
+            data: &Dummy,
+        }
+    }
+
+    static BASE: [ODEntry; 3] = [entry(0x1000), entry(0x1018), entry(0x2000)];
+    static APP: [ODEntry; 2] = [entry(0x1800), entry(0x3000)];
+
+    #[test]
+    fn test_composite_lookup_and_merge() {
+        let od = CompositeOd::new([&BASE, &APP]).unwrap();
+        assert_eq!(5, od.len());
+        assert!(od.find_object(0x1800).is_some());
+        assert!(od.find_object(0x2000).is_some());
+        assert!(od.find_object(0x2001).is_none());
+
+        let indices: Vec<u16> = od.iter().map(|e| e.index).collect();
+        assert_eq!(vec![0x1000, 0x1018, 0x1800, 0x2000, 0x3000], indices);
+
+        let mut buf = [const { MaybeUninit::uninit() }; 5];
+        let merged = od.merge_into(&mut buf).unwrap();
+        assert_eq!(0x1800, find_object_entry(merged, 0x1800).unwrap().index);
+
+        let mut short = [const { MaybeUninit::uninit() }; 4];
+        assert_eq!(
+            Err(CompositeOdError::BufferSize {
+                required: 5,
+                provided: 4
+            }),
+            od.merge_into(&mut short).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_composite_overlap() {
+        static OTHER: [ODEntry; 2] = [entry(0x2000), entry(0x4000)];
+        assert_eq!(
+            Some(CompositeOdError::Overlap { index: 0x2000 }),
+            CompositeOd::new([&BASE, &APP, &OTHER]).err()
+        );
+
+        static UNSORTED: [ODEntry; 2] = [entry(0x4001), entry(0x4000)];
+        assert_eq!(
+            Some(CompositeOdError::Unsorted { table: 1 }),
+            CompositeOd::new([&BASE, &UNSORTED]).err()
+        );
+    }
+}
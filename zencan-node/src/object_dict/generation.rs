@@ -0,0 +1,35 @@
+//! Change tracking for large objects
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// A counter which is incremented each time the value of an object changes
+///
+/// Objects larger than the SDO buffer cannot be uploaded with a single read, so their value may
+/// change part way through an upload. An object which can detect this embeds a `GenerationCounter`,
+/// calls [`increment`](Self::increment) whenever it stores a new value, and returns
+/// [`get`](Self::get) from [`ObjectAccess::generation`](super::ObjectAccess::generation) or
+/// [`SubObjectAccess::generation`](super::SubObjectAccess::generation). The SDO server then aborts
+/// an upload if the value changes before it is complete, rather than sending the client a mix of
+/// the old and new values.
+#[derive(Debug, Default)]
+pub struct GenerationCounter(AtomicU32);
+
+impl GenerationCounter {
+    /// Create a new counter
+    pub const fn new() -> Self {
+        Self(AtomicU32::new(0))
+    }
+
+    /// Get the current generation
+    pub fn get(&self) -> u32 {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Record a change of the value
+    ///
+    /// This should be called after each modification of the stored value, including each call to
+    /// `write_partial`, so that a read which began before the modification is detected when the
+    /// generation is checked after it.
+    pub fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Release);
+    }
+}
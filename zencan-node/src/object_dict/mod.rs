@@ -26,6 +26,29 @@
 //! `application_callback` objects at build time, so that a [`CallbackObject`] is inserted into the
 //! object dictionary as a placeholder to store the run-time provided object.
 //!
+//! Run-time provided objects can be mapped to PDOs in the same way as generated objects. When a
+//! TPDO is sent, the mapped sub objects are read through the handler, and when an RPDO is received,
+//! the values are written to it. To trigger event driven TPDOs, the handler should implement the
+//! event flag methods of [`ObjectAccess`], e.g. by returning an [`ObjectFlags`] from
+//! [`ProvidesSubObjects::flags`]. The handler must be registered with
+//! [`CallbackObject::register_handler`] before the object is mapped, as the mapping is validated
+//! using the [`SubInfo`](crate::common::objects::SubInfo) it provides.
+//!
+//! Values which are expensive to read, such as a sensor which must be polled over a bus, can be
+//! implemented by registering a [`CachedCallbackObject`] as the handler. It calls the application's
+//! read function at most once per interval, or once per SYNC, and serves the SDO reads and mapped
+//! TPDOs in between from the cached value.
+//!
+//! When the application only needs to know that an object was written, it can instead be declared
+//! with `on_write_callback = true`. The generated object keeps its own storage, and gets a
+//! `register_write_hook` method to register a [`WriteHook`] function, which is called with the sub
+//! index after each successful write via [`ObjectAccess`].
+//!
+//! Objects which use a feature that code generation does not support yet are generated as a
+//! [`StubObject`] when the node is built in permissive mode. A stub is a read-only var, and reads
+//! of it fail with
+//! [`AbortCode::ResourceNotAvailable`](crate::common::sdo::AbortCode::ResourceNotAvailable).
+//!
 //! # The ObjectAccess trait
 //!
 //! Any struct which implements the [`ObjectAccess`] trait can be used to represent an object in the
@@ -44,17 +67,34 @@
 //! Most sub objects can be implemented using one of the following existing types:
 //!
 //! - [`ScalarField<T>`]
+//! - [`AtomicScalarField<T>`]
+//! - [`ScaledField<T>`]
 //! - [`ByteField``]
 //! - [`NullTermByteField`]
 //! - [`ConstField`]
 //! - [`ConstByteRefField`]
 //!
+//! ## Byte order
+//!
+//! CiA 301 encodes all numeric values little-endian, and the bytes passed to and from
+//! [`SubObjectAccess::read`] and [`SubObjectAccess::write`] are always in this encoding, regardless
+//! of the byte order of the host. The provided sub object types store values natively and convert
+//! them explicitly with `to_le_bytes` and `from_le_bytes` on each access, so they behave the same
+//! on big-endian targets. Custom implementations should do the same, rather than copying the
+//! in-memory representation of a value.
+//!
 //! ## Example Custom Object Implementation
 //!
+//! The [`CanOpenObject`](crate::CanOpenObject) derive macro implements [`ProvidesSubObjects`] for a
+//! struct, based on `#[canopen(...)]` attributes on the fields which implement its sub objects. The
+//! sub index 0 of a record, containing the highest sub index, is generated automatically.
+//! Objects which need more control, e.g. to decide at run time which sub objects exist, can
+//! implement [`ProvidesSubObjects`] directly instead.
+//!
 //! ```rust
-//! use zencan_node::object_dict::{ConstField, ScalarField, ProvidesSubObjects, SubObjectAccess};
-//! use zencan_node::common::objects::{ObjectCode, SubInfo};
+//! use zencan_node::object_dict::{ObjectAccess, ScalarField, SubObjectAccess};
 //! use zencan_node::common::sdo::AbortCode;
+//! use zencan_node::CanOpenObject;
 //! // Example external API used to access a value for a sub field
 //! struct ExternalApi {}
 //!
@@ -110,8 +150,13 @@
 //!     }
 //! }
 //!
+//! #[derive(CanOpenObject)]
 //! struct CustomObject {
+//!     // Sub 1 is the u32 field stored in the object, implemented using ScalarField<u32>
+//!     #[canopen(sub = 1, data_type = "uint32", access = "rw", persist)]
 //!     stored_field: ScalarField<u32>,
+//!     // Sub 2 is a custom sub object which accesses the external API
+//!     #[canopen(sub = 2, data_type = "real32", access = "rw")]
 //!     external_field: ExternalSubObject,
 //! }
 //!
@@ -124,26 +169,12 @@
 //!     }
 //! }
 //!
-//! impl ProvidesSubObjects for CustomObject {
-//!     fn get_sub_object(&self, sub: u8) -> Option<(SubInfo, &dyn SubObjectAccess)> {
-//!         match sub {
-//!             // Sub 0 returns the highest sub index on the object
-//!             0 => Some((
-//!                 SubInfo::MAX_SUB_NUMBER,
-//!                 const { &ConstField::new(3u8.to_le_bytes()) },
-//!             )),
-//!             // Sub 1 returns the u32 field stored in the object, implemented using ScalarField<u32>
-//!             1 => Some((SubInfo::new_u32().rw_access().persist(true), &self.stored_field)),
-//!             // Sub 2 returns a custom sub object which accesses the external API
-//!             2 => Some((SubInfo::new_f32().rw_access().persist(false), &self.external_field)),
-//!             _ => None,
-//!         }
-//!     }
-//!
-//!     fn object_code(&self) -> ObjectCode {
-//!         ObjectCode::Record
-//!     }
-//! }
+//! static EXTERNAL_API: ExternalApi = ExternalApi {};
+//! let object = CustomObject::new(&EXTERNAL_API);
+//! // Sub 0 holds the highest sub index
+//! let mut buf = [0u8; 1];
+//! object.read(0, 0, &mut buf).unwrap();
+//! assert_eq!(2, buf[0]);
 //! ```
 //!
 //! # Object threading support
@@ -159,18 +190,50 @@
 //! possible that a client can get a "torn read". For writing data to an object, the partial write
 //! API is used, and has similar concerns.
 //!
+//! Objects can prevent torn reads by tracking changes with a [`GenerationCounter`], and returning
+//! it from [`ObjectAccess::generation`] (or [`SubObjectAccess::generation`]). The SDO server checks
+//! the generation each time it refills its buffer during an upload, and aborts the upload with
+//! [`AbortCode::GeneralError`](crate::common::sdo::AbortCode::GeneralError) if it has changed.
+//! Because a change can be detected, uploads of these objects also report their size to the
+//! client up front.
+//!
 //! # Object flags for TPDO event triggering
 //!
 //! Some objects support event flags, which can be set via [`ObjectAccess::set_event_flag`]. These
 //! are used to trigger TPDO transmission.
 //!
+//! Generated objects which can be mapped to a TPDO also get `_notify` variants of their setters
+//! (e.g. `set_value_notify`, or `set_notify` for arrays), which store the new value and set its
+//! event flag in one call.
+//!
+//! When several objects are updated together, [`set_event_flags_bulk`] sets all of their flags
+//! atomically, so that a TPDO mapping them is only sent once. Event driven TPDOs also honor their
+//! inhibit time (sub index 3 of the communication parameter), coalescing events which occur within
+//! it into a single transmission once it expires.
+//!
+//! # Composite object dictionaries
+//!
+//! A node's object dictionary can be assembled from several generated tables, e.g. a base profile
+//! plus an application dictionary, using [`CompositeOd`]. It checks that no object is defined in
+//! more than one table, and merges them into the single sorted table used by the node.
+//!
 
+mod cached_callback;
+mod composite;
+mod generation;
 mod object_flags;
 mod objects;
 mod sub_objects;
+mod write_hook;
 
 // Pull up public sub module definitions. The submodules provide some code organization, but
 // shouldn't clutter the public API
+pub use cached_callback::*;
+pub use composite::*;
+pub use generation::*;
 pub use object_flags::*;
 pub use objects::*;
 pub use sub_objects::*;
+pub use write_hook::*;
+
+pub(crate) use sub_objects::read_le_bytes;
@@ -1,13 +1,15 @@
-use core::cell::UnsafeCell;
+use core::cell::Cell;
 
-use critical_section::Mutex;
-use zencan_common::AtomicCell;
+use critical_section::{CriticalSection, Mutex};
+use zencan_common::sdo::AbortCode;
+
+use super::ObjectAccess;
 
 /// A struct used for synchronizing the A/B event flags of all objects, which are used for
 /// triggering PDO events
 #[derive(Debug)]
 pub struct ObjectFlagSync {
-    inner: Mutex<UnsafeCell<ObjectFlagsInner>>,
+    inner: Mutex<Cell<ObjectFlagsInner>>,
 }
 
 impl Default for ObjectFlagSync {
@@ -28,7 +30,7 @@ impl ObjectFlagSync {
     /// Create a new ObjectFlagSync
     pub const fn new() -> Self {
         Self {
-            inner: Mutex::new(UnsafeCell::new(ObjectFlagsInner {
+            inner: Mutex::new(Cell::new(ObjectFlagsInner {
                 toggle: false,
                 global_flag: false,
             })),
@@ -38,14 +40,13 @@ impl ObjectFlagSync {
     /// Toggle the flag and return the global flag
     pub fn toggle(&self) -> bool {
         critical_section::with(|cs| {
-            let inner = self.inner.borrow(cs).get();
-            // Safety: This is the only place inner is accessed, and it is in a critical section
-            unsafe {
-                let global = (*inner).global_flag;
-                (*inner).global_flag = false;
-                (*inner).toggle = !(*inner).toggle;
-                global
-            }
+            let cell = self.inner.borrow(cs);
+            let mut inner = cell.get();
+            let global = inner.global_flag;
+            inner.global_flag = false;
+            inner.toggle = !inner.toggle;
+            cell.set(inner);
+            global
         })
     }
 
@@ -53,11 +54,16 @@ impl ObjectFlagSync {
     ///
     /// `setting` should be true to set the global flag
     pub fn get_flag(&self, setting: bool) -> bool {
-        critical_section::with(|cs| {
-            let inner = unsafe { &mut (*self.inner.borrow(cs).get()) };
-            inner.global_flag |= setting;
-            inner.toggle
-        })
+        critical_section::with(|cs| self.get_flag_cs(cs, setting))
+    }
+
+    /// Get the current value of the flag, within an existing critical section
+    fn get_flag_cs(&self, cs: CriticalSection, setting: bool) -> bool {
+        let cell = self.inner.borrow(cs);
+        let mut inner = cell.get();
+        inner.global_flag |= setting;
+        cell.set(inner);
+        inner.toggle
     }
 }
 
@@ -70,11 +76,18 @@ impl ObjectFlagSync {
 /// In order to achieve this in a synchronized way without long critical sections, each object
 /// holds two sets of flags, and they are swapped atomically using a global `ObjectFlagSync` shared by
 /// all `ObjectFlags` instances.
+///
+/// The flags are a bitset with one bit per sub index, and `N` is the number of bytes in each set.
+/// `zencan-build` sizes it to cover the highest sub index of the object which can be mapped to a
+/// TPDO; flags for higher sub indices are ignored. Setting, reading, or clearing flags takes a
+/// single short critical section, regardless of the size of the object, so the cost of TPDO event
+/// processing depends only on the number of mapped objects, not on the size of the object
+/// dictionary.
 #[allow(missing_debug_implementations)]
 pub struct ObjectFlags<const N: usize> {
     sync: &'static ObjectFlagSync,
-    flags0: AtomicCell<[u8; N]>,
-    flags1: AtomicCell<[u8; N]>,
+    /// The two flag sets, indexed by the toggle value of the sync object
+    banks: Mutex<Cell<[[u8; N]; 2]>>,
 }
 
 /// Trait for accessing object flags
@@ -88,7 +101,7 @@ pub trait ObjectFlagAccess {
     /// The flag is read from the currently inactive flag set, i.e. the flag value from before the
     /// last sync toggle is returned
     fn get_flag(&self, sub: u8) -> bool;
-    /// Clear all flags in the currently active flag set
+    /// Clear all flags in the currently inactive flag set, i.e. the flags returned by `get_flag`
     fn clear(&self);
 }
 
@@ -97,47 +110,116 @@ impl<const N: usize> ObjectFlags<N> {
     pub const fn new(sync: &'static ObjectFlagSync) -> Self {
         Self {
             sync,
-            flags0: AtomicCell::new([0; N]),
-            flags1: AtomicCell::new([0; N]),
+            banks: Mutex::new(Cell::new([[0; N]; 2])),
+        }
+    }
+
+    /// Get the byte index and bit mask of a sub object's flag, or None if it is out of range
+    const fn flag_position(sub: u8) -> Option<(usize, u8)> {
+        let byte = sub as usize / 8;
+        if byte < N {
+            Some((byte, 1 << (sub & 7)))
+        } else {
+            None
         }
     }
 }
 
 impl<const N: usize> ObjectFlagAccess for ObjectFlags<N> {
     fn set_flag(&self, sub: u8) {
-        if sub as usize >= N * 8 {
+        let Some((byte, mask)) = Self::flag_position(sub) else {
             return;
-        }
-        let flags = if self.sync.get_flag(true) {
-            &self.flags0
-        } else {
-            &self.flags1
         };
-        flags
-            .fetch_update(|mut f| {
-                f[sub as usize / 8] |= 1 << (sub & 7);
-                Some(f)
-            })
-            .unwrap();
+        critical_section::with(|cs| {
+            let active = (!self.sync.get_flag_cs(cs, true)) as usize;
+            let cell = self.banks.borrow(cs);
+            let mut banks = cell.get();
+            banks[active][byte] |= mask;
+            cell.set(banks);
+        })
     }
 
     fn get_flag(&self, sub: u8) -> bool {
-        if sub as usize >= N * 8 {
+        let Some((byte, mask)) = Self::flag_position(sub) else {
             return false;
-        }
-        let flags = if self.sync.get_flag(false) {
-            &self.flags1.load()
-        } else {
-            &self.flags0.load()
         };
-        flags[(sub / 8) as usize] & (1 << (sub & 7)) != 0
+        critical_section::with(|cs| {
+            let inactive = self.sync.get_flag_cs(cs, false) as usize;
+            self.banks.borrow(cs).get()[inactive][byte] & mask != 0
+        })
     }
 
     fn clear(&self) {
-        if self.sync.get_flag(false) {
-            self.flags1.store([0; N]);
-        } else {
-            self.flags0.store([0; N]);
+        critical_section::with(|cs| {
+            let inactive = self.sync.get_flag_cs(cs, false) as usize;
+            let cell = self.banks.borrow(cs);
+            let mut banks = cell.get();
+            banks[inactive] = [0; N];
+            cell.set(banks);
+        })
+    }
+}
+
+/// Set the event flags of several sub objects at once
+///
+/// All of the flags are set within a single critical section, so `Node::process` cannot observe
+/// part of the update. When a burst of related values are updated together, this ensures that
+/// each TPDO they are mapped to is sent once, with all of the new values, rather than once for
+/// each flag set. Combined with a TPDO inhibit time, this limits the bus load produced by
+/// frequently updated values.
+///
+/// All flags are attempted; if any object does not support event flags, the first error is
+/// returned.
+pub fn set_event_flags_bulk(flags: &[(&dyn ObjectAccess, u8)]) -> Result<(), AbortCode> {
+    critical_section::with(|_| {
+        let mut result = Ok(());
+        for (object, sub) in flags {
+            if let Err(e) = object.set_event_flag(*sub) {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_toggle() {
+        static SYNC: ObjectFlagSync = ObjectFlagSync::new();
+        let flags = ObjectFlags::<2>::new(&SYNC);
+
+        flags.set_flag(3);
+        flags.set_flag(15);
+        // Flags set after the last toggle are not visible yet
+        assert!(!flags.get_flag(3));
+        assert!(SYNC.toggle());
+        assert!(flags.get_flag(3));
+        assert!(flags.get_flag(15));
+        assert!(!flags.get_flag(4));
+
+        // Out of range subs are ignored
+        flags.set_flag(16);
+        assert!(!flags.get_flag(16));
+
+        flags.clear();
+        assert!(!flags.get_flag(3));
+        // No flags were set since the last toggle
+        assert!(!SYNC.toggle());
+    }
+
+    #[test]
+    fn test_large_object_flags() {
+        static SYNC: ObjectFlagSync = ObjectFlagSync::new();
+        let flags = ObjectFlags::<32>::new(&SYNC);
+        for sub in (0..=255).step_by(5) {
+            flags.set_flag(sub);
+        }
+        SYNC.toggle();
+        for sub in 0..=255u8 {
+            assert_eq!(sub % 5 == 0, flags.get_flag(sub), "sub {sub}");
         }
     }
 }
@@ -1,12 +1,12 @@
 //! Traits and types for implementing objects in the OD
 
 use zencan_common::{
-    objects::{AccessType, DataType, ObjectCode, SubInfo},
+    objects::{AccessType, DataType, ObjectCode, PdoMapping, SubInfo},
     sdo::AbortCode,
     AtomicCell,
 };
 
-use super::{ObjectFlagAccess, SubObjectAccess};
+use super::{ObjectFlagAccess, SubObjectAccess, WriteHook};
 
 /// A trait for accessing objects
 ///
@@ -75,6 +75,18 @@ pub trait ObjectAccess: Sync + Send {
         Err(AbortCode::GeneralError)
     }
 
+    /// Get the generation of a sub object, if it tracks changes to its value
+    ///
+    /// The generation must change whenever the value of the sub object changes, e.g. by using a
+    /// [`GenerationCounter`](super::GenerationCounter). It allows the SDO server to detect a change
+    /// in the middle of an upload which is too large to be read at once, and abort it instead of
+    /// delivering a torn read.
+    ///
+    /// The default implementation returns None, for objects which do not track changes.
+    fn generation(&self, _sub: u8) -> Option<u32> {
+        None
+    }
+
     /// Get the type of this object
     fn object_code(&self) -> ObjectCode;
 
@@ -228,6 +240,14 @@ pub trait ProvidesSubObjects {
         None
     }
 
+    /// Get the write hook for this object
+    ///
+    /// If the object supports a [`WriteHook`], it should override this method to return a
+    /// reference to it, and it will be notified after each successful `write` or `end_partial`.
+    fn write_hook(&self) -> Option<&WriteHook> {
+        None
+    }
+
     /// What type of object is this
     fn object_code(&self) -> ObjectCode;
 }
@@ -257,7 +277,11 @@ impl<T: ProvidesSubObjects + Sync + Send> ObjectAccess for T {
     fn write(&self, sub: u8, data: &[u8]) -> Result<(), AbortCode> {
         if let Some((info, access)) = self.get_sub_object(sub) {
             if info.access_type.is_writable() {
-                access.write(data)
+                access.write(data)?;
+                if let Some(hook) = self.write_hook() {
+                    hook.notify(sub);
+                }
+                Ok(())
             } else {
                 Err(AbortCode::ReadOnly)
             }
@@ -288,12 +312,21 @@ impl<T: ProvidesSubObjects + Sync + Send> ObjectAccess for T {
 
     fn end_partial(&self, sub: u8) -> Result<(), AbortCode> {
         if let Some((_, access)) = self.get_sub_object(sub) {
-            access.end_partial()
+            access.end_partial()?;
+            if let Some(hook) = self.write_hook() {
+                hook.notify(sub);
+            }
+            Ok(())
         } else {
             Err(AbortCode::NoSuchSubIndex)
         }
     }
 
+    fn generation(&self, sub: u8) -> Option<u32> {
+        self.get_sub_object(sub)
+            .and_then(|(_, access)| access.generation())
+    }
+
     fn set_event_flag(&self, sub: u8) -> Result<(), AbortCode> {
         if let Some(flags) = self.flags() {
             flags.set_flag(sub);
@@ -311,6 +344,12 @@ impl<T: ProvidesSubObjects + Sync + Send> ObjectAccess for T {
         }
     }
 
+    fn clear_events(&self) {
+        if let Some(flags) = self.flags() {
+            flags.clear();
+        }
+    }
+
     fn object_code(&self) -> ObjectCode {
         self.object_code()
     }
@@ -325,20 +364,30 @@ impl<T: ProvidesSubObjects + Sync + Send> ObjectAccess for T {
 }
 
 /// OD placeholder for an object which will have a handler registered at runtime
+///
+/// All accesses to the object, including reads and writes performed by the PDO engine and the
+/// event flags used to trigger TPDOs, are forwarded to the registered handler. Until a handler is
+/// registered, accesses fail with [`AbortCode::ResourceNotAvailable`], so a handler must be
+/// registered before the object is mapped to a PDO, including by restoring stored PDO mappings.
 #[allow(missing_debug_implementations)]
 pub struct CallbackObject<'a> {
     obj: AtomicCell<Option<&'a dyn ObjectAccess>>,
     object_code: ObjectCode,
 }
 
-impl CallbackObject<'_> {
+impl<'a> CallbackObject<'a> {
     /// Create a new callback
-    pub fn new(object_code: ObjectCode) -> Self {
+    pub const fn new(object_code: ObjectCode) -> Self {
         Self {
             obj: AtomicCell::new(None),
             object_code,
         }
     }
+
+    /// Register the handler which implements the object
+    pub fn register_handler(&self, handler: &'a dyn ObjectAccess) {
+        self.obj.store(Some(handler));
+    }
 }
 
 impl ObjectAccess for CallbackObject<'_> {
@@ -366,6 +415,14 @@ impl ObjectAccess for CallbackObject<'_> {
         }
     }
 
+    fn begin_partial(&self, sub: u8) -> Result<(), AbortCode> {
+        if let Some(obj) = self.obj.load() {
+            obj.begin_partial(sub)
+        } else {
+            Err(AbortCode::ResourceNotAvailable)
+        }
+    }
+
     fn write_partial(&self, sub: u8, buf: &[u8]) -> Result<(), AbortCode> {
         if let Some(obj) = self.obj.load() {
             obj.write_partial(sub, buf)
@@ -382,6 +439,10 @@ impl ObjectAccess for CallbackObject<'_> {
         }
     }
 
+    fn generation(&self, sub: u8) -> Option<u32> {
+        self.obj.load().and_then(|obj| obj.generation(sub))
+    }
+
     fn object_code(&self) -> ObjectCode {
         self.object_code
     }
@@ -393,10 +454,85 @@ impl ObjectAccess for CallbackObject<'_> {
             Err(AbortCode::ResourceNotAvailable)
         }
     }
+
+    fn max_sub_number(&self) -> u8 {
+        self.obj.load().map(|obj| obj.max_sub_number()).unwrap_or(0)
+    }
+
+    fn set_event_flag(&self, sub: u8) -> Result<(), AbortCode> {
+        if let Some(obj) = self.obj.load() {
+            obj.set_event_flag(sub)
+        } else {
+            Err(AbortCode::ResourceNotAvailable)
+        }
+    }
+
+    fn read_event_flag(&self, sub: u8) -> bool {
+        self.obj.load().is_some_and(|obj| obj.read_event_flag(sub))
+    }
+
+    fn clear_events(&self) {
+        if let Some(obj) = self.obj.load() {
+            obj.clear_events();
+        }
+    }
+}
+
+/// OD placeholder for an object which is defined, but not implemented
+///
+/// Stubs are generated in place of objects which use features that code generation does not
+/// support yet, e.g. the TIME_OF_DAY data type, when a node is generated in permissive mode. The
+/// object appears in the dictionary as a read-only var of its declared data type, but reads fail
+/// with [`AbortCode::ResourceNotAvailable`], and it cannot be mapped to a PDO.
+#[derive(Debug)]
+pub struct StubObject {
+    data_type: DataType,
+}
+
+impl StubObject {
+    /// Create a stub for an object of the given data type
+    pub const fn new(data_type: DataType) -> Self {
+        Self { data_type }
+    }
+}
+
+impl ObjectAccess for StubObject {
+    fn read(&self, sub: u8, _offset: usize, _buf: &mut [u8]) -> Result<usize, AbortCode> {
+        self.sub_info(sub)?;
+        Err(AbortCode::ResourceNotAvailable)
+    }
+
+    fn read_size(&self, sub: u8) -> Result<usize, AbortCode> {
+        self.sub_info(sub)?;
+        Err(AbortCode::ResourceNotAvailable)
+    }
+
+    fn write(&self, sub: u8, _data: &[u8]) -> Result<(), AbortCode> {
+        self.sub_info(sub)?;
+        Err(AbortCode::ReadOnly)
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Var
+    }
+
+    fn sub_info(&self, sub: u8) -> Result<SubInfo, AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        Ok(SubInfo {
+            size: 0,
+            data_type: self.data_type,
+            access_type: AccessType::Ro,
+            pdo_mapping: PdoMapping::None,
+            persist: false,
+        })
+    }
 }
 
 /// Represents one item in the in-memory table of objects
 #[allow(missing_debug_implementations)]
+#[derive(Clone, Copy)]
 pub struct ODEntry<'a> {
     /// The object index
     pub index: u16,
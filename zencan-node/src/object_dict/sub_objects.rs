@@ -1,6 +1,9 @@
 //! Collection of generic fields which implement a sub-object
 
 use core::cell::UnsafeCell;
+use core::sync::atomic::{
+    AtomicBool, AtomicI16, AtomicI32, AtomicI8, AtomicU16, AtomicU32, AtomicU8, Ordering,
+};
 
 use zencan_common::{sdo::AbortCode, AtomicCell};
 
@@ -100,6 +103,37 @@ pub trait SubObjectAccess: Sync + Send {
     fn end_partial(&self) -> Result<(), AbortCode> {
         Err(AbortCode::UnsupportedAccess)
     }
+
+    /// Get the generation of the sub object, if it tracks changes to its value
+    ///
+    /// See [`ObjectAccess::generation`](super::ObjectAccess::generation).
+    fn generation(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// Copy the bytes of an encoded value, starting at `offset`, into `buf`
+///
+/// Returns the number of bytes copied, which is 0 if `offset` is past the end of the value.
+pub(crate) fn read_le_bytes(bytes: &[u8], offset: usize, buf: &mut [u8]) -> usize {
+    if offset < bytes.len() {
+        let read_len = buf.len().min(bytes.len() - offset);
+        buf[0..read_len].copy_from_slice(&bytes[offset..offset + read_len]);
+        read_len
+    } else {
+        0
+    }
+}
+
+/// Get the bytes of a little-endian value being written, checking that the length is correct
+fn le_bytes<const N: usize>(data: &[u8]) -> Result<[u8; N], AbortCode> {
+    data.try_into().map_err(|_| {
+        if data.len() < N {
+            AbortCode::DataTypeMismatchLengthLow
+        } else {
+            AbortCode::DataTypeMismatchLengthHigh
+        }
+    })
 }
 
 /// A sub object which contains a single scalar value of type T, which is a standard rust type
@@ -140,14 +174,7 @@ macro_rules! impl_scalar_field {
         }
         impl SubObjectAccess for ScalarField<$rust_type> {
             fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
-                let bytes = self.value.load().to_le_bytes();
-                if offset < bytes.len() {
-                    let read_len = buf.len().min(bytes.len() - offset);
-                    buf[0..read_len].copy_from_slice(&bytes[offset..offset + read_len]);
-                    Ok(read_len)
-                } else {
-                    Ok(0)
-                }
+                Ok(read_le_bytes(&self.value.load().to_le_bytes(), offset, buf))
             }
 
             fn read_size(&self) -> usize {
@@ -155,13 +182,7 @@ macro_rules! impl_scalar_field {
             }
 
             fn write(&self, data: &[u8]) -> Result<(), AbortCode> {
-                let value = <$rust_type>::from_le_bytes(data.try_into().map_err(|_| {
-                    if data.len() < size_of::<$rust_type>() {
-                        AbortCode::DataTypeMismatchLengthLow
-                    } else {
-                        AbortCode::DataTypeMismatchLengthHigh
-                    }
-                })?);
+                let value = <$rust_type>::from_le_bytes(le_bytes(data)?);
                 self.value.store(value);
                 Ok(())
             }
@@ -202,6 +223,253 @@ impl SubObjectAccess for ScalarField<bool> {
     }
 }
 
+/// A scalar type which can be stored in an [`AtomicScalarField`]
+pub trait AtomicScalar: Copy + Send + PartialEq {
+    /// The atomic type used to store the value
+    type Storage: Send + Sync + Default;
+
+    /// Atomically read a value from the storage
+    fn load(storage: &Self::Storage) -> Self;
+
+    /// Atomically write a value to the storage
+    fn store(storage: &Self::Storage, value: Self);
+}
+
+/// A sub object which contains a single scalar value, stored in a `core::sync::atomic` type
+///
+/// This has the same interface as [`ScalarField`], but loads and stores are performed with atomic
+/// instructions instead of in a critical section, so accessing the value never disables
+/// interrupts. This requires a target with native atomic loads and stores of the value's size,
+/// which includes all Cortex-M cores. f32 values are stored as their bit pattern in an
+/// [`AtomicU32`].
+#[allow(missing_debug_implementations)]
+pub struct AtomicScalarField<T: AtomicScalar> {
+    value: T::Storage,
+}
+
+impl<T: AtomicScalar> AtomicScalarField<T> {
+    /// Atomically read the value of the field
+    pub fn load(&self) -> T {
+        T::load(&self.value)
+    }
+
+    /// Atomically store a new value into the field
+    pub fn store(&self, value: T) {
+        T::store(&self.value, value);
+    }
+}
+
+impl<T: AtomicScalar> Default for AtomicScalarField<T> {
+    fn default() -> Self {
+        Self {
+            value: T::Storage::default(),
+        }
+    }
+}
+
+macro_rules! impl_atomic_scalar_field {
+    ($rust_type: ty, $atomic_type: ty) => {
+        impl AtomicScalar for $rust_type {
+            type Storage = $atomic_type;
+
+            fn load(storage: &Self::Storage) -> Self {
+                storage.load(Ordering::Acquire)
+            }
+
+            fn store(storage: &Self::Storage, value: Self) {
+                storage.store(value, Ordering::Release)
+            }
+        }
+
+        impl AtomicScalarField<$rust_type> {
+            /// Create a new AtomicScalarField with the given value
+            pub const fn new(value: $rust_type) -> Self {
+                Self {
+                    value: <$atomic_type>::new(value),
+                }
+            }
+        }
+
+        impl SubObjectAccess for AtomicScalarField<$rust_type> {
+            fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+                Ok(read_le_bytes(&self.load().to_le_bytes(), offset, buf))
+            }
+
+            fn read_size(&self) -> usize {
+                core::mem::size_of::<$rust_type>()
+            }
+
+            fn write(&self, data: &[u8]) -> Result<(), AbortCode> {
+                let value = <$rust_type>::from_le_bytes(le_bytes(data)?);
+                self.store(value);
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_atomic_scalar_field!(u8, AtomicU8);
+impl_atomic_scalar_field!(u16, AtomicU16);
+impl_atomic_scalar_field!(u32, AtomicU32);
+impl_atomic_scalar_field!(i8, AtomicI8);
+impl_atomic_scalar_field!(i16, AtomicI16);
+impl_atomic_scalar_field!(i32, AtomicI32);
+
+impl AtomicScalar for f32 {
+    type Storage = AtomicU32;
+
+    fn load(storage: &Self::Storage) -> Self {
+        f32::from_bits(storage.load(Ordering::Acquire))
+    }
+
+    fn store(storage: &Self::Storage, value: Self) {
+        storage.store(value.to_bits(), Ordering::Release)
+    }
+}
+
+/// Used to get the bits of an f32 in a const fn, as `f32::to_bits` is not const in the minimum
+/// supported rust version
+union F32Bits {
+    f: f32,
+    u: u32,
+}
+
+impl AtomicScalarField<f32> {
+    /// Create a new AtomicScalarField with the given value
+    pub const fn new(value: f32) -> Self {
+        // SAFETY: f32 and u32 have the same size, and every bit pattern is a valid u32
+        let bits = unsafe { F32Bits { f: value }.u };
+        Self {
+            value: AtomicU32::new(bits),
+        }
+    }
+}
+
+impl SubObjectAccess for AtomicScalarField<f32> {
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        Ok(read_le_bytes(&self.load().to_le_bytes(), offset, buf))
+    }
+
+    fn read_size(&self) -> usize {
+        size_of::<f32>()
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), AbortCode> {
+        let value = f32::from_le_bytes(le_bytes(data)?);
+        self.store(value);
+        Ok(())
+    }
+}
+
+impl AtomicScalar for bool {
+    type Storage = AtomicBool;
+
+    fn load(storage: &Self::Storage) -> Self {
+        storage.load(Ordering::Acquire)
+    }
+
+    fn store(storage: &Self::Storage, value: Self) {
+        storage.store(value, Ordering::Release)
+    }
+}
+
+impl AtomicScalarField<bool> {
+    /// Create a new AtomicScalarField with the given value
+    pub const fn new(value: bool) -> Self {
+        Self {
+            value: AtomicBool::new(value),
+        }
+    }
+}
+
+impl SubObjectAccess for AtomicScalarField<bool> {
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        let value = self.load();
+        if offset != 0 || buf.len() > 1 {
+            return Err(AbortCode::DataTypeMismatchLengthHigh);
+        }
+        buf[0] = if value { 1 } else { 0 };
+        Ok(1)
+    }
+
+    fn read_size(&self) -> usize {
+        1
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), AbortCode> {
+        if data.len() != 1 {
+            return Err(AbortCode::DataTypeMismatchLengthHigh);
+        }
+        self.store(data[0] != 0);
+        Ok(())
+    }
+}
+
+/// A raw value type which can be converted to a float for scaling by a [`ScaledField`]
+pub trait ScaleInput: Copy + Send + PartialEq {
+    /// Convert the value to an f32
+    fn to_f32(self) -> f32;
+}
+
+macro_rules! impl_scale_input {
+    ($($rust_type: ty),*) => {
+        $(impl ScaleInput for $rust_type {
+            fn to_f32(self) -> f32 {
+                self as f32
+            }
+        })*
+    };
+}
+
+impl_scale_input!(u8, u16, u32, i8, i16, i32, f32);
+
+/// A sub object whose value is a linear transform of a raw value
+///
+/// Reading the field returns `raw * gain + offset` as an f32. The raw value, gain, and offset are
+/// each stored in a [`ScalarField`], so that they can be exposed as sibling sub objects -- e.g. to
+/// allow the gain and offset to be calibrated via SDO, and persisted.
+#[allow(missing_debug_implementations)]
+pub struct ScaledField<T: Copy> {
+    /// The unscaled value
+    pub raw: ScalarField<T>,
+    /// The gain multiplied by the raw value
+    pub gain: ScalarField<f32>,
+    /// The offset added to the product of the raw value and gain
+    pub offset: ScalarField<f32>,
+}
+
+impl<T: Copy> ScaledField<T> {
+    /// Create a new ScaledField
+    pub const fn new(raw: ScalarField<T>, gain: f32, offset: f32) -> Self {
+        Self {
+            raw,
+            gain: ScalarField::<f32>::new(gain),
+            offset: ScalarField::<f32>::new(offset),
+        }
+    }
+}
+
+impl<T: ScaleInput> ScaledField<T> {
+    /// Compute the scaled value from the current raw value, gain, and offset
+    pub fn scaled(&self) -> f32 {
+        self.raw.load().to_f32() * self.gain.load() + self.offset.load()
+    }
+}
+
+impl<T: ScaleInput> SubObjectAccess for ScaledField<T> {
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        Ok(read_le_bytes(&self.scaled().to_le_bytes(), offset, buf))
+    }
+
+    fn read_size(&self) -> usize {
+        size_of::<f32>()
+    }
+
+    fn write(&self, _data: &[u8]) -> Result<(), AbortCode> {
+        Err(AbortCode::ReadOnly)
+    }
+}
+
 /// A sub object which contains a fixed-size byte array
 ///
 /// This is the data storage backing for all string types
@@ -315,7 +583,13 @@ impl<const N: usize> SubObjectAccess for ByteField<N> {
 
 /// A byte field which supports storing short values using null termination to indicate size
 ///
-/// This is here to support VisibleString and UnicodeString types.
+/// This is here to support VisibleString and UnicodeString types, and is used for all string
+/// objects generated by `zencan-build`. The length of the value is the number of bytes before the
+/// first null byte, or the full size of the field if it contains no null:
+///
+/// - Uploads report, and return, only the bytes before the first null.
+/// - A download shorter than the field is padded with nulls to fill the field, so no bytes of a
+///   previous, longer, value remain after it.
 #[allow(clippy::len_without_is_empty, missing_debug_implementations)]
 pub struct NullTermByteField<const N: usize>(ByteField<N>);
 
@@ -332,8 +606,8 @@ impl<const N: usize> NullTermByteField<N> {
 
     /// Atomically load the value stored in the object
     ///
-    /// Note that this will return the entire array, including any invalid bytes after the null
-    /// terminator.
+    /// Note that this will return the entire array, including the null padding after the value.
+    /// Use [`get_str`](Self::get_str) to get only the value.
     pub fn load(&self) -> [u8; N] {
         self.0.load()
     }
@@ -345,19 +619,26 @@ impl<const N: usize> NullTermByteField<N> {
 
     /// Store a str to the object
     ///
-    /// If the string is shorter than the object size, it will be stored with a null terminator
-    /// If longer, an error will be returned.
+    /// If the string is shorter than the object size, it will be padded with nulls. If longer, an
+    /// error will be returned.
     pub fn set_str(&self, value: &[u8]) -> Result<(), AbortCode> {
-        self.0.begin_partial()?;
-        self.0.write_partial(value)?;
-        if value.len() < N {
-            self.0.write_partial(&[0])?;
-        }
-        self.end_partial()?;
-        Ok(())
+        self.write(value)
+    }
+
+    /// Atomically load the value into `buf`, and return the bytes before the null terminator
+    pub fn get_str<'a>(&self, buf: &'a mut [u8; N]) -> &'a [u8] {
+        *buf = self.0.load();
+        let len = str_len(buf);
+        &buf[..len]
     }
 }
 
+/// Get the length of a null terminated value
+fn str_len(bytes: &[u8]) -> usize {
+    // Find the first 0, or if there are none the length is the full array
+    bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len())
+}
+
 impl<const N: usize> Default for NullTermByteField<N> {
     fn default() -> Self {
         Self(ByteField::default())
@@ -366,26 +647,30 @@ impl<const N: usize> Default for NullTermByteField<N> {
 
 impl<const N: usize> SubObjectAccess for NullTermByteField<N> {
     fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
-        let size = self.0.read(offset, buf)?;
-        let size = buf[0..size].iter().position(|b| *b == 0).unwrap_or(size);
-        Ok(size)
-    }
-
-    fn read_size(&self) -> usize {
         critical_section::with(|_| {
             let bytes = unsafe { &*self.0.value.get() };
-            // Find the first 0, or if there are none the length is the full array
-            bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len())
+            // The raw bytes are copied, but only those before the null count towards the length
+            let copy_len = buf.len().min(N.saturating_sub(offset));
+            buf[..copy_len].copy_from_slice(&bytes[offset..offset + copy_len]);
+            Ok(str_len(bytes).saturating_sub(offset).min(copy_len))
         })
     }
 
+    fn read_size(&self) -> usize {
+        critical_section::with(|_| str_len(unsafe { &*self.0.value.get() }))
+    }
+
     fn write(&self, data: &[u8]) -> Result<(), AbortCode> {
-        self.0.begin_partial()?;
-        self.0.write_partial(data)?;
-        if data.len() < N {
-            self.0.write_partial(&[0])?;
+        if data.len() > N {
+            return Err(AbortCode::DataTypeMismatchLengthHigh);
         }
-        self.0.end_partial()?;
+        // Any ongoing partial write will be cancelled
+        self.0.write_offset.store(None);
+        critical_section::with(|_| {
+            let bytes = unsafe { &mut *self.0.value.get() };
+            bytes[..data.len()].copy_from_slice(data);
+            bytes[data.len()..].fill(0);
+        });
         Ok(())
     }
 
@@ -398,9 +683,12 @@ impl<const N: usize> SubObjectAccess for NullTermByteField<N> {
     }
 
     fn end_partial(&self) -> Result<(), AbortCode> {
-        // Null terminate if the length of data written is less than the sub object size
-        if self.0.write_offset.load().unwrap_or(0) < N {
-            self.0.write_partial(&[0])?;
+        // Pad with nulls if the length of data written is less than the sub object size
+        if let Some(offset) = self.0.write_offset.load() {
+            critical_section::with(|_| {
+                let bytes = unsafe { &mut *self.0.value.get() };
+                bytes[offset.min(N)..].fill(0);
+            });
         }
         self.0.end_partial()
     }
@@ -421,9 +709,7 @@ impl ConstByteRefField {
 
 impl SubObjectAccess for ConstByteRefField {
     fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
-        let read_len = buf.len().min(self.value.len() - offset);
-        buf[..read_len].copy_from_slice(&self.value[offset..offset + read_len]);
-        Ok(read_len)
+        Ok(read_le_bytes(self.value, offset, buf))
     }
 
     fn read_size(&self) -> usize {
@@ -453,13 +739,7 @@ impl<const N: usize> ConstField<N> {
 
 impl<const N: usize> SubObjectAccess for ConstField<N> {
     fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
-        if offset < self.bytes.len() {
-            let read_len = buf.len().min(self.bytes.len() - offset);
-            buf[..read_len].copy_from_slice(&self.bytes[offset..offset + read_len]);
-            Ok(read_len)
-        } else {
-            Ok(0)
-        }
+        Ok(read_le_bytes(&self.bytes, offset, buf))
     }
 
     fn read_size(&self) -> usize {
@@ -545,6 +825,10 @@ impl SubObjectAccess for CallbackSubObject {
             Err(AbortCode::ResourceNotAvailable)
         }
     }
+
+    fn generation(&self) -> Option<u32> {
+        self.handler.load().and_then(|handler| handler.generation())
+    }
 }
 
 #[cfg(test)]
@@ -642,6 +926,79 @@ mod tests {
         assert_eq!(expected_bytes[1..n - 1], read_buf);
     }
 
+    /// Check the encoding of scalar fields against literal little-endian bytes, so that these fail
+    /// if the host byte order leaks into the object data, e.g. when run on a big-endian target
+    #[test]
+    fn test_scalar_byte_order() {
+        fn check(field: &dyn SubObjectAccess, bytes: &[u8]) {
+            let mut buf = [0; 4];
+            let n = field.read(0, &mut buf).unwrap();
+            assert_eq!(bytes, &buf[..n]);
+            // Reads at an offset get the higher order bytes
+            let n = field.read(1, &mut buf).unwrap();
+            assert_eq!(&bytes[1..], &buf[..n]);
+        }
+
+        check(
+            &ScalarField::<u32>::new(0x12345678),
+            &[0x78, 0x56, 0x34, 0x12],
+        );
+        check(&ScalarField::<u16>::new(0x1234), &[0x34, 0x12]);
+        check(&ScalarField::<i32>::new(-2), &[0xfe, 0xff, 0xff, 0xff]);
+        check(&ScalarField::<i16>::new(-0x1235), &[0xcb, 0xed]);
+        check(&ScalarField::<f32>::new(1.0), &[0x00, 0x00, 0x80, 0x3f]);
+        check(
+            &AtomicScalarField::<u32>::new(0x12345678),
+            &[0x78, 0x56, 0x34, 0x12],
+        );
+        check(&AtomicScalarField::<i16>::new(-0x1235), &[0xcb, 0xed]);
+        check(
+            &AtomicScalarField::<f32>::new(-2.5),
+            &[0x00, 0x00, 0x20, 0xc0],
+        );
+        check(
+            &ScaledField::new(ScalarField::<u8>::new(1), 1.0, 0.0),
+            &[0x00, 0x00, 0x80, 0x3f],
+        );
+
+        let field = ScalarField::<u32>::new(0);
+        field.write(&[0x78, 0x56, 0x34, 0x12]).unwrap();
+        assert_eq!(0x12345678, field.load());
+        let field = AtomicScalarField::<i16>::new(0);
+        field.write(&[0xcb, 0xed]).unwrap();
+        assert_eq!(-0x1235, field.load());
+        let field = ScalarField::<f32>::new(0.0);
+        field.write(&[0x00, 0x00, 0x20, 0xc0]).unwrap();
+        assert_eq!(-2.5, field.load());
+
+        // Lengths are checked before conversion
+        assert_eq!(
+            Err(AbortCode::DataTypeMismatchLengthLow),
+            field.write(&[0; 3])
+        );
+        assert_eq!(
+            Err(AbortCode::DataTypeMismatchLengthHigh),
+            field.write(&[0; 5])
+        );
+    }
+
+    #[test]
+    fn test_typed_accessor_byte_order() {
+        let record = ExampleRecord::default();
+        record.write(1, &[0x78, 0x56, 0x34, 0x12]).unwrap();
+        assert_eq!(0x12345678, record.read_u32(1).unwrap());
+        assert_eq!(0x12345678, record.val1.load());
+    }
+
+    #[test]
+    fn test_const_field_read_past_end() {
+        let field = ConstByteRefField::new(&[1, 2, 3]);
+        let mut buf = [0; 4];
+        assert_eq!(Ok(0), field.read(4, &mut buf));
+        assert_eq!(Ok(1), field.read(2, &mut buf));
+        assert_eq!(3, buf[0]);
+    }
+
     #[test]
     fn test_scalar_field() {
         let field = ScalarField::<u32>::new(42u32);
@@ -651,6 +1008,36 @@ mod tests {
         sub_read_test_helper(&field, &exp_bytes);
     }
 
+    #[test]
+    fn test_scaled_field() {
+        let field = ScaledField::new(ScalarField::<i16>::new(-200), 0.5, 10.0);
+        assert_eq!(-90.0, field.scaled());
+        sub_read_test_helper(&field, &(-90.0f32).to_le_bytes());
+
+        field.gain.store(2.0);
+        field.raw.store(3);
+        assert_eq!(16.0, field.scaled());
+        assert_eq!(Err(AbortCode::ReadOnly), field.write(&[0; 4]));
+    }
+
+    #[test]
+    fn test_atomic_scalar_field() {
+        let field = AtomicScalarField::<i16>::new(-300);
+        assert_eq!(-300, field.load());
+        field.write(&1234i16.to_le_bytes()).unwrap();
+        assert_eq!(1234, field.load());
+        assert_eq!(Err(AbortCode::DataTypeMismatchLengthLow), field.write(&[0]));
+
+        let field = AtomicScalarField::<f32>::new(1.5);
+        sub_read_test_helper(&field, &1.5f32.to_le_bytes());
+        field.store(-2.25);
+        sub_read_test_helper(&field, &(-2.25f32).to_le_bytes());
+
+        let field = AtomicScalarField::<bool>::default();
+        field.write(&[1]).unwrap();
+        assert!(field.load());
+    }
+
     #[test]
     fn test_byte_field() {
         const N: usize = 10;
@@ -671,6 +1058,29 @@ mod tests {
         // Write a short value
         field.write(&[1, 2, 3, 4]).unwrap();
         sub_read_test_helper(&field, &[1, 2, 3, 4]);
+        // Short values are padded with nulls
+        assert_eq!([1, 2, 3, 4, 0, 0, 0, 0, 0, 0], field.load());
+
+        // The same applies to segmented writes
+        field.write(&[1; 10]).unwrap();
+        field.begin_partial().unwrap();
+        field.write_partial(&[5, 6]).unwrap();
+        field.write_partial(&[7]).unwrap();
+        field.end_partial().unwrap();
+        assert_eq!([5, 6, 7, 0, 0, 0, 0, 0, 0, 0], field.load());
+        sub_read_test_helper(&field, &[5, 6, 7]);
+
+        // Reads past the end of the value return nothing
+        let mut buf = [0xff; 4];
+        assert_eq!(0, field.read(3, &mut buf).unwrap());
+
+        field.set_str(b"abc").unwrap();
+        let mut buf = [0; 10];
+        assert_eq!(b"abc", field.get_str(&mut buf));
+        assert_eq!(
+            Err(AbortCode::DataTypeMismatchLengthHigh),
+            field.set_str(b"abcdefghijk")
+        );
     }
 
     #[test]
@@ -0,0 +1,79 @@
+//! Post-write notification for generated objects
+use zencan_common::AtomicCell;
+
+/// The signature of a function registered with a [`WriteHook`]
+///
+/// It is called with the sub index which was written.
+pub type WriteHookFn = dyn Fn(u8) + Sync;
+
+/// Storage for an optional function called after an object is written
+///
+/// Generated objects configured with `on_write_callback = true` embed a `WriteHook`, return it from
+/// [`ProvidesSubObjects::write_hook`](super::ProvidesSubObjects::write_hook), and get a
+/// `register_write_hook` method to set it. The hook is called after each successful write through
+/// the [`ObjectAccess`](super::ObjectAccess) trait, i.e. by an SDO download or a received PDO. It
+/// is not called when the application updates the object with its generated setters.
+///
+/// The hook runs in the context of the write, e.g. inside `Node::process`, so it should return
+/// quickly -- for example, by setting a flag or waking a task which does the actual work.
+#[allow(missing_debug_implementations)]
+pub struct WriteHook {
+    hook: AtomicCell<Option<&'static WriteHookFn>>,
+}
+
+impl Default for WriteHook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WriteHook {
+    /// Create a new WriteHook with no function registered
+    pub const fn new() -> Self {
+        Self {
+            hook: AtomicCell::new(None),
+        }
+    }
+
+    /// Register the function to call after a write, replacing any previous one
+    pub fn register(&self, hook: &'static WriteHookFn) {
+        self.hook.store(Some(hook));
+    }
+
+    /// Remove the registered function
+    pub fn clear(&self) {
+        self.hook.store(None);
+    }
+
+    /// Call the registered function, if any, for a write to `sub`
+    pub fn notify(&self, sub: u8) {
+        // The hook is called outside of the critical section used to load it
+        if let Some(hook) = self.hook.load() {
+            hook(sub);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU8, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_write_hook() {
+        static LAST_SUB: AtomicU8 = AtomicU8::new(0);
+        let hook = WriteHook::new();
+
+        // Nothing happens without a registered function
+        hook.notify(1);
+
+        hook.register(&|sub| LAST_SUB.store(sub, Ordering::Relaxed));
+        hook.notify(3);
+        assert_eq!(3, LAST_SUB.load(Ordering::Relaxed));
+
+        hook.clear();
+        hook.notify(4);
+        assert_eq!(3, LAST_SUB.load(Ordering::Relaxed));
+    }
+}
@@ -16,6 +16,43 @@ use zencan_common::{
 /// objects to a single PDO
 const N_MAPPING_PARAMS: usize = 8;
 
+/// A value unpacked from a received PDO
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RpdoValue<'a> {
+    /// The index of the mapped object
+    pub index: u16,
+    /// The sub index of the mapped object
+    pub sub: u8,
+    /// The bytes received for the mapped sub object
+    pub data: &'a [u8],
+}
+
+/// Callback invoked with the values unpacked from a received PDO
+pub type RpdoCallback = dyn Fn(&[RpdoValue]) + Sync;
+
+/// The order in which TPDOs which are due in the same call to
+/// [`Node::process`](crate::Node::process) are transmitted
+///
+/// This only makes a difference when messages can be rejected, i.e. when the transmit queue used
+/// by [`Node::process_queued`](crate::Node::process_queued) is full. A rejected event driven TPDO
+/// keeps its pending event, and is sent on a later call.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TpdoOrder {
+    /// TPDOs are always sent in order of PDO number, starting with TPDO1
+    ///
+    /// When the transmit queue is repeatedly filled, the lower numbered TPDOs can prevent the
+    /// higher numbered ones from ever being sent.
+    #[default]
+    ByNumber,
+    /// TPDOs are sent in order of PDO number, starting with the first TPDO which was rejected in
+    /// the most recent call which rejected one, and wrapping around to TPDO1 after the last
+    ///
+    /// Each call starts where the previous one stopped making progress, so every due TPDO is
+    /// eventually sent.
+    RoundRobin,
+}
+
 #[derive(Clone, Copy)]
 struct MappingEntry {
     object: &'static ODEntry<'static>,
@@ -41,8 +78,13 @@ pub struct Pdo {
     transmission_type: AtomicCell<u8>,
     /// Tracks the number of sync signals since this was last sent or received
     sync_counter: AtomicCell<u8>,
-    /// The last received data value for an RPDO
-    pub buffered_value: AtomicCell<Option<[u8; 8]>>,
+    /// Minimum time between transmissions of an event driven TPDO, in units of 100us
+    /// (subindex 0x3)
+    inhibit_time: AtomicCell<u16>,
+    /// Set when an event has triggered the PDO, but it has not yet been sent
+    event_pending: AtomicCell<bool>,
+    /// The time at which the PDO was last sent, in microseconds
+    last_transmit_us: AtomicCell<Option<u64>>,
     /// Indicates how many of the values in mapping_params are valid
     ///
     /// This represents sub0 for the mapping object
@@ -51,6 +93,10 @@ pub struct Pdo {
     ///
     /// These specify which objects are
     mapping_params: [AtomicCell<Option<MappingEntry>>; N_MAPPING_PARAMS],
+    /// Application callback for received PDOs
+    rx_callback: AtomicCell<Option<&'static RpdoCallback>>,
+    /// Set when the COB-ID or valid bit changes, so that acceptance filters can be updated
+    filter_changed: AtomicCell<bool>,
 }
 
 impl Default for Pdo {
@@ -67,24 +113,43 @@ impl Pdo {
         let rtr_disabled = AtomicCell::new(false);
         let transmission_type = AtomicCell::new(0);
         let sync_counter = AtomicCell::new(0);
-        let buffered_value = AtomicCell::new(None);
+        let inhibit_time = AtomicCell::new(0);
+        let event_pending = AtomicCell::new(false);
+        let last_transmit_us = AtomicCell::new(None);
         let valid_maps = AtomicCell::new(0);
         let mapping_params = [const { AtomicCell::new(None) }; N_MAPPING_PARAMS];
+        let rx_callback = AtomicCell::new(None);
+        let filter_changed = AtomicCell::new(false);
         Self {
             cob_id,
             valid,
             rtr_disabled,
             transmission_type,
             sync_counter,
-            buffered_value,
+            inhibit_time,
+            event_pending,
+            last_transmit_us,
             valid_maps,
             mapping_params,
+            rx_callback,
+            filter_changed,
         }
     }
 
+    /// Register a callback to be called when this PDO is received
+    ///
+    /// The callback is called from [`Node::process`](crate::Node::process), after the received
+    /// values have been stored in the mapped objects, with one [`RpdoValue`] for each mapping.
+    pub fn register_rx_callback(&self, cb: &'static RpdoCallback) {
+        self.rx_callback.store(Some(cb));
+    }
+
     /// Set the valid bit
     pub fn set_valid(&self, value: bool) {
-        self.valid.store(value);
+        if self.valid.load() != value {
+            self.valid.store(value);
+            self.filter_changed.store(true);
+        }
     }
 
     /// Get the valid bit value
@@ -102,9 +167,76 @@ impl Pdo {
         self.transmission_type.load()
     }
 
+    /// Set the inhibit time for this PDO, in units of 100us
+    ///
+    /// When a TPDO is event driven, events which occur within the inhibit time after the last
+    /// transmission are coalesced, and the PDO is sent once when the inhibit time expires.
+    pub fn set_inhibit_time(&self, value: u16) {
+        self.inhibit_time.store(value);
+    }
+
+    /// Get the inhibit time for this PDO, in units of 100us
+    pub fn inhibit_time(&self) -> u16 {
+        self.inhibit_time.load()
+    }
+
+    /// Get the time remaining until the inhibit time has elapsed since the last transmission
+    fn inhibit_remaining_us(&self, now_us: u64) -> u64 {
+        match self.last_transmit_us.load() {
+            Some(last) => {
+                let inhibit_us = self.inhibit_time.load() as u64 * 100;
+                (last + inhibit_us).saturating_sub(now_us)
+            }
+            None => 0,
+        }
+    }
+
+    /// Latch any events from the mapped objects, and return true if an event driven TPDO should
+    /// be sent now
+    ///
+    /// `read_flags` should be true if any object flags were set since the last check. Events are
+    /// latched until the PDO is sent, so that all events during the inhibit time result in a
+    /// single transmission. If `ignore_inhibit` is true, pending events are reported regardless of
+    /// the inhibit time.
+    pub(crate) fn event_due(&self, read_flags: bool, now_us: u64, ignore_inhibit: bool) -> bool {
+        if read_flags && self.read_events() {
+            self.event_pending.store(true);
+        }
+        self.event_pending.load() && (ignore_inhibit || self.inhibit_remaining_us(now_us) == 0)
+    }
+
+    /// Request transmission of this PDO, regardless of the event flags of the mapped objects
+    ///
+    /// This applies to event driven PDOs (transmission type 254 or 255), and is ignored for SYNC
+    /// driven PDOs. The PDO is sent on the next call to [`Node::process`](crate::Node::process),
+    /// or when the inhibit time has elapsed since the last transmission.
+    pub fn trigger(&self) {
+        if self.transmission_type.load() >= 254 {
+            self.event_pending.store(true);
+        }
+    }
+
+    /// Get the time until a pending event can be sent, or None if no event is pending
+    pub(crate) fn next_event_us(&self, now_us: u64) -> Option<u64> {
+        if self.valid.load() && self.event_pending.load() {
+            Some(self.inhibit_remaining_us(now_us))
+        } else {
+            None
+        }
+    }
+
+    /// Record the transmission of the PDO, clearing any pending event
+    pub(crate) fn mark_transmitted(&self, now_us: u64) {
+        self.event_pending.store(false);
+        self.last_transmit_us.store(Some(now_us));
+    }
+
     /// Set the COB used for transmission of this PDO
     pub fn set_cob_id(&self, value: CanId) {
-        self.cob_id.store(value)
+        if self.cob_id.load() != value {
+            self.cob_id.store(value);
+            self.filter_changed.store(true);
+        }
     }
 
     /// Get the COB used for transmission of this PDO
@@ -112,6 +244,11 @@ impl Pdo {
         self.cob_id.load()
     }
 
+    /// Returns true if the COB-ID or valid bit has changed since the last call
+    pub(crate) fn take_filter_changed(&self) -> bool {
+        self.filter_changed.take()
+    }
+
     /// This function should be called when a SYNC event occurs
     ///
     /// It will return true if the PDO should be sent in response to the SYNC event
@@ -126,8 +263,14 @@ impl Pdo {
             // For now, send every sync
             true
         } else if transmission_type <= 240 {
+            // The PDO is sent on every n-th SYNC, so the count starts over after each transmission
             let cnt = self.sync_counter.fetch_add(1) + 1;
-            cnt == transmission_type
+            if cnt >= transmission_type {
+                self.sync_counter.store(0);
+                true
+            } else {
+                false
+            }
         } else {
             false
         }
@@ -163,7 +306,24 @@ impl Pdo {
         }
     }
 
+    /// Get the number of bytes mapped to the PDO
+    pub(crate) fn mapped_len(&self) -> usize {
+        let valid_maps = self.valid_maps.load() as usize;
+        self.mapping_params
+            .iter()
+            .take(valid_maps)
+            .map_while(|param| param.load())
+            .map(|param| param.length as usize)
+            .sum()
+    }
+
     pub(crate) fn store_pdo_data(&self, data: &[u8]) {
+        let mut values = [RpdoValue {
+            index: 0,
+            sub: 0,
+            data: &[],
+        }; N_MAPPING_PARAMS];
+        let mut count = 0;
         let mut offset = 0;
         let valid_maps = self.valid_maps.load() as usize;
         for (i, param) in self.mapping_params.iter().enumerate() {
@@ -183,8 +343,18 @@ impl Pdo {
             // validity of the mappings must be validated during write, so that error here is not
             // possible
             param.object.data.write(param.sub, data_to_write).ok();
+            values[i] = RpdoValue {
+                index: param.object.index,
+                sub: param.sub,
+                data: data_to_write,
+            };
+            count += 1;
             offset += length;
         }
+
+        if let Some(cb) = self.rx_callback.load() {
+            cb(&values[..count]);
+        }
     }
 
     pub(crate) fn read_pdo_data(&self, data: &mut [u8]) {
@@ -271,9 +441,51 @@ impl SubObjectAccess for PdoCobSubObject {
             } else {
                 CanId::Std((value & 0x7FF) as u16)
             };
-            self.pdo.cob_id.store(can_id);
-            self.pdo.valid.store(!not_valid);
+            self.pdo.set_cob_id(can_id);
+            self.pdo.set_valid(!not_valid);
             self.pdo.rtr_disabled.store(no_rtr);
+            // Reconfiguring the PDO discards any held event
+            self.pdo.event_pending.store(false);
+            self.pdo.last_transmit_us.store(None);
+            Ok(())
+        }
+    }
+}
+
+struct PdoInhibitTimeSubObject {
+    pdo: &'static Pdo,
+}
+
+impl PdoInhibitTimeSubObject {
+    pub const fn new(pdo: &'static Pdo) -> Self {
+        Self { pdo }
+    }
+}
+
+impl SubObjectAccess for PdoInhibitTimeSubObject {
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        let bytes = self.pdo.inhibit_time().to_le_bytes();
+        if offset < bytes.len() {
+            let read_len = buf.len().min(bytes.len() - offset);
+            buf[0..read_len].copy_from_slice(&bytes[offset..offset + read_len]);
+            Ok(read_len)
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn read_size(&self) -> usize {
+        2
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), AbortCode> {
+        if data.len() < 2 {
+            Err(AbortCode::DataTypeMismatchLengthLow)
+        } else if data.len() > 2 {
+            Err(AbortCode::DataTypeMismatchLengthHigh)
+        } else {
+            self.pdo
+                .set_inhibit_time(u16::from_le_bytes(data.try_into().unwrap()));
             Ok(())
         }
     }
@@ -317,6 +529,7 @@ impl SubObjectAccess for PdoTransmissionTypeSubObject {
 pub struct PdoCommObject {
     cob: PdoCobSubObject,
     transmission_type: PdoTransmissionTypeSubObject,
+    inhibit_time: PdoInhibitTimeSubObject,
 }
 
 impl PdoCommObject {
@@ -324,9 +537,11 @@ impl PdoCommObject {
     pub const fn new(pdo: &'static Pdo) -> Self {
         let cob = PdoCobSubObject::new(pdo);
         let transmission_type = PdoTransmissionTypeSubObject::new(pdo);
+        let inhibit_time = PdoInhibitTimeSubObject::new(pdo);
         Self {
             cob,
             transmission_type,
+            inhibit_time,
         }
     }
 }
@@ -336,13 +551,17 @@ impl ProvidesSubObjects for PdoCommObject {
         match sub {
             0 => Some((
                 SubInfo::MAX_SUB_NUMBER,
-                const { &ConstField::new(2u8.to_le_bytes()) },
+                const { &ConstField::new(3u8.to_le_bytes()) },
             )),
             1 => Some((SubInfo::new_u32().rw_access().persist(true), &self.cob)),
             2 => Some((
                 SubInfo::new_u8().rw_access().persist(true),
                 &self.transmission_type,
             )),
+            3 => Some((
+                SubInfo::new_u16().rw_access().persist(true),
+                &self.inhibit_time,
+            )),
             _ => None,
         }
     }
@@ -355,14 +574,26 @@ impl ProvidesSubObjects for PdoCommObject {
 /// Implements a PDO mapping config object for both TPDOs and RPDOs
 #[allow(missing_debug_implementations)]
 pub struct PdoMappingObject {
-    od: &'static [ODEntry<'static>],
+    od: AtomicCell<&'static [ODEntry<'static>]>,
     pdo: &'static Pdo,
 }
 
 impl PdoMappingObject {
     /// Create a new PdoMappingObject
     pub const fn new(od: &'static [ODEntry<'static>], pdo: &'static Pdo) -> Self {
-        Self { od, pdo }
+        Self {
+            od: AtomicCell::new(od),
+            pdo,
+        }
+    }
+
+    /// Set the object dictionary table in which mapped objects are looked up
+    ///
+    /// This is used when the node's object dictionary is a merged table built from a
+    /// [`CompositeOd`](crate::object_dict::CompositeOd), so that objects from any of the component
+    /// tables can be mapped.
+    pub fn set_od(&self, od: &'static [ODEntry<'static>]) {
+        self.od.store(od);
     }
 }
 
@@ -411,6 +642,11 @@ impl ObjectAccess for PdoMappingObject {
                 return Err(AbortCode::DataTypeMismatch);
             }
             let value = u32::from_le_bytes(data.try_into().unwrap());
+            if value == 0 {
+                // A zero entry maps nothing
+                self.pdo.mapping_params[(sub - 1) as usize].store(None);
+                return Ok(());
+            }
 
             let object_id = (value >> 16) as u16;
             let mapping_sub = ((value & 0xFF00) >> 8) as u8;
@@ -421,8 +657,12 @@ impl ObjectAccess for PdoMappingObject {
                 return Err(AbortCode::IncompatibleParameter);
             }
             let length = length / 8;
-            let entry = find_object_entry(self.od, object_id).ok_or(AbortCode::NoSuchObject)?;
+            let entry =
+                find_object_entry(self.od.load(), object_id).ok_or(AbortCode::NoSuchObject)?;
             let sub_info = entry.data.sub_info(mapping_sub)?;
+            if sub_info.pdo_mapping == PdoMapping::None {
+                return Err(AbortCode::UnnallowedPdo);
+            }
             if sub_info.size < length {
                 return Err(AbortCode::IncompatibleParameter);
             }
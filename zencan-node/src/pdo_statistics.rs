@@ -0,0 +1,227 @@
+//! Per-PDO transmission statistics (objects 0x500A-0x500D)
+//!
+//! When a device config sets `statistics` in its `[pdos]` section, zencan-build creates a
+//! [`PdoStatistics`], and the node counts each TPDO it sends and each RPDO it receives, and records
+//! the time of the most recent one. This allows a mapping which never fires, or a PDO which stops
+//! arriving, to be found over SDO without logging the bus. The statistics are reported in four
+//! read-only arrays, each with one element per PDO, starting with PDO 0 in sub 1:
+//!
+//! | Index  | Type | Description |
+//! | ------ | ---- | ----------- |
+//! | 0x500A | u32  | The number of times each TPDO has been sent |
+//! | 0x500B | u32  | The time each TPDO was last sent |
+//! | 0x500C | u32  | The number of times each RPDO has been received |
+//! | 0x500D | u32  | The time each RPDO was last received |
+//!
+//! Times are in milliseconds, from the clock passed to the most recent call to
+//! [`Node::process`](crate::Node::process), so they wrap after about 49 days. An RPDO is counted
+//! when it is applied by `process`, rather than when it is stored in the mailbox. Counts saturate
+//! at `u32::MAX`, and are not persisted.
+
+use zencan_common::{
+    objects::{ObjectCode, SubInfo},
+    sdo::AbortCode,
+    AtomicCell,
+};
+
+use crate::object_dict::{read_le_bytes, ObjectAccess};
+
+/// The statistics of a single PDO
+pub struct PdoCounter {
+    count: AtomicCell<u32>,
+    last_ms: AtomicCell<u32>,
+}
+
+impl core::fmt::Debug for PdoCounter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PdoCounter")
+            .field("count", &self.count())
+            .field("last_ms", &self.last_ms())
+            .finish()
+    }
+}
+
+impl Default for PdoCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PdoCounter {
+    /// Create a counter for a PDO which has not been sent or received
+    pub const fn new() -> Self {
+        Self {
+            count: AtomicCell::new(0),
+            last_ms: AtomicCell::new(0),
+        }
+    }
+
+    /// Get the number of times the PDO has been sent or received
+    pub fn count(&self) -> u32 {
+        self.count.load()
+    }
+
+    /// Get the time the PDO was last sent or received, in milliseconds
+    ///
+    /// This is 0 if the count is 0.
+    pub fn last_ms(&self) -> u32 {
+        self.last_ms.load()
+    }
+
+    fn record(&self, timestamp_ms: u32) {
+        self.count
+            .fetch_update(|count| Some(count.saturating_add(1)))
+            .ok();
+        self.last_ms.store(timestamp_ms);
+    }
+}
+
+/// The statistics of all of the PDOs of a node
+///
+/// This is instantiated by zencan-build, and registered on the node state with
+/// [`NodeState::with_pdo_statistics`](crate::NodeState::with_pdo_statistics).
+#[derive(Debug)]
+pub struct PdoStatistics {
+    tpdos: &'static [PdoCounter],
+    rpdos: &'static [PdoCounter],
+}
+
+impl PdoStatistics {
+    /// Create the statistics from a counter for each TPDO and each RPDO
+    pub const fn new(tpdos: &'static [PdoCounter], rpdos: &'static [PdoCounter]) -> Self {
+        Self { tpdos, rpdos }
+    }
+
+    /// Get the statistics of a TPDO
+    pub fn tpdo(&self, tpdo: usize) -> Option<&PdoCounter> {
+        self.tpdos.get(tpdo)
+    }
+
+    /// Get the statistics of an RPDO
+    pub fn rpdo(&self, rpdo: usize) -> Option<&PdoCounter> {
+        self.rpdos.get(rpdo)
+    }
+
+    /// Record the transmission of a TPDO
+    pub(crate) fn record_tpdo(&self, tpdo: usize, timestamp_ms: u32) {
+        if let Some(counter) = self.tpdos.get(tpdo) {
+            counter.record(timestamp_ms);
+        }
+    }
+
+    /// Record the reception of an RPDO
+    pub(crate) fn record_rpdo(&self, rpdo: usize, timestamp_ms: u32) {
+        if let Some(counter) = self.rpdos.get(rpdo) {
+            counter.record(timestamp_ms);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Field {
+    Count,
+    LastMs,
+}
+
+/// A read-only array object reporting one field of the [`PdoCounter`] of each PDO
+#[derive(Debug)]
+pub struct PdoStatisticsObject {
+    counters: &'static [PdoCounter],
+    field: Field,
+}
+
+impl PdoStatisticsObject {
+    /// Create an object reporting the count of each PDO
+    pub const fn counts(counters: &'static [PdoCounter]) -> Self {
+        Self {
+            counters,
+            field: Field::Count,
+        }
+    }
+
+    /// Create an object reporting the time each PDO was last sent or received
+    pub const fn timestamps(counters: &'static [PdoCounter]) -> Self {
+        Self {
+            counters,
+            field: Field::LastMs,
+        }
+    }
+}
+
+impl ObjectAccess for PdoStatisticsObject {
+    fn read(&self, sub: u8, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        if sub == 0 {
+            return Ok(read_le_bytes(&[self.counters.len() as u8], offset, buf));
+        }
+        let counter = self
+            .counters
+            .get(sub as usize - 1)
+            .ok_or(AbortCode::NoSuchSubIndex)?;
+        let value = match self.field {
+            Field::Count => counter.count(),
+            Field::LastMs => counter.last_ms(),
+        };
+        Ok(read_le_bytes(&value.to_le_bytes(), offset, buf))
+    }
+
+    fn read_size(&self, sub: u8) -> Result<usize, AbortCode> {
+        Ok(self.sub_info(sub)?.size)
+    }
+
+    fn write(&self, sub: u8, _data: &[u8]) -> Result<(), AbortCode> {
+        self.sub_info(sub)?;
+        Err(AbortCode::ReadOnly)
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Array
+    }
+
+    fn sub_info(&self, sub: u8) -> Result<SubInfo, AbortCode> {
+        match sub {
+            0 => Ok(SubInfo::MAX_SUB_NUMBER),
+            n if (n as usize) <= self.counters.len() => Ok(SubInfo::new_u32()),
+            _ => Err(AbortCode::NoSuchSubIndex),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TPDOS: [PdoCounter; 2] = [const { PdoCounter::new() }; 2];
+
+    fn read_u32(object: &PdoStatisticsObject, sub: u8) -> u32 {
+        let mut buf = [0; 4];
+        assert_eq!(Ok(4), object.read(sub, 0, &mut buf));
+        u32::from_le_bytes(buf)
+    }
+
+    #[test]
+    fn test_statistics_objects() {
+        let stats = PdoStatistics::new(&TPDOS, &[]);
+        let counts = PdoStatisticsObject::counts(&TPDOS);
+        let timestamps = PdoStatisticsObject::timestamps(&TPDOS);
+
+        stats.record_tpdo(1, 1000);
+        stats.record_tpdo(1, 1500);
+        // Out of range PDOs are ignored
+        stats.record_tpdo(2, 1500);
+        stats.record_rpdo(0, 1500);
+
+        let mut buf = [0];
+        assert_eq!(Ok(1), counts.read(0, 0, &mut buf));
+        assert_eq!(2, buf[0]);
+        assert_eq!(0, read_u32(&counts, 1));
+        assert_eq!(2, read_u32(&counts, 2));
+        assert_eq!(1500, read_u32(&timestamps, 2));
+        assert_eq!(2, stats.tpdo(1).unwrap().count());
+        assert!(stats.rpdo(0).is_none());
+
+        assert_eq!(Ok(4), counts.read_size(1));
+        assert_eq!(Err(AbortCode::NoSuchSubIndex), counts.read_size(3));
+        assert_eq!(Err(AbortCode::ReadOnly), counts.write(1, &[0; 4]));
+        assert_eq!(Err(AbortCode::ReadOnly), counts.write(0, &[0]));
+    }
+}
@@ -13,6 +13,7 @@ use defmt_or_log::{debug, warn};
 
 /// Specifies the types of nodes which can be serialized to persistent storage
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum NodeType {
     /// A node containing a saved sub-object value
@@ -73,7 +74,7 @@ async fn serialize_object(obj: &ODEntry<'_>, sub: u8, reg: &RefCell<u8>) {
         // can be modified on a different thread than `Node::process()` is called. Fixing it
         // requires an object locking mechanism, which may be worth considering in the future.
         obj.data.read(sub, read_pos, &mut buf).unwrap();
-        let copy_len = data_size as usize - read_pos;
+        let copy_len = (data_size as usize - read_pos).min(CHUNK_SIZE);
         read_pos += copy_len;
         write_bytes(&buf[0..copy_len], reg).await;
         if read_pos >= data_size as usize {
@@ -131,11 +132,17 @@ pub fn serialized_size(objects: &[ODEntry]) -> usize {
 struct PersistSerializer<'a, 'b, F: Future> {
     f: Pin<&'a mut F>,
     reg: &'b RefCell<u8>,
+    /// Set once the future has completed, after which it must not be polled again
+    done: bool,
 }
 
 impl<'a, 'b, F: Future> PersistSerializer<'a, 'b, F> {
     pub fn new(f: Pin<&'a mut F>, reg: &'b RefCell<u8>) -> Self {
-        Self { f, reg }
+        Self {
+            f,
+            reg,
+            done: false,
+        }
     }
 }
 
@@ -149,12 +156,15 @@ impl<F: Future> embedded_io::Read for PersistSerializer<'_, '_, F> {
 
         let mut pos = 0;
         loop {
-            if pos >= buf.len() {
+            if pos >= buf.len() || self.done {
                 return Ok(pos);
             }
 
             match self.f.as_mut().poll(&mut cx) {
-                core::task::Poll::Ready(_) => return Ok(pos),
+                core::task::Poll::Ready(_) => {
+                    self.done = true;
+                    return Ok(pos);
+                }
                 core::task::Poll::Pending => {
                     buf[pos] = *self.reg.borrow();
                     pos += 1;
@@ -177,6 +187,8 @@ pub fn serialize<F: Fn(&mut dyn embedded_io::Read<Error = Infallible>, usize)>(
 }
 
 /// Error which can be returned while reading persisted data
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PersistReadError {
     /// Not enough bytes were present to construct the node
     NodeLengthShort,
@@ -184,6 +196,7 @@ pub enum PersistReadError {
 
 /// The data for an ObjectValue node
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct ObjectValue<'a> {
     /// The object index this value belongs to
     pub index: u16,
@@ -197,6 +210,7 @@ pub struct ObjectValue<'a> {
 ///
 /// Returned by the PersistNodeReader iterator.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PersistNodeRef<'a> {
     /// A saved value for a sub-object
     ObjectValue(ObjectValue<'a>),
@@ -263,6 +277,56 @@ impl<'a> Iterator for PersistNodeReader<'a> {
     }
 }
 
+/// Read part of the serialized data for all persisted objects, starting at `offset`
+///
+/// The data is the same as provided to the store objects callback by [`serialize`]. Returns the
+/// number of bytes read, which is less than `buf.len()` at the end of the data.
+pub(crate) fn read_serialized(od: &'static [ODEntry], offset: usize, buf: &mut [u8]) -> usize {
+    use embedded_io::Read;
+
+    let reg = RefCell::new(0);
+    let fut = pin!(serialize_sm(od, &reg));
+    let mut serializer = PersistSerializer::new(fut, &reg);
+    // The data is generated as it is read, so the bytes before the offset are read and discarded
+    let mut skip_buf = [0u8; 32];
+    let mut skip = offset;
+    while skip > 0 {
+        let chunk_len = skip.min(skip_buf.len());
+        let n = serializer.read(&mut skip_buf[..chunk_len]).unwrap();
+        if n < chunk_len {
+            // The serializer must not be polled again after it has completed
+            return 0;
+        }
+        skip -= n;
+    }
+    serializer.read(buf).unwrap()
+}
+
+/// Write a single restored value to its sub object, logging any failure
+pub(crate) fn restore_value(od: &[ODEntry], restore: &ObjectValue) {
+    if let Some(obj) = find_object(od, restore.index) {
+        if let Ok(_sub_info) = obj.sub_info(restore.sub) {
+            debug!(
+                "Restoring 0x{:x}sub{} with {:?}",
+                restore.index, restore.sub, restore.data
+            );
+            if let Err(abort_code) = obj.write(restore.sub, restore.data) {
+                warn!(
+                    "Error restoring object 0x{:x}sub{}: {:x}",
+                    restore.index, restore.sub, abort_code as u32
+                );
+            }
+        } else {
+            warn!(
+                "Saved object 0x{:x}sub{} not found in OD",
+                restore.index, restore.sub
+            );
+        }
+    } else {
+        warn!("Saved object 0x{:x} not found in OD", restore.index);
+    }
+}
+
 /// Load values of objects previously persisted in serialized format
 ///
 /// # Arguments
@@ -272,29 +336,7 @@ pub fn restore_stored_objects(od: &[ODEntry], stored_data: &[u8]) {
     let reader = PersistNodeReader::new(stored_data);
     for item in reader {
         match item {
-            PersistNodeRef::ObjectValue(restore) => {
-                if let Some(obj) = find_object(od, restore.index) {
-                    if let Ok(_sub_info) = obj.sub_info(restore.sub) {
-                        debug!(
-                            "Restoring 0x{:x}sub{} with {:?}",
-                            restore.index, restore.sub, restore.data
-                        );
-                        if let Err(abort_code) = obj.write(restore.sub, restore.data) {
-                            warn!(
-                                "Error restoring object 0x{:x}sub{}: {:x}",
-                                restore.index, restore.sub, abort_code as u32
-                            );
-                        }
-                    } else {
-                        warn!(
-                            "Saved object 0x{:x}sub{} not found in OD",
-                            restore.index, restore.sub
-                        );
-                    }
-                } else {
-                    warn!("Saved object 0x{:x} not found in OD", restore.index);
-                }
-            }
+            PersistNodeRef::ObjectValue(restore) => restore_value(od, &restore),
             PersistNodeRef::Unknown(id) => warn!("Unknown persisted object read: {}", id[0]),
         }
     }
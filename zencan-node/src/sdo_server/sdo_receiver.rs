@@ -8,6 +8,8 @@ use zencan_common::{
     AtomicCell,
 };
 
+use crate::{BufferCell, BufferRefMut};
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ReceiverState {
     Normal,
@@ -19,14 +21,31 @@ pub enum ReceiverState {
     },
 }
 
+/// Exclusive access to one of the receiver's buffers
+///
+/// The buffer is removed from the receiver while the guard exists, and returned when it is dropped
 pub struct BufferGuard<'a> {
-    buf: Option<&'static mut [u8]>,
-    home: &'a AtomicCell<Option<&'static mut [u8]>>,
+    buf: &'static BufferCell<[u8]>,
+    data: Option<BufferRefMut<'static, [u8]>>,
+    home: &'a AtomicCell<Option<&'static BufferCell<[u8]>>>,
+}
+
+impl<'a> BufferGuard<'a> {
+    fn new(home: &'a AtomicCell<Option<&'static BufferCell<[u8]>>>) -> Self {
+        let buf = home.take().expect("SDO buffer is already borrowed");
+        Self {
+            buf,
+            data: Some(buf.borrow_mut()),
+            home,
+        }
+    }
 }
 
 impl Drop for BufferGuard<'_> {
     fn drop(&mut self) {
-        self.home.store(Some(self.buf.take().unwrap()));
+        // Release the borrow before the buffer can be taken again
+        self.data = None;
+        self.home.store(Some(self.buf));
     }
 }
 
@@ -34,13 +53,13 @@ impl Deref for BufferGuard<'_> {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        self.buf.as_ref().unwrap()
+        self.data.as_deref().unwrap()
     }
 }
 
 impl DerefMut for BufferGuard<'_> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        self.buf.as_mut().unwrap()
+        self.data.as_deref_mut().unwrap()
     }
 }
 
@@ -52,10 +71,15 @@ impl DerefMut for BufferGuard<'_> {
 ///
 /// A timer is also reset to 0 on each message received, and this can be used in `process()` to
 /// implement a timeout in case an expected message is never received.
+///
+/// An optional second buffer of the same size may be provided as a write buffer. When present, a
+/// full buffer of download data can be swapped into the write buffer, and written to the object
+/// while the next data is received.
 pub(crate) struct SdoReceiver {
     request: AtomicCell<Option<SdoRequest>>,
     state: AtomicCell<ReceiverState>,
-    buffer: AtomicCell<Option<&'static mut [u8]>>,
+    buffer: AtomicCell<Option<&'static BufferCell<[u8]>>>,
+    write_buffer: AtomicCell<Option<&'static BufferCell<[u8]>>>,
     timer: UnsafeCell<u32>,
     last_seqnum: UnsafeCell<u8>,
     blksize: UnsafeCell<u8>,
@@ -64,18 +88,54 @@ pub(crate) struct SdoReceiver {
 unsafe impl Sync for SdoReceiver {}
 
 impl SdoReceiver {
-    pub const fn new(sdo_buffer: &'static mut [u8]) -> Self {
+    pub const fn new(sdo_buffer: &'static BufferCell<[u8]>) -> Self {
         Self {
             request: AtomicCell::new(None),
             state: AtomicCell::new(ReceiverState::Normal),
             buffer: AtomicCell::new(Some(sdo_buffer)),
+            write_buffer: AtomicCell::new(None),
             timer: UnsafeCell::new(0),
             last_seqnum: UnsafeCell::new(0),
             blksize: UnsafeCell::new(0),
         }
     }
 
+    /// Add a write buffer, which must be the same size as the SDO buffer
+    pub const fn with_write_buffer(self, write_buffer: &'static BufferCell<[u8]>) -> Self {
+        Self {
+            write_buffer: AtomicCell::new(Some(write_buffer)),
+            ..self
+        }
+    }
+
+    /// Handle a request which was queued while the receiver was in the normal state
+    ///
+    /// A request queued before a block download began was sent before the client received the
+    /// acknowledgement of the download, so it is not a block segment, even if the receiver has
+    /// since begun receiving blocks. Rather than writing it into the buffer, it interrupts the
+    /// block download, and is stored for processing, so that the download is aborted.
+    pub fn handle_queued_req(&self, msg_data: &[u8]) -> bool {
+        if msg_data.len() != 8 {
+            return false;
+        }
+        if self.state.load() == ReceiverState::Normal {
+            return self.handle_req(msg_data);
+        }
+        match SdoRequest::try_from(msg_data) {
+            Ok(req) => {
+                self.request.store(Some(req));
+                self.set_state(ReceiverState::Normal);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
     /// Handle received request from client
+    ///
+    /// Returns true if the request must be handled by the server. Frames which are not 8 bytes
+    /// long are ignored in every state, so that a malformed frame can never be mistaken for a
+    /// request or a block segment.
     pub fn handle_req(&self, msg_data: &[u8]) -> bool {
         // Ignore invalid lengths
         if msg_data.len() != 8 {
@@ -118,13 +178,15 @@ impl SdoReceiver {
                 let mut process_required = false;
                 critical_section::with(|_| unsafe {
                     *self.timer.get() = 0;
-                    // seqnum comes from a 7-bit field so max possible value is 127
-                    let pos = (segment.seqnum - 1) as usize * 7;
-                    if pos + 7 <= buffer.len() {
-                        buffer[pos..pos + 7].copy_from_slice(&segment.data);
-                    }
-
+                    // Only segments received in order are stored. Segments following a missed
+                    // segment are retransmitted anyway, and a repeated seqnum must not overwrite
+                    // data which has already been received.
                     if segment.seqnum == *self.last_seqnum.get() + 1 {
+                        // seqnum comes from a 7-bit field so max possible value is 127
+                        let pos = (segment.seqnum - 1) as usize * 7;
+                        if pos + 7 <= buffer.len() {
+                            buffer[pos..pos + 7].copy_from_slice(&segment.data);
+                        }
                         *self.last_seqnum.get() += 1;
                     }
 
@@ -156,21 +218,42 @@ impl SdoReceiver {
     ///
     /// It will be returned on drop.
     ///
-    /// This function will panic if the buffer has already been borrowed, or if the buffer was never
-    /// set via `store_buffer`.
+    /// This function will panic if the buffer has already been borrowed.
     pub(crate) fn borrow_buffer(&self) -> BufferGuard<'_> {
-        let buf = self.buffer.take();
+        BufferGuard::new(&self.buffer)
+    }
 
-        BufferGuard {
-            buf,
-            home: &self.buffer,
-        }
+    /// Borrow the write buffer, holding data swapped out by [`swap_buffers`](Self::swap_buffers)
+    ///
+    /// It will be returned on drop. This function will panic if there is no write buffer.
+    pub(crate) fn borrow_write_buffer(&self) -> BufferGuard<'_> {
+        BufferGuard::new(&self.write_buffer)
+    }
+
+    /// Returns true if a write buffer is available for buffers of `len` bytes
+    pub(crate) fn can_swap_buffers(&self, len: usize) -> bool {
+        self.write_buffer.load().is_some_and(|b| b.len() == len)
+    }
+
+    /// Exchange the SDO buffer and the write buffer
+    ///
+    /// Neither buffer may be borrowed, and the write buffer must exist
+    pub(crate) fn swap_buffers(&self) {
+        critical_section::with(|_| {
+            let buffer = self.buffer.take();
+            self.buffer.store(self.write_buffer.take());
+            self.write_buffer.store(buffer);
+        });
     }
 
     pub(crate) fn take_request(&self) -> Option<SdoRequest> {
         self.request.take()
     }
 
+    pub(crate) fn peek_request(&self) -> Option<SdoRequest> {
+        self.request.load()
+    }
+
     pub(crate) fn begin_block_download(&self, blksize: u8) {
         critical_section::with(|_| unsafe {
             *self.last_seqnum.get() = 0;
@@ -188,6 +271,10 @@ impl SdoReceiver {
         });
     }
 
+    pub(crate) fn timer(&self) -> u32 {
+        critical_section::with(|_| unsafe { *self.timer.get() })
+    }
+
     pub(crate) fn increment_timer(&self, elapsed_us: u32) -> u32 {
         let mut timer = 0;
         critical_section::with(|_| unsafe {
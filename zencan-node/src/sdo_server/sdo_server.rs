@@ -1,10 +1,10 @@
 use crc16::CrcType as _;
 use zencan_common::{
     objects::{DataType, ObjectId, SubInfo},
-    sdo::{AbortCode, SdoRequest, SdoResponse},
+    sdo::{AbortCode, SdoRequest, SdoResponse, SdoServerStatus, SdoTransferState},
 };
 
-use crate::object_dict::{find_object_entry, ODEntry};
+use crate::object_dict::{find_object_entry, ODEntry, ObjectAccess};
 
 use crate::sdo_server::{sdo_receiver::ReceiverState, SdoReceiver};
 
@@ -37,10 +37,56 @@ fn validate_download_size(dl_size: usize, subobj: &SubInfo) -> Result<(), AbortC
     Ok(())
 }
 
+/// Check that a download to a sub object can be accepted, and return its info
+///
+/// The checks are made in the order expected by CiA 301 conformance tests, so that a request with
+/// several problems is aborted with the first: a missing sub object, then a sub object which is not
+/// writable, then a size which does not fit. `dl_size` is None when the client does not indicate
+/// the size.
+fn validate_download(
+    obj: &dyn ObjectAccess,
+    sub: u8,
+    dl_size: Option<usize>,
+) -> Result<SubInfo, AbortCode> {
+    let subinfo = obj.sub_info(sub)?;
+    if !subinfo.access_type.is_writable() {
+        return Err(AbortCode::ReadOnly);
+    }
+    if let Some(dl_size) = dl_size {
+        validate_download_size(dl_size, &subinfo)?;
+    }
+    Ok(subinfo)
+}
+
+/// A full buffer of download data, waiting to be written to the object
+///
+/// When the receiver has a write buffer, the data is swapped into it so that the next data can be
+/// received while the write is performed by the following call to `SdoServer::process`.
+#[derive(Clone, Copy)]
+struct PendingWrite {
+    object: &'static ODEntry<'static>,
+    sub: u8,
+    len: usize,
+    /// True if this is the first write of the transfer, and the partial write must be started
+    begin: bool,
+}
+
+impl PendingWrite {
+    fn write(&self, rx: &SdoReceiver) -> Result<(), AbortCode> {
+        let buf = rx.borrow_write_buffer();
+        let obj = &self.object.data;
+        if self.begin {
+            obj.begin_partial(self.sub)?;
+        }
+        obj.write_partial(self.sub, &buf[..self.len])
+    }
+}
+
 struct SdoResult {
     response: Option<SdoResponse>,
     updated_object: Option<ObjectId>,
     new_state: SdoState,
+    pending_write: Option<PendingWrite>,
 }
 
 impl SdoResult {
@@ -49,6 +95,7 @@ impl SdoResult {
             response: None,
             updated_object: None,
             new_state,
+            pending_write: None,
         }
     }
 
@@ -57,6 +104,7 @@ impl SdoResult {
             response: Some(SdoResponse::abort(index, sub, abort_code)),
             updated_object: None,
             new_state: SdoState::Idle,
+            pending_write: None,
         }
     }
 
@@ -65,6 +113,7 @@ impl SdoResult {
             response: Some(response),
             updated_object: None,
             new_state,
+            pending_write: None,
         }
     }
 
@@ -78,6 +127,7 @@ impl SdoResult {
             response: Some(response),
             updated_object: Some(ObjectId { index, sub }),
             new_state,
+            pending_write: None,
         }
     }
 }
@@ -89,6 +139,8 @@ struct Segmented {
     toggle_state: bool,
     segment_counter: u32,
     bytes_in_buffer: Option<u32>,
+    /// The generation of the object when an upload started, if the object tracks changes
+    generation: Option<u32>,
 }
 
 #[derive(Clone, Copy)]
@@ -110,6 +162,45 @@ enum SdoState {
 }
 
 impl SdoState {
+    /// Get the externally visible status of the transfer
+    fn status(&self) -> SdoServerStatus {
+        let (state, object, sub, bytes) = match self {
+            SdoState::Idle => return SdoServerStatus::IDLE,
+            SdoState::DownloadSegmented(s) => (
+                SdoTransferState::SegmentedDownload,
+                s.object,
+                s.sub,
+                s.segment_counter as usize * 7,
+            ),
+            SdoState::UploadSegmented(s) => (
+                SdoTransferState::SegmentedUpload,
+                s.object,
+                s.sub,
+                s.segment_counter as usize * 7,
+            ),
+            SdoState::DownloadBlock(s) => (
+                SdoTransferState::BlockDownload,
+                s.object,
+                s.sub,
+                s.block_counter * BLKSIZE as usize * 7,
+            ),
+            // The final block may be shorter, and its unused bytes are not known until the end
+            SdoState::EndDownloadBlock(s) => (
+                SdoTransferState::BlockDownload,
+                s.object,
+                s.sub,
+                (s.block_counter.saturating_sub(1) * BLKSIZE as usize + s.last_segment as usize)
+                    * 7,
+            ),
+        };
+        SdoServerStatus {
+            state,
+            index: object.index,
+            sub,
+            bytes: bytes as u32,
+        }
+    }
+
     pub fn update(
         &self,
         rx: &SdoReceiver,
@@ -126,6 +217,12 @@ impl SdoState {
     }
 
     fn idle(od: &'static [ODEntry<'static>], rx: &SdoReceiver) -> SdoResult {
+        // No transfer owns the buffer, so the receiver must not be writing block segments into it.
+        // It can only be left receiving blocks if the server was recreated during a block
+        // download.
+        if rx.state() != ReceiverState::Normal {
+            rx.set_state(ReceiverState::Normal);
+        }
         let req = match rx.take_request() {
             Some(req) => req,
             None => return SdoResult::no_response(SdoState::Idle),
@@ -146,24 +243,20 @@ impl SdoState {
                 };
                 let obj = &od_entry.data;
 
-                let subinfo = match obj.sub_info(sub) {
-                    Ok(s) => s,
-                    Err(abort_code) => return SdoResult::abort(index, sub, abort_code),
+                // The size is given in the data for a segmented download, and by n for an
+                // expedited download
+                let dl_size = match (e, s) {
+                    (true, _) => Some(4 - n as usize),
+                    (false, true) => Some(u32::from_le_bytes(data) as usize),
+                    (false, false) => None,
                 };
+                if let Err(abort_code) = validate_download(*obj, sub, dl_size) {
+                    return SdoResult::abort(index, sub, abort_code);
+                }
 
                 if e {
                     // Doing an expedited download
-                    // Verify that the requested object is writable
-                    if !subinfo.access_type.is_writable() {
-                        return SdoResult::abort(index, sub, AbortCode::ReadOnly);
-                    }
-
-                    // Verify data size requested by client fits object, and abort if not
                     let dl_size = 4 - n as usize;
-                    if let Err(abort_code) = validate_download_size(dl_size, &subinfo) {
-                        return SdoResult::abort(index, sub, abort_code);
-                    }
-
                     if let Err(abort_code) = obj.write(sub, &data[0..dl_size]) {
                         return SdoResult::abort(index, sub, abort_code);
                     }
@@ -172,24 +265,17 @@ impl SdoState {
                         response: Some(SdoResponse::download_acknowledge(index, sub)),
                         updated_object: Some(ObjectId { index, sub }),
                         new_state: SdoState::Idle,
+                        pending_write: None,
                     }
                 } else {
                     // starting a segmented download
-                    // If size is provided, verify data size requested by client fits object, and
-                    // abort if not
-                    if s {
-                        let dl_size = u32::from_le_bytes(data) as usize;
-                        if let Err(abort_code) = validate_download_size(dl_size, &subinfo) {
-                            return SdoResult::abort(index, sub, abort_code);
-                        }
-                    }
-
                     let new_state = SdoState::DownloadSegmented(Segmented {
                         object: od_entry,
                         sub,
                         toggle_state: false,
                         segment_counter: 0,
                         bytes_in_buffer: Some(0),
+                        generation: None,
                     });
                     SdoResult::response(SdoResponse::download_acknowledge(index, sub), new_state)
                 }
@@ -200,6 +286,9 @@ impl SdoState {
                     None => return SdoResult::abort(index, sub, AbortCode::NoSuchObject),
                 };
                 let obj = od_entry.data;
+                // Sample the generation before reading, so that a change during the first read is
+                // also detected
+                let generation = obj.generation(sub);
 
                 let mut full_buf = rx.borrow_buffer();
                 let len = full_buf.len();
@@ -221,16 +310,27 @@ impl SdoState {
 
                     // If read size is less than the buffer length then the read is atomic and we
                     // can safely report the size of the read up front. If it is equal, then the
-                    // read may be longer and we do not report the size up front because it may
-                    // change in the interim. We can't achieve atomic reads for sub objects that are
-                    // larger than the buffer, and so the read size may change. If large objects are
-                    // written during an SDO transfer, it is possible for the client to receive a
-                    // torn read, which is some combination of multiple values.
-                    let ack_size = if read_size == buf.len() {
+                    // read may be longer, and the object will be read again each time the buffer
+                    // is emptied. We can't achieve atomic reads for sub objects that are larger
+                    // than the buffer, so if they are written during the transfer, the client may
+                    // receive a torn read, which is some combination of multiple values. Objects
+                    // which provide a generation prevent this: the generation is checked after
+                    // each read, and the upload aborted if it has changed. For these objects, the
+                    // size can be reported up front, since it cannot change without an abort.
+                    let bytes_in_buffer = if read_size == buf.len() {
                         None
                     } else {
                         Some(read_size as u32)
                     };
+                    let ack_size = match (bytes_in_buffer, generation) {
+                        (Some(size), _) => Some(size),
+                        (None, Some(_)) => obj
+                            .read_size(sub)
+                            .ok()
+                            .filter(|size| *size >= read_size)
+                            .map(|size| size as u32),
+                        (None, None) => None,
+                    };
                     SdoResult::response(
                         SdoResponse::upload_acknowledge(index, sub, ack_size),
                         SdoState::UploadSegmented(Segmented {
@@ -238,7 +338,8 @@ impl SdoState {
                             sub,
                             toggle_state: false,
                             segment_counter: 0,
-                            bytes_in_buffer: ack_size,
+                            bytes_in_buffer,
+                            generation,
                         }),
                     )
                 }
@@ -256,17 +357,9 @@ impl SdoState {
                     None => return SdoResult::abort(index, sub, AbortCode::NoSuchObject),
                 };
 
-                let subinfo = match od_entry.data.sub_info(sub) {
-                    Ok(s) => s,
-                    Err(abort_code) => return SdoResult::abort(index, sub, abort_code),
-                };
-
-                // If size is provided, verify data size requested by client fits object, and
-                // abort if not
-                if s {
-                    if let Err(abort_code) = validate_download_size(size as usize, &subinfo) {
-                        return SdoResult::abort(index, sub, abort_code);
-                    }
+                let dl_size = s.then_some(size as usize);
+                if let Err(abort_code) = validate_download(od_entry.data, sub, dl_size) {
+                    return SdoResult::abort(index, sub, abort_code);
                 }
 
                 rx.begin_block_download(BLKSIZE);
@@ -334,8 +427,17 @@ impl SdoState {
                 let buffer_full = buffer_offset + copy_len == buf.len();
                 let more_bytes_in_message = copy_len < segment_size;
 
-                // See if we need to make this a partial write
-                if buffer_full && (!c || more_bytes_in_message) {
+                let mut pending_write = None;
+                if buffer_full && !c && rx.can_swap_buffers(buf.len()) {
+                    // Defer the write, so that this segment can be acknowledged right away
+                    pending_write = Some(PendingWrite {
+                        object: state.object,
+                        sub: state.sub,
+                        len: buf.len(),
+                        begin: on_first_buffer,
+                    });
+                } else if buffer_full && (!c || more_bytes_in_message) {
+                    // See if we need to make this a partial write
                     if on_first_buffer {
                         if let Err(abort_code) = obj.begin_partial(state.sub) {
                             return SdoResult::abort(state.object.index, state.sub, abort_code);
@@ -382,6 +484,12 @@ impl SdoState {
                         SdoState::Idle,
                     )
                 } else {
+                    if pending_write.is_some() {
+                        // Receive the following segments into the other buffer
+                        drop(buf);
+                        rx.swap_buffers();
+                        buf = rx.borrow_buffer();
+                    }
                     // Segments that didn't fit in the buffer get stored to beginning of new buffer
                     if copy_len < segment_size {
                         buf[0..segment_size - copy_len]
@@ -393,10 +501,13 @@ impl SdoState {
                         segment_counter: state.segment_counter + 1,
                         ..*state
                     });
-                    SdoResult::response(
-                        SdoResponse::download_segment_acknowledge(state.toggle_state),
-                        new_state,
-                    )
+                    SdoResult {
+                        pending_write,
+                        ..SdoResult::response(
+                            SdoResponse::download_segment_acknowledge(state.toggle_state),
+                            new_state,
+                        )
+                    }
                 }
             }
             SdoRequest::Abort {
@@ -420,7 +531,7 @@ impl SdoState {
                 if time > SDO_TIMEOUT_US {
                     return SdoResult::abort(state.object.index, state.sub, AbortCode::SdoTimeout);
                 } else {
-                    return SdoResult::no_response(SdoState::DownloadSegmented(*state));
+                    return SdoResult::no_response(SdoState::UploadSegmented(*state));
                 }
             }
         };
@@ -460,11 +571,29 @@ impl SdoState {
                     if buf_read_offset + segment_size == buf.len() {
                         // We completed the buffered data. Read again to see if there is more data
                         // to send
-                        let read_size = state
-                            .object
-                            .data
-                            .read(state.sub, total_read_offset + segment_size, buf)
-                            .unwrap();
+                        let obj = state.object.data;
+                        let read_size =
+                            match obj.read(state.sub, total_read_offset + segment_size, buf) {
+                                Ok(s) => s,
+                                Err(abort_code) => {
+                                    return SdoResult::abort(
+                                        state.object.index,
+                                        state.sub,
+                                        abort_code,
+                                    )
+                                }
+                            };
+                        if state.generation.is_some()
+                            && obj.generation(state.sub) != state.generation
+                        {
+                            // The object changed since the upload started, so the data read does
+                            // not follow on from the data already sent
+                            return SdoResult::abort(
+                                state.object.index,
+                                state.sub,
+                                AbortCode::GeneralError,
+                            );
+                        }
                         if read_size == 0 {
                             // No further data in object, this is the last segment
                             c = true;
@@ -492,6 +621,7 @@ impl SdoState {
                         toggle_state: !state.toggle_state,
                         segment_counter: state.segment_counter + 1,
                         bytes_in_buffer,
+                        generation: state.generation,
                     })
                 };
 
@@ -527,12 +657,18 @@ impl SdoState {
         // any acknowledgement, so the processing of these is handled in the receiver. Here, we wait
         // for the receiver to signal the completion of a block
         match rx.state() {
-            // If receiver went back to normal state, that means it detected an Abort request
-            ReceiverState::Normal => {
-                // Remove the request from the mailbox
-                let _ = rx.take_request();
-                SdoResult::no_response(SdoState::Idle)
-            }
+            // If receiver went back to normal state, that means it detected an Abort request, or
+            // a request which was queued before the transfer began
+            ReceiverState::Normal => match rx.take_request() {
+                None | Some(SdoRequest::Abort { .. }) => SdoResult::no_response(SdoState::Idle),
+                // The request can't be handled while the transfer is in progress, so the transfer
+                // is aborted
+                Some(_) => SdoResult::abort(
+                    state.object.index,
+                    state.sub,
+                    AbortCode::InvalidCommandSpecifier,
+                ),
+            },
             ReceiverState::BlockReceive => {
                 // Still waiting. Check timeout.
                 let time = rx.increment_timer(elapsed_us);
@@ -560,6 +696,7 @@ impl SdoState {
                         SdoState::DownloadBlock(*state),
                     )
                 } else {
+                    let mut pending_write = None;
                     let new_state = if complete {
                         // This is the last block, but we can't do anything with it until we get the
                         // end block transfer request because we don't know how many bytes are
@@ -580,26 +717,44 @@ impl SdoState {
                         // Update the running CRC
                         let crc = crc16::XMODEM::update(state.crc, valid_data);
 
-                        // If this is the first block of a multi-part block transfer, we begin
-                        // partial write now. Not all objects support partial write, although
-                        // generally any object large enough to warrant a multi-block transfer
-                        // probably should.
-                        if state.block_counter == 0 {
-                            if let Err(abort_code) = state.object.data.begin_partial(state.sub) {
+                        if rx.can_swap_buffers(buf.len()) {
+                            // Defer the write, and receive the next block into the other buffer
+                            // while it is performed
+                            drop(buf);
+                            rx.swap_buffers();
+                            pending_write = Some(PendingWrite {
+                                object: state.object,
+                                sub: state.sub,
+                                len: write_length,
+                                begin: state.block_counter == 0,
+                            });
+                        } else {
+                            // If this is the first block of a multi-part block transfer, we begin
+                            // partial write now. Not all objects support partial write, although
+                            // generally any object large enough to warrant a multi-block transfer
+                            // probably should.
+                            if state.block_counter == 0 {
+                                if let Err(abort_code) = state.object.data.begin_partial(state.sub)
+                                {
+                                    rx.set_state(ReceiverState::Normal);
+                                    return SdoResult::abort(
+                                        state.object.index,
+                                        state.sub,
+                                        abort_code,
+                                    );
+                                }
+                            }
+
+                            // Attempt to write the block. It may fail if, for example, the data exceeds
+                            // the size of the object
+                            if let Err(abort_code) =
+                                state.object.data.write_partial(state.sub, valid_data)
+                            {
                                 rx.set_state(ReceiverState::Normal);
                                 return SdoResult::abort(state.object.index, state.sub, abort_code);
                             }
                         }
 
-                        // Attempt to write the block. It may fail if, for example, the data exceeds
-                        // the size of the object
-                        if let Err(abort_code) =
-                            state.object.data.write_partial(state.sub, valid_data)
-                        {
-                            rx.set_state(ReceiverState::Normal);
-                            return SdoResult::abort(state.object.index, state.sub, abort_code);
-                        }
-
                         // Prepare to download a new block
                         rx.begin_block_download(BLKSIZE);
                         SdoState::DownloadBlock(DownloadBlock {
@@ -608,13 +763,16 @@ impl SdoState {
                             ..*state
                         })
                     };
-                    SdoResult::response(
-                        SdoResponse::ConfirmBlock {
-                            ackseq,
-                            blksize: BLKSIZE,
-                        },
-                        new_state,
-                    )
+                    SdoResult {
+                        pending_write,
+                        ..SdoResult::response(
+                            SdoResponse::ConfirmBlock {
+                                ackseq,
+                                blksize: BLKSIZE,
+                            },
+                            new_state,
+                        )
+                    }
                 }
             }
         }
@@ -687,6 +845,63 @@ impl SdoState {
     }
 }
 
+/// An object access completed or aborted by the SDO server
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct SdoAccess {
+    pub index: u16,
+    pub sub: u8,
+    /// True for a download, false for an upload
+    pub write: bool,
+    /// The abort code sent to the client, or 0 if the access succeeded
+    pub abort_code: u32,
+}
+
+impl SdoAccess {
+    /// Determine the access reported by the result of an update
+    ///
+    /// `write` is the direction of the transfer being processed, if known. Uploads are reported when
+    /// they are started, as the object is read then, and downloads when they are completed.
+    fn from_result(
+        response: Option<&SdoResponse>,
+        updated_object: Option<ObjectId>,
+        write: Option<bool>,
+    ) -> Option<Self> {
+        if let Some(id) = updated_object {
+            // The completion of a segmented upload also reports the object, but the upload was
+            // already reported when it started
+            if write == Some(false) {
+                return None;
+            }
+            return Some(Self {
+                index: id.index,
+                sub: id.sub,
+                write: true,
+                abort_code: 0,
+            });
+        }
+        match *response? {
+            SdoResponse::ConfirmUpload { index, sub, .. }
+            | SdoResponse::ConfirmBlockUpload { index, sub, .. } => Some(Self {
+                index,
+                sub,
+                write: false,
+                abort_code: 0,
+            }),
+            SdoResponse::Abort {
+                index,
+                sub,
+                abort_code,
+            } => Some(Self {
+                index,
+                sub,
+                write: write?,
+                abort_code,
+            }),
+            _ => None,
+        }
+    }
+}
+
 /// Implements an SDO server
 ///
 /// A single SDO server can be controlled by a single SDO client (at one time). This struct wraps up
@@ -694,6 +909,8 @@ impl SdoState {
 /// instantiate multiple instances of `SdoServer` to track each.
 pub(crate) struct SdoServer {
     state: SdoState,
+    pending_write: Option<PendingWrite>,
+    last_access: Option<SdoAccess>,
 }
 
 impl SdoServer {
@@ -701,6 +918,8 @@ impl SdoServer {
     pub fn new() -> Self {
         Self {
             state: SdoState::Idle,
+            pending_write: None,
+            last_access: None,
         }
     }
 
@@ -709,29 +928,104 @@ impl SdoServer {
     /// This will process the request, update server state and the object dictionary accordingly,
     /// and return a response to be transmitted back to the client, as well the index of the updated
     /// object when a download is completed.
+    ///
+    /// If the previous call deferred writing a buffer of download data, the write is performed
+    /// first. If it fails, the transfer is aborted and the abort response is returned.
     pub fn process(
         &mut self,
         rx: &SdoReceiver,
         elapsed_us: u32,
         od: &'static [ODEntry<'static>],
     ) -> (Option<SdoResponse>, Option<ObjectId>) {
+        if let Some(pending) = self.pending_write.take() {
+            if let Err(abort_code) = pending.write(rx) {
+                // Discard the transfer, and any request already received for it
+                rx.set_state(ReceiverState::Normal);
+                let _ = rx.take_request();
+                self.state = SdoState::Idle;
+                let index = pending.object.index;
+                self.last_access = Some(SdoAccess {
+                    index,
+                    sub: pending.sub,
+                    write: true,
+                    abort_code: abort_code as u32,
+                });
+                return (
+                    Some(SdoResponse::abort(index, pending.sub, abort_code)),
+                    None,
+                );
+            }
+        }
+
+        let write = match &self.state {
+            SdoState::Idle => rx.peek_request().and_then(|req| match req {
+                SdoRequest::InitiateDownload { .. } | SdoRequest::InitiateBlockDownload { .. } => {
+                    Some(true)
+                }
+                SdoRequest::InitiateUpload { .. } | SdoRequest::InitiateBlockUpload { .. } => {
+                    Some(false)
+                }
+                _ => None,
+            }),
+            SdoState::UploadSegmented(_) => Some(false),
+            SdoState::DownloadSegmented(_)
+            | SdoState::DownloadBlock(_)
+            | SdoState::EndDownloadBlock(_) => Some(true),
+        };
         let result = self.state.update(rx, elapsed_us, od);
         self.state = result.new_state;
+        self.pending_write = result.pending_write;
+        self.last_access =
+            SdoAccess::from_result(result.response.as_ref(), result.updated_object, write);
         (result.response, result.updated_object)
     }
+
+    /// Take the object access reported by the last call to [`process`](Self::process), if any
+    pub fn take_access(&mut self) -> Option<SdoAccess> {
+        self.last_access.take()
+    }
+
+    /// Returns true if a buffer of download data is waiting to be written by the next call to
+    /// [`process`](Self::process)
+    pub fn write_pending(&self) -> bool {
+        self.pending_write.is_some()
+    }
+
+    /// Returns true if a transfer is currently in progress
+    pub fn is_active(&self) -> bool {
+        !matches!(self.state, SdoState::Idle)
+    }
+
+    /// Get the type of transfer in progress, the object it accesses, and the bytes transferred
+    pub fn status(&self) -> SdoServerStatus {
+        self.state.status()
+    }
+
+    /// Get the time remaining before an in-progress transfer times out, in microseconds
+    ///
+    /// Returns None if no transfer is in progress
+    pub fn time_until_timeout(&self, rx: &SdoReceiver) -> Option<u32> {
+        if self.is_active() {
+            // The timeout fires once the timer exceeds the limit
+            Some((SDO_TIMEOUT_US + 1).saturating_sub(rx.timer()))
+        } else {
+            None
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::object_dict::{
-        find_object, ByteField, ConstField, NullTermByteField, ProvidesSubObjects, SubObjectAccess,
+        find_object, ByteField, CallbackObject, ConstField, GenerationCounter, NullTermByteField,
+        ProvidesSubObjects, ScalarField, StubObject, SubObjectAccess,
     };
     use zencan_common::{
         objects::{AccessType, DataType, ObjectCode},
         sdo::BlockSegment,
     };
 
-    use crate::SDO_BUFFER_SIZE;
+    use crate::{BufferCell, SDO_BUFFER_SIZE};
 
     use super::*;
 
@@ -881,7 +1175,7 @@ mod tests {
 
     #[test]
     fn test_block_download() {
-        let buffer = Box::leak(Box::new([0; SDO_BUFFER_SIZE]));
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; SDO_BUFFER_SIZE])));
         let mut server = SdoServer::new();
         let rx = SdoReceiver::new(buffer);
         let od = test_od();
@@ -892,9 +1186,75 @@ mod tests {
         do_happy_block_download(&mut server, &rx, od, 1200);
     }
 
+    #[test]
+    fn test_block_download_double_buffered() {
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; SDO_BUFFER_SIZE])));
+        let write_buffer = Box::leak(Box::new(BufferCell::new([0u8; SDO_BUFFER_SIZE])));
+        let mut server = SdoServer::new();
+        let rx = SdoReceiver::new(buffer).with_write_buffer(write_buffer);
+        let od = test_od();
+
+        do_happy_block_download(&mut server, &rx, od, 128);
+        do_happy_block_download(&mut server, &rx, od, 1200);
+    }
+
+    #[test]
+    fn test_block_download_deferred_write() {
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; SDO_BUFFER_SIZE])));
+        let write_buffer = Box::leak(Box::new(BufferCell::new([0u8; SDO_BUFFER_SIZE])));
+        let mut server = SdoServer::new();
+        let rx = SdoReceiver::new(buffer).with_write_buffer(write_buffer);
+        let od = test_od();
+
+        const INDEX: u16 = 0x1000;
+        const SUB: u8 = 1;
+        rx.handle_req(&SdoRequest::initiate_block_download(INDEX, SUB, true, 1000).to_bytes());
+        server.process(&rx, 0, od);
+
+        // Send a full block
+        for seqnum in 1..=127 {
+            let segment = BlockSegment {
+                c: false,
+                seqnum,
+                data: [seqnum; 7],
+            };
+            rx.handle_req(&segment.to_bytes());
+        }
+        // The block is acknowledged before it is written to the object
+        let (resp, _) = server.process(&rx, 0, od);
+        assert_eq!(
+            Some(SdoResponse::ConfirmBlock {
+                ackseq: 127,
+                blksize: 127
+            }),
+            resp
+        );
+        assert!(server.write_pending());
+        let mut read_buf = [0; 7];
+        od[0].data.read(SUB, 0, &mut read_buf).unwrap();
+        assert_eq!([0; 7], read_buf);
+
+        // The next block can be received while the write is pending, and the write is performed
+        // on the next call to process
+        rx.handle_req(
+            &BlockSegment {
+                c: false,
+                seqnum: 1,
+                data: [0xff; 7],
+            }
+            .to_bytes(),
+        );
+        assert_eq!((None, None), server.process(&rx, 0, od));
+        assert!(!server.write_pending());
+        od[0].data.read(SUB, 0, &mut read_buf).unwrap();
+        assert_eq!([1; 7], read_buf);
+        od[0].data.read(SUB, 126 * 7, &mut read_buf).unwrap();
+        assert_eq!([127; 7], read_buf);
+    }
+
     #[test]
     fn test_block_download_missing_block() {
-        let buffer = Box::leak(Box::new([0; SDO_BUFFER_SIZE]));
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; SDO_BUFFER_SIZE])));
         let mut server = SdoServer::new();
         let rx = SdoReceiver::new(buffer);
         let od = test_od();
@@ -1008,9 +1368,127 @@ mod tests {
         assert_eq!(data.as_slice(), read_buf);
     }
 
+    #[test]
+    fn test_block_download_ignores_repeated_segments() {
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; SDO_BUFFER_SIZE])));
+        let mut server = SdoServer::new();
+        let rx = SdoReceiver::new(buffer);
+        let od = test_od();
+
+        const INDEX: u16 = 0x1000;
+        const SUB: u8 = 1;
+        const DATA_SIZE: usize = 7 * 3;
+        let data: Vec<u8> = (0..DATA_SIZE as u8).collect();
+        let segment = |seqnum: u8, c: bool| BlockSegment {
+            c,
+            seqnum,
+            data: data[(seqnum as usize - 1) * 7..seqnum as usize * 7]
+                .try_into()
+                .unwrap(),
+        };
+
+        rx.handle_req(
+            &SdoRequest::initiate_block_download(INDEX, SUB, true, DATA_SIZE as u32).to_bytes(),
+        );
+        server.process(&rx, 0, od);
+
+        rx.handle_req(&segment(1, false).to_bytes());
+        rx.handle_req(&segment(2, false).to_bytes());
+        // A repeat of an earlier segment with different data, and a request from another client
+        // which is indistinguishable from a segment, must not overwrite the received data
+        rx.handle_req(
+            &BlockSegment {
+                c: false,
+                seqnum: 1,
+                data: [0xff; 7],
+            }
+            .to_bytes(),
+        );
+        rx.handle_req(&SdoRequest::initiate_upload(INDEX, 2).to_bytes());
+        assert_eq!((None, None), server.process(&rx, 0, od));
+
+        rx.handle_req(&segment(3, true).to_bytes());
+        let (resp, _) = server.process(&rx, 0, od);
+        assert_eq!(
+            Some(SdoResponse::ConfirmBlock {
+                ackseq: 3,
+                blksize: 127
+            }),
+            resp
+        );
+
+        let crc = crc16::State::<crc16::XMODEM>::calculate(&data);
+        rx.handle_req(&SdoRequest::end_block_download(0, crc).to_bytes());
+        let (resp, _) = server.process(&rx, 0, od);
+        assert_eq!(Some(SdoResponse::ConfirmBlockDownloadEnd), resp);
+
+        let mut read_buf = vec![0u8; DATA_SIZE];
+        od[0].data.read(SUB, 0, &mut read_buf).unwrap();
+        assert_eq!(data, read_buf);
+    }
+
+    #[test]
+    fn test_block_download_interrupted_by_queued_request() {
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; SDO_BUFFER_SIZE])));
+        let mut server = SdoServer::new();
+        let rx = SdoReceiver::new(buffer);
+        let od = test_od();
+
+        const INDEX: u16 = 0x1000;
+        const SUB: u8 = 1;
+        rx.handle_req(&SdoRequest::initiate_block_download(INDEX, SUB, true, 100).to_bytes());
+        server.process(&rx, 0, od);
+        assert_eq!(ReceiverState::BlockReceive, rx.state());
+        rx.handle_req(
+            &BlockSegment {
+                c: false,
+                seqnum: 1,
+                data: [1; 7],
+            }
+            .to_bytes(),
+        );
+
+        // An upload request which was queued before the block download was acknowledged is not
+        // treated as a segment, and aborts the block download
+        rx.handle_queued_req(&SdoRequest::initiate_upload(INDEX, 2).to_bytes());
+        assert_eq!(ReceiverState::Normal, rx.state());
+        let (resp, index) = server.process(&rx, 0, od);
+        assert_eq!(
+            Some(SdoResponse::abort(
+                INDEX,
+                SUB,
+                AbortCode::InvalidCommandSpecifier
+            )),
+            resp
+        );
+        assert_eq!(None, index);
+        assert!(!server.is_active());
+        assert_eq!(
+            Some(SdoAccess {
+                index: INDEX,
+                sub: SUB,
+                write: true,
+                abort_code: AbortCode::InvalidCommandSpecifier as u32,
+            }),
+            server.take_access()
+        );
+
+        // The buffer was released by the aborted transfer, and can be used by the next one
+        rx.handle_req(&SdoRequest::initiate_upload(INDEX, 2).to_bytes());
+        let (resp, _) = server.process(&rx, 0, od);
+        assert_eq!(
+            Some(SdoResponse::upload_acknowledge(
+                INDEX,
+                2,
+                Some(SUB2_SIZE as u32)
+            )),
+            resp
+        );
+    }
+
     #[test]
     fn test_block_download_timeout() {
-        let buffer = Box::leak(Box::new([0; SDO_BUFFER_SIZE]));
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; SDO_BUFFER_SIZE])));
         let mut server = SdoServer::new();
         let rx = SdoReceiver::new(buffer);
         let od = test_od();
@@ -1066,13 +1544,11 @@ mod tests {
         assert_eq!(None, index);
     }
 
-    /// Test uploading a value with a length of 7
-    #[test]
-    fn test_segmented_download() {
-        const SDO_BUFFER_SIZE: usize = 32;
-        let buffer = Box::leak(Box::new([0; SDO_BUFFER_SIZE]));
+    const SEGMENTED_BUFFER_SIZE: usize = 32;
+
+    /// Run segmented downloads of various sizes, with a `SEGMENTED_BUFFER_SIZE` byte SDO buffer
+    fn check_segmented_downloads(rx: SdoReceiver) {
         let mut server = SdoServer::new();
-        let rx = SdoReceiver::new(buffer);
         let od = test_od();
 
         const INDEX: u16 = 0x1000;
@@ -1152,10 +1628,515 @@ mod tests {
         // Test downloading a single segment object just bigger than one segment
         do_segmented_download(8);
         // Test doing a length equal to the SDO buffer size
-        do_segmented_download(SDO_BUFFER_SIZE);
+        do_segmented_download(SEGMENTED_BUFFER_SIZE);
         // Test doing a length just larger than the buffer
-        do_segmented_download(SDO_BUFFER_SIZE + 1);
+        do_segmented_download(SEGMENTED_BUFFER_SIZE + 1);
         // Tests full object write
         do_segmented_download(SUB2_SIZE);
     }
+
+    /// Test uploading a value with a length of 7
+    #[test]
+    fn test_segmented_download() {
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; SEGMENTED_BUFFER_SIZE])));
+        check_segmented_downloads(SdoReceiver::new(buffer));
+    }
+
+    #[test]
+    fn test_segmented_download_double_buffered() {
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; SEGMENTED_BUFFER_SIZE])));
+        let write_buffer = Box::leak(Box::new(BufferCell::new([0u8; SEGMENTED_BUFFER_SIZE])));
+        check_segmented_downloads(SdoReceiver::new(buffer).with_write_buffer(write_buffer));
+    }
+
+    #[test]
+    fn test_status() {
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; SDO_BUFFER_SIZE])));
+        let mut server = SdoServer::new();
+        let rx = SdoReceiver::new(buffer);
+        let od = test_od();
+        let mut round_trip = |req: SdoRequest| {
+            rx.handle_req(&req.to_bytes());
+            server.process(&rx, 0, od);
+            server.status()
+        };
+
+        let status = round_trip(SdoRequest::initiate_download(0x1000, 1, Some(20)));
+        assert_eq!(
+            SdoServerStatus {
+                state: SdoTransferState::SegmentedDownload,
+                index: 0x1000,
+                sub: 1,
+                bytes: 0,
+            },
+            status
+        );
+        let status = round_trip(SdoRequest::download_segment(false, false, &[0; 7]));
+        assert_eq!(7, status.bytes);
+        let status = round_trip(SdoRequest::abort(0x1000, 1, AbortCode::GeneralError));
+        assert_eq!(SdoServerStatus::IDLE, status);
+        assert!(!status.is_active());
+
+        let status = round_trip(SdoRequest::initiate_block_download(0x1000, 1, true, 1000));
+        assert_eq!(SdoTransferState::BlockDownload, status.state);
+        assert_eq!(0, status.bytes);
+    }
+
+    #[test]
+    fn test_access_from_result() {
+        let upload = SdoResponse::upload_acknowledge(0x1000, 1, Some(20));
+        assert_eq!(
+            Some(SdoAccess {
+                index: 0x1000,
+                sub: 1,
+                write: false,
+                abort_code: 0
+            }),
+            SdoAccess::from_result(Some(&upload), None, Some(false))
+        );
+
+        // Completing a download reports a write, but completing a segmented upload does not
+        let id = ObjectId {
+            index: 0x1000,
+            sub: 2,
+        };
+        let ack = SdoResponse::download_acknowledge(0x1000, 2);
+        assert!(
+            SdoAccess::from_result(Some(&ack), Some(id), Some(true))
+                .unwrap()
+                .write
+        );
+        assert_eq!(None, SdoAccess::from_result(None, Some(id), Some(false)));
+
+        // Aborts are reported only when the direction of the transfer is known
+        let abort = SdoResponse::abort(0x1000, 3, AbortCode::NoSuchSubIndex);
+        assert_eq!(
+            Some(SdoAccess {
+                index: 0x1000,
+                sub: 3,
+                write: true,
+                abort_code: AbortCode::NoSuchSubIndex as u32
+            }),
+            SdoAccess::from_result(Some(&abort), None, Some(true))
+        );
+        assert_eq!(None, SdoAccess::from_result(Some(&abort), None, None));
+    }
+
+    /// A large object which tracks changes to its value with a generation counter
+    struct TrackedObject {
+        value: ByteField<100>,
+        generation: GenerationCounter,
+    }
+
+    impl SubObjectAccess for TrackedObject {
+        fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+            self.value.read(offset, buf)
+        }
+
+        fn read_size(&self) -> usize {
+            self.value.len()
+        }
+
+        fn write(&self, data: &[u8]) -> Result<(), AbortCode> {
+            self.value.write(data)?;
+            self.generation.increment();
+            Ok(())
+        }
+
+        fn generation(&self) -> Option<u32> {
+            Some(self.generation.get())
+        }
+    }
+
+    impl ProvidesSubObjects for TrackedObject {
+        fn get_sub_object(&self, sub: u8) -> Option<(SubInfo, &dyn SubObjectAccess)> {
+            match sub {
+                0 => Some((
+                    SubInfo {
+                        size: self.value.len(),
+                        data_type: DataType::OctetString,
+                        access_type: AccessType::Rw,
+                        ..Default::default()
+                    },
+                    self,
+                )),
+                _ => None,
+            }
+        }
+
+        fn object_code(&self) -> ObjectCode {
+            ObjectCode::Var
+        }
+    }
+
+    #[test]
+    fn test_segmented_upload_generation() {
+        const INDEX: u16 = 0x2000;
+        let object: &'static TrackedObject = Box::leak(Box::new(TrackedObject {
+            value: ByteField::new(core::array::from_fn(|i| i as u8)),
+            generation: GenerationCounter::new(),
+        }));
+        let od: &'static [ODEntry<'static>] = Box::leak(Box::new([ODEntry {
+            index: INDEX,
+            data: object,
+        }]));
+        // Only 28 bytes of the buffer are used, so the object is read in several parts
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; SEGMENTED_BUFFER_SIZE])));
+        let rx = SdoReceiver::new(buffer);
+        let mut server = SdoServer::new();
+        let mut round_trip = |req: SdoRequest| {
+            rx.handle_req(&req.to_bytes());
+            server.process(&rx, 0, od).0.unwrap()
+        };
+
+        // The size is reported up front, since the object can detect changes
+        assert_eq!(
+            SdoResponse::upload_acknowledge(INDEX, 0, Some(100)),
+            round_trip(SdoRequest::initiate_upload(INDEX, 0))
+        );
+        let mut data = Vec::new();
+        let mut toggle = false;
+        loop {
+            let SdoResponse::UploadSegment { t, n, c, data: seg } =
+                round_trip(SdoRequest::upload_segment_request(toggle))
+            else {
+                panic!("Expected an upload segment");
+            };
+            assert_eq!(toggle, t);
+            data.extend_from_slice(&seg[..7 - n as usize]);
+            toggle = !toggle;
+            if c {
+                break;
+            }
+        }
+        assert_eq!(object.value.load().as_slice(), data.as_slice());
+
+        // Changing the object before the buffer is refilled aborts the upload
+        round_trip(SdoRequest::initiate_upload(INDEX, 0));
+        for i in 0..3 {
+            round_trip(SdoRequest::upload_segment_request(i % 2 == 1));
+        }
+        SubObjectAccess::write(object, &[0xff; 100]).unwrap();
+        assert_eq!(
+            SdoResponse::abort(INDEX, 0, AbortCode::GeneralError),
+            round_trip(SdoRequest::upload_segment_request(true))
+        );
+    }
+
+    /// Frames with every DLC below 8, with every command byte and pseudo-random data
+    fn short_frames() -> Vec<Vec<u8>> {
+        // xorshift32, so that the payloads are varied but the test is repeatable
+        let mut state = 0x2545_f491u32;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        };
+        let mut frames = vec![Vec::new()];
+        for len in 1..8 {
+            for cmd in 0..=255u8 {
+                let mut frame = vec![cmd];
+                frame.extend((1..len).map(|_| next()));
+                frames.push(frame);
+            }
+        }
+        frames
+    }
+
+    /// Feed every short frame to the receiver, and check that none of them affect the server
+    fn inject_short_frames(
+        server: &mut SdoServer,
+        rx: &SdoReceiver,
+        od: &'static [ODEntry<'static>],
+    ) {
+        for frame in short_frames() {
+            assert!(!rx.handle_req(&frame), "Accepted {frame:x?}");
+            assert!(!rx.handle_queued_req(&frame), "Accepted queued {frame:x?}");
+            assert_eq!(
+                (None, None),
+                server.process(rx, 0, od),
+                "Handled {frame:x?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_short_frames_ignored() {
+        const INDEX: u16 = 0x1000;
+        const SUB: u8 = 2;
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; SDO_BUFFER_SIZE])));
+        let rx = SdoReceiver::new(buffer);
+        let mut server = SdoServer::new();
+        let od = test_od();
+        let round_trip = |server: &mut SdoServer, req: [u8; 8]| {
+            assert!(rx.handle_req(&req));
+            server.process(&rx, 0, od).0
+        };
+
+        // Idle
+        inject_short_frames(&mut server, &rx, od);
+
+        // Segmented download
+        let resp = round_trip(
+            &mut server,
+            SdoRequest::initiate_download(INDEX, SUB, Some(10)).to_bytes(),
+        );
+        assert_eq!(
+            Some(SdoResponse::ConfirmDownload {
+                index: INDEX,
+                sub: SUB
+            }),
+            resp
+        );
+        inject_short_frames(&mut server, &rx, od);
+        let resp = round_trip(
+            &mut server,
+            SdoRequest::download_segment(false, false, &[1; 7]).to_bytes(),
+        );
+        assert_eq!(Some(SdoResponse::ConfirmDownloadSegment { t: false }), resp);
+        inject_short_frames(&mut server, &rx, od);
+        let resp = round_trip(
+            &mut server,
+            SdoRequest::download_segment(true, true, &[2; 3]).to_bytes(),
+        );
+        assert_eq!(Some(SdoResponse::ConfirmDownloadSegment { t: true }), resp);
+
+        // Segmented upload
+        let resp = round_trip(
+            &mut server,
+            SdoRequest::initiate_upload(INDEX, SUB).to_bytes(),
+        );
+        assert!(matches!(resp, Some(SdoResponse::ConfirmUpload { .. })));
+        inject_short_frames(&mut server, &rx, od);
+        let resp = round_trip(
+            &mut server,
+            SdoRequest::upload_segment_request(false).to_bytes(),
+        );
+        let Some(SdoResponse::UploadSegment { t: false, data, .. }) = resp else {
+            panic!("Expected an upload segment, got {resp:?}");
+        };
+        assert_eq!([1; 7], data);
+        let mut toggle = true;
+        loop {
+            let resp = round_trip(
+                &mut server,
+                SdoRequest::upload_segment_request(toggle).to_bytes(),
+            );
+            let Some(SdoResponse::UploadSegment { c, .. }) = resp else {
+                panic!("Expected an upload segment, got {resp:?}");
+            };
+            if c {
+                break;
+            }
+            toggle = !toggle;
+        }
+
+        // Block download, while receiving segments and after the last block
+        let resp = round_trip(
+            &mut server,
+            SdoRequest::initiate_block_download(INDEX, SUB, true, 14).to_bytes(),
+        );
+        assert!(matches!(
+            resp,
+            Some(SdoResponse::ConfirmBlockDownload { .. })
+        ));
+        inject_short_frames(&mut server, &rx, od);
+        let segment = BlockSegment {
+            c: false,
+            seqnum: 1,
+            data: [3; 7],
+        };
+        assert!(!rx.handle_req(&segment.to_bytes()));
+        inject_short_frames(&mut server, &rx, od);
+        let segment = BlockSegment {
+            c: true,
+            seqnum: 2,
+            data: [4; 7],
+        };
+        let resp = round_trip(&mut server, segment.to_bytes());
+        assert_eq!(
+            Some(SdoResponse::ConfirmBlock {
+                ackseq: 2,
+                blksize: 127
+            }),
+            resp
+        );
+        inject_short_frames(&mut server, &rx, od);
+        let mut data = [3; 14];
+        data[7..].fill(4);
+        let crc = crc16::State::<crc16::XMODEM>::calculate(&data);
+        let resp = round_trip(
+            &mut server,
+            SdoRequest::end_block_download(0, crc).to_bytes(),
+        );
+        assert_eq!(Some(SdoResponse::ConfirmBlockDownloadEnd), resp);
+
+        let mut read_buf = [0; 14];
+        od[0].data.read(SUB, 0, &mut read_buf).unwrap();
+        assert_eq!(data, read_buf);
+    }
+
+    /// An object with u32 sub objects, laid out according to its object code
+    ///
+    /// A Var has only sub 0. An Array has three read-write subs, and a Record has a read-only,
+    /// a write-only and a read-write sub.
+    struct AccessObject {
+        object_code: ObjectCode,
+        value: ScalarField<u32>,
+    }
+
+    impl ProvidesSubObjects for AccessObject {
+        fn get_sub_object(&self, sub: u8) -> Option<(SubInfo, &dyn SubObjectAccess)> {
+            let info = SubInfo::new_u32();
+            let access = match (self.object_code, sub) {
+                (ObjectCode::Var, 0) => info.rw_access(),
+                (ObjectCode::Var, _) => return None,
+                (_, 0) => {
+                    return Some((
+                        SubInfo::MAX_SUB_NUMBER,
+                        const { &ConstField::new(3u8.to_le_bytes()) },
+                    ))
+                }
+                (ObjectCode::Array, 1..=3) => info.rw_access(),
+                (ObjectCode::Record, 1) => info.ro_access(),
+                (ObjectCode::Record, 2) => info.wo_access(),
+                (ObjectCode::Record, 3) => info.rw_access(),
+                _ => return None,
+            };
+            Some((access, &self.value))
+        }
+
+        fn object_code(&self) -> ObjectCode {
+            self.object_code
+        }
+    }
+
+    /// Check the abort code returned for each kind of transfer, for each kind of object
+    ///
+    /// CiA 301 conformance testers expect a missing object to be reported before a missing sub
+    /// object, a missing sub object before an access violation, and an access violation before a
+    /// size mismatch.
+    #[test]
+    fn test_abort_code_precedence() {
+        fn object(object_code: ObjectCode) -> &'static AccessObject {
+            Box::leak(Box::new(AccessObject {
+                object_code,
+                value: ScalarField::<u32>::new(0),
+            }))
+        }
+        let record = object(ObjectCode::Record);
+        let no_handler: &'static CallbackObject =
+            Box::leak(Box::new(CallbackObject::new(ObjectCode::Record)));
+        let with_handler: &'static CallbackObject =
+            Box::leak(Box::new(CallbackObject::new(ObjectCode::Record)));
+        with_handler.register_handler(record);
+        let od: &'static [ODEntry<'static>] = Box::leak(Box::new([
+            ODEntry {
+                index: 0x2000,
+                data: object(ObjectCode::Var),
+            },
+            ODEntry {
+                index: 0x2001,
+                data: object(ObjectCode::Array),
+            },
+            ODEntry {
+                index: 0x2002,
+                data: record,
+            },
+            ODEntry {
+                index: 0x2003,
+                data: no_handler,
+            },
+            ODEntry {
+                index: 0x2004,
+                data: with_handler,
+            },
+            ODEntry {
+                index: 0x2005,
+                data: Box::leak(Box::new(StubObject::new(DataType::TimeOfDay))),
+            },
+        ]));
+
+        // Each request is sent to a new server, and the abort code of the response is returned,
+        // or None if the request was accepted
+        let abort_code = |req: SdoRequest| {
+            let buffer = Box::leak(Box::new(BufferCell::new([0u8; SDO_BUFFER_SIZE])));
+            let rx = SdoReceiver::new(buffer);
+            let mut server = SdoServer::new();
+            rx.handle_req(&req.to_bytes());
+            match server.process(&rx, 0, od).0.unwrap() {
+                SdoResponse::Abort { abort_code, .. } => {
+                    Some(AbortCode::try_from(abort_code).unwrap())
+                }
+                _ => None,
+            }
+        };
+
+        use AbortCode::*;
+        const OK: Option<AbortCode> = None;
+        let missing_object = [Some(NoSuchObject); 5];
+        let missing_sub = [Some(NoSuchSubIndex); 5];
+        let unregistered = [Some(ResourceNotAvailable); 5];
+        let read_only = [
+            OK,
+            Some(ReadOnly),
+            Some(ReadOnly),
+            Some(ReadOnly),
+            Some(ReadOnly),
+        ];
+        let stub = [
+            Some(ResourceNotAvailable),
+            Some(ReadOnly),
+            Some(ReadOnly),
+            Some(ReadOnly),
+            Some(ReadOnly),
+        ];
+        let write_only = [
+            Some(WriteOnly),
+            OK,
+            Some(DataTypeMismatchLengthLow),
+            Some(DataTypeMismatchLengthHigh),
+            Some(DataTypeMismatchLengthHigh),
+        ];
+        let read_write = [
+            OK,
+            OK,
+            Some(DataTypeMismatchLengthLow),
+            Some(DataTypeMismatchLengthHigh),
+            Some(DataTypeMismatchLengthHigh),
+        ];
+        // Expected results of: upload, expedited download of 4 and 2 bytes, and segmented and
+        // block downloads of 100 bytes
+        let cases = [
+            (0x3000, 0, missing_object),
+            (0x2000, 0, read_write),
+            (0x2000, 1, missing_sub),
+            (0x2001, 0, read_only),
+            (0x2001, 2, read_write),
+            (0x2001, 4, missing_sub),
+            (0x2002, 0, read_only),
+            (0x2002, 1, read_only),
+            (0x2002, 2, write_only),
+            (0x2002, 3, read_write),
+            (0x2002, 4, missing_sub),
+            (0x2003, 0, unregistered),
+            (0x2003, 1, unregistered),
+            (0x2004, 1, read_only),
+            (0x2004, 2, write_only),
+            (0x2004, 4, missing_sub),
+            (0x2005, 0, stub),
+            (0x2005, 1, missing_sub),
+        ];
+        for (index, sub, expected) in cases {
+            let results = [
+                abort_code(SdoRequest::initiate_upload(index, sub)),
+                abort_code(SdoRequest::expedited_download(index, sub, &[0; 4])),
+                abort_code(SdoRequest::expedited_download(index, sub, &[0; 2])),
+                abort_code(SdoRequest::initiate_download(index, sub, Some(100))),
+                abort_code(SdoRequest::initiate_block_download(index, sub, true, 100)),
+            ];
+            assert_eq!(expected, results, "0x{index:04X}sub{sub}");
+        }
+    }
 }
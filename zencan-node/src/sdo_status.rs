@@ -0,0 +1,177 @@
+//! The SDO server status object (0x5005)
+//!
+//! When a device config sets `sdo_status`, the node reports the transfer in progress on its SDO
+//! server in object 0x5005: its type, the object it accesses, and the number of bytes transferred.
+//! The same status is available to the application in [`NodeSnapshot::sdo_server`].
+//!
+//! A client which is itself using the SDO server cannot see its own transfer by reading the object,
+//! so the sub objects may be mapped to a synchronous TPDO, to monitor transfers which stall from
+//! another device.
+
+use zencan_common::{
+    objects::{ObjectCode, PdoMapping, SubInfo},
+    sdo::{AbortCode, SdoServerStatus},
+    AtomicCell,
+};
+
+use crate::object_dict::{ConstField, ProvidesSubObjects, SubObjectAccess};
+use crate::NodeSnapshot;
+
+/// Sub index of the transfer state
+const SUB_STATE: u8 = 1;
+/// Sub index of the object index
+const SUB_INDEX: u8 = 2;
+/// Sub index of the object sub index
+const SUB_SUB: u8 = 3;
+/// Sub index of the bytes transferred
+const SUB_BYTES: u8 = 4;
+
+struct StatusField {
+    status: &'static AtomicCell<NodeSnapshot>,
+    sub: u8,
+}
+
+impl StatusField {
+    const fn new(status: &'static AtomicCell<NodeSnapshot>, sub: u8) -> Self {
+        Self { status, sub }
+    }
+
+    fn sub_info(&self) -> SubInfo {
+        let info = match self.sub {
+            SUB_STATE | SUB_SUB => SubInfo::new_u8(),
+            SUB_INDEX => SubInfo::new_u16(),
+            _ => SubInfo::new_u32(),
+        };
+        SubInfo {
+            pdo_mapping: PdoMapping::Tpdo,
+            ..info
+        }
+    }
+
+    fn value(&self, status: SdoServerStatus) -> ([u8; 4], usize) {
+        let mut bytes = [0; 4];
+        let len = match self.sub {
+            SUB_STATE => {
+                bytes[0] = status.state as u8;
+                1
+            }
+            SUB_INDEX => {
+                bytes[..2].copy_from_slice(&status.index.to_le_bytes());
+                2
+            }
+            SUB_SUB => {
+                bytes[0] = status.sub;
+                1
+            }
+            _ => {
+                bytes = status.bytes.to_le_bytes();
+                4
+            }
+        };
+        (bytes, len)
+    }
+}
+
+impl SubObjectAccess for StatusField {
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        let (bytes, len) = self.value(self.status.load().sdo_server);
+        if offset < len {
+            let read_len = buf.len().min(len - offset);
+            buf[..read_len].copy_from_slice(&bytes[offset..offset + read_len]);
+            Ok(read_len)
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn read_size(&self) -> usize {
+        self.sub_info().size
+    }
+
+    fn write(&self, _data: &[u8]) -> Result<(), AbortCode> {
+        Err(AbortCode::ReadOnly)
+    }
+}
+
+/// Implements the SDO server status object (0x5005)
+#[allow(missing_debug_implementations)]
+pub struct SdoStatusObject {
+    state: StatusField,
+    index: StatusField,
+    sub: StatusField,
+    bytes: StatusField,
+}
+
+impl SdoStatusObject {
+    /// Create a new SdoStatusObject, which reports the status published by the node
+    pub const fn new(status: &'static AtomicCell<NodeSnapshot>) -> Self {
+        Self {
+            state: StatusField::new(status, SUB_STATE),
+            index: StatusField::new(status, SUB_INDEX),
+            sub: StatusField::new(status, SUB_SUB),
+            bytes: StatusField::new(status, SUB_BYTES),
+        }
+    }
+}
+
+impl ProvidesSubObjects for SdoStatusObject {
+    fn get_sub_object(&self, sub: u8) -> Option<(SubInfo, &dyn SubObjectAccess)> {
+        let field = match sub {
+            0 => {
+                return Some((
+                    SubInfo::MAX_SUB_NUMBER,
+                    const { &ConstField::new(4u8.to_le_bytes()) },
+                ))
+            }
+            SUB_STATE => &self.state,
+            SUB_INDEX => &self.index,
+            SUB_SUB => &self.sub,
+            SUB_BYTES => &self.bytes,
+            _ => return None,
+        };
+        Some((field.sub_info(), field))
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zencan_common::sdo::SdoTransferState;
+
+    use crate::object_dict::ObjectAccess;
+
+    use super::*;
+
+    #[test]
+    fn test_sdo_status_object() {
+        let status: &'static AtomicCell<NodeSnapshot> =
+            Box::leak(Box::new(AtomicCell::new(NodeSnapshot::new())));
+        let object = SdoStatusObject::new(status);
+
+        assert_eq!(4, object.read_u8(0).unwrap());
+        assert_eq!(0, object.read_u8(1).unwrap());
+        assert_eq!(0, object.read_u32(4).unwrap());
+
+        status
+            .fetch_update(|mut s| {
+                s.sdo_server = SdoServerStatus {
+                    state: SdoTransferState::BlockDownload,
+                    index: 0x3006,
+                    sub: 2,
+                    bytes: 889,
+                };
+                Some(s)
+            })
+            .ok();
+        assert_eq!(3, object.read_u8(1).unwrap());
+        assert_eq!(0x3006, object.read_u16(2).unwrap());
+        assert_eq!(2, object.read_u8(3).unwrap());
+        assert_eq!(889, object.read_u32(4).unwrap());
+        assert_eq!(PdoMapping::Tpdo, object.sub_info(4).unwrap().pdo_mapping);
+
+        assert_eq!(Err(AbortCode::ReadOnly), object.write(1, &[0]));
+    }
+}
@@ -0,0 +1,64 @@
+//! Maintains the node statistics object (0x5001)
+//!
+//! When a device config enables `statistics`, the node counts power cycles, operating time, bus-off
+//! events, and transmitted EMCY messages in object 0x5001. The sub objects are persisted, so the
+//! counts accumulate across reboots as long as the application stores objects, e.g. via the 0x1010
+//! save command, or on [`Node::shutdown`](crate::Node::shutdown).
+
+use zencan_common::constants::object_ids;
+
+use crate::object_dict::{find_object, ODEntry, ObjectAccess};
+
+/// Sub index of the power cycle count
+pub(crate) const SUB_POWER_CYCLES: u8 = 1;
+/// Sub index of the total operating time in minutes
+pub(crate) const SUB_OPERATING_MINUTES: u8 = 2;
+/// Sub index of the bus-off count
+pub(crate) const SUB_BUS_OFF_COUNT: u8 = 3;
+/// Sub index of the transmitted EMCY count
+pub(crate) const SUB_EMCY_COUNT: u8 = 4;
+
+const US_PER_MINUTE: u64 = 60_000_000;
+
+/// Updates the statistics object, if the node has one
+pub(crate) struct Statistics {
+    object: Option<&'static dyn ObjectAccess>,
+    /// Operating time which has not yet been counted as a full minute
+    partial_minute_us: u64,
+}
+
+impl Statistics {
+    pub fn new(od: &'static [ODEntry<'static>]) -> Self {
+        Self {
+            object: find_object(od, object_ids::NODE_STATISTICS),
+            partial_minute_us: 0,
+        }
+    }
+
+    /// Increment one of the counters
+    ///
+    /// Returns true if the object was updated, i.e. if the node has a statistics object
+    pub fn increment(&self, sub: u8) -> bool {
+        let Some(object) = self.object else {
+            return false;
+        };
+        let value = object.read_u32(sub).unwrap_or(0).saturating_add(1);
+        object.write(sub, &value.to_le_bytes()).is_ok()
+    }
+
+    /// Add elapsed operating time
+    ///
+    /// Returns true if the operating minutes counter was updated
+    pub fn add_time(&mut self, elapsed_us: u64) -> bool {
+        if self.object.is_none() {
+            return false;
+        }
+        self.partial_minute_us += elapsed_us;
+        let mut updated = false;
+        while self.partial_minute_us >= US_PER_MINUTE {
+            self.partial_minute_us -= US_PER_MINUTE;
+            updated |= self.increment(SUB_OPERATING_MINUTES);
+        }
+        updated
+    }
+}
@@ -1,17 +1,42 @@
 //! Handling for persistent storage control objects
 //!
+//! [`SettingsBackupObject`] implements the settings backup object (0x5F10), which allows all of the
+//! persisted objects to be read or restored over the bus in a single transfer.
 //!
+//! With the `embedded-storage` feature, [`NorFlashStore`] provides a ready made implementation of
+//! the store objects callback on NOR flash, and `embedded-storage-async` adds
+//! [`AsyncNorFlashStore`] for async flash drivers. On std, [`FileObjectStore`] stores objects in a
+//! file.
 
 use core::convert::Infallible;
 
+use defmt_or_log::warn;
 use zencan_common::{
     constants::values::SAVE_CMD,
-    objects::{ObjectCode, SubInfo},
+    objects::{AccessType, DataType, ObjectCode, PdoMapping, SubInfo},
     sdo::AbortCode,
     AtomicCell,
 };
 
-use crate::object_dict::{ODEntry, ObjectAccess};
+use crate::{
+    object_dict::{ODEntry, ObjectAccess},
+    persist::{NodeType, ObjectValue},
+    BufferCell,
+};
+
+#[cfg(feature = "std")]
+mod file_store;
+#[cfg(feature = "embedded-storage")]
+mod nor_flash;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub use file_store::FileObjectStore;
+#[cfg(feature = "embedded-storage-async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "embedded-storage-async")))]
+pub use nor_flash::AsyncNorFlashStore;
+#[cfg(feature = "embedded-storage")]
+#[cfg_attr(docsrs, doc(cfg(feature = "embedded-storage")))]
+pub use nor_flash::{NorFlashStore, NorFlashStoreError};
 
 /// A callback function type for handling a store objects event
 pub type StoreObjectsCallback =
@@ -22,6 +47,8 @@ pub type StoreObjectsCallback =
 /// Shared state for supporting object storage
 pub struct StorageContext {
     pub(crate) store_callback: AtomicCell<Option<&'static StoreObjectsCallback>>,
+    /// Set when a persistent object has been written via SDO since the last save
+    pub(crate) dirty: AtomicCell<bool>,
 }
 
 impl StorageContext {
@@ -29,8 +56,15 @@ impl StorageContext {
     pub const fn new() -> Self {
         Self {
             store_callback: AtomicCell::new(None),
+            dirty: AtomicCell::new(false),
         }
     }
+
+    /// Returns true if an object with persistent sub-objects has been written via SDO since
+    /// objects were last saved
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load()
+    }
 }
 
 /// Implements the storage command object (0x1010)
@@ -104,6 +138,7 @@ impl ObjectAccess for StorageCommandObject {
                     if value == SAVE_CMD {
                         if let Some(cb) = self.storage_context.store_callback.load() {
                             crate::persist::serialize(self.od, cb);
+                            self.storage_context.dirty.store(false);
                             Ok(())
                         } else {
                             Err(AbortCode::ResourceNotAvailable)
@@ -129,3 +164,286 @@ impl ObjectAccess for StorageCommandObject {
         }
     }
 }
+
+/// The progress of a restore through the settings backup object
+#[derive(Debug, Clone, Copy, Default)]
+struct RestoreState {
+    /// The node length, followed by the node type, object index and sub index
+    header: [u8; 6],
+    /// The number of header bytes received
+    header_len: usize,
+    /// The number of value bytes received
+    value_len: usize,
+}
+
+impl RestoreState {
+    fn node_size(&self) -> usize {
+        u16::from_le_bytes([self.header[0], self.header[1]]) as usize
+    }
+
+    /// The number of header bytes in the current node
+    fn header_size(&self) -> usize {
+        if self.header_len < 2 {
+            2
+        } else {
+            2 + self.node_size().min(4)
+        }
+    }
+
+    /// The number of value bytes in the current node
+    fn value_size(&self) -> usize {
+        self.node_size().saturating_sub(4)
+    }
+
+    fn is_complete(&self) -> bool {
+        self.header_len == self.header_size() && self.value_len == self.value_size()
+    }
+}
+
+/// Implements the settings backup object (0x5F10)
+///
+/// Reading the object returns all persisted sub objects, serialized in the same format provided to
+/// the store objects callback. Writing data in that format back to the object restores the values,
+/// so the settings of a device can be backed up and restored, or cloned to a replacement device,
+/// without accessing each object individually.
+///
+/// Values are written to their objects as soon as they are received, so an aborted restore may
+/// leave some of them updated. Values for objects which do not exist on the node are skipped, as
+/// when restoring from storage. A restore does not save the objects; it marks them as modified, and
+/// a save must be requested via object 0x1010 to persist them.
+///
+/// During a segmented download, each value is staged in `value_buffer` until it is complete, so
+/// it must be at least as large as the largest persisted sub object. Larger values are skipped.
+#[allow(missing_debug_implementations)]
+pub struct SettingsBackupObject {
+    od: &'static [ODEntry<'static>],
+    storage_context: &'static StorageContext,
+    value_buffer: &'static BufferCell<[u8]>,
+    restore: AtomicCell<RestoreState>,
+}
+
+impl SettingsBackupObject {
+    /// Create a new settings backup object
+    pub const fn new(
+        od: &'static [ODEntry<'static>],
+        storage_context: &'static StorageContext,
+        value_buffer: &'static BufferCell<[u8]>,
+    ) -> Self {
+        Self {
+            od,
+            storage_context,
+            value_buffer,
+            restore: AtomicCell::new(RestoreState {
+                header: [0; 6],
+                header_len: 0,
+                value_len: 0,
+            }),
+        }
+    }
+
+    fn restore_node(&self, state: &RestoreState, value: &[u8]) {
+        if NodeType::from_byte(state.header[2]) != NodeType::ObjectValue {
+            warn!("Unknown settings backup node: {}", state.header[2]);
+        } else if state.node_size() < 5 {
+            warn!("Settings backup node too short");
+        } else if state.value_size() > value.len() {
+            warn!("Settings backup value too large");
+        } else {
+            let restore = ObjectValue {
+                index: u16::from_le_bytes([state.header[3], state.header[4]]),
+                sub: state.header[5],
+                data: &value[..state.value_size()],
+            };
+            crate::persist::restore_value(self.od, &restore);
+        }
+    }
+}
+
+impl ObjectAccess for SettingsBackupObject {
+    fn read(&self, sub: u8, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        Ok(crate::persist::read_serialized(self.od, offset, buf))
+    }
+
+    fn read_size(&self, sub: u8) -> Result<usize, AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        Ok(crate::persist::serialized_size(self.od))
+    }
+
+    fn write(&self, sub: u8, data: &[u8]) -> Result<(), AbortCode> {
+        self.begin_partial(sub)?;
+        self.write_partial(sub, data)?;
+        self.end_partial(sub)
+    }
+
+    fn begin_partial(&self, sub: u8) -> Result<(), AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        self.restore.store(RestoreState::default());
+        Ok(())
+    }
+
+    fn write_partial(&self, sub: u8, mut data: &[u8]) -> Result<(), AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        let mut state = self.restore.load();
+        let mut value_buffer = self.value_buffer.borrow_mut();
+        loop {
+            if state.is_complete() {
+                self.restore_node(&state, &value_buffer);
+                state = RestoreState::default();
+            }
+            if data.is_empty() {
+                break;
+            }
+            let len = if state.header_len < state.header_size() {
+                let len = (state.header_size() - state.header_len).min(data.len());
+                state.header[state.header_len..state.header_len + len]
+                    .copy_from_slice(&data[..len]);
+                state.header_len += len;
+                len
+            } else {
+                let len = (state.value_size() - state.value_len).min(data.len());
+                // Bytes beyond the end of the buffer are dropped, and the value is skipped
+                let start = state.value_len.min(value_buffer.len());
+                let end = (state.value_len + len).min(value_buffer.len());
+                value_buffer[start..end].copy_from_slice(&data[..end - start]);
+                state.value_len += len;
+                len
+            };
+            data = &data[len..];
+        }
+        self.restore.store(state);
+        Ok(())
+    }
+
+    fn end_partial(&self, sub: u8) -> Result<(), AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        let state = self.restore.take();
+        self.storage_context.dirty.store(true);
+        if state.header_len != 0 {
+            // The data ended part way through a node
+            return Err(AbortCode::DataTypeMismatchLengthLow);
+        }
+        Ok(())
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Var
+    }
+
+    fn sub_info(&self, sub: u8) -> Result<SubInfo, AbortCode> {
+        if sub != 0 {
+            return Err(AbortCode::NoSuchSubIndex);
+        }
+        // The size of restored data may differ from the current serialized size, e.g. when it
+        // contains longer strings, so no size is given
+        Ok(SubInfo {
+            size: 0,
+            data_type: DataType::Domain,
+            access_type: AccessType::Rw,
+            pdo_mapping: PdoMapping::None,
+            persist: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object_dict::{
+        ConstField, NullTermByteField, ProvidesSubObjects, ScalarField, SubObjectAccess,
+    };
+
+    #[derive(Default)]
+    struct Settings {
+        value: ScalarField<u32>,
+        name: NullTermByteField<10>,
+    }
+
+    impl ProvidesSubObjects for Settings {
+        fn get_sub_object(&self, sub: u8) -> Option<(SubInfo, &dyn SubObjectAccess)> {
+            match sub {
+                0 => Some((
+                    SubInfo::MAX_SUB_NUMBER,
+                    const { &ConstField::new(2u8.to_le_bytes()) },
+                )),
+                1 => Some((SubInfo::new_u32().rw_access().persist(true), &self.value)),
+                2 => Some((
+                    SubInfo::new_visibile_str(self.name.len())
+                        .rw_access()
+                        .persist(true),
+                    &self.name,
+                )),
+                _ => None,
+            }
+        }
+
+        fn object_code(&self) -> ObjectCode {
+            ObjectCode::Record
+        }
+    }
+
+    fn backup_object() -> (&'static Settings, SettingsBackupObject) {
+        let settings: &'static Settings = Box::leak(Box::<Settings>::default());
+        let od = Box::leak(Box::new([ODEntry {
+            index: 0x2000,
+            data: settings,
+        }]));
+        let context = Box::leak(Box::new(StorageContext::new()));
+        let buffer = Box::leak(Box::new(BufferCell::new([0u8; 10])));
+        (settings, SettingsBackupObject::new(od, context, buffer))
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let (settings, object) = backup_object();
+        settings.value.store(0x12345678);
+        settings.name.set_str(b"motor").unwrap();
+
+        let size = object.read_size(0).unwrap();
+        let mut backup = vec![0; size];
+        // Read in chunks which do not line up with the nodes
+        for offset in (0..size).step_by(7) {
+            let end = (offset + 7).min(size);
+            assert_eq!(
+                end - offset,
+                object.read(0, offset, &mut backup[offset..end]).unwrap()
+            );
+        }
+        assert_eq!(0, object.read(0, size, &mut [0; 4]).unwrap());
+
+        settings.value.store(0);
+        settings.name.set_str(b"").unwrap();
+        object.write(0, &backup).unwrap();
+        assert_eq!(0x12345678, settings.value.load());
+        assert!(object.storage_context.is_dirty());
+
+        // Restore in single byte chunks
+        settings.value.store(0);
+        object.begin_partial(0).unwrap();
+        for b in &backup {
+            object.write_partial(0, &[*b]).unwrap();
+        }
+        object.end_partial(0).unwrap();
+        assert_eq!(0x12345678, settings.value.load());
+        let mut name = [0; 10];
+        assert_eq!(b"motor", settings.name.get_str(&mut name));
+
+        // Data which ends part way through a node is rejected, but the complete nodes are restored
+        settings.value.store(0);
+        assert_eq!(
+            Err(AbortCode::DataTypeMismatchLengthLow),
+            object.write(0, &backup[..size - 1])
+        );
+        assert_eq!(0x12345678, settings.value.load());
+    }
+}
@@ -0,0 +1,211 @@
+//! Object storage in a file, for std nodes
+
+use std::{
+    convert::Infallible,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use defmt_or_log::{error, info, warn};
+
+use crate::{object_dict::ODEntry, storage::StoreObjectsCallback};
+
+/// Marks the header of a stored file, "ZCF1"
+const MAGIC: u32 = 0x3146_435A;
+const HEADER_LEN: usize = 10;
+
+/// Format a path for logging
+fn display(path: &Path) -> &str {
+    path.to_str().unwrap_or("<non UTF-8 path>")
+}
+
+/// Stores object data in a file
+///
+/// Each save is written to a temporary file, which is then renamed over the object file, so that
+/// the object file always contains a complete save. The previous save is kept in a backup file
+/// (the object file path with a `.bak` extension appended). Files are stored with a length and CRC
+/// header, and if the object file is missing or fails validation on load, the backup is used
+/// instead.
+///
+/// ```ignore
+/// let store = FileObjectStore::new("zencan_node.flash");
+/// store.restore(&zencan::OD_TABLE);
+/// let mut node = Node::new(node_id, &zencan::NODE_MBOX, &zencan::NODE_STATE, &zencan::OD_TABLE);
+/// node.register_store_objects(store.into_callback());
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileObjectStore {
+    path: PathBuf,
+}
+
+impl FileObjectStore {
+    /// Create a store which saves objects to the file at `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Get the path of the object file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn sibling_path(&self, extension: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(extension);
+        path.into()
+    }
+
+    /// Get the path of the backup file, holding the previous save
+    pub fn backup_path(&self) -> PathBuf {
+        self.sibling_path(".bak")
+    }
+
+    /// Read and validate a stored file
+    ///
+    /// Returns None if the file does not exist or is invalid.
+    fn read_file(path: &Path) -> Option<Vec<u8>> {
+        let contents = match std::fs::read(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!(
+                    "Error reading object file {}: {}",
+                    display(path),
+                    e.to_string().as_str()
+                );
+                return None;
+            }
+        };
+        if contents.len() < HEADER_LEN
+            || u32::from_le_bytes(contents[0..4].try_into().unwrap()) != MAGIC
+        {
+            warn!("Object file {} has an invalid header", display(path));
+            return None;
+        }
+        let length = u32::from_le_bytes(contents[4..8].try_into().unwrap()) as usize;
+        let crc = u16::from_le_bytes(contents[8..10].try_into().unwrap());
+        let data = &contents[HEADER_LEN..];
+        if data.len() != length || crc16::State::<crc16::XMODEM>::calculate(data) != crc {
+            warn!("Object file {} is corrupt", display(path));
+            return None;
+        }
+        Some(data.to_vec())
+    }
+
+    /// Load the most recently stored object data
+    ///
+    /// The object file is read if it is valid, otherwise the backup file. Returns None if
+    /// neither file contains valid data.
+    pub fn load(&self) -> Option<Vec<u8>> {
+        if let Some(data) = Self::read_file(&self.path) {
+            return Some(data);
+        }
+        let backup = Self::read_file(&self.backup_path());
+        if backup.is_some() {
+            warn!(
+                "Loading objects from backup file {}",
+                display(&self.backup_path())
+            );
+        }
+        backup
+    }
+
+    /// Restore the most recently stored object values into the object dictionary
+    ///
+    /// Returns true if stored data was found and restored.
+    pub fn restore(&self, od: &[ODEntry]) -> bool {
+        match self.load() {
+            Some(data) => {
+                crate::restore_stored_objects(od, &data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Store object data to the file
+    ///
+    /// The arguments match the [`StoreObjectsCallback`], so this can be called directly from the
+    /// store objects callback. Errors are logged.
+    pub fn store(&self, reader: &mut dyn embedded_io::Read<Error = Infallible>, len: usize) {
+        match self.try_store(reader, len) {
+            Ok(()) => info!("Stored objects to {}", display(&self.path)),
+            Err(e) => error!(
+                "Error storing objects to {}: {}",
+                display(&self.path),
+                e.to_string().as_str()
+            ),
+        }
+    }
+
+    /// Store object data to the file, returning any error
+    pub fn try_store(
+        &self,
+        reader: &mut dyn embedded_io::Read<Error = Infallible>,
+        len: usize,
+    ) -> std::io::Result<()> {
+        let mut data = vec![0; len];
+        let mut pos = 0;
+        while pos < len {
+            let n = reader.read(&mut data[pos..]).unwrap();
+            if n == 0 {
+                break;
+            }
+            pos += n;
+        }
+        data.truncate(pos);
+
+        let tmp_path = self.sibling_path(".tmp");
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&MAGIC.to_le_bytes())?;
+        file.write_all(&(data.len() as u32).to_le_bytes())?;
+        file.write_all(&crc16::State::<crc16::XMODEM>::calculate(&data).to_le_bytes())?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        drop(file);
+
+        if self.path.exists() {
+            std::fs::rename(&self.path, self.backup_path())?;
+        }
+        std::fs::rename(&tmp_path, &self.path)
+    }
+
+    /// Convert the store into a callback which can be passed to
+    /// [`Node::register_store_objects`](crate::Node::register_store_objects)
+    ///
+    /// The store is leaked to create the static callback, so this should only be called once per
+    /// node.
+    pub fn into_callback(self) -> &'static StoreObjectsCallback {
+        Box::leak(Box::new(
+            move |reader: &mut dyn embedded_io::Read<Error = Infallible>, len: usize| {
+                self.store(reader, len)
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_store_fallback() {
+        let dir = std::env::temp_dir().join(format!("zencan_file_store_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = FileObjectStore::new(dir.join("objects.flash"));
+        assert_eq!(None, store.load());
+
+        store.try_store(&mut &[1u8, 2, 3][..], 3).unwrap();
+        store.try_store(&mut &[4u8, 5][..], 2).unwrap();
+        assert_eq!(Some(vec![4, 5]), store.load());
+
+        // Corrupt the latest save. The previous one should be loaded from the backup.
+        let mut contents = std::fs::read(store.path()).unwrap();
+        contents[HEADER_LEN] ^= 0xFF;
+        std::fs::write(store.path(), contents).unwrap();
+        assert_eq!(Some(vec![1, 2, 3]), store.load());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,754 @@
+//! Object storage on NOR flash, using the `embedded-storage` traits
+//!
+//! The stored data is kept in one of two equally sized slots, each of which must span a whole
+//! number of flash erase blocks. Each save is written to the slot which does not hold the latest
+//! data, and a header containing a sequence number and a CRC of the data is written last, so that
+//! a save which is interrupted, e.g. by a power loss, leaves the previous data intact. On restore,
+//! the valid slot with the newest sequence number is used.
+//!
+//! ```text
+//! | magic: u32 | sequence: u32 | length: u32 | crc: u16 | !crc: u16 | padding | data ... |
+//! ```
+//!
+//! The header is padded to a multiple of the flash write size.
+
+use core::convert::Infallible;
+
+use defmt_or_log::{error, info, warn};
+use embedded_storage::nor_flash::{NorFlash, NorFlashError, NorFlashErrorKind};
+use zencan_common::AtomicCell;
+
+use crate::object_dict::ODEntry;
+
+/// Marks a slot header, "ZCS1"
+const MAGIC: u32 = 0x3153_435A;
+const HEADER_LEN: usize = 16;
+/// Size of the buffer used for reading and writing flash
+///
+/// The flash write size must divide this evenly
+const CHUNK_SIZE: usize = 128;
+
+/// Error returned by the NOR flash object stores
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NorFlashStoreError {
+    /// No flash has been provided to the store
+    NoFlash,
+    /// Neither slot contains valid data
+    NoData,
+    /// The provided buffer is too small for the stored data
+    BufferTooSmall,
+    /// The data to be stored does not fit in a slot
+    TooLarge,
+    /// The flash driver returned an error
+    Flash(NorFlashErrorKind),
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for NorFlashStoreError {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Self::NoFlash => defmt::write!(f, "NoFlash"),
+            Self::NoData => defmt::write!(f, "NoData"),
+            Self::BufferTooSmall => defmt::write!(f, "BufferTooSmall"),
+            Self::TooLarge => defmt::write!(f, "TooLarge"),
+            Self::Flash(NorFlashErrorKind::NotAligned) => defmt::write!(f, "Flash(NotAligned)"),
+            Self::Flash(NorFlashErrorKind::OutOfBounds) => defmt::write!(f, "Flash(OutOfBounds)"),
+            Self::Flash(_) => defmt::write!(f, "Flash(Other)"),
+        }
+    }
+}
+
+impl<E: NorFlashError> From<E> for NorFlashStoreError {
+    fn from(value: E) -> Self {
+        Self::Flash(value.kind())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SlotHeader {
+    sequence: u32,
+    length: u32,
+    crc: u16,
+}
+
+impl SlotHeader {
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.length.to_le_bytes());
+        bytes[12..14].copy_from_slice(&self.crc.to_le_bytes());
+        bytes[14..16].copy_from_slice(&(!self.crc).to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let magic = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        let crc = u16::from_le_bytes(bytes[12..14].try_into().unwrap());
+        let crc_inv = u16::from_le_bytes(bytes[14..16].try_into().unwrap());
+        if magic != MAGIC || crc != !crc_inv {
+            return None;
+        }
+        Some(Self {
+            sequence: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            length: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            crc,
+        })
+    }
+
+    /// Returns true if this header was written after `other`, allowing for wrapping
+    fn is_newer_than(&self, other: &SlotHeader) -> bool {
+        (self.sequence.wrapping_sub(other.sequence) as i32) > 0
+    }
+}
+
+/// The location of the two slots in flash
+#[derive(Clone, Copy, Debug)]
+struct Layout {
+    offset: u32,
+    slot_size: u32,
+    read_size: usize,
+    write_size: usize,
+}
+
+impl Layout {
+    fn slot_start(&self, slot: usize) -> u32 {
+        self.offset + slot as u32 * self.slot_size
+    }
+
+    fn header_space(&self) -> usize {
+        HEADER_LEN.next_multiple_of(self.write_size)
+    }
+
+    fn data_start(&self, slot: usize) -> u32 {
+        self.slot_start(slot) + self.header_space() as u32
+    }
+
+    fn capacity(&self) -> usize {
+        (self.slot_size as usize).saturating_sub(self.header_space())
+    }
+
+    /// Get the number of bytes to read from flash for a chunk of `len` bytes
+    fn read_len(&self, len: usize) -> usize {
+        len.next_multiple_of(self.read_size)
+    }
+
+    /// Pick the slot to restore from, given the valid headers in each slot
+    fn newest(headers: [Option<SlotHeader>; 2]) -> Option<(usize, SlotHeader)> {
+        match headers {
+            [Some(a), Some(b)] => {
+                if b.is_newer_than(&a) {
+                    Some((1, b))
+                } else {
+                    Some((0, a))
+                }
+            }
+            [Some(a), None] => Some((0, a)),
+            [None, Some(b)] => Some((1, b)),
+            [None, None] => None,
+        }
+    }
+
+    /// Get the slot to write the next save to
+    fn next_slot(newest: Option<(usize, SlotHeader)>) -> usize {
+        newest.map(|(slot, _)| 1 - slot).unwrap_or(0)
+    }
+
+    /// Get the header for the next save
+    fn next_header(newest: Option<(usize, SlotHeader)>, length: usize, crc: u16) -> SlotHeader {
+        SlotHeader {
+            sequence: newest
+                .map(|(_, header)| header.sequence.wrapping_add(1))
+                .unwrap_or(0),
+            length: length as u32,
+            crc,
+        }
+    }
+}
+
+/// Read from `reader` until `buf` is full or the reader is exhausted
+fn fill(reader: &mut dyn embedded_io::Read<Error = Infallible>, buf: &mut [u8]) -> usize {
+    let mut pos = 0;
+    while pos < buf.len() {
+        let n = reader.read(&mut buf[pos..]).unwrap();
+        if n == 0 {
+            break;
+        }
+        pos += n;
+    }
+    pos
+}
+
+/// A persistent object store on blocking NOR flash
+///
+/// The store is intended to be placed in a static, so that its [`store`](Self::store) method can
+/// be called from the store objects callback. The flash driver is provided after creation, as
+/// drivers generally cannot be created in a const context.
+///
+/// ```ignore
+/// static OBJECT_STORE: NorFlashStore<Flash> = NorFlashStore::new(0x7_0000, 0x1000);
+///
+/// fn store_objects(reader: &mut dyn embedded_io::Read<Error = Infallible>, len: usize) {
+///     OBJECT_STORE.store(reader, len);
+/// }
+///
+/// OBJECT_STORE.set_flash(flash);
+/// let mut buf = [0; 512];
+/// OBJECT_STORE.restore(&zencan::OD_TABLE, &mut buf).ok();
+/// let mut node = Node::new(node_id, &zencan::NODE_MBOX, &zencan::NODE_STATE, &zencan::OD_TABLE);
+/// node.register_store_objects(&store_objects);
+/// ```
+///
+/// The flash is only accessed while saving or restoring, and is not locked during the erase and
+/// write. A save attempted while another is in progress fails, and is logged.
+#[allow(missing_debug_implementations)]
+pub struct NorFlashStore<F> {
+    flash: AtomicCell<Option<F>>,
+    offset: u32,
+    slot_size: u32,
+}
+
+impl<F: Send> NorFlashStore<F> {
+    /// Create a new store
+    ///
+    /// # Arguments
+    /// - `offset`: The flash offset of the first slot. Must be aligned to the flash erase size.
+    /// - `slot_size`: The size of each slot, in bytes. Must be a multiple of the flash erase size.
+    ///   The second slot immediately follows the first, so `2 * slot_size` bytes are used.
+    pub const fn new(offset: u32, slot_size: u32) -> Self {
+        Self {
+            flash: AtomicCell::new(None),
+            offset,
+            slot_size,
+        }
+    }
+
+    /// Provide the flash driver used by the store
+    pub fn set_flash(&self, flash: F) {
+        self.flash.store(Some(flash));
+    }
+
+    /// Remove the flash driver from the store, returning it
+    pub fn take_flash(&self) -> Option<F> {
+        self.flash.take()
+    }
+}
+
+impl<F: NorFlash + Send> NorFlashStore<F> {
+    fn layout(&self) -> Layout {
+        const { assert!(F::WRITE_SIZE <= CHUNK_SIZE && CHUNK_SIZE % F::WRITE_SIZE == 0) };
+        Layout {
+            offset: self.offset,
+            slot_size: self.slot_size,
+            read_size: F::READ_SIZE,
+            write_size: F::WRITE_SIZE,
+        }
+    }
+
+    /// Get the largest amount of data which can be stored
+    pub fn capacity(&self) -> usize {
+        self.layout().capacity()
+    }
+
+    /// Run `f` with the flash driver, returning it to the store afterwards
+    fn with_flash<T>(
+        &self,
+        f: impl FnOnce(&mut F) -> Result<T, NorFlashStoreError>,
+    ) -> Result<T, NorFlashStoreError> {
+        let mut flash = self.flash.take().ok_or(NorFlashStoreError::NoFlash)?;
+        let result = f(&mut flash);
+        self.flash.store(Some(flash));
+        result
+    }
+
+    /// Read and validate the header of a slot
+    fn read_header(
+        flash: &mut F,
+        layout: &Layout,
+        slot: usize,
+    ) -> Result<Option<SlotHeader>, NorFlashStoreError> {
+        let mut buf = [0; CHUNK_SIZE];
+        let read_len = layout.read_len(HEADER_LEN);
+        flash.read(layout.slot_start(slot), &mut buf[..read_len])?;
+        let Some(header) = SlotHeader::from_bytes(&buf) else {
+            return Ok(None);
+        };
+        if header.length as usize > layout.capacity() {
+            return Ok(None);
+        }
+
+        let mut crc = crc16::State::<crc16::XMODEM>::new();
+        Self::read_data(flash, layout, slot, header.length as usize, |chunk| {
+            crc.update(chunk)
+        })?;
+        if crc.get() != header.crc {
+            warn!("Object storage slot {} failed CRC check", slot);
+            return Ok(None);
+        }
+        Ok(Some(header))
+    }
+
+    /// Read `length` bytes of data from a slot, passing it to `on_data` in chunks
+    fn read_data(
+        flash: &mut F,
+        layout: &Layout,
+        slot: usize,
+        length: usize,
+        mut on_data: impl FnMut(&[u8]),
+    ) -> Result<(), NorFlashStoreError> {
+        let mut buf = [0; CHUNK_SIZE];
+        let mut pos = 0;
+        while pos < length {
+            let len = (length - pos).min(CHUNK_SIZE);
+            let read_len = layout.read_len(len);
+            flash.read(layout.data_start(slot) + pos as u32, &mut buf[..read_len])?;
+            on_data(&buf[..len]);
+            pos += len;
+        }
+        Ok(())
+    }
+
+    fn find_newest(
+        flash: &mut F,
+        layout: &Layout,
+    ) -> Result<Option<(usize, SlotHeader)>, NorFlashStoreError> {
+        Ok(Layout::newest([
+            Self::read_header(flash, layout, 0)?,
+            Self::read_header(flash, layout, 1)?,
+        ]))
+    }
+
+    /// Read the most recently stored data into `buf`
+    ///
+    /// Returns the slice of `buf` containing the data.
+    pub fn load<'b>(&self, buf: &'b mut [u8]) -> Result<&'b [u8], NorFlashStoreError> {
+        let layout = self.layout();
+        let length = self.with_flash(|flash| {
+            let (slot, header) =
+                Self::find_newest(flash, &layout)?.ok_or(NorFlashStoreError::NoData)?;
+            let length = header.length as usize;
+            if length > buf.len() {
+                return Err(NorFlashStoreError::BufferTooSmall);
+            }
+            let mut pos = 0;
+            Self::read_data(flash, &layout, slot, length, |chunk| {
+                buf[pos..pos + chunk.len()].copy_from_slice(chunk);
+                pos += chunk.len();
+            })?;
+            Ok(length)
+        })?;
+        Ok(&buf[..length])
+    }
+
+    /// Restore the most recently stored object values into the object dictionary
+    ///
+    /// `buf` is used to hold the stored data while it is restored, and must be at least as large
+    /// as the stored data. Returns the size of the restored data.
+    pub fn restore(&self, od: &[ODEntry], buf: &mut [u8]) -> Result<usize, NorFlashStoreError> {
+        let data = self.load(buf)?;
+        crate::restore_stored_objects(od, data);
+        Ok(data.len())
+    }
+
+    /// Store object data to flash
+    ///
+    /// The arguments match the [`StoreObjectsCallback`](super::StoreObjectsCallback), so this
+    /// can be called directly from the store objects callback. Errors are logged.
+    pub fn store(&self, reader: &mut dyn embedded_io::Read<Error = Infallible>, len: usize) {
+        match self.try_store(reader, len) {
+            Ok(()) => info!("Stored {} bytes of object data to flash", len),
+            Err(e) => error!("Error storing objects to flash: {:?}", e),
+        }
+    }
+
+    /// Store object data to flash, returning any error
+    pub fn try_store(
+        &self,
+        reader: &mut dyn embedded_io::Read<Error = Infallible>,
+        len: usize,
+    ) -> Result<(), NorFlashStoreError> {
+        let layout = self.layout();
+        if len > layout.capacity() {
+            return Err(NorFlashStoreError::TooLarge);
+        }
+        self.with_flash(|flash| {
+            let newest = Self::find_newest(flash, &layout)?;
+            let slot = Layout::next_slot(newest);
+            flash.erase(
+                layout.slot_start(slot),
+                layout.slot_start(slot) + layout.slot_size,
+            )?;
+
+            let mut crc = crc16::State::<crc16::XMODEM>::new();
+            let mut buf = [0xFF; CHUNK_SIZE];
+            let mut pos = 0;
+            while pos < len {
+                let n = fill(reader, &mut buf[..(len - pos).min(CHUNK_SIZE)]);
+                if n == 0 {
+                    break;
+                }
+                crc.update(&buf[..n]);
+                let write_len = n.next_multiple_of(layout.write_size);
+                buf[n..write_len].fill(0xFF);
+                flash.write(layout.data_start(slot) + pos as u32, &buf[..write_len])?;
+                pos += n;
+            }
+
+            // Header is written last, so the slot only becomes valid once all data is written
+            let header = Layout::next_header(newest, pos, crc.get());
+            buf.fill(0xFF);
+            buf[..HEADER_LEN].copy_from_slice(&header.to_bytes());
+            flash.write(layout.slot_start(slot), &buf[..layout.header_space()])?;
+            Ok(())
+        })
+    }
+
+    /// Erase both slots, removing all stored data
+    pub fn erase(&self) -> Result<(), NorFlashStoreError> {
+        let layout = self.layout();
+        self.with_flash(|flash| {
+            flash.erase(layout.slot_start(0), layout.slot_start(2))?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(feature = "embedded-storage-async")]
+mod nor_flash_async {
+    use super::*;
+
+    use core::cell::RefCell;
+    use critical_section::Mutex;
+    use embedded_storage_async::nor_flash::NorFlash as AsyncNorFlash;
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    enum FlushState {
+        #[default]
+        Idle,
+        /// Data is waiting in the buffer to be written
+        Pending,
+        /// Data is being written. If new data is stored while writing, the state is set to
+        /// Pending, so that it will be written again.
+        Writing,
+    }
+
+    /// A persistent object store on async NOR flash
+    ///
+    /// Since the store objects callback is synchronous, saved data is first copied into a RAM
+    /// buffer of `N` bytes, and then written to flash by [`flush`](Self::flush), which the
+    /// application should call from a task, e.g. after each call to `Node::process`. The flash
+    /// driver is owned by the application and passed to each async method.
+    ///
+    /// ```ignore
+    /// static OBJECT_STORE: AsyncNorFlashStore<512> = AsyncNorFlashStore::new(0x7_0000, 0x1000);
+    ///
+    /// fn store_objects(reader: &mut dyn embedded_io::Read<Error = Infallible>, len: usize) {
+    ///     OBJECT_STORE.store(reader, len);
+    /// }
+    ///
+    /// OBJECT_STORE.restore(&mut flash, &zencan::OD_TABLE).await.ok();
+    /// node.register_store_objects(&store_objects);
+    /// loop {
+    ///     node.process(now_us, &mut send);
+    ///     OBJECT_STORE.flush(&mut flash).await.ok();
+    /// }
+    /// ```
+    #[allow(missing_debug_implementations)]
+    pub struct AsyncNorFlashStore<const N: usize> {
+        buffer: Mutex<RefCell<[u8; N]>>,
+        length: AtomicCell<usize>,
+        state: AtomicCell<FlushState>,
+        offset: u32,
+        slot_size: u32,
+    }
+
+    impl<const N: usize> AsyncNorFlashStore<N> {
+        /// Create a new store
+        ///
+        /// # Arguments
+        /// - `offset`: The flash offset of the first slot. Must be aligned to the flash erase
+        ///   size.
+        /// - `slot_size`: The size of each slot, in bytes. Must be a multiple of the flash erase
+        ///   size. The second slot immediately follows the first, so `2 * slot_size` bytes are
+        ///   used.
+        pub const fn new(offset: u32, slot_size: u32) -> Self {
+            Self {
+                buffer: Mutex::new(RefCell::new([0; N])),
+                length: AtomicCell::new(0),
+                state: AtomicCell::new(FlushState::Idle),
+                offset,
+                slot_size,
+            }
+        }
+
+        fn layout<F: AsyncNorFlash>(&self) -> Layout {
+            const { assert!(F::WRITE_SIZE <= CHUNK_SIZE && CHUNK_SIZE % F::WRITE_SIZE == 0) };
+            Layout {
+                offset: self.offset,
+                slot_size: self.slot_size,
+                read_size: F::READ_SIZE,
+                write_size: F::WRITE_SIZE,
+            }
+        }
+
+        /// Copy object data into the buffer, to be written by the next call to
+        /// [`flush`](Self::flush)
+        ///
+        /// The arguments match the [`StoreObjectsCallback`](crate::storage::StoreObjectsCallback),
+        /// so this can be called directly from the store objects callback. Errors are logged.
+        pub fn store(&self, reader: &mut dyn embedded_io::Read<Error = Infallible>, len: usize) {
+            if len > N {
+                error!(
+                    "Object data ({} bytes) exceeds store buffer ({} bytes)",
+                    len, N
+                );
+                return;
+            }
+            critical_section::with(|cs| {
+                let mut buffer = self.buffer.borrow_ref_mut(cs);
+                let n = fill(reader, &mut buffer[..len]);
+                self.length.store(n);
+            });
+            self.state.store(FlushState::Pending);
+        }
+
+        /// Returns true if there is stored data which has not yet been written to flash
+        pub fn is_pending(&self) -> bool {
+            self.state.load() != FlushState::Idle
+        }
+
+        async fn read_header<F: AsyncNorFlash>(
+            flash: &mut F,
+            layout: &Layout,
+            slot: usize,
+        ) -> Result<Option<SlotHeader>, NorFlashStoreError> {
+            let mut buf = [0; CHUNK_SIZE];
+            let read_len = layout.read_len(HEADER_LEN);
+            flash
+                .read(layout.slot_start(slot), &mut buf[..read_len])
+                .await?;
+            let Some(header) = SlotHeader::from_bytes(&buf) else {
+                return Ok(None);
+            };
+            if header.length as usize > layout.capacity() {
+                return Ok(None);
+            }
+
+            let mut crc = crc16::State::<crc16::XMODEM>::new();
+            let mut pos = 0;
+            while pos < header.length as usize {
+                let len = (header.length as usize - pos).min(CHUNK_SIZE);
+                flash
+                    .read(
+                        layout.data_start(slot) + pos as u32,
+                        &mut buf[..layout.read_len(len)],
+                    )
+                    .await?;
+                crc.update(&buf[..len]);
+                pos += len;
+            }
+            if crc.get() != header.crc {
+                warn!("Object storage slot {} failed CRC check", slot);
+                return Ok(None);
+            }
+            Ok(Some(header))
+        }
+
+        async fn find_newest<F: AsyncNorFlash>(
+            flash: &mut F,
+            layout: &Layout,
+        ) -> Result<Option<(usize, SlotHeader)>, NorFlashStoreError> {
+            Ok(Layout::newest([
+                Self::read_header(flash, layout, 0).await?,
+                Self::read_header(flash, layout, 1).await?,
+            ]))
+        }
+
+        /// Restore the most recently stored object values into the object dictionary
+        ///
+        /// The stored data is read into the store's buffer, so it must be at least as large as
+        /// the stored data. Returns the size of the restored data.
+        pub async fn restore<F: AsyncNorFlash>(
+            &self,
+            flash: &mut F,
+            od: &[ODEntry<'_>],
+        ) -> Result<usize, NorFlashStoreError> {
+            let layout = self.layout::<F>();
+            let (slot, header) = Self::find_newest(flash, &layout)
+                .await?
+                .ok_or(NorFlashStoreError::NoData)?;
+            let length = header.length as usize;
+            if length > N {
+                return Err(NorFlashStoreError::BufferTooSmall);
+            }
+
+            let mut buf = [0; CHUNK_SIZE];
+            let mut pos = 0;
+            while pos < length {
+                let len = (length - pos).min(CHUNK_SIZE);
+                flash
+                    .read(
+                        layout.data_start(slot) + pos as u32,
+                        &mut buf[..layout.read_len(len)],
+                    )
+                    .await?;
+                critical_section::with(|cs| {
+                    self.buffer.borrow_ref_mut(cs)[pos..pos + len].copy_from_slice(&buf[..len])
+                });
+                pos += len;
+            }
+            critical_section::with(|cs| {
+                crate::restore_stored_objects(od, &self.buffer.borrow_ref(cs)[..length])
+            });
+            Ok(length)
+        }
+
+        /// Write any pending data to flash
+        ///
+        /// Returns true if data was written, or false if there was nothing to write.
+        pub async fn flush<F: AsyncNorFlash>(
+            &self,
+            flash: &mut F,
+        ) -> Result<bool, NorFlashStoreError> {
+            if self.state.load() != FlushState::Pending {
+                return Ok(false);
+            }
+            self.state.store(FlushState::Writing);
+            let result = self.write(flash).await;
+            // If new data arrived during the write, leave it pending
+            self.state
+                .fetch_update(|state| (state == FlushState::Writing).then_some(FlushState::Idle))
+                .ok();
+            match result {
+                Ok(()) => Ok(true),
+                Err(e) => {
+                    self.state.store(FlushState::Pending);
+                    Err(e)
+                }
+            }
+        }
+
+        async fn write<F: AsyncNorFlash>(&self, flash: &mut F) -> Result<(), NorFlashStoreError> {
+            let layout = self.layout::<F>();
+            let length = self.length.load();
+            if length > layout.capacity() {
+                return Err(NorFlashStoreError::TooLarge);
+            }
+            let newest = Self::find_newest(flash, &layout).await?;
+            let slot = Layout::next_slot(newest);
+            flash
+                .erase(
+                    layout.slot_start(slot),
+                    layout.slot_start(slot) + layout.slot_size,
+                )
+                .await?;
+
+            let mut crc = crc16::State::<crc16::XMODEM>::new();
+            let mut buf = [0xFF; CHUNK_SIZE];
+            let mut pos = 0;
+            while pos < length {
+                let n = (length - pos).min(CHUNK_SIZE);
+                critical_section::with(|cs| {
+                    buf[..n].copy_from_slice(&self.buffer.borrow_ref(cs)[pos..pos + n])
+                });
+                crc.update(&buf[..n]);
+                let write_len = n.next_multiple_of(layout.write_size);
+                buf[n..write_len].fill(0xFF);
+                flash
+                    .write(layout.data_start(slot) + pos as u32, &buf[..write_len])
+                    .await?;
+                pos += n;
+            }
+
+            // Header is written last, so the slot only becomes valid once all data is written
+            let header = Layout::next_header(newest, length, crc.get());
+            buf.fill(0xFF);
+            buf[..HEADER_LEN].copy_from_slice(&header.to_bytes());
+            flash
+                .write(layout.slot_start(slot), &buf[..layout.header_space()])
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "embedded-storage-async")]
+pub use nor_flash_async::AsyncNorFlashStore;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_storage::nor_flash::{ErrorType, ReadNorFlash};
+
+    const FLASH_SIZE: usize = 1024;
+
+    struct RamFlash {
+        data: [u8; FLASH_SIZE],
+    }
+
+    impl ErrorType for RamFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for RamFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            FLASH_SIZE
+        }
+    }
+
+    impl NorFlash for RamFlash {
+        const WRITE_SIZE: usize = 8;
+        const ERASE_SIZE: usize = 256;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            self.data[from as usize..to as usize].fill(0xFF);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            assert_eq!(0, offset as usize % Self::WRITE_SIZE);
+            assert_eq!(0, bytes.len() % Self::WRITE_SIZE);
+            let offset = offset as usize;
+            self.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_nor_flash_store_fallback() {
+        let store = NorFlashStore::new(256, 256);
+        store.set_flash(RamFlash {
+            data: [0xFF; FLASH_SIZE],
+        });
+        let mut buf = [0; 256];
+        assert_eq!(Err(NorFlashStoreError::NoData), store.load(&mut buf));
+
+        let first: Vec<u8> = (0..100).collect();
+        let second: Vec<u8> = (100..120).collect();
+        store.try_store(&mut &first[..], first.len()).unwrap();
+        assert_eq!(&first[..], store.load(&mut buf).unwrap());
+        store.try_store(&mut &second[..], second.len()).unwrap();
+        assert_eq!(&second[..], store.load(&mut buf).unwrap());
+
+        // Corrupt the newest data, which is in the second slot. The first slot should be used.
+        let mut flash = store.take_flash().unwrap();
+        flash.data[512 + 16] ^= 1;
+        store.set_flash(flash);
+        assert_eq!(&first[..], store.load(&mut buf).unwrap());
+
+        // Data too large for a slot is rejected
+        let large = [0; 256];
+        assert_eq!(
+            Err(NorFlashStoreError::TooLarge),
+            store.try_store(&mut &large[..], large.len())
+        );
+    }
+}
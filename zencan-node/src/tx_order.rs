@@ -0,0 +1,109 @@
+//! Ordering of the messages transmitted by [`Node::process`](crate::Node::process)
+
+use zencan_common::messages::CanMessage;
+
+use crate::tx_queue::TxQueue;
+
+/// The stages of node processing which transmit messages, in the order they are sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum TxStage {
+    /// The boot-up message, after a reset
+    Bootup,
+    /// SDO server responses
+    Sdo,
+    /// LSS slave responses
+    Lss,
+    /// Emergency messages
+    Emcy,
+    /// Heartbeat messages
+    Heartbeat,
+    /// Transmit PDOs
+    Tpdo,
+    /// Loopback self-test frames
+    SelfTest,
+}
+
+/// Where an [`OrderedSender`] passes messages
+enum Target<'a> {
+    Callback(&'a mut dyn FnMut(CanMessage)),
+    Queue(&'a TxQueue),
+}
+
+/// Wraps the send callback, and ensures messages are passed to it in [`TxStage`] order
+///
+/// Each message is sent along with the stage which produced it. Stages may be skipped, but a
+/// stage may not send after a later stage has already sent, as this would break the ordering
+/// guarantees documented on `Node::process`.
+pub(crate) struct OrderedSender<'a> {
+    target: Target<'a>,
+    stage: TxStage,
+}
+
+impl<'a> OrderedSender<'a> {
+    /// Create a new sender, starting at the first stage
+    pub fn new(send_cb: &'a mut dyn FnMut(CanMessage)) -> Self {
+        Self {
+            target: Target::Callback(send_cb),
+            stage: TxStage::Bootup,
+        }
+    }
+
+    /// Create a new sender which adds messages to a transmit queue, starting at the first stage
+    pub fn queued(queue: &'a TxQueue) -> Self {
+        Self {
+            target: Target::Queue(queue),
+            stage: TxStage::Bootup,
+        }
+    }
+
+    /// Send a message produced by `stage`
+    ///
+    /// Returns false if the transmit queue was full, and a message was discarded, see
+    /// [`TxQueue::push`]. Messages passed to a callback are always accepted.
+    pub fn send(&mut self, stage: TxStage, msg: CanMessage) -> bool {
+        debug_assert!(
+            stage >= self.stage,
+            "{:?} message sent after {:?} message",
+            stage,
+            self.stage
+        );
+        self.stage = stage;
+        match &mut self.target {
+            Target::Callback(send_cb) => {
+                send_cb(msg);
+                true
+            }
+            Target::Queue(queue) => queue.push(stage, msg),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zencan_common::messages::CanId;
+
+    use super::*;
+
+    #[test]
+    fn test_stages_in_order() {
+        let mut sent = Vec::new();
+        let mut send_cb = |msg: CanMessage| sent.push(msg.id());
+        let mut sender = OrderedSender::new(&mut send_cb);
+        sender.send(TxStage::Sdo, CanMessage::new(CanId::Std(0x581), &[]));
+        sender.send(TxStage::Sdo, CanMessage::new(CanId::Std(0x581), &[]));
+        sender.send(TxStage::Tpdo, CanMessage::new(CanId::Std(0x181), &[]));
+        assert_eq!(
+            vec![CanId::Std(0x581), CanId::Std(0x581), CanId::Std(0x181)],
+            sent
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_stage_out_of_order() {
+        let mut send_cb = |_msg: CanMessage| {};
+        let mut sender = OrderedSender::new(&mut send_cb);
+        sender.send(TxStage::Tpdo, CanMessage::new(CanId::Std(0x181), &[]));
+        sender.send(TxStage::Sdo, CanMessage::new(CanId::Std(0x581), &[]));
+    }
+}
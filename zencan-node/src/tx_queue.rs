@@ -0,0 +1,211 @@
+//! A bounded queue of messages waiting to be transmitted, released in priority order
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use zencan_common::messages::CanMessage;
+
+use crate::{tx_order::TxStage, BufferCell};
+
+/// Storage for one message in a transmit queue
+///
+/// The storage for the queue is a static array of slots, which zencan-build sizes from the
+/// `tx_queue_depth` in the `[mbox]` section of the device config.
+#[derive(Clone, Copy, Debug)]
+pub struct TxSlot(Option<(TxStage, CanMessage)>);
+
+impl TxSlot {
+    /// An empty slot
+    pub const EMPTY: Self = Self(None);
+
+    fn stage(&self) -> Option<TxStage> {
+        self.0.map(|(stage, _)| stage)
+    }
+}
+
+/// What to do with a message when the transmit queue is full
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TxOverflowPolicy {
+    /// Discard the new message
+    DropNew,
+    /// Discard the newest of the lowest priority queued messages, if it has a lower priority than
+    /// the new message, or else discard the new message
+    ///
+    /// This keeps e.g. SDO responses and heartbeats flowing when TPDOs are produced faster than
+    /// they can be transmitted.
+    #[default]
+    DropLowestPriority,
+}
+
+struct TxQueueInner {
+    buffer: Option<&'static BufferCell<[TxSlot]>>,
+    len: usize,
+    overflow: u32,
+    policy: TxOverflowPolicy,
+}
+
+/// A queue of messages produced by [`Node::process_queued`](crate::Node::process_queued)
+///
+/// Messages are kept in the order of the stage which produced them, see
+/// [`Node::process`](crate::Node::process), so that a message always leaves the queue before any
+/// message of a lower priority, and messages of the same priority leave in the order they were
+/// produced.
+pub(crate) struct TxQueue {
+    inner: Mutex<RefCell<TxQueueInner>>,
+}
+
+impl TxQueue {
+    /// Create a queue with no storage, which overflows on every push
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(TxQueueInner {
+                buffer: None,
+                len: 0,
+                overflow: 0,
+                policy: TxOverflowPolicy::DropLowestPriority,
+            })),
+        }
+    }
+
+    /// Create a queue using `buffer` for storage
+    ///
+    /// The capacity of the queue is the length of the buffer.
+    pub const fn with_buffer(buffer: &'static BufferCell<[TxSlot]>) -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(TxQueueInner {
+                buffer: Some(buffer),
+                len: 0,
+                overflow: 0,
+                policy: TxOverflowPolicy::DropLowestPriority,
+            })),
+        }
+    }
+
+    /// Set the overflow policy
+    pub fn set_policy(&self, policy: TxOverflowPolicy) {
+        critical_section::with(|cs| self.inner.borrow_ref_mut(cs).policy = policy);
+    }
+
+    /// Add a message produced by `stage` to the queue
+    ///
+    /// Returns false if a message was discarded because the queue was full
+    pub fn push(&self, stage: TxStage, msg: CanMessage) -> bool {
+        critical_section::with(|cs| {
+            let mut q = self.inner.borrow_ref_mut(cs);
+            let Some(buffer) = q.buffer else {
+                q.overflow = q.overflow.wrapping_add(1);
+                return false;
+            };
+            let mut slots = buffer.borrow_mut();
+            let mut no_drop = true;
+            if q.len == slots.len() {
+                q.overflow = q.overflow.wrapping_add(1);
+                no_drop = false;
+                let evict = q.policy == TxOverflowPolicy::DropLowestPriority
+                    && q.len > 0
+                    && slots[q.len - 1].stage().is_some_and(|s| s > stage);
+                if !evict {
+                    return false;
+                }
+                q.len -= 1;
+            }
+            // Insert after all messages of the same or a higher priority
+            let len = q.len;
+            let pos = slots[..len]
+                .iter()
+                .position(|slot| slot.stage().is_some_and(|s| s > stage))
+                .unwrap_or(len);
+            slots.copy_within(pos..len, pos + 1);
+            slots[pos] = TxSlot(Some((stage, msg)));
+            q.len += 1;
+            no_drop
+        })
+    }
+
+    /// Remove the highest priority message from the queue
+    pub fn pop(&self) -> Option<CanMessage> {
+        critical_section::with(|cs| {
+            let mut q = self.inner.borrow_ref_mut(cs);
+            if q.len == 0 {
+                return None;
+            }
+            let mut slots = q.buffer?.borrow_mut();
+            let len = q.len;
+            let msg = slots[0].0.map(|(_, msg)| msg);
+            slots.copy_within(1..len, 0);
+            slots[len - 1] = TxSlot::EMPTY;
+            q.len -= 1;
+            msg
+        })
+    }
+
+    /// The number of messages in the queue
+    pub fn len(&self) -> usize {
+        critical_section::with(|cs| self.inner.borrow_ref(cs).len)
+    }
+
+    /// The number of messages which have been discarded because the queue was full
+    pub fn overflow_count(&self) -> u32 {
+        critical_section::with(|cs| self.inner.borrow_ref(cs).overflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zencan_common::messages::CanId;
+
+    fn leak_buffer<const N: usize>() -> &'static BufferCell<[TxSlot]> {
+        Box::leak(Box::new(BufferCell::new([TxSlot::EMPTY; N])))
+    }
+
+    fn msg(id: u16) -> CanMessage {
+        CanMessage::new(CanId::std(id), &[])
+    }
+
+    fn drain(q: &TxQueue) -> Vec<u16> {
+        core::iter::from_fn(|| q.pop())
+            .map(|m| m.id().raw() as u16)
+            .collect()
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let q = TxQueue::with_buffer(leak_buffer::<4>());
+        assert!(q.push(TxStage::Tpdo, msg(0x181)));
+        assert!(q.push(TxStage::Heartbeat, msg(0x701)));
+        assert!(q.push(TxStage::Tpdo, msg(0x281)));
+        assert!(q.push(TxStage::Sdo, msg(0x581)));
+        assert_eq!(4, q.len());
+        assert_eq!(vec![0x581, 0x701, 0x181, 0x281], drain(&q));
+        assert_eq!(0, q.overflow_count());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let q = TxQueue::with_buffer(leak_buffer::<2>());
+        q.push(TxStage::Tpdo, msg(0x181));
+        q.push(TxStage::Tpdo, msg(0x281));
+        // A higher priority message replaces the newest TPDO
+        assert!(!q.push(TxStage::Sdo, msg(0x581)));
+        assert!(!q.push(TxStage::Sdo, msg(0x582)));
+        // A message of the same priority as the lowest queued message is dropped
+        assert!(!q.push(TxStage::Sdo, msg(0x583)));
+        assert_eq!(3, q.overflow_count());
+        assert_eq!(vec![0x581, 0x582], drain(&q));
+
+        q.set_policy(TxOverflowPolicy::DropNew);
+        q.push(TxStage::Tpdo, msg(0x181));
+        q.push(TxStage::Tpdo, msg(0x281));
+        assert!(!q.push(TxStage::Sdo, msg(0x581)));
+        assert_eq!(vec![0x181, 0x281], drain(&q));
+    }
+
+    #[test]
+    fn test_no_storage() {
+        let q = TxQueue::new();
+        assert!(!q.push(TxStage::Sdo, msg(0x581)));
+        assert_eq!(1, q.overflow_count());
+        assert_eq!(None, q.pop().map(|m| m.id()));
+    }
+}
@@ -0,0 +1,396 @@
+//! A shared pool for the values of variable length objects (0x5006)
+//!
+//! String objects are normally stored in a buffer of their maximum size, which is mostly unused
+//! when the strings are rarely filled. When a device config sets `[memory] var_pool`, objects which
+//! set `pooled = true` are generated as a [`PoolObject`], and their values are stored in one
+//! [`VarPool`], which only uses the length of each current value. The values are kept contiguous,
+//! in slot order, so a write which changes the length of a value moves the values which follow it.
+//! This is done inside a critical section, so the pool should be sized to the largest total the
+//! application expects, rather than for worst case headroom.
+//!
+//! A write which does not fit in the remaining space is aborted with
+//! [`AbortCode::OutOfMemory`]. The capacity, current use, and high-water mark of the pool are
+//! reported in object 0x5006 by a [`VarPoolStatusObject`].
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use zencan_common::{
+    objects::{ObjectCode, SubInfo},
+    sdo::AbortCode,
+};
+
+use crate::object_dict::{ConstField, ProvidesSubObjects, SubObjectAccess};
+
+/// Access to a [`VarPool`], without its size parameters
+pub trait VarPoolAccess: Sync {
+    /// Get the length of the value in `slot`
+    fn slot_len(&self, slot: usize) -> usize;
+
+    /// Read the value in `slot`, starting at `offset`, and return the number of bytes read
+    fn read(&self, slot: usize, offset: usize, buf: &mut [u8]) -> usize;
+
+    /// Replace the value in `slot`
+    ///
+    /// Returns [`AbortCode::OutOfMemory`] if the pool does not have room for the new value.
+    fn write(&self, slot: usize, data: &[u8]) -> Result<(), AbortCode>;
+
+    /// The size of the pool, in bytes
+    fn capacity(&self) -> usize;
+
+    /// The number of bytes used by the current values
+    fn used(&self) -> usize;
+
+    /// The most bytes used since the pool was created
+    fn high_water(&self) -> usize;
+}
+
+struct PoolState<const N: usize, const M: usize> {
+    data: [u8; N],
+    lens: [usize; M],
+    high_water: usize,
+}
+
+impl<const N: usize, const M: usize> PoolState<N, M> {
+    fn start(&self, slot: usize) -> usize {
+        self.lens[..slot].iter().sum()
+    }
+
+    fn used(&self) -> usize {
+        self.lens.iter().sum()
+    }
+}
+
+/// Storage for the values of `M` objects, sharing `N` bytes
+pub struct VarPool<const N: usize, const M: usize> {
+    state: Mutex<RefCell<PoolState<N, M>>>,
+}
+
+impl<const N: usize, const M: usize> core::fmt::Debug for VarPool<N, M> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("VarPool")
+            .field("capacity", &N)
+            .field("used", &self.used())
+            .field("high_water", &self.high_water())
+            .finish()
+    }
+}
+
+impl<const N: usize, const M: usize> VarPool<N, M> {
+    /// Create a pool holding initial values
+    ///
+    /// `init` holds the initial values of all slots, concatenated in slot order, and `lens` holds
+    /// the length of each.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `init` is longer than the pool, or does not match the total of `lens`
+    pub const fn new(init: &[u8], lens: [usize; M]) -> Self {
+        assert!(init.len() <= N, "Initial values do not fit in the var pool");
+        let mut total = 0;
+        let mut i = 0;
+        while i < M {
+            total += lens[i];
+            i += 1;
+        }
+        assert!(total == init.len(), "Initial value lengths do not match");
+
+        let mut data = [0u8; N];
+        let mut i = 0;
+        while i < init.len() {
+            data[i] = init[i];
+            i += 1;
+        }
+        Self {
+            state: Mutex::new(RefCell::new(PoolState {
+                data,
+                lens,
+                high_water: total,
+            })),
+        }
+    }
+}
+
+impl<const N: usize, const M: usize> VarPoolAccess for VarPool<N, M> {
+    fn slot_len(&self, slot: usize) -> usize {
+        critical_section::with(|cs| self.state.borrow_ref(cs).lens[slot])
+    }
+
+    fn read(&self, slot: usize, offset: usize, buf: &mut [u8]) -> usize {
+        critical_section::with(|cs| {
+            let state = self.state.borrow_ref(cs);
+            let len = state.lens[slot];
+            if offset >= len {
+                return 0;
+            }
+            let start = state.start(slot) + offset;
+            let read_len = buf.len().min(len - offset);
+            buf[..read_len].copy_from_slice(&state.data[start..start + read_len]);
+            read_len
+        })
+    }
+
+    fn write(&self, slot: usize, data: &[u8]) -> Result<(), AbortCode> {
+        critical_section::with(|cs| {
+            let mut state = self.state.borrow_ref_mut(cs);
+            let used = state.used();
+            let old_len = state.lens[slot];
+            let new_used = used - old_len + data.len();
+            if new_used > N {
+                return Err(AbortCode::OutOfMemory);
+            }
+            let start = state.start(slot);
+            // Move the values of the following slots to fit the new length
+            state
+                .data
+                .copy_within(start + old_len..used, start + data.len());
+            state.data[start..start + data.len()].copy_from_slice(data);
+            state.lens[slot] = data.len();
+            state.high_water = state.high_water.max(new_used);
+            Ok(())
+        })
+    }
+
+    fn capacity(&self) -> usize {
+        N
+    }
+
+    fn used(&self) -> usize {
+        critical_section::with(|cs| self.state.borrow_ref(cs).used())
+    }
+
+    fn high_water(&self) -> usize {
+        critical_section::with(|cs| self.state.borrow_ref(cs).high_water)
+    }
+}
+
+/// Sub object accessing one slot of a [`VarPool`]
+struct PoolField {
+    pool: &'static dyn VarPoolAccess,
+    slot: usize,
+    max_size: usize,
+}
+
+impl SubObjectAccess for PoolField {
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        Ok(self.pool.read(self.slot, offset, buf))
+    }
+
+    fn read_size(&self) -> usize {
+        self.pool.slot_len(self.slot)
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), AbortCode> {
+        if data.len() > self.max_size {
+            return Err(AbortCode::DataTypeMismatchLengthHigh);
+        }
+        self.pool.write(self.slot, data)
+    }
+}
+
+/// A var object whose value is stored in a [`VarPool`]
+///
+/// The size in the sub info is the maximum length of the value.
+#[allow(missing_debug_implementations)]
+pub struct PoolObject {
+    value: PoolField,
+    info: SubInfo,
+}
+
+impl PoolObject {
+    /// Create an object stored in `slot` of `pool`
+    pub const fn new(pool: &'static dyn VarPoolAccess, slot: usize, info: SubInfo) -> Self {
+        Self {
+            value: PoolField {
+                pool,
+                slot,
+                max_size: info.size,
+            },
+            info,
+        }
+    }
+
+    /// Store a new value
+    ///
+    /// Returns [`AbortCode::DataTypeMismatchLengthHigh`] if the value is longer than the object,
+    /// or [`AbortCode::OutOfMemory`] if it does not fit in the pool.
+    pub fn set_value(&self, value: &[u8]) -> Result<(), AbortCode> {
+        self.value.write(value)
+    }
+
+    /// Load the value into `buf`, and return the bytes read
+    pub fn get_value<'a>(&self, buf: &'a mut [u8]) -> &'a [u8] {
+        let len = self.value.pool.read(self.value.slot, 0, buf);
+        &buf[..len]
+    }
+
+    /// Get the length of the current value
+    pub fn len(&self) -> usize {
+        self.value.read_size()
+    }
+
+    /// Returns true if the current value is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl ProvidesSubObjects for PoolObject {
+    fn get_sub_object(&self, sub: u8) -> Option<(SubInfo, &dyn SubObjectAccess)> {
+        match sub {
+            0 => Some((self.info, &self.value)),
+            _ => None,
+        }
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Var
+    }
+}
+
+/// Sub index of the pool capacity
+const SUB_CAPACITY: u8 = 1;
+/// Sub index of the bytes used
+const SUB_USED: u8 = 2;
+/// Sub index of the high-water mark
+const SUB_HIGH_WATER: u8 = 3;
+
+struct StatusField {
+    pool: &'static dyn VarPoolAccess,
+    sub: u8,
+}
+
+impl SubObjectAccess for StatusField {
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, AbortCode> {
+        let value = match self.sub {
+            SUB_CAPACITY => self.pool.capacity(),
+            SUB_USED => self.pool.used(),
+            _ => self.pool.high_water(),
+        };
+        let bytes = (value as u32).to_le_bytes();
+        if offset < bytes.len() {
+            let read_len = buf.len().min(bytes.len() - offset);
+            buf[..read_len].copy_from_slice(&bytes[offset..offset + read_len]);
+            Ok(read_len)
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn read_size(&self) -> usize {
+        4
+    }
+
+    fn write(&self, _data: &[u8]) -> Result<(), AbortCode> {
+        Err(AbortCode::ReadOnly)
+    }
+}
+
+/// Implements the var pool status object (0x5006)
+#[allow(missing_debug_implementations)]
+pub struct VarPoolStatusObject {
+    capacity: StatusField,
+    used: StatusField,
+    high_water: StatusField,
+}
+
+impl VarPoolStatusObject {
+    /// Create a new VarPoolStatusObject, reporting the status of `pool`
+    pub const fn new(pool: &'static dyn VarPoolAccess) -> Self {
+        Self {
+            capacity: StatusField {
+                pool,
+                sub: SUB_CAPACITY,
+            },
+            used: StatusField {
+                pool,
+                sub: SUB_USED,
+            },
+            high_water: StatusField {
+                pool,
+                sub: SUB_HIGH_WATER,
+            },
+        }
+    }
+}
+
+impl ProvidesSubObjects for VarPoolStatusObject {
+    fn get_sub_object(&self, sub: u8) -> Option<(SubInfo, &dyn SubObjectAccess)> {
+        let field = match sub {
+            0 => {
+                return Some((
+                    SubInfo::MAX_SUB_NUMBER,
+                    const { &ConstField::new(3u8.to_le_bytes()) },
+                ))
+            }
+            SUB_CAPACITY => &self.capacity,
+            SUB_USED => &self.used,
+            SUB_HIGH_WATER => &self.high_water,
+            _ => return None,
+        };
+        Some((SubInfo::new_u32(), field))
+    }
+
+    fn object_code(&self) -> ObjectCode {
+        ObjectCode::Record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zencan_common::objects::{AccessType, DataType, PdoMapping};
+
+    use crate::object_dict::ObjectAccess;
+
+    use super::*;
+
+    fn string_info(size: usize) -> SubInfo {
+        SubInfo {
+            size,
+            data_type: DataType::VisibleString,
+            access_type: AccessType::Rw,
+            pdo_mapping: PdoMapping::None,
+            persist: false,
+        }
+    }
+
+    #[test]
+    fn test_var_pool() {
+        static POOL: VarPool<8, 3> = VarPool::new(b"abcd", [1, 0, 3]);
+        let first = PoolObject::new(&POOL, 0, string_info(4));
+        let second = PoolObject::new(&POOL, 1, string_info(4));
+        let third = PoolObject::new(&POOL, 2, string_info(4));
+
+        let mut buf = [0u8; 4];
+        assert_eq!(b"a", first.get_value(&mut buf));
+        assert!(second.is_empty());
+        assert_eq!(b"bcd", third.get_value(&mut buf));
+
+        // Growing a value moves the following values
+        second.set_value(b"xyz").unwrap();
+        assert_eq!(b"xyz", second.get_value(&mut buf));
+        assert_eq!(b"bcd", third.get_value(&mut buf));
+        assert_eq!(7, POOL.used());
+
+        assert_eq!(
+            Err(AbortCode::DataTypeMismatchLengthHigh),
+            first.set_value(b"12345")
+        );
+        assert_eq!(Err(AbortCode::OutOfMemory), first.set_value(b"123"));
+
+        // Shrinking frees space, but the high-water mark is kept
+        third.write(0, b"").unwrap();
+        first.set_value(b"123").unwrap();
+        assert_eq!(b"123", first.get_value(&mut buf));
+        assert_eq!(b"xyz", second.get_value(&mut buf));
+        assert_eq!(6, POOL.used());
+        assert_eq!(7, POOL.high_water());
+
+        let status = VarPoolStatusObject::new(&POOL);
+        assert_eq!(3, status.read_u8(0).unwrap());
+        assert_eq!(8, status.read_u32(1).unwrap());
+        assert_eq!(6, status.read_u32(2).unwrap());
+        assert_eq!(7, status.read_u32(3).unwrap());
+        assert_eq!(Err(AbortCode::ReadOnly), status.write(3, &[0; 4]));
+    }
+}
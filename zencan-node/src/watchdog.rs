@@ -0,0 +1,230 @@
+//! Application watchdog
+//!
+//! The watchdog detects when the application stops servicing the node, e.g. because the task which
+//! calls [`Node::process`](crate::Node::process) has hung. It is enabled with
+//! [`Node::set_watchdog`](crate::Node::set_watchdog), and the application must then call
+//! [`Node::feed_watchdog`](crate::Node::feed_watchdog) regularly. A feed only takes effect on the
+//! next call to `process`, so the watchdog expires when the application stops feeding, or stops
+//! calling `process`, for longer than the period.
+//!
+//! A hung task cannot check its own watchdog, so the [`Watchdog`] lives in the [`NodeMbox`], where
+//! it can be checked from another context, such as a timer interrupt, with [`Watchdog::check`].
+//! `process` also checks it, which catches an application which still calls `process` but has
+//! stopped feeding.
+//!
+//! When the watchdog expires, the registered [`WatchdogCallback`] is called once, with an EMCY
+//! message prepared by the node. The callback should force the device into a safe state, transmit
+//! the EMCY directly on the CAN controller, as `process` may never run again, and then reset the
+//! device.
+//!
+//! [`NodeMbox`]: crate::NodeMbox
+
+use zencan_common::{messages::CanMessage, AtomicCell};
+
+use crate::clock::elapsed_us;
+
+/// The EMCY error code sent when the watchdog expires (internal software error)
+pub const WATCHDOG_EMCY_CODE: u16 = 0x6100;
+
+/// The signature of the function called when the watchdog expires
+///
+/// It is passed the EMCY message to transmit, or `None` if the node is not currently able to send
+/// EMCY messages, e.g. because it has no node ID, or is in the Stopped state.
+pub type WatchdogCallback = dyn Fn(Option<CanMessage>) + Sync;
+
+/// Tracks the time since the application last fed the node watchdog
+///
+/// See the [module docs](self) for more info.
+#[allow(missing_debug_implementations)]
+pub struct Watchdog {
+    /// The watchdog period in microseconds, or 0 if it is disabled
+    period_us: AtomicCell<u64>,
+    /// Set by a feed, and cleared by the next call to process
+    fed: AtomicCell<bool>,
+    /// The time of the last process call following a feed
+    last_feed_us: AtomicCell<Option<u64>>,
+    expired: AtomicCell<bool>,
+    emcy: AtomicCell<Option<CanMessage>>,
+    callback: AtomicCell<Option<&'static WatchdogCallback>>,
+    /// The mask of the valid bits of the application clock
+    clock_mask: AtomicCell<u64>,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watchdog {
+    /// Create a new, disabled, Watchdog
+    pub const fn new() -> Self {
+        Self {
+            period_us: AtomicCell::new(0),
+            fed: AtomicCell::new(false),
+            last_feed_us: AtomicCell::new(None),
+            expired: AtomicCell::new(false),
+            emcy: AtomicCell::new(None),
+            callback: AtomicCell::new(None),
+            clock_mask: AtomicCell::new(u64::MAX),
+        }
+    }
+
+    /// Set the function to call when the watchdog expires
+    pub fn set_callback(&self, callback: &'static WatchdogCallback) {
+        self.callback.store(Some(callback));
+    }
+
+    /// Check if the watchdog has expired
+    ///
+    /// `now_us` must come from the same clock as the times passed to
+    /// [`Node::process`](crate::Node::process). This is safe to call from an interrupt handler.
+    ///
+    /// When the watchdog expires, the callback is called, and true is returned. This happens once;
+    /// the watchdog is only re-armed when it is fed, or enabled again.
+    pub fn check(&self, now_us: u64) -> bool {
+        let period_us = self.period_us.load();
+        let Some(last_feed_us) = self.last_feed_us.load() else {
+            return false;
+        };
+        let elapsed = elapsed_us(last_feed_us, now_us, self.clock_mask.load());
+        if period_us == 0 || elapsed <= period_us {
+            return false;
+        }
+        if self.expired.fetch_update(|e| (!e).then_some(true)).is_err() {
+            // Already expired
+            return false;
+        }
+        if let Some(callback) = self.callback.load() {
+            callback(self.emcy.load());
+        }
+        true
+    }
+
+    /// Returns true if the watchdog is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.period_us.load() != 0
+    }
+
+    /// Returns true if the watchdog has expired and has not been fed since
+    pub fn is_expired(&self) -> bool {
+        self.expired.load()
+    }
+
+    /// Enable the watchdog with a period in microseconds, or disable it with 0
+    ///
+    /// The watchdog starts running on the next call to process.
+    pub(crate) fn set_period(&self, period_us: u64) {
+        critical_section::with(|cs| {
+            self.period_us.borrow(cs).set(period_us);
+            self.last_feed_us.borrow(cs).set(None);
+            self.expired.borrow(cs).set(false);
+        });
+    }
+
+    /// Set the mask of the valid bits of the application clock, so that its wrap is handled
+    pub(crate) fn set_clock_mask(&self, mask: u64) {
+        self.clock_mask.store(mask);
+    }
+
+    /// Record that the application is alive
+    pub(crate) fn feed(&self) {
+        self.fed.store(true);
+    }
+
+    /// Set the EMCY message passed to the callback on expiry
+    pub(crate) fn set_emcy(&self, emcy: Option<CanMessage>) {
+        self.emcy.store(emcy);
+    }
+
+    /// Called from process to apply any feed since the last call
+    pub(crate) fn kick(&self, now_us: u64) {
+        if !self.is_enabled() {
+            return;
+        }
+        critical_section::with(|cs| {
+            let last_feed_us = self.last_feed_us.borrow(cs);
+            if self.fed.borrow(cs).take() || last_feed_us.get().is_none() {
+                last_feed_us.set(Some(now_us));
+                self.expired.borrow(cs).set(false);
+            }
+        });
+    }
+
+    /// Get the time until the watchdog expires, or None if it is disabled or already expired
+    pub(crate) fn time_until_expiry(&self, now_us: u64) -> Option<u64> {
+        let period_us = self.period_us.load();
+        if period_us == 0 || self.expired.load() {
+            return None;
+        }
+        let mask = self.clock_mask.load();
+        self.last_feed_us
+            .load()
+            .map(|last| (period_us + 1).saturating_sub(elapsed_us(last, now_us, mask)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    use zencan_common::messages::CanId;
+
+    use super::*;
+
+    #[test]
+    fn test_watchdog() {
+        static EXPIRED_COUNT: AtomicU32 = AtomicU32::new(0);
+        let watchdog = Watchdog::new();
+        watchdog.set_callback(&|emcy| {
+            assert_eq!(CanId::std(0x81), emcy.unwrap().id());
+            EXPIRED_COUNT.fetch_add(1, Ordering::Relaxed);
+        });
+        watchdog.set_emcy(Some(CanMessage::new(CanId::std(0x81), &[0; 8])));
+
+        // Disabled by default
+        watchdog.kick(0);
+        assert!(!watchdog.check(1_000_000));
+
+        watchdog.set_period(1000);
+        // Not running until the first process call
+        assert!(!watchdog.check(1_000_000));
+        watchdog.kick(1000);
+        assert_eq!(Some(1001), watchdog.time_until_expiry(1000));
+        assert!(!watchdog.check(2000));
+
+        // Process calls without a feed do not restart the period
+        watchdog.kick(1500);
+        watchdog.feed();
+        assert!(!watchdog.check(2000));
+        watchdog.kick(1900);
+        assert!(!watchdog.check(2900));
+
+        assert!(watchdog.check(2901));
+        assert!(watchdog.is_expired());
+        assert_eq!(1, EXPIRED_COUNT.load(Ordering::Relaxed));
+        // The callback is only called once
+        assert!(!watchdog.check(5000));
+        assert_eq!(1, EXPIRED_COUNT.load(Ordering::Relaxed));
+        assert_eq!(None, watchdog.time_until_expiry(5000));
+
+        // A feed re-arms it
+        watchdog.feed();
+        watchdog.kick(6000);
+        assert!(!watchdog.is_expired());
+        assert!(watchdog.check(7001));
+        assert_eq!(2, EXPIRED_COUNT.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_watchdog_clock_wrap() {
+        let watchdog = Watchdog::new();
+        watchdog.set_clock_mask(crate::clock::clock_mask(32));
+        watchdog.set_period(1000);
+        watchdog.kick(0xFFFF_FF00);
+        // 256us have passed when the clock wraps to 0
+        assert_eq!(Some(745), watchdog.time_until_expiry(0));
+        assert!(!watchdog.check(744));
+        assert!(watchdog.check(745));
+    }
+}